@@ -6,7 +6,11 @@
 
 #![deny(missing_docs)]
 
-use std::collections::HashSet;
+use std::{
+    collections::HashSet,
+    num::{NonZeroU64, NonZeroUsize},
+    time::Duration,
+};
 
 use serde::Deserialize;
 use serde_json::Value;
@@ -28,6 +32,18 @@ pub const APP_VERSION_KEY: MetadataKey = MetadataKey::new("version");
 pub const APP_DESCRIPTION_KEY: MetadataKey = MetadataKey::new("description");
 /// MetadataKey for extracting the OCI image digest.
 pub const OCI_IMAGE_DIGEST_KEY: MetadataKey = MetadataKey::new("oci_image_digest");
+/// MetadataKey for extracting the application's startup hook.
+pub const APP_ON_STARTUP_KEY: MetadataKey<LifecycleHook> = MetadataKey::new("on_startup");
+/// MetadataKey for extracting the application's shutdown hook.
+pub const APP_ON_SHUTDOWN_KEY: MetadataKey<LifecycleHook> = MetadataKey::new("on_shutdown");
+/// MetadataKey for extracting a component's precompilation strategy.
+pub const PRECOMPILE_KEY: MetadataKey<Precompile> = MetadataKey::new("precompile");
+/// MetadataKey for extracting a component's concurrency limit.
+pub const CONCURRENCY_KEY: MetadataKey<ConcurrencyLimit> = MetadataKey::new("concurrency");
+/// MetadataKey for extracting whether a component's instances are kept.
+pub const INSTANCE_KEY: MetadataKey<InstanceConfig> = MetadataKey::new("instance");
+/// MetadataKey for extracting the IDs of a component's middleware components.
+pub const MIDDLEWARE_KEY: MetadataKey<Vec<String>> = MetadataKey::new("middleware");
 
 /// Validation function type for ensuring that applications meet requirements
 /// even with components filtered out.
@@ -122,6 +138,21 @@ impl App {
         Ok(Some(metadata))
     }
 
+    /// Returns the concurrency limit for all components run by triggers of
+    /// the given type, from `[application.trigger.<type>] concurrency`.
+    pub fn trigger_concurrency_limit(
+        &self,
+        trigger_type: &str,
+    ) -> Result<Option<ConcurrencyLimit>> {
+        #[derive(Deserialize)]
+        struct TriggerConcurrency {
+            concurrency: Option<ConcurrencyLimit>,
+        }
+        Ok(self
+            .get_trigger_metadata::<TriggerConcurrency>(trigger_type)?
+            .and_then(|meta| meta.concurrency))
+    }
+
     fn get_trigger_metadata_value(&self, trigger_type: &str) -> Option<Value> {
         if let Some(trigger_configs) = self.locked.metadata.get("triggers") {
             // New-style: `{"triggers": {"<type>": {...}}}`
@@ -178,7 +209,7 @@ impl App {
         for validator in validators {
             validator(&self, retained_components).map_err(Error::ValidationError)?;
         }
-        let (component_ids, trigger_ids): (HashSet<String>, HashSet<String>) = self
+        let (mut component_ids, trigger_ids): (HashSet<String>, HashSet<String>) = self
             .triggers()
             .filter_map(|t| match t.component() {
                 Ok(comp) if retained_components.contains(&comp.id()) => {
@@ -187,6 +218,19 @@ impl App {
                 _ => None,
             })
             .collect();
+        // Lifecycle hooks run regardless of which triggers are retained.
+        for key in [APP_ON_STARTUP_KEY, APP_ON_SHUTDOWN_KEY] {
+            if let Some(hook) = self.get_metadata(key)? {
+                component_ids.insert(hook.component);
+            }
+        }
+        // Middleware is composed into the components it wraps.
+        let middleware_ids = self
+            .components()
+            .filter(|c| component_ids.contains(c.id()))
+            .map(|c| c.get_metadata(MIDDLEWARE_KEY))
+            .collect::<Result<Vec<_>>>()?;
+        component_ids.extend(middleware_ids.into_iter().flatten().flatten());
         self.locked
             .components
             .retain(|c| component_ids.contains(&c.id));
@@ -211,6 +255,97 @@ impl App {
     }
 }
 
+/// When a component should be compiled and kept ready for instantiation.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Precompile {
+    /// Compile the component when the app is loaded.
+    #[default]
+    Eager,
+    /// Compile the component on first use and keep it for later uses.
+    Lazy,
+    /// Compile the component on every use; never keep it ready.
+    Never,
+}
+
+/// A limit on how many instances run at once.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+pub struct ConcurrencyLimit {
+    /// The maximum number of instances that may run at once.
+    pub max_instances: NonZeroUsize,
+    /// How long an event may wait for an instance before it is rejected, in
+    /// milliseconds. If unset, events wait indefinitely.
+    #[serde(default)]
+    pub queue_timeout_ms: Option<u64>,
+}
+
+impl ConcurrencyLimit {
+    /// How long an event may wait for an instance before it is rejected.
+    pub fn queue_timeout(&self) -> Option<Duration> {
+        self.queue_timeout_ms.map(Duration::from_millis)
+    }
+}
+
+/// Whether a component's instances are kept to handle more than one request.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+pub struct InstanceConfig {
+    /// How long the component's instances live.
+    #[serde(default)]
+    pub lifetime: InstanceLifetime,
+    /// How long a pooled instance is used before it is replaced, in seconds.
+    #[serde(default)]
+    pub max_age_secs: Option<u64>,
+    /// How many requests a pooled instance handles before it is replaced.
+    #[serde(default)]
+    pub max_requests: Option<NonZeroU64>,
+    /// How many pooled instances are kept while they are idle. Defaults to 1.
+    #[serde(default)]
+    pub max_idle_instances: Option<NonZeroUsize>,
+}
+
+impl InstanceConfig {
+    /// How long a pooled instance is used before it is replaced.
+    pub fn max_age(&self) -> Option<Duration> {
+        self.max_age_secs.map(Duration::from_secs)
+    }
+}
+
+/// How long a component's instances live.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum InstanceLifetime {
+    /// A new instance handles each request.
+    #[default]
+    PerRequest,
+    /// Instances are kept after handling a request, to handle later ones.
+    Pooled,
+}
+
+/// A component the host runs once at a point in the app's lifecycle, such as
+/// before serving traffic or during graceful shutdown.
+#[derive(Clone, Debug, Deserialize)]
+pub struct LifecycleHook {
+    /// The ID of the component to run.
+    pub component: String,
+    /// How long the hook may run before it is considered failed.
+    #[serde(default)]
+    pub timeout_secs: Option<u64>,
+    /// What to do if the hook fails or times out.
+    #[serde(default)]
+    pub on_failure: HookFailurePolicy,
+}
+
+/// What the host does when a [`LifecycleHook`] fails or times out.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HookFailurePolicy {
+    /// Stop the app.
+    #[default]
+    Fail,
+    /// Log the failure and carry on.
+    Continue,
+}
+
 /// An `AppComponent` holds configuration for a Spin application component.
 pub struct AppComponent<'a> {
     /// The app this component belongs to.
@@ -268,6 +403,42 @@ impl<'a> AppComponent<'a> {
     pub fn config(&self) -> impl Iterator<Item = (&String, &String)> {
         self.locked.config.iter()
     }
+
+    /// Returns this component's [`Precompile`] strategy.
+    ///
+    /// Components without a `precompile` setting are compiled eagerly.
+    pub fn precompile(&self) -> Result<Precompile> {
+        Ok(self.get_metadata(PRECOMPILE_KEY)?.unwrap_or_default())
+    }
+
+    /// Returns this component's [`ConcurrencyLimit`], if it has one.
+    pub fn concurrency_limit(&self) -> Result<Option<ConcurrencyLimit>> {
+        self.get_metadata(CONCURRENCY_KEY)
+    }
+
+    /// Returns this component's [`InstanceConfig`].
+    ///
+    /// Components without an `instance` setting have a new instance for each
+    /// request.
+    pub fn instance_config(&self) -> Result<InstanceConfig> {
+        Ok(self.get_metadata(INSTANCE_KEY)?.unwrap_or_default())
+    }
+
+    /// Returns the components which wrap this component's handler, outermost
+    /// first.
+    pub fn middleware(&self) -> Result<Vec<AppComponent<'a>>> {
+        let ids = self.get_metadata(MIDDLEWARE_KEY)?.unwrap_or_default();
+        ids.iter()
+            .map(|id| {
+                self.app.get_component(id).ok_or_else(|| {
+                    Error::MetadataError(format!(
+                        "middleware component {id:?} of component {:?} not found",
+                        self.id()
+                    ))
+                })
+            })
+            .collect()
+    }
 }
 
 /// An `AppTrigger` holds configuration for a Spin application trigger.
@@ -360,4 +531,49 @@ mod test {
         assert!(components.contains("empty"));
         assert!(components.len() == 1);
     }
+
+    #[tokio::test]
+    async fn test_retain_components_keeps_middleware() {
+        let manifest = toml::toml! {
+            spin_manifest_version = 2
+
+            [application]
+            name = "test-app"
+
+            [[trigger.test-trigger]]
+            component = "web"
+
+            [[trigger.test-trigger]]
+            component = "other"
+
+            [component.web]
+            source = "does-not-exist.wasm"
+            middleware = ["auth"]
+
+            [component.auth]
+            source = "does-not-exist.wasm"
+
+            [component.other]
+            source = "does-not-exist.wasm"
+        };
+        let locked_app = build_locked_app(&manifest).await.unwrap();
+        let locked_app =
+            retain_components(locked_app, &["web"], &[&does_nothing_validator]).unwrap();
+        let components = locked_app
+            .components
+            .iter()
+            .map(|c| c.id.to_string())
+            .collect::<HashSet<_>>();
+        assert_eq!(
+            HashSet::from(["web".to_owned(), "auth".to_owned()]),
+            components
+        );
+
+        let app = App::new("test", locked_app);
+        let middleware = app.get_component("web").unwrap().middleware().unwrap();
+        assert_eq!(
+            vec!["auth"],
+            middleware.iter().map(|c| c.id()).collect::<Vec<_>>()
+        );
+    }
 }
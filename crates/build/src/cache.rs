@@ -0,0 +1,250 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use spin_common::sha256;
+use spin_manifest::schema::v2::ComponentBuildConfig;
+
+/// Where build state is recorded, relative to the application directory.
+const STATE_FILE: &str = ".spin/build-state.json";
+
+/// Toolchains whose version is part of a build's inputs if its commands run
+/// them, so that builds by different toolchain versions aren't mistaken for
+/// each other. Only these are asked for their `--version`, as other programs
+/// may not treat it as harmless.
+const TOOLCHAINS: &[&str] = &[
+    "cargo",
+    "componentize-py",
+    "dotnet",
+    "go",
+    "grain",
+    "jco",
+    "node",
+    "npm",
+    "npx",
+    "python",
+    "python3",
+    "swift",
+    "tinygo",
+    "yarn",
+    "zig",
+];
+
+/// The inputs and output of each component's last build, so that components
+/// whose inputs haven't changed need not be rebuilt.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub(crate) struct BuildState {
+    #[serde(skip)]
+    path: PathBuf,
+    // Component ID -> last build
+    components: BTreeMap<String, ComponentBuildState>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct ComponentBuildState {
+    /// The digest of the build's inputs.
+    inputs: String,
+    /// The digest of the built Wasm file.
+    output: String,
+}
+
+impl BuildState {
+    /// Loads the build state of the application in `app_dir`. Missing or
+    /// unreadable state is treated as empty, so that everything is rebuilt.
+    pub fn load(app_dir: &Path) -> Self {
+        let path = app_dir.join(STATE_FILE);
+        let components = std::fs::read(&path)
+            .ok()
+            .and_then(|json| serde_json::from_slice::<Self>(&json).ok())
+            .map(|state| state.components)
+            .unwrap_or_default();
+        Self { path, components }
+    }
+
+    /// Whether `output` was built by the last build of the component, and from
+    /// the same inputs.
+    pub fn is_fresh(&self, component_id: &str, inputs: &str, output: &Path) -> bool {
+        let Some(last) = self.components.get(component_id) else {
+            return false;
+        };
+        last.inputs == inputs
+            && sha256::hex_digest_from_file(output).is_ok_and(|digest| digest == last.output)
+    }
+
+    /// Records that `output` was built from `inputs`, and saves the state.
+    pub fn record(&mut self, component_id: &str, inputs: &str, output: &Path) -> Result<()> {
+        let output = sha256::hex_digest_from_file(output)
+            .with_context(|| format!("Cannot get digest for {}", output.display()))?;
+        self.components.insert(
+            component_id.to_owned(),
+            ComponentBuildState {
+                inputs: inputs.to_owned(),
+                output,
+            },
+        );
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(&self.path, serde_json::to_vec_pretty(self)?)
+            .with_context(|| format!("Cannot write build state {}", self.path.display()))
+    }
+}
+
+/// The digest of a component build's inputs: its build commands and working
+/// directory, the versions of the toolchains they run, the files matching its
+/// `watch` globs (except its output), and the input digests of the components
+/// it depends on.
+///
+/// Returns `None` if the component doesn't declare what to watch, in which case
+/// its inputs are unknown and it must always be rebuilt.
+pub(crate) fn input_digest(
+    build: &ComponentBuildConfig,
+    workdir: &Path,
+    output: &Path,
+    dependency_inputs: &[String],
+) -> Result<Option<String>> {
+    if build.watch.is_empty() {
+        return Ok(None);
+    }
+
+    let mut files = vec![];
+    for watch in &build.watch {
+        let pattern = format!(
+            "{}/{watch}",
+            glob::Pattern::escape(&workdir.to_string_lossy())
+        );
+        let matches =
+            glob::glob(&pattern).with_context(|| format!("Invalid watch glob '{watch}'"))?;
+        for path in matches {
+            let path = path?;
+            if path.is_file() && path != output {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    files.dedup();
+
+    let mut inputs = String::new();
+    for command in build.commands() {
+        inputs.push_str(&format!("command\t{command}\n"));
+    }
+    if let Some(workdir) = &build.workdir {
+        inputs.push_str(&format!("workdir\t{workdir}\n"));
+    }
+    for (toolchain, version) in toolchain_versions(build, workdir) {
+        inputs.push_str(&format!("toolchain\t{toolchain}\t{version}\n"));
+    }
+    for dependency in dependency_inputs {
+        inputs.push_str(&format!("dependency\t{dependency}\n"));
+    }
+    for file in files {
+        let relative = file.strip_prefix(workdir).unwrap_or(&file);
+        let digest = sha256::hex_digest_from_file(&file)
+            .with_context(|| format!("Cannot get digest for {}", file.display()))?;
+        inputs.push_str(&format!(
+            "file\t{}\t{digest}\n",
+            relative.to_string_lossy().replace('\\', "/")
+        ));
+    }
+    Ok(Some(sha256::hex_digest_from_bytes(inputs)))
+}
+
+/// The versions of the known toolchains which the build's commands run, run
+/// in `workdir` so as to respect any toolchain overrides there. A toolchain
+/// which can't report its version has an empty one.
+fn toolchain_versions<'a>(
+    build: &'a ComponentBuildConfig,
+    workdir: &Path,
+) -> BTreeMap<&'a str, String> {
+    let toolchains = build
+        .commands()
+        .flat_map(|command| command.split(|c: char| c.is_whitespace() || "&|;()".contains(c)))
+        .filter(|word| TOOLCHAINS.contains(word))
+        .collect::<BTreeSet<_>>();
+    toolchains
+        .into_iter()
+        .map(|toolchain| {
+            let version = Command::new(toolchain)
+                .arg("--version")
+                .current_dir(workdir)
+                .output()
+                .ok()
+                .filter(|output| output.status.success())
+                .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_owned())
+                .unwrap_or_default();
+            (toolchain, version)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn build_config(watch: &[&str]) -> ComponentBuildConfig {
+        toml::from_str(&format!(
+            "command = \"cargo build\"\nwatch = {}",
+            toml::Value::from(watch.to_vec())
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn input_digest_tracks_watched_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("app.wasm");
+        std::fs::create_dir(dir.path().join("src")).unwrap();
+        std::fs::write(dir.path().join("src/lib.rs"), "fn main() {}").unwrap();
+        std::fs::write(dir.path().join("README.md"), "hello").unwrap();
+
+        let build = build_config(&["src/**/*.rs", "*.wasm"]);
+        let digest = || {
+            input_digest(&build, dir.path(), &output, &[])
+                .unwrap()
+                .unwrap()
+        };
+        let original = digest();
+
+        // Unwatched files and the output don't affect the digest.
+        std::fs::write(dir.path().join("README.md"), "goodbye").unwrap();
+        std::fs::write(&output, "wasm").unwrap();
+        assert_eq!(original, digest());
+
+        std::fs::write(dir.path().join("src/lib.rs"), "fn main() { }").unwrap();
+        let changed = digest();
+        assert_ne!(original, changed);
+
+        let with_dependency = input_digest(&build, dir.path(), &output, &[original])
+            .unwrap()
+            .unwrap();
+        assert_ne!(changed, with_dependency);
+
+        assert!(input_digest(&build_config(&[]), dir.path(), &output, &[])
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn builds_are_fresh_until_inputs_or_output_change() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("app.wasm");
+        std::fs::write(&output, "wasm").unwrap();
+
+        let mut state = BuildState::load(dir.path());
+        assert!(!state.is_fresh("hello", "inputs", &output));
+        state.record("hello", "inputs", &output).unwrap();
+
+        let state = BuildState::load(dir.path());
+        assert!(state.is_fresh("hello", "inputs", &output));
+        assert!(!state.is_fresh("hello", "other-inputs", &output));
+        assert!(!state.is_fresh("goodbye", "inputs", &output));
+
+        std::fs::write(&output, "tampered").unwrap();
+        assert!(!state.is_fresh("hello", "inputs", &output));
+    }
+}
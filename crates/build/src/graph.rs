@@ -0,0 +1,197 @@
+use std::collections::HashMap;
+
+use anyhow::{bail, Result};
+
+use crate::manifest::ComponentBuildInfo;
+
+/// Selects the components to build: those in `component_ids`, or all
+/// components if it is empty, and the components they depend on. They are
+/// returned in an order in which each comes after its dependencies, and
+/// otherwise in manifest order.
+///
+/// It is an error for a component to depend on a component that doesn't
+/// exist, or for dependencies to form a cycle.
+pub(crate) fn plan(
+    components: Vec<ComponentBuildInfo>,
+    component_ids: &[String],
+) -> Result<Vec<ComponentBuildInfo>> {
+    let index: HashMap<_, _> = components
+        .iter()
+        .enumerate()
+        .map(|(i, c)| (c.id.as_str(), i))
+        .collect();
+
+    for c in &components {
+        if let Some(dep) = c
+            .depends_on()
+            .iter()
+            .find(|d| !index.contains_key(d.as_str()))
+        {
+            bail!("Component {} depends on unknown component {dep}", c.id);
+        }
+    }
+
+    let roots = if component_ids.is_empty() {
+        (0..components.len()).collect()
+    } else {
+        let unknown_component_ids: Vec<_> = component_ids
+            .iter()
+            .filter(|id| !index.contains_key(id.as_str()))
+            .map(|s| s.as_str())
+            .collect();
+
+        if !unknown_component_ids.is_empty() {
+            bail!("Unknown component(s) {}", unknown_component_ids.join(", "));
+        }
+
+        component_ids
+            .iter()
+            .map(|id| index[id.as_str()])
+            .collect::<Vec<_>>()
+    };
+
+    let mut planner = Planner {
+        components: &components,
+        index: &index,
+        visited: vec![Visit::NotVisited; components.len()],
+        path: vec![],
+        order: vec![],
+    };
+    for root in roots {
+        planner.visit(root)?;
+    }
+    let order = planner.order;
+
+    let mut components: Vec<_> = components.into_iter().map(Some).collect();
+    Ok(order
+        .into_iter()
+        .filter_map(|i| components[i].take())
+        .collect())
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Visit {
+    NotVisited,
+    InProgress,
+    Done,
+}
+
+struct Planner<'a> {
+    components: &'a [ComponentBuildInfo],
+    index: &'a HashMap<&'a str, usize>,
+    visited: Vec<Visit>,
+    // The components being visited, for reporting cycles
+    path: Vec<usize>,
+    order: Vec<usize>,
+}
+
+impl Planner<'_> {
+    fn visit(&mut self, i: usize) -> Result<()> {
+        match self.visited[i] {
+            Visit::Done => return Ok(()),
+            Visit::InProgress => {
+                let start = self.path.iter().position(|p| *p == i).unwrap_or_default();
+                let cycle: Vec<_> = self.path[start..]
+                    .iter()
+                    .chain([&i])
+                    .map(|p| self.components[*p].id.as_str())
+                    .collect();
+                bail!(
+                    "Component build dependencies form a cycle: {}",
+                    cycle.join(" -> ")
+                );
+            }
+            Visit::NotVisited => (),
+        }
+
+        let (components, index) = (self.components, self.index);
+        self.visited[i] = Visit::InProgress;
+        self.path.push(i);
+        for dep in components[i].depends_on() {
+            self.visit(index[dep.as_str()])?;
+        }
+        self.path.pop();
+        self.visited[i] = Visit::Done;
+        self.order.push(i);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn components(toml_text: &str) -> Vec<ComponentBuildInfo> {
+        #[derive(serde::Deserialize)]
+        struct Manifest {
+            component: Vec<ComponentBuildInfo>,
+        }
+        toml::from_str::<Manifest>(toml_text).unwrap().component
+    }
+
+    fn ids(components: &[ComponentBuildInfo]) -> Vec<&str> {
+        components.iter().map(|c| c.id.as_str()).collect()
+    }
+
+    const APP: &str = r#"
+        [[component]]
+        id = "web"
+        build = { command = "npm run build", depends_on = ["api-client"] }
+        [[component]]
+        id = "api"
+        build = { command = "cargo build", depends_on = ["shared"] }
+        [[component]]
+        id = "api-client"
+        build = { command = "cargo build", depends_on = ["shared"] }
+        [[component]]
+        id = "shared"
+        build = { command = "cargo build" }
+        [[component]]
+        id = "static"
+    "#;
+
+    #[test]
+    fn dependencies_are_built_first() {
+        let planned = plan(components(APP), &[]).unwrap();
+        assert_eq!(
+            vec!["shared", "api-client", "web", "api", "static"],
+            ids(&planned)
+        );
+    }
+
+    #[test]
+    fn dependencies_of_selected_components_are_built() {
+        let planned = plan(components(APP), &["web".to_owned()]).unwrap();
+        assert_eq!(vec!["shared", "api-client", "web"], ids(&planned));
+
+        assert!(plan(components(APP), &["nope".to_owned()]).is_err());
+    }
+
+    #[test]
+    fn cycles_are_errors() {
+        let cyclic = components(
+            r#"
+            [[component]]
+            id = "a"
+            build = { command = "make", depends_on = ["b"] }
+            [[component]]
+            id = "b"
+            build = { command = "make", depends_on = ["a"] }
+            "#,
+        );
+        let err = plan(cyclic, &[]).unwrap_err();
+        assert!(err.to_string().contains("a -> b -> a"), "{err}");
+    }
+
+    #[test]
+    fn unknown_dependencies_are_errors() {
+        let unknown = components(
+            r#"
+            [[component]]
+            id = "a"
+            build = { command = "make", depends_on = ["b"] }
+            "#,
+        );
+        assert!(plan(unknown, &[]).is_err());
+    }
+}
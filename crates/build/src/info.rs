@@ -0,0 +1,40 @@
+use std::{path::Path, process::Command, time::SystemTime};
+
+use spin_common::build_info::BuildInfo;
+
+/// The build info shared by all of an application's component builds: the
+/// git commit of the application directory, and the version of Spin.
+pub(crate) fn detect(app_dir: &Path) -> BuildInfo {
+    let git_sha = git(app_dir, &["rev-parse", "HEAD"]);
+    let git_dirty = git_sha.is_some()
+        && git(app_dir, &["status", "--porcelain"]).is_some_and(|status| !status.is_empty());
+    BuildInfo {
+        git_sha,
+        git_dirty,
+        built_at: None,
+        builder: Some(format!("spin/{}", env!("CARGO_PKG_VERSION"))),
+    }
+}
+
+/// Returns the build info of a component built from `shared` now.
+pub(crate) fn built_now(shared: &BuildInfo) -> BuildInfo {
+    BuildInfo {
+        built_at: Some(humantime::format_rfc3339_seconds(SystemTime::now()).to_string()),
+        ..shared.clone()
+    }
+}
+
+/// Runs a git command in `dir`, returning its trimmed output if it succeeds.
+/// Git not being installed, or `dir` not being in a repository, isn't an
+/// error; there is just no git info.
+fn git(dir: &Path, args: &[&str]) -> Option<String> {
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(String::from_utf8(output.stdout).ok()?.trim().to_owned())
+}
@@ -2,23 +2,68 @@
 
 //! A library for building Spin components.
 
+mod cache;
+mod graph;
+mod info;
 mod manifest;
+mod remote;
 
 use anyhow::{anyhow, bail, Context, Result};
 use manifest::ComponentBuildInfo;
-use spin_common::{paths::parent_dir, ui::quoted_path};
+use spin_common::{
+    build_info::{BuildInfo, BuildInfoFile},
+    paths::parent_dir,
+    ui::quoted_path,
+};
 use std::{
-    collections::HashSet,
+    collections::{HashMap, HashSet},
     path::{Path, PathBuf},
+    process::{ExitStatus, Stdio},
+    sync::{Arc, Mutex},
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, BufReader},
+    task::JoinSet,
 };
-use subprocess::{Exec, Redirection};
 
-use crate::manifest::component_build_configs;
+use crate::{cache::BuildState, manifest::component_build_configs, remote::RemoteCache};
+
+/// Options for building an application's components.
+#[derive(Debug, Default)]
+pub struct BuildOptions {
+    /// Build components even if their inputs haven't changed since they were
+    /// last built.
+    pub force: bool,
+    /// A cache of component builds shared between machines: an `http(s)://`
+    /// URL, or an `oci://` registry repository.
+    pub remote_cache: Option<String>,
+    /// Fetch component builds from the remote cache, but don't upload to it.
+    pub remote_cache_read_only: bool,
+    /// The most components to build at once. Components are only built once
+    /// the components they depend on have been. If `None`, components are
+    /// built one at a time.
+    pub jobs: Option<usize>,
+    /// The environment whose manifest overlay to build with, if any.
+    pub environment: Option<String>,
+}
 
 /// If present, run the build command of each component.
 pub async fn build(manifest_file: &Path, component_ids: &[String]) -> Result<()> {
+    build_with_options(manifest_file, component_ids, &BuildOptions::default()).await
+}
+
+/// If present, run the build command of each component whose inputs have
+/// changed since it was last built.
+///
+/// A component's inputs are the files matching its `watch` globs. Components
+/// that don't declare any are always built.
+pub async fn build_with_options(
+    manifest_file: &Path,
+    component_ids: &[String],
+    options: &BuildOptions,
+) -> Result<()> {
     let (components, manifest_err) =
-        component_build_configs(manifest_file)
+        component_build_configs(manifest_file, options.environment.as_deref())
             .await
             .with_context(|| {
                 format!(
@@ -28,7 +73,7 @@ pub async fn build(manifest_file: &Path, component_ids: &[String]) -> Result<()>
             })?;
     let app_dir = parent_dir(manifest_file)?;
 
-    let build_result = build_components(component_ids, components, app_dir);
+    let build_result = build_components(component_ids, components, app_dir, options).await;
 
     if let Some(e) = manifest_err {
         terminal::warn!("The manifest has errors not related to the Wasm component build. Error details:\n{e:#}");
@@ -37,85 +82,287 @@ pub async fn build(manifest_file: &Path, component_ids: &[String]) -> Result<()>
     build_result
 }
 
-fn build_components(
+async fn build_components(
     component_ids: &[String],
     components: Vec<ComponentBuildInfo>,
     app_dir: PathBuf,
+    options: &BuildOptions,
 ) -> Result<(), anyhow::Error> {
-    let components_to_build = if component_ids.is_empty() {
-        components
-    } else {
-        let all_ids: HashSet<_> = components.iter().map(|c| &c.id).collect();
-        let unknown_component_ids: Vec<_> = component_ids
-            .iter()
-            .filter(|id| !all_ids.contains(id))
-            .map(|s| s.as_str())
-            .collect();
-
-        if !unknown_component_ids.is_empty() {
-            bail!("Unknown component(s) {}", unknown_component_ids.join(", "));
-        }
-
-        components
-            .into_iter()
-            .filter(|c| component_ids.contains(&c.id))
-            .collect()
-    };
+    let mut pending: Vec<_> = graph::plan(components, component_ids)?
+        .into_iter()
+        .filter(|c| c.build.is_some())
+        .collect();
 
-    if components_to_build.iter().all(|c| c.build.is_none()) {
+    if pending.is_empty() {
         println!("None of the components have a build command.");
         println!("For information on specifying a build command, see https://developer.fermyon.com/spin/build#setting-up-for-spin-build.");
         return Ok(());
     }
 
-    components_to_build
-        .into_iter()
-        .map(|c| build_component(c, &app_dir))
-        .collect::<Result<Vec<_>, _>>()?;
+    let jobs = options.jobs.unwrap_or(1).max(1);
+    let context = Arc::new(BuildContext {
+        state: Mutex::new(BuildState::load(&app_dir)),
+        build_info: info::detect(&app_dir),
+        recorded_info: Mutex::new(BuildInfoFile::load(&app_dir)),
+        app_dir,
+        remote_cache: options
+            .remote_cache
+            .as_deref()
+            .map(RemoteCache::parse)
+            .transpose()?,
+        force: options.force,
+        remote_cache_read_only: options.remote_cache_read_only,
+        // The output of concurrent builds is interleaved, so tell it apart.
+        prefix_output: jobs > 1 && pending.len() > 1,
+    });
+
+    // Dependencies without a build command needn't be waited for.
+    let with_build: HashSet<_> = pending.iter().map(|c| c.id.clone()).collect();
+    // Component ID -> input digest, for each component built so far
+    let mut built = HashMap::<String, Option<String>>::new();
+    let mut running = JoinSet::new();
+
+    loop {
+        while running.len() < jobs {
+            let ready = pending.iter().position(|c| {
+                c.depends_on()
+                    .iter()
+                    .all(|dep| built.contains_key(dep) || !with_build.contains(dep))
+            });
+            let Some(ready) = ready else {
+                break;
+            };
+            let component = pending.remove(ready);
+            // If a dependency's inputs are unknown, so are this component's.
+            let dependency_inputs = component
+                .depends_on()
+                .iter()
+                .filter_map(|dep| built.get(dep))
+                .cloned()
+                .collect::<Option<Vec<_>>>();
+            running.spawn(build_component(
+                component,
+                context.clone(),
+                dependency_inputs,
+            ));
+        }
+
+        let Some(result) = running.join_next().await else {
+            break;
+        };
+        // On failure, the JoinSet is dropped, aborting the other builds.
+        let (id, inputs) = result??;
+        built.insert(id, inputs);
+    }
 
     terminal::step!("Finished", "building all Spin components");
     Ok(())
 }
 
-/// Run the build command of the component.
-fn build_component(build_info: ComponentBuildInfo, app_dir: &Path) -> Result<()> {
-    match build_info.build {
-        Some(b) => {
-            for command in b.commands() {
-                terminal::step!("Building", "component {} with `{}`", build_info.id, command);
-                let workdir = construct_workdir(app_dir, b.workdir.as_ref())?;
-                if b.workdir.is_some() {
-                    println!("Working directory: {}", quoted_path(&workdir));
-                }
+/// What all of an application's component builds share.
+struct BuildContext {
+    app_dir: PathBuf,
+    state: Mutex<BuildState>,
+    /// The build info shared by every component built.
+    build_info: BuildInfo,
+    recorded_info: Mutex<BuildInfoFile>,
+    remote_cache: Option<RemoteCache>,
+    force: bool,
+    remote_cache_read_only: bool,
+    prefix_output: bool,
+}
+
+/// Run the build command of the component, unless its inputs are unchanged
+/// since it was last built, or its build can be fetched from the remote cache.
+///
+/// Returns the component ID and the digest of its inputs, if they are known.
+/// `dependency_inputs` are the input digests of the components it depends on,
+/// or `None` if any of them are unknown.
+async fn build_component(
+    build_info: ComponentBuildInfo,
+    context: Arc<BuildContext>,
+    dependency_inputs: Option<Vec<String>>,
+) -> Result<(String, Option<String>)> {
+    let id = build_info.id.clone();
+    let Some(b) = &build_info.build else {
+        return Ok((id, None));
+    };
+    let workdir = construct_workdir(&context.app_dir, b.workdir.as_ref())?;
+
+    // Only components built to a local file, and whose inputs are known, can
+    // be skipped or cached.
+    let output = build_info
+        .local_source()
+        .map(|source| context.app_dir.join(source));
+    let cacheable = match (&output, dependency_inputs) {
+        (Some(output), Some(dependency_inputs)) => {
+            cache::input_digest(b, &workdir, output, &dependency_inputs)?
+                .map(|inputs| (inputs, output.clone()))
+        }
+        _ => None,
+    };
+    let inputs = cacheable.as_ref().map(|(inputs, _)| inputs.clone());
 
-                let exit_status = Exec::shell(command)
-                    .cwd(workdir)
-                    .stdout(Redirection::None)
-                    .stderr(Redirection::None)
-                    .stdin(Redirection::None)
-                    .popen()
-                    .map_err(|err| {
-                        anyhow!(
-                            "Cannot spawn build process '{:?}' for component {}: {}",
-                            &b.command,
-                            build_info.id,
-                            err
-                        )
-                    })?
-                    .wait()?;
-
-                if !exit_status.success() {
-                    bail!(
-                        "Build command for component {} failed with status {:?}",
-                        build_info.id,
-                        exit_status,
-                    );
+    if let Some((inputs, output)) = cacheable.as_ref().filter(|_| !context.force) {
+        if context.state.lock().unwrap().is_fresh(&id, inputs, output) {
+            terminal::step!("Skipping", "component {id} (unchanged since last build)");
+            return Ok((id, Some(inputs.clone())));
+        }
+        if let Some(remote_cache) = &context.remote_cache {
+            match remote_cache.get(inputs).await {
+                Ok(Some(wasm)) => {
+                    if let Some(dir) = output.parent() {
+                        std::fs::create_dir_all(dir)?;
+                    }
+                    std::fs::write(output, wasm)
+                        .with_context(|| format!("Cannot write {}", quoted_path(output)))?;
+                    terminal::step!("Fetched", "component {id} from build cache");
+                    record_build(&context.state, &id, inputs, output);
+                    record_build_info(&context, &id, output);
+                    return Ok((id, Some(inputs.clone())));
                 }
+                Ok(None) => (),
+                Err(e) => terminal::warn!("Cannot fetch component {id} from build cache: {e:#}"),
             }
+        }
+    }
 
-            Ok(())
+    let prefix = context.prefix_output.then(|| format!("[{id}]"));
+    for command in b.commands() {
+        terminal::step!("Building", "component {id} with `{command}`");
+        if b.workdir.is_some() {
+            match &prefix {
+                Some(prefix) => println!("{prefix} Working directory: {}", quoted_path(&workdir)),
+                None => println!("Working directory: {}", quoted_path(&workdir)),
+            }
         }
-        _ => Ok(()),
+
+        let exit_status = run_command(command, &workdir, prefix.as_deref())
+            .await
+            .map_err(|err| {
+                anyhow!(
+                    "Cannot spawn build process '{:?}' for component {}: {}",
+                    &b.command,
+                    id,
+                    err
+                )
+            })?;
+
+        if !exit_status.success() {
+            bail!(
+                "Build command for component {} failed with status {:?}",
+                id,
+                exit_status,
+            );
+        }
+    }
+
+    if let Some(output) = output.as_ref().filter(|output| output.exists()) {
+        record_build_info(&context, &id, output);
+    }
+
+    if let Some((inputs, output)) = &cacheable {
+        if !output.exists() {
+            return Ok((id, None));
+        }
+        record_build(&context.state, &id, inputs, output);
+        if let Some(remote_cache) = context
+            .remote_cache
+            .as_ref()
+            .filter(|_| !context.remote_cache_read_only)
+        {
+            let upload = match std::fs::read(output) {
+                Ok(wasm) => remote_cache.put(inputs, wasm).await,
+                Err(e) => Err(e.into()),
+            };
+            if let Err(e) = upload {
+                terminal::warn!("Cannot upload component {id} to build cache: {e:#}");
+            }
+        }
+    }
+
+    Ok((id, inputs))
+}
+
+/// Runs a build command through the shell. If `prefix` is given, each line the
+/// command outputs is printed with it.
+async fn run_command(
+    command: &str,
+    workdir: &Path,
+    prefix: Option<&str>,
+) -> std::io::Result<ExitStatus> {
+    let mut cmd = shell_command(command);
+    cmd.current_dir(workdir).kill_on_drop(true);
+
+    let Some(prefix) = prefix else {
+        return cmd.status().await;
+    };
+
+    let mut child = cmd.stdout(Stdio::piped()).stderr(Stdio::piped()).spawn()?;
+    let stdout = child.stdout.take().expect("stdout should be piped");
+    let stderr = child.stderr.take().expect("stderr should be piped");
+    let (_, _, status) = tokio::join!(
+        print_prefixed(stdout, prefix, false),
+        print_prefixed(stderr, prefix, true),
+        child.wait(),
+    );
+    status
+}
+
+#[cfg(not(windows))]
+fn shell_command(command: &str) -> tokio::process::Command {
+    let mut cmd = tokio::process::Command::new("sh");
+    cmd.arg("-c").arg(command);
+    cmd
+}
+
+#[cfg(windows)]
+fn shell_command(command: &str) -> tokio::process::Command {
+    let mut cmd = tokio::process::Command::new("cmd.exe");
+    cmd.arg("/C").raw_arg(command);
+    cmd
+}
+
+/// Prints each line read from `output` with `prefix`, until the end of the
+/// output.
+async fn print_prefixed(output: impl AsyncRead + Unpin, prefix: &str, to_stderr: bool) {
+    let mut output = BufReader::new(output);
+    let mut line = vec![];
+    loop {
+        line.clear();
+        match output.read_until(b'\n', &mut line).await {
+            Ok(0) | Err(_) => break,
+            Ok(_) => (),
+        }
+        let text = String::from_utf8_lossy(&line);
+        let text = text.trim_end_matches(['\r', '\n']);
+        if to_stderr {
+            eprintln!("{prefix} {text}");
+        } else {
+            println!("{prefix} {text}");
+        }
+    }
+}
+
+/// Records a component build, so that the component can be skipped next time.
+/// Failing to do so only means it will be rebuilt.
+fn record_build(state: &Mutex<BuildState>, component_id: &str, inputs: &str, output: &Path) {
+    if let Err(e) = state.lock().unwrap().record(component_id, inputs, output) {
+        terminal::warn!("Cannot record build of component {component_id}: {e:#}");
+    }
+}
+
+/// Records the build info of a component, so that it can be locked into the
+/// application. Failing to do so only means the component has no build info.
+fn record_build_info(context: &BuildContext, component_id: &str, output: &Path) {
+    let info = info::built_now(&context.build_info);
+    if let Err(e) = context
+        .recorded_info
+        .lock()
+        .unwrap()
+        .record(component_id, output, info)
+    {
+        terminal::warn!("Cannot record build info of component {component_id}: {e:#}");
     }
 }
 
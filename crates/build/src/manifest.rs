@@ -5,15 +5,26 @@ use std::{collections::BTreeMap, path::Path};
 use spin_manifest::{schema::v2, ManifestVersion};
 
 /// Returns a map of component IDs to [`v2::ComponentBuildConfig`]s for the
-/// given (v1 or v2) manifest path. If the manifest cannot be loaded, the
+/// given (v1 or v2) manifest path, patched with the overlay for `environment`
+/// if one is given. If the manifest cannot be loaded, the
 /// function attempts fallback: if fallback succeeds, result is Ok but the load error
 /// is also returned via the second part of the return value tuple.
 pub async fn component_build_configs(
     manifest_file: impl AsRef<Path>,
+    environment: Option<&str>,
 ) -> Result<(Vec<ComponentBuildInfo>, Option<spin_manifest::Error>)> {
     let manifest = spin_manifest::manifest_from_file(&manifest_file);
     match manifest {
-        Ok(manifest) => Ok((build_configs_from_manifest(manifest), None)),
+        Ok(mut manifest) => {
+            if let Some(environment) = environment {
+                spin_manifest::overlay::apply_environment(
+                    &mut manifest,
+                    manifest_file.as_ref(),
+                    environment,
+                )?;
+            }
+            Ok((build_configs_from_manifest(manifest), None))
+        }
         Err(e) => fallback_load_build_configs(&manifest_file)
             .await
             .map(|bc| (bc, Some(e))),
@@ -30,6 +41,10 @@ fn build_configs_from_manifest(
         .into_iter()
         .map(|(id, c)| ComponentBuildInfo {
             id: id.to_string(),
+            source: c
+                .source
+                .local_path()
+                .map(|path| toml::Value::String(path.to_owned())),
             build: c.build,
         })
         .collect()
@@ -61,9 +76,32 @@ async fn fallback_load_build_configs(
 pub struct ComponentBuildInfo {
     #[serde(default)]
     pub id: String,
+    #[serde(default)]
+    source: Option<toml::Value>,
     pub build: Option<v2::ComponentBuildConfig>,
 }
 
+impl ComponentBuildInfo {
+    /// The path of the component's Wasm file, relative to the app directory,
+    /// if the component is built locally.
+    pub fn local_source(&self) -> Option<&str> {
+        match self.source.as_ref()? {
+            toml::Value::String(path) => Some(path),
+            // A source per target, of which the local one is built
+            toml::Value::Table(sources) => sources.get("local")?.as_str(),
+            _ => None,
+        }
+    }
+
+    /// The IDs of the components that must be built before this one.
+    pub fn depends_on(&self) -> &[String] {
+        self.build
+            .as_ref()
+            .map(|b| b.depends_on.as_slice())
+            .unwrap_or_default()
+    }
+}
+
 #[derive(Deserialize)]
 struct ManifestV1BuildInfo {
     #[serde(rename = "component")]
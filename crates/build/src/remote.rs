@@ -0,0 +1,124 @@
+use anyhow::{bail, Context, Result};
+use url::Url;
+
+/// The media type of component builds stored in an OCI registry cache.
+const WASM_MEDIA_TYPE: &str = "application/wasm";
+
+/// An environment variable holding a bearer token for an HTTP remote cache.
+const HTTP_TOKEN_ENV_VAR: &str = "SPIN_BUILD_CACHE_TOKEN";
+
+/// A store of built components shared between machines, keyed by the digest of
+/// their build inputs.
+pub(crate) enum RemoteCache {
+    /// `https://example.com/cache`: component builds are at
+    /// `<base>/<digest>.wasm`, and are uploaded with `PUT`.
+    Http { base: Url, token: Option<String> },
+    /// `oci://registry.example.com/repo`: component builds are single-layer
+    /// artifacts tagged with their digest.
+    Oci { repository: String },
+}
+
+impl RemoteCache {
+    /// Parses a remote cache location.
+    pub fn parse(location: &str) -> Result<Self> {
+        if let Some(repository) = location.strip_prefix("oci://") {
+            return Ok(Self::Oci {
+                repository: repository.trim_end_matches('/').to_owned(),
+            });
+        }
+        let mut base = Url::parse(location)
+            .with_context(|| format!("Invalid build cache location '{location}'"))?;
+        if !matches!(base.scheme(), "http" | "https") {
+            bail!("Build cache location '{location}' must be an http(s):// or oci:// URL");
+        }
+        if !base.path().ends_with('/') {
+            base.set_path(&format!("{}/", base.path()));
+        }
+        let token = std::env::var(HTTP_TOKEN_ENV_VAR).ok();
+        if token.is_some() && base.scheme() == "http" {
+            bail!(
+                "{HTTP_TOKEN_ENV_VAR} is set, but build cache location '{location}' isn't https://; refusing to send the token unencrypted"
+            );
+        }
+        Ok(Self::Http { base, token })
+    }
+
+    /// Fetches the build of a component with the given input digest, if the
+    /// cache has one.
+    pub async fn get(&self, digest: &str) -> Result<Option<Vec<u8>>> {
+        match self {
+            Self::Http { base, token } => {
+                let url = base.join(&format!("{digest}.wasm"))?;
+                let mut request = reqwest::Client::new().get(url.clone());
+                if let Some(token) = token {
+                    request = request.bearer_auth(token);
+                }
+                let response = request.send().await?;
+                if response.status() == reqwest::StatusCode::NOT_FOUND {
+                    return Ok(None);
+                }
+                let response = response
+                    .error_for_status()
+                    .with_context(|| format!("Cannot fetch {url}"))?;
+                Ok(Some(response.bytes().await?.to_vec()))
+            }
+            Self::Oci { repository } => {
+                let mut client = spin_oci::Client::new(false, None).await?;
+                client.pull_artifact(format!("{repository}:{digest}")).await
+            }
+        }
+    }
+
+    /// Stores the build of a component with the given input digest.
+    pub async fn put(&self, digest: &str, wasm: Vec<u8>) -> Result<()> {
+        match self {
+            Self::Http { base, token } => {
+                let url = base.join(&format!("{digest}.wasm"))?;
+                let mut request = reqwest::Client::new().put(url.clone()).body(wasm);
+                if let Some(token) = token {
+                    request = request.bearer_auth(token);
+                }
+                request
+                    .send()
+                    .await?
+                    .error_for_status()
+                    .with_context(|| format!("Cannot upload {url}"))?;
+                Ok(())
+            }
+            Self::Oci { repository } => {
+                let mut client = spin_oci::Client::new(false, None).await?;
+                client
+                    .push_artifact(format!("{repository}:{digest}"), WASM_MEDIA_TYPE, wasm)
+                    .await
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_cache_locations() {
+        let RemoteCache::Http { base, .. } =
+            RemoteCache::parse("https://cache.example.com/spin").unwrap()
+        else {
+            panic!("expected an HTTP cache");
+        };
+        assert_eq!(
+            "https://cache.example.com/spin/abc.wasm",
+            base.join("abc.wasm").unwrap().as_str()
+        );
+
+        let RemoteCache::Oci { repository } =
+            RemoteCache::parse("oci://ghcr.io/example/cache/").unwrap()
+        else {
+            panic!("expected an OCI cache");
+        };
+        assert_eq!("ghcr.io/example/cache", repository);
+
+        assert!(RemoteCache::parse("ftp://example.com").is_err());
+        assert!(RemoteCache::parse("cache").is_err());
+    }
+}
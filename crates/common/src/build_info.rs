@@ -0,0 +1,126 @@
+//! Metadata about how components were built, recorded by `spin build` and
+//! locked into the application when it is loaded
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::sha256;
+
+/// Where build info is recorded, relative to the application directory.
+const BUILD_INFO_FILE: &str = ".spin/build-info.json";
+
+/// Metadata about the build of a component.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct BuildInfo {
+    /// The git commit the application directory was at, if it is in a git
+    /// repository.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub git_sha: Option<String>,
+    /// Whether the git working tree had uncommitted changes.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub git_dirty: bool,
+    /// When the component was built, as an RFC 3339 timestamp.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub built_at: Option<String>,
+    /// The tool that built the component, e.g. `spin/3.2.0`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub builder: Option<String>,
+}
+
+/// The build info of each of an application's components.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct BuildInfoFile {
+    #[serde(skip)]
+    path: PathBuf,
+    // Component ID -> last build
+    components: BTreeMap<String, RecordedBuild>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct RecordedBuild {
+    /// The digest of the built Wasm file.
+    output: String,
+    #[serde(flatten)]
+    info: BuildInfo,
+}
+
+impl BuildInfoFile {
+    /// Loads the build info of the application in `app_dir`. Missing or
+    /// unreadable build info is treated as empty.
+    pub fn load(app_dir: &Path) -> Self {
+        let path = app_dir.join(BUILD_INFO_FILE);
+        let components = std::fs::read(&path)
+            .ok()
+            .and_then(|json| serde_json::from_slice::<Self>(&json).ok())
+            .map(|file| file.components)
+            .unwrap_or_default();
+        Self { path, components }
+    }
+
+    /// Returns the build info of the component, if the Wasm file with the hex
+    /// SHA-256 digest given by `output_digest` is the one it was recorded for.
+    /// Build info for a file which has since been replaced, e.g. by building
+    /// it some other way, is ignored. `output_digest` is only called if build
+    /// info was recorded for the component, so that callers which know the
+    /// digest of the file needn't hash it.
+    pub fn get(
+        &self,
+        component_id: &str,
+        output_digest: impl FnOnce() -> Option<String>,
+    ) -> Option<&BuildInfo> {
+        let recorded = self.components.get(component_id)?;
+        let digest = output_digest()?;
+        (digest == recorded.output).then_some(&recorded.info)
+    }
+
+    /// Records that `output` was built as described by `info`, and saves the
+    /// build info.
+    pub fn record(
+        &mut self,
+        component_id: &str,
+        output: &Path,
+        info: BuildInfo,
+    ) -> anyhow::Result<()> {
+        let output = sha256::hex_digest_from_file(output)
+            .with_context(|| format!("Cannot get digest for {}", output.display()))?;
+        self.components
+            .insert(component_id.to_owned(), RecordedBuild { output, info });
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(&self.path, serde_json::to_vec_pretty(self)?)
+            .with_context(|| format!("Cannot write build info {}", self.path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn build_info_is_only_returned_for_the_recorded_output() {
+        let app_dir = tempfile::tempdir().unwrap();
+        let output = app_dir.path().join("app.wasm");
+        std::fs::write(&output, "built").unwrap();
+        let info = BuildInfo {
+            git_sha: Some("abc123".into()),
+            ..Default::default()
+        };
+
+        BuildInfoFile::load(app_dir.path())
+            .record("app", &output, info.clone())
+            .unwrap();
+        let file = BuildInfoFile::load(app_dir.path());
+        let digest = || sha256::hex_digest_from_file(&output).ok();
+        assert_eq!(Some(&info), file.get("app", digest));
+        assert_eq!(None, file.get("other", digest));
+
+        std::fs::write(&output, "rebuilt").unwrap();
+        assert_eq!(None, file.get("app", digest));
+    }
+}
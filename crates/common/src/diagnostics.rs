@@ -0,0 +1,199 @@
+//! Structured warnings about an app and its configuration
+
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+};
+
+/// The most diagnostics buffered while there's no listener. Later ones are
+/// only logged.
+const MAX_BUFFERED: usize = 1000;
+
+/// A warning about an app or its configuration, such as the use of a
+/// deprecated manifest field or a request to a host the component isn't
+/// allowed to reach.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    /// A stable, kebab-case identifier of the kind of problem, e.g.
+    /// `outbound-host-not-allowed`.
+    pub code: &'static str,
+    /// The component the problem is with, if any.
+    pub component: Option<String>,
+    /// What the problem is.
+    pub message: String,
+    /// How to fix the problem, if known.
+    pub suggestion: Option<String>,
+}
+
+impl Diagnostic {
+    /// Creates a diagnostic with the given code and message.
+    pub fn new(code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            component: None,
+            message: message.into(),
+            suggestion: None,
+        }
+    }
+
+    /// Sets the component the problem is with.
+    pub fn component(mut self, component_id: impl Into<String>) -> Self {
+        self.component = Some(component_id.into());
+        self
+    }
+
+    /// Sets how to fix the problem.
+    pub fn suggestion(mut self, suggestion: impl Into<String>) -> Self {
+        self.suggestion = Some(suggestion.into());
+        self
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.component {
+            Some(component_id) => write!(f, "component {component_id:?}: {}", self.message)?,
+            None => write!(f, "{}", self.message)?,
+        }
+        write!(f, " [{}]", self.code)?;
+        if let Some(suggestion) = &self.suggestion {
+            write!(f, "\n  help: {suggestion}")?;
+        }
+        Ok(())
+    }
+}
+
+type Listener = Arc<dyn Fn(&Diagnostic) + Send + Sync>;
+
+/// Where an app's diagnostics are reported.
+///
+/// Diagnostics are passed to the listener if one is set, or else buffered
+/// until [`take`](Self::take)n. Clones share the same buffer and listener, so
+/// each app should have its own handle. Diagnostics that are never taken or
+/// heard are logged when the last clone is dropped.
+#[derive(Clone, Default)]
+pub struct Diagnostics(Arc<Mutex<State>>);
+
+#[derive(Default)]
+struct State {
+    buffered: Vec<Diagnostic>,
+    listener: Option<Listener>,
+}
+
+impl Diagnostics {
+    /// Creates a handle with no buffered diagnostics or listener.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reports a diagnostic.
+    ///
+    /// The diagnostic is passed to the listener if one is set, or else
+    /// buffered. Buffered duplicates are dropped.
+    pub fn report(&self, diagnostic: Diagnostic) {
+        let mut state = self.0.lock().unwrap();
+        if let Some(listener) = state.listener.clone() {
+            // Don't hold the lock while the listener runs, in case it reports.
+            drop(state);
+            listener(&diagnostic);
+        } else if state.buffered.len() >= MAX_BUFFERED {
+            log(&diagnostic);
+        } else if !state.buffered.contains(&diagnostic) {
+            state.buffered.push(diagnostic);
+        }
+    }
+
+    /// Takes the diagnostics buffered so far, in the order they were reported.
+    pub fn take(&self) -> Vec<Diagnostic> {
+        std::mem::take(&mut self.0.lock().unwrap().buffered)
+    }
+
+    /// Passes diagnostics reported from now on to `listener`, instead of
+    /// buffering them. Already buffered diagnostics remain buffered.
+    pub fn set_listener(&self, listener: impl Fn(&Diagnostic) + Send + Sync + 'static) {
+        self.0.lock().unwrap().listener = Some(Arc::new(listener));
+    }
+
+    /// Goes back to buffering diagnostics reported from now on.
+    pub fn clear_listener(&self) {
+        self.0.lock().unwrap().listener = None;
+    }
+}
+
+impl fmt::Debug for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.0.lock().unwrap();
+        f.debug_struct("Diagnostics")
+            .field("buffered", &state.buffered)
+            .field("listener", &state.listener.is_some())
+            .finish()
+    }
+}
+
+impl Drop for State {
+    fn drop(&mut self) {
+        for diagnostic in &self.buffered {
+            log(diagnostic);
+        }
+    }
+}
+
+fn log(diagnostic: &Diagnostic) {
+    tracing::warn!(
+        code = diagnostic.code,
+        component = diagnostic.component.as_deref(),
+        suggestion = diagnostic.suggestion.as_deref(),
+        "{}",
+        diagnostic.message
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diagnostics_are_displayed_with_context() {
+        let diagnostic = Diagnostic::new("example", "something is wrong")
+            .component("hello")
+            .suggestion("fix it");
+        assert_eq!(
+            "component \"hello\": something is wrong [example]\n  help: fix it",
+            diagnostic.to_string()
+        );
+        assert_eq!(
+            "something is wrong [example]",
+            Diagnostic::new("example", "something is wrong").to_string()
+        );
+    }
+
+    #[test]
+    fn diagnostics_are_buffered_until_listened_for() {
+        let diagnostics = Diagnostics::new();
+        let diagnostic = Diagnostic::new("example", "something is wrong");
+        diagnostics.report(diagnostic.clone());
+        diagnostics.clone().report(diagnostic.clone());
+        assert_eq!(vec![diagnostic.clone()], diagnostics.take());
+        assert!(diagnostics.take().is_empty());
+
+        let heard = Arc::new(Mutex::new(vec![]));
+        let listener_heard = heard.clone();
+        diagnostics.set_listener(move |diagnostic| {
+            listener_heard.lock().unwrap().push(diagnostic.clone())
+        });
+        diagnostics.report(diagnostic.clone());
+        diagnostics.report(diagnostic.clone());
+        diagnostics.clear_listener();
+        assert_eq!(vec![diagnostic.clone(), diagnostic], *heard.lock().unwrap());
+        assert!(diagnostics.take().is_empty());
+    }
+
+    #[test]
+    fn apps_have_their_own_diagnostics() {
+        let (first, second) = (Diagnostics::new(), Diagnostics::new());
+        let diagnostic = Diagnostic::new("example", "something is wrong");
+        first.report(diagnostic.clone());
+        assert!(second.take().is_empty());
+        assert_eq!(vec![diagnostic], first.take());
+    }
+}
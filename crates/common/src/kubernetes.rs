@@ -0,0 +1,138 @@
+//! Detects metadata about the Kubernetes pod Spin is running in
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+/// Set by Kubernetes in every container.
+const SERVICE_HOST_ENV: &str = "KUBERNETES_SERVICE_HOST";
+/// The directory of the downward API volume to read pod labels from.
+const PODINFO_DIR_ENV: &str = "SPIN_K8S_PODINFO_DIR";
+const DEFAULT_PODINFO_DIR: &str = "/etc/podinfo";
+/// The file every pod's service account namespace is mounted to, unless
+/// service account tokens aren't mounted.
+const SERVICE_ACCOUNT_NAMESPACE_FILE: &str =
+    "/var/run/secrets/kubernetes.io/serviceaccount/namespace";
+
+/// Metadata about the Kubernetes pod Spin is running in.
+///
+/// The pod name and namespace are read from the `POD_NAME` and `POD_NAMESPACE`
+/// environment variables, which a pod spec can set from the downward API,
+/// falling back to the hostname and the service account namespace. Labels are
+/// read from the `labels` file of a downward API volume mounted at
+/// `/etc/podinfo`, or the directory given by `SPIN_K8S_PODINFO_DIR`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PodMetadata {
+    /// The pod name.
+    pub name: String,
+    /// The namespace of the pod, if known.
+    pub namespace: Option<String>,
+    /// The pod labels, if a downward API volume provides them.
+    pub labels: BTreeMap<String, String>,
+}
+
+impl PodMetadata {
+    /// Returns the metadata of the pod Spin is running in, or `None` if it
+    /// isn't running in Kubernetes. It is only detected once per process.
+    pub fn detect() -> Option<&'static PodMetadata> {
+        static METADATA: OnceLock<Option<PodMetadata>> = OnceLock::new();
+        METADATA
+            .get_or_init(|| {
+                std::env::var_os(SERVICE_HOST_ENV)?;
+                let podinfo_dir = std::env::var_os(PODINFO_DIR_ENV)
+                    .map(PathBuf::from)
+                    .unwrap_or_else(|| PathBuf::from(DEFAULT_PODINFO_DIR));
+                Some(Self::read(&podinfo_dir))
+            })
+            .as_ref()
+    }
+
+    fn read(podinfo_dir: &Path) -> Self {
+        let name = env_var("POD_NAME")
+            .or_else(|| env_var("HOSTNAME"))
+            .unwrap_or_default();
+        let namespace = env_var("POD_NAMESPACE").or_else(|| {
+            std::fs::read_to_string(SERVICE_ACCOUNT_NAMESPACE_FILE)
+                .ok()
+                .map(|ns| ns.trim().to_owned())
+                .filter(|ns| !ns.is_empty())
+        });
+        let labels = std::fs::read_to_string(podinfo_dir.join("labels"))
+            .map(|labels| parse_downward_api_map(&labels))
+            .unwrap_or_default();
+        Self {
+            name,
+            namespace,
+            labels,
+        }
+    }
+}
+
+fn env_var(name: &str) -> Option<String> {
+    std::env::var(name).ok().filter(|value| !value.is_empty())
+}
+
+/// Parses a downward API volume file of labels or annotations, which has a
+/// `key="value"` line for each, with the value quoted and escaped as a Go
+/// string.
+fn parse_downward_api_map(text: &str) -> BTreeMap<String, String> {
+    text.lines()
+        .filter_map(|line| {
+            let (key, value) = line.split_once('=')?;
+            let value = value.trim();
+            let value = value
+                .strip_prefix('"')
+                .and_then(|v| v.strip_suffix('"'))
+                .map(unescape)
+                .unwrap_or_else(|| value.to_owned());
+            Some((key.trim().to_owned(), value))
+        })
+        .collect()
+}
+
+fn unescape(quoted: &str) -> String {
+    let mut unescaped = String::with_capacity(quoted.len());
+    let mut chars = quoted.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => unescaped.push('\n'),
+            Some('t') => unescaped.push('\t'),
+            Some(escaped) => unescaped.push(escaped),
+            None => unescaped.push(c),
+        }
+    }
+    unescaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_downward_api_labels() {
+        let labels = parse_downward_api_map(
+            "app=\"hello\"\npod-template-hash=\"5d8f9c\"\nnote=\"say \\\"hi\\\"\"\n",
+        );
+        assert_eq!(3, labels.len());
+        assert_eq!("hello", labels["app"]);
+        assert_eq!("5d8f9c", labels["pod-template-hash"]);
+        assert_eq!("say \"hi\"", labels["note"]);
+    }
+
+    #[test]
+    fn reads_labels_from_podinfo_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("labels"), "app=\"hello\"\n").unwrap();
+        let metadata = PodMetadata::read(dir.path());
+        assert_eq!(
+            Some("hello"),
+            metadata.labels.get("app").map(String::as_str)
+        );
+    }
+}
@@ -9,7 +9,10 @@
 // - Code should have at least 2 dependents
 
 pub mod arg_parser;
+pub mod build_info;
 pub mod data_dir;
+pub mod diagnostics;
+pub mod kubernetes;
 pub mod paths;
 pub mod sha256;
 pub mod sloth;
@@ -33,6 +33,34 @@ pub async fn compose<'a, L: ComponentSourceLoader>(
     Composer::new(loader).compose(component).await
 }
 
+/// Composes a Spin AppComponent with its dependencies, as [`compose`] does,
+/// and then wraps it in the given middleware components, outermost first.
+///
+/// Each middleware component is composed with its own dependencies, and then
+/// has its imports which the wrapped component exports, such as
+/// `wasi:http/incoming-handler`, satisfied by the wrapped component. The
+/// result exports the outermost middleware's exports in place of the
+/// component's own, so the middleware sees every request before the
+/// component does, and every response after. The component itself is left
+/// unmodified.
+///
+/// The result is instantiated as the wrapped component, so the middleware
+/// runs with that component's configuration, such as its allowed outbound
+/// hosts, key-value stores, variables and files. Middleware components may
+/// not declare configuration of their own.
+pub async fn compose_with_middleware<'a, L: ComponentSourceLoader>(
+    loader: &'a L,
+    component: &LockedComponent,
+    middleware: &[&LockedComponent],
+) -> Result<Vec<u8>, ComposeError> {
+    let mut composed = compose(loader, component).await?;
+    for wrapper in middleware.iter().rev() {
+        let wrapper_source = compose(loader, wrapper).await?;
+        composed = apply_middleware(&component.id, composed, &wrapper.id, wrapper_source)?;
+    }
+    Ok(composed)
+}
+
 /// This trait is used to load component source code from a locked component source across various embdeddings.
 #[async_trait::async_trait]
 pub trait ComponentSourceLoader {
@@ -66,6 +94,13 @@ pub enum ComposeError {
         export_name: String,
         import_name: String,
     },
+    /// A middleware component doesn't import anything the component it wraps
+    /// exports.
+    #[error("middleware component '{middleware_id}' doesn't import any interface that component '{component_id}' exports, such as 'wasi:http/incoming-handler'")]
+    UnpluggableMiddleware {
+        component_id: String,
+        middleware_id: String,
+    },
     /// An error occurred when building the composition graph
     #[error("an error occurred when preparing dependencies")]
     PrepareError(#[source] anyhow::Error),
@@ -428,6 +463,49 @@ fn apply_deny_all_adapter(
     Ok(bytes)
 }
 
+// Plugs the (already composed) inner component into the imports of the
+// middleware component.
+fn apply_middleware(
+    component_id: &str,
+    inner_source: Vec<u8>,
+    middleware_id: &str,
+    middleware_source: Vec<u8>,
+) -> Result<Vec<u8>, ComposeError> {
+    let mut graph = CompositionGraph::new();
+
+    let inner_package = Package::from_bytes(component_id, None, inner_source, graph.types_mut())
+        .map_err(ComposeError::PrepareError)?;
+    let inner_id = graph
+        .register_package(inner_package)
+        .map_err(|e| ComposeError::PrepareError(e.into()))?;
+
+    let middleware_package =
+        Package::from_bytes(middleware_id, None, middleware_source, graph.types_mut())
+            .map_err(ComposeError::PrepareError)?;
+    let middleware_package_id = graph
+        .register_package(middleware_package)
+        .map_err(|e| ComposeError::PrepareError(e.into()))?;
+
+    match wac_graph::plug(&mut graph, vec![inner_id], middleware_package_id) {
+        Err(wac_graph::PlugError::NoPlugHappened) => {
+            return Err(ComposeError::UnpluggableMiddleware {
+                component_id: component_id.to_owned(),
+                middleware_id: middleware_id.to_owned(),
+            });
+        }
+        Err(other) => {
+            return Err(ComposeError::PrepareError(anyhow::anyhow!(
+                "failed to plug component '{component_id}' into middleware '{middleware_id}': {other:?}"
+            )));
+        }
+        Ok(_) => {}
+    }
+
+    graph
+        .encode(Default::default())
+        .map_err(|e| ComposeError::EncodeError(e.into()))
+}
+
 enum ImportName {
     Plain(KebabId),
     Package {
@@ -545,4 +623,78 @@ mod test {
             }
         }
     }
+
+    /// A component exporting a `test:middleware/handler` that returns its
+    /// argument.
+    const HANDLER: &str = r#"
+        (component
+          (core module $m
+            (func (export "handle") (param i32) (result i32) local.get 0))
+          (core instance $i (instantiate $m))
+          (func $handle (param "x" s32) (result s32) (canon lift (core func $i "handle")))
+          (instance $handler (export "handle" (func $handle)))
+          (export "test:middleware/handler" (instance $handler)))
+    "#;
+
+    /// A component wrapping a `test:middleware/handler`, adding one to what it
+    /// returns.
+    const MIDDLEWARE: &str = r#"
+        (component
+          (import "test:middleware/handler" (instance $inner
+            (export "handle" (func (param "x" s32) (result s32)))))
+          (core func $inner_handle (canon lower (func $inner "handle")))
+          (core module $m
+            (import "inner" "handle" (func $inner (param i32) (result i32)))
+            (func (export "handle") (param i32) (result i32)
+              local.get 0
+              call $inner
+              i32.const 1
+              i32.add))
+          (core instance $i (instantiate $m
+            (with "inner" (instance (export "handle" (func $inner_handle))))))
+          (func $handle (param "x" s32) (result s32) (canon lift (core func $i "handle")))
+          (instance $handler (export "handle" (func $handle)))
+          (export "test:middleware/handler" (instance $handler)))
+    "#;
+
+    /// Returns the names of the component's imports and exports.
+    fn imports_and_exports(source: Vec<u8>) -> (Vec<String>, Vec<String>) {
+        let mut types = wac_graph::types::Types::default();
+        let package = Package::from_bytes("composed", None, source, &mut types).unwrap();
+        let world = &types[package.ty()];
+        (
+            world.imports.keys().cloned().collect(),
+            world.exports.keys().cloned().collect(),
+        )
+    }
+
+    #[test]
+    fn middleware_wraps_the_component() {
+        let handler = wat::parse_str(HANDLER).unwrap();
+        let middleware = wat::parse_str(MIDDLEWARE).unwrap();
+        let (imports, _) = imports_and_exports(middleware.clone());
+        assert_eq!(vec!["test:middleware/handler"], imports);
+
+        let composed = apply_middleware("web", handler, "auth", middleware.clone()).unwrap();
+        let (imports, exports) = imports_and_exports(composed.clone());
+        assert!(imports.is_empty(), "{imports:?}");
+        assert_eq!(vec!["test:middleware/handler"], exports);
+
+        // Middleware can wrap middleware.
+        let twice = apply_middleware("web", composed, "auth", middleware).unwrap();
+        let (imports, exports) = imports_and_exports(twice);
+        assert!(imports.is_empty(), "{imports:?}");
+        assert_eq!(vec!["test:middleware/handler"], exports);
+    }
+
+    #[test]
+    fn middleware_must_import_what_the_component_exports() {
+        let handler = wat::parse_str(HANDLER).unwrap();
+        let unrelated = wat::parse_str("(component)").unwrap();
+        let err = apply_middleware("web", handler, "auth", unrelated).unwrap_err();
+        assert!(
+            matches!(err, ComposeError::UnpluggableMiddleware { .. }),
+            "{err:?}"
+        );
+    }
 }
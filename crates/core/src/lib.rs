@@ -63,6 +63,28 @@ impl Config {
         Ok(())
     }
 
+    /// Capture a Wasm core dump when a guest traps.
+    ///
+    /// Core dumps can be retrieved from trap errors with
+    /// [`Store::serialize_core_dump`].
+    pub fn enable_core_dumps(&mut self) -> &mut Self {
+        self.inner.coredump_on_trap(true);
+        self
+    }
+
+    /// Prepare guests for source-level debugging with a native debugger.
+    ///
+    /// This emits DWARF debug info for compiled guest code, which debuggers
+    /// such as LLDB and GDB read through the JIT debugging interface, and
+    /// disables optimizations that would obscure guest variables and control
+    /// flow.
+    pub fn enable_debug_info(&mut self) -> &mut Self {
+        self.inner
+            .debug_info(true)
+            .cranelift_opt_level(wasmtime::OptLevel::None);
+        self
+    }
+
     /// Disable the pooling instance allocator.
     pub fn disable_pooling(&mut self) -> &mut Self {
         self.inner
@@ -200,6 +222,7 @@ fn use_pooling_allocator_by_default() -> bool {
 #[derive(Default)]
 pub struct State {
     store_limits: limits::StoreLimitsAsync,
+    guest_profile: Option<store::GuestProfile>,
 }
 
 impl State {
@@ -1,5 +1,13 @@
 use anyhow::Result;
-use std::time::{Duration, Instant};
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+use wasmtime::{component::Component, GuestProfiler, UpdateDeadline};
 
 use crate::{limits::StoreLimitsAsync, State, WasmtimeEngine};
 
@@ -15,6 +23,10 @@ use crate::EngineBuilder;
 pub struct Store<T> {
     inner: wasmtime::Store<T>,
     epoch_tick_interval: Duration,
+    /// While profiling, epoch deadlines are used for sampling, so the
+    /// execution deadline is tracked here in remaining ticks instead.
+    profiling_deadline_ticks: Option<Arc<AtomicU64>>,
+    core_dump_dir: Option<PathBuf>,
 }
 
 impl<T> Store<T> {
@@ -36,7 +48,28 @@ impl<T> Store<T> {
             let ticks = ticks.min(u64::MAX as u128) as u64;
             ticks + 1 // Add one to allow for current partially-completed tick
         };
-        self.inner.set_epoch_deadline(ticks);
+        match &self.profiling_deadline_ticks {
+            Some(remaining) => remaining.store(ticks, Ordering::Relaxed),
+            None => self.inner.set_epoch_deadline(ticks),
+        }
+    }
+
+    /// The directory core dumps of this store's traps should be written to,
+    /// if set with [`StoreBuilder::core_dump_dir`].
+    pub fn core_dump_dir(&self) -> Option<&Path> {
+        self.core_dump_dir.as_deref()
+    }
+
+    /// Serializes the Wasm core dump attached to `err`, if any.
+    ///
+    /// Traps only carry core dumps if they are enabled with
+    /// [`crate::Config::enable_core_dumps`]. The dump is in the standard
+    /// [Wasm core dump format] and its process is identified by `name`.
+    ///
+    /// [Wasm core dump format]: https://github.com/WebAssembly/tool-conventions/blob/main/Coredump.md
+    pub fn serialize_core_dump(&mut self, err: &anyhow::Error, name: &str) -> Option<Vec<u8>> {
+        let core_dump = err.downcast_ref::<wasmtime::WasmCoreDump>()?;
+        Some(core_dump.serialize(&mut self.inner, name))
     }
 
     /// Provides access to the inner [`wasmtime::Store`]'s data.
@@ -83,6 +116,8 @@ pub struct StoreBuilder {
     engine: WasmtimeEngine,
     epoch_tick_interval: Duration,
     store_limits: StoreLimitsAsync,
+    guest_profile: Option<GuestProfile>,
+    core_dump_dir: Option<PathBuf>,
 }
 
 impl StoreBuilder {
@@ -92,6 +127,8 @@ impl StoreBuilder {
             engine,
             epoch_tick_interval,
             store_limits: StoreLimitsAsync::default(),
+            guest_profile: None,
+            core_dump_dir: None,
         }
     }
 
@@ -103,12 +140,42 @@ impl StoreBuilder {
         self.store_limits = StoreLimitsAsync::new(Some(max_memory_size), None);
     }
 
+    /// Enables sampling profiling of the guest.
+    ///
+    /// Guest stacks are sampled every epoch tick (see
+    /// [`EngineBuilder::epoch_tick_interval`]). When the built [`Store`] is
+    /// dropped, the profile is written to `output` in the [Firefox Profiler]
+    /// format, which can also be viewed as a flamegraph with e.g. speedscope.
+    ///
+    /// [Firefox Profiler]: https://profiler.firefox.com/
+    pub fn enable_guest_profiling(
+        &mut self,
+        component_name: &str,
+        component: Component,
+        output: impl Into<PathBuf>,
+    ) {
+        let profiler =
+            GuestProfiler::new_component(component_name, self.epoch_tick_interval, component, []);
+        self.guest_profile = Some(GuestProfile {
+            profiler: Some(profiler),
+            output: output.into(),
+        });
+    }
+
+    /// Sets the directory core dumps of the built [`Store`]'s traps should be
+    /// written to; see [`Store::core_dump_dir`].
+    pub fn core_dump_dir(&mut self, dir: impl Into<PathBuf>) {
+        self.core_dump_dir = Some(dir.into());
+    }
+
     /// Builds a [`Store`] from this builder with given host state data.
     ///
     /// The `T` parameter must provide access to a [`State`] via `impl
     /// AsMut<State>`.
     pub fn build<T: AsState>(self, mut data: T) -> Result<Store<T>> {
         data.as_state().store_limits = self.store_limits;
+        let profiling = self.guest_profile.is_some();
+        data.as_state().guest_profile = self.guest_profile;
 
         let mut inner = wasmtime::Store::new(&self.engine, data);
         inner.limiter_async(|data| &mut data.as_state().store_limits);
@@ -117,15 +184,69 @@ impl StoreBuilder {
         // or execution will trap immediately. Since this is a delta, we need
         // to avoid overflow so we'll use 2^63 which is still "practically
         // forever" for any plausible tick interval.
-        inner.set_epoch_deadline(u64::MAX / 2);
+        let profiling_deadline_ticks = if profiling {
+            let remaining = Arc::new(AtomicU64::new(u64::MAX / 2));
+            let interval = self.epoch_tick_interval;
+            inner.epoch_deadline_callback({
+                let remaining = remaining.clone();
+                move |mut store| {
+                    let guest_profile = store.data_mut().as_state().guest_profile.as_mut();
+                    if let Some(mut profiler) = guest_profile.and_then(|p| p.profiler.take()) {
+                        profiler.sample(&store, interval);
+                        if let Some(p) = store.data_mut().as_state().guest_profile.as_mut() {
+                            p.profiler = Some(profiler);
+                        }
+                    }
+                    match remaining.load(Ordering::Relaxed) {
+                        0 | 1 => Err(wasmtime::Trap::Interrupt.into()),
+                        ticks => {
+                            remaining.store(ticks - 1, Ordering::Relaxed);
+                            Ok(UpdateDeadline::Continue(1))
+                        }
+                    }
+                }
+            });
+            inner.set_epoch_deadline(1);
+            Some(remaining)
+        } else {
+            inner.set_epoch_deadline(u64::MAX / 2);
+            None
+        };
 
         Ok(Store {
             inner,
             epoch_tick_interval: self.epoch_tick_interval,
+            profiling_deadline_ticks,
+            core_dump_dir: self.core_dump_dir,
         })
     }
 }
 
+/// A guest profile being collected for a [`Store`].
+pub(crate) struct GuestProfile {
+    /// Taken while sampling.
+    profiler: Option<GuestProfiler>,
+    output: PathBuf,
+}
+
+impl Drop for GuestProfile {
+    fn drop(&mut self) {
+        let Some(profiler) = self.profiler.take() else {
+            return;
+        };
+        let result = std::fs::File::create(&self.output)
+            .map_err(anyhow::Error::from)
+            .and_then(|file| profiler.finish(std::io::BufWriter::new(file)));
+        match result {
+            Ok(()) => tracing::info!("Wrote guest profile to {:?}", self.output),
+            Err(err) => tracing::error!(
+                "Failed to write guest profile to {:?}: {err:?}",
+                self.output
+            ),
+        }
+    }
+}
+
 /// For consumers that need to use a type other than [`State`] as the [`Store`]
 /// `data`, this trait must be implemented for that type.
 pub trait AsState {
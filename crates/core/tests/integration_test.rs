@@ -18,7 +18,7 @@ async fn test_max_memory_size_obeyed() {
     let alloc = max / 10;
     run_test(
         ["alloc", &format!("{alloc}")],
-        |store_builder| {
+        |store_builder, _| {
             store_builder.max_memory_size(max);
         },
         |_| {},
@@ -33,7 +33,7 @@ async fn test_max_memory_size_violated() {
     let alloc = max * 2;
     let err = run_test(
         ["alloc", &format!("{alloc}")],
-        |store_builder| {
+        |store_builder, _| {
             store_builder.max_memory_size(max);
         },
         |_| {},
@@ -52,7 +52,7 @@ async fn test_max_memory_size_violated() {
 async fn test_set_deadline_obeyed() {
     run_test(
         ["sleep", "20"],
-        |_| {},
+        |_, _| {},
         |store| {
             store.set_deadline(Instant::now() + Duration::from_millis(10000));
         },
@@ -65,7 +65,44 @@ async fn test_set_deadline_obeyed() {
 async fn test_set_deadline_violated() {
     let err = run_test(
         ["sleep", "100"],
+        |_, _| {},
+        |store| {
+            store.set_deadline(Instant::now() + Duration::from_millis(10));
+        },
+    )
+    .await
+    .unwrap_err();
+    let trap = err.downcast::<Trap>().expect("trap");
+    assert_eq!(trap, Trap::Interrupt);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_guest_profile_written() {
+    let dir = tempfile::tempdir().unwrap();
+    let output = dir.path().join("profile.json");
+    run_test(
+        ["sleep", "20"],
+        |store_builder, component| {
+            store_builder.enable_guest_profiling("test-component", component.clone(), &output);
+        },
         |_| {},
+    )
+    .await
+    .unwrap();
+    let profile: serde_json::Value =
+        serde_json::from_slice(&std::fs::read(&output).unwrap()).unwrap();
+    assert!(profile.is_object());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_set_deadline_violated_while_profiling() {
+    let dir = tempfile::tempdir().unwrap();
+    let output = dir.path().join("profile.json");
+    let err = run_test(
+        ["sleep", "100"],
+        |store_builder, component| {
+            store_builder.enable_guest_profiling("test-component", component.clone(), &output);
+        },
         |store| {
             store.set_deadline(Instant::now() + Duration::from_millis(10));
         },
@@ -78,7 +115,7 @@ async fn test_set_deadline_violated() {
 
 #[tokio::test(flavor = "multi_thread")]
 async fn test_panic() {
-    let err = run_test(["panic"], |_| {}, |_| {}).await.unwrap_err();
+    let err = run_test(["panic"], |_, _| {}, |_| {}).await.unwrap_err();
     let trap = err.downcast::<Trap>().expect("trap");
     assert_eq!(trap, Trap::UnreachableCodeReached);
 }
@@ -107,7 +144,7 @@ impl AsInstanceState<TestFactorsInstanceState> for TestState {
 
 async fn run_test(
     args: impl IntoIterator<Item = &'_ str>,
-    update_store_builder: impl FnOnce(&mut StoreBuilder),
+    update_store_builder: impl FnOnce(&mut StoreBuilder, &Component),
     update_store: impl FnOnce(&mut Store<TestState>),
 ) -> anyhow::Result<()> {
     let mut factors = TestFactors {
@@ -123,8 +160,13 @@ async fn run_test(
     factors.init(builder.linker())?;
     let engine = builder.build();
 
+    let module_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("../../target/test-programs/core-wasi-test.wasm");
+    let component = spin_componentize::componentize_command(&fs::read(module_path).await?)?;
+    let component = Component::new(engine.as_ref(), &component)?;
+
     let mut store_builder = engine.store_builder();
-    update_store_builder(&mut store_builder);
+    update_store_builder(&mut store_builder, &component);
 
     let locked: LockedApp = serde_json::from_value(json!({
         "spin_lock_version": 1,
@@ -150,10 +192,6 @@ async fn run_test(
     let mut store = store_builder.build(state)?;
     update_store(&mut store);
 
-    let module_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
-        .join("../../target/test-programs/core-wasi-test.wasm");
-    let component = spin_componentize::componentize_command(&fs::read(module_path).await?)?;
-    let component = Component::new(engine.as_ref(), &component)?;
     let instance_pre = engine.instantiate_pre(&component)?;
     let instance = instance_pre.instantiate_async(&mut store).await?;
     let func = {
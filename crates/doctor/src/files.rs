@@ -0,0 +1,2 @@
+/// Diagnose files mounts of paths that don't exist.
+pub mod missing;
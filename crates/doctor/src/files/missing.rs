@@ -0,0 +1,149 @@
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use spin_common::{paths::parent_dir, ui::quoted_path};
+use spin_manifest::schema::v2::WasiFilesMount;
+
+use crate::{Diagnosis, Diagnostic, PatientApp, Treatment};
+
+/// FilesMissingDiagnostic detects component files mounts of paths that don't
+/// exist.
+#[derive(Default)]
+pub struct FilesMissingDiagnostic;
+
+#[async_trait]
+impl Diagnostic for FilesMissingDiagnostic {
+    type Diagnosis = FilesMissing;
+
+    async fn diagnose(&self, patient: &PatientApp) -> Result<Vec<Self::Diagnosis>> {
+        let manifest = spin_manifest::manifest_from_str(&patient.manifest_doc.to_string())?;
+        let app_dir = parent_dir(&patient.manifest_path)?;
+
+        let mut diags = vec![];
+        for (id, component) in manifest.components {
+            for mount in component.files {
+                let missing = match mount {
+                    WasiFilesMount::Pattern(pattern) => {
+                        let abs_pattern = format!(
+                            "{}/{pattern}",
+                            glob::Pattern::escape(&app_dir.to_string_lossy())
+                        );
+                        let mut matches = glob::glob(&abs_pattern)
+                            .with_context(|| format!("invalid files pattern {pattern:?}"))?;
+                        matches
+                            .next()
+                            .is_none()
+                            .then_some(MissingFiles::Pattern(pattern))
+                    }
+                    WasiFilesMount::Placement { source, .. } => {
+                        let abs_source = app_dir.join(&source);
+                        (!abs_source.exists())
+                            .then_some(MissingFiles::Directory { source, abs_source })
+                    }
+                };
+                if let Some(missing) = missing {
+                    diags.push(FilesMissing {
+                        component_id: id.to_string(),
+                        missing,
+                    });
+                }
+            }
+        }
+        Ok(diags)
+    }
+}
+
+/// FilesMissing represents a component files mount of a path that doesn't
+/// exist.
+#[derive(Debug)]
+pub struct FilesMissing {
+    component_id: String,
+    missing: MissingFiles,
+}
+
+#[derive(Debug)]
+enum MissingFiles {
+    /// `files = ["pattern"]` matching no files
+    Pattern(String),
+    /// `files = [{ source = "dir", ... }]` of a directory that doesn't exist
+    Directory { source: String, abs_source: PathBuf },
+}
+
+impl Diagnosis for FilesMissing {
+    fn description(&self) -> String {
+        let id = &self.component_id;
+        match &self.missing {
+            MissingFiles::Pattern(pattern) => {
+                format!("Component {id:?} files pattern {pattern:?} doesn't match any files")
+            }
+            MissingFiles::Directory { source, .. } => {
+                format!("Component {id:?} files source {source:?} doesn't exist")
+            }
+        }
+    }
+
+    fn treatment(&self) -> Option<&dyn Treatment> {
+        match self.missing {
+            MissingFiles::Directory { .. } => Some(self),
+            MissingFiles::Pattern(_) => None,
+        }
+    }
+}
+
+#[async_trait]
+impl Treatment for FilesMissing {
+    fn summary(&self) -> String {
+        match &self.missing {
+            MissingFiles::Directory { source, .. } => {
+                format!("Create an empty directory {source:?}")
+            }
+            MissingFiles::Pattern(_) => "[invalid treatment]".into(),
+        }
+    }
+
+    async fn dry_run(&self, _patient: &PatientApp) -> Result<String> {
+        match &self.missing {
+            MissingFiles::Directory { abs_source, .. } => {
+                Ok(format!("Create directory {}", quoted_path(abs_source)))
+            }
+            MissingFiles::Pattern(_) => anyhow::bail!("cannot be fixed"),
+        }
+    }
+
+    async fn treat(&self, _patient: &mut PatientApp) -> Result<()> {
+        match &self.missing {
+            MissingFiles::Directory { abs_source, .. } => std::fs::create_dir_all(abs_source)
+                .with_context(|| format!("failed to create {}", quoted_path(abs_source))),
+            MissingFiles::Pattern(_) => anyhow::bail!("cannot be fixed"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test::TestPatient;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_missing_files() {
+        let patient = TestPatient::from_toml_str(
+            r#"
+            spin_manifest_version = 2
+            [application]
+            name = "files-missing-test"
+            [[trigger.http]]
+            route = "/..."
+            component = "app"
+            [component.app]
+            source = "app.wasm"
+            files = ["does-not-exist/**/*", { source = "does-not-exist", destination = "/" }]
+            "#,
+        );
+        let diags = FilesMissingDiagnostic.diagnose(&patient).await.unwrap();
+        assert_eq!(2, diags.len(), "{diags:?}");
+        assert!(diags[0].treatment().is_none());
+        assert!(diags[1].treatment().is_some());
+    }
+}
@@ -8,8 +8,12 @@ use async_trait::async_trait;
 use spin_common::ui::quoted_path;
 use toml_edit::DocumentMut;
 
+/// Diagnoses for component files mounts problems.
+pub mod files;
 /// Diagnoses for app manifest format problems.
 pub mod manifest;
+/// Diagnoses for runtime config problems.
+pub mod runtime_config;
 /// Diagnose for Rust-specific problems.
 pub mod rustlang;
 /// Test helpers.
@@ -17,6 +21,9 @@ pub mod test;
 /// Diagnoses for Wasm source problems.
 pub mod wasm;
 
+/// The runtime config file that is checked if none is given.
+const DEFAULT_RUNTIME_CONFIG_FILE: &str = "runtime-config.toml";
+
 /// Configuration for an app to be checked for problems.
 pub struct Checkup {
     patient: PatientApp,
@@ -37,11 +44,21 @@ impl Checkup {
             .add_diagnostic::<manifest::upgrade::UpgradeDiagnostic>()
             .add_diagnostic::<manifest::version::VersionDiagnostic>()
             .add_diagnostic::<manifest::trigger::TriggerDiagnostic>()
+            .add_diagnostic::<manifest::outbound_hosts::OutboundHostsDiagnostic>()
+            .add_diagnostic::<runtime_config::labels::StoreLabelsDiagnostic>()
+            .add_diagnostic::<files::missing::FilesMissingDiagnostic>()
             .add_diagnostic::<rustlang::target::TargetDiagnostic>() // Do toolchain checks _before_ build check
             .add_diagnostic::<wasm::missing::WasmMissingDiagnostic>();
         Ok(checkup)
     }
 
+    /// Check the app against the given runtime config file, rather than the
+    /// `runtime-config.toml` next to the manifest, if any.
+    pub fn set_runtime_config_file(&mut self, path: impl Into<PathBuf>) -> &mut Self {
+        self.patient.runtime_config_path = Some(path.into());
+        self
+    }
+
     /// Returns the [`PatientApp`] being checked.
     pub fn patient(&self) -> &PatientApp {
         &self.patient
@@ -82,6 +99,8 @@ pub struct PatientApp {
     pub manifest_path: PathBuf,
     /// Parsed app manifest TOML document.
     pub manifest_doc: DocumentMut,
+    /// Path to the runtime config file the app is run with, if any.
+    pub runtime_config_path: Option<PathBuf>,
 }
 
 impl PatientApp {
@@ -107,9 +126,15 @@ impl PatientApp {
             )
         })?;
 
+        let runtime_config_path = path
+            .parent()
+            .map(|dir| dir.join(DEFAULT_RUNTIME_CONFIG_FILE))
+            .filter(|path| path.is_file());
+
         Ok(Self {
             manifest_path: path,
             manifest_doc,
+            runtime_config_path,
         })
     }
 }
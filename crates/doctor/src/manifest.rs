@@ -7,6 +7,8 @@ use toml_edit::DocumentMut;
 
 use crate::Treatment;
 
+/// Diagnose allowed outbound hosts that can never match.
+pub mod outbound_hosts;
 /// Diagnose app manifest trigger config problems.
 pub mod trigger;
 /// Diagnose old app manifest versions.
@@ -0,0 +1,353 @@
+use std::net::IpAddr;
+
+use anyhow::{bail, Context, Result};
+use async_trait::async_trait;
+use toml::Value;
+use toml_edit::DocumentMut;
+
+use crate::{Diagnosis, Diagnostic, PatientApp, Treatment};
+
+use super::ManifestTreatment;
+
+/// Schemes that Spin makes outbound connections with, and their default
+/// ports, if any.
+const KNOWN_SCHEMES: &[(&str, Option<u16>)] = &[
+    ("http", Some(80)),
+    ("https", Some(443)),
+    ("redis", Some(6379)),
+    ("mysql", Some(3306)),
+    ("postgres", Some(5432)),
+    ("mqtt", Some(1883)),
+    ("sftp", Some(22)),
+    ("smtp", Some(587)),
+    ("smtps", Some(465)),
+    ("tcp", None),
+    ("udp", None),
+];
+
+/// OutboundHostsDiagnostic detects `allowed_outbound_hosts` entries that can
+/// never match an outbound connection.
+#[derive(Default)]
+pub struct OutboundHostsDiagnostic;
+
+#[async_trait]
+impl Diagnostic for OutboundHostsDiagnostic {
+    type Diagnosis = OutboundHostUnmatchable;
+
+    async fn diagnose(&self, patient: &PatientApp) -> Result<Vec<Self::Diagnosis>> {
+        let manifest: Value = toml_edit::de::from_document(patient.manifest_doc.clone())?;
+
+        if manifest.get("spin_manifest_version") != Some(&Value::Integer(2)) {
+            // Manifest V1 is diagnosed by the upgrade check
+            return Ok(vec![]);
+        }
+        let Some(components) = manifest.get("component").and_then(|c| c.as_table()) else {
+            return Ok(vec![]);
+        };
+
+        let mut diags = vec![];
+        for (id, component) in components {
+            let hosts = component
+                .get("allowed_outbound_hosts")
+                .and_then(|hosts| hosts.as_array())
+                .into_iter()
+                .flatten()
+                .filter_map(|host| host.as_str());
+            for host in hosts {
+                if let Some(problem) = check_host(host) {
+                    diags.push(OutboundHostUnmatchable {
+                        component_id: id.clone(),
+                        host: host.to_owned(),
+                        problem,
+                    });
+                }
+            }
+        }
+        Ok(diags)
+    }
+}
+
+/// Why an allowed outbound host can never match.
+#[derive(Debug, PartialEq)]
+pub enum HostProblem {
+    /// The entry has no scheme. The fix adds the likeliest one.
+    MissingScheme(String),
+    /// Spin doesn't connect with the scheme. The fix, if any, corrects its
+    /// case.
+    UnknownScheme(Option<String>),
+    /// The entry has a path. The fix removes it.
+    HasPath(String),
+    /// The port isn't a valid port or range. The fix, if any, corrects an
+    /// empty range.
+    BadPort(Option<String>),
+    /// There is no port, and the scheme has no default port.
+    MissingPort,
+}
+
+impl HostProblem {
+    fn fixed_host(&self) -> Option<&str> {
+        match self {
+            Self::MissingScheme(fixed) | Self::HasPath(fixed) => Some(fixed),
+            Self::UnknownScheme(fixed) | Self::BadPort(fixed) => fixed.as_deref(),
+            Self::MissingPort => None,
+        }
+    }
+}
+
+/// Checks an allowed outbound host the way Spin parses it, returning why it
+/// can never match, if it can't.
+fn check_host(host: &str) -> Option<HostProblem> {
+    let host = host.trim();
+    if host.contains("{{") {
+        // Depends on variables, so can only be checked at runtime
+        return None;
+    }
+
+    let Some((scheme, rest)) = host.split_once("://") else {
+        return Some(HostProblem::MissingScheme(format!(
+            "{}://{host}",
+            likely_scheme(host)
+        )));
+    };
+
+    let default_port = if scheme == "*" {
+        None
+    } else {
+        match KNOWN_SCHEMES.iter().find(|(s, _)| *s == scheme) {
+            Some((_, port)) => *port,
+            None => {
+                let lower = scheme.to_ascii_lowercase();
+                let fixed = KNOWN_SCHEMES
+                    .iter()
+                    .any(|(s, _)| *s == lower)
+                    .then(|| format!("{lower}://{rest}"));
+                return Some(HostProblem::UnknownScheme(fixed));
+            }
+        }
+    };
+
+    let (name, port_and_path) = rest.rsplit_once(':').unwrap_or((rest, ""));
+    let (port, path) = port_and_path.split_once('/').unwrap_or((port_and_path, ""));
+    let name_path = name
+        .split_once('/')
+        .filter(|(ip, prefix)| !is_cidr(ip, prefix))
+        .map(|(_, path)| path)
+        .unwrap_or_default();
+    if !path.is_empty() || !name_path.is_empty() {
+        let name = name.split('/').next().unwrap_or_default();
+        let fixed = match port {
+            "" => format!("{scheme}://{name}"),
+            port => format!("{scheme}://{name}:{port}"),
+        };
+        return Some(HostProblem::HasPath(fixed));
+    }
+
+    match port {
+        "" if default_port.is_none() => Some(HostProblem::MissingPort),
+        "" | "*" => None,
+        port => match port.split_once("..") {
+            Some((start, end)) => match (parse_port(start), parse_port(end)) {
+                (Some(start), Some(end)) if start < end => None,
+                (Some(start), Some(end)) if start == end => Some(HostProblem::BadPort(Some(
+                    format!("{scheme}://{name}:{start}"),
+                ))),
+                _ => Some(HostProblem::BadPort(None)),
+            },
+            None => parse_port(port)
+                .is_none()
+                .then_some(HostProblem::BadPort(None)),
+        },
+    }
+}
+
+fn parse_port(port: &str) -> Option<u16> {
+    port.parse().ok().filter(|port| *port != 0)
+}
+
+fn is_cidr(ip: &str, prefix: &str) -> bool {
+    ip.parse::<IpAddr>().is_ok() && prefix.parse::<u8>().is_ok()
+}
+
+/// The scheme an entry without one most likely meant: the one whose default
+/// port it has, or else HTTPS, or HTTP for a non-default port.
+fn likely_scheme(host: &str) -> &'static str {
+    let port = host
+        .split('/')
+        .next()
+        .and_then(|authority| authority.rsplit_once(':'))
+        .and_then(|(_, port)| port.parse::<u16>().ok());
+    let Some(port) = port else {
+        return "https";
+    };
+    KNOWN_SCHEMES
+        .iter()
+        .find(|(_, default_port)| *default_port == Some(port))
+        .map(|(scheme, _)| *scheme)
+        .unwrap_or("http")
+}
+
+/// OutboundHostUnmatchable represents an `allowed_outbound_hosts` entry that
+/// can never match an outbound connection.
+#[derive(Debug)]
+pub struct OutboundHostUnmatchable {
+    component_id: String,
+    host: String,
+    problem: HostProblem,
+}
+
+impl Diagnosis for OutboundHostUnmatchable {
+    fn description(&self) -> String {
+        let Self {
+            component_id: id,
+            host,
+            ..
+        } = self;
+        let problem = match &self.problem {
+            HostProblem::MissingScheme(_) => "has no scheme (e.g. 'https://')",
+            HostProblem::UnknownScheme(_) => "has a scheme Spin doesn't connect with",
+            HostProblem::HasPath(_) => "has a path, which is not allowed",
+            HostProblem::BadPort(_) => "has an invalid port or empty port range",
+            HostProblem::MissingPort => "needs a port, as its scheme has no default port",
+        };
+        format!("Component {id:?} allowed outbound host {host:?} {problem}, so can never match")
+    }
+
+    fn treatment(&self) -> Option<&dyn Treatment> {
+        self.problem.fixed_host().map(|_| self as &dyn Treatment)
+    }
+}
+
+#[async_trait]
+impl ManifestTreatment for OutboundHostUnmatchable {
+    fn summary(&self) -> String {
+        match self.problem.fixed_host() {
+            Some(fixed) => format!(
+                "Replace {:?} with {fixed:?} for component {:?}",
+                self.host, self.component_id
+            ),
+            None => "[invalid treatment]".into(),
+        }
+    }
+
+    async fn treat_manifest(&self, doc: &mut DocumentMut) -> Result<()> {
+        let Some(fixed) = self.problem.fixed_host() else {
+            bail!("cannot be fixed");
+        };
+        let hosts = doc
+            .get_mut("component")
+            .and_then(|components| components.get_mut(&self.component_id))
+            .and_then(|component| component.get_mut("allowed_outbound_hosts"))
+            .and_then(|hosts| hosts.as_array_mut())
+            .with_context(|| {
+                format!(
+                    "couldn't find allowed_outbound_hosts for component {:?}",
+                    self.component_id
+                )
+            })?;
+        for host in hosts.iter_mut() {
+            if host.as_str() == Some(&self.host) {
+                let decor = host.decor().clone();
+                *host = fixed.into();
+                *host.decor_mut() = decor;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test::TestPatient;
+
+    use super::*;
+
+    #[test]
+    fn test_check_host() {
+        for ok in [
+            "https://example.com",
+            "http://localhost:3000",
+            "redis://*",
+            "*://example.com:*",
+            "tcp://10.0.0.0/8:5000",
+            "http://10.0.0.0/8",
+            "https://example.com/",
+            "mysql://db:3306..3310",
+            "https://{{ api_host }}",
+        ] {
+            assert_eq!(None, check_host(ok), "{ok}");
+        }
+
+        assert_eq!(
+            Some(HostProblem::MissingScheme("https://example.com".into())),
+            check_host("example.com")
+        );
+        assert_eq!(
+            Some(HostProblem::MissingScheme("redis://cache:6379".into())),
+            check_host("cache:6379")
+        );
+        assert_eq!(
+            Some(HostProblem::UnknownScheme(Some(
+                "https://example.com".into()
+            ))),
+            check_host("HTTPS://example.com")
+        );
+        assert_eq!(
+            Some(HostProblem::UnknownScheme(None)),
+            check_host("ftp://example.com")
+        );
+        assert_eq!(
+            Some(HostProblem::HasPath("https://example.com".into())),
+            check_host("https://example.com/api")
+        );
+        assert_eq!(
+            Some(HostProblem::HasPath("http://example.com:8080".into())),
+            check_host("http://example.com:8080/api")
+        );
+        assert_eq!(
+            Some(HostProblem::BadPort(None)),
+            check_host("http://example.com:99999")
+        );
+        assert_eq!(
+            Some(HostProblem::BadPort(Some("http://example.com:8000".into()))),
+            check_host("http://example.com:8000..8000")
+        );
+        assert_eq!(
+            Some(HostProblem::MissingPort),
+            check_host("tcp://example.com")
+        );
+        assert_eq!(
+            Some(HostProblem::MissingPort),
+            check_host("*://example.com")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_treatment() {
+        let mut patient = TestPatient::from_toml_str(
+            r#"
+            spin_manifest_version = 2
+            [application]
+            name = "outbound-hosts-test"
+            [[trigger.http]]
+            route = "/..."
+            component = "app"
+            [component.app]
+            source = "app.wasm"
+            allowed_outbound_hosts = ["https://example.com", "api.example.com", "ftp://example.com"]
+            "#,
+        );
+        let diags = OutboundHostsDiagnostic.diagnose(&patient).await.unwrap();
+        assert_eq!(2, diags.len(), "{diags:?}");
+        assert!(diags[1].treatment().is_none());
+
+        diags[0]
+            .treatment()
+            .unwrap()
+            .treat(&mut patient)
+            .await
+            .unwrap();
+        let diags = OutboundHostsDiagnostic.diagnose(&patient).await.unwrap();
+        assert_eq!(1, diags.len(), "{diags:?}");
+        assert_eq!("ftp://example.com", diags[0].host);
+    }
+}
@@ -0,0 +1,21 @@
+/// Diagnose key value store and SQLite database labels missing from runtime config.
+pub mod labels;
+
+use std::fs;
+
+use anyhow::{Context, Result};
+use spin_common::ui::quoted_path;
+
+use crate::PatientApp;
+
+/// Returns the runtime config the app is checked against, or an empty table
+/// if it has none.
+fn read_runtime_config(patient: &PatientApp) -> Result<toml::Table> {
+    let Some(path) = &patient.runtime_config_path else {
+        return Ok(Default::default());
+    };
+    let contents = fs::read_to_string(path)
+        .with_context(|| format!("Couldn't read runtime config file {}", quoted_path(path)))?;
+    toml::from_str(&contents)
+        .with_context(|| format!("Couldn't parse runtime config file {}", quoted_path(path)))
+}
@@ -0,0 +1,285 @@
+use std::{collections::BTreeMap, fs, path::PathBuf};
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use spin_common::ui::quoted_path;
+use toml_edit::{value, DocumentMut, Item, Table};
+
+use crate::{Diagnosis, Diagnostic, PatientApp, Treatment, DEFAULT_RUNTIME_CONFIG_FILE};
+
+use super::read_runtime_config;
+
+/// The label Spin provides a store or database for without runtime config.
+const DEFAULT_LABEL: &str = "default";
+
+/// StoreLabelsDiagnostic detects key value stores and SQLite databases that
+/// components use but that the runtime config doesn't provide.
+#[derive(Default)]
+pub struct StoreLabelsDiagnostic;
+
+#[async_trait]
+impl Diagnostic for StoreLabelsDiagnostic {
+    type Diagnosis = StoreLabelMissing;
+
+    async fn diagnose(&self, patient: &PatientApp) -> Result<Vec<Self::Diagnosis>> {
+        let manifest = spin_manifest::manifest_from_str(&patient.manifest_doc.to_string())?;
+        let runtime_config = read_runtime_config(patient)?;
+
+        // (kind, label) -> IDs of the components using it
+        let mut missing = BTreeMap::<(StoreKind, String), Vec<String>>::new();
+        for (id, component) in &manifest.components {
+            let labels = [
+                (StoreKind::KeyValue, &component.key_value_stores),
+                (StoreKind::Sqlite, &component.sqlite_databases),
+            ];
+            for (kind, labels) in labels {
+                for label in labels {
+                    if label != DEFAULT_LABEL && !kind.is_configured(&runtime_config, label) {
+                        missing
+                            .entry((kind, label.clone()))
+                            .or_default()
+                            .push(id.to_string());
+                    }
+                }
+            }
+        }
+
+        Ok(missing
+            .into_iter()
+            .map(|((kind, label), component_ids)| StoreLabelMissing {
+                kind,
+                label,
+                component_ids,
+                has_runtime_config: patient.runtime_config_path.is_some(),
+            })
+            .collect())
+    }
+}
+
+/// The kinds of labelled resources runtime config provides.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum StoreKind {
+    /// `key_value_stores = [...]`
+    KeyValue,
+    /// `sqlite_databases = [...]`
+    Sqlite,
+}
+
+impl StoreKind {
+    fn runtime_config_key(&self) -> &'static str {
+        match self {
+            Self::KeyValue => "key_value_store",
+            Self::Sqlite => "sqlite_database",
+        }
+    }
+
+    fn noun(&self) -> &'static str {
+        match self {
+            Self::KeyValue => "key value store",
+            Self::Sqlite => "SQLite database",
+        }
+    }
+
+    fn is_configured(&self, runtime_config: &toml::Table, label: &str) -> bool {
+        runtime_config
+            .get(self.runtime_config_key())
+            .and_then(|stores| stores.get(label))
+            .is_some()
+    }
+
+    /// Where a local store added by the treatment keeps its data, relative to
+    /// the runtime config file.
+    fn local_path(&self, label: &str) -> String {
+        match self {
+            Self::KeyValue => format!(".spin/{label}.db"),
+            Self::Sqlite => format!(".spin/{label}.sqlite.db"),
+        }
+    }
+}
+
+/// StoreLabelMissing represents a key value store or SQLite database label
+/// that components use but that the runtime config doesn't provide.
+#[derive(Debug)]
+pub struct StoreLabelMissing {
+    kind: StoreKind,
+    label: String,
+    component_ids: Vec<String>,
+    has_runtime_config: bool,
+}
+
+impl StoreLabelMissing {
+    /// The path of the runtime config file to add the store to, and its
+    /// contents before and after adding it.
+    fn treated_runtime_config(&self, patient: &PatientApp) -> Result<(PathBuf, String, String)> {
+        let path = match &patient.runtime_config_path {
+            Some(path) => path.clone(),
+            None => patient
+                .manifest_path
+                .with_file_name(DEFAULT_RUNTIME_CONFIG_FILE),
+        };
+        let before = if path.exists() {
+            fs::read_to_string(&path).with_context(|| {
+                format!("Couldn't read runtime config file {}", quoted_path(&path))
+            })?
+        } else {
+            String::new()
+        };
+
+        let mut doc: DocumentMut = before.parse().with_context(|| {
+            format!("Couldn't parse runtime config file {}", quoted_path(&path))
+        })?;
+        let stores = doc
+            .entry(self.kind.runtime_config_key())
+            .or_insert_with(|| {
+                let mut stores = Table::new();
+                stores.set_implicit(true);
+                Item::Table(stores)
+            })
+            .as_table_mut()
+            .with_context(|| format!("{} is not a table", self.kind.runtime_config_key()))?;
+        let mut store = Table::new();
+        store.insert("type", value("spin"));
+        store.insert("path", value(self.kind.local_path(&self.label)));
+        stores.insert(&self.label, Item::Table(store));
+
+        Ok((path, before, doc.to_string()))
+    }
+}
+
+impl Diagnosis for StoreLabelMissing {
+    fn description(&self) -> String {
+        let components = self
+            .component_ids
+            .iter()
+            .map(|id| format!("{id:?}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let location = if self.has_runtime_config {
+            "is not in the runtime config"
+        } else {
+            "needs a runtime config file, but there isn't one"
+        };
+        format!(
+            "{} {:?}, used by component(s) {components}, {location}",
+            capitalize(self.kind.noun()),
+            self.label,
+        )
+    }
+
+    fn treatment(&self) -> Option<&dyn Treatment> {
+        Some(self)
+    }
+}
+
+#[async_trait]
+impl Treatment for StoreLabelMissing {
+    fn summary(&self) -> String {
+        format!(
+            "Add a local {} {:?} to the runtime config",
+            self.kind.noun(),
+            self.label
+        )
+    }
+
+    async fn dry_run(&self, patient: &PatientApp) -> Result<String> {
+        let (path, before, after) = self.treated_runtime_config(patient)?;
+        let diff = similar::udiff::unified_diff(Default::default(), &before, &after, 1, None);
+        let mut description = format!(
+            "Apply the following diff to {}:\n{diff}",
+            quoted_path(&path)
+        );
+        if patient.runtime_config_path.is_none() {
+            description.push_str(&format!(
+                "\nThe app must then be run with `spin up --runtime-config-file {}`",
+                DEFAULT_RUNTIME_CONFIG_FILE
+            ));
+        }
+        Ok(description)
+    }
+
+    async fn treat(&self, patient: &mut PatientApp) -> Result<()> {
+        let (path, _, after) = self.treated_runtime_config(patient)?;
+        fs::write(&path, after).with_context(|| {
+            format!(
+                "failed to write fixed runtime config to {}",
+                quoted_path(&path)
+            )
+        })?;
+        patient.runtime_config_path = Some(path);
+        Ok(())
+    }
+}
+
+fn capitalize(s: &str) -> String {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test::TestPatient;
+
+    use super::*;
+
+    const MANIFEST: &str = r#"
+        spin_manifest_version = 2
+        [application]
+        name = "store-labels-test"
+        [[trigger.http]]
+        route = "/..."
+        component = "app"
+        [component.app]
+        source = "app.wasm"
+        key_value_stores = ["default", "cache"]
+        sqlite_databases = ["default", "data"]
+        [component.worker]
+        source = "worker.wasm"
+        key_value_stores = ["cache"]
+    "#;
+
+    async fn diagnose(patient: &PatientApp) -> Vec<StoreLabelMissing> {
+        StoreLabelsDiagnostic
+            .diagnose(patient)
+            .await
+            .expect("diagnose should succeed")
+    }
+
+    #[tokio::test]
+    async fn test_missing_labels() {
+        let mut patient = TestPatient::from_toml_str(MANIFEST);
+        patient.runtime_config_path = None;
+        let diags = diagnose(&patient).await;
+        assert_eq!(2, diags.len(), "{diags:?}");
+        assert_eq!(StoreKind::KeyValue, diags[0].kind);
+        assert_eq!(vec!["app", "worker"], diags[0].component_ids);
+        assert_eq!(StoreKind::Sqlite, diags[1].kind);
+        assert_eq!("data", diags[1].label);
+    }
+
+    #[tokio::test]
+    async fn test_treatment_adds_to_runtime_config() {
+        let dir = tempfile::tempdir().unwrap();
+        let runtime_config_path = dir.path().join("runtime-config.toml");
+        fs::write(
+            &runtime_config_path,
+            "[sqlite_database.data]\ntype = \"spin\"\n",
+        )
+        .unwrap();
+
+        let mut patient = TestPatient::from_toml_str(MANIFEST);
+        patient.runtime_config_path = Some(runtime_config_path.clone());
+        let diags = diagnose(&patient).await;
+        assert_eq!(1, diags.len(), "{diags:?}");
+
+        diags[0].treat(&mut patient).await.unwrap();
+        assert!(diagnose(&patient).await.is_empty());
+        let runtime_config = fs::read_to_string(&runtime_config_path).unwrap();
+        assert!(
+            runtime_config.contains("[key_value_store.cache]\ntype = \"spin\""),
+            "{runtime_config}"
+        );
+    }
+}
@@ -33,24 +33,17 @@ impl PatientWasm {
     }
 
     pub fn source_path(&self) -> Option<&Path> {
-        match &self.component.source {
-            v2::ComponentSource::Local(path) => Some(Path::new(path)),
-            _ => None,
-        }
+        self.component.source.local_path().map(Path::new)
     }
 
     pub fn abs_source_path(&self) -> Option<PathBuf> {
-        match &self.component.source {
-            v2::ComponentSource::Local(path) => {
-                // TODO: We probably need a doctor check to see if the path can be expanded!
-                // For now, fall back to the literal path.
-                let can_path = Path::new(path)
-                    .canonicalize()
-                    .unwrap_or(self.app_dir.join(path));
-                Some(can_path)
-            }
-            _ => None,
-        }
+        let path = self.component.source.local_path()?;
+        // TODO: We probably need a doctor check to see if the path can be expanded!
+        // For now, fall back to the literal path.
+        let can_path = Path::new(path)
+            .canonicalize()
+            .unwrap_or(self.app_dir.join(path));
+        Some(can_path)
     }
 
     pub fn has_build(&self) -> bool {
@@ -0,0 +1,181 @@
+//! A shared model of the errors Spin host interfaces return to guests.
+//!
+//! Hosts classify errors with [`ErrorKind::classify`], and tag errors whose
+//! kind they know with [`KindError`]. Where an interface has error variants
+//! for some kinds, e.g. `wasi:http`'s `connection-timeout`, hosts use the
+//! kind to choose the variant. Interfaces which report failures only as a
+//! string keep the string as it is, since existing guests may depend on it,
+//! and hosts log the kind alongside it instead.
+
+use std::{error::Error, fmt, io};
+
+/// Why a host operation failed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ErrorKind {
+    /// Credentials were missing or rejected by a service.
+    Auth,
+    /// The operation did not complete in time.
+    Timeout,
+    /// The component is not allowed to perform the operation, e.g. because
+    /// the destination is not in its `allowed_outbound_hosts`.
+    NotAllowed,
+    /// A limit was reached, e.g. on open connections.
+    ResourceExhausted,
+    /// A connection to a service could not be established or was lost.
+    Connection,
+    /// The guest's request was invalid, e.g. a malformed statement or an
+    /// unsupported parameter.
+    InvalidInput,
+    /// The requested resource does not exist.
+    NotFound,
+    /// Any other error.
+    Other,
+}
+
+impl ErrorKind {
+    /// All error kinds.
+    pub const ALL: [Self; 8] = [
+        Self::Auth,
+        Self::Timeout,
+        Self::NotAllowed,
+        Self::ResourceExhausted,
+        Self::Connection,
+        Self::InvalidInput,
+        Self::NotFound,
+        Self::Other,
+    ];
+
+    /// The stable, machine-readable code for this kind.
+    pub fn code(self) -> &'static str {
+        match self {
+            Self::Auth => "auth",
+            Self::Timeout => "timeout",
+            Self::NotAllowed => "not-allowed",
+            Self::ResourceExhausted => "resource-exhausted",
+            Self::Connection => "connection",
+            Self::InvalidInput => "invalid-input",
+            Self::NotFound => "not-found",
+            Self::Other => "other",
+        }
+    }
+
+    /// Returns the kind with the given [`code`](Self::code).
+    pub fn from_code(code: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.code() == code)
+    }
+
+    /// Classifies an error by the first [`KindError`] or recognized
+    /// [`io::Error`] in its chain of sources.
+    ///
+    /// Factors with more specific knowledge of an error, e.g. a database
+    /// driver's error codes, should check that before falling back to this.
+    pub fn classify(err: &(dyn Error + 'static)) -> Self {
+        let mut next = Some(err);
+        while let Some(err) = next {
+            if let Some(err) = err.downcast_ref::<KindError>() {
+                return err.kind;
+            }
+            if let Some(kind) = err.downcast_ref::<io::Error>().and_then(Self::from_io) {
+                return kind;
+            }
+            next = err.source();
+        }
+        Self::Other
+    }
+
+    fn from_io(err: &io::Error) -> Option<Self> {
+        use io::ErrorKind as Io;
+        Some(match err.kind() {
+            Io::TimedOut | Io::WouldBlock => Self::Timeout,
+            Io::PermissionDenied => Self::NotAllowed,
+            Io::ConnectionRefused
+            | Io::ConnectionReset
+            | Io::ConnectionAborted
+            | Io::NotConnected
+            | Io::AddrNotAvailable
+            | Io::BrokenPipe
+            | Io::UnexpectedEof => Self::Connection,
+            Io::NotFound => Self::NotFound,
+            Io::InvalidInput | Io::InvalidData => Self::InvalidInput,
+            Io::OutOfMemory => Self::ResourceExhausted,
+            _ => return None,
+        })
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.code())
+    }
+}
+
+/// An error whose [`ErrorKind`] is known where it is created.
+///
+/// Wrapping an error in a `KindError` lets [`ErrorKind::classify`] find its
+/// kind after it has been passed up through e.g. `anyhow` context.
+#[derive(Debug)]
+pub struct KindError {
+    kind: ErrorKind,
+    message: String,
+}
+
+impl KindError {
+    pub fn new(kind: ErrorKind, message: impl Into<String>) -> Self {
+        Self {
+            kind,
+            message: message.into(),
+        }
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+}
+
+impl fmt::Display for KindError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl Error for KindError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn codes_round_trip() {
+        for kind in ErrorKind::ALL {
+            assert_eq!(ErrorKind::from_code(kind.code()), Some(kind));
+        }
+        assert_eq!(ErrorKind::from_code("mystery"), None);
+    }
+
+    #[derive(Debug)]
+    struct Wrapper(io::Error);
+
+    impl fmt::Display for Wrapper {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("wrapped")
+        }
+    }
+
+    impl Error for Wrapper {
+        fn source(&self) -> Option<&(dyn Error + 'static)> {
+            Some(&self.0)
+        }
+    }
+
+    #[test]
+    fn errors_are_classified_by_their_sources() {
+        let err = Wrapper(io::Error::from(io::ErrorKind::ConnectionRefused));
+        assert_eq!(ErrorKind::classify(&err), ErrorKind::Connection);
+
+        let err = KindError::new(ErrorKind::Auth, "bad password");
+        assert_eq!(ErrorKind::classify(&err), ErrorKind::Auth);
+
+        let err = Wrapper(io::Error::other("mystery"));
+        assert_eq!(ErrorKind::classify(&err), ErrorKind::Other);
+    }
+}
@@ -7,7 +7,7 @@ use spin_locked_app::Variable;
 
 pub use async_trait;
 
-pub use provider::Provider;
+pub use provider::{Decryptor, Provider};
 use template::Part;
 pub use template::Template;
 
@@ -15,11 +15,16 @@ pub use template::Template;
 pub type SharedPreparedResolver =
     std::sync::Arc<std::sync::OnceLock<std::sync::Arc<PreparedResolver>>>;
 
-/// A [`Resolver`] which is extended by [`Provider`]s.
+/// The prefix of encrypted variable values, which is followed by the
+/// [scheme](Decryptor::scheme) they are encrypted with.
+pub const ENCRYPTED_VALUE_PREFIX: &str = "enc:";
+
+/// A [`Resolver`] which is extended by [`Provider`]s and [`Decryptor`]s.
 #[derive(Debug, Default)]
 pub struct ProviderResolver {
     internal: Resolver,
     providers: Vec<Box<dyn Provider>>,
+    decryptors: Vec<Box<dyn Decryptor>>,
 }
 
 impl ProviderResolver {
@@ -28,6 +33,7 @@ impl ProviderResolver {
         Ok(Self {
             internal: Resolver::new(variables)?,
             providers: Default::default(),
+            decryptors: Default::default(),
         })
     }
 
@@ -46,6 +52,11 @@ impl ProviderResolver {
         self.providers.push(provider);
     }
 
+    /// Adds a variable value Decryptor to the Resolver.
+    pub fn add_decryptor(&mut self, decryptor: Box<dyn Decryptor>) {
+        self.decryptors.push(decryptor);
+    }
+
     /// Resolves a variable value for the given path.
     pub async fn resolve(&self, component_id: &str, key: Key<'_>) -> Result<String> {
         let template = self.internal.get_template(component_id, key)?;
@@ -93,10 +104,42 @@ impl ProviderResolver {
     async fn resolve_variable(&self, key: &str) -> Result<String> {
         for provider in &self.providers {
             if let Some(value) = provider.get(&Key(key)).await.map_err(Error::Provider)? {
-                return Ok(value);
+                return self.decrypt(key, value).await;
             }
         }
-        self.internal.resolve_variable(key)
+        let value = self.internal.resolve_variable(key)?;
+        self.decrypt(key, value).await
+    }
+
+    /// Decrypts the value of the variable `key`, if it is encrypted.
+    ///
+    /// Values are only taken to be encrypted if a decryptor is configured, so
+    /// that apps without one can have values which happen to start with
+    /// [`ENCRYPTED_VALUE_PREFIX`].
+    async fn decrypt(&self, key: &str, value: String) -> Result<String> {
+        if self.decryptors.is_empty() {
+            return Ok(value);
+        }
+        let Some(encrypted) = value.strip_prefix(ENCRYPTED_VALUE_PREFIX) else {
+            return Ok(value);
+        };
+        let Some((scheme, ciphertext)) = encrypted.split_once(':') else {
+            return Err(Error::Provider(anyhow::anyhow!(
+                "encrypted value of variable {key:?} has no scheme"
+            )));
+        };
+        let decryptor = self
+            .decryptors
+            .iter()
+            .find(|decryptor| decryptor.scheme() == scheme)
+            .ok_or_else(|| {
+                Error::Provider(anyhow::anyhow!(
+                    "variable {key:?} is encrypted with {scheme:?}, but no decryptor is configured for it"
+                ))
+            })?;
+        decryptor.decrypt(ciphertext).await.map_err(|err| {
+            Error::Provider(err.context(format!("failed to decrypt variable {key:?}")))
+        })
     }
 }
 
@@ -325,7 +368,25 @@ mod tests {
         }
     }
 
+    #[derive(Debug)]
+    struct ReversingDecryptor;
+
+    #[async_trait]
+    impl Decryptor for ReversingDecryptor {
+        fn scheme(&self) -> &str {
+            "reverse"
+        }
+
+        async fn decrypt(&self, ciphertext: &str) -> anyhow::Result<String> {
+            Ok(ciphertext.chars().rev().collect())
+        }
+    }
+
     async fn test_resolve(template: &str) -> Result<String> {
+        test_resolve_with(template, true).await
+    }
+
+    async fn test_resolve_with(template: &str, decrypt: bool) -> Result<String> {
         let mut resolver = ProviderResolver::new([
             (
                 "required".into(),
@@ -341,12 +402,29 @@ mod tests {
                     secret: false,
                 },
             ),
+            (
+                "encrypted".into(),
+                Variable {
+                    default: Some("enc:reverse:eulav-detpyrcne".into()),
+                    secret: true,
+                },
+            ),
+            (
+                "unknown_scheme".into(),
+                Variable {
+                    default: Some("enc:rot13:cynva".into()),
+                    secret: true,
+                },
+            ),
         ])
         .unwrap();
         resolver
             .add_component_variables("test-component", [("test_key".into(), template.into())])
             .unwrap();
         resolver.add_provider(Box::new(TestProvider));
+        if decrypt {
+            resolver.add_decryptor(Box::new(ReversingDecryptor));
+        }
         resolver.resolve("test-component", Key("test_key")).await
     }
 
@@ -371,6 +449,28 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn resolve_encrypted_variable() {
+        assert_eq!(
+            test_resolve("{{ encrypted }}").await.unwrap(),
+            "encrypted-value"
+        );
+        assert!(matches!(
+            test_resolve("{{ unknown_scheme }}").await,
+            Err(Error::Provider(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn encrypted_values_are_plain_text_without_decryptors() {
+        assert_eq!(
+            test_resolve_with("{{ unknown_scheme }}", false)
+                .await
+                .unwrap(),
+            "enc:rot13:cynva"
+        );
+    }
+
     #[test]
     fn keys_good() {
         for key in ["a", "abc", "a1b2c3", "a_1", "a_1_b_3"] {
@@ -1,4 +1,4 @@
-use std::fmt::Debug;
+use std::{fmt::Debug, sync::Arc};
 
 use async_trait::async_trait;
 
@@ -10,3 +10,25 @@ pub trait Provider: Debug + Send + Sync {
     /// Returns the value at the given config path, if it exists.
     async fn get(&self, key: &Key) -> anyhow::Result<Option<String>>;
 }
+
+/// A decryptor of encrypted variable values.
+///
+/// If any decryptor is configured, variable values of the form
+/// `enc:<scheme>:<ciphertext>`, whether they come from a [`Provider`] or a
+/// default, are decrypted by the decryptor for the scheme when they are
+/// resolved.
+#[async_trait]
+pub trait Decryptor: Debug + Send + Sync {
+    /// The scheme of the values this decrypts.
+    fn scheme(&self) -> &str;
+
+    /// Decrypts the ciphertext of a value, i.e. what follows its scheme.
+    async fn decrypt(&self, ciphertext: &str) -> anyhow::Result<String>;
+}
+
+#[async_trait]
+impl<P: Provider + ?Sized> Provider for Arc<P> {
+    async fn get(&self, key: &Key) -> anyhow::Result<Option<String>> {
+        (**self).get(key).await
+    }
+}
@@ -0,0 +1,26 @@
+use spin_factors::anyhow;
+use spin_world::{async_trait, spin::actor::actor};
+use tracing::instrument;
+
+use crate::InstanceState;
+
+#[async_trait]
+impl actor::Host for InstanceState {
+    #[instrument(name = "spin_actor.enter", skip(self), err(level = tracing::Level::INFO))]
+    async fn enter(&mut self, key: String) -> Result<(), actor::Error> {
+        if self.entered.contains_key(&key) {
+            return Ok(());
+        }
+        let id = (self.component_id.clone(), key.clone());
+        let entered = self
+            .actors
+            .enter(id, self.max_queue_length, self.timeout)
+            .await?;
+        self.entered.insert(key, entered);
+        Ok(())
+    }
+
+    fn convert_error(&mut self, error: actor::Error) -> anyhow::Result<actor::Error> {
+        Ok(error)
+    }
+}
@@ -0,0 +1,191 @@
+mod host;
+
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use serde::Deserialize;
+use spin_factors::{
+    anyhow, ConfigureAppContext, Factor, InitContext, PrepareContext, RuntimeFactors,
+    SelfInstanceBuilder,
+};
+use spin_world::spin::actor::actor;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// A factor for serializing components' executions by key.
+///
+/// An instance which enters an actor holds it until the instance is dropped;
+/// other instances of the same component entering the actor wait their turn.
+///
+/// Actors belong to the factor rather than to each configured app, so that
+/// an app reconfigured on reload waits on the actors entered by instances of
+/// the app it replaces.
+#[derive(Default)]
+pub struct ActorFactor {
+    actors: Arc<Actors>,
+}
+
+impl ActorFactor {
+    /// Creates a new `ActorFactor`.
+    pub fn new() -> Self {
+        Default::default()
+    }
+}
+
+impl Factor for ActorFactor {
+    type RuntimeConfig = RuntimeConfig;
+    type AppState = AppState;
+    type InstanceBuilder = InstanceState;
+
+    fn init<T: Send + 'static>(&mut self, mut ctx: InitContext<T, Self>) -> anyhow::Result<()> {
+        ctx.link_bindings(actor::add_to_linker)?;
+        Ok(())
+    }
+
+    fn configure_app<T: RuntimeFactors>(
+        &self,
+        mut ctx: ConfigureAppContext<T, Self>,
+    ) -> anyhow::Result<Self::AppState> {
+        Ok(AppState {
+            config: ctx.take_runtime_config().unwrap_or_default(),
+            actors: self.actors.clone(),
+        })
+    }
+
+    fn prepare<T: RuntimeFactors>(
+        &self,
+        ctx: PrepareContext<T, Self>,
+    ) -> anyhow::Result<InstanceState> {
+        let app_state = ctx.app_state();
+        Ok(InstanceState {
+            component_id: ctx.app_component().id().to_string(),
+            actors: app_state.actors.clone(),
+            max_queue_length: app_state.config.max_queue_length,
+            timeout: Duration::from_secs(app_state.config.timeout_secs),
+            entered: Default::default(),
+        })
+    }
+}
+
+/// The `[actor]` runtime config section.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RuntimeConfig {
+    /// The most instances that may wait to enter any one actor.
+    pub max_queue_length: usize,
+    /// How long an instance may wait to enter an actor, in seconds.
+    pub timeout_secs: u64,
+}
+
+impl Default for RuntimeConfig {
+    fn default() -> Self {
+        Self {
+            max_queue_length: 64,
+            timeout_secs: 30,
+        }
+    }
+}
+
+pub struct AppState {
+    config: RuntimeConfig,
+    actors: Arc<Actors>,
+}
+
+/// The app's actors which are entered or waited for, by component ID and key.
+#[derive(Default)]
+struct Actors {
+    actors: Mutex<HashMap<(String, String), Arc<Actor>>>,
+}
+
+struct Actor {
+    semaphore: Arc<Semaphore>,
+    waiting: AtomicUsize,
+}
+
+impl Actors {
+    async fn enter(
+        self: &Arc<Self>,
+        id: (String, String),
+        max_queue_length: usize,
+        timeout: Duration,
+    ) -> Result<Entered, actor::Error> {
+        let actor = self
+            .actors
+            .lock()
+            .unwrap()
+            .entry(id.clone())
+            .or_insert_with(|| {
+                Arc::new(Actor {
+                    semaphore: Arc::new(Semaphore::new(1)),
+                    waiting: AtomicUsize::new(0),
+                })
+            })
+            .clone();
+
+        let result = if actor.waiting.fetch_add(1, Ordering::SeqCst) >= max_queue_length
+            && actor.semaphore.available_permits() == 0
+        {
+            Err(actor::Error::QueueFull)
+        } else {
+            tokio::time::timeout(timeout, actor.semaphore.clone().acquire_owned())
+                .await
+                .map_err(|_elapsed| actor::Error::Timeout)
+                .and_then(|permit| permit.map_err(|err| actor::Error::Other(err.to_string())))
+        };
+        actor.waiting.fetch_sub(1, Ordering::SeqCst);
+        drop(actor);
+
+        match result {
+            Ok(permit) => Ok(Entered {
+                actors: self.clone(),
+                id,
+                permit: Some(permit),
+            }),
+            Err(err) => {
+                self.remove_if_unused(&id);
+                Err(err)
+            }
+        }
+    }
+
+    /// Removes the actor if no instance is in it or waiting for it.
+    fn remove_if_unused(&self, id: &(String, String)) {
+        let mut actors = self.actors.lock().unwrap();
+        if actors
+            .get(id)
+            .is_some_and(|actor| Arc::strong_count(actor) == 1)
+        {
+            actors.remove(id);
+        }
+    }
+}
+
+/// An actor entered by an instance, which leaves it when dropped.
+struct Entered {
+    actors: Arc<Actors>,
+    id: (String, String),
+    permit: Option<OwnedSemaphorePermit>,
+}
+
+impl Drop for Entered {
+    fn drop(&mut self) {
+        drop(self.permit.take());
+        self.actors.remove_if_unused(&self.id);
+    }
+}
+
+pub struct InstanceState {
+    component_id: String,
+    actors: Arc<Actors>,
+    max_queue_length: usize,
+    timeout: Duration,
+    /// The actors this instance is in, by key.
+    entered: HashMap<String, Entered>,
+}
+
+impl SelfInstanceBuilder for InstanceState {}
@@ -0,0 +1,87 @@
+use spin_factor_actor::{ActorFactor, RuntimeConfig};
+use spin_factors::{anyhow, App, ConfiguredApp, RuntimeFactors};
+use spin_factors_test::{toml, TestEnvironment};
+use spin_world::spin::actor::actor::{Error, Host};
+
+#[derive(RuntimeFactors)]
+struct TestFactors {
+    actor: ActorFactor,
+}
+
+async fn configured_app(
+    max_queue_length: usize,
+) -> anyhow::Result<(TestFactors, ConfiguredApp<TestFactors>)> {
+    let env = TestEnvironment::new(TestFactors {
+        actor: ActorFactor::new(),
+    })
+    .extend_manifest(toml! {
+        [component.test-component]
+        source = "does-not-exist.wasm"
+
+        [component.other]
+        source = "does-not-exist.wasm"
+    })
+    .runtime_config(TestFactorsRuntimeConfig {
+        actor: Some(RuntimeConfig {
+            max_queue_length,
+            timeout_secs: 0,
+        }),
+    })?;
+    let app = App::new("test-app", env.build_locked_app().await?);
+    let configured_app = env.factors.configure_app(app, env.runtime_config)?;
+    Ok((env.factors, configured_app))
+}
+
+fn instance_state(
+    factors: &TestFactors,
+    configured_app: &ConfiguredApp<TestFactors>,
+    component_id: &str,
+) -> anyhow::Result<TestFactorsInstanceState> {
+    let builders = factors.prepare(configured_app, component_id)?;
+    Ok(factors.build_instance_state(builders)?)
+}
+
+#[tokio::test]
+async fn actors_are_entered_one_instance_at_a_time() -> anyhow::Result<()> {
+    let (factors, app) = configured_app(1).await?;
+    let mut first = instance_state(&factors, &app, "test-component")?;
+    let mut second = instance_state(&factors, &app, "test-component")?;
+
+    first.actor.enter("user-1".into()).await?;
+    // Entering again is a no-op.
+    first.actor.enter("user-1".into()).await?;
+    assert!(matches!(
+        second.actor.enter("user-1".into()).await,
+        Err(Error::Timeout)
+    ));
+    second.actor.enter("user-2".into()).await?;
+
+    drop(first);
+    second.actor.enter("user-1".into()).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn actors_are_scoped_to_components() -> anyhow::Result<()> {
+    let (factors, app) = configured_app(1).await?;
+    let mut first = instance_state(&factors, &app, "test-component")?;
+    let mut other = instance_state(&factors, &app, "other")?;
+
+    first.actor.enter("user-1".into()).await?;
+    other.actor.enter("user-1".into()).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn full_queues_are_rejected() -> anyhow::Result<()> {
+    let (factors, app) = configured_app(0).await?;
+    let mut first = instance_state(&factors, &app, "test-component")?;
+    let mut second = instance_state(&factors, &app, "test-component")?;
+
+    first.actor.enter("user-1".into()).await?;
+    assert!(matches!(
+        second.actor.enter("user-1".into()).await,
+        Err(Error::QueueFull)
+    ));
+    Ok(())
+}
@@ -0,0 +1,161 @@
+mod store;
+
+use std::{sync::Arc, time::Duration};
+
+use serde::Deserialize;
+use spin_factors::{
+    anyhow, ConfigureAppContext, Factor, InitContext, PrepareContext, RuntimeFactors,
+    SelfInstanceBuilder,
+};
+use spin_world::{
+    async_trait,
+    spin::cache::cache::{self, Error},
+};
+use tracing::instrument;
+
+use store::Lookup;
+pub use store::{Cache, CacheStats};
+
+/// A factor for an in-memory cache shared by an app's instances.
+#[derive(Default)]
+pub struct CacheFactor {
+    _priv: (),
+}
+
+impl CacheFactor {
+    /// Creates a new `CacheFactor`.
+    pub fn new() -> Self {
+        Default::default()
+    }
+}
+
+impl Factor for CacheFactor {
+    type RuntimeConfig = RuntimeConfig;
+    type AppState = AppState;
+    type InstanceBuilder = InstanceState;
+
+    fn init<T: Send + 'static>(&mut self, mut ctx: InitContext<T, Self>) -> anyhow::Result<()> {
+        ctx.link_bindings(cache::add_to_linker)?;
+        Ok(())
+    }
+
+    fn configure_app<T: RuntimeFactors>(
+        &self,
+        mut ctx: ConfigureAppContext<T, Self>,
+    ) -> anyhow::Result<Self::AppState> {
+        let config = ctx.take_runtime_config().unwrap_or_default();
+        Ok(AppState {
+            cache: Arc::new(Cache::new(
+                config.max_bytes,
+                Duration::from_secs(config.default_ttl_secs),
+            )),
+        })
+    }
+
+    fn prepare<T: RuntimeFactors>(
+        &self,
+        ctx: PrepareContext<T, Self>,
+    ) -> anyhow::Result<Self::InstanceBuilder> {
+        let component = ctx.app_component();
+        Ok(InstanceState {
+            cache: ctx.app_state().cache.clone(),
+            app_id: component.app.id().to_owned(),
+            component_id: component.id().to_owned(),
+        })
+    }
+}
+
+/// The `[cache]` runtime config section.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RuntimeConfig {
+    /// The most bytes of keys and values the cache may hold.
+    pub max_bytes: usize,
+    /// How long values are cached for when components don't say, in seconds.
+    pub default_ttl_secs: u64,
+}
+
+impl Default for RuntimeConfig {
+    fn default() -> Self {
+        Self {
+            max_bytes: 64 * 1024 * 1024,
+            default_ttl_secs: 300,
+        }
+    }
+}
+
+pub struct AppState {
+    cache: Arc<Cache>,
+}
+
+impl AppState {
+    /// The app's cache.
+    pub fn cache(&self) -> &Arc<Cache> {
+        &self.cache
+    }
+}
+
+pub struct InstanceState {
+    cache: Arc<Cache>,
+    app_id: String,
+    component_id: String,
+}
+
+impl SelfInstanceBuilder for InstanceState {}
+
+#[async_trait]
+impl cache::Host for InstanceState {
+    #[instrument(name = "spin_cache.get", skip(self))]
+    async fn get(&mut self, key: String) -> anyhow::Result<Option<Vec<u8>>> {
+        let (app_id, component_id) = (self.app_id.as_str(), self.component_id.as_str());
+        match self.cache.get(&key) {
+            Lookup::Hit(value) => {
+                spin_telemetry::metrics::monotonic_counter!(
+                    spin.cache.hits = 1,
+                    app_id = app_id,
+                    component_id = component_id
+                );
+                Ok(Some(value))
+            }
+            lookup => {
+                if let Lookup::Expired = lookup {
+                    spin_telemetry::metrics::monotonic_counter!(
+                        spin.cache.expirations = 1,
+                        app_id = app_id
+                    );
+                }
+                spin_telemetry::metrics::monotonic_counter!(
+                    spin.cache.misses = 1,
+                    app_id = app_id,
+                    component_id = component_id
+                );
+                Ok(None)
+            }
+        }
+    }
+
+    #[instrument(name = "spin_cache.set", skip(self, value), err(level = tracing::Level::INFO))]
+    async fn set(&mut self, key: String, value: Vec<u8>, ttl_ms: Option<u64>) -> Result<(), Error> {
+        let evicted = self
+            .cache
+            .set(&key, value, ttl_ms.map(Duration::from_millis))
+            .map_err(|_| Error::TooLarge)?;
+        if evicted > 0 {
+            spin_telemetry::metrics::monotonic_counter!(
+                spin.cache.evictions = evicted,
+                app_id = self.app_id.as_str()
+            );
+        }
+        Ok(())
+    }
+
+    #[instrument(name = "spin_cache.delete", skip(self))]
+    async fn delete(&mut self, key: String) -> anyhow::Result<()> {
+        self.cache.delete(&key);
+        Ok(())
+    }
+
+    fn convert_error(&mut self, error: Error) -> anyhow::Result<Error> {
+        Ok(error)
+    }
+}
@@ -0,0 +1,197 @@
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use lru::LruCache;
+
+/// A bounded, least-recently-used cache of values with time-to-lives.
+pub struct Cache {
+    max_bytes: usize,
+    default_ttl: Duration,
+    inner: Mutex<Inner>,
+}
+
+struct Inner {
+    entries: LruCache<String, Entry>,
+    /// The total size of the cached keys and values.
+    bytes: usize,
+    stats: CacheStats,
+}
+
+struct Entry {
+    value: Vec<u8>,
+    expires: Instant,
+}
+
+/// Counts of a cache's use since it was created, and its current size.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// The lookups which found an unexpired value.
+    pub hits: u64,
+    /// The lookups which found no value, or an expired one.
+    pub misses: u64,
+    /// The values removed to make room for others.
+    pub evictions: u64,
+    /// The values removed because they had expired.
+    pub expirations: u64,
+    /// The number of values cached.
+    pub entries: usize,
+    /// The total size of the cached keys and values.
+    pub bytes: usize,
+}
+
+impl CacheStats {
+    /// The fraction of lookups which found a value, or zero if there were none.
+    pub fn hit_ratio(&self) -> f64 {
+        match self.hits + self.misses {
+            0 => 0.0,
+            lookups => self.hits as f64 / lookups as f64,
+        }
+    }
+}
+
+/// The result of a lookup.
+pub enum Lookup {
+    Hit(Vec<u8>),
+    Miss,
+    /// The value had expired, and was removed.
+    Expired,
+}
+
+/// A value was larger than the whole cache.
+#[derive(Debug)]
+pub struct TooLarge;
+
+impl Cache {
+    pub fn new(max_bytes: usize, default_ttl: Duration) -> Self {
+        Self {
+            max_bytes,
+            default_ttl,
+            inner: Mutex::new(Inner {
+                entries: LruCache::unbounded(),
+                bytes: 0,
+                stats: CacheStats::default(),
+            }),
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Lookup {
+        let mut inner = self.inner.lock().unwrap();
+        let lookup = match inner.entries.get(key) {
+            Some(entry) if entry.expires > Instant::now() => Lookup::Hit(entry.value.clone()),
+            Some(_) => {
+                inner.remove(key);
+                inner.stats.expirations += 1;
+                Lookup::Expired
+            }
+            None => Lookup::Miss,
+        };
+        match lookup {
+            Lookup::Hit(_) => inner.stats.hits += 1,
+            Lookup::Miss | Lookup::Expired => inner.stats.misses += 1,
+        }
+        lookup
+    }
+
+    /// Caches `value` for `key` until `ttl` (or the default time-to-live) has
+    /// passed, returning how many other values were evicted to make room.
+    pub fn set(&self, key: &str, value: Vec<u8>, ttl: Option<Duration>) -> Result<u64, TooLarge> {
+        let size = key.len() + value.len();
+        if size > self.max_bytes {
+            return Err(TooLarge);
+        }
+        let expires = Instant::now() + ttl.unwrap_or(self.default_ttl);
+
+        let mut inner = self.inner.lock().unwrap();
+        inner.remove(key);
+        let mut evicted = 0;
+        while inner.bytes + size > self.max_bytes {
+            let Some((key, entry)) = inner.entries.pop_lru() else {
+                break;
+            };
+            inner.bytes -= key.len() + entry.value.len();
+            evicted += 1;
+        }
+        inner.stats.evictions += evicted;
+        inner.bytes += size;
+        inner.entries.put(key.to_owned(), Entry { value, expires });
+        Ok(evicted)
+    }
+
+    pub fn delete(&self, key: &str) {
+        self.inner.lock().unwrap().remove(key);
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let inner = self.inner.lock().unwrap();
+        CacheStats {
+            entries: inner.entries.len(),
+            bytes: inner.bytes,
+            ..inner.stats
+        }
+    }
+}
+
+impl Inner {
+    fn remove(&mut self, key: &str) {
+        if let Some(entry) = self.entries.pop(key) {
+            self.bytes -= key.len() + entry.value.len();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TTL: Duration = Duration::from_secs(60);
+
+    #[test]
+    fn least_recently_used_values_are_evicted() {
+        let cache = Cache::new(10, TTL);
+        assert_eq!(cache.set("a", b"1234".to_vec(), None).unwrap(), 0);
+        assert_eq!(cache.set("b", b"1234".to_vec(), None).unwrap(), 0);
+        assert!(matches!(cache.get("a"), Lookup::Hit(_)));
+
+        // Only "b" must go to make room, as "a" was used more recently.
+        assert_eq!(cache.set("c", b"12".to_vec(), None).unwrap(), 1);
+        assert!(matches!(cache.get("a"), Lookup::Hit(v) if v == b"1234"));
+        assert!(matches!(cache.get("b"), Lookup::Miss));
+        assert!(matches!(cache.get("c"), Lookup::Hit(_)));
+
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.evictions), (3, 1, 1));
+        assert_eq!((stats.entries, stats.bytes), (2, 8));
+        assert_eq!(stats.hit_ratio(), 0.75);
+    }
+
+    #[test]
+    fn replacing_values_frees_their_space() {
+        let cache = Cache::new(10, TTL);
+        cache.set("a", b"12345678".to_vec(), None).unwrap();
+        assert_eq!(cache.set("a", b"123456789".to_vec(), None).unwrap(), 0);
+        assert_eq!(cache.stats().bytes, 10);
+        cache.delete("a");
+        assert_eq!(cache.stats().bytes, 0);
+        assert!(matches!(cache.get("a"), Lookup::Miss));
+    }
+
+    #[test]
+    fn expired_values_are_removed() {
+        let cache = Cache::new(10, TTL);
+        cache.set("a", b"1".to_vec(), Some(Duration::ZERO)).unwrap();
+        assert!(matches!(cache.get("a"), Lookup::Expired));
+        assert!(matches!(cache.get("a"), Lookup::Miss));
+        let stats = cache.stats();
+        assert_eq!((stats.expirations, stats.entries, stats.bytes), (1, 0, 0));
+    }
+
+    #[test]
+    fn values_larger_than_the_cache_are_rejected() {
+        let cache = Cache::new(10, TTL);
+        cache.set("a", b"1".to_vec(), None).unwrap();
+        assert!(cache.set("b", vec![0; 10], None).is_err());
+        assert!(matches!(cache.get("a"), Lookup::Hit(_)));
+    }
+}
@@ -0,0 +1,65 @@
+use spin_factor_cache::{CacheFactor, RuntimeConfig};
+use spin_factors::{anyhow, App, RuntimeFactors};
+use spin_factors_test::{toml, TestEnvironment};
+use spin_world::spin::cache::cache::{Error, Host as _};
+
+#[derive(RuntimeFactors)]
+struct TestFactors {
+    cache: CacheFactor,
+}
+
+fn test_env() -> TestEnvironment<TestFactors> {
+    let factors = TestFactors {
+        cache: CacheFactor::new(),
+    };
+    TestEnvironment::new(factors).extend_manifest(toml! {
+        [component.other-component]
+        source = "does-not-exist.wasm"
+
+        [component.test-component]
+        source = "does-not-exist.wasm"
+    })
+}
+
+#[tokio::test]
+async fn values_are_shared_across_instances() -> anyhow::Result<()> {
+    let env = test_env();
+    let app = App::new("test-app", env.build_locked_app().await?);
+    let configured_app = env.factors.configure_app(app, env.runtime_config)?;
+
+    let builders = env.factors.prepare(&configured_app, "test-component")?;
+    let mut first = env.factors.build_instance_state(builders)?;
+    first
+        .cache
+        .set("key".into(), b"value".to_vec(), None)
+        .await?;
+
+    let builders = env.factors.prepare(&configured_app, "other-component")?;
+    let mut second = env.factors.build_instance_state(builders)?;
+    assert_eq!(
+        Some(b"value".to_vec()),
+        second.cache.get("key".into()).await?
+    );
+    assert_eq!(None, second.cache.get("missing".into()).await?);
+    second.cache.delete("key".into()).await?;
+    assert_eq!(None, first.cache.get("key".into()).await?);
+
+    let stats = configured_app.app_state::<CacheFactor>()?.cache().stats();
+    assert_eq!((1, 2), (stats.hits, stats.misses));
+    Ok(())
+}
+
+#[tokio::test]
+async fn values_larger_than_the_cache_are_rejected() -> anyhow::Result<()> {
+    let runtime_config = TestFactorsRuntimeConfig {
+        cache: Some(RuntimeConfig {
+            max_bytes: 16,
+            ..Default::default()
+        }),
+    };
+    let env = test_env().runtime_config(runtime_config)?;
+    let mut state = env.build_instance_state().await?;
+    let result = state.cache.set("key".into(), vec![0; 16], None).await;
+    assert!(matches!(result, Err(Error::TooLarge)), "{result:?}");
+    Ok(())
+}
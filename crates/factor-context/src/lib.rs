@@ -0,0 +1,205 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use serde::Deserialize;
+use spin_app::{MetadataKey, APP_NAME_KEY, APP_VERSION_KEY};
+use spin_common::build_info::BuildInfo;
+use spin_factors::{
+    anyhow, ConfigureAppContext, Factor, InitContext, PrepareContext, RuntimeFactors,
+    SelfInstanceBuilder,
+};
+use spin_world::{
+    async_trait,
+    spin::context::context::{self, ExecutionContext, PodMetadata},
+};
+use tracing::instrument;
+
+/// Metadata key for how a component was built, as recorded by `spin build`.
+pub const BUILD_INFO_KEY: MetadataKey<BuildInfo> = MetadataKey::new("build_info");
+
+/// A factor for giving components read access to metadata about the
+/// execution they were instantiated for.
+#[derive(Default)]
+pub struct ContextFactor {
+    _priv: (),
+}
+
+impl ContextFactor {
+    /// Creates a new `ContextFactor`.
+    pub fn new() -> Self {
+        Default::default()
+    }
+}
+
+impl Factor for ContextFactor {
+    type RuntimeConfig = RuntimeConfig;
+    type AppState = AppState;
+    type InstanceBuilder = InstanceState;
+
+    fn init<T: Send + 'static>(&mut self, mut ctx: InitContext<T, Self>) -> anyhow::Result<()> {
+        ctx.link_bindings(context::add_to_linker)?;
+        Ok(())
+    }
+
+    fn configure_app<T: RuntimeFactors>(
+        &self,
+        mut ctx: ConfigureAppContext<T, Self>,
+    ) -> anyhow::Result<Self::AppState> {
+        let config = ctx.take_runtime_config().unwrap_or_default();
+        let app = ctx.app();
+        let mut component_build_info = HashMap::new();
+        for component in app.components() {
+            if let Some(info) = component.get_metadata(BUILD_INFO_KEY)? {
+                component_build_info.insert(component.id().to_string(), info);
+            }
+        }
+        Ok(AppState {
+            app_name: app.get_metadata(APP_NAME_KEY)?.unwrap_or_default(),
+            app_version: app.get_metadata(APP_VERSION_KEY)?,
+            environment: config.environment,
+            pod: spin_common::kubernetes::PodMetadata::detect().map(|pod| PodMetadata {
+                name: pod.name.clone(),
+                namespace: pod.namespace.clone(),
+                labels: pod
+                    .labels
+                    .iter()
+                    .map(|(k, v)| (k.clone(), v.clone()))
+                    .collect(),
+            }),
+            component_build_info,
+        })
+    }
+
+    fn prepare<T: RuntimeFactors>(
+        &self,
+        ctx: PrepareContext<T, Self>,
+    ) -> anyhow::Result<Self::InstanceBuilder> {
+        let component = ctx.app_component();
+        let component_id = component.id().to_string();
+        // Components may have several triggers, so this is only a default for
+        // triggers which don't say which is executing the component.
+        let trigger = component
+            .app
+            .triggers()
+            .find(|trigger| trigger.component().is_ok_and(|c| c.id() == component_id));
+        let state = ctx.app_state();
+        let build = state
+            .component_build_info
+            .get(&component_id)
+            .map(|info| context::BuildInfo {
+                git_sha: info.git_sha.clone(),
+                git_dirty: info.git_dirty,
+                built_at: info.built_at.clone(),
+                builder: info.builder.clone(),
+            });
+        Ok(InstanceState {
+            trigger_type: trigger.as_ref().map(|t| t.trigger_type().to_string()),
+            trigger_id: trigger.as_ref().map(|t| t.id().to_string()),
+            app_name: state.app_name.clone(),
+            app_version: state.app_version.clone(),
+            component_id,
+            correlation_id: Default::default(),
+            environment: state.environment.clone(),
+            pod: state.pod.clone(),
+            build,
+        })
+    }
+}
+
+/// The `[context]` runtime config section.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RuntimeConfig {
+    /// The label of the environment the app is deployed to, e.g. `staging`.
+    pub environment: Option<String>,
+}
+
+pub struct AppState {
+    app_name: String,
+    app_version: Option<String>,
+    environment: Option<String>,
+    pod: Option<PodMetadata>,
+    // Component ID -> build info
+    component_build_info: HashMap<String, BuildInfo>,
+}
+
+impl AppState {
+    /// Returns how each of the app's components was built, by component ID,
+    /// for those built by `spin build`.
+    pub fn component_build_info(&self) -> &HashMap<String, BuildInfo> {
+        &self.component_build_info
+    }
+}
+
+pub struct InstanceState {
+    trigger_type: Option<String>,
+    trigger_id: Option<String>,
+    app_name: String,
+    app_version: Option<String>,
+    component_id: String,
+    correlation_id: CorrelationId,
+    environment: Option<String>,
+    pod: Option<PodMetadata>,
+    build: Option<context::BuildInfo>,
+}
+
+/// The ID correlating an execution with its caller and logs.
+///
+/// Clones share the ID, so that whatever labels an instance's output with it
+/// sees it change when the instance is reused for another execution.
+#[derive(Clone, Debug, Default)]
+pub struct CorrelationId(Arc<Mutex<Option<String>>>);
+
+impl CorrelationId {
+    /// Returns the current ID, if one has been set.
+    pub fn get(&self) -> Option<String> {
+        self.0.lock().unwrap().clone()
+    }
+
+    /// Sets the ID.
+    pub fn set(&self, id: impl Into<String>) {
+        *self.0.lock().unwrap() = Some(id.into());
+    }
+}
+
+impl InstanceState {
+    /// Sets the trigger executing the component, which otherwise defaults to
+    /// the first of the component's triggers in the app.
+    pub fn set_trigger(&mut self, trigger_type: impl Into<String>, trigger_id: impl Into<String>) {
+        self.trigger_type = Some(trigger_type.into());
+        self.trigger_id = Some(trigger_id.into());
+    }
+
+    /// Sets the ID correlating the execution with its caller and logs.
+    pub fn set_correlation_id(&mut self, correlation_id: impl Into<String>) {
+        self.correlation_id.set(correlation_id);
+    }
+
+    /// Returns the instance's correlation ID, which follows later calls to
+    /// [`Self::set_correlation_id`].
+    pub fn correlation_id(&self) -> CorrelationId {
+        self.correlation_id.clone()
+    }
+}
+
+impl SelfInstanceBuilder for InstanceState {}
+
+#[async_trait]
+impl context::Host for InstanceState {
+    #[instrument(name = "spin_context.get", skip(self))]
+    async fn get(&mut self) -> anyhow::Result<ExecutionContext> {
+        Ok(ExecutionContext {
+            trigger_type: self.trigger_type.clone(),
+            trigger_id: self.trigger_id.clone(),
+            app_name: self.app_name.clone(),
+            app_version: self.app_version.clone(),
+            component_id: self.component_id.clone(),
+            correlation_id: self.correlation_id.get(),
+            environment: self.environment.clone(),
+            pod: self.pod.clone(),
+            build: self.build.clone(),
+        })
+    }
+}
@@ -0,0 +1,104 @@
+use spin_factor_context::{ContextFactor, RuntimeConfig};
+use spin_factors::{anyhow, App, RuntimeFactors};
+use spin_factors_test::{toml, TestEnvironment};
+use spin_world::spin::context::context::Host;
+
+#[derive(RuntimeFactors)]
+struct TestFactors {
+    context: ContextFactor,
+}
+
+fn test_env() -> TestEnvironment<TestFactors> {
+    let factors = TestFactors {
+        context: ContextFactor::new(),
+    };
+    TestEnvironment::new(factors).extend_manifest(toml! {
+        [application]
+        name = "test-app"
+        version = "1.2.3"
+
+        [[trigger.http]]
+        id = "web"
+        route = "/..."
+        component = "test-component"
+
+        [component.test-component]
+        source = "does-not-exist.wasm"
+
+        [component.untriggered]
+        source = "does-not-exist.wasm"
+    })
+}
+
+#[tokio::test]
+async fn execution_context_is_reported() -> anyhow::Result<()> {
+    let env = test_env().runtime_config(TestFactorsRuntimeConfig {
+        context: Some(RuntimeConfig {
+            environment: Some("staging".into()),
+        }),
+    })?;
+    let app = App::new("test-app", env.build_locked_app().await?);
+    let configured_app = env.factors.configure_app(app, env.runtime_config)?;
+
+    let mut builders = env.factors.prepare(&configured_app, "test-component")?;
+    builders.context().unwrap().set_correlation_id("request-1");
+    let mut state = env.factors.build_instance_state(builders)?;
+    let context = state.context.get().await?;
+    assert_eq!(context.trigger_type.as_deref(), Some("http"));
+    assert_eq!(context.trigger_id.as_deref(), Some("web"));
+    assert_eq!(context.app_name, "test-app");
+    assert_eq!(context.app_version.as_deref(), Some("1.2.3"));
+    assert_eq!(context.component_id, "test-component");
+    assert_eq!(context.correlation_id.as_deref(), Some("request-1"));
+    assert_eq!(context.environment.as_deref(), Some("staging"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn untriggered_components_have_no_trigger() -> anyhow::Result<()> {
+    let env = test_env();
+    let app = App::new("test-app", env.build_locked_app().await?);
+    let configured_app = env.factors.configure_app(app, env.runtime_config)?;
+
+    let builders = env.factors.prepare(&configured_app, "untriggered")?;
+    let mut state = env.factors.build_instance_state(builders)?;
+    let context = state.context.get().await?;
+    assert_eq!(context.trigger_type, None);
+    assert_eq!(context.trigger_id, None);
+    assert_eq!(context.correlation_id, None);
+    assert_eq!(context.environment, None);
+    assert!(context.pod.is_none());
+    assert!(context.build.is_none());
+    Ok(())
+}
+
+#[tokio::test]
+async fn build_info_is_reported() -> anyhow::Result<()> {
+    let env = test_env();
+    let mut locked_app = env.build_locked_app().await?;
+    let component = locked_app
+        .components
+        .iter_mut()
+        .find(|c| c.id == "test-component")
+        .unwrap();
+    component.metadata.insert(
+        "build_info".into(),
+        serde_json::json!({
+            "git_sha": "abc123",
+            "git_dirty": true,
+            "built_at": "2024-06-01T12:00:00Z",
+            "builder": "spin/3.1.0",
+        }),
+    );
+    let app = App::new("test-app", locked_app);
+    let configured_app = env.factors.configure_app(app, env.runtime_config)?;
+
+    let builders = env.factors.prepare(&configured_app, "test-component")?;
+    let mut state = env.factors.build_instance_state(builders)?;
+    let build = state.context.get().await?.build.unwrap();
+    assert_eq!(build.git_sha.as_deref(), Some("abc123"));
+    assert!(build.git_dirty);
+    assert_eq!(build.built_at.as_deref(), Some("2024-06-01T12:00:00Z"));
+    assert_eq!(build.builder.as_deref(), Some("spin/3.1.0"));
+    Ok(())
+}
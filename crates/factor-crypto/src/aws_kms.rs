@@ -0,0 +1,307 @@
+use aws_config::{BehaviorVersion, Region, SdkConfig};
+use aws_credential_types::Credentials;
+use aws_sdk_kms::{
+    config::SharedCredentialsProvider,
+    primitives::Blob,
+    types::{MacAlgorithmSpec, MessageType, SigningAlgorithmSpec},
+    Client,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use spin_factors::anyhow;
+use spin_world::async_trait;
+use tokio::sync::OnceCell;
+
+use crate::{Algorithm, Error, Key};
+
+/// The encryption context under which the associated data of encryptions is
+/// passed to KMS, which only takes string pairs.
+const ASSOCIATED_DATA_CONTEXT: &str = "spin-associated-data";
+
+/// A key held in AWS KMS, which performs all operations with it.
+pub struct AwsKmsKey {
+    algorithm: Algorithm,
+    key_id: String,
+    region: String,
+    credentials: Option<Credentials>,
+    client: OnceCell<Client>,
+}
+
+impl AwsKmsKey {
+    /// Creates a key for the KMS key with the given ID, ARN or alias.
+    ///
+    /// Without `credentials`, the AWS SDK's usual environmental sources are
+    /// used.
+    pub fn new(
+        algorithm: Algorithm,
+        key_id: String,
+        region: String,
+        credentials: Option<Credentials>,
+    ) -> anyhow::Result<Self> {
+        anyhow::ensure!(
+            algorithm != Algorithm::Ed25519,
+            "AWS KMS keys don't support Ed25519"
+        );
+        Ok(Self {
+            algorithm,
+            key_id,
+            region,
+            credentials,
+            client: OnceCell::new(),
+        })
+    }
+
+    async fn client(&self) -> &Client {
+        self.client
+            .get_or_init(|| async {
+                let sdk_config = match &self.credentials {
+                    Some(credentials) => SdkConfig::builder()
+                        .credentials_provider(SharedCredentialsProvider::new(credentials.clone()))
+                        .region(Region::new(self.region.clone()))
+                        .behavior_version(BehaviorVersion::latest())
+                        .build(),
+                    None => {
+                        aws_config::defaults(BehaviorVersion::latest())
+                            .region(Region::new(self.region.clone()))
+                            .load()
+                            .await
+                    }
+                };
+                Client::new(&sdk_config)
+            })
+            .await
+    }
+
+    fn encryption_context(associated_data: &[u8]) -> Option<(&'static str, String)> {
+        (!associated_data.is_empty())
+            .then(|| (ASSOCIATED_DATA_CONTEXT, STANDARD.encode(associated_data)))
+    }
+}
+
+#[async_trait]
+impl Key for AwsKmsKey {
+    fn algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+
+    async fn sign(&self, message: &[u8]) -> Result<Vec<u8>, Error> {
+        let client = self.client().await;
+        match self.algorithm {
+            Algorithm::HmacSha256 => {
+                let output = client
+                    .generate_mac()
+                    .key_id(&self.key_id)
+                    .mac_algorithm(MacAlgorithmSpec::HmacSha256)
+                    .message(Blob::new(message))
+                    .send()
+                    .await
+                    .map_err(other_error)?;
+                Ok(output.mac.map(Blob::into_inner).unwrap_or_default())
+            }
+            Algorithm::EcdsaP256Sha256 => {
+                let output = client
+                    .sign()
+                    .key_id(&self.key_id)
+                    .signing_algorithm(SigningAlgorithmSpec::EcdsaSha256)
+                    .message_type(MessageType::Raw)
+                    .message(Blob::new(message))
+                    .send()
+                    .await
+                    .map_err(other_error)?;
+                let signature = output.signature.map(Blob::into_inner).unwrap_or_default();
+                der_to_fixed_signature(&signature)
+                    .ok_or_else(|| Error::Other("KMS returned an invalid signature".into()))
+            }
+            Algorithm::Ed25519 | Algorithm::Aes256Gcm => Err(Error::UnsupportedOperation),
+        }
+    }
+
+    async fn verify(&self, message: &[u8], signature: &[u8]) -> Result<bool, Error> {
+        let client = self.client().await;
+        match self.algorithm {
+            Algorithm::HmacSha256 => {
+                let result = client
+                    .verify_mac()
+                    .key_id(&self.key_id)
+                    .mac_algorithm(MacAlgorithmSpec::HmacSha256)
+                    .message(Blob::new(message))
+                    .mac(Blob::new(signature))
+                    .send()
+                    .await;
+                match result {
+                    Ok(output) => Ok(output.mac_valid),
+                    Err(err)
+                        if err
+                            .as_service_error()
+                            .is_some_and(|err| err.is_kms_invalid_mac_exception()) =>
+                    {
+                        Ok(false)
+                    }
+                    Err(err) => Err(other_error(err)),
+                }
+            }
+            Algorithm::EcdsaP256Sha256 => {
+                let Some(signature) = fixed_to_der_signature(signature) else {
+                    return Ok(false);
+                };
+                let result = client
+                    .verify()
+                    .key_id(&self.key_id)
+                    .signing_algorithm(SigningAlgorithmSpec::EcdsaSha256)
+                    .message_type(MessageType::Raw)
+                    .message(Blob::new(message))
+                    .signature(Blob::new(signature))
+                    .send()
+                    .await;
+                match result {
+                    Ok(output) => Ok(output.signature_valid),
+                    Err(err)
+                        if err
+                            .as_service_error()
+                            .is_some_and(|err| err.is_kms_invalid_signature_exception()) =>
+                    {
+                        Ok(false)
+                    }
+                    Err(err) => Err(other_error(err)),
+                }
+            }
+            Algorithm::Ed25519 | Algorithm::Aes256Gcm => Err(Error::UnsupportedOperation),
+        }
+    }
+
+    async fn encrypt(&self, plaintext: &[u8], associated_data: &[u8]) -> Result<Vec<u8>, Error> {
+        if self.algorithm != Algorithm::Aes256Gcm {
+            return Err(Error::UnsupportedOperation);
+        }
+        let mut request = self
+            .client()
+            .await
+            .encrypt()
+            .key_id(&self.key_id)
+            .plaintext(Blob::new(plaintext));
+        if let Some((key, value)) = Self::encryption_context(associated_data) {
+            request = request.encryption_context(key, value);
+        }
+        let output = request.send().await.map_err(other_error)?;
+        Ok(output
+            .ciphertext_blob
+            .map(Blob::into_inner)
+            .unwrap_or_default())
+    }
+
+    async fn decrypt(&self, ciphertext: &[u8], associated_data: &[u8]) -> Result<Vec<u8>, Error> {
+        if self.algorithm != Algorithm::Aes256Gcm {
+            return Err(Error::UnsupportedOperation);
+        }
+        let mut request = self
+            .client()
+            .await
+            .decrypt()
+            .key_id(&self.key_id)
+            .ciphertext_blob(Blob::new(ciphertext));
+        if let Some((key, value)) = Self::encryption_context(associated_data) {
+            request = request.encryption_context(key, value);
+        }
+        match request.send().await {
+            Ok(output) => Ok(output.plaintext.map(Blob::into_inner).unwrap_or_default()),
+            Err(err)
+                if err.as_service_error().is_some_and(|err| {
+                    err.is_invalid_ciphertext_exception() || err.is_incorrect_key_exception()
+                }) =>
+            {
+                Err(Error::DecryptionFailed)
+            }
+            Err(err) => Err(other_error(err)),
+        }
+    }
+}
+
+fn other_error(err: impl std::error::Error) -> Error {
+    tracing::error!("AWS KMS request failed: {err:?}");
+    Error::Other(format!("AWS KMS request failed: {err}"))
+}
+
+/// The length of each of the integers of a P-256 signature.
+const P256_INT_LEN: usize = 32;
+
+/// Converts a DER-encoded ECDSA P-256 signature, as returned by KMS, to the
+/// fixed length encoding used by e.g. JWTs.
+fn der_to_fixed_signature(der: &[u8]) -> Option<Vec<u8>> {
+    let (tag, rest) = der.split_first()?;
+    let (len, rest) = rest.split_first()?;
+    if *tag != 0x30 || usize::from(*len) != rest.len() {
+        return None;
+    }
+    let (r, rest) = der_integer(rest)?;
+    let (s, rest) = der_integer(rest)?;
+    if !rest.is_empty() {
+        return None;
+    }
+    let mut fixed = vec![0; 2 * P256_INT_LEN];
+    fixed[P256_INT_LEN - r.len()..P256_INT_LEN].copy_from_slice(r);
+    fixed[2 * P256_INT_LEN - s.len()..].copy_from_slice(s);
+    Some(fixed)
+}
+
+/// Reads a DER integer of at most [`P256_INT_LEN`] bytes, without leading
+/// zeros, returning it and what follows it.
+fn der_integer(der: &[u8]) -> Option<(&[u8], &[u8])> {
+    let (tag, rest) = der.split_first()?;
+    let (len, rest) = rest.split_first()?;
+    if *tag != 0x02 || usize::from(*len) > rest.len() {
+        return None;
+    }
+    let (mut int, rest) = rest.split_at(usize::from(*len));
+    while let Some((0, trimmed)) = int.split_first() {
+        int = trimmed;
+    }
+    (int.len() <= P256_INT_LEN).then_some((int, rest))
+}
+
+/// Converts a fixed length ECDSA P-256 signature to DER, for KMS.
+fn fixed_to_der_signature(fixed: &[u8]) -> Option<Vec<u8>> {
+    if fixed.len() != 2 * P256_INT_LEN {
+        return None;
+    }
+    let (r, s) = fixed.split_at(P256_INT_LEN);
+    let mut ints = der_encode_integer(r);
+    ints.extend(der_encode_integer(s));
+    let mut der = vec![0x30, ints.len() as u8];
+    der.extend(ints);
+    Some(der)
+}
+
+fn der_encode_integer(mut int: &[u8]) -> Vec<u8> {
+    while let [0, trimmed @ ..] = int {
+        int = trimmed;
+    }
+    let mut der = vec![0x02, int.len() as u8];
+    // Integers are signed, so a set high bit needs a leading zero.
+    if !matches!(int.first(), Some(byte) if byte & 0x80 == 0) {
+        der[1] += 1;
+        der.push(0);
+    }
+    der.extend_from_slice(int);
+    der
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signatures_convert_between_der_and_fixed_encodings() {
+        let mut fixed = vec![0; 64];
+        fixed[0] = 0x80;
+        fixed[31] = 1;
+        fixed[63] = 0x7f;
+        let der = fixed_to_der_signature(&fixed).unwrap();
+        // r needs a leading zero, as its high bit is set, and s is trimmed
+        // to its one byte.
+        assert_eq!(&der[..4], &[0x30, 38, 0x02, 33]);
+        assert_eq!(&der[der.len() - 3..], &[0x02, 1, 0x7f]);
+        assert_eq!(der_to_fixed_signature(&der).unwrap(), fixed);
+
+        assert!(der_to_fixed_signature(&der[1..]).is_none());
+        assert!(fixed_to_der_signature(&fixed[1..]).is_none());
+    }
+}
@@ -0,0 +1,55 @@
+use spin_factors::anyhow;
+use spin_world::{
+    async_trait,
+    spin::crypto::crypto::{self, Algorithm, Error},
+};
+use tracing::{instrument, Level};
+
+use crate::InstanceState;
+
+#[async_trait]
+impl crypto::Host for InstanceState {
+    #[instrument(name = "spin_crypto.key_algorithm", skip(self), err(level = Level::INFO))]
+    async fn key_algorithm(&mut self, key: String) -> Result<Algorithm, Error> {
+        Ok(self.key(&key)?.algorithm().into())
+    }
+
+    #[instrument(name = "spin_crypto.sign", skip(self, message), err(level = Level::INFO))]
+    async fn sign(&mut self, key: String, message: Vec<u8>) -> Result<Vec<u8>, Error> {
+        self.key(&key)?.sign(&message).await
+    }
+
+    #[instrument(name = "spin_crypto.verify", skip(self, message, signature), err(level = Level::INFO))]
+    async fn verify(
+        &mut self,
+        key: String,
+        message: Vec<u8>,
+        signature: Vec<u8>,
+    ) -> Result<bool, Error> {
+        self.key(&key)?.verify(&message, &signature).await
+    }
+
+    #[instrument(name = "spin_crypto.encrypt", skip(self, plaintext, associated_data), err(level = Level::INFO))]
+    async fn encrypt(
+        &mut self,
+        key: String,
+        plaintext: Vec<u8>,
+        associated_data: Vec<u8>,
+    ) -> Result<Vec<u8>, Error> {
+        self.key(&key)?.encrypt(&plaintext, &associated_data).await
+    }
+
+    #[instrument(name = "spin_crypto.decrypt", skip(self, ciphertext, associated_data), err(level = Level::INFO))]
+    async fn decrypt(
+        &mut self,
+        key: String,
+        ciphertext: Vec<u8>,
+        associated_data: Vec<u8>,
+    ) -> Result<Vec<u8>, Error> {
+        self.key(&key)?.decrypt(&ciphertext, &associated_data).await
+    }
+
+    fn convert_error(&mut self, error: Error) -> anyhow::Result<Error> {
+        Ok(error)
+    }
+}
@@ -0,0 +1,258 @@
+use ring::{
+    aead::{self, Aad, LessSafeKey, Nonce, UnboundKey, NONCE_LEN},
+    hmac,
+    rand::{SecureRandom, SystemRandom},
+    signature::{
+        self, EcdsaKeyPair, Ed25519KeyPair, KeyPair, UnparsedPublicKey,
+        ECDSA_P256_SHA256_FIXED_SIGNING,
+    },
+};
+use rustls_pki_types::PrivateKeyDer;
+use serde::Deserialize;
+use spin_factors::anyhow::{self, Context};
+use spin_world::{async_trait, spin::crypto::crypto};
+
+use crate::Error;
+
+/// The algorithm a key is used with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Algorithm {
+    HmacSha256,
+    Ed25519,
+    EcdsaP256Sha256,
+    Aes256Gcm,
+}
+
+impl From<Algorithm> for crypto::Algorithm {
+    fn from(algorithm: Algorithm) -> Self {
+        match algorithm {
+            Algorithm::HmacSha256 => Self::HmacSha256,
+            Algorithm::Ed25519 => Self::Ed25519,
+            Algorithm::EcdsaP256Sha256 => Self::EcdsaP256Sha256,
+            Algorithm::Aes256Gcm => Self::Aes256Gcm,
+        }
+    }
+}
+
+/// A key held by the host, which components use by label.
+///
+/// Operations the key's algorithm doesn't support fail with
+/// [`Error::UnsupportedOperation`].
+#[async_trait]
+pub trait Key: Send + Sync {
+    /// The algorithm the key is used with.
+    fn algorithm(&self) -> Algorithm;
+
+    /// Signs `message`.
+    async fn sign(&self, message: &[u8]) -> Result<Vec<u8>, Error>;
+
+    /// Returns whether `signature` is a valid signature of `message`.
+    async fn verify(&self, message: &[u8], signature: &[u8]) -> Result<bool, Error>;
+
+    /// Encrypts `plaintext`, authenticating `associated_data` along with it.
+    async fn encrypt(&self, plaintext: &[u8], associated_data: &[u8]) -> Result<Vec<u8>, Error>;
+
+    /// Decrypts `ciphertext` returned by [`Key::encrypt`].
+    async fn decrypt(&self, ciphertext: &[u8], associated_data: &[u8]) -> Result<Vec<u8>, Error>;
+}
+
+/// A key whose material is held in memory.
+pub struct LocalKey {
+    inner: LocalKeyInner,
+    rng: SystemRandom,
+}
+
+enum LocalKeyInner {
+    HmacSha256(hmac::Key),
+    Ed25519(Ed25519KeyPair),
+    EcdsaP256Sha256(EcdsaKeyPair),
+    Aes256Gcm(LessSafeKey),
+}
+
+impl LocalKey {
+    /// Creates a key for `algorithm` from its material.
+    ///
+    /// HMAC and AES keys are raw bytes; Ed25519 and ECDSA keys are PKCS#8
+    /// documents.
+    pub fn new(algorithm: Algorithm, material: &[u8]) -> anyhow::Result<Self> {
+        let rng = SystemRandom::new();
+        let inner = match algorithm {
+            Algorithm::HmacSha256 => {
+                anyhow::ensure!(!material.is_empty(), "HMAC keys may not be empty");
+                LocalKeyInner::HmacSha256(hmac::Key::new(hmac::HMAC_SHA256, material))
+            }
+            Algorithm::Ed25519 => LocalKeyInner::Ed25519(
+                Ed25519KeyPair::from_pkcs8_maybe_unchecked(material)
+                    .map_err(|err| anyhow::anyhow!("{err}"))
+                    .context("invalid Ed25519 key; expected a PKCS#8 document")?,
+            ),
+            Algorithm::EcdsaP256Sha256 => LocalKeyInner::EcdsaP256Sha256(
+                EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, material, &rng)
+                    .map_err(|err| anyhow::anyhow!("{err}"))
+                    .context("invalid ECDSA P-256 key; expected a PKCS#8 document")?,
+            ),
+            Algorithm::Aes256Gcm => LocalKeyInner::Aes256Gcm(LessSafeKey::new(
+                UnboundKey::new(&aead::AES_256_GCM, material)
+                    .map_err(|_| anyhow::anyhow!("invalid AES-256 key; expected 32 bytes"))?,
+            )),
+        };
+        Ok(Self { inner, rng })
+    }
+
+    /// Creates a key for `algorithm` from its encoded material: PEM, or
+    /// otherwise as the given raw bytes.
+    pub(crate) fn decode(algorithm: Algorithm, encoded: &[u8]) -> anyhow::Result<Self> {
+        if !encoded.trim_ascii_start().starts_with(b"-----BEGIN") {
+            return Self::new(algorithm, encoded);
+        }
+        let key = rustls_pemfile::private_key(&mut &*encoded)
+            .context("invalid PEM key")?
+            .context("expected a private key in the PEM file")?;
+        let PrivateKeyDer::Pkcs8(key) = key else {
+            anyhow::bail!("PEM keys must be PKCS#8 (`BEGIN PRIVATE KEY`)");
+        };
+        Self::new(algorithm, key.secret_pkcs8_der())
+    }
+}
+
+#[async_trait]
+impl Key for LocalKey {
+    fn algorithm(&self) -> Algorithm {
+        match &self.inner {
+            LocalKeyInner::HmacSha256(_) => Algorithm::HmacSha256,
+            LocalKeyInner::Ed25519(_) => Algorithm::Ed25519,
+            LocalKeyInner::EcdsaP256Sha256(_) => Algorithm::EcdsaP256Sha256,
+            LocalKeyInner::Aes256Gcm(_) => Algorithm::Aes256Gcm,
+        }
+    }
+
+    async fn sign(&self, message: &[u8]) -> Result<Vec<u8>, Error> {
+        match &self.inner {
+            LocalKeyInner::HmacSha256(key) => Ok(hmac::sign(key, message).as_ref().to_vec()),
+            LocalKeyInner::Ed25519(key) => Ok(key.sign(message).as_ref().to_vec()),
+            LocalKeyInner::EcdsaP256Sha256(key) => key
+                .sign(&self.rng, message)
+                .map(|signature| signature.as_ref().to_vec())
+                .map_err(|_| Error::Other("failed to sign message".into())),
+            LocalKeyInner::Aes256Gcm(_) => Err(Error::UnsupportedOperation),
+        }
+    }
+
+    async fn verify(&self, message: &[u8], signature: &[u8]) -> Result<bool, Error> {
+        let valid = match &self.inner {
+            LocalKeyInner::HmacSha256(key) => hmac::verify(key, message, signature).is_ok(),
+            LocalKeyInner::Ed25519(key) => {
+                UnparsedPublicKey::new(&signature::ED25519, key.public_key().as_ref())
+                    .verify(message, signature)
+                    .is_ok()
+            }
+            LocalKeyInner::EcdsaP256Sha256(key) => UnparsedPublicKey::new(
+                &signature::ECDSA_P256_SHA256_FIXED,
+                key.public_key().as_ref(),
+            )
+            .verify(message, signature)
+            .is_ok(),
+            LocalKeyInner::Aes256Gcm(_) => return Err(Error::UnsupportedOperation),
+        };
+        Ok(valid)
+    }
+
+    async fn encrypt(&self, plaintext: &[u8], associated_data: &[u8]) -> Result<Vec<u8>, Error> {
+        let LocalKeyInner::Aes256Gcm(key) = &self.inner else {
+            return Err(Error::UnsupportedOperation);
+        };
+        // The ciphertext is the random nonce followed by the sealed plaintext.
+        let mut nonce = [0; NONCE_LEN];
+        self.rng
+            .fill(&mut nonce)
+            .map_err(|_| Error::Other("failed to generate nonce".into()))?;
+        let mut sealed = plaintext.to_vec();
+        key.seal_in_place_append_tag(
+            Nonce::assume_unique_for_key(nonce),
+            Aad::from(associated_data),
+            &mut sealed,
+        )
+        .map_err(|_| Error::Other("failed to encrypt plaintext".into()))?;
+        let mut ciphertext = nonce.to_vec();
+        ciphertext.extend_from_slice(&sealed);
+        Ok(ciphertext)
+    }
+
+    async fn decrypt(&self, ciphertext: &[u8], associated_data: &[u8]) -> Result<Vec<u8>, Error> {
+        let LocalKeyInner::Aes256Gcm(key) = &self.inner else {
+            return Err(Error::UnsupportedOperation);
+        };
+        if ciphertext.len() < NONCE_LEN {
+            return Err(Error::DecryptionFailed);
+        }
+        let (nonce, sealed) = ciphertext.split_at(NONCE_LEN);
+        let nonce = Nonce::try_assume_unique_for_key(nonce).map_err(|_| Error::DecryptionFailed)?;
+        let mut plaintext = sealed.to_vec();
+        let len = key
+            .open_in_place(nonce, Aad::from(associated_data), &mut plaintext)
+            .map_err(|_| Error::DecryptionFailed)?
+            .len();
+        plaintext.truncate(len);
+        Ok(plaintext)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn signatures_verify() {
+        let rng = SystemRandom::new();
+        let ed25519 = Ed25519KeyPair::generate_pkcs8(&rng).unwrap();
+        let ecdsa = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng).unwrap();
+        let keys = [
+            LocalKey::new(Algorithm::HmacSha256, b"secret").unwrap(),
+            LocalKey::new(Algorithm::Ed25519, ed25519.as_ref()).unwrap(),
+            LocalKey::new(Algorithm::EcdsaP256Sha256, ecdsa.as_ref()).unwrap(),
+        ];
+        for key in keys {
+            let signature = key.sign(b"message").await.unwrap();
+            assert!(key.verify(b"message", &signature).await.unwrap());
+            assert!(!key.verify(b"massage", &signature).await.unwrap());
+            assert!(matches!(
+                key.encrypt(b"message", b"").await,
+                Err(Error::UnsupportedOperation)
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn ciphertexts_decrypt_with_the_same_associated_data() {
+        let key = LocalKey::new(Algorithm::Aes256Gcm, &[7; 32]).unwrap();
+        let ciphertext = key.encrypt(b"cookie", b"session").await.unwrap();
+        assert_ne!(
+            ciphertext,
+            key.encrypt(b"cookie", b"session").await.unwrap()
+        );
+        assert_eq!(
+            key.decrypt(&ciphertext, b"session").await.unwrap(),
+            b"cookie"
+        );
+        assert!(matches!(
+            key.decrypt(&ciphertext, b"other").await,
+            Err(Error::DecryptionFailed)
+        ));
+        assert!(matches!(
+            key.decrypt(&ciphertext[1..], b"session").await,
+            Err(Error::DecryptionFailed)
+        ));
+        assert!(matches!(
+            key.sign(b"message").await,
+            Err(Error::UnsupportedOperation)
+        ));
+    }
+
+    #[test]
+    fn keys_must_suit_their_algorithm() {
+        assert!(LocalKey::new(Algorithm::Aes256Gcm, &[7; 16]).is_err());
+        assert!(LocalKey::new(Algorithm::Ed25519, &[7; 32]).is_err());
+        assert!(LocalKey::new(Algorithm::HmacSha256, &[]).is_err());
+    }
+}
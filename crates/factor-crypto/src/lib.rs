@@ -0,0 +1,77 @@
+mod aws_kms;
+mod host;
+mod key;
+pub mod runtime_config;
+
+use std::{collections::HashMap, sync::Arc};
+
+use spin_factors::{
+    anyhow, ConfigureAppContext, Factor, InitContext, PrepareContext, RuntimeFactors,
+    SelfInstanceBuilder,
+};
+use spin_world::spin::crypto::crypto;
+
+pub use aws_kms::AwsKmsKey;
+pub use key::{Algorithm, Key, LocalKey};
+pub use runtime_config::RuntimeConfig;
+pub use spin_world::spin::crypto::crypto::Error;
+
+/// A factor for signing, verifying, encrypting and decrypting with keys held
+/// by the host, so that components never see them.
+#[derive(Default)]
+pub struct CryptoFactor {
+    _priv: (),
+}
+
+impl CryptoFactor {
+    /// Creates a new `CryptoFactor`.
+    pub fn new() -> Self {
+        Default::default()
+    }
+}
+
+impl Factor for CryptoFactor {
+    type RuntimeConfig = RuntimeConfig;
+    type AppState = AppState;
+    type InstanceBuilder = InstanceState;
+
+    fn init<T: Send + 'static>(&mut self, mut ctx: InitContext<T, Self>) -> anyhow::Result<()> {
+        ctx.link_bindings(crypto::add_to_linker)?;
+        Ok(())
+    }
+
+    fn configure_app<T: RuntimeFactors>(
+        &self,
+        mut ctx: ConfigureAppContext<T, Self>,
+    ) -> anyhow::Result<Self::AppState> {
+        let config = ctx.take_runtime_config().unwrap_or_default();
+        Ok(AppState {
+            keys: Arc::new(config.into_keys()),
+        })
+    }
+
+    fn prepare<T: RuntimeFactors>(
+        &self,
+        ctx: PrepareContext<T, Self>,
+    ) -> anyhow::Result<Self::InstanceBuilder> {
+        Ok(InstanceState {
+            keys: ctx.app_state().keys.clone(),
+        })
+    }
+}
+
+pub struct AppState {
+    keys: Arc<HashMap<String, Arc<dyn Key>>>,
+}
+
+pub struct InstanceState {
+    keys: Arc<HashMap<String, Arc<dyn Key>>>,
+}
+
+impl InstanceState {
+    fn key(&self, label: &str) -> Result<&Arc<dyn Key>, Error> {
+        self.keys.get(label).ok_or(Error::NoSuchKey)
+    }
+}
+
+impl SelfInstanceBuilder for InstanceState {}
@@ -0,0 +1,214 @@
+use std::{collections::HashMap, path::PathBuf, sync::Arc};
+
+use aws_credential_types::Credentials;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::Deserialize;
+use spin_common::ui::quoted_path;
+use spin_factors::{
+    anyhow::{self, Context},
+    runtime_config::toml::GetTomlValue,
+};
+
+use crate::{Algorithm, AwsKmsKey, Key, LocalKey};
+
+/// Runtime configuration for the crypto factor: the app's keys, by label.
+#[derive(Default)]
+pub struct RuntimeConfig {
+    keys: HashMap<String, Arc<dyn Key>>,
+}
+
+impl RuntimeConfig {
+    /// Adds a key with the given label, replacing any with the same label.
+    pub fn add_key(&mut self, label: impl Into<String>, key: impl Key + 'static) {
+        self.keys.insert(label.into(), Arc::new(key));
+    }
+
+    pub(crate) fn into_keys(self) -> HashMap<String, Arc<dyn Key>> {
+        self.keys
+    }
+}
+
+/// Spin's default handling of the runtime configuration for keys.
+pub struct SpinCryptoRuntimeConfig {
+    runtime_config_dir: PathBuf,
+}
+
+impl SpinCryptoRuntimeConfig {
+    /// Creates a new `SpinCryptoRuntimeConfig`.
+    ///
+    /// The given `runtime_config_dir` will be used as the root to resolve any
+    /// relative paths.
+    pub fn new(runtime_config_dir: impl Into<PathBuf>) -> Self {
+        Self {
+            runtime_config_dir: runtime_config_dir.into(),
+        }
+    }
+
+    /// Get the runtime configuration for keys from a TOML table.
+    ///
+    /// Expects table to be in the format:
+    /// ```toml
+    /// [crypto_key.session]
+    /// type = "file"
+    /// algorithm = "aes256_gcm"
+    /// path = "keys/session.key"
+    ///
+    /// [crypto_key.jwt]
+    /// type = "env"
+    /// algorithm = "ed25519"
+    /// variable = "JWT_SIGNING_KEY"
+    ///
+    /// [crypto_key.tokens]
+    /// type = "aws_kms"
+    /// algorithm = "ecdsa_p256_sha256"
+    /// key_id = "alias/tokens"
+    /// region = "us-east-1"
+    /// ```
+    ///
+    /// Files hold PEM or raw key material; environment variables hold PEM or
+    /// base64-encoded key material.
+    pub fn config_from_table(
+        &self,
+        table: &impl GetTomlValue,
+    ) -> anyhow::Result<Option<RuntimeConfig>> {
+        let Some(value) = table.get("crypto_key") else {
+            return Ok(None);
+        };
+        let keys: HashMap<String, KeyConfig> = value
+            .clone()
+            .try_into()
+            .context("invalid [crypto_key] runtime config")?;
+        let mut runtime_config = RuntimeConfig::default();
+        for (label, config) in keys {
+            let key = self
+                .load_key(config)
+                .with_context(|| format!("failed to load key {label:?}"))?;
+            runtime_config.keys.insert(label, key);
+        }
+        Ok(Some(runtime_config))
+    }
+
+    fn load_key(&self, config: KeyConfig) -> anyhow::Result<Arc<dyn Key>> {
+        Ok(match config {
+            KeyConfig::File { algorithm, path } => {
+                let path = self.runtime_config_dir.join(path);
+                let material = std::fs::read(&path)
+                    .with_context(|| format!("failed to read key from {}", quoted_path(&path)))?;
+                Arc::new(LocalKey::decode(algorithm, &material)?)
+            }
+            KeyConfig::Env {
+                algorithm,
+                variable,
+            } => {
+                let value = std::env::var(&variable)
+                    .with_context(|| format!("failed to read environment variable {variable}"))?;
+                let material = if value.trim_start().starts_with("-----BEGIN") {
+                    value.into_bytes()
+                } else {
+                    STANDARD
+                        .decode(value.trim())
+                        .with_context(|| format!("{variable} is neither PEM nor base64"))?
+                };
+                Arc::new(LocalKey::decode(algorithm, &material)?)
+            }
+            KeyConfig::AwsKms {
+                algorithm,
+                key_id,
+                region,
+                access_key,
+                secret_key,
+                token,
+            } => {
+                let credentials = match (access_key, secret_key) {
+                    (Some(access_key), Some(secret_key)) => Some(Credentials::new(
+                        access_key,
+                        secret_key,
+                        token,
+                        None,
+                        "spin_crypto_runtime_config",
+                    )),
+                    (None, None) => None,
+                    _ => anyhow::bail!("`access_key` and `secret_key` must be set together"),
+                };
+                Arc::new(AwsKmsKey::new(algorithm, key_id, region, credentials)?)
+            }
+        })
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum KeyConfig {
+    File {
+        algorithm: Algorithm,
+        path: PathBuf,
+    },
+    Env {
+        algorithm: Algorithm,
+        variable: String,
+    },
+    AwsKms {
+        algorithm: Algorithm,
+        key_id: String,
+        region: String,
+        access_key: Option<String>,
+        secret_key: Option<String>,
+        token: Option<String>,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_keys_are_read_relative_to_the_runtime_config() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("session.key"), [7; 32]).unwrap();
+        let table = toml::toml! {
+            [crypto_key.session]
+            type = "file"
+            algorithm = "aes256_gcm"
+            path = "session.key"
+        };
+        let config = SpinCryptoRuntimeConfig::new(dir.path())
+            .config_from_table(&table)
+            .unwrap()
+            .unwrap();
+        assert_eq!(config.keys["session"].algorithm(), Algorithm::Aes256Gcm);
+    }
+
+    #[test]
+    fn invalid_keys_are_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("short.key"), [7; 16]).unwrap();
+        let config = SpinCryptoRuntimeConfig::new(dir.path());
+        for table in [
+            toml::toml! {
+                [crypto_key.short]
+                type = "file"
+                algorithm = "aes256_gcm"
+                path = "short.key"
+            },
+            toml::toml! {
+                [crypto_key.missing]
+                type = "file"
+                algorithm = "hmac_sha256"
+                path = "missing.key"
+            },
+            toml::toml! {
+                [crypto_key.kms]
+                type = "aws_kms"
+                algorithm = "ed25519"
+                key_id = "alias/key"
+                region = "us-east-1"
+            },
+        ] {
+            assert!(config.config_from_table(&table).is_err());
+        }
+        assert!(config
+            .config_from_table(&toml::Table::new())
+            .unwrap()
+            .is_none());
+    }
+}
@@ -0,0 +1,65 @@
+use spin_factor_crypto::{Algorithm, CryptoFactor, LocalKey, RuntimeConfig};
+use spin_factors::{anyhow, RuntimeFactors};
+use spin_factors_test::{toml, TestEnvironment};
+use spin_world::spin::crypto::crypto::{self, Error, Host};
+
+#[derive(RuntimeFactors)]
+struct TestFactors {
+    crypto: CryptoFactor,
+}
+
+fn test_env() -> anyhow::Result<TestEnvironment<TestFactors>> {
+    let factors = TestFactors {
+        crypto: CryptoFactor::new(),
+    };
+    let mut keys = RuntimeConfig::default();
+    keys.add_key("jwt", LocalKey::new(Algorithm::HmacSha256, b"secret")?);
+    keys.add_key("cookies", LocalKey::new(Algorithm::Aes256Gcm, &[7; 32])?);
+    TestEnvironment::new(factors)
+        .extend_manifest(toml! {
+            [component.test-component]
+            source = "does-not-exist.wasm"
+        })
+        .runtime_config(TestFactorsRuntimeConfig { crypto: Some(keys) })
+}
+
+#[tokio::test]
+async fn keys_are_used_by_label() -> anyhow::Result<()> {
+    let mut state = test_env()?.build_instance_state().await?;
+    let crypto = &mut state.crypto;
+
+    assert!(matches!(
+        crypto.key_algorithm("jwt".into()).await,
+        Ok(crypto::Algorithm::HmacSha256)
+    ));
+    let signature = crypto.sign("jwt".into(), b"claims".to_vec()).await?;
+    assert!(
+        crypto
+            .verify("jwt".into(), b"claims".to_vec(), signature)
+            .await?
+    );
+
+    let ciphertext = crypto
+        .encrypt("cookies".into(), b"session".to_vec(), vec![])
+        .await?;
+    assert_eq!(
+        crypto.decrypt("cookies".into(), ciphertext, vec![]).await?,
+        b"session"
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn unknown_keys_and_operations_are_errors() -> anyhow::Result<()> {
+    let mut state = test_env()?.build_instance_state().await?;
+    let crypto = &mut state.crypto;
+
+    let result = crypto.sign("missing".into(), vec![]).await;
+    assert!(matches!(result, Err(Error::NoSuchKey)), "{result:?}");
+    let result = crypto.encrypt("jwt".into(), vec![], vec![]).await;
+    assert!(
+        matches!(result, Err(Error::UnsupportedOperation)),
+        "{result:?}"
+    );
+    Ok(())
+}
@@ -0,0 +1,49 @@
+use spin_factor_outbound_networking::OutboundUrl;
+use spin_factors::anyhow;
+use spin_world::{async_trait, spin::discovery::discovery};
+use tracing::instrument;
+
+use crate::{ChainableComponent, InstanceState};
+
+impl InstanceState {
+    /// Returns whether the instance's component may call `component`.
+    async fn can_call(&self, component: &ChainableComponent) -> anyhow::Result<bool> {
+        if component.id == self.component_id {
+            return Ok(false);
+        }
+        let url = OutboundUrl::parse(&component.url, "http")?;
+        self.allowed_hosts.allows(&url).await
+    }
+}
+
+#[async_trait]
+impl discovery::Host for InstanceState {
+    #[instrument(name = "spin_discovery.list_components", skip(self))]
+    async fn list_components(&mut self) -> anyhow::Result<Vec<discovery::Component>> {
+        let mut callable = vec![];
+        for component in self.components.iter() {
+            if self.can_call(component).await? {
+                callable.push(component.into());
+            }
+        }
+        Ok(callable)
+    }
+
+    #[instrument(name = "spin_discovery.get_component", skip(self))]
+    async fn get_component(&mut self, id: String) -> anyhow::Result<Option<discovery::Component>> {
+        let Some(component) = self.components.iter().find(|c| c.id == id) else {
+            return Ok(None);
+        };
+        Ok(self.can_call(component).await?.then(|| component.into()))
+    }
+}
+
+impl From<&ChainableComponent> for discovery::Component {
+    fn from(component: &ChainableComponent) -> Self {
+        Self {
+            id: component.id.clone(),
+            url: component.url.clone(),
+            routes: component.routes.clone(),
+        }
+    }
+}
@@ -0,0 +1,101 @@
+mod host;
+
+use std::{collections::BTreeMap, sync::Arc};
+
+use spin_factor_outbound_networking::{
+    OutboundAllowedHosts, OutboundNetworkingFactor, SERVICE_CHAINING_DOMAIN_SUFFIX,
+};
+use spin_factors::{
+    anyhow::{self, Context},
+    ConfigureAppContext, Factor, InitContext, PrepareContext, RuntimeFactors, SelfInstanceBuilder,
+};
+use spin_http::config::{HttpTriggerConfig, HttpTriggerRouteConfig};
+
+/// A factor for letting components discover the other components of their
+/// app that they can call through local service chaining.
+#[derive(Default)]
+pub struct DiscoveryFactor {
+    _priv: (),
+}
+
+impl DiscoveryFactor {
+    /// Creates a new `DiscoveryFactor`.
+    pub fn new() -> Self {
+        Default::default()
+    }
+}
+
+impl Factor for DiscoveryFactor {
+    type RuntimeConfig = ();
+    type AppState = AppState;
+    type InstanceBuilder = InstanceState;
+
+    fn init<T: Send + 'static>(&mut self, mut ctx: InitContext<T, Self>) -> anyhow::Result<()> {
+        ctx.link_bindings(spin_world::spin::discovery::discovery::add_to_linker)?;
+        Ok(())
+    }
+
+    fn configure_app<T: RuntimeFactors>(
+        &self,
+        ctx: ConfigureAppContext<T, Self>,
+    ) -> anyhow::Result<Self::AppState> {
+        // Only HTTP-triggered components can be reached through service chaining.
+        let mut components = BTreeMap::<String, Vec<String>>::new();
+        for trigger in ctx.app().triggers_with_type("http") {
+            let config = trigger
+                .typed_config::<HttpTriggerConfig>()
+                .with_context(|| format!("invalid config for HTTP trigger {}", trigger.id()))?;
+            let routes = components.entry(config.component).or_default();
+            if let HttpTriggerRouteConfig::Route(route) = config.route {
+                routes.push(route);
+            }
+        }
+        let components = components
+            .into_iter()
+            .map(|(id, routes)| ChainableComponent {
+                url: format!("http://{id}{SERVICE_CHAINING_DOMAIN_SUFFIX}"),
+                id,
+                routes,
+            })
+            .collect();
+        Ok(AppState {
+            components: Arc::new(components),
+        })
+    }
+
+    fn prepare<T: RuntimeFactors>(
+        &self,
+        mut ctx: PrepareContext<T, Self>,
+    ) -> anyhow::Result<InstanceState> {
+        let component_id = ctx.app_component().id().to_string();
+        let components = ctx.app_state().components.clone();
+        let allowed_hosts = ctx
+            .instance_builder::<OutboundNetworkingFactor>()?
+            .allowed_hosts();
+        Ok(InstanceState {
+            component_id,
+            components,
+            allowed_hosts,
+        })
+    }
+}
+
+pub struct AppState {
+    components: Arc<Vec<ChainableComponent>>,
+}
+
+/// An HTTP-triggered component and how to reach it.
+#[derive(Clone, Debug)]
+struct ChainableComponent {
+    id: String,
+    url: String,
+    routes: Vec<String>,
+}
+
+pub struct InstanceState {
+    component_id: String,
+    components: Arc<Vec<ChainableComponent>>,
+    allowed_hosts: OutboundAllowedHosts,
+}
+
+impl SelfInstanceBuilder for InstanceState {}
@@ -0,0 +1,109 @@
+use spin_factor_discovery::DiscoveryFactor;
+use spin_factor_outbound_networking::OutboundNetworkingFactor;
+use spin_factor_variables::VariablesFactor;
+use spin_factors::{anyhow, RuntimeFactors};
+use spin_factors_test::{toml, TestEnvironment};
+use spin_world::spin::discovery::discovery::{Component, Host};
+
+#[derive(RuntimeFactors)]
+struct TestFactors {
+    variables: VariablesFactor,
+    networking: OutboundNetworkingFactor,
+    discovery: DiscoveryFactor,
+}
+
+fn test_env(allowed_outbound_hosts: &[&str]) -> TestEnvironment<TestFactors> {
+    let factors = TestFactors {
+        variables: VariablesFactor::default(),
+        networking: OutboundNetworkingFactor::new(),
+        discovery: DiscoveryFactor::new(),
+    };
+    let mut manifest = toml! {
+        [[trigger.http]]
+        route = "/api/..."
+        component = "api"
+
+        [[trigger.http]]
+        route = "/v2/api/..."
+        component = "api"
+
+        [[trigger.http]]
+        route = { private = true }
+        component = "internal"
+
+        [[trigger.http]]
+        route = "/..."
+        component = "web"
+
+        [component.api]
+        source = "does-not-exist.wasm"
+
+        [component.internal]
+        source = "does-not-exist.wasm"
+
+        [component.unrouted]
+        source = "does-not-exist.wasm"
+
+        [component.web]
+        source = "does-not-exist.wasm"
+    };
+    manifest["component"]["web"]["allowed_outbound_hosts"] = allowed_outbound_hosts
+        .iter()
+        .map(|host| toml::Value::from(*host))
+        .collect::<Vec<_>>()
+        .into();
+    TestEnvironment::new(factors).extend_manifest(manifest)
+}
+
+fn summary(component: &Component) -> (&str, &str, Vec<&str>) {
+    (
+        &component.id,
+        &component.url,
+        component.routes.iter().map(String::as_str).collect(),
+    )
+}
+
+#[tokio::test]
+async fn lists_only_allowed_components() -> anyhow::Result<()> {
+    let env = test_env(&["http://internal.spin.internal"]);
+    let mut state = env.build_instance_state().await?;
+
+    let components = state.discovery.list_components().await?;
+    assert_eq!(
+        components.iter().map(summary).collect::<Vec<_>>(),
+        [("internal", "http://internal.spin.internal", vec![])]
+    );
+
+    assert!(state.discovery.get_component("api".into()).await?.is_none());
+    Ok(())
+}
+
+#[tokio::test]
+async fn wildcard_service_chaining_lists_all_http_components() -> anyhow::Result<()> {
+    let env = test_env(&["http://*.spin.internal"]);
+    let mut state = env.build_instance_state().await?;
+
+    let components = state.discovery.list_components().await?;
+    assert_eq!(
+        components.iter().map(summary).collect::<Vec<_>>(),
+        [
+            (
+                "api",
+                "http://api.spin.internal",
+                vec!["/api/...", "/v2/api/..."]
+            ),
+            ("internal", "http://internal.spin.internal", vec![]),
+        ]
+    );
+
+    let api = state.discovery.get_component("api".into()).await?;
+    assert_eq!(api.as_ref().map(|api| api.id.as_str()), Some("api"));
+    // Components without HTTP triggers, and the caller itself, are never listed.
+    assert!(state
+        .discovery
+        .get_component("unrouted".into())
+        .await?
+        .is_none());
+    assert!(state.discovery.get_component("web".into()).await?.is_none());
+    Ok(())
+}
@@ -0,0 +1,142 @@
+use std::collections::BTreeMap;
+
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use spin_factors::anyhow::{self, ensure};
+
+/// An experiment, as defined by `[experiment.<name>]` in the runtime config.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ExperimentConfig {
+    /// The experiment's variants and their relative weights, e.g.
+    /// `{ control = 90, new-checkout = 10 }`. A variant with a weight of 0 is
+    /// never assigned.
+    pub variants: BTreeMap<String, u32>,
+    /// Mixed into the hash users are assigned by, so that experiments assign
+    /// users independently of each other. Defaults to the experiment's name;
+    /// changing it reassigns every user.
+    #[serde(default)]
+    pub salt: Option<String>,
+}
+
+/// An experiment, ready to assign users to its variants.
+#[derive(Debug)]
+pub(crate) struct Experiment {
+    salt: String,
+    /// The variants, in order of name, with the total of their own and the
+    /// preceding variants' weights.
+    variants: Vec<(String, u64)>,
+    total_weight: u64,
+}
+
+impl Experiment {
+    pub fn new(name: &str, config: ExperimentConfig) -> anyhow::Result<Self> {
+        let mut total_weight = 0;
+        let mut variants = Vec::with_capacity(config.variants.len());
+        for (variant, weight) in config.variants {
+            total_weight += u64::from(weight);
+            variants.push((variant, total_weight));
+        }
+        ensure!(
+            total_weight > 0,
+            "experiment {name:?} has no variants with a weight above 0"
+        );
+        Ok(Self {
+            salt: config.salt.unwrap_or_else(|| name.to_owned()),
+            variants,
+            total_weight,
+        })
+    }
+
+    /// Returns the variant assigned to the user with the given key.
+    ///
+    /// Users are assigned by hashing their key into one of `total_weight`
+    /// buckets, which are divided between the variants by weight.
+    pub fn assign(&self, user_key: &str) -> &str {
+        let digest = Sha256::new()
+            .chain_update(self.salt.as_bytes())
+            .chain_update([0])
+            .chain_update(user_key.as_bytes())
+            .finalize();
+        let hash = u64::from_be_bytes(digest[..8].try_into().unwrap());
+        let bucket = hash % self.total_weight;
+        self.variants
+            .iter()
+            .find(|(_, cumulative_weight)| bucket < *cumulative_weight)
+            .map(|(variant, _)| variant.as_str())
+            .expect("every bucket is below the total weight")
+    }
+
+    /// The names of the variants, including those with a weight of 0.
+    pub fn variants(&self) -> impl Iterator<Item = &str> {
+        self.variants.iter().map(|(variant, _)| variant.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn experiment(variants: &[(&str, u32)], salt: Option<&str>) -> Experiment {
+        let config = ExperimentConfig {
+            variants: variants
+                .iter()
+                .map(|(variant, weight)| (variant.to_string(), *weight))
+                .collect(),
+            salt: salt.map(ToOwned::to_owned),
+        };
+        Experiment::new("checkout", config).unwrap()
+    }
+
+    fn assignments(experiment: &Experiment) -> HashMap<&str, usize> {
+        let mut counts = HashMap::new();
+        for user in 0..10_000 {
+            *counts
+                .entry(experiment.assign(&format!("user-{user}")))
+                .or_default() += 1;
+        }
+        counts
+    }
+
+    #[test]
+    fn users_are_assigned_by_weight() {
+        let experiment = experiment(&[("control", 3), ("green", 1), ("off", 0)], None);
+        let counts = assignments(&experiment);
+        assert!((7_000..8_000).contains(&counts["control"]), "{counts:?}");
+        assert!((2_000..3_000).contains(&counts["green"]), "{counts:?}");
+        assert!(!counts.contains_key("off"), "{counts:?}");
+    }
+
+    #[test]
+    fn assignments_are_sticky() {
+        let first = experiment(&[("control", 1), ("green", 1)], None);
+        let second = experiment(&[("control", 1), ("green", 1)], None);
+        for user in ["alice", "bob", "carol"] {
+            assert_eq!(first.assign(user), second.assign(user));
+        }
+    }
+
+    #[test]
+    fn salt_changes_assignments() {
+        let unsalted = experiment(&[("control", 1), ("green", 1)], None);
+        let salted = experiment(&[("control", 1), ("green", 1)], Some("round-2"));
+        let changed = (0..100)
+            .map(|user| format!("user-{user}"))
+            .filter(|user| unsalted.assign(user) != salted.assign(user))
+            .count();
+        assert!(changed > 0);
+    }
+
+    #[test]
+    fn experiments_need_a_weighted_variant() {
+        for variants in [vec![], vec![("control".to_owned(), 0)]] {
+            let config = ExperimentConfig {
+                variants: variants.into_iter().collect(),
+                salt: None,
+            };
+            assert!(Experiment::new("checkout", config).is_err());
+        }
+    }
+}
@@ -0,0 +1,145 @@
+mod experiment;
+
+use std::{collections::HashMap, sync::Arc};
+
+use serde::Deserialize;
+use spin_factors::{
+    anyhow::{self, Context},
+    ConfigureAppContext, Factor, InitContext, PrepareContext, RuntimeFactors, SelfInstanceBuilder,
+};
+use spin_world::{
+    async_trait,
+    spin::experiments::experiments::{self, Error},
+};
+use tracing::{instrument, Level};
+
+use experiment::Experiment;
+pub use experiment::ExperimentConfig;
+
+/// A factor for assigning users to the variants of experiments, such as A/B
+/// tests, defined in the runtime config.
+#[derive(Default)]
+pub struct ExperimentsFactor {
+    _priv: (),
+}
+
+impl ExperimentsFactor {
+    /// Creates a new `ExperimentsFactor`.
+    pub fn new() -> Self {
+        Default::default()
+    }
+}
+
+impl Factor for ExperimentsFactor {
+    type RuntimeConfig = RuntimeConfig;
+    type AppState = AppState;
+    type InstanceBuilder = InstanceState;
+
+    fn init<T: Send + 'static>(&mut self, mut ctx: InitContext<T, Self>) -> anyhow::Result<()> {
+        ctx.link_bindings(experiments::add_to_linker)?;
+        Ok(())
+    }
+
+    fn configure_app<T: RuntimeFactors>(
+        &self,
+        mut ctx: ConfigureAppContext<T, Self>,
+    ) -> anyhow::Result<Self::AppState> {
+        let config = ctx.take_runtime_config().unwrap_or_default();
+        let experiments = config
+            .experiments
+            .into_iter()
+            .map(|(name, config)| {
+                let experiment = Experiment::new(&name, config)
+                    .with_context(|| format!("invalid [experiment.{name}] runtime config"))?;
+                Ok((name, experiment))
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Ok(AppState {
+            experiments: Arc::new(experiments),
+        })
+    }
+
+    fn prepare<T: RuntimeFactors>(
+        &self,
+        ctx: PrepareContext<T, Self>,
+    ) -> anyhow::Result<Self::InstanceBuilder> {
+        let component = ctx.app_component();
+        Ok(InstanceState {
+            experiments: ctx.app_state().experiments.clone(),
+            app_id: component.app.id().to_owned(),
+            component_id: component.id().to_owned(),
+        })
+    }
+}
+
+/// The `[experiment.<name>]` runtime config sections.
+#[derive(Debug, Default, Deserialize)]
+#[serde(transparent)]
+pub struct RuntimeConfig {
+    /// The app's experiments, by name.
+    pub experiments: HashMap<String, ExperimentConfig>,
+}
+
+pub struct AppState {
+    experiments: Arc<HashMap<String, Experiment>>,
+}
+
+pub struct InstanceState {
+    experiments: Arc<HashMap<String, Experiment>>,
+    app_id: String,
+    component_id: String,
+}
+
+impl InstanceState {
+    fn experiment(&self, name: &str) -> Result<&Experiment, Error> {
+        self.experiments.get(name).ok_or(Error::NoSuchExperiment)
+    }
+}
+
+impl SelfInstanceBuilder for InstanceState {}
+
+#[async_trait]
+impl experiments::Host for InstanceState {
+    #[instrument(name = "spin_experiments.get_variant", skip(self, user_key), err(level = Level::INFO))]
+    async fn get_variant(&mut self, experiment: String, user_key: String) -> Result<String, Error> {
+        let variant = self.experiment(&experiment)?.assign(&user_key);
+        // Exposures are recorded on the current span, so they reach the same
+        // place as the rest of the request's telemetry, and counted by
+        // variant. The user key is left out, as it may identify a person.
+        tracing::info!(
+            experiment = experiment.as_str(),
+            variant,
+            component_id = self.component_id.as_str(),
+            "Experiment exposure"
+        );
+        spin_telemetry::metrics::monotonic_counter!(
+            spin.experiment_exposures = 1,
+            app_id = self.app_id.as_str(),
+            experiment = experiment.as_str(),
+            variant = variant
+        );
+        Ok(variant.to_owned())
+    }
+
+    #[instrument(name = "spin_experiments.peek_variant", skip(self, user_key), err(level = Level::INFO))]
+    async fn peek_variant(
+        &mut self,
+        experiment: String,
+        user_key: String,
+    ) -> Result<String, Error> {
+        Ok(self.experiment(&experiment)?.assign(&user_key).to_owned())
+    }
+
+    #[instrument(name = "spin_experiments.list_variants", skip(self), err(level = Level::INFO))]
+    async fn list_variants(&mut self, experiment: String) -> Result<Vec<String>, Error> {
+        Ok(self
+            .experiment(&experiment)?
+            .variants()
+            .map(ToOwned::to_owned)
+            .collect())
+    }
+
+    fn convert_error(&mut self, error: Error) -> anyhow::Result<Error> {
+        Ok(error)
+    }
+}
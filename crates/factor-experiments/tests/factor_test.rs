@@ -0,0 +1,72 @@
+use spin_factor_experiments::{ExperimentsFactor, RuntimeConfig};
+use spin_factors::{anyhow, RuntimeFactors};
+use spin_factors_test::{toml, TestEnvironment};
+use spin_world::spin::experiments::experiments::{Error, Host};
+
+#[derive(RuntimeFactors)]
+struct TestFactors {
+    experiments: ExperimentsFactor,
+}
+
+fn test_env(experiments: toml::Table) -> anyhow::Result<TestEnvironment<TestFactors>> {
+    let factors = TestFactors {
+        experiments: ExperimentsFactor::new(),
+    };
+    let config: RuntimeConfig = experiments.try_into()?;
+    TestEnvironment::new(factors)
+        .extend_manifest(toml! {
+            [component.test-component]
+            source = "does-not-exist.wasm"
+        })
+        .runtime_config(TestFactorsRuntimeConfig {
+            experiments: Some(config),
+        })
+}
+
+#[tokio::test]
+async fn users_are_assigned_variants() -> anyhow::Result<()> {
+    let mut state = test_env(toml! {
+        [checkout]
+        variants = { control = 1, green = 1, retired = 0 }
+    })?
+    .build_instance_state()
+    .await?;
+    let experiments = &mut state.experiments;
+
+    let variant = experiments
+        .get_variant("checkout".into(), "user-1".into())
+        .await?;
+    assert!(["control", "green"].contains(&variant.as_str()));
+    assert_eq!(
+        variant,
+        experiments
+            .peek_variant("checkout".into(), "user-1".into())
+            .await?
+    );
+    assert_eq!(
+        vec!["control", "green", "retired"],
+        experiments.list_variants("checkout".into()).await?
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn unknown_experiments_are_errors() -> anyhow::Result<()> {
+    let mut state = test_env(toml::Table::new())?.build_instance_state().await?;
+    let result = state
+        .experiments
+        .get_variant("checkout".into(), "user-1".into())
+        .await;
+    assert!(matches!(result, Err(Error::NoSuchExperiment)), "{result:?}");
+    Ok(())
+}
+
+#[tokio::test]
+async fn experiments_without_weights_are_rejected() -> anyhow::Result<()> {
+    let env = test_env(toml! {
+        [checkout]
+        variants = { control = 0 }
+    })?;
+    assert!(env.build_instance_state().await.is_err());
+    Ok(())
+}
@@ -0,0 +1,145 @@
+//! Dumping key-value stores to a portable format and loading dumps into
+//! stores, e.g. to move an app's data to a store of a different type.
+//!
+//! A dump is a JSON Lines file: a header line identifying the format, then a
+//! line for each key-value pair, in order of key. Values are base64-encoded,
+//! as they may be arbitrary bytes.
+//!
+//! ```text
+//! {"spin_key_value_dump":1}
+//! {"key":"greeting","value":"aGVsbG8="}
+//! ```
+
+use std::io::{BufRead, Write};
+
+use anyhow::{bail, Context, Result};
+use base64::{prelude::BASE64_STANDARD, Engine};
+use serde::{Deserialize, Serialize};
+
+use crate::{is_lease_key, Store};
+
+/// The dump format version written by this version of Spin.
+pub const DUMP_VERSION: u32 = 1;
+
+/// How many pairs to get or set in a single store operation.
+const BATCH_SIZE: usize = 100;
+
+#[derive(Serialize, Deserialize)]
+struct Header {
+    spin_key_value_dump: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    key: String,
+    value: String,
+}
+
+/// Writes all the key-value pairs in the store to `writer`, returning how
+/// many were written.
+pub async fn dump(store: &dyn Store, mut writer: impl Write) -> Result<usize> {
+    write_line(
+        &mut writer,
+        &Header {
+            spin_key_value_dump: DUMP_VERSION,
+        },
+    )?;
+
+    let mut keys = store.get_keys().await.context("failed to list keys")?;
+    // Leases are held by running instances, and aren't app data.
+    keys.retain(|key| !is_lease_key(key));
+    keys.sort();
+    let mut count = 0;
+    for batch in keys.chunks(BATCH_SIZE) {
+        let values = store
+            .get_many(batch.to_vec())
+            .await
+            .context("failed to get values")?;
+        for (key, value) in values {
+            // The key may have been deleted since it was listed.
+            let Some(value) = value else { continue };
+            write_line(
+                &mut writer,
+                &Entry {
+                    key,
+                    value: BASE64_STANDARD.encode(value),
+                },
+            )?;
+            count += 1;
+        }
+    }
+    writer.flush()?;
+    Ok(count)
+}
+
+/// The outcome of [`load`].
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct LoadReport {
+    /// How many pairs were set in the store.
+    pub loaded: usize,
+    /// How many pairs were not set because their key already existed.
+    pub skipped: usize,
+}
+
+/// Sets the key-value pairs of a dump read from `reader` in the store. Keys
+/// that already exist in the store are skipped unless `overwrite` is set.
+pub async fn load(store: &dyn Store, reader: impl BufRead, overwrite: bool) -> Result<LoadReport> {
+    let mut lines = reader.lines().enumerate();
+    let Some((_, header)) = lines.next() else {
+        bail!("the dump is empty");
+    };
+    let header: Header = serde_json::from_str(&header?)
+        .context("not a Spin key-value dump: the first line is not a dump header")?;
+    if header.spin_key_value_dump > DUMP_VERSION {
+        bail!(
+            "the dump has format version {}, but this version of Spin only reads versions up to {DUMP_VERSION}",
+            header.spin_key_value_dump
+        );
+    }
+
+    let mut report = LoadReport::default();
+    let mut batch = Vec::with_capacity(BATCH_SIZE);
+    for (index, line) in lines {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry: Entry = serde_json::from_str(&line)
+            .with_context(|| format!("invalid entry on line {}", index + 1))?;
+        let value = BASE64_STANDARD
+            .decode(&entry.value)
+            .with_context(|| format!("invalid value on line {}", index + 1))?;
+        if !overwrite
+            && store
+                .exists(&entry.key)
+                .await
+                .with_context(|| format!("failed to check whether key {:?} exists", entry.key))?
+        {
+            report.skipped += 1;
+            continue;
+        }
+        batch.push((entry.key, value));
+        if batch.len() == BATCH_SIZE {
+            report.loaded += set_batch(store, &mut batch).await?;
+        }
+    }
+    report.loaded += set_batch(store, &mut batch).await?;
+    Ok(report)
+}
+
+async fn set_batch(store: &dyn Store, batch: &mut Vec<(String, Vec<u8>)>) -> Result<usize> {
+    let count = batch.len();
+    if count > 0 {
+        store
+            .set_many(std::mem::take(batch))
+            .await
+            .context("failed to set values")?;
+    }
+    Ok(count)
+}
+
+fn write_line(writer: &mut impl Write, value: &impl Serialize) -> Result<()> {
+    serde_json::to_writer(&mut *writer, value)?;
+    writer.write_all(b"\n")?;
+    Ok(())
+}
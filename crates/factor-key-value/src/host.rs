@@ -1,16 +1,38 @@
-use super::{Cas, SwapError};
+use super::{
+    watch::{Change, Watcher},
+    Cas, SwapError,
+};
 use anyhow::{Context, Result};
 use spin_core::{async_trait, wasmtime::component::Resource};
+use spin_errors::ErrorKind;
 use spin_resource_table::Table;
 use spin_world::v2::key_value;
 use spin_world::wasi::keyvalue as wasi_keyvalue;
-use std::{collections::HashSet, sync::Arc};
+use std::{collections::HashSet, sync::Arc, time::Duration};
 use tracing::{instrument, Level};
 
 const DEFAULT_STORE_TABLE_CAPACITY: u32 = 256;
 
 pub use key_value::Error;
 
+/// The prefix of the keys holding `spin:lock` leases. Keys with this prefix
+/// are reserved: they are left out of key listings and watches, so that the
+/// leases in stores which hold them alongside other keys aren't seen as data.
+pub const LEASE_KEY_PREFIX: &str = "__spin_lock:";
+
+/// Returns whether `key` holds a `spin:lock` lease.
+pub fn is_lease_key(key: &str) -> bool {
+    key.starts_with(LEASE_KEY_PREFIX)
+}
+
+fn lease_key(name: &str) -> String {
+    format!("{LEASE_KEY_PREFIX}{name}")
+}
+
+fn without_lease_keys(keys: Vec<String>) -> Vec<String> {
+    keys.into_iter().filter(|key| !is_lease_key(key)).collect()
+}
+
 #[async_trait]
 pub trait StoreManager: Sync + Send {
     async fn get(&self, name: &str) -> Result<Arc<dyn Store>, Error>;
@@ -38,6 +60,44 @@ pub trait Store: Sync + Send {
     async fn increment(&self, key: String, delta: i64) -> Result<i64, Error>;
     async fn new_compare_and_swap(&self, bucket_rep: u32, key: &str)
         -> Result<Arc<dyn Cas>, Error>;
+
+    /// Acquires a lease on `key` with the given token, expiring after `ttl`,
+    /// unless another lease on it is unexpired. Returns whether the lease was
+    /// acquired.
+    ///
+    /// Stores which don't support leases return an error.
+    async fn acquire_lease(&self, key: &str, token: &str, ttl: Duration) -> Result<bool, Error> {
+        let _ = (key, token, ttl);
+        Err(leases_unsupported())
+    }
+
+    /// Extends the unexpired lease on `key` with the given token to expire
+    /// after `ttl`. Returns false if there is no such lease.
+    async fn renew_lease(&self, key: &str, token: &str, ttl: Duration) -> Result<bool, Error> {
+        let _ = (key, token, ttl);
+        Err(leases_unsupported())
+    }
+
+    /// Releases the unexpired lease on `key` with the given token. Returns
+    /// false if there is no such lease.
+    async fn release_lease(&self, key: &str, token: &str) -> Result<bool, Error> {
+        let _ = (key, token);
+        Err(leases_unsupported())
+    }
+
+    /// Watches for changes to keys starting with `prefix`.
+    ///
+    /// Stores which don't support watches return an error.
+    async fn watch(&self, prefix: &str) -> Result<Watcher, Error> {
+        let _ = prefix;
+        Err(Error::Other(
+            "this key-value store does not support watches".to_string(),
+        ))
+    }
+}
+
+fn leases_unsupported() -> Error {
+    Error::Other("this key-value store does not support leases".to_string())
 }
 
 pub struct KeyValueDispatch {
@@ -45,6 +105,7 @@ pub struct KeyValueDispatch {
     manager: Arc<dyn StoreManager>,
     stores: Table<Arc<dyn Store>>,
     compare_and_swaps: Table<Arc<dyn Cas>>,
+    watchers: Table<Watcher>,
 }
 
 impl KeyValueDispatch {
@@ -62,9 +123,18 @@ impl KeyValueDispatch {
             manager,
             stores: Table::new(capacity),
             compare_and_swaps: Table::new(capacity),
+            watchers: Table::new(capacity),
         }
     }
 
+    /// Opens a store by label, if the component is allowed to use it.
+    async fn open_allowed(&self, name: &str) -> Result<Arc<dyn Store>, Error> {
+        if !self.allowed_stores.contains(name) {
+            return Err(Error::AccessDenied);
+        }
+        self.manager.get(name).await
+    }
+
     pub fn get_store<T: 'static>(&self, store: Resource<T>) -> anyhow::Result<&Arc<dyn Store>> {
         self.stores.get(store.rep()).context("invalid store")
     }
@@ -168,7 +238,7 @@ impl key_value::HostStore for KeyValueDispatch {
         store: Resource<key_value::Store>,
     ) -> Result<Result<Vec<String>, Error>> {
         let store = self.get_store(store)?;
-        Ok(store.get_keys().await)
+        Ok(store.get_keys().await.map(without_lease_keys))
     }
 
     async fn drop(&mut self, store: Resource<key_value::Store>) -> Result<()> {
@@ -177,11 +247,15 @@ impl key_value::HostStore for KeyValueDispatch {
     }
 }
 
+fn store_table_full() -> wasi_keyvalue::store::Error {
+    wasi_keyvalue::store::Error::Other("store table full".to_string())
+}
+
 fn to_wasi_err(e: Error) -> wasi_keyvalue::store::Error {
     match e {
         Error::AccessDenied => wasi_keyvalue::store::Error::AccessDenied,
         Error::NoSuchStore => wasi_keyvalue::store::Error::NoSuchStore,
-        Error::StoreTableFull => wasi_keyvalue::store::Error::Other("store table full".to_string()),
+        Error::StoreTableFull => store_table_full(),
         Error::Other(msg) => wasi_keyvalue::store::Error::Other(msg),
     }
 }
@@ -196,7 +270,7 @@ impl wasi_keyvalue::store::Host for KeyValueDispatch {
             let store = self
                 .stores
                 .push(self.manager.get(&identifier).await.map_err(to_wasi_err)?)
-                .map_err(|()| wasi_keyvalue::store::Error::Other("store table full".to_string()))?;
+                .map_err(|()| store_table_full())?;
             Ok(Resource::new_own(store))
         } else {
             Err(wasi_keyvalue::store::Error::AccessDenied)
@@ -263,7 +337,10 @@ impl wasi_keyvalue::store::HostBucket for KeyValueDispatch {
             None => {
                 let store = self.get_store_wasi(self_)?;
                 let keys = store.get_keys().await.map_err(to_wasi_err)?;
-                Ok(wasi_keyvalue::store::KeyResponse { keys, cursor: None })
+                Ok(wasi_keyvalue::store::KeyResponse {
+                    keys: without_lease_keys(keys),
+                    cursor: None,
+                })
             }
         }
     }
@@ -410,7 +487,12 @@ impl wasi_keyvalue::atomics::Host for KeyValueDispatch {
 }
 
 pub fn log_error(err: impl std::fmt::Debug) -> Error {
-    tracing::warn!("key-value error: {err:?}");
+    log_error_kind(ErrorKind::Other, err)
+}
+
+/// Like [`log_error`], for store errors whose [`ErrorKind`] is known.
+pub fn log_error_kind(kind: ErrorKind, err: impl std::fmt::Debug) -> Error {
+    tracing::warn!(error.kind = %kind, "key-value error: {err:?}");
     Error::Other(format!("{err:?}"))
 }
 
@@ -419,6 +501,7 @@ pub fn log_cas_error(err: impl std::fmt::Debug) -> SwapError {
     SwapError::Other(format!("{err:?}"))
 }
 
+use spin_world::spin::lock::lock;
 use spin_world::v1::key_value::Error as LegacyError;
 use spin_world::wasi::keyvalue::atomics;
 use spin_world::wasi::keyvalue::atomics::{CasError, HostCas};
@@ -481,3 +564,133 @@ impl spin_world::v1::key_value::Host for KeyValueDispatch {
         <Self as key_value::HostStore>::drop(self, this).await
     }
 }
+
+fn to_lock_error(value: key_value::Error) -> lock::Error {
+    match value {
+        Error::NoSuchStore => lock::Error::NoSuchStore,
+        Error::AccessDenied => lock::Error::AccessDenied,
+        Error::StoreTableFull => lock::Error::Other("store table full".into()),
+        Error::Other(s) => lock::Error::Other(s),
+    }
+}
+
+#[async_trait]
+impl lock::Host for KeyValueDispatch {
+    #[instrument(name = "spin_lock.acquire", skip(self), err(level = Level::INFO), fields(otel.kind = "client"))]
+    async fn acquire(
+        &mut self,
+        store: String,
+        name: String,
+        ttl_ms: u64,
+    ) -> Result<Option<String>, lock::Error> {
+        let store = self.open_allowed(&store).await.map_err(to_lock_error)?;
+        let token = uuid::Uuid::new_v4().to_string();
+        let acquired = store
+            .acquire_lease(&lease_key(&name), &token, Duration::from_millis(ttl_ms))
+            .await
+            .map_err(to_lock_error)?;
+        Ok(acquired.then_some(token))
+    }
+
+    #[instrument(name = "spin_lock.renew", skip(self, token), err(level = Level::INFO), fields(otel.kind = "client"))]
+    async fn renew(
+        &mut self,
+        store: String,
+        name: String,
+        token: String,
+        ttl_ms: u64,
+    ) -> Result<bool, lock::Error> {
+        let store = self.open_allowed(&store).await.map_err(to_lock_error)?;
+        store
+            .renew_lease(&lease_key(&name), &token, Duration::from_millis(ttl_ms))
+            .await
+            .map_err(to_lock_error)
+    }
+
+    #[instrument(name = "spin_lock.release", skip(self, token), err(level = Level::INFO), fields(otel.kind = "client"))]
+    async fn release(
+        &mut self,
+        store: String,
+        name: String,
+        token: String,
+    ) -> Result<bool, lock::Error> {
+        let store = self.open_allowed(&store).await.map_err(to_lock_error)?;
+        store
+            .release_lease(&lease_key(&name), &token)
+            .await
+            .map_err(to_lock_error)
+    }
+
+    fn convert_error(&mut self, error: lock::Error) -> anyhow::Result<lock::Error> {
+        Ok(error)
+    }
+}
+
+use spin_world::spin::key_value::watch;
+
+fn to_watch_error(value: key_value::Error) -> watch::Error {
+    match value {
+        Error::NoSuchStore => watch::Error::NoSuchStore,
+        Error::AccessDenied => watch::Error::AccessDenied,
+        Error::StoreTableFull => watch::Error::Other("store table full".into()),
+        Error::Other(s) => watch::Error::Other(s),
+    }
+}
+
+impl watch::Host for KeyValueDispatch {
+    fn convert_error(&mut self, error: watch::Error) -> anyhow::Result<watch::Error> {
+        Ok(error)
+    }
+}
+
+#[async_trait]
+impl watch::HostWatcher for KeyValueDispatch {
+    #[instrument(name = "spin_key_value.watch", skip(self), err(level = Level::INFO), fields(otel.kind = "client"))]
+    async fn open(
+        &mut self,
+        store: String,
+        key_prefix: String,
+    ) -> Result<Resource<watch::Watcher>, watch::Error> {
+        let store = self.open_allowed(&store).await.map_err(to_watch_error)?;
+        let watcher = store
+            .watch(&key_prefix)
+            .await
+            .map_err(to_watch_error)?
+            .map_keys(|key| (!is_lease_key(&key)).then_some(key));
+        self.watchers
+            .push(watcher)
+            .map(Resource::new_own)
+            .map_err(|()| watch::Error::TooManyWatchers)
+    }
+
+    async fn next(
+        &mut self,
+        watcher: Resource<watch::Watcher>,
+        timeout_ms: Option<u64>,
+    ) -> Result<Option<watch::Change>, watch::Error> {
+        let watcher = self
+            .watchers
+            .get_mut(watcher.rep())
+            .ok_or_else(|| watch::Error::Other("invalid watcher".into()))?;
+        let change = match timeout_ms {
+            Some(timeout_ms) => {
+                match tokio::time::timeout(Duration::from_millis(timeout_ms), watcher.next()).await
+                {
+                    Ok(change) => change,
+                    Err(_) => return Ok(None),
+                }
+            }
+            None => watcher.next().await,
+        };
+        Ok(Some(match change.map_err(to_watch_error)? {
+            Change::Set(key) => watch::Change::Set(key),
+            Change::Deleted(key) => watch::Change::Deleted(key),
+            Change::Lagged => watch::Change::Lagged,
+        }))
+    }
+
+    async fn drop(&mut self, watcher: Resource<watch::Watcher>) -> Result<()> {
+        self.watchers.remove(watcher.rep());
+        Ok(())
+    }
+}
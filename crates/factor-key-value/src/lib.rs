@@ -1,13 +1,15 @@
+pub mod dump;
 mod host;
 pub mod runtime_config;
 mod util;
+pub mod watch;
 
 use std::{
     collections::{HashMap, HashSet},
     sync::Arc,
 };
 
-use anyhow::ensure;
+use anyhow::{ensure, Context};
 use spin_factors::{
     ConfigureAppContext, Factor, FactorInstanceBuilder, InitContext, PrepareContext, RuntimeFactors,
 };
@@ -15,10 +17,19 @@ use spin_locked_app::MetadataKey;
 
 /// Metadata key for key-value stores.
 pub const KEY_VALUE_STORES_KEY: MetadataKey<Vec<String>> = MetadataKey::new("key_value_stores");
-pub use host::{log_cas_error, log_error, Error, KeyValueDispatch, Store, StoreManager};
+/// Metadata key for a component's key-value namespace.
+pub const KEY_VALUE_NAMESPACE_KEY: MetadataKey<String> = MetadataKey::new("key_value_namespace");
+pub use host::{
+    is_lease_key, log_cas_error, log_error, log_error_kind, Error, KeyValueDispatch, Store,
+    StoreManager, LEASE_KEY_PREFIX,
+};
 pub use runtime_config::RuntimeConfig;
 use spin_core::async_trait;
-pub use util::{CachingStoreManager, DelegatingStoreManager};
+pub use spin_errors::ErrorKind;
+pub use util::{
+    migrate_into_namespace, namespace_prefix, CachingStoreManager, DelegatingStoreManager,
+    NamespacedStoreManager, NAMESPACE_SEPARATOR,
+};
 
 /// A factor that provides key-value storage.
 #[derive(Default)]
@@ -44,6 +55,8 @@ impl Factor for KeyValueFactor {
         ctx.link_bindings(spin_world::wasi::keyvalue::store::add_to_linker)?;
         ctx.link_bindings(spin_world::wasi::keyvalue::batch::add_to_linker)?;
         ctx.link_bindings(spin_world::wasi::keyvalue::atomics::add_to_linker)?;
+        ctx.link_bindings(spin_world::spin::lock::lock::add_to_linker)?;
+        ctx.link_bindings(spin_world::spin::key_value::watch::add_to_linker)?;
         Ok(())
     }
 
@@ -57,8 +70,9 @@ impl Factor for KeyValueFactor {
         let caching_manager = CachingStoreManager::new(delegating_manager);
         let store_manager = Arc::new(caching_manager);
 
-        // Build component -> allowed stores map
+        // Build component -> allowed stores and namespace maps
         let mut component_allowed_stores = HashMap::new();
+        let mut component_namespaces = HashMap::new();
         for component in ctx.app().components() {
             let component_id = component.id().to_string();
             let key_value_stores = component
@@ -73,6 +87,13 @@ impl Factor for KeyValueFactor {
                     "unknown key_value_stores label {label:?} for component {component_id:?}"
                 );
             }
+            if let Some(namespace) = component.get_metadata(KEY_VALUE_NAMESPACE_KEY)? {
+                ensure!(
+                    !namespace.is_empty() && !namespace.contains(NAMESPACE_SEPARATOR),
+                    "invalid key_value_namespace {namespace:?} for component {component_id:?}: must be non-empty and not contain {NAMESPACE_SEPARATOR:?}"
+                );
+                component_namespaces.insert(component_id.clone(), namespace);
+            }
             component_allowed_stores.insert(component_id, key_value_stores);
             // TODO: warn (?) on unused store?
         }
@@ -80,6 +101,7 @@ impl Factor for KeyValueFactor {
         Ok(AppState {
             store_manager,
             component_allowed_stores,
+            component_namespaces,
         })
     }
 
@@ -93,8 +115,16 @@ impl Factor for KeyValueFactor {
             .get(ctx.app_component().id())
             .expect("component should be in component_stores")
             .clone();
+        let store_manager: Arc<dyn StoreManager> =
+            match app_state.component_namespaces.get(ctx.app_component().id()) {
+                Some(namespace) => Arc::new(NamespacedStoreManager::new(
+                    app_state.store_manager.clone(),
+                    namespace,
+                )),
+                None => app_state.store_manager.clone(),
+            };
         Ok(InstanceBuilder {
-            store_manager: app_state.store_manager.clone(),
+            store_manager,
             allowed_stores,
         })
     }
@@ -114,6 +144,8 @@ pub struct AppState {
     /// This is a map from component ID to the set of store labels that the
     /// component is allowed to use.
     component_allowed_stores: HashMap<String, HashSet<String>>,
+    /// The key-value namespace of each component that has one.
+    component_namespaces: HashMap<String, String>,
 }
 
 impl AppState {
@@ -122,6 +154,12 @@ impl AppState {
         self.store_manager.summary(label)
     }
 
+    /// Returns true if the given store label is defined in the runtime config,
+    /// or by default.
+    pub fn store_is_defined(&self, label: &str) -> bool {
+        self.store_manager.is_defined(label)
+    }
+
     /// Returns true if the given store label is used by any component.
     pub fn store_is_used(&self, label: &str) -> bool {
         self.component_allowed_stores
@@ -129,10 +167,74 @@ impl AppState {
             .any(|stores| stores.contains(label))
     }
 
+    /// Returns the labels of the stores used by any component, in order.
+    pub fn used_stores(&self) -> Vec<&str> {
+        let mut labels = self
+            .component_allowed_stores
+            .values()
+            .flatten()
+            .map(String::as_str)
+            .collect::<Vec<_>>();
+        labels.sort();
+        labels.dedup();
+        labels
+    }
+
+    /// Returns the app's store manager, which opens stores by label outside
+    /// of any component's namespace.
+    pub fn store_manager(&self) -> Arc<dyn StoreManager> {
+        self.store_manager.clone()
+    }
+
     /// Get a store by label.
     pub async fn get_store(&self, label: &str) -> Option<Arc<dyn Store>> {
         self.store_manager.get(label).await.ok()
     }
+
+    /// Returns the key-value namespace of the given component, if it has one.
+    pub fn component_namespace(&self, component_id: &str) -> Option<&str> {
+        self.component_namespaces
+            .get(component_id)
+            .map(String::as_str)
+    }
+
+    /// Moves the keys in the given component's stores that are not in any
+    /// component's namespace into that component's namespace, returning the
+    /// number of keys moved.
+    ///
+    /// This migrates the existing data of a component that has been given a
+    /// `key_value_namespace`.
+    pub async fn migrate_into_namespace(&self, component_id: &str) -> anyhow::Result<usize> {
+        let namespace = self
+            .component_namespace(component_id)
+            .with_context(|| format!("component {component_id:?} has no key_value_namespace"))?;
+        let namespaces = self
+            .component_namespaces
+            .values()
+            .map(String::as_str)
+            .collect::<Vec<_>>();
+        let mut labels = self
+            .component_allowed_stores
+            .get(component_id)
+            .with_context(|| format!("no such component {component_id:?}"))?
+            .iter()
+            .collect::<Vec<_>>();
+        labels.sort();
+
+        let mut moved = 0;
+        for label in labels {
+            let store = self
+                .store_manager
+                .get(label)
+                .await
+                .with_context(|| format!("failed to open key-value store {label:?}"))?;
+            moved += migrate_into_namespace(&*store, namespace, &namespaces)
+                .await
+                .with_context(|| format!("failed to migrate key-value store {label:?}"))?
+                .len();
+        }
+        Ok(moved)
+    }
 }
 
 /// `SwapError` are errors that occur during compare and swap operations
@@ -171,12 +273,23 @@ pub struct InstanceBuilder {
     ///
     /// This is a cache around a delegating store manager. For `get` requests,
     /// first checks the cache before delegating to the underlying store
-    /// manager.
-    store_manager: Arc<AppStoreManager>,
+    /// manager. It may be wrapped with [`InstanceBuilder::wrap_store_manager`].
+    store_manager: Arc<dyn StoreManager>,
     /// The allowed stores for this component instance.
     allowed_stores: HashSet<String>,
 }
 
+impl InstanceBuilder {
+    /// Wraps the store manager used by this instance, e.g. to observe or
+    /// substitute store operations.
+    pub fn wrap_store_manager(
+        &mut self,
+        wrap: impl FnOnce(Arc<dyn StoreManager>) -> Arc<dyn StoreManager>,
+    ) {
+        self.store_manager = wrap(self.store_manager.clone());
+    }
+}
+
 impl FactorInstanceBuilder for InstanceBuilder {
     type InstanceState = KeyValueDispatch;
 
@@ -1,4 +1,4 @@
-use crate::{Cas, Error, Store, StoreManager, SwapError};
+use crate::{watch::Watcher, Cas, Error, Store, StoreManager, SwapError};
 use lru::LruCache;
 use spin_core::async_trait;
 use std::{
@@ -6,6 +6,7 @@ use std::{
     future::Future,
     num::NonZeroUsize,
     sync::Arc,
+    time::Duration,
 };
 use tokio::{
     sync::Mutex as AsyncMutex,
@@ -318,6 +319,42 @@ impl Store for CachingStore {
             inner_cas: inner,
         }))
     }
+
+    // Leases bypass the cache, as they are shared with other instances and
+    // replicas.
+
+    async fn acquire_lease(&self, key: &str, token: &str, ttl: Duration) -> Result<bool, Error> {
+        self.uncached(key).await?;
+        self.inner.acquire_lease(key, token, ttl).await
+    }
+
+    async fn renew_lease(&self, key: &str, token: &str, ttl: Duration) -> Result<bool, Error> {
+        self.uncached(key).await?;
+        self.inner.renew_lease(key, token, ttl).await
+    }
+
+    async fn release_lease(&self, key: &str, token: &str) -> Result<bool, Error> {
+        self.uncached(key).await?;
+        self.inner.release_lease(key, token).await
+    }
+
+    // Watches see the backing store, so outstanding writes are flushed first
+    // rather than being seen as changes made after the watch started.
+    async fn watch(&self, prefix: &str) -> Result<Watcher, Error> {
+        self.state.lock().await.flush().await?;
+        self.inner.watch(prefix).await
+    }
+}
+
+impl CachingStore {
+    /// Flushes outstanding writes and evicts `key` from the cache, so that the
+    /// key can be used directly in the backing store.
+    async fn uncached(&self, key: &str) -> Result<(), Error> {
+        let mut state = self.state.lock().await;
+        state.flush().await?;
+        state.cache.pop(key);
+        Ok(())
+    }
 }
 
 struct CompareAndSwap {
@@ -372,3 +409,218 @@ impl Cas for CompareAndSwap {
         self.key.clone()
     }
 }
+
+/// The separator between a [`NamespacedStoreManager`]'s namespace and the
+/// keys in it.
+pub const NAMESPACE_SEPARATOR: char = '/';
+
+/// Returns the prefix of keys in the given namespace.
+pub fn namespace_prefix(namespace: &str) -> String {
+    format!("{namespace}{NAMESPACE_SEPARATOR}")
+}
+
+/// A [`StoreManager`] whose stores keep keys in a namespace, so that
+/// components sharing a store can't read or overwrite each other's keys.
+///
+/// Keys are stored in the inner store prefixed with the namespace and
+/// [`NAMESPACE_SEPARATOR`]; stores only see and list keys in their namespace.
+pub struct NamespacedStoreManager {
+    inner: Arc<dyn StoreManager>,
+    prefix: Arc<str>,
+}
+
+impl NamespacedStoreManager {
+    pub fn new(inner: Arc<dyn StoreManager>, namespace: &str) -> Self {
+        Self {
+            inner,
+            prefix: namespace_prefix(namespace).into(),
+        }
+    }
+}
+
+#[async_trait]
+impl StoreManager for NamespacedStoreManager {
+    async fn get(&self, name: &str) -> Result<Arc<dyn Store>, Error> {
+        Ok(Arc::new(NamespacedStore {
+            inner: self.inner.get(name).await?,
+            prefix: self.prefix.clone(),
+        }))
+    }
+
+    fn is_defined(&self, store_name: &str) -> bool {
+        self.inner.is_defined(store_name)
+    }
+
+    fn summary(&self, store_name: &str) -> Option<String> {
+        self.inner.summary(store_name)
+    }
+}
+
+struct NamespacedStore {
+    inner: Arc<dyn Store>,
+    prefix: Arc<str>,
+}
+
+impl NamespacedStore {
+    fn key(&self, key: &str) -> String {
+        format!("{}{key}", self.prefix)
+    }
+
+    fn keys(&self, keys: Vec<String>) -> Vec<String> {
+        keys.iter().map(|key| self.key(key)).collect()
+    }
+
+    /// Strips the namespace from a key of the inner store, or returns `None`
+    /// if the key is not in the namespace.
+    fn strip(&self, key: String) -> Option<String> {
+        key.strip_prefix(&*self.prefix).map(ToOwned::to_owned)
+    }
+}
+
+#[async_trait]
+impl Store for NamespacedStore {
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, Error> {
+        self.inner.get(&self.key(key)).await
+    }
+
+    async fn set(&self, key: &str, value: &[u8]) -> Result<(), Error> {
+        self.inner.set(&self.key(key), value).await
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), Error> {
+        self.inner.delete(&self.key(key)).await
+    }
+
+    async fn exists(&self, key: &str) -> Result<bool, Error> {
+        self.inner.exists(&self.key(key)).await
+    }
+
+    async fn get_keys(&self) -> Result<Vec<String>, Error> {
+        let keys = self.inner.get_keys().await?;
+        Ok(keys.into_iter().filter_map(|key| self.strip(key)).collect())
+    }
+
+    async fn get_many(&self, keys: Vec<String>) -> Result<Vec<(String, Option<Vec<u8>>)>, Error> {
+        let found = self.inner.get_many(self.keys(keys)).await?;
+        Ok(found
+            .into_iter()
+            .filter_map(|(key, value)| Some((self.strip(key)?, value)))
+            .collect())
+    }
+
+    async fn set_many(&self, key_values: Vec<(String, Vec<u8>)>) -> Result<(), Error> {
+        let key_values = key_values
+            .into_iter()
+            .map(|(key, value)| (self.key(&key), value))
+            .collect();
+        self.inner.set_many(key_values).await
+    }
+
+    async fn delete_many(&self, keys: Vec<String>) -> Result<(), Error> {
+        self.inner.delete_many(self.keys(keys)).await
+    }
+
+    async fn increment(&self, key: String, delta: i64) -> Result<i64, Error> {
+        self.inner.increment(self.key(&key), delta).await
+    }
+
+    async fn new_compare_and_swap(
+        &self,
+        bucket_rep: u32,
+        key: &str,
+    ) -> Result<Arc<dyn Cas>, Error> {
+        let inner = self
+            .inner
+            .new_compare_and_swap(bucket_rep, &self.key(key))
+            .await?;
+        Ok(Arc::new(NamespacedCas {
+            inner,
+            key: key.to_owned(),
+        }))
+    }
+
+    async fn acquire_lease(&self, key: &str, token: &str, ttl: Duration) -> Result<bool, Error> {
+        self.inner.acquire_lease(&self.key(key), token, ttl).await
+    }
+
+    async fn renew_lease(&self, key: &str, token: &str, ttl: Duration) -> Result<bool, Error> {
+        self.inner.renew_lease(&self.key(key), token, ttl).await
+    }
+
+    async fn release_lease(&self, key: &str, token: &str) -> Result<bool, Error> {
+        self.inner.release_lease(&self.key(key), token).await
+    }
+
+    async fn watch(&self, prefix: &str) -> Result<Watcher, Error> {
+        let watcher = self.inner.watch(&self.key(prefix)).await?;
+        let prefix = self.prefix.clone();
+        Ok(watcher.map_keys(move |key| key.strip_prefix(&*prefix).map(ToOwned::to_owned)))
+    }
+}
+
+/// A [`Cas`] on a [`NamespacedStore`], which reports its key without the
+/// namespace so that retrying a failed swap doesn't namespace it twice.
+struct NamespacedCas {
+    inner: Arc<dyn Cas>,
+    key: String,
+}
+
+#[async_trait]
+impl Cas for NamespacedCas {
+    async fn current(&self) -> Result<Option<Vec<u8>>, Error> {
+        self.inner.current().await
+    }
+
+    async fn swap(&self, value: Vec<u8>) -> Result<(), SwapError> {
+        self.inner.swap(value).await
+    }
+
+    async fn bucket_rep(&self) -> u32 {
+        self.inner.bucket_rep().await
+    }
+
+    async fn key(&self) -> String {
+        self.key.clone()
+    }
+}
+
+/// Moves the keys of `store` that are not in one of `namespaces` into
+/// `namespace`, returning the keys that were moved.
+///
+/// This migrates existing data when a component that has been using a store
+/// is given a namespace. `namespaces` should be every namespace used with the
+/// store, so that keys other components have already namespaced are left alone.
+pub async fn migrate_into_namespace(
+    store: &dyn Store,
+    namespace: &str,
+    namespaces: &[&str],
+) -> Result<Vec<String>, Error> {
+    let prefixes = namespaces
+        .iter()
+        .chain([&namespace])
+        .map(|ns| namespace_prefix(ns))
+        .collect::<Vec<_>>();
+    let keys = store
+        .get_keys()
+        .await?
+        .into_iter()
+        .filter(|key| !prefixes.iter().any(|prefix| key.starts_with(prefix)))
+        .collect::<Vec<_>>();
+    if keys.is_empty() {
+        return Ok(keys);
+    }
+
+    let values = store.get_many(keys).await?;
+    let mut moved = Vec::with_capacity(values.len());
+    let mut namespaced = Vec::with_capacity(values.len());
+    for (key, value) in values {
+        // The key may have been deleted since it was listed.
+        if let Some(value) = value {
+            namespaced.push((format!("{}{key}", namespace_prefix(namespace)), value));
+            moved.push(key);
+        }
+    }
+    store.set_many(namespaced).await?;
+    store.delete_many(moved.clone()).await?;
+    Ok(moved)
+}
@@ -0,0 +1,257 @@
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    future::Future,
+    hash::{Hash, Hasher},
+    time::Duration,
+};
+
+use tokio::sync::mpsc::{self, error::TrySendError};
+
+use crate::Error;
+
+/// How many changes a watch buffers before its watcher is considered to have
+/// fallen behind.
+const WATCH_BUFFER: usize = 256;
+
+/// A change to a key in a store.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Change {
+    /// The key was set.
+    Set(String),
+    /// The key was deleted, or expired.
+    Deleted(String),
+    /// Changes were missed because the watcher fell behind.
+    Lagged,
+}
+
+/// Creates a watch, whose [`WatchSender`] a store sends changes to.
+pub fn watch_channel() -> (WatchSender, Watcher) {
+    let (tx, rx) = mpsc::channel(WATCH_BUFFER);
+    let sender = WatchSender { tx, lagged: false };
+    let watcher = Watcher { rx, map_key: None };
+    (sender, watcher)
+}
+
+/// The store's end of a watch.
+pub struct WatchSender {
+    tx: mpsc::Sender<Result<Change, Error>>,
+    lagged: bool,
+}
+
+impl WatchSender {
+    /// Sends a change to the watcher, returning false once the watcher has
+    /// been dropped.
+    ///
+    /// Changes aren't buffered without bound: if the watcher has fallen
+    /// behind, the change is dropped, and the watcher sees [`Change::Lagged`]
+    /// once it catches up.
+    pub fn send(&mut self, change: Change) -> bool {
+        if self.lagged {
+            match self.tx.try_send(Ok(Change::Lagged)) {
+                Ok(()) => self.lagged = false,
+                Err(TrySendError::Full(_)) => return true,
+                Err(TrySendError::Closed(_)) => return false,
+            }
+        }
+        match self.tx.try_send(Ok(change)) {
+            Ok(()) => true,
+            Err(TrySendError::Full(_)) => {
+                self.lagged = true;
+                true
+            }
+            Err(TrySendError::Closed(_)) => false,
+        }
+    }
+
+    /// Ends the watch with an error, e.g. because the connection to the store
+    /// was lost.
+    pub fn fail(self, err: Error) {
+        // A watcher which has fallen behind sees the watch end without the error.
+        let _ = self.tx.try_send(Err(err));
+    }
+
+    /// Waits until the watcher has been dropped.
+    pub async fn closed(&self) {
+        self.tx.closed().await
+    }
+}
+
+/// The watcher's end of a watch.
+pub struct Watcher {
+    rx: mpsc::Receiver<Result<Change, Error>>,
+    map_key: Option<Box<dyn Fn(String) -> Option<String> + Send + Sync>>,
+}
+
+impl Watcher {
+    /// Waits for the next change.
+    pub async fn next(&mut self) -> Result<Change, Error> {
+        loop {
+            let change = self
+                .rx
+                .recv()
+                .await
+                .ok_or_else(|| Error::Other("the watch has ended".into()))??;
+            let change = match change {
+                Change::Set(key) => self.map_key(key).map(Change::Set),
+                Change::Deleted(key) => self.map_key(key).map(Change::Deleted),
+                Change::Lagged => Some(Change::Lagged),
+            };
+            if let Some(change) = change {
+                return Ok(change);
+            }
+        }
+    }
+
+    /// Maps the keys of changes with `f`, skipping changes for which it
+    /// returns `None`.
+    pub fn map_keys(
+        mut self,
+        f: impl Fn(String) -> Option<String> + Send + Sync + 'static,
+    ) -> Self {
+        self.map_key = Some(match self.map_key.take() {
+            Some(previous) => Box::new(move |key| f(previous(key)?)),
+            None => Box::new(f),
+        });
+        self
+    }
+
+    fn map_key(&self, key: String) -> Option<String> {
+        match &self.map_key {
+            Some(map_key) => map_key(key),
+            None => Some(key),
+        }
+    }
+}
+
+/// Watches a store which doesn't notify of changes, by comparing snapshots of
+/// the keys and values being watched taken every `interval`.
+///
+/// `snapshot` may return `None` if the store can tell that nothing has changed
+/// since its last snapshot, so that the keys needn't be read again.
+pub async fn poll<F, Fut>(interval: Duration, mut snapshot: F) -> Result<Watcher, Error>
+where
+    F: FnMut() -> Fut + Send + 'static,
+    Fut: Future<Output = Result<Option<Vec<(String, Vec<u8>)>>, Error>> + Send,
+{
+    // Only changes after the watch starts are seen, so the first snapshot is
+    // taken before returning.
+    let mut previous = hashes(snapshot().await?.unwrap_or_default());
+    let (mut sender, watcher) = watch_channel();
+    tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                _ = sender.closed() => return,
+                _ = tokio::time::sleep(interval) => {}
+            }
+            let current = match snapshot().await {
+                Ok(Some(key_values)) => hashes(key_values),
+                Ok(None) => continue,
+                Err(err) => return sender.fail(err),
+            };
+            for change in changes(&previous, &current) {
+                if !sender.send(change) {
+                    return;
+                }
+            }
+            previous = current;
+        }
+    });
+    Ok(watcher)
+}
+
+/// Hashes values, so that snapshots don't hold every value being watched.
+fn hashes(key_values: Vec<(String, Vec<u8>)>) -> HashMap<String, u64> {
+    key_values
+        .into_iter()
+        .map(|(key, value)| {
+            let mut hasher = DefaultHasher::new();
+            value.hash(&mut hasher);
+            (key, hasher.finish())
+        })
+        .collect()
+}
+
+/// The changes between two snapshots, in order of key.
+fn changes(previous: &HashMap<String, u64>, current: &HashMap<String, u64>) -> Vec<Change> {
+    let mut changes = current
+        .iter()
+        .filter(|(key, hash)| previous.get(*key) != Some(hash))
+        .map(|(key, _)| Change::Set(key.clone()))
+        .chain(
+            previous
+                .keys()
+                .filter(|key| !current.contains_key(*key))
+                .map(|key| Change::Deleted(key.clone())),
+        )
+        .collect::<Vec<_>>();
+    changes.sort_by(|a, b| change_key(a).cmp(change_key(b)));
+    changes
+}
+
+fn change_key(change: &Change) -> &str {
+    match change {
+        Change::Set(key) | Change::Deleted(key) => key,
+        Change::Lagged => "",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn watchers_which_fall_behind_see_a_lag() {
+        let (mut sender, mut watcher) = watch_channel();
+        for i in 0..WATCH_BUFFER + 10 {
+            assert!(sender.send(Change::Set(i.to_string())));
+        }
+        for i in 0..WATCH_BUFFER {
+            assert_eq!(watcher.next().await.unwrap(), Change::Set(i.to_string()));
+        }
+        assert!(sender.send(Change::Deleted("later".into())));
+        assert_eq!(watcher.next().await.unwrap(), Change::Lagged);
+        assert_eq!(
+            watcher.next().await.unwrap(),
+            Change::Deleted("later".into())
+        );
+
+        drop(watcher);
+        assert!(!sender.send(Change::Set("dropped".into())));
+    }
+
+    #[tokio::test]
+    async fn keys_are_mapped_and_filtered() {
+        let (mut sender, watcher) = watch_channel();
+        let mut watcher = watcher
+            .map_keys(|key| key.strip_prefix("ns/").map(ToOwned::to_owned))
+            .map_keys(|key| Some(key.to_uppercase()));
+        sender.send(Change::Set("other/a".into()));
+        sender.send(Change::Set("ns/b".into()));
+        sender.fail(Error::Other("gone".into()));
+        assert_eq!(watcher.next().await.unwrap(), Change::Set("B".into()));
+        assert!(matches!(watcher.next().await, Err(Error::Other(e)) if e == "gone"));
+        assert!(watcher.next().await.is_err());
+    }
+
+    #[test]
+    fn snapshots_are_compared_by_value() {
+        let previous = hashes(vec![
+            ("same".into(), b"1".to_vec()),
+            ("changed".into(), b"1".to_vec()),
+            ("deleted".into(), b"1".to_vec()),
+        ]);
+        let current = hashes(vec![
+            ("same".into(), b"1".to_vec()),
+            ("changed".into(), b"2".to_vec()),
+            ("added".into(), b"1".to_vec()),
+        ]);
+        assert_eq!(
+            changes(&previous, &current),
+            [
+                Change::Set("added".into()),
+                Change::Set("changed".into()),
+                Change::Deleted("deleted".into()),
+            ]
+        );
+    }
+}
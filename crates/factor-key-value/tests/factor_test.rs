@@ -1,10 +1,16 @@
 use anyhow::bail;
-use spin_core::async_trait;
-use spin_factor_key_value::{Cas, KeyValueFactor, RuntimeConfig, Store, StoreManager};
+use spin_core::{async_trait, wasmtime::component::Resource};
+use spin_factor_key_value::{
+    dump::{self, LoadReport},
+    migrate_into_namespace, Cas, KeyValueFactor, RuntimeConfig, Store, StoreManager,
+};
 use spin_factors::RuntimeFactors;
 use spin_factors_test::{toml, TestEnvironment};
 use spin_world::v2::key_value::{Error, HostStore};
-use std::{collections::HashSet, sync::Arc};
+use std::{
+    collections::{BTreeMap, HashSet},
+    sync::{Arc, Mutex},
+};
 
 #[derive(RuntimeFactors)]
 struct TestFactors {
@@ -98,6 +104,202 @@ async fn errors_when_store_is_not_allowed() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+async fn namespaced_component_only_sees_its_own_keys() -> anyhow::Result<()> {
+    let store = Arc::new(MemoryStore::with_keys(["shared", "other/key"]));
+    let mut runtime_config = RuntimeConfig::default();
+    runtime_config.add_store_manager(
+        "default".into(),
+        Arc::new(MemoryStoreManager(store.clone())),
+    );
+    let factors = TestFactors {
+        key_value: KeyValueFactor::new(),
+    };
+    let env = TestEnvironment::new(factors).extend_manifest(toml! {
+        [component.test-component]
+        source = "does-not-exist.wasm"
+        key_value_stores = ["default"]
+        key_value_namespace = "ns"
+    });
+    let mut state = env
+        .runtime_config(runtime_config)?
+        .build_instance_state()
+        .await?;
+
+    let handle = state.key_value.open("default".to_owned()).await??;
+    let rep = handle.rep();
+    let borrow = || Resource::new_borrow(rep);
+    state
+        .key_value
+        .set(borrow(), "key".into(), b"mine".to_vec())
+        .await??;
+    assert!(state
+        .key_value
+        .get(borrow(), "shared".into())
+        .await??
+        .is_none());
+    assert_eq!(state.key_value.get_keys(borrow()).await??, ["key"]);
+
+    assert_eq!(store.keys(), ["ns/key", "other/key", "shared"]);
+    Ok(())
+}
+
+#[tokio::test]
+async fn errors_when_namespace_is_invalid() -> anyhow::Result<()> {
+    let mut runtime_config = RuntimeConfig::default();
+    runtime_config.add_store_manager("default".into(), mock_store_manager());
+    let factors = TestFactors {
+        key_value: KeyValueFactor::new(),
+    };
+    let env = TestEnvironment::new(factors).extend_manifest(toml! {
+        [component.test-component]
+        source = "does-not-exist.wasm"
+        key_value_stores = ["default"]
+        key_value_namespace = "a/b"
+    });
+    let Err(err) = env
+        .runtime_config(runtime_config)?
+        .build_instance_state()
+        .await
+    else {
+        bail!("expected instance build to fail but it didn't");
+    };
+
+    assert!(err
+        .to_string()
+        .contains(r#"invalid key_value_namespace "a/b""#));
+    Ok(())
+}
+
+#[tokio::test]
+async fn migration_moves_only_unnamespaced_keys() -> anyhow::Result<()> {
+    let store = MemoryStore::with_keys(["shared", "other/key", "ns/existing"]);
+
+    let moved = migrate_into_namespace(&store, "ns", &["ns", "other"]).await?;
+
+    assert_eq!(moved, ["shared"]);
+    assert_eq!(store.keys(), ["ns/existing", "ns/shared", "other/key"]);
+    Ok(())
+}
+
+#[tokio::test]
+async fn dumps_load_into_another_store() -> anyhow::Result<()> {
+    let source = MemoryStore::with_keys(["a", "b"]);
+    source.set("binary", &[0, 159, 146, 150]).await?;
+    let mut dumped = vec![];
+    assert_eq!(3, dump::dump(&source, &mut dumped).await?);
+
+    let target = MemoryStore::with_keys(["a"]);
+    target.set("a", b"kept").await?;
+    let report = dump::load(&target, dumped.as_slice(), false).await?;
+    assert_eq!(
+        LoadReport {
+            loaded: 2,
+            skipped: 1
+        },
+        report
+    );
+    assert_eq!(target.keys(), ["a", "b", "binary"]);
+    assert_eq!(Some(b"kept".to_vec()), target.get("a").await?);
+    assert_eq!(Some(vec![0, 159, 146, 150]), target.get("binary").await?);
+
+    dump::load(&target, dumped.as_slice(), true).await?;
+    assert_eq!(Some(b"value".to_vec()), target.get("a").await?);
+    Ok(())
+}
+
+#[tokio::test]
+async fn non_dumps_are_rejected() {
+    let store = MemoryStore::default();
+    assert!(dump::load(&store, "key,value\n".as_bytes(), true)
+        .await
+        .is_err());
+}
+
+struct MemoryStoreManager(Arc<MemoryStore>);
+
+#[async_trait]
+impl StoreManager for MemoryStoreManager {
+    async fn get(&self, _name: &str) -> Result<Arc<dyn Store>, Error> {
+        Ok(self.0.clone())
+    }
+
+    fn is_defined(&self, _store_name: &str) -> bool {
+        true
+    }
+}
+
+#[derive(Default)]
+struct MemoryStore(Mutex<BTreeMap<String, Vec<u8>>>);
+
+impl MemoryStore {
+    fn with_keys<'a>(keys: impl IntoIterator<Item = &'a str>) -> Self {
+        let values = keys.into_iter().map(|k| (k.to_owned(), b"value".to_vec()));
+        Self(Mutex::new(values.collect()))
+    }
+
+    fn keys(&self) -> Vec<String> {
+        self.0.lock().unwrap().keys().cloned().collect()
+    }
+}
+
+#[async_trait]
+impl Store for MemoryStore {
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, Error> {
+        Ok(self.0.lock().unwrap().get(key).cloned())
+    }
+    async fn set(&self, key: &str, value: &[u8]) -> Result<(), Error> {
+        self.0.lock().unwrap().insert(key.into(), value.into());
+        Ok(())
+    }
+    async fn delete(&self, key: &str) -> Result<(), Error> {
+        self.0.lock().unwrap().remove(key);
+        Ok(())
+    }
+    async fn exists(&self, key: &str) -> Result<bool, Error> {
+        Ok(self.0.lock().unwrap().contains_key(key))
+    }
+    async fn get_keys(&self) -> Result<Vec<String>, Error> {
+        Ok(self.keys())
+    }
+    async fn get_many(
+        &self,
+        keys: Vec<String>,
+    ) -> anyhow::Result<Vec<(String, Option<Vec<u8>>)>, Error> {
+        let values = self.0.lock().unwrap();
+        Ok(keys
+            .into_iter()
+            .map(|key| {
+                let value = values.get(&key).cloned();
+                (key, value)
+            })
+            .collect())
+    }
+    async fn set_many(&self, key_values: Vec<(String, Vec<u8>)>) -> anyhow::Result<(), Error> {
+        self.0.lock().unwrap().extend(key_values);
+        Ok(())
+    }
+    async fn delete_many(&self, keys: Vec<String>) -> anyhow::Result<(), Error> {
+        let mut values = self.0.lock().unwrap();
+        for key in keys {
+            values.remove(&key);
+        }
+        Ok(())
+    }
+    async fn increment(&self, key: String, delta: i64) -> anyhow::Result<i64, Error> {
+        let (_, _) = (key, delta);
+        todo!()
+    }
+    async fn new_compare_and_swap(
+        &self,
+        bucket_rep: u32,
+        key: &str,
+    ) -> anyhow::Result<Arc<dyn Cas>, Error> {
+        let (_, _) = (key, bucket_rep);
+        todo!()
+    }
+}
+
 fn mock_store_manager() -> Arc<dyn StoreManager> {
     Arc::new(MockStoreManager)
 }
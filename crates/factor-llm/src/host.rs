@@ -18,22 +18,18 @@ impl v2::Host for InstanceState {
         if !self.allowed_models.contains(&model) {
             return Err(access_denied_error(&model));
         }
-        let mut engine = self.engine.lock().await;
-        tracing::Span::current().record("llm.backend", engine.summary());
-        engine
-            .infer(
-                model,
-                prompt,
-                params.unwrap_or(v2::InferencingParams {
-                    max_tokens: 100,
-                    repeat_penalty: 1.1,
-                    repeat_penalty_last_n_token_count: 64,
-                    temperature: 0.8,
-                    top_k: 40,
-                    top_p: 0.9,
-                }),
-            )
-            .await
+        let params = params.unwrap_or(v2::InferencingParams {
+            max_tokens: 100,
+            repeat_penalty: 1.1,
+            repeat_penalty_last_n_token_count: 64,
+            temperature: 0.8,
+            top_k: 40,
+            top_p: 0.9,
+        });
+        let (result, tokens) = self.infer_within_policy(&model, prompt, params).await;
+        self.policy
+            .audit("infer", &model, tokens, result.as_ref().map(|_| ()));
+        result
     }
 
     #[instrument(name = "spin_llm.generate_embeddings", skip(self, data), err(level = Level::INFO), fields(otel.kind = "client", llm.backend = Empty))]
@@ -45,9 +41,17 @@ impl v2::Host for InstanceState {
         if !self.allowed_models.contains(&model) {
             return Err(access_denied_error(&model));
         }
-        let mut engine = self.engine.lock().await;
-        tracing::Span::current().record("llm.backend", engine.summary());
-        engine.generate_embeddings(model, data).await
+        let result = self.embeddings_within_policy(&model, data).await;
+        let tokens: u64 = result
+            .as_ref()
+            .map_or(0, |result| result.usage.prompt_token_count.into());
+        self.policy.audit(
+            "generate-embeddings",
+            &model,
+            tokens,
+            result.as_ref().map(|_| ()),
+        );
+        result
     }
 
     fn convert_error(&mut self, error: v2::Error) -> anyhow::Result<v2::Error> {
@@ -85,6 +89,65 @@ impl v1::Host for InstanceState {
     }
 }
 
+impl InstanceState {
+    /// Runs inference subject to the component's policy, returning the result
+    /// and the number of tokens used.
+    async fn infer_within_policy(
+        &mut self,
+        model: &str,
+        prompt: String,
+        mut params: v2::InferencingParams,
+    ) -> (Result<v2::InferencingResult, v2::Error>, u64) {
+        if let Err(err) = self.policy.check_input(&prompt) {
+            return (Err(err), 0);
+        }
+        let reservation = match self.policy.reserve(params.max_tokens) {
+            Ok((max_tokens, reservation)) => {
+                params.max_tokens = max_tokens;
+                reservation
+            }
+            Err(err) => return (Err(err), 0),
+        };
+        let mut engine = self.engine.lock().await;
+        tracing::Span::current().record("llm.backend", engine.summary());
+        let result = match engine.infer(model.to_owned(), prompt, params).await {
+            Ok(result) => result,
+            Err(err) => {
+                self.policy.settle(reservation, 0);
+                return (Err(err), 0);
+            }
+        };
+        let tokens = u64::from(result.usage.prompt_token_count)
+            + u64::from(result.usage.generated_token_count);
+        self.policy.settle(reservation, tokens);
+        // The tokens were used even if the completion is withheld.
+        match self.policy.check_output(&result.text) {
+            Ok(()) => (Ok(result), tokens),
+            Err(err) => (Err(err), tokens),
+        }
+    }
+
+    /// Generates embeddings subject to the component's policy.
+    async fn embeddings_within_policy(
+        &mut self,
+        model: &str,
+        data: Vec<String>,
+    ) -> Result<v2::EmbeddingsResult, v2::Error> {
+        for input in &data {
+            self.policy.check_input(input)?;
+        }
+        let (_, reservation) = self.policy.reserve(0)?;
+        let mut engine = self.engine.lock().await;
+        tracing::Span::current().record("llm.backend", engine.summary());
+        let result = engine.generate_embeddings(model.to_owned(), data).await;
+        let tokens = result
+            .as_ref()
+            .map_or(0, |result| result.usage.prompt_token_count.into());
+        self.policy.settle(reservation, tokens);
+        result
+    }
+}
+
 fn access_denied_error(model: &str) -> v2::Error {
     v2::Error::InvalidInput(format!(
         "The component does not have access to use '{model}'. To give the component access, add '{model}' to the 'ai_models' key for the component in your spin.toml manifest"
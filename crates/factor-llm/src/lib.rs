@@ -1,4 +1,5 @@
 mod host;
+mod policy;
 pub mod spin;
 
 use std::collections::{HashMap, HashSet};
@@ -13,6 +14,9 @@ use spin_world::v1::llm::{self as v1};
 use spin_world::v2::llm::{self as v2};
 use tokio::sync::Mutex;
 
+use policy::ComponentPolicy;
+pub use policy::{LlmPolicy, TokenLimits, AUDIT_TARGET};
+
 pub const ALLOWED_MODELS_KEY: MetadataKey<Vec<String>> = MetadataKey::new("ai_models");
 
 /// The factor for LLMs.
@@ -64,13 +68,17 @@ impl Factor for LlmFactor {
                 ))
             })
             .collect::<anyhow::Result<_>>()?;
-        let engine = ctx
-            .take_runtime_config()
-            .map(|c| c.engine)
-            .unwrap_or_else(|| self.default_engine_creator.create());
+        let (engine, policy) = match ctx.take_runtime_config() {
+            Some(config) => (config.engine, config.policy),
+            None => (None, Default::default()),
+        };
+        let engine = engine.unwrap_or_else(|| self.default_engine_creator.create());
+        let component_policies =
+            policy.into_component_policies(ctx.app().components().map(|c| c.id()))?;
         Ok(AppState {
             engine,
             component_allowed_models,
+            component_policies,
         })
     }
 
@@ -85,10 +93,18 @@ impl Factor for LlmFactor {
             .cloned()
             .unwrap_or_default();
         let engine = ctx.app_state().engine.clone();
+        let component_id = ctx.app_component().id();
+        let policy = ctx
+            .app_state()
+            .component_policies
+            .get(component_id)
+            .cloned()
+            .unwrap_or_else(|| Arc::new(ComponentPolicy::unrestricted(component_id)));
 
         Ok(InstanceState {
             engine,
             allowed_models,
+            policy,
         })
     }
 }
@@ -97,21 +113,36 @@ impl Factor for LlmFactor {
 pub struct AppState {
     engine: Arc<Mutex<dyn LlmEngine>>,
     component_allowed_models: HashMap<String, Arc<HashSet<String>>>,
+    component_policies: HashMap<String, Arc<ComponentPolicy>>,
 }
 
 /// The instance state for the LLM factor.
 pub struct InstanceState {
     engine: Arc<Mutex<dyn LlmEngine>>,
     pub allowed_models: Arc<HashSet<String>>,
+    policy: Arc<ComponentPolicy>,
 }
 
 /// The runtime configuration for the LLM factor.
 pub struct RuntimeConfig {
-    engine: Arc<Mutex<dyn LlmEngine>>,
+    /// The engine, or `None` to use the factor's default engine.
+    engine: Option<Arc<Mutex<dyn LlmEngine>>>,
+    policy: LlmPolicy,
 }
 
 impl SelfInstanceBuilder for InstanceState {}
 
+impl InstanceState {
+    /// Wraps the engine used by this instance, e.g. to observe or substitute
+    /// inferences.
+    pub fn wrap_engine(
+        &mut self,
+        wrap: impl FnOnce(Arc<Mutex<dyn LlmEngine>>) -> Arc<Mutex<dyn LlmEngine>>,
+    ) {
+        self.engine = wrap(self.engine.clone());
+    }
+}
+
 /// The interface for a language model engine.
 #[async_trait]
 pub trait LlmEngine: Send + Sync {
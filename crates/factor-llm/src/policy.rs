@@ -0,0 +1,364 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context as _};
+use regex::RegexSet;
+use serde::Deserialize;
+use spin_world::v2::llm as v2;
+
+/// How long an hourly token budget lasts before it is replenished.
+const BUDGET_WINDOW: Duration = Duration::from_secs(60 * 60);
+
+/// The tracing target of audit events.
+pub const AUDIT_TARGET: &str = "spin_llm::audit";
+
+/// Host-side policy on components' use of the LLM factor.
+///
+/// The limits apply to every component, unless overridden for it. Content
+/// filters apply to every component's prompts, embedding inputs and
+/// completions.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LlmPolicy {
+    /// The default for [`TokenLimits::max_tokens_per_request`].
+    pub max_tokens_per_request: Option<u32>,
+    /// The default for [`TokenLimits::max_tokens_per_hour`].
+    pub max_tokens_per_hour: Option<u64>,
+    /// Regular expressions which prompts and completions must not match.
+    #[serde(default)]
+    pub deny_patterns: Vec<String>,
+    /// Words or phrases which prompts and completions must not contain,
+    /// ignoring case.
+    #[serde(default)]
+    pub deny_words: Vec<String>,
+    /// Whether to emit an audit event, with target [`AUDIT_TARGET`], for
+    /// each inferencing and embeddings call.
+    #[serde(default)]
+    pub audit: bool,
+    /// Limits for specific components, by component ID.
+    #[serde(default)]
+    pub components: HashMap<String, TokenLimits>,
+}
+
+/// Limits on the tokens a component may use.
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TokenLimits {
+    /// The most tokens a single inferencing request may generate. Requests
+    /// for more are capped to this.
+    pub max_tokens_per_request: Option<u32>,
+    /// The most tokens, prompt and generated, a component may use in an
+    /// hour across all its instances.
+    pub max_tokens_per_hour: Option<u64>,
+}
+
+impl TokenLimits {
+    /// Takes each limit from `self`, or from `defaults` if unset.
+    fn or(self, defaults: TokenLimits) -> Self {
+        Self {
+            max_tokens_per_request: self
+                .max_tokens_per_request
+                .or(defaults.max_tokens_per_request),
+            max_tokens_per_hour: self.max_tokens_per_hour.or(defaults.max_tokens_per_hour),
+        }
+    }
+}
+
+impl LlmPolicy {
+    /// Builds the policy enforced for each of the given components, by
+    /// component ID.
+    ///
+    /// Returns an error if the policy has limits for a component which isn't
+    /// one of them, as the limits would otherwise silently go unenforced.
+    pub(crate) fn into_component_policies<'a>(
+        self,
+        component_ids: impl IntoIterator<Item = &'a str>,
+    ) -> anyhow::Result<HashMap<String, Arc<ComponentPolicy>>> {
+        let component_ids = component_ids.into_iter().collect::<HashSet<_>>();
+        for id in self.components.keys() {
+            if !component_ids.contains(id.as_str()) {
+                bail!("[llm_policy.components] has limits for component {id:?}, but the app has no such component");
+            }
+        }
+        let filter = Arc::new(ContentFilter::new(&self.deny_patterns, &self.deny_words)?);
+        let defaults = TokenLimits {
+            max_tokens_per_request: self.max_tokens_per_request,
+            max_tokens_per_hour: self.max_tokens_per_hour,
+        };
+        component_ids
+            .into_iter()
+            .map(|id| {
+                let limits = self
+                    .components
+                    .get(id)
+                    .copied()
+                    .unwrap_or_default()
+                    .or(defaults);
+                let policy = ComponentPolicy {
+                    component_id: id.to_owned(),
+                    limits,
+                    filter: filter.clone(),
+                    audit: self.audit,
+                    budget: Default::default(),
+                };
+                Ok((id.to_owned(), Arc::new(policy)))
+            })
+            .collect()
+    }
+}
+
+/// The policy enforced for one component, shared by all its instances.
+#[derive(Debug)]
+pub(crate) struct ComponentPolicy {
+    component_id: String,
+    limits: TokenLimits,
+    filter: Arc<ContentFilter>,
+    audit: bool,
+    budget: Mutex<HourlyBudget>,
+}
+
+#[derive(Debug)]
+struct HourlyBudget {
+    window_start: Instant,
+    /// The tokens used, or reserved by requests still running, in the window.
+    used: u64,
+}
+
+/// Tokens reserved from a component's hourly budget by a request which is
+/// running, so that concurrent requests can't overspend the budget between
+/// them. The reservation is replaced by the tokens the request actually used
+/// by [`ComponentPolicy::settle`].
+#[derive(Debug)]
+#[must_use]
+pub(crate) struct Reservation {
+    window_start: Instant,
+    tokens: u64,
+}
+
+impl Default for HourlyBudget {
+    fn default() -> Self {
+        Self {
+            window_start: Instant::now(),
+            used: 0,
+        }
+    }
+}
+
+impl ComponentPolicy {
+    /// A policy which enforces nothing, for a component the policy wasn't
+    /// built for.
+    pub fn unrestricted(component_id: &str) -> Self {
+        Self {
+            component_id: component_id.to_owned(),
+            limits: Default::default(),
+            filter: Default::default(),
+            audit: false,
+            budget: Default::default(),
+        }
+    }
+
+    /// Checks that `text`, a prompt or embeddings input, is allowed.
+    pub fn check_input(&self, text: &str) -> Result<(), v2::Error> {
+        if self.filter.is_denied(text) {
+            return Err(v2::Error::InvalidInput(
+                "The prompt was rejected by the host's content policy".into(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Checks that `text`, a completion, is allowed.
+    pub fn check_output(&self, text: &str) -> Result<(), v2::Error> {
+        if self.filter.is_denied(text) {
+            return Err(v2::Error::RuntimeError(
+                "The completion was rejected by the host's content policy".into(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Returns how many tokens a request for `requested` may generate, and
+    /// reserves them from the component's hourly budget, or returns an error
+    /// if the budget is used up. Requests which don't generate tokens, such as
+    /// for embeddings, request none, and only check the budget.
+    pub fn reserve(&self, requested: u32) -> Result<(u32, Reservation), v2::Error> {
+        let mut max_tokens = requested;
+        if let Some(limit) = self.limits.max_tokens_per_request {
+            max_tokens = max_tokens.min(limit);
+        }
+        let mut budget = self.budget();
+        if let Some(limit) = self.limits.max_tokens_per_hour {
+            let remaining = limit.saturating_sub(budget.used);
+            if remaining == 0 {
+                return Err(v2::Error::RuntimeError(format!(
+                    "The component has used its budget of {limit} tokens for this hour"
+                )));
+            }
+            max_tokens = max_tokens.min(remaining.try_into().unwrap_or(u32::MAX));
+        }
+        budget.used = budget.used.saturating_add(max_tokens.into());
+        let reservation = Reservation {
+            window_start: budget.window_start,
+            tokens: max_tokens.into(),
+        };
+        Ok((max_tokens, reservation))
+    }
+
+    /// Replaces a reservation with the `tokens` its request actually used,
+    /// which is none if the request failed.
+    pub fn settle(&self, reservation: Reservation, tokens: u64) {
+        let mut budget = self.budget();
+        // Reservations made in a previous window were replenished with it.
+        if budget.window_start == reservation.window_start {
+            budget.used = budget.used.saturating_sub(reservation.tokens);
+        }
+        budget.used = budget.used.saturating_add(tokens);
+    }
+
+    /// Emits an audit event for a call, if auditing is enabled.
+    pub fn audit(&self, call: &str, model: &str, tokens: u64, result: Result<(), &v2::Error>) {
+        if !self.audit {
+            return;
+        }
+        let outcome = match result {
+            Ok(()) => "ok".to_owned(),
+            Err(err) => format!("{err:?}"),
+        };
+        tracing::info!(
+            target: AUDIT_TARGET,
+            component_id = %self.component_id,
+            call,
+            model,
+            tokens,
+            outcome,
+            "LLM call"
+        );
+    }
+
+    /// Locks the hourly budget, replenishing it if its window has passed.
+    fn budget(&self) -> std::sync::MutexGuard<'_, HourlyBudget> {
+        let mut budget = self.budget.lock().unwrap();
+        if budget.window_start.elapsed() >= BUDGET_WINDOW {
+            *budget = HourlyBudget::default();
+        }
+        budget
+    }
+}
+
+/// Denies text matching any of a set of patterns.
+#[derive(Debug, Default)]
+struct ContentFilter {
+    patterns: Option<RegexSet>,
+}
+
+impl ContentFilter {
+    fn new(deny_patterns: &[String], deny_words: &[String]) -> anyhow::Result<Self> {
+        let patterns = deny_patterns
+            .iter()
+            .cloned()
+            .chain(
+                deny_words
+                    .iter()
+                    .map(|word| format!("(?i){}", regex::escape(word))),
+            )
+            .collect::<Vec<_>>();
+        if patterns.is_empty() {
+            return Ok(Self { patterns: None });
+        }
+        let patterns = RegexSet::new(patterns).context("invalid [llm_policy] deny pattern")?;
+        Ok(Self {
+            patterns: Some(patterns),
+        })
+    }
+
+    fn is_denied(&self, text: &str) -> bool {
+        self.patterns
+            .as_ref()
+            .is_some_and(|patterns| patterns.is_match(text))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(toml: toml::Table) -> Arc<ComponentPolicy> {
+        let policy: LlmPolicy = toml::Value::Table(toml).try_into().unwrap();
+        policy
+            .into_component_policies(["component"])
+            .unwrap()
+            .remove("component")
+            .unwrap()
+    }
+
+    #[test]
+    fn component_limits_override_defaults() {
+        let policy = policy(toml::toml! {
+            max_tokens_per_request = 100
+            max_tokens_per_hour = 1000
+            [components.component]
+            max_tokens_per_request = 10
+        });
+        assert_eq!(policy.limits.max_tokens_per_request, Some(10));
+        assert_eq!(policy.limits.max_tokens_per_hour, Some(1000));
+    }
+
+    #[test]
+    fn tokens_are_capped_by_budget() {
+        let policy = policy(toml::toml! {
+            max_tokens_per_request = 100
+            max_tokens_per_hour = 150
+        });
+        let (max_tokens, first) = policy.reserve(500).unwrap();
+        assert_eq!(max_tokens, 100);
+        // Tokens reserved by running requests aren't available to others.
+        let (max_tokens, second) = policy.reserve(500).unwrap();
+        assert_eq!(max_tokens, 50);
+        policy.settle(first, 80);
+        policy.settle(second, 40);
+        let (max_tokens, third) = policy.reserve(500).unwrap();
+        assert_eq!(max_tokens, 30);
+        policy.settle(third, 30);
+        assert!(matches!(
+            policy.reserve(1),
+            Err(v2::Error::RuntimeError(msg)) if msg.contains("budget of 150 tokens")
+        ));
+    }
+
+    #[test]
+    fn limits_for_unknown_components_are_rejected() {
+        let policy: LlmPolicy = toml::Value::Table(toml::toml! {
+            [components.typo]
+            max_tokens_per_request = 10
+        })
+        .try_into()
+        .unwrap();
+        assert!(policy.into_component_policies(["component"]).is_err());
+    }
+
+    #[test]
+    fn denied_content_is_rejected() {
+        let policy = policy(toml::toml! {
+            deny_patterns = ["\\b\\d{3}-\\d{2}-\\d{4}\\b"]
+            deny_words = ["Secret Project"]
+        });
+        assert!(policy.check_input("tell me a story").is_ok());
+        assert!(policy.check_input("my SSN is 123-45-6789").is_err());
+        assert!(policy.check_input("what is the secret project?").is_err());
+        assert!(matches!(
+            policy.check_output("the SECRET PROJECT is..."),
+            Err(v2::Error::RuntimeError(_))
+        ));
+    }
+
+    #[test]
+    fn invalid_patterns_are_reported() {
+        let policy: LlmPolicy = toml::Value::Table(toml::toml! {
+            deny_patterns = ["("]
+        })
+        .try_into()
+        .unwrap();
+        assert!(policy.into_component_policies(["component"]).is_err());
+    }
+}
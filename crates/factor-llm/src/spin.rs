@@ -1,6 +1,7 @@
 use std::path::PathBuf;
 use std::sync::Arc;
 
+use anyhow::Context as _;
 use spin_factors::runtime_config::toml::GetTomlValue;
 use spin_llm_remote_http::RemoteHttpLlmEngine;
 use spin_world::async_trait;
@@ -9,7 +10,7 @@ use spin_world::v2::llm::{self as v2};
 use tokio::sync::Mutex;
 use url::Url;
 
-use crate::{LlmEngine, LlmEngineCreator, RuntimeConfig};
+use crate::{LlmEngine, LlmEngineCreator, LlmPolicy, RuntimeConfig};
 
 #[cfg(feature = "llm")]
 mod local {
@@ -47,7 +48,6 @@ pub fn default_engine_creator(
 ) -> anyhow::Result<impl LlmEngineCreator + 'static> {
     #[cfg(feature = "llm")]
     let engine = {
-        use anyhow::Context as _;
         let models_dir_parent = match state_dir {
             Some(ref dir) => dir.clone(),
             None => std::env::current_dir().context("failed to get current working directory")?,
@@ -88,18 +88,33 @@ impl LlmEngine for RemoteHttpLlmEngine {
     }
 }
 
+/// Get the runtime configuration from the `[llm_compute]` and `[llm_policy]`
+/// sections of a TOML table.
 pub fn runtime_config_from_toml(
     table: &impl GetTomlValue,
     state_dir: Option<PathBuf>,
 ) -> anyhow::Result<Option<RuntimeConfig>> {
-    let Some(value) = table.get("llm_compute") else {
+    let compute = table.get("llm_compute");
+    let policy = table.get("llm_policy");
+    if compute.is_none() && policy.is_none() {
         return Ok(None);
+    }
+    let engine = match compute {
+        Some(value) => {
+            let config: LlmCompute = value.clone().try_into()?;
+            Some(config.into_engine(state_dir)?)
+        }
+        None => None,
+    };
+    let policy: LlmPolicy = match policy {
+        Some(value) => value
+            .clone()
+            .try_into()
+            .context("invalid [llm_policy] runtime config")?,
+        None => Default::default(),
     };
-    let config: LlmCompute = value.clone().try_into()?;
 
-    Ok(Some(RuntimeConfig {
-        engine: config.into_engine(state_dir)?,
-    }))
+    Ok(Some(RuntimeConfig { engine, policy }))
 }
 
 #[derive(Debug, serde::Deserialize)]
@@ -1,7 +1,7 @@
 use std::collections::HashSet;
 use std::sync::Arc;
 
-use spin_factor_llm::{LlmEngine, LlmFactor};
+use spin_factor_llm::{spin::runtime_config_from_toml, LlmEngine, LlmFactor};
 use spin_factors::{anyhow, RuntimeFactors};
 use spin_factors_test::{toml, TestEnvironment};
 use spin_world::v1::llm::{self as v1};
@@ -73,6 +73,71 @@ async fn llm_works() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+async fn llm_policy_is_enforced() -> anyhow::Result<()> {
+    let handle = Box::new(|op| match op {
+        Operation::Inference { prompt, params, .. } => {
+            assert_eq!(params.max_tokens, 10);
+            Ok(v2::InferencingResult {
+                text: format!("echo: {prompt}"),
+                usage: v2::InferencingUsage {
+                    prompt_token_count: 5,
+                    generated_token_count: 10,
+                },
+            }
+            .into())
+        }
+        Operation::Embedding { .. } => panic!("unexpected embeddings request"),
+    });
+    let factors = TestFactors {
+        llm: LlmFactor::new(move || {
+            Arc::new(Mutex::new(FakeLLm {
+                handle: handle.clone(),
+            })) as _
+        }),
+    };
+    let runtime_config = runtime_config_from_toml(
+        &toml! {
+            [llm_policy]
+            max_tokens_per_request = 10
+            deny_words = ["forbidden"]
+        },
+        None,
+    )?;
+    let env = TestEnvironment::new(factors)
+        .extend_manifest(toml! {
+            [component.test-component]
+            source = "does-not-exist.wasm"
+            ai_models = ["llama2-chat"]
+        })
+        .runtime_config(TestFactorsRuntimeConfig {
+            llm: runtime_config,
+        })?;
+    let mut state = env.build_instance_state().await?;
+
+    state
+        .llm
+        .infer("llama2-chat".into(), "some prompt".into(), None)
+        .await?;
+
+    assert!(matches!(
+        state
+            .llm
+            .infer("llama2-chat".into(), "a Forbidden prompt".into(), None)
+            .await,
+        Err(v2::Error::InvalidInput(msg)) if msg.contains("content policy")
+    ));
+
+    assert!(matches!(
+        state
+            .llm
+            .generate_embeddings("llama2-chat".into(), vec!["forbidden".into()])
+            .await,
+        Err(v2::Error::InvalidInput(msg)) if msg.contains("content policy")
+    ));
+    Ok(())
+}
+
 struct FakeLLm {
     handle: Box<dyn Fn(Operation) -> Result<OperationResult, v2::Error> + Sync + Send>,
 }
@@ -0,0 +1,274 @@
+use std::{
+    sync::{Arc, Mutex},
+    task::Poll,
+};
+
+use bytes::Bytes;
+use spin_factors::{
+    anyhow::{self, Context},
+    wasmtime::component::{Resource, ResourceTable},
+    InitContext,
+};
+use spin_world::{
+    async_trait,
+    spin::multipart::multipart::{self, Error, Part},
+};
+use tracing::instrument;
+use wasmtime_wasi::{HostInputStream, InputStream, StreamError, StreamResult, Subscribe};
+
+use crate::{
+    parser::{ParseError, Parser},
+    InstanceState, MultipartFactor,
+};
+
+/// The most bytes to read from a body at once.
+const READ_SIZE: usize = 64 * 1024;
+
+/// A body being parsed, shared by its `parser` resource and the streams of
+/// its parts.
+pub(crate) type Body = Arc<Mutex<Parsing>>;
+
+pub(crate) struct Parsing {
+    parser: Parser,
+    /// The stream of the body, or `None` once it has ended or while it is
+    /// being waited on.
+    stream: Option<InputStream>,
+    /// The error reading the body, if any.
+    error: Option<String>,
+    /// The number of parts returned so far; only the stream of the last one
+    /// returned may be read.
+    parts: u64,
+}
+
+impl Parsing {
+    /// Pushes what can be read of the body without waiting to the parser,
+    /// returning whether anything was.
+    fn read_available(&mut self) -> bool {
+        let Some(stream) = &mut self.stream else {
+            return false;
+        };
+        match stream.read(READ_SIZE) {
+            Ok(bytes) if bytes.is_empty() => false,
+            Ok(bytes) => {
+                self.parser.push(&bytes);
+                true
+            }
+            Err(err) => {
+                self.end(err);
+                true
+            }
+        }
+    }
+
+    fn end(&mut self, err: StreamError) {
+        self.stream = None;
+        match err {
+            StreamError::Closed => self.parser.finish(),
+            err => self.error = Some(format!("failed to read multipart body: {err}")),
+        }
+    }
+}
+
+/// Waits until more of `body` can be read, and pushes it to the parser.
+async fn fill(body: &Body) {
+    let Some(mut stream) = body.lock().unwrap().stream.take() else {
+        return;
+    };
+    let result = loop {
+        match stream.read(READ_SIZE) {
+            Ok(bytes) if bytes.is_empty() => stream.ready().await,
+            result => break result,
+        }
+    };
+    let mut parsing = body.lock().unwrap();
+    match result {
+        Ok(bytes) => {
+            parsing.parser.push(&bytes);
+            parsing.stream = Some(stream);
+        }
+        Err(err) => parsing.end(err),
+    }
+}
+
+/// The stream of a part's content.
+struct PartStream {
+    body: Body,
+    part: u64,
+}
+
+impl PartStream {
+    fn read_content(&self, size: usize) -> StreamResult<Poll<Bytes>> {
+        let mut parsing = self.body.lock().unwrap();
+        if parsing.parts != self.part {
+            return Err(StreamError::Closed);
+        }
+        loop {
+            if let Some(err) = &parsing.error {
+                return Err(StreamError::LastOperationFailed(anyhow::anyhow!("{err}")));
+            }
+            match parsing.parser.read_content(size) {
+                Ok(Poll::Ready(Some(bytes))) => return Ok(Poll::Ready(bytes)),
+                Ok(Poll::Ready(None)) => return Err(StreamError::Closed),
+                Ok(Poll::Pending) if parsing.read_available() => continue,
+                Ok(Poll::Pending) => return Ok(Poll::Pending),
+                Err(err) => return Err(StreamError::LastOperationFailed(err.into())),
+            }
+        }
+    }
+}
+
+impl HostInputStream for PartStream {
+    fn read(&mut self, size: usize) -> StreamResult<Bytes> {
+        match self.read_content(size)? {
+            Poll::Ready(bytes) => Ok(bytes),
+            Poll::Pending => Ok(Bytes::new()),
+        }
+    }
+}
+
+#[async_trait]
+impl Subscribe for PartStream {
+    async fn ready(&mut self) {
+        loop {
+            {
+                let parsing = self.body.lock().unwrap();
+                if parsing.parts != self.part
+                    || parsing.error.is_some()
+                    || parsing.parser.content_ready()
+                {
+                    return;
+                }
+            }
+            fill(&self.body).await;
+        }
+    }
+}
+
+pub(crate) fn add_to_linker<T: Send + 'static>(
+    ctx: &mut InitContext<T, MultipartFactor>,
+) -> anyhow::Result<()> {
+    fn type_annotate<T, F>(f: F) -> F
+    where
+        F: Fn(&mut T) -> MultipartImpl,
+    {
+        f
+    }
+    let get_data_with_table = ctx.get_data_with_table_fn();
+    let closure = type_annotate(move |data| {
+        let (state, table) = get_data_with_table(data);
+        MultipartImpl { state, table }
+    });
+    multipart::add_to_linker_get_host(ctx.linker(), closure)
+}
+
+/// The instance state and resource table, which hold the streams of bodies
+/// and their parts.
+struct MultipartImpl<'a> {
+    state: &'a mut InstanceState,
+    table: &'a mut ResourceTable,
+}
+
+impl MultipartImpl<'_> {
+    fn get_body(&self, parser: &Resource<multipart::Parser>) -> Result<Body, Error> {
+        self.state
+            .parsers
+            .get(parser.rep())
+            .cloned()
+            .ok_or_else(|| Error::Other("invalid parser".into()))
+    }
+}
+
+#[async_trait]
+impl multipart::Host for MultipartImpl<'_> {
+    #[instrument(name = "spin_multipart.parse", skip(self, body), err(level = tracing::Level::INFO))]
+    async fn parse(
+        &mut self,
+        content_type: String,
+        body: Resource<InputStream>,
+    ) -> Result<Resource<multipart::Parser>, Error> {
+        let parser = Parser::new(&content_type)?;
+        let stream = self
+            .table
+            .delete(body)
+            .map_err(|err| Error::Other(err.to_string()))?;
+        let parsing = Parsing {
+            parser,
+            stream: Some(stream),
+            error: None,
+            parts: 0,
+        };
+        let rep = self
+            .state
+            .parsers
+            .push(Arc::new(Mutex::new(parsing)))
+            .map_err(|()| Error::Other("too many multipart bodies are being parsed".into()))?;
+        Ok(Resource::new_own(rep))
+    }
+
+    fn convert_error(&mut self, error: Error) -> anyhow::Result<Error> {
+        Ok(error)
+    }
+}
+
+#[async_trait]
+impl multipart::HostParser for MultipartImpl<'_> {
+    #[instrument(name = "spin_multipart.next_part", skip_all, err(level = tracing::Level::INFO))]
+    async fn next_part(
+        &mut self,
+        parser: Resource<multipart::Parser>,
+    ) -> Result<Option<Part>, Error> {
+        let body = self.get_body(&parser)?;
+        let part = {
+            let mut parsing = body.lock().unwrap();
+            parsing.parts += 1;
+            parsing.parts
+        };
+        let headers = loop {
+            {
+                let mut parsing = body.lock().unwrap();
+                if let Some(err) = &parsing.error {
+                    return Err(Error::Other(err.clone()));
+                }
+                match parsing.parser.next_part()? {
+                    Poll::Ready(headers) => break headers,
+                    Poll::Pending if parsing.read_available() => continue,
+                    Poll::Pending => {}
+                }
+            }
+            fill(&body).await;
+        };
+        let Some(headers) = headers else {
+            return Ok(None);
+        };
+        let stream: InputStream = Box::new(PartStream { body, part });
+        let stream = self
+            .table
+            .push(stream)
+            .map_err(|err| Error::Other(err.to_string()))?;
+        Ok(Some(Part {
+            name: headers.name,
+            filename: headers.filename,
+            content_type: headers.content_type,
+            headers: headers.headers,
+            size: headers.size,
+            body: stream,
+        }))
+    }
+
+    async fn drop(&mut self, parser: Resource<multipart::Parser>) -> anyhow::Result<()> {
+        self.state
+            .parsers
+            .remove(parser.rep())
+            .context("invalid parser")?;
+        Ok(())
+    }
+}
+
+impl From<ParseError> for Error {
+    fn from(err: ParseError) -> Self {
+        match err {
+            ParseError::InvalidContentType(msg) => Error::InvalidContentType(msg),
+            ParseError::Malformed(msg) => Error::Malformed(msg),
+        }
+    }
+}
@@ -0,0 +1,59 @@
+mod host;
+mod parser;
+
+use host::Body;
+use spin_factors::{
+    anyhow, ConfigureAppContext, Factor, InitContext, PrepareContext, RuntimeFactors,
+    SelfInstanceBuilder,
+};
+use spin_resource_table::Table;
+
+/// The most multipart bodies an instance may be parsing at once.
+const DEFAULT_PARSER_TABLE_CAPACITY: u32 = 64;
+
+/// A factor for parsing multipart bodies, such as file uploads, part by part
+/// as the guest reads them.
+#[derive(Default)]
+pub struct MultipartFactor {
+    _priv: (),
+}
+
+impl MultipartFactor {
+    /// Creates a new `MultipartFactor`.
+    pub fn new() -> Self {
+        Default::default()
+    }
+}
+
+impl Factor for MultipartFactor {
+    type RuntimeConfig = ();
+    type AppState = ();
+    type InstanceBuilder = InstanceState;
+
+    fn init<T: Send + 'static>(&mut self, mut ctx: InitContext<T, Self>) -> anyhow::Result<()> {
+        host::add_to_linker(&mut ctx)
+    }
+
+    fn configure_app<T: RuntimeFactors>(
+        &self,
+        _ctx: ConfigureAppContext<T, Self>,
+    ) -> anyhow::Result<Self::AppState> {
+        Ok(())
+    }
+
+    fn prepare<T: RuntimeFactors>(
+        &self,
+        _ctx: PrepareContext<T, Self>,
+    ) -> anyhow::Result<InstanceState> {
+        Ok(InstanceState {
+            parsers: Table::new(DEFAULT_PARSER_TABLE_CAPACITY),
+        })
+    }
+}
+
+pub struct InstanceState {
+    /// The bodies being parsed, by `parser` resource.
+    parsers: Table<Body>,
+}
+
+impl SelfInstanceBuilder for InstanceState {}
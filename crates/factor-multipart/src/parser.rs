@@ -0,0 +1,403 @@
+use std::task::Poll;
+
+use bytes::{Buf, Bytes, BytesMut};
+
+/// The most bytes of headers a part may have.
+const MAX_HEADERS_SIZE: usize = 16 * 1024;
+
+/// A parser for multipart bodies which doesn't do any I/O: the body is
+/// [pushed](Self::push) into it as it arrives, and methods which need more of
+/// the body than has been pushed return [`Poll::Pending`].
+pub(crate) struct Parser {
+    /// `\r\n--<boundary>`, which precedes each part and follows the last one.
+    delimiter: Vec<u8>,
+    buffer: BytesMut,
+    state: State,
+    /// Whether the whole body has been pushed.
+    finished: bool,
+}
+
+#[derive(Debug, PartialEq)]
+enum State {
+    /// Before the first delimiter.
+    Preamble,
+    /// After a delimiter, which may be the last one.
+    Delimiter,
+    /// In the headers of a part.
+    Headers,
+    /// In the content of a part, of which `read` bytes have been read.
+    Content { size: Option<u64>, read: u64 },
+    /// After the last delimiter.
+    Epilogue,
+}
+
+/// The headers of a part.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct PartHeaders {
+    pub name: Option<String>,
+    pub filename: Option<String>,
+    pub content_type: Option<String>,
+    pub headers: Vec<(String, Vec<u8>)>,
+    pub size: Option<u64>,
+}
+
+/// An error parsing a multipart body.
+#[derive(Debug, PartialEq, thiserror::Error)]
+pub(crate) enum ParseError {
+    #[error("invalid multipart content type: {0}")]
+    InvalidContentType(String),
+    #[error("malformed multipart body: {0}")]
+    Malformed(String),
+}
+
+impl Parser {
+    /// Creates a parser for a body with the given `content-type` header.
+    pub fn new(content_type: &str) -> Result<Self, ParseError> {
+        let boundary = boundary(content_type)?;
+        // The first delimiter may be at the start of the body, without the CRLF
+        // which precedes the others.
+        let mut buffer = BytesMut::new();
+        buffer.extend_from_slice(b"\r\n");
+        Ok(Self {
+            delimiter: format!("\r\n--{boundary}").into_bytes(),
+            buffer,
+            state: State::Preamble,
+            finished: false,
+        })
+    }
+
+    /// Adds more of the body.
+    pub fn push(&mut self, bytes: &[u8]) {
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    /// Notes that the whole body has been pushed.
+    pub fn finish(&mut self) {
+        self.finished = true;
+    }
+
+    /// Skips to the next part, returning its headers, or `None` after the last
+    /// part.
+    pub fn next_part(&mut self) -> Result<Poll<Option<PartHeaders>>, ParseError> {
+        loop {
+            match self.state {
+                State::Preamble => match find(&self.buffer, &self.delimiter) {
+                    Some(index) => {
+                        self.buffer.advance(index + self.delimiter.len());
+                        self.state = State::Delimiter;
+                    }
+                    None => {
+                        let keep = self.delimiter.len() - 1;
+                        if self.buffer.len() > keep {
+                            self.buffer.advance(self.buffer.len() - keep);
+                        }
+                        return self.pending("the body has no parts");
+                    }
+                },
+                State::Delimiter => {
+                    if self.buffer.len() < 2 {
+                        return self.pending("the body ended after a delimiter");
+                    }
+                    if self.buffer.starts_with(b"--") {
+                        self.buffer.clear();
+                        self.state = State::Epilogue;
+                        continue;
+                    }
+                    // Skip any transport padding before the CRLF.
+                    let Some(end) = find(&self.buffer, b"\r\n") else {
+                        return self.pending("the body ended after a delimiter");
+                    };
+                    if !self.buffer[..end].iter().all(|b| *b == b' ' || *b == b'\t') {
+                        return Err(ParseError::Malformed(
+                            "a delimiter is followed by other characters".into(),
+                        ));
+                    }
+                    self.buffer.advance(end + 2);
+                    self.state = State::Headers;
+                }
+                State::Headers => {
+                    // A part with no headers starts with the blank line.
+                    let end = if self.buffer.starts_with(b"\r\n") {
+                        Some(0)
+                    } else {
+                        find(&self.buffer, b"\r\n\r\n").map(|index| index + 2)
+                    };
+                    let Some(end) = end else {
+                        if self.buffer.len() > MAX_HEADERS_SIZE {
+                            return Err(ParseError::Malformed(format!(
+                                "a part has more than {MAX_HEADERS_SIZE} bytes of headers"
+                            )));
+                        }
+                        return self.pending("the body ended in the headers of a part");
+                    };
+                    let headers = parse_headers(&self.buffer[..end])?;
+                    self.buffer.advance(end + 2);
+                    self.state = State::Content {
+                        size: headers.size,
+                        read: 0,
+                    };
+                    return Ok(Poll::Ready(Some(headers)));
+                }
+                State::Content { .. } => match self.read_content(usize::MAX)? {
+                    Poll::Ready(Some(_)) => continue,
+                    Poll::Ready(None) => {}
+                    Poll::Pending => return Ok(Poll::Pending),
+                },
+                State::Epilogue => return Ok(Poll::Ready(None)),
+            }
+        }
+    }
+
+    /// Reads up to `max` bytes of the content of the current part, returning
+    /// `None` at the end of the part.
+    pub fn read_content(&mut self, max: usize) -> Result<Poll<Option<Bytes>>, ParseError> {
+        let State::Content { size, read } = &mut self.state else {
+            return Ok(Poll::Ready(None));
+        };
+        let available = match find(&self.buffer, &self.delimiter) {
+            Some(0) => {
+                if size.is_some_and(|size| size != *read) {
+                    return Err(ParseError::Malformed(format!(
+                        "a part declared {} bytes of content but had {read}",
+                        size.unwrap()
+                    )));
+                }
+                self.buffer.advance(self.delimiter.len());
+                self.state = State::Delimiter;
+                return Ok(Poll::Ready(None));
+            }
+            Some(index) => index,
+            // The end of the buffer may be the start of a delimiter.
+            None => self.buffer.len().saturating_sub(self.delimiter.len() - 1),
+        };
+        if available == 0 {
+            return self.pending("the body ended in the content of a part");
+        }
+        let content = self.buffer.split_to(available.min(max)).freeze();
+        *read += content.len() as u64;
+        if size.is_some_and(|size| *read > size) {
+            return Err(ParseError::Malformed(format!(
+                "a part declared {} bytes of content but had more",
+                size.unwrap()
+            )));
+        }
+        Ok(Poll::Ready(Some(content)))
+    }
+
+    /// Whether [`read_content`](Self::read_content) can return without more of
+    /// the body being pushed.
+    pub fn content_ready(&self) -> bool {
+        !matches!(self.state, State::Content { .. })
+            || self.finished
+            || self.buffer.len() >= self.delimiter.len()
+    }
+
+    fn pending<T>(&self, truncated: &str) -> Result<Poll<T>, ParseError> {
+        if self.finished {
+            Err(ParseError::Malformed(truncated.into()))
+        } else {
+            Ok(Poll::Pending)
+        }
+    }
+}
+
+/// The `boundary` parameter of a multipart content type.
+fn boundary(content_type: &str) -> Result<String, ParseError> {
+    let (mime, params) = content_type.split_once(';').unwrap_or((content_type, ""));
+    if !mime.trim().to_ascii_lowercase().starts_with("multipart/") {
+        return Err(ParseError::InvalidContentType(format!(
+            "{content_type:?} is not a multipart type"
+        )));
+    }
+    let boundary = param(params, "boundary").ok_or_else(|| {
+        ParseError::InvalidContentType(format!("{content_type:?} has no boundary"))
+    })?;
+    if boundary.is_empty() || boundary.len() > 70 {
+        return Err(ParseError::InvalidContentType(format!(
+            "boundary {boundary:?} must be 1 to 70 characters"
+        )));
+    }
+    Ok(boundary)
+}
+
+/// The value of a parameter of a header such as `content-type`, unquoted.
+fn param(params: &str, name: &str) -> Option<String> {
+    params.split(';').find_map(|param| {
+        let (key, value) = param.split_once('=')?;
+        if !key.trim().eq_ignore_ascii_case(name) {
+            return None;
+        }
+        let value = value.trim();
+        let value = match value.strip_prefix('"').and_then(|v| v.strip_suffix('"')) {
+            Some(quoted) => quoted.replace("\\\"", "\"").replace("\\\\", "\\"),
+            None => value.to_owned(),
+        };
+        Some(value)
+    })
+}
+
+fn parse_headers(block: &[u8]) -> Result<PartHeaders, ParseError> {
+    let mut part = PartHeaders::default();
+    for line in block.split(|b| *b == b'\n') {
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        if line.is_empty() {
+            continue;
+        }
+        let colon = line
+            .iter()
+            .position(|b| *b == b':')
+            .ok_or_else(|| ParseError::Malformed("a part header has no colon".into()))?;
+        let name = std::str::from_utf8(&line[..colon])
+            .map_err(|_| ParseError::Malformed("a part header name is not UTF-8".into()))?
+            .trim()
+            .to_ascii_lowercase();
+        let value = line[colon + 1..].trim_ascii().to_vec();
+        let text = String::from_utf8_lossy(&value);
+        match name.as_str() {
+            "content-disposition" => {
+                let params = text.split_once(';').map_or("", |(_, params)| params);
+                part.name = param(params, "name");
+                part.filename = param(params, "filename");
+            }
+            "content-type" => part.content_type = Some(text.into_owned()),
+            "content-length" => {
+                part.size = Some(text.trim().parse().map_err(|_| {
+                    ParseError::Malformed(format!("invalid part content-length {text:?}"))
+                })?)
+            }
+            _ => {}
+        }
+        part.headers.push((name, value));
+    }
+    Ok(part)
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONTENT_TYPE: &str = "multipart/form-data; boundary=\"xyz\"";
+
+    const BODY: &[u8] = b"preamble\r\n--xyz\r\n\
+        Content-Disposition: form-data; name=\"field\"\r\n\
+        \r\n\
+        value\r\n--xyz\r\n\
+        Content-Disposition: form-data; name=\"file\"; filename=\"a.txt\"\r\n\
+        Content-Type: text/plain\r\n\
+        Content-Length: 11\r\n\
+        \r\n\
+        hello\r\nworld\r\n--xyz--\r\nepilogue";
+
+    /// Parses a body pushed `chunk_size` bytes at a time, returning each part's
+    /// headers and content.
+    fn parse(body: &[u8], chunk_size: usize) -> Result<Vec<(PartHeaders, Vec<u8>)>, ParseError> {
+        let mut parser = Parser::new(CONTENT_TYPE)?;
+        let mut chunks = body.chunks(chunk_size);
+        let mut push = |parser: &mut Parser| match chunks.next() {
+            Some(chunk) => parser.push(chunk),
+            None => parser.finish(),
+        };
+        let mut parts = vec![];
+        loop {
+            let headers = match parser.next_part()? {
+                Poll::Ready(Some(headers)) => headers,
+                Poll::Ready(None) => return Ok(parts),
+                Poll::Pending => {
+                    push(&mut parser);
+                    continue;
+                }
+            };
+            let mut content = vec![];
+            loop {
+                match parser.read_content(3)? {
+                    Poll::Ready(Some(bytes)) => content.extend_from_slice(&bytes),
+                    Poll::Ready(None) => break,
+                    Poll::Pending => push(&mut parser),
+                }
+            }
+            parts.push((headers, content));
+        }
+    }
+
+    #[test]
+    fn parts_are_parsed_however_the_body_is_split() {
+        for chunk_size in [1, 2, 5, 7, BODY.len()] {
+            let parts = parse(BODY, chunk_size).unwrap();
+            assert_eq!(2, parts.len(), "chunk size {chunk_size}");
+
+            let (field, value) = &parts[0];
+            assert_eq!(Some("field".into()), field.name);
+            assert_eq!(None, field.filename);
+            assert_eq!(b"value", value.as_slice());
+
+            let (file, content) = &parts[1];
+            assert_eq!(Some("file".into()), file.name);
+            assert_eq!(Some("a.txt".into()), file.filename);
+            assert_eq!(Some("text/plain".into()), file.content_type);
+            assert_eq!(Some(11), file.size);
+            assert_eq!(3, file.headers.len());
+            assert_eq!(b"hello\r\nworld", content.as_slice());
+        }
+    }
+
+    #[test]
+    fn unread_content_is_skipped() {
+        let mut parser = Parser::new(CONTENT_TYPE).unwrap();
+        parser.push(BODY);
+        parser.finish();
+        let Poll::Ready(Some(first)) = parser.next_part().unwrap() else {
+            panic!("expected a part");
+        };
+        assert_eq!(Some("field".into()), first.name);
+        let Poll::Ready(Some(second)) = parser.next_part().unwrap() else {
+            panic!("expected a part");
+        };
+        assert_eq!(Some("file".into()), second.name);
+        assert_eq!(Poll::Ready(None), parser.next_part().unwrap());
+    }
+
+    #[test]
+    fn wrong_content_length_is_malformed() {
+        let body = String::from_utf8_lossy(BODY).replace("Length: 11", "Length: 4");
+        assert!(matches!(
+            parse(body.as_bytes(), 4),
+            Err(ParseError::Malformed(_))
+        ));
+        let body = String::from_utf8_lossy(BODY).replace("Length: 11", "Length: 40");
+        assert!(matches!(
+            parse(body.as_bytes(), 4),
+            Err(ParseError::Malformed(_))
+        ));
+    }
+
+    #[test]
+    fn truncated_bodies_are_malformed() {
+        assert!(matches!(
+            parse(&BODY[..BODY.len() - 20], 8),
+            Err(ParseError::Malformed(_))
+        ));
+        assert!(matches!(
+            parse(b"no parts", 8),
+            Err(ParseError::Malformed(_))
+        ));
+    }
+
+    #[test]
+    fn content_types_need_a_boundary() {
+        assert!(Parser::new("multipart/form-data; boundary=abc").is_ok());
+        assert!(matches!(
+            Parser::new("multipart/form-data"),
+            Err(ParseError::InvalidContentType(_))
+        ));
+        assert!(matches!(
+            Parser::new("text/plain; boundary=abc"),
+            Err(ParseError::InvalidContentType(_))
+        ));
+    }
+}
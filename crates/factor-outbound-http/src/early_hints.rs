@@ -0,0 +1,42 @@
+use http::{HeaderMap, HeaderName, HeaderValue};
+use spin_factors::anyhow;
+use spin_world::{
+    async_trait,
+    spin::http::early_hints::{self, Error},
+};
+use tracing::{instrument, Level};
+
+use crate::InstanceState;
+
+/// Sends `103 Early Hints` responses to the client of the request an instance
+/// is handling, to be used with [`InstanceState::set_early_hints_sender`].
+#[async_trait]
+pub trait EarlyHintsSender: Send + Sync {
+    /// Sends a `103 Early Hints` response with the given headers.
+    async fn send(&self, headers: HeaderMap) -> Result<(), Error>;
+}
+
+#[async_trait]
+impl early_hints::Host for InstanceState {
+    #[instrument(name = "spin_outbound_http.send_early_hints", skip_all, err(level = Level::INFO))]
+    async fn send(&mut self, headers: Vec<(String, String)>) -> Result<(), Error> {
+        let Some(sender) = &self.early_hints_sender else {
+            return Err(Error::Unsupported);
+        };
+        let mut map = HeaderMap::with_capacity(headers.len());
+        for (name, value) in headers {
+            let (Ok(header_name), Ok(value)) = (
+                HeaderName::try_from(name.as_str()),
+                HeaderValue::try_from(value),
+            ) else {
+                return Err(Error::InvalidHeader(name));
+            };
+            map.append(header_name, value);
+        }
+        sender.send(map).await
+    }
+
+    fn convert_error(&mut self, error: Error) -> anyhow::Result<Error> {
+        Ok(error)
+    }
+}
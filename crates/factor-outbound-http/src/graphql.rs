@@ -0,0 +1,607 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+use futures::{
+    future::{BoxFuture, Shared},
+    FutureExt,
+};
+use http::{
+    header::{ACCEPT, CACHE_CONTROL, CONTENT_TYPE},
+    HeaderMap, HeaderName, HeaderValue,
+};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use spin_world::{
+    async_trait,
+    spin::graphql::graphql::{self, Error, Request, Response},
+};
+use tracing::{field::Empty, instrument, Level, Span};
+
+use crate::{spin::spin_http_client, InstanceState};
+
+/// The most responses to cache for an app.
+const MAX_CACHED_RESPONSES: usize = 1024;
+
+/// Identifies requests which get the same response.
+type Key = [u8; 32];
+
+type InFlight = Shared<BoxFuture<'static, Result<Fetched, Error>>>;
+
+/// The in-flight requests and cached responses of an app's query operations.
+#[derive(Default)]
+pub(crate) struct GraphqlCache {
+    in_flight: Mutex<HashMap<Key, InFlight>>,
+    responses: Mutex<HashMap<Key, Cached>>,
+}
+
+struct Cached {
+    response: Fetched,
+    expires: Instant,
+}
+
+impl GraphqlCache {
+    fn get(&self, key: &Key) -> Option<Fetched> {
+        let mut responses = self.responses.lock().unwrap();
+        match responses.get(key) {
+            Some(cached) if cached.expires > Instant::now() => Some(cached.response.clone()),
+            Some(_) => {
+                responses.remove(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    fn insert(&self, key: Key, response: Fetched, max_age: Duration) {
+        let now = Instant::now();
+        let mut responses = self.responses.lock().unwrap();
+        if responses.len() >= MAX_CACHED_RESPONSES {
+            responses.retain(|_, cached| cached.expires > now);
+        }
+        if responses.len() < MAX_CACHED_RESPONSES {
+            let expires = now + max_age;
+            responses.insert(key, Cached { response, expires });
+        }
+    }
+
+    /// Fetches the response for `key` with `fetch`, or, if it is already being
+    /// fetched, waits for that instead.
+    async fn fetch_once(
+        &self,
+        key: Key,
+        fetch: impl Future<Output = Result<Fetched, Error>> + Send + 'static,
+    ) -> Result<Fetched, Error> {
+        let in_flight = self
+            .in_flight
+            .lock()
+            .unwrap()
+            .entry(key)
+            .or_insert_with(|| fetch.boxed().shared())
+            .clone();
+        let result = in_flight.clone().await;
+        let mut all_in_flight = self.in_flight.lock().unwrap();
+        if all_in_flight
+            .get(&key)
+            .is_some_and(|other| other.ptr_eq(&in_flight))
+        {
+            all_in_flight.remove(&key);
+        }
+        result
+    }
+}
+
+/// A response from a server.
+#[derive(Clone)]
+struct Fetched {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: Bytes,
+    /// How long the response may be cached for, if at all.
+    max_age: Option<Duration>,
+}
+
+impl Fetched {
+    fn into_response(self, cached: bool) -> Response {
+        Response {
+            status: self.status,
+            headers: self.headers,
+            body: self.body.into(),
+            cached,
+        }
+    }
+}
+
+#[async_trait]
+impl graphql::Host for InstanceState {
+    #[instrument(name = "spin_outbound_graphql.execute", skip_all, err(level = Level::INFO),
+        fields(otel.kind = "client", url.full = Empty, server.address = Empty, graphql.operation.name = Empty))]
+    async fn execute(&mut self, request: Request) -> Result<Response, Error> {
+        let url = reqwest::Url::parse(&request.url).map_err(|_| Error::AddressNotAllowed)?;
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(Error::AddressNotAllowed);
+        }
+        let is_allowed = self
+            .allowed_hosts
+            .check_url(&request.url, "https")
+            .await
+            .unwrap_or(false);
+        if !is_allowed {
+            return Err(Error::AddressNotAllowed);
+        }
+        let span = Span::current();
+        span.record("url.full", url.as_str());
+        if let Some(host) = url.host_str() {
+            span.record("server.address", host);
+        }
+        if let Some(operation_name) = &request.operation_name {
+            span.record("graphql.operation.name", operation_name.as_str());
+        }
+
+        let variables = request
+            .variables
+            .as_deref()
+            .map(serde_json::from_str::<Value>)
+            .transpose()
+            .map_err(|err| invalid(format!("invalid variables: {err}")))?;
+        if variables
+            .as_ref()
+            .is_some_and(|variables| !variables.is_object())
+        {
+            return Err(invalid("variables must be a JSON object".into()));
+        }
+        let headers = header_map(&request.headers)?;
+        if self.spin_http_client.is_none() {
+            self.spin_http_client = Some(
+                spin_http_client(&self.proxy_config)
+                    .map_err(|_| Error::Transport("failed to build HTTP client".into()))?,
+            );
+        }
+        let client = self.spin_http_client.clone().unwrap();
+
+        let key = cache_key(&request, variables.as_ref());
+        let query_hash = request.persisted.then(|| query_hash(&request.query));
+        let body = operation_body(&request, variables);
+        let fetch = fetch(client, url, headers, body, query_hash);
+
+        // Only queries are safe to share between requests.
+        if !is_query(&request.query, request.operation_name.as_deref()) {
+            return Ok(fetch.await?.into_response(false));
+        }
+        if let Some(cached) = self.graphql_cache.get(&key) {
+            return Ok(cached.into_response(true));
+        }
+        let fetched = self.graphql_cache.fetch_once(key, fetch).await?;
+        if let Some(max_age) = fetched.max_age {
+            self.graphql_cache.insert(key, fetched.clone(), max_age);
+        }
+        Ok(fetched.into_response(false))
+    }
+
+    fn convert_error(&mut self, error: Error) -> anyhow::Result<Error> {
+        Ok(error)
+    }
+}
+
+/// Posts `body`, first as an automatic persisted query if `query_hash` is
+/// given.
+async fn fetch(
+    client: reqwest::Client,
+    url: reqwest::Url,
+    headers: HeaderMap,
+    mut body: Value,
+    query_hash: Option<String>,
+) -> Result<Fetched, Error> {
+    let Some(query_hash) = query_hash else {
+        return post(&client, &url, &headers, &body).await;
+    };
+    body["extensions"] = json!({
+        "persistedQuery": { "version": 1, "sha256Hash": query_hash },
+    });
+    let mut hash_only = body.clone();
+    hash_only.as_object_mut().unwrap().remove("query");
+    let fetched = post(&client, &url, &headers, &hash_only).await?;
+    if !persisted_query_not_found(&fetched.body) {
+        return Ok(fetched);
+    }
+    // Sending the query along with its hash registers it with the server.
+    post(&client, &url, &headers, &body).await
+}
+
+async fn post(
+    client: &reqwest::Client,
+    url: &reqwest::Url,
+    headers: &HeaderMap,
+    body: &Value,
+) -> Result<Fetched, Error> {
+    let mut headers = headers.clone();
+    headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    headers.entry(ACCEPT).or_insert(HeaderValue::from_static(
+        "application/graphql-response+json, application/json",
+    ));
+    let response = client
+        .post(url.clone())
+        .headers(headers)
+        .body(body.to_string())
+        .send()
+        .await
+        .map_err(transport)?;
+    let status = response.status().as_u16();
+    let headers = response.headers().clone();
+    let body = response.bytes().await.map_err(transport)?;
+    let max_age = match status {
+        200 => max_age(&headers, &body),
+        _ => None,
+    };
+    Ok(Fetched {
+        status,
+        headers: headers
+            .iter()
+            .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_owned())))
+            .collect(),
+        body,
+        max_age,
+    })
+}
+
+fn operation_body(request: &Request, variables: Option<Value>) -> Value {
+    let mut body = json!({ "query": request.query });
+    if let Some(operation_name) = &request.operation_name {
+        body["operationName"] = operation_name.as_str().into();
+    }
+    if let Some(variables) = variables {
+        body["variables"] = variables;
+    }
+    body
+}
+
+fn header_map(headers: &[(String, String)]) -> Result<HeaderMap, Error> {
+    let mut map = HeaderMap::new();
+    for (name, value) in headers {
+        let name = HeaderName::try_from(name.as_str())
+            .map_err(|_| invalid(format!("invalid header name {name:?}")))?;
+        let value = HeaderValue::try_from(value.as_str())
+            .map_err(|_| invalid(format!("invalid value for header {name}")))?;
+        map.append(name, value);
+    }
+    Ok(map)
+}
+
+/// The hash of a request's URL, operation, and headers.
+fn cache_key(request: &Request, variables: Option<&Value>) -> Key {
+    let mut headers = request
+        .headers
+        .iter()
+        .map(|(name, value)| (name.to_ascii_lowercase(), value.as_str()))
+        .collect::<Vec<_>>();
+    headers.sort();
+    let variables = variables.map(Value::to_string).unwrap_or_default();
+
+    let mut hasher = Sha256::new();
+    let mut update = |field: &str| {
+        // Prefixing each field with its length keeps them from running together.
+        hasher.update((field.len() as u64).to_le_bytes());
+        hasher.update(field);
+    };
+    update(&request.url);
+    update(&request.query);
+    update(request.operation_name.as_deref().unwrap_or_default());
+    update(&variables);
+    for (name, value) in &headers {
+        update(name);
+        update(value);
+    }
+    hasher.finalize().into()
+}
+
+/// The hash identifying a persisted query.
+fn query_hash(query: &str) -> String {
+    format!("{:x}", Sha256::digest(query))
+}
+
+/// Whether the server doesn't know a persisted query's hash, so the query must
+/// be sent in full.
+fn persisted_query_not_found(body: &[u8]) -> bool {
+    let Ok(body) = serde_json::from_slice::<Value>(body) else {
+        return false;
+    };
+    let Some(errors) = body.get("errors").and_then(Value::as_array) else {
+        return false;
+    };
+    errors.iter().any(|error| {
+        error.get("message").and_then(Value::as_str) == Some("PersistedQueryNotFound")
+            || error.pointer("/extensions/code").and_then(Value::as_str)
+                == Some("PERSISTED_QUERY_NOT_FOUND")
+    })
+}
+
+/// How long a successful response may be cached for, per its `Cache-Control`
+/// header or else its `extensions.cacheControl` hints. Responses with errors
+/// are never cached.
+fn max_age(headers: &HeaderMap, body: &[u8]) -> Option<Duration> {
+    let body = serde_json::from_slice::<Value>(body).ok()?;
+    if body.get("errors").is_some_and(|errors| !errors.is_null()) {
+        return None;
+    }
+    let age = match headers.get(CACHE_CONTROL) {
+        Some(cache_control) => cache_control_max_age(cache_control.to_str().ok()?),
+        None => hinted_max_age(&body),
+    }?;
+    (age > 0).then(|| Duration::from_secs(age))
+}
+
+/// The max age of a `Cache-Control` header value, preferring `s-maxage` since
+/// the host's cache is shared.
+fn cache_control_max_age(cache_control: &str) -> Option<u64> {
+    let (mut max_age, mut s_maxage) = (None, None);
+    for directive in cache_control.split(',') {
+        let (name, value) = match directive.split_once('=') {
+            Some((name, value)) => (name.trim(), Some(value.trim().trim_matches('"'))),
+            None => (directive.trim(), None),
+        };
+        match name.to_ascii_lowercase().as_str() {
+            "no-store" | "no-cache" | "private" => return None,
+            "max-age" => max_age = value.and_then(|value| value.parse().ok()),
+            "s-maxage" => s_maxage = value.and_then(|value| value.parse().ok()),
+            _ => {}
+        }
+    }
+    s_maxage.or(max_age)
+}
+
+/// The least max age of the cache hints in a response's extensions, as sent
+/// by Apollo servers, unless any of them is private.
+fn hinted_max_age(body: &Value) -> Option<u64> {
+    let hints = body.pointer("/extensions/cacheControl/hints")?.as_array()?;
+    let mut max_age = None;
+    for hint in hints {
+        let scope = hint.get("scope").and_then(Value::as_str);
+        if scope.is_some_and(|scope| scope.eq_ignore_ascii_case("private")) {
+            return None;
+        }
+        let hint_max_age = hint.get("maxAge").and_then(Value::as_u64)?;
+        max_age = Some(max_age.map_or(hint_max_age, |age: u64| age.min(hint_max_age)));
+    }
+    max_age
+}
+
+/// Whether the operation to execute from `document` is a query, rather than a
+/// mutation or subscription.
+///
+/// This only tokenizes the document as far as it needs to find its operations'
+/// types and names; invalid documents may be misread, but are sent as they are
+/// for the server to reject.
+fn is_query(document: &str, operation_name: Option<&str>) -> bool {
+    let mut operations = vec![];
+    // The type and name of the definition being read, until its selection set.
+    let mut definition = None;
+    let (mut braces, mut parens) = (0usize, 0usize);
+    let mut tokens = tokens(document).peekable();
+    while let Some(token) = tokens.next() {
+        let top_level = braces == 0 && parens == 0;
+        match token {
+            "(" => parens += 1,
+            ")" => parens = parens.saturating_sub(1),
+            "{" => {
+                if top_level {
+                    match definition.take() {
+                        Some(("fragment", _)) => {}
+                        Some(operation) => operations.push(operation),
+                        // A selection set on its own is a query.
+                        None => operations.push(("query", None)),
+                    }
+                }
+                braces += 1;
+            }
+            "}" => braces = braces.saturating_sub(1),
+            "query" | "mutation" | "subscription" | "fragment"
+                if top_level && definition.is_none() =>
+            {
+                let name = tokens
+                    .peek()
+                    .copied()
+                    .filter(|name| !matches!(*name, "{" | "}" | "(" | ")"));
+                definition = Some((token, name));
+            }
+            _ => {}
+        }
+    }
+    let operation = match operation_name {
+        Some(operation_name) => operations
+            .iter()
+            .find(|(_, name)| *name == Some(operation_name)),
+        None if operations.len() == 1 => operations.first(),
+        None => None,
+    };
+    operation.is_some_and(|(kind, _)| *kind == "query")
+}
+
+/// The names and brackets of a GraphQL document, skipping its comments and
+/// strings.
+fn tokens(document: &str) -> impl Iterator<Item = &str> {
+    let mut rest = document;
+    std::iter::from_fn(move || loop {
+        let c = rest.chars().next()?;
+        let end = match c {
+            '#' => {
+                rest = rest.find('\n').map_or("", |end| &rest[end..]);
+                continue;
+            }
+            '"' => {
+                rest = skip_string(rest);
+                continue;
+            }
+            '{' | '}' | '(' | ')' => 1,
+            c if c == '_' || c.is_ascii_alphabetic() => rest
+                .find(|c: char| c != '_' && !c.is_ascii_alphanumeric())
+                .unwrap_or(rest.len()),
+            c => {
+                rest = &rest[c.len_utf8()..];
+                continue;
+            }
+        };
+        let (token, remaining) = rest.split_at(end);
+        rest = remaining;
+        return Some(token);
+    })
+}
+
+/// Skips the string or block string at the start of `s`.
+fn skip_string(s: &str) -> &str {
+    if let Some(block) = s.strip_prefix(r#"""""#) {
+        let mut start = 0;
+        while let Some(end) = block[start..].find(r#"""""#) {
+            let end = start + end;
+            if !block[..end].ends_with('\\') {
+                return &block[end + 3..];
+            }
+            start = end + 3;
+        }
+        return "";
+    }
+    let mut chars = s.char_indices().skip(1);
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '"' | '\n' => return &s[i + 1..],
+            _ => {}
+        }
+    }
+    ""
+}
+
+fn invalid(message: String) -> Error {
+    Error::InvalidRequest(message)
+}
+
+fn transport(err: reqwest::Error) -> Error {
+    Error::Transport(err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_queries_are_shared() {
+        assert!(is_query("{ hero { name } }", None));
+        assert!(is_query(
+            "query Hero($id: ID!) { hero(id: $id) { name } }",
+            None
+        ));
+        assert!(is_query(
+            "# mutation Nope { nope }\nquery { field(arg: \"mutation {\") }",
+            None
+        ));
+        assert!(!is_query("mutation { like(id: 1) { likes } }", None));
+        assert!(!is_query("subscription { likes }", None));
+        assert!(!is_query("not a document", None));
+    }
+
+    #[test]
+    fn operations_are_chosen_by_name() {
+        let document = r#"
+            query Hero { hero { ...Name } }
+            fragment Name on Character { name }
+            mutation Like($input: LikeInput = { id: 1 }) { like(input: $input) { likes } }
+        "#;
+        assert!(is_query(document, Some("Hero")));
+        assert!(!is_query(document, Some("Like")));
+        assert!(!is_query(document, Some("Name")));
+        assert!(!is_query(document, None));
+    }
+
+    #[test]
+    fn cache_control_headers_take_precedence() {
+        let body = br#"{"data":{},"extensions":{"cacheControl":{"version":1,"hints":[{"path":["a"],"maxAge":30}]}}}"#;
+        let max_age_with = |cache_control: Option<&'static str>| {
+            let mut headers = HeaderMap::new();
+            if let Some(cache_control) = cache_control {
+                headers.insert(CACHE_CONTROL, HeaderValue::from_static(cache_control));
+            }
+            max_age(&headers, body)
+        };
+        assert_eq!(max_age_with(None), Some(Duration::from_secs(30)));
+        assert_eq!(
+            max_age_with(Some("public, max-age=60, s-maxage=120")),
+            Some(Duration::from_secs(120))
+        );
+        assert_eq!(max_age_with(Some("max-age=60, private")), None);
+        assert_eq!(max_age_with(Some("no-store")), None);
+    }
+
+    #[test]
+    fn cache_hints_give_the_least_max_age() {
+        let hinted = |hints: Value| {
+            hinted_max_age(&json!({ "extensions": { "cacheControl": { "hints": hints } } }))
+        };
+        assert_eq!(
+            hinted(json!([{ "maxAge": 60 }, { "maxAge": 10, "scope": "PUBLIC" }])),
+            Some(10)
+        );
+        assert_eq!(
+            hinted(json!([{ "maxAge": 60 }, { "maxAge": 10, "scope": "PRIVATE" }])),
+            None
+        );
+        assert_eq!(hinted(json!([{ "maxAge": 60 }, { "path": ["a"] }])), None);
+    }
+
+    #[test]
+    fn responses_with_errors_are_not_cached() {
+        let headers =
+            HeaderMap::from_iter([(CACHE_CONTROL, HeaderValue::from_static("max-age=60"))]);
+        assert_eq!(
+            max_age(&headers, br#"{"data":null,"errors":[{"message":"nope"}]}"#),
+            None
+        );
+        assert_eq!(
+            max_age(&headers, br#"{"data":{}}"#),
+            Some(Duration::from_secs(60))
+        );
+    }
+
+    #[test]
+    fn unknown_persisted_queries_are_detected() {
+        assert!(persisted_query_not_found(
+            br#"{"errors":[{"message":"PersistedQueryNotFound"}]}"#
+        ));
+        assert!(persisted_query_not_found(
+            br#"{"errors":[{"message":"not found","extensions":{"code":"PERSISTED_QUERY_NOT_FOUND"}}]}"#
+        ));
+        assert!(!persisted_query_not_found(br#"{"data":{}}"#));
+        assert_eq!(
+            query_hash("{ hero { name } }"),
+            "aae585680c3470e4947255eafbd1eafe87d1c3f129259cf15e404d1bb7f1e8f4"
+        );
+    }
+
+    #[test]
+    fn cache_keys_depend_on_headers_but_not_their_order() {
+        let request = |headers: &[(&str, &str)]| Request {
+            url: "https://api.example.com/graphql".into(),
+            query: "{ me { name } }".into(),
+            operation_name: None,
+            variables: None,
+            headers: headers
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+            persisted: false,
+        };
+        let key = |headers| cache_key(&request(headers), None);
+        assert_eq!(
+            key(&[("Authorization", "Bearer a"), ("x-tenant", "1")]),
+            key(&[("x-tenant", "1"), ("authorization", "Bearer a")])
+        );
+        assert_ne!(
+            key(&[("authorization", "Bearer a")]),
+            key(&[("authorization", "Bearer b")])
+        );
+    }
+}
@@ -0,0 +1,596 @@
+use std::{sync::Arc, time::Duration};
+
+use base64::{
+    engine::{general_purpose::GeneralPurpose, DecodePaddingMode, GeneralPurposeConfig},
+    Engine,
+};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use http::{
+    header::{CONTENT_TYPE, TE},
+    HeaderMap, HeaderName, HeaderValue, Method, StatusCode, Uri,
+};
+use http_body_util::{BodyExt, Full};
+use hyper::{body::Incoming, client::conn::http2::SendRequest};
+use hyper_util::rt::TokioExecutor;
+use rustls::pki_types::ServerName;
+use spin_factors::wasmtime::component::Resource;
+use spin_world::{
+    async_trait,
+    spin::grpc::grpc::{self, Error, Metadata, ServerStream, Status, UnaryResponse},
+};
+use tracing::{field::Empty, instrument, Level, Span};
+use wasmtime_wasi_http::io::TokioIo;
+
+use crate::{wasi::connect_tcp, InstanceState};
+
+/// How long to wait to connect to a server.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The largest response message to accept, as for gRPC's own clients.
+const MAX_MESSAGE_SIZE: usize = 4 * 1024 * 1024;
+
+/// gRPC status codes used by the host.
+const UNKNOWN: u32 = 2;
+const DEADLINE_EXCEEDED: u32 = 4;
+const PERMISSION_DENIED: u32 = 7;
+const UNIMPLEMENTED: u32 = 12;
+const INTERNAL: u32 = 13;
+const UNAVAILABLE: u32 = 14;
+const UNAUTHENTICATED: u32 = 16;
+
+/// Base64 for `-bin` metadata, which is sent unpadded but may be received
+/// either way.
+const BASE64: GeneralPurpose = GeneralPurpose::new(
+    &base64::alphabet::STANDARD,
+    GeneralPurposeConfig::new()
+        .with_encode_padding(false)
+        .with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
+
+#[async_trait]
+impl grpc::Host for InstanceState {
+    #[instrument(name = "spin_outbound_grpc.unary", skip_all, err(level = Level::INFO),
+        fields(otel.kind = "client", rpc.system = "grpc", rpc.method = %request.method, server.address = Empty))]
+    async fn unary(
+        &mut self,
+        request: grpc::Request,
+        message: Vec<u8>,
+    ) -> Result<UnaryResponse, Error> {
+        let deadline = request.timeout_ms.map(Duration::from_millis);
+        let call = async {
+            let mut stream = self.call(request, message).await?;
+            let message = stream
+                .next_message()
+                .await?
+                .ok_or_else(|| transport("the server sent no response message"))?;
+            if stream.next_message().await?.is_some() {
+                return Err(transport("the server sent more than one response message"));
+            }
+            let trailers = stream.trailers().unwrap_or_default();
+            Ok(UnaryResponse {
+                message: message.into(),
+                metadata: stream.metadata,
+                trailers,
+            })
+        };
+        match deadline {
+            Some(deadline) => tokio::time::timeout(deadline, call)
+                .await
+                .map_err(|_| deadline_exceeded())?,
+            None => call.await,
+        }
+    }
+
+    #[instrument(name = "spin_outbound_grpc.server_streaming", skip_all, err(level = Level::INFO),
+        fields(otel.kind = "client", rpc.system = "grpc", rpc.method = %request.method, server.address = Empty))]
+    async fn server_streaming(
+        &mut self,
+        request: grpc::Request,
+        message: Vec<u8>,
+    ) -> Result<Resource<ServerStream>, Error> {
+        let deadline = request.timeout_ms.map(Duration::from_millis);
+        let call = self.call(request, message);
+        let mut stream = match deadline {
+            Some(deadline) => tokio::time::timeout(deadline, call)
+                .await
+                .map_err(|_| deadline_exceeded())??,
+            None => call.await?,
+        };
+        stream.deadline = deadline.map(|deadline| tokio::time::Instant::now() + deadline);
+        self.grpc_streams
+            .push(stream)
+            .map(Resource::new_own)
+            .map_err(|()| transport("too many server streams are open"))
+    }
+
+    fn convert_error(&mut self, error: Error) -> anyhow::Result<Error> {
+        Ok(error)
+    }
+}
+
+#[async_trait]
+impl grpc::HostServerStream for InstanceState {
+    async fn metadata(&mut self, stream: Resource<ServerStream>) -> anyhow::Result<Metadata> {
+        Ok(self.get_grpc_stream(&stream)?.metadata.clone())
+    }
+
+    async fn next(&mut self, stream: Resource<ServerStream>) -> Result<Option<Vec<u8>>, Error> {
+        let stream = self
+            .grpc_streams
+            .get_mut(stream.rep())
+            .ok_or_else(|| transport("invalid server stream"))?;
+        let next = match stream.deadline {
+            Some(deadline) => tokio::time::timeout_at(deadline, stream.next_message())
+                .await
+                .map_err(|_| deadline_exceeded())?,
+            None => stream.next_message().await,
+        };
+        Ok(next?.map(Into::into))
+    }
+
+    async fn trailers(
+        &mut self,
+        stream: Resource<ServerStream>,
+    ) -> anyhow::Result<Option<Metadata>> {
+        Ok(self.get_grpc_stream(&stream)?.trailers())
+    }
+
+    async fn drop(&mut self, stream: Resource<ServerStream>) -> anyhow::Result<()> {
+        self.grpc_streams.remove(stream.rep());
+        Ok(())
+    }
+}
+
+impl InstanceState {
+    fn get_grpc_stream(&self, stream: &Resource<ServerStream>) -> anyhow::Result<&GrpcStream> {
+        self.grpc_streams
+            .get(stream.rep())
+            .ok_or_else(|| anyhow::anyhow!("invalid server stream"))
+    }
+
+    /// Sends a request over a new HTTP/2 connection, returning the response
+    /// once its headers have been received.
+    async fn call(&self, request: grpc::Request, message: Vec<u8>) -> Result<GrpcStream, Error> {
+        let server: Uri = request
+            .server
+            .parse()
+            .map_err(|_| Error::AddressNotAllowed)?;
+        let use_tls = match server.scheme_str() {
+            Some("https") => true,
+            Some("http") => false,
+            _ => return Err(Error::AddressNotAllowed),
+        };
+        let is_allowed = self
+            .allowed_hosts
+            .check_url(&request.server, "https")
+            .await
+            .unwrap_or(false);
+        if !is_allowed {
+            return Err(Error::AddressNotAllowed);
+        }
+        let authority = server.authority().ok_or(Error::AddressNotAllowed)?;
+        let host = authority.host();
+        Span::current().record("server.address", host);
+
+        let uri = Uri::builder()
+            .scheme(server.scheme_str().unwrap_or_default())
+            .authority(authority.as_str())
+            .path_and_query(request.method.as_str())
+            .build()
+            .map_err(|err| invalid(format!("invalid method {:?}: {err}", request.method)))?;
+        let http_request = build_request(uri, &request.metadata, request.timeout_ms, &message)?;
+
+        let authority_str = match authority.port() {
+            Some(_) => authority.to_string(),
+            None => format!("{authority}:{}", if use_tls { 443 } else { 80 }),
+        };
+        let tcp_stream = connect_tcp(
+            &server,
+            &authority_str,
+            use_tls,
+            CONNECT_TIMEOUT,
+            &self.proxy_config,
+            self.allow_private_ips,
+        )
+        .await
+        .map_err(|err| transport(format!("failed to connect to {authority}: {err:?}")))?;
+
+        let mut sender = if use_tls {
+            let mut tls_config = (**self.component_tls_configs.get_client_config(host)).clone();
+            tls_config.alpn_protocols = vec![b"h2".to_vec()];
+            let connector = tokio_rustls::TlsConnector::from(Arc::new(tls_config));
+            let domain = ServerName::try_from(host.trim_start_matches('[').trim_end_matches(']'))
+                .map_err(|err| invalid(format!("invalid server name {host:?}: {err}")))?
+                .to_owned();
+            let stream = connector
+                .connect(domain, tcp_stream)
+                .await
+                .map_err(|err| transport(format!("TLS error: {err}")))?;
+            handshake(TokioIo::new(stream)).await?
+        } else {
+            handshake(TokioIo::new(tcp_stream)).await?
+        };
+        let response = sender
+            .send_request(http_request)
+            .await
+            .map_err(|err| transport(format!("request failed: {err}")))?;
+        GrpcStream::new(response)
+    }
+}
+
+/// Starts an HTTP/2 connection over `io`.
+async fn handshake<I>(io: I) -> Result<SendRequest<Full<Bytes>>, Error>
+where
+    I: hyper::rt::Read + hyper::rt::Write + Unpin + Send + 'static,
+{
+    let (sender, conn) = hyper::client::conn::http2::handshake(TokioExecutor::new(), io)
+        .await
+        .map_err(|err| transport(format!("HTTP/2 handshake failed: {err}")))?;
+    tokio::spawn(async move {
+        if let Err(err) = conn.await {
+            tracing::warn!("gRPC connection error: {err}");
+        }
+    });
+    Ok(sender)
+}
+
+fn build_request(
+    uri: Uri,
+    metadata: &Metadata,
+    timeout_ms: Option<u64>,
+    message: &[u8],
+) -> Result<http::Request<Full<Bytes>>, Error> {
+    let mut builder = http::Request::builder()
+        .method(Method::POST)
+        .uri(uri)
+        .version(http::Version::HTTP_2)
+        .header(CONTENT_TYPE, "application/grpc")
+        .header(TE, "trailers");
+    if let Some(timeout_ms) = timeout_ms {
+        builder = builder.header("grpc-timeout", grpc_timeout(timeout_ms));
+    }
+    let headers = builder.headers_mut().expect("request builder is valid");
+    for (key, value) in metadata {
+        let name = HeaderName::try_from(key.as_str())
+            .map_err(|_| invalid(format!("invalid metadata key {key:?}")))?;
+        let value = if key.ends_with("-bin") {
+            HeaderValue::try_from(BASE64.encode(value))
+        } else {
+            HeaderValue::from_bytes(value)
+        }
+        .map_err(|_| invalid(format!("invalid value for metadata key {key:?}")))?;
+        headers.append(name, value);
+    }
+    builder
+        .body(Full::new(encode_message(message)))
+        .map_err(|err| invalid(err.to_string()))
+}
+
+/// Formats a `grpc-timeout` header, which may have at most 8 digits.
+fn grpc_timeout(timeout_ms: u64) -> String {
+    const MAX: u64 = 99_999_999;
+    if timeout_ms <= MAX {
+        return format!("{timeout_ms}m");
+    }
+    let secs = timeout_ms / 1000;
+    if secs <= MAX {
+        return format!("{secs}S");
+    }
+    format!("{}H", (secs / 3600).min(MAX))
+}
+
+/// Frames a message with its length, uncompressed.
+fn encode_message(message: &[u8]) -> Bytes {
+    let mut framed = BytesMut::with_capacity(5 + message.len());
+    framed.put_u8(0);
+    framed.put_u32(message.len() as u32);
+    framed.put_slice(message);
+    framed.freeze()
+}
+
+/// Splits the body of a response into its messages.
+#[derive(Default)]
+struct MessageDecoder {
+    buffer: BytesMut,
+}
+
+impl MessageDecoder {
+    fn push(&mut self, data: &[u8]) {
+        self.buffer.extend_from_slice(data);
+    }
+
+    /// Returns the next message, if all of it has been pushed.
+    fn next_message(&mut self) -> Result<Option<Bytes>, Error> {
+        if self.buffer.len() < 5 {
+            return Ok(None);
+        }
+        if self.buffer[0] != 0 {
+            return Err(transport(
+                "the server sent a compressed message, which is not supported",
+            ));
+        }
+        let len = u32::from_be_bytes(self.buffer[1..5].try_into().unwrap()) as usize;
+        if len > MAX_MESSAGE_SIZE {
+            return Err(transport(format!(
+                "the server sent a {len} byte message; the most allowed is {MAX_MESSAGE_SIZE}"
+            )));
+        }
+        if self.buffer.len() < 5 + len {
+            return Ok(None);
+        }
+        self.buffer.advance(5);
+        Ok(Some(self.buffer.split_to(len).freeze()))
+    }
+
+    fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+}
+
+/// The responses of a call.
+pub(crate) struct GrpcStream {
+    body: Incoming,
+    decoder: MessageDecoder,
+    metadata: Metadata,
+    /// The trailers if the call completed with `OK`, or its error otherwise.
+    status: Option<Result<Metadata, Error>>,
+    deadline: Option<tokio::time::Instant>,
+}
+
+impl GrpcStream {
+    fn new(response: http::Response<Incoming>) -> Result<Self, Error> {
+        let (parts, body) = response.into_parts();
+        if parts.status != StatusCode::OK {
+            return Err(Error::Status(Status {
+                code: http_status_code(parts.status),
+                message: format!("the server responded with HTTP status {}", parts.status),
+                trailers: vec![],
+            }));
+        }
+        let mut stream = Self {
+            body,
+            decoder: Default::default(),
+            metadata: metadata(&parts.headers),
+            status: None,
+            deadline: None,
+        };
+        // A call that fails immediately has a "trailers-only" response, with
+        // its status in its headers.
+        if parts.headers.contains_key("grpc-status") {
+            stream.finish(&parts.headers)?;
+            return Ok(stream);
+        }
+        let content_type = parts
+            .headers
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default();
+        if !content_type.starts_with("application/grpc") {
+            return Err(Error::Status(Status {
+                code: UNKNOWN,
+                message: format!("the server responded with content type {content_type:?}"),
+                trailers: vec![],
+            }));
+        }
+        Ok(stream)
+    }
+
+    /// Returns the next message, or `None` once the call completes with `OK`.
+    async fn next_message(&mut self) -> Result<Option<Bytes>, Error> {
+        loop {
+            if let Some(message) = self.decoder.next_message()? {
+                return Ok(Some(message));
+            }
+            if let Some(status) = &self.status {
+                return status.clone().map(|_| None);
+            }
+            match self.body.frame().await {
+                Some(Ok(frame)) => match frame.into_data() {
+                    Ok(data) => self.decoder.push(&data),
+                    Err(frame) => {
+                        if let Ok(trailers) = frame.into_trailers() {
+                            self.finish(&trailers)?;
+                        }
+                    }
+                },
+                Some(Err(err)) => return Err(transport(format!("response failed: {err}"))),
+                None => {
+                    let err = transport("the response ended without a gRPC status");
+                    self.status = Some(Err(err.clone()));
+                    return Err(err);
+                }
+            }
+        }
+    }
+
+    /// Records the status in `trailers`, returning it if it isn't `OK`.
+    fn finish(&mut self, trailers: &HeaderMap) -> Result<(), Error> {
+        let status = if !self.decoder.is_empty() {
+            Err(transport("the response ended in the middle of a message"))
+        } else {
+            match status(trailers) {
+                Some((0, _)) => Ok(metadata(trailers)),
+                Some((code, message)) => Err(Error::Status(Status {
+                    code,
+                    message,
+                    trailers: metadata(trailers),
+                })),
+                None => Err(transport("the response has an invalid gRPC status")),
+            }
+        };
+        self.status = Some(status.clone());
+        status.map(|_| ())
+    }
+
+    /// The trailers, if the call has completed with `OK`.
+    fn trailers(&self) -> Option<Metadata> {
+        self.status.as_ref()?.as_ref().ok().cloned()
+    }
+}
+
+/// The `grpc-status` and `grpc-message` in `headers`.
+fn status(headers: &HeaderMap) -> Option<(u32, String)> {
+    let code = headers.get("grpc-status")?.to_str().ok()?.parse().ok()?;
+    let message = headers
+        .get("grpc-message")
+        .map(|value| percent_decode(value.as_bytes()))
+        .unwrap_or_default();
+    Some((code, message))
+}
+
+/// Decodes a `grpc-message`, which is percent-encoded UTF-8.
+fn percent_decode(value: &[u8]) -> String {
+    let mut decoded = Vec::with_capacity(value.len());
+    let mut i = 0;
+    while i < value.len() {
+        let hex = value
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (value[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Converts headers or trailers to metadata, decoding binary values.
+fn metadata(headers: &HeaderMap) -> Metadata {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            let value = match name.as_str().ends_with("-bin") {
+                true => BASE64
+                    .decode(value.as_bytes())
+                    .unwrap_or_else(|_| value.as_bytes().to_vec()),
+                false => value.as_bytes().to_vec(),
+            };
+            (name.to_string(), value)
+        })
+        .collect()
+}
+
+/// The gRPC status code for a response which isn't a gRPC response, per
+/// https://github.com/grpc/grpc/blob/master/doc/http-grpc-status-mapping.md.
+fn http_status_code(status: StatusCode) -> u32 {
+    match status.as_u16() {
+        400 => INTERNAL,
+        401 => UNAUTHENTICATED,
+        403 => PERMISSION_DENIED,
+        404 => UNIMPLEMENTED,
+        429 | 502 | 503 | 504 => UNAVAILABLE,
+        _ => UNKNOWN,
+    }
+}
+
+fn deadline_exceeded() -> Error {
+    Error::Status(Status {
+        code: DEADLINE_EXCEEDED,
+        message: "the call's deadline was exceeded".into(),
+        trailers: vec![],
+    })
+}
+
+fn invalid(message: impl Into<String>) -> Error {
+    Error::InvalidRequest(message.into())
+}
+
+fn transport(message: impl Into<String>) -> Error {
+    Error::Transport(message.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn messages_are_split_however_they_arrive() {
+        let mut body = encode_message(b"first").to_vec();
+        body.extend_from_slice(&encode_message(b""));
+        body.extend_from_slice(&encode_message(b"third"));
+
+        let mut decoder = MessageDecoder::default();
+        let mut messages = vec![];
+        for byte in body {
+            decoder.push(&[byte]);
+            while let Some(message) = decoder.next_message().unwrap() {
+                messages.push(message);
+            }
+        }
+        assert_eq!(vec!["first", "", "third"], messages);
+        assert!(decoder.is_empty());
+    }
+
+    #[test]
+    fn compressed_messages_are_rejected() {
+        let mut decoder = MessageDecoder::default();
+        decoder.push(&[1, 0, 0, 0, 1, 42]);
+        assert!(matches!(decoder.next_message(), Err(Error::Transport(_))));
+    }
+
+    #[test]
+    fn statuses_are_read_from_trailers() {
+        let mut trailers = HeaderMap::new();
+        trailers.insert("grpc-status", HeaderValue::from_static("5"));
+        trailers.insert(
+            "grpc-message",
+            HeaderValue::from_static("no%20such%20thing%3A%20%E2%9C%93"),
+        );
+        trailers.insert("details-bin", HeaderValue::from_static("AQI"));
+        assert_eq!(
+            Some((5, "no such thing: \u{2713}".to_string())),
+            status(&trailers)
+        );
+        let metadata = metadata(&trailers);
+        assert!(metadata.contains(&("details-bin".to_string(), vec![1, 2])));
+    }
+
+    #[test]
+    fn http_statuses_map_to_grpc_codes() {
+        assert_eq!(UNIMPLEMENTED, http_status_code(StatusCode::NOT_FOUND));
+        assert_eq!(UNAVAILABLE, http_status_code(StatusCode::BAD_GATEWAY));
+        assert_eq!(UNKNOWN, http_status_code(StatusCode::IM_A_TEAPOT));
+    }
+
+    #[test]
+    fn requests_carry_metadata_and_deadlines() {
+        let request = build_request(
+            "https://example.com/pkg.Service/Method".parse().unwrap(),
+            &vec![
+                ("x-token".to_string(), b"secret".to_vec()),
+                ("trace-bin".to_string(), vec![1, 2]),
+            ],
+            Some(1500),
+            b"hi",
+        )
+        .unwrap();
+        let headers = request.headers();
+        assert_eq!("application/grpc", headers[CONTENT_TYPE]);
+        assert_eq!("trailers", headers[TE]);
+        assert_eq!("1500m", headers["grpc-timeout"]);
+        assert_eq!("secret", headers["x-token"]);
+        assert_eq!("AQI", headers["trace-bin"]);
+
+        assert!(build_request(
+            "https://example.com/m".parse().unwrap(),
+            &vec![("not a key".to_string(), vec![])],
+            None,
+            b"",
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn timeouts_fit_in_eight_digits() {
+        assert_eq!("250m", grpc_timeout(250));
+        assert_eq!("100000S", grpc_timeout(100_000_000));
+        assert_eq!("99999999H", grpc_timeout(u64::MAX));
+    }
+}
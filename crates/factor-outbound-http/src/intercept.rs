@@ -1,3 +1,5 @@
+use std::sync::Arc;
+
 use http::{Request, Response};
 use http_body_util::{BodyExt, Full};
 use spin_world::async_trait;
@@ -19,6 +21,45 @@ pub trait OutboundHttpInterceptor: Send + Sync {
     /// will be returned as the result of the request, bypassing the default
     /// handler. The `request` will also be dropped immediately.
     async fn intercept(&self, request: InterceptRequest) -> HttpResult<InterceptOutcome>;
+
+    /// Intercept the response to a request that this interceptor passed on
+    /// with [`InterceptOutcome::Continue`].
+    ///
+    /// The `request` is the envelope of the request as it was passed on. The
+    /// default implementation returns the `response` unchanged.
+    async fn intercept_response(
+        &self,
+        request: &Request<()>,
+        response: Response<HyperBody>,
+    ) -> HttpResult<Response<HyperBody>> {
+        let _ = request;
+        Ok(response)
+    }
+}
+
+/// Passes requests through `first` and then, if it continues, `second`.
+pub(crate) struct ChainedInterceptor {
+    pub first: Arc<dyn OutboundHttpInterceptor>,
+    pub second: Arc<dyn OutboundHttpInterceptor>,
+}
+
+#[async_trait]
+impl OutboundHttpInterceptor for ChainedInterceptor {
+    async fn intercept(&self, request: InterceptRequest) -> HttpResult<InterceptOutcome> {
+        match self.first.intercept(request).await? {
+            InterceptOutcome::Continue(request) => self.second.intercept(request).await,
+            complete @ InterceptOutcome::Complete(_) => Ok(complete),
+        }
+    }
+
+    async fn intercept_response(
+        &self,
+        request: &Request<()>,
+        response: Response<HyperBody>,
+    ) -> HttpResult<Response<HyperBody>> {
+        let response = self.second.intercept_response(request, response).await?;
+        self.first.intercept_response(request, response).await
+    }
 }
 
 /// The type returned by an [`OutboundHttpInterceptor`].
@@ -47,6 +88,11 @@ enum InterceptBody {
 }
 
 impl InterceptRequest {
+    /// Returns a copy of the request envelope, without the body.
+    pub(crate) fn envelope(&self) -> Request<()> {
+        envelope(&self.inner)
+    }
+
     pub fn into_hyper_request(self) -> Request<HyperBody> {
         let (parts, ()) = self.inner.into_parts();
         Request::from_parts(parts, self.body.into())
@@ -61,6 +107,16 @@ impl InterceptRequest {
     }
 }
 
+/// Returns a copy of the given request's envelope, without the body.
+pub(crate) fn envelope<B>(request: &Request<B>) -> Request<()> {
+    let mut envelope = Request::new(());
+    *envelope.method_mut() = request.method().clone();
+    *envelope.uri_mut() = request.uri().clone();
+    *envelope.version_mut() = request.version();
+    *envelope.headers_mut() = request.headers().clone();
+    envelope
+}
+
 impl std::ops::Deref for InterceptRequest {
     type Target = Request<()>;
 
@@ -1,4 +1,8 @@
+mod early_hints;
+mod graphql;
+mod grpc;
 pub mod intercept;
+mod single_flight;
 mod spin;
 mod wasi;
 pub mod wasi_2023_10_18;
@@ -12,14 +16,16 @@ use http::{
     HeaderValue, Uri,
 };
 use intercept::OutboundHttpInterceptor;
+use serde::Deserialize;
 use spin_factor_outbound_networking::{
-    ComponentTlsConfigs, OutboundAllowedHosts, OutboundNetworkingFactor,
+    ComponentTlsConfigs, OutboundAllowedHosts, OutboundNetworkingFactor, ProxyConfig,
 };
 use spin_factors::{
     anyhow, ConfigureAppContext, Factor, PrepareContext, RuntimeFactors, SelfInstanceBuilder,
 };
 use wasmtime_wasi_http::WasiHttpCtx;
 
+pub use early_hints::EarlyHintsSender;
 pub use wasmtime_wasi_http::{
     body::HyperOutgoingBody,
     types::{HostFutureIncomingResponse, OutgoingRequestConfig},
@@ -28,6 +34,7 @@ pub use wasmtime_wasi_http::{
 
 pub struct OutboundHttpFactor {
     allow_private_ips: bool,
+    request_interceptor: Option<Arc<dyn OutboundHttpInterceptor>>,
 }
 
 impl OutboundHttpFactor {
@@ -35,21 +42,30 @@ impl OutboundHttpFactor {
     ///
     /// If `allow_private_ips` is true, requests to private IP addresses will be allowed.
     pub fn new(allow_private_ips: bool) -> Self {
-        Self { allow_private_ips }
+        Self {
+            allow_private_ips,
+            request_interceptor: None,
+        }
+    }
+
+    /// Sets an [`OutboundHttpInterceptor`] for requests from every instance.
+    ///
+    /// Requests pass through any interceptor set with
+    /// [`InstanceState::set_request_interceptor`] first.
+    pub fn set_request_interceptor(&mut self, interceptor: impl OutboundHttpInterceptor + 'static) {
+        self.request_interceptor = Some(Arc::new(interceptor));
     }
 }
 
 impl Default for OutboundHttpFactor {
     fn default() -> Self {
-        Self {
-            allow_private_ips: true,
-        }
+        Self::new(true)
     }
 }
 
 impl Factor for OutboundHttpFactor {
-    type RuntimeConfig = ();
-    type AppState = ();
+    type RuntimeConfig = RuntimeConfig;
+    type AppState = AppState;
     type InstanceBuilder = InstanceState;
 
     fn init<T: Send + 'static>(
@@ -57,15 +73,22 @@ impl Factor for OutboundHttpFactor {
         mut ctx: spin_factors::InitContext<T, Self>,
     ) -> anyhow::Result<()> {
         ctx.link_bindings(spin_world::v1::http::add_to_linker)?;
+        ctx.link_bindings(spin_world::spin::graphql::graphql::add_to_linker)?;
+        ctx.link_bindings(spin_world::spin::grpc::grpc::add_to_linker)?;
+        ctx.link_bindings(spin_world::spin::http::early_hints::add_to_linker)?;
         wasi::add_to_linker::<T>(&mut ctx)?;
         Ok(())
     }
 
     fn configure_app<T: RuntimeFactors>(
         &self,
-        _ctx: ConfigureAppContext<T, Self>,
+        mut ctx: ConfigureAppContext<T, Self>,
     ) -> anyhow::Result<Self::AppState> {
-        Ok(())
+        let config = ctx.take_runtime_config().unwrap_or_default();
+        Ok(AppState {
+            graphql_cache: Default::default(),
+            single_flight: Arc::new(single_flight::SingleFlight::new(&config.single_flight)?),
+        })
     }
 
     fn prepare<T: RuntimeFactors>(
@@ -75,27 +98,64 @@ impl Factor for OutboundHttpFactor {
         let outbound_networking = ctx.instance_builder::<OutboundNetworkingFactor>()?;
         let allowed_hosts = outbound_networking.allowed_hosts();
         let component_tls_configs = outbound_networking.component_tls_configs().clone();
+        let proxy_config = outbound_networking.proxy_config().clone();
         Ok(InstanceState {
             wasi_http_ctx: WasiHttpCtx::new(),
             allowed_hosts,
             allow_private_ips: self.allow_private_ips,
             component_tls_configs,
+            proxy_config,
             self_request_origin: None,
-            request_interceptor: None,
+            request_interceptor: self.request_interceptor.clone(),
+            instance_request_interceptor: None,
+            early_hints_sender: None,
             spin_http_client: None,
+            grpc_streams: spin_resource_table::Table::new(1024),
+            graphql_cache: ctx.app_state().graphql_cache.clone(),
+            single_flight: ctx.app_state().single_flight.clone(),
+            component_id: ctx.app_component().id().into(),
         })
     }
 }
 
+/// The `[outbound_http]` runtime config section.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RuntimeConfig {
+    /// The origins (e.g. `https://api.example.com`) to which concurrent
+    /// identical GET requests are collapsed into one upstream request, whose
+    /// response is shared. Shared responses are read into memory, so those
+    /// with bodies larger than 16 MiB fail.
+    pub single_flight: Vec<String>,
+}
+
+pub struct AppState {
+    /// The in-flight and cached 'spin:graphql' queries of the app's components.
+    graphql_cache: Arc<graphql::GraphqlCache>,
+    /// The in-flight requests to single-flight destinations.
+    single_flight: Arc<single_flight::SingleFlight>,
+}
+
 pub struct InstanceState {
     wasi_http_ctx: WasiHttpCtx,
     allowed_hosts: OutboundAllowedHosts,
     allow_private_ips: bool,
     component_tls_configs: ComponentTlsConfigs,
+    proxy_config: Arc<ProxyConfig>,
     self_request_origin: Option<SelfRequestOrigin>,
     request_interceptor: Option<Arc<dyn OutboundHttpInterceptor>>,
-    // Connection-pooling client for 'fermyon:spin/http' interface
+    // Set with `set_request_interceptor`; runs before `request_interceptor`
+    instance_request_interceptor: Option<Arc<dyn OutboundHttpInterceptor>>,
+    early_hints_sender: Option<Arc<dyn EarlyHintsSender>>,
+    // Connection-pooling client for 'fermyon:spin/http' and 'spin:graphql' interfaces
     spin_http_client: Option<reqwest::Client>,
+    // The responses of 'spin:grpc' server-streaming calls
+    grpc_streams: spin_resource_table::Table<grpc::GrpcStream>,
+    // The app's in-flight and cached 'spin:graphql' queries
+    graphql_cache: Arc<graphql::GraphqlCache>,
+    // The app's in-flight requests to single-flight destinations
+    single_flight: Arc<single_flight::SingleFlight>,
+    component_id: Arc<str>,
 }
 
 impl InstanceState {
@@ -107,6 +167,19 @@ impl InstanceState {
         self.self_request_origin = Some(origin);
     }
 
+    /// Sets the [`EarlyHintsSender`] for the request this instance handles.
+    ///
+    /// If unset, 'spin:http/early-hints' calls fail as unsupported.
+    pub fn set_early_hints_sender(&mut self, sender: impl EarlyHintsSender + 'static) {
+        self.early_hints_sender = Some(Arc::new(sender));
+    }
+
+    /// Removes any [`EarlyHintsSender`], for an instance which handles another
+    /// request, to which hints can't be sent.
+    pub fn clear_early_hints_sender(&mut self) {
+        self.early_hints_sender = None;
+    }
+
     /// Sets a [`OutboundHttpInterceptor`] for this instance.
     ///
     /// Returns an error if it has already been called for this instance.
@@ -114,12 +187,53 @@ impl InstanceState {
         &mut self,
         interceptor: impl OutboundHttpInterceptor + 'static,
     ) -> anyhow::Result<()> {
-        if self.request_interceptor.is_some() {
+        if self.instance_request_interceptor.is_some() {
             anyhow::bail!("set_request_interceptor can only be called once");
         }
-        self.request_interceptor = Some(Arc::new(interceptor));
+        self.instance_request_interceptor = Some(Arc::new(interceptor));
         Ok(())
     }
+
+    /// Replaces the [`OutboundHttpInterceptor`] set with
+    /// [`InstanceState::set_request_interceptor`], for an instance which
+    /// handles another request.
+    pub fn replace_request_interceptor(
+        &mut self,
+        interceptor: impl OutboundHttpInterceptor + 'static,
+    ) {
+        self.instance_request_interceptor = Some(Arc::new(interceptor));
+    }
+
+    /// Adds an [`OutboundHttpInterceptor`] for this instance.
+    ///
+    /// Unlike [`InstanceState::set_request_interceptor`], this may be called
+    /// any number of times. Requests pass through interceptors in the reverse
+    /// of the order they were added, so an interceptor added here runs after
+    /// any set with [`InstanceState::set_request_interceptor`].
+    pub fn add_request_interceptor(&mut self, interceptor: impl OutboundHttpInterceptor + 'static) {
+        let interceptor: Arc<dyn OutboundHttpInterceptor> = Arc::new(interceptor);
+        self.request_interceptor = Some(match self.request_interceptor.take() {
+            Some(next) => Arc::new(intercept::ChainedInterceptor {
+                first: interceptor,
+                second: next,
+            }),
+            None => interceptor,
+        });
+    }
+
+    /// The interceptors requests pass through, chained.
+    fn request_interceptor(&self) -> Option<Arc<dyn OutboundHttpInterceptor>> {
+        match (
+            &self.instance_request_interceptor,
+            &self.request_interceptor,
+        ) {
+            (Some(first), Some(second)) => Some(Arc::new(intercept::ChainedInterceptor {
+                first: first.clone(),
+                second: second.clone(),
+            })),
+            (first, second) => first.clone().or_else(|| second.clone()),
+        }
+    }
 }
 
 impl SelfInstanceBuilder for InstanceState {}
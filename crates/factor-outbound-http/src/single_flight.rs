@@ -0,0 +1,362 @@
+use std::{collections::HashMap, future::Future, sync::Mutex, time::Duration};
+
+use anyhow::{ensure, Context};
+use bytes::{Bytes, BytesMut};
+use futures::{
+    future::{BoxFuture, Shared},
+    FutureExt,
+};
+use http::{uri::Scheme, HeaderMap, Method, Request, Response, StatusCode, Uri, Version};
+use http_body_util::{BodyExt, Full};
+use sha2::{Digest, Sha256};
+use tokio::time::timeout;
+use wasmtime_wasi_http::{bindings::http::types::ErrorCode, types::IncomingResponse};
+
+/// Headers which differ between otherwise identical requests, as they carry
+/// the trace context of the instance sending them.
+const TRACE_CONTEXT_HEADERS: &[&str] = &["traceparent", "tracestate", "b3"];
+
+/// The largest response body which is read to be shared. Responses with
+/// larger bodies fail, rather than being held in memory.
+const MAX_SHARED_BODY_BYTES: usize = 16 * 1024 * 1024;
+
+/// Identifies requests which get the same response.
+pub(crate) type Key = [u8; 32];
+
+type InFlight = Shared<BoxFuture<'static, Result<SharedResponse, ErrorCode>>>;
+
+/// Collapses concurrent identical GET requests to the destinations configured
+/// for single-flight into one upstream request, whose response is shared.
+///
+/// Only requests from instances of the same component are collapsed, as they
+/// share the component's TLS and networking settings.
+pub(crate) struct SingleFlight {
+    destinations: Vec<Destination>,
+    in_flight: Mutex<HashMap<Key, InFlight>>,
+}
+
+impl SingleFlight {
+    /// Creates a `SingleFlight` for the given destinations, which are origins
+    /// like `https://api.example.com`.
+    pub(crate) fn new(destinations: &[String]) -> anyhow::Result<Self> {
+        let destinations = destinations
+            .iter()
+            .map(|destination| Destination::parse(destination))
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Self {
+            destinations,
+            in_flight: Default::default(),
+        })
+    }
+
+    /// Returns the key of `request`, if it should be collapsed with identical
+    /// requests.
+    pub(crate) fn key<B>(&self, component_id: &str, request: &Request<B>) -> Option<Key> {
+        if request.method() != Method::GET
+            || !self.destinations.iter().any(|d| d.matches(request.uri()))
+        {
+            return None;
+        }
+        let mut headers = request
+            .headers()
+            .iter()
+            .filter(|(name, _)| !is_trace_context_header(name.as_str()))
+            .map(|(name, value)| (name.as_str(), value.as_bytes()))
+            .collect::<Vec<_>>();
+        headers.sort();
+
+        let mut hasher = Sha256::new();
+        for part in [
+            component_id.as_bytes(),
+            request.uri().to_string().as_bytes(),
+        ] {
+            hasher.update((part.len() as u64).to_le_bytes());
+            hasher.update(part);
+        }
+        for (name, value) in headers {
+            hasher.update((name.len() as u64).to_le_bytes());
+            hasher.update(name);
+            hasher.update((value.len() as u64).to_le_bytes());
+            hasher.update(value);
+        }
+        Some(hasher.finalize().into())
+    }
+
+    /// Sends the request for `key` with `send`, or, if it is already being
+    /// sent, waits for its response instead.
+    ///
+    /// The response body is read in full before it is shared, so responses
+    /// with bodies larger than [`MAX_SHARED_BODY_BYTES`] fail.
+    pub(crate) async fn send(
+        &self,
+        key: Key,
+        between_bytes_timeout: Duration,
+        send: impl Future<Output = Result<IncomingResponse, ErrorCode>> + Send + 'static,
+    ) -> Result<IncomingResponse, ErrorCode> {
+        let in_flight = self
+            .in_flight
+            .lock()
+            .unwrap()
+            .entry(key)
+            .or_insert_with(|| SharedResponse::read(send).boxed().shared())
+            .clone();
+        let result = in_flight.clone().await;
+        let mut all_in_flight = self.in_flight.lock().unwrap();
+        if all_in_flight
+            .get(&key)
+            .is_some_and(|other| other.ptr_eq(&in_flight))
+        {
+            all_in_flight.remove(&key);
+        }
+        drop(all_in_flight);
+        Ok(result?.into_incoming_response(between_bytes_timeout))
+    }
+}
+
+fn is_trace_context_header(name: &str) -> bool {
+    TRACE_CONTEXT_HEADERS.contains(&name) || name.starts_with("x-b3-")
+}
+
+/// A destination whose requests are collapsed.
+#[derive(Debug)]
+struct Destination {
+    scheme: Scheme,
+    host: String,
+    port: u16,
+}
+
+impl Destination {
+    fn parse(destination: &str) -> anyhow::Result<Self> {
+        let uri: Uri = destination
+            .parse()
+            .with_context(|| format!("invalid single-flight destination {destination:?}"))?;
+        ensure!(
+            matches!(
+                uri.path_and_query().map(|paq| paq.as_str()),
+                None | Some("/")
+            ),
+            "invalid single-flight destination {destination:?}; destinations may not have a path"
+        );
+        let (Some(scheme), Some(host)) = (uri.scheme(), uri.host()) else {
+            anyhow::bail!(
+                "invalid single-flight destination {destination:?}; expected e.g. 'https://api.example.com'"
+            );
+        };
+        Ok(Self {
+            scheme: scheme.clone(),
+            host: host.to_ascii_lowercase(),
+            port: port_or_default(&uri)
+                .context("single-flight destinations must be http or https")?,
+        })
+    }
+
+    fn matches(&self, uri: &Uri) -> bool {
+        uri.scheme() == Some(&self.scheme)
+            && uri
+                .host()
+                .is_some_and(|host| host.eq_ignore_ascii_case(&self.host))
+            && port_or_default(uri) == Some(self.port)
+    }
+}
+
+fn port_or_default(uri: &Uri) -> Option<u16> {
+    uri.port_u16().or(match uri.scheme()? {
+        scheme if *scheme == Scheme::HTTPS => Some(443),
+        scheme if *scheme == Scheme::HTTP => Some(80),
+        _ => None,
+    })
+}
+
+/// A response which has been read in full, so that it can be shared.
+#[derive(Clone)]
+struct SharedResponse {
+    status: StatusCode,
+    version: Version,
+    headers: HeaderMap,
+    body: Bytes,
+}
+
+impl SharedResponse {
+    async fn read(
+        send: impl Future<Output = Result<IncomingResponse, ErrorCode>>,
+    ) -> Result<Self, ErrorCode> {
+        // The worker driving the connection must outlive the reading of the body.
+        let IncomingResponse {
+            resp,
+            worker: _worker,
+            between_bytes_timeout,
+        } = send.await?;
+        let (parts, mut body) = resp.into_parts();
+        let too_large = |size: usize| ErrorCode::HttpResponseBodySize(Some(size as u64));
+        let content_length = parts
+            .headers
+            .get(http::header::CONTENT_LENGTH)
+            .and_then(|value| value.to_str().ok()?.parse::<usize>().ok());
+        if let Some(content_length) = content_length {
+            if content_length > MAX_SHARED_BODY_BYTES {
+                return Err(too_large(content_length));
+            }
+        }
+        let mut bytes = BytesMut::new();
+        while let Some(frame) = timeout(between_bytes_timeout, body.frame())
+            .await
+            .map_err(|_| ErrorCode::ConnectionReadTimeout)?
+        {
+            if let Ok(data) = frame?.into_data() {
+                if bytes.len() + data.len() > MAX_SHARED_BODY_BYTES {
+                    return Err(too_large(bytes.len() + data.len()));
+                }
+                bytes.extend_from_slice(&data);
+            }
+        }
+        Ok(Self {
+            status: parts.status,
+            version: parts.version,
+            headers: parts.headers,
+            body: bytes.freeze(),
+        })
+    }
+
+    fn into_incoming_response(self, between_bytes_timeout: Duration) -> IncomingResponse {
+        let mut resp = Response::new(Full::new(self.body).map_err(|err| match err {}).boxed());
+        *resp.status_mut() = self.status;
+        *resp.version_mut() = self.version;
+        *resp.headers_mut() = self.headers;
+        IncomingResponse {
+            resp,
+            worker: None,
+            between_bytes_timeout,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use super::*;
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    fn single_flight() -> SingleFlight {
+        SingleFlight::new(&["https://api.example.com".into()]).unwrap()
+    }
+
+    fn get(uri: &str, headers: &[(&str, &str)]) -> Request<()> {
+        let mut builder = Request::get(uri);
+        for (name, value) in headers {
+            builder = builder.header(*name, *value);
+        }
+        builder.body(()).unwrap()
+    }
+
+    #[test]
+    fn only_gets_to_configured_destinations_are_collapsed() {
+        let single_flight = single_flight();
+        assert!(single_flight
+            .key("c", &get("https://API.example.com:443/a?b", &[]))
+            .is_some());
+        assert!(single_flight
+            .key("c", &get("http://api.example.com/a", &[]))
+            .is_none());
+        assert!(single_flight
+            .key("c", &get("https://other.example.com/a", &[]))
+            .is_none());
+        let post = Request::post("https://api.example.com/a").body(()).unwrap();
+        assert!(single_flight.key("c", &post).is_none());
+    }
+
+    #[test]
+    fn keys_ignore_trace_context_and_header_order() {
+        let single_flight = single_flight();
+        let key = |component, uri, headers| single_flight.key(component, &get(uri, headers));
+        let url = "https://api.example.com/a";
+        let base = key("c", url, &[("accept", "a"), ("x-api-key", "k")]);
+        assert_eq!(
+            base,
+            key(
+                "c",
+                url,
+                &[("x-api-key", "k"), ("traceparent", "t"), ("accept", "a")]
+            )
+        );
+        assert_ne!(base, key("c", url, &[("accept", "a"), ("x-api-key", "j")]));
+        assert_ne!(base, key("d", url, &[("accept", "a"), ("x-api-key", "k")]));
+        assert_ne!(
+            base,
+            key(
+                "c",
+                "https://api.example.com/b",
+                &[("accept", "a"), ("x-api-key", "k")]
+            )
+        );
+    }
+
+    #[test]
+    fn destinations_must_be_origins() {
+        assert!(SingleFlight::new(&["https://api.example.com/".into()]).is_ok());
+        assert!(SingleFlight::new(&["https://api.example.com/v1".into()]).is_err());
+        assert!(SingleFlight::new(&["api.example.com".into()]).is_err());
+        assert!(SingleFlight::new(&["ftp://api.example.com".into()]).is_err());
+    }
+
+    #[tokio::test]
+    async fn concurrent_requests_share_one_response() {
+        let single_flight = Arc::new(single_flight());
+        let sent = Arc::new(AtomicUsize::new(0));
+        let (release, released) = tokio::sync::oneshot::channel::<()>();
+        let released = released.shared();
+
+        let requests = (0..3).map(|_| {
+            let sent = sent.clone();
+            let released = released.clone();
+            let single_flight = single_flight.clone();
+            tokio::spawn(async move {
+                let send = async move {
+                    sent.fetch_add(1, Ordering::SeqCst);
+                    let _ = released.await;
+                    let body = Full::new(Bytes::from_static(b"shared"))
+                        .map_err(|err| match err {})
+                        .boxed();
+                    Ok(IncomingResponse {
+                        resp: Response::new(body),
+                        worker: None,
+                        between_bytes_timeout: TIMEOUT,
+                    })
+                };
+                let resp = single_flight.send([0; 32], TIMEOUT, send).await.unwrap();
+                resp.resp.into_body().collect().await.unwrap().to_bytes()
+            })
+        });
+        let requests = requests.collect::<Vec<_>>();
+        tokio::task::yield_now().await;
+        release.send(()).unwrap();
+
+        for request in requests {
+            assert_eq!(request.await.unwrap(), "shared");
+        }
+        assert_eq!(sent.load(Ordering::SeqCst), 1);
+        assert!(single_flight.in_flight.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn large_responses_are_not_shared() {
+        let single_flight = single_flight();
+        let send = async {
+            let body = Full::new(Bytes::from(vec![0; MAX_SHARED_BODY_BYTES + 1]))
+                .map_err(|err| match err {})
+                .boxed();
+            Ok(IncomingResponse {
+                resp: Response::new(body),
+                worker: None,
+                between_bytes_timeout: TIMEOUT,
+            })
+        };
+        let result = single_flight.send([0; 32], TIMEOUT, send).await;
+        assert!(matches!(result, Err(ErrorCode::HttpResponseBodySize(_))));
+        assert!(single_flight.in_flight.lock().unwrap().is_empty());
+    }
+}
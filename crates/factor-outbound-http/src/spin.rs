@@ -1,4 +1,7 @@
-use http_body_util::BodyExt;
+use std::sync::Arc;
+
+use http_body_util::{BodyExt, Full};
+use spin_factor_outbound_networking::ProxyConfig;
 use spin_world::{
     async_trait,
     v1::{
@@ -8,7 +11,27 @@ use spin_world::{
 };
 use tracing::{field::Empty, instrument, Level, Span};
 
-use crate::intercept::InterceptOutcome;
+use crate::intercept::{envelope, InterceptOutcome};
+
+/// Builds the client for 'fermyon:spin/http' and 'spin:graphql' requests, which
+/// connects through any configured proxies.
+pub(crate) fn spin_http_client(
+    proxy_config: &Arc<ProxyConfig>,
+) -> Result<reqwest::Client, HttpError> {
+    let mut builder = reqwest::Client::builder();
+    // Adding any proxy disables reqwest's proxies from the environment.
+    if !proxy_config.is_empty() {
+        let proxy_config = proxy_config.clone();
+        builder = builder.proxy(reqwest::Proxy::custom(move |url| {
+            let proxy = proxy_config.proxy_for(url.host_str()?)?;
+            Some(proxy.url().clone())
+        }));
+    }
+    builder.build().map_err(|err| {
+        tracing::error!("Error building outbound HTTP client: {err}");
+        HttpError::RuntimeError
+    })
+}
 
 #[async_trait]
 impl spin_http::Host for crate::InstanceState {
@@ -75,11 +98,13 @@ impl spin_http::Host for crate::InstanceState {
 
         spin_telemetry::inject_trace_context(req.headers_mut());
 
-        if let Some(interceptor) = &self.request_interceptor {
+        let mut response_interceptor = None;
+        if let Some(interceptor) = self.request_interceptor() {
             let intercepted_request = std::mem::take(&mut req).into();
             match interceptor.intercept(intercepted_request).await {
                 Ok(InterceptOutcome::Continue(intercepted_request)) => {
                     req = intercepted_request.into_vec_request().unwrap();
+                    response_interceptor = Some((interceptor.clone(), envelope(&req)));
                 }
                 Ok(InterceptOutcome::Complete(resp)) => return response_from_hyper(resp).await,
                 Err(err) => {
@@ -94,13 +119,28 @@ impl spin_http::Host for crate::InstanceState {
 
         // Allow reuse of Client's internal connection pool for multiple requests
         // in a single component execution
-        let client = self.spin_http_client.get_or_insert_with(Default::default);
+        if self.spin_http_client.is_none() {
+            self.spin_http_client = Some(spin_http_client(&self.proxy_config)?);
+        }
+        let client = self.spin_http_client.as_ref().unwrap();
 
         let resp = client.execute(req).await.map_err(log_reqwest_error)?;
 
         tracing::trace!("Returning response from outbound request to {req_url}");
         span.record("http.response.status_code", resp.status().as_u16());
-        response_from_reqwest(resp).await
+        match response_interceptor {
+            Some((interceptor, req)) => {
+                let resp = hyper_response_from_reqwest(resp).await?;
+                match interceptor.intercept_response(&req, resp).await {
+                    Ok(resp) => response_from_hyper(resp).await,
+                    Err(err) => {
+                        tracing::error!("Error in outbound HTTP interceptor: {err}");
+                        Err(HttpError::RuntimeError)
+                    }
+                }
+            }
+            None => response_from_reqwest(resp).await,
+        }
     }
 }
 
@@ -206,6 +246,17 @@ async fn response_from_reqwest(res: reqwest::Response) -> Result<Response, HttpE
     })
 }
 
+async fn hyper_response_from_reqwest(res: reqwest::Response) -> Result<crate::Response, HttpError> {
+    let mut builder = http::Response::builder().status(res.status());
+    if let Some(headers) = builder.headers_mut() {
+        *headers = res.headers().clone();
+    }
+    let body = res.bytes().await.map_err(|_| HttpError::RuntimeError)?;
+    builder
+        .body(Full::new(body).map_err(|err| match err {}).boxed())
+        .map_err(|_| HttpError::RuntimeError)
+}
+
 fn headers_from_map(map: &http::HeaderMap) -> Vec<(String, String)> {
     map.iter()
         .filter_map(|(key, val)| {
@@ -1,11 +1,16 @@
-use std::{error::Error, net::IpAddr, sync::Arc};
+use std::{
+    error::Error,
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+    time::Duration,
+};
 
 use anyhow::Context;
 use http::{header::HOST, Request};
 use http_body_util::BodyExt;
 use ip_network::IpNetwork;
 use rustls::ClientConfig;
-use spin_factor_outbound_networking::{ComponentTlsConfigs, OutboundAllowedHosts};
+use spin_factor_outbound_networking::{ComponentTlsConfigs, OutboundAllowedHosts, ProxyConfig};
 use spin_factors::{wasmtime::component::ResourceTable, RuntimeFactorsInstanceState};
 use tokio::{net::TcpStream, time::timeout};
 use tracing::{field::Empty, instrument, Instrument};
@@ -19,6 +24,7 @@ use wasmtime_wasi_http::{
 
 use crate::{
     intercept::{InterceptOutcome, OutboundHttpInterceptor},
+    single_flight::SingleFlight,
     wasi_2023_10_18, wasi_2023_11_10, InstanceState, OutboundHttpFactor, SelfRequestOrigin,
 };
 
@@ -94,9 +100,12 @@ impl<'a> WasiHttpView for WasiHttpImplInner<'a> {
                     config,
                     self.state.allowed_hosts.clone(),
                     self.state.component_tls_configs.clone(),
-                    self.state.request_interceptor.clone(),
+                    self.state.proxy_config.clone(),
+                    self.state.request_interceptor(),
                     self.state.self_request_origin.clone(),
                     self.state.allow_private_ips,
+                    self.state.single_flight.clone(),
+                    self.state.component_id.clone(),
                 )
                 .in_current_span(),
             ),
@@ -104,14 +113,18 @@ impl<'a> WasiHttpView for WasiHttpImplInner<'a> {
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn send_request_impl(
     mut request: Request<wasmtime_wasi_http::body::HyperOutgoingBody>,
     mut config: wasmtime_wasi_http::types::OutgoingRequestConfig,
     outbound_allowed_hosts: OutboundAllowedHosts,
     component_tls_configs: ComponentTlsConfigs,
+    proxy_config: Arc<ProxyConfig>,
     request_interceptor: Option<Arc<dyn OutboundHttpInterceptor>>,
     self_request_origin: Option<SelfRequestOrigin>,
     allow_private_ips: bool,
+    single_flight: Arc<SingleFlight>,
+    component_id: Arc<str>,
 ) -> anyhow::Result<Result<IncomingResponse, ErrorCode>> {
     // wasmtime-wasi-http fills in scheme and authority for relative URLs
     // (e.g. https://:443/<path>), which makes them hard to reason about.
@@ -132,10 +145,12 @@ async fn send_request_impl(
 
     spin_telemetry::inject_trace_context(&mut request);
 
+    let mut response_interceptor = None;
     if let Some(interceptor) = request_interceptor {
         let intercept_request = std::mem::take(&mut request).into();
         match interceptor.intercept(intercept_request).await? {
             InterceptOutcome::Continue(req) => {
+                response_interceptor = Some((interceptor, req.envelope()));
                 request = req.into_hyper_request();
             }
             InterceptOutcome::Complete(resp) => {
@@ -190,12 +205,47 @@ async fn send_request_impl(
         span.record("server.port", port.as_u16());
     }
 
-    Ok(send_request_handler(request, config, tls_client_config, allow_private_ips).await)
+    let resp = match single_flight.key(&component_id, &request) {
+        Some(key) => {
+            let between_bytes_timeout = config.between_bytes_timeout;
+            let send = async move {
+                send_request_handler(
+                    request,
+                    config,
+                    tls_client_config,
+                    &proxy_config,
+                    allow_private_ips,
+                )
+                .await
+            };
+            single_flight
+                .send(key, between_bytes_timeout, send.in_current_span())
+                .await
+        }
+        None => {
+            send_request_handler(
+                request,
+                config,
+                tls_client_config,
+                &proxy_config,
+                allow_private_ips,
+            )
+            .await
+        }
+    };
+    match (resp, response_interceptor) {
+        (Ok(mut resp), Some((interceptor, request))) => {
+            resp.resp = interceptor.intercept_response(&request, resp.resp).await?;
+            Ok(Ok(resp))
+        }
+        (resp, _) => Ok(resp),
+    }
 }
 
 /// This is a fork of wasmtime_wasi_http::default_send_request_handler function
 /// forked from bytecodealliance/wasmtime commit-sha 29a76b68200fcfa69c8fb18ce6c850754279a05b
-/// This fork provides the ability to configure client cert auth for mTLS
+/// This fork provides the ability to configure client cert auth for mTLS and
+/// to connect through outbound proxies
 async fn send_request_handler(
     mut request: http::Request<HyperOutgoingBody>,
     wasmtime_wasi_http::types::OutgoingRequestConfig {
@@ -205,6 +255,7 @@ async fn send_request_handler(
         between_bytes_timeout,
     }: wasmtime_wasi_http::types::OutgoingRequestConfig,
     tls_client_config: Arc<ClientConfig>,
+    proxy_config: &ProxyConfig,
     allow_private_ips: bool,
 ) -> Result<wasmtime_wasi_http::types::IncomingResponse, ErrorCode> {
     let authority_str = if let Some(authority) = request.uri().authority() {
@@ -218,27 +269,15 @@ async fn send_request_handler(
         return Err(ErrorCode::HttpRequestUriInvalid);
     };
 
-    // Resolve the authority to IP addresses
-    let mut socket_addrs = tokio::net::lookup_host(&authority_str)
-        .await
-        .map_err(|_| dns_error("address not available".into(), 0))?
-        .collect::<Vec<_>>();
-
-    // Potentially filter out private IPs
-    if !allow_private_ips && !socket_addrs.is_empty() {
-        socket_addrs.retain(|addr| !is_private_ip(addr.ip()));
-        if socket_addrs.is_empty() {
-            return Err(ErrorCode::DestinationIpProhibited);
-        }
-    }
-
-    let tcp_stream = timeout(connect_timeout, TcpStream::connect(socket_addrs.as_slice()))
-        .await
-        .map_err(|_| ErrorCode::ConnectionTimeout)?
-        .map_err(|err| match err.kind() {
-            std::io::ErrorKind::AddrNotAvailable => dns_error("address not available".into(), 0),
-            _ => ErrorCode::ConnectionRefused,
-        })?;
+    let tcp_stream = connect_tcp(
+        request.uri(),
+        &authority_str,
+        use_tls,
+        connect_timeout,
+        proxy_config,
+        allow_private_ips,
+    )
+    .await?;
 
     let (mut sender, worker) = if use_tls {
         #[cfg(any(target_arch = "riscv64", target_arch = "s390x"))]
@@ -336,6 +375,81 @@ async fn send_request_handler(
     })
 }
 
+/// Opens a TCP connection to `authority_str`, the `host:port` of `uri`,
+/// through a proxy if one is configured for the host.
+pub(crate) async fn connect_tcp(
+    uri: &http::Uri,
+    authority_str: &str,
+    use_tls: bool,
+    connect_timeout: Duration,
+    proxy_config: &ProxyConfig,
+    allow_private_ips: bool,
+) -> Result<TcpStream, ErrorCode> {
+    let host = uri.host().unwrap_or_default();
+    let stream = if let Some(proxy) = proxy_config.proxy_for(host) {
+        // With private IPs blocked, host names are resolved here rather than
+        // by the proxy, so that the address the proxy connects to is checked.
+        let host = if allow_private_ips {
+            host.to_owned()
+        } else {
+            resolve(authority_str, allow_private_ips).await?[0]
+                .ip()
+                .to_string()
+        };
+        let port = uri.port_u16().unwrap_or(if use_tls { 443 } else { 80 });
+        timeout(connect_timeout, proxy.connect(&host, port))
+            .await
+            .map_err(|_| ErrorCode::ConnectionTimeout)?
+            .map_err(|err| {
+                tracing::warn!("proxy connection error: {err}");
+                match spin_errors::ErrorKind::classify(&err) {
+                    spin_errors::ErrorKind::Timeout => ErrorCode::ConnectionTimeout,
+                    _ => ErrorCode::ConnectionRefused,
+                }
+            })?
+    } else {
+        let socket_addrs = resolve(authority_str, allow_private_ips).await?;
+        timeout(connect_timeout, TcpStream::connect(socket_addrs.as_slice()))
+            .await
+            .map_err(|_| ErrorCode::ConnectionTimeout)?
+            .map_err(|err| match err.kind() {
+                std::io::ErrorKind::AddrNotAvailable => {
+                    dns_error("address not available".into(), 0)
+                }
+                _ => match spin_errors::ErrorKind::classify(&err) {
+                    spin_errors::ErrorKind::Timeout => ErrorCode::ConnectionTimeout,
+                    spin_errors::ErrorKind::NotAllowed => ErrorCode::DestinationIpProhibited,
+                    _ => ErrorCode::ConnectionRefused,
+                },
+            })?
+    };
+    Ok(stream)
+}
+
+/// Resolves `authority_str` to socket addresses, leaving out private IPs
+/// unless they are allowed.
+async fn resolve(
+    authority_str: &str,
+    allow_private_ips: bool,
+) -> Result<Vec<SocketAddr>, ErrorCode> {
+    let mut socket_addrs = tokio::net::lookup_host(authority_str)
+        .await
+        .map_err(|_| dns_error("address not available".into(), 0))?
+        .collect::<Vec<_>>();
+    if socket_addrs.is_empty() {
+        return Err(dns_error("address not available".into(), 0));
+    }
+
+    // Potentially filter out private IPs
+    if !allow_private_ips {
+        socket_addrs.retain(|addr| !is_private_ip(addr.ip()));
+        if socket_addrs.is_empty() {
+            return Err(ErrorCode::DestinationIpProhibited);
+        }
+    }
+    Ok(socket_addrs)
+}
+
 /// Translate a [`hyper::Error`] to a wasi-http `ErrorCode` in the context of a request.
 fn hyper_request_error(err: hyper::Error) -> ErrorCode {
     // If there's a source, we might be able to extract a wasi-http error from it.
@@ -347,7 +461,14 @@ fn hyper_request_error(err: hyper::Error) -> ErrorCode {
 
     tracing::warn!("hyper request error: {err:?}");
 
-    ErrorCode::HttpProtocolError
+    if err.is_timeout() {
+        return ErrorCode::ConnectionReadTimeout;
+    }
+    match spin_errors::ErrorKind::classify(&err) {
+        spin_errors::ErrorKind::Timeout => ErrorCode::ConnectionReadTimeout,
+        spin_errors::ErrorKind::Connection => ErrorCode::ConnectionTerminated,
+        _ => ErrorCode::HttpProtocolError,
+    }
 }
 
 fn dns_error(rcode: String, info_code: u16) -> ErrorCode {
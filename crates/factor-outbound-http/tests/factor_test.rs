@@ -1,12 +1,17 @@
 use std::time::Duration;
 
 use anyhow::bail;
-use http::{Request, Uri};
-use spin_factor_outbound_http::{OutboundHttpFactor, SelfRequestOrigin};
+use http::{Request, Response, StatusCode, Uri};
+use http_body_util::{BodyExt, Empty};
+use spin_factor_outbound_http::{
+    intercept::{InterceptOutcome, InterceptRequest, OutboundHttpInterceptor},
+    HttpResult, OutboundHttpFactor, SelfRequestOrigin,
+};
 use spin_factor_outbound_networking::OutboundNetworkingFactor;
 use spin_factor_variables::VariablesFactor;
 use spin_factors::{anyhow, RuntimeFactors};
 use spin_factors_test::{toml, TestEnvironment};
+use spin_world::async_trait;
 use wasmtime_wasi::Subscribe;
 use wasmtime_wasi_http::{
     bindings::http::types::ErrorCode, types::OutgoingRequestConfig, WasiHttpView,
@@ -104,14 +109,84 @@ async fn disallowed_private_ips_fails() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+async fn factor_interceptor_runs_after_instance_interceptor() -> anyhow::Result<()> {
+    let mut http = OutboundHttpFactor::new(true);
+    http.set_request_interceptor(RespondWith(StatusCode::IM_A_TEAPOT));
+    let mut state = test_instance_state_with_factor("https://*", http).await?;
+    state.http.set_request_interceptor(PassThrough)?;
+
+    let mut wasi_http = OutboundHttpFactor::get_wasi_http_impl(&mut state).unwrap();
+    let req = Request::get("https://[100::1]:443").body(Default::default())?;
+    let mut future_resp = wasi_http.send_request(req, test_request_config())?;
+    future_resp.ready().await;
+
+    match future_resp.unwrap_ready().unwrap() {
+        Ok(resp) => assert_eq!(resp.resp.status(), StatusCode::IM_A_TEAPOT),
+        Err(err) => bail!("expected Ok, got {err:?}"),
+    };
+    Ok(())
+}
+
+#[tokio::test]
+async fn instance_interceptor_can_be_replaced() -> anyhow::Result<()> {
+    let mut state = test_instance_state("https://*", true).await?;
+    state
+        .http
+        .set_request_interceptor(RespondWith(StatusCode::IM_A_TEAPOT))?;
+    assert!(state.http.set_request_interceptor(PassThrough).is_err());
+    state
+        .http
+        .replace_request_interceptor(RespondWith(StatusCode::ACCEPTED));
+
+    let mut wasi_http = OutboundHttpFactor::get_wasi_http_impl(&mut state).unwrap();
+    let req = Request::get("https://[100::1]:443").body(Default::default())?;
+    let mut future_resp = wasi_http.send_request(req, test_request_config())?;
+    future_resp.ready().await;
+
+    match future_resp.unwrap_ready().unwrap() {
+        Ok(resp) => assert_eq!(resp.resp.status(), StatusCode::ACCEPTED),
+        Err(err) => bail!("expected Ok, got {err:?}"),
+    };
+    Ok(())
+}
+
+struct PassThrough;
+
+#[async_trait]
+impl OutboundHttpInterceptor for PassThrough {
+    async fn intercept(&self, request: InterceptRequest) -> HttpResult<InterceptOutcome> {
+        Ok(InterceptOutcome::Continue(request))
+    }
+}
+
+struct RespondWith(StatusCode);
+
+#[async_trait]
+impl OutboundHttpInterceptor for RespondWith {
+    async fn intercept(&self, _request: InterceptRequest) -> HttpResult<InterceptOutcome> {
+        let body = Empty::new().map_err(|err| match err {}).boxed();
+        let resp = Response::builder().status(self.0).body(body).unwrap();
+        Ok(InterceptOutcome::Complete(resp))
+    }
+}
+
 async fn test_instance_state(
     allowed_outbound_hosts: &str,
     allow_private_ips: bool,
+) -> anyhow::Result<TestFactorsInstanceState> {
+    let http = OutboundHttpFactor::new(allow_private_ips);
+    test_instance_state_with_factor(allowed_outbound_hosts, http).await
+}
+
+async fn test_instance_state_with_factor(
+    allowed_outbound_hosts: &str,
+    http: OutboundHttpFactor,
 ) -> anyhow::Result<TestFactorsInstanceState> {
     let factors = TestFactors {
         variables: VariablesFactor::default(),
         networking: OutboundNetworkingFactor::new(),
-        http: OutboundHttpFactor::new(allow_private_ips),
+        http,
     };
     let env = TestEnvironment::new(factors).extend_manifest(toml! {
         [component.test-component]
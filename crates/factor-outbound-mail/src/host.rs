@@ -0,0 +1,183 @@
+use spin_factors::{
+    anyhow,
+    wasmtime::component::{Resource, ResourceTable},
+    InitContext,
+};
+use spin_world::{
+    async_trait,
+    spin::mail::send::{self, Error, Message, Server},
+};
+use tracing::{field::Empty, instrument, Level};
+use url::Url;
+use wasmtime_wasi::{HostInputStream, InputStream, StreamError, Subscribe};
+
+use crate::{
+    message::{Attachment, OutgoingMessage},
+    smtp::{Base64Lines, Session, SmtpServer, Stream},
+    InstanceState, OutboundMailFactor,
+};
+
+/// The most bytes of attachments a message may have.
+const MAX_ATTACHMENTS_SIZE: usize = 25 * 1024 * 1024;
+
+/// The most bytes to read from an attachment stream at once.
+const READ_SIZE: usize = 64 * 1024;
+
+/// The port for `smtp` addresses without one, upgraded with STARTTLS.
+const SUBMISSION_PORT: u16 = 587;
+
+/// The port for `smtps` addresses without one.
+const SUBMISSIONS_PORT: u16 = 465;
+
+pub(crate) fn add_to_linker<T: Send + 'static>(
+    ctx: &mut InitContext<T, OutboundMailFactor>,
+) -> anyhow::Result<()> {
+    fn type_annotate<T, F>(f: F) -> F
+    where
+        F: Fn(&mut T) -> MailImpl,
+    {
+        f
+    }
+    let get_data_with_table = ctx.get_data_with_table_fn();
+    let closure = type_annotate(move |data| {
+        let (state, table) = get_data_with_table(data);
+        MailImpl { state, table }
+    });
+    send::add_to_linker_get_host(ctx.linker(), closure)
+}
+
+/// The instance state and resource table, which holds the streams of
+/// attachments.
+struct MailImpl<'a> {
+    state: &'a mut InstanceState,
+    table: &'a mut ResourceTable,
+}
+
+impl MailImpl<'_> {
+    /// Returns the server to send through, if it is allowed.
+    async fn server(&self, server: &Server) -> Result<SmtpServer, Error> {
+        let url = Url::parse(&server.address).map_err(|_| Error::AddressNotAllowed)?;
+        if !matches!(url.scheme(), "smtp" | "smtps") {
+            return Err(Error::AddressNotAllowed);
+        }
+        let allowed = self
+            .state
+            .allowed_hosts
+            .check_url(&server.address, url.scheme())
+            .await
+            .map_err(|e| Error::Other(e.to_string()))?;
+        if !allowed {
+            return Err(Error::AddressNotAllowed);
+        }
+        let host = url.host_str().ok_or(Error::AddressNotAllowed)?;
+        let span = tracing::Span::current();
+        span.record("server.address", host);
+
+        let implicit_tls = url.scheme() == "smtps";
+        let default_port = if implicit_tls {
+            SUBMISSIONS_PORT
+        } else {
+            SUBMISSION_PORT
+        };
+        let credentials = server.username.as_ref().map(|username| {
+            let password = server.password.clone().unwrap_or_default();
+            (username.clone(), password)
+        });
+        Ok(SmtpServer {
+            host: host
+                .trim_start_matches('[')
+                .trim_end_matches(']')
+                .to_owned(),
+            port: url.port().unwrap_or(default_port),
+            implicit_tls,
+            credentials,
+        })
+    }
+
+    /// Writes the attachment's stream to the session as it is read, counting
+    /// its bytes towards `total`.
+    async fn send_stream(
+        &mut self,
+        stream: Resource<InputStream>,
+        session: &mut Session<Box<dyn Stream>>,
+        total: &mut usize,
+    ) -> Result<(), Error> {
+        let mut stream = self
+            .table
+            .delete(stream)
+            .map_err(|e| Error::Other(e.to_string()))?;
+        let mut lines = Base64Lines::default();
+        loop {
+            match stream.read(READ_SIZE) {
+                Ok(bytes) if bytes.is_empty() => stream.ready().await,
+                Ok(bytes) => {
+                    *total += bytes.len();
+                    if *total > MAX_ATTACHMENTS_SIZE {
+                        return Err(Error::InvalidMessage(format!(
+                            "attachments may not be more than {MAX_ATTACHMENTS_SIZE} bytes"
+                        )));
+                    }
+                    session.write(&lines.push(&bytes)).await?;
+                }
+                Err(StreamError::Closed) => return session.write(&lines.finish()).await,
+                Err(err) => return Err(Error::Other(format!("failed to read attachment: {err}"))),
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl send::Host for MailImpl<'_> {
+    #[instrument(name = "spin_outbound_mail.send", skip_all, err(level = Level::INFO), fields(otel.kind = "client", server.address = Empty))]
+    async fn send(&mut self, server: Server, message: Message) -> Result<(), Error> {
+        let server = self.server(&server).await?;
+        let (attachments, streams): (Vec<_>, Vec<_>) = message
+            .attachments
+            .into_iter()
+            .map(|attachment| {
+                let headers = Attachment {
+                    filename: attachment.filename,
+                    content_type: attachment.content_type,
+                };
+                (headers, attachment.content)
+            })
+            .unzip();
+        let message = OutgoingMessage {
+            from: message.from,
+            to: message.to,
+            cc: message.cc,
+            bcc: message.bcc,
+            reply_to: message.reply_to,
+            subject: message.subject,
+            text: message.text,
+            html: message.html,
+            attachments,
+        }
+        .build()?;
+
+        let tls_config = self
+            .state
+            .component_tls_configs
+            .get_client_config(&server.host)
+            .clone();
+        let mut session = Session::connect(&server, tls_config).await?;
+        session.start_data(&message.envelope).await?;
+        session.write(&message.head).await?;
+        // Attachments are read as they are sent, rather than held in memory.
+        // If one fails to be read, the connection is dropped without ending
+        // the message, so the server discards it.
+        let mut total = 0;
+        for (headers, stream) in message.attachment_headers.into_iter().zip(streams) {
+            session.write(&headers).await?;
+            self.send_stream(stream, &mut session, &mut total).await?;
+        }
+        session.write(&message.tail).await?;
+        session.end_data().await?;
+        session.quit().await;
+        Ok(())
+    }
+
+    fn convert_error(&mut self, error: Error) -> anyhow::Result<Error> {
+        Ok(error)
+    }
+}
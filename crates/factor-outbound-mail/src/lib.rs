@@ -0,0 +1,58 @@
+mod host;
+mod message;
+mod smtp;
+
+use spin_factor_outbound_networking::{
+    ComponentTlsConfigs, OutboundAllowedHosts, OutboundNetworkingFactor,
+};
+use spin_factors::{
+    anyhow, ConfigureAppContext, Factor, InitContext, PrepareContext, RuntimeFactors,
+    SelfInstanceBuilder,
+};
+
+/// The [`Factor`] for `spin:mail/send`.
+#[derive(Default)]
+pub struct OutboundMailFactor {
+    _priv: (),
+}
+
+impl OutboundMailFactor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Factor for OutboundMailFactor {
+    type RuntimeConfig = ();
+    type AppState = ();
+    type InstanceBuilder = InstanceState;
+
+    fn init<T: Send + 'static>(&mut self, mut ctx: InitContext<T, Self>) -> anyhow::Result<()> {
+        host::add_to_linker(&mut ctx)
+    }
+
+    fn configure_app<T: RuntimeFactors>(
+        &self,
+        _ctx: ConfigureAppContext<T, Self>,
+    ) -> anyhow::Result<Self::AppState> {
+        Ok(())
+    }
+
+    fn prepare<T: RuntimeFactors>(
+        &self,
+        mut ctx: PrepareContext<T, Self>,
+    ) -> anyhow::Result<Self::InstanceBuilder> {
+        let networking = ctx.instance_builder::<OutboundNetworkingFactor>()?;
+        Ok(InstanceState {
+            allowed_hosts: networking.allowed_hosts(),
+            component_tls_configs: networking.component_tls_configs().clone(),
+        })
+    }
+}
+
+pub struct InstanceState {
+    allowed_hosts: OutboundAllowedHosts,
+    component_tls_configs: ComponentTlsConfigs,
+}
+
+impl SelfInstanceBuilder for InstanceState {}
@@ -0,0 +1,225 @@
+use lettre::{
+    address::Envelope,
+    message::{
+        header::{ContentDisposition, ContentTransferEncoding, ContentType, Headers},
+        Mailbox, MessageBuilder, MultiPart, SinglePart,
+    },
+    Message,
+};
+use spin_world::spin::mail::send::Error;
+
+/// A message whose attachments are read as it is sent.
+pub struct OutgoingMessage {
+    pub from: String,
+    pub to: Vec<String>,
+    pub cc: Vec<String>,
+    pub bcc: Vec<String>,
+    pub reply_to: Option<String>,
+    pub subject: String,
+    pub text: Option<String>,
+    pub html: Option<String>,
+    pub attachments: Vec<Attachment>,
+}
+
+pub struct Attachment {
+    pub filename: String,
+    pub content_type: String,
+}
+
+/// A MIME message, in parts around the content of its attachments.
+///
+/// The message is sent as `head`, then each attachment's headers followed by
+/// its content encoded with [`Base64Lines`](crate::smtp::Base64Lines), then
+/// `tail`.
+pub struct BuiltMessage {
+    pub envelope: Envelope,
+    pub head: Vec<u8>,
+    pub attachment_headers: Vec<Vec<u8>>,
+    pub tail: Vec<u8>,
+}
+
+impl OutgoingMessage {
+    /// Builds the MIME message to send.
+    pub fn build(self) -> Result<BuiltMessage, Error> {
+        let mut builder = Message::builder()
+            .from(mailbox(&self.from)?)
+            .subject(self.subject);
+        for to in &self.to {
+            builder = builder.to(mailbox(to)?);
+        }
+        for cc in &self.cc {
+            builder = builder.cc(mailbox(cc)?);
+        }
+        for bcc in &self.bcc {
+            builder = builder.bcc(mailbox(bcc)?);
+        }
+        if let Some(reply_to) = &self.reply_to {
+            builder = builder.reply_to(mailbox(reply_to)?);
+        }
+
+        let body = match (self.text, self.html) {
+            (Some(text), Some(html)) => Body::Multi(MultiPart::alternative_plain_html(text, html)),
+            (None, Some(html)) => Body::Single(SinglePart::html(html)),
+            (text, None) => Body::Single(SinglePart::plain(text.unwrap_or_default())),
+        };
+        if self.attachments.is_empty() {
+            let message = body.build(builder)?;
+            return Ok(BuiltMessage {
+                envelope: message.envelope().clone(),
+                head: message.formatted(),
+                attachment_headers: vec![],
+                tail: vec![],
+            });
+        }
+
+        // lettre ends the mixed part with its closing delimiter, which comes
+        // after the attachments instead.
+        let boundary = format!("spin-{}", uuid::Uuid::new_v4().simple());
+        let mixed = MultiPart::mixed().boundary(&boundary);
+        let mixed = match body {
+            Body::Single(part) => mixed.singlepart(part),
+            Body::Multi(part) => mixed.multipart(part),
+        };
+        let message = Body::Multi(mixed).build(builder)?;
+        let tail = format!("--{boundary}--\r\n").into_bytes();
+        let mut head = message.formatted();
+        if !head.ends_with(&tail) {
+            return Err(Error::Other("failed to format message".into()));
+        }
+        head.truncate(head.len() - tail.len());
+
+        let attachment_headers = self
+            .attachments
+            .iter()
+            .map(|attachment| attachment_headers(&boundary, attachment))
+            .collect::<Result<_, _>>()?;
+        Ok(BuiltMessage {
+            envelope: message.envelope().clone(),
+            head,
+            attachment_headers,
+            tail,
+        })
+    }
+}
+
+/// Returns the delimiter and headers which start an attachment's part.
+fn attachment_headers(boundary: &str, attachment: &Attachment) -> Result<Vec<u8>, Error> {
+    let content_type = ContentType::parse(&attachment.content_type).map_err(|_| {
+        invalid(format!(
+            "invalid attachment content type {:?}",
+            attachment.content_type
+        ))
+    })?;
+    let mut headers = Headers::new();
+    headers.set(content_type);
+    headers.set(ContentDisposition::attachment(&attachment.filename));
+    headers.set(ContentTransferEncoding::Base64);
+    Ok(format!("--{boundary}\r\n{headers}\r\n").into_bytes())
+}
+
+enum Body {
+    Single(SinglePart),
+    Multi(MultiPart),
+}
+
+impl Body {
+    fn build(self, builder: MessageBuilder) -> Result<Message, Error> {
+        match self {
+            Body::Single(part) => builder.singlepart(part),
+            Body::Multi(part) => builder.multipart(part),
+        }
+        .map_err(|err| invalid(err.to_string()))
+    }
+}
+
+fn mailbox(address: &str) -> Result<Mailbox, Error> {
+    address
+        .parse()
+        .map_err(|err| invalid(format!("invalid address {address:?}: {err}")))
+}
+
+fn invalid(message: String) -> Error {
+    Error::InvalidMessage(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::smtp::Base64Lines;
+
+    use super::*;
+
+    fn message() -> OutgoingMessage {
+        OutgoingMessage {
+            from: "Spin <spin@example.com>".into(),
+            to: vec!["someone@example.com".into()],
+            cc: vec![],
+            bcc: vec!["hidden@example.com".into()],
+            reply_to: None,
+            subject: "Hello".into(),
+            text: Some("Hello, world".into()),
+            html: None,
+            attachments: vec![],
+        }
+    }
+
+    /// Formats the message as it is sent, with the given attachment content.
+    fn formatted(message: OutgoingMessage, contents: &[&[u8]]) -> String {
+        let built = message.build().unwrap();
+        let mut formatted = built.head;
+        for (headers, content) in built.attachment_headers.into_iter().zip(contents) {
+            formatted.extend(headers);
+            let mut lines = Base64Lines::default();
+            formatted.extend(lines.push(content));
+            formatted.extend(lines.finish());
+        }
+        formatted.extend(built.tail);
+        String::from_utf8(formatted).unwrap()
+    }
+
+    #[test]
+    fn plain_messages_have_a_single_part() {
+        let formatted = formatted(message(), &[]);
+        assert!(formatted.contains("From: Spin <spin@example.com>"));
+        assert!(formatted.contains("Subject: Hello"));
+        assert!(formatted.contains("Content-Type: text/plain"));
+        assert!(!formatted.contains("hidden@example.com"));
+        assert!(!formatted.contains("multipart/"));
+    }
+
+    #[test]
+    fn attachments_are_mixed_with_the_bodies() {
+        let formatted = formatted(
+            OutgoingMessage {
+                html: Some("<p>Hello, world</p>".into()),
+                attachments: vec![Attachment {
+                    filename: "report.csv".into(),
+                    content_type: "text/csv".into(),
+                }],
+                ..message()
+            },
+            &[b"a,b\n1,2\n"],
+        );
+        assert!(formatted.contains("multipart/mixed"));
+        assert!(formatted.contains("multipart/alternative"));
+        assert!(formatted.contains("text/html"));
+        assert!(formatted.contains("filename=\"report.csv\""));
+        assert!(
+            formatted.contains("Content-Transfer-Encoding: base64\r\n\r\nYSxiCjEsMgo=\r\n--spin-")
+        );
+
+        // The attachment is the last part of the mixed body.
+        let start = formatted.find("spin-").unwrap();
+        let boundary = &formatted[start..start + "spin-".len() + 32];
+        assert_eq!(formatted.matches(&format!("--{boundary}\r\n")).count(), 2);
+        assert!(formatted.ends_with(&format!("YSxiCjEsMgo=\r\n--{boundary}--\r\n")));
+    }
+
+    #[test]
+    fn invalid_addresses_are_rejected() {
+        let message = OutgoingMessage {
+            to: vec!["not an address".into()],
+            ..message()
+        };
+        assert!(matches!(message.build(), Err(Error::InvalidMessage(_))));
+    }
+}
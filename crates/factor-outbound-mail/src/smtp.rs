@@ -0,0 +1,440 @@
+//! A minimal SMTP client.
+//!
+//! lettre's transports only send messages which are wholly in memory, so this
+//! client lets a message's content be written as it is read from the guest.
+
+use std::{sync::Arc, time::Duration};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use lettre::{address::Envelope, transport::smtp::extension::ClientId};
+use rustls::{pki_types::ServerName, ClientConfig};
+use spin_world::spin::mail::send::Error;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader},
+    net::TcpStream,
+};
+use tokio_rustls::TlsConnector;
+
+/// How long to wait to connect to a server.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long to wait for a server to reply to a command.
+const REPLY_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The longest reply line a server may send.
+const MAX_REPLY_LINE: usize = 4096;
+
+/// How many bytes of content make up a line of base64.
+const BASE64_LINE_BYTES: usize = 57;
+
+/// A server to send mail through.
+pub struct SmtpServer {
+    pub host: String,
+    pub port: u16,
+    /// Whether the connection is TLS from the start, rather than upgraded
+    /// with STARTTLS.
+    pub implicit_tls: bool,
+    pub credentials: Option<(String, String)>,
+}
+
+pub trait Stream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Stream for T {}
+
+/// A server's reply to a command.
+struct Reply {
+    code: u16,
+    lines: Vec<String>,
+}
+
+impl Reply {
+    fn is_positive(&self) -> bool {
+        (200..400).contains(&self.code)
+    }
+
+    fn into_error(self) -> Error {
+        let message = format!("{} {}", self.code, self.lines.join(" "));
+        match self.code {
+            535 => Error::AuthenticationFailed,
+            500..=599 => Error::Rejected(message),
+            _ => Error::Other(message),
+        }
+    }
+}
+
+/// A session with a server, logged in and ready to send messages.
+pub struct Session<S> {
+    stream: BufReader<S>,
+    extensions: Vec<String>,
+    /// Whether the next byte of message content starts a line.
+    at_line_start: bool,
+}
+
+impl Session<Box<dyn Stream>> {
+    /// Connects to the server, over TLS, and logs in.
+    pub async fn connect(
+        server: &SmtpServer,
+        tls_config: Arc<ClientConfig>,
+    ) -> Result<Self, Error> {
+        let tcp = tokio::time::timeout(
+            CONNECT_TIMEOUT,
+            TcpStream::connect((server.host.as_str(), server.port)),
+        )
+        .await
+        .map_err(|_| Error::Other(format!("timed out connecting to {}", server.host)))?
+        .map_err(|err| Error::Other(format!("failed to connect to {}: {err}", server.host)))?;
+
+        let stream = if server.implicit_tls {
+            tls(tcp, &server.host, tls_config).await?
+        } else {
+            let mut session = Session::greet(tcp).await?;
+            if !session.supports("STARTTLS") {
+                return Err(Error::Other(format!(
+                    "{} does not support STARTTLS",
+                    server.host
+                )));
+            }
+            session.command("STARTTLS").await?;
+            tls(session.stream.into_inner(), &server.host, tls_config).await?
+        };
+        let mut session = if server.implicit_tls {
+            Session::greet(stream).await?
+        } else {
+            // The server doesn't greet again after STARTTLS.
+            let mut session = Session::new(stream);
+            session.ehlo().await?;
+            session
+        };
+
+        if let Some((username, password)) = &server.credentials {
+            session.login(username, password).await?;
+        }
+        Ok(session)
+    }
+}
+
+async fn tls(
+    tcp: TcpStream,
+    host: &str,
+    tls_config: Arc<ClientConfig>,
+) -> Result<Box<dyn Stream>, Error> {
+    let name = ServerName::try_from(host.to_owned())
+        .map_err(|_| Error::Other(format!("invalid server name {host:?}")))?;
+    let stream = TlsConnector::from(tls_config)
+        .connect(name, tcp)
+        .await
+        .map_err(|err| Error::Other(format!("TLS handshake with {host} failed: {err}")))?;
+    Ok(Box::new(stream))
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> Session<S> {
+    fn new(stream: S) -> Self {
+        Self {
+            stream: BufReader::new(stream),
+            extensions: vec![],
+            at_line_start: true,
+        }
+    }
+
+    /// Waits for the server's greeting and introduces the client.
+    async fn greet(stream: S) -> Result<Self, Error> {
+        let mut session = Self::new(stream);
+        let greeting = session.read_reply().await?;
+        if greeting.code != 220 {
+            return Err(greeting.into_error());
+        }
+        session.ehlo().await?;
+        Ok(session)
+    }
+
+    async fn ehlo(&mut self) -> Result<(), Error> {
+        let reply = self
+            .command(&format!("EHLO {}", ClientId::default()))
+            .await?;
+        // The first line is the server's name, the rest its extensions.
+        self.extensions = reply.lines.into_iter().skip(1).collect();
+        Ok(())
+    }
+
+    /// Returns whether the server supports the extension with the given
+    /// keyword and, if any, parameter.
+    fn supports(&self, keyword: &str) -> bool {
+        let mut wanted = keyword.split(' ');
+        let (keyword, parameter) = (wanted.next().unwrap_or_default(), wanted.next());
+        self.extensions.iter().any(|extension| {
+            let mut words = extension.split(' ');
+            words
+                .next()
+                .is_some_and(|word| word.eq_ignore_ascii_case(keyword))
+                && parameter.map_or(true, |parameter| {
+                    words.any(|word| word.eq_ignore_ascii_case(parameter))
+                })
+        })
+    }
+
+    async fn login(&mut self, username: &str, password: &str) -> Result<(), Error> {
+        if self.supports("AUTH PLAIN") || !self.supports("AUTH LOGIN") {
+            let token = STANDARD.encode(format!("\0{username}\0{password}"));
+            self.command(&format!("AUTH PLAIN {token}")).await?;
+        } else {
+            self.command("AUTH LOGIN").await?;
+            self.command(&STANDARD.encode(username)).await?;
+            self.command(&STANDARD.encode(password)).await?;
+        }
+        Ok(())
+    }
+
+    /// Sends the envelope and starts the message's content, which is then
+    /// written with [`Self::write`] and ended with [`Self::end_data`].
+    pub async fn start_data(&mut self, envelope: &Envelope) -> Result<(), Error> {
+        let from = envelope.from().map(ToString::to_string).unwrap_or_default();
+        self.command(&format!("MAIL FROM:<{from}>")).await?;
+        for to in envelope.to() {
+            self.command(&format!("RCPT TO:<{to}>")).await?;
+        }
+        self.command("DATA").await?;
+        self.at_line_start = true;
+        Ok(())
+    }
+
+    /// Writes message content, escaping lines which start with a period.
+    pub async fn write(&mut self, content: &[u8]) -> Result<(), Error> {
+        let mut stuffed = Vec::with_capacity(content.len());
+        for &byte in content {
+            if self.at_line_start && byte == b'.' {
+                stuffed.push(b'.');
+            }
+            stuffed.push(byte);
+            self.at_line_start = byte == b'\n';
+        }
+        self.write_raw(&stuffed).await
+    }
+
+    /// Ends the message's content, and returns once the server has accepted
+    /// the message.
+    pub async fn end_data(&mut self) -> Result<(), Error> {
+        if !self.at_line_start {
+            self.write_raw(b"\r\n").await?;
+        }
+        self.write_raw(b".\r\n").await?;
+        self.expect_positive().await?;
+        Ok(())
+    }
+
+    /// Ends the session. The message has been sent by now, so a server which
+    /// doesn't reply isn't an error.
+    pub async fn quit(mut self) {
+        if self.write_raw(b"QUIT\r\n").await.is_ok() {
+            _ = self.read_reply().await;
+        }
+    }
+
+    async fn command(&mut self, command: &str) -> Result<Reply, Error> {
+        self.write_raw(format!("{command}\r\n").as_bytes()).await?;
+        self.expect_positive().await
+    }
+
+    async fn expect_positive(&mut self) -> Result<Reply, Error> {
+        let reply = self.read_reply().await?;
+        if reply.is_positive() {
+            Ok(reply)
+        } else {
+            Err(reply.into_error())
+        }
+    }
+
+    async fn write_raw(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        let stream = self.stream.get_mut();
+        stream.write_all(bytes).await.map_err(io_error)?;
+        stream.flush().await.map_err(io_error)
+    }
+
+    async fn read_reply(&mut self) -> Result<Reply, Error> {
+        tokio::time::timeout(REPLY_TIMEOUT, self.read_reply_without_timeout())
+            .await
+            .map_err(|_| Error::Other("timed out waiting for the server".into()))?
+    }
+
+    async fn read_reply_without_timeout(&mut self) -> Result<Reply, Error> {
+        let mut lines = vec![];
+        loop {
+            let mut line = vec![];
+            (&mut self.stream)
+                .take(MAX_REPLY_LINE as u64)
+                .read_until(b'\n', &mut line)
+                .await
+                .map_err(io_error)?;
+            if !line.ends_with(b"\n") {
+                return Err(Error::Other("unexpected reply from the server".into()));
+            }
+            let line = String::from_utf8_lossy(&line);
+            let line = line.trim_end();
+            let code = line
+                .get(..3)
+                .and_then(|code| code.parse().ok())
+                .ok_or_else(|| Error::Other(format!("unexpected reply from the server: {line}")))?;
+            lines.push(line.get(4..).unwrap_or_default().to_owned());
+            // "250-" continues a reply, "250 " ends it.
+            if line.as_bytes().get(3) != Some(&b'-') {
+                return Ok(Reply { code, lines });
+            }
+        }
+    }
+}
+
+fn io_error(err: std::io::Error) -> Error {
+    Error::Other(format!("connection to the server failed: {err}"))
+}
+
+/// Encodes content as base64 lines as it is written.
+#[derive(Default)]
+pub struct Base64Lines {
+    pending: Vec<u8>,
+}
+
+impl Base64Lines {
+    /// Returns the lines of content which are complete after `bytes`.
+    pub fn push(&mut self, bytes: &[u8]) -> Vec<u8> {
+        self.pending.extend_from_slice(bytes);
+        let complete = self.pending.len() - self.pending.len() % BASE64_LINE_BYTES;
+        let encoded = encode_lines(&self.pending[..complete]);
+        self.pending.drain(..complete);
+        encoded
+    }
+
+    /// Returns the last line of content.
+    pub fn finish(self) -> Vec<u8> {
+        encode_lines(&self.pending)
+    }
+}
+
+fn encode_lines(bytes: &[u8]) -> Vec<u8> {
+    let mut encoded = vec![];
+    for line in bytes.chunks(BASE64_LINE_BYTES) {
+        encoded.extend_from_slice(STANDARD.encode(line).as_bytes());
+        encoded.extend_from_slice(b"\r\n");
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{duplex, DuplexStream};
+
+    use super::*;
+
+    /// Plays a server which replies to each line in turn with the given
+    /// replies, and returns what the client sent.
+    fn server(
+        stream: DuplexStream,
+        replies: &'static [&'static str],
+    ) -> tokio::task::JoinHandle<String> {
+        tokio::spawn(async move {
+            let mut stream = BufReader::new(stream);
+            let mut received = String::new();
+            for reply in replies {
+                stream.get_mut().write_all(reply.as_bytes()).await.unwrap();
+                if reply.starts_with("354") {
+                    // Read the content up to its end.
+                    while !received.ends_with("\r\n.\r\n") {
+                        stream.read_line(&mut received).await.unwrap();
+                    }
+                } else {
+                    stream.read_line(&mut received).await.unwrap();
+                }
+            }
+            let mut rest = String::new();
+            stream.read_to_string(&mut rest).await.unwrap();
+            received + &rest
+        })
+    }
+
+    #[tokio::test]
+    async fn messages_are_sent_with_escaped_content() {
+        let (client, server_stream) = duplex(4096);
+        let server = server(
+            server_stream,
+            &[
+                "220 mail.example.com\r\n",
+                "250-mail.example.com\r\n250-AUTH LOGIN PLAIN\r\n250 8BITMIME\r\n",
+                "235 ok\r\n",
+                "250 ok\r\n",
+                "250 ok\r\n",
+                "354 go ahead\r\n",
+                "250 queued\r\n",
+                "221 bye\r\n",
+            ],
+        );
+
+        let mut session = Session::greet(client).await.unwrap();
+        assert!(session.supports("AUTH PLAIN"));
+        assert!(!session.supports("STARTTLS"));
+        session.login("user", "secret").await.unwrap();
+        let envelope = Envelope::new(
+            Some("spin@example.com".parse().unwrap()),
+            vec!["someone@example.com".parse().unwrap()],
+        )
+        .unwrap();
+        session.start_data(&envelope).await.unwrap();
+        session
+            .write(b"Subject: Hi\r\n\r\n.hidden\r\n")
+            .await
+            .unwrap();
+        session.write(b"..").await.unwrap();
+        session.end_data().await.unwrap();
+        session.quit().await;
+
+        let received = server.await.unwrap();
+        assert!(received.contains(&format!(
+            "AUTH PLAIN {}\r\n",
+            STANDARD.encode("\0user\0secret")
+        )));
+        assert!(received
+            .contains("MAIL FROM:<spin@example.com>\r\nRCPT TO:<someone@example.com>\r\nDATA\r\n"));
+        assert!(received.contains("\r\n\r\n..hidden\r\n...\r\n.\r\nQUIT\r\n"));
+    }
+
+    #[tokio::test]
+    async fn rejections_are_errors() {
+        let (client, server_stream) = duplex(4096);
+        let _server = server(
+            server_stream,
+            &[
+                "220 mail.example.com\r\n",
+                "250 mail.example.com\r\n",
+                "535 5.7.8 bad credentials\r\n",
+                "550 5.1.1 no such user\r\n",
+            ],
+        );
+
+        let mut session = Session::greet(client).await.unwrap();
+        assert!(matches!(
+            session.login("user", "wrong").await,
+            Err(Error::AuthenticationFailed)
+        ));
+        assert!(matches!(
+            session.command("RCPT TO:<nobody@example.com>").await,
+            Err(Error::Rejected(_))
+        ));
+    }
+
+    #[test]
+    fn base64_lines_are_split_across_pushes() {
+        let content = (0..=255u8).cycle().take(1000).collect::<Vec<_>>();
+        let mut lines = Base64Lines::default();
+        let mut encoded = vec![];
+        for chunk in content.chunks(100) {
+            encoded.extend(lines.push(chunk));
+        }
+        encoded.extend(lines.finish());
+
+        let encoded = String::from_utf8(encoded).unwrap();
+        assert!(encoded.lines().all(|line| line.len() <= 76));
+        assert_eq!(encoded, String::from_utf8(encode_lines(&content)).unwrap());
+        let decoded = encoded
+            .split("\r\n")
+            .flat_map(|line| STANDARD.decode(line).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(decoded, content);
+    }
+}
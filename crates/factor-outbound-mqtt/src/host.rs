@@ -26,7 +26,14 @@ impl InstanceState {
 
 #[async_trait]
 pub trait MqttClient: Send + Sync {
-    async fn publish_bytes(&self, topic: String, qos: Qos, payload: Vec<u8>) -> Result<(), Error>;
+    /// Publishes a message with the given MQTT v5 user properties.
+    async fn publish_bytes(
+        &self,
+        topic: String,
+        qos: Qos,
+        payload: Vec<u8>,
+        user_properties: Vec<(String, String)>,
+    ) -> Result<(), Error>;
 }
 
 impl InstanceState {
@@ -93,9 +100,8 @@ impl v2::HostConnection for InstanceState {
 
     /// Publish a message to the MQTT broker.
     ///
-    /// OTEL trace propagation is not directly supported in MQTT V3. You will need to embed the
-    /// current trace context into the payload yourself.
-    /// https://w3c.github.io/trace-context-mqtt/#mqtt-v3-recommendation.
+    /// The current trace context is propagated in the message's user properties.
+    /// https://w3c.github.io/trace-context-mqtt/#mqtt-v5-format.
     #[instrument(name = "spin_outbound_mqtt.publish", skip(self, connection, payload), err(level = Level::INFO),
         fields(otel.kind = "producer", otel.name = format!("{} publish", topic), messaging.operation = "publish",
         messaging.system = "mqtt"))]
@@ -108,7 +114,10 @@ impl v2::HostConnection for InstanceState {
     ) -> Result<(), Error> {
         let conn = self.get_conn(connection).await.map_err(other_error)?;
 
-        conn.publish_bytes(topic, qos, payload).await?;
+        let mut user_properties = vec![];
+        spin_telemetry::inject_trace_context(&mut user_properties);
+        conn.publish_bytes(topic, qos, payload, user_properties)
+            .await?;
 
         Ok(())
     }
@@ -5,7 +5,9 @@ use std::time::Duration;
 
 use host::other_error;
 use host::InstanceState;
-use rumqttc::{AsyncClient, Event, Incoming, Outgoing, QoS};
+use rumqttc::v5::mqttbytes::{v5::PublishProperties, QoS};
+use rumqttc::v5::{AsyncClient, Event, Incoming};
+use rumqttc::Outgoing;
 use spin_core::async_trait;
 use spin_factor_outbound_networking::OutboundNetworkingFactor;
 use spin_factors::{
@@ -62,10 +64,11 @@ impl Factor for OutboundMqttFactor {
 
 impl SelfInstanceBuilder for InstanceState {}
 
-// This is a concrete implementation of the MQTT client using rumqttc.
+// This is a concrete implementation of the MQTT client using rumqttc. It
+// speaks MQTT v5 so that messages can carry trace context in user properties.
 pub struct NetworkedMqttClient {
-    inner: rumqttc::AsyncClient,
-    event_loop: Mutex<rumqttc::EventLoop>,
+    inner: AsyncClient,
+    event_loop: Mutex<rumqttc::v5::EventLoop>,
 }
 
 const MQTT_CHANNEL_CAP: usize = 1000;
@@ -90,7 +93,7 @@ impl NetworkedMqttClient {
         password: String,
         keep_alive_interval: Duration,
     ) -> Result<Self, Error> {
-        let mut conn_opts = rumqttc::MqttOptions::parse_url(address).map_err(|e| {
+        let mut conn_opts = rumqttc::v5::MqttOptions::parse_url(address).map_err(|e| {
             tracing::error!("MQTT URL parse error: {e:?}");
             Error::InvalidAddress
         })?;
@@ -106,15 +109,25 @@ impl NetworkedMqttClient {
 
 #[async_trait]
 impl MqttClient for NetworkedMqttClient {
-    async fn publish_bytes(&self, topic: String, qos: Qos, payload: Vec<u8>) -> Result<(), Error> {
+    async fn publish_bytes(
+        &self,
+        topic: String,
+        qos: Qos,
+        payload: Vec<u8>,
+        user_properties: Vec<(String, String)>,
+    ) -> Result<(), Error> {
         let qos = match qos {
-            Qos::AtMostOnce => rumqttc::QoS::AtMostOnce,
-            Qos::AtLeastOnce => rumqttc::QoS::AtLeastOnce,
-            Qos::ExactlyOnce => rumqttc::QoS::ExactlyOnce,
+            Qos::AtMostOnce => QoS::AtMostOnce,
+            Qos::AtLeastOnce => QoS::AtLeastOnce,
+            Qos::ExactlyOnce => QoS::ExactlyOnce,
+        };
+        let properties = PublishProperties {
+            user_properties,
+            ..Default::default()
         };
         // Message published to EventLoop (not MQTT Broker)
         self.inner
-            .publish_bytes(topic, qos, false, payload.into())
+            .publish_with_properties(topic, qos, false, payload, properties)
             .await
             .map_err(other_error)?;
 
@@ -19,6 +19,7 @@ impl MqttClient for MockMqttClient {
         _topic: String,
         _qos: Qos,
         _payload: Vec<u8>,
+        _user_properties: Vec<(String, String)>,
     ) -> Result<(), Error> {
         Ok(())
     }
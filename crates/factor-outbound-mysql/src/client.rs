@@ -5,6 +5,7 @@ use mysql_async::consts::ColumnType;
 use mysql_async::prelude::{FromValue, Queryable as _};
 use mysql_async::{from_value_opt, Conn as MysqlClient, Opts, OptsBuilder, SslOpts};
 use spin_core::async_trait;
+use spin_errors::ErrorKind;
 use spin_world::v2::mysql::{self as v2};
 use spin_world::v2::rdbms_types::{
     self as v2_types, Column, DbDataType, DbValue, ParameterValue, RowSet,
@@ -55,7 +56,7 @@ impl Client for MysqlClient {
 
         self.exec_batch(&statement, &[parameters])
             .await
-            .map_err(|e| v2::Error::QueryFailed(format!("{:?}", e)))
+            .map_err(query_failed)
     }
 
     async fn query(
@@ -69,13 +70,16 @@ impl Client for MysqlClient {
         let mut query_result = self
             .exec_iter(&statement, parameters)
             .await
-            .map_err(|e| v2::Error::QueryFailed(format!("{:?}", e)))?;
+            .map_err(query_failed)?;
 
         // We have to get these before collect() destroys them
         let columns = convert_columns(query_result.columns());
 
         match query_result.collect::<mysql_async::Row>().await {
-            Err(e) => Err(v2::Error::Other(e.to_string())),
+            Err(e) => {
+                tracing::debug!(error.kind = %error_kind(&e), "MySQL query failed: {e:?}");
+                Err(v2::Error::Other(e.to_string()))
+            }
             Ok(result_set) => {
                 let rows = result_set
                     .into_iter()
@@ -88,6 +92,38 @@ impl Client for MysqlClient {
     }
 }
 
+fn query_failed(err: mysql_async::Error) -> v2::Error {
+    tracing::debug!(error.kind = %error_kind(&err), "MySQL query failed: {err:?}");
+    v2::Error::QueryFailed(format!("{:?}", err))
+}
+
+/// Classifies a MySQL error by its server error code, if the server sent one.
+pub(crate) fn error_kind(err: &mysql_async::Error) -> ErrorKind {
+    match err {
+        mysql_async::Error::Server(err) => match err.code {
+            // ER_DBACCESS_DENIED_ERROR, ER_ACCESS_DENIED_ERROR
+            1044 | 1045 => ErrorKind::Auth,
+            // ER_TABLEACCESS_DENIED_ERROR, ER_COLUMNACCESS_DENIED_ERROR, ER_SPECIFIC_ACCESS_DENIED_ERROR
+            1142 | 1143 | 1227 => ErrorKind::NotAllowed,
+            // ER_CON_COUNT_ERROR, ER_TOO_MANY_USER_CONNECTIONS, ER_OUT_OF_RESOURCES
+            1040 | 1203 | 1041 => ErrorKind::ResourceExhausted,
+            // ER_BAD_DB_ERROR, ER_BAD_FIELD_ERROR, ER_NO_SUCH_TABLE
+            1049 | 1054 | 1146 => ErrorKind::NotFound,
+            // ER_LOCK_WAIT_TIMEOUT, ER_QUERY_TIMEOUT
+            1205 | 3024 => ErrorKind::Timeout,
+            // ER_PARSE_ERROR, ER_WRONG_VALUE_COUNT_ON_ROW
+            1064 | 1136 => ErrorKind::InvalidInput,
+            _ => ErrorKind::Other,
+        },
+        mysql_async::Error::Io(_) => match ErrorKind::classify(err) {
+            ErrorKind::Other => ErrorKind::Connection,
+            kind => kind,
+        },
+        mysql_async::Error::Url(_) => ErrorKind::InvalidInput,
+        _ => ErrorKind::classify(err),
+    }
+}
+
 fn to_sql_parameter(value: ParameterValue) -> mysql_async::Value {
     match value {
         ParameterValue::Boolean(v) => mysql_async::Value::from(v),
@@ -305,4 +341,19 @@ mod test {
             10
         )
     }
+
+    #[test]
+    fn server_errors_are_classified_by_code() {
+        let server_error = |code| {
+            mysql_async::Error::Server(mysql_async::ServerError {
+                code,
+                message: "it went wrong".into(),
+                state: "HY000".into(),
+            })
+        };
+        assert_eq!(error_kind(&server_error(1045)), ErrorKind::Auth);
+        assert_eq!(error_kind(&server_error(1146)), ErrorKind::NotFound);
+        assert_eq!(error_kind(&server_error(1205)), ErrorKind::Timeout);
+        assert_eq!(error_kind(&server_error(9999)), ErrorKind::Other);
+    }
 }
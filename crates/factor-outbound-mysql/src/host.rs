@@ -1,6 +1,8 @@
 use anyhow::Result;
 use spin_core::async_trait;
 use spin_core::wasmtime::component::Resource;
+use spin_errors::ErrorKind;
+use spin_rdbms::Dialect;
 use spin_world::v1::mysql as v1;
 use spin_world::v2::mysql::{self as v2, Connection};
 use spin_world::v2::rdbms_types as v2_types;
@@ -17,7 +19,7 @@ impl<C: Client> InstanceState<C> {
             .push(
                 C::build_client(address)
                     .await
-                    .map_err(|e| v2::Error::ConnectionFailed(format!("{e:?}")))?,
+                    .map_err(|e| v2::Error::ConnectionFailed(build_error_message(&e)))?,
             )
             .map_err(|_| v2::Error::ConnectionFailed("too many connections".into()))
             .map(Resource::new_own)
@@ -29,11 +31,37 @@ impl<C: Client> InstanceState<C> {
             .ok_or_else(|| v2::Error::ConnectionFailed("no connection found".into()))
     }
 
+    /// Binds the named parameters of `statement` and, if strict statements
+    /// are enabled, rejects statements that look like values were pasted in.
+    fn prepare_statement(
+        &self,
+        statement: &str,
+        params: Vec<ParameterValue>,
+    ) -> Result<(String, Vec<ParameterValue>), v2::Error> {
+        if self.strict_statements {
+            spin_rdbms::ensure_parameterized(statement, Dialect::MySql)
+                .map_err(|e| v2::Error::BadParameter(e.to_string()))?;
+        }
+        spin_rdbms::bind_named_params(statement, Dialect::MySql, params)
+            .map_err(|e| v2::Error::BadParameter(e.to_string()))
+    }
+
     async fn is_address_allowed(&self, address: &str) -> Result<bool> {
         self.allowed_hosts.check_url(address, "mysql").await
     }
 }
 
+/// Formats an error from [`Client::build_client`] for the guest, logging its
+/// [`ErrorKind`].
+fn build_error_message(err: &anyhow::Error) -> String {
+    let kind = match err.downcast_ref::<mysql_async::Error>() {
+        Some(err) => crate::client::error_kind(err),
+        None => ErrorKind::classify(&**err),
+    };
+    tracing::debug!(error.kind = %kind, "MySQL connection failed: {err:?}");
+    format!("{err:?}")
+}
+
 #[async_trait]
 impl<C: Client> v2::Host for InstanceState<C> {}
 
@@ -62,6 +90,7 @@ impl<C: Client> v2::HostConnection for InstanceState<C> {
         statement: String,
         params: Vec<ParameterValue>,
     ) -> Result<(), v2::Error> {
+        let (statement, params) = self.prepare_statement(&statement, params)?;
         Ok(self
             .get_client(connection)
             .await?
@@ -76,6 +105,7 @@ impl<C: Client> v2::HostConnection for InstanceState<C> {
         statement: String,
         params: Vec<ParameterValue>,
     ) -> Result<v2_types::RowSet, v2::Error> {
+        let (statement, params) = self.prepare_statement(&statement, params)?;
         Ok(self
             .get_client(connection)
             .await?
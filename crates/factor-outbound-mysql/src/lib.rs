@@ -3,6 +3,7 @@ mod host;
 
 use client::Client;
 use mysql_async::Conn as MysqlClient;
+use serde::Deserialize;
 use spin_factor_outbound_networking::{OutboundAllowedHosts, OutboundNetworkingFactor};
 use spin_factors::{Factor, InitContext, RuntimeFactors, SelfInstanceBuilder};
 use spin_world::v1::mysql as v1;
@@ -13,8 +14,8 @@ pub struct OutboundMysqlFactor<C = MysqlClient> {
 }
 
 impl<C: Send + Sync + Client + 'static> Factor for OutboundMysqlFactor<C> {
-    type RuntimeConfig = ();
-    type AppState = ();
+    type RuntimeConfig = RuntimeConfig;
+    type AppState = RuntimeConfig;
     type InstanceBuilder = InstanceState<C>;
 
     fn init<T: Send + 'static>(&mut self, mut ctx: InitContext<T, Self>) -> anyhow::Result<()> {
@@ -25,9 +26,9 @@ impl<C: Send + Sync + Client + 'static> Factor for OutboundMysqlFactor<C> {
 
     fn configure_app<T: RuntimeFactors>(
         &self,
-        _ctx: spin_factors::ConfigureAppContext<T, Self>,
+        mut ctx: spin_factors::ConfigureAppContext<T, Self>,
     ) -> anyhow::Result<Self::AppState> {
-        Ok(())
+        Ok(ctx.take_runtime_config().unwrap_or_default())
     }
 
     fn prepare<T: spin_factors::RuntimeFactors>(
@@ -40,6 +41,7 @@ impl<C: Send + Sync + Client + 'static> Factor for OutboundMysqlFactor<C> {
         Ok(InstanceState {
             allowed_hosts,
             connections: Default::default(),
+            strict_statements: ctx.app_state().strict_statements,
         })
     }
 }
@@ -58,9 +60,21 @@ impl<C> OutboundMysqlFactor<C> {
     }
 }
 
+/// Runtime configuration for outbound MySQL, from the `[outbound_mysql]`
+/// runtime config section.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RuntimeConfig {
+    /// Reject statements that [`spin_rdbms::lint`] flags as possibly built
+    /// by pasting values into SQL.
+    #[serde(default)]
+    pub strict_statements: bool,
+}
+
 pub struct InstanceState<C> {
     allowed_hosts: OutboundAllowedHosts,
     connections: spin_resource_table::Table<C>,
+    strict_statements: bool,
 }
 
 impl<C: Send + 'static> SelfInstanceBuilder for InstanceState<C> {}
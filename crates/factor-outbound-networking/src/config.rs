@@ -331,6 +331,9 @@ fn well_known_port(scheme: &str) -> Option<u16> {
         "mysql" => Some(3306),
         "redis" => Some(6379),
         "mqtt" => Some(1883),
+        "sftp" => Some(22),
+        "smtp" => Some(587),
+        "smtps" => Some(465),
         "http" => Some(80),
         "https" => Some(443),
         _ => None,
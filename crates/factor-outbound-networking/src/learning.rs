@@ -0,0 +1,133 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::Mutex,
+};
+
+/// Records the outbound destinations components were not allowed to reach,
+/// so that `allowed_outbound_hosts` entries can be suggested for them all at
+/// once.
+///
+/// While learning, such requests are allowed rather than denied, so that a
+/// component can get past its first disallowed request to make its later
+/// ones. Learning is meant for development only.
+#[derive(Debug, Default)]
+pub struct OutboundHostLearner {
+    // Component ID -> host patterns
+    learned: Mutex<BTreeMap<String, BTreeSet<String>>>,
+}
+
+impl OutboundHostLearner {
+    /// Creates a new `OutboundHostLearner`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records that `component_id` needs `{scheme}://{authority}`.
+    pub fn record(&self, component_id: &str, scheme: &str, authority: &str) {
+        let host_pattern = format!("{scheme}://{authority}");
+        let mut learned = self.learned.lock().unwrap();
+        let patterns = learned.entry(component_id.to_owned()).or_default();
+        if patterns.insert(host_pattern.clone()) {
+            tracing::info!(
+                "Learned that component {component_id:?} needs outbound access to '{host_pattern}'"
+            );
+        }
+    }
+
+    /// The host patterns each component needs that it isn't allowed, by
+    /// component ID.
+    pub fn learned(&self) -> BTreeMap<String, BTreeSet<String>> {
+        self.learned.lock().unwrap().clone()
+    }
+}
+
+/// Renders manifest sections with the `allowed_outbound_hosts` each
+/// component needs: those it is already `allowed`, and those it was
+/// `learned` to need.
+///
+/// Only components which need more than they are allowed are included.
+pub fn suggest_allowed_outbound_hosts(
+    allowed: &BTreeMap<String, Vec<String>>,
+    learned: &BTreeMap<String, BTreeSet<String>>,
+) -> String {
+    let mut suggestion = String::new();
+    for (component_id, needed) in learned {
+        let existing = allowed.get(component_id).map(Vec::as_slice).unwrap_or(&[]);
+        let added = needed.iter().filter(|pattern| !existing.contains(pattern));
+        let hosts = existing
+            .iter()
+            .chain(added)
+            .map(|pattern| toml::Value::String(pattern.clone()).to_string())
+            .collect::<Vec<_>>();
+        if !suggestion.is_empty() {
+            suggestion.push('\n');
+        }
+        suggestion.push_str(&format!(
+            "[component.{}]\nallowed_outbound_hosts = [{}]\n",
+            toml_key(component_id),
+            hosts.join(", ")
+        ));
+    }
+    suggestion
+}
+
+/// Quotes a component ID for use as a TOML key, if it needs it.
+fn toml_key(key: &str) -> String {
+    let bare = !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if bare {
+        key.to_owned()
+    } else {
+        toml::Value::String(key.to_owned()).to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn destinations_are_learned_once() {
+        let learner = OutboundHostLearner::new();
+        learner.record("api", "https", "example.com");
+        learner.record("api", "https", "example.com");
+        learner.record("api", "redis", "cache:6379");
+        learner.record("worker", "http", "self");
+        let learned = learner.learned();
+        assert_eq!(
+            learned["api"],
+            ["https://example.com", "redis://cache:6379"]
+                .map(String::from)
+                .into()
+        );
+        assert_eq!(learned["worker"], ["http://self"].map(String::from).into());
+    }
+
+    #[test]
+    fn suggestions_extend_allowed_hosts() {
+        let learner = OutboundHostLearner::new();
+        learner.record("api", "https", "example.com");
+        learner.record("worker", "http", "self");
+        let allowed = [
+            (
+                "api".to_string(),
+                vec!["https://existing.example".to_string()],
+            ),
+            (
+                "idle".to_string(),
+                vec!["https://other.example".to_string()],
+            ),
+        ]
+        .into();
+        assert_eq!(
+            suggest_allowed_outbound_hosts(&allowed, &learner.learned()),
+            "[component.api]\n\
+             allowed_outbound_hosts = [\"https://existing.example\", \"https://example.com\"]\n\
+             \n\
+             [component.worker]\n\
+             allowed_outbound_hosts = [\"http://self\"]\n"
+        );
+    }
+}
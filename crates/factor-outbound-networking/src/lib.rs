@@ -1,4 +1,6 @@
 mod config;
+mod learning;
+pub mod proxy;
 pub mod runtime_config;
 
 use futures_util::{
@@ -6,13 +8,17 @@ use futures_util::{
     FutureExt,
 };
 use runtime_config::RuntimeConfig;
+use spin_common::diagnostics::{Diagnostic, Diagnostics};
 use spin_factor_variables::VariablesFactor;
 use spin_factor_wasi::{SocketAddrUse, WasiFactor};
 use spin_factors::{
     anyhow::{self, Context},
     ConfigureAppContext, Error, Factor, FactorInstanceBuilder, PrepareContext, RuntimeFactors,
 };
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    sync::Arc,
+};
 
 pub use config::{
     allowed_outbound_hosts, is_service_chaining_host, parse_service_chaining_target,
@@ -20,6 +26,8 @@ pub use config::{
     OutboundUrl, SERVICE_CHAINING_DOMAIN_SUFFIX,
 };
 
+pub use learning::{suggest_allowed_outbound_hosts, OutboundHostLearner};
+pub use proxy::{Proxy, ProxyConfig};
 pub use runtime_config::ComponentTlsConfigs;
 use url::Url;
 
@@ -28,6 +36,8 @@ pub type SharedFutureResult<T> = Shared<BoxFuture<'static, Result<Arc<T>, Arc<an
 #[derive(Default)]
 pub struct OutboundNetworkingFactor {
     disallowed_host_handler: Option<Arc<dyn DisallowedHostHandler>>,
+    learner: Option<Arc<OutboundHostLearner>>,
+    diagnostics: Diagnostics,
 }
 
 impl OutboundNetworkingFactor {
@@ -40,6 +50,18 @@ impl OutboundNetworkingFactor {
     pub fn set_disallowed_host_handler(&mut self, handler: impl DisallowedHostHandler + 'static) {
         self.disallowed_host_handler = Some(Arc::new(handler));
     }
+
+    /// Allows requests disallowed by an instance's configured
+    /// `allowed_outbound_hosts`, recording them with a learner shared by all
+    /// apps configured by this factor. See [`OutboundHostLearner`].
+    pub fn enable_learning(&mut self) {
+        self.learner = Some(Default::default());
+    }
+
+    /// Sets where disallowed requests are reported as diagnostics.
+    pub fn set_diagnostics(&mut self, diagnostics: Diagnostics) {
+        self.diagnostics = diagnostics;
+    }
 }
 
 impl Factor for OutboundNetworkingFactor {
@@ -74,6 +96,7 @@ impl Factor for OutboundNetworkingFactor {
         Ok(AppState {
             component_allowed_hosts,
             runtime_config,
+            learner: self.learner.clone(),
         })
     }
 
@@ -81,10 +104,11 @@ impl Factor for OutboundNetworkingFactor {
         &self,
         mut ctx: PrepareContext<T, Self>,
     ) -> anyhow::Result<Self::InstanceBuilder> {
+        let component_id: Arc<str> = ctx.app_component().id().into();
         let hosts = ctx
             .app_state()
             .component_allowed_hosts
-            .get(ctx.app_component().id())
+            .get(&*component_id)
             .cloned()
             .context("missing component allowed hosts")?;
         let resolver = ctx
@@ -105,6 +129,9 @@ impl Factor for OutboundNetworkingFactor {
                 let allowed_hosts = OutboundAllowedHosts {
                     allowed_hosts_future: allowed_hosts_future.clone(),
                     disallowed_host_handler: self.disallowed_host_handler.clone(),
+                    component_id: component_id.clone(),
+                    learner: self.learner.clone(),
+                    diagnostics: self.diagnostics.clone(),
                 };
                 wasi_builder.outbound_socket_addr_check(move |addr, addr_use| {
                     let allowed_hosts = allowed_hosts.clone();
@@ -135,10 +162,16 @@ impl Factor for OutboundNetworkingFactor {
             .runtime_config
             .get_component_tls_configs(ctx.app_component().id());
 
+        let proxy_config = ctx.app_state().runtime_config.proxy_config().clone();
+
         Ok(InstanceBuilder {
             allowed_hosts_future,
             component_tls_configs,
+            proxy_config,
             disallowed_host_handler: self.disallowed_host_handler.clone(),
+            component_id,
+            learner: self.learner.clone(),
+            diagnostics: self.diagnostics.clone(),
         })
     }
 }
@@ -146,12 +179,38 @@ impl Factor for OutboundNetworkingFactor {
 pub struct AppState {
     component_allowed_hosts: HashMap<String, Arc<[String]>>,
     runtime_config: RuntimeConfig,
+    learner: Option<Arc<OutboundHostLearner>>,
+}
+
+impl AppState {
+    /// The outbound destinations each component was learned to need but
+    /// isn't allowed, by component ID, or `None` if learning isn't enabled.
+    pub fn learned_outbound_hosts(&self) -> Option<BTreeMap<String, BTreeSet<String>>> {
+        Some(self.learner.as_ref()?.learned())
+    }
+
+    /// Manifest sections with the `allowed_outbound_hosts` each component
+    /// was learned to need, or `None` if learning isn't enabled. See
+    /// [`suggest_allowed_outbound_hosts`].
+    pub fn suggested_allowed_outbound_hosts(&self) -> Option<String> {
+        let learned = self.learned_outbound_hosts()?;
+        let allowed = self
+            .component_allowed_hosts
+            .iter()
+            .map(|(component_id, hosts)| (component_id.clone(), hosts.to_vec()))
+            .collect();
+        Some(suggest_allowed_outbound_hosts(&allowed, &learned))
+    }
 }
 
 pub struct InstanceBuilder {
     allowed_hosts_future: SharedFutureResult<AllowedHostsConfig>,
     component_tls_configs: ComponentTlsConfigs,
+    proxy_config: Arc<ProxyConfig>,
     disallowed_host_handler: Option<Arc<dyn DisallowedHostHandler>>,
+    component_id: Arc<str>,
+    learner: Option<Arc<OutboundHostLearner>>,
+    diagnostics: Diagnostics,
 }
 
 impl InstanceBuilder {
@@ -159,12 +218,20 @@ impl InstanceBuilder {
         OutboundAllowedHosts {
             allowed_hosts_future: self.allowed_hosts_future.clone(),
             disallowed_host_handler: self.disallowed_host_handler.clone(),
+            component_id: self.component_id.clone(),
+            learner: self.learner.clone(),
+            diagnostics: self.diagnostics.clone(),
         }
     }
 
     pub fn component_tls_configs(&self) -> &ComponentTlsConfigs {
         &self.component_tls_configs
     }
+
+    /// The proxies outbound connections go through.
+    pub fn proxy_config(&self) -> &Arc<ProxyConfig> {
+        &self.proxy_config
+    }
 }
 
 impl FactorInstanceBuilder for InstanceBuilder {
@@ -180,12 +247,16 @@ impl FactorInstanceBuilder for InstanceBuilder {
 pub struct OutboundAllowedHosts {
     allowed_hosts_future: SharedFutureResult<AllowedHostsConfig>,
     disallowed_host_handler: Option<Arc<dyn DisallowedHostHandler>>,
+    component_id: Arc<str>,
+    learner: Option<Arc<OutboundHostLearner>>,
+    diagnostics: Diagnostics,
 }
 
 impl OutboundAllowedHosts {
     /// Checks address against allowed hosts
     ///
-    /// Calls the [`DisallowedHostHandler`] if set and URL is disallowed.
+    /// Calls the [`DisallowedHostHandler`] if set and URL is disallowed. If
+    /// learning is enabled, a disallowed URL is recorded and allowed instead.
     /// If `url` cannot be parsed, `{scheme}://` is prepended to `url` and retried.
     pub async fn check_url(&self, url: &str, scheme: &str) -> anyhow::Result<bool> {
         tracing::debug!("Checking outbound networking request to '{url}'");
@@ -203,15 +274,22 @@ impl OutboundAllowedHosts {
         let is_allowed = allowed_hosts.allows(&url);
         if !is_allowed {
             tracing::debug!("Disallowed outbound networking request to '{url}'");
-            self.report_disallowed_host(url.scheme(), &url.authority());
+            return Ok(self.report_disallowed_host(url.scheme(), &url.authority()));
         }
         Ok(is_allowed)
     }
 
+    /// Checks address against allowed hosts without calling the
+    /// [`DisallowedHostHandler`], e.g. to report what a component could access.
+    pub async fn allows(&self, url: &OutboundUrl) -> anyhow::Result<bool> {
+        Ok(self.resolve().await?.allows(url))
+    }
+
     /// Checks if allowed hosts permit relative requests
     ///
     /// Calls the [`DisallowedHostHandler`] if set and relative requests are
-    /// disallowed.
+    /// disallowed. If learning is enabled, they are recorded and allowed
+    /// instead.
     pub async fn check_relative_url(&self, schemes: &[&str]) -> anyhow::Result<bool> {
         tracing::debug!("Checking relative outbound networking request with schemes {schemes:?}");
         let allowed_hosts = self.resolve().await?;
@@ -221,7 +299,7 @@ impl OutboundAllowedHosts {
                 "Disallowed relative outbound networking request with schemes {schemes:?}"
             );
             let scheme = schemes.first().unwrap_or(&"");
-            self.report_disallowed_host(scheme, "self");
+            return Ok(self.report_disallowed_host(scheme, "self"));
         }
         Ok(is_allowed)
     }
@@ -233,10 +311,29 @@ impl OutboundAllowedHosts {
         })
     }
 
-    fn report_disallowed_host(&self, scheme: &str, authority: &str) {
+    /// Reports a disallowed destination as a diagnostic and to the
+    /// [`DisallowedHostHandler`], returning whether it is allowed anyway
+    /// because it was learned.
+    fn report_disallowed_host(&self, scheme: &str, authority: &str) -> bool {
+        if let Some(learner) = &self.learner {
+            learner.record(&self.component_id, scheme, authority);
+            return true;
+        }
+        let host_pattern = format!("{scheme}://{authority}");
+        let message = if scheme.starts_with("http") && authority == "self" {
+            "tried to make an HTTP request to its own app but does not have permission".to_string()
+        } else {
+            format!("tried to make an outbound network connection to disallowed destination '{host_pattern}'")
+        };
+        self.diagnostics.report(
+            Diagnostic::new("outbound-host-not-allowed", message)
+                .component(&*self.component_id)
+                .suggestion(format!("To allow this request, add 'allowed_outbound_hosts = [\"{host_pattern}\"]' to the manifest component section.")),
+        );
         if let Some(handler) = &self.disallowed_host_handler {
             handler.handle_disallowed_host(scheme, authority);
         }
+        false
     }
 }
 
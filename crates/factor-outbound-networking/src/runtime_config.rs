@@ -7,18 +7,27 @@ use anyhow::{ensure, Context};
 use rustls::{ClientConfig, RootCertStore};
 use rustls_pki_types::{CertificateDer, PrivateKeyDer};
 
+use crate::proxy::ProxyConfig;
+
 /// Runtime configuration for outbound networking.
 #[derive(Debug)]
 pub struct RuntimeConfig {
     /// Maps component ID -> HostClientConfigs
     component_host_client_configs: HashMap<String, HostClientConfigs>,
+    /// [`ClientConfig`]s for hosts matching a pattern, whichever component
+    /// connects to them, most specific pattern first.
+    host_pattern_client_configs: HostPatternClientConfigs,
     /// The default [`ClientConfig`] for a host if one is not explicitly configured for it.
     default_client_config: Arc<ClientConfig>,
+    /// The proxies outbound connections go through.
+    proxy_config: Arc<ProxyConfig>,
 }
 
 // Maps host authority -> ClientConfig
 type HostClientConfigs = Arc<HashMap<String, Arc<ClientConfig>>>;
 
+type HostPatternClientConfigs = Arc<Vec<(HostPattern, Arc<ClientConfig>)>>;
+
 impl RuntimeConfig {
     /// Returns runtime config with the given list of [`TlsConfig`]s. The first
     /// [`TlsConfig`] to match an outgoing request (based on
@@ -58,10 +67,49 @@ impl RuntimeConfig {
 
         Ok(Self {
             component_host_client_configs,
+            host_pattern_client_configs: Default::default(),
             default_client_config,
+            proxy_config: Default::default(),
         })
     }
 
+    /// Sets TLS configuration for hosts matching a pattern, used by any
+    /// component that has no [`TlsConfig`] for the host.
+    pub fn with_host_tls_configs(
+        mut self,
+        host_tls_configs: impl IntoIterator<Item = HostTlsConfig>,
+    ) -> anyhow::Result<Self> {
+        let mut configs = host_tls_configs
+            .into_iter()
+            .map(
+                |HostTlsConfig {
+                     pattern,
+                     tls_config,
+                 }| {
+                    let pattern = HostPattern::parse(&pattern)?;
+                    let client_config = tls_config.to_client_config().with_context(|| {
+                        format!("error building TLS client config for {pattern}")
+                    })?;
+                    Ok((pattern, Arc::new(client_config)))
+                },
+            )
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        configs.sort_by(|(a, _), (b, _)| a.specificity().cmp(&b.specificity()).reverse());
+        self.host_pattern_client_configs = Arc::new(configs);
+        Ok(self)
+    }
+
+    /// Sets the proxies outbound connections go through.
+    pub fn with_proxy_config(mut self, proxy_config: ProxyConfig) -> Self {
+        self.proxy_config = Arc::new(proxy_config);
+        self
+    }
+
+    /// Returns the proxies outbound connections go through.
+    pub fn proxy_config(&self) -> &Arc<ProxyConfig> {
+        &self.proxy_config
+    }
+
     /// Returns [`ComponentTlsConfigs`] for the given component.
     pub fn get_component_tls_configs(&self, component_id: &str) -> ComponentTlsConfigs {
         let host_client_configs = self
@@ -70,6 +118,7 @@ impl RuntimeConfig {
             .cloned();
         ComponentTlsConfigs {
             host_client_configs,
+            host_pattern_client_configs: self.host_pattern_client_configs.clone(),
             default_client_config: self.default_client_config.clone(),
         }
     }
@@ -95,10 +144,63 @@ pub(crate) fn validate_host(host: &str) -> anyhow::Result<()> {
     Ok(())
 }
 
+/// A host name or `*.`-prefixed wildcard matching any subdomain of a domain.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum HostPattern {
+    Exact(String),
+    AnySubdomain(String),
+}
+
+impl HostPattern {
+    fn parse(pattern: &str) -> anyhow::Result<Self> {
+        match pattern.strip_prefix("*.") {
+            Some(domain) => {
+                validate_host(domain)?;
+                Ok(Self::AnySubdomain(domain.to_ascii_lowercase()))
+            }
+            None => {
+                validate_host(pattern)?;
+                Ok(Self::Exact(pattern.to_ascii_lowercase()))
+            }
+        }
+    }
+
+    fn matches(&self, host: &str) -> bool {
+        match self {
+            Self::Exact(exact) => host.eq_ignore_ascii_case(exact),
+            Self::AnySubdomain(domain) => host
+                .len()
+                .checked_sub(domain.len() + 1)
+                .and_then(|dot| host.get(dot..))
+                .is_some_and(|suffix| {
+                    suffix.starts_with('.') && suffix[1..].eq_ignore_ascii_case(domain)
+                }),
+        }
+    }
+
+    /// Exact hosts first, then wildcards with longer domains.
+    fn specificity(&self) -> (bool, usize) {
+        match self {
+            Self::Exact(host) => (true, host.len()),
+            Self::AnySubdomain(domain) => (false, domain.len()),
+        }
+    }
+}
+
+impl std::fmt::Display for HostPattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Exact(host) => f.write_str(host),
+            Self::AnySubdomain(domain) => write!(f, "*.{domain}"),
+        }
+    }
+}
+
 /// TLS configurations for a specific component.
 #[derive(Clone)]
 pub struct ComponentTlsConfigs {
     host_client_configs: Option<HostClientConfigs>,
+    host_pattern_client_configs: HostPatternClientConfigs,
     default_client_config: Arc<ClientConfig>,
 }
 
@@ -108,10 +210,49 @@ impl ComponentTlsConfigs {
         self.host_client_configs
             .as_ref()
             .and_then(|configs| configs.get(host))
+            .or_else(|| {
+                self.host_pattern_client_configs
+                    .iter()
+                    .find(|(pattern, _)| pattern.matches(host))
+                    .map(|(_, config)| config)
+            })
             .unwrap_or(&self.default_client_config)
     }
 }
 
+/// TLS configuration for the hosts matching a pattern.
+#[derive(Debug)]
+pub struct HostTlsConfig {
+    /// A host name, e.g. `api.example.com`, or a wildcard matching any
+    /// subdomain, e.g. `*.example.com`.
+    pub pattern: String,
+    /// The configuration for matching hosts. Its `components` and `hosts` are
+    /// ignored.
+    pub tls_config: TlsConfig,
+}
+
+/// A TLS protocol version.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum TlsVersion {
+    /// TLS 1.2
+    #[default]
+    Tls12,
+    /// TLS 1.3
+    Tls13,
+}
+
+impl FromStr for TlsVersion {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "1.2" => Ok(Self::Tls12),
+            "1.3" => Ok(Self::Tls13),
+            _ => anyhow::bail!("unsupported TLS version {s:?}; expected \"1.2\" or \"1.3\""),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct ClientCertConfig {
     cert_chain: Vec<CertificateDer<'static>>,
@@ -133,6 +274,8 @@ pub struct TlsConfig {
     /// A certificate and private key to be used as the client certificate for
     /// "mutual TLS" (mTLS).
     pub client_cert: Option<ClientCertConfig>,
+    /// The lowest TLS version to negotiate.
+    pub min_tls_version: TlsVersion,
 }
 
 impl Default for TlsConfig {
@@ -144,6 +287,7 @@ impl Default for TlsConfig {
             // Use webpki roots by default
             use_webpki_roots: true,
             client_cert: None,
+            min_tls_version: TlsVersion::default(),
         }
     }
 }
@@ -158,7 +302,12 @@ impl TlsConfig {
             root_store.add(ca.clone())?;
         }
 
-        let builder = ClientConfig::builder().with_root_certificates(root_store);
+        let versions: &[&rustls::SupportedProtocolVersion] = match self.min_tls_version {
+            TlsVersion::Tls12 => rustls::DEFAULT_VERSIONS,
+            TlsVersion::Tls13 => &[&rustls::version::TLS13],
+        };
+        let builder = ClientConfig::builder_with_protocol_versions(versions)
+            .with_root_certificates(root_store);
 
         if let Some(ClientCertConfig {
             cert_chain,
@@ -196,6 +345,7 @@ mod tests {
             root_certificates: vec![],
             use_webpki_roots: false,
             client_cert: None,
+            min_tls_version: TlsVersion::Tls12,
         }])?;
         let client_config = runtime_config.get_client_config("test-component", "test-host");
         // Check that we didn't just get the default
@@ -217,6 +367,7 @@ mod tests {
                 cert_chain: test_certs,
                 key_der: test_key,
             }),
+            min_tls_version: TlsVersion::Tls13,
         }])?;
         let client_config = runtime_config.get_client_config("test-component", "test-host");
         assert!(client_config.client_auth_cert_resolver.has_certs());
@@ -253,6 +404,54 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_host_pattern_configs() -> anyhow::Result<()> {
+        let runtime_config = RuntimeConfig::new([TlsConfig {
+            components: vec!["test-component".into()],
+            hosts: vec!["api.example.com".into()],
+            ..Default::default()
+        }])?
+        .with_host_tls_configs([
+            HostTlsConfig {
+                pattern: "*.example.com".into(),
+                tls_config: Default::default(),
+            },
+            HostTlsConfig {
+                pattern: "*.internal.example.com".into(),
+                tls_config: TlsConfig {
+                    client_cert: Some(ClientCertConfig {
+                        cert_chain: test_certs()?,
+                        key_der: test_key()?,
+                    }),
+                    ..Default::default()
+                },
+            },
+        ])?;
+        let configs = runtime_config.get_component_tls_configs("other-component");
+
+        // Most specific pattern wins
+        let internal = configs.get_client_config("db.internal.example.com");
+        assert!(internal.client_auth_cert_resolver.has_certs());
+        let public = configs.get_client_config("www.example.com");
+        assert!(!public.client_auth_cert_resolver.has_certs());
+        assert!(!Arc::ptr_eq(internal, public));
+
+        // Patterns don't match the bare domain
+        let bare = configs.get_client_config("example.com");
+        assert!(Arc::ptr_eq(bare, &runtime_config.default_client_config));
+
+        // Component configs take precedence over host patterns
+        let component = runtime_config.get_client_config("test-component", "api.example.com");
+        assert!(!Arc::ptr_eq(&component, public));
+        Ok(())
+    }
+
+    #[test]
+    fn test_tls_version_parsing() {
+        assert_eq!(TlsVersion::Tls13, "1.3".parse().unwrap());
+        "1.1".parse::<TlsVersion>().unwrap_err();
+    }
+
     const TESTDATA_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/testdata");
 
     fn test_certs() -> anyhow::Result<Vec<CertificateDer<'static>>> {
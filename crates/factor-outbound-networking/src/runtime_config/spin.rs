@@ -3,11 +3,13 @@ use serde::{Deserialize, Deserializer};
 use spin_factors::runtime_config::toml::GetTomlValue;
 use std::io;
 use std::{
+    collections::BTreeMap,
     fs,
     path::{Path, PathBuf},
 };
 
-use super::{validate_host, TlsConfig};
+use super::{validate_host, HostTlsConfig, TlsConfig};
+use crate::proxy::ProxyConfig;
 
 /// Spin's default handling of the runtime configuration for outbound TLS.
 pub struct SpinTlsRuntimeConfig {
@@ -25,7 +27,8 @@ impl SpinTlsRuntimeConfig {
         }
     }
 
-    /// Get the runtime configuration for client TLS from a TOML table.
+    /// Get the runtime configuration for client TLS and outbound proxies from
+    /// a TOML table.
     ///
     /// Expects table to be in the format:
     /// ````toml
@@ -36,15 +39,38 @@ impl SpinTlsRuntimeConfig {
     /// ca_roots_file = "path/to/roots.crt"
     /// client_cert_file = "path/to/client.crt"
     /// client_private_key_file = "path/to/client.key"
+    ///
+    /// [outbound_tls."*.internal.example.com"]
+    /// ca_roots_file = "path/to/internal-roots.crt"
+    /// client_cert_file = "path/to/client.crt"
+    /// client_private_key_file = "path/to/client.key"
+    /// min_tls_version = "1.3"
+    ///
+    /// [outbound_proxy]
+    /// url = "http://proxy.example.com:3128"
+    /// no_proxy = ["localhost", "*.internal.example.com", "10.0.0.0/8"]
+    ///
+    /// [[outbound_proxy.override]]
+    /// hosts = ["*.partner.example.com"]
+    /// url = "socks5h://partner-gateway.example.com:1080"
     /// ```
     pub fn config_from_table(
         &self,
         table: &impl GetTomlValue,
     ) -> anyhow::Result<Option<super::RuntimeConfig>> {
-        let Some(tls_configs) = self.tls_configs_from_table(table)? else {
+        let tls_configs = self.tls_configs_from_table(table)?;
+        let host_tls_configs = self.host_tls_configs_from_table(table)?;
+        let proxy_config = proxy_config_from_table(table)?;
+        if tls_configs.is_none() && host_tls_configs.is_none() && proxy_config.is_none() {
             return Ok(None);
-        };
-        let runtime_config = super::RuntimeConfig::new(tls_configs)?;
+        }
+        let mut runtime_config = super::RuntimeConfig::new(tls_configs.unwrap_or_default())?;
+        if let Some(host_tls_configs) = host_tls_configs {
+            runtime_config = runtime_config.with_host_tls_configs(host_tls_configs)?;
+        }
+        if let Some(proxy_config) = proxy_config {
+            runtime_config = runtime_config.with_proxy_config(proxy_config);
+        }
         Ok(Some(runtime_config))
     }
 
@@ -65,6 +91,32 @@ impl SpinTlsRuntimeConfig {
         Ok(Some(tls_configs))
     }
 
+    fn host_tls_configs_from_table<T: GetTomlValue>(
+        &self,
+        table: &T,
+    ) -> anyhow::Result<Option<Vec<HostTlsConfig>>> {
+        let Some(value) = table.get("outbound_tls") else {
+            return Ok(None);
+        };
+        let toml_configs: BTreeMap<String, TlsSettingsToml> = value
+            .clone()
+            .try_into()
+            .context("invalid [outbound_tls] runtime config")?;
+        let host_tls_configs = toml_configs
+            .into_iter()
+            .map(|(pattern, settings)| {
+                let tls_config = self.load_tls_settings(settings).with_context(|| {
+                    format!("invalid [outbound_tls.{pattern:?}] runtime config")
+                })?;
+                Ok(HostTlsConfig {
+                    pattern,
+                    tls_config,
+                })
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Some(host_tls_configs))
+    }
+
     fn load_tls_config(&self, toml_config: RuntimeConfigToml) -> anyhow::Result<TlsConfig> {
         let RuntimeConfigToml {
             component_ids,
@@ -73,7 +125,15 @@ impl SpinTlsRuntimeConfig {
             ca_roots_file,
             client_cert_file,
             client_private_key_file,
+            min_tls_version,
         } = toml_config;
+        let settings = TlsSettingsToml {
+            ca_use_webpki_roots,
+            ca_roots_file,
+            client_cert_file,
+            client_private_key_file,
+            min_tls_version,
+        };
         ensure!(
             !component_ids.is_empty(),
             "[[client_tls]] 'component_ids' list may not be empty"
@@ -93,6 +153,23 @@ impl SpinTlsRuntimeConfig {
             })
             .collect::<anyhow::Result<Vec<_>>>()?;
 
+        Ok(TlsConfig {
+            components,
+            hosts,
+            ..self.load_tls_settings(settings)?
+        })
+    }
+
+    /// Load the settings shared by `[[client_tls]]` and `[outbound_tls.<host>]`.
+    fn load_tls_settings(&self, settings: TlsSettingsToml) -> anyhow::Result<TlsConfig> {
+        let TlsSettingsToml {
+            ca_use_webpki_roots,
+            ca_roots_file,
+            client_cert_file,
+            client_private_key_file,
+            min_tls_version,
+        } = settings;
+
         let use_webpki_roots = if let Some(ca_use_webpki_roots) = ca_use_webpki_roots {
             ca_use_webpki_roots
         } else {
@@ -115,12 +192,19 @@ impl SpinTlsRuntimeConfig {
             (None, Some(_)) => bail!("client_private_key_file specified without client_cert_file"),
         };
 
+        let min_tls_version = min_tls_version
+            .as_deref()
+            .map(str::parse)
+            .transpose()?
+            .unwrap_or_default();
+
         Ok(TlsConfig {
-            components,
-            hosts,
+            components: vec![],
+            hosts: vec![],
             root_certificates,
             use_webpki_roots,
             client_cert,
+            min_tls_version,
         })
     }
 
@@ -173,6 +257,56 @@ pub struct RuntimeConfigToml {
     ca_roots_file: Option<PathBuf>,
     client_cert_file: Option<PathBuf>,
     client_private_key_file: Option<PathBuf>,
+    min_tls_version: Option<String>,
+}
+
+/// The settings of an `[outbound_tls.<host>]` table, which are also those of
+/// a `[[client_tls]]` table besides its components and hosts.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TlsSettingsToml {
+    ca_use_webpki_roots: Option<bool>,
+    ca_roots_file: Option<PathBuf>,
+    client_cert_file: Option<PathBuf>,
+    client_private_key_file: Option<PathBuf>,
+    min_tls_version: Option<String>,
+}
+
+fn proxy_config_from_table(table: &impl GetTomlValue) -> anyhow::Result<Option<ProxyConfig>> {
+    let Some(value) = table.get("outbound_proxy") else {
+        return Ok(None);
+    };
+    let ProxyConfigToml {
+        url,
+        no_proxy,
+        overrides,
+    } = value
+        .clone()
+        .try_into()
+        .context("invalid [outbound_proxy] runtime config")?;
+    let overrides = overrides
+        .into_iter()
+        .map(|ProxyOverrideToml { hosts, url }| (hosts, url));
+    let proxy_config = ProxyConfig::new(url.as_deref(), overrides, &no_proxy)
+        .context("invalid [outbound_proxy] runtime config")?;
+    Ok(Some(proxy_config))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ProxyConfigToml {
+    url: Option<String>,
+    #[serde(default)]
+    no_proxy: Vec<String>,
+    #[serde(default, rename = "override")]
+    overrides: Vec<ProxyOverrideToml>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ProxyOverrideToml {
+    hosts: Vec<String>,
+    url: String,
 }
 
 fn deserialize_hosts<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
@@ -209,6 +343,36 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_proxy_config() -> anyhow::Result<()> {
+        let config = SpinTlsRuntimeConfig::new("/doesnt-matter");
+
+        let table: toml::Table = toml::from_str(
+            r#"
+            [outbound_proxy]
+            url = "http://proxy.example.com:3128"
+            no_proxy = ["localhost"]
+
+            [[outbound_proxy.override]]
+            hosts = ["*.partner.example.com"]
+            url = "socks5h://gateway.example.com:1080"
+            "#,
+        )?;
+        let runtime_config = config
+            .config_from_table(&table)?
+            .context("missing config section")?;
+        let proxies = runtime_config.proxy_config();
+
+        let proxy = proxies.proxy_for("example.com").context("missing proxy")?;
+        assert_eq!(proxy.url().host_str(), Some("proxy.example.com"));
+        let proxy = proxies
+            .proxy_for("api.partner.example.com")
+            .context("missing proxy")?;
+        assert_eq!(proxy.url().scheme(), "socks5h");
+        assert!(proxies.proxy_for("localhost").is_none());
+        Ok(())
+    }
+
     #[test]
     fn test_max_config() -> anyhow::Result<()> {
         let config = SpinTlsRuntimeConfig::new(TESTDATA_DIR);
@@ -249,6 +413,42 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_host_tls_config() -> anyhow::Result<()> {
+        let config = SpinTlsRuntimeConfig::new(TESTDATA_DIR);
+
+        let host_tls_configs = config
+            .host_tls_configs_from_table(&toml::toml! {
+                [outbound_tls."*.internal.example.com"]
+                client_cert_file = "valid-cert.pem"
+                client_private_key_file = "valid-private-key.pem"
+                min_tls_version = "1.3"
+            })?
+            .context("missing config section")?;
+        assert_eq!(host_tls_configs.len(), 1);
+
+        let host_tls_config = &host_tls_configs[0];
+        assert_eq!(host_tls_config.pattern, "*.internal.example.com");
+        assert!(host_tls_config.tls_config.client_cert.is_some());
+        assert_eq!(
+            host_tls_config.tls_config.min_tls_version,
+            super::super::TlsVersion::Tls13
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_host_tls_config() {
+        let config = SpinTlsRuntimeConfig::new(TESTDATA_DIR);
+
+        config
+            .host_tls_configs_from_table(&toml::toml! {
+                [outbound_tls."example.com"]
+                min_tls_version = "1.0"
+            })
+            .unwrap_err();
+    }
+
     #[test]
     fn test_invalid_cert() {
         let config = SpinTlsRuntimeConfig::new(TESTDATA_DIR);
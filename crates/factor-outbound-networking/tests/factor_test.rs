@@ -64,3 +64,32 @@ async fn wasi_factor_is_optional() -> anyhow::Result<()> {
     .await?;
     Ok(())
 }
+
+#[tokio::test]
+async fn learning_allows_disallowed_addrs() -> anyhow::Result<()> {
+    let mut networking = OutboundNetworkingFactor::new();
+    networking.enable_learning();
+    let factors = TestFactors {
+        wasi: WasiFactor::new(DummyFilesMounter),
+        variables: VariablesFactor::default(),
+        networking,
+    };
+    let env = TestEnvironment::new(factors).extend_manifest(toml! {
+        [component.test-component]
+        source = "does-not-exist.wasm"
+        allowed_outbound_hosts = ["*://192.0.2.1:12345"]
+    });
+    let mut state = env.build_instance_state().await?;
+    let mut wasi = WasiFactor::get_wasi_impl(&mut state).unwrap();
+
+    let network_resource = wasi.instance_network()?;
+    let network = wasi.table().get(&network_resource)?;
+
+    network
+        .check_socket_addr(
+            "192.0.2.2:12345".parse().unwrap(),
+            SocketAddrUse::TcpConnect,
+        )
+        .await?;
+    Ok(())
+}
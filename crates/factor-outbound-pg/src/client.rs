@@ -1,17 +1,25 @@
 use anyhow::{anyhow, Result};
 use native_tls::TlsConnector;
 use postgres_native_tls::MakeTlsConnector;
+use spin_errors::ErrorKind;
+use spin_factor_outbound_networking::ProxyConfig;
 use spin_world::async_trait;
 use spin_world::spin::postgres::postgres::{
     self as v3, Column, DbDataType, DbValue, ParameterValue, RowSet,
 };
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpStream;
+use tokio_postgres::config::Host;
+use tokio_postgres::tls::MakeTlsConnect;
 use tokio_postgres::types::Type;
 use tokio_postgres::{config::SslMode, types::ToSql, Row};
-use tokio_postgres::{Client as TokioClient, NoTls, Socket};
+use tokio_postgres::{Client as TokioClient, NoTls};
 
 #[async_trait]
 pub trait Client {
-    async fn build_client(address: &str) -> Result<Self>
+    /// Connects to the database at `address`, through the proxy configured
+    /// for its host in `proxies`, if any.
+    async fn build_client(address: &str, proxies: &ProxyConfig) -> Result<Self>
     where
         Self: Sized;
 
@@ -30,7 +38,7 @@ pub trait Client {
 
 #[async_trait]
 impl Client for TokioClient {
-    async fn build_client(address: &str) -> Result<Self>
+    async fn build_client(address: &str, proxies: &ProxyConfig) -> Result<Self>
     where
         Self: Sized,
     {
@@ -38,15 +46,49 @@ impl Client for TokioClient {
 
         tracing::debug!("Build new connection: {}", address);
 
+        // Only single-host addresses are proxied, as the proxy can't fail
+        // over between hosts.
+        let proxied = match config.get_hosts() {
+            [Host::Tcp(host)] => match proxies.proxy_for(host) {
+                Some(proxy) => {
+                    let port = config.get_ports().first().copied().unwrap_or(5432);
+                    Some((proxy.connect(host, port).await?, host.clone()))
+                }
+                None => None,
+            },
+            _ => None,
+        };
+
         if config.get_ssl_mode() == SslMode::Disable {
-            let (client, connection) = config.connect(NoTls).await?;
-            spawn_connection(connection);
+            let client = match proxied {
+                Some((stream, _)) => {
+                    let (client, connection) = config.connect_raw(stream, NoTls).await?;
+                    spawn_connection(connection);
+                    client
+                }
+                None => {
+                    let (client, connection) = config.connect(NoTls).await?;
+                    spawn_connection(connection);
+                    client
+                }
+            };
             Ok(client)
         } else {
             let builder = TlsConnector::builder();
-            let connector = MakeTlsConnector::new(builder.build()?);
-            let (client, connection) = config.connect(connector).await?;
-            spawn_connection(connection);
+            let mut connector = MakeTlsConnector::new(builder.build()?);
+            let client = match proxied {
+                Some((stream, host)) => {
+                    let tls = MakeTlsConnect::<TcpStream>::make_tls_connect(&mut connector, &host)?;
+                    let (client, connection) = config.connect_raw(stream, tls).await?;
+                    spawn_connection(connection);
+                    client
+                }
+                None => {
+                    let (client, connection) = config.connect(connector).await?;
+                    spawn_connection(connection);
+                    client
+                }
+            };
             Ok(client)
         }
     }
@@ -69,7 +111,7 @@ impl Client for TokioClient {
 
         self.execute(&statement, params_refs.as_slice())
             .await
-            .map_err(|e| v3::Error::QueryFailed(format!("{:?}", e)))
+            .map_err(query_failed)
     }
 
     async fn query(
@@ -91,7 +133,7 @@ impl Client for TokioClient {
         let results = self
             .query(&statement, params_refs.as_slice())
             .await
-            .map_err(|e| v3::Error::QueryFailed(format!("{:?}", e)))?;
+            .map_err(query_failed)?;
 
         if results.is_empty() {
             return Ok(RowSet {
@@ -111,9 +153,45 @@ impl Client for TokioClient {
     }
 }
 
-fn spawn_connection<T>(connection: tokio_postgres::Connection<Socket, T>)
+fn query_failed(err: tokio_postgres::Error) -> v3::Error {
+    tracing::debug!(error.kind = %error_kind(&err), "Postgres query failed: {err:?}");
+    v3::Error::QueryFailed(format!("{:?}", err))
+}
+
+/// Classifies a Postgres error by its SQLSTATE code, if the server sent one.
+pub(crate) fn error_kind(err: &tokio_postgres::Error) -> ErrorKind {
+    let Some(code) = err.code().map(|state| state.code()) else {
+        return if err.is_closed() {
+            ErrorKind::Connection
+        } else {
+            ErrorKind::classify(err)
+        };
+    };
+    match code {
+        // insufficient_privilege
+        "42501" => ErrorKind::NotAllowed,
+        // undefined_table, undefined_column, undefined_function, invalid_catalog_name
+        "42P01" | "42703" | "42883" | "3D000" => ErrorKind::NotFound,
+        // query_canceled, e.g. by `statement_timeout`
+        "57014" => ErrorKind::Timeout,
+        _ => match &code[..2] {
+            // invalid_authorization_specification, invalid_password
+            "28" => ErrorKind::Auth,
+            // insufficient_resources, e.g. too_many_connections
+            "53" => ErrorKind::ResourceExhausted,
+            // connection_exception
+            "08" => ErrorKind::Connection,
+            // syntax_error_or_access_rule_violation, data_exception
+            "42" | "22" => ErrorKind::InvalidInput,
+            _ => ErrorKind::Other,
+        },
+    }
+}
+
+fn spawn_connection<S, T>(connection: tokio_postgres::Connection<S, T>)
 where
-    T: tokio_postgres::tls::TlsStream + std::marker::Unpin + std::marker::Send + 'static,
+    S: AsyncRead + AsyncWrite + std::marker::Unpin + std::marker::Send + 'static,
+    T: AsyncRead + AsyncWrite + std::marker::Unpin + std::marker::Send + 'static,
 {
     tokio::spawn(async move {
         if let Err(e) = connection.await {
@@ -1,5 +1,7 @@
 use anyhow::Result;
 use spin_core::{async_trait, wasmtime::component::Resource};
+use spin_errors::ErrorKind;
+use spin_rdbms::Dialect;
 use spin_world::spin::postgres::postgres::{self as v3};
 use spin_world::v1::postgres as v1;
 use spin_world::v1::rdbms_types as v1_types;
@@ -19,9 +21,9 @@ impl<C: Client> InstanceState<C> {
     ) -> Result<Resource<Conn>, v3::Error> {
         self.connections
             .push(
-                C::build_client(address)
+                C::build_client(address, &self.proxy_config)
                     .await
-                    .map_err(|e| v3::Error::ConnectionFailed(format!("{e:?}")))?,
+                    .map_err(|e| v3::Error::ConnectionFailed(build_error_message(&e)))?,
             )
             .map_err(|_| v3::Error::ConnectionFailed("too many connections".into()))
             .map(Resource::new_own)
@@ -36,6 +38,21 @@ impl<C: Client> InstanceState<C> {
             .ok_or_else(|| v3::Error::ConnectionFailed("no connection found".into()))
     }
 
+    /// Binds the named parameters of `statement` and, if strict statements
+    /// are enabled, rejects statements that look like values were pasted in.
+    fn prepare_statement(
+        &self,
+        statement: &str,
+        params: Vec<v3::ParameterValue>,
+    ) -> Result<(String, Vec<v3::ParameterValue>), v3::Error> {
+        if self.strict_statements {
+            spin_rdbms::ensure_parameterized(statement, Dialect::Postgres)
+                .map_err(|e| v3::Error::BadParameter(e.to_string()))?;
+        }
+        spin_rdbms::bind_named_params(statement, Dialect::Postgres, params)
+            .map_err(|e| v3::Error::BadParameter(e.to_string()))
+    }
+
     async fn is_address_allowed(&self, address: &str) -> Result<bool> {
         let Ok(config) = address.parse::<tokio_postgres::Config>() else {
             return Ok(false);
@@ -65,6 +82,17 @@ impl<C: Client> InstanceState<C> {
     }
 }
 
+/// Formats an error from [`Client::build_client`] for the guest, logging its
+/// [`ErrorKind`].
+fn build_error_message(err: &anyhow::Error) -> String {
+    let kind = match err.downcast_ref::<tokio_postgres::Error>() {
+        Some(err) => crate::client::error_kind(err),
+        None => ErrorKind::classify(&**err),
+    };
+    tracing::debug!(error.kind = %kind, "Postgres connection failed: {err:?}");
+    format!("{err:?}")
+}
+
 fn v2_params_to_v3(
     params: Vec<v2_types::ParameterValue>,
 ) -> Result<Vec<v3::ParameterValue>, v2::Error> {
@@ -98,6 +126,7 @@ impl<C: Send + Sync + Client> spin_world::spin::postgres::postgres::HostConnecti
         statement: String,
         params: Vec<v3::ParameterValue>,
     ) -> Result<u64, v3::Error> {
+        let (statement, params) = self.prepare_statement(&statement, params)?;
         Ok(self
             .get_client(connection)
             .await?
@@ -112,6 +141,7 @@ impl<C: Send + Sync + Client> spin_world::spin::postgres::postgres::HostConnecti
         statement: String,
         params: Vec<v3::ParameterValue>,
     ) -> Result<v3::RowSet, v3::Error> {
+        let (statement, params) = self.prepare_statement(&statement, params)?;
         Ok(self
             .get_client(connection)
             .await?
@@ -183,10 +213,11 @@ impl<C: Send + Sync + Client> v2::HostConnection for InstanceState<C> {
         statement: String,
         params: Vec<v2_types::ParameterValue>,
     ) -> Result<u64, v2::Error> {
+        let (statement, params) = self.prepare_statement(&statement, v2_params_to_v3(params)?)?;
         Ok(self
             .get_client(connection)
             .await?
-            .execute(statement, v2_params_to_v3(params)?)
+            .execute(statement, params)
             .await?)
     }
 
@@ -197,10 +228,11 @@ impl<C: Send + Sync + Client> v2::HostConnection for InstanceState<C> {
         statement: String,
         params: Vec<v2_types::ParameterValue>,
     ) -> Result<v2_types::RowSet, v2::Error> {
+        let (statement, params) = self.prepare_statement(&statement, v2_params_to_v3(params)?)?;
         Ok(self
             .get_client(connection)
             .await?
-            .query(statement, v2_params_to_v3(params)?)
+            .query(statement, params)
             .await?
             .into())
     }
@@ -1,8 +1,13 @@
 pub mod client;
 mod host;
 
+use std::sync::Arc;
+
 use client::Client;
-use spin_factor_outbound_networking::{OutboundAllowedHosts, OutboundNetworkingFactor};
+use serde::Deserialize;
+use spin_factor_outbound_networking::{
+    OutboundAllowedHosts, OutboundNetworkingFactor, ProxyConfig,
+};
 use spin_factors::{
     anyhow, ConfigureAppContext, Factor, PrepareContext, RuntimeFactors, SelfInstanceBuilder,
 };
@@ -13,8 +18,8 @@ pub struct OutboundPgFactor<C = PgClient> {
 }
 
 impl<C: Send + Sync + Client + 'static> Factor for OutboundPgFactor<C> {
-    type RuntimeConfig = ();
-    type AppState = ();
+    type RuntimeConfig = RuntimeConfig;
+    type AppState = RuntimeConfig;
     type InstanceBuilder = InstanceState<C>;
 
     fn init<T: Send + 'static>(
@@ -29,21 +34,23 @@ impl<C: Send + Sync + Client + 'static> Factor for OutboundPgFactor<C> {
 
     fn configure_app<T: RuntimeFactors>(
         &self,
-        _ctx: ConfigureAppContext<T, Self>,
+        mut ctx: ConfigureAppContext<T, Self>,
     ) -> anyhow::Result<Self::AppState> {
-        Ok(())
+        Ok(ctx.take_runtime_config().unwrap_or_default())
     }
 
     fn prepare<T: RuntimeFactors>(
         &self,
         mut ctx: PrepareContext<T, Self>,
     ) -> anyhow::Result<Self::InstanceBuilder> {
-        let allowed_hosts = ctx
-            .instance_builder::<OutboundNetworkingFactor>()?
-            .allowed_hosts();
+        let outbound_networking = ctx.instance_builder::<OutboundNetworkingFactor>()?;
+        let allowed_hosts = outbound_networking.allowed_hosts();
+        let proxy_config = outbound_networking.proxy_config().clone();
         Ok(InstanceState {
             allowed_hosts,
+            proxy_config,
             connections: Default::default(),
+            strict_statements: ctx.app_state().strict_statements,
         })
     }
 }
@@ -62,9 +69,22 @@ impl<C> OutboundPgFactor<C> {
     }
 }
 
+/// Runtime configuration for outbound Postgres, from the `[outbound_pg]`
+/// runtime config section.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RuntimeConfig {
+    /// Reject statements that [`spin_rdbms::lint`] flags as possibly built
+    /// by pasting values into SQL.
+    #[serde(default)]
+    pub strict_statements: bool,
+}
+
 pub struct InstanceState<C> {
     allowed_hosts: OutboundAllowedHosts,
+    proxy_config: Arc<ProxyConfig>,
     connections: spin_resource_table::Table<C>,
+    strict_statements: bool,
 }
 
 impl<C: Send + 'static> SelfInstanceBuilder for InstanceState<C> {}
@@ -1,7 +1,7 @@
 use anyhow::{bail, Result};
-use spin_factor_outbound_networking::OutboundNetworkingFactor;
+use spin_factor_outbound_networking::{OutboundNetworkingFactor, ProxyConfig};
 use spin_factor_outbound_pg::client::Client;
-use spin_factor_outbound_pg::OutboundPgFactor;
+use spin_factor_outbound_pg::{OutboundPgFactor, RuntimeConfig};
 use spin_factor_variables::VariablesFactor;
 use spin_factors::{anyhow, RuntimeFactors};
 use spin_factors_test::{toml, TestEnvironment};
@@ -9,7 +9,7 @@ use spin_world::async_trait;
 use spin_world::spin::postgres::postgres::Error as PgError;
 use spin_world::spin::postgres::postgres::HostConnection;
 use spin_world::spin::postgres::postgres::{self as v2};
-use spin_world::spin::postgres::postgres::{ParameterValue, RowSet};
+use spin_world::spin::postgres::postgres::{Column, DbDataType, DbValue, ParameterValue, RowSet};
 
 #[derive(RuntimeFactors)]
 struct TestFactors {
@@ -103,12 +103,72 @@ async fn exercise_query() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+async fn named_parameters_are_bound() -> anyhow::Result<()> {
+    let mut state = test_env().build_instance_state().await?;
+
+    let connection = state
+        .pg
+        .open("postgres://localhost:5432/test".to_string())
+        .await?;
+
+    let rows = state
+        .pg
+        .query(
+            connection,
+            "SELECT * FROM test WHERE a = :a OR b = :a".to_string(),
+            vec![ParameterValue::Int32(1)],
+        )
+        .await?;
+    assert_eq!(
+        rows.columns[0].name,
+        "SELECT * FROM test WHERE a = $1 OR b = $1"
+    );
+    assert!(matches!(rows.rows[0][0], DbValue::Int64(1)));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn strict_statements_reject_string_literals() -> anyhow::Result<()> {
+    let runtime_config = TestFactorsRuntimeConfig {
+        pg: Some(RuntimeConfig {
+            strict_statements: true,
+        }),
+        ..Default::default()
+    };
+    let mut state = test_env()
+        .runtime_config(runtime_config)?
+        .build_instance_state()
+        .await?;
+
+    let connection = state
+        .pg
+        .open("postgres://localhost:5432/test".to_string())
+        .await?;
+
+    let res = state
+        .pg
+        .query(
+            connection,
+            "SELECT * FROM test WHERE name = 'alice'".to_string(),
+            vec![],
+        )
+        .await;
+    let Err(PgError::BadParameter(message)) = res else {
+        bail!("expected BadParameter, got {res:?}");
+    };
+    assert!(message.contains("inline string literal"), "{message}");
+
+    Ok(())
+}
+
 // TODO: We can expand this mock to track calls and simulate return values
 pub struct MockClient {}
 
 #[async_trait]
 impl Client for MockClient {
-    async fn build_client(_address: &str) -> anyhow::Result<Self>
+    async fn build_client(_address: &str, _proxies: &ProxyConfig) -> anyhow::Result<Self>
     where
         Self: Sized,
     {
@@ -123,14 +183,19 @@ impl Client for MockClient {
         Ok(0)
     }
 
+    /// Returns the statement as the only column name, and the number of
+    /// parameters as the only row.
     async fn query(
         &self,
-        _statement: String,
-        _params: Vec<ParameterValue>,
+        statement: String,
+        params: Vec<ParameterValue>,
     ) -> Result<RowSet, v2::Error> {
         Ok(RowSet {
-            columns: vec![],
-            rows: vec![],
+            columns: vec![Column {
+                name: statement,
+                data_type: DbDataType::Int64,
+            }],
+            rows: vec![vec![DbValue::Int64(params.len() as i64)]],
         })
     }
 }
@@ -1,6 +1,7 @@
 use anyhow::Result;
 use redis::{aio::MultiplexedConnection, AsyncCommands, FromRedisValue, Value};
 use spin_core::{async_trait, wasmtime::component::Resource};
+use spin_errors::ErrorKind;
 use spin_factor_outbound_networking::OutboundAllowedHosts;
 use spin_world::v1::{redis as v1, redis_types};
 use spin_world::v2::redis::{
@@ -27,7 +28,7 @@ impl InstanceState {
             .map_err(|_| Error::InvalidAddress)?
             .get_multiplexed_async_connection()
             .await
-            .map_err(other_error)?;
+            .map_err(redis_error)?;
         self.connections
             .push(conn)
             .map(Resource::new_own)
@@ -77,12 +78,13 @@ impl v2::HostConnection for crate::InstanceState {
         payload: Vec<u8>,
     ) -> Result<(), Error> {
         let conn = self.get_conn(connection).await.map_err(other_error)?;
+        let payload = spin_telemetry::embed_trace_context(payload);
         // The `let () =` syntax is needed to suppress a warning when the result type is inferred.
         // You can read more about the issue here: <https://github.com/redis-rs/redis-rs/issues/1228>
         let () = conn
             .publish(&channel, &payload)
             .await
-            .map_err(other_error)?;
+            .map_err(redis_error)?;
         Ok(())
     }
 
@@ -93,7 +95,7 @@ impl v2::HostConnection for crate::InstanceState {
         key: String,
     ) -> Result<Option<Vec<u8>>, Error> {
         let conn = self.get_conn(connection).await.map_err(other_error)?;
-        let value = conn.get(&key).await.map_err(other_error)?;
+        let value = conn.get(&key).await.map_err(redis_error)?;
         Ok(value)
     }
 
@@ -107,7 +109,7 @@ impl v2::HostConnection for crate::InstanceState {
         let conn = self.get_conn(connection).await.map_err(other_error)?;
         // The `let () =` syntax is needed to suppress a warning when the result type is inferred.
         // You can read more about the issue here: <https://github.com/redis-rs/redis-rs/issues/1228>
-        let () = conn.set(&key, &value).await.map_err(other_error)?;
+        let () = conn.set(&key, &value).await.map_err(redis_error)?;
         Ok(())
     }
 
@@ -118,7 +120,7 @@ impl v2::HostConnection for crate::InstanceState {
         key: String,
     ) -> Result<i64, Error> {
         let conn = self.get_conn(connection).await.map_err(other_error)?;
-        let value = conn.incr(&key, 1).await.map_err(other_error)?;
+        let value = conn.incr(&key, 1).await.map_err(redis_error)?;
         Ok(value)
     }
 
@@ -129,7 +131,7 @@ impl v2::HostConnection for crate::InstanceState {
         keys: Vec<String>,
     ) -> Result<u32, Error> {
         let conn = self.get_conn(connection).await.map_err(other_error)?;
-        let value = conn.del(&keys).await.map_err(other_error)?;
+        let value = conn.del(&keys).await.map_err(redis_error)?;
         Ok(value)
     }
 
@@ -145,7 +147,7 @@ impl v2::HostConnection for crate::InstanceState {
             if e.kind() == redis::ErrorKind::TypeError {
                 Error::TypeError
             } else {
-                Error::Other(e.to_string())
+                redis_error(e)
             }
         })?;
         Ok(value)
@@ -158,7 +160,7 @@ impl v2::HostConnection for crate::InstanceState {
         key: String,
     ) -> Result<Vec<String>, Error> {
         let conn = self.get_conn(connection).await.map_err(other_error)?;
-        let value = conn.smembers(&key).await.map_err(other_error)?;
+        let value = conn.smembers(&key).await.map_err(redis_error)?;
         Ok(value)
     }
 
@@ -170,7 +172,7 @@ impl v2::HostConnection for crate::InstanceState {
         values: Vec<String>,
     ) -> Result<u32, Error> {
         let conn = self.get_conn(connection).await.map_err(other_error)?;
-        let value = conn.srem(&key, &values).await.map_err(other_error)?;
+        let value = conn.srem(&key, &values).await.map_err(redis_error)?;
         Ok(value)
     }
 
@@ -195,7 +197,7 @@ impl v2::HostConnection for crate::InstanceState {
         cmd.query_async::<_, RedisResults>(conn)
             .await
             .map(|values| values.0)
-            .map_err(other_error)
+            .map_err(redis_error)
     }
 
     async fn drop(&mut self, connection: Resource<RedisConnection>) -> anyhow::Result<()> {
@@ -208,6 +210,31 @@ fn other_error(e: impl std::fmt::Display) -> Error {
     Error::Other(e.to_string())
 }
 
+/// Like [`other_error`], logging the [`ErrorKind`] of the Redis error.
+fn redis_error(err: redis::RedisError) -> Error {
+    tracing::debug!(error.kind = %error_kind(&err), "Redis error: {err}");
+    other_error(err)
+}
+
+/// Classifies a Redis error by its kind or the server's error code.
+fn error_kind(err: &redis::RedisError) -> ErrorKind {
+    if err.is_timeout() {
+        return ErrorKind::Timeout;
+    }
+    if err.is_connection_refusal() || err.is_connection_dropped() {
+        return ErrorKind::Connection;
+    }
+    match (err.kind(), err.code()) {
+        (redis::ErrorKind::AuthenticationFailed, _) | (_, Some("NOAUTH" | "WRONGPASS")) => {
+            ErrorKind::Auth
+        }
+        (_, Some("NOPERM")) => ErrorKind::NotAllowed,
+        (_, Some("OOM")) => ErrorKind::ResourceExhausted,
+        (redis::ErrorKind::InvalidClientConfig, _) => ErrorKind::InvalidInput,
+        _ => ErrorKind::classify(err),
+    }
+}
+
 /// Delegate a function call to the v2::HostConnection implementation
 macro_rules! delegate {
     ($self:ident.$name:ident($address:expr, $($arg:expr),*)) => {{
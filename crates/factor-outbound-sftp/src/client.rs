@@ -0,0 +1,243 @@
+use std::{sync::Arc, time::Duration};
+
+use russh::client;
+use russh_keys::key::PublicKey;
+use russh_sftp::{
+    client::{fs::File, SftpSession},
+    protocol::StatusCode,
+};
+use spin_world::{async_trait, spin::sftp::sftp::Error};
+
+/// How long connecting to and authenticating with a server may take.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long a connection may go without any traffic before it is closed.
+const INACTIVITY_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+/// A connection to an SFTP server.
+pub struct SftpConnection {
+    // Dropping the SSH session closes the connection.
+    _session: client::Handle<HostKeyCheck>,
+    sftp: SftpSession,
+}
+
+impl SftpConnection {
+    /// Connects to `host` and authenticates as `username`, with `password` or
+    /// `private_key`, failing unless the server's host key is `host_key`.
+    ///
+    /// Fails if that takes longer than [`CONNECT_TIMEOUT`]. Once connected,
+    /// each SFTP request times out on its own, and the connection is closed
+    /// after [`INACTIVITY_TIMEOUT`] without traffic.
+    pub async fn connect(
+        host: &str,
+        port: u16,
+        host_key: &str,
+        username: &str,
+        password: Option<&str>,
+        private_key: Option<&str>,
+    ) -> Result<Self, Error> {
+        let connect =
+            Self::connect_without_timeout(host, port, host_key, username, password, private_key);
+        tokio::time::timeout(CONNECT_TIMEOUT, connect)
+            .await
+            .map_err(|_| {
+                Error::Other(format!(
+                    "timed out connecting to {host}:{port} after {CONNECT_TIMEOUT:?}"
+                ))
+            })?
+    }
+
+    async fn connect_without_timeout(
+        host: &str,
+        port: u16,
+        host_key: &str,
+        username: &str,
+        password: Option<&str>,
+        private_key: Option<&str>,
+    ) -> Result<Self, Error> {
+        let check = HostKeyCheck {
+            expected: HostKey::parse(host_key)?,
+        };
+        let config = Arc::new(client::Config {
+            inactivity_timeout: Some(INACTIVITY_TIMEOUT),
+            ..Default::default()
+        });
+        let mut session = client::connect(config, (host, port), check)
+            .await
+            .map_err(|err| match err {
+                russh::Error::UnknownKey => Error::HostKeyMismatch,
+                err => other_error(err),
+            })?;
+
+        let mut authenticated = false;
+        if let Some(private_key) = private_key {
+            let key = russh_keys::decode_secret_key(private_key, None)
+                .map_err(|err| Error::Other(format!("invalid private key: {err}")))?;
+            authenticated = session
+                .authenticate_publickey(username, Arc::new(key))
+                .await
+                .map_err(other_error)?;
+        }
+        if let (false, Some(password)) = (authenticated, password) {
+            authenticated = session
+                .authenticate_password(username, password)
+                .await
+                .map_err(other_error)?;
+        }
+        if !authenticated {
+            return Err(Error::AuthenticationFailed);
+        }
+
+        let channel = session.channel_open_session().await.map_err(other_error)?;
+        channel
+            .request_subsystem(true, "sftp")
+            .await
+            .map_err(other_error)?;
+        let sftp = SftpSession::new(channel.into_stream())
+            .await
+            .map_err(sftp_error)?;
+        Ok(Self {
+            _session: session,
+            sftp,
+        })
+    }
+
+    /// Lists the entries in the directory at `path`.
+    pub async fn list(&self, path: &str) -> Result<Vec<DirEntry>, Error> {
+        let entries = self.sftp.read_dir(path).await.map_err(sftp_error)?;
+        Ok(entries
+            .map(|entry| {
+                let metadata = entry.metadata();
+                DirEntry {
+                    name: entry.file_name(),
+                    is_directory: entry.file_type().is_dir(),
+                    size: metadata.size,
+                    modified_secs: metadata.mtime.map(u64::from),
+                }
+            })
+            .collect())
+    }
+
+    /// Opens the file at `path` for reading.
+    pub async fn get(&self, path: &str) -> Result<File, Error> {
+        self.sftp.open(path).await.map_err(sftp_error)
+    }
+
+    /// Creates or truncates the file at `path` for writing.
+    pub async fn put(&self, path: &str) -> Result<File, Error> {
+        self.sftp.create(path).await.map_err(sftp_error)
+    }
+}
+
+/// An entry in a directory.
+pub struct DirEntry {
+    pub name: String,
+    pub is_directory: bool,
+    pub size: Option<u64>,
+    pub modified_secs: Option<u64>,
+}
+
+/// A pinned host key.
+enum HostKey {
+    /// The SHA-256 fingerprint of the key, base64-encoded without padding.
+    Fingerprint(String),
+    /// The key itself.
+    PublicKey(PublicKey),
+}
+
+impl HostKey {
+    /// Parses an OpenSSH public key line, e.g. `ssh-ed25519 AAAA... comment`,
+    /// or a fingerprint, e.g. `SHA256:...`.
+    fn parse(host_key: &str) -> Result<Self, Error> {
+        let host_key = host_key.trim();
+        if let Some(fingerprint) = host_key.strip_prefix("SHA256:") {
+            return Ok(Self::Fingerprint(
+                fingerprint.trim_end_matches('=').to_owned(),
+            ));
+        }
+        let mut fields = host_key.split_whitespace();
+        let key = match (fields.next(), fields.next()) {
+            (Some(_algorithm), Some(key)) => key,
+            (Some(key), None) => key,
+            _ => return Err(Error::Other("no host key given".into())),
+        };
+        russh_keys::parse_public_key_base64(key)
+            .map(Self::PublicKey)
+            .map_err(|err| Error::Other(format!("invalid host key {host_key:?}: {err}")))
+    }
+
+    fn matches(&self, key: &PublicKey) -> bool {
+        match self {
+            Self::Fingerprint(fingerprint) => key.fingerprint() == *fingerprint,
+            Self::PublicKey(expected) => key.fingerprint() == expected.fingerprint(),
+        }
+    }
+}
+
+/// Accepts only servers with the pinned host key.
+pub struct HostKeyCheck {
+    expected: HostKey,
+}
+
+#[async_trait]
+impl client::Handler for HostKeyCheck {
+    type Error = russh::Error;
+
+    async fn check_server_key(
+        &mut self,
+        server_public_key: &PublicKey,
+    ) -> Result<bool, Self::Error> {
+        let matches = self.expected.matches(server_public_key);
+        if !matches {
+            tracing::warn!(
+                "SFTP server host key SHA256:{} doesn't match the pinned host key",
+                server_public_key.fingerprint()
+            );
+        }
+        Ok(matches)
+    }
+}
+
+fn sftp_error(err: russh_sftp::client::error::Error) -> Error {
+    match &err {
+        russh_sftp::client::error::Error::Status(status) => match status.status_code {
+            StatusCode::NoSuchFile => Error::NotFound,
+            StatusCode::PermissionDenied => Error::PermissionDenied,
+            _ => other_error(err),
+        },
+        _ => other_error(err),
+    }
+}
+
+fn other_error(err: impl std::fmt::Display) -> Error {
+    Error::Other(err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &str = "AAAAC3NzaC1lZDI1NTE5AAAAIAcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8gISIjJCUm";
+
+    #[test]
+    fn host_keys_are_pinned_by_key_or_fingerprint() {
+        let key = russh_keys::parse_public_key_base64(KEY).unwrap();
+        let fingerprint = format!("SHA256:{}", key.fingerprint());
+
+        for pinned in [
+            format!("ssh-ed25519 {KEY} someone@example.com"),
+            KEY.to_owned(),
+            fingerprint.clone(),
+            format!("{fingerprint}="),
+        ] {
+            assert!(HostKey::parse(&pinned).unwrap().matches(&key), "{pinned}");
+        }
+        assert!(!HostKey::parse("SHA256:nope").unwrap().matches(&key));
+    }
+
+    #[test]
+    fn invalid_host_keys_are_rejected() {
+        assert!(HostKey::parse("").is_err());
+        assert!(HostKey::parse("ssh-ed25519 not-a-key").is_err());
+    }
+}
@@ -0,0 +1,151 @@
+use spin_factors::{
+    anyhow,
+    wasmtime::component::{Resource, ResourceTable},
+    InitContext,
+};
+use spin_world::{
+    async_trait,
+    spin::sftp::sftp::{self, ConnectOptions, Connection, DirEntry, Error},
+};
+use tracing::{field::Empty, instrument, Level};
+use url::Url;
+use wasmtime_wasi::{
+    pipe::{AsyncReadStream, AsyncWriteStream},
+    InputStream, OutputStream,
+};
+
+use crate::{client::SftpConnection, InstanceState, OutboundSftpFactor};
+
+/// The most bytes a guest may write to a `put` stream before waiting for them
+/// to be sent.
+const WRITE_BUDGET: usize = 1024 * 1024;
+
+pub(crate) fn add_to_linker<T: Send + 'static>(
+    ctx: &mut InitContext<T, OutboundSftpFactor>,
+) -> anyhow::Result<()> {
+    fn type_annotate<T, F>(f: F) -> F
+    where
+        F: Fn(&mut T) -> SftpImpl,
+    {
+        f
+    }
+    let get_data_with_table = ctx.get_data_with_table_fn();
+    let closure = type_annotate(move |data| {
+        let (state, table) = get_data_with_table(data);
+        SftpImpl { state, table }
+    });
+    sftp::add_to_linker_get_host(ctx.linker(), closure)
+}
+
+/// The instance state and resource table, which holds the streams of files.
+struct SftpImpl<'a> {
+    state: &'a mut InstanceState,
+    table: &'a mut ResourceTable,
+}
+
+impl SftpImpl<'_> {
+    fn get_conn(&self, connection: &Resource<Connection>) -> Result<&SftpConnection, Error> {
+        self.state
+            .connections
+            .get(connection.rep())
+            .ok_or_else(|| Error::Other("could not find connection for resource".into()))
+    }
+}
+
+impl sftp::Host for SftpImpl<'_> {
+    fn convert_error(&mut self, error: Error) -> anyhow::Result<Error> {
+        Ok(error)
+    }
+}
+
+#[async_trait]
+impl sftp::HostConnection for SftpImpl<'_> {
+    #[instrument(name = "spin_outbound_sftp.open_connection", skip(self, options), err(level = Level::INFO), fields(otel.kind = "client", server.address = Empty, server.port = Empty))]
+    async fn open(
+        &mut self,
+        address: String,
+        options: ConnectOptions,
+    ) -> Result<Resource<Connection>, Error> {
+        let allowed = self
+            .state
+            .allowed_hosts
+            .check_url(&address, "sftp")
+            .await
+            .map_err(|e| Error::Other(e.to_string()))?;
+        if !allowed {
+            return Err(Error::AddressNotAllowed);
+        }
+        let url = Url::parse(&address).map_err(|_| Error::AddressNotAllowed)?;
+        let (Some(host), "sftp") = (url.host_str(), url.scheme()) else {
+            return Err(Error::AddressNotAllowed);
+        };
+        let port = url.port().unwrap_or(22);
+        let span = tracing::Span::current();
+        span.record("server.address", host);
+        span.record("server.port", port);
+
+        let connection = SftpConnection::connect(
+            host.trim_start_matches('[').trim_end_matches(']'),
+            port,
+            &options.host_key,
+            &options.username,
+            options.password.as_deref(),
+            options.private_key.as_deref(),
+        )
+        .await?;
+        self.state
+            .connections
+            .push(connection)
+            .map(Resource::new_own)
+            .map_err(|_| Error::TooManyConnections)
+    }
+
+    #[instrument(name = "spin_outbound_sftp.list", skip(self, connection), err(level = Level::INFO), fields(otel.kind = "client"))]
+    async fn list(
+        &mut self,
+        connection: Resource<Connection>,
+        path: String,
+    ) -> Result<Vec<DirEntry>, Error> {
+        let entries = self.get_conn(&connection)?.list(&path).await?;
+        Ok(entries
+            .into_iter()
+            .map(|entry| DirEntry {
+                name: entry.name,
+                is_directory: entry.is_directory,
+                size: entry.size,
+                modified_secs: entry.modified_secs,
+            })
+            .collect())
+    }
+
+    #[instrument(name = "spin_outbound_sftp.get", skip(self, connection), err(level = Level::INFO), fields(otel.kind = "client"))]
+    async fn get(
+        &mut self,
+        connection: Resource<Connection>,
+        path: String,
+    ) -> Result<Resource<InputStream>, Error> {
+        let file = self.get_conn(&connection)?.get(&path).await?;
+        let stream: InputStream = Box::new(AsyncReadStream::new(file));
+        self.table
+            .push(stream)
+            .map_err(|e| Error::Other(e.to_string()))
+    }
+
+    #[instrument(name = "spin_outbound_sftp.put", skip(self, connection), err(level = Level::INFO), fields(otel.kind = "client"))]
+    async fn put(
+        &mut self,
+        connection: Resource<Connection>,
+        path: String,
+    ) -> Result<Resource<OutputStream>, Error> {
+        let file = self.get_conn(&connection)?.put(&path).await?;
+        let stream: OutputStream = Box::new(AsyncWriteStream::new(WRITE_BUDGET, file));
+        self.table
+            .push(stream)
+            .map_err(|e| Error::Other(e.to_string()))
+    }
+
+    async fn drop(&mut self, connection: Resource<Connection>) -> anyhow::Result<()> {
+        self.state.connections.remove(connection.rep());
+        Ok(())
+    }
+}
@@ -0,0 +1,59 @@
+mod client;
+mod host;
+
+use client::SftpConnection;
+use spin_factor_outbound_networking::{OutboundAllowedHosts, OutboundNetworkingFactor};
+use spin_factors::{
+    anyhow, ConfigureAppContext, Factor, InitContext, PrepareContext, RuntimeFactors,
+    SelfInstanceBuilder,
+};
+use spin_resource_table::Table;
+
+/// The [`Factor`] for `spin:sftp/sftp`.
+#[derive(Default)]
+pub struct OutboundSftpFactor {
+    _priv: (),
+}
+
+impl OutboundSftpFactor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Factor for OutboundSftpFactor {
+    type RuntimeConfig = ();
+    type AppState = ();
+    type InstanceBuilder = InstanceState;
+
+    fn init<T: Send + 'static>(&mut self, mut ctx: InitContext<T, Self>) -> anyhow::Result<()> {
+        host::add_to_linker(&mut ctx)
+    }
+
+    fn configure_app<T: RuntimeFactors>(
+        &self,
+        _ctx: ConfigureAppContext<T, Self>,
+    ) -> anyhow::Result<Self::AppState> {
+        Ok(())
+    }
+
+    fn prepare<T: RuntimeFactors>(
+        &self,
+        mut ctx: PrepareContext<T, Self>,
+    ) -> anyhow::Result<Self::InstanceBuilder> {
+        let allowed_hosts = ctx
+            .instance_builder::<OutboundNetworkingFactor>()?
+            .allowed_hosts();
+        Ok(InstanceState {
+            allowed_hosts,
+            connections: Table::new(128),
+        })
+    }
+}
+
+pub struct InstanceState {
+    allowed_hosts: OutboundAllowedHosts,
+    connections: Table<SftpConnection>,
+}
+
+impl SelfInstanceBuilder for InstanceState {}
@@ -0,0 +1,208 @@
+//! Dumping SQLite databases to SQL scripts and loading them, e.g. to move an
+//! app's data from a local database to libSQL.
+//!
+//! A dump creates the database's tables, inserts their rows, and then creates
+//! its indexes, triggers and views, all in one transaction. Virtual tables,
+//! such as full-text search indexes, are not supported.
+
+use std::io::Write;
+
+use spin_factors::anyhow::{self, bail, Context};
+use spin_world::v2::sqlite as v2;
+
+use crate::Connection;
+
+/// The first line of a dump.
+const DUMP_HEADER: &str = "-- Spin SQLite dump";
+
+/// How many rows are read from a table at a time.
+const BATCH_SIZE: usize = 1000;
+
+/// Writes the schema and contents of the database to `writer` as a SQL
+/// script. Rows are read and written a batch at a time, so tables needn't fit
+/// in memory.
+pub async fn dump(connection: &dyn Connection, mut writer: impl Write) -> anyhow::Result<()> {
+    let schema = connection
+        .query(
+            "SELECT type, name, sql FROM sqlite_master \
+             WHERE sql IS NOT NULL AND name NOT LIKE 'sqlite_%' \
+             ORDER BY CASE type WHEN 'table' THEN 0 ELSE 1 END, rowid",
+            vec![],
+        )
+        .await
+        .context("failed to read database schema")?;
+
+    writeln!(writer, "{DUMP_HEADER}\nBEGIN TRANSACTION;")?;
+    let mut tables_done = false;
+    for row in schema.rows {
+        let [v2::Value::Text(ty), v2::Value::Text(name), v2::Value::Text(sql)] =
+            row.values.as_slice()
+        else {
+            bail!("unexpected database schema row {:?}", row.values);
+        };
+        if sql.to_ascii_uppercase().starts_with("CREATE VIRTUAL TABLE") {
+            bail!("table {name:?} is a virtual table, which can't be dumped");
+        }
+        // Tables come first, so the sequences are set once their rows are in.
+        if ty != "table" && !tables_done {
+            dump_sequences(connection, &mut writer).await?;
+            tables_done = true;
+        }
+        writeln!(writer, "{sql};")?;
+        if ty == "table" {
+            dump_rows(connection, name, sql, &mut writer)
+                .await
+                .with_context(|| format!("failed to dump table {name:?}"))?;
+        }
+    }
+    if !tables_done {
+        dump_sequences(connection, &mut writer).await?;
+    }
+    writeln!(writer, "COMMIT;")?;
+    writer.flush()?;
+    Ok(())
+}
+
+async fn dump_rows(
+    connection: &dyn Connection,
+    table: &str,
+    sql: &str,
+    writer: &mut impl Write,
+) -> anyhow::Result<()> {
+    let table = quote_identifier(table);
+    // Tables with rowids are read in rowid order, from after the last row
+    // read; others are read in pages.
+    let has_rowid = !sql.to_ascii_uppercase().contains("WITHOUT ROWID");
+    let mut last_rowid = None;
+    let mut offset = 0;
+    loop {
+        let rows = if has_rowid {
+            connection
+                .query(
+                    &format!(
+                        "SELECT rowid, * FROM {table} WHERE ?1 IS NULL OR rowid > ?1 ORDER BY rowid LIMIT {BATCH_SIZE}"
+                    ),
+                    vec![last_rowid.map_or(v2::Value::Null, v2::Value::Integer)],
+                )
+                .await?
+                .rows
+        } else {
+            connection
+                .query(
+                    &format!("SELECT * FROM {table} LIMIT {BATCH_SIZE} OFFSET {offset}"),
+                    vec![],
+                )
+                .await?
+                .rows
+        };
+        for row in &rows {
+            let mut values = row.values.as_slice();
+            if has_rowid {
+                let [v2::Value::Integer(rowid), rest @ ..] = values else {
+                    bail!("unexpected rowid in {:?}", row.values);
+                };
+                last_rowid = Some(*rowid);
+                values = rest;
+            }
+            let values = values.iter().map(literal).collect::<Vec<_>>().join(",");
+            writeln!(writer, "INSERT INTO {table} VALUES({values});")?;
+        }
+        if rows.len() < BATCH_SIZE {
+            return Ok(());
+        }
+        offset += rows.len();
+    }
+}
+
+/// Writes the AUTOINCREMENT counters in `sqlite_sequence`, if the database
+/// has any, so that rowids aren't reused after loading.
+async fn dump_sequences(
+    connection: &dyn Connection,
+    writer: &mut impl Write,
+) -> anyhow::Result<()> {
+    let exists = connection
+        .query(
+            "SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'sqlite_sequence'",
+            vec![],
+        )
+        .await
+        .context("failed to read database schema")?;
+    if exists.rows.is_empty() {
+        return Ok(());
+    }
+    let sequences = connection
+        .query("SELECT name, seq FROM sqlite_sequence", vec![])
+        .await
+        .context("failed to dump AUTOINCREMENT counters")?;
+    // Loading the tables' rows creates sequences, which are replaced.
+    writeln!(writer, "DELETE FROM sqlite_sequence;")?;
+    for row in sequences.rows {
+        let values = row.values.iter().map(literal).collect::<Vec<_>>().join(",");
+        writeln!(writer, "INSERT INTO sqlite_sequence VALUES({values});")?;
+    }
+    Ok(())
+}
+
+/// Loads a dump made by [`dump`] into the database. The database should not
+/// already have any of the dumped tables; if loading fails, nothing is
+/// changed.
+pub async fn load(connection: &dyn Connection, script: &str) -> anyhow::Result<()> {
+    if !script.starts_with(DUMP_HEADER) {
+        bail!("not a Spin SQLite dump: it does not start with {DUMP_HEADER:?}");
+    }
+    if let Err(err) = connection.execute_batch(script).await {
+        let _ = connection.execute_batch("ROLLBACK").await;
+        return Err(err.context("failed to load dump"));
+    }
+    Ok(())
+}
+
+fn quote_identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+/// The SQL literal for a value.
+fn literal(value: &v2::Value) -> String {
+    match value {
+        v2::Value::Integer(i) => i.to_string(),
+        // SQLite stores NaN as NULL, and reads infinities from overlarge
+        // literals.
+        v2::Value::Real(r) if r.is_nan() => "NULL".to_owned(),
+        v2::Value::Real(r) if r.is_infinite() => {
+            let literal = if r.is_sign_positive() {
+                "9e999"
+            } else {
+                "-9e999"
+            };
+            literal.to_owned()
+        }
+        v2::Value::Real(r) => format!("{r:?}"),
+        v2::Value::Text(text) => format!("'{}'", text.replace('\'', "''")),
+        v2::Value::Blob(bytes) => {
+            let hex = bytes.iter().map(|b| format!("{b:02x}")).collect::<String>();
+            format!("X'{hex}'")
+        }
+        v2::Value::Null => "NULL".to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values_are_written_as_literals() {
+        assert_eq!("-3", literal(&v2::Value::Integer(-3)));
+        assert_eq!("1.0", literal(&v2::Value::Real(1.0)));
+        assert_eq!("-9e999", literal(&v2::Value::Real(f64::NEG_INFINITY)));
+        assert_eq!("NULL", literal(&v2::Value::Real(f64::NAN)));
+        assert_eq!("'it''s'", literal(&v2::Value::Text("it's".into())));
+        assert_eq!("X'00ff'", literal(&v2::Value::Blob(vec![0, 255])));
+        assert_eq!("NULL", literal(&v2::Value::Null));
+    }
+
+    #[test]
+    fn identifiers_are_quoted() {
+        assert_eq!("\"my \"\"table\"\"\"", quote_identifier("my \"table\""));
+    }
+}
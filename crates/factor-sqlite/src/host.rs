@@ -5,6 +5,7 @@ use async_trait::async_trait;
 
 use spin_factors::wasmtime::component::Resource;
 use spin_factors::{anyhow, SelfInstanceBuilder};
+use spin_world::spin::sqlite::sqlite as v3;
 use spin_world::v1::sqlite as v1;
 use spin_world::v2::sqlite as v2;
 use tracing::field::Empty;
@@ -50,6 +51,17 @@ impl InstanceState {
     pub fn allowed_databases(&self) -> &HashSet<String> {
         &self.allowed_databases
     }
+
+    /// Wraps the connection creator of each database used by this instance,
+    /// e.g. to observe or substitute database operations.
+    pub fn wrap_connection_creators(
+        &mut self,
+        wrap: impl Fn(&str, Arc<dyn ConnectionCreator>) -> Arc<dyn ConnectionCreator>,
+    ) {
+        for (label, creator) in &mut self.connection_creators {
+            *creator = wrap(label, creator.clone());
+        }
+    }
 }
 
 impl SelfInstanceBuilder for InstanceState {}
@@ -107,6 +119,69 @@ impl v2::HostConnection for InstanceState {
     }
 }
 
+impl v3::Host for InstanceState {
+    fn convert_error(&mut self, error: v3::Error) -> anyhow::Result<v3::Error> {
+        Ok(error)
+    }
+}
+
+#[async_trait]
+impl v3::HostConnection for InstanceState {
+    async fn open(&mut self, database: String) -> Result<Resource<v3::Connection>, v3::Error> {
+        let result = <Self as v2::HostConnection>::open(self, database).await;
+        Ok(Resource::new_own(result?.rep()))
+    }
+
+    async fn execute(
+        &mut self,
+        connection: Resource<v3::Connection>,
+        query: String,
+        parameters: Vec<v3::Value>,
+    ) -> Result<v3::QueryResult, v3::Error> {
+        let result = <Self as v2::HostConnection>::execute(
+            self,
+            Resource::new_borrow(connection.rep()),
+            query,
+            parameters.into_iter().map(Into::into).collect(),
+        )
+        .await;
+        Ok(result?.into())
+    }
+
+    #[instrument(name = "spin_sqlite.execute_batch", skip_all, err(level = Level::INFO), fields(otel.kind = "client", db.system = "sqlite", sqlite.backend = Empty))]
+    async fn execute_batch(
+        &mut self,
+        connection: Resource<v3::Connection>,
+        statements: String,
+    ) -> Result<(), v3::Error> {
+        let conn = self.get_connection(Resource::new_borrow(connection.rep()))?;
+        tracing::Span::current().record(
+            "sqlite.backend",
+            conn.summary().as_deref().unwrap_or("unknown"),
+        );
+        conn.execute_batch(&statements)
+            .await
+            .map_err(|e| v3::Error::Io(format!("{e:#}")))
+    }
+
+    async fn changes(&mut self, connection: Resource<v3::Connection>) -> Result<u64, v3::Error> {
+        let conn = self.get_connection(Resource::new_borrow(connection.rep()))?;
+        Ok(conn.changes().await?)
+    }
+
+    async fn last_insert_rowid(
+        &mut self,
+        connection: Resource<v3::Connection>,
+    ) -> Result<i64, v3::Error> {
+        let conn = self.get_connection(Resource::new_borrow(connection.rep()))?;
+        Ok(conn.last_insert_rowid().await?)
+    }
+
+    async fn drop(&mut self, connection: Resource<v3::Connection>) -> anyhow::Result<()> {
+        <Self as v2::HostConnection>::drop(self, Resource::new_own(connection.rep())).await
+    }
+}
+
 #[async_trait]
 impl v1::Host for InstanceState {
     async fn open(&mut self, database: String) -> Result<u32, v1::Error> {
@@ -1,14 +1,21 @@
+pub mod dump;
 mod host;
+pub mod migrations;
 pub mod runtime_config;
 
 use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 
 use host::InstanceState;
+use migrations::{Migration, MIGRATIONS_KEY};
 
 use async_trait::async_trait;
-use spin_factors::{anyhow, Factor};
+use spin_factors::{
+    anyhow::{self, Context},
+    Factor,
+};
 use spin_locked_app::MetadataKey;
+use spin_world::spin::sqlite::sqlite as v3;
 use spin_world::v1::sqlite as v1;
 use spin_world::v2::sqlite as v2;
 
@@ -37,6 +44,7 @@ impl Factor for SqliteFactor {
     ) -> anyhow::Result<()> {
         ctx.link_bindings(v1::add_to_linker)?;
         ctx.link_bindings(v2::add_to_linker)?;
+        ctx.link_bindings(v3::add_to_linker)?;
         Ok(())
     }
 
@@ -70,7 +78,19 @@ impl Factor for SqliteFactor {
             connection_creators.contains_key(label)
         })?;
 
-        Ok(AppState::new(allowed_databases, connection_creators))
+        let migrations = ctx.app().get_metadata(MIGRATIONS_KEY)?.unwrap_or_default();
+        if let Some(label) = migrations
+            .keys()
+            .find(|label| !connection_creators.contains_key(label.as_str()))
+        {
+            anyhow::bail!(
+                "The application has migrations for SQLite database '{label}', which is not defined. Check the spelling, or pass a runtime configuration file that defines this database."
+            );
+        }
+
+        let mut app_state = AppState::new(allowed_databases, connection_creators);
+        app_state.migrations = migrations;
+        Ok(app_state)
     }
 
     fn prepare<T: spin_factors::RuntimeFactors>(
@@ -132,6 +152,8 @@ pub struct AppState {
     allowed_databases: HashMap<String, Arc<HashSet<String>>>,
     /// A mapping from database label to a connection creator.
     connection_creators: HashMap<String, Arc<dyn ConnectionCreator>>,
+    /// A mapping from database label to the migrations to apply to it.
+    migrations: HashMap<String, Vec<Migration>>,
 }
 
 impl AppState {
@@ -143,6 +165,7 @@ impl AppState {
         Self {
             allowed_databases,
             connection_creators,
+            migrations: HashMap::new(),
         }
     }
 
@@ -167,6 +190,38 @@ impl AppState {
             .values()
             .any(|stores| stores.contains(label))
     }
+
+    /// Returns the labels of the databases used by any component, in order.
+    pub fn used_databases(&self) -> Vec<&str> {
+        let mut labels = self
+            .allowed_databases
+            .values()
+            .flat_map(|databases| databases.iter())
+            .map(String::as_str)
+            .collect::<Vec<_>>();
+        labels.sort();
+        labels.dedup();
+        labels
+    }
+
+    /// Applies the app's migrations that have not yet been applied to each
+    /// database.
+    pub async fn apply_migrations(&self) -> anyhow::Result<()> {
+        for (label, migrations) in &self.migrations {
+            let connection = self
+                .get_connection(label)
+                .await
+                .with_context(|| format!("no SQLite database '{label}'"))?
+                .with_context(|| format!("failed to connect to SQLite database '{label}'"))?;
+            let applied = migrations::apply(connection.as_ref(), migrations)
+                .await
+                .with_context(|| format!("failed to migrate SQLite database '{label}'"))?;
+            if applied > 0 {
+                tracing::info!("Applied {applied} migration(s) to SQLite database '{label}'");
+            }
+        }
+        Ok(())
+    }
 }
 
 /// A creator of a connections for a particular SQLite database.
@@ -206,6 +261,21 @@ pub trait Connection: Send + Sync {
 
     async fn execute_batch(&self, statements: &str) -> anyhow::Result<()>;
 
+    /// The number of rows modified, inserted or deleted by the most recently
+    /// completed INSERT, UPDATE or DELETE statement on this connection.
+    async fn changes(&self) -> Result<u64, v2::Error> {
+        Err(v2::Error::Io(
+            "this database does not report changed row counts".into(),
+        ))
+    }
+
+    /// The rowid of the most recent successful INSERT on this connection.
+    async fn last_insert_rowid(&self) -> Result<i64, v2::Error> {
+        Err(v2::Error::Io(
+            "this database does not report inserted rowids".into(),
+        ))
+    }
+
     /// A human-readable summary of the connection's configuration
     ///
     /// Example: "libSQL at libsql://example.com"
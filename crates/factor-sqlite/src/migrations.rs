@@ -0,0 +1,194 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+use spin_factors::anyhow::{self, bail, ensure, Context};
+use spin_locked_app::MetadataKey;
+use spin_world::v2::sqlite as v2;
+
+use crate::Connection;
+
+/// Metadata key for the SQL migrations to apply to each database, by label.
+pub const MIGRATIONS_KEY: MetadataKey<HashMap<String, Vec<Migration>>> =
+    MetadataKey::new("sqlite_migrations");
+
+/// The table recording which migrations have been applied to a database.
+pub const MIGRATIONS_TABLE: &str = "schema_migrations";
+
+/// A SQL script that changes a database's schema.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Migration {
+    /// The unique name of the migration, such as its file name. Migrations are
+    /// applied in order of name.
+    pub name: String,
+    /// The SQL statements of the migration.
+    pub sql: String,
+}
+
+/// Applies the migrations that have not yet been applied to the database,
+/// returning their number.
+///
+/// Each migration is applied in a transaction along with its record in the
+/// [`MIGRATIONS_TABLE`]. The transaction takes the database's write lock
+/// before reading which migrations have been applied, so that processes
+/// starting the app at the same time don't apply the same migration twice.
+pub(crate) async fn apply(
+    connection: &dyn Connection,
+    migrations: &[Migration],
+) -> anyhow::Result<usize> {
+    connection
+        .execute_batch(&format!(
+            "CREATE TABLE IF NOT EXISTS {MIGRATIONS_TABLE} (
+                name TEXT PRIMARY KEY NOT NULL,
+                applied_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+            )"
+        ))
+        .await
+        .context("failed to create migrations table")?;
+
+    let mut count = 0;
+    loop {
+        connection
+            .execute_batch("BEGIN IMMEDIATE")
+            .await
+            .context("failed to begin migration transaction")?;
+        match apply_next(connection, migrations).await {
+            Ok(Some(name)) => {
+                tracing::debug!("Applied SQLite migration {name:?}");
+                count += 1;
+            }
+            Ok(None) => {
+                connection
+                    .execute_batch("COMMIT")
+                    .await
+                    .context("failed to end migration transaction")?;
+                return Ok(count);
+            }
+            Err(err) => {
+                let _ = connection.execute_batch("ROLLBACK").await;
+                return Err(err);
+            }
+        }
+    }
+}
+
+/// Applies, and commits, the first migration that has not yet been applied
+/// in the transaction begun by the caller, returning its name, or `None` if
+/// every migration has been applied.
+async fn apply_next<'a>(
+    connection: &dyn Connection,
+    migrations: &'a [Migration],
+) -> anyhow::Result<Option<&'a str>> {
+    let result = connection
+        .query(
+            &format!("SELECT name FROM {MIGRATIONS_TABLE} ORDER BY name"),
+            vec![],
+        )
+        .await
+        .context("failed to read applied migrations")?;
+    let applied = result
+        .rows
+        .into_iter()
+        .map(|row| match row.values.into_iter().next() {
+            Some(v2::Value::Text(name)) => Ok(name),
+            other => bail!("unexpected migration name {other:?}"),
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let Some(migration) = pending(&applied, migrations)?.first() else {
+        return Ok(None);
+    };
+    let name = &migration.name;
+    let script = format!(
+        "{sql}\n;\nINSERT INTO {MIGRATIONS_TABLE} (name) VALUES ('{escaped}');\nCOMMIT;",
+        sql = migration.sql,
+        escaped = name.replace('\'', "''"),
+    );
+    connection
+        .execute_batch(&script)
+        .await
+        .with_context(|| format!("failed to apply migration {name:?}"))?;
+    Ok(Some(name))
+}
+
+/// Returns the migrations that come after those already applied, checking
+/// that the applied migrations are the first of the app's migrations.
+fn pending<'a>(applied: &[String], migrations: &'a [Migration]) -> anyhow::Result<&'a [Migration]> {
+    for pair in migrations.windows(2) {
+        ensure!(
+            pair[0].name < pair[1].name,
+            "migrations must have unique names in order, but {:?} comes before {:?}",
+            pair[0].name,
+            pair[1].name
+        );
+    }
+    for (index, name) in applied.iter().enumerate() {
+        ensure!(
+            migrations.iter().any(|m| &m.name == name),
+            "the database has had migration {name:?} applied, but the app has no such migration"
+        );
+        let expected = &migrations[index].name;
+        ensure!(
+            expected == name,
+            "migration {expected:?} has not been applied, but comes before the applied migration {name:?}; new migrations must come after all applied migrations"
+        );
+    }
+    Ok(&migrations[applied.len()..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn migrations(names: &[&str]) -> Vec<Migration> {
+        names
+            .iter()
+            .map(|name| Migration {
+                name: name.to_string(),
+                sql: String::new(),
+            })
+            .collect()
+    }
+
+    fn names(migrations: &[Migration]) -> Vec<&str> {
+        migrations.iter().map(|m| m.name.as_str()).collect()
+    }
+
+    #[test]
+    fn pending_migrations_follow_applied() {
+        let all = migrations(&["0001_init.sql", "0002_users.sql", "0003_index.sql"]);
+        let applied = vec!["0001_init.sql".to_owned()];
+        assert_eq!(
+            vec!["0002_users.sql", "0003_index.sql"],
+            names(pending(&applied, &all).unwrap())
+        );
+        assert!(pending(
+            &names(&all)
+                .iter()
+                .map(|n| n.to_string())
+                .collect::<Vec<_>>(),
+            &all
+        )
+        .unwrap()
+        .is_empty());
+    }
+
+    #[test]
+    fn out_of_order_migrations_are_rejected() {
+        let all = migrations(&["0001_init.sql", "0002_users.sql", "0003_index.sql"]);
+        let applied = vec!["0001_init.sql".to_owned(), "0003_index.sql".to_owned()];
+        pending(&applied, &all).unwrap_err();
+    }
+
+    #[test]
+    fn missing_applied_migrations_are_rejected() {
+        let all = migrations(&["0001_init.sql"]);
+        let applied = vec!["0000_old.sql".to_owned()];
+        pending(&applied, &all).unwrap_err();
+    }
+
+    #[test]
+    fn unsorted_migrations_are_rejected() {
+        let all = migrations(&["0002_users.sql", "0001_init.sql"]);
+        pending(&[], &all).unwrap_err();
+    }
+}
@@ -0,0 +1,93 @@
+use std::time::Duration;
+
+use spin_factors::anyhow;
+use spin_world::{
+    async_trait,
+    spin::task::{tasks, timers},
+};
+use tracing::instrument;
+
+use crate::{InstanceState, Task, MAX_PAYLOAD_BYTES};
+
+impl InstanceState {
+    fn check_task(&self, component: &str, payload: &[u8]) -> Result<(), tasks::Error> {
+        if !self.components.contains(component) {
+            return Err(tasks::Error::NoSuchComponent);
+        }
+        if payload.len() > MAX_PAYLOAD_BYTES {
+            return Err(tasks::Error::PayloadTooLarge);
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl tasks::Host for InstanceState {
+    #[instrument(name = "spin_task.enqueue", skip(self, payload), err(level = tracing::Level::INFO))]
+    async fn enqueue(
+        &mut self,
+        component: String,
+        payload: Vec<u8>,
+        delay_ms: Option<u64>,
+    ) -> Result<String, tasks::Error> {
+        self.check_task(&component, &payload)?;
+        let delay = Duration::from_millis(delay_ms.unwrap_or_default());
+        let task = Task::new(component, payload, delay);
+        let id = task.id.clone();
+        self.pending.push(task);
+        Ok(id)
+    }
+
+    fn convert_error(&mut self, error: tasks::Error) -> anyhow::Result<tasks::Error> {
+        Ok(error)
+    }
+}
+
+#[async_trait]
+impl timers::Host for InstanceState {
+    #[instrument(name = "spin_task.schedule", skip(self, payload), err(level = tracing::Level::INFO))]
+    async fn schedule(
+        &mut self,
+        component: String,
+        payload: Vec<u8>,
+        fire_at_ms: u64,
+    ) -> Result<String, tasks::Error> {
+        self.check_task(&component, &payload)?;
+        let timer = Task::at(component, payload, fire_at_ms);
+        if let Some(store) = &self.timer_store {
+            store.save(&timer).await.map_err(other_error)?;
+        }
+        let id = timer.id.clone();
+        self.timers.add(timer);
+        Ok(id)
+    }
+
+    #[instrument(name = "spin_task.cancel", skip(self), err(level = tracing::Level::INFO))]
+    async fn cancel(&mut self, id: String) -> Result<bool, tasks::Error> {
+        if !self.timers.cancel(&id) {
+            return Ok(false);
+        }
+        if let Some(store) = &self.timer_store {
+            store.remove(&id).await.map_err(other_error)?;
+        }
+        Ok(true)
+    }
+
+    #[instrument(name = "spin_task.list", skip(self), err(level = tracing::Level::INFO))]
+    async fn list(&mut self) -> Result<Vec<timers::Timer>, tasks::Error> {
+        Ok(self
+            .timers
+            .list()
+            .into_iter()
+            .map(|timer| timers::Timer {
+                id: timer.id,
+                component: timer.component,
+                fire_at_ms: timer.run_at_ms,
+            })
+            .collect())
+    }
+}
+
+fn other_error(err: anyhow::Error) -> tasks::Error {
+    tasks::Error::Other(format!("{err:#}"))
+}
@@ -0,0 +1,263 @@
+mod host;
+mod store;
+mod timers;
+
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+use spin_factor_key_value::KeyValueFactor;
+use spin_factors::{
+    anyhow::{self, bail},
+    ConfigureAppContext, Factor, InitContext, PrepareContext, RuntimeFactors, SelfInstanceBuilder,
+};
+use tokio::sync::mpsc;
+
+pub use store::TaskStore;
+pub use timers::Timers;
+
+/// The prefix of the keys tasks are saved under in the task store.
+const TASK_KEY_PREFIX: &str = "spin-task:";
+
+/// The prefix of the keys timers are saved under in the task store.
+const TIMER_KEY_PREFIX: &str = "spin-timer:";
+
+/// The largest task payload components may enqueue.
+pub const MAX_PAYLOAD_BYTES: usize = 1024 * 1024;
+
+/// A factor for letting components defer work to background tasks.
+///
+/// The tasks an instance enqueues are released to the app's [`TaskQueue`]
+/// when the instance is dropped, after it has finished handling its request.
+/// Timers are saved and added to the app's [`Timers`] as soon as they are
+/// scheduled. Running both is up to the trigger that takes their receivers.
+///
+/// The queue and timers belong to the factor rather than to each configured
+/// app, so that an app reconfigured on reload shares them with the app it
+/// replaces, and nothing released or scheduled before the reload is lost.
+pub struct TasksFactor {
+    queue: Arc<TaskQueue>,
+    timers: Arc<Timers>,
+}
+
+impl TasksFactor {
+    /// Creates a new `TasksFactor`.
+    pub fn new() -> Self {
+        Self {
+            queue: Arc::new(TaskQueue::new()),
+            timers: Arc::new(Timers::new()),
+        }
+    }
+}
+
+impl Default for TasksFactor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Factor for TasksFactor {
+    type RuntimeConfig = RuntimeConfig;
+    type AppState = AppState;
+    type InstanceBuilder = InstanceState;
+
+    fn init<T: Send + 'static>(&mut self, mut ctx: InitContext<T, Self>) -> anyhow::Result<()> {
+        ctx.link_bindings(spin_world::spin::task::tasks::add_to_linker)?;
+        ctx.link_bindings(spin_world::spin::task::timers::add_to_linker)?;
+        Ok(())
+    }
+
+    fn configure_app<T: RuntimeFactors>(
+        &self,
+        mut ctx: ConfigureAppContext<T, Self>,
+    ) -> anyhow::Result<Self::AppState> {
+        let runtime_config = ctx.take_runtime_config().unwrap_or_default();
+        let store_manager = match &runtime_config.key_value_store {
+            Some(label) => {
+                let key_value = ctx
+                    .app_state::<KeyValueFactor>()
+                    .ok()
+                    .filter(|key_value| key_value.store_is_defined(label));
+                let Some(key_value) = key_value else {
+                    bail!("the [tasks] runtime config uses key-value store '{label}', which is not defined");
+                };
+                Some((key_value.store_manager(), label.clone()))
+            }
+            None => None,
+        };
+        let store = |prefix| {
+            store_manager
+                .clone()
+                .map(|(store_manager, label)| TaskStore::new(store_manager, label, prefix))
+        };
+        let components = ctx
+            .app()
+            .components()
+            .map(|component| component.id().to_string())
+            .collect();
+        Ok(AppState {
+            queue: self.queue.clone(),
+            timers: self.timers.clone(),
+            components: Arc::new(components),
+            task_store: store(TASK_KEY_PREFIX),
+            timer_store: store(TIMER_KEY_PREFIX),
+        })
+    }
+
+    fn prepare<T: RuntimeFactors>(
+        &self,
+        ctx: PrepareContext<T, Self>,
+    ) -> anyhow::Result<InstanceState> {
+        let app_state = ctx.app_state();
+        Ok(InstanceState {
+            queue: app_state.queue.clone(),
+            timers: app_state.timers.clone(),
+            components: app_state.components.clone(),
+            timer_store: app_state.timer_store.clone(),
+            pending: vec![],
+        })
+    }
+}
+
+/// The `[tasks]` runtime config section.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RuntimeConfig {
+    /// The key-value store tasks and timers are kept in until they have run,
+    /// so that they survive restarts. Without one, they are kept only in
+    /// memory.
+    #[serde(default)]
+    pub key_value_store: Option<String>,
+}
+
+pub struct AppState {
+    queue: Arc<TaskQueue>,
+    timers: Arc<Timers>,
+    components: Arc<HashSet<String>>,
+    task_store: Option<TaskStore>,
+    timer_store: Option<TaskStore>,
+}
+
+impl AppState {
+    /// The queue of tasks released by the app's instances.
+    pub fn queue(&self) -> &Arc<TaskQueue> {
+        &self.queue
+    }
+
+    /// The app's pending timers.
+    pub fn timers(&self) -> &Arc<Timers> {
+        &self.timers
+    }
+
+    /// The store tasks are saved in, if any.
+    pub fn task_store(&self) -> Option<&TaskStore> {
+        self.task_store.as_ref()
+    }
+
+    /// The store timers are saved in, if any.
+    pub fn timer_store(&self) -> Option<&TaskStore> {
+        self.timer_store.as_ref()
+    }
+}
+
+/// A task enqueued, or timer scheduled, by a component.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Task {
+    /// The unique ID of the task.
+    pub id: String,
+    /// The component to run the task.
+    pub component: String,
+    /// The payload to pass to the component.
+    pub payload: Vec<u8>,
+    /// The earliest time the task may run, in milliseconds since the Unix
+    /// epoch.
+    pub run_at_ms: u64,
+}
+
+impl Task {
+    /// Creates a task to run no sooner than `delay` from now.
+    pub fn new(component: String, payload: Vec<u8>, delay: Duration) -> Self {
+        Self::at(
+            component,
+            payload,
+            now_ms().saturating_add(delay.as_millis() as u64),
+        )
+    }
+
+    /// Creates a task to run no sooner than `run_at_ms` milliseconds since the
+    /// Unix epoch.
+    pub fn at(component: String, payload: Vec<u8>, run_at_ms: u64) -> Self {
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            component,
+            payload,
+            run_at_ms,
+        }
+    }
+
+    /// Delays the task to run no sooner than `delay` from now.
+    pub fn reschedule(&mut self, delay: Duration) {
+        self.run_at_ms = now_ms().saturating_add(delay.as_millis() as u64);
+    }
+
+    /// How long until the task may run.
+    pub fn time_until_due(&self) -> Duration {
+        Duration::from_millis(self.run_at_ms.saturating_sub(now_ms()))
+    }
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+/// The tasks released by an app's instances, in order of release.
+pub struct TaskQueue {
+    sender: mpsc::UnboundedSender<Task>,
+    receiver: Mutex<Option<mpsc::UnboundedReceiver<Task>>>,
+}
+
+impl TaskQueue {
+    fn new() -> Self {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        Self {
+            sender,
+            receiver: Mutex::new(Some(receiver)),
+        }
+    }
+
+    fn push(&self, task: Task) {
+        // The receiver is only dropped when the runner stops at shutdown.
+        let _ = self.sender.send(task);
+    }
+
+    /// Takes the receiving end of the queue, through which released tasks
+    /// are received for running. Returns `None` if it has already been taken.
+    pub fn take_receiver(&self) -> Option<mpsc::UnboundedReceiver<Task>> {
+        self.receiver.lock().unwrap().take()
+    }
+}
+
+pub struct InstanceState {
+    queue: Arc<TaskQueue>,
+    timers: Arc<Timers>,
+    components: Arc<HashSet<String>>,
+    timer_store: Option<TaskStore>,
+    /// Tasks enqueued by this instance, released when it is dropped.
+    pending: Vec<Task>,
+}
+
+impl SelfInstanceBuilder for InstanceState {}
+
+impl Drop for InstanceState {
+    fn drop(&mut self) {
+        for task in self.pending.drain(..) {
+            self.queue.push(task);
+        }
+    }
+}
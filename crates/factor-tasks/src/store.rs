@@ -0,0 +1,78 @@
+use std::sync::Arc;
+
+use spin_factor_key_value::{Store, StoreManager};
+use spin_factors::anyhow::{self, Context};
+
+use crate::Task;
+
+/// Persists tasks in a key-value store, each under its ID with a prefix, so
+/// that they survive restarts.
+#[derive(Clone)]
+pub struct TaskStore {
+    store_manager: Arc<dyn StoreManager>,
+    label: String,
+    prefix: &'static str,
+}
+
+impl TaskStore {
+    pub(crate) fn new(
+        store_manager: Arc<dyn StoreManager>,
+        label: String,
+        prefix: &'static str,
+    ) -> Self {
+        Self {
+            store_manager,
+            label,
+            prefix,
+        }
+    }
+
+    async fn store(&self) -> anyhow::Result<Arc<dyn Store>> {
+        self.store_manager
+            .get(&self.label)
+            .await
+            .with_context(|| format!("failed to open task store {:?}", self.label))
+    }
+
+    fn key(&self, id: &str) -> String {
+        format!("{}{id}", self.prefix)
+    }
+
+    /// Saves a task, replacing any saved task with the same ID.
+    pub async fn save(&self, task: &Task) -> anyhow::Result<()> {
+        let value = serde_json::to_vec(task)?;
+        self.store()
+            .await?
+            .set(&self.key(&task.id), &value)
+            .await
+            .with_context(|| format!("failed to save task {}", task.id))
+    }
+
+    /// Removes the saved task with the given ID, if any.
+    pub async fn remove(&self, id: &str) -> anyhow::Result<()> {
+        self.store()
+            .await?
+            .delete(&self.key(id))
+            .await
+            .with_context(|| format!("failed to remove task {id}"))
+    }
+
+    /// Loads all saved tasks, skipping any that can't be read.
+    pub async fn load(&self) -> anyhow::Result<Vec<Task>> {
+        let store = self.store().await?;
+        let mut tasks = vec![];
+        for key in store.get_keys().await.context("failed to list tasks")? {
+            if !key.starts_with(self.prefix) {
+                continue;
+            }
+            let Some(value) = store.get(&key).await? else {
+                continue;
+            };
+            match serde_json::from_slice::<Task>(&value) {
+                Ok(task) => tasks.push(task),
+                Err(err) => tracing::warn!("Ignoring invalid saved task {key:?}: {err}"),
+            }
+        }
+        Ok(tasks)
+    }
+}
@@ -0,0 +1,132 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use tokio::sync::mpsc;
+
+use crate::{Task, TaskQueue};
+
+/// The app's pending timers.
+///
+/// Timers added here are sent to the runner that takes the receiver, which
+/// marks each as firing while its component runs.
+pub struct Timers {
+    pending: Mutex<HashMap<String, Entry>>,
+    queue: TaskQueue,
+}
+
+struct Entry {
+    timer: Task,
+    firing: bool,
+}
+
+impl Timers {
+    pub(crate) fn new() -> Self {
+        Self {
+            pending: Default::default(),
+            queue: TaskQueue::new(),
+        }
+    }
+
+    /// Adds a pending timer and sends it to the runner.
+    pub fn add(&self, timer: Task) {
+        let entry = Entry {
+            timer: timer.clone(),
+            firing: false,
+        };
+        self.pending.lock().unwrap().insert(timer.id.clone(), entry);
+        self.queue.push(timer);
+    }
+
+    /// Cancels a pending timer, returning whether it was cancelled. Timers
+    /// which are firing can't be cancelled.
+    pub fn cancel(&self, id: &str) -> bool {
+        let mut pending = self.pending.lock().unwrap();
+        match pending.get(id) {
+            Some(entry) if !entry.firing => {
+                pending.remove(id);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns the pending timers in the order they are due.
+    pub fn list(&self) -> Vec<Task> {
+        let mut timers = self
+            .pending
+            .lock()
+            .unwrap()
+            .values()
+            .map(|entry| entry.timer.clone())
+            .collect::<Vec<_>>();
+        timers.sort_by(|a, b| a.run_at_ms.cmp(&b.run_at_ms).then(a.id.cmp(&b.id)));
+        timers
+    }
+
+    /// Marks a timer as firing, returning false if it is no longer pending.
+    pub fn begin_firing(&self, id: &str) -> bool {
+        match self.pending.lock().unwrap().get_mut(id) {
+            Some(entry) => {
+                entry.firing = true;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Removes a timer which has fired.
+    pub fn complete(&self, id: &str) {
+        self.pending.lock().unwrap().remove(id);
+    }
+
+    /// Returns a timer which failed to fire to pending, to be retried as
+    /// rescheduled.
+    pub fn retry(&self, timer: Task) {
+        if let Some(entry) = self.pending.lock().unwrap().get_mut(&timer.id) {
+            entry.timer = timer;
+            entry.firing = false;
+        }
+    }
+
+    /// Takes the receiving end of the timers sent to the runner. Returns
+    /// `None` if it has already been taken.
+    pub fn take_receiver(&self) -> Option<mpsc::UnboundedReceiver<Task>> {
+        self.queue.take_receiver()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn timer(id: &str, run_at_ms: u64) -> Task {
+        Task {
+            id: id.into(),
+            component: "test".into(),
+            payload: vec![],
+            run_at_ms,
+        }
+    }
+
+    #[test]
+    fn timers_are_listed_in_order_due() {
+        let timers = Timers::new();
+        timers.add(timer("b", 20));
+        timers.add(timer("a", 30));
+        timers.add(timer("c", 10));
+        let ids = timers.list().into_iter().map(|t| t.id).collect::<Vec<_>>();
+        assert_eq!(vec!["c", "b", "a"], ids);
+    }
+
+    #[test]
+    fn firing_timers_cannot_be_cancelled() {
+        let timers = Timers::new();
+        timers.add(timer("a", 0));
+        assert!(timers.begin_firing("a"));
+        assert!(!timers.cancel("a"));
+
+        timers.retry(timer("a", 100));
+        assert!(timers.cancel("a"));
+        assert!(!timers.begin_firing("a"));
+        assert!(timers.list().is_empty());
+    }
+}
@@ -0,0 +1,150 @@
+use spin_factor_key_value::KeyValueFactor;
+use spin_factor_tasks::{RuntimeConfig, TasksFactor, MAX_PAYLOAD_BYTES};
+use spin_factors::{anyhow, App, RuntimeFactors};
+use spin_factors_test::{toml, TestEnvironment};
+use spin_world::spin::task::{
+    tasks::{Error, Host as _},
+    timers::Host as _,
+};
+
+#[derive(RuntimeFactors)]
+struct TestFactors {
+    key_value: KeyValueFactor,
+    tasks: TasksFactor,
+}
+
+fn test_env() -> TestEnvironment<TestFactors> {
+    let factors = TestFactors {
+        key_value: KeyValueFactor::new(),
+        tasks: TasksFactor::new(),
+    };
+    TestEnvironment::new(factors).extend_manifest(toml! {
+        [component.worker]
+        source = "does-not-exist.wasm"
+
+        [component.test-component]
+        source = "does-not-exist.wasm"
+    })
+}
+
+#[tokio::test]
+async fn enqueued_tasks_are_released_when_the_instance_is_dropped() -> anyhow::Result<()> {
+    let env = test_env();
+    let app = App::new("test-app", env.build_locked_app().await?);
+    let configured_app = env.factors.configure_app(app, env.runtime_config)?;
+    let mut receiver = configured_app
+        .app_state::<TasksFactor>()?
+        .queue()
+        .take_receiver()
+        .expect("receiver should not have been taken");
+
+    let builders = env.factors.prepare(&configured_app, "test-component")?;
+    let mut state = env.factors.build_instance_state(builders)?;
+    let id = state
+        .tasks
+        .enqueue("worker".into(), b"payload".to_vec(), Some(1000))
+        .await?;
+    assert!(receiver.try_recv().is_err(), "task released too early");
+
+    drop(state);
+    let task = receiver.try_recv()?;
+    assert_eq!(id, task.id);
+    assert_eq!("worker", task.component);
+    assert_eq!(b"payload".to_vec(), task.payload);
+    assert!(task.time_until_due().as_millis() <= 1000);
+    Ok(())
+}
+
+#[tokio::test]
+async fn reconfigured_apps_release_tasks_to_the_same_queue() -> anyhow::Result<()> {
+    let env = test_env();
+    let locked_app = env.build_locked_app().await?;
+    let configured_app = env
+        .factors
+        .configure_app(App::new("test-app", locked_app.clone()), env.runtime_config)?;
+    let mut receiver = configured_app
+        .app_state::<TasksFactor>()?
+        .queue()
+        .take_receiver()
+        .expect("receiver should not have been taken");
+
+    let reconfigured_app = env.factors.configure_app(
+        App::new("test-app", locked_app),
+        TestFactorsRuntimeConfig::default(),
+    )?;
+    let builders = env.factors.prepare(&reconfigured_app, "test-component")?;
+    let mut state = env.factors.build_instance_state(builders)?;
+    let id = state.tasks.enqueue("worker".into(), vec![], None).await?;
+    drop(state);
+    assert_eq!(id, receiver.try_recv()?.id);
+    Ok(())
+}
+
+#[tokio::test]
+async fn invalid_tasks_are_rejected() -> anyhow::Result<()> {
+    let mut state = test_env().build_instance_state().await?;
+    assert!(matches!(
+        state.tasks.enqueue("nope".into(), vec![], None).await,
+        Err(Error::NoSuchComponent)
+    ));
+    assert!(matches!(
+        state
+            .tasks
+            .enqueue("worker".into(), vec![0; MAX_PAYLOAD_BYTES + 1], None)
+            .await,
+        Err(Error::PayloadTooLarge)
+    ));
+    Ok(())
+}
+
+#[tokio::test]
+async fn timers_can_be_listed_and_cancelled() -> anyhow::Result<()> {
+    let env = test_env();
+    let app = App::new("test-app", env.build_locked_app().await?);
+    let configured_app = env.factors.configure_app(app, env.runtime_config)?;
+    let mut receiver = configured_app
+        .app_state::<TasksFactor>()?
+        .timers()
+        .take_receiver()
+        .expect("receiver should not have been taken");
+
+    let builders = env.factors.prepare(&configured_app, "test-component")?;
+    let mut state = env.factors.build_instance_state(builders)?;
+    let later = state
+        .tasks
+        .schedule("worker".into(), b"later".to_vec(), 2000)
+        .await?;
+    let sooner = state
+        .tasks
+        .schedule("worker".into(), b"sooner".to_vec(), 1000)
+        .await?;
+    // Timers are sent to the runner as soon as they are scheduled.
+    assert_eq!(later, receiver.try_recv()?.id);
+    assert_eq!(sooner, receiver.try_recv()?.id);
+
+    let listed = state.tasks.list().await?;
+    let ids = listed.iter().map(|t| t.id.as_str()).collect::<Vec<_>>();
+    assert_eq!(vec![sooner.as_str(), later.as_str()], ids);
+    assert_eq!(1000, listed[0].fire_at_ms);
+
+    assert!(state.tasks.cancel(sooner.clone()).await?);
+    assert!(!state.tasks.cancel(sooner).await?);
+    let listed = state.tasks.list().await?;
+    assert_eq!(1, listed.len());
+    assert_eq!(later, listed[0].id);
+    Ok(())
+}
+
+#[tokio::test]
+async fn undefined_key_value_store_is_rejected() -> anyhow::Result<()> {
+    let runtime_config = TestFactorsRuntimeConfig {
+        tasks: Some(RuntimeConfig {
+            key_value_store: Some("tasks".into()),
+        }),
+        ..Default::default()
+    };
+    let env = test_env().runtime_config(runtime_config)?;
+    let err = env.build_instance_state().await.err().unwrap();
+    assert!(err.to_string().contains("not defined"), "{err:?}");
+    Ok(())
+}
@@ -50,8 +50,11 @@ impl Factor for VariablesFactor {
             )?;
         }
 
-        let providers = ctx.take_runtime_config().unwrap_or_default();
-        for provider in providers {
+        let runtime_config = ctx.take_runtime_config().unwrap_or_default();
+        for decryptor in runtime_config.decryptors {
+            expression_resolver.add_decryptor(decryptor);
+        }
+        for provider in runtime_config.providers {
             expression_resolver.add_provider(provider);
         }
 
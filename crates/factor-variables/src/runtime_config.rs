@@ -1,9 +1,11 @@
-use spin_expressions::Provider;
+use spin_expressions::{Decryptor, Provider};
 
 /// The runtime configuration for the variables factor.
 #[derive(Default)]
 pub struct RuntimeConfig {
     pub providers: Vec<Box<dyn Provider>>,
+    /// The decryptors of encrypted variable values, by scheme.
+    pub decryptors: Vec<Box<dyn Decryptor>>,
 }
 
 impl IntoIterator for RuntimeConfig {
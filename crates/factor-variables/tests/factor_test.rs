@@ -16,7 +16,10 @@ async fn provider_works() -> anyhow::Result<()> {
     };
     let providers = vec![Box::new(MockProvider) as _];
     let runtime_config = TestFactorsRuntimeConfig {
-        variables: Some(RuntimeConfig { providers }),
+        variables: Some(RuntimeConfig {
+            providers,
+            ..Default::default()
+        }),
     };
     let env = TestEnvironment::new(factors)
         .extend_manifest(toml! {
@@ -0,0 +1,196 @@
+use std::{path::Path, sync::Arc};
+
+use spin_common::sha256::hex_digest_from_bytes;
+use spin_factors::anyhow::anyhow;
+use wasmtime_wasi_nn::{
+    backend::{BackendError, BackendFromDir, BackendInner},
+    wit::{ExecutionTarget, GraphEncoding},
+    Backend, Graph,
+};
+
+use crate::models::Models;
+
+/// A backend whose models are capped by runtime config, and cached by the
+/// app's [`Models`].
+pub(crate) struct CappedBackend {
+    inner: Backend,
+    max_model_bytes: usize,
+    gpu: bool,
+    models: Arc<Models>,
+}
+
+impl CappedBackend {
+    pub fn new(inner: Backend, max_model_bytes: usize, gpu: bool, models: Arc<Models>) -> Self {
+        Self {
+            inner,
+            max_model_bytes,
+            gpu,
+            models,
+        }
+    }
+
+    fn check_target(&self, target: ExecutionTarget) -> Result<(), BackendError> {
+        if !self.gpu && !matches!(target, ExecutionTarget::Cpu) {
+            return Err(anyhow!(
+                "models may only run on the CPU; set `gpu = true` in the [wasi_nn] runtime config to allow other targets"
+            )
+            .into());
+        }
+        Ok(())
+    }
+}
+
+impl BackendInner for CappedBackend {
+    fn encoding(&self) -> GraphEncoding {
+        self.inner.encoding()
+    }
+
+    fn load(&mut self, builders: &[&[u8]], target: ExecutionTarget) -> Result<Graph, BackendError> {
+        self.check_target(target)?;
+        let len = builders.iter().map(|builder| builder.len()).sum::<usize>();
+        if len > self.max_model_bytes {
+            return Err(anyhow!(
+                "model is {len} bytes, more than the {} allowed by the [wasi_nn] runtime config",
+                self.max_model_bytes
+            )
+            .into());
+        }
+        let digests = builders
+            .iter()
+            .map(hex_digest_from_bytes)
+            .collect::<Vec<_>>()
+            .join(",");
+        let digest = format!("{:?}/{target:?}/{digests}", self.encoding());
+        let models = self.models.clone();
+        models.get_or_load(digest, || self.inner.load(builders, target))
+    }
+
+    fn as_dir_loadable(&mut self) -> Option<&mut dyn BackendFromDir> {
+        // Components load models from bytes or by name, never from host
+        // directories.
+        None
+    }
+}
+
+impl BackendFromDir for CappedBackend {
+    fn load_from_dir(
+        &mut self,
+        _path: &Path,
+        _target: ExecutionTarget,
+    ) -> Result<Graph, BackendError> {
+        Err(anyhow!("models can't be loaded from host directories").into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use wasmtime_wasi_nn::{backend::BackendGraph, ExecutionContext};
+
+    use super::*;
+    use crate::models::MAX_CACHED_MODELS;
+
+    /// A backend which fails to load every model, with an error saying so.
+    struct Unloadable;
+
+    impl BackendInner for Unloadable {
+        fn encoding(&self) -> GraphEncoding {
+            GraphEncoding::Onnx
+        }
+
+        fn load(&mut self, _: &[&[u8]], _: ExecutionTarget) -> Result<Graph, BackendError> {
+            Err(anyhow!("reached the backend").into())
+        }
+
+        fn as_dir_loadable(&mut self) -> Option<&mut dyn BackendFromDir> {
+            None
+        }
+    }
+
+    impl BackendFromDir for Unloadable {
+        fn load_from_dir(&mut self, _: &Path, _: ExecutionTarget) -> Result<Graph, BackendError> {
+            Err(anyhow!("reached the backend").into())
+        }
+    }
+
+    /// A backend which loads every model, counting the loads.
+    struct Loadable(Arc<AtomicUsize>);
+
+    struct Loaded;
+
+    impl BackendGraph for Loaded {
+        fn init_execution_context(&self) -> Result<ExecutionContext, BackendError> {
+            Err(anyhow!("can't run test models").into())
+        }
+    }
+
+    impl BackendInner for Loadable {
+        fn encoding(&self) -> GraphEncoding {
+            GraphEncoding::Onnx
+        }
+
+        fn load(&mut self, _: &[&[u8]], _: ExecutionTarget) -> Result<Graph, BackendError> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            let graph: Box<dyn BackendGraph> = Box::new(Loaded);
+            Ok(graph.into())
+        }
+
+        fn as_dir_loadable(&mut self) -> Option<&mut dyn BackendFromDir> {
+            None
+        }
+    }
+
+    fn capped(inner: impl BackendInner + 'static, gpu: bool) -> CappedBackend {
+        let models = Arc::new(Models::new(Default::default(), 1));
+        CappedBackend::new(Backend::from(inner), 16, gpu, models)
+    }
+
+    fn load_error(backend: &mut CappedBackend, len: usize, target: ExecutionTarget) -> String {
+        let model = vec![0; len];
+        let Err(err) = backend.load(&[&model], target) else {
+            panic!("expected load to fail");
+        };
+        format!("{:?}", spin_factors::anyhow::Error::from(err))
+    }
+
+    #[test]
+    fn models_are_capped() {
+        let mut backend = capped(Unloadable, false);
+        assert!(load_error(&mut backend, 16, ExecutionTarget::Cpu).contains("reached the backend"));
+        assert!(load_error(&mut backend, 17, ExecutionTarget::Cpu).contains("more than the 16"));
+        assert!(load_error(&mut backend, 1, ExecutionTarget::Gpu).contains("only run on the CPU"));
+
+        let mut backend = capped(Unloadable, true);
+        assert!(load_error(&mut backend, 1, ExecutionTarget::Gpu).contains("reached the backend"));
+    }
+
+    #[test]
+    fn models_are_cached() {
+        let loads = Arc::new(AtomicUsize::new(0));
+        let mut backend = capped(Loadable(loads.clone()), false);
+        for _ in 0..2 {
+            backend
+                .load(&[b"model".as_slice()], ExecutionTarget::Cpu)
+                .unwrap();
+        }
+        assert_eq!(loads.load(Ordering::SeqCst), 1);
+
+        backend
+            .load(&[b"other".as_slice()], ExecutionTarget::Cpu)
+            .unwrap();
+        assert_eq!(loads.load(Ordering::SeqCst), 2);
+
+        // Only the most recently used models are kept.
+        for n in 0..MAX_CACHED_MODELS {
+            backend
+                .load(&[format!("model {n}").as_bytes()], ExecutionTarget::Cpu)
+                .unwrap();
+        }
+        let loads_before = loads.load(Ordering::SeqCst);
+        backend
+            .load(&[b"model".as_slice()], ExecutionTarget::Cpu)
+            .unwrap();
+        assert_eq!(loads.load(Ordering::SeqCst), loads_before + 1);
+    }
+}
@@ -0,0 +1,134 @@
+mod backend;
+mod limit;
+mod models;
+pub mod runtime_config;
+
+use std::{collections::HashSet, sync::Arc};
+
+use spin_factors::{
+    anyhow, ConfigureAppContext, Factor, InitContext, PrepareContext, RuntimeFactors,
+    SelfInstanceBuilder,
+};
+use wasmtime_wasi_nn::{
+    wit::{WasiNnCtx, WasiNnView},
+    Backend,
+};
+
+use backend::CappedBackend;
+use models::{Models, Registry};
+pub use runtime_config::RuntimeConfig;
+
+/// A factor for `wasi:nn`, with which components run machine learning models
+/// on the host.
+///
+/// Only the components listed in the `[wasi_nn]` runtime config may load
+/// models, from bytes, such as those of a mounted file, or by name from the
+/// models set up in runtime config.
+#[derive(Default)]
+pub struct WasiNnFactor {
+    _priv: (),
+}
+
+impl WasiNnFactor {
+    /// Creates a new `WasiNnFactor`.
+    pub fn new() -> Self {
+        Default::default()
+    }
+}
+
+impl Factor for WasiNnFactor {
+    type RuntimeConfig = RuntimeConfig;
+    type AppState = AppState;
+    type InstanceBuilder = InstanceState;
+
+    fn init<T: Send + 'static>(&mut self, mut ctx: InitContext<T, Self>) -> anyhow::Result<()> {
+        fn type_annotate<T, F>(f: F) -> F
+        where
+            F: Fn(&mut T) -> WasiNnView<'_>,
+        {
+            f
+        }
+        let get_data_with_table = ctx.get_data_with_table_fn();
+        let closure = type_annotate(move |data| {
+            let (state, table) = get_data_with_table(data);
+            WasiNnView::new(table, &mut state.ctx)
+        });
+        wasmtime_wasi_nn::wit::add_to_linker(ctx.linker(), closure)
+    }
+
+    fn configure_app<T: RuntimeFactors>(
+        &self,
+        mut ctx: ConfigureAppContext<T, Self>,
+    ) -> anyhow::Result<Self::AppState> {
+        let config = ctx.take_runtime_config().unwrap_or_default();
+        let app_components = ctx
+            .app()
+            .components()
+            .map(|c| c.id())
+            .collect::<HashSet<_>>();
+        for component_id in &config.components {
+            anyhow::ensure!(
+                app_components.contains(component_id.as_str()),
+                "the [wasi_nn] runtime config lists component {component_id:?}, which the app doesn't have"
+            );
+        }
+        Ok(AppState {
+            components: config.components.into_iter().collect(),
+            max_model_bytes: config.max_model_bytes,
+            gpu: config.gpu,
+            models: Arc::new(Models::new(config.models, config.max_concurrent_inferences)),
+        })
+    }
+
+    fn prepare<T: RuntimeFactors>(
+        &self,
+        ctx: PrepareContext<T, Self>,
+    ) -> anyhow::Result<Self::InstanceBuilder> {
+        let app_state = ctx.app_state();
+        if !app_state.components.contains(ctx.app_component().id()) {
+            return Ok(InstanceState {
+                ctx: WasiNnCtx::new(std::iter::empty::<Backend>(), Registry::new(None).into()),
+            });
+        }
+        let backends = wasmtime_wasi_nn::backend::list()
+            .into_iter()
+            .map(|backend| {
+                CappedBackend::new(
+                    backend,
+                    app_state.max_model_bytes,
+                    app_state.gpu,
+                    app_state.models.clone(),
+                )
+            });
+        let registry = Registry::new(Some(app_state.models.clone()));
+        Ok(InstanceState {
+            ctx: WasiNnCtx::new(backends.map(Into::into), registry.into()),
+        })
+    }
+}
+
+pub struct AppState {
+    /// The components which may use `wasi:nn`.
+    components: HashSet<String>,
+    max_model_bytes: usize,
+    gpu: bool,
+    models: Arc<Models>,
+}
+
+impl AppState {
+    /// The names of the models set up in runtime config.
+    pub fn model_names(&self) -> impl Iterator<Item = &str> {
+        self.models.names()
+    }
+
+    /// Whether the component may use `wasi:nn`.
+    pub fn allows(&self, component_id: &str) -> bool {
+        self.components.contains(component_id)
+    }
+}
+
+pub struct InstanceState {
+    ctx: WasiNnCtx,
+}
+
+impl SelfInstanceBuilder for InstanceState {}
@@ -0,0 +1,125 @@
+use std::sync::{Arc, Condvar, Mutex};
+
+use wasmtime_wasi_nn::{
+    backend::{BackendError, BackendExecutionContext, BackendGraph, Id},
+    ExecutionContext, Graph, Tensor,
+};
+
+/// Caps how many inferences an app's instances run at once.
+///
+/// Backends such as ONNX Runtime run each inference on a pool of threads as
+/// large as the host's cores, so unbounded concurrent inferences would
+/// oversubscribe the host.
+pub(crate) struct InferenceLimiter {
+    max: usize,
+    running: Mutex<usize>,
+    finished: Condvar,
+}
+
+impl InferenceLimiter {
+    pub fn new(max: usize) -> Self {
+        Self {
+            max: max.max(1),
+            running: Mutex::new(0),
+            finished: Condvar::new(),
+        }
+    }
+
+    /// Returns `graph` with its inferences subject to the limit.
+    pub fn limit(self: &Arc<Self>, graph: Graph) -> Graph {
+        let graph: Box<dyn BackendGraph> = Box::new(LimitedGraph {
+            inner: graph,
+            limiter: self.clone(),
+        });
+        graph.into()
+    }
+
+    /// Waits for an inference to be allowed to run, until the returned guard
+    /// is dropped.
+    fn start(&self) -> Running<'_> {
+        let mut running = self.running.lock().unwrap();
+        while *running >= self.max {
+            running = self.finished.wait(running).unwrap();
+        }
+        *running += 1;
+        Running(self)
+    }
+}
+
+struct Running<'a>(&'a InferenceLimiter);
+
+impl Drop for Running<'_> {
+    fn drop(&mut self) {
+        *self.0.running.lock().unwrap() -= 1;
+        self.0.finished.notify_one();
+    }
+}
+
+struct LimitedGraph {
+    inner: Graph,
+    limiter: Arc<InferenceLimiter>,
+}
+
+impl BackendGraph for LimitedGraph {
+    fn init_execution_context(&self) -> Result<ExecutionContext, BackendError> {
+        let context: Box<dyn BackendExecutionContext> = Box::new(LimitedContext {
+            inner: self.inner.init_execution_context()?,
+            limiter: self.limiter.clone(),
+        });
+        Ok(context.into())
+    }
+}
+
+struct LimitedContext {
+    inner: ExecutionContext,
+    limiter: Arc<InferenceLimiter>,
+}
+
+impl BackendExecutionContext for LimitedContext {
+    fn set_input(&mut self, id: Id, tensor: &Tensor) -> Result<(), BackendError> {
+        self.inner.set_input(id, tensor)
+    }
+
+    fn compute(&mut self) -> Result<(), BackendError> {
+        let _running = self.limiter.start();
+        self.inner.compute()
+    }
+
+    fn get_output(&mut self, id: Id) -> Result<Tensor, BackendError> {
+        self.inner.get_output(id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
+
+    use super::*;
+
+    #[test]
+    fn inferences_are_capped() {
+        let limiter = Arc::new(InferenceLimiter::new(2));
+        let running = Arc::new(AtomicUsize::new(0));
+        let most_running = Arc::new(AtomicUsize::new(0));
+        let threads = (0..6)
+            .map(|_| {
+                let (limiter, running, most_running) =
+                    (limiter.clone(), running.clone(), most_running.clone());
+                std::thread::spawn(move || {
+                    let _running = limiter.start();
+                    let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                    most_running.fetch_max(now, Ordering::SeqCst);
+                    std::thread::sleep(Duration::from_millis(10));
+                    running.fetch_sub(1, Ordering::SeqCst);
+                })
+            })
+            .collect::<Vec<_>>();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        assert!(most_running.load(Ordering::SeqCst) <= 2);
+    }
+}
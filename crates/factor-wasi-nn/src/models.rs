@@ -0,0 +1,170 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, OnceLock},
+};
+
+use spin_common::ui::quoted_path;
+use spin_factors::anyhow::{self, Context};
+use wasmtime_wasi_nn::{
+    wit::{ExecutionTarget, GraphEncoding},
+    Backend, Graph, GraphRegistry,
+};
+
+use crate::limit::InferenceLimiter;
+
+/// How many models loaded from bytes are kept for later loads of the same
+/// bytes.
+pub(crate) const MAX_CACHED_MODELS: usize = 4;
+
+/// A model components may load by name.
+pub(crate) enum NamedModel {
+    /// A model loaded by the embedder.
+    Loaded(Graph),
+    /// A model file set up in runtime config, loaded when first used.
+    File {
+        path: PathBuf,
+        encoding: GraphEncoding,
+        target: ExecutionTarget,
+    },
+}
+
+/// The models of an app, shared by its instances.
+pub(crate) struct Models {
+    named: HashMap<String, (NamedModel, OnceLock<Option<Graph>>)>,
+    /// Models loaded from bytes, most recently used last, by digest.
+    cache: Mutex<Vec<(String, Graph)>>,
+    limiter: Arc<InferenceLimiter>,
+}
+
+impl Models {
+    pub fn new(named: HashMap<String, NamedModel>, max_concurrent_inferences: usize) -> Self {
+        Self {
+            named: named
+                .into_iter()
+                .map(|(name, model)| (name, (model, OnceLock::new())))
+                .collect(),
+            cache: Default::default(),
+            limiter: Arc::new(InferenceLimiter::new(max_concurrent_inferences)),
+        }
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.named.keys().map(String::as_str)
+    }
+
+    /// Returns the named model, loading it if it hasn't been. A model which
+    /// fails to load isn't loaded again.
+    fn get(&self, name: &str) -> Option<&Graph> {
+        let (model, graph) = self.named.get(name)?;
+        graph
+            .get_or_init(|| {
+                let graph = match model {
+                    NamedModel::Loaded(graph) => graph.clone(),
+                    NamedModel::File {
+                        path,
+                        encoding,
+                        target,
+                    } => match load_file(path, *encoding, *target) {
+                        Ok(graph) => graph,
+                        Err(err) => {
+                            tracing::error!("Failed to load model {name:?}: {err:?}");
+                            return None;
+                        }
+                    },
+                };
+                Some(self.limiter.limit(graph))
+            })
+            .as_ref()
+    }
+
+    /// Returns the model loaded from the bytes with the given digest, loading
+    /// it with `load` if it isn't cached.
+    pub fn get_or_load<E>(
+        &self,
+        digest: String,
+        load: impl FnOnce() -> Result<Graph, E>,
+    ) -> Result<Graph, E> {
+        {
+            let mut cache = self.cache.lock().unwrap();
+            if let Some(index) = cache.iter().position(|(d, _)| *d == digest) {
+                let entry = cache.remove(index);
+                let graph = entry.1.clone();
+                cache.push(entry);
+                return Ok(graph);
+            }
+        }
+        // Concurrent loads of the same bytes may both load them, rather than
+        // holding the lock while loading.
+        let graph = self.limiter.limit(load()?);
+        let mut cache = self.cache.lock().unwrap();
+        if !cache.iter().any(|(d, _)| *d == digest) {
+            if cache.len() >= MAX_CACHED_MODELS {
+                cache.remove(0);
+            }
+            cache.push((digest, graph.clone()));
+        }
+        Ok(graph)
+    }
+}
+
+/// The models an instance may load by name.
+pub(crate) struct Registry {
+    models: Option<Arc<Models>>,
+    // Models the instance has used mutably
+    loaded: HashMap<String, Graph>,
+}
+
+impl Registry {
+    pub fn new(models: Option<Arc<Models>>) -> Self {
+        Self {
+            models,
+            loaded: Default::default(),
+        }
+    }
+}
+
+impl GraphRegistry for Registry {
+    fn get(&self, name: &str) -> Option<&Graph> {
+        match self.loaded.get(name) {
+            Some(graph) => Some(graph),
+            None => self.models.as_ref()?.get(name),
+        }
+    }
+
+    fn get_mut(&mut self, name: &str) -> Option<&mut Graph> {
+        if !self.loaded.contains_key(name) {
+            let graph = self.models.as_ref()?.get(name)?.clone();
+            self.loaded.insert(name.to_owned(), graph);
+        }
+        self.loaded.get_mut(name)
+    }
+}
+
+/// Loads a model file, or directory, with the backend for its encoding.
+fn load_file(
+    path: &Path,
+    encoding: GraphEncoding,
+    target: ExecutionTarget,
+) -> anyhow::Result<Graph> {
+    let mut backend = backend_for(encoding).with_context(|| {
+        format!("this build of Spin has no wasi-nn backend for {encoding:?} models")
+    })?;
+    let graph = if path.is_dir() {
+        let backend = backend
+            .as_dir_loadable()
+            .with_context(|| format!("{encoding:?} models can't be loaded from a directory"))?;
+        backend.load_from_dir(path, target)?
+    } else {
+        let model =
+            std::fs::read(path).with_context(|| format!("failed to read {}", quoted_path(path)))?;
+        backend.load(&[&model], target)?
+    };
+    Ok(graph)
+}
+
+pub(crate) fn backend_for(encoding: GraphEncoding) -> Option<Backend> {
+    wasmtime_wasi_nn::backend::list()
+        .into_iter()
+        .find(|backend| backend.encoding() == encoding)
+}
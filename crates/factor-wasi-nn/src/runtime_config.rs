@@ -0,0 +1,262 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use serde::Deserialize;
+use spin_common::ui::quoted_path;
+use spin_factors::{
+    anyhow::{self, Context},
+    runtime_config::toml::GetTomlValue,
+};
+use wasmtime_wasi_nn::{
+    wit::{ExecutionTarget, GraphEncoding},
+    Graph,
+};
+
+use crate::models::{backend_for, NamedModel};
+
+/// The largest model components may load from bytes by default.
+const DEFAULT_MAX_MODEL_BYTES: usize = 512 * 1024 * 1024;
+
+/// Runtime configuration for the `wasi:nn` factor.
+pub struct RuntimeConfig {
+    /// The components which may use `wasi:nn`. Others can't load models.
+    pub components: Vec<String>,
+    /// The largest model, in bytes, components may load from bytes.
+    pub max_model_bytes: usize,
+    /// Whether models may run on targets other than the CPU, such as a GPU.
+    pub gpu: bool,
+    /// How many inferences the app's instances may run at once.
+    pub max_concurrent_inferences: usize,
+    pub(crate) models: HashMap<String, NamedModel>,
+}
+
+impl Default for RuntimeConfig {
+    fn default() -> Self {
+        Self {
+            components: vec![],
+            max_model_bytes: DEFAULT_MAX_MODEL_BYTES,
+            gpu: false,
+            max_concurrent_inferences: 1,
+            models: Default::default(),
+        }
+    }
+}
+
+impl RuntimeConfig {
+    /// Adds a loaded model which components may load by `name`, replacing any
+    /// with the same name.
+    pub fn add_model(&mut self, name: impl Into<String>, model: Graph) {
+        self.models.insert(name.into(), NamedModel::Loaded(model));
+    }
+}
+
+/// Spin's default handling of the `[wasi_nn]` runtime config section.
+pub struct SpinWasiNnRuntimeConfig {
+    runtime_config_dir: PathBuf,
+}
+
+impl SpinWasiNnRuntimeConfig {
+    /// Creates a new `SpinWasiNnRuntimeConfig`.
+    ///
+    /// The given `runtime_config_dir` will be used as the root to resolve any
+    /// relative model paths.
+    pub fn new(runtime_config_dir: impl Into<PathBuf>) -> Self {
+        Self {
+            runtime_config_dir: runtime_config_dir.into(),
+        }
+    }
+
+    /// Get the runtime configuration from a TOML table.
+    ///
+    /// Expects table to be in the format:
+    /// ```toml
+    /// [wasi_nn]
+    /// components = ["classifier"]
+    /// max_model_bytes = 268435456
+    /// max_concurrent_inferences = 2
+    /// gpu = true
+    ///
+    /// [wasi_nn.models.mobilenet]
+    /// path = "models/mobilenet.onnx"
+    /// encoding = "onnx"
+    /// target = "gpu"
+    /// ```
+    ///
+    /// A model's path is a file, or a directory for encodings whose models
+    /// span several files, like OpenVINO's. Models are loaded when a
+    /// component first loads them by name.
+    pub fn config_from_table(
+        &self,
+        table: &impl GetTomlValue,
+    ) -> anyhow::Result<Option<RuntimeConfig>> {
+        let Some(value) = table.get("wasi_nn") else {
+            return Ok(None);
+        };
+        let config: WasiNnConfig = value
+            .clone()
+            .try_into()
+            .context("invalid [wasi_nn] runtime config")?;
+        anyhow::ensure!(
+            config.max_concurrent_inferences > 0,
+            "`max_concurrent_inferences` in the [wasi_nn] runtime config must be at least 1"
+        );
+        let mut runtime_config = RuntimeConfig {
+            components: config.components,
+            max_model_bytes: config.max_model_bytes,
+            gpu: config.gpu,
+            max_concurrent_inferences: config.max_concurrent_inferences,
+            models: Default::default(),
+        };
+        for (name, model) in config.models {
+            anyhow::ensure!(
+                runtime_config.gpu || model.target == Target::Cpu,
+                "model {name:?} targets {:?}, but `gpu` isn't enabled in the [wasi_nn] runtime config",
+                model.target
+            );
+            anyhow::ensure!(
+                backend_for(model.encoding.into()).is_some(),
+                "model {name:?} is a {:?} model, and this build of Spin has no wasi-nn backend for those",
+                model.encoding
+            );
+            let path = self.runtime_config_dir.join(&model.path);
+            anyhow::ensure!(
+                path.exists(),
+                "model {name:?} doesn't exist at {}",
+                quoted_path(&path)
+            );
+            runtime_config.models.insert(
+                name,
+                NamedModel::File {
+                    path,
+                    encoding: model.encoding.into(),
+                    target: model.target.into(),
+                },
+            );
+        }
+        Ok(Some(runtime_config))
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct WasiNnConfig {
+    #[serde(default)]
+    components: Vec<String>,
+    #[serde(default = "default_max_model_bytes")]
+    max_model_bytes: usize,
+    #[serde(default = "default_max_concurrent_inferences")]
+    max_concurrent_inferences: usize,
+    #[serde(default)]
+    gpu: bool,
+    #[serde(default)]
+    models: HashMap<String, ModelConfig>,
+}
+
+fn default_max_model_bytes() -> usize {
+    DEFAULT_MAX_MODEL_BYTES
+}
+
+fn default_max_concurrent_inferences() -> usize {
+    1
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ModelConfig {
+    path: PathBuf,
+    encoding: Encoding,
+    #[serde(default)]
+    target: Target,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Encoding {
+    Onnx,
+    Openvino,
+    Pytorch,
+    Tensorflow,
+    Tensorflowlite,
+    Ggml,
+}
+
+impl From<Encoding> for GraphEncoding {
+    fn from(encoding: Encoding) -> Self {
+        match encoding {
+            Encoding::Onnx => GraphEncoding::Onnx,
+            Encoding::Openvino => GraphEncoding::Openvino,
+            Encoding::Pytorch => GraphEncoding::Pytorch,
+            Encoding::Tensorflow => GraphEncoding::Tensorflow,
+            Encoding::Tensorflowlite => GraphEncoding::Tensorflowlite,
+            Encoding::Ggml => GraphEncoding::Ggml,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Target {
+    #[default]
+    Cpu,
+    Gpu,
+    Tpu,
+}
+
+impl From<Target> for ExecutionTarget {
+    fn from(target: Target) -> Self {
+        match target {
+            Target::Cpu => ExecutionTarget::Cpu,
+            Target::Gpu => ExecutionTarget::Gpu,
+            Target::Tpu => ExecutionTarget::Tpu,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config_from_toml(toml: toml::Table) -> anyhow::Result<Option<RuntimeConfig>> {
+        SpinWasiNnRuntimeConfig::new("/").config_from_table(&toml)
+    }
+
+    #[test]
+    fn limits_default_when_unset() {
+        let config = config_from_toml(toml::toml! {
+            [wasi_nn]
+        })
+        .unwrap()
+        .unwrap();
+        assert!(config.components.is_empty());
+        assert_eq!(config.max_model_bytes, DEFAULT_MAX_MODEL_BYTES);
+        assert_eq!(config.max_concurrent_inferences, 1);
+        assert!(!config.gpu);
+        assert!(config_from_toml(toml::Table::new()).unwrap().is_none());
+    }
+
+    #[test]
+    fn gpu_models_require_gpu() {
+        let err = config_from_toml(toml::toml! {
+            [wasi_nn.models.mobilenet]
+            path = "mobilenet.onnx"
+            encoding = "onnx"
+            target = "gpu"
+        })
+        .err()
+        .unwrap();
+        assert!(err.to_string().contains("`gpu` isn't enabled"), "{err}");
+    }
+
+    #[test]
+    fn missing_models_are_reported() {
+        let dir = tempfile::tempdir().unwrap();
+        let err = SpinWasiNnRuntimeConfig::new(dir.path())
+            .config_from_table(&toml::toml! {
+                [wasi_nn.models.mobilenet]
+                path = "mobilenet.onnx"
+                encoding = "onnx"
+            })
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("mobilenet"), "{err}");
+    }
+}
@@ -0,0 +1,71 @@
+use spin_factor_wasi_nn::{RuntimeConfig, WasiNnFactor};
+use spin_factors::{anyhow, App, RuntimeFactors};
+use spin_factors_test::{toml, TestEnvironment};
+
+#[derive(RuntimeFactors)]
+struct TestFactors {
+    wasi_nn: WasiNnFactor,
+}
+
+fn test_env() -> TestEnvironment<TestFactors> {
+    let factors = TestFactors {
+        wasi_nn: WasiNnFactor::new(),
+    };
+    TestEnvironment::new(factors).extend_manifest(toml! {
+        [component.test-component]
+        source = "does-not-exist.wasm"
+    })
+}
+
+#[tokio::test]
+async fn instances_are_built_without_runtime_config() -> anyhow::Result<()> {
+    let env = test_env();
+    let app = App::new("test-app", env.build_locked_app().await?);
+    let configured_app = env.factors.configure_app(app, env.runtime_config)?;
+    let app_state = configured_app.app_state::<WasiNnFactor>()?;
+    assert_eq!(app_state.model_names().count(), 0);
+    assert!(!app_state.allows("test-component"));
+
+    let builders = env.factors.prepare(&configured_app, "test-component")?;
+    env.factors.build_instance_state(builders)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn runtime_config_is_applied() -> anyhow::Result<()> {
+    let env = test_env().runtime_config(TestFactorsRuntimeConfig {
+        wasi_nn: Some(RuntimeConfig {
+            components: vec!["test-component".into()],
+            max_model_bytes: 1024,
+            gpu: true,
+            ..Default::default()
+        }),
+    })?;
+    let app = App::new("test-app", env.build_locked_app().await?);
+    let configured_app = env.factors.configure_app(app, env.runtime_config)?;
+    assert!(configured_app
+        .app_state::<WasiNnFactor>()?
+        .allows("test-component"));
+
+    let builders = env.factors.prepare(&configured_app, "test-component")?;
+    env.factors.build_instance_state(builders)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn unknown_components_are_rejected() -> anyhow::Result<()> {
+    let env = test_env().runtime_config(TestFactorsRuntimeConfig {
+        wasi_nn: Some(RuntimeConfig {
+            components: vec!["other-component".into()],
+            ..Default::default()
+        }),
+    })?;
+    let app = App::new("test-app", env.build_locked_app().await?);
+    let err = env
+        .factors
+        .configure_app(app, env.runtime_config)
+        .err()
+        .unwrap();
+    assert!(format!("{err:?}").contains("other-component"), "{err:?}");
+    Ok(())
+}
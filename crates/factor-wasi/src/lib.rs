@@ -1,38 +1,71 @@
 mod io;
+mod passthrough;
+mod quota;
 pub mod spin;
 mod wasi_2023_10_18;
 mod wasi_2023_11_10;
 
 use std::{
+    collections::{HashMap, HashSet},
     future::Future,
     io::{Read, Write},
     net::SocketAddr,
     path::Path,
+    sync::Arc,
 };
 
 use io::{PipeReadStream, PipedWriteStream};
+use quota::{QuotaFs, Quotas};
+use serde::Deserialize;
+use spin_common::diagnostics::{Diagnostic, Diagnostics};
 use spin_factors::{
-    anyhow, AppComponent, Factor, FactorInstanceBuilder, InitContext, PrepareContext,
+    anyhow::{self, Context},
+    AppComponent, ConfigureAppContext, Factor, FactorInstanceBuilder, InitContext, PrepareContext,
     RuntimeFactors, RuntimeFactorsInstanceState,
 };
+use spin_locked_app::MetadataKey;
 use wasmtime_wasi::{
     DirPerms, FilePerms, ResourceTable, StdinStream, StdoutStream, WasiCtx, WasiCtxBuilder,
     WasiImpl, WasiView,
 };
 
-pub use wasmtime_wasi::SocketAddrUse;
+pub use quota::MountQuota;
+pub use wasmtime_wasi::{HostMonotonicClock, HostWallClock, RngCore, SocketAddrUse};
+
+/// Metadata key for the host environment variables a component requests, by
+/// name. Each must be allowed by the operator.
+pub const ENVIRONMENT_PASSTHROUGH_KEY: MetadataKey<Vec<String>> =
+    MetadataKey::new("environment_passthrough");
 
 pub struct WasiFactor {
     files_mounter: Box<dyn FilesMounter>,
+    allowed_passthrough: Vec<String>,
+    diagnostics: Diagnostics,
 }
 
 impl WasiFactor {
     pub fn new(files_mounter: impl FilesMounter + 'static) -> Self {
         Self {
             files_mounter: Box::new(files_mounter),
+            allowed_passthrough: Vec::new(),
+            diagnostics: Diagnostics::new(),
         }
     }
 
+    /// Sets where missing passed-through environment variables are reported
+    /// as diagnostics.
+    pub fn set_diagnostics(&mut self, diagnostics: Diagnostics) {
+        self.diagnostics = diagnostics;
+    }
+
+    /// Allows components to request the host environment variables matching
+    /// the given names or `*`-suffixed prefixes through
+    /// `environment_passthrough`, in addition to those allowed by the runtime
+    /// config.
+    pub fn allow_environment_passthrough(&mut self, patterns: impl IntoIterator<Item = String>) {
+        self.allowed_passthrough.extend(patterns);
+    }
+
     pub fn get_wasi_impl(
         runtime_instance_state: &mut impl RuntimeFactorsInstanceState,
     ) -> Option<WasiImpl<impl WasiView + '_>> {
@@ -45,8 +78,8 @@ impl WasiFactor {
 }
 
 impl Factor for WasiFactor {
-    type RuntimeConfig = ();
-    type AppState = ();
+    type RuntimeConfig = RuntimeConfig;
+    type AppState = AppState;
     type InstanceBuilder = InstanceBuilder;
 
     fn init<T: Send + 'static>(&mut self, mut ctx: InitContext<T, Self>) -> anyhow::Result<()> {
@@ -56,6 +89,12 @@ impl Factor for WasiFactor {
         {
             f
         }
+        fn type_annotate_fs<T, F>(f: F) -> F
+        where
+            F: Fn(&mut T) -> QuotaFs,
+        {
+            f
+        }
         let get_data_with_table = ctx.get_data_with_table_fn();
         let closure = type_annotate(move |data| {
             let (state, table) = get_data_with_table(data);
@@ -64,12 +103,21 @@ impl Factor for WasiFactor {
                 table,
             })
         });
+        // The filesystem interfaces enforce the quotas of files mounts
+        let fs_closure = type_annotate_fs(move |data| {
+            let (state, table) = get_data_with_table(data);
+            let wasi = WasiImpl(WasiImplInner {
+                ctx: &mut state.ctx,
+                table,
+            });
+            QuotaFs::new(wasi, &mut state.quotas)
+        });
         let linker = ctx.linker();
         use wasmtime_wasi::bindings;
         bindings::clocks::wall_clock::add_to_linker_get_host(linker, closure)?;
         bindings::clocks::monotonic_clock::add_to_linker_get_host(linker, closure)?;
-        bindings::filesystem::types::add_to_linker_get_host(linker, closure)?;
-        bindings::filesystem::preopens::add_to_linker_get_host(linker, closure)?;
+        bindings::filesystem::types::add_to_linker_get_host(linker, fs_closure)?;
+        bindings::filesystem::preopens::add_to_linker_get_host(linker, fs_closure)?;
         bindings::io::error::add_to_linker_get_host(linker, closure)?;
         bindings::io::poll::add_to_linker_get_host(linker, closure)?;
         bindings::io::streams::add_to_linker_get_host(linker, closure)?;
@@ -94,17 +142,64 @@ impl Factor for WasiFactor {
         bindings::sockets::network::add_to_linker_get_host(linker, closure)?;
         bindings::sockets::ip_name_lookup::add_to_linker_get_host(linker, closure)?;
 
-        wasi_2023_10_18::add_to_linker(linker, closure)?;
-        wasi_2023_11_10::add_to_linker(linker, closure)?;
+        wasi_2023_10_18::add_to_linker(linker, closure, fs_closure)?;
+        wasi_2023_11_10::add_to_linker(linker, closure, fs_closure)?;
 
         Ok(())
     }
 
     fn configure_app<T: RuntimeFactors>(
         &self,
-        _ctx: spin_factors::ConfigureAppContext<T, Self>,
+        mut ctx: ConfigureAppContext<T, Self>,
     ) -> anyhow::Result<Self::AppState> {
-        Ok(())
+        let config = ctx.take_runtime_config().unwrap_or_default();
+        let global_patterns = passthrough::parse(&config.environment_passthrough)
+            .context("invalid `environment_passthrough` in [wasi] runtime config")?;
+        let allowed_patterns = [
+            passthrough::parse(&config.allowed_environment_passthrough)
+                .context("invalid `allowed_environment_passthrough` in [wasi] runtime config")?,
+            passthrough::parse(&self.allowed_passthrough)
+                .context("invalid allowed environment passthrough")?,
+            global_patterns.clone(),
+        ]
+        .concat();
+
+        // The host environment is read once, when the app is loaded, so that
+        // every instance of a component sees the same values.
+        let host_env = passthrough::host_env();
+        for name in passthrough::missing(&global_patterns, &host_env) {
+            self.report_missing(name, None);
+        }
+
+        let mut component_passthrough = HashMap::new();
+        for component in ctx.app().components() {
+            let patterns = component
+                .get_metadata(ENVIRONMENT_PASSTHROUGH_KEY)?
+                .unwrap_or_default();
+            let patterns = passthrough::parse_names(&patterns).with_context(|| {
+                format!(
+                    "invalid `environment_passthrough` for component {:?}",
+                    component.id()
+                )
+            })?;
+            if let Some(name) = passthrough::unpermitted(&patterns, &allowed_patterns).next() {
+                anyhow::bail!(
+                    "component {:?} requests host environment variable {name:?} through `environment_passthrough`, but it isn't allowed; allow it with `allowed_environment_passthrough` in the [wasi] runtime config, or `--allow-env-passthrough`",
+                    component.id()
+                );
+            }
+            for name in passthrough::missing(&patterns, &host_env) {
+                self.report_missing(name, Some(component.id()));
+            }
+            let all_patterns = [global_patterns.as_slice(), patterns.as_slice()].concat();
+            let vars = passthrough::select(&all_patterns, &host_env);
+            if !vars.is_empty() {
+                component_passthrough.insert(component.id().to_string(), vars);
+            }
+        }
+        Ok(AppState {
+            component_passthrough,
+        })
     }
 
     fn prepare<T: RuntimeFactors>(
@@ -114,19 +209,72 @@ impl Factor for WasiFactor {
         let mut wasi_ctx = WasiCtxBuilder::new();
 
         // Mount files
-        let mount_ctx = MountFilesContext { ctx: &mut wasi_ctx };
+        let mut quotas = Vec::new();
+        let mount_ctx = MountFilesContext {
+            ctx: &mut wasi_ctx,
+            quotas: &mut quotas,
+        };
         self.files_mounter
             .mount_files(ctx.app_component(), mount_ctx)?;
 
-        let mut builder = InstanceBuilder { ctx: wasi_ctx };
+        let mut builder = InstanceBuilder {
+            ctx: wasi_ctx,
+            quotas,
+        };
 
-        // Apply environment variables
-        builder.env(ctx.app_component().environment());
+        // Apply environment variables, with the component's own environment
+        // taking precedence over any passed through from the host
+        let component = ctx.app_component();
+        if let Some(vars) = ctx.app_state().component_passthrough.get(component.id()) {
+            let overridden = component
+                .environment()
+                .into_iter()
+                .map(|(k, _)| k)
+                .collect::<HashSet<_>>();
+            builder.env(
+                vars.iter()
+                    .filter(|(k, _)| !overridden.contains(k.as_str()))
+                    .map(|(k, v)| (k, v)),
+            );
+        }
+        builder.env(component.environment());
 
         Ok(builder)
     }
 }
 
+impl WasiFactor {
+    fn report_missing(&self, name: &str, component_id: Option<&str>) {
+        let message = format!(
+            "environment variable {name:?} is passed through by `environment_passthrough`, but is not set on the host"
+        );
+        let mut diagnostic = Diagnostic::new("environment-passthrough-missing", message)
+            .suggestion(format!("Set {name} before running the app"));
+        if let Some(component_id) = component_id {
+            diagnostic = diagnostic.component(component_id);
+        }
+        self.diagnostics.report(diagnostic);
+    }
+}
+
+/// The `[wasi]` runtime config section.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RuntimeConfig {
+    /// Host environment variables passed through to every component, by name
+    /// or `*`-suffixed prefix, e.g. `["OTEL_*"]`.
+    pub environment_passthrough: Vec<String>,
+    /// Host environment variables which components may request through
+    /// `environment_passthrough` in the manifest, by name or `*`-suffixed
+    /// prefix.
+    pub allowed_environment_passthrough: Vec<String>,
+}
+
+pub struct AppState {
+    /// Component ID -> the host environment variables passed through to it
+    component_passthrough: HashMap<String, Vec<(String, String)>>,
+}
+
 pub trait FilesMounter: Send + Sync {
     fn mount_files(
         &self,
@@ -153,6 +301,7 @@ impl FilesMounter for DummyFilesMounter {
 
 pub struct MountFilesContext<'a> {
     ctx: &'a mut WasiCtxBuilder,
+    quotas: &'a mut Vec<(String, Arc<MountQuota>)>,
 }
 
 impl<'a> MountFilesContext<'a> {
@@ -171,10 +320,25 @@ impl<'a> MountFilesContext<'a> {
             .preopened_dir(host_path, guest_path, dir_perms, file_perms)?;
         Ok(())
     }
+
+    /// Mounts the given `host_path` writable at the given `guest_path`, with
+    /// writes which would take its files past the given quota failing.
+    pub fn preopened_dir_with_quota(
+        &mut self,
+        host_path: impl AsRef<Path>,
+        guest_path: impl AsRef<str>,
+        quota: Arc<MountQuota>,
+    ) -> anyhow::Result<()> {
+        let guest_path = guest_path.as_ref();
+        self.preopened_dir(host_path, guest_path, true)?;
+        self.quotas.push((guest_path.to_owned(), quota));
+        Ok(())
+    }
 }
 
 pub struct InstanceBuilder {
     ctx: WasiCtxBuilder,
+    quotas: Vec<(String, Arc<MountQuota>)>,
 }
 
 impl InstanceBuilder {
@@ -222,6 +386,26 @@ impl InstanceBuilder {
         }
     }
 
+    /// Sets the WASI monotonic clock to the given [`HostMonotonicClock`].
+    pub fn monotonic_clock(&mut self, clock: impl HostMonotonicClock + 'static) {
+        self.ctx.monotonic_clock(clock);
+    }
+
+    /// Sets the WASI wall clock to the given [`HostWallClock`].
+    pub fn wall_clock(&mut self, clock: impl HostWallClock + 'static) {
+        self.ctx.wall_clock(clock);
+    }
+
+    /// Sets the source of WASI secure random bytes to the given [`RngCore`].
+    pub fn secure_random(&mut self, random: impl RngCore + Send + 'static) {
+        self.ctx.secure_random(random);
+    }
+
+    /// Sets the source of WASI insecure random bytes to the given [`RngCore`].
+    pub fn insecure_random(&mut self, random: impl RngCore + Send + 'static) {
+        self.ctx.insecure_random(random);
+    }
+
     /// "Mounts" the given `host_path` into the WASI filesystem at the given
     /// `guest_path`.
     pub fn preopened_dir(
@@ -245,9 +429,13 @@ impl FactorInstanceBuilder for InstanceBuilder {
     type InstanceState = InstanceState;
 
     fn build(self) -> anyhow::Result<Self::InstanceState> {
-        let InstanceBuilder { ctx: mut wasi_ctx } = self;
+        let InstanceBuilder {
+            ctx: mut wasi_ctx,
+            quotas,
+        } = self;
         Ok(InstanceState {
             ctx: wasi_ctx.build(),
+            quotas: Quotas::new(quotas),
         })
     }
 }
@@ -277,6 +465,7 @@ impl InstanceBuilder {
 
 pub struct InstanceState {
     ctx: WasiCtx,
+    quotas: Quotas,
 }
 
 struct WasiImplInner<'a> {
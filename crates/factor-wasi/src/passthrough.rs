@@ -0,0 +1,158 @@
+//! Passing host environment variables through to components, as requested
+//! by `environment_passthrough` in the manifest or runtime config.
+//!
+//! The runtime config may pass through variables by name or prefix, while
+//! manifests may only request variables by name, and only those the operator
+//! allows, so that an app can't read the host's secrets by naming them.
+
+use std::str::FromStr;
+
+use spin_factors::anyhow::{self, ensure};
+
+/// A host environment variable name, or a prefix of names, such as `OTEL_*`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Pattern {
+    Name(String),
+    Prefix(String),
+}
+
+impl Pattern {
+    fn matches(&self, name: &str) -> bool {
+        match self {
+            Self::Name(n) => n == name,
+            Self::Prefix(prefix) => name.starts_with(prefix),
+        }
+    }
+}
+
+impl FromStr for Pattern {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, is_prefix) = match s.strip_suffix('*') {
+            Some(prefix) => (prefix, true),
+            None => (s, false),
+        };
+        ensure!(
+            !name.is_empty(),
+            "{s:?} would pass through every host environment variable; list names or prefixes such as \"OTEL_*\""
+        );
+        ensure!(
+            !name.contains(['=', '*', '\0']),
+            "{s:?} is not an environment variable name, or a prefix ending with '*'"
+        );
+        Ok(if is_prefix {
+            Self::Prefix(name.to_owned())
+        } else {
+            Self::Name(name.to_owned())
+        })
+    }
+}
+
+/// Parses a list of patterns.
+pub(crate) fn parse(patterns: &[String]) -> anyhow::Result<Vec<Pattern>> {
+    patterns.iter().map(|p| p.parse()).collect()
+}
+
+/// Parses a list of names, rejecting prefixes.
+pub(crate) fn parse_names(names: &[String]) -> anyhow::Result<Vec<Pattern>> {
+    let patterns = parse(names)?;
+    for pattern in &patterns {
+        if let Pattern::Prefix(prefix) = pattern {
+            anyhow::bail!(
+                "\"{prefix}*\" is a prefix; components may only request host environment variables by name"
+            );
+        }
+    }
+    Ok(patterns)
+}
+
+/// Returns the names which aren't matched by any of the allowed patterns.
+pub(crate) fn unpermitted<'a>(
+    names: &'a [Pattern],
+    allowed: &'a [Pattern],
+) -> impl Iterator<Item = &'a str> {
+    names.iter().filter_map(|name| match name {
+        Pattern::Name(name) if !allowed.iter().any(|p| p.matches(name)) => Some(name.as_str()),
+        _ => None,
+    })
+}
+
+/// Returns the host variables matching any of the patterns.
+pub(crate) fn select(patterns: &[Pattern], host_env: &[(String, String)]) -> Vec<(String, String)> {
+    host_env
+        .iter()
+        .filter(|(name, _)| patterns.iter().any(|p| p.matches(name)))
+        .cloned()
+        .collect()
+}
+
+/// Returns the names the patterns pass through which aren't set on the host.
+pub(crate) fn missing<'a>(
+    patterns: &'a [Pattern],
+    host_env: &[(String, String)],
+) -> impl Iterator<Item = &'a str> {
+    let host_names = host_env
+        .iter()
+        .map(|(name, _)| name.clone())
+        .collect::<std::collections::HashSet<_>>();
+    patterns.iter().filter_map(move |p| match p {
+        Pattern::Name(name) if !host_names.contains(name) => Some(name.as_str()),
+        _ => None,
+    })
+}
+
+/// The host's environment variables, skipping any which aren't Unicode.
+pub(crate) fn host_env() -> Vec<(String, String)> {
+    std::env::vars_os()
+        .filter_map(|(name, value)| Some((name.into_string().ok()?, value.into_string().ok()?)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn host_env() -> Vec<(String, String)> {
+        [
+            ("HOME", "/root"),
+            ("OTEL_ENDPOINT", "x"),
+            ("OTEL_NAME", "y"),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_owned(), v.to_owned()))
+        .collect()
+    }
+
+    #[test]
+    fn names_and_prefixes_are_selected() {
+        let patterns = parse(&["OTEL_*".into(), "HOME".into(), "USER".into()]).unwrap();
+        let selected = select(&patterns, &host_env());
+        assert_eq!(3, selected.len());
+        assert_eq!(
+            vec!["USER"],
+            missing(&patterns, &host_env()).collect::<Vec<_>>()
+        );
+
+        let patterns = parse(&["HOM".into()]).unwrap();
+        assert!(select(&patterns, &host_env()).is_empty());
+    }
+
+    #[test]
+    fn names_must_be_allowed() {
+        let names = parse_names(&["OTEL_ENDPOINT".into(), "AWS_SECRET".into()]).unwrap();
+        let allowed = parse(&["OTEL_*".into()]).unwrap();
+        assert_eq!(
+            vec!["AWS_SECRET"],
+            unpermitted(&names, &allowed).collect::<Vec<_>>()
+        );
+        assert!(parse_names(&["OTEL_*".into()]).is_err());
+    }
+
+    #[test]
+    fn invalid_patterns_are_rejected() {
+        for pattern in ["*", "", "A=B", "OTEL_*_URL"] {
+            assert!(pattern.parse::<Pattern>().is_err(), "{pattern:?}");
+        }
+    }
+}
@@ -0,0 +1,712 @@
+//! Limits on the total size of the files in writable files mounts.
+//!
+//! Writes are accounted as they happen: each descriptor opened under a mount
+//! with a quota tracks the size of its file, so only writes which grow a file
+//! count against the quota, and truncating or removing files frees space.
+//! The accounting is deliberately conservative where the guest's view of a
+//! file may be stale, e.g. when several instances write the same file.
+
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use spin_factors::anyhow;
+use wasmtime::component::Resource;
+use wasmtime_wasi::{
+    bindings::filesystem::{
+        preopens,
+        types::{
+            self, Advice, Descriptor, DescriptorFlags, DescriptorStat, DescriptorType,
+            DirectoryEntry, DirectoryEntryStream, ErrorCode, Filesize, MetadataHashValue,
+            NewTimestamp, OpenFlags, PathFlags,
+        },
+    },
+    FsError, FsResult, HostOutputStream, InputStream, OutputStream, StreamError, StreamResult,
+    Subscribe, WasiImpl,
+};
+
+use crate::WasiImplInner;
+
+/// The space available to the files in a mounted directory, shared by every
+/// instance which mounts it.
+#[derive(Debug)]
+pub struct MountQuota {
+    max_bytes: u64,
+    used: AtomicU64,
+}
+
+impl MountQuota {
+    /// A quota of `max_bytes` for a directory whose files already use `used`
+    /// bytes.
+    pub fn new(max_bytes: u64, used: u64) -> Self {
+        Self {
+            max_bytes,
+            used: AtomicU64::new(used),
+        }
+    }
+
+    /// The bytes the mounted files are known to use.
+    pub fn used(&self) -> u64 {
+        self.used.load(Ordering::SeqCst)
+    }
+
+    fn try_grow(&self, bytes: u64) -> bool {
+        bytes == 0
+            || self
+                .used
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |used| {
+                    used.checked_add(bytes)
+                        .filter(|used| *used <= self.max_bytes)
+                })
+                .is_ok()
+    }
+
+    fn shrink(&self, bytes: u64) {
+        _ = self
+            .used
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |used| {
+                Some(used.saturating_sub(bytes))
+            });
+    }
+}
+
+/// A file opened under a mount with a quota.
+struct QuotaFile {
+    quota: Arc<MountQuota>,
+    size: AtomicU64,
+}
+
+impl QuotaFile {
+    /// Accounts for the file growing to at least `end` bytes, returning
+    /// false if that would exceed the quota.
+    fn grow_to(&self, end: u64) -> bool {
+        let size = self.size.load(Ordering::SeqCst);
+        if end <= size {
+            return true;
+        }
+        if !self.quota.try_grow(end - size) {
+            return false;
+        }
+        // Give back any growth accounted by a concurrent write
+        let prev = self.size.fetch_max(end, Ordering::SeqCst);
+        if prev > size {
+            self.quota.shrink(prev.min(end) - size);
+        }
+        true
+    }
+
+    /// Accounts for the file being resized to `size` bytes, after any growth
+    /// has been accounted with [`QuotaFile::grow_to`].
+    fn resized(&self, size: u64) {
+        let prev = self.size.swap(size, Ordering::SeqCst);
+        if prev > size {
+            self.quota.shrink(prev - size);
+        }
+    }
+}
+
+enum Quoted {
+    Dir(Arc<MountQuota>),
+    File(Arc<QuotaFile>),
+}
+
+impl Quoted {
+    fn quota(&self) -> &Arc<MountQuota> {
+        match self {
+            Quoted::Dir(quota) => quota,
+            Quoted::File(file) => &file.quota,
+        }
+    }
+}
+
+/// The quotas of an instance's files mounts, and the descriptors they apply
+/// to.
+#[derive(Default)]
+pub(crate) struct Quotas {
+    /// Guest path -> quota of a preopened directory
+    preopens: Vec<(String, Arc<MountQuota>)>,
+    /// Descriptor rep -> what it is subject to
+    descriptors: HashMap<u32, Quoted>,
+}
+
+impl Quotas {
+    pub fn new(preopens: Vec<(String, Arc<MountQuota>)>) -> Self {
+        Self {
+            preopens,
+            descriptors: Default::default(),
+        }
+    }
+}
+
+/// The WASI filesystem interfaces, subject to the quotas of files mounts.
+pub(crate) struct QuotaFs<'a> {
+    wasi: WasiImpl<WasiImplInner<'a>>,
+    quotas: &'a mut Quotas,
+}
+
+impl<'a> QuotaFs<'a> {
+    pub fn new(wasi: WasiImpl<WasiImplInner<'a>>, quotas: &'a mut Quotas) -> Self {
+        Self { wasi, quotas }
+    }
+
+    fn quoted(&self, fd: &Resource<Descriptor>) -> Option<&Quoted> {
+        self.quotas.descriptors.get(&fd.rep())
+    }
+
+    fn file(&self, fd: &Resource<Descriptor>) -> Option<Arc<QuotaFile>> {
+        match self.quoted(fd)? {
+            Quoted::File(file) => Some(file.clone()),
+            Quoted::Dir(_) => None,
+        }
+    }
+
+    fn dir_quota(&self, fd: &Resource<Descriptor>) -> Option<Arc<MountQuota>> {
+        Some(self.quoted(fd)?.quota().clone())
+    }
+
+    /// The size of the regular file at `path`, if there is one and removing
+    /// it would free its space.
+    async fn removable_size(&mut self, dir: &Resource<Descriptor>, path: &str) -> u64 {
+        match types::HostDescriptor::stat_at(
+            &mut self.wasi,
+            borrow(dir),
+            PathFlags::empty(),
+            path.to_owned(),
+        )
+        .await
+        {
+            Ok(stat) if stat.type_ == DescriptorType::RegularFile && stat.link_count <= 1 => {
+                stat.size
+            }
+            _ => 0,
+        }
+    }
+
+    fn wrap_stream(
+        &mut self,
+        stream: Resource<OutputStream>,
+        file: Arc<QuotaFile>,
+        position: Option<u64>,
+    ) -> wasmtime::Result<Resource<OutputStream>> {
+        let table = &mut self.wasi.0.table;
+        let inner = table.delete(stream)?;
+        let stream: OutputStream = Box::new(QuotaOutputStream {
+            inner,
+            file,
+            position,
+        });
+        Ok(table.push(stream)?)
+    }
+}
+
+fn borrow(fd: &Resource<Descriptor>) -> Resource<Descriptor> {
+    Resource::new_borrow(fd.rep())
+}
+
+fn insufficient_space<T>() -> FsResult<T> {
+    Err(FsError::from(ErrorCode::InsufficientSpace))
+}
+
+impl types::Host for QuotaFs<'_> {
+    fn convert_error_code(&mut self, err: FsError) -> wasmtime::Result<ErrorCode> {
+        types::Host::convert_error_code(&mut self.wasi, err)
+    }
+
+    fn filesystem_error_code(
+        &mut self,
+        err: Resource<types::Error>,
+    ) -> wasmtime::Result<Option<ErrorCode>> {
+        // Streams report exceeding a quota with the error code itself
+        if let Some(code) = self.wasi.0.table.get(&err)?.downcast_ref::<ErrorCode>() {
+            return Ok(Some(*code));
+        }
+        types::Host::filesystem_error_code(&mut self.wasi, err)
+    }
+}
+
+#[async_trait]
+impl types::HostDescriptor for QuotaFs<'_> {
+    fn read_via_stream(
+        &mut self,
+        self_: Resource<Descriptor>,
+        offset: Filesize,
+    ) -> FsResult<Resource<InputStream>> {
+        types::HostDescriptor::read_via_stream(&mut self.wasi, self_, offset)
+    }
+
+    fn write_via_stream(
+        &mut self,
+        self_: Resource<Descriptor>,
+        offset: Filesize,
+    ) -> FsResult<Resource<OutputStream>> {
+        let file = self.file(&self_);
+        let stream = types::HostDescriptor::write_via_stream(&mut self.wasi, self_, offset)?;
+        match file {
+            Some(file) => Ok(self.wrap_stream(stream, file, Some(offset))?),
+            None => Ok(stream),
+        }
+    }
+
+    fn append_via_stream(
+        &mut self,
+        self_: Resource<Descriptor>,
+    ) -> FsResult<Resource<OutputStream>> {
+        let file = self.file(&self_);
+        let stream = types::HostDescriptor::append_via_stream(&mut self.wasi, self_)?;
+        match file {
+            Some(file) => Ok(self.wrap_stream(stream, file, None)?),
+            None => Ok(stream),
+        }
+    }
+
+    async fn advise(
+        &mut self,
+        self_: Resource<Descriptor>,
+        offset: Filesize,
+        length: Filesize,
+        advice: Advice,
+    ) -> FsResult<()> {
+        types::HostDescriptor::advise(&mut self.wasi, self_, offset, length, advice).await
+    }
+
+    async fn sync_data(&mut self, self_: Resource<Descriptor>) -> FsResult<()> {
+        types::HostDescriptor::sync_data(&mut self.wasi, self_).await
+    }
+
+    async fn get_flags(&mut self, self_: Resource<Descriptor>) -> FsResult<DescriptorFlags> {
+        types::HostDescriptor::get_flags(&mut self.wasi, self_).await
+    }
+
+    async fn get_type(&mut self, self_: Resource<Descriptor>) -> FsResult<DescriptorType> {
+        types::HostDescriptor::get_type(&mut self.wasi, self_).await
+    }
+
+    async fn set_size(&mut self, self_: Resource<Descriptor>, size: Filesize) -> FsResult<()> {
+        let file = self.file(&self_);
+        if let Some(file) = &file {
+            if !file.grow_to(size) {
+                return insufficient_space();
+            }
+        }
+        types::HostDescriptor::set_size(&mut self.wasi, self_, size).await?;
+        if let Some(file) = file {
+            file.resized(size);
+        }
+        Ok(())
+    }
+
+    async fn set_times(
+        &mut self,
+        self_: Resource<Descriptor>,
+        data_access_timestamp: NewTimestamp,
+        data_modification_timestamp: NewTimestamp,
+    ) -> FsResult<()> {
+        types::HostDescriptor::set_times(
+            &mut self.wasi,
+            self_,
+            data_access_timestamp,
+            data_modification_timestamp,
+        )
+        .await
+    }
+
+    async fn read(
+        &mut self,
+        self_: Resource<Descriptor>,
+        length: Filesize,
+        offset: Filesize,
+    ) -> FsResult<(Vec<u8>, bool)> {
+        types::HostDescriptor::read(&mut self.wasi, self_, length, offset).await
+    }
+
+    async fn write(
+        &mut self,
+        self_: Resource<Descriptor>,
+        buffer: Vec<u8>,
+        offset: Filesize,
+    ) -> FsResult<Filesize> {
+        if let Some(file) = self.file(&self_) {
+            if !file.grow_to(offset.saturating_add(buffer.len() as u64)) {
+                return insufficient_space();
+            }
+        }
+        types::HostDescriptor::write(&mut self.wasi, self_, buffer, offset).await
+    }
+
+    async fn read_directory(
+        &mut self,
+        self_: Resource<Descriptor>,
+    ) -> FsResult<Resource<DirectoryEntryStream>> {
+        types::HostDescriptor::read_directory(&mut self.wasi, self_).await
+    }
+
+    async fn sync(&mut self, self_: Resource<Descriptor>) -> FsResult<()> {
+        types::HostDescriptor::sync(&mut self.wasi, self_).await
+    }
+
+    async fn create_directory_at(
+        &mut self,
+        self_: Resource<Descriptor>,
+        path: String,
+    ) -> FsResult<()> {
+        types::HostDescriptor::create_directory_at(&mut self.wasi, self_, path).await
+    }
+
+    async fn stat(&mut self, self_: Resource<Descriptor>) -> FsResult<DescriptorStat> {
+        types::HostDescriptor::stat(&mut self.wasi, self_).await
+    }
+
+    async fn stat_at(
+        &mut self,
+        self_: Resource<Descriptor>,
+        path_flags: PathFlags,
+        path: String,
+    ) -> FsResult<DescriptorStat> {
+        types::HostDescriptor::stat_at(&mut self.wasi, self_, path_flags, path).await
+    }
+
+    async fn set_times_at(
+        &mut self,
+        self_: Resource<Descriptor>,
+        path_flags: PathFlags,
+        path: String,
+        data_access_timestamp: NewTimestamp,
+        data_modification_timestamp: NewTimestamp,
+    ) -> FsResult<()> {
+        types::HostDescriptor::set_times_at(
+            &mut self.wasi,
+            self_,
+            path_flags,
+            path,
+            data_access_timestamp,
+            data_modification_timestamp,
+        )
+        .await
+    }
+
+    async fn link_at(
+        &mut self,
+        self_: Resource<Descriptor>,
+        old_path_flags: PathFlags,
+        old_path: String,
+        new_descriptor: Resource<Descriptor>,
+        new_path: String,
+    ) -> FsResult<()> {
+        // Linking a file into a mount from elsewhere adds it to the mount
+        let added = match self.dir_quota(&new_descriptor) {
+            Some(quota)
+                if !self
+                    .dir_quota(&self_)
+                    .is_some_and(|old| Arc::ptr_eq(&old, &quota)) =>
+            {
+                let stat = types::HostDescriptor::stat_at(
+                    &mut self.wasi,
+                    borrow(&self_),
+                    old_path_flags,
+                    old_path.clone(),
+                )
+                .await?;
+                if !quota.try_grow(stat.size) {
+                    return insufficient_space();
+                }
+                Some((quota, stat.size))
+            }
+            _ => None,
+        };
+        let result = types::HostDescriptor::link_at(
+            &mut self.wasi,
+            self_,
+            old_path_flags,
+            old_path,
+            new_descriptor,
+            new_path,
+        )
+        .await;
+        if let (Err(_), Some((quota, size))) = (&result, added) {
+            quota.shrink(size);
+        }
+        result
+    }
+
+    async fn open_at(
+        &mut self,
+        self_: Resource<Descriptor>,
+        path_flags: PathFlags,
+        path: String,
+        open_flags: OpenFlags,
+        flags: DescriptorFlags,
+    ) -> FsResult<Resource<Descriptor>> {
+        let Some(quota) = self.dir_quota(&self_) else {
+            return types::HostDescriptor::open_at(
+                &mut self.wasi,
+                self_,
+                path_flags,
+                path,
+                open_flags,
+                flags,
+            )
+            .await;
+        };
+        let truncated = if open_flags.contains(OpenFlags::TRUNCATE) {
+            self.removable_size(&self_, &path).await
+        } else {
+            0
+        };
+        let fd = types::HostDescriptor::open_at(
+            &mut self.wasi,
+            self_,
+            path_flags,
+            path,
+            open_flags,
+            flags,
+        )
+        .await?;
+        quota.shrink(truncated);
+        // If the new descriptor can't be examined, its file is taken to be
+        // empty, so that writes to it are still accounted
+        let stat = types::HostDescriptor::stat(&mut self.wasi, borrow(&fd)).await;
+        let quoted = match stat {
+            Ok(stat) if stat.type_ == DescriptorType::Directory => Quoted::Dir(quota),
+            stat => Quoted::File(Arc::new(QuotaFile {
+                quota,
+                size: AtomicU64::new(stat.map_or(0, |stat| stat.size)),
+            })),
+        };
+        self.quotas.descriptors.insert(fd.rep(), quoted);
+        Ok(fd)
+    }
+
+    async fn readlink_at(&mut self, self_: Resource<Descriptor>, path: String) -> FsResult<String> {
+        types::HostDescriptor::readlink_at(&mut self.wasi, self_, path).await
+    }
+
+    async fn remove_directory_at(
+        &mut self,
+        self_: Resource<Descriptor>,
+        path: String,
+    ) -> FsResult<()> {
+        types::HostDescriptor::remove_directory_at(&mut self.wasi, self_, path).await
+    }
+
+    async fn rename_at(
+        &mut self,
+        self_: Resource<Descriptor>,
+        old_path: String,
+        new_descriptor: Resource<Descriptor>,
+        new_path: String,
+    ) -> FsResult<()> {
+        let old_quota = self.dir_quota(&self_);
+        let new_quota = self.dir_quota(&new_descriptor);
+        if old_quota.is_none() && new_quota.is_none() {
+            return types::HostDescriptor::rename_at(
+                &mut self.wasi,
+                self_,
+                old_path,
+                new_descriptor,
+                new_path,
+            )
+            .await;
+        }
+        let same_quota = match (&old_quota, &new_quota) {
+            (Some(old), Some(new)) => Arc::ptr_eq(old, new),
+            _ => false,
+        };
+        // A file moved between mounts moves its size between their quotas
+        let moved = if same_quota {
+            0
+        } else {
+            self.removable_size(&self_, &old_path).await
+        };
+        // Replacing a file frees its space
+        let replaced = match &new_quota {
+            Some(_) => self.removable_size(&new_descriptor, &new_path).await,
+            None => 0,
+        };
+        if let (false, Some(quota)) = (same_quota, &new_quota) {
+            if !quota.try_grow(moved) {
+                return insufficient_space();
+            }
+        }
+        let result = types::HostDescriptor::rename_at(
+            &mut self.wasi,
+            self_,
+            old_path,
+            new_descriptor,
+            new_path,
+        )
+        .await;
+        match (&result, same_quota) {
+            (Ok(_), _) => {
+                if let (false, Some(quota)) = (same_quota, &old_quota) {
+                    quota.shrink(moved);
+                }
+                if let Some(quota) = &new_quota {
+                    quota.shrink(replaced);
+                }
+            }
+            (Err(_), false) => {
+                if let Some(quota) = &new_quota {
+                    quota.shrink(moved);
+                }
+            }
+            (Err(_), true) => {}
+        }
+        result
+    }
+
+    async fn symlink_at(
+        &mut self,
+        self_: Resource<Descriptor>,
+        old_path: String,
+        new_path: String,
+    ) -> FsResult<()> {
+        types::HostDescriptor::symlink_at(&mut self.wasi, self_, old_path, new_path).await
+    }
+
+    async fn unlink_file_at(&mut self, self_: Resource<Descriptor>, path: String) -> FsResult<()> {
+        let Some(quota) = self.dir_quota(&self_) else {
+            return types::HostDescriptor::unlink_file_at(&mut self.wasi, self_, path).await;
+        };
+        let removed = self.removable_size(&self_, &path).await;
+        types::HostDescriptor::unlink_file_at(&mut self.wasi, self_, path).await?;
+        quota.shrink(removed);
+        Ok(())
+    }
+
+    async fn is_same_object(
+        &mut self,
+        self_: Resource<Descriptor>,
+        other: Resource<Descriptor>,
+    ) -> wasmtime::Result<bool> {
+        types::HostDescriptor::is_same_object(&mut self.wasi, self_, other).await
+    }
+
+    async fn metadata_hash(&mut self, self_: Resource<Descriptor>) -> FsResult<MetadataHashValue> {
+        types::HostDescriptor::metadata_hash(&mut self.wasi, self_).await
+    }
+
+    async fn metadata_hash_at(
+        &mut self,
+        self_: Resource<Descriptor>,
+        path_flags: PathFlags,
+        path: String,
+    ) -> FsResult<MetadataHashValue> {
+        types::HostDescriptor::metadata_hash_at(&mut self.wasi, self_, path_flags, path).await
+    }
+
+    fn drop(&mut self, rep: Resource<Descriptor>) -> wasmtime::Result<()> {
+        self.quotas.descriptors.remove(&rep.rep());
+        types::HostDescriptor::drop(&mut self.wasi, rep)
+    }
+}
+
+#[async_trait]
+impl types::HostDirectoryEntryStream for QuotaFs<'_> {
+    async fn read_directory_entry(
+        &mut self,
+        self_: Resource<DirectoryEntryStream>,
+    ) -> FsResult<Option<DirectoryEntry>> {
+        types::HostDirectoryEntryStream::read_directory_entry(&mut self.wasi, self_).await
+    }
+
+    fn drop(&mut self, rep: Resource<DirectoryEntryStream>) -> wasmtime::Result<()> {
+        types::HostDirectoryEntryStream::drop(&mut self.wasi, rep)
+    }
+}
+
+impl preopens::Host for QuotaFs<'_> {
+    fn get_directories(&mut self) -> wasmtime::Result<Vec<(Resource<Descriptor>, String)>> {
+        let dirs = preopens::Host::get_directories(&mut self.wasi)?;
+        for (fd, guest_path) in &dirs {
+            if let Some((_, quota)) = self.quotas.preopens.iter().find(|(p, _)| p == guest_path) {
+                self.quotas
+                    .descriptors
+                    .insert(fd.rep(), Quoted::Dir(quota.clone()));
+            }
+        }
+        Ok(dirs)
+    }
+}
+
+/// A stream writing to a file under a mount with a quota.
+struct QuotaOutputStream {
+    inner: OutputStream,
+    file: Arc<QuotaFile>,
+    /// Where the next write goes, or `None` to append
+    position: Option<u64>,
+}
+
+#[async_trait]
+impl HostOutputStream for QuotaOutputStream {
+    fn write(&mut self, bytes: Bytes) -> StreamResult<()> {
+        let len = bytes.len() as u64;
+        let end = match self.position {
+            Some(position) => position.saturating_add(len),
+            None => self.file.size.load(Ordering::SeqCst).saturating_add(len),
+        };
+        if !self.file.grow_to(end) {
+            return Err(StreamError::LastOperationFailed(anyhow::Error::from(
+                ErrorCode::InsufficientSpace,
+            )));
+        }
+        self.inner.write(bytes)?;
+        if let Some(position) = &mut self.position {
+            *position = end;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> StreamResult<()> {
+        self.inner.flush()
+    }
+
+    fn check_write(&mut self) -> StreamResult<usize> {
+        self.inner.check_write()
+    }
+
+    async fn cancel(&mut self) {
+        self.inner.cancel().await
+    }
+}
+
+#[async_trait]
+impl Subscribe for QuotaOutputStream {
+    async fn ready(&mut self) {
+        self.inner.ready().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quota_is_shared_by_files() {
+        let quota = Arc::new(MountQuota::new(10, 4));
+        let file = |size| QuotaFile {
+            quota: quota.clone(),
+            size: AtomicU64::new(size),
+        };
+        let (a, b) = (file(4), file(0));
+
+        // Overwriting doesn't use more space
+        assert!(a.grow_to(4));
+        assert_eq!(4, quota.used());
+
+        assert!(b.grow_to(5));
+        assert_eq!(9, quota.used());
+        assert!(!a.grow_to(6));
+        assert_eq!(9, quota.used());
+
+        b.resized(0);
+        assert_eq!(4, quota.used());
+        assert!(a.grow_to(10));
+        assert_eq!(10, quota.used());
+    }
+}
@@ -1,13 +1,37 @@
-use std::path::PathBuf;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::Metadata,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::UNIX_EPOCH,
+};
 
-use spin_common::{ui::quoted_path, url::parse_file_url};
-use spin_factors::anyhow::{ensure, Context};
+use spin_common::{sha256::hex_digest_from_file, ui::quoted_path, url::parse_file_url};
+use spin_factors::{
+    anyhow::{self, bail, ensure, Context},
+    locked::{FileIntegrity, LockedMap},
+};
 
-use crate::FilesMounter;
+use crate::{FilesMounter, MountQuota};
 
 pub struct SpinFilesMounter {
     working_dir: PathBuf,
     allow_transient_writes: bool,
+    asset_verification: Option<AssetVerification>,
+    /// Host path -> quota of a mount with `max_bytes`, shared by instances
+    quotas: Mutex<HashMap<PathBuf, Arc<MountQuota>>>,
+    /// Host path -> digest of a mounted file, when it was last verified
+    verified: Mutex<HashMap<PathBuf, VerifiedFile>>,
+}
+
+/// The digest of a file, and the size and modification time it had when it
+/// was hashed.
+#[derive(Clone, Debug, PartialEq)]
+struct VerifiedFile {
+    size: u64,
+    modified_ms: Option<u64>,
+    digest: String,
 }
 
 impl SpinFilesMounter {
@@ -15,6 +39,42 @@ impl SpinFilesMounter {
         Self {
             working_dir: working_dir.into(),
             allow_transient_writes,
+            asset_verification: None,
+            quotas: Default::default(),
+            verified: Default::default(),
+        }
+    }
+
+    /// Verifies that the files in read-only mounts haven't changed since the
+    /// app was loaded before mounting them, failing instantiation if they
+    /// have.
+    pub fn with_asset_verification(mut self, verification: Option<AssetVerification>) -> Self {
+        self.asset_verification = verification;
+        self
+    }
+}
+
+/// How to verify that mounted files haven't changed since the app was loaded.
+///
+/// Digests are cached, so either way a file is only hashed again when its
+/// size or modification time changes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AssetVerification {
+    /// Check the size and modification time of each file, only hashing files
+    /// with a different modification time than when the app was loaded.
+    Fast,
+    /// Hash each file the first time it is mounted.
+    Full,
+}
+
+impl FromStr for AssetVerification {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fast" => Ok(Self::Fast),
+            "full" => Ok(Self::Full),
+            _ => bail!("unknown asset verification {s:?}; expected 'fast' or 'full'"),
         }
     }
 }
@@ -41,8 +101,280 @@ impl FilesMounter for SpinFilesMounter {
             let guest_path = guest_path
                 .to_str()
                 .with_context(|| format!("guest path {guest_path:?} not valid UTF-8"))?;
-            ctx.preopened_dir(source_path, guest_path, self.allow_transient_writes)?;
+            let writable = content_dir.writable.unwrap_or(self.allow_transient_writes);
+            if let (true, Some(max_bytes)) = (writable, content_dir.max_bytes) {
+                let quota = self.quota(&source_path, max_bytes)?;
+                ctx.preopened_dir_with_quota(source_path, guest_path, quota)?;
+                continue;
+            }
+            if let (Some(verification), false) = (self.asset_verification, writable) {
+                self.verify_files(&source_path, &content_dir.integrity, verification)
+                    .with_context(|| {
+                        format!(
+                            "Files mount {guest_path:?} of component {:?} failed verification",
+                            app_component.id()
+                        )
+                    })?;
+            }
+            ctx.preopened_dir(source_path, guest_path, writable)?;
         }
         Ok(())
     }
 }
+
+impl SpinFilesMounter {
+    /// Returns the quota of the mount of the given directory, measuring the
+    /// directory the first time it is mounted. Writes are accounted from then
+    /// on, so it isn't measured again.
+    fn quota(&self, path: &Path, max_bytes: u64) -> anyhow::Result<Arc<MountQuota>> {
+        let mut quotas = self.quotas.lock().unwrap();
+        if let Some(quota) = quotas.get(path) {
+            return Ok(quota.clone());
+        }
+        let used = dir_size(path)
+            .with_context(|| format!("failed to measure files mount {}", quoted_path(path)))?;
+        let quota = Arc::new(MountQuota::new(max_bytes, used));
+        quotas.insert(path.to_owned(), quota.clone());
+        Ok(quota)
+    }
+}
+
+impl SpinFilesMounter {
+    /// Checks that the files in a directory and its subdirectories are the files
+    /// recorded when the app was loaded, listing any changes. Mounts with no
+    /// recorded files aren't checked.
+    fn verify_files(
+        &self,
+        dir: &Path,
+        expected: &LockedMap<FileIntegrity>,
+        verification: AssetVerification,
+    ) -> anyhow::Result<()> {
+        if expected.is_empty() {
+            return Ok(());
+        }
+        let mut actual = BTreeMap::new();
+        list_files(dir, "", &mut actual)
+            .with_context(|| format!("failed to list files in {}", quoted_path(dir)))?;
+
+        let mut changes = BTreeMap::new();
+        for (rel_path, integrity) in expected {
+            match actual.remove(rel_path) {
+                None => {
+                    changes.insert(rel_path.clone(), "missing");
+                }
+                Some((path, metadata)) => {
+                    if !self
+                        .file_matches(&path, &metadata, integrity, verification)
+                        .with_context(|| format!("failed to verify {}", quoted_path(&path)))?
+                    {
+                        changes.insert(rel_path.clone(), "modified");
+                    }
+                }
+            }
+        }
+        changes.extend(actual.into_keys().map(|rel_path| (rel_path, "added")));
+
+        if changes.is_empty() {
+            return Ok(());
+        }
+        let report = changes
+            .iter()
+            .map(|(rel_path, change)| format!("\n  {change}: {rel_path}"))
+            .collect::<String>();
+        bail!(
+            "{} file(s) changed since the app was loaded:{report}",
+            changes.len()
+        )
+    }
+
+    fn file_matches(
+        &self,
+        path: &Path,
+        metadata: &Metadata,
+        expected: &FileIntegrity,
+        verification: AssetVerification,
+    ) -> std::io::Result<bool> {
+        if metadata.len() != expected.size {
+            return Ok(false);
+        }
+        if verification == AssetVerification::Fast
+            && expected.modified_ms.is_some()
+            && modified_ms(metadata) == expected.modified_ms
+        {
+            return Ok(true);
+        }
+        Ok(self.digest(path, metadata)? == expected.digest)
+    }
+
+    /// Returns the digest of a mounted file, hashing it only if it has changed
+    /// since it was last hashed.
+    fn digest(&self, path: &Path, metadata: &Metadata) -> std::io::Result<String> {
+        let size = metadata.len();
+        let modified_ms = modified_ms(metadata);
+        if let Some(verified) = self.verified.lock().unwrap().get(path) {
+            // Without a modification time a change can't be detected.
+            if verified.size == size && verified.modified_ms == modified_ms && modified_ms.is_some()
+            {
+                return Ok(verified.digest.clone());
+            }
+        }
+        let digest = format!("sha256:{}", hex_digest_from_file(path)?);
+        self.verified.lock().unwrap().insert(
+            path.to_owned(),
+            VerifiedFile {
+                size,
+                modified_ms,
+                digest: digest.clone(),
+            },
+        );
+        Ok(digest)
+    }
+}
+
+fn modified_ms(metadata: &Metadata) -> Option<u64> {
+    let since_epoch = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    since_epoch.as_millis().try_into().ok()
+}
+
+/// Lists the files in a directory and its subdirectories by path relative to
+/// it, with forward slashes.
+fn list_files(
+    dir: &Path,
+    prefix: &str,
+    files: &mut BTreeMap<String, (PathBuf, Metadata)>,
+) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let rel_path = format!("{prefix}{}", entry.file_name().to_string_lossy());
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            list_files(&entry.path(), &format!("{rel_path}/"), files)?;
+        } else if file_type.is_file() {
+            files.insert(rel_path, (entry.path(), entry.metadata()?));
+        }
+    }
+    Ok(())
+}
+
+/// The total size of the files in a directory and its subdirectories.
+fn dir_size(path: &Path) -> std::io::Result<u64> {
+    let mut size = 0;
+    for entry in std::fs::read_dir(path)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            size += dir_size(&entry.path())?;
+        } else if file_type.is_file() {
+            size += entry.metadata()?.len();
+        }
+    }
+    Ok(size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dir_size_includes_subdirectories() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("a.txt"), [0; 10]).unwrap();
+        std::fs::create_dir(dir.path().join("sub")).unwrap();
+        std::fs::write(dir.path().join("sub/b.txt"), [0; 5]).unwrap();
+        assert_eq!(15, dir_size(dir.path()).unwrap());
+    }
+
+    #[test]
+    fn mounts_are_measured_once() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("a.txt"), [0; 10]).unwrap();
+        let mounter = SpinFilesMounter::new(dir.path(), false);
+        let quota = mounter.quota(dir.path(), 100).unwrap();
+        assert_eq!(10, quota.used());
+
+        std::fs::write(dir.path().join("b.txt"), [0; 5]).unwrap();
+        let again = mounter.quota(dir.path(), 100).unwrap();
+        assert!(Arc::ptr_eq(&quota, &again));
+        assert_eq!(10, again.used());
+    }
+
+    #[test]
+    fn changed_files_fail_verification() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("same.txt"), "spin").unwrap();
+        std::fs::write(dir.path().join("changed.txt"), "spin").unwrap();
+        std::fs::write(dir.path().join("added.txt"), "spin").unwrap();
+
+        let integrity = |modified_ms| FileIntegrity {
+            digest: "sha256:a5a2729ffa0eeacc15323a9168807c72d18d1cb375dbde899c44d6803dad2b19"
+                .into(),
+            size: 4,
+            modified_ms,
+        };
+        let mounter = SpinFilesMounter::new(dir.path(), false);
+        let mut expected = LockedMap::new();
+        expected.insert("same.txt".into(), integrity(Some(0)));
+        expected.insert("missing.txt".into(), integrity(None));
+        expected.insert(
+            "changed.txt".into(),
+            FileIntegrity {
+                digest: "sha256:0".into(),
+                ..integrity(None)
+            },
+        );
+
+        for verification in [AssetVerification::Fast, AssetVerification::Full] {
+            let err = mounter
+                .verify_files(dir.path(), &expected, verification)
+                .unwrap_err();
+            assert_eq!(
+                "3 file(s) changed since the app was loaded:\n  added: added.txt\n  modified: changed.txt\n  missing: missing.txt",
+                err.to_string()
+            );
+        }
+
+        std::fs::remove_file(dir.path().join("added.txt")).unwrap();
+        std::fs::write(dir.path().join("missing.txt"), "spin").unwrap();
+        expected.remove("changed.txt");
+        std::fs::remove_file(dir.path().join("changed.txt")).unwrap();
+        mounter
+            .verify_files(dir.path(), &expected, AssetVerification::Full)
+            .unwrap();
+    }
+
+    #[test]
+    fn digests_are_cached_until_files_change() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("a.txt");
+        std::fs::write(&path, "spin").unwrap();
+        let mut expected = LockedMap::new();
+        expected.insert(
+            "a.txt".into(),
+            FileIntegrity {
+                digest: "sha256:a5a2729ffa0eeacc15323a9168807c72d18d1cb375dbde899c44d6803dad2b19"
+                    .into(),
+                size: 4,
+                modified_ms: None,
+            },
+        );
+        let mounter = SpinFilesMounter::new(dir.path(), false);
+        mounter
+            .verify_files(dir.path(), &expected, AssetVerification::Full)
+            .unwrap();
+
+        // A change which keeps the size and modification time isn't hashed.
+        let modified = std::fs::metadata(&path).unwrap().modified().unwrap();
+        std::fs::write(&path, "SPIN").unwrap();
+        let file = std::fs::File::options().write(true).open(&path).unwrap();
+        file.set_modified(modified).unwrap();
+        mounter
+            .verify_files(dir.path(), &expected, AssetVerification::Full)
+            .unwrap();
+
+        file.set_modified(modified + std::time::Duration::from_secs(1))
+            .unwrap();
+        mounter
+            .verify_files(dir.path(), &expected, AssetVerification::Full)
+            .unwrap_err();
+    }
+}
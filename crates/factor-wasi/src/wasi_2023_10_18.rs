@@ -122,17 +122,18 @@ use wasi::sockets::tcp::{
 };
 use wasi::sockets::udp::Datagram;
 
-use crate::WasiImplInner;
+use crate::{quota::QuotaFs, WasiImplInner};
 
-pub fn add_to_linker<T, F>(linker: &mut Linker<T>, closure: F) -> Result<()>
+pub fn add_to_linker<T, F, G>(linker: &mut Linker<T>, closure: F, fs_closure: G) -> Result<()>
 where
     T: Send,
     F: Fn(&mut T) -> WasiImpl<WasiImplInner> + Send + Sync + Copy + 'static,
+    G: Fn(&mut T) -> QuotaFs + Send + Sync + Copy + 'static,
 {
     wasi::clocks::monotonic_clock::add_to_linker_get_host(linker, closure)?;
     wasi::clocks::wall_clock::add_to_linker_get_host(linker, closure)?;
-    wasi::filesystem::types::add_to_linker_get_host(linker, closure)?;
-    wasi::filesystem::preopens::add_to_linker_get_host(linker, closure)?;
+    wasi::filesystem::types::add_to_linker_get_host(linker, fs_closure)?;
+    wasi::filesystem::preopens::add_to_linker_get_host(linker, fs_closure)?;
     wasi::io::poll::add_to_linker_get_host(linker, closure)?;
     wasi::io::streams::add_to_linker_get_host(linker, closure)?;
     wasi::random::random::add_to_linker_get_host(linker, closure)?;
@@ -192,10 +193,7 @@ where
     }
 }
 
-impl<T> wasi::filesystem::types::Host for WasiImpl<T>
-where
-    T: WasiView,
-{
+impl wasi::filesystem::types::Host for QuotaFs<'_> {
     fn filesystem_error_code(
         &mut self,
         err: Resource<wasi::filesystem::types::Error>,
@@ -205,10 +203,7 @@ where
 }
 
 #[async_trait]
-impl<T> wasi::filesystem::types::HostDescriptor for WasiImpl<T>
-where
-    T: WasiView,
-{
+impl wasi::filesystem::types::HostDescriptor for QuotaFs<'_> {
     fn read_via_stream(
         &mut self,
         self_: Resource<Descriptor>,
@@ -603,10 +598,7 @@ where
 }
 
 #[async_trait]
-impl<T> wasi::filesystem::types::HostDirectoryEntryStream for WasiImpl<T>
-where
-    T: WasiView,
-{
+impl wasi::filesystem::types::HostDirectoryEntryStream for QuotaFs<'_> {
     async fn read_directory_entry(
         &mut self,
         self_: Resource<DirectoryEntryStream>,
@@ -623,10 +615,7 @@ where
     }
 }
 
-impl<T> wasi::filesystem::preopens::Host for WasiImpl<T>
-where
-    T: WasiView,
-{
+impl wasi::filesystem::preopens::Host for QuotaFs<'_> {
     fn get_directories(&mut self) -> wasmtime::Result<Vec<(Resource<Descriptor>, String)>> {
         latest::filesystem::preopens::Host::get_directories(self)
     }
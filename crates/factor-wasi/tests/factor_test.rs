@@ -1,4 +1,4 @@
-use spin_factor_wasi::{DummyFilesMounter, WasiFactor};
+use spin_factor_wasi::{DummyFilesMounter, RuntimeConfig, WasiFactor};
 use spin_factors::{anyhow, RuntimeFactors};
 use spin_factors_test::{toml, TestEnvironment};
 use wasmtime_wasi::bindings::cli::environment::Host;
@@ -28,3 +28,84 @@ async fn environment_works() -> anyhow::Result<()> {
     assert_eq!(val.as_deref(), Some("bar"));
     Ok(())
 }
+
+#[tokio::test]
+async fn environment_passthrough_works() -> anyhow::Result<()> {
+    std::env::set_var("SPIN_PASSTHROUGH_TEST_NAME", "host");
+    std::env::set_var("SPIN_PASSTHROUGH_TEST_OVERRIDDEN", "host");
+    std::env::set_var("SPIN_PASSTHROUGH_GLOBAL_TEST", "global");
+    std::env::set_var("SPIN_PASSTHROUGH_UNLISTED_TEST", "unlisted");
+
+    let factors = TestFactors {
+        wasi: WasiFactor::new(DummyFilesMounter),
+    };
+    let env = TestEnvironment::new(factors)
+        .extend_manifest(toml! {
+            [component.test-component]
+            source = "does-not-exist.wasm"
+            environment = { SPIN_PASSTHROUGH_TEST_OVERRIDDEN = "manifest" }
+            environment_passthrough = [
+                "SPIN_PASSTHROUGH_TEST_NAME",
+                "SPIN_PASSTHROUGH_TEST_OVERRIDDEN",
+                "SPIN_PASSTHROUGH_MISSING_TEST",
+            ]
+        })
+        .runtime_config(TestFactorsRuntimeConfig {
+            wasi: Some(RuntimeConfig {
+                environment_passthrough: vec!["SPIN_PASSTHROUGH_GLOBAL_TEST".into()],
+                allowed_environment_passthrough: vec![
+                    "SPIN_PASSTHROUGH_TEST_*".into(),
+                    "SPIN_PASSTHROUGH_MISSING_TEST".into(),
+                ],
+            }),
+        })?;
+    let mut state = env.build_instance_state().await?;
+    let mut wasi = WasiFactor::get_wasi_impl(&mut state).unwrap();
+
+    let mut vars = wasi
+        .get_environment()?
+        .into_iter()
+        .filter(|(key, _)| key.starts_with("SPIN_PASSTHROUGH_"))
+        .collect::<Vec<_>>();
+    vars.sort();
+    assert_eq!(
+        vars,
+        [
+            ("SPIN_PASSTHROUGH_GLOBAL_TEST", "global"),
+            ("SPIN_PASSTHROUGH_TEST_NAME", "host"),
+            ("SPIN_PASSTHROUGH_TEST_OVERRIDDEN", "manifest"),
+        ]
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn environment_passthrough_must_be_allowed() -> anyhow::Result<()> {
+    let build = |component, allowed: &[&str]| {
+        let mut wasi = WasiFactor::new(DummyFilesMounter);
+        wasi.allow_environment_passthrough(allowed.iter().map(|p| p.to_string()));
+        TestEnvironment::new(TestFactors { wasi }).extend_manifest(component)
+    };
+
+    // Manifests may only request variables by name...
+    let prefix = toml! {
+        [component.test-component]
+        source = "does-not-exist.wasm"
+        environment_passthrough = ["SPIN_PASSTHROUGH_*"]
+    };
+    let env = build(prefix, &["SPIN_PASSTHROUGH_*"]);
+    assert!(env.build_instance_state().await.is_err());
+
+    // ...which the operator allows.
+    let name = toml! {
+        [component.test-component]
+        source = "does-not-exist.wasm"
+        environment_passthrough = ["SPIN_PASSTHROUGH_ALLOWED_TEST"]
+    };
+    let env = build(name.clone(), &[]);
+    assert!(env.build_instance_state().await.is_err());
+    let env = build(name, &["SPIN_PASSTHROUGH_*"]);
+    env.build_instance_state().await?;
+    Ok(())
+}
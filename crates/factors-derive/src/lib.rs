@@ -1,8 +1,18 @@
 use proc_macro2::TokenStream;
 use quote::{format_ident, quote};
-use syn::{parse_macro_input, Data, DeriveInput, Error};
+use syn::{
+    parse_macro_input, Data, DeriveInput, Error, Field, GenericArgument, PathArguments, Type,
+};
 
-#[proc_macro_derive(RuntimeFactors)]
+/// Derives `RuntimeFactors` for a struct whose fields are all `Factor`s.
+///
+/// A field marked `#[factor(optional)]` must have type `Option<F>` where `F`
+/// is a `Factor`. If it is `None` the factor is disabled: it is not initialized,
+/// configured or prepared, and its instance state is `None`. The generated
+/// `disable_unconfigured` method disables the optional factors that a runtime
+/// config has no configuration for, so that factors an app's runtime config
+/// doesn't call for are left out.
+#[proc_macro_derive(RuntimeFactors, attributes(factor))]
 pub fn derive_factors(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     let input = parse_macro_input!(input as DeriveInput);
     let expanded = expand_factors(&input).unwrap_or_else(|err| err.into_compile_error());
@@ -48,6 +58,7 @@ fn expand_factors(input: &DeriveInput) -> syn::Result<TokenStream> {
     };
     let mut factor_names = Vec::with_capacity(fields.len());
     let mut factor_types = Vec::with_capacity(fields.len());
+    let mut optional = Vec::with_capacity(fields.len());
     for field in fields.iter() {
         factor_names.push(
             field
@@ -55,7 +66,16 @@ fn expand_factors(input: &DeriveInput) -> syn::Result<TokenStream> {
                 .as_ref()
                 .ok_or_else(|| Error::new_spanned(input, "tuple structs are not supported"))?,
         );
-        factor_types.push(&field.ty);
+        if is_optional(field)? {
+            let ty = option_inner_type(&field.ty).ok_or_else(|| {
+                Error::new_spanned(&field.ty, "optional factors must have type `Option<_>`")
+            })?;
+            factor_types.push(ty);
+            optional.push(true);
+        } else {
+            factor_types.push(&field.ty);
+            optional.push(false);
+        }
     }
 
     let Any = quote!(::std::any::Any);
@@ -70,8 +90,167 @@ fn expand_factors(input: &DeriveInput) -> syn::Result<TokenStream> {
     let Factor = quote!(#factors_path::Factor);
     let ConfiguredApp = quote!(#factors_path::ConfiguredApp);
     let FactorInstanceBuilder = quote!(#factors_path::FactorInstanceBuilder);
+    let FactorInstanceState = quote!(#factors_path::FactorInstanceState);
+
+    // Optional factors differ from required ones wherever the factor itself,
+    // its builder or its instance state is touched.
+    let mut init_factors = Vec::with_capacity(fields.len());
+    let mut configure_factors = Vec::with_capacity(fields.len());
+    let mut prepare_factors = Vec::with_capacity(fields.len());
+    let mut build_factors = Vec::with_capacity(fields.len());
+    let mut builder_accessors = Vec::with_capacity(fields.len());
+    let mut for_factor_builders = Vec::with_capacity(fields.len());
+    let mut state_fields = Vec::with_capacity(fields.len());
+    let mut get_states = Vec::with_capacity(fields.len());
+    let mut optional_names = Vec::with_capacity(fields.len());
+    for ((name, ty), optional) in factor_names.iter().zip(&factor_types).zip(optional) {
+        let init_context = |get_state: TokenStream| {
+            quote! {
+                #factors_path::InitContext::<T, #ty>::new(
+                    linker,
+                    |data| {
+                        let state = data.as_instance_state();
+                        #get_state
+                    },
+                    |data| {
+                        let state = data.as_instance_state();
+                        (#get_state, &mut state.__table)
+                    },
+                )
+            }
+        };
+        let configure_app_context = quote! {
+            #factors_path::ConfigureAppContext::<Self, #ty>::new(
+                &app,
+                &app_state,
+                runtime_config.#name,
+            )?
+        };
+        let prepare_context = quote! {
+            #factors_path::PrepareContext::new(
+                configured_app.app_state::<#ty>().unwrap(),
+                &app_component,
+                &mut builders,
+            )
+        };
+
+        if optional {
+            optional_names.push(name);
+            let init_context = init_context(quote! {
+                state.#name.as_mut().expect("optional factor was initialized but not built")
+            });
+            init_factors.push(quote! {
+                if let Some(factor) = &mut self.#name {
+                    #Factor::init::<T>(factor, #init_context)
+                        .map_err(#Error::factor_init_error::<#ty>)?;
+                }
+            });
+            configure_factors.push(quote! {
+                if let Some(factor) = &self.#name {
+                    app_state.#name = Some(
+                        #Factor::configure_app(factor, #configure_app_context)
+                            .map_err(#Error::factor_configure_app_error::<#ty>)?
+                    );
+                }
+            });
+            prepare_factors.push(quote! {
+                if let Some(factor) = &self.#name {
+                    builders.#name = Some(
+                        #Factor::prepare::<Self>(factor, #prepare_context)
+                            .map_err(#Error::factor_prepare_error::<#ty>)?
+                    );
+                }
+            });
+            build_factors.push(quote! {
+                #name: builders.#name
+                    .map(#FactorInstanceBuilder::build)
+                    .transpose()
+                    .map_err(#Error::factor_build_error::<#ty>)?,
+            });
+            builder_accessors.push(quote! {
+                pub fn #name(&mut self) -> Option<&mut <#ty as #Factor>::InstanceBuilder> {
+                    self.#name.as_mut()
+                }
+            });
+            for_factor_builders.push(quote! {
+                if type_id == #TypeId::of::<<#ty as #Factor>::InstanceBuilder>() {
+                    return self.#name.as_mut().map(|builder| {
+                        <dyn #Any>::downcast_mut(builder).unwrap()
+                    });
+                }
+            });
+            state_fields.push(quote! {
+                pub #name: Option<#FactorInstanceState<#ty>>,
+            });
+            get_states.push(quote! {
+                if let Some(state) = &mut self.#name {
+                    if let Some(state) = (state as &mut (dyn #Any + #Send)).downcast_mut() {
+                        return Some((state, &mut self.__table))
+                    }
+                }
+            });
+        } else {
+            let init_context = init_context(quote!(&mut state.#name));
+            init_factors.push(quote! {
+                #Factor::init::<T>(&mut self.#name, #init_context)
+                    .map_err(#Error::factor_init_error::<#ty>)?;
+            });
+            configure_factors.push(quote! {
+                app_state.#name = Some(
+                    #Factor::configure_app(&self.#name, #configure_app_context)
+                        .map_err(#Error::factor_configure_app_error::<#ty>)?
+                );
+            });
+            prepare_factors.push(quote! {
+                builders.#name = Some(
+                    #Factor::prepare::<Self>(&self.#name, #prepare_context)
+                        .map_err(#Error::factor_prepare_error::<#ty>)?
+                );
+            });
+            build_factors.push(quote! {
+                #name: #FactorInstanceBuilder::build(
+                    builders.#name.unwrap()
+                ).map_err(#Error::factor_build_error::<#ty>)?,
+            });
+            builder_accessors.push(quote! {
+                pub fn #name(&mut self) -> &mut <#ty as #Factor>::InstanceBuilder {
+                    self.#name.as_mut().unwrap()
+                }
+            });
+            for_factor_builders.push(quote! {
+                if type_id == #TypeId::of::<<#ty as #Factor>::InstanceBuilder>() {
+                    let builder = self.#name.as_mut().unwrap();
+                    return Some(
+                        <dyn #Any>::downcast_mut(builder).unwrap()
+                    );
+                }
+            });
+            state_fields.push(quote! {
+                pub #name: #FactorInstanceState<#ty>,
+            });
+            get_states.push(quote! {
+                if let Some(state) = (&mut self.#name as &mut (dyn #Any + #Send)).downcast_mut() {
+                    return Some((state, &mut self.__table))
+                }
+            });
+        }
+    }
 
     Ok(quote! {
+        impl #name {
+            /// Disables the optional factors that the given runtime config has
+            /// no configuration for.
+            #[allow(dead_code)]
+            pub fn disable_unconfigured(&mut self, runtime_config: &#runtime_config_name) {
+                #(
+                    if runtime_config.#optional_names.is_none() {
+                        self.#optional_names = None;
+                    }
+                )*
+                _ = runtime_config;
+            }
+        }
+
         impl #factors_path::RuntimeFactors for #name {
             type AppState = #app_state_name;
             type InstanceBuilders = #builders_name;
@@ -93,19 +272,7 @@ fn expand_factors(input: &DeriveInput) -> syn::Result<TokenStream> {
                     }
                 }
 
-                #(
-                    #Factor::init::<T>(
-                        &mut self.#factor_names,
-                        #factors_path::InitContext::<T, #factor_types>::new(
-                            linker,
-                            |data| &mut data.as_instance_state().#factor_names,
-                            |data| {
-                                let state = data.as_instance_state();
-                                (&mut state.#factor_names, &mut state.__table)
-                            },
-                        )
-                    ).map_err(#Error::factor_init_error::<#factor_types>)?;
-                )*
+                #( #init_factors )*
                 Ok(())
             }
 
@@ -117,18 +284,7 @@ fn expand_factors(input: &DeriveInput) -> syn::Result<TokenStream> {
                 let mut app_state = #app_state_name {
                     #( #factor_names: None, )*
                 };
-                #(
-                    app_state.#factor_names = Some(
-                        #Factor::configure_app(
-                            &self.#factor_names,
-                            #factors_path::ConfigureAppContext::<Self, #factor_types>::new(
-                                &app,
-                                &app_state,
-                                runtime_config.#factor_names,
-                            )?,
-                        ).map_err(#Error::factor_configure_app_error::<#factor_types>)?
-                    );
-                )*
+                #( #configure_factors )*
                 Ok(#ConfiguredApp::new(app, app_state))
             }
 
@@ -142,18 +298,7 @@ fn expand_factors(input: &DeriveInput) -> syn::Result<TokenStream> {
                 let mut builders = #builders_name {
                     #( #factor_names: None, )*
                 };
-                #(
-                    builders.#factor_names = Some(
-                        #Factor::prepare::<Self>(
-                            &self.#factor_names,
-                            #factors_path::PrepareContext::new(
-                                configured_app.app_state::<#factor_types>().unwrap(),
-                                &app_component,
-                                &mut builders,
-                            ),
-                        ).map_err(#Error::factor_prepare_error::<#factor_types>)?
-                    );
-                )*
+                #( #prepare_factors )*
                 Ok(builders)
             }
 
@@ -163,11 +308,7 @@ fn expand_factors(input: &DeriveInput) -> syn::Result<TokenStream> {
             ) -> #Result<Self::InstanceState> {
                 Ok(#state_name {
                     __table: #ResourceTable::new(),
-                    #(
-                        #factor_names: #FactorInstanceBuilder::build(
-                            builders.#factor_names.unwrap()
-                        ).map_err(#Error::factor_build_error::<#factor_types>)?,
-                    )*
+                    #( #build_factors )*
                 })
             }
 
@@ -213,11 +354,7 @@ fn expand_factors(input: &DeriveInput) -> syn::Result<TokenStream> {
 
         #[allow(dead_code)]
         impl #builders_name {
-            #(
-                pub fn #factor_names(&mut self) -> &mut <#factor_types as #Factor>::InstanceBuilder {
-                    self.#factor_names.as_mut().unwrap()
-                }
-            )*
+            #( #builder_accessors )*
         }
 
         impl #factors_path::HasInstanceBuilder for #builders_name {
@@ -225,34 +362,21 @@ fn expand_factors(input: &DeriveInput) -> syn::Result<TokenStream> {
                 &mut self
             ) -> Option<&mut F::InstanceBuilder> {
                 let type_id = #TypeId::of::<F::InstanceBuilder>();
-                #(
-                    if type_id == #TypeId::of::<<#factor_types as #Factor>::InstanceBuilder>() {
-                        let builder = self.#factor_names.as_mut().unwrap();
-                        return Some(
-                            <dyn #Any>::downcast_mut(builder).unwrap()
-                        );
-                    }
-                )*
+                #( #for_factor_builders )*
                 None
             }
         }
 
         #vis struct #state_name {
             __table: #ResourceTable,
-            #(
-                pub #factor_names: #factors_path::FactorInstanceState<#factor_types>,
-            )*
+            #( #state_fields )*
         }
 
         impl #factors_path::RuntimeFactorsInstanceState for #state_name {
             fn get_with_table<F: #Factor>(
                 &mut self
             ) -> ::std::option::Option<(&mut #factors_path::FactorInstanceState<F>, &mut #ResourceTable)> {
-                #(
-                    if let Some(state) = (&mut self.#factor_names as &mut (dyn #Any + #Send)).downcast_mut() {
-                        return Some((state, &mut self.__table))
-                    }
-                )*
+                #( #get_states )*
                 None
             }
 
@@ -297,3 +421,118 @@ fn expand_factors(input: &DeriveInput) -> syn::Result<TokenStream> {
         }
     })
 }
+
+/// Derives `Factor` for a type that implements `SimpleFactor`.
+///
+/// Bindings listed in a `#[factor(bindings(...))]` attribute are linked when
+/// the factor is initialized; each must be a bindgen-generated
+/// `add_to_linker` function for the factor's instance state, e.g.
+/// `#[factor(bindings(my_world::greeting::add_to_linker))]`.
+#[proc_macro_derive(Factor, attributes(factor))]
+pub fn derive_factor(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_factor(&input)
+        .unwrap_or_else(|err| err.into_compile_error())
+        .into()
+}
+
+#[allow(non_snake_case)]
+fn expand_factor(input: &DeriveInput) -> syn::Result<TokenStream> {
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let mut bindings = vec![];
+    for attr in input
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("factor"))
+    {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("bindings") {
+                meta.parse_nested_meta(|binding| {
+                    bindings.push(binding.path);
+                    Ok(())
+                })
+            } else {
+                Err(meta.error("unsupported factor attribute"))
+            }
+        })?;
+    }
+
+    let factors_path = quote!(::spin_factors);
+    let Factor = quote!(#factors_path::Factor);
+    let SimpleFactor = quote!(#factors_path::simple::SimpleFactor);
+    let InstanceStateBuilder = quote!(#factors_path::simple::InstanceStateBuilder);
+    let anyhow = quote!(#factors_path::anyhow);
+
+    Ok(quote! {
+        impl #impl_generics #Factor for #name #ty_generics #where_clause {
+            type RuntimeConfig = <Self as #SimpleFactor>::RuntimeConfig;
+            type AppState = <Self as #SimpleFactor>::AppState;
+            type InstanceBuilder = #InstanceStateBuilder<<Self as #SimpleFactor>::InstanceState>;
+
+            fn init<T: ::std::marker::Send + 'static>(
+                &mut self,
+                mut ctx: #factors_path::InitContext<T, Self>,
+            ) -> #anyhow::Result<()> {
+                #( ctx.link_bindings(#bindings)?; )*
+                _ = &mut ctx;
+                Ok(())
+            }
+
+            fn configure_app<T: #factors_path::RuntimeFactors>(
+                &self,
+                mut ctx: #factors_path::ConfigureAppContext<T, Self>,
+            ) -> #anyhow::Result<Self::AppState> {
+                let runtime_config = ctx.take_runtime_config();
+                <Self as #SimpleFactor>::configure_app(self, ctx.app(), runtime_config)
+            }
+
+            fn prepare<T: #factors_path::RuntimeFactors>(
+                &self,
+                ctx: #factors_path::PrepareContext<T, Self>,
+            ) -> #anyhow::Result<Self::InstanceBuilder> {
+                <Self as #SimpleFactor>::instance_state(self, ctx.app_state(), ctx.app_component())
+                    .map(#InstanceStateBuilder::new)
+            }
+        }
+    })
+}
+
+/// Returns whether the field is marked `#[factor(optional)]`.
+fn is_optional(field: &Field) -> syn::Result<bool> {
+    let mut optional = false;
+    for attr in field
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("factor"))
+    {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("optional") {
+                optional = true;
+                Ok(())
+            } else {
+                Err(meta.error("unsupported factor attribute"))
+            }
+        })?;
+    }
+    Ok(optional)
+}
+
+/// Returns `T` if the type is `Option<T>`.
+fn option_inner_type(ty: &Type) -> Option<&Type> {
+    let Type::Path(path) = ty else {
+        return None;
+    };
+    let segment = path.path.segments.last()?;
+    if path.qself.is_some() || segment.ident != "Option" {
+        return None;
+    }
+    let PathArguments::AngleBracketed(args) = &segment.arguments else {
+        return None;
+    };
+    match args.args.iter().collect::<Vec<_>>().as_slice() {
+        [GenericArgument::Type(ty)] => Some(ty),
+        _ => None,
+    }
+}
@@ -0,0 +1,89 @@
+//! Limits on how many instances run at once.
+
+use std::{fmt, sync::Arc};
+
+use spin_app::ConcurrencyLimit;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Bounds the number of instances running at once, queueing the excess for
+/// up to the limit's queue timeout.
+#[derive(Clone)]
+pub(crate) struct Limiter {
+    limit: ConcurrencyLimit,
+    semaphore: Arc<Semaphore>,
+}
+
+impl Limiter {
+    pub fn new(limit: ConcurrencyLimit) -> Self {
+        Self {
+            limit,
+            semaphore: Arc::new(Semaphore::new(limit.max_instances.get())),
+        }
+    }
+
+    /// Whether every slot is taken, so that the next instance must wait.
+    pub fn is_full(&self) -> bool {
+        self.semaphore.available_permits() == 0
+    }
+
+    /// Waits for a free slot, which is held until the returned permit is
+    /// dropped. `component_id` identifies a per-component limit in errors.
+    pub async fn acquire(
+        &self,
+        component_id: Option<&str>,
+    ) -> Result<OwnedSemaphorePermit, ConcurrencyLimitExceeded> {
+        let acquire = self.semaphore.clone().acquire_owned();
+        let permit = match self.limit.queue_timeout() {
+            Some(timeout) => tokio::time::timeout(timeout, acquire).await.ok(),
+            None => Some(acquire.await),
+        };
+        // The semaphore is never closed, so acquiring fails only on timeout.
+        permit
+            .and_then(Result::ok)
+            .ok_or_else(|| ConcurrencyLimitExceeded {
+                component_id: component_id.map(ToOwned::to_owned),
+                max_instances: self.limit.max_instances.get(),
+            })
+    }
+}
+
+/// The concurrency slots held by an instance, released when dropped.
+///
+/// Returned by [`FactorsExecutorApp::acquire_concurrency_permits`] for
+/// instances which are kept idle between executions without holding slots.
+///
+/// [`FactorsExecutorApp::acquire_concurrency_permits`]: crate::FactorsExecutorApp::acquire_concurrency_permits
+#[derive(Default)]
+pub struct ConcurrencyPermits(pub(crate) Vec<OwnedSemaphorePermit>);
+
+/// The error returned by [`FactorsExecutorApp::prepare`] when an instance
+/// could not start within its concurrency limit's queue timeout.
+///
+/// Triggers can recover it with [`anyhow::Error::downcast_ref`] to reject the
+/// event as overloaded rather than failed, e.g. with an HTTP 503.
+///
+/// [`FactorsExecutorApp::prepare`]: crate::FactorsExecutorApp::prepare
+#[derive(Debug)]
+pub struct ConcurrencyLimitExceeded {
+    /// The component whose limit was reached, or `None` for the limit on all
+    /// of the trigger's components.
+    pub component_id: Option<String>,
+    /// The number of instances allowed to run at once.
+    pub max_instances: usize,
+}
+
+impl fmt::Display for ConcurrencyLimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.component_id {
+            Some(id) => write!(f, "component {id:?}")?,
+            None => f.write_str("trigger")?,
+        }
+        write!(
+            f,
+            " is running its limit of {} instance(s) and no slot became free in time",
+            self.max_instances
+        )
+    }
+}
+
+impl std::error::Error for ConcurrencyLimitExceeded {}
@@ -1,13 +1,22 @@
-use std::{collections::HashMap, sync::Arc};
+mod concurrency;
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
 
 use anyhow::Context;
-use spin_app::{App, AppComponent};
+use concurrency::Limiter;
+use spin_app::{App, AppComponent, ConcurrencyLimit, Precompile};
+use spin_common::diagnostics::Diagnostics;
 use spin_core::{async_trait, Component};
 use spin_factors::{
     AsInstanceState, ConfiguredApp, Factor, HasInstanceBuilder, RuntimeFactors,
     RuntimeFactorsInstanceState,
 };
 
+pub use concurrency::{ConcurrencyLimitExceeded, ConcurrencyPermits};
+
 /// A FactorsExecutor manages execution of a Spin app.
 ///
 /// It is generic over the executor's [`RuntimeFactors`]. Additionally, it
@@ -16,6 +25,8 @@ pub struct FactorsExecutor<T: RuntimeFactors, U = ()> {
     core_engine: spin_core::Engine<InstanceState<T::InstanceState, U>>,
     factors: T,
     hooks: Vec<Box<dyn ExecutorHooks<T, U>>>,
+    concurrency_limit: Option<ConcurrencyLimit>,
+    diagnostics: Diagnostics,
 }
 
 impl<T: RuntimeFactors, U: Send + 'static> FactorsExecutor<T, U> {
@@ -33,6 +44,8 @@ impl<T: RuntimeFactors, U: Send + 'static> FactorsExecutor<T, U> {
             factors,
             core_engine: core_engine_builder.build(),
             hooks: Default::default(),
+            concurrency_limit: None,
+            diagnostics: Diagnostics::new(),
         })
     }
 
@@ -47,12 +60,33 @@ impl<T: RuntimeFactors, U: Send + 'static> FactorsExecutor<T, U> {
         self.hooks.push(Box::new(hooks));
     }
 
+    /// Limits the number of instances, across all components, that apps
+    /// loaded with this executor run at once.
+    ///
+    /// This applies in addition to any per-component
+    /// [`AppComponent::concurrency_limit`]s. Events in excess of either limit
+    /// wait in [`FactorsExecutorApp::prepare`] for up to the limit's queue
+    /// timeout.
+    pub fn limit_concurrency(&mut self, limit: ConcurrencyLimit) {
+        self.concurrency_limit = Some(limit);
+    }
+
+    /// Sets where diagnostics about apps loaded with this executor are
+    /// reported.
+    pub fn set_diagnostics(&mut self, diagnostics: Diagnostics) {
+        self.diagnostics = diagnostics;
+    }
+
     /// Loads a [`App`] with this executor.
+    ///
+    /// Components are compiled according to their [`Precompile`] strategy:
+    /// eager components are compiled here, while lazy and never components
+    /// are compiled by [`FactorsExecutorApp::prepare`] when first needed.
     pub async fn load_app(
         self: Arc<Self>,
         app: App,
         runtime_config: T::RuntimeConfig,
-        component_loader: &impl ComponentLoader,
+        component_loader: &(impl ComponentLoader + Clone + 'static),
     ) -> anyhow::Result<FactorsExecutorApp<T, U>> {
         let configured_app = self
             .factors
@@ -63,21 +97,48 @@ impl<T: RuntimeFactors, U: Send + 'static> FactorsExecutor<T, U> {
             hooks.configure_app(&configured_app).await?;
         }
 
+        // Middleware components import the handlers of the components they
+        // wrap, so are only compiled as part of those components.
+        let mut middleware_ids = HashSet::new();
+        for app_component in configured_app.app().components() {
+            for middleware in app_component.middleware()? {
+                middleware_ids.insert(middleware.id().to_owned());
+            }
+        }
+
         let mut component_instance_pres = HashMap::new();
 
         for app_component in configured_app.app().components() {
-            let component = component_loader
-                .load_component(self.core_engine.as_ref(), &app_component)
-                .await?;
-            let instance_pre = self.core_engine.instantiate_pre(&component)?;
-
-            component_instance_pres.insert(app_component.id().to_string(), instance_pre);
+            let precompile = if middleware_ids.contains(app_component.id()) {
+                Precompile::Lazy
+            } else {
+                app_component.precompile()?
+            };
+            let instance_pre = if precompile == Precompile::Eager {
+                let component = component_loader
+                    .load_component(self.core_engine.as_ref(), &app_component)
+                    .await?;
+                Some(self.core_engine.instantiate_pre(&component)?)
+            } else {
+                None
+            };
+
+            component_instance_pres.insert(
+                app_component.id().to_string(),
+                ComponentInstancePre {
+                    precompile,
+                    instance_pre: tokio::sync::OnceCell::new_with(instance_pre),
+                    limiter: app_component.concurrency_limit()?.map(Limiter::new),
+                },
+            );
         }
 
         Ok(FactorsExecutorApp {
+            limiter: self.concurrency_limit.map(Limiter::new),
             executor: self.clone(),
-            configured_app,
-            component_instance_pres,
+            configured_app: Arc::new(configured_app),
+            component_loader: Arc::new(component_loader.clone()),
+            component_instance_pres: Arc::new(component_instance_pres),
         })
     }
 }
@@ -102,7 +163,7 @@ where
 
 /// A ComponentLoader is responsible for loading Wasmtime [`Component`]s.
 #[async_trait]
-pub trait ComponentLoader {
+pub trait ComponentLoader: Send + Sync {
     /// Loads a [`Component`] for the given [`AppComponent`].
     async fn load_component(
         &self,
@@ -114,15 +175,40 @@ pub trait ComponentLoader {
 type InstancePre<T, U> =
     spin_core::InstancePre<InstanceState<<T as RuntimeFactors>::InstanceState, U>>;
 
+/// The (possibly not yet compiled) [`InstancePre`] for a component.
+struct ComponentInstancePre<T: RuntimeFactors, U> {
+    precompile: Precompile,
+    instance_pre: tokio::sync::OnceCell<InstancePre<T, U>>,
+    /// The component's own concurrency limit, if any.
+    limiter: Option<Limiter>,
+}
+
 /// A FactorsExecutorApp represents a loaded Spin app, ready for instantiation.
 ///
 /// It is generic over the executor's [`RuntimeFactors`] and any ad-hoc additional
 /// per-instance state needed by the caller.
+///
+/// Cloning a FactorsExecutorApp is cheap; clones share compiled components.
 pub struct FactorsExecutorApp<T: RuntimeFactors, U> {
     executor: Arc<FactorsExecutor<T, U>>,
-    configured_app: ConfiguredApp<T>,
+    configured_app: Arc<ConfiguredApp<T>>,
+    component_loader: Arc<dyn ComponentLoader>,
     // Maps component IDs -> InstancePres
-    component_instance_pres: HashMap<String, InstancePre<T, U>>,
+    component_instance_pres: Arc<HashMap<String, ComponentInstancePre<T, U>>>,
+    // The concurrency limit shared by all components, if any
+    limiter: Option<Limiter>,
+}
+
+impl<T: RuntimeFactors, U> Clone for FactorsExecutorApp<T, U> {
+    fn clone(&self) -> Self {
+        Self {
+            executor: self.executor.clone(),
+            configured_app: self.configured_app.clone(),
+            component_loader: self.component_loader.clone(),
+            component_instance_pres: self.component_instance_pres.clone(),
+            limiter: self.limiter.clone(),
+        }
+    }
 }
 
 impl<T: RuntimeFactors, U: Send + 'static> FactorsExecutorApp<T, U> {
@@ -138,23 +224,123 @@ impl<T: RuntimeFactors, U: Send + 'static> FactorsExecutorApp<T, U> {
         self.configured_app.app()
     }
 
+    /// Where diagnostics about the app are reported.
+    pub fn diagnostics(&self) -> &Diagnostics {
+        &self.executor.diagnostics
+    }
+
+    /// Returns the compiled component for the given component ID.
+    ///
+    /// Returns an error if the component has not been compiled yet; see
+    /// [`FactorsExecutorApp::is_compiled`].
     pub fn get_component(&self, component_id: &str) -> anyhow::Result<&Component> {
         let instance_pre = self
             .component_instance_pres
             .get(component_id)
-            .with_context(|| format!("no such component {component_id:?}"))?;
+            .with_context(|| format!("no such component {component_id:?}"))?
+            .instance_pre
+            .get()
+            .with_context(|| format!("component {component_id:?} has not been compiled"))?;
         Ok(instance_pre.component())
     }
 
+    /// Configures a new version of this app, whose components have the same
+    /// sources, reusing this app's compiled components.
+    ///
+    /// This applies changes to the app's configuration, such as its
+    /// variables or its components' environments and allowed hosts, without
+    /// recompiling it. Changes to components' concurrency limits aren't
+    /// applied. Returns an error if components have been added or removed, or
+    /// their sources or dependencies have changed; apps with such changes
+    /// must be loaded with [`FactorsExecutor::load_app`].
+    pub async fn reconfigure(
+        &self,
+        app: App,
+        runtime_config: T::RuntimeConfig,
+    ) -> anyhow::Result<Self> {
+        ensure_same_components(self.app(), &app)?;
+
+        let configured_app = self
+            .executor
+            .factors
+            .configure_app(app, runtime_config)
+            .context("failed to configure app")?;
+
+        for hooks in &self.executor.hooks {
+            hooks.configure_app(&configured_app).await?;
+        }
+
+        Ok(Self {
+            configured_app: Arc::new(configured_app),
+            ..self.clone()
+        })
+    }
+
+    /// Returns true if the given component has been compiled and is ready
+    /// for instantiation.
+    pub fn is_compiled(&self, component_id: &str) -> bool {
+        self.component_instance_pres
+            .get(component_id)
+            .is_some_and(|pre| pre.instance_pre.initialized())
+    }
+
+    /// Returns true if the given component, or the executor, is running as
+    /// many instances as its concurrency limit allows, so that new instances
+    /// of the component must wait for one to finish.
+    pub fn is_at_concurrency_limit(&self, component_id: &str) -> bool {
+        let component_full = self
+            .component_instance_pres
+            .get(component_id)
+            .and_then(|pre| pre.limiter.as_ref())
+            .is_some_and(Limiter::is_full);
+        component_full || self.limiter.as_ref().is_some_and(Limiter::is_full)
+    }
+
     /// Returns an instance builder for the given component ID.
-    pub fn prepare(&self, component_id: &str) -> anyhow::Result<FactorsInstanceBuilder<T, U>> {
+    ///
+    /// Components that were not compiled by [`FactorsExecutor::load_app`] are
+    /// compiled here.
+    ///
+    /// If the component or the executor has a concurrency limit, this waits
+    /// for the number of running instances to fall below it, failing with
+    /// [`ConcurrencyLimitExceeded`] if that takes longer than the limit's
+    /// queue timeout. The slot is held until the instance is dropped.
+    pub async fn prepare(
+        &self,
+        component_id: &str,
+    ) -> anyhow::Result<FactorsInstanceBuilder<T, U>> {
+        let concurrency_permits = self.acquire_concurrency_permits(component_id).await?;
+        self.prepare_with_permits(component_id, concurrency_permits)
+            .await
+    }
+
+    /// Returns an instance builder for the given component ID, as
+    /// [`Self::prepare`] does, but holding `concurrency_permits` rather than
+    /// waiting for slots within the concurrency limits.
+    ///
+    /// This is for executions which are limited in other ways, and shouldn't
+    /// take slots from those the limits are for.
+    pub async fn prepare_with_permits(
+        &self,
+        component_id: &str,
+        concurrency_permits: ConcurrencyPermits,
+    ) -> anyhow::Result<FactorsInstanceBuilder<T, U>> {
         let app_component = self
             .configured_app
             .app()
             .get_component(component_id)
             .with_context(|| format!("no such component {component_id:?}"))?;
 
-        let instance_pre = self.component_instance_pres.get(component_id).unwrap();
+        let component_instance_pre = self.component_instance_pres.get(component_id).unwrap();
+
+        let instance_pre = match component_instance_pre.precompile {
+            Precompile::Eager | Precompile::Lazy => component_instance_pre
+                .instance_pre
+                .get_or_try_init(|| self.load_instance_pre(&app_component))
+                .await?
+                .clone(),
+            Precompile::Never => self.load_instance_pre(&app_component).await?,
+        };
 
         let factor_builders = self
             .executor
@@ -169,6 +355,7 @@ impl<T: RuntimeFactors, U: Send + 'static> FactorsExecutorApp<T, U> {
             instance_pre,
             app_component,
             factors: &self.executor.factors,
+            concurrency_permits,
         };
 
         for hooks in &self.executor.hooks {
@@ -177,6 +364,44 @@ impl<T: RuntimeFactors, U: Send + 'static> FactorsExecutorApp<T, U> {
 
         Ok(builder)
     }
+
+    /// Waits for slots within the component's and the executor's concurrency
+    /// limits, as [`Self::prepare`] does, failing with
+    /// [`ConcurrencyLimitExceeded`] if that takes longer than a limit's queue
+    /// timeout.
+    ///
+    /// This is for instances kept between executions, which release their
+    /// slots with [`InstanceState::release_concurrency_permits`] while idle.
+    pub async fn acquire_concurrency_permits(
+        &self,
+        component_id: &str,
+    ) -> anyhow::Result<ConcurrencyPermits> {
+        let component_instance_pre = self
+            .component_instance_pres
+            .get(component_id)
+            .with_context(|| format!("no such component {component_id:?}"))?;
+        // Wait on the component's own limit first, so that events queued for a
+        // busy component don't hold slots that other components could use.
+        let mut permits = vec![];
+        if let Some(limiter) = &component_instance_pre.limiter {
+            permits.push(limiter.acquire(Some(component_id)).await?);
+        }
+        if let Some(limiter) = &self.limiter {
+            permits.push(limiter.acquire(None).await?);
+        }
+        Ok(ConcurrencyPermits(permits))
+    }
+
+    async fn load_instance_pre(
+        &self,
+        app_component: &AppComponent<'_>,
+    ) -> anyhow::Result<InstancePre<T, U>> {
+        let component = self
+            .component_loader
+            .load_component(self.executor.core_engine.as_ref(), app_component)
+            .await?;
+        self.executor.core_engine.instantiate_pre(&component)
+    }
 }
 
 /// A FactorsInstanceBuilder manages the instantiation of a Spin component instance.
@@ -187,8 +412,9 @@ pub struct FactorsInstanceBuilder<'a, F: RuntimeFactors, U> {
     app_component: AppComponent<'a>,
     store_builder: spin_core::StoreBuilder,
     factor_builders: F::InstanceBuilders,
-    instance_pre: &'a InstancePre<F, U>,
+    instance_pre: InstancePre<F, U>,
     factors: &'a F,
+    concurrency_permits: ConcurrencyPermits,
 }
 
 impl<'a, T: RuntimeFactors, U> FactorsInstanceBuilder<'a, T, U> {
@@ -236,6 +462,7 @@ impl<'a, T: RuntimeFactors, U: Send> FactorsInstanceBuilder<'a, T, U> {
             core: Default::default(),
             factors: self.factors.build_instance_state(self.factor_builders)?,
             executor: executor_instance_state,
+            concurrency_permits: self.concurrency_permits,
         };
         let mut store = self.store_builder.build(instance_state)?;
         let instance = self.instance_pre.instantiate_async(&mut store).await?;
@@ -251,6 +478,9 @@ pub struct InstanceState<T, U> {
     core: spin_core::State,
     factors: T,
     executor: U,
+    // Held until the instance is dropped or they are released; see
+    // [`FactorsExecutorApp::prepare`].
+    concurrency_permits: ConcurrencyPermits,
 }
 
 impl<T, U> InstanceState<T, U> {
@@ -283,6 +513,18 @@ impl<T, U> InstanceState<T, U> {
     pub fn executor_instance_state_mut(&mut self) -> &mut U {
         &mut self.executor
     }
+
+    /// Releases the instance's concurrency slots, for an instance kept idle
+    /// between executions.
+    pub fn release_concurrency_permits(&mut self) {
+        self.concurrency_permits = ConcurrencyPermits::default();
+    }
+
+    /// Holds `permits` for an idle instance which is executed again, until
+    /// it is dropped or they are released.
+    pub fn hold_concurrency_permits(&mut self, permits: ConcurrencyPermits) {
+        self.concurrency_permits = permits;
+    }
 }
 
 impl<T, U> spin_core::AsState for InstanceState<T, U> {
@@ -297,11 +539,59 @@ impl<T: RuntimeFactorsInstanceState, U> AsInstanceState<T> for InstanceState<T,
     }
 }
 
+/// Checks that two versions of an app have the same components, compiled
+/// from the same sources and composed with the same middleware.
+fn ensure_same_components(current: &App, next: &App) -> anyhow::Result<()> {
+    for component in next.components() {
+        let Some(current) = current.get_component(component.id()) else {
+            anyhow::bail!("component {:?} was added", component.id());
+        };
+        anyhow::ensure!(
+            same_source(&component, &current),
+            "the source of component {:?} changed",
+            component.id()
+        );
+        // Middleware is composed into the component when it is compiled.
+        let middleware = component.middleware()?;
+        let current_middleware = current.middleware()?;
+        anyhow::ensure!(
+            middleware
+                .iter()
+                .map(AppComponent::id)
+                .eq(current_middleware.iter().map(AppComponent::id)),
+            "the middleware of component {:?} changed",
+            component.id()
+        );
+        for (middleware, current_middleware) in middleware.iter().zip(&current_middleware) {
+            anyhow::ensure!(
+                same_source(middleware, current_middleware),
+                "the source of middleware component {:?} of component {:?} changed",
+                middleware.id(),
+                component.id()
+            );
+        }
+    }
+    for component in current.components() {
+        anyhow::ensure!(
+            next.get_component(component.id()).is_some(),
+            "component {:?} was removed",
+            component.id()
+        );
+    }
+    Ok(())
+}
+
+/// Whether two versions of a component are compiled from the same sources.
+fn same_source(component: &AppComponent, current: &AppComponent) -> bool {
+    component.locked.source == current.locked.source
+        && component.locked.dependencies == current.locked.dependencies
+}
+
 #[cfg(test)]
 mod tests {
     use spin_factor_wasi::{DummyFilesMounter, WasiFactor};
     use spin_factors::RuntimeFactors;
-    use spin_factors_test::TestEnvironment;
+    use spin_factors_test::{toml, TestEnvironment};
 
     use super::*;
 
@@ -326,7 +616,7 @@ mod tests {
             .load_app(app, Default::default(), &DummyComponentLoader)
             .await?;
 
-        let mut instance_builder = factors_app.prepare("empty")?;
+        let mut instance_builder = factors_app.prepare("empty").await?;
 
         assert_eq!(instance_builder.app_component().id(), "empty");
 
@@ -341,6 +631,257 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn lazy_components_are_compiled_on_prepare() -> anyhow::Result<()> {
+        let factors = TestFactors {
+            wasi: WasiFactor::new(DummyFilesMounter),
+        };
+        let env = TestEnvironment::new(factors).extend_manifest(toml! {
+            [component.empty]
+            source = "does-not-exist.wasm"
+            precompile = "lazy"
+        });
+        let locked = env.build_locked_app().await?;
+        let app = App::new("test-app", locked);
+
+        let engine_builder = spin_core::Engine::builder(&Default::default())?;
+        let executor = Arc::new(FactorsExecutor::new(engine_builder, env.factors)?);
+
+        let factors_app = executor
+            .load_app(app, Default::default(), &DummyComponentLoader)
+            .await?;
+        assert!(!factors_app.is_compiled("empty"));
+
+        factors_app.prepare("empty").await?;
+        assert!(factors_app.is_compiled("empty"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn concurrency_limits_reject_excess_instances() -> anyhow::Result<()> {
+        let factors = TestFactors {
+            wasi: WasiFactor::new(DummyFilesMounter),
+        };
+        let env = TestEnvironment::new(factors).extend_manifest(toml! {
+            [component.empty]
+            source = "does-not-exist.wasm"
+            concurrency = { max_instances = 1, queue_timeout_ms = 0 }
+
+            [component.other]
+            source = "does-not-exist.wasm"
+        });
+        let locked = env.build_locked_app().await?;
+        let app = App::new("test-app", locked);
+
+        let engine_builder = spin_core::Engine::builder(&Default::default())?;
+        let mut executor = FactorsExecutor::new(engine_builder, env.factors)?;
+        executor.limit_concurrency(ConcurrencyLimit {
+            max_instances: 2.try_into().unwrap(),
+            queue_timeout_ms: Some(0),
+        });
+
+        let factors_app = Arc::new(executor)
+            .load_app(app, Default::default(), &DummyComponentLoader)
+            .await?;
+
+        let (_instance, store) = factors_app.prepare("empty").await?.instantiate(()).await?;
+        let err = factors_app.prepare("empty").await.err().unwrap();
+        let err = err.downcast_ref::<ConcurrencyLimitExceeded>().unwrap();
+        assert_eq!(err.component_id.as_deref(), Some("empty"));
+
+        // The trigger-wide limit of 2 is now reached.
+        let other = factors_app.prepare("other").await?;
+        let err = factors_app.prepare("other").await.err().unwrap();
+        let err = err.downcast_ref::<ConcurrencyLimitExceeded>().unwrap();
+        assert_eq!(err.component_id, None);
+        assert_eq!(err.max_instances, 2);
+
+        // Instances may be prepared without slots.
+        factors_app
+            .prepare_with_permits("other", Default::default())
+            .await?;
+
+        // Idle instances may release their slots, and hold them again.
+        let mut store = store;
+        store.data_mut().release_concurrency_permits();
+        let permits = factors_app.acquire_concurrency_permits("empty").await?;
+        assert!(factors_app.prepare("empty").await.is_err());
+        store.data_mut().hold_concurrency_permits(permits);
+        store.data_mut().release_concurrency_permits();
+
+        // Dropping instances frees their slots.
+        drop((store, other));
+        factors_app.prepare("empty").await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn reconfigured_apps_reuse_compiled_components() -> anyhow::Result<()> {
+        let test_factors = || TestFactors {
+            wasi: WasiFactor::new(DummyFilesMounter),
+        };
+        let env = TestEnvironment::new(test_factors());
+        let locked = env.build_locked_app().await?;
+        let app = App::new("test-app", locked);
+
+        let engine_builder = spin_core::Engine::builder(&Default::default())?;
+        let executor = Arc::new(FactorsExecutor::new(engine_builder, env.factors)?);
+        let factors_app = executor
+            .load_app(app, Default::default(), &DummyComponentLoader)
+            .await?;
+        assert!(factors_app.is_compiled("empty"));
+
+        let changed_env = TestEnvironment::new(test_factors()).extend_manifest(toml! {
+            [component.empty]
+            environment = { GREETING = "hello" }
+        });
+        let app = App::new("test-app", changed_env.build_locked_app().await?);
+        let reconfigured = factors_app.reconfigure(app, Default::default()).await?;
+        assert!(reconfigured.is_compiled("empty"));
+        let component = reconfigured.app().get_component("empty").unwrap();
+        assert_eq!(component.locked.env["GREETING"], "hello");
+
+        let changed_source = TestEnvironment::new(test_factors()).extend_manifest(toml! {
+            [component.empty]
+            source = "other.wasm"
+        });
+        let app = App::new("test-app", changed_source.build_locked_app().await?);
+        assert!(factors_app
+            .reconfigure(app, Default::default())
+            .await
+            .is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn reconfigured_apps_keep_their_middleware() -> anyhow::Result<()> {
+        let test_env = |manifest| {
+            TestEnvironment::new(TestFactors {
+                wasi: WasiFactor::new(DummyFilesMounter),
+            })
+            .extend_manifest(manifest)
+        };
+        let env = test_env(toml! {
+            [component.empty]
+            source = "does-not-exist.wasm"
+            middleware = ["auth"]
+
+            [component.auth]
+            source = "auth.wasm"
+        });
+        let app = App::new("test-app", env.build_locked_app().await?);
+
+        let engine_builder = spin_core::Engine::builder(&Default::default())?;
+        let executor = Arc::new(FactorsExecutor::new(engine_builder, env.factors)?);
+        let factors_app = executor
+            .load_app(app, Default::default(), &DummyComponentLoader)
+            .await?;
+
+        let unchanged = test_env(toml! {
+            [component.empty]
+            source = "does-not-exist.wasm"
+            middleware = ["auth"]
+            environment = { GREETING = "hello" }
+
+            [component.auth]
+            source = "auth.wasm"
+        });
+        let app = App::new("test-app", unchanged.build_locked_app().await?);
+        factors_app.reconfigure(app, Default::default()).await?;
+
+        let changed_middleware = test_env(toml! {
+            [component.empty]
+            source = "does-not-exist.wasm"
+
+            [component.auth]
+            source = "auth.wasm"
+        });
+        let app = App::new("test-app", changed_middleware.build_locked_app().await?);
+        let err = factors_app
+            .reconfigure(app, Default::default())
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("middleware"), "{err}");
+
+        let changed_middleware_source = test_env(toml! {
+            [component.empty]
+            source = "does-not-exist.wasm"
+            middleware = ["auth"]
+
+            [component.auth]
+            source = "auth-v2.wasm"
+        });
+        let app = App::new(
+            "test-app",
+            changed_middleware_source.build_locked_app().await?,
+        );
+        assert!(factors_app
+            .reconfigure(app, Default::default())
+            .await
+            .is_err());
+        Ok(())
+    }
+
+    #[derive(RuntimeFactors)]
+    struct OptionalTestFactors {
+        #[factor(optional)]
+        wasi: Option<WasiFactor>,
+    }
+
+    #[tokio::test]
+    async fn optional_factors_can_be_disabled() -> anyhow::Result<()> {
+        for enabled in [true, false] {
+            let factors = OptionalTestFactors {
+                wasi: enabled.then(|| WasiFactor::new(DummyFilesMounter)),
+            };
+            let env = TestEnvironment::new(factors);
+            let locked = env.build_locked_app().await?;
+            let app = App::new("test-app", locked);
+
+            let engine_builder = spin_core::Engine::builder(&Default::default())?;
+            let executor = Arc::new(FactorsExecutor::new(engine_builder, env.factors)?);
+
+            let factors_app = executor
+                .load_app(app, Default::default(), &DummyComponentLoader)
+                .await?;
+            assert_eq!(
+                factors_app
+                    .configured_app()
+                    .app_state::<WasiFactor>()
+                    .is_ok(),
+                enabled
+            );
+
+            let mut instance_builder = factors_app.prepare("empty").await?;
+            assert_eq!(
+                instance_builder.factor_builder::<WasiFactor>().is_some(),
+                enabled
+            );
+
+            let (_instance, store) = instance_builder.instantiate(()).await?;
+            assert_eq!(
+                store.data().factors_instance_state().wasi.is_some(),
+                enabled
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn optional_factors_are_disabled_without_runtime_config() {
+        let mut factors = OptionalTestFactors {
+            wasi: Some(WasiFactor::new(DummyFilesMounter)),
+        };
+        factors.disable_unconfigured(&OptionalTestFactorsRuntimeConfig {
+            wasi: Some(Default::default()),
+        });
+        assert!(factors.wasi.is_some());
+
+        factors.disable_unconfigured(&Default::default());
+        assert!(factors.wasi.is_none());
+    }
+
+    #[derive(Clone)]
     struct DummyComponentLoader;
 
     #[async_trait]
@@ -0,0 +1,144 @@
+use spin_factors::{
+    anyhow::{self, bail},
+    simple::SimpleFactor,
+    wasmtime::{
+        component::{Component, Linker},
+        Config, Engine,
+    },
+    App, AppComponent, Factor, RuntimeFactors,
+};
+use spin_factors_test::{toml, TestEnvironment};
+
+mod bindings {
+    wasmtime::component::bindgen!({
+        inline: r#"
+            package test:greeting;
+
+            interface greeter {
+                greet: func() -> string;
+            }
+
+            world greeting {
+                import greeter;
+            }
+        "#,
+    });
+}
+
+#[derive(Factor)]
+#[factor(bindings(bindings::test::greeting::greeter::add_to_linker))]
+struct GreetingFactor;
+
+struct GreetingRuntimeConfig {
+    greeting: String,
+}
+
+struct Greeter {
+    message: String,
+}
+
+impl SimpleFactor for GreetingFactor {
+    type RuntimeConfig = GreetingRuntimeConfig;
+    type AppState = String;
+    type InstanceState = Greeter;
+
+    fn configure_app(
+        &self,
+        _app: &App,
+        runtime_config: Option<GreetingRuntimeConfig>,
+    ) -> anyhow::Result<String> {
+        let greeting = runtime_config.map_or("Hello".into(), |config| config.greeting);
+        if greeting.is_empty() {
+            bail!("the greeting must not be empty");
+        }
+        Ok(greeting)
+    }
+
+    fn instance_state(
+        &self,
+        greeting: &String,
+        component: &AppComponent,
+    ) -> anyhow::Result<Greeter> {
+        Ok(Greeter {
+            message: format!("{greeting}, {}!", component.id()),
+        })
+    }
+}
+
+impl bindings::test::greeting::greeter::Host for Greeter {
+    fn greet(&mut self) -> String {
+        self.message.clone()
+    }
+}
+
+#[derive(RuntimeFactors)]
+struct TestFactors {
+    greeting: GreetingFactor,
+}
+
+fn test_env() -> TestEnvironment<TestFactors> {
+    TestEnvironment::new(TestFactors {
+        greeting: GreetingFactor,
+    })
+    .extend_manifest(toml! {
+        [component.test-component]
+        source = "does-not-exist.wasm"
+    })
+}
+
+#[tokio::test]
+async fn derived_factor_builds_instance_state() -> anyhow::Result<()> {
+    let state = test_env().build_instance_state().await?;
+    assert_eq!(state.greeting.message, "Hello, test-component!");
+    Ok(())
+}
+
+#[tokio::test]
+async fn derived_factor_uses_runtime_config() -> anyhow::Result<()> {
+    let mut env = test_env();
+    env.runtime_config.greeting = Some(GreetingRuntimeConfig {
+        greeting: "Howdy".into(),
+    });
+    let state = env.build_instance_state().await?;
+    assert_eq!(state.greeting.message, "Howdy, test-component!");
+    Ok(())
+}
+
+#[tokio::test]
+async fn derived_factor_returns_configure_app_errors() -> anyhow::Result<()> {
+    let mut env = test_env();
+    env.runtime_config.greeting = Some(GreetingRuntimeConfig {
+        greeting: String::new(),
+    });
+    let Err(err) = env.build_instance_state().await else {
+        panic!("an empty greeting should be rejected");
+    };
+    assert!(
+        format!("{err:#}").contains("the greeting must not be empty"),
+        "unexpected error {err:#}"
+    );
+    Ok(())
+}
+
+#[test]
+fn derived_factor_links_bindings() -> anyhow::Result<()> {
+    let engine = Engine::new(Config::new().async_support(true))?;
+    let component = Component::new(
+        &engine,
+        r#"(component
+            (import "test:greeting/greeter" (instance
+                (export "greet" (func (result string)))
+            ))
+        )"#,
+    )?;
+
+    let mut linker = Linker::<<TestFactors as RuntimeFactors>::InstanceState>::new(&engine);
+    assert!(linker.instantiate_pre(&component).is_err());
+
+    let mut factors = TestFactors {
+        greeting: GreetingFactor,
+    };
+    factors.init(&mut linker)?;
+    linker.instantiate_pre(&component)?;
+    Ok(())
+}
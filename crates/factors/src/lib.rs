@@ -2,13 +2,14 @@ mod factor;
 mod prepare;
 pub mod runtime_config;
 mod runtime_factors;
+pub mod simple;
 
 pub use anyhow;
 pub use serde;
 pub use wasmtime;
 
 pub use spin_app::{App, AppComponent};
-pub use spin_factors_derive::RuntimeFactors;
+pub use spin_factors_derive::{Factor, RuntimeFactors};
 
 pub use crate::{
     factor::{ConfigureAppContext, ConfiguredApp, Factor, FactorInstanceState, InitContext},
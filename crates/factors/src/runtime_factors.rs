@@ -6,6 +6,10 @@ use crate::{factor::FactorInstanceState, App, ConfiguredApp, Factor};
 ///
 /// Implemented by `#[derive(RuntimeFactors)]` and should not be implemented manually.
 ///
+/// Fields marked `#[factor(optional)]` have type `Option<F>`; a `None` factor is
+/// skipped entirely, so its imports are not linked and it has no app or instance
+/// state.
+///
 /// # Example
 ///
 /// A typical usage of `RuntimeFactors` would look something like the following pseudo-code:
@@ -0,0 +1,93 @@
+//! A simpler way to write a [`Factor`](crate::Factor).
+//!
+//! Most host components need only some app-wide state, built from the app and
+//! the factor's runtime config, and some per-instance state that implements
+//! their bindings. Such a factor can implement [`SimpleFactor`] and derive
+//! `Factor` rather than implementing [`Factor`](crate::Factor) itself:
+//!
+//! ```ignore
+//! use spin_factors::{anyhow, simple::SimpleFactor, App, AppComponent, Factor};
+//!
+//! #[derive(Factor)]
+//! #[factor(bindings(bindings::example::greeting::greeter::add_to_linker))]
+//! pub struct GreetingFactor;
+//!
+//! impl SimpleFactor for GreetingFactor {
+//!     type RuntimeConfig = GreetingRuntimeConfig;
+//!     type AppState = String;
+//!     type InstanceState = Greeter;
+//!
+//!     fn configure_app(
+//!         &self,
+//!         _app: &App,
+//!         runtime_config: Option<GreetingRuntimeConfig>,
+//!     ) -> anyhow::Result<String> {
+//!         Ok(runtime_config.map(|c| c.greeting).unwrap_or("Hello".into()))
+//!     }
+//!
+//!     fn instance_state(
+//!         &self,
+//!         greeting: &String,
+//!         component: &AppComponent,
+//!     ) -> anyhow::Result<Greeter> {
+//!         Ok(Greeter::new(greeting, component.id()))
+//!     }
+//! }
+//! ```
+//!
+//! [`SimpleFactor`] and the types it uses are a stable surface for factors
+//! written outside of Spin; factors that depend on other factors' state, or
+//! need to do more when the runtime is initialized, implement
+//! [`Factor`](crate::Factor) directly.
+
+use crate::{App, AppComponent, FactorInstanceBuilder};
+
+/// A [`Factor`](crate::Factor) with app state and instance state that don't
+/// depend on other factors, for use with `#[derive(Factor)]`.
+pub trait SimpleFactor: Sized + 'static {
+    /// The runtime configuration of the factor, if it has any.
+    type RuntimeConfig;
+
+    /// The state of the factor for an app.
+    type AppState: Sync;
+
+    /// The state of the factor for an instance. The factor's bindings are
+    /// implemented for this type.
+    type InstanceState: Send + 'static;
+
+    /// Validates the app and builds the factor's state for it.
+    ///
+    /// `runtime_config` is `None` if the runtime config has nothing for this
+    /// factor.
+    fn configure_app(
+        &self,
+        app: &App,
+        runtime_config: Option<Self::RuntimeConfig>,
+    ) -> anyhow::Result<Self::AppState>;
+
+    /// Builds the factor's state for an instance of the given component.
+    fn instance_state(
+        &self,
+        app_state: &Self::AppState,
+        component: &AppComponent,
+    ) -> anyhow::Result<Self::InstanceState>;
+}
+
+/// The [`FactorInstanceBuilder`] of a [`SimpleFactor`], which holds its
+/// already-built instance state.
+pub struct InstanceStateBuilder<S>(S);
+
+impl<S> InstanceStateBuilder<S> {
+    /// Wraps the given instance state.
+    pub fn new(state: S) -> Self {
+        Self(state)
+    }
+}
+
+impl<S: Send + 'static> FactorInstanceBuilder for InstanceStateBuilder<S> {
+    type InstanceState = S;
+
+    fn build(self) -> anyhow::Result<S> {
+        Ok(self.0)
+    }
+}
@@ -1,3 +1,5 @@
+use std::collections::BTreeMap;
+
 use serde::{Deserialize, Serialize};
 
 /// Configuration for the HTTP trigger
@@ -11,6 +13,170 @@ pub struct HttpTriggerConfig {
     /// The HTTP executor the component requires
     #[serde(default)]
     pub executor: Option<HttpExecutorType>,
+    /// Limits the rate of requests the component is invoked for
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rate_limit: Option<RateLimitConfig>,
+    /// Requires requests to carry a valid JWT bearer token
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jwt: Option<JwtConfig>,
+    /// Rewrites the headers of requests to, and responses from, the component
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub headers: Option<HeaderRewriteConfig>,
+    /// Replays the responses to retried `POST` requests
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idempotency: Option<IdempotencyConfig>,
+    /// Experimental: routes the requests of a session to the same instance
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_affinity: Option<SessionAffinityConfig>,
+}
+
+/// Experimental session affinity for a component.
+///
+/// Requests carrying the same session key, taken from a cookie or a header,
+/// are handled one at a time by the same instance of the component, which is
+/// kept alive between them. This lets a component keep per-session state in
+/// memory, but that state is not durable: it is lost when the session has
+/// been idle for `idle_timeout_secs`, when the instance fails, and when Spin
+/// restarts or the app is reconfigured, and it is not shared with other Spin
+/// processes serving the app. Components must be able to rebuild it.
+///
+/// Only `wasi:http` components can keep their instances.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct SessionAffinityConfig {
+    /// The cookie carrying the session key
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cookie: Option<String>,
+    /// The header carrying the session key
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub header: Option<String>,
+    /// How long a session's instance is kept once it is idle, in seconds.
+    /// Defaults to 5 minutes.
+    #[serde(default = "default_session_idle_timeout_secs")]
+    pub idle_timeout_secs: u64,
+    /// The most sessions kept at once. Requests starting further sessions are
+    /// handled by new instances, as if without session affinity. Defaults
+    /// to 1000.
+    #[serde(default = "default_max_sessions")]
+    pub max_sessions: usize,
+}
+
+fn default_session_idle_timeout_secs() -> u64 {
+    5 * 60
+}
+
+fn default_max_sessions() -> usize {
+    1000
+}
+
+/// Replay of the responses to retried `POST` requests for a component.
+///
+/// The response to a `POST` request carrying an `Idempotency-Key` header is
+/// kept in a key-value store, and later requests with the same key get that
+/// response without the component being invoked, until `ttl_secs` elapse.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct IdempotencyConfig {
+    /// The label of the key-value store in which to keep responses
+    pub store: String,
+    /// How long responses are replayed for, in seconds. Defaults to a day.
+    #[serde(default = "default_idempotency_ttl_secs")]
+    pub ttl_secs: u64,
+}
+
+fn default_idempotency_ttl_secs() -> u64 {
+    24 * 60 * 60
+}
+
+/// Header rewriting for the requests to, and responses from, a component.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct HeaderRewriteConfig {
+    /// Rules applied to requests before they are passed to the component
+    #[serde(default)]
+    pub request: HeaderRules,
+    /// Rules applied to the component's responses
+    #[serde(default)]
+    pub response: HeaderRules,
+}
+
+/// Changes to make to a set of headers, applied in the order `remove`, `set`,
+/// `append`.
+///
+/// Values of `set` and `append` may refer to application variables, e.g.
+/// `"Bearer {{ api_key }}"`.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct HeaderRules {
+    /// Headers to remove, e.g. `["cookie"]`
+    #[serde(default)]
+    pub remove: Vec<String>,
+    /// Headers to set, replacing any existing values
+    #[serde(default)]
+    pub set: BTreeMap<String, String>,
+    /// Headers to add values to, keeping any existing values
+    #[serde(default)]
+    pub append: BTreeMap<String, String>,
+}
+
+/// Validation of the JWT bearer tokens of requests for a component.
+///
+/// Requests without a valid token for the `issuer` and `audience` are
+/// rejected with a 401 before the component is invoked.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct JwtConfig {
+    /// The required `iss` claim, e.g. `"https://example.auth0.com/"`
+    pub issuer: String,
+    /// A value the `aud` claim must contain. If unset, any audience is
+    /// accepted.
+    #[serde(default)]
+    pub audience: Option<String>,
+    /// The URL of the issuer's JSON Web Key Set. If unset, it is discovered
+    /// from the issuer's OpenID configuration.
+    #[serde(default)]
+    pub jwks_url: Option<String>,
+}
+
+/// A token bucket rate limit on the requests for a component.
+///
+/// Each bucket holds up to `burst` tokens and is refilled at
+/// `requests_per_second`; a request takes a token, and is rejected with a 429
+/// if there are none left.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct RateLimitConfig {
+    /// The sustained number of requests allowed per second
+    pub requests_per_second: f64,
+    /// The number of requests allowed at once after a quiet period. Defaults
+    /// to `requests_per_second`, rounded up.
+    #[serde(default)]
+    pub burst: Option<u32>,
+    /// What requests share a bucket
+    #[serde(default)]
+    pub by: RateLimitKey,
+    /// The request header whose value identifies a client when `by = "header"`,
+    /// e.g. `"x-api-key"`
+    #[serde(default)]
+    pub header: Option<String>,
+    /// The label of a key-value store in which to keep buckets, so that
+    /// instances of the app share them. If unset, buckets are kept in memory.
+    #[serde(default)]
+    pub store: Option<String>,
+}
+
+/// What requests share a [`RateLimitConfig`] bucket.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RateLimitKey {
+    /// All requests share one bucket.
+    Global,
+    /// Requests from each client IP address share a bucket.
+    #[default]
+    ClientIp,
+    /// Requests with each value of the [`RateLimitConfig::header`] share a
+    /// bucket. Requests without the header share one bucket.
+    Header,
 }
 
 /// An HTTP trigger route
@@ -105,4 +271,64 @@ mod tests {
         assert_eq!(config.entrypoint, "_start");
         assert_eq!(config.argv, "${SCRIPT_NAME} ${ARGS}");
     }
+
+    #[test]
+    fn rate_limit_defaults_to_client_ip() {
+        let config: HttpTriggerConfig = toml::toml! {
+            component = "api"
+            route = "/..."
+            rate_limit = { requests_per_second = 2.5 }
+        }
+        .try_into()
+        .unwrap();
+        let rate_limit = config.rate_limit.unwrap();
+        assert_eq!(rate_limit.by, RateLimitKey::ClientIp);
+        assert_eq!(rate_limit.burst, None);
+        assert_eq!(rate_limit.store, None);
+    }
+
+    #[test]
+    fn header_rules_default_to_empty() {
+        let config: HttpTriggerConfig = toml::toml! {
+            component = "api"
+            route = "/..."
+            headers = { request = { set = { authorization = "Bearer {{ api_key }}" } } }
+        }
+        .try_into()
+        .unwrap();
+        let headers = config.headers.unwrap();
+        assert_eq!("Bearer {{ api_key }}", headers.request.set["authorization"]);
+        assert!(headers.request.remove.is_empty());
+        assert!(headers.response.set.is_empty());
+    }
+
+    #[test]
+    fn idempotency_ttl_defaults_to_a_day() {
+        let config: HttpTriggerConfig = toml::toml! {
+            component = "api"
+            route = "/..."
+            idempotency = { store = "default" }
+        }
+        .try_into()
+        .unwrap();
+        let idempotency = config.idempotency.unwrap();
+        assert_eq!(idempotency.store, "default");
+        assert_eq!(idempotency.ttl_secs, 86400);
+    }
+
+    #[test]
+    fn session_affinity_defaults() {
+        let config: HttpTriggerConfig = toml::toml! {
+            component = "api"
+            route = "/..."
+            session_affinity = { cookie = "session" }
+        }
+        .try_into()
+        .unwrap();
+        let session_affinity = config.session_affinity.unwrap();
+        assert_eq!(session_affinity.cookie.as_deref(), Some("session"));
+        assert_eq!(session_affinity.header, None);
+        assert_eq!(session_affinity.idle_timeout_secs, 300);
+        assert_eq!(session_affinity.max_sessions, 1000);
+    }
 }
@@ -213,34 +213,46 @@ fn parse_host_header_uri(
 
 pub fn compose_response(stdout: &[u8]) -> Result<Response<Body>, Error> {
     // Okay, once we get here, all the information we need to send back in the response
-    // should be written to the STDOUT buffer. We fetch that, format it, and send
-    // it back. In the process, we might need to alter the status code of the result.
-    //
-    // This is a little janky, but basically we are looping through the output once,
-    // looking for the double-newline that distinguishes the headers from the body.
-    // The headers can then be parsed separately, while the body can be sent back
-    // to the client.
+    // should be written to the STDOUT buffer. We split it into the headers, which are
+    // parsed separately, and the body, which is sent back to the client.
+    let (headers, body) = match split_headers(stdout) {
+        Some((headers, body_start)) => (headers, &stdout[body_start..]),
+        None => (Vec::new(), stdout),
+    };
+    compose_response_with_body(headers, body::full(body.to_vec().into()))
+}
+
+/// Finds the double-newline that distinguishes the CGI headers at the start of
+/// `stdout` from the body, ignoring CRs.
+///
+/// Returns the headers, without CRs, and the offset at which the body starts, or
+/// `None` if `stdout` doesn't yet contain all of the headers.
+pub fn split_headers(stdout: &[u8]) -> Option<(Vec<u8>, usize)> {
     let mut last = 0;
-    let mut scan_headers = true;
-    let mut buffer: Vec<u8> = Vec::new();
-    let mut out_headers: Vec<u8> = Vec::new();
-    stdout.iter().for_each(|i| {
-        // Ignore CR in headers
-        if scan_headers && *i == 13 {
-            return;
-        } else if scan_headers && *i == 10 && last == 10 {
-            out_headers.append(&mut buffer);
-            buffer = Vec::new();
-            scan_headers = false;
-            return; // Consume the linefeed
+    let mut headers = Vec::new();
+    for (i, byte) in stdout.iter().enumerate() {
+        match *byte {
+            // Ignore CR in headers
+            b'\r' => continue,
+            // Consume the linefeed
+            b'\n' if last == b'\n' => return Some((headers, i + 1)),
+            _ => {}
         }
-        last = *i;
-        buffer.push(*i)
-    });
-    let mut res = Response::new(body::full(buffer.into()));
+        last = *byte;
+        headers.push(*byte);
+    }
+    None
+}
+
+/// Composes the response to send for the CGI `headers` (as returned by
+/// [`split_headers`]), with the given body.
+///
+/// This allows the body to be streamed as the component writes it.
+pub fn compose_response_with_body(headers: Vec<u8>, body: Body) -> Result<Response<Body>, Error> {
+    let mut res = Response::new(body);
     let mut sufficient_response = false;
     let mut explicit_status_code = false;
-    parse_cgi_headers(String::from_utf8(out_headers)?)
+    parse_cgi_headers(String::from_utf8(headers)?)
         .iter()
         .for_each(|h| {
             use hyper::header::{CONTENT_TYPE, LOCATION};
@@ -292,7 +304,6 @@ pub fn compose_response(stdout: &[u8]) -> Result<Response<Body>, Error> {
             }
         });
     if !sufficient_response {
-        tracing::debug!("{:?}", res.body());
         return Ok(internal_error(
             // Technically, we let `status` be sufficient, but this is more lenient
             // than the specification.
@@ -323,3 +334,35 @@ fn internal_error(msg: impl std::string::ToString) -> Response<Body> {
     *res.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
     res
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn headers_end_at_the_first_blank_line() {
+        let stdout = b"Content-Type: text/plain\r\n\r\nhello\r\n\r\n";
+        let (headers, body_start) = split_headers(stdout).unwrap();
+        assert_eq!(headers, b"Content-Type: text/plain\n");
+        assert_eq!(&stdout[body_start..], b"hello\r\n\r\n");
+
+        assert!(split_headers(b"Content-Type: text/plain\r\n").is_none());
+        assert!(split_headers(b"Content-Type: text/plain\n\r").is_none());
+    }
+
+    #[test]
+    fn status_and_location_headers_set_the_status() {
+        let response = compose_response(b"Status: 404 Not Found\n\n").unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let response = compose_response(b"Location: /elsewhere\n\n").unwrap();
+        assert_eq!(response.status(), StatusCode::FOUND);
+        assert_eq!(response.headers()["location"], "/elsewhere");
+    }
+
+    #[test]
+    fn incomplete_headers_are_an_internal_error() {
+        let response = compose_response(b"Content-Type: text/plain\nhello").unwrap();
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+}
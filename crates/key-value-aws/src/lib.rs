@@ -1,4 +1,5 @@
 mod store;
+mod stream;
 
 use serde::Deserialize;
 use spin_factor_key_value::runtime_config::spin::MakeKeyValueStore;
@@ -2,6 +2,7 @@ use core::str;
 use std::{
     collections::HashMap,
     sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 use anyhow::Result;
@@ -9,6 +10,7 @@ use aws_config::{BehaviorVersion, Region, SdkConfig};
 use aws_credential_types::Credentials;
 use aws_sdk_dynamodb::{
     config::{ProvideCredentials, SharedCredentialsProvider},
+    error::{ProvideErrorMetadata, SdkError},
     operation::{
         batch_get_item::BatchGetItemOutput, batch_write_item::BatchWriteItemOutput,
         get_item::GetItemOutput,
@@ -20,8 +22,13 @@ use aws_sdk_dynamodb::{
     },
     Client,
 };
+use aws_sdk_dynamodbstreams::Client as StreamsClient;
 use spin_core::async_trait;
-use spin_factor_key_value::{log_error, Cas, Error, Store, StoreManager, SwapError};
+use spin_factor_key_value::{
+    log_error, watch::Watcher, Cas, Error, Store, StoreManager, SwapError,
+};
+
+use crate::stream;
 
 pub struct KeyValueAwsDynamo {
     /// AWS region
@@ -30,13 +37,19 @@ pub struct KeyValueAwsDynamo {
     consistent_read: bool,
     /// DynamoDB table, needs to be cloned when getting a store
     table: Arc<String>,
-    /// DynamoDB client
-    client: async_once_cell::Lazy<
-        Client,
-        std::pin::Pin<Box<dyn std::future::Future<Output = Client> + Send>>,
+    /// DynamoDB clients
+    clients: async_once_cell::Lazy<
+        Clients,
+        std::pin::Pin<Box<dyn std::future::Future<Output = Clients> + Send>>,
     >,
 }
 
+/// The clients for the table and its stream, which share their configuration.
+struct Clients {
+    table: Client,
+    streams: StreamsClient,
+}
+
 /// AWS Dynamo Key / Value runtime config literal options for authentication
 #[derive(Clone, Debug)]
 pub struct KeyValueAwsDynamoRuntimeConfigOptions {
@@ -105,14 +118,17 @@ impl KeyValueAwsDynamo {
                     aws_config::load_defaults(BehaviorVersion::latest()).await
                 }
             };
-            Client::new(&sdk_config)
+            Clients {
+                table: Client::new(&sdk_config),
+                streams: StreamsClient::new(&sdk_config),
+            }
         });
 
         Ok(Self {
             region,
             consistent_read,
             table: Arc::new(table),
-            client: async_once_cell::Lazy::from_future(client_fut),
+            clients: async_once_cell::Lazy::from_future(client_fut),
         })
     }
 }
@@ -120,8 +136,10 @@ impl KeyValueAwsDynamo {
 #[async_trait]
 impl StoreManager for KeyValueAwsDynamo {
     async fn get(&self, _name: &str) -> Result<Arc<dyn Store>, Error> {
+        let clients = self.clients.get_unpin().await;
         Ok(Arc::new(AwsDynamoStore {
-            client: self.client.get_unpin().await.clone(),
+            client: clients.table.clone(),
+            streams: clients.streams.clone(),
             table: self.table.clone(),
             consistent_read: self.consistent_read,
         }))
@@ -142,6 +160,7 @@ impl StoreManager for KeyValueAwsDynamo {
 struct AwsDynamoStore {
     // Client wraps an Arc so should be low cost to clone
     client: Client,
+    streams: StreamsClient,
     table: Arc<String>,
     consistent_read: bool,
 }
@@ -167,11 +186,14 @@ struct CompareAndSwap {
 }
 
 /// Primary key in DynamoDB items used for querying items
-const PK: &str = "PK";
+pub(crate) const PK: &str = "PK";
 /// Value key in DynamoDB items storing item value as binary
 const VAL: &str = "VAL";
 /// Version key in DynamoDB items used for atomic operations
 const VER: &str = "VER";
+/// Expiry key in DynamoDB items holding leases, in milliseconds since the
+/// Unix epoch. The lease's token is stored as the item's value.
+const EXP: &str = "EXP";
 
 #[async_trait]
 impl Store for AwsDynamoStore {
@@ -463,6 +485,66 @@ impl Store for AwsDynamoStore {
             bucket_rep,
         }))
     }
+
+    async fn acquire_lease(&self, key: &str, token: &str, ttl: Duration) -> Result<bool, Error> {
+        let now = now_ms();
+        let result = self
+            .client
+            .put_item()
+            .table_name(self.table.as_str())
+            .item(PK, AttributeValue::S(key.to_string()))
+            .item(VAL, AttributeValue::B(Blob::new(token.as_bytes())))
+            .item(EXP, AttributeValue::N(expiry_ms(now, ttl)))
+            .condition_expression(
+                "attribute_not_exists (#PK) OR (attribute_exists (#EXP) AND #EXP <= :now)",
+            )
+            .expression_attribute_names("#PK", PK)
+            .expression_attribute_names("#EXP", EXP)
+            .expression_attribute_values(":now", AttributeValue::N(now.to_string()))
+            .send()
+            .await;
+        conditional_write(result)
+    }
+
+    async fn renew_lease(&self, key: &str, token: &str, ttl: Duration) -> Result<bool, Error> {
+        let now = now_ms();
+        let result = self
+            .client
+            .update_item()
+            .table_name(self.table.as_str())
+            .key(PK, AttributeValue::S(key.to_string()))
+            .update_expression("SET #EXP = :exp")
+            .condition_expression("#VAL = :token AND #EXP > :now")
+            .expression_attribute_names("#VAL", VAL)
+            .expression_attribute_names("#EXP", EXP)
+            .expression_attribute_values(":token", AttributeValue::B(Blob::new(token.as_bytes())))
+            .expression_attribute_values(":exp", AttributeValue::N(expiry_ms(now, ttl)))
+            .expression_attribute_values(":now", AttributeValue::N(now.to_string()))
+            .send()
+            .await;
+        conditional_write(result)
+    }
+
+    async fn release_lease(&self, key: &str, token: &str) -> Result<bool, Error> {
+        let now = now_ms();
+        let result = self
+            .client
+            .delete_item()
+            .table_name(self.table.as_str())
+            .key(PK, AttributeValue::S(key.to_string()))
+            .condition_expression("#VAL = :token AND #EXP > :now")
+            .expression_attribute_names("#VAL", VAL)
+            .expression_attribute_names("#EXP", EXP)
+            .expression_attribute_values(":token", AttributeValue::B(Blob::new(token.as_bytes())))
+            .expression_attribute_values(":now", AttributeValue::N(now.to_string()))
+            .send()
+            .await;
+        conditional_write(result)
+    }
+
+    async fn watch(&self, prefix: &str) -> Result<Watcher, Error> {
+        stream::watch(&self.client, self.streams.clone(), &self.table, prefix).await
+    }
 }
 
 #[async_trait]
@@ -565,3 +647,35 @@ impl Cas for CompareAndSwap {
         self.key.clone()
     }
 }
+
+fn now_ms() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis()
+}
+
+fn expiry_ms(now: u128, ttl: Duration) -> String {
+    (now + ttl.as_millis()).to_string()
+}
+
+/// Returns whether a conditional write was made, or an error if it failed
+/// for any reason other than its condition.
+fn conditional_write<T, E, R>(result: Result<T, SdkError<E, R>>) -> Result<bool, Error>
+where
+    E: ProvideErrorMetadata + std::fmt::Debug,
+    R: std::fmt::Debug,
+{
+    match result {
+        Ok(_) => Ok(true),
+        Err(err)
+            if err
+                .as_service_error()
+                .and_then(|e| e.code())
+                .is_some_and(|code| code == "ConditionalCheckFailedException") =>
+        {
+            Ok(false)
+        }
+        Err(err) => Err(log_error(err)),
+    }
+}
@@ -0,0 +1,207 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use aws_sdk_dynamodb::Client;
+use aws_sdk_dynamodbstreams::{
+    types::{AttributeValue, OperationType, Record, ShardIteratorType},
+    Client as StreamsClient,
+};
+use spin_factor_key_value::{
+    log_error,
+    watch::{watch_channel, Change, WatchSender, Watcher},
+    Error,
+};
+
+use crate::store::PK;
+
+/// How long to wait between reads of a stream's shards.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How often to look for new shards, which DynamoDB opens as shards are split
+/// or rotated.
+const SHARD_REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Watches a table's DynamoDB stream for changes to keys starting with
+/// `prefix`.
+pub(crate) async fn watch(
+    client: &Client,
+    streams: StreamsClient,
+    table: &str,
+    prefix: &str,
+) -> Result<Watcher, Error> {
+    let stream_arn = client
+        .describe_table()
+        .table_name(table)
+        .send()
+        .await
+        .map_err(log_error)?
+        .table
+        .and_then(|table| table.latest_stream_arn)
+        .ok_or_else(|| {
+            Error::Other(format!(
+                "watching needs a DynamoDB stream on table {table:?}, but it has none"
+            ))
+        })?;
+    let mut reader = StreamReader {
+        streams,
+        stream_arn,
+        prefix: prefix.to_owned(),
+        iterators: HashMap::new(),
+    };
+    reader.refresh_shards(true).await?;
+    let (sender, watcher) = watch_channel();
+    tokio::spawn(reader.run(sender));
+    Ok(watcher)
+}
+
+struct StreamReader {
+    streams: StreamsClient,
+    stream_arn: String,
+    prefix: String,
+    /// The iterators of the stream's shards, by shard ID; `None` for shards
+    /// which have been read to their end.
+    iterators: HashMap<String, Option<String>>,
+}
+
+impl StreamReader {
+    async fn run(mut self, mut sender: WatchSender) {
+        let mut refreshed = Instant::now();
+        loop {
+            tokio::select! {
+                _ = sender.closed() => return,
+                _ = tokio::time::sleep(POLL_INTERVAL) => {}
+            }
+            match self.read(&mut sender).await {
+                Ok(true) => {}
+                Ok(false) => return,
+                Err(err) => return sender.fail(err),
+            }
+            if refreshed.elapsed() >= SHARD_REFRESH_INTERVAL {
+                if let Err(err) = self.refresh_shards(false).await {
+                    return sender.fail(err);
+                }
+                refreshed = Instant::now();
+            }
+        }
+    }
+
+    /// Reads the new records of every shard, returning false once the watcher
+    /// has been dropped.
+    async fn read(&mut self, sender: &mut WatchSender) -> Result<bool, Error> {
+        for iterator in self.iterators.values_mut() {
+            let Some(current) = iterator.take() else {
+                continue;
+            };
+            let output = self
+                .streams
+                .get_records()
+                .shard_iterator(current)
+                .send()
+                .await
+                .map_err(log_error)?;
+            *iterator = output.next_shard_iterator;
+            for record in output.records.unwrap_or_default() {
+                let Some(change) = change(&record, &self.prefix) else {
+                    continue;
+                };
+                if !sender.send(change) {
+                    return Ok(false);
+                }
+            }
+        }
+        Ok(true)
+    }
+
+    /// Starts reading any shards which aren't already being read, and forgets
+    /// those which have been trimmed from the stream.
+    ///
+    /// When `initial`, only changes from now on are read; otherwise, new shards
+    /// are read from their start, as they continue shards already being read.
+    async fn refresh_shards(&mut self, initial: bool) -> Result<(), Error> {
+        let mut shards = vec![];
+        let mut exclusive_start_shard_id = None;
+        loop {
+            let output = self
+                .streams
+                .describe_stream()
+                .stream_arn(&self.stream_arn)
+                .set_exclusive_start_shard_id(exclusive_start_shard_id)
+                .send()
+                .await
+                .map_err(log_error)?;
+            let Some(description) = output.stream_description else {
+                break;
+            };
+            shards.extend(description.shards.unwrap_or_default());
+            exclusive_start_shard_id = description.last_evaluated_shard_id;
+            if exclusive_start_shard_id.is_none() {
+                break;
+            }
+        }
+
+        let mut iterators = HashMap::with_capacity(shards.len());
+        for shard in shards {
+            let Some(shard_id) = shard.shard_id else {
+                continue;
+            };
+            if let Some(iterator) = self.iterators.remove(&shard_id) {
+                iterators.insert(shard_id, iterator);
+                continue;
+            }
+            let is_open = shard
+                .sequence_number_range
+                .and_then(|range| range.ending_sequence_number)
+                .is_none();
+            let iterator = match (initial, is_open) {
+                // Shards which closed before the watch started hold no
+                // changes since.
+                (true, false) => None,
+                (true, true) => {
+                    self.shard_iterator(&shard_id, ShardIteratorType::Latest)
+                        .await?
+                }
+                (false, _) => {
+                    self.shard_iterator(&shard_id, ShardIteratorType::TrimHorizon)
+                        .await?
+                }
+            };
+            iterators.insert(shard_id, iterator);
+        }
+        self.iterators = iterators;
+        Ok(())
+    }
+
+    async fn shard_iterator(
+        &self,
+        shard_id: &str,
+        start: ShardIteratorType,
+    ) -> Result<Option<String>, Error> {
+        Ok(self
+            .streams
+            .get_shard_iterator()
+            .stream_arn(&self.stream_arn)
+            .shard_id(shard_id)
+            .shard_iterator_type(start)
+            .send()
+            .await
+            .map_err(log_error)?
+            .shard_iterator)
+    }
+}
+
+/// The change a stream record made to a key starting with `prefix`, if any.
+fn change(record: &Record, prefix: &str) -> Option<Change> {
+    let Some(AttributeValue::S(key)) = record.dynamodb()?.keys()?.get(PK) else {
+        return None;
+    };
+    if !key.starts_with(prefix) {
+        return None;
+    }
+    match record.event_name()? {
+        OperationType::Insert | OperationType::Modify => Some(Change::Set(key.clone())),
+        OperationType::Remove => Some(Change::Deleted(key.clone())),
+        _ => None,
+    }
+}
@@ -1,15 +1,24 @@
 mod store;
 
+use std::{sync::Arc, time::Duration};
+
+use anyhow::Context as _;
 use serde::Deserialize;
+use spin_expressions::{Key, Provider};
 use spin_factor_key_value::runtime_config::spin::MakeKeyValueStore;
 use store::{
     KeyValueAzureCosmos, KeyValueAzureCosmosAuthOptions, KeyValueAzureCosmosRuntimeConfigOptions,
+    KeyValueAzureCosmosVariableOptions, RotatingSecretKind,
 };
 
+/// How often credentials sourced from variables are re-read if the runtime
+/// config doesn't say otherwise.
+const DEFAULT_CREDENTIAL_REFRESH_INTERVAL: Duration = Duration::from_secs(300);
+
 /// A key-value store that uses Azure Cosmos as the backend.
 #[derive(Default)]
 pub struct AzureKeyValueStore {
-    _priv: (),
+    variable_providers: Arc<Vec<Box<dyn Provider>>>,
 }
 
 impl AzureKeyValueStore {
@@ -17,6 +26,14 @@ impl AzureKeyValueStore {
     pub fn new() -> Self {
         Self::default()
     }
+
+    /// Creates a new `AzureKeyValueStore` that resolves credentials given by
+    /// `key_variable` or `resource_token_variable` from the given providers.
+    pub fn with_variable_providers(providers: Vec<Box<dyn Provider>>) -> Self {
+        Self {
+            variable_providers: Arc::new(providers),
+        }
+    }
 }
 
 /// Runtime configuration for the Azure Cosmos key-value store.
@@ -24,6 +41,22 @@ impl AzureKeyValueStore {
 pub struct AzureCosmosKeyValueRuntimeConfig {
     /// The authorization token for the Azure Cosmos DB account.
     key: Option<String>,
+    /// The name of a variable holding the authorization token for the Azure
+    /// Cosmos DB account. The variable is re-read periodically so that
+    /// rotated keys are picked up without restarting.
+    key_variable: Option<String>,
+    /// The name of a variable holding a Cosmos DB resource token (SAS) for
+    /// the container. The variable is re-read periodically so that rotated
+    /// tokens are picked up without restarting.
+    resource_token_variable: Option<String>,
+    /// Authenticate with the host's managed identity.
+    #[serde(default)]
+    managed_identity: bool,
+    /// The client ID of a user-assigned managed identity. If unset, the
+    /// system-assigned identity is used.
+    managed_identity_client_id: Option<String>,
+    /// How often, in seconds, credentials sourced from variables are re-read.
+    credential_refresh_interval_secs: Option<u64>,
     /// The Azure Cosmos DB account name.
     account: String,
     /// The Azure Cosmos DB database.
@@ -44,12 +77,7 @@ impl MakeKeyValueStore for AzureKeyValueStore {
         &self,
         runtime_config: Self::RuntimeConfig,
     ) -> anyhow::Result<Self::StoreManager> {
-        let auth_options = match runtime_config.key {
-            Some(key) => KeyValueAzureCosmosAuthOptions::RuntimeConfigValues(
-                KeyValueAzureCosmosRuntimeConfigOptions::new(key),
-            ),
-            None => KeyValueAzureCosmosAuthOptions::Environmental,
-        };
+        let auth_options = self.auth_options(&runtime_config)?;
         KeyValueAzureCosmos::new(
             runtime_config.account,
             runtime_config.database,
@@ -58,3 +86,57 @@ impl MakeKeyValueStore for AzureKeyValueStore {
         )
     }
 }
+
+impl AzureKeyValueStore {
+    fn auth_options(
+        &self,
+        runtime_config: &AzureCosmosKeyValueRuntimeConfig,
+    ) -> anyhow::Result<KeyValueAzureCosmosAuthOptions> {
+        let configured = [
+            runtime_config.key.is_some(),
+            runtime_config.key_variable.is_some(),
+            runtime_config.resource_token_variable.is_some(),
+            runtime_config.managed_identity,
+        ];
+        if configured.into_iter().filter(|set| *set).count() > 1 {
+            anyhow::bail!(
+                "only one of `key`, `key_variable`, `resource_token_variable` and `managed_identity` may be set"
+            );
+        }
+        if runtime_config.managed_identity_client_id.is_some() && !runtime_config.managed_identity {
+            anyhow::bail!("`managed_identity_client_id` requires `managed_identity = true`");
+        }
+
+        let refresh_interval = runtime_config
+            .credential_refresh_interval_secs
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_CREDENTIAL_REFRESH_INTERVAL);
+        let variable = |name: &String, kind| -> anyhow::Result<_> {
+            Key::new(name).with_context(|| format!("invalid variable name {name:?}"))?;
+            Ok(KeyValueAzureCosmosAuthOptions::Variable(
+                KeyValueAzureCosmosVariableOptions::new(
+                    name.clone(),
+                    kind,
+                    self.variable_providers.clone(),
+                    refresh_interval,
+                ),
+            ))
+        };
+
+        Ok(if let Some(key) = &runtime_config.key {
+            KeyValueAzureCosmosAuthOptions::RuntimeConfigValues(
+                KeyValueAzureCosmosRuntimeConfigOptions::new(key.clone()),
+            )
+        } else if let Some(name) = &runtime_config.key_variable {
+            variable(name, RotatingSecretKind::PrimaryKey)?
+        } else if let Some(name) = &runtime_config.resource_token_variable {
+            variable(name, RotatingSecretKind::ResourceToken)?
+        } else if runtime_config.managed_identity {
+            KeyValueAzureCosmosAuthOptions::ManagedIdentity {
+                client_id: runtime_config.managed_identity_client_id.clone(),
+            }
+        } else {
+            KeyValueAzureCosmosAuthOptions::Environmental
+        })
+    }
+}
@@ -5,13 +5,36 @@ use azure_data_cosmos::{
     prelude::{AuthorizationToken, CollectionClient, CosmosClient, Query},
     CosmosEntity,
 };
+use azure_identity::{TokenCredentialOptions, VirtualMachineManagedIdentityCredential};
 use futures::StreamExt;
 use serde::{Deserialize, Serialize};
 use spin_core::async_trait;
+use spin_expressions::{Key, Provider};
 use spin_factor_key_value::{log_cas_error, log_error, Cas, Error, Store, StoreManager, SwapError};
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 pub struct KeyValueAzureCosmos {
+    account: String,
+    database: String,
+    container: String,
+    credentials: Credentials,
+}
+
+enum Credentials {
+    /// A client whose credentials never change, or are refreshed by the SDK
+    /// itself (as for token credentials).
+    Fixed(CollectionClient),
+    /// A client rebuilt whenever the secret held in a variable changes.
+    Rotating {
+        options: KeyValueAzureCosmosVariableOptions,
+        current: futures::lock::Mutex<Option<RotatingClient>>,
+    },
+}
+
+struct RotatingClient {
+    secret: String,
+    fetched_at: Instant,
     client: CollectionClient,
 }
 
@@ -27,11 +50,71 @@ impl KeyValueAzureCosmosRuntimeConfigOptions {
     }
 }
 
+/// The kind of secret held in a variable used for authentication.
+#[derive(Clone, Copy, Debug)]
+pub enum RotatingSecretKind {
+    /// A primary or secondary key for the account.
+    PrimaryKey,
+    /// A resource token scoped to the container.
+    ResourceToken,
+}
+
+/// Azure Cosmos Key / Value options for authenticating with a secret that is
+/// resolved from a variable and re-read periodically
+#[derive(Clone, Debug)]
+pub struct KeyValueAzureCosmosVariableOptions {
+    variable: String,
+    kind: RotatingSecretKind,
+    providers: Arc<Vec<Box<dyn Provider>>>,
+    refresh_interval: Duration,
+}
+
+impl KeyValueAzureCosmosVariableOptions {
+    pub fn new(
+        variable: String,
+        kind: RotatingSecretKind,
+        providers: Arc<Vec<Box<dyn Provider>>>,
+        refresh_interval: Duration,
+    ) -> Self {
+        Self {
+            variable,
+            kind,
+            providers,
+            refresh_interval,
+        }
+    }
+
+    /// Resolves the secret from the first provider that has a value for it.
+    async fn resolve(&self) -> anyhow::Result<String> {
+        let key = Key::new(&self.variable)?;
+        for provider in self.providers.iter() {
+            if let Some(value) = provider.get(&key).await? {
+                return Ok(value);
+            }
+        }
+        anyhow::bail!("no variable provider has a value for {:?}", self.variable)
+    }
+
+    fn authorization_token(&self, secret: &str) -> Result<AuthorizationToken> {
+        Ok(match self.kind {
+            RotatingSecretKind::PrimaryKey => AuthorizationToken::primary_key(secret)?,
+            RotatingSecretKind::ResourceToken => AuthorizationToken::new_resource(secret.into()),
+        })
+    }
+}
+
 /// Azure Cosmos Key / Value enumeration for the possible authentication options
 #[derive(Clone, Debug)]
 pub enum KeyValueAzureCosmosAuthOptions {
     /// Runtime Config values indicates the account and key have been specified directly
     RuntimeConfigValues(KeyValueAzureCosmosRuntimeConfigOptions),
+    /// Variable indicates that the key or resource token is held in a variable, which is
+    /// re-read so that rotated credentials are picked up by long-running hosts.
+    Variable(KeyValueAzureCosmosVariableOptions),
+    /// ManagedIdentity indicates that the host's managed identity should be used, via the
+    /// instance metadata service. If `client_id` is set, that user assigned identity is used;
+    /// otherwise the system assigned identity is. Tokens are refreshed before they expire.
+    ManagedIdentity { client_id: Option<String> },
     /// Environmental indicates that the environment variables of the process should be used to
     /// create the TokenCredential for the Cosmos client. This will use the Azure Rust SDK's
     /// DefaultCredentialChain to derive the TokenCredential based on what environment variables
@@ -76,25 +159,105 @@ impl KeyValueAzureCosmos {
             KeyValueAzureCosmosAuthOptions::RuntimeConfigValues(config) => {
                 AuthorizationToken::primary_key(config.key).map_err(log_error)?
             }
+            KeyValueAzureCosmosAuthOptions::Variable(options) => {
+                return Ok(Self {
+                    account,
+                    database,
+                    container,
+                    credentials: Credentials::Rotating {
+                        options,
+                        current: Default::default(),
+                    },
+                })
+            }
+            KeyValueAzureCosmosAuthOptions::ManagedIdentity { client_id } => {
+                let mut credential =
+                    VirtualMachineManagedIdentityCredential::new(TokenCredentialOptions::default());
+                if let Some(client_id) = client_id {
+                    credential = credential.with_client_id(client_id);
+                }
+                AuthorizationToken::from_token_credential(Arc::new(credential))
+            }
             KeyValueAzureCosmosAuthOptions::Environmental => {
                 AuthorizationToken::from_token_credential(
                     azure_identity::create_default_credential()?,
                 )
             }
         };
-        let cosmos_client = CosmosClient::new(account, token);
-        let database_client = cosmos_client.database_client(database);
-        let client = database_client.collection_client(container);
+        let client = collection_client(&account, &database, &container, token);
+
+        Ok(Self {
+            account,
+            database,
+            container,
+            credentials: Credentials::Fixed(client),
+        })
+    }
+
+    /// Returns a client with current credentials, refreshing them if needed.
+    async fn client(&self) -> Result<CollectionClient> {
+        let (options, current) = match &self.credentials {
+            Credentials::Fixed(client) => return Ok(client.clone()),
+            Credentials::Rotating { options, current } => (options, current),
+        };
+
+        let mut current = current.lock().await;
+        if let Some(rotating) = current.as_ref() {
+            if rotating.fetched_at.elapsed() < options.refresh_interval {
+                return Ok(rotating.client.clone());
+            }
+        }
 
-        Ok(Self { client })
+        let secret = match (options.resolve().await, current.as_mut()) {
+            (Ok(secret), _) => secret,
+            // Keep using the last known credentials rather than failing outright; they may
+            // well still be valid.
+            (Err(err), Some(rotating)) => {
+                tracing::warn!(
+                    "failed to refresh Azure Cosmos credentials from variable {:?}: {err:?}",
+                    options.variable
+                );
+                rotating.fetched_at = Instant::now();
+                return Ok(rotating.client.clone());
+            }
+            (Err(err), None) => return Err(err),
+        };
+
+        match current.as_mut() {
+            Some(rotating) if rotating.secret == secret => {
+                rotating.fetched_at = Instant::now();
+            }
+            _ => {
+                let token = options.authorization_token(&secret)?;
+                let client =
+                    collection_client(&self.account, &self.database, &self.container, token);
+                *current = Some(RotatingClient {
+                    secret,
+                    fetched_at: Instant::now(),
+                    client,
+                });
+            }
+        }
+        Ok(current.as_ref().unwrap().client.clone())
     }
 }
 
+fn collection_client(
+    account: &str,
+    database: &str,
+    container: &str,
+    token: AuthorizationToken,
+) -> CollectionClient {
+    let cosmos_client = CosmosClient::new(account, token);
+    let database_client = cosmos_client.database_client(database.to_owned());
+    database_client.collection_client(container.to_owned())
+}
+
 #[async_trait]
 impl StoreManager for KeyValueAzureCosmos {
     async fn get(&self, _name: &str) -> Result<Arc<dyn Store>, Error> {
         Ok(Arc::new(AzureCosmosStore {
-            client: self.client.clone(),
+            client: self.client().await.map_err(log_error)?,
         }))
     }
 
@@ -103,8 +266,8 @@ impl StoreManager for KeyValueAzureCosmos {
     }
 
     fn summary(&self, _store_name: &str) -> Option<String> {
-        let database = self.client.database_client().database_name();
-        let collection = self.client.collection_name();
+        let database = &self.database;
+        let collection = &self.container;
         Some(format!(
             "Azure CosmosDB database: {database}, collection: {collection}"
         ))
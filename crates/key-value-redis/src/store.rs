@@ -1,9 +1,17 @@
 use anyhow::{Context, Result};
-use redis::{aio::MultiplexedConnection, parse_redis_url, AsyncCommands, Client, RedisError};
+use futures::StreamExt;
+use redis::{
+    aio::MultiplexedConnection, parse_redis_url, AsyncCommands, Client, ConnectionInfo, RedisError,
+};
 use spin_core::async_trait;
-use spin_factor_key_value::{log_error, Cas, Error, Store, StoreManager, SwapError};
+use spin_factor_key_value::{
+    log_error_kind,
+    watch::{watch_channel, Change, Watcher},
+    Cas, Error, ErrorKind, Store, StoreManager, SwapError,
+};
 use std::ops::DerefMut;
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::{Mutex, OnceCell};
 use url::Url;
 
@@ -36,7 +44,7 @@ impl StoreManager for KeyValueRedis {
                     .map(Arc::new)
             })
             .await
-            .map_err(log_error)?;
+            .map_err(log_redis_error)?;
 
         Ok(Arc::new(RedisStore {
             connection: connection.clone(),
@@ -69,7 +77,7 @@ struct CompareAndSwap {
 impl Store for RedisStore {
     async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, Error> {
         let mut conn = self.connection.lock().await;
-        conn.get(key).await.map_err(log_error)
+        conn.get(key).await.map_err(log_redis_error)
     }
 
     async fn set(&self, key: &str, value: &[u8]) -> Result<(), Error> {
@@ -78,7 +86,7 @@ impl Store for RedisStore {
             .await
             .set(key, value)
             .await
-            .map_err(log_error)
+            .map_err(log_redis_error)
     }
 
     async fn delete(&self, key: &str) -> Result<(), Error> {
@@ -87,7 +95,7 @@ impl Store for RedisStore {
             .await
             .del(key)
             .await
-            .map_err(log_error)
+            .map_err(log_redis_error)
     }
 
     async fn exists(&self, key: &str) -> Result<bool, Error> {
@@ -96,7 +104,7 @@ impl Store for RedisStore {
             .await
             .exists(key)
             .await
-            .map_err(log_error)
+            .map_err(log_redis_error)
     }
 
     async fn get_keys(&self) -> Result<Vec<String>, Error> {
@@ -105,7 +113,7 @@ impl Store for RedisStore {
             .await
             .keys("*")
             .await
-            .map_err(log_error)
+            .map_err(log_redis_error)
     }
 
     async fn get_many(&self, keys: Vec<String>) -> Result<Vec<(String, Option<Vec<u8>>)>, Error> {
@@ -114,7 +122,7 @@ impl Store for RedisStore {
             .await
             .keys(keys)
             .await
-            .map_err(log_error)
+            .map_err(log_redis_error)
     }
 
     async fn set_many(&self, key_values: Vec<(String, Vec<u8>)>) -> Result<(), Error> {
@@ -123,7 +131,7 @@ impl Store for RedisStore {
             .await
             .mset(&key_values)
             .await
-            .map_err(log_error)
+            .map_err(log_redis_error)
     }
 
     async fn delete_many(&self, keys: Vec<String>) -> Result<(), Error> {
@@ -132,7 +140,7 @@ impl Store for RedisStore {
             .await
             .del(keys)
             .await
-            .map_err(log_error)
+            .map_err(log_redis_error)
     }
 
     async fn increment(&self, key: String, delta: i64) -> Result<i64, Error> {
@@ -141,7 +149,7 @@ impl Store for RedisStore {
             .await
             .incr(key, delta)
             .await
-            .map_err(log_error)
+            .map_err(log_redis_error)
     }
 
     /// `new_compare_and_swap` builds a new CAS structure giving it its own connection since Redis
@@ -153,12 +161,12 @@ impl Store for RedisStore {
         key: &str,
     ) -> Result<Arc<dyn Cas>, Error> {
         let cx = Client::open(self.database_url.clone())
-            .map_err(log_error)?
+            .map_err(log_redis_error)?
             .get_multiplexed_async_connection()
             .await
             .map(Mutex::new)
             .map(Arc::new)
-            .map_err(log_error)?;
+            .map_err(log_redis_error)?;
 
         Ok(Arc::new(CompareAndSwap {
             key: key.to_string(),
@@ -166,6 +174,100 @@ impl Store for RedisStore {
             bucket_rep,
         }))
     }
+
+    async fn acquire_lease(&self, key: &str, token: &str, ttl: Duration) -> Result<bool, Error> {
+        let set: Option<String> = redis::cmd("SET")
+            .arg(key)
+            .arg(token)
+            .arg("NX")
+            .arg("PX")
+            .arg(lease_ttl_ms(ttl))
+            .query_async(self.connection.lock().await.deref_mut())
+            .await
+            .map_err(log_redis_error)?;
+        Ok(set.is_some())
+    }
+
+    async fn renew_lease(&self, key: &str, token: &str, ttl: Duration) -> Result<bool, Error> {
+        let renewed: i64 = redis::Script::new(RENEW_LEASE_SCRIPT)
+            .key(key)
+            .arg(token)
+            .arg(lease_ttl_ms(ttl))
+            .invoke_async(self.connection.lock().await.deref_mut())
+            .await
+            .map_err(log_redis_error)?;
+        Ok(renewed == 1)
+    }
+
+    async fn release_lease(&self, key: &str, token: &str) -> Result<bool, Error> {
+        let released: i64 = redis::Script::new(RELEASE_LEASE_SCRIPT)
+            .key(key)
+            .arg(token)
+            .invoke_async(self.connection.lock().await.deref_mut())
+            .await
+            .map_err(log_redis_error)?;
+        Ok(released == 1)
+    }
+
+    // Watches subscribe to keyspace notifications, which the server must be
+    // configured to send.
+    async fn watch(&self, prefix: &str) -> Result<Watcher, Error> {
+        // Servers which disallow CONFIG may still send notifications.
+        let config: Result<Vec<String>, RedisError> = redis::cmd("CONFIG")
+            .arg("GET")
+            .arg("notify-keyspace-events")
+            .query_async(self.connection.lock().await.deref_mut())
+            .await;
+        if let Ok([_, flags]) = config.as_deref() {
+            if !keyspace_events_enabled(flags) {
+                return Err(Error::Other(format!(
+                    "watching a Redis store needs keyspace notifications, but notify-keyspace-events is {flags:?}; set it to \"KA\""
+                )));
+            }
+        }
+
+        let info: ConnectionInfo = self
+            .database_url
+            .as_str()
+            .parse()
+            .map_err(log_redis_error)?;
+        let channel_prefix = format!("__keyspace@{}__:", info.redis.db);
+        let mut pubsub = Client::open(info)
+            .map_err(log_redis_error)?
+            .get_async_pubsub()
+            .await
+            .map_err(log_redis_error)?;
+        pubsub
+            .psubscribe(format!("{channel_prefix}{}*", escape_glob(prefix)))
+            .await
+            .map_err(log_redis_error)?;
+
+        let (mut sender, watcher) = watch_channel();
+        tokio::spawn(async move {
+            let mut messages = std::pin::pin!(pubsub.into_on_message());
+            loop {
+                let message = tokio::select! {
+                    _ = sender.closed() => return,
+                    message = messages.next() => message,
+                };
+                let Some(message) = message else {
+                    return sender.fail(Error::Other(
+                        "lost connection to Redis while watching".to_string(),
+                    ));
+                };
+                let Some(key) = message.get_channel_name().strip_prefix(&channel_prefix) else {
+                    continue;
+                };
+                let Ok(event) = message.get_payload::<String>() else {
+                    continue;
+                };
+                if !sender.send(keyspace_change(key.to_owned(), &event)) {
+                    return;
+                }
+            }
+        });
+        Ok(watcher)
+    }
 }
 
 #[async_trait]
@@ -177,13 +279,13 @@ impl Cas for CompareAndSwap {
             .arg(&self.key)
             .exec_async(self.connection.lock().await.deref_mut())
             .await
-            .map_err(log_error)?;
+            .map_err(log_redis_error)?;
         self.connection
             .lock()
             .await
             .get(&self.key)
             .await
-            .map_err(log_error)
+            .map_err(log_redis_error)
     }
 
     /// swap will set the key to the new value only if the key has not changed. Afterward, the
@@ -217,3 +319,65 @@ impl Cas for CompareAndSwap {
         self.key.clone()
     }
 }
+
+/// Extends a lease's expiry if the key still holds the lease's token.
+const RENEW_LEASE_SCRIPT: &str = r#"
+if redis.call("GET", KEYS[1]) == ARGV[1] then
+    return redis.call("PEXPIRE", KEYS[1], ARGV[2])
+end
+return 0
+"#;
+
+/// Deletes a lease's key if it still holds the lease's token.
+const RELEASE_LEASE_SCRIPT: &str = r#"
+if redis.call("GET", KEYS[1]) == ARGV[1] then
+    return redis.call("DEL", KEYS[1])
+end
+return 0
+"#;
+
+/// Redis expiry times must be positive.
+fn lease_ttl_ms(ttl: Duration) -> u64 {
+    (ttl.as_millis() as u64).max(1)
+}
+
+/// Whether `notify-keyspace-events` flags enable the keyspace notifications
+/// watches need: those for all keys, or at least for generic and string
+/// commands and for expiry.
+fn keyspace_events_enabled(flags: &str) -> bool {
+    flags.contains('K')
+        && (flags.contains('A') || ['g', '$', 'x'].into_iter().all(|flag| flags.contains(flag)))
+}
+
+/// The change a keyspace notification's event made to its key.
+fn keyspace_change(key: String, event: &str) -> Change {
+    match event {
+        "del" | "expired" | "evicted" | "rename_from" | "move_from" => Change::Deleted(key),
+        _ => Change::Set(key),
+    }
+}
+
+/// Escapes the characters which are special in Redis glob-style patterns.
+fn escape_glob(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if matches!(c, '*' | '?' | '[' | ']' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+fn log_redis_error(err: RedisError) -> Error {
+    let kind = if err.is_timeout() {
+        ErrorKind::Timeout
+    } else if err.is_connection_refusal() || err.is_connection_dropped() {
+        ErrorKind::Connection
+    } else if err.kind() == redis::ErrorKind::AuthenticationFailed {
+        ErrorKind::Auth
+    } else {
+        ErrorKind::classify(&err)
+    };
+    log_error_kind(kind, err)
+}
@@ -1,15 +1,23 @@
 use anyhow::Result;
 use rusqlite::{named_params, Connection};
 use spin_core::async_trait;
-use spin_factor_key_value::{log_cas_error, log_error, Cas, Error, Store, StoreManager, SwapError};
+use spin_factor_key_value::{
+    log_cas_error, log_error,
+    watch::{self, Watcher},
+    Cas, Error, Store, StoreManager, SwapError,
+};
 use std::rc::Rc;
 use std::{
     path::PathBuf,
     sync::OnceLock,
     sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 use tokio::task;
 
+/// How often a watched store is checked for changes.
+const WATCH_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
 #[derive(Clone, Debug)]
 pub enum DatabaseLocation {
     InMemory,
@@ -54,6 +62,20 @@ impl KeyValueSqlite {
             )
             .map_err(log_error)?;
 
+        connection
+            .execute(
+                "CREATE TABLE IF NOT EXISTS spin_key_value_lease (
+                           store      TEXT NOT NULL,
+                           key        TEXT NOT NULL,
+                           token      TEXT NOT NULL,
+                           expires_at INTEGER NOT NULL,
+
+                           PRIMARY KEY (store, key)
+                        )",
+                [],
+            )
+            .map_err(log_error)?;
+
         // the array module is needed for `rarray` usage in queries.
         rusqlite::vtab::array::load_module(&connection).map_err(log_error)?;
 
@@ -273,6 +295,93 @@ impl Store for SqliteStore {
             bucket_rep,
         }))
     }
+
+    async fn acquire_lease(&self, key: &str, token: &str, ttl: Duration) -> Result<bool, Error> {
+        let now = now_ms();
+        task::block_in_place(|| {
+            self.connection
+                .lock()
+                .unwrap()
+                .prepare_cached(
+                    "INSERT INTO spin_key_value_lease (store, key, token, expires_at)
+                     VALUES (:name, :key, :token, :expires_at)
+                     ON CONFLICT(store, key) DO UPDATE SET token=:token, expires_at=:expires_at
+                     WHERE expires_at <= :now",
+                )
+                .map_err(log_error)?
+                .execute(named_params! {
+                    ":name": &self.name,
+                    ":key": key,
+                    ":token": token,
+                    ":expires_at": now.saturating_add(ttl.as_millis() as i64),
+                    ":now": now,
+                })
+                .map_err(log_error)
+                .map(|rows| rows == 1)
+        })
+    }
+
+    async fn renew_lease(&self, key: &str, token: &str, ttl: Duration) -> Result<bool, Error> {
+        let now = now_ms();
+        task::block_in_place(|| {
+            self.connection
+                .lock()
+                .unwrap()
+                .prepare_cached(
+                    "UPDATE spin_key_value_lease SET expires_at=:expires_at
+                     WHERE store=:name AND key=:key AND token=:token AND expires_at > :now",
+                )
+                .map_err(log_error)?
+                .execute(named_params! {
+                    ":name": &self.name,
+                    ":key": key,
+                    ":token": token,
+                    ":expires_at": now.saturating_add(ttl.as_millis() as i64),
+                    ":now": now,
+                })
+                .map_err(log_error)
+                .map(|rows| rows == 1)
+        })
+    }
+
+    async fn release_lease(&self, key: &str, token: &str) -> Result<bool, Error> {
+        let now = now_ms();
+        task::block_in_place(|| {
+            self.connection
+                .lock()
+                .unwrap()
+                .prepare_cached(
+                    "DELETE FROM spin_key_value_lease
+                     WHERE store=:name AND key=:key AND token=:token AND expires_at > :now",
+                )
+                .map_err(log_error)?
+                .execute(named_params! {
+                    ":name": &self.name,
+                    ":key": key,
+                    ":token": token,
+                    ":now": now,
+                })
+                .map_err(log_error)
+                .map(|rows| rows == 1)
+        })
+    }
+
+    // SQLite has no change notifications for other connections, so watches
+    // poll the store, reading the watched keys only when the database has
+    // changed since they were last read.
+    async fn watch(&self, prefix: &str) -> Result<Watcher, Error> {
+        let connection = self.connection.clone();
+        let name = self.name.clone();
+        let prefix = prefix.to_owned();
+        let mut version = None;
+        watch::poll(WATCH_POLL_INTERVAL, move || {
+            let snapshot = task::block_in_place(|| {
+                snapshot_if_changed(&connection, &name, &prefix, &mut version)
+            });
+            std::future::ready(snapshot)
+        })
+        .await
+    }
 }
 
 struct CompareAndSwap {
@@ -355,6 +464,51 @@ impl Cas for CompareAndSwap {
     }
 }
 
+/// The keys in `store` which start with `prefix`, and their values, or `None`
+/// if the database hasn't changed since `version`, which is updated.
+///
+/// The database's version combines the changes made through the connection
+/// with its `data_version`, which changes when other connections commit.
+fn snapshot_if_changed(
+    connection: &Mutex<Connection>,
+    store: &str,
+    prefix: &str,
+    version: &mut Option<(i64, i64)>,
+) -> Result<Option<Vec<(String, Vec<u8>)>>, Error> {
+    let connection = connection.lock().unwrap();
+    let current = connection
+        .query_row(
+            "SELECT total_changes(), data_version FROM pragma_data_version",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .map_err(log_error)?;
+    if *version == Some(current) {
+        return Ok(None);
+    }
+    *version = Some(current);
+    connection
+        .prepare_cached(
+            "SELECT key, value FROM spin_key_value
+             WHERE store=$1 AND substr(key, 1, length($2))=$2",
+        )
+        .map_err(log_error)?
+        .query_map([store, prefix], |row| Ok((row.get(0)?, row.get(1)?)))
+        .map_err(log_error)?
+        .collect::<Result<_, _>>()
+        .map(Some)
+        .map_err(log_error)
+}
+
+/// The current time in milliseconds since the Unix epoch, as stored in lease
+/// expiry times.
+fn now_ms() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as i64
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -555,6 +709,114 @@ mod test {
         }
     }
 
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn leases() -> Result<()> {
+        use spin_world::spin::lock::lock::{Error as LockError, Host as LockHost};
+
+        let mut kv = KeyValueDispatch::new(
+            ["default".to_owned()].into_iter().collect(),
+            Arc::new(DelegatingStoreManager::new([(
+                "default".to_owned(),
+                Arc::new(KeyValueSqlite::new(DatabaseLocation::InMemory)) as _,
+            )])),
+        );
+        let store = || "default".to_owned();
+        let name = || "job".to_owned();
+
+        let token = LockHost::acquire(&mut kv, store(), name(), 60_000)
+            .await?
+            .expect("lease should be acquired");
+        assert!(LockHost::acquire(&mut kv, store(), name(), 60_000)
+            .await?
+            .is_none());
+        assert!(!LockHost::renew(&mut kv, store(), name(), "wrong".into(), 60_000).await?);
+        assert!(LockHost::renew(&mut kv, store(), name(), token.clone(), 60_000).await?);
+        assert!(LockHost::release(&mut kv, store(), name(), token.clone()).await?);
+        assert!(!LockHost::release(&mut kv, store(), name(), token).await?);
+
+        // Expired leases may be taken over.
+        let expired = LockHost::acquire(&mut kv, store(), name(), 0)
+            .await?
+            .expect("lease should be acquired");
+        let token = LockHost::acquire(&mut kv, store(), name(), 60_000)
+            .await?
+            .expect("expired lease should be taken over");
+        assert_ne!(expired, token);
+        assert!(!LockHost::renew(&mut kv, store(), name(), expired, 60_000).await?);
+
+        assert!(matches!(
+            LockHost::acquire(&mut kv, "forbidden".into(), name(), 60_000).await,
+            Err(LockError::AccessDenied)
+        ));
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn watch() -> Result<()> {
+        use spin_world::spin::key_value::watch::{Change, HostWatcher};
+
+        let mut kv = KeyValueDispatch::new(
+            ["default".to_owned()].into_iter().collect(),
+            Arc::new(DelegatingStoreManager::new([(
+                "default".to_owned(),
+                Arc::new(KeyValueSqlite::new(DatabaseLocation::InMemory)) as _,
+            )])),
+        );
+        let rep = HostStore::open(&mut kv, "default".to_owned()).await??.rep();
+        kv.set(Resource::new_own(rep), "user:1".to_owned(), b"a".to_vec())
+            .await??;
+        kv.set(Resource::new_own(rep), "user:2".to_owned(), b"b".to_vec())
+            .await??;
+
+        let watcher = HostWatcher::open(&mut kv, "default".into(), "user:".into()).await?;
+        let watcher = watcher.rep();
+        kv.set(Resource::new_own(rep), "user:1".to_owned(), b"c".to_vec())
+            .await??;
+        kv.set(Resource::new_own(rep), "other".to_owned(), b"d".to_vec())
+            .await??;
+        kv.delete(Resource::new_own(rep), "user:2".to_owned())
+            .await??;
+
+        let mut changes = vec![];
+        while changes.len() < 2 {
+            let change =
+                HostWatcher::next(&mut kv, Resource::new_own(watcher), Some(5_000)).await?;
+            changes.push(change.expect("change should be seen"));
+        }
+        assert!(matches!(&changes[0], Change::Set(key) if key == "user:1"));
+        assert!(matches!(&changes[1], Change::Deleted(key) if key == "user:2"));
+        assert!(
+            HostWatcher::next(&mut kv, Resource::new_own(watcher), Some(1_000))
+                .await?
+                .is_none()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn watched_keys_are_only_read_after_changes() -> Result<()> {
+        let store = KeyValueSqlite::new(DatabaseLocation::InMemory);
+        let connection = store.create_connection()?;
+        let mut version = None;
+        let snapshot = |version: &mut Option<(i64, i64)>| {
+            snapshot_if_changed(&connection, "default", "user:", version)
+        };
+
+        assert_eq!(Some(vec![]), snapshot(&mut version)?);
+        assert_eq!(None, snapshot(&mut version)?);
+
+        connection.lock().unwrap().execute(
+            "INSERT INTO spin_key_value (store, key, value) VALUES ('default', 'user:1', x'61')",
+            [],
+        )?;
+        assert_eq!(
+            Some(vec![("user:1".to_owned(), b"a".to_vec())]),
+            snapshot(&mut version)?
+        );
+        assert_eq!(None, snapshot(&mut version)?);
+        Ok(())
+    }
+
     async fn kv_incr(kv: &mut KeyValueDispatch, rep: u32, delta: i64) -> i64 {
         let res = kv
             .increment(Resource::new_own(rep), "counter".to_owned(), delta)
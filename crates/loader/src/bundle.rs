@@ -0,0 +1,307 @@
+//! Portable application bundles.
+//!
+//! A bundle is a gzipped tarball containing everything needed to run an
+//! application without access to the original sources or a registry: the
+//! locked app, its Wasm components and static assets, and optionally a
+//! runtime config file to use as a starting point on the target host.
+//!
+//! Content sources in a bundled lock file are paths relative to the bundle
+//! root; they are resolved to file URLs when the bundle is unpacked.
+
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, ensure, Context, Result};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use spin_common::{sha256, ui::quoted_path, url::parse_file_url};
+use spin_locked_app::locked::{ContentRef, LockedApp};
+
+/// The file describing the bundle format.
+const BUNDLE_INFO_FILE: &str = "bundle.json";
+/// The locked app, with sources relative to the bundle root.
+const LOCK_FILE: &str = "spin.lock";
+/// The runtime config template, if the bundle has one.
+const RUNTIME_CONFIG_FILE: &str = "runtime-config.toml";
+/// Wasm components (and dependencies), named by digest.
+const BLOBS_DIR: &str = "blobs";
+/// Static assets, by component ID and mount index.
+const FILES_DIR: &str = "files";
+
+const BUNDLE_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+struct BundleInfo {
+    bundle_version: u32,
+}
+
+/// An application unpacked from a bundle.
+#[derive(Debug)]
+pub struct UnpackedBundle {
+    /// The locked app, with content sources pointing into the unpacked bundle.
+    pub locked_app: LockedApp,
+    /// The bundled runtime config file, if there is one.
+    pub runtime_config_file: Option<PathBuf>,
+}
+
+/// Write the given app to a bundle at `output`. All of the app's content must
+/// be inline or available at local file URLs, as it is after loading from a
+/// manifest with files copied to a mount root.
+pub fn export(
+    locked_app: &LockedApp,
+    runtime_config_file: Option<&Path>,
+    output: &Path,
+) -> Result<()> {
+    let file = File::create(output)
+        .with_context(|| format!("failed to create bundle {}", quoted_path(output)))?;
+    let mut tar = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+    tar.follow_symlinks(true);
+
+    let mut locked_app = locked_app.clone();
+    for component in &mut locked_app.components {
+        add_blob(&mut tar, &mut component.source.content)
+            .with_context(|| format!("failed to bundle component {:?}", component.id))?;
+        for (name, dependency) in &mut component.dependencies {
+            add_blob(&mut tar, &mut dependency.source.content).with_context(|| {
+                format!(
+                    "failed to bundle dependency {name} of component {:?}",
+                    component.id
+                )
+            })?;
+        }
+        for (index, mount) in component.files.iter_mut().enumerate() {
+            let Some(source) = local_source(&mount.content)? else {
+                continue;
+            };
+            let bundle_path = format!("{FILES_DIR}/{}/{index}", component.id);
+            if source.is_dir() {
+                tar.append_dir_all(&bundle_path, &source)
+            } else {
+                tar.append_path_with_name(&source, &bundle_path)
+            }
+            .with_context(|| format!("failed to bundle files from {}", quoted_path(&source)))?;
+            mount.content.source = Some(bundle_path);
+        }
+    }
+
+    append_bytes(
+        &mut tar,
+        BUNDLE_INFO_FILE,
+        &serde_json::to_vec(&BundleInfo {
+            bundle_version: BUNDLE_VERSION,
+        })?,
+    )?;
+    append_bytes(&mut tar, LOCK_FILE, &locked_app.to_json()?)?;
+    if let Some(runtime_config_file) = runtime_config_file {
+        tar.append_path_with_name(runtime_config_file, RUNTIME_CONFIG_FILE)
+            .with_context(|| {
+                format!(
+                    "failed to bundle runtime config {}",
+                    quoted_path(runtime_config_file)
+                )
+            })?;
+    }
+
+    tar.into_inner()?
+        .finish()
+        .with_context(|| format!("failed to write bundle {}", quoted_path(output)))?;
+    Ok(())
+}
+
+/// Unpack the bundle at `bundle` into `dest_dir` and load its app.
+pub fn unpack(bundle: &Path, dest_dir: &Path) -> Result<UnpackedBundle> {
+    let file = File::open(bundle)
+        .with_context(|| format!("failed to open bundle {}", quoted_path(bundle)))?;
+    std::fs::create_dir_all(dest_dir)
+        .with_context(|| format!("failed to create directory {}", quoted_path(dest_dir)))?;
+    tar::Archive::new(GzDecoder::new(file))
+        .unpack(dest_dir)
+        .with_context(|| format!("failed to unpack bundle {}", quoted_path(bundle)))?;
+
+    let info: BundleInfo = serde_json::from_slice(
+        &std::fs::read(dest_dir.join(BUNDLE_INFO_FILE))
+            .with_context(|| format!("{} is not a Spin bundle", quoted_path(bundle)))?,
+    )
+    .context("invalid bundle info")?;
+    ensure!(
+        info.bundle_version == BUNDLE_VERSION,
+        "unsupported bundle version {}; this version of Spin supports version {BUNDLE_VERSION}",
+        info.bundle_version
+    );
+
+    let mut locked_app = LockedApp::from_json(&std::fs::read(dest_dir.join(LOCK_FILE))?)
+        .context("invalid bundled lock file")?;
+    for component in &mut locked_app.components {
+        resolve_source(dest_dir, &mut component.source.content, true)?;
+        for dependency in component.dependencies.values_mut() {
+            resolve_source(dest_dir, &mut dependency.source.content, true)?;
+        }
+        for mount in &mut component.files {
+            resolve_source(dest_dir, &mut mount.content, false)?;
+        }
+    }
+
+    let runtime_config_file = Some(dest_dir.join(RUNTIME_CONFIG_FILE)).filter(|p| p.is_file());
+    Ok(UnpackedBundle {
+        locked_app,
+        runtime_config_file,
+    })
+}
+
+/// Add the content to the bundle as a blob named by its digest, and point its
+/// source at the blob.
+fn add_blob(tar: &mut tar::Builder<impl std::io::Write>, content: &mut ContentRef) -> Result<()> {
+    let Some(source) = local_source(content)? else {
+        return Ok(());
+    };
+    let bytes = std::fs::read(&source)
+        .with_context(|| format!("failed to read {}", quoted_path(&source)))?;
+    let hex = sha256::hex_digest_from_bytes(&bytes);
+    let bundle_path = format!("{BLOBS_DIR}/sha256-{hex}");
+    append_bytes(tar, &bundle_path, &bytes)?;
+    content.source = Some(bundle_path);
+    content.digest = Some(format!("sha256:{hex}"));
+    Ok(())
+}
+
+/// The local path of the content, or `None` if it is inline.
+fn local_source(content: &ContentRef) -> Result<Option<PathBuf>> {
+    match &content.source {
+        Some(source) => {
+            let path = parse_file_url(source).with_context(|| {
+                format!("only local content can be bundled, but found {source:?}")
+            })?;
+            Ok(Some(path))
+        }
+        None if content.inline.is_some() => Ok(None),
+        None => bail!("content has neither a source nor inline data"),
+    }
+}
+
+fn resolve_source(root: &Path, content: &mut ContentRef, check_digest: bool) -> Result<()> {
+    let Some(source) = &content.source else {
+        return Ok(());
+    };
+    let relative = Path::new(source);
+    ensure!(
+        relative.is_relative() && !source.contains(".."),
+        "invalid bundled content path {source:?}"
+    );
+    let path = root.join(relative);
+    if check_digest {
+        if let Some(digest) = &content.digest {
+            let actual = sha256::hex_digest_from_file(&path)
+                .with_context(|| format!("bundled content {source:?} is missing"))?;
+            ensure!(
+                digest.strip_prefix("sha256:") == Some(actual.as_str()),
+                "bundled content {source:?} does not match its digest {digest}"
+            );
+        }
+    }
+    let url = Url::from_file_path(&path)
+        .map_err(|_| anyhow::anyhow!("cannot convert {} to a file URL", quoted_path(&path)))?;
+    content.source = Some(url.to_string());
+    Ok(())
+}
+
+fn append_bytes(
+    tar: &mut tar::Builder<impl std::io::Write>,
+    path: &str,
+    bytes: &[u8],
+) -> Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(bytes.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    tar.append_data(&mut header, path, bytes)
+        .with_context(|| format!("failed to add {path} to bundle"))
+}
+
+#[cfg(test)]
+mod test {
+    use spin_locked_app::locked::{ContentPath, LockedComponent, LockedComponentSource};
+
+    use super::*;
+
+    fn file_url(path: &Path) -> String {
+        Url::from_file_path(path).unwrap().to_string()
+    }
+
+    #[test]
+    fn can_round_trip_bundle() {
+        let source_dir = tempfile::tempdir().unwrap();
+        let wasm = source_dir.path().join("component.wasm");
+        std::fs::write(&wasm, b"\0asm").unwrap();
+        let assets = source_dir.path().join("assets");
+        std::fs::create_dir(&assets).unwrap();
+        std::fs::write(assets.join("index.html"), "hello").unwrap();
+        let runtime_config = source_dir.path().join("runtime-config.toml");
+        std::fs::write(
+            &runtime_config,
+            "[key_value_store.default]\ntype = \"spin\"\n",
+        )
+        .unwrap();
+
+        let mut locked_app =
+            LockedApp::from_json(br#"{"spin_lock_version": 1, "triggers": [], "components": []}"#)
+                .unwrap();
+        locked_app.components.push(LockedComponent {
+            id: "web".into(),
+            metadata: Default::default(),
+            source: LockedComponentSource {
+                content_type: "application/wasm".into(),
+                content: ContentRef {
+                    source: Some(file_url(&wasm)),
+                    ..Default::default()
+                },
+            },
+            env: Default::default(),
+            files: vec![ContentPath {
+                content: ContentRef {
+                    source: Some(file_url(&assets)),
+                    ..Default::default()
+                },
+                path: "/".into(),
+                writable: None,
+                max_bytes: None,
+                integrity: Default::default(),
+            }],
+            config: Default::default(),
+            dependencies: Default::default(),
+        });
+
+        let bundle = source_dir.path().join("app.tar.gz");
+        export(&locked_app, Some(&runtime_config), &bundle).unwrap();
+
+        let dest_dir = tempfile::tempdir().unwrap();
+        let unpacked = unpack(&bundle, dest_dir.path()).unwrap();
+        let component = &unpacked.locked_app.components[0];
+
+        let wasm = parse_file_url(component.source.content.source.as_ref().unwrap()).unwrap();
+        assert!(wasm.starts_with(dest_dir.path()));
+        assert_eq!(b"\0asm", std::fs::read(wasm).unwrap().as_slice());
+
+        let assets = parse_file_url(component.files[0].content.source.as_ref().unwrap()).unwrap();
+        assert_eq!(
+            "hello",
+            std::fs::read_to_string(assets.join("index.html")).unwrap()
+        );
+        assert!(unpacked.runtime_config_file.unwrap().is_file());
+    }
+
+    #[test]
+    fn rejects_tampered_blob() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("blob"), "tampered").unwrap();
+        let mut content = ContentRef {
+            source: Some("blob".into()),
+            digest: Some(format!(
+                "sha256:{}",
+                sha256::hex_digest_from_bytes("original")
+            )),
+            ..Default::default()
+        };
+        resolve_source(dir.path(), &mut content, true).unwrap_err();
+    }
+}
@@ -3,17 +3,33 @@
 use anyhow::{ensure, Context, Result};
 
 use std::{
-    path::PathBuf,
-    sync::atomic::{AtomicBool, Ordering},
+    collections::HashSet,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    time::Duration,
 };
 
-use crate::fs::{create_dir_all, write_file};
+use crate::fs::{create_dir_all, rename, write_file};
+
+mod gc;
+mod lock;
+
+pub use gc::{GcPolicy, GcReport};
+pub use lock::CacheLock;
 
 const CONFIG_DIR: &str = "spin";
 const REGISTRY_CACHE_DIR: &str = "registry";
 const MANIFESTS_DIR: &str = "manifests";
 const WASM_DIR: &str = "wasm";
 const DATA_DIR: &str = "data";
+const PARTIAL_DIR: &str = "partial";
+const LOCKS_DIR: &str = "locks";
+/// File locked shared while content is added to the cache, and exclusively
+/// while it is garbage collected.
+const LOCK_FILE: &str = ".lock";
 
 /// Cache for registry entities.
 #[derive(Debug)]
@@ -23,6 +39,10 @@ pub struct Cache {
     /// Whether the cache directories have been checked to exist (and
     /// created if necessary).
     dirs_ensured_once: AtomicBool,
+    /// How long to wait for another process to release a lock.
+    lock_timeout: Duration,
+    /// The manifest directories of the apps this process has leased.
+    leased: Mutex<HashSet<PathBuf>>,
 }
 
 impl Cache {
@@ -39,6 +59,8 @@ impl Cache {
         Ok(Self {
             root,
             dirs_ensured_once: AtomicBool::new(false),
+            lock_timeout: lock::lock_timeout_from_env(),
+            leased: Default::default(),
         })
     }
 
@@ -89,15 +111,27 @@ impl Cache {
     /// Write the contents in the cache's wasm directory.
     pub async fn write_wasm(&self, bytes: impl AsRef<[u8]>, digest: impl AsRef<str>) -> Result<()> {
         self.ensure_dirs().await?;
-        write_file(&self.wasm_path(digest), bytes.as_ref()).await?;
-        Ok(())
+        self.write_entry(&self.wasm_path(&digest), digest.as_ref(), bytes.as_ref())
+            .await
     }
 
     /// Write the contents in the cache's data directory.
     pub async fn write_data(&self, bytes: impl AsRef<[u8]>, digest: impl AsRef<str>) -> Result<()> {
         self.ensure_dirs().await?;
-        write_file(&self.data_path(digest), bytes.as_ref()).await?;
-        Ok(())
+        self.write_entry(&self.data_path(&digest), digest.as_ref(), bytes.as_ref())
+            .await
+    }
+
+    /// Writes an entry unless another process already has. Entries are named
+    /// by digest, so any process writing one writes the same content.
+    async fn write_entry(&self, path: &Path, digest: &str, bytes: &[u8]) -> Result<()> {
+        let mut lock = self.entry_lock(digest)?;
+        let _guard = lock.write().await?;
+        if path.exists() {
+            tracing::debug!("{} was written by another process", path.display());
+            return Ok(());
+        }
+        write_atomically(path, bytes).await
     }
 
     /// The path of contents in the cache's wasm directory, which may or may not exist.
@@ -110,6 +144,12 @@ impl Cache {
         self.data_dir().join(safe_name(digest).as_ref())
     }
 
+    /// The path of the part of a blob downloaded so far, kept so that the
+    /// download can be resumed, which may or may not exist.
+    pub fn partial_path(&self, digest: impl AsRef<str>) -> PathBuf {
+        self.root.join(PARTIAL_DIR).join(safe_name(digest).as_ref())
+    }
+
     /// Ensure the expected configuration directories are found in the root.
     ///
     /// ```text
@@ -157,6 +197,16 @@ impl Cache {
     }
 }
 
+/// Writes the file by renaming a temporary file into place, so that other
+/// processes never see it partially written.
+pub async fn write_atomically(path: &Path, bytes: &[u8]) -> Result<()> {
+    let mut temp_name = path.file_name().unwrap_or_default().to_owned();
+    temp_name.push(format!(".{}.tmp", std::process::id()));
+    let temp_path = path.with_file_name(temp_name);
+    write_file(&temp_path, bytes).await?;
+    rename(&temp_path, path).await
+}
+
 #[cfg(windows)]
 fn safe_name(digest: impl AsRef<str>) -> impl AsRef<std::path::Path> {
     digest.as_ref().replace(':', "_")
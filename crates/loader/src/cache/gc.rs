@@ -0,0 +1,499 @@
+//! Garbage collection of the registry cache.
+//!
+//! Each app pulled into the cache has a directory under `manifests` holding
+//! its OCI manifest and locked app config, which refer to the Wasm and data
+//! files it needs by digest. Local apps whose components or dependencies come
+//! from a URL or a package registry have a directory under `manifests/local`
+//! instead, listing the digests of the files they cached. Garbage collection
+//! removes apps that are too old or that don't fit in the size budget, least
+//! recently used first, and then every file that no remaining app refers to.
+//!
+//! Apps which are running are never removed: a process running an app takes
+//! a shared lock on its `lease` file for as long as the process lives, so an
+//! app is in use if that file can't be locked exclusively. Content is added
+//! to the cache under a shared lock on the cache's lock file, which garbage
+//! collection locks exclusively, so that it never sees an app half pulled.
+
+use std::{
+    collections::HashSet,
+    fs::{File, OpenOptions},
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{Context, Result};
+use spin_common::ui::quoted_path;
+
+use super::{write_atomically, Cache};
+use crate::fs::create_dir_all;
+
+const MANIFEST_FILE: &str = "manifest.json";
+const LEASE_FILE: &str = "lease";
+const LOCAL_APPS_DIR: &str = "local";
+
+/// Which cached apps garbage collection removes.
+#[derive(Clone, Debug, Default)]
+pub struct GcPolicy {
+    /// Remove the least recently used apps until the cache is no bigger than
+    /// this many bytes.
+    pub max_size: Option<u64>,
+    /// Remove apps that haven't been used for this long.
+    pub max_age: Option<Duration>,
+    /// Report what would be removed, without removing it.
+    pub dry_run: bool,
+}
+
+/// What garbage collection removed.
+#[derive(Debug, Default)]
+pub struct GcReport {
+    /// The references of the apps removed, e.g. `ghcr.io/org/app/v1`.
+    pub removed_apps: Vec<String>,
+    /// How many Wasm and data files were removed.
+    pub removed_files: usize,
+    /// How many bytes were freed.
+    pub freed_bytes: u64,
+    /// How many bytes the cache uses after garbage collection.
+    pub remaining_bytes: u64,
+    /// The references of apps which weren't removed because they are in use.
+    pub in_use_apps: Vec<String>,
+}
+
+/// An app in the cache.
+struct CachedApp {
+    dir: PathBuf,
+    reference: String,
+    last_used: SystemTime,
+    size: u64,
+    in_use: bool,
+    /// The paths of the files the app refers to.
+    files: HashSet<PathBuf>,
+}
+
+impl Cache {
+    /// Protects the app whose manifest is in the given directory from garbage
+    /// collection for the rest of the life of the process, and records that
+    /// it was used.
+    pub fn lease(&self, manifest_dir: &Path) -> Result<()> {
+        if !self.leased.lock().unwrap().insert(manifest_dir.to_owned()) {
+            return Ok(());
+        }
+        let path = manifest_dir.join(LEASE_FILE);
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)
+            .with_context(|| format!("failed to open cache lease {}", quoted_path(&path)))?;
+        // The lock is released when the process exits.
+        let lock = Box::leak(Box::new(fd_lock::RwLock::new(file)));
+        let guard = lock
+            .read()
+            .with_context(|| format!("failed to lock cache lease {}", quoted_path(&path)))?;
+        std::mem::forget(guard);
+
+        // The manifest's modification time records when the app was last used.
+        if let Ok(manifest) = File::options()
+            .write(true)
+            .open(manifest_dir.join(MANIFEST_FILE))
+        {
+            _ = manifest.set_modified(SystemTime::now());
+        }
+        Ok(())
+    }
+
+    /// Records that the local app in `app_root` uses the cached file with the
+    /// given digest, and protects the file from garbage collection for the
+    /// rest of the life of the process.
+    ///
+    /// Like pulls, this must be done under a shared lock on the cache taken
+    /// before the file was added.
+    pub async fn lease_local_file(&self, app_root: &Path, digest: &str) -> Result<()> {
+        let app_key =
+            spin_common::sha256::hex_digest_from_bytes(app_root.to_string_lossy().as_bytes());
+        let dir = self.manifests_dir().join(LOCAL_APPS_DIR).join(&app_key);
+        let path = dir.join(MANIFEST_FILE);
+
+        // Other loads of the app, in this process or others, may be recording
+        // other files.
+        let mut lock = self.entry_lock(&app_key)?;
+        let _guard = lock.write().await?;
+        let mut manifest = std::fs::read(&path)
+            .ok()
+            .and_then(|json| serde_json::from_slice::<LocalAppManifest>(&json).ok())
+            .unwrap_or_default();
+        if !manifest.files.iter().any(|file| file.digest == digest) {
+            manifest.app = app_root.to_owned();
+            manifest.files.push(LocalFile {
+                digest: digest.to_owned(),
+            });
+            create_dir_all(&dir)
+                .await
+                .with_context(|| format!("failed to create {}", quoted_path(&dir)))?;
+            write_atomically(&path, &serde_json::to_vec(&manifest)?)
+                .await
+                .with_context(|| format!("failed to write {}", quoted_path(&path)))?;
+        }
+        self.lease(&dir)
+    }
+
+    /// Removes apps from the cache according to the policy, and then all
+    /// files which no remaining app refers to. Apps which are in use are
+    /// never removed.
+    ///
+    /// This waits for any pulls into the cache to finish.
+    pub async fn gc(&self, policy: &GcPolicy) -> Result<GcReport> {
+        let mut lock = self.lock()?;
+        let _guard = lock.write().await?;
+
+        let now = SystemTime::now();
+        let mut apps = self.cached_apps()?;
+        // Least recently used first
+        apps.sort_by_key(|app| app.last_used);
+        let blobs = self.cached_blobs()?;
+
+        let mut report = GcReport::default();
+        let mut removed = vec![false; apps.len()];
+        if let Some(max_age) = policy.max_age {
+            for (i, app) in apps.iter().enumerate() {
+                let age = now.duration_since(app.last_used).unwrap_or_default();
+                removed[i] = !app.in_use && age > max_age;
+            }
+        }
+
+        if let Some(max_size) = policy.max_size {
+            let mut size = total_size(&apps, &removed, &blobs);
+            for i in 0..apps.len() {
+                if size <= max_size {
+                    break;
+                }
+                if removed[i] || apps[i].in_use {
+                    continue;
+                }
+                removed[i] = true;
+                size = total_size(&apps, &removed, &blobs);
+            }
+        }
+
+        for (app, removed) in apps.iter().zip(&removed) {
+            if *removed {
+                if !policy.dry_run {
+                    std::fs::remove_dir_all(&app.dir).with_context(|| {
+                        format!("failed to remove cached app {}", quoted_path(&app.dir))
+                    })?;
+                }
+                report.removed_apps.push(app.reference.clone());
+                report.freed_bytes += app.size;
+            } else if app.in_use {
+                report.in_use_apps.push(app.reference.clone());
+            }
+        }
+
+        let kept = referenced_files(&apps, &removed);
+        for (path, size) in &blobs {
+            if kept.contains(path) {
+                continue;
+            }
+            if !policy.dry_run {
+                match std::fs::remove_file(path) {
+                    Ok(()) => (),
+                    Err(e) if e.kind() == ErrorKind::NotFound => continue,
+                    Err(e) => {
+                        return Err(e).with_context(|| {
+                            format!("failed to remove cached file {}", quoted_path(path))
+                        })
+                    }
+                }
+            }
+            report.removed_files += 1;
+            report.freed_bytes += size;
+        }
+
+        report.remaining_bytes = total_size(&apps, &removed, &blobs);
+        Ok(report)
+    }
+
+    fn cached_apps(&self) -> Result<Vec<CachedApp>> {
+        let manifests_dir = self.manifests_dir();
+        let mut dirs = vec![];
+        find_manifest_dirs(&manifests_dir, &mut dirs)?;
+
+        dirs.into_iter()
+            .map(|dir| {
+                let manifest = dir.join(MANIFEST_FILE);
+                let metadata = std::fs::metadata(&manifest)
+                    .with_context(|| format!("failed to read {}", quoted_path(&manifest)))?;
+                let mut digests = HashSet::new();
+                let mut size = 0;
+                for entry in std::fs::read_dir(&dir)? {
+                    let entry = entry?;
+                    size += entry.metadata()?.len();
+                    if entry.path().extension().is_some_and(|ext| ext == "json") {
+                        if let Ok(json) = serde_json::from_slice(&std::fs::read(entry.path())?) {
+                            collect_digests(&json, &mut digests);
+                        }
+                    }
+                }
+                let files = digests
+                    .iter()
+                    .flat_map(|digest| [self.wasm_path(digest), self.data_path(digest)])
+                    .collect();
+                Ok(CachedApp {
+                    reference: dir
+                        .strip_prefix(&manifests_dir)
+                        .unwrap_or(&dir)
+                        .to_string_lossy()
+                        .replace('\\', "/"),
+                    last_used: metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+                    size,
+                    in_use: is_leased(&dir),
+                    files,
+                    dir,
+                })
+            })
+            .collect()
+    }
+
+    /// The paths and sizes of the Wasm and data files in the cache.
+    fn cached_blobs(&self) -> Result<Vec<(PathBuf, u64)>> {
+        let mut blobs = vec![];
+        for dir in [self.wasm_dir(), self.data_dir()] {
+            let entries = match std::fs::read_dir(&dir) {
+                Ok(entries) => entries,
+                Err(e) if e.kind() == ErrorKind::NotFound => continue,
+                Err(e) => {
+                    return Err(e).with_context(|| format!("failed to read {}", quoted_path(&dir)))
+                }
+            };
+            for entry in entries {
+                let entry = entry?;
+                let metadata = entry.metadata()?;
+                if metadata.is_file() {
+                    blobs.push((entry.path(), metadata.len()));
+                }
+            }
+        }
+        Ok(blobs)
+    }
+}
+
+/// The files a local app cached, in its directory under `manifests/local`.
+#[derive(Default, serde::Deserialize, serde::Serialize)]
+struct LocalAppManifest {
+    app: PathBuf,
+    files: Vec<LocalFile>,
+}
+
+#[derive(serde::Deserialize, serde::Serialize)]
+struct LocalFile {
+    digest: String,
+}
+
+/// Finds the directories under `dir` which hold an app's manifest.
+fn find_manifest_dirs(dir: &Path, dirs: &mut Vec<PathBuf>) -> Result<()> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e).with_context(|| format!("failed to read {}", quoted_path(dir))),
+    };
+    if dir.join(MANIFEST_FILE).is_file() {
+        dirs.push(dir.to_owned());
+    }
+    for entry in entries {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            find_manifest_dirs(&entry.path(), dirs)?;
+        }
+    }
+    Ok(())
+}
+
+/// Whether a process holds a lease on the app in the given directory.
+fn is_leased(dir: &Path) -> bool {
+    let Ok(file) = File::open(dir.join(LEASE_FILE)) else {
+        return false;
+    };
+    let mut lock = fd_lock::RwLock::new(file);
+    // If it can't be told whether the app is in use, assume it is.
+    let leased = lock.try_write().is_err();
+    leased
+}
+
+/// Collects the values of all the `digest` fields in an OCI manifest or
+/// locked app.
+fn collect_digests(json: &serde_json::Value, digests: &mut HashSet<String>) {
+    match json {
+        serde_json::Value::Object(object) => {
+            for (key, value) in object {
+                match value {
+                    serde_json::Value::String(digest) if key == "digest" => {
+                        digests.insert(digest.clone());
+                    }
+                    value => collect_digests(value, digests),
+                }
+            }
+        }
+        serde_json::Value::Array(values) => {
+            for value in values {
+                collect_digests(value, digests);
+            }
+        }
+        _ => (),
+    }
+}
+
+fn referenced_files(apps: &[CachedApp], removed: &[bool]) -> HashSet<PathBuf> {
+    apps.iter()
+        .zip(removed)
+        .filter(|(_, removed)| !**removed)
+        .flat_map(|(app, _)| app.files.iter().cloned())
+        .collect()
+}
+
+/// The size of the apps that aren't removed and the files they refer to.
+fn total_size(apps: &[CachedApp], removed: &[bool], blobs: &[(PathBuf, u64)]) -> u64 {
+    let kept = referenced_files(apps, removed);
+    let apps_size: u64 = apps
+        .iter()
+        .zip(removed)
+        .filter(|(_, removed)| !**removed)
+        .map(|(app, _)| app.size)
+        .sum();
+    let blobs_size: u64 = blobs
+        .iter()
+        .filter(|(path, _)| kept.contains(path))
+        .map(|(_, size)| size)
+        .sum();
+    apps_size + blobs_size
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Caches an app with the given files, used `age` ago.
+    async fn cache_app(cache: &Cache, tag: &str, files: &[&[u8]], age: Duration) -> PathBuf {
+        let mut layers = vec![];
+        for file in files {
+            let digest = format!(
+                "sha256:{}",
+                spin_common::sha256::hex_digest_from_bytes(file)
+            );
+            cache.write_wasm(file, &digest).await.unwrap();
+            layers.push(serde_json::json!({ "digest": digest, "size": file.len() }));
+        }
+        let dir = cache.manifests_dir().join("example.com/app").join(tag);
+        std::fs::create_dir_all(&dir).unwrap();
+        let manifest = serde_json::json!({ "layers": layers });
+        std::fs::write(dir.join(MANIFEST_FILE), manifest.to_string()).unwrap();
+        File::options()
+            .write(true)
+            .open(dir.join(MANIFEST_FILE))
+            .unwrap()
+            .set_modified(SystemTime::now() - age)
+            .unwrap();
+        dir
+    }
+
+    const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+    #[tokio::test]
+    async fn old_apps_and_their_files_are_removed() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let cache = Cache::new(Some(temp_dir.path().to_owned())).await?;
+        let old = cache_app(&cache, "old", &[b"old", b"shared"], 10 * DAY).await;
+        let new = cache_app(&cache, "new", &[b"new", b"shared"], DAY).await;
+
+        let policy = GcPolicy {
+            max_age: Some(7 * DAY),
+            ..Default::default()
+        };
+        let report = cache.gc(&policy).await?;
+        assert_eq!(vec!["example.com/app/old"], report.removed_apps);
+        assert_eq!(1, report.removed_files);
+        assert!(!old.exists());
+        assert!(new.exists());
+        assert_eq!(2, cache.cached_blobs()?.len());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn least_recently_used_apps_are_removed_to_fit_size() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let cache = Cache::new(Some(temp_dir.path().to_owned())).await?;
+        cache_app(&cache, "v1", &[&[1; 1000]], 3 * DAY).await;
+        cache_app(&cache, "v2", &[&[2; 1000]], 2 * DAY).await;
+        cache_app(&cache, "v3", &[&[3; 1000]], DAY).await;
+
+        let policy = GcPolicy {
+            max_size: Some(2500),
+            dry_run: true,
+            ..Default::default()
+        };
+        let report = cache.gc(&policy).await?;
+        assert_eq!(vec!["example.com/app/v1"], report.removed_apps);
+        // A dry run removes nothing.
+        assert_eq!(3, cache.cached_apps()?.len());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn leased_apps_are_kept() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let cache = Cache::new(Some(temp_dir.path().to_owned())).await?;
+        let dir = cache_app(&cache, "running", &[b"running"], 10 * DAY).await;
+        cache.lease(&dir)?;
+
+        let policy = GcPolicy {
+            max_age: Some(DAY),
+            max_size: Some(0),
+            ..Default::default()
+        };
+        let report = cache.gc(&policy).await?;
+        assert!(report.removed_apps.is_empty());
+        assert_eq!(vec!["example.com/app/running"], report.in_use_apps);
+        assert!(dir.exists());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn files_leased_by_local_apps_are_kept() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let cache = Cache::new(Some(temp_dir.path().to_owned())).await?;
+        let digest = format!(
+            "sha256:{}",
+            spin_common::sha256::hex_digest_from_bytes(b"remote")
+        );
+        cache.write_wasm(b"remote", &digest).await?;
+        cache
+            .lease_local_file(Path::new("/apps/local"), &digest)
+            .await?;
+
+        let policy = GcPolicy {
+            max_age: Some(Duration::ZERO),
+            max_size: Some(0),
+            ..Default::default()
+        };
+        let report = cache.gc(&policy).await?;
+        assert!(report.removed_apps.is_empty());
+        assert_eq!(0, report.removed_files);
+        assert_eq!(1, report.in_use_apps.len());
+        assert!(cache.wasm_file(&digest).is_ok());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn unreferenced_files_are_removed() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let cache = Cache::new(Some(temp_dir.path().to_owned())).await?;
+        cache_app(&cache, "v1", &[b"v1"], DAY).await;
+        cache.write_data(b"orphan", "sha256:orphan").await?;
+
+        let report = cache.gc(&GcPolicy::default()).await?;
+        assert!(report.removed_apps.is_empty());
+        assert_eq!(1, report.removed_files);
+        assert!(cache.data_file("sha256:orphan").is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,217 @@
+//! Coordinating access to the cache between processes.
+//!
+//! Several Spin processes may use the same cache at once, for example when
+//! several apps are started with `spin up` together. Content is published to
+//! the cache by renaming complete files into place, so it is never seen half
+//! written, and file locks keep processes from doing the same work twice:
+//! while one process writes an entry, or pulls an app, others wait for it to
+//! finish and then use what it wrote.
+
+use std::{
+    fs::{File, OpenOptions},
+    io::ErrorKind,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Context, Result};
+use spin_common::{sha256::hex_digest_from_bytes, ui::quoted_path};
+
+use super::{Cache, LOCKS_DIR, LOCK_FILE};
+
+/// Env var setting how many seconds to wait for another process to finish
+/// with a cache entry before giving up.
+const SPIN_CACHE_LOCK_TIMEOUT_OPT: &str = "SPIN_CACHE_LOCK_TIMEOUT";
+const DEFAULT_LOCK_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+const LOCK_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A file lock shared by all the processes using a cache.
+///
+/// Locks are released when their guard is dropped, or when the process
+/// holding them exits.
+#[derive(Debug)]
+pub struct CacheLock {
+    lock: fd_lock::RwLock<File>,
+    path: PathBuf,
+    timeout: Duration,
+}
+
+impl Cache {
+    /// Returns the lock on the whole cache. Content is added to the cache
+    /// under a shared lock, and garbage collected under an exclusive one, so
+    /// that garbage collection doesn't remove content before it is leased.
+    pub fn lock(&self) -> Result<CacheLock> {
+        std::fs::create_dir_all(&self.root)
+            .with_context(|| format!("failed to create cache {}", quoted_path(&self.root)))?;
+        self.open_lock(self.root.join(LOCK_FILE))
+    }
+
+    /// Returns the lock on the entry with the given key, such as a digest or
+    /// a registry reference.
+    pub fn entry_lock(&self, key: impl AsRef<str>) -> Result<CacheLock> {
+        let dir = self.root.join(LOCKS_DIR);
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("failed to create cache locks {}", quoted_path(&dir)))?;
+        // Keys may contain characters which aren't allowed in file names.
+        let name = hex_digest_from_bytes(key.as_ref().as_bytes());
+        self.open_lock(dir.join(name))
+    }
+
+    /// Sets how long to wait for another process to release a lock.
+    pub fn with_lock_timeout(mut self, timeout: Duration) -> Self {
+        self.lock_timeout = timeout;
+        self
+    }
+
+    fn open_lock(&self, path: PathBuf) -> Result<CacheLock> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)
+            .with_context(|| format!("failed to open cache lock {}", quoted_path(&path)))?;
+        Ok(CacheLock {
+            lock: fd_lock::RwLock::new(file),
+            path,
+            timeout: self.lock_timeout,
+        })
+    }
+}
+
+impl CacheLock {
+    /// Takes a shared lock, waiting for any exclusive lock to be released.
+    pub async fn read(&mut self) -> Result<fd_lock::RwLockReadGuard<'_, File>> {
+        self.wait(false).await?;
+        self.lock
+            .read()
+            .with_context(|| format!("failed to lock {}", quoted_path(&self.path)))
+    }
+
+    /// Takes an exclusive lock, waiting for any other lock to be released.
+    pub async fn write(&mut self) -> Result<fd_lock::RwLockWriteGuard<'_, File>> {
+        self.wait(true).await?;
+        self.lock
+            .write()
+            .with_context(|| format!("failed to lock {}", quoted_path(&self.path)))
+    }
+
+    /// Waits until the lock can be taken, or the timeout passes.
+    ///
+    /// Another process may take the lock between this returning and the lock
+    /// being taken, in which case taking it blocks until that process is done.
+    async fn wait(&mut self, exclusive: bool) -> Result<()> {
+        let start = Instant::now();
+        let mut waiting = false;
+        loop {
+            let attempt = if exclusive {
+                self.lock.try_write().map(drop)
+            } else {
+                self.lock.try_read().map(drop)
+            };
+            match attempt {
+                Ok(()) => return Ok(()),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => (),
+                Err(e) => {
+                    return Err(e)
+                        .with_context(|| format!("failed to lock {}", quoted_path(&self.path)))
+                }
+            }
+            if start.elapsed() >= self.timeout {
+                bail!(
+                    "timed out after {}s waiting for another Spin process to release {}",
+                    self.timeout.as_secs(),
+                    quoted_path(&self.path)
+                );
+            }
+            if !waiting {
+                tracing::info!(
+                    "Waiting for another Spin process to release {}",
+                    quoted_path(&self.path)
+                );
+                waiting = true;
+            }
+            tokio::time::sleep(LOCK_POLL_INTERVAL).await;
+        }
+    }
+}
+
+/// Reads the lock timeout from `SPIN_CACHE_LOCK_TIMEOUT`, if it is set.
+pub(super) fn lock_timeout_from_env() -> Duration {
+    let Ok(timeout) = std::env::var(SPIN_CACHE_LOCK_TIMEOUT_OPT) else {
+        return DEFAULT_LOCK_TIMEOUT;
+    };
+    match timeout.trim().parse() {
+        Ok(secs) => Duration::from_secs(secs),
+        Err(_) => {
+            tracing::warn!(
+                "Ignoring {SPIN_CACHE_LOCK_TIMEOUT_OPT}={timeout:?}: expected a number of seconds"
+            );
+            DEFAULT_LOCK_TIMEOUT
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn cache(dir: &std::path::Path) -> Cache {
+        Cache::new(Some(dir.to_owned()))
+            .await
+            .unwrap()
+            .with_lock_timeout(Duration::from_millis(300))
+    }
+
+    #[tokio::test]
+    async fn exclusive_lock_times_out_while_held() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let cache = cache(temp_dir.path()).await;
+
+        let mut held = cache.entry_lock("sha256:abc")?;
+        let _guard = held.write().await?;
+
+        let mut other = cache.entry_lock("sha256:abc")?;
+        let err = other.write().await.unwrap_err();
+        assert!(err.to_string().contains("timed out"), "{err:#}");
+        assert!(other.read().await.is_err());
+
+        // Other entries aren't affected.
+        let mut unrelated = cache.entry_lock("sha256:def")?;
+        unrelated.write().await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn shared_locks_are_shared() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let cache = cache(temp_dir.path()).await;
+
+        let mut first = cache.lock()?;
+        let _first = first.read().await?;
+        let mut second = cache.lock()?;
+        let _second = second.read().await?;
+        let mut exclusive = cache.lock()?;
+        assert!(exclusive.write().await.is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn lock_is_taken_once_released() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let cache = cache(temp_dir.path())
+            .await
+            .with_lock_timeout(Duration::from_secs(5));
+
+        let mut held = cache.entry_lock("ghcr.io/org/app:v1")?;
+        let guard = held.write().await?;
+        let release = async move {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            drop(guard);
+        };
+        let mut other = cache.entry_lock("ghcr.io/org/app:v1")?;
+        let (_, taken) = tokio::join!(release, other.write());
+        taken?;
+        Ok(())
+    }
+}
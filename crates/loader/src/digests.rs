@@ -0,0 +1,163 @@
+//! The digests of an app's source files, recorded so that later loads of the
+//! app only hash the files which have changed since.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use serde::{Deserialize, Serialize};
+use spin_common::sha256::hex_digest_from_file;
+
+use crate::integrity::unix_millis;
+
+/// Where source file digests are recorded, relative to the app directory.
+const DIGESTS_FILE: &str = ".spin/file-digests.json";
+
+/// The digests of source files, by path.
+#[derive(Debug)]
+pub(crate) struct DigestCache {
+    path: PathBuf,
+    files: Mutex<DigestCacheFiles>,
+}
+
+#[derive(Debug, Default)]
+struct DigestCacheFiles {
+    hashed: BTreeMap<PathBuf, HashedFile>,
+    changed: bool,
+}
+
+/// The digest of a file, and the size and modification time it had when it
+/// was hashed.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct HashedFile {
+    size: u64,
+    modified_ms: u64,
+    digest: String,
+}
+
+impl DigestCache {
+    /// Reads the digests recorded for the app in `app_root`. Missing or
+    /// unreadable digests are treated as empty.
+    pub fn load(app_root: &Path) -> Self {
+        let path = app_root.join(DIGESTS_FILE);
+        let hashed = std::fs::read(&path)
+            .ok()
+            .and_then(|json| serde_json::from_slice(&json).ok())
+            .unwrap_or_default();
+        Self {
+            path,
+            files: Mutex::new(DigestCacheFiles {
+                hashed,
+                changed: false,
+            }),
+        }
+    }
+
+    /// The digest of the file at `path`, if it hasn't changed since it was
+    /// hashed.
+    pub fn get(&self, path: &Path, size: u64, modified_ms: u64) -> Option<String> {
+        let files = self.files.lock().unwrap();
+        let hashed = files.hashed.get(path)?;
+        (hashed.size == size && hashed.modified_ms == modified_ms).then(|| hashed.digest.clone())
+    }
+
+    /// The digest of the file at `path`, as `sha256:<hex>`, hashing it only
+    /// if it has changed since it was last hashed.
+    pub fn digest_file(&self, path: &Path) -> std::io::Result<String> {
+        let metadata = std::fs::metadata(path)?;
+        let modified_ms = metadata.modified().ok().and_then(unix_millis);
+        if let Some(modified_ms) = modified_ms {
+            if let Some(digest) = self.get(path, metadata.len(), modified_ms) {
+                return Ok(digest);
+            }
+        }
+        let digest = format!("sha256:{}", hex_digest_from_file(path)?);
+        if let Some(modified_ms) = modified_ms {
+            self.insert(path, metadata.len(), modified_ms, &digest);
+        }
+        Ok(digest)
+    }
+
+    /// Records the digest of the file at `path`.
+    pub fn insert(&self, path: &Path, size: u64, modified_ms: u64, digest: &str) {
+        let hashed = HashedFile {
+            size,
+            modified_ms,
+            digest: digest.to_owned(),
+        };
+        let mut files = self.files.lock().unwrap();
+        if files.hashed.get(path) != Some(&hashed) {
+            files.hashed.insert(path.to_owned(), hashed);
+            files.changed = true;
+        }
+    }
+
+    /// Saves the digests, if any were recorded. The digests only save
+    /// hashing, so failing to save them is logged rather than returned.
+    pub fn save(&self) {
+        let mut files = self.files.lock().unwrap();
+        if !files.changed {
+            return;
+        }
+        // Forget files which have been deleted.
+        files.hashed.retain(|path, _| path.exists());
+        let result = serde_json::to_vec(&files.hashed)
+            .map_err(std::io::Error::from)
+            .and_then(|json| {
+                if let Some(dir) = self.path.parent() {
+                    std::fs::create_dir_all(dir)?;
+                }
+                std::fs::write(&self.path, json)
+            });
+        match result {
+            Ok(()) => files.changed = false,
+            Err(err) => tracing::debug!("Failed to save file digests {:?}: {err}", self.path),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn digests_are_kept_until_files_change() {
+        let app_root = tempfile::tempdir().unwrap();
+        let file = app_root.path().join("a.txt");
+        std::fs::write(&file, "spin").unwrap();
+
+        let digests = DigestCache::load(app_root.path());
+        assert_eq!(None, digests.get(&file, 4, 1000));
+        digests.insert(&file, 4, 1000, "sha256:a5a2");
+        digests.save();
+
+        let digests = DigestCache::load(app_root.path());
+        assert_eq!(Some("sha256:a5a2".into()), digests.get(&file, 4, 1000));
+        assert_eq!(None, digests.get(&file, 4, 2000));
+        assert_eq!(None, digests.get(&file, 5, 1000));
+    }
+
+    #[test]
+    fn file_digests_are_cached() {
+        let app_root = tempfile::tempdir().unwrap();
+        let file = app_root.path().join("a.wasm");
+        std::fs::write(&file, "spin").unwrap();
+
+        let digests = DigestCache::load(app_root.path());
+        let digest = digests.digest_file(&file).unwrap();
+        assert_eq!(
+            "sha256:a5a2729ffa0eeacc15323a9168807c72d18d1cb375dbde899c44d6803dad2b19",
+            digest
+        );
+        digests.save();
+
+        // The recorded digest is used while the file is unchanged.
+        let metadata = std::fs::metadata(&file).unwrap();
+        let modified_ms = unix_millis(metadata.modified().unwrap()).unwrap();
+        let digests = DigestCache::load(app_root.path());
+        digests.insert(&file, metadata.len(), modified_ms, "sha256:recorded");
+        assert_eq!("sha256:recorded", digests.digest_file(&file).unwrap());
+    }
+}
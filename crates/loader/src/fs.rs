@@ -19,9 +19,25 @@ mod io {
         tokio::fs::copy(from, to).await.map_err(Into::into)
     }
 
+    pub async fn rename(from: &Path, to: &Path) -> Result<()> {
+        tokio::fs::rename(from, to).await?;
+        Ok(())
+    }
+
     pub async fn metadata(path: &Path) -> Result<std::fs::Metadata> {
         tokio::fs::metadata(path).await.map_err(Into::into)
     }
+
+    pub async fn link_or_copy(from: &Path, to: &Path) -> Result<()> {
+        match tokio::fs::remove_file(to).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+            _ => {}
+        }
+        if tokio::fs::hard_link(from, to).await.is_err() {
+            tokio::fs::copy(from, to).await?;
+        }
+        Ok(())
+    }
 }
 
 #[cfg(not(feature = "async-io"))]
@@ -42,9 +58,25 @@ mod io {
         Ok(std::fs::copy(from, to)?)
     }
 
+    pub async fn rename(from: &Path, to: &Path) -> Result<()> {
+        std::fs::rename(from, to)?;
+        Ok(())
+    }
+
     pub async fn metadata(path: &Path) -> Result<std::fs::Metadata> {
         Ok(std::fs::metadata(path)?)
     }
+
+    pub async fn link_or_copy(from: &Path, to: &Path) -> Result<()> {
+        match std::fs::remove_file(to) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+            _ => {}
+        }
+        if std::fs::hard_link(from, to).is_err() {
+            std::fs::copy(from, to)?;
+        }
+        Ok(())
+    }
 }
 
 pub use io::*;
@@ -0,0 +1,165 @@
+//! Recording the files in mounted directories, so that the runtime can verify
+//! that they haven't changed since the app was loaded.
+
+use std::{
+    collections::HashMap,
+    fs::Metadata,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Context, Result};
+use spin_common::{sha256::hex_digest_from_file, ui::quoted_path};
+use spin_locked_app::locked::{FileIntegrity, LockedMap};
+
+use crate::sync::SyncManifest;
+
+/// Records the digest, size and modification time of each file in `dir` and
+/// its subdirectories, by path relative to `dir` with forward slashes.
+pub fn directory_integrity(dir: &Path) -> Result<LockedMap<FileIntegrity>> {
+    directory_integrity_with(dir, &())
+}
+
+/// Like [`directory_integrity`], but uses the given digests, by file path,
+/// rather than hashing those files. Use this for files written from content
+/// whose digest is already known.
+pub fn directory_integrity_with_digests(
+    dir: &Path,
+    digests: &HashMap<PathBuf, String>,
+) -> Result<LockedMap<FileIntegrity>> {
+    directory_integrity_with(dir, digests)
+}
+
+/// Like [`directory_integrity`], but only hashes the copies in `dir` whose
+/// digests weren't recorded by an incremental copy, or by an earlier load.
+pub(crate) fn synced_directory_integrity(
+    dir: &Path,
+    sync: Option<&SyncManifest>,
+) -> Result<LockedMap<FileIntegrity>> {
+    match sync {
+        Some(sync) => directory_integrity_with(dir, sync),
+        None => directory_integrity(dir),
+    }
+}
+
+/// The digests of files which needn't be hashed again.
+pub(crate) trait KnownDigests {
+    /// The digest of the file at `path`, as `sha256:<hex>`, if it is known.
+    fn digest(&self, path: &Path, metadata: &Metadata) -> Option<String>;
+
+    /// Records the digest of a file which had to be hashed.
+    fn hashed(&self, path: &Path, digest: &str) {
+        let _ = (path, digest);
+    }
+}
+
+impl KnownDigests for () {
+    fn digest(&self, _path: &Path, _metadata: &Metadata) -> Option<String> {
+        None
+    }
+}
+
+impl KnownDigests for HashMap<PathBuf, String> {
+    fn digest(&self, path: &Path, _metadata: &Metadata) -> Option<String> {
+        self.get(path).cloned()
+    }
+}
+
+fn directory_integrity_with(
+    dir: &Path,
+    known: &dyn KnownDigests,
+) -> Result<LockedMap<FileIntegrity>> {
+    let mut integrity = LockedMap::new();
+    add_directory_integrity(dir, "", known, &mut integrity)
+        .with_context(|| format!("Failed to record the files in {}", quoted_path(dir)))?;
+    Ok(integrity)
+}
+
+fn add_directory_integrity(
+    dir: &Path,
+    prefix: &str,
+    known: &dyn KnownDigests,
+    integrity: &mut LockedMap<FileIntegrity>,
+) -> Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let name = name
+            .to_str()
+            .with_context(|| format!("file name {name:?} is not valid UTF-8"))?;
+        let rel_path = format!("{prefix}{name}");
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            add_directory_integrity(&entry.path(), &format!("{rel_path}/"), known, integrity)?;
+        } else if file_type.is_file() {
+            let path = entry.path();
+            let metadata = entry.metadata()?;
+            let digest = match known.digest(&path, &metadata) {
+                Some(digest) => digest,
+                None => {
+                    let digest = format!("sha256:{}", hex_digest_from_file(&path)?);
+                    known.hashed(&path, &digest);
+                    digest
+                }
+            };
+            integrity.insert(
+                rel_path,
+                FileIntegrity {
+                    digest,
+                    size: metadata.len(),
+                    modified_ms: metadata.modified().ok().and_then(unix_millis),
+                },
+            );
+        }
+    }
+    Ok(())
+}
+
+/// The given time in milliseconds since the Unix epoch.
+pub fn unix_millis(time: SystemTime) -> Option<u64> {
+    let since_epoch = time.duration_since(UNIX_EPOCH).ok()?;
+    since_epoch.as_millis().try_into().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn files_in_subdirectories_are_recorded() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        std::fs::write(dir.path().join("a.txt"), "spin")?;
+        std::fs::create_dir(dir.path().join("sub"))?;
+        std::fs::write(dir.path().join("sub/b.txt"), "")?;
+
+        let integrity = directory_integrity(dir.path())?;
+        assert_eq!(
+            vec!["a.txt", "sub/b.txt"],
+            integrity.keys().collect::<Vec<_>>()
+        );
+        let a = &integrity["a.txt"];
+        assert_eq!(
+            "sha256:a5a2729ffa0eeacc15323a9168807c72d18d1cb375dbde899c44d6803dad2b19",
+            a.digest
+        );
+        assert_eq!(4, a.size);
+        assert!(a.modified_ms.is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn known_digests_are_not_recomputed() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        std::fs::write(dir.path().join("a.txt"), "spin")?;
+        std::fs::write(dir.path().join("b.txt"), "spin")?;
+
+        let digests = HashMap::from([(dir.path().join("a.txt"), "sha256:known".to_owned())]);
+        let integrity = directory_integrity_with_digests(dir.path(), &digests)?;
+        assert_eq!("sha256:known", integrity["a.txt"].digest);
+        assert_eq!(
+            "sha256:a5a2729ffa0eeacc15323a9168807c72d18d1cb375dbde899c44d6803dad2b19",
+            integrity["b.txt"].digest
+        );
+        Ok(())
+    }
+}
@@ -14,14 +14,19 @@ use std::path::{Path, PathBuf};
 
 use anyhow::{Context, Result};
 use local::LocalLoader;
-use spin_common::paths::parent_dir;
+use spin_common::{diagnostics::Diagnostics, paths::parent_dir};
 use spin_locked_app::locked::LockedApp;
 
+pub mod bundle;
 pub mod cache;
+mod digests;
 mod fs;
 #[cfg(feature = "async-io")]
 mod http;
+pub mod integrity;
 mod local;
+pub mod lockfile;
+mod sync;
 
 /// Maximum number of files to copy (or download) concurrently
 pub(crate) const MAX_FILE_LOADING_CONCURRENCY: usize = 16;
@@ -40,6 +45,84 @@ pub async fn from_file(
     loader.load_file(path).await
 }
 
+/// Like [`from_file`], but components with a [targeted
+/// source](spin_manifest::schema::v2::ComponentSource::Targeted) use their
+/// source for `source_target` rather than for the default target.
+pub async fn from_file_for_target(
+    manifest_path: impl AsRef<Path>,
+    files_mount_strategy: FilesMountStrategy,
+    cache_root: Option<PathBuf>,
+    source_target: Option<String>,
+) -> Result<LockedApp> {
+    let options = ManifestLoadOptions {
+        source_target,
+        ..Default::default()
+    };
+    from_file_with_options(manifest_path, files_mount_strategy, cache_root, options).await
+}
+
+/// Like [`from_file_for_target`], but also writes or verifies the app's
+/// [lockfile](lockfile::Lockfile) according to `lockfile_mode`.
+pub async fn from_file_with_lockfile(
+    manifest_path: impl AsRef<Path>,
+    files_mount_strategy: FilesMountStrategy,
+    cache_root: Option<PathBuf>,
+    source_target: Option<String>,
+    lockfile_mode: lockfile::LockfileMode,
+) -> Result<LockedApp> {
+    let options = ManifestLoadOptions {
+        source_target,
+        lockfile_mode,
+        ..Default::default()
+    };
+    from_file_with_options(manifest_path, files_mount_strategy, cache_root, options).await
+}
+
+/// Like [`from_file`], with the given [`ManifestLoadOptions`].
+pub async fn from_file_with_options(
+    manifest_path: impl AsRef<Path>,
+    files_mount_strategy: FilesMountStrategy,
+    cache_root: Option<PathBuf>,
+    options: ManifestLoadOptions,
+) -> Result<LockedApp> {
+    let path = manifest_path.as_ref();
+    let app_root = parent_dir(path).context("manifest path has no parent directory")?;
+    let loader = LocalLoader::new(&app_root, files_mount_strategy, cache_root)
+        .await?
+        .with_source_target(options.source_target.clone())
+        .with_features(options.features.clone())
+        .with_environment(options.environment.clone())
+        .with_diagnostics(options.diagnostics.clone());
+    let locked = loader.load_file(path).await?;
+    lockfile::apply(
+        options.lockfile_mode,
+        path,
+        options.source_target.as_deref(),
+        &options.features,
+        options.environment.as_deref(),
+        &locked,
+    )?;
+    Ok(locked)
+}
+
+/// Options for loading a Spin app from a manifest file.
+#[derive(Debug, Default)]
+pub struct ManifestLoadOptions {
+    /// The target whose source to use for components with a [targeted
+    /// source](spin_manifest::schema::v2::ComponentSource::Targeted). If
+    /// `None`, the default target is used.
+    pub source_target: Option<String>,
+    /// The component features to enable.
+    pub features: Vec<String>,
+    /// What to do with the app's [lockfile](lockfile::Lockfile).
+    pub lockfile_mode: lockfile::LockfileMode,
+    /// The environment whose [overlay](spin_manifest::overlay) to apply to the
+    /// manifest, if any.
+    pub environment: Option<String>,
+    /// Where diagnostics about the manifest are reported.
+    pub diagnostics: Diagnostics,
+}
+
 /// Load a Spin locked app from a standalone Wasm file.
 pub async fn from_wasm_file(wasm_path: impl AsRef<Path>) -> Result<LockedApp> {
     let app_root = std::env::current_dir()?;
@@ -57,6 +140,13 @@ pub enum FilesMountStrategy {
     /// supports mounting full directories; mounting single files, glob
     /// patterns, and `exclude_files` are not supported.
     Direct,
+    /// Mount full directories directly from their source, as with `Direct`,
+    /// and mount single files, glob patterns, and directories with
+    /// `exclude_files` through overlay directories under the given root.
+    /// Overlays hard-link the files they include where possible, so changes
+    /// made in place to those files are visible without reloading, but files
+    /// added later are not.
+    Overlay(PathBuf),
 }
 
 fn single_file_manifest(
@@ -3,21 +3,32 @@ use std::path::{Path, PathBuf};
 use anyhow::{anyhow, bail, ensure, Context, Result};
 use futures::{future::try_join_all, StreamExt};
 use reqwest::Url;
-use spin_common::{paths::parent_dir, sloth, ui::quoted_path};
+use spin_common::{
+    build_info::BuildInfoFile,
+    diagnostics::{Diagnostic, Diagnostics},
+    paths::parent_dir,
+    sloth,
+    ui::quoted_path,
+};
 use spin_factor_outbound_networking::SERVICE_CHAINING_DOMAIN_SUFFIX;
 use spin_locked_app::{
     locked::{
-        self, ContentPath, ContentRef, LockedApp, LockedComponent, LockedComponentDependency,
-        LockedComponentSource, LockedTrigger,
+        self, ContentPath, ContentRef, FileIntegrity, LockedApp, LockedComponent,
+        LockedComponentDependency, LockedComponentSource, LockedMap, LockedTrigger,
     },
     values::{ValuesMap, ValuesMapBuilder},
 };
 use spin_manifest::schema::v2::{self, AppManifest, KebabId, WasiFilesMount};
 use spin_serde::DependencyName;
 use std::collections::BTreeMap;
+use std::sync::Arc;
 use tokio::{io::AsyncWriteExt, sync::Semaphore};
 
-use crate::{cache::Cache, FilesMountStrategy};
+use crate::{cache::Cache, digests::DigestCache, sync::SyncManifest, FilesMountStrategy};
+
+/// The directory, within the files mount root, of the copies of mounts that
+/// are mounted separately from the rest of their component's files.
+const OWN_DIR_MOUNTS_DIR: &str = ".mounts";
 
 #[derive(Debug)]
 pub struct LocalLoader {
@@ -25,6 +36,16 @@ pub struct LocalLoader {
     files_mount_strategy: FilesMountStrategy,
     cache: Cache,
     file_loading_permits: Semaphore,
+    source_target: Option<String>,
+    features: Vec<String>,
+    environment: Option<String>,
+    // The files copied by the previous load, if the files are copied.
+    sync: Option<SyncManifest>,
+    // The build info recorded by `spin build`.
+    build_info: BuildInfoFile,
+    // The digests of the app's source files, as of when they were last hashed.
+    source_digests: Arc<DigestCache>,
+    diagnostics: Diagnostics,
 }
 
 impl LocalLoader {
@@ -35,26 +56,68 @@ impl LocalLoader {
     ) -> Result<Self> {
         let app_root = safe_canonicalize(app_root)
             .with_context(|| format!("Invalid manifest dir `{}`", app_root.display()))?;
+        let source_digests = Arc::new(DigestCache::load(&app_root));
+        let sync = match &files_mount_strategy {
+            FilesMountStrategy::Copy(files_mount_root) => {
+                Some(SyncManifest::load(files_mount_root, source_digests.clone()))
+            }
+            _ => None,
+        };
         Ok(Self {
             app_root,
             files_mount_strategy,
             cache: Cache::new(cache_root).await?,
             // Limit concurrency to avoid hitting system resource limits
             file_loading_permits: Semaphore::new(crate::MAX_FILE_LOADING_CONCURRENCY),
+            source_target: None,
+            features: vec![],
+            environment: None,
+            sync,
+            build_info: BuildInfoFile::load(&app_root),
+            source_digests,
+            diagnostics: Diagnostics::new(),
         })
     }
 
+    // Select the given target of components with targeted sources, instead of
+    // the default target.
+    pub fn with_source_target(mut self, source_target: Option<String>) -> Self {
+        self.source_target = source_target;
+        self
+    }
+
+    // Apply the settings of the given component features.
+    pub fn with_features(mut self, features: Vec<String>) -> Self {
+        self.features = features;
+        self
+    }
+
+    // Patch the manifest with its overlay for the given environment.
+    pub fn with_environment(mut self, environment: Option<String>) -> Self {
+        self.environment = environment;
+        self
+    }
+
+    // Report diagnostics about the manifest to the given handle.
+    pub fn with_diagnostics(mut self, diagnostics: Diagnostics) -> Self {
+        self.diagnostics = diagnostics;
+        self
+    }
+
     // Load the manifest file (spin.toml) at the given path into a LockedApp,
     // preparing all its content for execution.
     pub async fn load_file(&self, path: impl AsRef<Path>) -> Result<LockedApp> {
         // Parse manifest
         let path = path.as_ref();
-        let manifest = spin_manifest::manifest_from_file(path).with_context(|| {
+        let mut manifest = spin_manifest::manifest_from_file(path).with_context(|| {
             format!(
                 "Failed to read Spin app manifest from {}",
                 quoted_path(path)
             )
         })?;
+        if let Some(environment) = &self.environment {
+            spin_manifest::overlay::apply_environment(&mut manifest, path, environment)?;
+        }
         let mut locked = self
             .load_manifest(manifest)
             .await
@@ -71,8 +134,15 @@ impl LocalLoader {
     // Load the given manifest into a LockedApp, ready for execution.
     pub(crate) async fn load_manifest(&self, mut manifest: AppManifest) -> Result<LockedApp> {
         spin_manifest::normalize::normalize_manifest(&mut manifest);
+        spin_manifest::normalize::select_source_targets(
+            &mut manifest,
+            self.source_target.as_deref(),
+        )?;
+        spin_manifest::normalize::select_features(&mut manifest, &self.features)?;
 
         manifest.validate_dependencies()?;
+        validate_lifecycle_hooks(&manifest)?;
+        validate_middleware(&manifest)?;
 
         let AppManifest {
             spin_manifest_version: _,
@@ -82,7 +152,10 @@ impl LocalLoader {
             components,
         } = manifest;
 
-        let metadata = locked_metadata(application, triggers.keys().cloned())?;
+        let sqlite_migrations = self
+            .load_sqlite_migrations(&application.sqlite_migrations)
+            .await?;
+        let metadata = locked_metadata(application, sqlite_migrations, triggers.keys().cloned())?;
 
         let app_requires_service_chaining = components.values().any(requires_service_chaining);
 
@@ -111,6 +184,11 @@ impl LocalLoader {
         }))
         .await?;
 
+        if let Some(sync) = &self.sync {
+            sync.finish()?;
+        }
+        self.source_digests.save();
+
         let mut host_requirements = ValuesMapBuilder::new();
         if app_requires_service_chaining {
             host_requirements.string(
@@ -138,6 +216,42 @@ impl LocalLoader {
         })
     }
 
+    // Read the SQL files in each database's migrations directory, in order of
+    // file name.
+    async fn load_sqlite_migrations(
+        &self,
+        dirs: &v2::Map<String, String>,
+    ) -> Result<BTreeMap<String, Vec<SqliteMigration>>> {
+        let mut migrations = BTreeMap::new();
+        for (label, dir) in dirs {
+            let dir_path = self.app_root.join(dir);
+            let mut entries = tokio::fs::read_dir(&dir_path).await.with_context(|| {
+                format!(
+                    "Failed to read migrations directory {} for SQLite database '{label}'",
+                    quoted_path(&dir_path)
+                )
+            })?;
+            let mut paths = vec![];
+            while let Some(entry) = entries.next_entry().await? {
+                let path = entry.path();
+                if path.is_file() && path.extension().is_some_and(|ext| ext == "sql") {
+                    paths.push(path);
+                }
+            }
+            paths.sort();
+            let mut label_migrations = Vec::with_capacity(paths.len());
+            for path in paths {
+                let sql = tokio::fs::read_to_string(&path)
+                    .await
+                    .with_context(|| format!("Failed to read migration {}", quoted_path(&path)))?;
+                let name = path.file_name().unwrap().to_string_lossy().into_owned();
+                label_migrations.push(SqliteMigration { name, sql });
+            }
+            migrations.insert(label.clone(), label_migrations);
+        }
+        Ok(migrations)
+    }
+
     // Load the given component into a LockedComponent, ready for execution.
     async fn load_component(
         &self,
@@ -147,16 +261,48 @@ impl LocalLoader {
         let allowed_outbound_hosts = component
             .normalized_allowed_outbound_hosts()
             .context("`allowed_http_hosts` is malformed")?;
+        if !component.allowed_http_hosts.is_empty() {
+            self.diagnostics.report(
+                Diagnostic::new(
+                    "deprecated-allowed-http-hosts",
+                    "uses the deprecated field `allowed_http_hosts`",
+                )
+                .component(id.as_ref())
+                .suggestion(format!(
+                    "Replace `allowed_http_hosts` with `allowed_outbound_hosts = {allowed_outbound_hosts:?}`"
+                )),
+            );
+        }
         spin_factor_outbound_networking::AllowedHostsConfig::validate(&allowed_outbound_hosts)
             .context("`allowed_outbound_hosts` is malformed")?;
 
+        // Build info is only locked for local sources, which `spin build` can
+        // have built.
+        let build_info = match &component.source {
+            v2::ComponentSource::Local(path) => self.build_info.get(id.as_ref(), || {
+                let digest = self
+                    .source_digests
+                    .digest_file(&self.app_root.join(path))
+                    .ok()?;
+                digest.strip_prefix("sha256:").map(ToOwned::to_owned)
+            }),
+            _ => None,
+        };
+
         let metadata = ValuesMapBuilder::new()
             .string("description", component.description)
             .string_array("allowed_outbound_hosts", allowed_outbound_hosts)
             .string_array("key_value_stores", component.key_value_stores)
+            .string_option("key_value_namespace", component.key_value_namespace)
             .string_array("databases", component.sqlite_databases)
             .string_array("ai_models", component.ai_models)
+            .serializable("precompile", component.precompile)?
+            .serializable("concurrency", component.concurrency)?
+            .serializable("instance", component.instance)?
             .serializable("build", component.build)?
+            .serializable("build_info", build_info)?
+            .string_array("middleware", component.middleware)
+            .string_array("environment_passthrough", component.environment_passthrough)
             .take();
 
         let source = self
@@ -174,23 +320,50 @@ impl LocalLoader {
 
         let env = component.environment.into_iter().collect();
 
+        for mount in &component.files {
+            if let (writable, Some(_)) = mount_options(mount) {
+                ensure!(
+                    writable == Some(true),
+                    "Component {id}: files mounts with `max_bytes` must also set `writable = true`"
+                );
+            }
+        }
+
         let files = if component.files.is_empty() {
             vec![]
         } else {
             match &self.files_mount_strategy {
                 FilesMountStrategy::Copy(files_mount_root) => {
-                    let component_mount_root = files_mount_root.join(id.as_ref());
-                    // Copy mounted files into component mount root, concurrently
-                    try_join_all(component.files.iter().map(|f| {
-                        self.copy_file_mounts(f, &component_mount_root, &component.exclude_files)
-                    }))
-                    .await?;
-
-                    // All component files (copies) are in `component_mount_root` now
-                    vec![ContentPath {
-                        content: file_content_ref(component_mount_root)?,
-                        path: "/".into(),
-                    }]
+                    self.copy_component_files(
+                        id,
+                        component.files.iter().enumerate().collect(),
+                        &component.exclude_files,
+                        files_mount_root,
+                    )
+                    .await?
+                }
+                FilesMountStrategy::Overlay(overlay_root) => {
+                    // Full directories are mounted directly; narrower mounts are
+                    // mounted through an overlay of the files they include.
+                    let mut files = vec![];
+                    let mut overlaid = vec![];
+                    for (index, mount) in component.files.iter().enumerate() {
+                        if component.exclude_files.is_empty() && self.is_directory_mount(mount) {
+                            files.push(self.resolve_direct_mount(mount).await?);
+                        } else {
+                            overlaid.push((index, mount));
+                        }
+                    }
+                    files.extend(
+                        self.copy_component_files(
+                            id,
+                            overlaid,
+                            &component.exclude_files,
+                            overlay_root,
+                        )
+                        .await?,
+                    );
+                    files
                 }
                 FilesMountStrategy::Direct => {
                     ensure!(
@@ -360,6 +533,9 @@ impl LocalLoader {
                 self.load_registry_source(registry.as_ref(), &package, &version_req)
                     .await?
             }
+            v2::ComponentSource::Targeted(_) => {
+                unreachable!("source target should have already been selected")
+            }
         };
         Ok(LockedComponentSource {
             content_type: "application/wasm".into(),
@@ -374,6 +550,10 @@ impl LocalLoader {
             digest.starts_with("sha256:"),
             "invalid `digest` {digest:?}; must start with 'sha256:'"
         );
+        // Keep garbage collection out of the cache until the file is leased
+        let mut cache_lock = self.cache.lock()?;
+        let _cache_guard = cache_lock.read().await?;
+
         let path = if let Ok(cached_path) = self.cache.wasm_file(digest) {
             cached_path
         } else {
@@ -386,6 +566,7 @@ impl LocalLoader {
                 .with_context(|| format!("Error fetching source URL {url:?}"))?;
             dest
         };
+        self.lease_cached_file(digest).await?;
         file_content_ref(path)
     }
 
@@ -427,6 +608,9 @@ impl LocalLoader {
             wasm_pkg_client::ContentDigest::Sha256 { hex } => format!("sha256:{hex}"),
         };
 
+        let mut cache_lock = self.cache.lock()?;
+        let _cache_guard = cache_lock.read().await?;
+
         let path = if let Ok(cached_path) = self.cache.wasm_file(&digest) {
             cached_path
         } else {
@@ -445,10 +629,67 @@ impl LocalLoader {
 
             dest
         };
+        self.lease_cached_file(&digest).await?;
 
         file_content_ref(path)
     }
 
+    // Protect a file the app uses from the cache from garbage collection.
+    async fn lease_cached_file(&self, digest: &str) -> Result<()> {
+        self.cache
+            .lease_local_file(&self.app_root, digest)
+            .await
+            .context("Failed to protect cached Wasm from cache garbage collection")
+    }
+
+    // Copy the given (indexed) files mounts of a component under
+    // `files_mount_root`, returning the mounts of the copies.
+    async fn copy_component_files(
+        &self,
+        id: &KebabId,
+        mounts: Vec<(usize, &WasiFilesMount)>,
+        exclude_files: &[String],
+        files_mount_root: &Path,
+    ) -> Result<Vec<ContentPath>> {
+        let component_mount_root = files_mount_root.join(id.as_ref());
+        // Mounts with their own `writable` or `max_bytes` are copied to
+        // their own directories so that they can be mounted separately.
+        let (own_dirs, shared): (Vec<_>, Vec<_>) = mounts
+            .into_iter()
+            .partition(|(_, f)| mount_options(f) != (None, None));
+
+        // Copy mounted files into component mount root, concurrently
+        try_join_all(
+            shared
+                .iter()
+                .map(|(_, f)| self.copy_file_mounts(f, &component_mount_root, exclude_files)),
+        )
+        .await?;
+
+        // All other component files (copies) are in `component_mount_root` now
+        let mut files = vec![];
+        if !shared.is_empty() {
+            files.push(ContentPath {
+                integrity: self.copied_mount_integrity(&component_mount_root, None)?,
+                content: file_content_ref(component_mount_root)?,
+                path: "/".into(),
+                writable: None,
+                max_bytes: None,
+            });
+        }
+        for (index, mount) in own_dirs {
+            let mount_root = files_mount_root
+                .join(OWN_DIR_MOUNTS_DIR)
+                .join(id.as_ref())
+                .join(index.to_string());
+            files.push(
+                self.copy_own_dir_mount(mount, &mount_root, exclude_files)
+                    .await?,
+            );
+        }
+        Ok(files)
+    }
+
     // Copy content(s) from the given `mount`
     async fn copy_file_mounts(
         &self,
@@ -464,6 +705,7 @@ impl LocalLoader {
             WasiFilesMount::Placement {
                 source,
                 destination,
+                ..
             } => {
                 let src = Path::new(source);
                 let dest = dest_root.join(destination.trim_start_matches('/'));
@@ -473,6 +715,68 @@ impl LocalLoader {
         }
     }
 
+    // Copy a directory mount with its own options into `dest_root`, returning
+    // the mount of that directory.
+    async fn copy_own_dir_mount(
+        &self,
+        mount: &WasiFilesMount,
+        dest_root: &Path,
+        exclude_files: &[String],
+    ) -> Result<ContentPath> {
+        let WasiFilesMount::Placement {
+            source,
+            destination,
+            writable,
+            max_bytes,
+        } = mount
+        else {
+            unreachable!("only placement mounts have options");
+        };
+        let src_path = self.app_root.join(source);
+        if !src_path.is_dir() {
+            bail!("Only directory mounts can set `writable` or `max_bytes`; {source:?} is not a directory.");
+        }
+        let pattern = src_path.join("**/*");
+        self.copy_glob(&pattern, &src_path, dest_root, exclude_files)
+            .await?;
+        // The source directory may be empty
+        crate::fs::create_dir_all(dest_root)
+            .await
+            .with_context(|| {
+                format!(
+                    "Failed to create mount directory {}",
+                    quoted_path(dest_root)
+                )
+            })?;
+        Ok(ContentPath {
+            content: file_content_ref(dest_root)?,
+            path: destination.into(),
+            writable: *writable,
+            max_bytes: *max_bytes,
+            integrity: self.copied_mount_integrity(dest_root, *writable)?,
+        })
+    }
+
+    // Remove stale copies from a mount directory, and record the files
+    // copied to it so that they can be verified at runtime, unless they are
+    // expected to change: overlays link to the source files, and the guest
+    // may write to writable mounts.
+    fn copied_mount_integrity(
+        &self,
+        dir: &Path,
+        writable: Option<bool>,
+    ) -> Result<LockedMap<FileIntegrity>> {
+        if let Some(sync) = &self.sync {
+            sync.prune(dir);
+        }
+        if matches!(self.files_mount_strategy, FilesMountStrategy::Overlay(_))
+            || writable == Some(true)
+        {
+            return Ok(Default::default());
+        }
+        crate::integrity::synced_directory_integrity(dir, self.sync.as_ref())
+    }
+
     // Copy files matching glob pattern or single file/directory path.
     async fn copy_glob_or_path(
         &self,
@@ -581,11 +885,24 @@ impl LocalLoader {
     async fn copy_single_file(&self, src: &Path, dest: &Path, guest_dest: &str) -> Result<()> {
         // Sanity checks: src is in app_root...
         src.strip_prefix(&self.app_root)?;
-        // ...and dest is in the Copy root.
-        if let FilesMountStrategy::Copy(files_mount_root) = &self.files_mount_strategy {
-            dest.strip_prefix(files_mount_root)?;
-        } else {
-            unreachable!();
+        // ...and dest is in the Copy or Overlay root.
+        let is_overlay = match &self.files_mount_strategy {
+            FilesMountStrategy::Copy(files_mount_root) => {
+                dest.strip_prefix(files_mount_root)?;
+                false
+            }
+            FilesMountStrategy::Overlay(overlay_root) => {
+                dest.strip_prefix(overlay_root)?;
+                true
+            }
+            FilesMountStrategy::Direct => unreachable!(),
+        };
+
+        if let Some(sync) = &self.sync {
+            if sync.is_current(src, dest) {
+                tracing::debug!("Skipped copying unchanged {src:?} to {dest:?}");
+                return Ok(());
+            }
         }
 
         let _loading_permit = self.file_loading_permits.acquire().await?;
@@ -598,9 +915,20 @@ impl LocalLoader {
                     quoted_path(&dest_parent)
                 )
             })?;
-        crate::fs::copy(src, dest)
-            .await
-            .or_else(|e| Self::failed_to_copy_single_file_error(src, dest, guest_dest, e))?;
+        if is_overlay {
+            // Overlays link rather than copy where they can, so that the
+            // guest sees changes to the source files.
+            crate::fs::link_or_copy(src, dest)
+                .await
+                .or_else(|e| Self::failed_to_copy_single_file_error(src, dest, guest_dest, e))?;
+        } else {
+            crate::fs::copy(src, dest)
+                .await
+                .or_else(|e| Self::failed_to_copy_single_file_error(src, dest, guest_dest, e))?;
+        }
+        if let Some(sync) = &self.sync {
+            sync.record(src, dest)?;
+        }
         tracing::debug!("Copied {src:?} to {dest:?}");
         Ok(())
     }
@@ -635,6 +963,16 @@ impl LocalLoader {
         guest_path.ends_with('/') || guest_path.ends_with('.') || guest_path.ends_with("..")
     }
 
+    // Whether the given mount is of a full directory, which can be mounted
+    // directly.
+    fn is_directory_mount(&self, mount: &WasiFilesMount) -> bool {
+        let src = match mount {
+            WasiFilesMount::Pattern(pattern) => pattern,
+            WasiFilesMount::Placement { source, .. } => source,
+        };
+        self.app_root.join(src).is_dir()
+    }
+
     // Resolve the given direct mount directory, checking that it is valid for
     // direct mounting and returning its canonicalized source path.
     async fn resolve_direct_mount(&self, mount: &WasiFilesMount) -> Result<ContentPath> {
@@ -643,15 +981,20 @@ impl LocalLoader {
             WasiFilesMount::Placement {
                 source,
                 destination,
+                ..
             } => (source, destination),
         };
+        let (writable, max_bytes) = mount_options(mount);
         let path = self.app_root.join(src);
         if !path.is_dir() {
             bail!("Only directory mounts are supported with `--direct-mounts`; {src:?} is not a directory.");
         }
         Ok(ContentPath {
-            content: file_content_ref(src)?,
+            content: file_content_ref(path)?,
             path: dest.into(),
+            writable,
+            max_bytes,
+            integrity: Default::default(),
         })
     }
 }
@@ -682,8 +1025,17 @@ fn safe_canonicalize(path: &Path) -> std::io::Result<PathBuf> {
     Ok(path.absolutize()?.into_owned())
 }
 
+/// A SQL migration, as recorded in the locked app's `sqlite_migrations`
+/// metadata.
+#[derive(serde::Serialize)]
+struct SqliteMigration {
+    name: String,
+    sql: String,
+}
+
 fn locked_metadata(
     details: v2::AppDetails,
+    sqlite_migrations: BTreeMap<String, Vec<SqliteMigration>>,
     trigger_types: impl Iterator<Item = String>,
 ) -> Result<ValuesMap> {
     let mut builder = ValuesMapBuilder::new();
@@ -693,6 +1045,18 @@ fn locked_metadata(
         .string("description", details.description)
         .string_array("authors", details.authors)
         .serializable("triggers", &details.trigger_global_configs)?;
+    if !details.labels.is_empty() {
+        builder.serializable("labels", &details.labels)?;
+    }
+    if let Some(hook) = &details.on_startup {
+        builder.serializable("on_startup", hook)?;
+    }
+    if let Some(hook) = &details.on_shutdown {
+        builder.serializable("on_shutdown", hook)?;
+    }
+    if !sqlite_migrations.is_empty() {
+        builder.serializable("sqlite_migrations", sqlite_migrations)?;
+    }
 
     // Duplicate single-trigger global options into "trigger" with "type"
     // key to maintain backward compatibility for a while.
@@ -711,6 +1075,98 @@ fn locked_metadata(
     Ok(builder.build())
 }
 
+fn validate_lifecycle_hooks(manifest: &AppManifest) -> Result<()> {
+    let hooks = [
+        ("on_startup", &manifest.application.on_startup),
+        ("on_shutdown", &manifest.application.on_shutdown),
+    ];
+    for (name, hook) in hooks {
+        if let Some(hook) = hook {
+            ensure!(
+                manifest.components.contains_key(&hook.component),
+                "`{name}` hook refers to nonexistent component {:?}",
+                hook.component.as_ref()
+            );
+        }
+    }
+    Ok(())
+}
+
+fn validate_middleware(manifest: &AppManifest) -> Result<()> {
+    for (id, component) in &manifest.components {
+        for middleware_id in &component.middleware {
+            ensure!(
+                middleware_id != id,
+                "component {:?} can't be its own middleware",
+                id.as_ref()
+            );
+            let middleware = manifest.components.get(middleware_id).with_context(|| {
+                format!(
+                    "component {:?} refers to nonexistent middleware component {:?}",
+                    id.as_ref(),
+                    middleware_id.as_ref()
+                )
+            })?;
+            // Middleware is composed into the component it wraps, so it
+            // can't be wrapped in turn.
+            ensure!(
+                middleware.middleware.is_empty(),
+                "component {:?} uses {:?} as middleware, but {:?} has middleware of its own",
+                id.as_ref(),
+                middleware_id.as_ref(),
+                middleware_id.as_ref()
+            );
+            // Middleware runs as part of the component it wraps, with that
+            // component's configuration, so configuration of its own would
+            // be ignored.
+            if let Some(field) = middleware_configuration(middleware).first() {
+                bail!(
+                    "component {:?} uses {:?} as middleware, but {:?} sets `{field}`; middleware runs with the configuration of the component it wraps",
+                    id.as_ref(),
+                    middleware_id.as_ref(),
+                    middleware_id.as_ref()
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Returns the configuration fields a component sets which would be ignored
+/// if it were used as middleware.
+fn middleware_configuration(component: &v2::Component) -> Vec<&'static str> {
+    [
+        ("variables", !component.variables.is_empty()),
+        ("environment", !component.environment.is_empty()),
+        (
+            "environment_passthrough",
+            !component.environment_passthrough.is_empty(),
+        ),
+        ("files", !component.files.is_empty()),
+        (
+            "allowed_http_hosts",
+            !component.allowed_http_hosts.is_empty(),
+        ),
+        (
+            "allowed_outbound_hosts",
+            !component.allowed_outbound_hosts.is_empty(),
+        ),
+        ("key_value_stores", !component.key_value_stores.is_empty()),
+        (
+            "key_value_namespace",
+            component.key_value_namespace.is_some(),
+        ),
+        ("sqlite_databases", !component.sqlite_databases.is_empty()),
+        ("ai_models", !component.ai_models.is_empty()),
+        ("concurrency", component.concurrency.is_some()),
+        ("instance", component.instance.is_some()),
+        ("features", !component.features.is_empty()),
+    ]
+    .into_iter()
+    .filter_map(|(field, set)| set.then_some(field))
+    .collect()
+}
+
 fn locked_variable(variable: v2::Variable) -> Result<locked::Variable> {
     ensure!(
         variable.required ^ variable.default.is_some(),
@@ -770,6 +1226,18 @@ fn looks_like_glob_pattern(s: impl AsRef<str>) -> bool {
     glob::Pattern::escape(s) != s
 }
 
+/// The `writable` and `max_bytes` options of a files mount.
+fn mount_options(mount: &WasiFilesMount) -> (Option<bool>, Option<u64>) {
+    match mount {
+        WasiFilesMount::Pattern(_) => (None, None),
+        WasiFilesMount::Placement {
+            writable,
+            max_bytes,
+            ..
+        } => (*writable, *max_bytes),
+    }
+}
+
 fn file_content_ref(path: impl AsRef<Path>) -> Result<ContentRef> {
     Ok(ContentRef {
         source: Some(file_url(path)?),
@@ -843,4 +1311,47 @@ mod test {
         );
         Ok(())
     }
+
+    #[tokio::test]
+    async fn overlay_mounts_directories_directly_and_links_other_files() -> anyhow::Result<()> {
+        let app_root = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("tests")
+            .join("overlay-mounts");
+        let wd = tempfile::tempdir()?;
+        let loader = LocalLoader::new(
+            &app_root,
+            FilesMountStrategy::Overlay(wd.path().to_owned()),
+            None,
+        )
+        .await?;
+        let locked = loader.load_file(app_root.join("spin.toml")).await?;
+
+        let files = |id: &str| {
+            locked
+                .components
+                .iter()
+                .find(|c| c.id == id)
+                .unwrap()
+                .files
+                .clone()
+        };
+
+        let direct = files("direct");
+        assert_eq!(1, direct.len());
+        assert_eq!(PathBuf::from("/assets"), direct[0].path);
+        assert_eq!(
+            Some(file_url(app_root.join("assets"))?),
+            direct[0].content.source
+        );
+
+        let overlaid = files("overlaid");
+        assert_eq!(1, overlaid.len());
+        assert_eq!(PathBuf::from("/"), overlaid[0].path);
+        let overlay = wd.path().join("overlaid");
+        assert_eq!(Some(file_url(&overlay)?), overlaid[0].content.source);
+        assert!(overlay.join("static/index.html").is_file());
+        assert!(overlay.join("etc/config.txt").is_file());
+        assert!(!overlay.join("static/secret.txt").exists());
+        Ok(())
+    }
 }
@@ -0,0 +1,480 @@
+//! Application lockfiles.
+//!
+//! A lockfile (`spin.lock`, next to `spin.toml`) records exactly what an
+//! application resolved to when it was loaded: the reference and digest of
+//! each component source and dependency, and the digest of every file mounted
+//! into each component. It is meant to be committed alongside the manifest so
+//! that later loads can be checked for drift, for example a registry package
+//! republished under the same version or an asset changed without review.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, ensure, Context, Result};
+use serde::{Deserialize, Serialize};
+use spin_common::{sha256, ui::quoted_path, url::parse_file_url};
+use spin_locked_app::locked::{ContentRef, LockedApp};
+use spin_manifest::schema::v2::{AppManifest, ComponentDependency, ComponentSource};
+
+/// The default lockfile name, relative to the manifest directory.
+pub const DEFAULT_LOCKFILE_NAME: &str = "spin.lock";
+
+const LOCKFILE_VERSION: u32 = 1;
+
+/// What to do with the application lockfile when loading from a manifest.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LockfileMode {
+    /// Don't read or write the lockfile.
+    #[default]
+    Ignore,
+    /// Write the lockfile for the loaded app, replacing any existing one.
+    Write,
+    /// Fail unless the loaded app matches the existing lockfile.
+    Verify,
+}
+
+/// The contents of a lockfile.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Lockfile {
+    lockfile_version: u32,
+    /// Locked components, by ID.
+    #[serde(default, rename = "component")]
+    pub components: BTreeMap<String, LockedComponentEntry>,
+}
+
+/// What a single component resolved to.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LockedComponentEntry {
+    /// The component's Wasm source.
+    pub source: LockedSource,
+    /// The component's dependencies, by dependency name.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub dependencies: BTreeMap<String, LockedSource>,
+    /// Digests of the files mounted into the component, by guest path.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub files: BTreeMap<String, String>,
+}
+
+/// A resolved Wasm source.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LockedSource {
+    /// The source as written in the manifest: a path, URL, or registry package.
+    pub reference: String,
+    /// The SHA-256 digest of the Wasm, as `sha256:<hex>`.
+    pub digest: String,
+}
+
+impl Lockfile {
+    /// Build the lockfile for an app loaded from the manifest at
+    /// `manifest_path`. `source_target`, `features` and `environment` must be
+    /// those the app was loaded with.
+    pub fn for_app(
+        manifest_path: &Path,
+        source_target: Option<&str>,
+        features: &[String],
+        environment: Option<&str>,
+        locked_app: &LockedApp,
+    ) -> Result<Self> {
+        let mut manifest = spin_manifest::manifest_from_file(manifest_path)?;
+        if let Some(environment) = environment {
+            spin_manifest::overlay::apply_environment(&mut manifest, manifest_path, environment)?;
+        }
+        spin_manifest::normalize::normalize_manifest(&mut manifest);
+        spin_manifest::normalize::select_source_targets(&mut manifest, source_target)?;
+        spin_manifest::normalize::select_features(&mut manifest, features)?;
+        Self::from_manifest_and_app(&manifest, locked_app)
+    }
+
+    fn from_manifest_and_app(manifest: &AppManifest, locked_app: &LockedApp) -> Result<Self> {
+        let mut components = BTreeMap::new();
+        for locked in &locked_app.components {
+            let id = &locked.id;
+            let component = manifest
+                .components
+                .iter()
+                .find(|(manifest_id, _)| manifest_id.as_ref() == id)
+                .map(|(_, c)| c)
+                .with_context(|| format!("component {id:?} is not in the manifest"))?;
+
+            let source = LockedSource {
+                reference: source_reference(&component.source),
+                digest: content_digest(&locked.source.content)
+                    .with_context(|| format!("failed to hash source of component {id:?}"))?,
+            };
+
+            let mut dependencies = BTreeMap::new();
+            for (name, dependency) in &locked.dependencies {
+                let reference = component
+                    .dependencies
+                    .inner
+                    .get(name)
+                    .map(|d| dependency_reference(&name.to_string(), d))
+                    .unwrap_or_default();
+                let digest = content_digest(&dependency.source.content).with_context(|| {
+                    format!("failed to hash dependency {name} of component {id:?}")
+                })?;
+                dependencies.insert(name.to_string(), LockedSource { reference, digest });
+            }
+
+            let mut files = BTreeMap::new();
+            for mount in &locked.files {
+                hash_mount(&mount.content, &mount.path, &mut files)
+                    .with_context(|| format!("failed to hash files of component {id:?}"))?;
+            }
+
+            components.insert(
+                id.clone(),
+                LockedComponentEntry {
+                    source,
+                    dependencies,
+                    files,
+                },
+            );
+        }
+        Ok(Self {
+            lockfile_version: LOCKFILE_VERSION,
+            components,
+        })
+    }
+
+    /// Read a lockfile.
+    pub fn read(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read lockfile {}", quoted_path(path)))?;
+        let lockfile: Self = toml::from_str(&contents)
+            .with_context(|| format!("invalid lockfile {}", quoted_path(path)))?;
+        ensure!(
+            lockfile.lockfile_version == LOCKFILE_VERSION,
+            "unsupported lockfile version {} in {}; this version of Spin supports version {LOCKFILE_VERSION}",
+            lockfile.lockfile_version,
+            quoted_path(path)
+        );
+        Ok(lockfile)
+    }
+
+    /// Write the lockfile. Output is deterministic, so an unchanged app
+    /// produces an identical file.
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let contents = format!(
+            "# This file is generated by Spin. Do not edit it by hand.\n{}",
+            toml::to_string_pretty(self)?
+        );
+        std::fs::write(path, contents)
+            .with_context(|| format!("failed to write lockfile {}", quoted_path(path)))
+    }
+
+    /// Describe each way in which `actual` differs from this lockfile.
+    pub fn drift(&self, actual: &Self) -> Vec<String> {
+        let mut drift = vec![];
+        for (id, locked) in &self.components {
+            let Some(found) = actual.components.get(id) else {
+                drift.push(format!("component {id:?} was removed"));
+                continue;
+            };
+            source_drift(
+                &format!("source of component {id:?}"),
+                &locked.source,
+                &found.source,
+                &mut drift,
+            );
+            map_drift(
+                &format!("dependency of component {id:?}"),
+                &locked.dependencies,
+                &found.dependencies,
+                |name, locked, found, drift| {
+                    source_drift(
+                        &format!("dependency {name} of component {id:?}"),
+                        locked,
+                        found,
+                        drift,
+                    )
+                },
+                &mut drift,
+            );
+            map_drift(
+                &format!("file of component {id:?}"),
+                &locked.files,
+                &found.files,
+                |path, locked, found, drift| {
+                    if locked != found {
+                        drift.push(format!(
+                            "file {path:?} of component {id:?} changed (locked {locked}, found {found})"
+                        ));
+                    }
+                },
+                &mut drift,
+            );
+        }
+        for id in actual.components.keys() {
+            if !self.components.contains_key(id) {
+                drift.push(format!("component {id:?} was added"));
+            }
+        }
+        drift
+    }
+}
+
+/// Write or verify the lockfile for an app loaded from `manifest_path`,
+/// according to `mode`.
+pub fn apply(
+    mode: LockfileMode,
+    manifest_path: &Path,
+    source_target: Option<&str>,
+    features: &[String],
+    environment: Option<&str>,
+    locked_app: &LockedApp,
+) -> Result<()> {
+    if mode == LockfileMode::Ignore {
+        return Ok(());
+    }
+    let lockfile_path = match environment {
+        Some(environment) => environment_lockfile_path(manifest_path, environment),
+        None => lockfile_path(manifest_path),
+    };
+    let actual = Lockfile::for_app(
+        manifest_path,
+        source_target,
+        features,
+        environment,
+        locked_app,
+    )?;
+    match mode {
+        LockfileMode::Ignore => unreachable!(),
+        LockfileMode::Write => actual.write(&lockfile_path),
+        LockfileMode::Verify => {
+            ensure!(
+                lockfile_path.exists(),
+                "lockfile {} does not exist",
+                quoted_path(&lockfile_path)
+            );
+            let drift = Lockfile::read(&lockfile_path)?.drift(&actual);
+            if !drift.is_empty() {
+                bail!(
+                    "application does not match lockfile {}:\n  - {}",
+                    quoted_path(&lockfile_path),
+                    drift.join("\n  - ")
+                );
+            }
+            Ok(())
+        }
+    }
+}
+
+/// The path of the lockfile for the manifest at `manifest_path`.
+pub fn lockfile_path(manifest_path: &Path) -> PathBuf {
+    manifest_path.with_file_name(DEFAULT_LOCKFILE_NAME)
+}
+
+/// The path of the lockfile for the manifest at `manifest_path` loaded for
+/// `environment`, named for the manifest like its overlay, e.g.
+/// `spin.staging.lock` for `spin.toml` and `staging`.
+pub fn environment_lockfile_path(manifest_path: &Path, environment: &str) -> PathBuf {
+    spin_manifest::overlay::overlay_path(manifest_path, environment).with_extension("lock")
+}
+
+fn source_reference(source: &ComponentSource) -> String {
+    match source {
+        ComponentSource::Local(path) => path.clone(),
+        ComponentSource::Remote { url, .. } => url.clone(),
+        ComponentSource::Registry {
+            registry,
+            package,
+            version,
+        } => registry_reference(registry.as_ref().map(|r| r.to_string()), package, version),
+        ComponentSource::Targeted(_) => {
+            unreachable!("source target should have already been selected")
+        }
+    }
+}
+
+fn dependency_reference(name: &str, dependency: &ComponentDependency) -> String {
+    match dependency {
+        ComponentDependency::Version(version) => registry_reference(None, name, version),
+        ComponentDependency::Package {
+            version,
+            registry,
+            package,
+            ..
+        } => registry_reference(
+            registry.clone(),
+            package.as_deref().unwrap_or(name),
+            version,
+        ),
+        ComponentDependency::Local { path, .. } => path.display().to_string(),
+        ComponentDependency::HTTP { url, .. } => url.clone(),
+    }
+}
+
+fn registry_reference(
+    registry: Option<String>,
+    package: impl std::fmt::Display,
+    version: &str,
+) -> String {
+    match registry {
+        Some(registry) => format!("{registry}/{package}@{version}"),
+        None => format!("{package}@{version}"),
+    }
+}
+
+fn content_digest(content: &ContentRef) -> Result<String> {
+    if let Some(digest) = &content.digest {
+        return Ok(digest.clone());
+    }
+    let hex = match (&content.source, &content.inline) {
+        (_, Some(inline)) => sha256::hex_digest_from_bytes(inline),
+        (Some(source), None) => {
+            let path = parse_file_url(source)?;
+            sha256::hex_digest_from_file(&path)
+                .with_context(|| format!("failed to read {}", quoted_path(&path)))?
+        }
+        (None, None) => bail!("content has neither a source nor inline data"),
+    };
+    Ok(format!("sha256:{hex}"))
+}
+
+/// Record the digest of each file in the mount, keyed by guest path.
+fn hash_mount(
+    content: &ContentRef,
+    guest_path: &str,
+    files: &mut BTreeMap<String, String>,
+) -> Result<()> {
+    let Some(source) = &content.source else {
+        files.insert(guest_path.to_owned(), content_digest(content)?);
+        return Ok(());
+    };
+    let path = parse_file_url(source)?;
+    if path.is_dir() {
+        hash_dir(&path, guest_path.trim_end_matches('/'), files)
+    } else {
+        files.insert(guest_path.to_owned(), content_digest(content)?);
+        Ok(())
+    }
+}
+
+fn hash_dir(dir: &Path, guest_dir: &str, files: &mut BTreeMap<String, String>) -> Result<()> {
+    let entries = std::fs::read_dir(dir)
+        .with_context(|| format!("failed to read directory {}", quoted_path(dir)))?;
+    for entry in entries {
+        let entry = entry?;
+        let path = entry.path();
+        let guest_path = format!("{guest_dir}/{}", entry.file_name().to_string_lossy());
+        if path.is_dir() {
+            hash_dir(&path, &guest_path, files)?;
+        } else {
+            let hex = sha256::hex_digest_from_file(&path)
+                .with_context(|| format!("failed to read {}", quoted_path(&path)))?;
+            files.insert(guest_path, format!("sha256:{hex}"));
+        }
+    }
+    Ok(())
+}
+
+fn source_drift(what: &str, locked: &LockedSource, found: &LockedSource, drift: &mut Vec<String>) {
+    if locked.reference != found.reference {
+        drift.push(format!(
+            "{what} changed from {:?} to {:?}",
+            locked.reference, found.reference
+        ));
+    } else if locked.digest != found.digest {
+        drift.push(format!(
+            "{what} ({}) changed (locked {}, found {})",
+            locked.reference, locked.digest, found.digest
+        ));
+    }
+}
+
+fn map_drift<T>(
+    what: &str,
+    locked: &BTreeMap<String, T>,
+    found: &BTreeMap<String, T>,
+    compare: impl Fn(&str, &T, &T, &mut Vec<String>),
+    drift: &mut Vec<String>,
+) {
+    for (key, locked_value) in locked {
+        match found.get(key) {
+            Some(found_value) => compare(key, locked_value, found_value, drift),
+            None => drift.push(format!("{what} {key:?} was removed")),
+        }
+    }
+    for key in found.keys() {
+        if !locked.contains_key(key) {
+            drift.push(format!("{what} {key:?} was added"));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn lockfile(digest: &str, files: &[(&str, &str)]) -> Lockfile {
+        Lockfile {
+            lockfile_version: LOCKFILE_VERSION,
+            components: [(
+                "web".to_owned(),
+                LockedComponentEntry {
+                    source: LockedSource {
+                        reference: "target/web.wasm".into(),
+                        digest: digest.into(),
+                    },
+                    dependencies: Default::default(),
+                    files: files
+                        .iter()
+                        .map(|(k, v)| (k.to_string(), v.to_string()))
+                        .collect(),
+                },
+            )]
+            .into(),
+        }
+    }
+
+    #[test]
+    fn identical_lockfiles_have_no_drift() {
+        let locked = lockfile("sha256:aa", &[("/index.html", "sha256:bb")]);
+        assert!(locked.drift(&locked.clone()).is_empty());
+    }
+
+    #[test]
+    fn changed_source_and_files_are_drift() {
+        let locked = lockfile("sha256:aa", &[("/index.html", "sha256:bb")]);
+        let actual = lockfile("sha256:cc", &[("/new.html", "sha256:bb")]);
+        let drift = locked.drift(&actual);
+        assert_eq!(3, drift.len(), "{drift:?}");
+        assert!(drift[0].contains("sha256:cc"));
+        assert!(drift[1].contains("\"/index.html\" was removed"));
+        assert!(drift[2].contains("\"/new.html\" was added"));
+    }
+
+    #[test]
+    fn can_round_trip_lockfile() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(DEFAULT_LOCKFILE_NAME);
+        let locked = lockfile("sha256:aa", &[("/index.html", "sha256:bb")]);
+        locked.write(&path).unwrap();
+        assert_eq!(locked, Lockfile::read(&path).unwrap());
+    }
+
+    #[test]
+    fn hashes_directory_mounts_by_guest_path() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("css")).unwrap();
+        std::fs::write(dir.path().join("css/site.css"), "body {}").unwrap();
+        let content = ContentRef {
+            source: Some(
+                reqwest::Url::from_file_path(dir.path())
+                    .unwrap()
+                    .to_string(),
+            ),
+            ..Default::default()
+        };
+        let mut files = BTreeMap::new();
+        hash_mount(&content, "/static", &mut files).unwrap();
+        assert_eq!(
+            Some(&format!(
+                "sha256:{}",
+                sha256::hex_digest_from_bytes("body {}")
+            )),
+            files.get("/static/css/site.css")
+        );
+    }
+}
@@ -0,0 +1,261 @@
+//! Incremental copying of mounted files, so that loading an app into a files
+//! mount root it was loaded into before only copies the files which changed.
+
+use std::{
+    collections::BTreeMap,
+    fs::Metadata,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use spin_common::ui::quoted_path;
+
+use crate::digests::DigestCache;
+use crate::integrity::{unix_millis, KnownDigests};
+
+/// The file, within the files mount root, recording the files copied there.
+const SYNC_MANIFEST_FILE: &str = ".sync.json";
+
+/// The files copied into a files mount root, by path relative to it.
+#[derive(Debug)]
+pub(crate) struct SyncManifest {
+    files_mount_root: PathBuf,
+    /// The files copied by the previous load.
+    previous: BTreeMap<String, SyncedFile>,
+    /// The files copied, or found to be unchanged, by this load.
+    current: Mutex<BTreeMap<String, SyncedFile>>,
+    /// The digests of the sources copies were made from, so that copies of
+    /// unchanged sources needn't be hashed even into a new files mount root.
+    source_digests: Arc<DigestCache>,
+}
+
+/// A copied file, and the source it was copied from.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub(crate) struct SyncedFile {
+    source: PathBuf,
+    source_size: u64,
+    source_modified_ms: u64,
+    size: u64,
+    modified_ms: u64,
+    /// The SHA-256 digest of the copy, as `sha256:<hex>`, if it was recorded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    digest: Option<String>,
+}
+
+impl SyncManifest {
+    /// Reads the files copied into `files_mount_root` by the previous load.
+    /// If they can't be read, all files are copied again.
+    pub fn load(files_mount_root: &Path, source_digests: Arc<DigestCache>) -> Self {
+        let path = files_mount_root.join(SYNC_MANIFEST_FILE);
+        let previous = match std::fs::read(&path) {
+            Ok(json) => serde_json::from_slice(&json).unwrap_or_else(|err| {
+                tracing::debug!("Ignoring invalid sync manifest {path:?}: {err}");
+                Default::default()
+            }),
+            Err(_) => Default::default(),
+        };
+        Self {
+            files_mount_root: files_mount_root.to_owned(),
+            previous,
+            current: Default::default(),
+            source_digests,
+        }
+    }
+
+    /// Whether `dest` is an unchanged copy of `src`, which needn't be copied
+    /// again. If so, it is recorded as copied by this load.
+    pub fn is_current(&self, src: &Path, dest: &Path) -> bool {
+        let Some(key) = self.key(dest) else {
+            return false;
+        };
+        let Some(previous) = self.previous.get(&key) else {
+            return false;
+        };
+        let (Ok(src_meta), Ok(dest_meta)) = (std::fs::metadata(src), std::fs::metadata(dest))
+        else {
+            return false;
+        };
+        let unchanged = previous.source == src
+            && previous.source_size == src_meta.len()
+            && Some(previous.source_modified_ms) == modified_ms(&src_meta)
+            && previous.size == dest_meta.len()
+            && Some(previous.modified_ms) == modified_ms(&dest_meta);
+        if unchanged {
+            self.current.lock().unwrap().insert(key, previous.clone());
+        }
+        unchanged
+    }
+
+    /// Records that `dest` was copied from `src`.
+    pub fn record(&self, src: &Path, dest: &Path) -> Result<()> {
+        let Some(key) = self.key(dest) else {
+            return Ok(());
+        };
+        let src_meta = std::fs::metadata(src)?;
+        let dest_meta = std::fs::metadata(dest)?;
+        let (Some(source_modified_ms), Some(modified_ms)) =
+            (modified_ms(&src_meta), modified_ms(&dest_meta))
+        else {
+            return Ok(());
+        };
+        let synced = SyncedFile {
+            source: src.to_owned(),
+            source_size: src_meta.len(),
+            source_modified_ms,
+            size: dest_meta.len(),
+            modified_ms,
+            digest: None,
+        };
+        self.current.lock().unwrap().insert(key, synced);
+        Ok(())
+    }
+
+    /// Removes the copies in `dir` made by the previous load which this load
+    /// didn't copy. This must only be called once everything to be copied
+    /// into `dir` has been.
+    pub fn prune(&self, dir: &Path) {
+        let current = self.current.lock().unwrap();
+        let stale = self.previous.keys().filter(|key| {
+            !current.contains_key(*key) && self.files_mount_root.join(key).starts_with(dir)
+        });
+        for key in stale {
+            let path = self.files_mount_root.join(key);
+            match std::fs::remove_file(&path) {
+                Err(err) if err.kind() != std::io::ErrorKind::NotFound => {
+                    tracing::warn!("Failed to remove stale copy {path:?}: {err}");
+                }
+                _ => tracing::debug!("Removed stale copy {path:?}"),
+            }
+        }
+    }
+
+    /// Removes all the stale copies made by the previous load, and saves the
+    /// files copied by this load for the next one.
+    pub fn finish(&self) -> Result<()> {
+        self.prune(&self.files_mount_root);
+        self.source_digests.save();
+        let current = self.current.lock().unwrap();
+        let path = self.files_mount_root.join(SYNC_MANIFEST_FILE);
+        let json = serde_json::to_vec(&*current).context("failed to serialize sync manifest")?;
+        std::fs::create_dir_all(&self.files_mount_root)
+            .and_then(|_| std::fs::write(&path, json))
+            .with_context(|| format!("Failed to write sync manifest {}", quoted_path(&path)))
+    }
+
+    fn key(&self, path: &Path) -> Option<String> {
+        let rel_path = path.strip_prefix(&self.files_mount_root).ok()?;
+        let components = rel_path
+            .components()
+            .map(|c| c.as_os_str().to_str())
+            .collect::<Option<Vec<_>>>()?;
+        Some(components.join("/"))
+    }
+}
+
+impl KnownDigests for SyncManifest {
+    /// The digest of the copy at `path`, if it was recorded, or its source's
+    /// was, and the copy hasn't changed since it was made.
+    fn digest(&self, path: &Path, metadata: &Metadata) -> Option<String> {
+        let key = self.key(path)?;
+        let current = self.current.lock().unwrap();
+        let synced = current.get(&key)?;
+        if synced.size != metadata.len() || Some(synced.modified_ms) != modified_ms(metadata) {
+            return None;
+        }
+        synced.digest.clone().or_else(|| {
+            self.source_digests.get(
+                &synced.source,
+                synced.source_size,
+                synced.source_modified_ms,
+            )
+        })
+    }
+
+    fn hashed(&self, path: &Path, digest: &str) {
+        let Some(key) = self.key(path) else {
+            return;
+        };
+        if let Some(synced) = self.current.lock().unwrap().get_mut(&key) {
+            synced.digest = Some(digest.to_owned());
+            self.source_digests.insert(
+                &synced.source,
+                synced.source_size,
+                synced.source_modified_ms,
+                digest,
+            );
+        }
+    }
+}
+
+fn modified_ms(metadata: &Metadata) -> Option<u64> {
+    unix_millis(metadata.modified().ok()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unchanged_copies_are_current() -> Result<()> {
+        let src_dir = tempfile::tempdir()?;
+        let root = tempfile::tempdir()?;
+        let src = src_dir.path().join("a.txt");
+        let dest = root.path().join("web/a.txt");
+        std::fs::write(&src, "spin")?;
+        std::fs::create_dir_all(dest.parent().unwrap())?;
+        std::fs::copy(&src, &dest)?;
+
+        let first = SyncManifest::load(root.path(), Arc::new(DigestCache::load(src_dir.path())));
+        assert!(!first.is_current(&src, &dest));
+        first.record(&src, &dest)?;
+        first.finish()?;
+
+        let second = SyncManifest::load(root.path(), Arc::new(DigestCache::load(src_dir.path())));
+        assert!(second.is_current(&src, &dest));
+        std::fs::write(&src, "spin!")?;
+        assert!(!second.is_current(&src, &dest));
+        Ok(())
+    }
+
+    #[test]
+    fn stale_copies_are_removed() -> Result<()> {
+        let src_dir = tempfile::tempdir()?;
+        let root = tempfile::tempdir()?;
+        let src = src_dir.path().join("a.txt");
+        let dest = root.path().join("a.txt");
+        std::fs::write(&src, "spin")?;
+        std::fs::copy(&src, &dest)?;
+
+        let first = SyncManifest::load(root.path(), Arc::new(DigestCache::load(src_dir.path())));
+        first.record(&src, &dest)?;
+        first.finish()?;
+
+        SyncManifest::load(root.path(), Arc::new(DigestCache::load(src_dir.path()))).finish()?;
+        assert!(!dest.exists());
+        Ok(())
+    }
+
+    #[test]
+    fn source_digests_are_kept_across_files_mount_roots() -> Result<()> {
+        let src_dir = tempfile::tempdir()?;
+        let src = src_dir.path().join("a.txt");
+        std::fs::write(&src, "spin")?;
+
+        let mut digests = vec![];
+        for _ in 0..2 {
+            let root = tempfile::tempdir()?;
+            let dest = root.path().join("a.txt");
+            std::fs::copy(&src, &dest)?;
+            let sync = SyncManifest::load(root.path(), Arc::new(DigestCache::load(src_dir.path())));
+            sync.record(&src, &dest)?;
+            let metadata = std::fs::metadata(&dest)?;
+            digests.push(sync.digest(&dest, &metadata));
+            sync.hashed(&dest, "sha256:a5a2");
+            sync.finish()?;
+        }
+        assert_eq!(vec![None, Some("sha256:a5a2".to_owned())], digests);
+        Ok(())
+    }
+}
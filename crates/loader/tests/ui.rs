@@ -46,13 +46,20 @@ fn run_test(input: &Path, normalizer: &mut Normalizer) -> Result<String, Failed>
     normalizer.replace_path(temp_path, "<temp-dir>");
 
     block_on(async {
-        let locked = spin_loader::from_file(
+        let mut locked = spin_loader::from_file(
             input,
             spin_loader::FilesMountStrategy::Copy(files_mount_root),
             None,
         )
         .await
         .map_err(|err| format!("{err:?}"))?;
+        // The recorded files include modification times, and may include the
+        // snapshot itself.
+        for component in &mut locked.components {
+            for files in &mut component.files {
+                files.integrity.clear();
+            }
+        }
         Ok(serde_json::to_string_pretty(&locked).expect("serialization should work"))
     })
 }
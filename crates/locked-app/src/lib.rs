@@ -22,6 +22,12 @@ pub const APP_VERSION_KEY: MetadataKey = MetadataKey::new("version");
 pub const APP_DESCRIPTION_KEY: MetadataKey = MetadataKey::new("description");
 /// MetadataKey for extracting the OCI image digest.
 pub const OCI_IMAGE_DIGEST_KEY: MetadataKey = MetadataKey::new("oci_image_digest");
+/// MetadataKey for extracting the application labels.
+pub const APP_LABELS_KEY: MetadataKey<std::collections::BTreeMap<String, String>> =
+    MetadataKey::new("labels");
+/// MetadataKey for extracting the annotations of the OCI image an app was loaded from.
+pub const OCI_ANNOTATIONS_KEY: MetadataKey<std::collections::BTreeMap<String, String>> =
+    MetadataKey::new("oci_annotations");
 
 /// Type alias for a [`Result`]s with [`Error`].
 pub type Result<T> = std::result::Result<T, Error>;
@@ -228,7 +228,7 @@ pub struct LockedComponent {
 }
 
 /// A LockedDependency represents a "fully resolved" Spin component dependency.
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct LockedComponentDependency {
     /// Locked dependency source
     pub source: LockedComponentSource,
@@ -240,7 +240,7 @@ pub struct LockedComponentDependency {
 }
 
 /// InheritConfiguration specifies which configurations to inherit from parent.
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum InheritConfiguration {
     /// Dependencies will inherit all configurations from parent.
     All,
@@ -262,7 +262,7 @@ impl InheritConfiguration {
 }
 
 /// A LockedComponentSource specifies a Wasm source.
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct LockedComponentSource {
     /// Wasm source content type (e.g. "application/wasm")
     pub content_type: String,
@@ -272,6 +272,11 @@ pub struct LockedComponentSource {
 }
 
 /// A ContentPath specifies content mapped to a WASI path.
+///
+/// In apps pushed to a registry, each file is a separate ContentPath, and a
+/// ContentPath with no content marks a directory mounted with its own
+/// `writable` or `max_bytes`, from which the files under its path are
+/// mounted.
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct ContentPath {
     /// Content specification
@@ -279,13 +284,39 @@ pub struct ContentPath {
     pub content: ContentRef,
     /// WASI mount path
     pub path: PathBuf,
+    /// Whether the guest may write to the mounted directory. If unset, this
+    /// is up to the runtime.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub writable: Option<bool>,
+    /// The maximum total size, in bytes, of the files in a writable mounted
+    /// directory.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_bytes: Option<u64>,
+    /// The files in the mounted directory when the app was loaded, by path
+    /// relative to the directory, with forward slashes. If this is empty, the
+    /// mounted files can't be verified.
+    #[serde(default, skip_serializing_if = "LockedMap::is_empty")]
+    pub integrity: LockedMap<FileIntegrity>,
+}
+
+/// The expected content of a mounted file.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct FileIntegrity {
+    /// The SHA-256 digest of the file, as `sha256:<hex>`.
+    pub digest: String,
+    /// The size of the file in bytes.
+    pub size: u64,
+    /// When the file was last modified, in milliseconds since the Unix epoch.
+    /// If this doesn't match, the file may still have the expected content.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub modified_ms: Option<u64>,
 }
 
 /// A ContentRef represents content used by an application.
 ///
 /// At least one of `source`, `inline`, or `digest` must be specified. Implementations may
 /// require one or the other (or both).
-#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
 pub struct ContentRef {
     /// A URI where the content can be accessed. Implementations may support
     /// different URI schemes.
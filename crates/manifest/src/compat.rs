@@ -20,6 +20,10 @@ pub fn v1_to_v2_app(manifest: v1::AppManifestV1) -> Result<v2::AppManifest, Erro
         version: manifest.version,
         description: manifest.description,
         authors: manifest.authors,
+        labels: Default::default(),
+        on_startup: None,
+        on_shutdown: None,
+        sqlite_migrations: Default::default(),
         trigger_global_configs,
         tool: Default::default(),
     };
@@ -65,17 +69,24 @@ pub fn v1_to_v2_app(manifest: v1::AppManifestV1) -> Result<v2::AppManifest, Erro
                 description: component.description,
                 variables,
                 environment: component.environment,
+                environment_passthrough: Vec::new(),
                 files: component.files,
                 exclude_files: component.exclude_files,
                 key_value_stores: component.key_value_stores,
+                key_value_namespace: None,
                 sqlite_databases: component.sqlite_databases,
                 ai_models,
+                precompile: None,
+                concurrency: None,
+                instance: None,
                 build: component.build,
                 tool: Default::default(),
                 allowed_outbound_hosts,
                 allowed_http_hosts: Vec::new(),
                 dependencies_inherit_configuration: false,
                 dependencies: Default::default(),
+                middleware: Vec::new(),
+                features: Default::default(),
             },
         );
         triggers
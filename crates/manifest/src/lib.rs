@@ -5,6 +5,7 @@
 pub mod compat;
 pub mod error;
 pub mod normalize;
+pub mod overlay;
 pub mod schema;
 
 use std::path::Path;
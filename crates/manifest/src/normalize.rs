@@ -2,7 +2,9 @@
 
 use std::collections::HashSet;
 
-use crate::schema::v2::{AppManifest, ComponentSpec, KebabId};
+use anyhow::Context;
+
+use crate::schema::v2::{AppManifest, ComponentSource, ComponentSpec, KebabId};
 
 /// Normalizes some optional [`AppManifest`] features into a canonical form:
 /// - Inline components in trigger configs are moved into top-level
@@ -103,3 +105,166 @@ fn normalize_trigger_ids(manifest: &mut AppManifest) {
         }
     }
 }
+
+/// Replaces each [`ComponentSource::Targeted`] component source with its
+/// source for the given target, or for [`ComponentSource::DEFAULT_TARGET`] if
+/// no target is given.
+///
+/// This should be called after [`normalize_manifest`], so that inline
+/// components' sources are selected too.
+pub fn select_source_targets(
+    manifest: &mut AppManifest,
+    target: Option<&str>,
+) -> anyhow::Result<()> {
+    let target = target.unwrap_or(ComponentSource::DEFAULT_TARGET);
+    for (id, component) in &mut manifest.components {
+        let ComponentSource::Targeted(sources) = &mut component.source else {
+            continue;
+        };
+        let targets = sources.keys().cloned().collect::<Vec<_>>();
+        let source = sources.remove(target).with_context(|| {
+            format!(
+                "component `{id}` has no source for target {target:?}; its source targets are {targets:?}. Select one with `--source-target`."
+            )
+        })?;
+        anyhow::ensure!(
+            !matches!(source, ComponentSource::Targeted(_)),
+            "component `{id}` source for target {target:?} must not itself have targets"
+        );
+        component.source = source;
+    }
+    Ok(())
+}
+
+/// Applies each component's settings for the given `features`, and removes
+/// all feature settings from the manifest. Enabled features are applied in
+/// the order the component declares them.
+///
+/// It is an error to enable a feature that no component declares.
+pub fn select_features(manifest: &mut AppManifest, features: &[String]) -> anyhow::Result<()> {
+    for feature in features {
+        anyhow::ensure!(
+            manifest
+                .components
+                .values()
+                .any(|c| c.features.contains_key(feature)),
+            "feature {feature:?} is not declared by any component"
+        );
+    }
+    for component in manifest.components.values_mut() {
+        for (name, feature) in std::mem::take(&mut component.features) {
+            if !features.contains(&name) {
+                continue;
+            }
+            component.environment.extend(feature.environment);
+            component.files.extend(feature.files);
+            component
+                .dependencies
+                .inner
+                .extend(feature.dependencies.inner);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manifest() -> AppManifest {
+        toml::from_str(
+            r#"
+            spin_manifest_version = 2
+            [application]
+            name = "targets"
+            [[trigger.http]]
+            route = "/..."
+            component = "app"
+            [component.app.source]
+            local = "target/app.wasm"
+            remote = { url = "https://example.test/app.wasm", digest = "sha256:abc" }
+            "#,
+        )
+        .unwrap()
+    }
+
+    fn app_source(manifest: &AppManifest) -> &ComponentSource {
+        &manifest.components.values().next().unwrap().source
+    }
+
+    #[test]
+    fn selects_local_target_by_default() {
+        let mut manifest = manifest();
+        select_source_targets(&mut manifest, None).unwrap();
+        assert!(
+            matches!(app_source(&manifest), ComponentSource::Local(path) if path == "target/app.wasm")
+        );
+    }
+
+    #[test]
+    fn selects_given_target() {
+        let mut manifest = manifest();
+        select_source_targets(&mut manifest, Some("remote")).unwrap();
+        assert!(
+            matches!(app_source(&manifest), ComponentSource::Remote { url, .. } if url == "https://example.test/app.wasm")
+        );
+    }
+
+    #[test]
+    fn missing_target_is_an_error() {
+        let mut manifest = manifest();
+        let err = select_source_targets(&mut manifest, Some("staging")).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains(r#"no source for target "staging""#));
+    }
+
+    fn features_manifest() -> AppManifest {
+        toml::from_str(
+            r#"
+            spin_manifest_version = 2
+            [application]
+            name = "features"
+            [[trigger.http]]
+            route = "/..."
+            component = "app"
+            [component.app]
+            source = "target/app.wasm"
+            environment = { LOG_LEVEL = "info" }
+            [component.app.features.debug]
+            environment = { LOG_LEVEL = "debug" }
+            files = ["debug-assets"]
+            "#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn enabled_features_are_applied() {
+        let mut manifest = features_manifest();
+        select_features(&mut manifest, &["debug".into()]).unwrap();
+        let component = manifest.components.values().next().unwrap();
+        assert_eq!("debug", component.environment["LOG_LEVEL"]);
+        assert_eq!(1, component.files.len());
+        assert!(component.features.is_empty());
+    }
+
+    #[test]
+    fn disabled_features_are_removed() {
+        let mut manifest = features_manifest();
+        select_features(&mut manifest, &[]).unwrap();
+        let component = manifest.components.values().next().unwrap();
+        assert_eq!("info", component.environment["LOG_LEVEL"]);
+        assert!(component.files.is_empty());
+        assert!(component.features.is_empty());
+    }
+
+    #[test]
+    fn undeclared_feature_is_an_error() {
+        let mut manifest = features_manifest();
+        let err = select_features(&mut manifest, &["prod".into()]).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains(r#"feature "prod" is not declared"#));
+    }
+}
@@ -0,0 +1,293 @@
+//! Per-environment manifest overlays.
+//!
+//! An overlay is a TOML file next to the manifest, named for the manifest and
+//! an environment, e.g. `spin.staging.toml` for `spin.toml` in the `staging`
+//! environment. It patches the manifest with the settings that differ in that
+//! environment:
+//!
+//! ```toml
+//! [variables]
+//! api_url = { default = "https://staging.example.com" }
+//!
+//! [component.api]
+//! source = { url = "https://example.com/api.wasm", digest = "sha256:..." }
+//! allowed_outbound_hosts = ["https://staging.example.com"]
+//! variables = { log_level = "debug" }
+//! environment = { RUST_BACKTRACE = "1" }
+//! ```
+//!
+//! Overlays are applied as follows:
+//! - An overlay variable is added to the manifest's variables, or patches the
+//!   manifest variable of the same name. Setting its `default` makes it
+//!   optional, setting `required = true` removes its default, and `secret`
+//!   replaces the manifest's `secret`.
+//! - A component's `source` and `allowed_outbound_hosts` replace those in the
+//!   manifest.
+//! - A component's `variables` and `environment` are added to those in the
+//!   manifest, replacing any of the same name.
+//!
+//! Only components declared in `[component.<id>]` tables can be patched, and
+//! it is an error for an overlay to patch a component the manifest doesn't
+//! have.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use serde::Deserialize;
+use spin_serde::{KebabId, LowerSnakeId};
+
+use crate::schema::v2::{AppManifest, ComponentSource, Map, Variable};
+use crate::Error;
+
+/// The settings of a manifest that differ in an environment.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ManifestOverlay {
+    /// `[variables]`
+    #[serde(default)]
+    pub variables: Map<LowerSnakeId, VariableOverlay>,
+    /// `[component.<id>]`
+    #[serde(default, rename = "component")]
+    pub components: Map<KebabId, ComponentOverlay>,
+}
+
+/// A patch to, or addition of, an application variable.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct VariableOverlay {
+    /// `required = true`: removes any default
+    #[serde(default)]
+    pub required: bool,
+    /// `default = "default value"`: makes the variable optional
+    #[serde(default)]
+    pub default: Option<String>,
+    /// `secret = true`
+    #[serde(default)]
+    pub secret: Option<bool>,
+}
+
+/// A patch to a component.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ComponentOverlay {
+    /// `source = ...`: replaces the component's source
+    #[serde(default)]
+    pub source: Option<ComponentSource>,
+    /// `allowed_outbound_hosts = [...]`: replaces the component's allowed
+    /// outbound hosts
+    #[serde(default)]
+    pub allowed_outbound_hosts: Option<Vec<String>>,
+    /// `variables = { name = "{{ app_var }}" }`: added to the component's
+    /// variables, replacing any of the same name
+    #[serde(default)]
+    pub variables: Map<LowerSnakeId, String>,
+    /// `environment = { VAR = "value" }`: added to the component's
+    /// environment, replacing any variables of the same name
+    #[serde(default)]
+    pub environment: Map<String, String>,
+}
+
+/// The path of the overlay for `environment` of the manifest at
+/// `manifest_path`, e.g. `spin.staging.toml` for `spin.toml` and `staging`.
+pub fn overlay_path(manifest_path: &Path, environment: &str) -> PathBuf {
+    let stem = manifest_path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy())
+        .unwrap_or_default();
+    manifest_path.with_file_name(format!("{stem}.{environment}.toml"))
+}
+
+/// Parses a manifest overlay file.
+pub fn overlay_from_file(path: impl AsRef<Path>) -> Result<ManifestOverlay, Error> {
+    let overlay_str = std::fs::read_to_string(path)?;
+    Ok(toml::from_str(&overlay_str)?)
+}
+
+/// Applies the overlay for `environment` of the manifest at `manifest_path` to
+/// `manifest`. It is an error for the overlay not to exist.
+pub fn apply_environment(
+    manifest: &mut AppManifest,
+    manifest_path: &Path,
+    environment: &str,
+) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        !environment.is_empty()
+            && environment
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'),
+        "invalid environment {environment:?}: must be letters, digits, '-' and '_'"
+    );
+    let path = overlay_path(manifest_path, environment);
+    let overlay = overlay_from_file(&path).with_context(|| {
+        format!(
+            "failed to read overlay for environment {environment:?} from {}",
+            path.display()
+        )
+    })?;
+    overlay
+        .apply(manifest)
+        .with_context(|| format!("failed to apply overlay {}", path.display()))
+}
+
+impl ManifestOverlay {
+    /// Patches `manifest` with this overlay.
+    pub fn apply(self, manifest: &mut AppManifest) -> anyhow::Result<()> {
+        for (name, patch) in self.variables {
+            anyhow::ensure!(
+                !(patch.required && patch.default.is_some()),
+                "variable `{name}` must not be both `required` and have a `default`"
+            );
+            let variable = manifest.variables.entry(name).or_insert(Variable {
+                required: false,
+                default: None,
+                secret: false,
+            });
+            if patch.required {
+                variable.required = true;
+                variable.default = None;
+            }
+            if let Some(default) = patch.default {
+                variable.required = false;
+                variable.default = Some(default);
+            }
+            if let Some(secret) = patch.secret {
+                variable.secret = secret;
+            }
+        }
+
+        for (id, patch) in self.components {
+            let component = manifest
+                .components
+                .get_mut(&id)
+                .with_context(|| format!("component `{id}` is not in the manifest"))?;
+            if let Some(source) = patch.source {
+                component.source = source;
+            }
+            if let Some(allowed_outbound_hosts) = patch.allowed_outbound_hosts {
+                component.allowed_outbound_hosts = allowed_outbound_hosts;
+                // The deprecated field would otherwise still allow its hosts.
+                component.allowed_http_hosts.clear();
+            }
+            component.variables.extend(patch.variables);
+            component.environment.extend(patch.environment);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manifest() -> AppManifest {
+        toml::from_str(
+            r#"
+            spin_manifest_version = 2
+            [application]
+            name = "overlays"
+            [variables]
+            api_url = { required = true }
+            token = { default = "dev", secret = true }
+            [[trigger.http]]
+            route = "/..."
+            component = "api"
+            [component.api]
+            source = "target/api.wasm"
+            allowed_outbound_hosts = ["http://localhost:3000"]
+            variables = { api_url = "{{ api_url }}", log_level = "info" }
+            environment = { MODE = "dev" }
+            "#,
+        )
+        .unwrap()
+    }
+
+    fn variable<'a>(manifest: &'a AppManifest, name: &str) -> &'a Variable {
+        manifest
+            .variables
+            .iter()
+            .find(|(variable_name, _)| variable_name.as_ref() == name)
+            .map(|(_, variable)| variable)
+            .unwrap()
+    }
+
+    fn overlay(toml: &str) -> ManifestOverlay {
+        toml::from_str(toml).unwrap()
+    }
+
+    #[test]
+    fn overlays_patch_the_manifest() {
+        let mut manifest = manifest();
+        overlay(
+            r#"
+            [variables]
+            api_url = { default = "https://staging.example.com" }
+            token = { required = true }
+            region = { default = "eu" }
+            [component.api]
+            source = { url = "https://example.com/api.wasm", digest = "sha256:abc" }
+            allowed_outbound_hosts = ["https://staging.example.com"]
+            variables = { log_level = "debug" }
+            environment = { TRACE = "1" }
+            "#,
+        )
+        .apply(&mut manifest)
+        .unwrap();
+
+        let api_url = variable(&manifest, "api_url");
+        assert!(!api_url.required);
+        assert_eq!(
+            Some("https://staging.example.com"),
+            api_url.default.as_deref()
+        );
+        let token = variable(&manifest, "token");
+        assert!(token.required && token.default.is_none() && token.secret);
+        assert_eq!(Some("eu"), variable(&manifest, "region").default.as_deref());
+
+        let api = manifest.components.values().next().unwrap();
+        assert!(
+            matches!(&api.source, ComponentSource::Remote { url, digest } if url == "https://example.com/api.wasm" && digest == "sha256:abc")
+        );
+        assert_eq!(
+            vec!["https://staging.example.com"],
+            api.allowed_outbound_hosts
+        );
+        let variables = api
+            .variables
+            .iter()
+            .map(|(name, value)| (name.as_ref(), value.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![("api_url", "{{ api_url }}"), ("log_level", "debug")],
+            variables
+        );
+        assert_eq!("dev", api.environment["MODE"]);
+        assert_eq!("1", api.environment["TRACE"]);
+    }
+
+    #[test]
+    fn overlays_must_patch_existing_components() {
+        let mut manifest = manifest();
+        let err = overlay("[component.web]\nenvironment = { MODE = \"staging\" }")
+            .apply(&mut manifest)
+            .unwrap_err();
+        assert!(err.to_string().contains("component `web`"));
+    }
+
+    #[test]
+    fn overlays_reject_unknown_fields() {
+        assert!(toml::from_str::<ManifestOverlay>("[component.api]\nfiles = []").is_err());
+        assert!(toml::from_str::<ManifestOverlay>("[application]\nname = \"x\"").is_err());
+    }
+
+    #[test]
+    fn overlay_paths_are_named_for_the_manifest() {
+        assert_eq!(
+            Path::new("/app/spin.staging.toml"),
+            overlay_path(Path::new("/app/spin.toml"), "staging")
+        );
+        assert_eq!(
+            Path::new("app.prod.toml"),
+            overlay_path(Path::new("app.toml"), "prod")
+        );
+    }
+}
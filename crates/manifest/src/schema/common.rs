@@ -1,4 +1,4 @@
-use std::fmt::Display;
+use std::{collections::BTreeMap, fmt::Display};
 
 use serde::{Deserialize, Serialize};
 
@@ -41,6 +41,28 @@ pub enum ComponentSource {
         /// `version = "1.2.3"`
         version: String,
     },
+    /// `{ local = "target/app.wasm", registry = { ... } }`: a source for each
+    /// target environment, of which the loader selects one
+    Targeted(BTreeMap<String, ComponentSource>),
+}
+
+impl ComponentSource {
+    /// The target selected from a [`ComponentSource::Targeted`] source if no
+    /// other target is given.
+    pub const DEFAULT_TARGET: &'static str = "local";
+
+    /// The local path of the source, or of the default target's source, if
+    /// it is a local file.
+    pub fn local_path(&self) -> Option<&str> {
+        match self {
+            Self::Local(path) => Some(path),
+            Self::Targeted(sources) => match sources.get(Self::DEFAULT_TARGET)? {
+                Self::Local(path) => Some(path),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
 }
 
 impl Display for ComponentSource {
@@ -59,6 +81,10 @@ impl Display for ComponentSource {
                 };
                 write!(f, "\"{package}@{version}\" from {registry_suffix}")
             }
+            ComponentSource::Targeted(sources) => {
+                let targets = sources.keys().map(|t| format!("{t:?}")).collect::<Vec<_>>();
+                write!(f, "sources for targets {}", targets.join(", "))
+            }
         }
     }
 }
@@ -75,6 +101,12 @@ pub enum WasiFilesMount {
         source: String,
         /// `destination = "/"`
         destination: String,
+        /// `writable = false`
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        writable: Option<bool>,
+        /// `max_bytes = 10485760`
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        max_bytes: Option<u64>,
     },
 }
 
@@ -90,6 +122,9 @@ pub struct ComponentBuildConfig {
     /// watch = ["src/**/*.rs"]
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub watch: Vec<String>,
+    /// `depends_on = ["shared-lib"]`: components to build before this one
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub depends_on: Vec<String>,
 }
 
 impl ComponentBuildConfig {
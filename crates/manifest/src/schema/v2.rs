@@ -2,7 +2,10 @@ use anyhow::Context;
 use serde::{Deserialize, Serialize};
 use spin_serde::{DependencyName, DependencyPackageName, FixedVersion, LowerSnakeId};
 pub use spin_serde::{KebabId, SnakeId};
-use std::path::PathBuf;
+use std::{
+    num::{NonZeroU64, NonZeroUsize},
+    path::PathBuf,
+};
 
 pub use super::common::{ComponentBuildConfig, ComponentSource, Variable, WasiFilesMount};
 
@@ -56,6 +59,18 @@ pub struct AppDetails {
     /// `authors = ["author@example.com"]`
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub authors: Vec<String>,
+    /// `labels = { team = "payments" }`
+    #[serde(default, skip_serializing_if = "Map::is_empty")]
+    pub labels: Map<String, String>,
+    /// `on_startup = { component = "warm-cache" }`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_startup: Option<LifecycleHook>,
+    /// `on_shutdown = { component = "drain-connections" }`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_shutdown: Option<LifecycleHook>,
+    /// `sqlite_migrations = { default = "migrations" }`
+    #[serde(default, skip_serializing_if = "Map::is_empty")]
+    pub sqlite_migrations: Map<String, String>,
     /// `[application.triggers.<type>]`
     #[serde(rename = "trigger", default, skip_serializing_if = "Map::is_empty")]
     pub trigger_global_configs: Map<String, toml::Table>,
@@ -64,6 +79,37 @@ pub struct AppDetails {
     pub tool: Map<String, toml::Table>,
 }
 
+/// A component run by the host at a point in the app's lifecycle
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LifecycleHook {
+    /// `component = "component-id"`
+    pub component: KebabId,
+    /// `timeout_secs = 30`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_secs: Option<u64>,
+    /// `on_failure = "continue"`
+    #[serde(default, skip_serializing_if = "HookFailurePolicy::is_default")]
+    pub on_failure: HookFailurePolicy,
+}
+
+/// What the host does when a lifecycle hook fails or times out
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HookFailurePolicy {
+    /// `on_failure = "fail"`: stop the app
+    #[default]
+    Fail,
+    /// `on_failure = "continue"`: log the failure and carry on
+    Continue,
+}
+
+impl HookFailurePolicy {
+    fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
 /// Trigger configuration
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Trigger {
@@ -161,6 +207,11 @@ pub struct Component {
     /// `environment = { VAR = "value" }`
     #[serde(default, skip_serializing_if = "Map::is_empty")]
     pub environment: Map<String, String>,
+    /// `environment_passthrough = ["DATABASE_URL", "OTEL_ENDPOINT"]`: host
+    /// environment variables passed through to the component, by name. Each
+    /// must be allowed by the runtime config or `--allow-env-passthrough`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub environment_passthrough: Vec<String>,
     /// `files = [...]`
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub files: Vec<WasiFilesMount>,
@@ -180,6 +231,10 @@ pub struct Component {
         skip_serializing_if = "Vec::is_empty"
     )]
     pub key_value_stores: Vec<String>,
+    /// `key_value_namespace = "cart"`: keys the component uses are kept
+    /// separate from those of other components sharing its stores
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_value_namespace: Option<String>,
     /// `sqlite_databases = ["default", "my-database"]`
     #[serde(
         default,
@@ -190,6 +245,15 @@ pub struct Component {
     /// `ai_models = ["llama2-chat"]`
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub ai_models: Vec<KebabId>,
+    /// `precompile = "lazy"`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub precompile: Option<Precompile>,
+    /// `concurrency = { max_instances = 10, queue_timeout_ms = 500 }`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub concurrency: Option<ConcurrencyLimit>,
+    /// `[component.<id>.instance]`: whether instances are kept between requests
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub instance: Option<InstanceConfig>,
     /// Build configuration
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub build: Option<ComponentBuildConfig>,
@@ -202,6 +266,91 @@ pub struct Component {
     /// Component dependencies
     #[serde(default, skip_serializing_if = "ComponentDependencies::is_empty")]
     pub dependencies: ComponentDependencies,
+    /// `middleware = ["auth", "compress"]`: components which handle requests
+    /// before they reach this component, and its responses after, outermost
+    /// first. Middleware runs with this component's configuration, so may not
+    /// declare its own
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub middleware: Vec<KebabId>,
+    /// `[component.<id>.features.<name>]`
+    #[serde(default, skip_serializing_if = "Map::is_empty")]
+    pub features: Map<String, ComponentFeature>,
+}
+
+/// Component settings that apply only when a feature is enabled, e.g. with
+/// `spin up --feature <name>`
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ComponentFeature {
+    /// `environment = { LOG_LEVEL = "debug" }`: added to the component's
+    /// environment, replacing any variables of the same name
+    #[serde(default, skip_serializing_if = "Map::is_empty")]
+    pub environment: Map<String, String>,
+    /// `files = [...]`: mounted in addition to the component's files
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub files: Vec<WasiFilesMount>,
+    /// `dependencies = { "foo:bar" = ">= 0.1.0" }`: added to the component's
+    /// dependencies, replacing any of the same name
+    #[serde(default, skip_serializing_if = "ComponentDependencies::is_empty")]
+    pub dependencies: ComponentDependencies,
+}
+
+/// When a component should be compiled and kept ready for execution
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Precompile {
+    /// `"eager"`: compiled when the application is loaded (the default)
+    #[default]
+    Eager,
+    /// `"lazy"`: compiled on first use, then kept ready
+    Lazy,
+    /// `"never"`: compiled on every use and never kept ready
+    Never,
+}
+
+/// A limit on how many instances of a component run at once
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConcurrencyLimit {
+    /// `max_instances = 10`
+    pub max_instances: NonZeroUsize,
+    /// `queue_timeout_ms = 500`: how long an event waits for an instance
+    /// before it is rejected; if unset, events wait indefinitely
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub queue_timeout_ms: Option<u64>,
+}
+
+/// Whether a component's instances are kept to handle more than one request
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct InstanceConfig {
+    /// `lifetime = "pooled"`
+    #[serde(default)]
+    pub lifetime: InstanceLifetime,
+    /// `max_age_secs = 600`: how long a pooled instance is used before it is
+    /// replaced; if unset, instances are used until they fail
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_age_secs: Option<u64>,
+    /// `max_requests = 1000`: how many requests a pooled instance handles
+    /// before it is replaced; if unset, there is no limit
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_requests: Option<NonZeroU64>,
+    /// `max_idle_instances = 4`: how many pooled instances are kept while
+    /// they are idle; defaults to 1
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_idle_instances: Option<NonZeroUsize>,
+}
+
+/// How long a component's instances live
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum InstanceLifetime {
+    /// `"per-request"`: a new instance handles each request (the default)
+    #[default]
+    PerRequest,
+    /// `"pooled"`: instances are kept after handling a request, to handle
+    /// later ones. Only `wasi:http` components' instances can be pooled.
+    Pooled,
 }
 
 /// Component dependencies
@@ -379,13 +528,6 @@ impl Component {
     pub fn normalized_allowed_outbound_hosts(&self) -> anyhow::Result<Vec<String>> {
         let normalized =
             crate::compat::convert_allowed_http_to_allowed_hosts(&self.allowed_http_hosts, false)?;
-        if !normalized.is_empty() {
-            terminal::warn!(
-                "Use of the deprecated field `allowed_http_hosts` - to fix, \
-            replace `allowed_http_hosts` with `allowed_outbound_hosts = {normalized:?}`",
-            )
-        }
-
         Ok(self
             .allowed_outbound_hosts
             .iter()
@@ -536,17 +678,24 @@ mod tests {
             description: "".to_string(),
             variables: Map::new(),
             environment: Map::new(),
+            environment_passthrough: vec![],
             files: vec![],
             exclude_files: vec![],
             allowed_http_hosts: vec![],
             allowed_outbound_hosts: vec![],
             key_value_stores: labels.clone(),
+            key_value_namespace: None,
             sqlite_databases: labels,
             ai_models: vec![],
+            precompile: None,
+            concurrency: None,
+            instance: None,
             build: None,
             tool: Map::new(),
             dependencies_inherit_configuration: false,
             dependencies: Default::default(),
+            middleware: vec![],
+            features: Map::new(),
         }
     }
 
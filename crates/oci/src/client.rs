@@ -4,25 +4,34 @@ use std::collections::{BTreeMap, HashMap};
 use std::path::{Path, PathBuf};
 
 use anyhow::{bail, Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
 use docker_credential::DockerCredential;
 use futures_util::future;
 use futures_util::stream::{self, StreamExt, TryStreamExt};
 use itertools::Itertools;
 use oci_distribution::{
-    client::ImageLayer, config::ConfigFile, manifest::OciImageManifest, secrets::RegistryAuth,
-    token_cache::RegistryTokenType, Reference, RegistryOperation,
+    client::ImageLayer,
+    config::ConfigFile,
+    errors::{OciDistributionError, OciErrorCode},
+    manifest::{OciDescriptor, OciImageManifest, OCI_IMAGE_MEDIA_TYPE},
+    secrets::RegistryAuth,
+    token_cache::RegistryTokenType,
+    Reference, RegistryOperation,
 };
 use reqwest::Url;
 use spin_common::sha256;
 use spin_common::ui::quoted_path;
 use spin_common::url::parse_file_url;
-use spin_loader::cache::Cache;
-use spin_loader::FilesMountStrategy;
+use spin_loader::cache::{write_atomically, Cache};
+use spin_loader::{FilesMountStrategy, ManifestLoadOptions};
 use spin_locked_app::locked::{ContentPath, ContentRef, LockedApp};
+use spin_locked_app::APP_LABELS_KEY;
 use tokio::fs;
 use walkdir::WalkDir;
 
 use crate::auth::AuthConfig;
+use crate::download::{self, BlobRequest, RateLimiter};
+use crate::mirrors::{Mirror, RegistryMirrors};
 
 // TODO: the media types for application, data and archive layer are not final
 /// Media type for a layer representing a locked Spin application configuration
@@ -34,8 +43,13 @@ pub const ARCHIVE_MEDIATYPE: &str = "application/vnd.wasm.content.bundle.v1.tar+
 // Note: this will be updated with a canonical value once defined upstream
 const WASM_LAYER_MEDIA_TYPE: &str = "application/vnd.wasm.content.layer.v1+wasm";
 
+/// Media type for the empty config of an attachment artifact
+const EMPTY_CONFIG_MEDIA_TYPE: &str = "application/vnd.oci.empty.v1+json";
+/// Annotation recording the digest of the application an attachment refers to
+const ATTACHMENT_SUBJECT_ANNOTATION: &str = "com.fermyon.spin.attachment.subject";
+
 const CONFIG_FILE: &str = "config.json";
-const LATEST_TAG: &str = "latest";
+pub(crate) const LATEST_TAG: &str = "latest";
 const MANIFEST_FILE: &str = "manifest.json";
 
 /// Env var to force use of archive layers when publishing a Spin app
@@ -73,8 +87,51 @@ pub struct Client {
     pub cache: Cache,
     /// Underlying OCI client.
     oci: oci_distribution::Client,
+    /// HTTP client for downloading layers directly, so that downloads can be
+    /// resumed.
+    http: reqwest::Client,
+    /// The scheme registries are connected to with.
+    scheme: &'static str,
     /// Client options
     pub opts: ClientOpts,
+    /// Mirrors to pull applications through.
+    mirrors: RegistryMirrors,
+    /// The clients for each mirror, by endpoint.
+    mirror_clients: HashMap<String, MirrorClient>,
+}
+
+/// The clients for a registry mirror, which may have its own TLS settings.
+struct MirrorClient {
+    oci: oci_distribution::Client,
+    http: reqwest::Client,
+    scheme: &'static str,
+}
+
+/// Where to pull a reference from: a registry mirror, or the registry itself.
+struct PullSource<'a> {
+    oci: &'a oci_distribution::Client,
+    http: &'a reqwest::Client,
+    scheme: &'static str,
+    reference: Reference,
+    auth: RegistryAuth,
+}
+
+impl PullSource<'_> {
+    /// The `Authorization` header for downloading blobs from the source.
+    async fn authorization(&self) -> Result<Option<String>> {
+        let token = self
+            .oci
+            .auth(&self.reference, &self.auth, RegistryOperation::Pull)
+            .await?;
+        Ok(match (token, &self.auth) {
+            (Some(token), _) => Some(format!("Bearer {token}")),
+            (None, RegistryAuth::Basic(username, password)) => Some(format!(
+                "Basic {}",
+                STANDARD.encode(format!("{username}:{password}"))
+            )),
+            (None, _) => None,
+        })
+    }
 }
 
 #[derive(Clone)]
@@ -82,6 +139,14 @@ pub struct Client {
 pub struct ClientOpts {
     /// Inline content into ContentRef iff < this size.
     pub content_ref_inline_max_size: usize,
+    /// The most bytes per second each pull may download, across all its
+    /// layers. Defaults to the `SPIN_OCI_PULL_BANDWIDTH_LIMIT` env var, if
+    /// set. The `SPIN_OCI_BANDWIDTH_LIMIT` env var limits all pulls together.
+    pub pull_bandwidth_limit: Option<u64>,
+    /// How many times to retry downloading a layer, resuming where the
+    /// previous attempt got to. Defaults to the `SPIN_OCI_DOWNLOAD_RETRIES`
+    /// env var, if set.
+    pub download_retries: u32,
 }
 
 /// Controls whether predefined annotations are generated when pushing an application.
@@ -95,22 +160,99 @@ pub enum InferPredefinedAnnotations {
     None,
 }
 
+/// The kind of a document attached to a pushed application.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AttachmentKind {
+    /// A software bill of materials.
+    Sbom,
+    /// A provenance attestation.
+    Provenance,
+    /// A signature over the application manifest.
+    Signature,
+}
+
+impl AttachmentKind {
+    /// The suffix of the tag the attachment is stored under, following the
+    /// "tag schema" fallback for registries without the OCI referrers API.
+    fn tag_suffix(&self) -> &'static str {
+        match self {
+            Self::Sbom => "sbom",
+            Self::Provenance => "att",
+            Self::Signature => "sig",
+        }
+    }
+
+    /// The artifact type of the attachment manifest, by which the OCI
+    /// referrers API filters the attachments of an application.
+    fn artifact_type(&self) -> &'static str {
+        match self {
+            Self::Sbom => "application/vnd.fermyon.spin.sbom.v1",
+            Self::Provenance => "application/vnd.fermyon.spin.provenance.v1",
+            Self::Signature => "application/vnd.fermyon.spin.signature.v1",
+        }
+    }
+}
+
+/// A document, such as an SBOM or provenance attestation, attached to a
+/// pushed application.
+#[derive(Clone, Debug)]
+pub struct Attachment {
+    /// The kind of document.
+    pub kind: AttachmentKind,
+    /// The media type of the document (e.g. "application/spdx+json").
+    pub media_type: String,
+    /// The document content.
+    pub data: Vec<u8>,
+    /// Annotations on the document layer (e.g. the signature of a signed payload).
+    pub annotations: BTreeMap<String, String>,
+}
+
 impl Client {
     /// Create a new instance of an OCI client for distributing Spin applications.
     pub async fn new(insecure: bool, cache_root: Option<PathBuf>) -> Result<Self> {
         let client = oci_distribution::Client::new(Self::build_config(insecure));
         let cache = Cache::new(cache_root).await?;
+        let pull_bandwidth_limit = match std::env::var(download::SPIN_OCI_PULL_BANDWIDTH_LIMIT_OPT)
+        {
+            Ok(limit) => Some(download::parse_bandwidth(&limit).with_context(|| {
+                format!("invalid {}", download::SPIN_OCI_PULL_BANDWIDTH_LIMIT_OPT)
+            })?),
+            Err(_) => None,
+        };
+        let download_retries = match std::env::var(download::SPIN_OCI_DOWNLOAD_RETRIES_OPT) {
+            Ok(retries) => retries
+                .parse()
+                .with_context(|| format!("invalid {}", download::SPIN_OCI_DOWNLOAD_RETRIES_OPT))?,
+            Err(_) => download::DEFAULT_DOWNLOAD_RETRIES,
+        };
         let opts = ClientOpts {
             content_ref_inline_max_size: DEFAULT_CONTENT_REF_INLINE_MAX_SIZE,
+            pull_bandwidth_limit,
+            download_retries,
         };
 
         Ok(Self {
             oci: client,
+            http: reqwest::Client::new(),
+            scheme: if insecure { "http" } else { "https" },
             cache,
             opts,
+            mirrors: Default::default(),
+            mirror_clients: Default::default(),
         })
     }
 
+    /// Pull applications through the given registry mirrors, falling back to
+    /// the registry an application is in if none of its mirrors have it.
+    pub fn with_mirrors(mut self, mirrors: RegistryMirrors) -> Result<Self> {
+        for mirror in mirrors.all() {
+            self.mirror_clients
+                .insert(mirror.endpoint.clone(), Self::mirror_client(mirror)?);
+        }
+        self.mirrors = mirrors;
+        Ok(self)
+    }
+
     /// Push a Spin application to an OCI registry and return the digest (or None
     /// if the digest cannot be determined).
     pub async fn push(
@@ -119,6 +261,26 @@ impl Client {
         reference: impl AsRef<str>,
         annotations: Option<BTreeMap<String, String>>,
         infer_annotations: InferPredefinedAnnotations,
+    ) -> Result<Option<String>> {
+        self.push_with_options(
+            manifest_path,
+            ManifestLoadOptions::default(),
+            reference,
+            annotations,
+            infer_annotations,
+        )
+        .await
+    }
+
+    /// Like [`Self::push`], loading the application manifest with the given
+    /// options, e.g. to apply an environment's overlay.
+    pub async fn push_with_options(
+        &mut self,
+        manifest_path: &Path,
+        load_options: ManifestLoadOptions,
+        reference: impl AsRef<str>,
+        annotations: Option<BTreeMap<String, String>>,
+        infer_annotations: InferPredefinedAnnotations,
     ) -> Result<Option<String>> {
         let reference: Reference = reference
             .as_ref()
@@ -130,10 +292,11 @@ impl Client {
         // Create a locked application from the application manifest.
         // TODO: We don't need an extra copy here for each asset to prepare the application.
         // We should be able to use assets::collect instead when constructing the locked app.
-        let locked = spin_loader::from_file(
+        let locked = spin_loader::from_file_with_options(
             manifest_path,
             FilesMountStrategy::Copy(working_dir.path().into()),
             None,
+            load_options,
         )
         .await?;
 
@@ -199,7 +362,14 @@ impl Client {
         let config_layer_digest = locked_config_layer.sha256_digest().clone();
         layers.push(locked_config_layer);
 
-        let mut labels = HashMap::new();
+        // Application labels from the manifest are carried on the image config,
+        // but may not override labels that Spin itself relies on.
+        let mut labels: HashMap<String, String> = locked_app
+            .get_metadata(APP_LABELS_KEY)
+            .context("invalid application labels")?
+            .unwrap_or_default()
+            .into_iter()
+            .collect();
         labels.insert(
             "com.fermyon.spin.lockedAppDigest".to_string(),
             config_layer_digest,
@@ -240,6 +410,303 @@ impl Client {
         Ok(digest)
     }
 
+    /// Attach a document to the application with the given digest, previously
+    /// pushed to the repository of the given reference. The attachment refers
+    /// to the application as its subject, so that registries list it through
+    /// the OCI referrers API, and is also tagged for registries without that
+    /// API. Returns the digest of the attachment artifact (or None if the
+    /// digest cannot be determined).
+    pub async fn push_attachment(
+        &mut self,
+        reference: impl AsRef<str>,
+        subject_digest: &str,
+        attachment: &Attachment,
+    ) -> Result<Option<String>> {
+        let reference: Reference = reference
+            .as_ref()
+            .parse()
+            .with_context(|| format!("cannot parse reference {}", reference.as_ref()))?;
+        let auth = Self::auth(&reference).await?;
+        let attachment_reference =
+            attachment_reference(&reference, subject_digest, attachment.kind)?;
+
+        let layer_annotations =
+            (!attachment.annotations.is_empty()).then(|| attachment.annotations.clone());
+        let layers = vec![ImageLayer::new(
+            attachment.data.clone(),
+            attachment.media_type.clone(),
+            layer_annotations,
+        )];
+        let config = oci_distribution::client::Config::new(
+            b"{}".to_vec(),
+            EMPTY_CONFIG_MEDIA_TYPE.to_string(),
+            None,
+        );
+        let annotations = BTreeMap::from([(
+            ATTACHMENT_SUBJECT_ANNOTATION.to_string(),
+            subject_digest.to_string(),
+        )]);
+        let mut manifest = OciImageManifest::build(&layers, &config, Some(annotations));
+        manifest.artifact_type = Some(attachment.kind.artifact_type().to_string());
+        manifest.subject = Some(
+            self.subject_descriptor(&reference, subject_digest, &auth)
+                .await?,
+        );
+
+        let response = self
+            .oci
+            .push(
+                &attachment_reference,
+                &layers,
+                config,
+                &auth,
+                Some(manifest),
+            )
+            .await
+            .map(|push_response| push_response.manifest_url)
+            .with_context(|| format!("cannot push {:?} attachment", attachment.kind))?;
+
+        tracing::info!("Pushed attachment {:?}", response);
+
+        Ok(digest_from_url(&response))
+    }
+
+    /// The descriptor of the application manifest with the given digest, for
+    /// an attachment to refer to as its subject.
+    async fn subject_descriptor(
+        &self,
+        reference: &Reference,
+        subject_digest: &str,
+        auth: &RegistryAuth,
+    ) -> Result<OciDescriptor> {
+        let subject_reference = digest_reference(reference, subject_digest);
+        let (manifest, media_type) = self
+            .oci
+            .pull_manifest_raw(&subject_reference, auth, &[OCI_IMAGE_MEDIA_TYPE])
+            .await
+            .with_context(|| format!("cannot pull manifest of {subject_reference}"))?;
+        Ok(OciDescriptor {
+            media_type,
+            digest: subject_digest.to_string(),
+            size: manifest.len().try_into()?,
+            ..Default::default()
+        })
+    }
+
+    /// Pull the document of the given kind attached to the application at the
+    /// given reference, if there is one.
+    pub async fn pull_attachment(
+        &mut self,
+        reference: impl AsRef<str>,
+        kind: AttachmentKind,
+    ) -> Result<Option<Attachment>> {
+        let reference: Reference = reference
+            .as_ref()
+            .parse()
+            .with_context(|| format!("cannot parse reference {}", reference.as_ref()))?;
+        let (source, _, subject_digest) = self.pull_image_manifest(&reference).await?;
+        let attachments = self
+            .pull_attachments_core(&source, &subject_digest, kind)
+            .await?;
+        Ok(attachments.into_iter().next())
+    }
+
+    /// Pull all documents of the given kind attached to the application with
+    /// the given digest in the repository of the given reference.
+    pub async fn pull_attachments(
+        &mut self,
+        reference: impl AsRef<str>,
+        subject_digest: &str,
+        kind: AttachmentKind,
+    ) -> Result<Vec<Attachment>> {
+        let reference: Reference = reference
+            .as_ref()
+            .parse()
+            .with_context(|| format!("cannot parse reference {}", reference.as_ref()))?;
+
+        // Mirrors may not have attachments, so look in each source until
+        // one does.
+        for source in self.pull_sources(&reference).await? {
+            let attachments = self
+                .pull_attachments_core(&source, subject_digest, kind)
+                .await?;
+            if !attachments.is_empty() {
+                return Ok(attachments);
+            }
+        }
+        Ok(vec![])
+    }
+
+    async fn pull_attachments_core(
+        &self,
+        source: &PullSource<'_>,
+        subject_digest: &str,
+        kind: AttachmentKind,
+    ) -> Result<Vec<Attachment>> {
+        let reference = &source.reference;
+
+        // Look for referrers first, falling back to the tag schema for
+        // registries without the referrers API and attachments pushed before
+        // they referred to their subject.
+        let subject_reference = digest_reference(reference, subject_digest);
+        match source
+            .oci
+            .pull_referrers(&subject_reference, Some(kind.artifact_type()))
+            .await
+        {
+            Ok(index) => {
+                let mut attachments = vec![];
+                for referrer in &index.manifests {
+                    let referrer_reference = digest_reference(reference, &referrer.digest);
+                    let (manifest, _) = source
+                        .oci
+                        .pull_image_manifest(&referrer_reference, &source.auth)
+                        .await
+                        .with_context(|| {
+                            format!("cannot pull {kind:?} attachment {referrer_reference}")
+                        })?;
+                    // Registries may not apply the artifact type filter.
+                    if manifest.artifact_type.as_deref() == Some(kind.artifact_type()) {
+                        attachments
+                            .extend(self.pull_attachment_layers(source, &manifest, kind).await?);
+                    }
+                }
+                if !attachments.is_empty() {
+                    return Ok(attachments);
+                }
+            }
+            Err(e) if is_referrers_unsupported(&e) => {
+                tracing::debug!("No referrers API for {reference}, falling back to tags: {e}");
+            }
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| format!("cannot list {kind:?} attachments for {reference}"))
+            }
+        }
+
+        let attachment_reference = attachment_reference(reference, subject_digest, kind)?;
+        let manifest = match source
+            .oci
+            .pull_image_manifest(&attachment_reference, &source.auth)
+            .await
+        {
+            Ok((manifest, _)) => manifest,
+            Err(e) if is_manifest_not_found(&e) => {
+                tracing::debug!("No {kind:?} attachment found for {reference}: {e}");
+                return Ok(vec![]);
+            }
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| format!("cannot pull {kind:?} attachment for {reference}"))
+            }
+        };
+        self.pull_attachment_layers(source, &manifest, kind).await
+    }
+
+    async fn pull_attachment_layers(
+        &self,
+        source: &PullSource<'_>,
+        manifest: &OciImageManifest,
+        kind: AttachmentKind,
+    ) -> Result<Vec<Attachment>> {
+        if manifest.layers.is_empty() {
+            bail!(
+                "{kind:?} attachment for {} has no content",
+                source.reference
+            );
+        }
+
+        let mut attachments = Vec::with_capacity(manifest.layers.len());
+        for layer in &manifest.layers {
+            let data = self.pull_blob(source, layer, None).await?;
+            attachments.push(Attachment {
+                kind,
+                media_type: layer.media_type.clone(),
+                data,
+                annotations: layer.annotations.clone().unwrap_or_default(),
+            });
+        }
+        Ok(attachments)
+    }
+
+    /// Push a single-layer artifact, such as a cached build output, to the
+    /// given reference.
+    pub async fn push_artifact(
+        &mut self,
+        reference: impl AsRef<str>,
+        media_type: &str,
+        data: Vec<u8>,
+    ) -> Result<()> {
+        let reference: Reference = reference
+            .as_ref()
+            .parse()
+            .with_context(|| format!("cannot parse reference {}", reference.as_ref()))?;
+        let auth = Self::auth(&reference).await?;
+
+        let layers = vec![ImageLayer::new(data, media_type.to_string(), None)];
+        let config = oci_distribution::client::Config::new(
+            b"{}".to_vec(),
+            EMPTY_CONFIG_MEDIA_TYPE.to_string(),
+            None,
+        );
+        let manifest = OciImageManifest::build(&layers, &config, None);
+
+        self.oci
+            .push(&reference, &layers, config, &auth, Some(manifest))
+            .await
+            .with_context(|| format!("cannot push artifact {reference}"))?;
+        tracing::info!("Pushed artifact {reference}");
+        Ok(())
+    }
+
+    /// Pull the content of a single-layer artifact pushed with
+    /// [`Client::push_artifact`], if the reference exists.
+    pub async fn pull_artifact(&mut self, reference: impl AsRef<str>) -> Result<Option<Vec<u8>>> {
+        let reference: Reference = reference
+            .as_ref()
+            .parse()
+            .with_context(|| format!("cannot parse reference {}", reference.as_ref()))?;
+        let auth = Self::auth(&reference).await?;
+
+        let manifest = match self.oci.pull_image_manifest(&reference, &auth).await {
+            Ok((manifest, _)) => manifest,
+            Err(e) if is_manifest_not_found(&e) => {
+                tracing::debug!("No artifact found at {reference}: {e}");
+                return Ok(None);
+            }
+            Err(e) => {
+                return Err(e).with_context(|| format!("cannot pull artifact {reference}"));
+            }
+        };
+        let [layer] = manifest.layers.as_slice() else {
+            bail!("artifact {reference} should have exactly one layer");
+        };
+        let source = PullSource {
+            oci: &self.oci,
+            http: &self.http,
+            scheme: self.scheme,
+            reference,
+            auth,
+        };
+        let data = self.pull_blob(&source, layer, None).await?;
+        Ok(Some(data))
+    }
+
+    /// Get the annotations of the previously pulled application at the given
+    /// reference.
+    pub async fn annotations(
+        &self,
+        reference: impl AsRef<str>,
+    ) -> Result<BTreeMap<String, String>> {
+        let manifest_path = self.manifest_path(reference).await?;
+        let manifest_json = fs::read(&manifest_path)
+            .await
+            .with_context(|| format!("cannot read OCI manifest {}", quoted_path(&manifest_path)))?;
+        let manifest: OciImageManifest =
+            serde_json::from_slice(&manifest_json).context("cannot parse OCI manifest")?;
+        Ok(manifest.annotations.unwrap_or_default())
+    }
+
     /// Assemble ImageLayers for a locked application using the provided
     /// AssemblyMode and return the resulting Vec<ImageLayer>.
     async fn assemble_layers(
@@ -292,16 +759,30 @@ impl Client {
                     .context("file mount loaded from disk should contain a file source")?;
                 let source = parse_file_url(source.as_str())?;
 
+                // A mount with its own options is recorded as an entry with
+                // no content, so that it can be mounted separately when the
+                // app is pulled. Its files are recorded under its path.
+                let dest = f.path.strip_prefix("/").unwrap_or(&f.path).to_owned();
+                if f.writable.is_some() || f.max_bytes.is_some() {
+                    files.push(ContentPath {
+                        content: ContentRef::default(),
+                        path: f.path.clone(),
+                        writable: f.writable,
+                        max_bytes: f.max_bytes,
+                        integrity: Default::default(),
+                    });
+                }
+
                 match assembly_mode {
                     AssemblyMode::Archive => self
-                        .push_archive_layer(&source, &mut files, &mut layers)
+                        .push_archive_layer(&source, &dest, &mut files, &mut layers)
                         .await
                         .context(format!(
                             "cannot push archive layer for source {}",
                             quoted_path(&source)
                         ))?,
                     AssemblyMode::Simple => self
-                        .push_file_layers(&source, &mut files, &mut layers)
+                        .push_file_layers(&source, &dest, &mut files, &mut layers)
                         .await
                         .context(format!(
                             "cannot push file layers for source {}",
@@ -323,10 +804,12 @@ impl Client {
     }
 
     /// Archive all of the files recursively under the source directory
-    /// and push as a compressed archive layer
+    /// and push as a compressed archive layer, recording the files under
+    /// the given destination
     async fn push_archive_layer(
         &mut self,
         source: &PathBuf,
+        dest: &Path,
         files: &mut Vec<ContentPath>,
         layers: &mut Vec<ImageLayer>,
     ) -> Result<()> {
@@ -337,14 +820,17 @@ impl Client {
                 continue;
             }
             // Can unwrap because we got to 'entry' from walking 'source'
-            let rel_path = entry.path().strip_prefix(source).unwrap();
+            let rel_path = dest.join(entry.path().strip_prefix(source).unwrap());
             tracing::trace!("Adding asset {rel_path:?} to component files list");
             // Add content/path to the locked component files list
             let layer = Self::data_layer(entry.path(), DATA_MEDIATYPE.to_string()).await?;
             let content = self.content_ref_for_layer(&layer);
             files.push(ContentPath {
                 content,
-                path: rel_path.into(),
+                path: rel_path,
+                writable: None,
+                max_bytes: None,
+                integrity: Default::default(),
             });
         }
 
@@ -362,10 +848,12 @@ impl Client {
         Ok(())
     }
 
-    /// Recursively traverse the source directory and add layers for each file.
+    /// Recursively traverse the source directory and add layers for each file,
+    /// recording the files under the given destination.
     async fn push_file_layers(
         &mut self,
         source: &PathBuf,
+        dest: &Path,
         files: &mut Vec<ContentPath>,
         layers: &mut Vec<ImageLayer>,
     ) -> Result<()> {
@@ -378,10 +866,10 @@ impl Client {
                 continue;
             }
             // Can unwrap because we got to 'entry' from walking 'source'
-            let rel_path = entry.path().strip_prefix(source).unwrap();
+            let rel_path = dest.join(entry.path().strip_prefix(source).unwrap());
             // Paths must be in portable (forward slash) format in the registry,
             // so that they can be placed correctly on any host system
-            let rel_path = portable_path(rel_path);
+            let rel_path = portable_path(&rel_path);
 
             tracing::trace!("Adding new layer for asset {rel_path:?}");
             // Construct and push layer, adding its digest to the locked component files Vec
@@ -391,6 +879,9 @@ impl Client {
             files.push(ContentPath {
                 content,
                 path: rel_path,
+                writable: None,
+                max_bytes: None,
+                integrity: Default::default(),
             });
             // As a workaround for OCI implementations that don't support very small blobs,
             // don't push very small content that has been inlined into the manifest:
@@ -403,37 +894,65 @@ impl Client {
         Ok(())
     }
 
-    /// Pull a Spin application from an OCI registry.
-    pub async fn pull(&mut self, reference: &str) -> Result<()> {
+    /// Pull a Spin application from an OCI registry and return the digest of
+    /// its manifest.
+    pub async fn pull(&mut self, reference: &str) -> Result<String> {
         let reference: Reference = reference.parse().context("cannot parse reference")?;
-        let auth = Self::auth(&reference).await?;
 
-        // Pull the manifest from the registry.
-        let (manifest, digest) = self.oci.pull_image_manifest(&reference, &auth).await?;
+        // If another process is pulling the same reference, wait for it to
+        // finish, so that the layers it pulled are taken from the cache.
+        let mut pull_lock = self.cache.entry_lock(reference.whole())?;
+        let _pull_guard = pull_lock.write().await?;
+
+        // Pull from each mirror of the registry in turn, and then the registry
+        // itself. Layers pulled from a mirror which then fails are cached, so
+        // aren't pulled again from the next source.
+        let mut sources = self.pull_sources(&reference).await?;
+        // The registry itself is always the last source.
+        let registry = sources.pop().context("no source to pull from")?;
+        for source in sources {
+            match self.pull_from(&reference, &source).await {
+                Ok(digest) => return Ok(digest),
+                Err(e) => tracing::warn!(
+                    "Cannot pull {reference} from mirror {}, trying next source: {e:#}",
+                    source.reference.registry()
+                ),
+            }
+        }
+        self.pull_from(&reference, &registry).await
+    }
+
+    /// Pull a Spin application from one of its sources and return the digest
+    /// of its manifest.
+    async fn pull_from(&self, reference: &Reference, source: &PullSource<'_>) -> Result<String> {
+        let (manifest, digest) = source
+            .oci
+            .pull_image_manifest(&source.reference, &source.auth)
+            .await?;
 
         let manifest_json = serde_json::to_string(&manifest)?;
         tracing::debug!("Pulled manifest: {}", manifest_json);
 
         // Write the manifest in `<cache_root>/registry/oci/manifests/repository:<tag_or_latest>/manifest.json`
         let m = self.manifest_path(&reference.to_string()).await?;
-        fs::write(&m, &manifest_json).await?;
+        write_atomically(&m, manifest_json.as_bytes()).await?;
 
         // Older published Spin apps feature the locked app config *as* the OCI manifest config layer,
         // while newer versions publish the locked app config as a generic layer alongside others.
         // Assume that these bytes may represent the locked app config and write it as such.
-        let mut cfg_bytes = Vec::new();
-        self.oci
-            .pull_blob(&reference, &manifest.config, &mut cfg_bytes)
-            .await?;
+        let cfg_bytes = self.pull_blob(source, &manifest.config, None).await?;
         self.write_locked_app_config(&reference.to_string(), &cfg_bytes)
             .await
             .context("unable to write locked app config to cache")?;
 
+        let limiter = self.opts.pull_bandwidth_limit.map(RateLimiter::new);
+
         // If a layer is a Wasm module, write it in the Wasm directory.
         // Otherwise, write it in the data directory (after unpacking if archive layer)
         stream::iter(manifest.layers)
             .map(|layer| {
                 let this = &self;
+                let limiter = limiter.as_ref();
                 let reference = reference.clone();
                 async move {
                     // Skip pulling if the digest already exists in the wasm or data directories.
@@ -445,8 +964,7 @@ impl Client {
                     }
 
                     tracing::debug!("Pulling layer {}", &layer.digest);
-                    let mut bytes = Vec::with_capacity(layer.size.try_into()?);
-                    this.oci.pull_blob(&reference, &layer, &mut bytes).await?;
+                    let bytes = this.pull_blob(source, &layer, limiter).await?;
                     match layer.media_type.as_str() {
                         SPIN_APPLICATION_MEDIA_TYPE => {
                             this.write_locked_app_config(&reference.to_string(), &bytes)
@@ -469,9 +987,98 @@ impl Client {
             .buffer_unordered(MAX_PARALLEL_PULL)
             .try_for_each(future::ok)
             .await?;
-        tracing::info!("Pulled {}@{}", reference, digest);
+        tracing::info!("Pulled {}@{}", source.reference, digest);
 
-        Ok(())
+        Ok(digest)
+    }
+
+    /// Pull the image manifest of the reference from the first of its
+    /// sources that has it, returning that source, the manifest and its
+    /// digest.
+    async fn pull_image_manifest(
+        &self,
+        reference: &Reference,
+    ) -> Result<(PullSource<'_>, OciImageManifest, String)> {
+        let mut sources = self.pull_sources(reference).await?;
+        // The registry itself is always the last source.
+        let registry = sources.pop().context("no source to pull from")?;
+        for source in sources {
+            match source
+                .oci
+                .pull_image_manifest(&source.reference, &source.auth)
+                .await
+            {
+                Ok((manifest, digest)) => return Ok((source, manifest, digest)),
+                Err(e) => tracing::warn!(
+                    "Cannot pull {reference} from mirror {}, trying next source: {e}",
+                    source.reference.registry()
+                ),
+            }
+        }
+        let (manifest, digest) = registry
+            .oci
+            .pull_image_manifest(&registry.reference, &registry.auth)
+            .await?;
+        Ok((registry, manifest, digest))
+    }
+
+    /// Download a blob, such as a layer, retrying with backoff if the
+    /// download fails, and resuming it where possible.
+    async fn pull_blob(
+        &self,
+        source: &PullSource<'_>,
+        layer: &OciDescriptor,
+        limiter: Option<&RateLimiter>,
+    ) -> Result<Vec<u8>> {
+        let request = BlobRequest {
+            http: source.http,
+            url: format!(
+                "{}://{}/v2/{}/blobs/{}",
+                source.scheme,
+                source.reference.resolve_registry(),
+                source.reference.repository(),
+                layer.digest
+            ),
+            digest: &layer.digest,
+            size: layer.size.try_into().ok(),
+            partial_path: self.cache.partial_path(&layer.digest),
+        };
+        download::download_blob(&request, self.opts.download_retries, limiter, || {
+            source.authorization()
+        })
+        .await
+        .with_context(|| format!("cannot pull blob {}", layer.digest))
+    }
+
+    /// Where to pull the reference from: each of its registry's mirrors, in
+    /// the order they are configured, and then the registry itself.
+    async fn pull_sources(&self, reference: &Reference) -> Result<Vec<PullSource<'_>>> {
+        let mut sources = vec![];
+        for mirror in self.mirrors.for_reference(reference) {
+            let Some(client) = self.mirror_clients.get(&mirror.endpoint) else {
+                continue;
+            };
+            let mirrored = mirror.mirrored(reference)?;
+            let auth = match mirror.auth() {
+                Some(auth) => auth,
+                None => Self::auth(&mirrored).await?,
+            };
+            sources.push(PullSource {
+                oci: &client.oci,
+                http: &client.http,
+                scheme: client.scheme,
+                reference: mirrored,
+                auth,
+            });
+        }
+        sources.push(PullSource {
+            oci: &self.oci,
+            http: &self.http,
+            scheme: self.scheme,
+            reference: reference.clone(),
+            auth: Self::auth(reference).await?,
+        });
+        Ok(sources)
     }
 
     /// Get the file path to an OCI manifest given a reference.
@@ -529,7 +1136,7 @@ impl Client {
         tracing::debug!("Pulled config: {}", cfg);
 
         let c = self.lockfile_path(reference).await?;
-        fs::write(&c, &cfg).await.map_err(anyhow::Error::from)
+        write_atomically(&c, cfg.as_bytes()).await
     }
 
     /// Create a new wasm layer based on a file.
@@ -667,6 +1274,41 @@ impl Client {
             ..Default::default()
         }
     }
+
+    /// Build the OCI client configuration for a registry mirror.
+    fn mirror_client(mirror: &Mirror) -> Result<MirrorClient> {
+        let mut config = Self::build_config(mirror.insecure());
+        let mut http =
+            reqwest::Client::builder().danger_accept_invalid_certs(mirror.skip_tls_verify);
+        if let Some(ca_cert_file) = &mirror.ca_cert_file {
+            let data = std::fs::read(ca_cert_file).with_context(|| {
+                format!(
+                    "cannot read CA certificates for registry mirror {} from {}",
+                    mirror.registry(),
+                    quoted_path(ca_cert_file)
+                )
+            })?;
+            for certificate in reqwest::Certificate::from_pem_bundle(&data)
+                .with_context(|| format!("invalid CA certificates {}", quoted_path(ca_cert_file)))?
+            {
+                http = http.add_root_certificate(certificate);
+            }
+            config
+                .extra_root_certificates
+                .push(oci_distribution::client::Certificate {
+                    encoding: oci_distribution::client::CertificateEncoding::Pem,
+                    data,
+                });
+        }
+        config.accept_invalid_certificates = mirror.skip_tls_verify;
+        Ok(MirrorClient {
+            oci: oci_distribution::Client::new(config),
+            http: http
+                .build()
+                .context("cannot create registry mirror client")?,
+            scheme: if mirror.insecure() { "http" } else { "https" },
+        })
+    }
 }
 
 /// Unpack contents of the provided archive layer, represented by bytes and its
@@ -721,6 +1363,33 @@ fn digest_from_url(manifest_url: &str) -> Option<String> {
     }
 }
 
+/// The reference under which an attachment of the given kind for the
+/// application with the given digest is stored, e.g. `repo:sha256-abc123.sbom`.
+fn attachment_reference(
+    reference: &Reference,
+    subject_digest: &str,
+    kind: AttachmentKind,
+) -> Result<Reference> {
+    let Some((algorithm, hex)) = subject_digest.split_once(':') else {
+        bail!("invalid digest {subject_digest:?}");
+    };
+    Ok(Reference::with_tag(
+        reference.registry().to_string(),
+        reference.repository().to_string(),
+        format!("{algorithm}-{hex}.{}", kind.tag_suffix()),
+    ))
+}
+
+/// The reference to the manifest with the given digest in the repository of
+/// the given reference.
+fn digest_reference(reference: &Reference, digest: &str) -> Reference {
+    Reference::with_digest(
+        reference.registry().to_string(),
+        reference.repository().to_string(),
+        digest.to_string(),
+    )
+}
+
 fn registry_from_input(server: impl AsRef<str>) -> String {
     // We want to allow a user to login to both https://ghcr.io and ghcr.io.
     let server = server.as_ref();
@@ -840,6 +1509,41 @@ fn fs_safe_segment(segment: &str) -> impl AsRef<Path> + '_ {
     segment
 }
 
+/// Whether an error pulling a manifest means that there is no such manifest,
+/// rather than that the registry couldn't be asked.
+fn is_manifest_not_found(e: &OciDistributionError) -> bool {
+    match e {
+        OciDistributionError::ImageManifestNotFoundError(_) => true,
+        OciDistributionError::RegistryError { envelope, .. } => {
+            envelope.errors.iter().any(|error| {
+                matches!(
+                    error.code,
+                    OciErrorCode::ManifestUnknown | OciErrorCode::NameUnknown
+                )
+            })
+        }
+        OciDistributionError::ServerError { code, .. } => *code == 404,
+        _ => false,
+    }
+}
+
+/// Whether an error listing referrers means that the registry doesn't
+/// support the referrers API, rather than that it couldn't be asked.
+fn is_referrers_unsupported(e: &OciDistributionError) -> bool {
+    match e {
+        OciDistributionError::ServerError { code, .. } if matches!(code, 400 | 405) => true,
+        OciDistributionError::RegistryError { envelope, .. }
+            if envelope
+                .errors
+                .iter()
+                .any(|error| matches!(error.code, OciErrorCode::Unsupported)) =>
+        {
+            true
+        }
+        _ => is_manifest_not_found(e),
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -854,6 +1558,38 @@ mod test {
         );
     }
 
+    #[test]
+    fn can_derive_attachment_reference() {
+        let reference: Reference = "ghcr.io/fermyon/app:1.0.0".parse().unwrap();
+        let digest = "sha256:0a867093096e0ef01ef749b12b6e7a90e4952eda107f89a676eeedce63a8361f";
+
+        let sbom = attachment_reference(&reference, digest, AttachmentKind::Sbom).unwrap();
+        assert_eq!("ghcr.io", sbom.registry());
+        assert_eq!("fermyon/app", sbom.repository());
+        assert_eq!(
+            Some("sha256-0a867093096e0ef01ef749b12b6e7a90e4952eda107f89a676eeedce63a8361f.sbom"),
+            sbom.tag()
+        );
+
+        let provenance =
+            attachment_reference(&reference, digest, AttachmentKind::Provenance).unwrap();
+        assert!(provenance.tag().unwrap().ends_with(".att"));
+
+        assert!(attachment_reference(&reference, "nodigest", AttachmentKind::Sbom).is_err());
+    }
+
+    #[test]
+    fn can_derive_digest_reference() {
+        let reference: Reference = "ghcr.io/fermyon/app:1.0.0".parse().unwrap();
+        let digest = "sha256:0a867093096e0ef01ef749b12b6e7a90e4952eda107f89a676eeedce63a8361f";
+
+        let subject = digest_reference(&reference, digest);
+        assert_eq!("ghcr.io", subject.registry());
+        assert_eq!("fermyon/app", subject.repository());
+        assert_eq!(None, subject.tag());
+        assert_eq!(Some(digest), subject.digest());
+    }
+
     #[test]
     fn can_derive_registry_from_input() {
         #[derive(Clone)]
@@ -0,0 +1,375 @@
+//! Downloading layer blobs resumably, with retries and bandwidth limits.
+
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use reqwest::{header, StatusCode};
+use spin_common::sha256;
+use tokio::{fs, io::AsyncWriteExt, time::Instant};
+
+/// Env var limiting the bandwidth of all pulls made by the process, e.g. `10M`
+/// for ten mebibytes per second.
+const SPIN_OCI_BANDWIDTH_LIMIT_OPT: &str = "SPIN_OCI_BANDWIDTH_LIMIT";
+/// Env var limiting the bandwidth of each pull, e.g. `512K`.
+pub(crate) const SPIN_OCI_PULL_BANDWIDTH_LIMIT_OPT: &str = "SPIN_OCI_PULL_BANDWIDTH_LIMIT";
+/// Env var setting how many times to retry a failed layer download.
+pub(crate) const SPIN_OCI_DOWNLOAD_RETRIES_OPT: &str = "SPIN_OCI_DOWNLOAD_RETRIES";
+
+/// Default number of times to retry a failed layer download.
+pub(crate) const DEFAULT_DOWNLOAD_RETRIES: u32 = 5;
+
+/// The most memory reserved up front for a blob. The size in a manifest comes
+/// from the registry, so isn't trusted to reserve more; larger blobs grow as
+/// they are read.
+const MAX_PREALLOCATION: usize = 16 * 1024 * 1024;
+
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Where to download a blob from.
+pub(crate) struct BlobRequest<'a> {
+    /// The client to make the requests with.
+    pub http: &'a reqwest::Client,
+    /// The URL of the blob.
+    pub url: String,
+    /// The digest of the blob, e.g. `sha256:abc123...`.
+    pub digest: &'a str,
+    /// The expected size of the blob, if known.
+    pub size: Option<usize>,
+    /// Where the downloaded part of the blob is kept, so that a download
+    /// interrupted by the process exiting can be resumed by a later one.
+    pub partial_path: PathBuf,
+}
+
+/// Downloads a blob, retrying with backoff if the download fails, and resuming
+/// from where the failed attempt, or a previous process, got to if the
+/// registry supports range requests. The blob's content is checked against its
+/// digest.
+///
+/// `authorization` returns the `Authorization` header for each attempt, so
+/// that expired tokens can be renewed.
+pub(crate) async fn download_blob<F, Fut>(
+    request: &BlobRequest<'_>,
+    retries: u32,
+    pull_limiter: Option<&RateLimiter>,
+    authorization: F,
+) -> Result<Vec<u8>>
+where
+    F: Fn() -> Fut,
+    Fut: std::future::Future<Output = Result<Option<String>>>,
+{
+    let mut data = read_partial(request).await;
+    let mut backoff = INITIAL_BACKOFF;
+    let mut attempt = 0;
+    loop {
+        let result = match authorization().await {
+            Ok(authorization) => {
+                download_attempt(request, &mut data, authorization, pull_limiter).await
+            }
+            Err(e) => Err(AttemptError::Retryable(e)),
+        };
+        let error = match result {
+            Ok(()) => {
+                let actual = format!("sha256:{}", sha256::hex_digest_from_bytes(&data));
+                remove_partial(request).await;
+                if actual == request.digest {
+                    return Ok(data);
+                }
+                // The content can't be trusted, so start over.
+                data.clear();
+                anyhow::anyhow!("blob has digest {actual}, expected {}", request.digest)
+            }
+            Err(AttemptError::Fatal(e)) => return Err(e),
+            Err(AttemptError::Retryable(e)) => e,
+        };
+
+        attempt += 1;
+        if attempt > retries {
+            return Err(error.context(format!(
+                "cannot download blob {} after {attempt} attempts",
+                request.digest
+            )));
+        }
+        tracing::warn!(
+            "Download of blob {} failed at {} bytes, retrying in {backoff:?}: {error:#}",
+            request.digest,
+            data.len()
+        );
+        tokio::time::sleep(backoff).await;
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+    }
+}
+
+enum AttemptError {
+    /// Retrying won't help, e.g. the blob doesn't exist.
+    Fatal(anyhow::Error),
+    Retryable(anyhow::Error),
+}
+
+/// Makes one attempt to download the rest of a blob, appending to `data`,
+/// which holds what previous attempts downloaded.
+async fn download_attempt(
+    request: &BlobRequest<'_>,
+    data: &mut Vec<u8>,
+    authorization: Option<String>,
+    pull_limiter: Option<&RateLimiter>,
+) -> Result<(), AttemptError> {
+    let mut req = request.http.get(&request.url);
+    if let Some(authorization) = authorization {
+        req = req.header(header::AUTHORIZATION, authorization);
+    }
+    if !data.is_empty() {
+        req = req.header(header::RANGE, format!("bytes={}-", data.len()));
+    }
+    let mut response = req
+        .send()
+        .await
+        .map_err(|e| AttemptError::Retryable(e.into()))?;
+
+    let resuming = match response.status() {
+        StatusCode::PARTIAL_CONTENT => {
+            tracing::debug!(
+                "Resuming download of blob {} from {} bytes",
+                request.digest,
+                data.len()
+            );
+            true
+        }
+        status if status.is_success() => {
+            // The registry is sending the whole blob.
+            data.clear();
+            false
+        }
+        StatusCode::RANGE_NOT_SATISFIABLE => {
+            data.clear();
+            return Err(AttemptError::Retryable(anyhow::anyhow!(
+                "registry cannot resume download"
+            )));
+        }
+        status if is_retryable(status) => {
+            return Err(AttemptError::Retryable(anyhow::anyhow!(
+                "registry responded {status}"
+            )));
+        }
+        status => {
+            return Err(AttemptError::Fatal(anyhow::anyhow!(
+                "cannot download blob {}: registry responded {status}",
+                request.digest
+            )));
+        }
+    };
+    let mut partial = open_partial(&request.partial_path, resuming).await;
+
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .map_err(|e| AttemptError::Retryable(e.into()))?
+    {
+        if let Some(limiter) = global_limiter() {
+            limiter.acquire(chunk.len()).await;
+        }
+        if let Some(limiter) = pull_limiter {
+            limiter.acquire(chunk.len()).await;
+        }
+        data.extend_from_slice(&chunk);
+        if let Some(file) = &mut partial {
+            if let Err(e) = file.write_all(&chunk).await {
+                tracing::debug!("Cannot keep the downloaded part of blob: {e}");
+                partial = None;
+            }
+        }
+        if request.size.is_some_and(|size| data.len() > size) {
+            data.clear();
+            return Err(AttemptError::Retryable(anyhow::anyhow!(
+                "blob is larger than its descriptor says"
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Reads what a previous download of the blob got to, if anything.
+async fn read_partial(request: &BlobRequest<'_>) -> Vec<u8> {
+    match fs::read(&request.partial_path).await {
+        Ok(data) if request.size.map_or(true, |size| data.len() <= size) => {
+            tracing::debug!(
+                "Found {} bytes of blob {} downloaded by a previous pull",
+                data.len(),
+                request.digest
+            );
+            data
+        }
+        _ => Vec::with_capacity(request.size.unwrap_or_default().min(MAX_PREALLOCATION)),
+    }
+}
+
+/// Opens the file the downloaded part of the blob is kept in, to append to it
+/// if `resuming` or else to replace it. Downloads go on without it if it
+/// can't be opened.
+async fn open_partial(path: &Path, resuming: bool) -> Option<fs::File> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).await.ok()?;
+    }
+    let mut options = fs::OpenOptions::new();
+    options.create(true);
+    if resuming {
+        options.append(true);
+    } else {
+        options.write(true).truncate(true);
+    }
+    match options.open(path).await {
+        Ok(file) => Some(file),
+        Err(e) => {
+            tracing::debug!("Cannot keep the downloaded part of blob in {path:?}: {e}");
+            None
+        }
+    }
+}
+
+async fn remove_partial(request: &BlobRequest<'_>) {
+    if let Err(e) = fs::remove_file(&request.partial_path).await {
+        if e.kind() != std::io::ErrorKind::NotFound {
+            tracing::debug!(
+                "Cannot remove the downloaded part of blob {}: {e}",
+                request.digest
+            );
+        }
+    }
+}
+
+fn is_retryable(status: StatusCode) -> bool {
+    status.is_server_error()
+        || status == StatusCode::REQUEST_TIMEOUT
+        || status == StatusCode::TOO_MANY_REQUESTS
+}
+
+/// The limiter shared by all pulls made by the process, if the
+/// `SPIN_OCI_BANDWIDTH_LIMIT` env var sets a limit.
+fn global_limiter() -> Option<&'static RateLimiter> {
+    static LIMITER: OnceLock<Option<RateLimiter>> = OnceLock::new();
+    LIMITER
+        .get_or_init(|| {
+            let limit = std::env::var(SPIN_OCI_BANDWIDTH_LIMIT_OPT).ok()?;
+            match parse_bandwidth(&limit) {
+                Ok(bytes_per_sec) => Some(RateLimiter::new(bytes_per_sec)),
+                Err(e) => {
+                    tracing::warn!("Ignoring {SPIN_OCI_BANDWIDTH_LIMIT_OPT}: {e:#}");
+                    None
+                }
+            }
+        })
+        .as_ref()
+}
+
+/// Parses a bandwidth in bytes per second, optionally with a `K`, `M` or `G`
+/// binary suffix, e.g. `1048576` or `1M`.
+pub(crate) fn parse_bandwidth(text: &str) -> Result<u64> {
+    let text = text.trim();
+    let (number, multiplier) = match text.char_indices().last() {
+        Some((i, 'k' | 'K')) => (&text[..i], 1 << 10),
+        Some((i, 'm' | 'M')) => (&text[..i], 1 << 20),
+        Some((i, 'g' | 'G')) => (&text[..i], 1 << 30),
+        _ => (text, 1),
+    };
+    let number: u64 = number
+        .trim()
+        .parse()
+        .with_context(|| format!("invalid bandwidth {text:?}"))?;
+    if number == 0 {
+        bail!("bandwidth must be greater than zero");
+    }
+    number
+        .checked_mul(multiplier)
+        .with_context(|| format!("bandwidth {text:?} is too large"))
+}
+
+/// Limits the rate bytes are downloaded at, across all the downloads it is
+/// shared by. Up to a second's worth of bytes may be downloaded in a burst.
+#[derive(Debug)]
+pub(crate) struct RateLimiter {
+    bytes_per_sec: u64,
+    /// When the bytes acquired so far will have been downloaded at the limit.
+    caught_up_at: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    const BURST: Duration = Duration::from_secs(1);
+
+    pub(crate) fn new(bytes_per_sec: u64) -> Self {
+        Self {
+            bytes_per_sec,
+            caught_up_at: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Waits until downloading `bytes` more stays within the limit.
+    pub(crate) async fn acquire(&self, bytes: usize) {
+        let cost = Duration::from_secs_f64(bytes as f64 / self.bytes_per_sec as f64);
+        let wait_until = {
+            let mut caught_up_at = self.caught_up_at.lock().unwrap();
+            let now = Instant::now();
+            *caught_up_at = (*caught_up_at).max(now) + cost;
+            caught_up_at.checked_sub(Self::BURST).unwrap_or(now)
+        };
+        tokio::time::sleep_until(wait_until).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bandwidths_are_parsed() {
+        assert_eq!(1000, parse_bandwidth("1000").unwrap());
+        assert_eq!(512 * 1024, parse_bandwidth("512K").unwrap());
+        assert_eq!(10 * 1024 * 1024, parse_bandwidth("10m").unwrap());
+        assert_eq!(1 << 30, parse_bandwidth(" 1G ").unwrap());
+        assert!(parse_bandwidth("0").is_err());
+        assert!(parse_bandwidth("fast").is_err());
+        assert!(parse_bandwidth("M").is_err());
+    }
+
+    #[tokio::test]
+    async fn partial_downloads_are_kept_across_pulls() {
+        let dir = tempfile::tempdir().unwrap();
+        let http = reqwest::Client::new();
+        let request = BlobRequest {
+            http: &http,
+            url: "http://localhost/v2/test/blobs/sha256:abc".to_string(),
+            digest: "sha256:abc",
+            size: Some(4),
+            partial_path: dir.path().join("partial").join("sha256:abc"),
+        };
+        assert!(read_partial(&request).await.is_empty());
+
+        let mut file = open_partial(&request.partial_path, false).await.unwrap();
+        file.write_all(b"ab").await.unwrap();
+        drop(file);
+        let mut file = open_partial(&request.partial_path, true).await.unwrap();
+        file.write_all(b"c").await.unwrap();
+        drop(file);
+        assert_eq!(b"abc".to_vec(), read_partial(&request).await);
+
+        // More than the blob's size can't be part of it.
+        std::fs::write(&request.partial_path, b"abcde").unwrap();
+        assert!(read_partial(&request).await.is_empty());
+
+        remove_partial(&request).await;
+        assert!(!request.partial_path.exists());
+    }
+
+    #[tokio::test]
+    async fn rate_limiter_delays_downloads_over_the_limit() {
+        let limiter = RateLimiter::new(1000);
+        let start = Instant::now();
+        // The first second's worth is a burst, and the rest is limited.
+        limiter.acquire(1000).await;
+        assert!(start.elapsed() < Duration::from_millis(100));
+        limiter.acquire(200).await;
+        limiter.acquire(200).await;
+        assert!(start.elapsed() >= Duration::from_millis(400));
+    }
+}
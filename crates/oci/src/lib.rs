@@ -3,7 +3,10 @@
 
 mod auth;
 pub mod client;
+mod download;
 mod loader;
+pub mod mirrors;
+pub mod signing;
 pub mod utils;
 
 pub use client::Client;
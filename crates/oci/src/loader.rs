@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 
 use anyhow::{anyhow, ensure, Context, Result};
@@ -6,12 +7,16 @@ use reqwest::Url;
 use spin_common::ui::quoted_path;
 use spin_loader::cache::Cache;
 use spin_locked_app::locked::{ContentPath, ContentRef, LockedApp, LockedComponent};
+use spin_locked_app::OCI_ANNOTATIONS_KEY;
 
+use crate::client::AttachmentKind;
+use crate::signing::Verifier;
 use crate::{Client, ORIGIN_URL_SCHEME};
 
 /// OciLoader loads an OCI app in preparation for running with Spin.
 pub struct OciLoader {
     working_dir: PathBuf,
+    verifier: Option<Verifier>,
 }
 
 impl OciLoader {
@@ -19,23 +24,66 @@ impl OciLoader {
     /// the given working_dir.
     pub fn new(working_dir: impl Into<PathBuf>) -> Self {
         let working_dir = working_dir.into();
-        Self { working_dir }
+        Self {
+            working_dir,
+            verifier: None,
+        }
+    }
+
+    /// Requires apps to carry a signature accepted by the given verifier.
+    pub fn with_verifier(mut self, verifier: Verifier) -> Self {
+        self.verifier = Some(verifier);
+        self
     }
 
     /// Pulls and loads an OCI Artifact and returns a LockedApp with the given OCI client and reference
     pub async fn load_app(&self, client: &mut Client, reference: &str) -> Result<LockedApp> {
+        // Keep garbage collection out of the cache until the app is leased
+        let mut cache_lock = client.cache.lock()?;
+        let _cache_guard = cache_lock.read().await?;
+
         // Fetch app
-        client.pull(reference).await.with_context(|| {
+        let digest = client.pull(reference).await.with_context(|| {
             format!("cannot pull Spin application from registry reference {reference:?}")
         })?;
 
+        if let Some(verifier) = &self.verifier {
+            let signatures = client
+                .pull_attachments(reference, &digest, AttachmentKind::Signature)
+                .await
+                .context("cannot fetch application signatures")?;
+            verifier
+                .verify(&digest, &signatures)
+                .with_context(|| format!("signature verification failed for {reference:?}"))?;
+        }
+
         // Read locked app
         let lockfile_path = client
             .lockfile_path(&reference)
             .await
             .context("cannot get path to spin.lock")?;
-        self.load_from_cache(lockfile_path, reference, &client.cache)
+        let mut locked_app = self
+            .load_from_cache(lockfile_path.clone(), reference, &client.cache)
+            .await?;
+        if let Some(manifest_dir) = lockfile_path.parent() {
+            client
+                .cache
+                .lease(manifest_dir)
+                .context("cannot protect application from cache garbage collection")?;
+        }
+
+        // Record image annotations (e.g. source and revision) for audit tooling
+        let annotations = client
+            .annotations(reference)
             .await
+            .context("cannot read OCI image annotations")?;
+        if !annotations.is_empty() {
+            locked_app.metadata.insert(
+                OCI_ANNOTATIONS_KEY.into(),
+                serde_json::to_value(annotations)?,
+            );
+        }
+        Ok(locked_app)
     }
 
     /// Loads an OCI Artifact from the given cache and returns a LockedApp with the given reference
@@ -88,55 +136,129 @@ impl OciLoader {
             dep.source.content = content_ref(dep_wasm_path)?;
         }
 
-        if !component.files.is_empty() {
-            let mount_dir = self.working_dir.join("assets").join(&component.id);
-            for file in &mut component.files {
-                ensure!(is_safe_to_join(&file.path), "invalid file mount {file:?}");
-                let mount_path = mount_dir.join(&file.path);
-
-                // Create parent directory
-                let mount_parent = mount_path
-                    .parent()
-                    .with_context(|| format!("invalid mount path {mount_path:?}"))?;
-                tokio::fs::create_dir_all(mount_parent)
-                    .await
-                    .with_context(|| {
-                        format!("failed to create temporary mount path {mount_path:?}")
-                    })?;
-
-                if let Some(content_bytes) = file.content.inline.as_deref() {
-                    // Write inline content to disk
-                    tokio::fs::write(&mount_path, content_bytes)
-                        .await
-                        .with_context(|| {
-                            format!("failed to write inline content to {mount_path:?}")
-                        })?;
-                } else {
-                    // Copy content
-                    let digest = content_digest(&file.content)?;
-                    let content_path = cache.data_file(digest)?;
-                    // TODO: parallelize
-                    tokio::fs::copy(&content_path, &mount_path)
-                        .await
-                        .with_context(|| {
-                            format!(
-                                "failed to copy {}->{mount_path:?}",
-                                quoted_path(&content_path)
-                            )
-                        })?;
+        if component.files.is_empty() {
+            return Ok(());
+        }
+
+        // Entries with no content are mounts with their own options; the
+        // files under their paths are mounted from their own directories.
+        let (own_dirs, files): (Vec<_>, Vec<_>) = std::mem::take(&mut component.files)
+            .into_iter()
+            .partition(is_own_dir_mount);
+        let own_dirs = own_dirs
+            .into_iter()
+            .enumerate()
+            .map(|(index, mount)| {
+                let root = self
+                    .working_dir
+                    .join("mounts")
+                    .join(&component.id)
+                    .join(index.to_string());
+                (mount, root)
+            })
+            .collect::<Vec<_>>();
+
+        let mount_dir = self.working_dir.join("assets").join(&component.id);
+        let mut shared_files = false;
+        // Files copied from the cache are known to have their content digest.
+        let mut digests = HashMap::new();
+        for file in &files {
+            ensure!(is_safe_to_join(&file.path), "invalid file mount {file:?}");
+            let own_dir = own_dirs
+                .iter()
+                .filter_map(|(mount, root)| {
+                    let dest = mount.path.strip_prefix("/").unwrap_or(&mount.path);
+                    Some((
+                        dest.components().count(),
+                        root,
+                        file.path.strip_prefix(dest).ok()?,
+                    ))
+                })
+                .max_by_key(|(depth, ..)| *depth);
+            let mount_path = match own_dir {
+                Some((_, root, rel_path)) => root.join(rel_path),
+                None => {
+                    shared_files = true;
+                    mount_dir.join(&file.path)
                 }
+            };
+            write_mount_file(file, &mount_path, cache).await?;
+            if let (None, Some(digest)) = (&file.content.inline, &file.content.digest) {
+                digests.insert(mount_path, digest.clone());
             }
+        }
 
-            component.files = vec![ContentPath {
+        if shared_files {
+            component.files.push(ContentPath {
+                integrity: spin_loader::integrity::directory_integrity_with_digests(
+                    &mount_dir, &digests,
+                )?,
                 content: content_ref(mount_dir)?,
                 path: "/".into(),
-            }]
+                writable: None,
+                max_bytes: None,
+            });
+        }
+        for (mount, root) in own_dirs {
+            // The mount may have no files
+            tokio::fs::create_dir_all(&root)
+                .await
+                .with_context(|| format!("failed to create mount directory {root:?}"))?;
+            // The guest may change the files of writable mounts
+            let integrity = if mount.writable == Some(true) {
+                Default::default()
+            } else {
+                spin_loader::integrity::directory_integrity_with_digests(&root, &digests)?
+            };
+            component.files.push(ContentPath {
+                integrity,
+                content: content_ref(root)?,
+                ..mount
+            });
         }
 
         Ok(())
     }
 }
 
+/// Whether the given files entry marks a mount with its own options, rather
+/// than being a file.
+fn is_own_dir_mount(file: &ContentPath) -> bool {
+    file.content == ContentRef::default() && (file.writable.is_some() || file.max_bytes.is_some())
+}
+
+/// Writes the content of a file to its path in a mount directory.
+async fn write_mount_file(file: &ContentPath, mount_path: &Path, cache: &Cache) -> Result<()> {
+    // Create parent directory
+    let mount_parent = mount_path
+        .parent()
+        .with_context(|| format!("invalid mount path {mount_path:?}"))?;
+    tokio::fs::create_dir_all(mount_parent)
+        .await
+        .with_context(|| format!("failed to create temporary mount path {mount_path:?}"))?;
+
+    if let Some(content_bytes) = file.content.inline.as_deref() {
+        // Write inline content to disk
+        tokio::fs::write(&mount_path, content_bytes)
+            .await
+            .with_context(|| format!("failed to write inline content to {mount_path:?}"))?;
+    } else {
+        // Copy content
+        let digest = content_digest(&file.content)?;
+        let content_path = cache.data_file(digest)?;
+        // TODO: parallelize
+        tokio::fs::copy(&content_path, &mount_path)
+            .await
+            .with_context(|| {
+                format!(
+                    "failed to copy {}->{mount_path:?}",
+                    quoted_path(&content_path)
+                )
+            })?;
+    }
+    Ok(())
+}
+
 fn content_digest(content_ref: &ContentRef) -> Result<&str> {
     content_ref
         .digest
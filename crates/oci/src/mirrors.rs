@@ -0,0 +1,299 @@
+//! Pulling applications through registry mirrors, such as pull-through
+//! proxies inside a firewall.
+//!
+//! Mirrors are configured in the `[registry.mirrors]` section of the runtime
+//! config file, by the registry they mirror:
+//!
+//! ```toml
+//! [[registry.mirrors."ghcr.io"]]
+//! endpoint = "https://mirror.internal:5000/ghcr"
+//! ca_cert_file = "certs/mirror-ca.pem"
+//! username = "puller"
+//! password_env = "GHCR_MIRROR_PASSWORD"
+//! ```
+//!
+//! A reference such as `ghcr.io/org/app:v1` is then pulled from
+//! `mirror.internal:5000/ghcr/org/app:v1`, and from `ghcr.io` itself only if
+//! none of its mirrors can provide it. Mirrors under `"*"` are used for every
+//! registry without mirrors of its own.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use oci_distribution::{secrets::RegistryAuth, Reference};
+use reqwest::Url;
+use serde::Deserialize;
+use spin_common::ui::quoted_path;
+
+/// The key of mirrors to use for registries without mirrors of their own.
+const ANY_REGISTRY: &str = "*";
+
+/// Registry mirrors, by the registry they mirror (e.g. `ghcr.io`).
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(transparent)]
+pub struct RegistryMirrors {
+    mirrors: HashMap<String, Vec<Mirror>>,
+}
+
+/// A mirror of a registry.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Mirror {
+    /// `endpoint = "https://mirror.internal:5000/ghcr"`: the mirror's
+    /// registry, and optionally the path its repositories are under. An
+    /// `http://` endpoint is connected to without TLS.
+    pub endpoint: String,
+    /// `username = "puller"`
+    pub username: Option<String>,
+    /// `password = "..."`
+    pub password: Option<String>,
+    /// `password_env = "MIRROR_PASSWORD"`: the environment variable holding
+    /// the password, so that it needn't be written in the runtime config.
+    pub password_env: Option<String>,
+    /// `ca_cert_file = "mirror-ca.pem"`: PEM certificates of additional
+    /// authorities to trust for the mirror's TLS certificate. Relative paths
+    /// are relative to the runtime config file.
+    pub ca_cert_file: Option<PathBuf>,
+    /// `skip_tls_verify = true`: don't verify the mirror's TLS certificate.
+    #[serde(default)]
+    pub skip_tls_verify: bool,
+}
+
+impl RegistryMirrors {
+    /// Read the mirrors from a runtime config file, if it has any.
+    pub fn from_runtime_config_file(path: &Path) -> Result<Option<Self>> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read runtime config file {}", quoted_path(path)))?;
+        let mut table: toml::Table = toml::from_str(&content).with_context(|| {
+            format!(
+                "failed to parse runtime config file {} as toml",
+                quoted_path(path)
+            )
+        })?;
+        let Some(mirrors) = table
+            .remove("registry")
+            .and_then(|mut registry| registry.as_table_mut()?.remove("mirrors"))
+        else {
+            return Ok(None);
+        };
+        let mut mirrors =
+            Self::deserialize(mirrors).context("invalid [registry.mirrors] runtime config")?;
+
+        let base_dir = path.parent().unwrap_or(Path::new(""));
+        for mirror in mirrors.mirrors.values_mut().flatten() {
+            mirror.parse_endpoint()?;
+            mirror.resolve_password()?;
+            if let Some(ca_cert_file) = &mut mirror.ca_cert_file {
+                *ca_cert_file = base_dir.join(&ca_cert_file);
+            }
+        }
+        Ok(Some(mirrors))
+    }
+
+    /// The mirrors of the registry of the given reference, in the order they
+    /// should be tried.
+    pub(crate) fn for_reference(&self, reference: &Reference) -> &[Mirror] {
+        self.mirrors
+            .get(reference.registry())
+            .or_else(|| self.mirrors.get(ANY_REGISTRY))
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// All the configured mirrors.
+    pub(crate) fn all(&self) -> impl Iterator<Item = &Mirror> {
+        self.mirrors.values().flatten()
+    }
+}
+
+impl Mirror {
+    /// The mirror's registry (with port, if any), its repository path
+    /// prefix, and whether it is connected to without TLS.
+    fn parse_endpoint(&self) -> Result<(String, String, bool)> {
+        let endpoint = if self.endpoint.contains("://") {
+            self.endpoint.clone()
+        } else {
+            format!("https://{}", self.endpoint)
+        };
+        let url = Url::parse(&endpoint)
+            .with_context(|| format!("invalid registry mirror endpoint {:?}", self.endpoint))?;
+        let insecure = match url.scheme() {
+            "https" => false,
+            "http" => true,
+            scheme => bail!(
+                "invalid registry mirror endpoint {:?}: scheme must be https or http, not {scheme}",
+                self.endpoint
+            ),
+        };
+        let Some(host) = url.host_str() else {
+            bail!(
+                "invalid registry mirror endpoint {:?}: no host",
+                self.endpoint
+            );
+        };
+        let registry = match url.port() {
+            Some(port) => format!("{host}:{port}"),
+            None => host.to_owned(),
+        };
+        let prefix = url.path().trim_matches('/').to_owned();
+        Ok((registry, prefix, insecure))
+    }
+
+    /// The registry the mirror is at.
+    pub(crate) fn registry(&self) -> String {
+        self.parse_endpoint()
+            .map(|(registry, _, _)| registry)
+            .unwrap_or_default()
+    }
+
+    /// Whether the mirror is connected to without TLS.
+    pub(crate) fn insecure(&self) -> bool {
+        self.parse_endpoint()
+            .map(|(_, _, insecure)| insecure)
+            .unwrap_or_default()
+    }
+
+    /// The reference in the mirror that mirrors the given reference.
+    pub(crate) fn mirrored(&self, reference: &Reference) -> Result<Reference> {
+        let (registry, prefix, _) = self.parse_endpoint()?;
+        let repository = match prefix.as_str() {
+            "" => reference.repository().to_owned(),
+            prefix => format!("{prefix}/{}", reference.repository()),
+        };
+        Ok(match (reference.digest(), reference.tag()) {
+            (Some(digest), _) => Reference::with_digest(registry, repository, digest.to_owned()),
+            (None, tag) => Reference::with_tag(
+                registry,
+                repository,
+                tag.unwrap_or(crate::client::LATEST_TAG).to_owned(),
+            ),
+        })
+    }
+
+    /// Reads the password from `password_env`, if it is given.
+    fn resolve_password(&mut self) -> Result<()> {
+        let Some(variable) = &self.password_env else {
+            return Ok(());
+        };
+        if self.password.is_some() {
+            bail!(
+                "registry mirror {:?} may have a `password` or a `password_env`, not both",
+                self.endpoint
+            );
+        }
+        let password = std::env::var(variable).with_context(|| {
+            format!(
+                "cannot read the password for registry mirror {:?} from environment variable {variable}",
+                self.endpoint
+            )
+        })?;
+        self.password = Some(password);
+        Ok(())
+    }
+
+    /// The credentials configured for the mirror, if any.
+    pub(crate) fn auth(&self) -> Option<RegistryAuth> {
+        let username = self.username.clone()?;
+        Some(RegistryAuth::Basic(
+            username,
+            self.password.clone().unwrap_or_default(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mirrors(toml_text: &str) -> RegistryMirrors {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("runtime-config.toml");
+        std::fs::write(&path, toml_text).unwrap();
+        RegistryMirrors::from_runtime_config_file(&path)
+            .unwrap()
+            .unwrap()
+    }
+
+    #[test]
+    fn references_are_mirrored() {
+        let mirrors = mirrors(
+            r#"
+            [[registry.mirrors."ghcr.io"]]
+            endpoint = "https://mirror.internal:5000/ghcr"
+            [[registry.mirrors."ghcr.io"]]
+            endpoint = "http://fallback.internal"
+            [[registry.mirrors."*"]]
+            endpoint = "proxy.internal"
+            "#,
+        );
+
+        let reference: Reference = "ghcr.io/org/app:v1".parse().unwrap();
+        let ghcr = mirrors.for_reference(&reference);
+        assert_eq!(2, ghcr.len());
+        assert_eq!(
+            "mirror.internal:5000/ghcr/org/app:v1",
+            ghcr[0].mirrored(&reference).unwrap().whole()
+        );
+        assert!(!ghcr[0].insecure());
+        assert_eq!(
+            "fallback.internal/org/app:v1",
+            ghcr[1].mirrored(&reference).unwrap().whole()
+        );
+        assert!(ghcr[1].insecure());
+
+        let digest = "sha256:0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef";
+        let reference: Reference = format!("example.com/app@{digest}").parse().unwrap();
+        let any = mirrors.for_reference(&reference);
+        assert_eq!(1, any.len());
+        assert_eq!(
+            format!("proxy.internal/app@{digest}"),
+            any[0].mirrored(&reference).unwrap().whole()
+        );
+    }
+
+    #[test]
+    fn no_mirrors_without_section() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("runtime-config.toml");
+        std::fs::write(&path, "[registry.verification]\nkeys = []\n").unwrap();
+        assert!(RegistryMirrors::from_runtime_config_file(&path)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn passwords_are_read_from_the_environment() {
+        std::env::set_var("SPIN_TEST_MIRROR_PASSWORD", "hunter2");
+        let mirrors = mirrors(
+            r#"
+            [[registry.mirrors."ghcr.io"]]
+            endpoint = "mirror.internal"
+            username = "puller"
+            password_env = "SPIN_TEST_MIRROR_PASSWORD"
+            "#,
+        );
+        let reference: Reference = "ghcr.io/org/app:v1".parse().unwrap();
+        let Some(RegistryAuth::Basic(username, password)) =
+            mirrors.for_reference(&reference)[0].auth()
+        else {
+            panic!("expected basic auth");
+        };
+        assert_eq!(
+            ("puller", "hunter2"),
+            (username.as_str(), password.as_str())
+        );
+    }
+
+    #[test]
+    fn invalid_endpoints_are_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("runtime-config.toml");
+        std::fs::write(
+            &path,
+            "[[registry.mirrors.\"ghcr.io\"]]\nendpoint = \"ftp://mirror.internal\"\n",
+        )
+        .unwrap();
+        assert!(RegistryMirrors::from_runtime_config_file(&path).is_err());
+    }
+}
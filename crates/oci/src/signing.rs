@@ -0,0 +1,793 @@
+//! Signing and verification of Spin applications in OCI registries.
+//!
+//! Signatures follow the conventions of sigstore's cosign, so that apps signed
+//! by Spin can be checked with cosign and vice versa: a "simple signing"
+//! payload naming the signed manifest digest is stored under the
+//! `sha256-<hex>.sig` tag, with the signature (and, for keyless signing, the
+//! Fulcio certificate and Rekor transparency log bundle) in the layer
+//! annotations.
+
+mod cert;
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, ensure, Context, Result};
+use base64::{
+    engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD},
+    Engine,
+};
+use itertools::Itertools;
+use oci_distribution::Reference;
+use p256::ecdsa::signature::{Signer as _, Verifier as _};
+use p256::ecdsa::{Signature, SigningKey, VerifyingKey};
+use p256::pkcs8::{DecodePrivateKey, DecodePublicKey, EncodePublicKey, LineEnding};
+use rand_core::OsRng;
+use regex::Regex;
+use reqwest::Url;
+use rustls_pki_types::{CertificateDer, UnixTime};
+use serde::{Deserialize, Serialize};
+use spin_common::sha256;
+use spin_common::ui::quoted_path;
+
+use crate::client::{Attachment, AttachmentKind};
+
+/// Media type of a cosign simple signing payload.
+pub const SIMPLE_SIGNING_MEDIA_TYPE: &str = "application/vnd.dev.cosign.simplesigning.v1+json";
+/// The public-good sigstore certificate authority.
+pub const DEFAULT_FULCIO_URL: &str = "https://fulcio.sigstore.dev";
+/// The public-good sigstore transparency log.
+pub const DEFAULT_REKOR_URL: &str = "https://rekor.sigstore.dev";
+
+const SIGNATURE_ANNOTATION: &str = "dev.cosignproject.cosign/signature";
+const CERTIFICATE_ANNOTATION: &str = "dev.sigstore.cosign/certificate";
+const CHAIN_ANNOTATION: &str = "dev.sigstore.cosign/chain";
+const BUNDLE_ANNOTATION: &str = "dev.sigstore.cosign/bundle";
+
+const SIMPLE_SIGNING_TYPE: &str = "cosign container image signature";
+/// 1.3.6.1.5.5.7.3.3
+const OID_CODE_SIGNING: &[u8] = &[0x2b, 0x06, 0x01, 0x05, 0x05, 0x07, 0x03, 0x03];
+
+const GITHUB_ID_TOKEN_URL_ENV: &str = "ACTIONS_ID_TOKEN_REQUEST_URL";
+const GITHUB_ID_TOKEN_TOKEN_ENV: &str = "ACTIONS_ID_TOKEN_REQUEST_TOKEN";
+
+/// Signs application manifests.
+pub enum Signer {
+    /// Sign with a long-lived ECDSA P-256 key.
+    Key(SigningKey),
+    /// Sign with an ephemeral key, certified by Fulcio for an OIDC identity
+    /// and recorded in the Rekor transparency log.
+    Keyless {
+        /// The OIDC identity token to exchange for a signing certificate.
+        identity_token: String,
+        /// The Fulcio certificate authority.
+        fulcio_url: Url,
+        /// The Rekor transparency log.
+        rekor_url: Url,
+    },
+}
+
+impl Signer {
+    /// Create a signer from a PEM-encoded PKCS#8 ECDSA P-256 private key file.
+    pub fn from_key_file(path: &Path) -> Result<Self> {
+        let pem = std::fs::read_to_string(path)
+            .with_context(|| format!("cannot read signing key {}", quoted_path(path)))?;
+        let key = SigningKey::from_pkcs8_pem(&pem).map_err(|e| {
+            anyhow!(
+                "{} is not a PKCS#8 ECDSA P-256 private key: {e}",
+                quoted_path(path)
+            )
+        })?;
+        Ok(Self::Key(key))
+    }
+
+    /// Produce a signature attachment for the manifest with the given digest,
+    /// pushed to the repository of the given reference.
+    pub async fn sign(&self, reference: &str, digest: &str) -> Result<Attachment> {
+        let reference: Reference = reference
+            .parse()
+            .with_context(|| format!("cannot parse reference {reference}"))?;
+        let payload = SimpleSigning::new(&reference, digest);
+        let payload = serde_json::to_vec(&payload)?;
+
+        let annotations = match self {
+            Self::Key(key) => {
+                let signature: Signature = key.sign(&payload);
+                BTreeMap::from([(
+                    SIGNATURE_ANNOTATION.to_owned(),
+                    STANDARD.encode(signature.to_der()),
+                )])
+            }
+            Self::Keyless {
+                identity_token,
+                fulcio_url,
+                rekor_url,
+            } => sign_keyless(&payload, identity_token, fulcio_url, rekor_url).await?,
+        };
+
+        Ok(Attachment {
+            kind: AttachmentKind::Signature,
+            media_type: SIMPLE_SIGNING_MEDIA_TYPE.to_owned(),
+            data: payload,
+            annotations,
+        })
+    }
+}
+
+/// Find an OIDC identity token provided by the environment, such as a GitHub
+/// Actions workflow with the `id-token: write` permission.
+pub async fn ambient_identity_token() -> Result<Option<String>> {
+    let (Ok(url), Ok(token)) = (
+        std::env::var(GITHUB_ID_TOKEN_URL_ENV),
+        std::env::var(GITHUB_ID_TOKEN_TOKEN_ENV),
+    ) else {
+        return Ok(None);
+    };
+
+    #[derive(Deserialize)]
+    struct TokenResponse {
+        value: String,
+    }
+
+    let mut url = Url::parse(&url).context("invalid GitHub Actions token URL")?;
+    url.query_pairs_mut().append_pair("audience", "sigstore");
+    let response: TokenResponse = reqwest::Client::new()
+        .get(url)
+        .bearer_auth(token)
+        .send()
+        .await
+        .context("cannot request GitHub Actions identity token")?
+        .error_for_status()?
+        .json()
+        .await?;
+    Ok(Some(response.value))
+}
+
+async fn sign_keyless(
+    payload: &[u8],
+    identity_token: &str,
+    fulcio_url: &Url,
+    rekor_url: &Url,
+) -> Result<BTreeMap<String, String>> {
+    let http = reqwest::Client::new();
+    let key = SigningKey::random(&mut OsRng);
+
+    // Fulcio checks that we hold the key by having us sign the token subject.
+    let proof: Signature = key.sign(token_subject(identity_token)?.as_bytes());
+    let public_key = key
+        .verifying_key()
+        .to_public_key_pem(LineEnding::LF)
+        .map_err(|e| anyhow!("cannot encode public key: {e}"))?;
+    let request = serde_json::json!({
+        "credentials": { "oidcIdentityToken": identity_token },
+        "publicKeyRequest": {
+            "publicKey": { "algorithm": "ECDSA", "content": public_key },
+            "proofOfPossession": STANDARD.encode(proof.to_der()),
+        },
+    });
+    let response: SigningCertificateResponse = http
+        .post(fulcio_url.join("api/v2/signingCert")?)
+        .json(&request)
+        .send()
+        .await
+        .context("cannot reach Fulcio")?
+        .error_for_status()
+        .context("Fulcio did not issue a signing certificate")?
+        .json()
+        .await
+        .context("cannot parse Fulcio response")?;
+    let mut chain = response.into_certificates().into_iter();
+    let certificate = chain.next().context("Fulcio returned no certificate")?;
+
+    let signature: Signature = key.sign(payload);
+    let signature = STANDARD.encode(signature.to_der());
+
+    let entry = serde_json::json!({
+        "apiVersion": "0.0.1",
+        "kind": "hashedrekord",
+        "spec": {
+            "signature": {
+                "content": signature,
+                "publicKey": { "content": STANDARD.encode(&certificate) },
+            },
+            "data": {
+                "hash": { "algorithm": "sha256", "value": sha256::hex_digest_from_bytes(payload) },
+            },
+        },
+    });
+    let entries: HashMap<String, LogEntry> = http
+        .post(rekor_url.join("api/v1/log/entries")?)
+        .json(&entry)
+        .send()
+        .await
+        .context("cannot reach Rekor")?
+        .error_for_status()
+        .context("Rekor did not accept the signature")?
+        .json()
+        .await
+        .context("cannot parse Rekor response")?;
+    let entry = entries
+        .into_values()
+        .next()
+        .context("Rekor returned no log entry")?;
+    let bundle = Bundle {
+        signed_entry_timestamp: entry.verification.signed_entry_timestamp,
+        payload: BundlePayload {
+            body: entry.body,
+            integrated_time: entry.integrated_time,
+            log_id: entry.log_id,
+            log_index: entry.log_index,
+        },
+    };
+
+    Ok(BTreeMap::from([
+        (SIGNATURE_ANNOTATION.to_owned(), signature),
+        (CERTIFICATE_ANNOTATION.to_owned(), certificate),
+        (CHAIN_ANNOTATION.to_owned(), chain.collect::<String>()),
+        (
+            BUNDLE_ANNOTATION.to_owned(),
+            serde_json::to_string(&bundle)?,
+        ),
+    ]))
+}
+
+/// The identity Fulcio will certify for the token: its email claim if it
+/// has one, otherwise its subject.
+fn token_subject(identity_token: &str) -> Result<String> {
+    #[derive(Deserialize)]
+    struct Claims {
+        sub: String,
+        email: Option<String>,
+    }
+
+    let claims = identity_token
+        .split('.')
+        .nth(1)
+        .context("identity token is not a JWT")?;
+    let claims: Claims = serde_json::from_slice(&URL_SAFE_NO_PAD.decode(claims)?)
+        .context("cannot parse identity token claims")?;
+    Ok(claims.email.unwrap_or(claims.sub))
+}
+
+/// Signature requirements for applications pulled from a registry, from the
+/// `[registry.verification]` section of the runtime config file.
+///
+/// An app is accepted if any of its signatures was made by one of `keys`, or
+/// is a keyless signature for one of `identities`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct VerificationPolicy {
+    /// `keys = ["cosign.pub"]`
+    #[serde(default)]
+    pub keys: Vec<PathBuf>,
+    /// `identities = [{ issuer = "https://token.actions.githubusercontent.com", subject_regex = "^https://github.com/my-org/" }]`
+    #[serde(default)]
+    pub identities: Vec<IdentityConstraint>,
+    /// `fulcio_roots = "fulcio.crt.pem"`
+    pub fulcio_roots: Option<PathBuf>,
+    /// `rekor_public_key = "rekor.pub"`
+    pub rekor_public_key: Option<PathBuf>,
+}
+
+/// An identity allowed to sign applications keylessly.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct IdentityConstraint {
+    /// The OIDC issuer that authenticated the signer.
+    pub issuer: String,
+    /// The exact certificate identity (e.g. email or workflow URI).
+    pub subject: Option<String>,
+    /// A regular expression the certificate identity must match.
+    pub subject_regex: Option<String>,
+}
+
+impl VerificationPolicy {
+    /// Read the policy from a runtime config file, if it has one.
+    pub fn from_runtime_config_file(path: &Path) -> Result<Option<Self>> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read runtime config file {}", quoted_path(path)))?;
+        let mut table: toml::Table = toml::from_str(&content).with_context(|| {
+            format!(
+                "failed to parse runtime config file {} as toml",
+                quoted_path(path)
+            )
+        })?;
+        let Some(verification) = table
+            .remove("registry")
+            .and_then(|mut registry| registry.as_table_mut()?.remove("verification"))
+        else {
+            return Ok(None);
+        };
+        let policy = Self::deserialize(verification)
+            .context("invalid [registry.verification] runtime config")?;
+        Ok(Some(policy))
+    }
+}
+
+/// Verifies application signatures against a [`VerificationPolicy`].
+pub struct Verifier {
+    keys: Vec<VerifyingKey>,
+    keyless: Option<KeylessVerifier>,
+}
+
+struct KeylessVerifier {
+    roots: Vec<CertificateDer<'static>>,
+    rekor_key: VerifyingKey,
+    identities: Vec<Identity>,
+}
+
+struct Identity {
+    issuer: String,
+    subject: SubjectMatcher,
+}
+
+enum SubjectMatcher {
+    Exact(String),
+    Regex(Regex),
+}
+
+impl Verifier {
+    /// Create a verifier for the policy, resolving relative paths against `base_dir`.
+    pub fn new(policy: VerificationPolicy, base_dir: &Path) -> Result<Self> {
+        let keys = policy
+            .keys
+            .iter()
+            .map(|path| read_public_key(&base_dir.join(path)))
+            .collect::<Result<_>>()?;
+
+        let keyless = if policy.identities.is_empty() {
+            None
+        } else {
+            let roots = policy
+                .fulcio_roots
+                .context("keyless verification requires `fulcio_roots`")?;
+            let roots = read_certificates(&base_dir.join(roots))?;
+            let rekor_key = policy
+                .rekor_public_key
+                .context("keyless verification requires `rekor_public_key`")?;
+            let rekor_key = read_public_key(&base_dir.join(rekor_key))?;
+            let identities = policy
+                .identities
+                .into_iter()
+                .map(Identity::try_from)
+                .collect::<Result<_>>()?;
+            Some(KeylessVerifier {
+                roots,
+                rekor_key,
+                identities,
+            })
+        };
+
+        ensure!(
+            !keys.is_empty() || keyless.is_some(),
+            "signature verification requires at least one of `keys` or `identities`"
+        );
+        Ok(Self { keys, keyless })
+    }
+
+    /// Check that at least one of the signatures is valid for the manifest
+    /// with the given digest.
+    pub fn verify(&self, digest: &str, signatures: &[Attachment]) -> Result<()> {
+        ensure!(!signatures.is_empty(), "the application is not signed");
+        let mut errors = vec![];
+        for signature in signatures {
+            match self.verify_signature(digest, signature) {
+                Ok(()) => return Ok(()),
+                Err(e) => errors.push(format!("{e:#}")),
+            }
+        }
+        bail!("no valid signature: {}", errors.iter().join("; "))
+    }
+
+    fn verify_signature(&self, digest: &str, attachment: &Attachment) -> Result<()> {
+        ensure!(
+            attachment.media_type == SIMPLE_SIGNING_MEDIA_TYPE,
+            "unsupported signature media type {}",
+            attachment.media_type
+        );
+        let payload: SimpleSigning =
+            serde_json::from_slice(&attachment.data).context("invalid signature payload")?;
+        let signed_digest = &payload.critical.image.docker_manifest_digest;
+        ensure!(
+            signed_digest == digest,
+            "signature is for {signed_digest}, not {digest}"
+        );
+
+        let encoded = attachment
+            .annotations
+            .get(SIGNATURE_ANNOTATION)
+            .context("signature annotation is missing")?;
+        let signature = Signature::from_der(&STANDARD.decode(encoded)?)?;
+
+        match attachment.annotations.get(CERTIFICATE_ANNOTATION) {
+            Some(certificate) => {
+                let keyless = self
+                    .keyless
+                    .as_ref()
+                    .context("keyless signature found but no `identities` are configured")?;
+                keyless.verify(attachment, certificate, encoded, &signature)
+            }
+            None => {
+                let signed_by_key = self
+                    .keys
+                    .iter()
+                    .any(|key| key.verify(&attachment.data, &signature).is_ok());
+                ensure!(signed_by_key, "signature does not match any configured key");
+                Ok(())
+            }
+        }
+    }
+}
+
+impl KeylessVerifier {
+    fn verify(
+        &self,
+        attachment: &Attachment,
+        certificate: &str,
+        encoded_signature: &str,
+        signature: &Signature,
+    ) -> Result<()> {
+        // The transparency log entry proves when the signature was made, which
+        // must be within the lifetime of the short-lived certificate.
+        let bundle: Bundle = serde_json::from_str(
+            attachment
+                .annotations
+                .get(BUNDLE_ANNOTATION)
+                .context("keyless signature has no transparency log bundle")?,
+        )
+        .context("invalid transparency log bundle")?;
+        let timestamp = Signature::from_der(&STANDARD.decode(&bundle.signed_entry_timestamp)?)?;
+        self.rekor_key
+            .verify(&serde_json::to_vec(&bundle.payload)?, &timestamp)
+            .context("transparency log entry timestamp is invalid")?;
+        let entry: HashedRekord = serde_json::from_slice(&STANDARD.decode(&bundle.payload.body)?)
+            .context("invalid transparency log entry")?;
+        ensure!(
+            entry.spec.signature.content == encoded_signature
+                && entry.spec.data.hash.value == sha256::hex_digest_from_bytes(&attachment.data),
+            "transparency log entry does not match the signature"
+        );
+
+        let leaf = parse_certificates(certificate)?
+            .into_iter()
+            .next()
+            .context("signing certificate is missing")?;
+        let intermediates = match attachment.annotations.get(CHAIN_ANNOTATION) {
+            Some(chain) => parse_certificates(chain)?,
+            None => vec![],
+        };
+        let anchors = self
+            .roots
+            .iter()
+            .map(webpki::anchor_from_trusted_cert)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| anyhow!("invalid Fulcio root certificate: {e:?}"))?;
+        let signed_at = UnixTime::since_unix_epoch(Duration::from_secs(
+            bundle.payload.integrated_time.try_into()?,
+        ));
+        webpki::EndEntityCert::try_from(&leaf)
+            .map_err(|e| anyhow!("invalid signing certificate: {e:?}"))?
+            .verify_for_usage(
+                webpki::ALL_VERIFICATION_ALGS,
+                &anchors,
+                &intermediates,
+                signed_at,
+                webpki::KeyUsage::required(OID_CODE_SIGNING),
+                None,
+                None,
+            )
+            .map_err(|e| anyhow!("signing certificate is not trusted: {e:?}"))?;
+
+        let info = cert::parse(&leaf)?;
+        let key = VerifyingKey::from_public_key_der(&info.public_key)
+            .map_err(|e| anyhow!("unsupported signing certificate key: {e}"))?;
+        key.verify(&attachment.data, signature)
+            .context("signature does not match its certificate")?;
+
+        let issuer = info.issuer.as_deref().unwrap_or_default();
+        let allowed = self
+            .identities
+            .iter()
+            .any(|identity| identity.matches(issuer, &info.subjects));
+        ensure!(
+            allowed,
+            "signer {} from issuer {issuer:?} is not an allowed identity",
+            info.subjects.iter().join(", ")
+        );
+        Ok(())
+    }
+}
+
+impl Identity {
+    fn matches(&self, issuer: &str, subjects: &[String]) -> bool {
+        self.issuer == issuer
+            && subjects.iter().any(|subject| match &self.subject {
+                SubjectMatcher::Exact(expected) => subject == expected,
+                SubjectMatcher::Regex(regex) => regex.is_match(subject),
+            })
+    }
+}
+
+impl TryFrom<IdentityConstraint> for Identity {
+    type Error = anyhow::Error;
+
+    fn try_from(constraint: IdentityConstraint) -> Result<Self> {
+        let subject = match (constraint.subject, constraint.subject_regex) {
+            (Some(subject), None) => SubjectMatcher::Exact(subject),
+            (None, Some(regex)) => SubjectMatcher::Regex(
+                Regex::new(&regex).with_context(|| format!("invalid subject_regex {regex:?}"))?,
+            ),
+            _ => bail!(
+                "identity for issuer {:?} must have exactly one of `subject` or `subject_regex`",
+                constraint.issuer
+            ),
+        };
+        Ok(Self {
+            issuer: constraint.issuer,
+            subject,
+        })
+    }
+}
+
+fn read_public_key(path: &Path) -> Result<VerifyingKey> {
+    let pem = std::fs::read_to_string(path)
+        .with_context(|| format!("cannot read public key {}", quoted_path(path)))?;
+    VerifyingKey::from_public_key_pem(&pem).map_err(|e| {
+        anyhow!(
+            "{} is not an ECDSA P-256 public key: {e}",
+            quoted_path(path)
+        )
+    })
+}
+
+fn read_certificates(path: &Path) -> Result<Vec<CertificateDer<'static>>> {
+    let pem = std::fs::read_to_string(path)
+        .with_context(|| format!("cannot read certificates {}", quoted_path(path)))?;
+    let certificates = parse_certificates(&pem)
+        .with_context(|| format!("invalid certificates in {}", quoted_path(path)))?;
+    ensure!(
+        !certificates.is_empty(),
+        "no certificates in {}",
+        quoted_path(path)
+    );
+    Ok(certificates)
+}
+
+fn parse_certificates(pem: &str) -> Result<Vec<CertificateDer<'static>>> {
+    Ok(pem::parse_many(pem)?
+        .into_iter()
+        .filter(|p| p.tag() == "CERTIFICATE")
+        .map(|p| CertificateDer::from(p.into_contents()))
+        .collect())
+}
+
+/// A cosign "simple signing" payload.
+#[derive(Debug, Deserialize, Serialize)]
+struct SimpleSigning {
+    critical: Critical,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    optional: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct Critical {
+    identity: CriticalIdentity,
+    image: CriticalImage,
+    #[serde(rename = "type")]
+    kind: String,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct CriticalIdentity {
+    #[serde(rename = "docker-reference")]
+    docker_reference: String,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct CriticalImage {
+    #[serde(rename = "docker-manifest-digest")]
+    docker_manifest_digest: String,
+}
+
+impl SimpleSigning {
+    fn new(reference: &Reference, digest: &str) -> Self {
+        Self {
+            critical: Critical {
+                identity: CriticalIdentity {
+                    docker_reference: format!(
+                        "{}/{}",
+                        reference.registry(),
+                        reference.repository()
+                    ),
+                },
+                image: CriticalImage {
+                    docker_manifest_digest: digest.to_owned(),
+                },
+                kind: SIMPLE_SIGNING_TYPE.to_owned(),
+            },
+            optional: None,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+enum SigningCertificateResponse {
+    SignedCertificateEmbeddedSct { chain: CertificateChain },
+    SignedCertificateDetachedSct { chain: CertificateChain },
+}
+
+#[derive(Deserialize)]
+struct CertificateChain {
+    certificates: Vec<String>,
+}
+
+impl SigningCertificateResponse {
+    fn into_certificates(self) -> Vec<String> {
+        match self {
+            Self::SignedCertificateEmbeddedSct { chain }
+            | Self::SignedCertificateDetachedSct { chain } => chain.certificates,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LogEntry {
+    body: String,
+    integrated_time: i64,
+    #[serde(rename = "logID")]
+    log_id: String,
+    log_index: i64,
+    verification: LogEntryVerification,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LogEntryVerification {
+    signed_entry_timestamp: String,
+}
+
+/// The cosign bundle recording a signature's transparency log entry.
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct Bundle {
+    signed_entry_timestamp: String,
+    payload: BundlePayload,
+}
+
+/// The signed part of a transparency log entry. Fields are in canonical
+/// (sorted) order, as the timestamp is a signature over the compact JSON.
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct BundlePayload {
+    body: String,
+    integrated_time: i64,
+    #[serde(rename = "logID")]
+    log_id: String,
+    log_index: i64,
+}
+
+#[derive(Deserialize)]
+struct HashedRekord {
+    spec: HashedRekordSpec,
+}
+
+#[derive(Deserialize)]
+struct HashedRekordSpec {
+    signature: HashedRekordSignature,
+    data: HashedRekordData,
+}
+
+#[derive(Deserialize)]
+struct HashedRekordSignature {
+    content: String,
+}
+
+#[derive(Deserialize)]
+struct HashedRekordData {
+    hash: HashedRekordHash,
+}
+
+#[derive(Deserialize)]
+struct HashedRekordHash {
+    value: String,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const DIGEST: &str = "sha256:6b1f1f6c8d1b4a5ae3a0f0d30b8d9cbe80dd0b0511ec0fa7d5f2f3b6c0d4cd2a";
+
+    fn key_verifier(key: &SigningKey) -> Verifier {
+        Verifier {
+            keys: vec![*key.verifying_key()],
+            keyless: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn can_verify_key_signature() {
+        let key = SigningKey::random(&mut OsRng);
+        let signature = Signer::Key(key.clone())
+            .sign("ghcr.io/fermyon/app:v1", DIGEST)
+            .await
+            .unwrap();
+
+        let payload: SimpleSigning = serde_json::from_slice(&signature.data).unwrap();
+        assert_eq!(
+            "ghcr.io/fermyon/app",
+            payload.critical.identity.docker_reference
+        );
+
+        key_verifier(&key).verify(DIGEST, &[signature]).unwrap();
+    }
+
+    #[tokio::test]
+    async fn rejects_signature_for_other_digest_or_key() {
+        let key = SigningKey::random(&mut OsRng);
+        let signature = Signer::Key(key.clone())
+            .sign("ghcr.io/fermyon/app:v1", DIGEST)
+            .await
+            .unwrap();
+
+        let other_digest = format!("sha256:{}", "0".repeat(64));
+        key_verifier(&key)
+            .verify(&other_digest, &[signature.clone()])
+            .unwrap_err();
+
+        let other_key = SigningKey::random(&mut OsRng);
+        key_verifier(&other_key)
+            .verify(DIGEST, &[signature])
+            .unwrap_err();
+
+        key_verifier(&key).verify(DIGEST, &[]).unwrap_err();
+    }
+
+    #[test]
+    fn identity_requires_one_subject_matcher() {
+        let identity = |subject: Option<&str>, subject_regex: Option<&str>| {
+            Identity::try_from(IdentityConstraint {
+                issuer: "https://token.actions.githubusercontent.com".into(),
+                subject: subject.map(Into::into),
+                subject_regex: subject_regex.map(Into::into),
+            })
+        };
+        identity(None, None).unwrap_err();
+        identity(Some("a"), Some("b")).unwrap_err();
+
+        let identity = identity(None, Some("^https://github.com/fermyon/")).unwrap();
+        let subjects = ["https://github.com/fermyon/app/.github/workflows/ci.yml".to_owned()];
+        assert!(identity.matches("https://token.actions.githubusercontent.com", &subjects));
+        assert!(!identity.matches("https://accounts.google.com", &subjects));
+    }
+
+    #[test]
+    fn can_read_policy_from_runtime_config() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("runtime-config.toml");
+        std::fs::write(
+            &path,
+            r#"
+            [registry.verification]
+            keys = ["cosign.pub"]
+            [[registry.verification.identities]]
+            issuer = "https://token.actions.githubusercontent.com"
+            subject = "https://github.com/fermyon/app/.github/workflows/ci.yml@refs/heads/main"
+            "#,
+        )
+        .unwrap();
+        let policy = VerificationPolicy::from_runtime_config_file(&path)
+            .unwrap()
+            .unwrap();
+        assert_eq!(vec![PathBuf::from("cosign.pub")], policy.keys);
+        assert_eq!(1, policy.identities.len());
+
+        std::fs::write(&path, "[key_value_store.default]\ntype = \"spin\"\n").unwrap();
+        assert!(VerificationPolicy::from_runtime_config_file(&path)
+            .unwrap()
+            .is_none());
+    }
+}
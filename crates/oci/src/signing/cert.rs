@@ -0,0 +1,169 @@
+//! Just enough X.509 parsing to read the identity that Fulcio binds to a
+//! signing certificate. Chain validation is left to webpki.
+
+use anyhow::{bail, ensure, Context, Result};
+
+const TAG_SEQUENCE: u8 = 0x30;
+const TAG_OID: u8 = 0x06;
+const TAG_BOOLEAN: u8 = 0x01;
+const TAG_OCTET_STRING: u8 = 0x04;
+const TAG_UTF8_STRING: u8 = 0x0c;
+const TAG_VERSION: u8 = 0xa0;
+const TAG_EXTENSIONS: u8 = 0xa3;
+const TAG_SAN_EMAIL: u8 = 0x81;
+const TAG_SAN_URI: u8 = 0x86;
+
+/// 2.5.29.17
+const OID_SUBJECT_ALT_NAME: &[u8] = &[0x55, 0x1d, 0x11];
+/// 1.3.6.1.4.1.57264.1.1 (deprecated raw string form)
+const OID_FULCIO_ISSUER_V1: &[u8] = &[0x2b, 0x06, 0x01, 0x04, 0x01, 0x83, 0xbf, 0x30, 0x01, 0x01];
+/// 1.3.6.1.4.1.57264.1.8 (DER UTF8String form)
+const OID_FULCIO_ISSUER_V2: &[u8] = &[0x2b, 0x06, 0x01, 0x04, 0x01, 0x83, 0xbf, 0x30, 0x01, 0x08];
+
+/// The parts of a Fulcio signing certificate needed for verification.
+#[derive(Debug, Default)]
+pub(super) struct CertificateInfo {
+    /// The DER SubjectPublicKeyInfo.
+    pub public_key: Vec<u8>,
+    /// Email and URI subject alternative names.
+    pub subjects: Vec<String>,
+    /// The OIDC issuer that authenticated the subject.
+    pub issuer: Option<String>,
+}
+
+/// Parse a DER certificate.
+pub(super) fn parse(der: &[u8]) -> Result<CertificateInfo> {
+    let (certificate, _) = read(der, TAG_SEQUENCE).context("invalid certificate")?;
+    let (tbs, _) = read(certificate.value, TAG_SEQUENCE).context("invalid TBS certificate")?;
+
+    let mut rest = tbs.value;
+    if rest.first() == Some(&TAG_VERSION) {
+        (_, rest) = read_any(rest)?;
+    }
+    // serial, signature algorithm, issuer, validity, subject
+    for _ in 0..5 {
+        (_, rest) = read_any(rest)?;
+    }
+    let (spki, mut rest) = read(rest, TAG_SEQUENCE).context("invalid subject public key info")?;
+
+    let mut info = CertificateInfo {
+        public_key: spki.raw.to_vec(),
+        ..Default::default()
+    };
+
+    // Skip the optional unique IDs to reach the extensions.
+    while let Some(&tag) = rest.first() {
+        let (tlv, next) = read_any(rest)?;
+        rest = next;
+        if tag != TAG_EXTENSIONS {
+            continue;
+        }
+        let (extensions, _) = read(tlv.value, TAG_SEQUENCE)?;
+        let mut extensions = extensions.value;
+        while !extensions.is_empty() {
+            let (extension, next) = read(extensions, TAG_SEQUENCE)?;
+            extensions = next;
+            read_extension(extension.value, &mut info)?;
+        }
+    }
+    Ok(info)
+}
+
+fn read_extension(extension: &[u8], info: &mut CertificateInfo) -> Result<()> {
+    let (oid, mut rest) = read(extension, TAG_OID)?;
+    if rest.first() == Some(&TAG_BOOLEAN) {
+        (_, rest) = read_any(rest)?;
+    }
+    let (value, _) = read(rest, TAG_OCTET_STRING)?;
+
+    match oid.value {
+        OID_SUBJECT_ALT_NAME => {
+            let (names, _) = read(value.value, TAG_SEQUENCE)?;
+            let mut names = names.value;
+            while !names.is_empty() {
+                let (name, next) = read_any(names)?;
+                names = next;
+                if matches!(name.tag, TAG_SAN_EMAIL | TAG_SAN_URI) {
+                    info.subjects
+                        .push(String::from_utf8(name.value.to_vec()).context("invalid SAN")?);
+                }
+            }
+        }
+        OID_FULCIO_ISSUER_V2 => {
+            let (issuer, _) = read(value.value, TAG_UTF8_STRING)?;
+            info.issuer = Some(String::from_utf8(issuer.value.to_vec())?);
+        }
+        OID_FULCIO_ISSUER_V1 if info.issuer.is_none() => {
+            info.issuer = Some(String::from_utf8(value.value.to_vec())?);
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// A DER tag-length-value.
+struct Tlv<'a> {
+    tag: u8,
+    value: &'a [u8],
+    /// The whole encoding, including tag and length.
+    raw: &'a [u8],
+}
+
+fn read(input: &[u8], tag: u8) -> Result<(Tlv<'_>, &[u8])> {
+    let (tlv, rest) = read_any(input)?;
+    ensure!(
+        tlv.tag == tag,
+        "expected DER tag {tag:#04x} but found {:#04x}",
+        tlv.tag
+    );
+    Ok((tlv, rest))
+}
+
+fn read_any(input: &[u8]) -> Result<(Tlv<'_>, &[u8])> {
+    let [tag, first, rest @ ..] = input else {
+        bail!("truncated DER");
+    };
+    let (len, header) = if first & 0x80 == 0 {
+        (*first as usize, 2)
+    } else {
+        let count = (first & 0x7f) as usize;
+        ensure!((1..=4).contains(&count), "unsupported DER length");
+        ensure!(rest.len() >= count, "truncated DER");
+        let len = rest[..count]
+            .iter()
+            .fold(0usize, |len, byte| (len << 8) | *byte as usize);
+        (len, 2 + count)
+    };
+    ensure!(input.len() >= header + len, "truncated DER");
+    let (raw, rest) = input.split_at(header + len);
+    Ok((
+        Tlv {
+            tag: *tag,
+            value: &raw[header..],
+            raw,
+        },
+        rest,
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn can_read_long_form_lengths() {
+        let mut der = vec![TAG_OCTET_STRING, 0x81, 200];
+        der.extend([7; 200]);
+        der.push(0xff);
+        let (tlv, rest) = read(&der, TAG_OCTET_STRING).unwrap();
+        assert_eq!(200, tlv.value.len());
+        assert_eq!(203, tlv.raw.len());
+        assert_eq!(&[0xff], rest);
+    }
+
+    #[test]
+    fn rejects_truncated_der() {
+        assert!(read_any(&[TAG_SEQUENCE, 0x05, 0x01]).is_err());
+        assert!(read_any(&[TAG_SEQUENCE]).is_err());
+    }
+}
@@ -4,6 +4,7 @@ mod git;
 pub mod lookup;
 pub mod manager;
 pub mod manifest;
+pub mod project;
 mod store;
 pub use store::PluginStore;
 
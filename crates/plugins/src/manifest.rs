@@ -10,7 +10,7 @@ use crate::PluginStore;
 /// Expected schema of a plugin manifest. Should match the latest Spin plugin
 /// manifest JSON schema:
 /// <https://github.com/fermyon/spin-plugins/tree/main/json-schema>
-#[derive(Serialize, Debug, Deserialize, PartialEq)]
+#[derive(Clone, Serialize, Debug, Deserialize, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub struct PluginManifest {
     /// Name of the plugin.
@@ -29,6 +29,10 @@ pub struct PluginManifest {
     license: String,
     /// Points to source package[s] of the plugin..
     pub(crate) packages: Vec<PluginPackage>,
+    /// For trigger plugins that Spin runs as a host for, the version of the
+    /// trigger plugin protocol they speak.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    trigger_protocol: Option<u32>,
 }
 
 impl PluginManifest {
@@ -50,6 +54,10 @@ impl PluginManifest {
         self.description.as_deref()
     }
 
+    pub fn trigger_protocol(&self) -> Option<u32> {
+        self.trigger_protocol
+    }
+
     pub fn homepage_url(&self) -> Option<Url> {
         Url::parse(self.homepage.as_deref()?).ok()
     }
@@ -83,7 +91,7 @@ impl PluginManifest {
 }
 
 /// Describes compatibility and location of a plugin source.
-#[derive(Serialize, Debug, Deserialize, PartialEq)]
+#[derive(Clone, Serialize, Debug, Deserialize, PartialEq)]
 pub struct PluginPackage {
     /// Compatible OS.
     pub(crate) os: Os,
@@ -106,7 +114,7 @@ impl PluginPackage {
 }
 
 /// Describes the compatible OS of a plugin
-#[derive(Serialize, Debug, Deserialize, PartialEq)]
+#[derive(Clone, Serialize, Debug, Deserialize, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub(crate) enum Os {
     Linux,
@@ -127,7 +135,7 @@ impl Os {
 }
 
 /// Describes the compatible architecture of a plugin
-#[derive(Serialize, Debug, Deserialize, PartialEq)]
+#[derive(Clone, Serialize, Debug, Deserialize, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub(crate) enum Architecture {
     Amd64,
@@ -313,5 +321,6 @@ mod test {
         let deserialized_plugin = generate_test_manifest("name", "0.1.1", "Mit", None, None);
         assert_eq!(deserialized_plugin.description, None);
         assert_eq!(deserialized_plugin.homepage, None);
+        assert_eq!(deserialized_plugin.trigger_protocol(), None);
     }
 }
@@ -0,0 +1,298 @@
+//! Plugins required by a project, and the exact versions they were locked to.
+//!
+//! A project lists the plugins it needs, with semver constraints, in
+//! `spin-plugins.toml`:
+//!
+//! ```toml
+//! [plugins]
+//! cloud = "0.9"
+//! kube = ">=0.3, <0.5"
+//! ```
+//!
+//! Resolving the requirements against the plugins catalogue picks a version of
+//! each, which is recorded, with the checksums of its packages, in
+//! `spin-plugins.lock` next to it. Later resolutions keep the locked versions
+//! while they still satisfy the requirements, so that everyone working on the
+//! project, and CI, installs identical plugins.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context, Result};
+use semver::{Version, VersionReq};
+use serde::{Deserialize, Serialize};
+
+use crate::manifest::{PluginManifest, PluginPackage};
+
+/// The file in which a project lists the plugins it requires.
+pub const REQUIREMENTS_FILE_NAME: &str = "spin-plugins.toml";
+
+const LOCKFILE_VERSION: u32 = 1;
+
+/// The plugins a project requires.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PluginRequirements {
+    /// Plugin name -> version constraint
+    #[serde(default)]
+    pub plugins: BTreeMap<String, VersionReq>,
+}
+
+impl PluginRequirements {
+    /// Reads requirements from a `spin-plugins.toml` file.
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read plugin requirements {}", path.display()))?;
+        toml::from_str(&text)
+            .with_context(|| format!("Invalid plugin requirements {}", path.display()))
+    }
+}
+
+/// The exact plugin versions a project's requirements were resolved to.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct PluginLockfile {
+    version: u32,
+    #[serde(default, rename = "plugin")]
+    plugins: Vec<LockedPlugin>,
+}
+
+/// A plugin version recorded in a lockfile.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct LockedPlugin {
+    pub name: String,
+    pub version: Version,
+    /// The packages of the version, for all platforms, so that their
+    /// checksums can be verified wherever the lockfile is used.
+    #[serde(rename = "package")]
+    pub packages: Vec<PluginPackage>,
+}
+
+impl Default for PluginLockfile {
+    fn default() -> Self {
+        Self {
+            version: LOCKFILE_VERSION,
+            plugins: vec![],
+        }
+    }
+}
+
+impl PluginLockfile {
+    /// The path of the lockfile for the requirements file at `requirements_path`.
+    pub fn path_for(requirements_path: &Path) -> PathBuf {
+        requirements_path.with_extension("lock")
+    }
+
+    /// Reads a lockfile, returning `None` if it doesn't exist.
+    pub fn from_file(path: &Path) -> Result<Option<Self>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read plugin lockfile {}", path.display()))?;
+        let lockfile: Self = toml::from_str(&text)
+            .with_context(|| format!("Invalid plugin lockfile {}", path.display()))?;
+        anyhow::ensure!(
+            lockfile.version == LOCKFILE_VERSION,
+            "Plugin lockfile {} has unsupported version {}",
+            path.display(),
+            lockfile.version
+        );
+        Ok(Some(lockfile))
+    }
+
+    /// Writes the lockfile.
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let text = format!(
+            "# Generated by `spin plugins sync`: do not edit by hand.\n{}",
+            toml::to_string_pretty(self)?
+        );
+        std::fs::write(path, text)
+            .with_context(|| format!("Failed to write plugin lockfile {}", path.display()))
+    }
+
+    /// The locked plugins.
+    pub fn plugins(&self) -> &[LockedPlugin] {
+        &self.plugins
+    }
+
+    fn get(&self, name: &str) -> Option<&LockedPlugin> {
+        self.plugins.iter().find(|p| p.name == name)
+    }
+}
+
+/// Resolves `requirements` to exact plugin versions from the `catalogue`.
+///
+/// A plugin locked in `lockfile` keeps its locked version if it still
+/// satisfies the requirement, and it is an error if the catalogue's packages
+/// for that version no longer match the lockfile's checksums. Otherwise the
+/// highest version that satisfies the requirement and is compatible with
+/// `spin_version` is chosen. Packages are not considered when choosing, so that
+/// the resolution is the same on every platform.
+///
+/// Returns the new lockfile, and the manifests of the locked versions.
+pub fn resolve(
+    requirements: &PluginRequirements,
+    lockfile: Option<&PluginLockfile>,
+    catalogue: &[PluginManifest],
+    spin_version: &str,
+) -> Result<(PluginLockfile, Vec<PluginManifest>)> {
+    let mut resolved = PluginLockfile::default();
+    let mut manifests = vec![];
+
+    for (name, requirement) in &requirements.plugins {
+        let name = name.to_lowercase();
+        let mut versions = catalogue
+            .iter()
+            .filter(|m| m.name() == name)
+            .filter_map(|m| Some((m.try_version().ok()?, m)));
+
+        let locked = lockfile
+            .and_then(|l| l.get(&name))
+            .filter(|locked| requirement.matches(&locked.version));
+
+        let (version, manifest) = match locked {
+            Some(locked) => {
+                let (version, manifest) = versions
+                    .find(|(version, _)| version == &locked.version)
+                    .ok_or_else(|| {
+                        anyhow!(
+                            "Locked plugin {name}@{} is not in the plugins catalogue",
+                            locked.version
+                        )
+                    })?;
+                anyhow::ensure!(
+                    manifest.packages == locked.packages,
+                    "The packages of plugin {name}@{version} have changed since they were locked. If this is expected, remove the plugin from the lockfile and sync again."
+                );
+                (version, manifest)
+            }
+            None => versions
+                .filter(|(version, m)| {
+                    requirement.matches(version) && m.is_compatible_spin_version(spin_version)
+                })
+                .max_by(|(v1, _), (v2, _)| v1.cmp(v2))
+                .ok_or_else(|| {
+                    anyhow!("No version of plugin {name} matching '{requirement}' is compatible with this version of Spin")
+                })?,
+        };
+
+        resolved.plugins.push(LockedPlugin {
+            name,
+            version,
+            packages: manifest.packages.clone(),
+        });
+        manifests.push(manifest.clone());
+    }
+
+    Ok((resolved, manifests))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn manifest(name: &str, version: &str, spin_compatibility: &str) -> PluginManifest {
+        serde_json::from_value(serde_json::json!({
+            "name": name,
+            "version": version,
+            "spinCompatibility": spin_compatibility,
+            "license": "Apache-2.0",
+            "packages": [
+                {
+                    "os": "linux",
+                    "arch": "amd64",
+                    "url": format!("https://example.com/{name}-{version}.tar.gz"),
+                    "sha256": format!("{name}-{version}-sha"),
+                }
+            ]
+        }))
+        .unwrap()
+    }
+
+    fn catalogue() -> Vec<PluginManifest> {
+        vec![
+            manifest("cloud", "0.8.0", ">=2.0"),
+            manifest("cloud", "0.9.0", ">=2.0"),
+            manifest("cloud", "0.9.1", ">=2.0"),
+            manifest("cloud", "0.10.0", ">=3.0"),
+            manifest("kube", "0.3.0", ">=2.0"),
+        ]
+    }
+
+    fn requirements(toml_text: &str) -> PluginRequirements {
+        toml::from_str(toml_text).unwrap()
+    }
+
+    fn locked_versions(lockfile: &PluginLockfile) -> Vec<(&str, String)> {
+        lockfile
+            .plugins()
+            .iter()
+            .map(|p| (p.name.as_str(), p.version.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn resolves_highest_compatible_matching_versions() {
+        let reqs = requirements("[plugins]\ncloud = \">=0.9\"\nkube = \"0.3\"");
+        let (lockfile, manifests) = resolve(&reqs, None, &catalogue(), "2.5.0").unwrap();
+        assert_eq!(
+            vec![("cloud", "0.9.1".to_owned()), ("kube", "0.3.0".to_owned())],
+            locked_versions(&lockfile)
+        );
+        assert_eq!("0.9.1", manifests[0].version());
+        assert_eq!("cloud-0.9.1-sha", lockfile.plugins()[0].packages[0].sha256);
+    }
+
+    #[test]
+    fn keeps_locked_versions_that_satisfy_requirements() {
+        let reqs = requirements("[plugins]\ncloud = \"0.9\"");
+        let (mut lockfile, _) = resolve(&reqs, None, &catalogue(), "2.5.0").unwrap();
+        lockfile.plugins[0] = LockedPlugin {
+            name: "cloud".into(),
+            version: Version::new(0, 9, 0),
+            packages: manifest("cloud", "0.9.0", ">=2.0").packages,
+        };
+
+        let (relocked, _) = resolve(&reqs, Some(&lockfile), &catalogue(), "2.5.0").unwrap();
+        assert_eq!(lockfile, relocked);
+
+        let reqs = requirements("[plugins]\ncloud = \"0.9.1\"");
+        let (relocked, _) = resolve(&reqs, Some(&lockfile), &catalogue(), "2.5.0").unwrap();
+        assert_eq!(
+            vec![("cloud", "0.9.1".to_owned())],
+            locked_versions(&relocked)
+        );
+    }
+
+    #[test]
+    fn locked_checksums_must_match_catalogue() {
+        let reqs = requirements("[plugins]\ncloud = \"0.9\"");
+        let (mut lockfile, _) = resolve(&reqs, None, &catalogue(), "2.5.0").unwrap();
+        lockfile.plugins[0].packages[0].sha256 = "tampered".into();
+        let err = resolve(&reqs, Some(&lockfile), &catalogue(), "2.5.0").unwrap_err();
+        assert!(err.to_string().contains("have changed"));
+    }
+
+    #[test]
+    fn unsatisfiable_requirements_are_errors() {
+        let reqs = requirements("[plugins]\ncloud = \"0.10\"");
+        assert!(resolve(&reqs, None, &catalogue(), "2.5.0").is_err());
+        let reqs = requirements("[plugins]\nunknown = \"1\"");
+        assert!(resolve(&reqs, None, &catalogue(), "2.5.0").is_err());
+    }
+
+    #[test]
+    fn lockfiles_round_trip() {
+        let reqs = requirements("[plugins]\ncloud = \"0.9\"\nkube = \"0.3\"");
+        let (lockfile, _) = resolve(&reqs, None, &catalogue(), "2.5.0").unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let path = PluginLockfile::path_for(&dir.path().join(REQUIREMENTS_FILE_NAME));
+        assert_eq!(dir.path().join("spin-plugins.lock"), path);
+        lockfile.write(&path).unwrap();
+        assert_eq!(Some(lockfile), PluginLockfile::from_file(&path).unwrap());
+    }
+}
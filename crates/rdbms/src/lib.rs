@@ -0,0 +1,170 @@
+//! Statement handling shared by the relational database factors.
+//!
+//! [`bind_named_params`] lets guests write `:name` parameters, which are
+//! translated to the database's positional parameters. [`lint`] flags
+//! statements that look like they were built by pasting values into SQL
+//! rather than passing them as parameters, and [`ensure_parameterized`]
+//! rejects them.
+
+mod lint;
+mod scan;
+
+use scan::Token;
+
+pub use lint::{ensure_parameterized, lint, Lint, Rejected};
+
+/// The SQL dialect of a statement.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Dialect {
+    /// Postgres, with `$1` positional parameters.
+    Postgres,
+    /// MySQL, with `?` positional parameters.
+    MySql,
+}
+
+/// An error binding a statement's named parameters.
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+pub enum Error {
+    /// The statement uses both named and positional parameters.
+    #[error("statement mixes named (`:{named}`) and positional (`{positional}`) parameters")]
+    MixedParams { named: String, positional: String },
+    /// The number of values doesn't match the number of named parameters.
+    #[error(
+        "statement has {} named parameter(s) ({}) but {given} value(s) were given",
+        names.len(),
+        names.iter().map(|name| format!(":{name}")).collect::<Vec<_>>().join(", ")
+    )]
+    ParamCount { names: Vec<String>, given: usize },
+}
+
+/// Translates the `:name` parameters in `statement` to positional parameters.
+///
+/// Guests pass one value per distinct name, in the order in which the names
+/// first appear in the statement; a name that appears more than once uses
+/// the same value each time. Statements without named parameters are
+/// returned unchanged. Names in string literals (including Postgres `E'...'`
+/// escape strings), quoted identifiers and comments, Postgres `::` casts, and
+/// colons within words such as the slice `arr[1:n]`, are not parameters.
+pub fn bind_named_params<T: Clone>(
+    statement: &str,
+    dialect: Dialect,
+    params: Vec<T>,
+) -> Result<(String, Vec<T>), Error> {
+    let tokens = scan::tokenize(statement, dialect);
+    let mut positional = None;
+    let mut names = vec![];
+    for token in &tokens {
+        match token {
+            Token::Named(name) if !names.contains(name) => names.push(*name),
+            Token::Positional(p) => positional = positional.or(Some(*p)),
+            _ => (),
+        }
+    }
+    if names.is_empty() {
+        return Ok((statement.to_owned(), params));
+    }
+    if let Some(positional) = positional {
+        return Err(Error::MixedParams {
+            named: names[0].to_owned(),
+            positional: positional.to_owned(),
+        });
+    }
+    if names.len() != params.len() {
+        return Err(Error::ParamCount {
+            names: names.into_iter().map(ToOwned::to_owned).collect(),
+            given: params.len(),
+        });
+    }
+
+    let mut translated = String::with_capacity(statement.len());
+    // MySQL parameters are bound in order of appearance, so values are
+    // repeated for names that appear more than once.
+    let mut bound = vec![];
+    for token in tokens {
+        match token {
+            Token::Named(name) => {
+                let index = names.iter().position(|n| *n == name).unwrap();
+                match dialect {
+                    Dialect::Postgres => translated.push_str(&format!("${}", index + 1)),
+                    Dialect::MySql => {
+                        translated.push('?');
+                        bound.push(params[index].clone());
+                    }
+                }
+            }
+            token => translated.push_str(token.text()),
+        }
+    }
+    let params = match dialect {
+        Dialect::Postgres => params,
+        Dialect::MySql => bound,
+    };
+    Ok((translated, params))
+}
+
+impl Token<'_> {
+    fn text(&self) -> &str {
+        match self {
+            Token::Text(text)
+            | Token::Quoted { text, .. }
+            | Token::Comment(text)
+            | Token::Positional(text) => text,
+            Token::Named(_) => unreachable!("named parameters are translated"),
+            Token::Semicolon => ";",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn postgres_names_become_numbered_parameters() {
+        let (statement, params) = bind_named_params(
+            "SELECT * FROM t WHERE a = :id OR b = :id::text AND c = ':not' AND d = :name",
+            Dialect::Postgres,
+            vec![1, 2],
+        )
+        .unwrap();
+        assert_eq!(
+            statement,
+            "SELECT * FROM t WHERE a = $1 OR b = $1::text AND c = ':not' AND d = $2"
+        );
+        assert_eq!(params, [1, 2]);
+    }
+
+    #[test]
+    fn mysql_values_are_repeated_for_repeated_names() {
+        let (statement, params) = bind_named_params(
+            "UPDATE t SET a = :a, b = :b WHERE a <> :a",
+            Dialect::MySql,
+            vec!["x", "y"],
+        )
+        .unwrap();
+        assert_eq!(statement, "UPDATE t SET a = ?, b = ? WHERE a <> ?");
+        assert_eq!(params, ["x", "y", "x"]);
+    }
+
+    #[test]
+    fn statements_without_names_are_unchanged() {
+        let statement = "SELECT ':x', a::int FROM t WHERE id = $1";
+        let bound = bind_named_params(statement, Dialect::Postgres, vec![1]).unwrap();
+        assert_eq!(bound, (statement.to_owned(), vec![1]));
+    }
+
+    #[test]
+    fn mismatched_parameters_are_rejected() {
+        let err = bind_named_params("SELECT :a, ?", Dialect::MySql, vec![1, 2]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "statement mixes named (`:a`) and positional (`?`) parameters"
+        );
+
+        let err = bind_named_params("SELECT :a, :b", Dialect::Postgres, vec![1]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "statement has 2 named parameter(s) (:a, :b) but 1 value(s) were given"
+        );
+    }
+}
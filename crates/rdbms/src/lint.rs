@@ -0,0 +1,128 @@
+//! A heuristic check for statements built by pasting values into SQL.
+
+use std::fmt;
+
+use crate::scan::{self, Token};
+use crate::Dialect;
+
+/// Something in a statement that suggests values were pasted into it.
+///
+/// Each of these is legitimate in some statements, so linting is only
+/// enforced when strict statements are enabled in the runtime config.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Lint {
+    /// A string literal, e.g. `WHERE name = 'alice'`.
+    StringLiteral,
+    /// A quote without a matching closing quote.
+    UnterminatedQuote,
+    /// A comment, which injected values use to cut off the rest of a
+    /// statement.
+    Comment,
+    /// More than one statement, e.g. `SELECT 1; DROP TABLE t`.
+    MultipleStatements,
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Lint::StringLiteral => "an inline string literal",
+            Lint::UnterminatedQuote => "an unterminated quote",
+            Lint::Comment => "a comment",
+            Lint::MultipleStatements => "more than one statement",
+        })
+    }
+}
+
+/// An error for a statement that [`lint`] flagged.
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+#[error(
+    "statement contains {}; pass values as parameters instead",
+    .0.iter().map(ToString::to_string).collect::<Vec<_>>().join(" and ")
+)]
+pub struct Rejected(pub Vec<Lint>);
+
+/// Fails if [`lint`] flags anything in `statement`.
+pub fn ensure_parameterized(statement: &str, dialect: Dialect) -> Result<(), Rejected> {
+    let lints = lint(statement, dialect);
+    if lints.is_empty() {
+        Ok(())
+    } else {
+        Err(Rejected(lints))
+    }
+}
+
+/// Returns what `statement` contains that suggests values were pasted into
+/// it rather than passed as parameters, without duplicates.
+pub fn lint(statement: &str, dialect: Dialect) -> Vec<Lint> {
+    let mut lints = vec![];
+    let mut after_semicolon = false;
+    for token in scan::tokenize(statement, dialect) {
+        if after_semicolon && !is_blank(&token) {
+            push_unique(&mut lints, Lint::MultipleStatements);
+        }
+        match token {
+            Token::Quoted {
+                terminated: false, ..
+            } => push_unique(&mut lints, Lint::UnterminatedQuote),
+            Token::Quoted { string: true, .. } => push_unique(&mut lints, Lint::StringLiteral),
+            Token::Comment(_) => push_unique(&mut lints, Lint::Comment),
+            Token::Semicolon => after_semicolon = true,
+            _ => (),
+        }
+    }
+    lints
+}
+
+/// Whether `token` can follow the last statement, e.g. trailing whitespace.
+fn is_blank(token: &Token) -> bool {
+    match token {
+        Token::Text(text) => text.trim().is_empty(),
+        Token::Comment(_) | Token::Semicolon => true,
+        _ => false,
+    }
+}
+
+fn push_unique(lints: &mut Vec<Lint>, lint: Lint) {
+    if !lints.contains(&lint) {
+        lints.push(lint);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parameterized_statements_pass() {
+        let statement = "INSERT INTO t (a, \"b\") VALUES ($1, :b::text);\n";
+        assert_eq!(lint(statement, Dialect::Postgres), []);
+    }
+
+    #[test]
+    fn pasted_values_are_flagged() {
+        assert_eq!(
+            lint(
+                "SELECT * FROM users WHERE name = '' OR '1'='1' -- '",
+                Dialect::MySql
+            ),
+            [Lint::StringLiteral, Lint::Comment]
+        );
+        assert_eq!(
+            lint("SELECT 1; DROP TABLE users", Dialect::Postgres),
+            [Lint::MultipleStatements]
+        );
+        assert_eq!(
+            lint("SELECT * FROM t WHERE a = 'x", Dialect::Postgres),
+            [Lint::UnterminatedQuote]
+        );
+    }
+
+    #[test]
+    fn rejections_list_lints() {
+        let err = ensure_parameterized("SELECT 'a' -- b", Dialect::Postgres).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "statement contains an inline string literal and a comment; pass values as parameters instead"
+        );
+    }
+}
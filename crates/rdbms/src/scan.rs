@@ -0,0 +1,274 @@
+//! Splitting SQL statements into the tokens that parameter binding and
+//! linting care about.
+
+use crate::Dialect;
+
+/// A piece of a SQL statement.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Token<'a> {
+    /// SQL text with no special meaning here, e.g. keywords and operators.
+    Text(&'a str),
+    /// A quoted string or identifier, including its quotes.
+    Quoted {
+        text: &'a str,
+        /// Whether this is a string literal rather than a quoted identifier.
+        string: bool,
+        /// Whether the closing quote was found.
+        terminated: bool,
+    },
+    /// A comment, including its delimiters.
+    Comment(&'a str),
+    /// A named parameter, e.g. `:id`, without the colon.
+    Named(&'a str),
+    /// A positional parameter, e.g. `$1` or `?`.
+    Positional(&'a str),
+    /// The `;` statement separator.
+    Semicolon,
+}
+
+/// Splits `sql` into tokens. Concatenating the tokens' text gives `sql`.
+pub(crate) fn tokenize(sql: &str, dialect: Dialect) -> Vec<Token<'_>> {
+    let mut tokens = vec![];
+    let mut offset = 0;
+    while offset < sql.len() {
+        let prev = offset.checked_sub(1).map(|i| sql.as_bytes()[i]);
+        let (token, len) = next_token(&sql[offset..], prev, dialect);
+        tokens.push(token);
+        offset += len;
+    }
+    tokens
+}
+
+/// Returns the token at the start of `rest` and its length in bytes. `prev` is
+/// the byte before `rest` in the statement, if any.
+fn next_token(rest: &str, prev: Option<u8>, dialect: Dialect) -> (Token<'_>, usize) {
+    let bytes = rest.as_bytes();
+    let next = bytes.get(1).copied();
+    // Whether `rest` starts a word, rather than continuing one, e.g. the `E`
+    // in `E'...'` but not in `TYPE'...'`.
+    let word_start = !prev.is_some_and(is_name_char);
+    match (bytes[0], dialect) {
+        // A Postgres escape string, in which backslashes escape quotes.
+        (b'E' | b'e', Dialect::Postgres) if word_start && next == Some(b'\'') => {
+            let (token, len) = quoted(&rest[1..], b'\'', true, true);
+            let Token::Quoted { terminated, .. } = token else {
+                unreachable!()
+            };
+            let token = Token::Quoted {
+                text: &rest[..len + 1],
+                string: true,
+                terminated,
+            };
+            (token, len + 1)
+        }
+        (b'\'', _) => quoted(rest, b'\'', true, dialect == Dialect::MySql),
+        (b'"', Dialect::Postgres) => quoted(rest, b'"', false, false),
+        (b'"', Dialect::MySql) => quoted(rest, b'"', true, true),
+        (b'`', Dialect::MySql) => quoted(rest, b'`', false, false),
+        (b'-', _) if next == Some(b'-') => line_comment(rest),
+        (b'#', Dialect::MySql) => line_comment(rest),
+        (b'/', _) if next == Some(b'*') => {
+            let len = rest[2..].find("*/").map_or(rest.len(), |end| end + 4);
+            (Token::Comment(&rest[..len]), len)
+        }
+        (b'$', Dialect::Postgres) if next.is_some_and(|b| b.is_ascii_digit()) => {
+            let len = 1 + rest[1..]
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(rest.len() - 1);
+            (Token::Positional(&rest[..len]), len)
+        }
+        (b'$', Dialect::Postgres) => dollar_quoted(rest),
+        (b'?', Dialect::MySql) => (Token::Positional("?"), 1),
+        // `::` is a Postgres cast, not a parameter.
+        (b':', _) if next == Some(b':') => (Token::Text("::"), 2),
+        // A colon within a word, e.g. in `arr[1:n]` or `a:b`, isn't a parameter.
+        (b':', _) if word_start && next.is_some_and(is_name_start) => {
+            let len = 1 + name_len(&rest[1..]);
+            (Token::Named(&rest[1..len]), len)
+        }
+        (b';', _) => (Token::Semicolon, 1),
+        _ => {
+            // Consume up to the next byte that might start another token.
+            let len = (1..rest.len())
+                .find(|&i| match bytes[i] {
+                    b'\'' | b'"' | b'`' | b'-' | b'#' | b'/' | b'$' | b'?' | b':' | b';' => true,
+                    b'E' | b'e' => {
+                        dialect == Dialect::Postgres
+                            && bytes.get(i + 1) == Some(&b'\'')
+                            && !is_name_char(bytes[i - 1])
+                    }
+                    _ => false,
+                })
+                .unwrap_or(rest.len());
+            (Token::Text(&rest[..len]), len)
+        }
+    }
+}
+
+fn quoted(rest: &str, quote: u8, string: bool, backslash_escapes: bool) -> (Token<'_>, usize) {
+    let bytes = rest.as_bytes();
+    let mut i = 1;
+    let terminated = loop {
+        match bytes.get(i) {
+            None => break false,
+            Some(b'\\') if backslash_escapes => i += 2,
+            // A doubled quote is an escaped quote.
+            Some(&b) if b == quote && bytes.get(i + 1) == Some(&quote) => i += 2,
+            Some(&b) if b == quote => {
+                i += 1;
+                break true;
+            }
+            Some(_) => i += 1,
+        }
+    };
+    let len = i.min(rest.len());
+    let token = Token::Quoted {
+        text: &rest[..len],
+        string,
+        terminated,
+    };
+    (token, len)
+}
+
+fn line_comment(rest: &str) -> (Token<'_>, usize) {
+    let len = rest.find('\n').unwrap_or(rest.len());
+    (Token::Comment(&rest[..len]), len)
+}
+
+/// A Postgres dollar-quoted string, e.g. `$$text$$` or `$tag$text$tag$`.
+fn dollar_quoted(rest: &str) -> (Token<'_>, usize) {
+    let tag_len = name_len(&rest[1..]);
+    if rest.as_bytes().get(1 + tag_len) != Some(&b'$') {
+        return (Token::Text("$"), 1);
+    }
+    let tag = &rest[..tag_len + 2];
+    let (len, terminated) = match rest[tag.len()..].find(tag) {
+        Some(end) => (2 * tag.len() + end, true),
+        None => (rest.len(), false),
+    };
+    let token = Token::Quoted {
+        text: &rest[..len],
+        string: true,
+        terminated,
+    };
+    (token, len)
+}
+
+fn is_name_start(b: u8) -> bool {
+    b.is_ascii_alphabetic() || b == b'_'
+}
+
+fn is_name_char(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b == b'_'
+}
+
+/// The length of the name at the start of `s`, which may be zero.
+fn name_len(s: &str) -> usize {
+    if !s.as_bytes().first().copied().is_some_and(is_name_start) {
+        return 0;
+    }
+    s.bytes().position(|b| !is_name_char(b)).unwrap_or(s.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokens_cover_the_statement() {
+        let sql =
+            "SELECT x::int, 'it''s :not' AS \"a:b\" -- :nor\nFROM t WHERE id = :id AND n = $1;";
+        let tokens = tokenize(sql, Dialect::Postgres);
+        assert_eq!(
+            tokens,
+            [
+                Token::Text("SELECT x"),
+                Token::Text("::"),
+                Token::Text("int, "),
+                Token::Quoted {
+                    text: "'it''s :not'",
+                    string: true,
+                    terminated: true
+                },
+                Token::Text(" AS "),
+                Token::Quoted {
+                    text: "\"a:b\"",
+                    string: false,
+                    terminated: true
+                },
+                Token::Text(" "),
+                Token::Comment("-- :nor"),
+                Token::Text("\nFROM t WHERE id = "),
+                Token::Named("id"),
+                Token::Text(" AND n = "),
+                Token::Positional("$1"),
+                Token::Semicolon,
+            ]
+        );
+    }
+
+    #[test]
+    fn dialects_quote_differently() {
+        let tokens = tokenize(r#"$body$ :x $body$ `:y` "a\"b" ?"#, Dialect::Postgres);
+        assert!(matches!(
+            tokens[0],
+            Token::Quoted {
+                text: "$body$ :x $body$",
+                ..
+            }
+        ));
+        assert!(tokens.contains(&Token::Named("y")));
+        assert!(!tokens.iter().any(|t| matches!(t, Token::Positional(_))));
+
+        let tokens = tokenize(r#"`:y` "a\"b" ? 'x"#, Dialect::MySql);
+        assert_eq!(
+            tokens,
+            [
+                Token::Quoted {
+                    text: "`:y`",
+                    string: false,
+                    terminated: true
+                },
+                Token::Text(" "),
+                Token::Quoted {
+                    text: r#""a\"b""#,
+                    string: true,
+                    terminated: true
+                },
+                Token::Text(" "),
+                Token::Positional("?"),
+                Token::Text(" "),
+                Token::Quoted {
+                    text: "'x",
+                    string: true,
+                    terminated: false
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn colons_within_words_are_not_parameters() {
+        let tokens = tokenize("SELECT arr[1:n], a:b, (:c)", Dialect::Postgres);
+        let named = tokens
+            .iter()
+            .filter_map(|t| match t {
+                Token::Named(name) => Some(*name),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(named, ["c"]);
+    }
+
+    #[test]
+    fn postgres_escape_strings_are_quoted() {
+        let tokens = tokenize(r"SELECT E'it\'s :x', type'y' :z", Dialect::Postgres);
+        assert!(tokens.contains(&Token::Quoted {
+            text: r"E'it\'s :x'",
+            string: true,
+            terminated: true
+        }));
+        assert!(!tokens.contains(&Token::Named("x")));
+        assert!(tokens.contains(&Token::Named("z")));
+    }
+}
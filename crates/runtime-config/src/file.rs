@@ -0,0 +1,270 @@
+//! Reading runtime config files.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, ensure, Context as _};
+use spin_common::ui::quoted_path;
+use toml::Value;
+
+/// The top-level key listing the files a runtime config file includes.
+const INCLUDE_KEY: &str = "include";
+
+/// Reads and parses a runtime config file.
+///
+/// String values may reference environment variables as `${NAME}`, or as
+/// `${NAME:-default}` to use `default` if `NAME` is not set. `$${` is a
+/// literal `${`.
+///
+/// The top-level `include` key lists other runtime config files, relative to
+/// the including file, whose keys are merged into the including file's. Each
+/// key may be defined by only one file. Relative paths in the values of any of
+/// the files, e.g. to TLS certificates, are relative to the directory of the
+/// top-level file.
+pub fn read_runtime_config_file(path: &Path) -> anyhow::Result<toml::Table> {
+    read_file(path, &mut vec![], &|name| std::env::var(name).ok())
+}
+
+type Env<'a> = dyn Fn(&str) -> Option<String> + 'a;
+
+/// Reads the file at `path`, which is included by the files in `including`.
+fn read_file(path: &Path, including: &mut Vec<PathBuf>, env: &Env) -> anyhow::Result<toml::Table> {
+    let canonical_path = path
+        .canonicalize()
+        .with_context(|| format!("failed to read runtime config file {}", quoted_path(path)))?;
+    if let Some(start) = including.iter().position(|p| p == &canonical_path) {
+        let cycle = including[start..]
+            .iter()
+            .chain([&canonical_path])
+            .map(|p| quoted_path(p).to_string())
+            .collect::<Vec<_>>()
+            .join(" -> ");
+        bail!("runtime config files include each other: {cycle}");
+    }
+
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read runtime config file {}", quoted_path(path)))?;
+    let mut table: toml::Table = toml::from_str(&contents).with_context(|| {
+        format!(
+            "failed to parse runtime config file {} as toml",
+            quoted_path(path)
+        )
+    })?;
+    interpolate_table(&mut table, "", env)
+        .with_context(|| format!("invalid runtime config file {}", quoted_path(path)))?;
+
+    let includes: Vec<String> = match table.remove(INCLUDE_KEY) {
+        Some(includes) => includes.try_into().with_context(|| {
+            format!(
+                "invalid runtime config file {}: `{INCLUDE_KEY}` must be a list of file paths",
+                quoted_path(path)
+            )
+        })?,
+        None => return Ok(table),
+    };
+
+    let dir = path.parent().unwrap_or(Path::new("."));
+    including.push(canonical_path);
+    for include in includes {
+        let included = read_file(&dir.join(&include), including, env)?;
+        merge(&mut table, included, "").with_context(|| {
+            format!(
+                "failed to include {} in runtime config file {}",
+                quoted_path(&include),
+                quoted_path(path)
+            )
+        })?;
+    }
+    including.pop();
+    Ok(table)
+}
+
+/// Merges the keys of `from` into `into`, failing if both define a key.
+fn merge(into: &mut toml::Table, from: toml::Table, prefix: &str) -> anyhow::Result<()> {
+    for (key, value) in from {
+        let key_path = join_key(prefix, &key);
+        match (into.get_mut(&key), value) {
+            (None, value) => {
+                into.insert(key, value);
+            }
+            (Some(Value::Table(into)), Value::Table(from)) => merge(into, from, &key_path)?,
+            (Some(_), _) => bail!("`{key_path}` is defined in more than one file"),
+        }
+    }
+    Ok(())
+}
+
+fn interpolate_table(table: &mut toml::Table, prefix: &str, env: &Env) -> anyhow::Result<()> {
+    for (key, value) in table {
+        interpolate_value(value, &join_key(prefix, key), env)?;
+    }
+    Ok(())
+}
+
+fn interpolate_value(value: &mut Value, key_path: &str, env: &Env) -> anyhow::Result<()> {
+    match value {
+        Value::String(s) => {
+            *s = interpolate(s, env).with_context(|| format!("invalid value for `{key_path}`"))?
+        }
+        Value::Array(values) => {
+            for (index, value) in values.iter_mut().enumerate() {
+                interpolate_value(value, &format!("{key_path}[{index}]"), env)?;
+            }
+        }
+        Value::Table(table) => interpolate_table(table, key_path, env)?,
+        _ => (),
+    }
+    Ok(())
+}
+
+/// Replaces the environment variable references in `s`.
+fn interpolate(s: &str, env: &Env) -> anyhow::Result<String> {
+    let mut interpolated = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(start) = rest.find('$') {
+        interpolated.push_str(&rest[..start]);
+        rest = &rest[start..];
+        if let Some(after) = rest.strip_prefix("$${") {
+            interpolated.push_str("${");
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix("${") {
+            let end = after
+                .find('}')
+                .with_context(|| format!("unterminated `${{` in {s:?}"))?;
+            let (name, default) = match after[..end].split_once(":-") {
+                Some((name, default)) => (name, Some(default)),
+                None => (&after[..end], None),
+            };
+            ensure!(
+                !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'),
+                "invalid environment variable name {name:?}"
+            );
+            match (env(name), default) {
+                (Some(value), _) => interpolated.push_str(&value),
+                (None, Some(default)) => interpolated.push_str(default),
+                (None, None) => bail!("environment variable {name:?} is not set"),
+            }
+            rest = &after[end + 1..];
+        } else {
+            interpolated.push('$');
+            rest = &rest[1..];
+        }
+    }
+    interpolated.push_str(rest);
+    Ok(interpolated)
+}
+
+fn join_key(prefix: &str, key: &str) -> String {
+    if prefix.is_empty() {
+        key.to_owned()
+    } else {
+        format!("{prefix}.{key}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env(name: &str) -> Option<String> {
+        match name {
+            "REDIS_HOST" => Some("redis.local".into()),
+            "EMPTY" => Some("".into()),
+            _ => None,
+        }
+    }
+
+    fn read(path: &Path) -> anyhow::Result<toml::Table> {
+        read_file(path, &mut vec![], &env)
+    }
+
+    #[test]
+    fn interpolates_environment_variables() -> anyhow::Result<()> {
+        assert_eq!(
+            interpolate("redis://${REDIS_HOST}:6379", &env)?,
+            "redis://redis.local:6379"
+        );
+        assert_eq!(interpolate("${UNSET:-fallback}", &env)?, "fallback");
+        assert_eq!(interpolate("${EMPTY:-fallback}", &env)?, "");
+        assert_eq!(
+            interpolate("$${REDIS_HOST} costs $5", &env)?,
+            "${REDIS_HOST} costs $5"
+        );
+
+        let err = interpolate("${UNSET}", &env).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            r#"environment variable "UNSET" is not set"#
+        );
+        assert!(interpolate("${REDIS_HOST", &env).is_err());
+        assert!(interpolate("${NOT-A-NAME}", &env).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn merges_included_files() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        std::fs::write(
+            dir.path().join("runtime-config.toml"),
+            r#"
+            include = ["stores/kv.toml"]
+            [sqlite_database.default]
+            type = "spin"
+            "#,
+        )?;
+        std::fs::create_dir(dir.path().join("stores"))?;
+        std::fs::write(
+            dir.path().join("stores/kv.toml"),
+            r#"
+            [key_value_store.default]
+            type = "redis"
+            url = "redis://${REDIS_HOST}"
+            "#,
+        )?;
+
+        let table = read(&dir.path().join("runtime-config.toml"))?;
+        assert_eq!(
+            table,
+            toml::toml! {
+                [sqlite_database.default]
+                type = "spin"
+                [key_value_store.default]
+                type = "redis"
+                url = "redis://redis.local"
+            }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn keys_defined_in_more_than_one_file_are_rejected() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        std::fs::write(
+            dir.path().join("a.toml"),
+            "include = [\"b.toml\"]\n[key_value_store.default]\ntype = \"spin\"\n",
+        )?;
+        std::fs::write(
+            dir.path().join("b.toml"),
+            "[key_value_store.default]\ntype = \"redis\"\n",
+        )?;
+
+        let err = read(&dir.path().join("a.toml")).unwrap_err();
+        assert_eq!(
+            err.root_cause().to_string(),
+            "`key_value_store.default.type` is defined in more than one file"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn include_cycles_are_rejected() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        std::fs::write(dir.path().join("a.toml"), "include = [\"b.toml\"]\n")?;
+        std::fs::write(dir.path().join("b.toml"), "include = [\"a.toml\"]\n")?;
+
+        let err = read(&dir.path().join("a.toml")).unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("runtime config files include each other: "));
+        Ok(())
+    }
+}
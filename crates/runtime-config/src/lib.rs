@@ -1,20 +1,38 @@
+mod file;
+mod validate;
+
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 use anyhow::Context as _;
 use spin_common::ui::quoted_path;
+use spin_expressions::Provider;
+use spin_factor_actor::ActorFactor;
+use spin_factor_cache::CacheFactor;
+use spin_factor_context::ContextFactor;
+use spin_factor_crypto::{runtime_config::SpinCryptoRuntimeConfig, CryptoFactor};
+use spin_factor_discovery::DiscoveryFactor;
+use spin_factor_experiments::ExperimentsFactor;
 use spin_factor_key_value::runtime_config::spin::{self as key_value};
 use spin_factor_key_value::KeyValueFactor;
 use spin_factor_llm::{spin as llm, LlmFactor};
+use spin_factor_multipart::MultipartFactor;
 use spin_factor_outbound_http::OutboundHttpFactor;
+use spin_factor_outbound_mail::OutboundMailFactor;
 use spin_factor_outbound_mqtt::OutboundMqttFactor;
 use spin_factor_outbound_mysql::OutboundMysqlFactor;
 use spin_factor_outbound_networking::runtime_config::spin::SpinTlsRuntimeConfig;
 use spin_factor_outbound_networking::OutboundNetworkingFactor;
 use spin_factor_outbound_pg::OutboundPgFactor;
 use spin_factor_outbound_redis::OutboundRedisFactor;
+use spin_factor_outbound_sftp::OutboundSftpFactor;
 use spin_factor_sqlite::SqliteFactor;
-use spin_factor_variables::VariablesFactor;
+use spin_factor_tasks::TasksFactor;
+use spin_factor_variables::{
+    runtime_config::RuntimeConfig as VariablesRuntimeConfig, VariablesFactor,
+};
 use spin_factor_wasi::WasiFactor;
+use spin_factor_wasi_nn::{runtime_config::SpinWasiNnRuntimeConfig, WasiNnFactor};
 use spin_factors::runtime_config::toml::GetTomlValue as _;
 use spin_factors::{
     runtime_config::toml::TomlKeyTracker, FactorRuntimeConfigSource, RuntimeConfigSourceFinalizer,
@@ -24,6 +42,10 @@ use spin_sqlite as sqlite;
 use spin_trigger::cli::UserProvidedPath;
 use toml::Value;
 
+pub use file::read_runtime_config_file;
+pub use sqlite::DEFAULT_SQLITE_DB_FILENAME;
+pub use validate::{json_schema, validate, IssueKind, Section, ValidationIssue, SECTIONS};
+
 /// The default state directory for the trigger.
 pub const DEFAULT_STATE_DIR: &str = ".spin";
 
@@ -91,6 +113,8 @@ where
 {
     /// Creates a new resolved runtime configuration from a runtime config source TOML file.
     ///
+    /// See [`read_runtime_config_file`] for how the file is read.
+    ///
     /// `provided_state_dir` is the explicitly provided state directory, if any.
     pub fn from_file(
         runtime_config_path: Option<&Path>,
@@ -99,20 +123,7 @@ where
         provided_log_dir: UserProvidedPath,
     ) -> anyhow::Result<Self> {
         let toml = match runtime_config_path {
-            Some(runtime_config_path) => {
-                let file = std::fs::read_to_string(runtime_config_path).with_context(|| {
-                    format!(
-                        "failed to read runtime config file '{}'",
-                        runtime_config_path.display()
-                    )
-                })?;
-                toml::from_str(&file).with_context(|| {
-                    format!(
-                        "failed to parse runtime config file '{}' as toml",
-                        runtime_config_path.display()
-                    )
-                })?
-            }
+            Some(runtime_config_path) => read_runtime_config_file(runtime_config_path)?,
             None => Default::default(),
         };
         let toml_resolver =
@@ -131,7 +142,21 @@ where
             .map(ToOwned::to_owned);
         let state_dir = toml_resolver.state_dir()?;
         let tls_resolver = runtime_config_dir.clone().map(SpinTlsRuntimeConfig::new);
-        let key_value_resolver = key_value_config_resolver(runtime_config_dir, state_dir.clone());
+        let crypto_resolver = runtime_config_dir.clone().map(SpinCryptoRuntimeConfig::new);
+        let wasi_nn_resolver = runtime_config_dir.clone().map(SpinWasiNnRuntimeConfig::new);
+        // Variable providers are shared with the key-value stores that source
+        // credentials from variables.
+        let variables = spin_variables::runtime_config_from_toml(&toml_resolver.table)?;
+        let variable_providers = variables
+            .providers
+            .into_iter()
+            .map(Arc::from)
+            .collect::<Vec<Arc<dyn Provider>>>();
+        let key_value_resolver = key_value_config_resolver(
+            runtime_config_dir,
+            state_dir.clone(),
+            shared_providers(&variable_providers),
+        );
         let sqlite_resolver = sqlite_config_resolver(state_dir.clone())
             .context("failed to resolve sqlite runtime config")?;
 
@@ -141,7 +166,13 @@ where
             toml_resolver,
             &key_value_resolver,
             tls_resolver.as_ref(),
+            crypto_resolver.as_ref(),
+            wasi_nn_resolver.as_ref(),
             &sqlite_resolver,
+            VariablesRuntimeConfig {
+                providers: shared_providers(&variable_providers),
+                decryptors: variables.decryptors,
+            },
         );
         // Note: all valid fields in the runtime config must have been referenced at
         // this point or the finalizer will fail due to `validate_all_keys_used`
@@ -276,7 +307,10 @@ pub struct TomlRuntimeConfigSource<'a, 'b> {
     toml: TomlResolver<'b>,
     key_value: &'a key_value::RuntimeConfigResolver,
     tls: Option<&'a SpinTlsRuntimeConfig>,
+    crypto: Option<&'a SpinCryptoRuntimeConfig>,
+    wasi_nn: Option<&'a SpinWasiNnRuntimeConfig>,
     sqlite: &'a sqlite::RuntimeConfigResolver,
+    variables: Option<VariablesRuntimeConfig>,
 }
 
 impl<'a, 'b> TomlRuntimeConfigSource<'a, 'b> {
@@ -284,13 +318,19 @@ impl<'a, 'b> TomlRuntimeConfigSource<'a, 'b> {
         toml_resolver: TomlResolver<'b>,
         key_value: &'a key_value::RuntimeConfigResolver,
         tls: Option<&'a SpinTlsRuntimeConfig>,
+        crypto: Option<&'a SpinCryptoRuntimeConfig>,
+        wasi_nn: Option<&'a SpinWasiNnRuntimeConfig>,
         sqlite: &'a sqlite::RuntimeConfigResolver,
+        variables: VariablesRuntimeConfig,
     ) -> Self {
         Self {
             toml: toml_resolver,
             key_value,
             tls,
+            crypto,
+            wasi_nn,
             sqlite,
+            variables: Some(variables),
         }
     }
 }
@@ -319,19 +359,132 @@ impl FactorRuntimeConfigSource<VariablesFactor> for TomlRuntimeConfigSource<'_,
     fn get_runtime_config(
         &mut self,
     ) -> anyhow::Result<Option<<VariablesFactor as spin_factors::Factor>::RuntimeConfig>> {
-        Ok(Some(spin_variables::runtime_config_from_toml(
-            &self.toml.table,
-        )?))
+        Ok(self.variables.take())
     }
 }
 
 impl FactorRuntimeConfigSource<OutboundPgFactor> for TomlRuntimeConfigSource<'_, '_> {
+    fn get_runtime_config(
+        &mut self,
+    ) -> anyhow::Result<Option<spin_factor_outbound_pg::RuntimeConfig>> {
+        let Some(value) = self.toml.table.get("outbound_pg") else {
+            return Ok(None);
+        };
+        let config = value
+            .clone()
+            .try_into()
+            .context("invalid [outbound_pg] runtime config")?;
+        Ok(Some(config))
+    }
+}
+
+impl FactorRuntimeConfigSource<OutboundMysqlFactor> for TomlRuntimeConfigSource<'_, '_> {
+    fn get_runtime_config(
+        &mut self,
+    ) -> anyhow::Result<Option<spin_factor_outbound_mysql::RuntimeConfig>> {
+        let Some(value) = self.toml.table.get("outbound_mysql") else {
+            return Ok(None);
+        };
+        let config = value
+            .clone()
+            .try_into()
+            .context("invalid [outbound_mysql] runtime config")?;
+        Ok(Some(config))
+    }
+}
+
+impl FactorRuntimeConfigSource<TasksFactor> for TomlRuntimeConfigSource<'_, '_> {
+    fn get_runtime_config(&mut self) -> anyhow::Result<Option<spin_factor_tasks::RuntimeConfig>> {
+        let Some(value) = self.toml.table.get("tasks") else {
+            return Ok(None);
+        };
+        let config = value
+            .clone()
+            .try_into()
+            .context("invalid [tasks] runtime config")?;
+        Ok(Some(config))
+    }
+}
+
+impl FactorRuntimeConfigSource<ActorFactor> for TomlRuntimeConfigSource<'_, '_> {
+    fn get_runtime_config(&mut self) -> anyhow::Result<Option<spin_factor_actor::RuntimeConfig>> {
+        let Some(value) = self.toml.table.get("actor") else {
+            return Ok(None);
+        };
+        let config = value
+            .clone()
+            .try_into()
+            .context("invalid [actor] runtime config")?;
+        Ok(Some(config))
+    }
+}
+
+impl FactorRuntimeConfigSource<CacheFactor> for TomlRuntimeConfigSource<'_, '_> {
+    fn get_runtime_config(&mut self) -> anyhow::Result<Option<spin_factor_cache::RuntimeConfig>> {
+        let Some(value) = self.toml.table.get("cache") else {
+            return Ok(None);
+        };
+        let config = value
+            .clone()
+            .try_into()
+            .context("invalid [cache] runtime config")?;
+        Ok(Some(config))
+    }
+}
+
+impl FactorRuntimeConfigSource<ContextFactor> for TomlRuntimeConfigSource<'_, '_> {
+    fn get_runtime_config(&mut self) -> anyhow::Result<Option<spin_factor_context::RuntimeConfig>> {
+        let Some(value) = self.toml.table.get("context") else {
+            return Ok(None);
+        };
+        let config = value
+            .clone()
+            .try_into()
+            .context("invalid [context] runtime config")?;
+        Ok(Some(config))
+    }
+}
+
+impl FactorRuntimeConfigSource<CryptoFactor> for TomlRuntimeConfigSource<'_, '_> {
+    fn get_runtime_config(&mut self) -> anyhow::Result<Option<spin_factor_crypto::RuntimeConfig>> {
+        let Some(crypto) = self.crypto else {
+            return Ok(None);
+        };
+        crypto.config_from_table(&self.toml.table)
+    }
+}
+
+impl FactorRuntimeConfigSource<WasiNnFactor> for TomlRuntimeConfigSource<'_, '_> {
+    fn get_runtime_config(&mut self) -> anyhow::Result<Option<spin_factor_wasi_nn::RuntimeConfig>> {
+        let Some(wasi_nn) = self.wasi_nn else {
+            return Ok(None);
+        };
+        wasi_nn.config_from_table(&self.toml.table)
+    }
+}
+
+impl FactorRuntimeConfigSource<DiscoveryFactor> for TomlRuntimeConfigSource<'_, '_> {
     fn get_runtime_config(&mut self) -> anyhow::Result<Option<()>> {
         Ok(None)
     }
 }
 
-impl FactorRuntimeConfigSource<OutboundMysqlFactor> for TomlRuntimeConfigSource<'_, '_> {
+impl FactorRuntimeConfigSource<ExperimentsFactor> for TomlRuntimeConfigSource<'_, '_> {
+    fn get_runtime_config(
+        &mut self,
+    ) -> anyhow::Result<Option<spin_factor_experiments::RuntimeConfig>> {
+        let Some(value) = self.toml.table.get("experiment") else {
+            return Ok(None);
+        };
+        let config = value
+            .clone()
+            .try_into()
+            .context("invalid [experiment] runtime config")?;
+        Ok(Some(config))
+    }
+}
+
+impl FactorRuntimeConfigSource<MultipartFactor> for TomlRuntimeConfigSource<'_, '_> {
     fn get_runtime_config(&mut self) -> anyhow::Result<Option<()>> {
         Ok(None)
     }
@@ -349,18 +502,46 @@ impl FactorRuntimeConfigSource<OutboundRedisFactor> for TomlRuntimeConfigSource<
     }
 }
 
-impl FactorRuntimeConfigSource<WasiFactor> for TomlRuntimeConfigSource<'_, '_> {
+impl FactorRuntimeConfigSource<OutboundMailFactor> for TomlRuntimeConfigSource<'_, '_> {
     fn get_runtime_config(&mut self) -> anyhow::Result<Option<()>> {
         Ok(None)
     }
 }
 
-impl FactorRuntimeConfigSource<OutboundHttpFactor> for TomlRuntimeConfigSource<'_, '_> {
+impl FactorRuntimeConfigSource<OutboundSftpFactor> for TomlRuntimeConfigSource<'_, '_> {
     fn get_runtime_config(&mut self) -> anyhow::Result<Option<()>> {
         Ok(None)
     }
 }
 
+impl FactorRuntimeConfigSource<WasiFactor> for TomlRuntimeConfigSource<'_, '_> {
+    fn get_runtime_config(&mut self) -> anyhow::Result<Option<spin_factor_wasi::RuntimeConfig>> {
+        let Some(value) = self.toml.table.get("wasi") else {
+            return Ok(None);
+        };
+        let config = value
+            .clone()
+            .try_into()
+            .context("invalid [wasi] runtime config")?;
+        Ok(Some(config))
+    }
+}
+
+impl FactorRuntimeConfigSource<OutboundHttpFactor> for TomlRuntimeConfigSource<'_, '_> {
+    fn get_runtime_config(
+        &mut self,
+    ) -> anyhow::Result<Option<spin_factor_outbound_http::RuntimeConfig>> {
+        let Some(value) = self.toml.table.get("outbound_http") else {
+            return Ok(None);
+        };
+        let config = value
+            .clone()
+            .try_into()
+            .context("invalid [outbound_http] runtime config")?;
+        Ok(Some(config))
+    }
+}
+
 impl FactorRuntimeConfigSource<OutboundMqttFactor> for TomlRuntimeConfigSource<'_, '_> {
     fn get_runtime_config(&mut self) -> anyhow::Result<Option<()>> {
         Ok(None)
@@ -375,6 +556,12 @@ impl FactorRuntimeConfigSource<SqliteFactor> for TomlRuntimeConfigSource<'_, '_>
 
 impl RuntimeConfigSourceFinalizer for TomlRuntimeConfigSource<'_, '_> {
     fn finalize(&mut self) -> anyhow::Result<()> {
+        // The `[registry]` section is consumed by `spin up` when pulling the app.
+        self.toml.table.get("registry");
+        // The `[http_server]` section is consumed by the HTTP trigger.
+        self.toml.table.get("http_server");
+        // The `[telemetry]` section is consumed by the trigger.
+        self.toml.table.get("telemetry");
         Ok(self.toml.validate_all_keys_used()?)
     }
 }
@@ -385,10 +572,12 @@ const DEFAULT_KEY_VALUE_STORE_LABEL: &str = "default";
 ///
 /// Takes a base path that all local key-value stores which are configured with
 /// relative paths will be relative to. It also takes a default store base path
-/// which will be used as the directory for the default store.
+/// which will be used as the directory for the default store. Store types that
+/// can source credentials from variables resolve them with the given providers.
 pub fn key_value_config_resolver(
     local_store_base_path: Option<PathBuf>,
     default_store_base_path: Option<PathBuf>,
+    variable_providers: Vec<Box<dyn Provider>>,
 ) -> key_value::RuntimeConfigResolver {
     let mut key_value = key_value::RuntimeConfigResolver::new();
 
@@ -403,7 +592,9 @@ pub fn key_value_config_resolver(
         .register_store_type(spin_key_value_redis::RedisKeyValueStore::new())
         .unwrap();
     key_value
-        .register_store_type(spin_key_value_azure::AzureKeyValueStore::new())
+        .register_store_type(
+            spin_key_value_azure::AzureKeyValueStore::with_variable_providers(variable_providers),
+        )
         .unwrap();
     key_value
         .register_store_type(spin_key_value_aws::AwsDynamoKeyValueStore::new())
@@ -422,8 +613,17 @@ pub fn key_value_config_resolver(
     key_value
 }
 
+/// Boxes each of the given providers so that they can be shared by more than
+/// one runtime config.
+fn shared_providers(providers: &[Arc<dyn Provider>]) -> Vec<Box<dyn Provider>> {
+    providers
+        .iter()
+        .map(|provider| Box::new(provider.clone()) as _)
+        .collect()
+}
+
 /// The default filename for the SQLite database.
-const DEFAULT_SPIN_STORE_FILENAME: &str = "sqlite_key_value.db";
+pub const DEFAULT_SPIN_STORE_FILENAME: &str = "sqlite_key_value.db";
 
 /// The sqlite runtime configuration resolver.
 ///
@@ -540,6 +740,33 @@ mod tests {
             .all(|label| runtime_config.has_store_manager(label)));
     }
 
+    #[test]
+    fn azure_key_value_credentials_can_come_from_variables() {
+        define_test_factor!(key_value: KeyValueFactor);
+
+        let toml = toml::toml! {
+            [key_value_store.foo]
+            type = "azure_cosmos"
+            account = "account"
+            database = "database"
+            container = "container"
+            key_variable = "cosmos_key"
+            credential_refresh_interval_secs = 60
+        };
+        assert!(resolve_toml(toml, "config.toml").is_ok());
+
+        let toml = toml::toml! {
+            [key_value_store.foo]
+            type = "azure_cosmos"
+            account = "account"
+            database = "database"
+            container = "container"
+            key = "secret"
+            resource_token_variable = "cosmos_token"
+        };
+        assert!(resolve_toml(toml, "config.toml").is_err());
+    }
+
     #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
     async fn custom_spin_key_value_works_with_custom_paths() -> anyhow::Result<()> {
         use spin_world::v2::key_value::HostStore;
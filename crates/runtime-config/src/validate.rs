@@ -0,0 +1,827 @@
+//! Validating runtime config files against the sections Spin understands.
+
+use std::fmt;
+
+use serde_json::{json, Value as Json};
+use toml::Value;
+
+/// A top-level section of the runtime config file.
+#[derive(Debug)]
+pub struct Section {
+    /// The top-level key of the section.
+    pub key: &'static str,
+    /// The factor (or command) that reads the section.
+    pub owner: &'static str,
+    /// A short description of the section, for the JSON schema.
+    pub description: &'static str,
+    shape: Shape,
+}
+
+#[derive(Debug)]
+enum Shape {
+    /// A single value, e.g. `state_dir = ".spin"`.
+    Value(FieldType),
+    /// A table of fields, e.g. `[registry]`.
+    Table(&'static [Field]),
+    /// A table of labeled entries, each of which selects a type, e.g.
+    /// `[key_value_store.<label>]`.
+    Labeled(&'static [Type]),
+    /// A single table which selects a type, e.g. `[llm_compute]`.
+    Typed(&'static [Type]),
+    /// An array of tables, each of which selects a type, e.g.
+    /// `[[variables_provider]]`.
+    TypedArray(&'static [Type]),
+    /// An array of tables of fields, e.g. `[[client_tls]]`.
+    Array(&'static [Field]),
+    /// A table of keyed tables of fields, e.g. `[outbound_tls.<host>]`.
+    Keyed(&'static [Field]),
+}
+
+/// One of the types a typed table may select with its `type` field.
+#[derive(Debug)]
+struct Type {
+    name: &'static str,
+    fields: &'static [Field],
+}
+
+#[derive(Debug)]
+struct Field {
+    name: &'static str,
+    ty: FieldType,
+    required: bool,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum FieldType {
+    String,
+    Bool,
+    Integer,
+    StringArray,
+    StringMap,
+    /// A table whose contents are not validated here.
+    Table,
+    /// An array of tables whose contents are not validated here.
+    TableArray,
+}
+
+const fn field(name: &'static str, ty: FieldType) -> Field {
+    Field {
+        name,
+        ty,
+        required: false,
+    }
+}
+
+const fn required(name: &'static str, ty: FieldType) -> Field {
+    Field {
+        name,
+        ty,
+        required: true,
+    }
+}
+
+/// The sections of the runtime config file, as read by the Spin CLI.
+pub const SECTIONS: &[Section] = &[
+    Section {
+        key: "state_dir",
+        owner: "spin up",
+        description: "The directory for application state, such as the default key-value store and SQLite database.",
+        shape: Shape::Value(FieldType::String),
+    },
+    Section {
+        key: "log_dir",
+        owner: "spin up",
+        description: "The directory component stdout and stderr are logged to.",
+        shape: Shape::Value(FieldType::String),
+    },
+    Section {
+        key: "registry",
+        owner: "spin up",
+        description: "How applications pulled from registries are verified, and mirrors of registries to pull them through.",
+        shape: Shape::Table(&[
+            field("verification", FieldType::Table),
+            field("mirrors", FieldType::Table),
+        ]),
+    },
+    Section {
+        key: "http_server",
+        owner: "http trigger",
+        description: "How the HTTP trigger serves TLS connections, verifies client certificates, splits traffic to canary components, and mirrors traffic to other components or servers.",
+        shape: Shape::Table(&[
+            field("tls", FieldType::Table),
+            field("canaries", FieldType::Table),
+            field("mirrors", FieldType::Table),
+        ]),
+    },
+    Section {
+        key: "telemetry",
+        owner: "spin up",
+        description: "How trace context is propagated to and from other services.",
+        shape: Shape::Table(&[
+            field("propagation", FieldType::String),
+            field("redis_payloads", FieldType::Bool),
+        ]),
+    },
+    Section {
+        key: "key_value_store",
+        owner: "key-value",
+        description: "Key-value stores, by label.",
+        shape: Shape::Labeled(&[
+            Type {
+                name: "spin",
+                fields: &[field("path", FieldType::String)],
+            },
+            Type {
+                name: "redis",
+                fields: &[required("url", FieldType::String)],
+            },
+            Type {
+                name: "azure_cosmos",
+                fields: &[
+                    field("key", FieldType::String),
+                    field("key_variable", FieldType::String),
+                    field("resource_token_variable", FieldType::String),
+                    field("managed_identity", FieldType::Bool),
+                    field("managed_identity_client_id", FieldType::String),
+                    field("credential_refresh_interval_secs", FieldType::Integer),
+                    required("account", FieldType::String),
+                    required("database", FieldType::String),
+                    required("container", FieldType::String),
+                ],
+            },
+            Type {
+                name: "aws_dynamo",
+                fields: &[
+                    field("access_key", FieldType::String),
+                    field("secret_key", FieldType::String),
+                    field("token", FieldType::String),
+                    required("region", FieldType::String),
+                    field("consistent_read", FieldType::Bool),
+                    required("table", FieldType::String),
+                ],
+            },
+        ]),
+    },
+    Section {
+        key: "sqlite_database",
+        owner: "sqlite",
+        description: "SQLite databases, by label.",
+        shape: Shape::Labeled(&[
+            Type {
+                name: "spin",
+                fields: &[field("path", FieldType::String)],
+            },
+            Type {
+                name: "libsql",
+                fields: &[
+                    required("url", FieldType::String),
+                    required("token", FieldType::String),
+                ],
+            },
+        ]),
+    },
+    Section {
+        key: "llm_compute",
+        owner: "llm",
+        description: "The engine for LLM inferencing and embeddings.",
+        shape: Shape::Typed(&[
+            Type {
+                name: "spin",
+                fields: &[],
+            },
+            Type {
+                name: "remote_http",
+                fields: &[
+                    required("url", FieldType::String),
+                    required("auth_token", FieldType::String),
+                ],
+            },
+        ]),
+    },
+    Section {
+        key: "llm_policy",
+        owner: "llm",
+        description: "Token limits, content filters and auditing for components' LLM use.",
+        shape: Shape::Table(&[
+            field("max_tokens_per_request", FieldType::Integer),
+            field("max_tokens_per_hour", FieldType::Integer),
+            field("deny_patterns", FieldType::StringArray),
+            field("deny_words", FieldType::StringArray),
+            field("audit", FieldType::Bool),
+            field("components", FieldType::Table),
+        ]),
+    },
+    Section {
+        key: "outbound_http",
+        owner: "outbound http",
+        description: "Settings for components' outbound HTTP requests.",
+        shape: Shape::Table(&[field("single_flight", FieldType::StringArray)]),
+    },
+    Section {
+        key: "outbound_pg",
+        owner: "outbound postgres",
+        description: "Settings for components' outbound Postgres connections.",
+        shape: Shape::Table(&[field("strict_statements", FieldType::Bool)]),
+    },
+    Section {
+        key: "outbound_mysql",
+        owner: "outbound mysql",
+        description: "Settings for components' outbound MySQL connections.",
+        shape: Shape::Table(&[field("strict_statements", FieldType::Bool)]),
+    },
+    Section {
+        key: "actor",
+        owner: "actor",
+        description: "Limits on instances waiting to enter components' actors.",
+        shape: Shape::Table(&[
+            field("max_queue_length", FieldType::Integer),
+            field("timeout_secs", FieldType::Integer),
+        ]),
+    },
+    Section {
+        key: "cache",
+        owner: "cache",
+        description: "Limits on the in-memory cache each app's components share.",
+        shape: Shape::Table(&[
+            field("max_bytes", FieldType::Integer),
+            field("default_ttl_secs", FieldType::Integer),
+        ]),
+    },
+    Section {
+        key: "context",
+        owner: "context",
+        description: "Metadata about the deployment that components can read.",
+        shape: Shape::Table(&[field("environment", FieldType::String)]),
+    },
+    Section {
+        key: "wasi",
+        owner: "wasi",
+        description: "Host environment variables passed through to components.",
+        shape: Shape::Table(&[
+            field("environment_passthrough", FieldType::StringArray),
+            field("allowed_environment_passthrough", FieldType::StringArray),
+        ]),
+    },
+    Section {
+        key: "experiment",
+        owner: "experiments",
+        description: "Experiments, such as A/B tests, whose variants components assign users to, by name.",
+        shape: Shape::Keyed(&[
+            required("variants", FieldType::Table),
+            field("salt", FieldType::String),
+        ]),
+    },
+    Section {
+        key: "wasi_nn",
+        owner: "wasi-nn",
+        description: "Which components may run models with wasi:nn, limits on the models, and models they can load by name.",
+        shape: Shape::Table(&[
+            field("components", FieldType::StringArray),
+            field("max_model_bytes", FieldType::Integer),
+            field("max_concurrent_inferences", FieldType::Integer),
+            field("gpu", FieldType::Bool),
+            field("models", FieldType::Table),
+        ]),
+    },
+    Section {
+        key: "crypto_key",
+        owner: "crypto",
+        description: "Keys components sign, verify, encrypt and decrypt with, by label.",
+        shape: Shape::Labeled(&[
+            Type {
+                name: "file",
+                fields: &[
+                    required("algorithm", FieldType::String),
+                    required("path", FieldType::String),
+                ],
+            },
+            Type {
+                name: "env",
+                fields: &[
+                    required("algorithm", FieldType::String),
+                    required("variable", FieldType::String),
+                ],
+            },
+            Type {
+                name: "aws_kms",
+                fields: &[
+                    required("algorithm", FieldType::String),
+                    required("key_id", FieldType::String),
+                    required("region", FieldType::String),
+                    field("access_key", FieldType::String),
+                    field("secret_key", FieldType::String),
+                    field("token", FieldType::String),
+                ],
+            },
+        ]),
+    },
+    Section {
+        key: "tasks",
+        owner: "tasks",
+        description: "Settings for the background tasks and timers components schedule.",
+        shape: Shape::Table(&[field("key_value_store", FieldType::String)]),
+    },
+    Section {
+        key: "variables_provider",
+        owner: "variables",
+        description: "Providers of application variable values, in order of precedence.",
+        shape: Shape::TypedArray(VARIABLES_PROVIDER_TYPES),
+    },
+    Section {
+        key: "config_provider",
+        owner: "variables",
+        description: "Deprecated alias for `variables_provider`.",
+        shape: Shape::TypedArray(VARIABLES_PROVIDER_TYPES),
+    },
+    Section {
+        key: "variables_decryptor",
+        owner: "variables",
+        description: "Key management services which decrypt `enc:`-prefixed variable values.",
+        shape: Shape::TypedArray(&[
+            Type {
+                name: "aws_kms",
+                fields: &[
+                    required("region", FieldType::String),
+                    field("access_key", FieldType::String),
+                    field("secret_key", FieldType::String),
+                    field("token", FieldType::String),
+                ],
+            },
+            Type {
+                name: "gcp_kms",
+                fields: &[
+                    required("key_name", FieldType::String),
+                    field("access_token", FieldType::String),
+                ],
+            },
+            Type {
+                name: "azure_key_vault",
+                fields: &[
+                    required("key_id", FieldType::String),
+                    field("client_id", FieldType::String),
+                    field("client_secret", FieldType::String),
+                    field("tenant_id", FieldType::String),
+                    field("authority_host", FieldType::String),
+                ],
+            },
+        ]),
+    },
+    Section {
+        key: "client_tls",
+        owner: "outbound networking",
+        description: "TLS settings for components' outbound connections.",
+        shape: Shape::Array(&[
+            required("component_ids", FieldType::StringArray),
+            required("hosts", FieldType::StringArray),
+            field("ca_use_webpki_roots", FieldType::Bool),
+            field("ca_roots_file", FieldType::String),
+            field("client_cert_file", FieldType::String),
+            field("client_private_key_file", FieldType::String),
+            field("min_tls_version", FieldType::String),
+        ]),
+    },
+    Section {
+        key: "outbound_tls",
+        owner: "outbound networking",
+        description: "TLS settings for all components' outbound connections, by host or `*.`-prefixed domain.",
+        shape: Shape::Keyed(&[
+            field("ca_use_webpki_roots", FieldType::Bool),
+            field("ca_roots_file", FieldType::String),
+            field("client_cert_file", FieldType::String),
+            field("client_private_key_file", FieldType::String),
+            field("min_tls_version", FieldType::String),
+        ]),
+    },
+    Section {
+        key: "outbound_proxy",
+        owner: "outbound networking",
+        description: "The proxies components' outbound HTTP and PostgreSQL connections go through.",
+        shape: Shape::Table(&[
+            field("url", FieldType::String),
+            field("no_proxy", FieldType::StringArray),
+            field("override", FieldType::TableArray),
+        ]),
+    },
+];
+
+const VARIABLES_PROVIDER_TYPES: &[Type] = &[
+    Type {
+        name: "env",
+        fields: &[
+            field("prefix", FieldType::String),
+            field("dotenv_path", FieldType::String),
+        ],
+    },
+    Type {
+        name: "static",
+        fields: &[required("values", FieldType::StringMap)],
+    },
+    Type {
+        name: "vault",
+        fields: &[
+            required("url", FieldType::String),
+            required("token", FieldType::String),
+            required("mount", FieldType::String),
+            field("prefix", FieldType::String),
+        ],
+    },
+    Type {
+        name: "azure_key_vault",
+        fields: &[
+            required("vault_url", FieldType::String),
+            field("client_id", FieldType::String),
+            field("client_secret", FieldType::String),
+            field("tenant_id", FieldType::String),
+            field("authority_host", FieldType::String),
+        ],
+    },
+];
+
+/// A problem found by [`validate`].
+#[derive(Debug, PartialEq, Eq)]
+pub struct ValidationIssue {
+    /// The [owner](Section::owner) of the section the problem is in, or
+    /// `None` for top-level keys that no section uses.
+    pub owner: Option<&'static str>,
+    /// The dotted path to the problem key, e.g. `key_value_store.default.url`.
+    pub key: String,
+    /// What is wrong with the key.
+    pub kind: IssueKind,
+}
+
+/// What is wrong with a runtime config key.
+#[derive(Debug, PartialEq, Eq)]
+pub enum IssueKind {
+    /// A top-level key that no section uses.
+    UnusedSection,
+    /// A key that the section does not use.
+    UnknownKey,
+    /// A required key is missing.
+    MissingKey,
+    /// A value has the wrong type.
+    WrongType { expected: &'static str },
+    /// A `type` field names a type the section doesn't support.
+    UnknownType { expected: Vec<&'static str> },
+}
+
+impl fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let key = &self.key;
+        match &self.kind {
+            IssueKind::UnusedSection => write!(f, "`{key}` is not used by Spin"),
+            IssueKind::UnknownKey => write!(f, "unknown key `{key}`"),
+            IssueKind::MissingKey => write!(f, "missing required key `{key}`"),
+            IssueKind::WrongType { expected } => write!(f, "`{key}` must be {expected}"),
+            IssueKind::UnknownType { expected } => write!(
+                f,
+                "unknown `{key}`; expected one of: {}",
+                expected.join(", ")
+            ),
+        }
+    }
+}
+
+/// Checks a runtime config table, e.g. as read by
+/// [`read_runtime_config_file`](crate::read_runtime_config_file), against
+/// the [`SECTIONS`] Spin understands.
+///
+/// This checks the structure of the file only; values such as URLs and file
+/// paths are checked when the runtime config is resolved.
+pub fn validate(table: &toml::Table) -> Vec<ValidationIssue> {
+    let mut validator = Validator::default();
+    for (key, value) in table {
+        match SECTIONS.iter().find(|section| section.key == key) {
+            Some(section) => {
+                validator.owner = Some(section.owner);
+                validator.section(&section.shape, key, value);
+            }
+            None => {
+                validator.owner = None;
+                validator.issue(key.clone(), IssueKind::UnusedSection);
+            }
+        }
+    }
+    validator.issues
+}
+
+#[derive(Default)]
+struct Validator {
+    owner: Option<&'static str>,
+    issues: Vec<ValidationIssue>,
+}
+
+impl Validator {
+    fn issue(&mut self, key: String, kind: IssueKind) {
+        self.issues.push(ValidationIssue {
+            owner: self.owner,
+            key,
+            kind,
+        });
+    }
+
+    fn section(&mut self, shape: &Shape, key: &str, value: &Value) {
+        match shape {
+            Shape::Value(ty) => self.value(*ty, key, value),
+            Shape::Table(fields) => {
+                if let Some(table) = self.table(key, value) {
+                    self.fields(fields, key, table);
+                }
+            }
+            Shape::Labeled(types) => {
+                if let Some(table) = self.table(key, value) {
+                    for (label, value) in table {
+                        self.typed(types, &format!("{key}.{label}"), value);
+                    }
+                }
+            }
+            Shape::Typed(types) => self.typed(types, key, value),
+            Shape::TypedArray(types) => {
+                for (index, value) in self.array(key, value).iter().enumerate() {
+                    self.typed(types, &format!("{key}[{index}]"), value);
+                }
+            }
+            Shape::Array(fields) => {
+                for (index, value) in self.array(key, value).iter().enumerate() {
+                    let key = format!("{key}[{index}]");
+                    if let Some(table) = self.table(&key, value) {
+                        self.fields(fields, &key, table);
+                    }
+                }
+            }
+            Shape::Keyed(fields) => {
+                if let Some(table) = self.table(key, value) {
+                    for (name, value) in table {
+                        let key = format!("{key}.{name}");
+                        if let Some(table) = self.table(&key, value) {
+                            self.fields(fields, &key, table);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn typed(&mut self, types: &[Type], key: &str, value: &Value) {
+        let Some(table) = self.table(key, value) else {
+            return;
+        };
+        let type_key = format!("{key}.type");
+        let name = match table.get("type") {
+            Some(Value::String(name)) => name,
+            Some(_) => return self.wrong_type(type_key, "a string"),
+            None => return self.issue(type_key, IssueKind::MissingKey),
+        };
+        let Some(ty) = types.iter().find(|ty| ty.name == name) else {
+            let expected = types.iter().map(|ty| ty.name).collect();
+            return self.issue(type_key, IssueKind::UnknownType { expected });
+        };
+        let mut table = table.clone();
+        table.remove("type");
+        self.fields(ty.fields, key, &table);
+    }
+
+    fn fields(&mut self, fields: &[Field], key: &str, table: &toml::Table) {
+        for (name, value) in table {
+            let field_key = format!("{key}.{name}");
+            match fields.iter().find(|field| field.name == name) {
+                Some(field) => self.value(field.ty, &field_key, value),
+                None => self.issue(field_key, IssueKind::UnknownKey),
+            }
+        }
+        for field in fields {
+            if field.required && !table.contains_key(field.name) {
+                self.issue(format!("{key}.{}", field.name), IssueKind::MissingKey);
+            }
+        }
+    }
+
+    fn value(&mut self, ty: FieldType, key: &str, value: &Value) {
+        let ok = match ty {
+            FieldType::String => value.is_str(),
+            FieldType::Bool => value.is_bool(),
+            FieldType::Integer => value.is_integer(),
+            FieldType::StringArray => value
+                .as_array()
+                .is_some_and(|values| values.iter().all(Value::is_str)),
+            FieldType::StringMap => value
+                .as_table()
+                .is_some_and(|table| table.values().all(Value::is_str)),
+            FieldType::Table => value.is_table(),
+            FieldType::TableArray => value
+                .as_array()
+                .is_some_and(|values| values.iter().all(Value::is_table)),
+        };
+        if !ok {
+            self.wrong_type(key.to_owned(), ty.description());
+        }
+    }
+
+    fn table<'a>(&mut self, key: &str, value: &'a Value) -> Option<&'a toml::Table> {
+        let table = value.as_table();
+        if table.is_none() {
+            self.wrong_type(key.to_owned(), "a table");
+        }
+        table
+    }
+
+    fn array<'a>(&mut self, key: &str, value: &'a Value) -> &'a [Value] {
+        match value.as_array() {
+            Some(values) => values,
+            None => {
+                self.wrong_type(key.to_owned(), "an array of tables");
+                &[]
+            }
+        }
+    }
+
+    fn wrong_type(&mut self, key: String, expected: &'static str) {
+        self.issue(key, IssueKind::WrongType { expected });
+    }
+}
+
+impl FieldType {
+    fn description(self) -> &'static str {
+        match self {
+            Self::String => "a string",
+            Self::Bool => "a boolean",
+            Self::Integer => "an integer",
+            Self::StringArray => "an array of strings",
+            Self::StringMap => "a table of strings",
+            Self::Table => "a table",
+            Self::TableArray => "an array of tables",
+        }
+    }
+
+    fn json_schema(self) -> Json {
+        match self {
+            Self::String => json!({ "type": "string" }),
+            Self::Bool => json!({ "type": "boolean" }),
+            Self::Integer => json!({ "type": "integer" }),
+            Self::StringArray => json!({ "type": "array", "items": { "type": "string" } }),
+            Self::StringMap => {
+                json!({ "type": "object", "additionalProperties": { "type": "string" } })
+            }
+            Self::Table => json!({ "type": "object" }),
+            Self::TableArray => json!({ "type": "array", "items": { "type": "object" } }),
+        }
+    }
+}
+
+/// Returns a JSON schema for runtime config files, for editor autocomplete
+/// and validation.
+pub fn json_schema() -> Json {
+    let properties = SECTIONS
+        .iter()
+        .map(|section| {
+            let mut schema = section.shape.json_schema();
+            schema["description"] = section.description.into();
+            (section.key.to_owned(), schema)
+        })
+        .collect::<serde_json::Map<_, _>>();
+    json!({
+        "$schema": "http://json-schema.org/draft-07/schema#",
+        "title": "Spin runtime config",
+        "type": "object",
+        "properties": properties,
+        "additionalProperties": false,
+    })
+}
+
+impl Shape {
+    fn json_schema(&self) -> Json {
+        match self {
+            Self::Value(ty) => ty.json_schema(),
+            Self::Table(fields) => fields_json_schema(fields, None),
+            Self::Labeled(types) => json!({
+                "type": "object",
+                "additionalProperties": types_json_schema(types),
+            }),
+            Self::Typed(types) => types_json_schema(types),
+            Self::TypedArray(types) => json!({
+                "type": "array",
+                "items": types_json_schema(types),
+            }),
+            Self::Array(fields) => json!({
+                "type": "array",
+                "items": fields_json_schema(fields, None),
+            }),
+            Self::Keyed(fields) => json!({
+                "type": "object",
+                "additionalProperties": fields_json_schema(fields, None),
+            }),
+        }
+    }
+}
+
+fn types_json_schema(types: &[Type]) -> Json {
+    let one_of = types
+        .iter()
+        .map(|ty| fields_json_schema(ty.fields, Some(ty.name)))
+        .collect::<Vec<_>>();
+    json!({ "oneOf": one_of })
+}
+
+fn fields_json_schema(fields: &[Field], type_name: Option<&str>) -> Json {
+    let mut properties = fields
+        .iter()
+        .map(|field| (field.name.to_owned(), field.ty.json_schema()))
+        .collect::<serde_json::Map<_, _>>();
+    let mut required = fields
+        .iter()
+        .filter(|field| field.required)
+        .map(|field| field.name)
+        .collect::<Vec<_>>();
+    if let Some(type_name) = type_name {
+        properties.insert("type".to_owned(), json!({ "const": type_name }));
+        required.insert(0, "type");
+    }
+    json!({
+        "type": "object",
+        "properties": properties,
+        "required": required,
+        "additionalProperties": false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn valid_config_has_no_issues() {
+        let table = toml::toml! {
+            state_dir = ".spin"
+            [key_value_store.default]
+            type = "redis"
+            url = "redis://localhost"
+            [sqlite_database.default]
+            type = "spin"
+            [llm_compute]
+            type = "remote_http"
+            url = "http://localhost"
+            auth_token = "secret"
+            [[variables_provider]]
+            type = "static"
+            values = { greeting = "hello" }
+            [[client_tls]]
+            component_ids = ["api"]
+            hosts = ["example.com"]
+            [outbound_tls."*.example.com"]
+            min_tls_version = "1.3"
+            [registry.verification]
+            keys = ["cosign.pub"]
+        };
+        assert_eq!(validate(&table), vec![]);
+    }
+
+    #[test]
+    fn issues_are_reported_by_owner() {
+        let table = toml::toml! {
+            stat_dir = ".spin"
+            [key_value_store.default]
+            type = "redis"
+            uri = "redis://localhost"
+            [key_value_store.other]
+            type = "mongo"
+            [[client_tls]]
+            component_ids = "api"
+            hosts = ["example.com"]
+        };
+        let mut issues = validate(&table)
+            .into_iter()
+            .map(|issue| (issue.owner, issue.to_string()))
+            .collect::<Vec<_>>();
+        issues.sort();
+        assert_eq!(
+            issues,
+            [
+                (None, "`stat_dir` is not used by Spin".to_owned()),
+                (
+                    Some("key-value"),
+                    "missing required key `key_value_store.default.url`".to_owned()
+                ),
+                (
+                    Some("key-value"),
+                    "unknown `key_value_store.other.type`; expected one of: spin, redis, azure_cosmos, aws_dynamo".to_owned()
+                ),
+                (
+                    Some("key-value"),
+                    "unknown key `key_value_store.default.uri`".to_owned()
+                ),
+                (
+                    Some("outbound networking"),
+                    "`client_tls[0].component_ids` must be an array of strings".to_owned()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn json_schema_describes_typed_sections() {
+        let schema = json_schema();
+        let redis = &schema["properties"]["key_value_store"]["additionalProperties"]["oneOf"][1];
+        assert_eq!(redis["properties"]["type"], json!({ "const": "redis" }));
+        assert_eq!(redis["required"], json!(["type", "url"]));
+        assert_eq!(schema["additionalProperties"], json!(false));
+    }
+}
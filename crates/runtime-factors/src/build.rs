@@ -1,13 +1,17 @@
-use std::path::PathBuf;
+use std::{path::PathBuf, time::Duration};
 
 use super::{TriggerAppArgs, TriggerFactors, TriggerFactorsRuntimeConfig};
 
 use anyhow::Context as _;
+use spin_common::diagnostics::Diagnostic;
 use spin_factors_executor::FactorsExecutor;
-use spin_runtime_config::ResolvedRuntimeConfig;
+use spin_runtime_config::{ResolvedRuntimeConfig, TomlResolver};
 use spin_trigger::cli::{
-    FactorsConfig, InitialKvSetterHook, KeyValueDefaultStoreSummaryHook, RuntimeFactorsBuilder,
-    SqlStatementExecutorHook, SqliteDefaultStoreSummaryHook, StdioLoggingExecutorHooks,
+    CoreDumpHook, DeterministicExecutionHook, FactorsConfig, HostCallAuditHook,
+    HostCallRecordingHook, InitialKvSetterHook, KeyValueDefaultStoreSummaryHook,
+    KeyValueNamespaceMigrationHook, RuntimeFactorsBuilder, SqlStatementExecutorHook,
+    SqliteDefaultStoreSummaryHook, SqliteMigrationsHook, StdioLoggingExecutorHooks,
+    DEFAULT_CORE_DUMP_DIR_NAME,
 };
 
 /// A [`RuntimeFactorsBuilder`] for [`TriggerFactors`].
@@ -22,24 +26,64 @@ impl RuntimeFactorsBuilder for FactorsBuilder {
         config: &FactorsConfig,
         args: &Self::CliArgs,
     ) -> anyhow::Result<(Self::Factors, Self::RuntimeConfig)> {
-        let runtime_config = ResolvedRuntimeConfig::<TriggerFactorsRuntimeConfig>::from_file(
-            config.runtime_config_file.clone().as_deref(),
-            config.local_app_dir.clone().map(PathBuf::from),
-            config.state_dir.clone(),
-            config.log_dir.clone(),
-        )?;
+        let mut runtime_config = match &config.runtime_config {
+            Some(toml) => ResolvedRuntimeConfig::<TriggerFactorsRuntimeConfig>::new(
+                TomlResolver::new(
+                    toml,
+                    config.local_app_dir.clone().map(PathBuf::from),
+                    config.state_dir.clone(),
+                    config.log_dir.clone(),
+                ),
+                config.runtime_config_file.as_deref(),
+            )?,
+            None => ResolvedRuntimeConfig::<TriggerFactorsRuntimeConfig>::from_file(
+                config.runtime_config_file.clone().as_deref(),
+                config.local_app_dir.clone().map(PathBuf::from),
+                config.state_dir.clone(),
+                config.log_dir.clone(),
+            )?,
+        };
+        if let Some(runtime_config_override) = &args.runtime_config_override {
+            runtime_config_override(&mut runtime_config.runtime_config)
+                .context("failed to apply runtime config override")?;
+        }
 
-        runtime_config.summarize(config.runtime_config_file.as_deref());
+        // A runtime config given as a table wasn't read from the file.
+        let runtime_config_file = config
+            .runtime_config_file
+            .as_deref()
+            .filter(|_| config.runtime_config.is_none());
+        runtime_config.summarize(runtime_config_file);
 
-        let factors = TriggerFactors::new(
+        let mut factors = TriggerFactors::new(
             runtime_config.state_dir(),
             config.working_dir.clone(),
             args.allow_transient_write,
+            args.verify_assets,
         )
         .context("failed to create factors")?;
+        factors.disable_unconfigured(&runtime_config.runtime_config);
+        factors
+            .wasi
+            .allow_environment_passthrough(args.allow_env_passthrough.iter().cloned());
+        factors.wasi.set_diagnostics(config.diagnostics.clone());
+        factors
+            .outbound_networking
+            .set_diagnostics(config.diagnostics.clone());
+        if args.learn_outbound_hosts {
+            config.diagnostics.report(Diagnostic::new(
+                "learning-outbound-hosts",
+                "Learning outbound hosts: requests to destinations that aren't allowed will be made anyway.",
+            ));
+            factors.outbound_networking.enable_learning();
+        }
         Ok((factors, runtime_config))
     }
 
+    fn runtime_config_toml(runtime_config: &Self::RuntimeConfig) -> Option<&toml::Table> {
+        Some(&runtime_config.toml)
+    }
+
     fn configure_app<U: Send + 'static>(
         executor: &mut FactorsExecutor<Self::Factors, U>,
         runtime_config: &Self::RuntimeConfig,
@@ -50,12 +94,49 @@ impl RuntimeFactorsBuilder for FactorsBuilder {
             config.follow_components.clone(),
             runtime_config.log_dir(),
         ));
+        // Migrate databases before running any statements against them.
+        executor.add_hooks(SqliteMigrationsHook);
         executor.add_hooks(SqlStatementExecutorHook::new(
             args.sqlite_statements.clone(),
         ));
+        // Migrate existing data before setting any new keys.
+        executor.add_hooks(KeyValueNamespaceMigrationHook::new(
+            args.key_value_namespace_migrations.clone(),
+        ));
         executor.add_hooks(InitialKvSetterHook::new(args.key_values.clone()));
         executor.add_hooks(SqliteDefaultStoreSummaryHook);
         executor.add_hooks(KeyValueDefaultStoreSummaryHook);
+        if config.core_dumps {
+            let dir = config
+                .core_dump_dir
+                .clone()
+                .or_else(|| {
+                    runtime_config
+                        .state_dir()
+                        .map(|dir| dir.join(DEFAULT_CORE_DUMP_DIR_NAME))
+                })
+                .context("core dumps require a state directory; pass `--core-dump-dir`")?;
+            executor.add_hooks(CoreDumpHook::new(dir));
+        }
+        if let Some(dir) = &args.record_host_calls {
+            executor.add_hooks(HostCallRecordingHook::record(dir)?);
+        }
+        if let Some(path) = &args.replay_host_calls {
+            executor.add_hooks(HostCallRecordingHook::replay(path)?);
+        }
+        if let Some(seed) = args.deterministic_seed {
+            let mut hook = DeterministicExecutionHook::new(seed);
+            if let Some(start) = args.deterministic_clock_start {
+                hook = hook.clock_start(Duration::from_secs(start));
+            }
+            if let Some(step) = args.deterministic_clock_step {
+                hook = hook.clock_step(Duration::from_micros(step));
+            }
+            executor.add_hooks(hook);
+        }
+        if let Some(path) = &args.audit_host_calls {
+            executor.add_hooks(HostCallAuditHook::new(path)?);
+        }
         Ok(())
     }
 }
@@ -3,20 +3,35 @@ mod build;
 pub use build::FactorsBuilder;
 
 use std::path::PathBuf;
+use std::sync::Arc;
 
 use anyhow::Context as _;
 use spin_common::arg_parser::parse_kv;
+use spin_factor_actor::ActorFactor;
+use spin_factor_cache::CacheFactor;
+use spin_factor_context::ContextFactor;
+use spin_factor_crypto::CryptoFactor;
+use spin_factor_discovery::DiscoveryFactor;
+use spin_factor_experiments::ExperimentsFactor;
 use spin_factor_key_value::KeyValueFactor;
 use spin_factor_llm::LlmFactor;
+use spin_factor_multipart::MultipartFactor;
 use spin_factor_outbound_http::OutboundHttpFactor;
+use spin_factor_outbound_mail::OutboundMailFactor;
 use spin_factor_outbound_mqtt::{NetworkedMqttClient, OutboundMqttFactor};
 use spin_factor_outbound_mysql::OutboundMysqlFactor;
 use spin_factor_outbound_networking::OutboundNetworkingFactor;
 use spin_factor_outbound_pg::OutboundPgFactor;
 use spin_factor_outbound_redis::OutboundRedisFactor;
+use spin_factor_outbound_sftp::OutboundSftpFactor;
 use spin_factor_sqlite::SqliteFactor;
+use spin_factor_tasks::TasksFactor;
 use spin_factor_variables::VariablesFactor;
-use spin_factor_wasi::{spin::SpinFilesMounter, WasiFactor};
+use spin_factor_wasi::{
+    spin::{AssetVerification, SpinFilesMounter},
+    WasiFactor,
+};
+use spin_factor_wasi_nn::WasiNnFactor;
 use spin_factors::RuntimeFactors;
 use spin_runtime_config::{ResolvedRuntimeConfig, TomlRuntimeConfigSource};
 
@@ -25,6 +40,7 @@ pub struct TriggerFactors {
     pub wasi: WasiFactor,
     pub variables: VariablesFactor,
     pub key_value: KeyValueFactor,
+    pub tasks: TasksFactor,
     pub outbound_networking: OutboundNetworkingFactor,
     pub outbound_http: OutboundHttpFactor,
     pub sqlite: SqliteFactor,
@@ -33,6 +49,19 @@ pub struct TriggerFactors {
     pub pg: OutboundPgFactor,
     pub mysql: OutboundMysqlFactor,
     pub llm: LlmFactor,
+    pub discovery: DiscoveryFactor,
+    pub actor: ActorFactor,
+    pub cache: CacheFactor,
+    pub context: ContextFactor,
+    pub crypto: CryptoFactor,
+    pub experiments: ExperimentsFactor,
+    pub multipart: MultipartFactor,
+    pub sftp: OutboundSftpFactor,
+    pub mail: OutboundMailFactor,
+    /// Only enabled by a `[wasi_nn]` runtime config section, without which no
+    /// component may load models.
+    #[factor(optional)]
+    pub wasi_nn: Option<WasiNnFactor>,
 }
 
 impl TriggerFactors {
@@ -40,12 +69,14 @@ impl TriggerFactors {
         state_dir: Option<PathBuf>,
         working_dir: impl Into<PathBuf>,
         allow_transient_writes: bool,
+        asset_verification: Option<AssetVerification>,
     ) -> anyhow::Result<Self> {
         Ok(Self {
-            wasi: wasi_factor(working_dir, allow_transient_writes),
+            wasi: wasi_factor(working_dir, allow_transient_writes, asset_verification),
             variables: VariablesFactor::default(),
             key_value: KeyValueFactor::new(),
-            outbound_networking: outbound_networking_factor(),
+            tasks: TasksFactor::new(),
+            outbound_networking: OutboundNetworkingFactor::new(),
             outbound_http: OutboundHttpFactor::default(),
             sqlite: SqliteFactor::new(),
             redis: OutboundRedisFactor::new(),
@@ -56,50 +87,136 @@ impl TriggerFactors {
                 spin_factor_llm::spin::default_engine_creator(state_dir)
                     .context("failed to configure LLM factor")?,
             ),
+            discovery: DiscoveryFactor::new(),
+            actor: ActorFactor::new(),
+            cache: CacheFactor::new(),
+            context: ContextFactor::new(),
+            crypto: CryptoFactor::new(),
+            experiments: ExperimentsFactor::new(),
+            multipart: MultipartFactor::new(),
+            sftp: OutboundSftpFactor::new(),
+            mail: OutboundMailFactor::new(),
+            wasi_nn: Some(WasiNnFactor::new()),
         })
     }
 }
 
-fn wasi_factor(working_dir: impl Into<PathBuf>, allow_transient_writes: bool) -> WasiFactor {
-    WasiFactor::new(SpinFilesMounter::new(working_dir, allow_transient_writes))
+fn wasi_factor(
+    working_dir: impl Into<PathBuf>,
+    allow_transient_writes: bool,
+    asset_verification: Option<AssetVerification>,
+) -> WasiFactor {
+    WasiFactor::new(
+        SpinFilesMounter::new(working_dir, allow_transient_writes)
+            .with_asset_verification(asset_verification),
+    )
 }
 
-fn outbound_networking_factor() -> OutboundNetworkingFactor {
-    fn disallowed_host_handler(scheme: &str, authority: &str) {
-        let host_pattern = format!("{scheme}://{authority}");
-        tracing::error!("Outbound network destination not allowed: {host_pattern}");
-        if scheme.starts_with("http") && authority == "self" {
-            terminal::warn!("A component tried to make an HTTP request to its own app but it does not have permission.");
-        } else {
-            terminal::warn!(
-                "A component tried to make an outbound network connection to disallowed destination '{host_pattern}'."
-            );
-        };
-        eprintln!("To allow this request, add 'allowed_outbound_hosts = [\"{host_pattern}\"]' to the manifest component section.");
-    }
-
-    let mut factor = OutboundNetworkingFactor::new();
-    factor.set_disallowed_host_handler(disallowed_host_handler);
-    factor
-}
+/// Changes a [`TriggerFactors`] runtime config once it has been read, such as
+/// to add a key-value store implemented by an embedder.
+pub type RuntimeConfigOverride =
+    Arc<dyn Fn(&mut TriggerFactorsRuntimeConfig) -> anyhow::Result<()> + Send + Sync>;
 
 /// Options for building a [`TriggerFactors`].
 #[derive(Default, clap::Args)]
 pub struct TriggerAppArgs {
+    /// Applied to the runtime config once it has been read. Only set by
+    /// embedders; there is no command line option.
+    #[clap(skip)]
+    pub runtime_config_override: Option<RuntimeConfigOverride>,
+
     /// Set the static assets of the components in the temporary directory as writable.
     #[clap(long = "allow-transient-write")]
     pub allow_transient_write: bool,
 
+    /// Verify that the static assets of the components haven't changed since
+    /// the app was loaded each time they are mounted, failing the request if
+    /// they have. `fast` checks file sizes and modification times, hashing
+    /// only files modified since; `full` hashes every file. Digests are
+    /// cached, so files are only hashed again when they are modified.
+    #[clap(long = "verify-assets", value_name = "MODE", parse(try_from_str))]
+    pub verify_assets: Option<AssetVerification>,
+
     /// Set a key/value pair (key=value) in the application's
     /// default store. Any existing value will be overwritten.
     /// Can be used multiple times.
     #[clap(long = "key-value", parse(try_from_str = parse_kv))]
     pub key_values: Vec<(String, String)>,
 
+    /// Move the keys in a component's key-value stores that are not in any
+    /// namespace into the component's `key_value_namespace`. Use this once
+    /// after giving a component with existing data a namespace.
+    /// Can be used multiple times.
+    #[clap(long = "key-value-migrate-namespace", value_name = "COMPONENT")]
+    pub key_value_namespace_migrations: Vec<String>,
+
     /// Run a SQLite statement such as a migration against the default database.
     /// To run from a file, prefix the filename with @ e.g. spin up --sqlite @migration.sql
     #[clap(long = "sqlite")]
     pub sqlite_statements: Vec<String>,
+
+    /// Record the host calls (clocks, random, outbound HTTP and key-value reads)
+    /// made by each component instance to a file in the given directory.
+    #[clap(
+        long = "record-host-calls",
+        value_name = "DIR",
+        conflicts_with = "replay_host_calls"
+    )]
+    pub record_host_calls: Option<PathBuf>,
+
+    /// Replay the host calls in the given recording, instead of making them, for
+    /// instances of the recorded component. Send the original request again to
+    /// reproduce the recorded execution.
+    #[clap(long = "replay-host-calls", value_name = "FILE")]
+    pub replay_host_calls: Option<PathBuf>,
+
+    /// Make the random bytes and clocks seen by components deterministic, for
+    /// reproducible tests. Random bytes come from a stream seeded with the
+    /// given seed and the component ID, and clocks start at a fixed time and
+    /// advance only when read.
+    #[clap(
+        long = "deterministic",
+        value_name = "SEED",
+        conflicts_with_all = &["record_host_calls", "replay_host_calls"]
+    )]
+    pub deterministic_seed: Option<u64>,
+
+    /// The time, in seconds since the Unix epoch, at which clocks start in
+    /// deterministic mode. The default is 2024-01-01T00:00:00Z.
+    #[clap(
+        long = "deterministic-clock-start",
+        value_name = "SECONDS",
+        requires = "deterministic_seed"
+    )]
+    pub deterministic_clock_start: Option<u64>,
+
+    /// How far clocks advance, in microseconds, each time they are read in
+    /// deterministic mode. The default is 1000.
+    #[clap(
+        long = "deterministic-clock-step",
+        value_name = "MICROS",
+        requires = "deterministic_seed"
+    )]
+    pub deterministic_clock_step: Option<u64>,
+
+    /// Append the outbound HTTP, key-value, SQLite and LLM calls made by each
+    /// component instance, with their durations and outcomes, to the given
+    /// JSON lines file. Use this to find the capabilities a component
+    /// actually needs.
+    #[clap(long = "audit-host-calls", value_name = "FILE")]
+    pub audit_host_calls: Option<PathBuf>,
+
+    /// Allow outbound network requests to destinations not in a component's
+    /// `allowed_outbound_hosts`, and on exit print the entries needed to allow
+    /// them. For development only.
+    #[clap(long = "learn-outbound-hosts")]
+    pub learn_outbound_hosts: bool,
+
+    /// Allow components to read the host environment variable with this name,
+    /// or prefix ending with `*`, by listing it in `environment_passthrough`.
+    /// May be given more than once.
+    #[clap(long = "allow-env-passthrough", value_name = "NAME")]
+    pub allow_env_passthrough: Vec<String>,
 }
 
 impl From<ResolvedRuntimeConfig<TriggerFactorsRuntimeConfig>> for TriggerFactorsRuntimeConfig {
@@ -0,0 +1,569 @@
+//! Embed Spin applications in Rust programs.
+//!
+//! [`AppHost`] loads an application from its manifest, a registry, or a
+//! locked app, and serves it on a listener the embedder provides, returning a
+//! [`RunningApp`] whose [`AppHandle`] can reload or shut down the application:
+//!
+//! ```no_run
+//! # async fn run() -> anyhow::Result<()> {
+//! let listener = tokio::net::TcpListener::bind("127.0.0.1:3000").await?;
+//! let host = spin_runtime::AppHost::new("path/to/spin.toml")
+//!     .with_runtime_config_file("runtime-config.toml");
+//! let mut events = host.subscribe();
+//! let running = host.serve(listener).await?;
+//!
+//! // Pick up changes to the manifest and component sources.
+//! running.handle().reload().await?;
+//!
+//! running.shutdown().await?;
+//! while let Ok(event) = events.recv().await {
+//!     println!("{event:?}");
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The application's HTTP and Redis triggers are run. Applications with
+//! other types of triggers can't be embedded.
+//!
+//! Runtime config can be read from a file, given as a table in the same
+//! format, or changed in code with [`AppHost::with_runtime_config_override`].
+//!
+//! This crate is the supported way to embed Spin; the crates it wraps are
+//! implementation details that may change in any release.
+
+use std::{fmt, net::SocketAddr, path::PathBuf, sync::Arc};
+
+use anyhow::{anyhow, Context};
+use http::uri::Scheme;
+use spin_app::{locked::LockedApp, App};
+use spin_loader::FilesMountStrategy;
+use spin_oci::{mirrors::RegistryMirrors, OciLoader};
+use spin_runtime_factors::{FactorsBuilder, RuntimeConfigOverride, TriggerAppArgs, TriggerFactors};
+use spin_trigger::{
+    cli::{
+        ensure_lifecycle_hooks_supported, run_lifecycle_hook, FactorsConfig, LifecycleStage,
+        NoCliArgs, TriggerAppBuilder, UserProvidedPath,
+    },
+    loader::ComponentLoader,
+    Trigger,
+};
+use spin_trigger_http::{HttpServer, HttpTrigger};
+use spin_trigger_redis::RedisTrigger;
+use tempfile::TempDir;
+use tokio::{
+    net::TcpListener,
+    sync::{broadcast, mpsc},
+    task::JoinHandle,
+};
+
+pub use spin_runtime_factors::TriggerFactorsRuntimeConfig;
+pub use spin_trigger_http::RequestIdConfig;
+
+type TriggerApp = spin_trigger::TriggerApp<HttpTrigger, TriggerFactors>;
+
+/// The number of events kept for subscribers which fall behind.
+const EVENT_CAPACITY: usize = 64;
+
+/// Loads and serves a Spin application.
+///
+/// An `AppHost` holds the configuration to load the application with; nothing
+/// is loaded until [`AppHost::serve`].
+#[derive(Clone)]
+pub struct AppHost {
+    source: AppSource,
+    insecure_registry: bool,
+    runtime_config_file: Option<PathBuf>,
+    runtime_config: Option<toml::Table>,
+    runtime_config_override: Option<RuntimeConfigOverride>,
+    components: Vec<String>,
+    state_dir: UserProvidedPath,
+    log_dir: UserProvidedPath,
+    request_ids: RequestIdConfig,
+    events: broadcast::Sender<AppEvent>,
+}
+
+/// Where an [`AppHost`] loads its application from.
+#[derive(Clone, Debug)]
+enum AppSource {
+    Manifest(PathBuf),
+    Registry(String),
+    Locked(LockedApp),
+}
+
+impl AppHost {
+    /// Creates a host for the application with the given manifest (`spin.toml`).
+    pub fn new(manifest_path: impl Into<PathBuf>) -> Self {
+        Self::from_source(AppSource::Manifest(manifest_path.into()))
+    }
+
+    /// Creates a host for the application pushed to the given registry
+    /// reference, as for `spin up --from-registry`.
+    ///
+    /// Reloading the application pulls the reference again.
+    pub fn from_registry(reference: impl Into<String>) -> Self {
+        Self::from_source(AppSource::Registry(reference.into()))
+    }
+
+    /// Creates a host for an application which has already been loaded, such
+    /// as one read from a `spin.lock` file.
+    pub fn from_locked_app(locked_app: LockedApp) -> Self {
+        Self::from_source(AppSource::Locked(locked_app))
+    }
+
+    fn from_source(source: AppSource) -> Self {
+        Self {
+            source,
+            insecure_registry: false,
+            runtime_config_file: None,
+            runtime_config: None,
+            runtime_config_override: None,
+            components: vec![],
+            state_dir: UserProvidedPath::Default,
+            log_dir: UserProvidedPath::Default,
+            request_ids: RequestIdConfig::default(),
+            events: broadcast::channel(EVENT_CAPACITY).0,
+        }
+    }
+
+    /// Allows pulling from a registry over plain HTTP, as for
+    /// `spin up --insecure`.
+    pub fn with_insecure_registry(mut self, insecure: bool) -> Self {
+        self.insecure_registry = insecure;
+        self
+    }
+
+    /// Sets the runtime config file, as for `spin up --runtime-config-file`.
+    pub fn with_runtime_config_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.runtime_config_file = Some(path.into());
+        self
+    }
+
+    /// Sets the runtime config, in the format of a runtime config file.
+    ///
+    /// This is used in place of reading the runtime config file, if one is
+    /// set; relative paths in it are resolved against the file's directory.
+    pub fn with_runtime_config(mut self, runtime_config: toml::Table) -> Self {
+        self.runtime_config = Some(runtime_config);
+        self
+    }
+
+    /// Changes the runtime config in code once it has been read from the
+    /// runtime config file or given with [`AppHost::with_runtime_config`].
+    ///
+    /// Use this to configure factors in ways runtime config files can't, such
+    /// as to add a key-value store implemented by the embedder. It is applied
+    /// each time the application is loaded, for each trigger type.
+    pub fn with_runtime_config_override(
+        mut self,
+        runtime_config_override: impl Fn(&mut TriggerFactorsRuntimeConfig) -> anyhow::Result<()>
+            + Send
+            + Sync
+            + 'static,
+    ) -> Self {
+        self.runtime_config_override = Some(Arc::new(runtime_config_override));
+        self
+    }
+
+    /// Runs only the given components, as for `spin up --component-id`.
+    pub fn with_components(
+        mut self,
+        component_ids: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        self.components = component_ids.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sets the application state directory, as for `spin up --state-dir`.
+    /// Defaults to `.spin/` next to the manifest.
+    pub fn with_state_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.state_dir = UserProvidedPath::Provided(dir.into());
+        self
+    }
+
+    /// Sets the directory component stdout and stderr are logged to, as for
+    /// `spin up --log-dir`. Defaults to `logs/` in the state directory.
+    pub fn with_log_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.log_dir = UserProvidedPath::Provided(dir.into());
+        self
+    }
+
+    /// Sets how request IDs are assigned and propagated.
+    pub fn with_request_id_config(mut self, request_ids: RequestIdConfig) -> Self {
+        self.request_ids = request_ids;
+        self
+    }
+
+    /// Subscribes to the [`AppEvent`]s of applications served by this host,
+    /// including any served after the subscription by clones of it.
+    pub fn subscribe(&self) -> broadcast::Receiver<AppEvent> {
+        self.events.subscribe()
+    }
+
+    /// Loads the application, runs its `on_startup` hook, and starts serving
+    /// its HTTP triggers on `listener`, and running its Redis triggers, in a
+    /// background task.
+    ///
+    /// Errors loading or starting the application are returned here; errors
+    /// while serving are returned by [`RunningApp::wait`].
+    pub async fn serve(self, listener: TcpListener) -> anyhow::Result<RunningApp> {
+        let local_addr = listener.local_addr()?;
+        let host = Arc::new(self);
+        let current = host.load(local_addr).await?;
+
+        host.emit(AppEvent::Started { local_addr });
+        let (control_tx, control_rx) = mpsc::unbounded_channel();
+        let task = tokio::spawn(serve(listener, current, control_rx, host.events.clone()));
+        Ok(RunningApp {
+            local_addr,
+            handle: AppHandle {
+                host,
+                local_addr,
+                control: control_tx,
+            },
+            task,
+        })
+    }
+
+    fn emit(&self, event: AppEvent) {
+        // Nobody may be subscribed.
+        _ = self.events.send(event);
+    }
+
+    /// Loads the application and runs its `on_startup` hook.
+    async fn load(&self, listen_addr: SocketAddr) -> anyhow::Result<LoadedApp> {
+        let working_dir = tempfile::tempdir().context("failed to create working directory")?;
+
+        let (id, mut locked, app_dir) = match &self.source {
+            AppSource::Manifest(manifest_path) => {
+                let manifest_path = manifest_path.canonicalize().with_context(|| {
+                    format!("failed to find manifest {}", manifest_path.display())
+                })?;
+                let app_dir = manifest_path
+                    .parent()
+                    .context("manifest path has no parent directory")?
+                    .to_owned();
+                let locked = spin_loader::from_file(
+                    &manifest_path,
+                    FilesMountStrategy::Copy(working_dir.path().join("assets")),
+                    None,
+                )
+                .await?;
+                (manifest_path.display().to_string(), locked, Some(app_dir))
+            }
+            AppSource::Registry(reference) => {
+                let mut client = spin_oci::Client::new(self.insecure_registry, None)
+                    .await
+                    .context("cannot create registry client")?;
+                if let Some(mirrors) = self
+                    .runtime_config_file
+                    .as_deref()
+                    .map(RegistryMirrors::from_runtime_config_file)
+                    .transpose()?
+                    .flatten()
+                {
+                    client = client.with_mirrors(mirrors)?;
+                }
+                let locked = OciLoader::new(working_dir.path())
+                    .load_app(&mut client, reference)
+                    .await?;
+                (reference.clone(), locked, None)
+            }
+            AppSource::Locked(locked) => ("<locked app>".to_owned(), locked.clone(), None),
+        };
+        if !self.components.is_empty() {
+            let components = self
+                .components
+                .iter()
+                .map(String::as_str)
+                .collect::<Vec<_>>();
+            locked = spin_app::retain_components(locked, &components, &[])
+                .context("failed to select components")?;
+        }
+        let app = App::new(&id, locked.clone());
+        if let Some(trigger) = app
+            .triggers()
+            .find(|trigger| !matches!(trigger.trigger_type(), "http" | "redis"))
+        {
+            anyhow::bail!(
+                "embedded applications can't run {:?} triggers",
+                trigger.trigger_type()
+            );
+        }
+        let has_redis_triggers = app.triggers_with_type("redis").next().is_some();
+
+        let supported = <HttpTrigger as Trigger<TriggerFactors>>::supported_host_requirements();
+        if let Err(unmet) = app.ensure_needs_only(&supported) {
+            anyhow::bail!("This application requires the following features that are not available in this version of Spin: {unmet}");
+        }
+        ensure_lifecycle_hooks_supported::<HttpTrigger, TriggerFactors>(&app)?;
+
+        let trigger = HttpTrigger::new(&app, listen_addr, None)?
+            .with_request_id_config(self.request_ids.clone());
+        let mut builder: TriggerAppBuilder<HttpTrigger, FactorsBuilder> =
+            TriggerAppBuilder::new(trigger);
+        builder.engine_config().enable_cache(&None)?;
+
+        let args = TriggerAppArgs {
+            runtime_config_override: self.runtime_config_override.clone(),
+            ..Default::default()
+        };
+        let config = FactorsConfig {
+            working_dir: working_dir.path().to_owned(),
+            runtime_config_file: self.runtime_config_file.clone(),
+            runtime_config: self.runtime_config.clone(),
+            state_dir: self.state_dir.clone(),
+            local_app_dir: app_dir.map(|dir| dir.display().to_string()),
+            log_dir: self.log_dir.clone(),
+            ..Default::default()
+        };
+        let trigger_app = builder
+            .build(app, &config, &args, &ComponentLoader::new())
+            .await?;
+
+        // Each trigger type gets its own factors, as it would running in its
+        // own `spin up` trigger process.
+        let redis = if has_redis_triggers {
+            let app = App::new(id, locked);
+            let trigger = <RedisTrigger as Trigger<TriggerFactors>>::new(NoCliArgs, &app)?;
+            let mut builder: TriggerAppBuilder<RedisTrigger, FactorsBuilder> =
+                TriggerAppBuilder::new(trigger);
+            builder.engine_config().enable_cache(&None)?;
+            let trigger_app = builder
+                .build(app, &config, &args, &ComponentLoader::new())
+                .await?;
+            Some((builder.trigger, trigger_app))
+        } else {
+            None
+        };
+
+        run_lifecycle_hook::<HttpTrigger, TriggerFactors>(&trigger_app, LifecycleStage::Startup)
+            .await?;
+
+        let server = builder.trigger.into_server(trigger_app.clone())?;
+        let redis = redis.map(|(trigger, trigger_app)| tokio::spawn(trigger.run(trigger_app)));
+        Ok(LoadedApp {
+            server,
+            trigger_app,
+            redis,
+            working_dir,
+        })
+    }
+}
+
+impl fmt::Debug for AppHost {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AppHost")
+            .field("source", &self.source)
+            .field("insecure_registry", &self.insecure_registry)
+            .field("runtime_config_file", &self.runtime_config_file)
+            .field("runtime_config", &self.runtime_config)
+            .field(
+                "runtime_config_override",
+                &self.runtime_config_override.is_some(),
+            )
+            .field("components", &self.components)
+            .field("state_dir", &self.state_dir)
+            .field("log_dir", &self.log_dir)
+            .field("request_ids", &self.request_ids)
+            .finish_non_exhaustive()
+    }
+}
+
+/// An application being served by an [`AppHost`].
+///
+/// Dropping a `RunningApp` shuts the application down once every
+/// [`AppHandle`] to it has also been dropped.
+pub struct RunningApp {
+    local_addr: SocketAddr,
+    handle: AppHandle,
+    task: JoinHandle<anyhow::Result<()>>,
+}
+
+impl RunningApp {
+    /// The address the application is being served on.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Returns a handle for reloading or shutting down the application.
+    pub fn handle(&self) -> AppHandle {
+        self.handle.clone()
+    }
+
+    /// Shuts the application down and waits for it to stop.
+    pub async fn shutdown(self) -> anyhow::Result<()> {
+        self.handle.shutdown();
+        self.wait().await
+    }
+
+    /// Waits for the application to stop, after a call to
+    /// [`AppHandle::shutdown`] or a fatal error serving it.
+    ///
+    /// Returns an error if serving failed or the application's `on_shutdown`
+    /// hook failed.
+    pub async fn wait(self) -> anyhow::Result<()> {
+        let Self { handle, task, .. } = self;
+        // Keep our handle alive so that waiting doesn't shut the app down.
+        let result = task.await.context("application task panicked")?;
+        drop(handle);
+        result
+    }
+}
+
+/// Reloads or shuts down a [`RunningApp`]. Handles are cheap to clone.
+#[derive(Clone)]
+pub struct AppHandle {
+    host: Arc<AppHost>,
+    local_addr: SocketAddr,
+    control: mpsc::UnboundedSender<Control>,
+}
+
+impl AppHandle {
+    /// Loads the application again from its manifest and switches new
+    /// connections to it.
+    ///
+    /// The new application's `on_startup` hook runs before it starts serving,
+    /// and the old application's `on_shutdown` hook runs once new connections
+    /// have been switched. Connections that were already open continue to be
+    /// served by the old application. If loading fails, the old application
+    /// continues to serve all connections and the error is returned.
+    pub async fn reload(&self) -> anyhow::Result<()> {
+        let next = match self.host.load(self.local_addr).await {
+            Ok(next) => next,
+            Err(err) => {
+                self.host.emit(AppEvent::ReloadFailed {
+                    error: format!("{err:#}"),
+                });
+                return Err(err);
+            }
+        };
+        self.control
+            .send(Control::Replace(next))
+            .map_err(|_| anyhow!("the application has shut down"))
+    }
+
+    /// Stops accepting connections and runs the application's `on_shutdown`
+    /// hook. Use [`RunningApp::wait`] to wait for this to finish.
+    pub fn shutdown(&self) {
+        // If the app has already stopped there is nothing to do.
+        _ = self.control.send(Control::Shutdown);
+    }
+
+    /// Subscribes to the application's [`AppEvent`]s.
+    pub fn subscribe(&self) -> broadcast::Receiver<AppEvent> {
+        self.host.subscribe()
+    }
+}
+
+/// Something that happened to a served application.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum AppEvent {
+    /// The application was loaded and started serving.
+    Started {
+        /// The address the application is served on.
+        local_addr: SocketAddr,
+    },
+    /// The application was reloaded, and new connections are served by the
+    /// reloaded application.
+    Reloaded,
+    /// Reloading the application failed, and it continues to be served as it
+    /// was.
+    ReloadFailed {
+        /// The reason the application failed to load.
+        error: String,
+    },
+    /// The application stopped serving and its `on_shutdown` hook has run.
+    Stopped {
+        /// The error which stopped the application, if it didn't stop because
+        /// it was shut down, or the error from its `on_shutdown` hook.
+        error: Option<String>,
+    },
+}
+
+enum Control {
+    Replace(LoadedApp),
+    Shutdown,
+}
+
+/// A loaded application and the resources it needs while serving.
+struct LoadedApp {
+    server: Arc<HttpServer<TriggerFactors>>,
+    trigger_app: TriggerApp,
+    /// Runs the application's Redis triggers, if it has any.
+    redis: Option<JoinHandle<anyhow::Result<()>>>,
+    /// Holds the application's copied assets.
+    working_dir: TempDir,
+}
+
+async fn serve(
+    listener: TcpListener,
+    current: LoadedApp,
+    control: mpsc::UnboundedReceiver<Control>,
+    events: broadcast::Sender<AppEvent>,
+) -> anyhow::Result<()> {
+    let result = serve_until_shutdown(listener, current, control, &events).await;
+    _ = events.send(AppEvent::Stopped {
+        error: result.as_ref().err().map(|err| format!("{err:#}")),
+    });
+    result
+}
+
+async fn serve_until_shutdown(
+    listener: TcpListener,
+    mut current: LoadedApp,
+    mut control: mpsc::UnboundedReceiver<Control>,
+    events: &broadcast::Sender<AppEvent>,
+) -> anyhow::Result<()> {
+    // Connections opened before a reload may still be reading the assets of
+    // replaced apps, so their working directories are kept until shutdown.
+    let mut replaced_working_dirs = vec![];
+    tracing::info!("Serving http://{}", listener.local_addr()?);
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => {
+                let (stream, client_addr) = accepted?;
+                current
+                    .server
+                    .clone()
+                    .serve_connection(stream, Scheme::HTTP, client_addr);
+            }
+            result = redis_stopped(&mut current.redis) => {
+                current.redis = None;
+                result?;
+                anyhow::bail!("Redis trigger stopped unexpectedly");
+            }
+            control = control.recv() => match control {
+                Some(Control::Replace(next)) => {
+                    let replaced = std::mem::replace(&mut current, next);
+                    tracing::info!("Reloaded application");
+                    _ = events.send(AppEvent::Reloaded);
+                    if let Err(err) = shut_down(&replaced).await {
+                        tracing::warn!("Replaced application failed to shut down: {err:?}");
+                    }
+                    replaced_working_dirs.push(replaced.working_dir);
+                }
+                Some(Control::Shutdown) | None => break,
+            },
+        }
+    }
+    drop(listener);
+    shut_down(&current).await
+}
+
+/// Waits for the Redis trigger task to stop, or forever if there isn't one.
+async fn redis_stopped(redis: &mut Option<JoinHandle<anyhow::Result<()>>>) -> anyhow::Result<()> {
+    match redis {
+        Some(task) => task.await.context("Redis trigger task panicked")?,
+        None => std::future::pending().await,
+    }
+}
+
+async fn shut_down(app: &LoadedApp) -> anyhow::Result<()> {
+    if let Some(redis) = &app.redis {
+        redis.abort();
+    }
+    run_lifecycle_hook::<HttpTrigger, TriggerFactors>(&app.trigger_app, LifecycleStage::Shutdown)
+        .await
+}
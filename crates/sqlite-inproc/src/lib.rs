@@ -106,6 +106,18 @@ impl Connection for InProcConnection {
         Ok(())
     }
 
+    async fn changes(&self) -> Result<u64, sqlite::Error> {
+        let connection = self.db_connection()?;
+        let changes = connection.lock().unwrap().changes();
+        Ok(changes)
+    }
+
+    async fn last_insert_rowid(&self) -> Result<i64, sqlite::Error> {
+        let connection = self.db_connection()?;
+        let rowid = connection.lock().unwrap().last_insert_rowid();
+        Ok(rowid)
+    }
+
     fn summary(&self) -> Option<String> {
         Some(match &self.location {
             InProcDatabaseLocation::InMemory => "a temporary in-memory database".to_string(),
@@ -182,3 +194,80 @@ impl rusqlite::types::FromSql for ValueWrapper {
         Ok(ValueWrapper(value))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use spin_factor_sqlite::dump;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn dumps_load_into_another_database() -> anyhow::Result<()> {
+        let source = InProcConnection::new(InProcDatabaseLocation::InMemory)?;
+        source
+            .execute_batch(
+                "CREATE TABLE todos (id INTEGER PRIMARY KEY, title TEXT NOT NULL, done REAL, data BLOB);
+                 CREATE INDEX todos_title ON todos (title);
+                 INSERT INTO todos VALUES (1, 'it''s done', 1.5, X'00ff');
+                 INSERT INTO todos VALUES (2, 'not yet', NULL, NULL);",
+            )
+            .await?;
+        let mut script = vec![];
+        dump::dump(&source, &mut script).await?;
+        let script = String::from_utf8(script)?;
+
+        let target = InProcConnection::new(InProcDatabaseLocation::InMemory)?;
+        dump::load(&target, &script).await?;
+
+        let query = "SELECT id, title, done, data FROM todos ORDER BY id";
+        assert_eq!(
+            format!("{:?}", source.query(query, vec![]).await?.rows),
+            format!("{:?}", target.query(query, vec![]).await?.rows)
+        );
+        let indexes = target
+            .query(
+                "SELECT name FROM sqlite_master WHERE type = 'index'",
+                vec![],
+            )
+            .await?;
+        assert_eq!(1, indexes.rows.len());
+
+        // Loading again fails, as the tables already exist, and changes nothing.
+        assert!(dump::load(&target, &script).await.is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn dumps_keep_autoincrement_counters() -> anyhow::Result<()> {
+        let source = InProcConnection::new(InProcDatabaseLocation::InMemory)?;
+        source
+            .execute_batch(
+                "CREATE TABLE events (id INTEGER PRIMARY KEY AUTOINCREMENT, name TEXT);
+                 INSERT INTO events (name) VALUES ('a'), ('b'), ('c');
+                 DELETE FROM events WHERE id = 3;",
+            )
+            .await?;
+        let mut script = vec![];
+        dump::dump(&source, &mut script).await?;
+
+        let target = InProcConnection::new(InProcDatabaseLocation::InMemory)?;
+        dump::load(&target, &String::from_utf8(script)?).await?;
+        target
+            .execute_batch("INSERT INTO events (name) VALUES ('d')")
+            .await?;
+
+        let ids = target
+            .query("SELECT id FROM events ORDER BY id", vec![])
+            .await?
+            .rows
+            .into_iter()
+            .map(|row| match row.values.as_slice() {
+                [sqlite::Value::Integer(id)] => *id,
+                values => panic!("unexpected row {values:?}"),
+            })
+            .collect::<Vec<_>>();
+        // The deleted row's id isn't reused.
+        assert_eq!(vec![1, 2, 4], ids);
+        Ok(())
+    }
+}
@@ -52,6 +52,16 @@ impl Connection for LazyLibSqlConnection {
         client.execute_batch(statements).await
     }
 
+    async fn changes(&self) -> Result<u64, v2::Error> {
+        let client = self.get_or_create_connection().await?;
+        Ok(client.changes())
+    }
+
+    async fn last_insert_rowid(&self) -> Result<i64, v2::Error> {
+        let client = self.get_or_create_connection().await?;
+        Ok(client.last_insert_rowid())
+    }
+
     fn summary(&self) -> Option<String> {
         Some(format!("libSQL at {}", self.url))
     }
@@ -96,6 +106,14 @@ impl LibSqlConnection {
 
         Ok(())
     }
+
+    pub fn changes(&self) -> u64 {
+        self.inner.changes()
+    }
+
+    pub fn last_insert_rowid(&self) -> i64 {
+        self.inner.last_insert_rowid()
+    }
 }
 
 fn columns(rows: &libsql::Rows) -> Vec<String> {
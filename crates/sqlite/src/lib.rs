@@ -133,7 +133,8 @@ impl RuntimeConfigResolver {
     }
 }
 
-const DEFAULT_SQLITE_DB_FILENAME: &str = "sqlite_db.db";
+/// The filename of the default SQLite database within the state directory.
+pub const DEFAULT_SQLITE_DB_FILENAME: &str = "sqlite_db.db";
 
 /// Configuration for a local SQLite database.
 #[derive(Clone, Debug, Deserialize)]
@@ -45,3 +45,28 @@ impl ResourceDetector for SpinResourceDetector {
         ])
     }
 }
+
+/// Resource detector for the Kubernetes pod Spin is running in, if it is.
+///
+/// Sets `k8s.pod.name`, `k8s.namespace.name` and a `k8s.pod.label.<key>`
+/// attribute for each pod label provided through the downward API.
+#[derive(Debug)]
+pub struct KubernetesResourceDetector;
+
+impl ResourceDetector for KubernetesResourceDetector {
+    fn detect(&self, _timeout: Duration) -> Resource {
+        let Some(pod) = spin_common::kubernetes::PodMetadata::detect() else {
+            return Resource::empty();
+        };
+        let mut attributes = vec![KeyValue::new("k8s.pod.name", pod.name.clone())];
+        if let Some(namespace) = &pod.namespace {
+            attributes.push(KeyValue::new("k8s.namespace.name", namespace.clone()));
+        }
+        attributes.extend(
+            pod.labels
+                .iter()
+                .map(|(key, value)| KeyValue::new(format!("k8s.pod.label.{key}"), value.clone())),
+        );
+        Resource::new(attributes)
+    }
+}
@@ -3,7 +3,6 @@ use std::io::IsTerminal;
 use env::otel_logs_enabled;
 use env::otel_metrics_enabled;
 use env::otel_tracing_enabled;
-use opentelemetry_sdk::propagation::TraceContextPropagator;
 use tracing_subscriber::{fmt, prelude::*, registry, EnvFilter, Layer};
 
 pub mod detector;
@@ -15,6 +14,8 @@ mod traces;
 
 pub use propagation::extract_trace_context;
 pub use propagation::inject_trace_context;
+pub use propagation::{embed_trace_context, split_embedded_trace_context};
+pub use propagation::{set_payload_propagation, set_propagation_format, PropagationFormat};
 
 /// Initializes telemetry for Spin using the [tracing] library.
 ///
@@ -83,9 +84,9 @@ pub fn init(spin_version: String) -> anyhow::Result<ShutdownGuard> {
         .with(fmt_layer)
         .init();
 
-    // Used to propagate trace information in the standard W3C TraceContext format. Even if the otel
-    // layer is disabled we still want to propagate trace context.
-    opentelemetry::global::set_text_map_propagator(TraceContextPropagator::new());
+    // Used to propagate trace information, by default in the standard W3C TraceContext format. Even
+    // if the otel layer is disabled we still want to propagate trace context.
+    set_propagation_format(PropagationFormat::default());
 
     if otel_logs_enabled() {
         logs::init_otel_logging_backend(spin_version)?;
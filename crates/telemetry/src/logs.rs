@@ -1,7 +1,7 @@
 use std::{ascii::escape_default, sync::OnceLock, time::Duration};
 
 use anyhow::bail;
-use opentelemetry::logs::{LogRecord, Logger, LoggerProvider};
+use opentelemetry::logs::{AnyValue, LogRecord, Logger, LoggerProvider, Severity};
 use opentelemetry_sdk::{
     logs::{BatchConfigBuilder, BatchLogProcessor, Logger as SdkLogger},
     resource::{EnvResourceDetector, TelemetryResourceDetector},
@@ -9,53 +9,239 @@ use opentelemetry_sdk::{
 };
 
 use crate::{
-    detector::SpinResourceDetector,
+    detector::{KubernetesResourceDetector, SpinResourceDetector},
     env::{self, otel_logs_enabled, OtlpProtocol},
 };
 
 static LOGGER: OnceLock<SdkLogger> = OnceLock::new();
 
-/// Handle an application log. Has the potential to both forward the log to OTel and to emit it as a
-/// tracing event.
-pub fn handle_app_log(buf: &[u8]) {
-    app_log_to_otel(buf);
-    app_log_to_tracing_event(buf);
+/// The longest partial line buffered by an [`AppLogger`]. Longer lines are
+/// handled in pieces.
+const MAX_LINE_BYTES: usize = 16 * 1024;
+
+/// Which of a component's output streams a log was written to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AppLogStream {
+    Stdout,
+    Stderr,
+}
+
+impl AppLogStream {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Stdout => "stdout",
+            Self::Stderr => "stderr",
+        }
+    }
+}
+
+/// Where an application log came from, forwarded to OTel as attributes of
+/// the log record.
+#[derive(Clone, Debug)]
+pub struct AppLogSource {
+    /// The name of the app.
+    pub app: String,
+    /// The ID of the component which wrote the log.
+    pub component: String,
+    /// The type of the trigger the component is run by, if any.
+    pub trigger: Option<String>,
+    /// Identifies the execution of the component which wrote the log.
+    pub correlation_id: Option<String>,
+    /// The stream the log was written to.
+    pub stream: AppLogStream,
+}
+
+/// Handles the output of a component as application logs, one per line.
+///
+/// Lines which are JSON objects are treated as structured logs: their
+/// `message` (or `msg`) becomes the body of the OTel log record, their
+/// `level` (or `severity`) its severity, and their other fields its
+/// attributes.
+pub struct AppLogger {
+    source: AppLogSource,
+    partial: Vec<u8>,
+}
+
+impl AppLogger {
+    pub fn new(source: AppLogSource) -> Self {
+        Self {
+            source,
+            partial: vec![],
+        }
+    }
+
+    /// Handles output written by the component, holding back any incomplete
+    /// last line until more is written.
+    pub fn write(&mut self, mut buf: &[u8]) {
+        while let Some(end) = buf.iter().position(|&b| b == b'\n') {
+            let line = &buf[..end];
+            if self.partial.is_empty() {
+                handle_app_log(line, &self.source);
+            } else {
+                self.partial.extend_from_slice(line);
+                handle_app_log(&std::mem::take(&mut self.partial), &self.source);
+            }
+            buf = &buf[end + 1..];
+        }
+        self.partial.extend_from_slice(buf);
+        if self.partial.len() > MAX_LINE_BYTES {
+            self.flush();
+        }
+    }
+
+    /// Sets the correlation ID of later logs, for a component instance which
+    /// is reused for another execution. Any incomplete last line is handled
+    /// as a log of the previous execution.
+    pub fn set_correlation_id(&mut self, correlation_id: Option<String>) {
+        self.flush();
+        self.source.correlation_id = correlation_id;
+    }
+
+    /// Handles any incomplete last line.
+    pub fn flush(&mut self) {
+        if !self.partial.is_empty() {
+            handle_app_log(&std::mem::take(&mut self.partial), &self.source);
+        }
+    }
+}
+
+impl Drop for AppLogger {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+/// Handle a line of an application log. Has the potential to both forward the log to OTel and to
+/// emit it as a tracing event.
+fn handle_app_log(line: &[u8], source: &AppLogSource) {
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+    if line.is_empty() {
+        return;
+    }
+    app_log_to_otel(line, source);
+    app_log_to_tracing_event(line);
 }
 
 /// Forward the app log to OTel.
-fn app_log_to_otel(buf: &[u8]) {
+fn app_log_to_otel(line: &[u8], source: &AppLogSource) {
     if !otel_logs_enabled() {
         return;
     }
 
-    if let Some(logger) = LOGGER.get() {
-        if let Ok(s) = std::str::from_utf8(buf) {
-            let mut record = logger.create_log_record();
-            record.set_body(s.to_string().into());
-            logger.emit(record);
-        } else {
-            let mut record = logger.create_log_record();
-            record.set_body(escape_non_utf8_buf(buf).into());
+    let Some(logger) = LOGGER.get() else {
+        tracing::trace!("OTel logger not initialized, failed to log");
+        return;
+    };
+    let mut record = logger.create_log_record();
+    record.add_attribute("spin.app.name", source.app.clone());
+    record.add_attribute("spin.component.id", source.component.clone());
+    if let Some(trigger) = &source.trigger {
+        record.add_attribute("spin.trigger.type", trigger.clone());
+    }
+    if let Some(correlation_id) = &source.correlation_id {
+        record.add_attribute("spin.correlation_id", correlation_id.clone());
+    }
+    record.add_attribute("log.iostream", source.stream.as_str());
+    let default_severity = match source.stream {
+        AppLogStream::Stdout => Severity::Info,
+        AppLogStream::Stderr => Severity::Warn,
+    };
+    let severity = match std::str::from_utf8(line) {
+        Ok(s) => match parse_structured_log(s) {
+            Some(structured) => {
+                record.set_body(structured.body.unwrap_or_else(|| s.to_string()).into());
+                for (key, value) in structured.attributes {
+                    record.add_attribute(key, value);
+                }
+                structured.severity.unwrap_or(default_severity)
+            }
+            None => {
+                record.set_body(s.to_string().into());
+                default_severity
+            }
+        },
+        Err(_) => {
+            record.set_body(escape_non_utf8_buf(line).into());
             record.add_attribute("app_log_non_utf8", true);
-            logger.emit(record);
+            default_severity
+        }
+    };
+    record.set_severity_number(severity);
+    record.set_severity_text(severity.name());
+    logger.emit(record);
+}
+
+/// The parts of a JSON log line.
+#[derive(Debug, Default)]
+struct StructuredLog {
+    body: Option<String>,
+    severity: Option<Severity>,
+    attributes: Vec<(String, AnyValue)>,
+}
+
+/// Parses a log line which is a JSON object.
+fn parse_structured_log(line: &str) -> Option<StructuredLog> {
+    if !line.trim_start().starts_with('{') {
+        return None;
+    }
+    let serde_json::Value::Object(fields) = serde_json::from_str(line).ok()? else {
+        return None;
+    };
+    let mut log = StructuredLog::default();
+    for (key, value) in fields {
+        match (key.as_str(), value) {
+            ("message" | "msg", serde_json::Value::String(message)) if log.body.is_none() => {
+                log.body = Some(message);
+            }
+            ("level" | "severity", serde_json::Value::String(level)) if log.severity.is_none() => {
+                log.severity = parse_severity(&level);
+                if log.severity.is_none() {
+                    log.attributes.push((key, level.into()));
+                }
+            }
+            (_, value) => log.attributes.push((key, json_to_any_value(value))),
         }
-    } else {
-        tracing::trace!("OTel logger not initialized, failed to log");
+    }
+    Some(log)
+}
+
+fn parse_severity(level: &str) -> Option<Severity> {
+    Some(match level.to_ascii_lowercase().as_str() {
+        "trace" => Severity::Trace,
+        "debug" => Severity::Debug,
+        "info" | "information" => Severity::Info,
+        "warn" | "warning" => Severity::Warn,
+        "error" | "err" => Severity::Error,
+        "fatal" | "critical" | "crit" => Severity::Fatal,
+        _ => return None,
+    })
+}
+
+fn json_to_any_value(value: serde_json::Value) -> AnyValue {
+    match value {
+        serde_json::Value::String(s) => s.into(),
+        serde_json::Value::Bool(b) => b.into(),
+        serde_json::Value::Number(n) => match n.as_i64() {
+            Some(i) => i.into(),
+            None => n.as_f64().unwrap_or_default().into(),
+        },
+        // Nulls, arrays and objects are kept as their JSON text.
+        value => value.to_string().into(),
     }
 }
 
 /// Takes a Spin application log and emits it as a tracing event. This acts as a compatibility layer
 /// to easily get Spin app logs as events in our OTel traces.
-fn app_log_to_tracing_event(buf: &[u8]) {
+fn app_log_to_tracing_event(line: &[u8]) {
     static CELL: OnceLock<bool> = OnceLock::new();
     if *CELL.get_or_init(env::spin_disable_log_to_tracing) {
         return;
     }
 
-    if let Ok(s) = std::str::from_utf8(buf) {
+    if let Ok(s) = std::str::from_utf8(line) {
         tracing::info!(app_log = s);
     } else {
-        tracing::info!(app_log_non_utf8 = escape_non_utf8_buf(buf));
+        tracing::info!(app_log_non_utf8 = escape_non_utf8_buf(line));
     }
 }
 
@@ -74,6 +260,8 @@ pub(crate) fn init_otel_logging_backend(spin_version: String) -> anyhow::Result<
             // Set service.name from env OTEL_SERVICE_NAME > env OTEL_RESOURCE_ATTRIBUTES > spin
             // Set service.version from Spin metadata
             Box::new(SpinResourceDetector::new(spin_version)),
+            // Sets k8s.{pod.name, namespace.name, pod.label.*} when running in Kubernetes
+            Box::new(KubernetesResourceDetector),
             // Sets fields from env OTEL_RESOURCE_ATTRIBUTES
             Box::new(EnvResourceDetector::new()),
             // Sets telemetry.sdk{name, language, version}
@@ -107,3 +295,35 @@ pub(crate) fn init_otel_logging_backend(spin_version: String) -> anyhow::Result<
     let _ = LOGGER.set(provider.logger("spin"));
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn structured_logs_are_parsed() {
+        let log = parse_structured_log(
+            r#"{"level":"warning","msg":"disk low","free_mb":12,"ratio":0.5,"tags":["a"]}"#,
+        )
+        .unwrap();
+        assert_eq!(log.body.as_deref(), Some("disk low"));
+        assert_eq!(log.severity, Some(Severity::Warn));
+        let keys: Vec<_> = log.attributes.iter().map(|(k, _)| k.as_str()).collect();
+        assert_eq!(keys, ["free_mb", "ratio", "tags"]);
+        assert_eq!(log.attributes[0].1, AnyValue::Int(12));
+        assert_eq!(log.attributes[2].1, AnyValue::from("[\"a\"]".to_string()));
+    }
+
+    #[test]
+    fn plain_lines_are_not_structured() {
+        assert!(parse_structured_log("hello world").is_none());
+        assert!(parse_structured_log("{not json").is_none());
+    }
+
+    #[test]
+    fn unknown_levels_are_kept_as_attributes() {
+        let log = parse_structured_log(r#"{"level":"notice","message":"hi"}"#).unwrap();
+        assert_eq!(log.severity, None);
+        assert_eq!(log.attributes[0].0, "level");
+    }
+}
@@ -11,7 +11,10 @@ use tracing::Subscriber;
 use tracing_opentelemetry::MetricsLayer;
 use tracing_subscriber::{registry::LookupSpan, Layer};
 
-use crate::{detector::SpinResourceDetector, env::OtlpProtocol};
+use crate::{
+    detector::{KubernetesResourceDetector, SpinResourceDetector},
+    env::OtlpProtocol,
+};
 
 /// Constructs a layer for the tracing subscriber that sends metrics to an OTEL collector.
 ///
@@ -27,6 +30,8 @@ pub(crate) fn otel_metrics_layer<S: Subscriber + for<'span> LookupSpan<'span>>(
             // Set service.name from env OTEL_SERVICE_NAME > env OTEL_RESOURCE_ATTRIBUTES > spin
             // Set service.version from Spin metadata
             Box::new(SpinResourceDetector::new(spin_version)),
+            // Sets k8s.{pod.name, namespace.name, pod.label.*} when running in Kubernetes
+            Box::new(KubernetesResourceDetector),
             // Sets fields from env OTEL_RESOURCE_ATTRIBUTES
             Box::new(EnvResourceDetector::new()),
             // Sets telemetry.sdk{name, language, version}
@@ -1,10 +1,38 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+
 use opentelemetry::{
     global,
-    propagation::{Extractor, Injector},
+    propagation::{text_map_propagator::FieldIter, Extractor, Injector, TextMapPropagator},
+    trace::{SpanContext, SpanId, TraceContextExt, TraceFlags, TraceId, TraceState},
+    Context,
 };
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use serde::Deserialize;
 use tracing_opentelemetry::OpenTelemetrySpanExt;
 
-/// Injects the current W3C TraceContext into the provided request.
+/// The format trace context is propagated in, to and from other services.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PropagationFormat {
+    /// W3C Trace Context `traceparent` and `tracestate` headers.
+    #[default]
+    W3c,
+    /// The Zipkin B3 single `b3` header. The multiple `X-B3-*` headers are
+    /// also accepted on incoming requests.
+    B3,
+}
+
+/// Sets the format trace context is propagated in.
+pub fn set_propagation_format(format: PropagationFormat) {
+    match format {
+        PropagationFormat::W3c => global::set_text_map_propagator(TraceContextPropagator::new()),
+        PropagationFormat::B3 => global::set_text_map_propagator(B3Propagator),
+    }
+}
+
+/// Injects the current trace context into the provided request, in the
+/// configured [`PropagationFormat`].
 pub fn inject_trace_context<'a>(req: impl Into<HeaderInjector<'a>>) {
     let mut injector = req.into();
     global::get_text_map_propagator(|propagator| {
@@ -13,8 +41,8 @@ pub fn inject_trace_context<'a>(req: impl Into<HeaderInjector<'a>>) {
     });
 }
 
-/// Extracts the W3C TraceContext from the provided request and sets it as the parent of the
-/// current span.
+/// Extracts the trace context, in the configured [`PropagationFormat`], from the provided request
+/// and sets it as the parent of the current span.
 pub fn extract_trace_context<'a>(req: impl Into<HeaderExtractor<'a>>) {
     let extractor = req.into();
     let parent_context =
@@ -22,9 +50,75 @@ pub fn extract_trace_context<'a>(req: impl Into<HeaderExtractor<'a>>) {
     tracing::Span::current().set_parent(parent_context);
 }
 
+/// Whether trace context is embedded in the payloads of messages, for
+/// messaging systems such as Redis pub/sub which have no message attributes.
+static EMBED_IN_PAYLOADS: AtomicBool = AtomicBool::new(false);
+
+/// Marks a payload with embedded trace context. The trace context follows as
+/// `{name}: {value}` lines, ended by an empty line, and then the original
+/// payload.
+const EMBEDDED_CONTEXT_MARKER: &[u8] = b"\0spin-trace-context\n";
+
+/// Sets whether trace context is embedded in message payloads. Producers and
+/// consumers of the messages must agree, so this is off by default.
+pub fn set_payload_propagation(enabled: bool) {
+    EMBED_IN_PAYLOADS.store(enabled, Ordering::Relaxed);
+}
+
+/// Embeds the current trace context in the provided message payload, if
+/// payload propagation is enabled.
+pub fn embed_trace_context(payload: Vec<u8>) -> Vec<u8> {
+    if !EMBED_IN_PAYLOADS.load(Ordering::Relaxed) {
+        return payload;
+    }
+    let mut fields = vec![];
+    inject_trace_context(&mut fields);
+    embed_fields(&fields, payload)
+}
+
+/// Splits trace context embedded with [`embed_trace_context`] from the
+/// provided message payload, if payload propagation is enabled. The fields
+/// can be passed to [`extract_trace_context`].
+pub fn split_embedded_trace_context(payload: &[u8]) -> (Vec<(String, String)>, &[u8]) {
+    if !EMBED_IN_PAYLOADS.load(Ordering::Relaxed) {
+        return (vec![], payload);
+    }
+    split_fields(payload).unwrap_or((vec![], payload))
+}
+
+fn embed_fields(fields: &[(String, String)], payload: Vec<u8>) -> Vec<u8> {
+    if fields.is_empty() {
+        return payload;
+    }
+    let mut embedded = EMBEDDED_CONTEXT_MARKER.to_vec();
+    for (name, value) in fields {
+        embedded.extend_from_slice(format!("{name}: {value}\n").as_bytes());
+    }
+    embedded.push(b'\n');
+    embedded.extend(payload);
+    embedded
+}
+
+fn split_fields(payload: &[u8]) -> Option<(Vec<(String, String)>, &[u8])> {
+    let mut rest = payload.strip_prefix(EMBEDDED_CONTEXT_MARKER)?;
+    let mut fields = vec![];
+    loop {
+        let end = rest.iter().position(|b| *b == b'\n')?;
+        let line = std::str::from_utf8(&rest[..end]).ok()?;
+        rest = &rest[end + 1..];
+        if line.is_empty() {
+            return Some((fields, rest));
+        }
+        let (name, value) = line.split_once(": ")?;
+        fields.push((name.to_owned(), value.to_owned()));
+    }
+}
+
 pub enum HeaderInjector<'a> {
     Http0(&'a mut http0::HeaderMap),
     Http1(&'a mut http1::HeaderMap),
+    /// Message attributes, such as MQTT user properties.
+    Attributes(&'a mut Vec<(String, String)>),
 }
 
 impl<'a> Injector for HeaderInjector<'a> {
@@ -44,6 +138,10 @@ impl<'a> Injector for HeaderInjector<'a> {
                     }
                 }
             }
+            HeaderInjector::Attributes(attributes) => {
+                attributes.retain(|(name, _)| name != key);
+                attributes.push((key.to_owned(), value));
+            }
         }
     }
 }
@@ -72,9 +170,17 @@ impl<'a> From<&'a mut http1::HeaderMap> for HeaderInjector<'a> {
     }
 }
 
+impl<'a> From<&'a mut Vec<(String, String)>> for HeaderInjector<'a> {
+    fn from(attributes: &'a mut Vec<(String, String)>) -> Self {
+        Self::Attributes(attributes)
+    }
+}
+
 pub enum HeaderExtractor<'a> {
     Http0(&'a http0::HeaderMap),
     Http1(&'a http1::HeaderMap),
+    /// Message attributes, such as MQTT user properties.
+    Attributes(&'a [(String, String)]),
 }
 
 impl<'a> Extractor for HeaderExtractor<'a> {
@@ -86,6 +192,10 @@ impl<'a> Extractor for HeaderExtractor<'a> {
             HeaderExtractor::Http1(headers) => {
                 headers.get(key).map(|v| v.to_str().unwrap_or_default())
             }
+            HeaderExtractor::Attributes(attributes) => attributes
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case(key))
+                .map(|(_, value)| value.as_str()),
         }
     }
 
@@ -93,6 +203,9 @@ impl<'a> Extractor for HeaderExtractor<'a> {
         match self {
             HeaderExtractor::Http0(headers) => headers.keys().map(|k| k.as_str()).collect(),
             HeaderExtractor::Http1(headers) => headers.keys().map(|k| k.as_str()).collect(),
+            HeaderExtractor::Attributes(attributes) => {
+                attributes.iter().map(|(name, _)| name.as_str()).collect()
+            }
         }
     }
 }
@@ -108,3 +221,172 @@ impl<'a, T> From<&'a http1::Request<T>> for HeaderExtractor<'a> {
         Self::Http1(req.headers())
     }
 }
+
+impl<'a> From<&'a [(String, String)]> for HeaderExtractor<'a> {
+    fn from(attributes: &'a [(String, String)]) -> Self {
+        Self::Attributes(attributes)
+    }
+}
+
+const B3_SINGLE_HEADER: &str = "b3";
+const B3_TRACE_ID_HEADER: &str = "x-b3-traceid";
+const B3_SPAN_ID_HEADER: &str = "x-b3-spanid";
+const B3_SAMPLED_HEADER: &str = "x-b3-sampled";
+const B3_FLAGS_HEADER: &str = "x-b3-flags";
+
+/// Propagates trace context in the Zipkin B3 format.
+///
+/// See https://github.com/openzipkin/b3-propagation.
+#[derive(Debug)]
+struct B3Propagator;
+
+impl TextMapPropagator for B3Propagator {
+    fn inject_context(&self, cx: &Context, injector: &mut dyn Injector) {
+        let span = cx.span();
+        let span_context = span.span_context();
+        if !span_context.is_valid() {
+            return;
+        }
+        let sampled = if span_context.is_sampled() { "1" } else { "0" };
+        injector.set(
+            B3_SINGLE_HEADER,
+            format!(
+                "{}-{}-{sampled}",
+                span_context.trace_id(),
+                span_context.span_id()
+            ),
+        );
+    }
+
+    fn extract_with_context(&self, cx: &Context, extractor: &dyn Extractor) -> Context {
+        let span_context = match extractor.get(B3_SINGLE_HEADER) {
+            Some(header) => parse_b3_single(header),
+            None => parse_b3_multi(extractor),
+        };
+        match span_context {
+            Some(span_context) => cx.with_remote_span_context(span_context),
+            None => cx.clone(),
+        }
+    }
+
+    fn fields(&self) -> FieldIter<'_> {
+        static FIELDS: OnceLock<[String; 1]> = OnceLock::new();
+        FieldIter::new(FIELDS.get_or_init(|| [B3_SINGLE_HEADER.to_owned()]))
+    }
+}
+
+/// Parses a `b3` header of the form `{trace id}-{span id}[-{sampled}[-{parent span id}]]`.
+fn parse_b3_single(header: &str) -> Option<SpanContext> {
+    let mut parts = header.trim().split('-');
+    let trace_id = parts.next()?;
+    let span_id = parts.next()?;
+    let sampled = parts.next();
+    b3_span_context(trace_id, span_id, sampled)
+}
+
+fn parse_b3_multi(extractor: &dyn Extractor) -> Option<SpanContext> {
+    let sampled = match extractor.get(B3_FLAGS_HEADER) {
+        // The debug flag implies the trace is sampled.
+        Some("1") => Some("1"),
+        _ => extractor.get(B3_SAMPLED_HEADER),
+    };
+    b3_span_context(
+        extractor.get(B3_TRACE_ID_HEADER)?.trim(),
+        extractor.get(B3_SPAN_ID_HEADER)?.trim(),
+        sampled.map(str::trim),
+    )
+}
+
+fn b3_span_context(trace_id: &str, span_id: &str, sampled: Option<&str>) -> Option<SpanContext> {
+    // 64-bit trace IDs are widened to 128 bits.
+    if !matches!(trace_id.len(), 16 | 32) || span_id.len() != 16 {
+        return None;
+    }
+    let trace_id = TraceId::from_hex(trace_id).ok()?;
+    let span_id = SpanId::from_hex(span_id).ok()?;
+    let trace_flags = match sampled {
+        Some("1" | "d" | "true") => TraceFlags::SAMPLED,
+        Some("0" | "false") | None => TraceFlags::default(),
+        Some(_) => return None,
+    };
+    let span_context = SpanContext::new(trace_id, span_id, trace_flags, true, TraceState::NONE);
+    span_context.is_valid().then_some(span_context)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn extract(headers: &[(&str, &str)]) -> SpanContext {
+        let headers: HashMap<String, String> = headers
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        B3Propagator.extract(&headers).span().span_context().clone()
+    }
+
+    #[test]
+    fn b3_round_trips() {
+        let span_context =
+            extract(&[("b3", "80f198ee56343ba864fe8b2a57d3eff7-e457b5a2e4d86bd1-1")]);
+        assert!(span_context.is_valid());
+        assert!(span_context.is_sampled());
+        assert!(span_context.is_remote());
+
+        let cx = Context::new().with_remote_span_context(span_context);
+        let mut headers = HashMap::new();
+        B3Propagator.inject_context(&cx, &mut headers);
+        assert_eq!(
+            headers["b3"],
+            "80f198ee56343ba864fe8b2a57d3eff7-e457b5a2e4d86bd1-1"
+        );
+    }
+
+    #[test]
+    fn b3_multiple_headers_are_accepted() {
+        let span_context = extract(&[
+            ("x-b3-traceid", "64fe8b2a57d3eff7"),
+            ("x-b3-spanid", "e457b5a2e4d86bd1"),
+            ("x-b3-sampled", "0"),
+        ]);
+        assert!(span_context.is_valid());
+        assert!(!span_context.is_sampled());
+        assert_eq!(
+            span_context.trace_id().to_string(),
+            "000000000000000064fe8b2a57d3eff7"
+        );
+    }
+
+    #[test]
+    fn embedded_fields_round_trip() {
+        let fields = vec![
+            (
+                "traceparent".to_owned(),
+                "00-80f198ee56343ba864fe8b2a57d3eff7-e457b5a2e4d86bd1-01".to_owned(),
+            ),
+            ("tracestate".to_owned(), "vendor=value".to_owned()),
+        ];
+        let embedded = embed_fields(&fields, b"payload\n\nwith lines".to_vec());
+        assert_eq!(
+            split_fields(&embedded),
+            Some((fields, &b"payload\n\nwith lines"[..]))
+        );
+
+        assert_eq!(embed_fields(&[], b"payload".to_vec()), b"payload");
+        assert_eq!(split_fields(b"payload"), None);
+    }
+
+    #[test]
+    fn invalid_b3_is_ignored() {
+        for header in [
+            "",
+            "0",
+            "80f198ee56343ba8-e457b5a2e4d86bd",
+            "zz-e457b5a2e4d86bd1-1",
+        ] {
+            assert!(!extract(&[("b3", header)]).is_valid(), "{header:?}");
+        }
+    }
+}
@@ -9,7 +9,7 @@ use opentelemetry_sdk::{
 use tracing::Subscriber;
 use tracing_subscriber::{registry::LookupSpan, EnvFilter, Layer};
 
-use crate::detector::SpinResourceDetector;
+use crate::detector::{KubernetesResourceDetector, SpinResourceDetector};
 use crate::env::OtlpProtocol;
 
 /// Constructs a layer for the tracing subscriber that sends spans to an OTEL collector.
@@ -26,6 +26,8 @@ pub(crate) fn otel_tracing_layer<S: Subscriber + for<'span> LookupSpan<'span>>(
             // Set service.name from env OTEL_SERVICE_NAME > env OTEL_RESOURCE_ATTRIBUTES > spin
             // Set service.version from Spin metadata
             Box::new(SpinResourceDetector::new(spin_version)),
+            // Sets k8s.{pod.name, namespace.name, pod.label.*} when running in Kubernetes
+            Box::new(KubernetesResourceDetector),
             // Sets fields from env OTEL_RESOURCE_ATTRIBUTES
             Box::new(EnvResourceDetector::new()),
             // Sets telemetry.sdk{name, language, version}
@@ -0,0 +1,169 @@
+use std::{
+    path::{Component, Path, PathBuf},
+    process::Stdio,
+    time::Duration,
+};
+
+use anyhow::{anyhow, Context};
+
+use crate::reader::RawHook;
+
+/// How long a post-generation hook may run before it is killed.
+const HOOK_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// Environment variables passed through to hooks. Everything else is cleared,
+/// so that hooks don't see credentials and the like from the user's
+/// environment.
+const PASSED_THROUGH_ENV_VARS: &[&str] = &[
+    "PATH",
+    "HOME",
+    "USERPROFILE",
+    "APPDATA",
+    "LOCALAPPDATA",
+    "SYSTEMROOT",
+    "TEMP",
+    "TMP",
+    "TMPDIR",
+    "LANG",
+];
+
+/// Whether to run a template's post-generation hooks.
+///
+/// Hooks run arbitrary commands with the user's privileges, so they are only
+/// run if the user opts in up front, rather than on confirming a prompt.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum HookPolicy {
+    /// Do not run hooks.
+    #[default]
+    Skip,
+    /// Run hooks.
+    Run,
+}
+
+/// A command a template runs after generating its files, such as
+/// `npm install` or `git init`.
+#[derive(Clone, Debug)]
+pub(crate) struct PostGenerateHook {
+    id: String,
+    description: Option<String>,
+    command: Vec<String>,
+    dir: Option<PathBuf>,
+}
+
+impl PostGenerateHook {
+    pub(crate) fn from_raw(id: &str, raw: &RawHook) -> anyhow::Result<Self> {
+        if raw.command.is_empty() || raw.command[0].is_empty() {
+            anyhow::bail!("Template error: hook {id} has no command");
+        }
+        let dir = raw.dir.as_ref().map(PathBuf::from);
+        if let Some(dir) = &dir {
+            if !dir
+                .components()
+                .all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+            {
+                anyhow::bail!(
+                    "Template error: hook {id} directory '{}' must be a relative path within the generated files",
+                    dir.display()
+                );
+            }
+        }
+        Ok(Self {
+            id: id.to_owned(),
+            description: raw.description.clone(),
+            command: raw.command.clone(),
+            dir,
+        })
+    }
+
+    /// A human-readable description of the hook, including its command line.
+    pub(crate) fn describe(&self) -> String {
+        let command_line = self.command.join(" ");
+        let location = match &self.dir {
+            Some(dir) => format!(" in {}", dir.display()),
+            None => String::new(),
+        };
+        match &self.description {
+            Some(description) => format!("{description} (`{command_line}`{location})"),
+            None => format!("`{command_line}`{location}"),
+        }
+    }
+
+    /// Runs the hook in `generated_dir`, or its `dir` within it.
+    ///
+    /// The command is run directly rather than through a shell, with no
+    /// input, a minimal environment, and a time limit.
+    pub(crate) async fn run(&self, generated_dir: &Path) -> anyhow::Result<()> {
+        let working_dir = match &self.dir {
+            Some(dir) => generated_dir.join(dir),
+            None => generated_dir.to_owned(),
+        };
+        let (program, args) = self.command.split_first().unwrap(); // validated non-empty on load
+
+        let mut command = tokio::process::Command::new(program);
+        command
+            .args(args)
+            .current_dir(&working_dir)
+            .env_clear()
+            .envs(
+                PASSED_THROUGH_ENV_VARS
+                    .iter()
+                    .filter_map(|name| std::env::var_os(name).map(|value| (name, value))),
+            )
+            .stdin(Stdio::null())
+            .kill_on_drop(true);
+
+        let status = tokio::time::timeout(HOOK_TIMEOUT, command.status())
+            .await
+            .map_err(|_| {
+                anyhow!(
+                    "Hook {} did not finish within {} seconds",
+                    self.id,
+                    HOOK_TIMEOUT.as_secs()
+                )
+            })?
+            .with_context(|| format!("Failed to run hook {} ({program})", self.id))?;
+
+        if status.success() {
+            Ok(())
+        } else {
+            Err(anyhow!("Hook {} failed: {status}", self.id))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn hook(toml_text: &str) -> anyhow::Result<PostGenerateHook> {
+        let raw: RawHook = toml::from_str(toml_text).unwrap();
+        PostGenerateHook::from_raw("test", &raw)
+    }
+
+    #[test]
+    fn hooks_are_described_with_their_command() {
+        let install = hook(
+            "command = [\"npm\", \"install\"]\ndescription = \"Install dependencies\"\ndir = \"web\"",
+        )
+        .unwrap();
+        assert_eq!(
+            "Install dependencies (`npm install` in web)",
+            install.describe()
+        );
+        let init = hook("command = [\"git\", \"init\"]").unwrap();
+        assert_eq!("`git init`", init.describe());
+    }
+
+    #[test]
+    fn hooks_must_stay_in_generated_files() {
+        assert!(hook("command = [\"npm\", \"install\"]\ndir = \"../elsewhere\"").is_err());
+        assert!(hook("command = [\"npm\", \"install\"]\ndir = \"/etc\"").is_err());
+        assert!(hook("command = [\"npm\", \"install\"]\ndir = \"./web/app\"").is_ok());
+    }
+
+    #[test]
+    fn hooks_must_have_a_command() {
+        assert!(hook("command = []").is_err());
+        assert!(hook("command = [\"\"]").is_err());
+    }
+}
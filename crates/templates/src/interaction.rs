@@ -35,7 +35,9 @@ pub(crate) trait InteractionStrategy {
     ) -> Cancellable<String, anyhow::Error>;
 }
 
+#[derive(Clone, Copy)]
 pub(crate) struct Interactive;
+#[derive(Clone, Copy)]
 pub(crate) struct Silent;
 
 impl InteractionStrategy for Interactive {
@@ -9,6 +9,7 @@ mod directory;
 mod environment;
 mod filters;
 mod git;
+mod hooks;
 mod interaction;
 mod manager;
 mod reader;
@@ -20,6 +21,7 @@ mod template;
 mod toml;
 mod writer;
 
+pub use hooks::HookPolicy;
 pub use manager::*;
 pub use run::{Run, RunOptions};
 pub use source::TemplateSource;
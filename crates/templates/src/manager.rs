@@ -1,11 +1,14 @@
-use std::{io::IsTerminal, path::Path};
+use std::{
+    io::IsTerminal,
+    path::{Path, PathBuf},
+};
 
 use anyhow::Context;
 
 use crate::{
     source::TemplateSource,
     store::{TemplateLayout, TemplateStore},
-    template::Template,
+    template::{Template, TemplateVariantInfo},
 };
 
 /// Provides access to and operations on the set of installed
@@ -262,11 +265,49 @@ impl TemplateManager {
     /// Gets the specified template. The result will be `Ok(Some(template))` if
     /// the template was found, and `Ok(None)` if the template was not
     /// found.
+    ///
+    /// Any templates the template includes are loaded with it, and it is an
+    /// error if they are not installed.
     pub fn get(&self, id: impl AsRef<str>) -> anyhow::Result<Option<Template>> {
-        self.store
-            .get_layout(id)
-            .map(|l| Template::load_from(&l))
-            .transpose()
+        self.get_with_includes(id.as_ref(), &mut vec![])
+    }
+
+    fn get_with_includes(
+        &self,
+        id: &str,
+        including: &mut Vec<String>,
+    ) -> anyhow::Result<Option<Template>> {
+        let Some(layout) = self.store.get_layout(id) else {
+            return Ok(None);
+        };
+        let mut template = Template::load_from(&layout)?;
+
+        including.push(id.to_owned());
+        for include in template.includes_mut() {
+            let included_id = include.template_id().to_owned();
+            if including.contains(&included_id) {
+                anyhow::bail!(
+                    "Template {id} includes template {included_id}, which includes {id}: templates cannot include themselves"
+                );
+            }
+            let included = self
+                .get_with_includes(&included_id, including)?
+                .with_context(|| {
+                    format!("Template {id} includes template {included_id}, which is not installed")
+                })?;
+            let add_component = TemplateVariantInfo::AddComponent {
+                manifest_path: PathBuf::new(),
+            };
+            if !included.supports_variant(&add_component) {
+                anyhow::bail!(
+                    "Template {id} includes template {included_id}, which does not support adding a component"
+                );
+            }
+            include.resolve(included);
+        }
+        including.pop();
+
+        Ok(Some(template))
     }
 }
 
@@ -445,7 +486,7 @@ mod tests {
 
     use tempfile::tempdir;
 
-    use crate::{RunOptions, TemplateVariantInfo};
+    use crate::{HookPolicy, RunOptions, TemplateVariantInfo};
 
     use super::*;
 
@@ -522,7 +563,7 @@ mod tests {
     // to save repeating the same thing over and over! By default, the options are:
     // * Create a mew application
     // * Dummy parameter values to satisfy the HTTP template
-    // * All other flags are false
+    // * All other flags are false, and hooks are skipped
     // Use the `rest` callback to modify fields not given in the `name` and `output_path`
     // arguments. For example, to create a RunOptions with `no_vcs` turned on, do:
     // `run_options(name, path, |opts| { opts.no_vcs = true; })`
@@ -539,6 +580,7 @@ mod tests {
             accept_defaults: false,
             no_vcs: false,
             allow_overwrite: false,
+            hooks: HookPolicy::Skip,
         };
         rest(&mut options);
         options
@@ -973,6 +1015,54 @@ mod tests {
         assert!(!spin_toml.contains("service.example.com"));
     }
 
+    #[tokio::test]
+    async fn can_include_templates() {
+        let manager = TempManager::new();
+        manager.install_test_data_templates().await;
+
+        let dest_temp_dir = tempdir().unwrap();
+        let application_dir = dest_temp_dir.path().join("included");
+
+        let template = manager.get("include-variables").unwrap().unwrap();
+        let options = run_options("my included project", &application_dir, |opts| {
+            opts.values = HashMap::new();
+            opts.hooks = HookPolicy::Run;
+        });
+        template.run(options).silent().await.unwrap();
+
+        let spin_toml = tokio::fs::read_to_string(application_dir.join("spin.toml"))
+            .await
+            .unwrap();
+        assert!(spin_toml.contains("name = \"my-included-project\""));
+        assert!(spin_toml.contains("[component.my-included-project-vars]"));
+        assert!(
+            spin_toml.contains("url = { default = \"https://my-included-project.example.com\" }")
+        );
+    }
+
+    #[tokio::test]
+    async fn templates_cannot_include_themselves() {
+        let manager = TempManager::new();
+        manager.install_test_data_templates().await;
+
+        let err = manager
+            .get("include-self")
+            .expect_err("Expected template to fail to load but it loaded");
+        assert_contains(&err.to_string(), "cannot include themselves");
+    }
+
+    #[tokio::test]
+    async fn included_templates_must_be_installed() {
+        let manager = TempManager::new();
+        manager.install_test_data_templates().await;
+        manager.uninstall("add-variables").await.unwrap();
+
+        let err = manager
+            .get("include-variables")
+            .expect_err("Expected template to fail to load but it loaded");
+        assert_contains(&err.to_string(), "not installed");
+    }
+
     #[tokio::test]
     async fn component_new_no_vcs() {
         let manager = TempManager::new_with_this_repo_templates().await;
@@ -24,6 +24,8 @@ pub(crate) struct RawTemplateManifestV1 {
     pub parameters: Option<IndexMap<String, RawParameter>>,
     pub custom_filters: Option<serde::de::IgnoredAny>, // kept for error messaging
     pub outputs: Option<IndexMap<String, RawExtraOutput>>,
+    pub includes: Option<IndexMap<String, RawInclude>>,
+    pub hooks: Option<IndexMap<String, RawHook>>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -109,6 +111,23 @@ pub(crate) enum CreateLocation {
     Manifest,
 }
 
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub(crate) struct RawInclude {
+    pub template: String,
+    pub name: Option<String>,
+    pub path: Option<String>,
+    pub values: Option<IndexMap<String, String>>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub(crate) struct RawHook {
+    pub command: Vec<String>,
+    pub description: Option<String>,
+    pub dir: Option<String>,
+}
+
 pub(crate) fn parse_manifest_toml(text: impl AsRef<str>) -> anyhow::Result<RawTemplateManifest> {
     toml::from_str(text.as_ref()).context("Failed to parse template manifest TOML")
 }
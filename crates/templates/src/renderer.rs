@@ -45,17 +45,21 @@ impl TemplateRenderer {
     }
 
     fn renderer_globals(&self) -> liquid::Object {
-        let mut object = liquid::Object::new();
+        renderer_globals(&self.parameter_values)
+    }
+}
 
-        for (k, v) in &self.parameter_values {
-            object.insert(
-                k.to_owned().into(),
-                liquid_core::Value::Scalar(v.to_owned().into()),
-            );
-        }
+pub(crate) fn renderer_globals(parameter_values: &HashMap<String, String>) -> liquid::Object {
+    let mut object = liquid::Object::new();
 
-        object
+    for (k, v) in parameter_values {
+        object.insert(
+            k.to_owned().into(),
+            liquid_core::Value::Scalar(v.to_owned().into()),
+        );
     }
+
+    object
 }
 
 impl RenderOperation {
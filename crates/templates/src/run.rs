@@ -4,15 +4,17 @@ use std::{
 };
 
 use anyhow::{anyhow, Context};
+use heck::ToKebabCase;
 use itertools::Itertools;
 use path_absolutize::Absolutize;
 use walkdir::WalkDir;
 
 use crate::{
     cancellable::Cancellable,
+    hooks::HookPolicy,
     interaction::{InteractionStrategy, Interactive, Silent},
-    renderer::MergeTarget,
-    template::{ExtraOutputAction, TemplateVariantInfo},
+    renderer::{renderer_globals, MergeTarget},
+    template::{ExtraOutputAction, TemplateInclude, TemplateVariantInfo},
 };
 use crate::{
     renderer::{RenderOperation, TemplateContent, TemplateRenderer},
@@ -43,6 +45,8 @@ pub struct RunOptions {
     /// Skip the overwrite prompt if the output directory already contains files
     /// (or, if silent, allow overwrite instead of erroring).
     pub allow_overwrite: bool,
+    /// Whether to run the template's post-generation hooks.
+    pub hooks: HookPolicy,
 }
 
 impl Run {
@@ -65,15 +69,123 @@ impl Run {
         self.run(Silent).await
     }
 
-    async fn run(&self, interaction: impl InteractionStrategy) -> anyhow::Result<()> {
+    async fn run(&self, interaction: impl InteractionStrategy + Copy) -> anyhow::Result<()> {
         self.build_renderer(interaction)
             .await
-            .and_then(|t| t.render())
-            .and_then_async(|o| async move { o.write().await })
+            .and_then(|t| {
+                let values = t.parameter_values.clone();
+                t.render().map(|o| (o, values))
+            })
+            .and_then_async(|(o, values)| async move {
+                o.write().await?;
+                Ok(values)
+            })
+            .await
+            .and_then_async(|values| async move {
+                self.run_includes(&values, interaction).await?;
+                self.run_hooks().await
+            })
             .await
             .err()
     }
 
+    // Included templates add their components to the manifest this template
+    // generated or added to.
+    async fn run_includes(
+        &self,
+        values: &HashMap<String, String>,
+        interaction: impl InteractionStrategy + Copy,
+    ) -> anyhow::Result<()> {
+        let manifest_path = match &self.options.variant {
+            TemplateVariantInfo::NewApplication => self.generation_target_dir().join("spin.toml"),
+            TemplateVariantInfo::AddComponent { manifest_path } => manifest_path.clone(),
+        };
+        let globals = renderer_globals(values);
+
+        for include in self.template.includes() {
+            let template = include.template().with_context(|| {
+                format!(
+                    "Included template {} has not been loaded",
+                    include.template_id()
+                )
+            })?;
+            let options = self.include_options(include, &manifest_path, &globals)?;
+            let run = template.clone().run(options);
+            // Boxed because included templates may include others in turn.
+            Box::pin(run.run(interaction))
+                .await
+                .with_context(|| format!("Failed to add included template {}", include.id()))?;
+        }
+        Ok(())
+    }
+
+    fn include_options(
+        &self,
+        include: &TemplateInclude,
+        manifest_path: &Path,
+        globals: &liquid::Object,
+    ) -> anyhow::Result<RunOptions> {
+        let parser = Self::template_parser();
+        let render = |text: &str| {
+            parser
+                .parse(text)
+                .and_then(|template| template.render(globals))
+                .with_context(|| {
+                    format!(
+                        "Template error: include {} is not a valid template",
+                        include.id()
+                    )
+                })
+        };
+
+        let name = render(include.name())?;
+        let output_path = match include.path() {
+            Some(path) => render(path)?,
+            None => name.to_kebab_case(),
+        };
+        let values = include
+            .values()
+            .iter()
+            .map(|(k, v)| Ok((k.clone(), render(v)?)))
+            .collect::<anyhow::Result<_>>()?;
+
+        Ok(RunOptions {
+            variant: TemplateVariantInfo::AddComponent {
+                manifest_path: manifest_path.to_owned(),
+            },
+            name,
+            output_path: PathBuf::from(output_path),
+            values,
+            accept_defaults: self.options.accept_defaults,
+            no_vcs: self.options.no_vcs,
+            allow_overwrite: self.options.allow_overwrite,
+            hooks: self.options.hooks,
+        })
+    }
+
+    async fn run_hooks(&self) -> anyhow::Result<()> {
+        let generated_dir = self.generation_target_dir();
+        let hooks = self.template.hooks();
+        match self.options.hooks {
+            HookPolicy::Run => {
+                for hook in hooks {
+                    hook.run(&generated_dir)
+                        .await
+                        .context("Files were generated, but a post-generation step failed")?;
+                }
+            }
+            HookPolicy::Skip => {
+                for hook in hooks {
+                    println!("Skipped post-generation step: {}", hook.describe());
+                }
+                if !hooks.is_empty() {
+                    println!("To run post-generation steps, use `--allow-hooks`.");
+                }
+            }
+        }
+        Ok(())
+    }
+
     async fn build_renderer(
         &self,
         interaction: impl InteractionStrategy,
@@ -10,16 +10,17 @@ use regex::Regex;
 
 use crate::{
     constraints::StringConstraints,
+    hooks::PostGenerateHook,
     reader::{
-        RawCondition, RawConditional, RawExtraOutput, RawParameter, RawTemplateManifest,
-        RawTemplateManifestV1, RawTemplateVariant,
+        RawCondition, RawConditional, RawExtraOutput, RawHook, RawInclude, RawParameter,
+        RawTemplateManifest, RawTemplateManifestV1, RawTemplateVariant,
     },
     run::{Run, RunOptions},
     store::TemplateLayout,
 };
 
 /// A Spin template.
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct Template {
     id: String,
     tags: HashSet<String>,
@@ -29,11 +30,13 @@ pub struct Template {
     variants: HashMap<TemplateVariantKind, TemplateVariant>,
     parameters: Vec<TemplateParameter>,
     extra_outputs: Vec<ExtraOutputAction>,
+    includes: Vec<TemplateInclude>,
+    hooks: Vec<PostGenerateHook>,
     snippets_dir: Option<PathBuf>,
     content_dir: Option<PathBuf>, // TODO: maybe always need a spin.toml file in there?
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 enum InstalledFrom {
     Git(String),
     Directory(String),
@@ -41,7 +44,7 @@ enum InstalledFrom {
     Unknown,
 }
 
-#[derive(Debug, Eq, PartialEq, Hash)]
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
 enum TemplateVariantKind {
     NewApplication,
     AddComponent,
@@ -127,7 +130,7 @@ pub(crate) enum TemplateParameterDataType {
     String(StringConstraints),
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub(crate) struct TemplateParameter {
     id: String,
     data_type: TemplateParameterDataType, // TODO: possibly abstract to a ValidationCriteria type?
@@ -135,6 +138,7 @@ pub(crate) struct TemplateParameter {
     default_value: Option<String>,
 }
 
+#[derive(Clone)]
 pub(crate) enum ExtraOutputAction {
     CreateDirectory(
         String,
@@ -143,6 +147,20 @@ pub(crate) enum ExtraOutputAction {
     ),
 }
 
+/// Another template, run in add component mode to add its component to the
+/// application generated by the including template.
+#[derive(Clone, Debug)]
+pub(crate) struct TemplateInclude {
+    id: String,
+    template_id: String,
+    // These are Liquid templates, rendered with the including template's values.
+    name: String,
+    path: Option<String>,
+    values: IndexMap<String, String>,
+    // Resolved by the template manager, which knows the installed templates.
+    template: Option<Box<Template>>,
+}
+
 impl std::fmt::Debug for ExtraOutputAction {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -196,6 +214,8 @@ impl Template {
                 variants: Self::parse_template_variants(raw.new_application, raw.add_component),
                 parameters: Self::parse_parameters(&raw.parameters)?,
                 extra_outputs: Self::parse_extra_outputs(&raw.outputs)?,
+                includes: Self::parse_includes(&raw.includes),
+                hooks: Self::parse_hooks(&raw.hooks)?,
                 snippets_dir,
                 content_dir,
             },
@@ -286,6 +306,18 @@ impl Template {
         &self.extra_outputs
     }
 
+    pub(crate) fn includes(&self) -> &[TemplateInclude] {
+        &self.includes
+    }
+
+    pub(crate) fn includes_mut(&mut self) -> &mut [TemplateInclude] {
+        &mut self.includes
+    }
+
+    pub(crate) fn hooks(&self) -> &[PostGenerateHook] {
+        &self.hooks
+    }
+
     pub(crate) fn content_dir(&self) -> &Option<PathBuf> {
         &self.content_dir
     }
@@ -427,6 +459,28 @@ impl Template {
         }
     }
 
+    fn parse_includes(raw: &Option<IndexMap<String, RawInclude>>) -> Vec<TemplateInclude> {
+        match raw {
+            None => vec![],
+            Some(includes) => includes
+                .iter()
+                .map(|(k, v)| TemplateInclude::from_raw(k, v))
+                .collect(),
+        }
+    }
+
+    fn parse_hooks(
+        raw: &Option<IndexMap<String, RawHook>>,
+    ) -> anyhow::Result<Vec<PostGenerateHook>> {
+        match raw {
+            None => Ok(vec![]),
+            Some(hooks) => hooks
+                .iter()
+                .map(|(k, v)| PostGenerateHook::from_raw(k, v))
+                .collect(),
+        }
+    }
+
     pub(crate) fn included_files(
         &self,
         base: &std::path::Path,
@@ -562,6 +616,49 @@ impl ExtraOutputAction {
     }
 }
 
+impl TemplateInclude {
+    fn from_raw(id: &str, raw: &RawInclude) -> Self {
+        Self {
+            id: id.to_owned(),
+            template_id: raw.template.clone(),
+            name: raw.name.clone().unwrap_or_else(|| id.to_owned()),
+            path: raw.path.clone(),
+            values: raw.values.clone().unwrap_or_default(),
+            template: None,
+        }
+    }
+
+    pub(crate) fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub(crate) fn template_id(&self) -> &str {
+        &self.template_id
+    }
+
+    pub(crate) fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The output path, relative to the application directory. Defaults to the
+    /// (rendered) name.
+    pub(crate) fn path(&self) -> Option<&str> {
+        self.path.as_deref()
+    }
+
+    pub(crate) fn values(&self) -> &IndexMap<String, String> {
+        &self.values
+    }
+
+    pub(crate) fn template(&self) -> Option<&Template> {
+        self.template.as_deref()
+    }
+
+    pub(crate) fn resolve(&mut self, template: Template) {
+        self.template = Some(Box::new(template));
+    }
+}
+
 impl TemplateVariant {
     pub(crate) fn skip_file(&self, base: &std::path::Path, path: &std::path::Path) -> bool {
         self.skip_files
@@ -749,6 +846,8 @@ mod test {
             variants,
             parameters: vec![],
             extra_outputs: vec![],
+            includes: vec![],
+            hooks: vec![],
             snippets_dir: None,
             content_dir: None,
         };
@@ -40,6 +40,7 @@ async fn new_fileserver_creates_assets_dir() -> anyhow::Result<()> {
         accept_defaults: true,
         no_vcs: false,
         allow_overwrite: false,
+        hooks: HookPolicy::Skip,
     };
     manager
         .get("static-fileserver")?
@@ -87,6 +88,7 @@ async fn add_fileserver_creates_assets_dir_next_to_manifest() -> anyhow::Result<
         accept_defaults: true,
         no_vcs: false,
         allow_overwrite: false,
+        hooks: HookPolicy::Skip,
     };
     manager
         .get("http-empty")?
@@ -107,6 +109,7 @@ async fn add_fileserver_creates_assets_dir_next_to_manifest() -> anyhow::Result<
         accept_defaults: true,
         no_vcs: false,
         allow_overwrite: false,
+        hooks: HookPolicy::Skip,
     };
     manager
         .get("static-fileserver")?
@@ -0,0 +1,368 @@
+use std::{
+    fs::File,
+    io::{LineWriter, Write},
+    net::SocketAddr,
+    path::Path,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Context as _;
+use http::{HeaderName, Request, Response};
+use http_body_util::BodyExt;
+use hyper::body::{Body as _, Bytes, Frame, SizeHint};
+use serde::{Serialize, Serializer};
+use wasmtime_wasi_http::bindings::http::types::ErrorCode;
+
+use crate::{instrument::MatchedRoute, Body};
+
+/// The format of access log lines.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum AccessLogFormat {
+    /// The Common Log Format used by web servers such as nginx and Apache.
+    #[default]
+    Common,
+    /// JSON lines, including the latency, route, and component of each request.
+    Json,
+}
+
+/// The component a request was routed to, as a response extension.
+#[derive(Clone)]
+pub(crate) struct RoutedComponent(pub String);
+
+/// Writes a line for each request handled by the server.
+pub(crate) struct AccessLog {
+    format: AccessLogFormat,
+    writer: Mutex<Box<dyn Write + Send>>,
+}
+
+impl AccessLog {
+    /// Opens an access log which appends to the file at `path`, or writes to
+    /// stdout if `path` is `-`.
+    pub fn open(path: &Path, format: AccessLogFormat) -> anyhow::Result<Self> {
+        let writer: Box<dyn Write + Send> = if path == Path::new("-") {
+            Box::new(std::io::stdout())
+        } else {
+            let file = File::options()
+                .create(true)
+                .append(true)
+                .open(path)
+                .with_context(|| format!("failed to open access log {path:?}"))?;
+            Box::new(LineWriter::new(file))
+        };
+        Ok(Self {
+            format,
+            writer: Mutex::new(writer),
+        })
+    }
+
+    /// Notes the details of a request, to be logged with its response.
+    pub fn start<B>(&self, req: &Request<B>, client_addr: SocketAddr) -> PendingEntry {
+        PendingEntry {
+            started: Instant::now(),
+            time: SystemTime::now(),
+            client: client_addr.ip().to_string(),
+            method: req.method().to_string(),
+            target: req
+                .uri()
+                .path_and_query()
+                .map_or_else(|| "/".into(), ToString::to_string),
+            protocol: format!("{:?}", req.version()),
+        }
+    }
+
+    /// Returns the response with a body which logs the request once it has
+    /// been sent, when its size and the total latency are known.
+    pub fn finish(
+        self: &Arc<Self>,
+        pending: PendingEntry,
+        res: Response<Body>,
+        request_id_header: &HeaderName,
+    ) -> Response<Body> {
+        let started = pending.started;
+        let entry = Entry {
+            route: res
+                .extensions()
+                .get::<MatchedRoute>()
+                .map(|matched| matched.route.clone()),
+            component: res
+                .extensions()
+                .get::<RoutedComponent>()
+                .map(|routed| routed.0.clone()),
+            request_id: res
+                .headers()
+                .get(request_id_header)
+                .and_then(|value| value.to_str().ok())
+                .map(ToOwned::to_owned),
+            ..Entry::new(pending, res.status().as_u16())
+        };
+        let log = self.clone();
+        res.map(|inner| {
+            LoggedBody {
+                inner,
+                started,
+                entry,
+                log,
+            }
+            .boxed()
+        })
+    }
+
+    /// Logs a request which failed without a response, and whose connection
+    /// is closed instead, as a server error.
+    pub fn fail(&self, pending: PendingEntry) {
+        let started = pending.started;
+        let mut entry = Entry::new(pending, 500);
+        entry.duration_ms = started.elapsed().as_secs_f64() * 1000.0;
+        self.write(&entry);
+    }
+
+    fn write(&self, entry: &Entry) {
+        let line = match self.format {
+            AccessLogFormat::Common => entry.to_common(),
+            AccessLogFormat::Json => match serde_json::to_string(entry) {
+                Ok(line) => line,
+                Err(err) => {
+                    tracing::warn!("Failed to serialize access log entry: {err}");
+                    return;
+                }
+            },
+        };
+        let mut writer = self.writer.lock().unwrap();
+        if let Err(err) = writeln!(writer, "{line}") {
+            tracing::warn!("Failed to write access log: {err}");
+        }
+    }
+}
+
+/// The details of a request which is being handled.
+pub(crate) struct PendingEntry {
+    started: Instant,
+    time: SystemTime,
+    client: String,
+    method: String,
+    target: String,
+    protocol: String,
+}
+
+/// An access log line.
+#[derive(Debug, Serialize)]
+struct Entry {
+    #[serde(serialize_with = "serialize_rfc3339")]
+    time: SystemTime,
+    client: String,
+    method: String,
+    target: String,
+    protocol: String,
+    status: u16,
+    bytes: u64,
+    duration_ms: f64,
+    route: Option<String>,
+    component: Option<String>,
+    request_id: Option<String>,
+}
+
+impl Entry {
+    fn new(pending: PendingEntry, status: u16) -> Self {
+        Self {
+            time: pending.time,
+            client: pending.client,
+            method: pending.method,
+            target: pending.target,
+            protocol: pending.protocol,
+            status,
+            bytes: 0,
+            duration_ms: 0.0,
+            route: None,
+            component: None,
+            request_id: None,
+        }
+    }
+
+    /// Formats the entry in the Common Log Format, e.g.
+    /// `127.0.0.1 - - [10/Oct/2000:13:55:36 +0000] "GET / HTTP/1.1" 200 2326`.
+    fn to_common(&self) -> String {
+        let DateTime {
+            year,
+            month,
+            day,
+            hour,
+            minute,
+            second,
+            ..
+        } = DateTime::from(self.time);
+        const MONTHS: [&str; 12] = [
+            "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+        ];
+        let month = MONTHS[month as usize - 1];
+        // The Common Log Format logs a body of no bytes as `-`.
+        let bytes = match self.bytes {
+            0 => "-".to_string(),
+            bytes => bytes.to_string(),
+        };
+        format!(
+            "{} - - [{day:02}/{month}/{year}:{hour:02}:{minute:02}:{second:02} +0000] \"{} {} {}\" {} {bytes}",
+            self.client, self.method, self.target, self.protocol, self.status
+        )
+    }
+}
+
+/// A response body which counts the bytes sent, and logs the request when it
+/// is dropped, having been sent or abandoned.
+struct LoggedBody {
+    inner: Body,
+    started: Instant,
+    entry: Entry,
+    log: Arc<AccessLog>,
+}
+
+impl hyper::body::Body for LoggedBody {
+    type Data = Bytes;
+    type Error = ErrorCode;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let frame = Pin::new(&mut self.inner).poll_frame(cx);
+        if let Poll::Ready(Some(Ok(frame))) = &frame {
+            if let Some(data) = frame.data_ref() {
+                self.entry.bytes += data.len() as u64;
+            }
+        }
+        frame
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+impl Drop for LoggedBody {
+    fn drop(&mut self) {
+        self.entry.duration_ms = self.started.elapsed().as_secs_f64() * 1000.0;
+        self.log.write(&self.entry);
+    }
+}
+
+/// A UTC date and time.
+struct DateTime {
+    year: i64,
+    month: u32,
+    day: u32,
+    hour: u32,
+    minute: u32,
+    second: u32,
+    millis: u32,
+}
+
+impl From<SystemTime> for DateTime {
+    fn from(time: SystemTime) -> Self {
+        let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let secs = since_epoch.as_secs();
+        let time_of_day = (secs % 86400) as u32;
+        // Converts days since the epoch to a civil date, from
+        // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+        let z = (secs / 86400) as i64 + 719468;
+        let era = z.div_euclid(146097);
+        let doe = z - era * 146097;
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+        let month = (if mp < 10 { mp + 3 } else { mp - 9 }) as u32;
+        let year = yoe + era * 400 + i64::from(month <= 2);
+        Self {
+            year,
+            month,
+            day,
+            hour: time_of_day / 3600,
+            minute: time_of_day / 60 % 60,
+            second: time_of_day % 60,
+            millis: since_epoch.subsec_millis(),
+        }
+    }
+}
+
+fn serialize_rfc3339<S: Serializer>(time: &SystemTime, serializer: S) -> Result<S::Ok, S::Error> {
+    let DateTime {
+        year,
+        month,
+        day,
+        hour,
+        minute,
+        second,
+        millis,
+    } = DateTime::from(*time);
+    serializer.serialize_str(&format!(
+        "{year:04}-{month:02}-{day:02}T{hour:02}:{minute:02}:{second:02}.{millis:03}Z"
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn entry() -> Entry {
+        Entry {
+            // 2000-10-10T13:55:36.250Z
+            time: UNIX_EPOCH + Duration::from_millis(971186136250),
+            client: "127.0.0.1".into(),
+            method: "GET".into(),
+            target: "/apache_pb.gif?x=1".into(),
+            protocol: "HTTP/1.1".into(),
+            status: 200,
+            bytes: 2326,
+            duration_ms: 1.5,
+            route: Some("/...".into()),
+            component: Some("web".into()),
+            request_id: Some("abc".into()),
+        }
+    }
+
+    #[test]
+    fn entries_are_formatted_as_common_log_format() {
+        assert_eq!(
+            entry().to_common(),
+            "127.0.0.1 - - [10/Oct/2000:13:55:36 +0000] \"GET /apache_pb.gif?x=1 HTTP/1.1\" 200 2326"
+        );
+        let empty = Entry {
+            bytes: 0,
+            ..entry()
+        };
+        assert!(empty.to_common().ends_with("\" 200 -"));
+    }
+
+    #[test]
+    fn entries_are_formatted_as_json() -> anyhow::Result<()> {
+        let json = serde_json::to_value(entry())?;
+        assert_eq!(json["time"], "2000-10-10T13:55:36.250Z");
+        assert_eq!(json["status"], 200);
+        assert_eq!(json["bytes"], 2326);
+        assert_eq!(json["duration_ms"], 1.5);
+        assert_eq!(json["route"], "/...");
+        assert_eq!(json["component"], "web");
+        Ok(())
+    }
+
+    #[test]
+    fn dates_are_converted_across_leap_years() {
+        // 2024-02-29T23:59:59Z
+        let leap_day = DateTime::from(UNIX_EPOCH + Duration::from_secs(1709251199));
+        assert_eq!((leap_day.year, leap_day.month, leap_day.day), (2024, 2, 29));
+        assert_eq!(
+            (leap_day.hour, leap_day.minute, leap_day.second),
+            (23, 59, 59)
+        );
+        let epoch = DateTime::from(UNIX_EPOCH);
+        assert_eq!((epoch.year, epoch.month, epoch.day), (1970, 1, 1));
+    }
+}
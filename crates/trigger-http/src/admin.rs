@@ -0,0 +1,742 @@
+//! An admin API for browsing and managing an app's key-value stores and
+//! SQLite databases, splitting its traffic, and reporting what it is running,
+//! from outside the app.
+//!
+//! The API is off unless the trigger is given an admin token, and then is
+//! served under `/.well-known/spin/admin/` to requests bearing that token:
+//!
+//! - `GET stores`: the labels of the key-value stores and SQLite databases
+//!   used by the app's components.
+//! - `GET key-value/<store>`: the store's keys.
+//! - `GET`, `PUT` or `DELETE key-value/<store>/<key>`: a key's value.
+//! - `GET sqlite/<database>`: the database's tables and views.
+//! - `GET sqlite/<database>/<table>?limit=<n>&offset=<n>`: rows of a table.
+//! - `GET export/key-value/<store>`: all of the store's keys and values, in
+//!   the format written by `spin data export`.
+//! - `POST import/key-value/<store>?overwrite`: sets keys from such an export,
+//!   keeping the values of existing keys unless `overwrite` is given.
+//! - `GET export/sqlite/<database>`: a SQL script that recreates the database,
+//!   as written by `spin data export`.
+//! - `POST import/sqlite/<database>`: loads such a script into the database.
+//! - `GET canaries`: the canaries of the app's routed components.
+//! - `PUT canaries/<component>`: sends a share of the component's requests to
+//!   a canary, given as a JSON object like `[http_server.canaries.<component>]`
+//!   in the runtime config.
+//! - `DELETE canaries/<component>`: sends all of the component's requests
+//!   back to it.
+//! - `GET allowed-outbound-hosts`: when learning outbound hosts, manifest
+//!   sections with the `allowed_outbound_hosts` that components were found to
+//!   need, as TOML.
+//! - `GET build-info`: how each of the app's components was built, by
+//!   component ID, for those built by `spin build`: the git commit, when, and
+//!   by which version of Spin.
+//! - `GET saturation`: the load of the trigger, as JSON. With a `watch` query
+//!   parameter, streams newline-delimited JSON with the current load followed
+//!   by every change, to at most 16 watchers at once.
+//!
+//! Labels, keys and table names are percent-decoded. Request bodies larger
+//! than 32 MiB are rejected. Exports are streamed as they are read.
+
+use std::{
+    borrow::Cow,
+    collections::BTreeMap,
+    future::Future,
+    io::{BufWriter, ErrorKind, Write},
+    sync::Arc,
+};
+
+use anyhow::Context;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use http::{
+    header::{AUTHORIZATION, CONTENT_TYPE, WWW_AUTHENTICATE},
+    Method, Request, Response, StatusCode,
+};
+use http_body_util::{combinators::BoxBody, BodyExt, StreamBody};
+use hyper::body::{Body as _, Bytes, Frame};
+use percent_encoding::percent_decode_str;
+use serde::Serialize;
+use serde_json::{json, Value};
+use spin_factor_context::ContextFactor;
+use spin_factor_key_value::{KeyValueFactor, Store};
+use spin_factor_outbound_networking::OutboundNetworkingFactor;
+use spin_factor_sqlite::{Connection, SqliteFactor};
+use spin_factors::{ConfiguredApp, RuntimeFactors};
+use spin_http::body;
+use spin_trigger::saturation::{Saturation, SaturationTracker};
+use spin_world::v2::sqlite;
+use tokio::sync::Semaphore;
+use wasmtime_wasi_http::bindings::http::types::ErrorCode;
+
+use crate::{
+    canary::{CanaryConfig, TrafficSplits},
+    Body,
+};
+
+/// The number of rows returned when browsing a table without a `limit`.
+const DEFAULT_ROW_LIMIT: i64 = 100;
+
+/// The largest request body accepted, e.g. for an import.
+const MAX_BODY_BYTES: usize = 32 * 1024 * 1024;
+
+/// The size of the chunks in which exports are streamed.
+const EXPORT_CHUNK_BYTES: usize = 64 * 1024;
+
+/// How many chunks of an export may wait to be sent to the client.
+const EXPORT_CHUNKS: usize = 4;
+
+/// The most requests which may watch the trigger's saturation at once.
+const MAX_SATURATION_WATCHERS: usize = 16;
+
+/// Serves the admin API to requests bearing its token.
+pub(crate) struct AdminApi {
+    token: String,
+    saturation_watchers: Arc<Semaphore>,
+}
+
+impl AdminApi {
+    pub(crate) fn new(token: String) -> Self {
+        Self {
+            token,
+            saturation_watchers: Arc::new(Semaphore::new(MAX_SATURATION_WATCHERS)),
+        }
+    }
+
+    /// Handles a request for `path`, the part of the request path after
+    /// `/.well-known/spin/admin/`.
+    pub(crate) async fn handle<F: RuntimeFactors>(
+        &self,
+        req: Request<Body>,
+        path: &str,
+        app: &ConfiguredApp<F>,
+        splits: &TrafficSplits,
+        saturation: &SaturationTracker,
+    ) -> anyhow::Result<Response<Body>> {
+        if !self.is_authorized(&req) {
+            return Ok(Response::builder()
+                .status(StatusCode::UNAUTHORIZED)
+                .header(WWW_AUTHENTICATE, "Bearer")
+                .body(body::empty())?);
+        }
+        let response = if path == "saturation" {
+            self.saturation(&req, saturation)
+        } else {
+            route(req, path, app, splits).await
+        };
+        match response {
+            Ok(response) => Ok(response),
+            Err(err) if err.is::<BodyTooLarge>() => {
+                status(StatusCode::PAYLOAD_TOO_LARGE, err.to_string())
+            }
+            Err(err) => {
+                tracing::warn!("Admin API request for {path:?} failed: {err:?}");
+                status(StatusCode::INTERNAL_SERVER_ERROR, format!("{err:#}"))
+            }
+        }
+    }
+
+    fn is_authorized<B>(&self, req: &Request<B>) -> bool {
+        let token = req
+            .headers()
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| {
+                let (scheme, token) = value.split_once(' ')?;
+                scheme.eq_ignore_ascii_case("bearer").then(|| token.trim())
+            });
+        token.is_some_and(|token| constant_time_eq(token.as_bytes(), self.token.as_bytes()))
+    }
+
+    /// Returns the trigger's saturation, or streams its changes if the
+    /// request asks to watch it.
+    fn saturation<B>(
+        &self,
+        req: &Request<B>,
+        saturation: &SaturationTracker,
+    ) -> anyhow::Result<Response<Body>> {
+        fn to_json_line(saturation: Saturation) -> Bytes {
+            let mut line = serde_json::to_vec(&saturation).unwrap();
+            line.push(b'\n');
+            line.into()
+        }
+
+        if req.method() != Method::GET {
+            return method_not_allowed();
+        }
+        let watch = req.uri().query().is_some_and(|q| {
+            q.split('&')
+                .any(|p| p == "watch" || p.starts_with("watch="))
+        });
+        if !watch {
+            return Ok(Response::builder()
+                .header(CONTENT_TYPE, "application/json")
+                .body(body::full(to_json_line(saturation.current())))?);
+        }
+
+        let Ok(permit) = self.saturation_watchers.clone().try_acquire_owned() else {
+            return status(
+                StatusCode::SERVICE_UNAVAILABLE,
+                format!("at most {MAX_SATURATION_WATCHERS} requests may watch saturation at once"),
+            );
+        };
+        let mut changes = saturation.subscribe();
+        let (tx, mut rx) = tokio::sync::mpsc::channel(1);
+        tokio::spawn(async move {
+            let _permit = permit;
+            let current = to_json_line(*changes.borrow_and_update());
+            if tx.send(current).await.is_err() {
+                return;
+            }
+            loop {
+                tokio::select! {
+                    _ = tx.closed() => break,
+                    changed = changes.changed() => {
+                        if changed.is_err() {
+                            break;
+                        }
+                        let line = to_json_line(*changes.borrow_and_update());
+                        if tx.send(line).await.is_err() {
+                            break;
+                        }
+                    }
+                }
+            }
+        });
+        let frames = futures::stream::poll_fn(move |cx| {
+            rx.poll_recv(cx)
+                .map(|line| line.map(|line| Ok(Frame::data(line))))
+        });
+        Ok(Response::builder()
+            .header(CONTENT_TYPE, "application/x-ndjson")
+            .body(BoxBody::new(StreamBody::new(frames)))?)
+    }
+}
+
+async fn route<F: RuntimeFactors>(
+    req: Request<Body>,
+    path: &str,
+    app: &ConfiguredApp<F>,
+    splits: &TrafficSplits,
+) -> anyhow::Result<Response<Body>> {
+    let method = req.method().clone();
+    let (resource, rest) = path.split_once('/').unwrap_or((path, ""));
+    match (method, resource) {
+        (Method::GET, "stores") if rest.is_empty() => {
+            let key_value = app
+                .app_state::<KeyValueFactor>()
+                .map(|kv| kv.used_stores())
+                .unwrap_or_default();
+            let sqlite = app
+                .app_state::<SqliteFactor>()
+                .map(|sqlite| sqlite.used_databases())
+                .unwrap_or_default();
+            json_response(&json!({ "key_value": key_value, "sqlite": sqlite }))
+        }
+        (method, "key-value") => {
+            let (label, key) = split_name(rest)?;
+            let Some(store) = key_value_store(app, &label).await else {
+                return not_found(format!("no key-value store {label:?}"));
+            };
+            match (method, key) {
+                (Method::GET, None) => {
+                    let mut keys = store.get_keys().await?;
+                    keys.sort();
+                    json_response(&keys)
+                }
+                (Method::GET, Some(key)) => match store.get(&key).await? {
+                    Some(value) => Ok(Response::builder()
+                        .header(CONTENT_TYPE, "application/octet-stream")
+                        .body(body::full(value.into()))?),
+                    None => not_found(format!("no key {key:?}")),
+                },
+                (Method::PUT, Some(key)) => {
+                    let value = read_body(req).await?;
+                    store.set(&key, &value).await?;
+                    tracing::info!("Admin API set key {key:?} in key-value store {label:?}");
+                    status(StatusCode::NO_CONTENT, "")
+                }
+                (Method::DELETE, Some(key)) => {
+                    store.delete(&key).await?;
+                    tracing::info!("Admin API deleted key {key:?} from key-value store {label:?}");
+                    status(StatusCode::NO_CONTENT, "")
+                }
+                _ => method_not_allowed(),
+            }
+        }
+        (method, "sqlite") => {
+            let (label, table) = split_name(rest)?;
+            let Some(connection) = sqlite_connection(app, &label).await? else {
+                return not_found(format!("no SQLite database {label:?}"));
+            };
+            let connection = connection.as_ref();
+            match (method, table) {
+                (Method::GET, None) => {
+                    let tables = query(
+                        connection,
+                        "SELECT name FROM sqlite_master WHERE type IN ('table', 'view') ORDER BY name",
+                        vec![],
+                    )
+                    .await?;
+                    let names = tables
+                        .rows
+                        .into_iter()
+                        .filter_map(|row| match row.values.into_iter().next() {
+                            Some(sqlite::Value::Text(name)) => Some(name),
+                            _ => None,
+                        })
+                        .collect::<Vec<_>>();
+                    json_response(&names)
+                }
+                (Method::GET, Some(table)) => {
+                    let exists = query(
+                        connection,
+                        "SELECT 1 FROM sqlite_master WHERE type IN ('table', 'view') AND name = ?",
+                        vec![sqlite::Value::Text(table.to_string())],
+                    )
+                    .await?;
+                    if exists.rows.is_empty() {
+                        return not_found(format!("no table {table:?}"));
+                    }
+                    let (limit, offset) = match page(req.uri().query()) {
+                        Ok(page) => page,
+                        Err(message) => return status(StatusCode::BAD_REQUEST, message),
+                    };
+                    let rows = query(
+                        connection,
+                        &format!("SELECT * FROM {} LIMIT ? OFFSET ?", identifier(&table)),
+                        vec![
+                            sqlite::Value::Integer(limit),
+                            sqlite::Value::Integer(offset),
+                        ],
+                    )
+                    .await?;
+                    json_response(&json!({
+                        "columns": rows.columns,
+                        "rows": rows
+                            .rows
+                            .iter()
+                            .map(|row| row.values.iter().map(value_to_json).collect())
+                            .collect::<Vec<Vec<_>>>(),
+                    }))
+                }
+                _ => method_not_allowed(),
+            }
+        }
+        (Method::GET, "export") => match rest.split_once('/') {
+            Some(("key-value", label)) => {
+                let label = decode(label)?;
+                let Some(store) = key_value_store(app, &label).await else {
+                    return not_found(format!("no key-value store {label:?}"));
+                };
+                stream_export("application/x-ndjson", move |writer| async move {
+                    spin_factor_key_value::dump::dump(&*store, writer).await?;
+                    Ok(())
+                })
+            }
+            Some(("sqlite", label)) => {
+                let label = decode(label)?;
+                let Some(connection) = sqlite_connection(app, &label).await? else {
+                    return not_found(format!("no SQLite database {label:?}"));
+                };
+                stream_export("application/sql", move |writer| async move {
+                    spin_factor_sqlite::dump::dump(&*connection, writer).await
+                })
+            }
+            _ => not_found(format!("no admin resource {path:?}")),
+        },
+        (Method::POST, "import") => match rest.split_once('/') {
+            Some(("key-value", label)) => {
+                let label = decode(label)?;
+                let Some(store) = key_value_store(app, &label).await else {
+                    return not_found(format!("no key-value store {label:?}"));
+                };
+                let overwrite = req.uri().query().is_some_and(|q| {
+                    q.split('&')
+                        .any(|p| p == "overwrite" || p.starts_with("overwrite="))
+                });
+                let body = read_body(req).await?;
+                let report =
+                    match spin_factor_key_value::dump::load(&*store, &body[..], overwrite).await {
+                        Ok(report) => report,
+                        Err(err) => return status(StatusCode::BAD_REQUEST, format!("{err:#}")),
+                    };
+                tracing::info!(
+                    "Admin API imported {} key(s) into key-value store {label:?}, keeping {} existing key(s)",
+                    report.loaded,
+                    report.skipped
+                );
+                json_response(&json!({ "imported": report.loaded, "skipped": report.skipped }))
+            }
+            Some(("sqlite", label)) => {
+                let label = decode(label)?;
+                let Some(connection) = sqlite_connection(app, &label).await? else {
+                    return not_found(format!("no SQLite database {label:?}"));
+                };
+                let body = read_body(req).await?;
+                let Ok(script) = std::str::from_utf8(&body) else {
+                    return status(StatusCode::BAD_REQUEST, "SQL script is not valid UTF-8");
+                };
+                if let Err(err) = spin_factor_sqlite::dump::load(&*connection, script).await {
+                    return status(StatusCode::BAD_REQUEST, format!("{err:#}"));
+                }
+                tracing::info!("Admin API imported a dump into SQLite database {label:?}");
+                status(StatusCode::NO_CONTENT, "")
+            }
+            _ => not_found(format!("no admin resource {path:?}")),
+        },
+        (Method::GET, "canaries") if rest.is_empty() => json_response(&splits.list()),
+        (Method::PUT, "canaries") if !rest.is_empty() => {
+            let component_id = decode(rest)?;
+            let body = read_body(req).await?;
+            let config = match serde_json::from_slice::<CanaryConfig>(&body) {
+                Ok(config) => config,
+                Err(err) => return status(StatusCode::BAD_REQUEST, format!("{err:#}")),
+            };
+            match splits.set(&component_id, config) {
+                Ok(()) => status(StatusCode::NO_CONTENT, ""),
+                Err(err) => status(StatusCode::BAD_REQUEST, format!("{err:#}")),
+            }
+        }
+        (Method::DELETE, "canaries") if !rest.is_empty() => {
+            let component_id = decode(rest)?;
+            if splits.remove(&component_id) {
+                status(StatusCode::NO_CONTENT, "")
+            } else {
+                not_found(format!("component {component_id:?} has no canary"))
+            }
+        }
+        (Method::GET, "allowed-outbound-hosts") if rest.is_empty() => {
+            let Some(suggestion) = app
+                .app_state::<OutboundNetworkingFactor>()
+                .ok()
+                .and_then(|networking| networking.suggested_allowed_outbound_hosts())
+            else {
+                return not_found("outbound hosts are not being learned".to_string());
+            };
+            Ok(Response::builder()
+                .header(CONTENT_TYPE, "application/toml")
+                .body(body::full(suggestion.into()))?)
+        }
+        (Method::GET, "build-info") if rest.is_empty() => {
+            let build_info = app
+                .app_state::<ContextFactor>()
+                .map(|context| {
+                    context
+                        .component_build_info()
+                        .iter()
+                        .collect::<BTreeMap<_, _>>()
+                })
+                .unwrap_or_default();
+            json_response(&build_info)
+        }
+        (
+            _,
+            "stores"
+            | "export"
+            | "import"
+            | "canaries"
+            | "allowed-outbound-hosts"
+            | "build-info"
+            | "saturation",
+        ) => method_not_allowed(),
+        _ => not_found(format!("no admin resource {path:?}")),
+    }
+}
+
+/// Gets a key-value store used by the app.
+async fn key_value_store<F: RuntimeFactors>(
+    app: &ConfiguredApp<F>,
+    label: &str,
+) -> Option<std::sync::Arc<dyn Store>> {
+    let key_value = app.app_state::<KeyValueFactor>().ok()?;
+    if !key_value.store_is_used(label) {
+        return None;
+    }
+    key_value.get_store(label).await
+}
+
+/// Connects to a SQLite database used by the app.
+async fn sqlite_connection<F: RuntimeFactors>(
+    app: &ConfiguredApp<F>,
+    label: &str,
+) -> anyhow::Result<Option<Box<dyn Connection>>> {
+    let Ok(sqlite) = app.app_state::<SqliteFactor>() else {
+        return Ok(None);
+    };
+    if !sqlite.database_is_used(label) {
+        return Ok(None);
+    }
+    sqlite
+        .get_connection(label)
+        .await
+        .transpose()
+        .with_context(|| format!("failed to connect to SQLite database {label:?}"))
+}
+
+async fn query(
+    connection: &dyn Connection,
+    sql: &str,
+    parameters: Vec<sqlite::Value>,
+) -> anyhow::Result<sqlite::QueryResult> {
+    connection
+        .query(sql, parameters)
+        .await
+        .with_context(|| format!("failed to execute {sql:?}"))
+}
+
+/// Parses the `limit` and `offset` query parameters.
+fn page(query: Option<&str>) -> Result<(i64, i64), String> {
+    let mut limit = DEFAULT_ROW_LIMIT;
+    let mut offset = 0;
+    for param in query.unwrap_or_default().split('&') {
+        let (name, value) = param.split_once('=').unwrap_or((param, ""));
+        let target = match name {
+            "limit" => &mut limit,
+            "offset" => &mut offset,
+            _ => continue,
+        };
+        *target = value
+            .parse()
+            .ok()
+            .filter(|n: &i64| *n >= 0)
+            .ok_or_else(|| format!("{name} must be a non-negative integer"))?;
+    }
+    Ok((limit, offset))
+}
+
+/// Splits a path into a percent-decoded label and the percent-decoded rest of
+/// the path, if there is any.
+fn split_name(path: &str) -> anyhow::Result<(Cow<'_, str>, Option<Cow<'_, str>>)> {
+    match path.split_once('/') {
+        Some((label, rest)) if !rest.is_empty() => Ok((decode(label)?, Some(decode(rest)?))),
+        Some((label, _)) => Ok((decode(label)?, None)),
+        None => Ok((decode(path)?, None)),
+    }
+}
+
+fn decode(s: &str) -> anyhow::Result<Cow<'_, str>> {
+    percent_decode_str(s)
+        .decode_utf8()
+        .with_context(|| format!("{s:?} is not percent-encoded UTF-8"))
+}
+
+/// Quotes a SQL identifier.
+fn identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+/// Converts a value to JSON, with blobs as `{"base64": "..."}`.
+fn value_to_json(value: &sqlite::Value) -> Value {
+    match value {
+        sqlite::Value::Integer(i) => json!(i),
+        sqlite::Value::Real(r) => json!(r),
+        sqlite::Value::Text(t) => json!(t),
+        sqlite::Value::Blob(b) => json!({ "base64": STANDARD.encode(b) }),
+        sqlite::Value::Null => Value::Null,
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// The error reading a request body larger than [`MAX_BODY_BYTES`].
+#[derive(Debug)]
+struct BodyTooLarge;
+
+impl std::fmt::Display for BodyTooLarge {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "request body is larger than {MAX_BODY_BYTES} bytes")
+    }
+}
+
+impl std::error::Error for BodyTooLarge {}
+
+async fn read_body(req: Request<Body>) -> anyhow::Result<Bytes> {
+    let mut body = req.into_body();
+    anyhow::ensure!(
+        body.size_hint().lower() <= MAX_BODY_BYTES as u64,
+        BodyTooLarge
+    );
+    let mut bytes = Vec::new();
+    while let Some(frame) = body.frame().await {
+        if let Ok(data) = frame?.into_data() {
+            anyhow::ensure!(bytes.len() + data.len() <= MAX_BODY_BYTES, BodyTooLarge);
+            bytes.extend_from_slice(&data);
+        }
+    }
+    Ok(bytes.into())
+}
+
+/// Streams an export written by `export` as the response body. The export is
+/// written on a blocking thread, which waits for the client to read each chunk
+/// before writing more, so the export needn't fit in memory.
+fn stream_export<E, Fut>(content_type: &str, export: E) -> anyhow::Result<Response<Body>>
+where
+    E: FnOnce(BufWriter<ExportWriter>) -> Fut + Send + 'static,
+    Fut: Future<Output = anyhow::Result<()>>,
+{
+    let (tx, mut rx) = tokio::sync::mpsc::channel(EXPORT_CHUNKS);
+    let runtime = tokio::runtime::Handle::current();
+    let writer = BufWriter::with_capacity(EXPORT_CHUNK_BYTES, ExportWriter(tx.clone()));
+    tokio::task::spawn_blocking(move || {
+        if let Err(err) = runtime.block_on(export(writer)) {
+            tracing::warn!("Admin API export failed: {err:?}");
+            // The response has started, so the error can only end it.
+            let err = ErrorCode::InternalError(Some(format!("{err:#}")));
+            let _ = futures::executor::block_on(tx.send(Err(err)));
+        }
+    });
+    let frames = futures::stream::poll_fn(move |cx| {
+        rx.poll_recv(cx)
+            .map(|chunk| chunk.map(|chunk| chunk.map(Frame::data)))
+    });
+    Ok(Response::builder()
+        .header(CONTENT_TYPE, content_type)
+        .body(BoxBody::new(StreamBody::new(frames)))?)
+}
+
+/// Sends what is written to it to a [`stream_export`] response body, waiting
+/// for room in the channel.
+struct ExportWriter(tokio::sync::mpsc::Sender<Result<Bytes, ErrorCode>>);
+
+impl Write for ExportWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        // This runs within the runtime's `block_on`, where Tokio doesn't allow
+        // blocking on its own channels.
+        futures::executor::block_on(self.0.send(Ok(Bytes::copy_from_slice(buf))))
+            .map_err(|_| std::io::Error::new(ErrorKind::BrokenPipe, "the client went away"))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+fn json_response(value: &impl Serialize) -> anyhow::Result<Response<Body>> {
+    let body = serde_json::to_vec_pretty(value)?;
+    Ok(Response::builder()
+        .header(CONTENT_TYPE, "application/json")
+        .body(body::full(body.into()))?)
+}
+
+fn status(status: StatusCode, message: impl Into<String>) -> anyhow::Result<Response<Body>> {
+    let message = message.into();
+    let body = if message.is_empty() {
+        body::empty()
+    } else {
+        body::full(format!("{message}\n").into())
+    };
+    Ok(Response::builder().status(status).body(body)?)
+}
+
+fn not_found(message: String) -> anyhow::Result<Response<Body>> {
+    status(StatusCode::NOT_FOUND, message)
+}
+
+fn method_not_allowed() -> anyhow::Result<Response<Body>> {
+    status(StatusCode::METHOD_NOT_ALLOWED, "")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requests_need_the_admin_token() {
+        let api = AdminApi::new("s3cret".into());
+        let request = |auth: Option<&str>| {
+            let mut builder = Request::get("/");
+            if let Some(auth) = auth {
+                builder = builder.header(AUTHORIZATION, auth);
+            }
+            builder.body(()).unwrap()
+        };
+        assert!(api.is_authorized(&request(Some("Bearer s3cret"))));
+        assert!(api.is_authorized(&request(Some("bearer s3cret"))));
+        assert!(!api.is_authorized(&request(Some("Bearer s3cre"))));
+        assert!(!api.is_authorized(&request(Some("Basic s3cret"))));
+        assert!(!api.is_authorized(&request(None)));
+    }
+
+    #[tokio::test]
+    async fn saturation_watchers_are_bounded() {
+        let api = AdminApi::new("s3cret".into());
+        let saturation = SaturationTracker::new();
+        let watch = || {
+            let req = Request::get("/saturation?watch").body(()).unwrap();
+            api.saturation(&req, &saturation).unwrap()
+        };
+
+        let mut watchers = (0..MAX_SATURATION_WATCHERS)
+            .map(|_| watch())
+            .collect::<Vec<_>>();
+        assert!(watchers.iter().all(|res| res.status() == StatusCode::OK));
+        assert_eq!(watch().status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        let first = watchers.remove(0).into_body().frame().await;
+        let line = first.unwrap().unwrap().into_data().unwrap();
+        let current: serde_json::Value = serde_json::from_slice(&line).unwrap();
+        assert_eq!(current["in_flight"], 0);
+
+        // Dropping a watcher's response frees its place once its task notices.
+        drop(watchers);
+        tokio::time::timeout(std::time::Duration::from_secs(5), async {
+            while watch().status() != StatusCode::OK {
+                tokio::task::yield_now().await;
+            }
+        })
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn large_bodies_are_rejected() {
+        let req = |len| Request::new(body::full(vec![b'x'; len].into()));
+        assert_eq!(read_body(req(10)).await.unwrap().len(), 10);
+        let err = read_body(req(MAX_BODY_BYTES + 1)).await.unwrap_err();
+        assert!(err.is::<BodyTooLarge>());
+    }
+
+    #[test]
+    fn names_are_percent_decoded() {
+        let (label, key) = split_name("default/user%2F1").unwrap();
+        assert_eq!("default", label);
+        assert_eq!(Some("user/1"), key.as_deref());
+        let (label, key) = split_name("default/").unwrap();
+        assert_eq!("default", label);
+        assert!(key.is_none());
+    }
+
+    #[test]
+    fn pages_are_parsed() {
+        assert_eq!(Ok((DEFAULT_ROW_LIMIT, 0)), page(None));
+        assert_eq!(Ok((10, 20)), page(Some("limit=10&offset=20")));
+        assert!(page(Some("limit=-1")).is_err());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn exports_are_streamed() {
+        let lines = 10_000;
+        let res = stream_export("text/plain", move |mut writer| async move {
+            for line in 0..lines {
+                writeln!(writer, "line {line}")?;
+            }
+            writer.flush()?;
+            Ok(())
+        })
+        .unwrap();
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        let body = std::str::from_utf8(&body).unwrap();
+        assert_eq!(lines, body.lines().count());
+        assert!(body.len() > EXPORT_CHUNK_BYTES);
+
+        let res = stream_export("text/plain", |mut writer| async move {
+            writeln!(writer, "partial")?;
+            writer.flush()?;
+            anyhow::bail!("export failed")
+        })
+        .unwrap();
+        assert!(res.into_body().collect().await.is_err());
+    }
+}
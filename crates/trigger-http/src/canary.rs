@@ -0,0 +1,283 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    sync::RwLock,
+};
+
+use anyhow::Context;
+use http::{HeaderName, Request};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+/// Sends a share of the requests routed to a component to another component,
+/// such as a new version of it, as set by `[http_server.canaries.<component>]`
+/// in the runtime config or through the admin API.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct CanaryConfig {
+    /// The component to send the split requests to.
+    pub component: String,
+    /// The percentage of requests to send to `component`, from 0 to 100.
+    #[serde(default)]
+    pub percent: f64,
+    /// Requests with this header are sent to `component` whatever `percent` is.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub header: Option<String>,
+    /// If set, `header` must have this value to send a request to `component`,
+    /// and requests with another value are never sent to `component`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub header_value: Option<String>,
+}
+
+/// The canaries of the components routed by the HTTP trigger.
+pub(crate) struct TrafficSplits {
+    /// The components of the app being served, replaced when it is
+    /// reconfigured.
+    components: RwLock<AppComponents>,
+    // Component ID -> canary
+    canaries: RwLock<HashMap<String, Canary>>,
+}
+
+struct AppComponents {
+    /// The components with HTTP routes, which may have canaries.
+    routed: HashSet<String>,
+    /// All of the app's components, which may be canaries.
+    all: HashSet<String>,
+}
+
+struct Canary {
+    config: CanaryConfig,
+    header: Option<HeaderName>,
+}
+
+impl TrafficSplits {
+    pub fn new(
+        routed: impl IntoIterator<Item = String>,
+        components: impl IntoIterator<Item = String>,
+    ) -> Self {
+        Self {
+            components: RwLock::new(AppComponents {
+                routed: routed.into_iter().collect(),
+                all: components.into_iter().collect(),
+            }),
+            canaries: Default::default(),
+        }
+    }
+
+    /// Updates the components which may have and be canaries, such as when
+    /// the app's routes are reconfigured, removing canaries which no longer
+    /// apply.
+    pub fn set_components(
+        &self,
+        routed: impl IntoIterator<Item = String>,
+        components: impl IntoIterator<Item = String>,
+    ) {
+        let mut app_components = self.components.write().unwrap();
+        *app_components = AppComponents {
+            routed: routed.into_iter().collect(),
+            all: components.into_iter().collect(),
+        };
+        self.canaries.write().unwrap().retain(|component_id, canary| {
+            let kept = app_components.routed.contains(component_id)
+                && app_components.all.contains(&canary.config.component);
+            if !kept {
+                tracing::warn!(
+                    "Removed the canary {:?} of component {component_id:?}, which is no longer routed or in the app",
+                    canary.config.component
+                );
+            }
+            kept
+        });
+    }
+
+    /// Sends a share of the requests routed to `component_id` to a canary,
+    /// replacing any canary it had.
+    pub fn set(&self, component_id: &str, config: CanaryConfig) -> anyhow::Result<()> {
+        // Held until the canary is added so that it can't miss a reconfiguration.
+        let app_components = self.components.read().unwrap();
+        anyhow::ensure!(
+            app_components.routed.contains(component_id),
+            "component {component_id:?} has no HTTP route to split"
+        );
+        anyhow::ensure!(
+            app_components.all.contains(&config.component),
+            "canary component {:?} is not in the app",
+            config.component
+        );
+        anyhow::ensure!(
+            config.component != component_id,
+            "component {component_id:?} can't be its own canary"
+        );
+        anyhow::ensure!(
+            (0.0..=100.0).contains(&config.percent),
+            "canary percent must be from 0 to 100, not {}",
+            config.percent
+        );
+        let header = config
+            .header
+            .as_deref()
+            .map(HeaderName::try_from)
+            .transpose()
+            .with_context(|| format!("invalid canary header {:?}", config.header))?;
+        anyhow::ensure!(
+            header.is_some() || config.header_value.is_none(),
+            "canary `header_value` needs a `header`"
+        );
+        tracing::info!(
+            "Sending {}% of requests for component {component_id:?} to canary {:?}",
+            config.percent,
+            config.component
+        );
+        self.canaries
+            .write()
+            .unwrap()
+            .insert(component_id.to_owned(), Canary { config, header });
+        Ok(())
+    }
+
+    /// Stops splitting the requests routed to `component_id`, returning
+    /// whether it had a canary.
+    pub fn remove(&self, component_id: &str) -> bool {
+        let removed = self.canaries.write().unwrap().remove(component_id);
+        if removed.is_some() {
+            tracing::info!("Removed the canary of component {component_id:?}");
+        }
+        removed.is_some()
+    }
+
+    /// The canaries of each component which has one.
+    pub fn list(&self) -> BTreeMap<String, CanaryConfig> {
+        self.canaries
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(component_id, canary)| (component_id.clone(), canary.config.clone()))
+            .collect()
+    }
+
+    /// Returns the canary which should handle a request routed to
+    /// `component_id`, if any.
+    pub fn choose<B>(&self, component_id: &str, req: &Request<B>) -> Option<String> {
+        let canaries = self.canaries.read().unwrap();
+        let canary = canaries.get(component_id)?;
+        let header = canary
+            .header
+            .as_ref()
+            .and_then(|header| req.headers().get(header));
+        let chosen = match (header, &canary.config.header_value) {
+            (Some(value), Some(expected)) => value.as_bytes() == expected.as_bytes(),
+            (Some(_), None) => true,
+            (None, _) => rand::thread_rng().gen_range(0.0..100.0) < canary.config.percent,
+        };
+        chosen.then(|| canary.config.component.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn splits() -> TrafficSplits {
+        TrafficSplits::new(
+            ["api".to_string()],
+            ["api".to_string(), "api-v2".to_string()],
+        )
+    }
+
+    fn canary(percent: f64) -> CanaryConfig {
+        CanaryConfig {
+            component: "api-v2".into(),
+            percent,
+            header: Some("x-canary".into()),
+            header_value: Some("1".into()),
+        }
+    }
+
+    fn request(canary_header: Option<&str>) -> Request<()> {
+        let mut builder = Request::get("/");
+        if let Some(value) = canary_header {
+            builder = builder.header("x-canary", value);
+        }
+        builder.body(()).unwrap()
+    }
+
+    #[test]
+    fn percent_splits_requests() {
+        let splits = splits();
+        splits.set("api", canary(0.0)).unwrap();
+        assert_eq!(None, splits.choose("api", &request(None)));
+        splits.set("api", canary(100.0)).unwrap();
+        assert_eq!(Some("api-v2".into()), splits.choose("api", &request(None)));
+        assert!(splits.remove("api"));
+        assert_eq!(None, splits.choose("api", &request(None)));
+        assert!(!splits.remove("api"));
+    }
+
+    #[test]
+    fn header_overrides_percent() {
+        let splits = splits();
+        splits.set("api", canary(0.0)).unwrap();
+        assert_eq!(
+            Some("api-v2".into()),
+            splits.choose("api", &request(Some("1")))
+        );
+        splits.set("api", canary(100.0)).unwrap();
+        assert_eq!(None, splits.choose("api", &request(Some("0"))));
+
+        let any_value = CanaryConfig {
+            header_value: None,
+            ..canary(0.0)
+        };
+        splits.set("api", any_value).unwrap();
+        assert_eq!(
+            Some("api-v2".into()),
+            splits.choose("api", &request(Some("yes")))
+        );
+    }
+
+    #[test]
+    fn invalid_canaries_are_rejected() {
+        let splits = splits();
+        assert!(splits.set("api-v2", canary(10.0)).is_err());
+        assert!(splits.set("api", canary(101.0)).is_err());
+        let missing = CanaryConfig {
+            component: "api-v3".into(),
+            ..canary(10.0)
+        };
+        assert!(splits.set("api", missing).is_err());
+        let itself = CanaryConfig {
+            component: "api".into(),
+            ..canary(10.0)
+        };
+        assert!(splits.set("api", itself).is_err());
+        assert!(splits.list().is_empty());
+    }
+
+    #[test]
+    fn reconfiguring_components_removes_stale_canaries() {
+        let splits = splits();
+        splits.set("api", canary(100.0)).unwrap();
+        assert!(splits.set("web", canary(100.0)).is_err());
+
+        splits.set_components(
+            ["api".to_string(), "web".to_string()],
+            ["api".to_string(), "api-v2".to_string(), "web".to_string()],
+        );
+        assert_eq!(Some("api-v2".into()), splits.choose("api", &request(None)));
+        splits.set("web", canary(100.0)).unwrap();
+
+        splits.set_components(
+            ["api".to_string(), "web".to_string()],
+            ["api".to_string(), "web".to_string()],
+        );
+        assert!(splits.list().is_empty());
+        assert_eq!(None, splits.choose("web", &request(None)));
+        assert!(splits.set("web", canary(100.0)).is_err());
+    }
+
+    #[test]
+    fn canaries_deserialize_from_runtime_config() {
+        let config: CanaryConfig = toml::from_str("component = \"api-v2\"\npercent = 5").unwrap();
+        assert_eq!(5.0, config.percent);
+        assert!(config.header.is_none());
+    }
+}
@@ -0,0 +1,294 @@
+//! The verified client certificates of mutual TLS connections, as passed to
+//! components in request headers.
+
+use std::{net::IpAddr, sync::Arc};
+
+use anyhow::{ensure, Context};
+use http::{HeaderMap, HeaderValue, Request};
+use x509_parser::{
+    der_parser::asn1_rs::{Any, Tag, ToDer},
+    extensions::GeneralName,
+    prelude::{FromDer, X509Certificate},
+    x509::{AttributeTypeAndValue, X509Name},
+};
+
+/// The header the client certificate's subject is passed in, as an RFC 4514
+/// distinguished name, e.g. `CN=billing,O=Example`.
+pub(crate) const CLIENT_CERT_SUBJECT_HEADER: &str = "spin-client-cert-subject";
+/// The header the client certificate's subject alternative names are passed
+/// in, e.g. `DNS:billing.internal, URI:spiffe://example.com/billing`.
+pub(crate) const CLIENT_CERT_SAN_HEADER: &str = "spin-client-cert-san";
+/// The header the hex SHA-256 fingerprint of the client certificate is passed in.
+pub(crate) const CLIENT_CERT_SHA256_HEADER: &str = "spin-client-cert-sha256";
+
+/// The parts of a verified client certificate passed to components.
+#[derive(Debug)]
+pub(crate) struct ClientCertificate {
+    subject: String,
+    subject_alt_names: Vec<String>,
+    sha256: String,
+}
+
+impl ClientCertificate {
+    /// Parses a DER-encoded X.509 certificate.
+    pub fn parse(der: &[u8]) -> anyhow::Result<Self> {
+        let (rest, cert) = X509Certificate::from_der(der).context("invalid certificate")?;
+        ensure!(rest.is_empty(), "unexpected data after certificate");
+        let subject = format_name(cert.subject());
+        let subject_alt_names = match cert
+            .subject_alternative_name()
+            .context("invalid subject alternative name extension")?
+        {
+            Some(extension) => format_general_names(&extension.value.general_names),
+            None => vec![],
+        };
+
+        let sha256 = ring::digest::digest(&ring::digest::SHA256, der)
+            .as_ref()
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect();
+        Ok(Self {
+            subject,
+            subject_alt_names,
+            sha256,
+        })
+    }
+
+    fn headers(&self) -> impl Iterator<Item = (&'static str, String)> + '_ {
+        [
+            (CLIENT_CERT_SUBJECT_HEADER, self.subject.clone()),
+            (CLIENT_CERT_SHA256_HEADER, self.sha256.clone()),
+        ]
+        .into_iter()
+        .chain(
+            (!self.subject_alt_names.is_empty())
+                .then(|| (CLIENT_CERT_SAN_HEADER, self.subject_alt_names.join(", "))),
+        )
+    }
+}
+
+/// Replaces any client certificate headers the client sent with those of the
+/// connection's verified client certificate, if it has one.
+pub(crate) fn set_client_cert_headers<B>(req: &mut Request<B>) {
+    let cert = req.extensions().get::<Arc<ClientCertificate>>().cloned();
+    let headers = req.headers_mut();
+    remove_client_cert_headers(headers);
+    for (name, value) in cert.iter().flat_map(|cert| cert.headers()) {
+        match HeaderValue::from_bytes(value.as_bytes()) {
+            Ok(value) => {
+                headers.insert(name, value);
+            }
+            Err(_) => tracing::warn!("Client certificate {name} {value:?} is not a valid header"),
+        }
+    }
+}
+
+fn remove_client_cert_headers(headers: &mut HeaderMap) {
+    for name in [
+        CLIENT_CERT_SUBJECT_HEADER,
+        CLIENT_CERT_SAN_HEADER,
+        CLIENT_CERT_SHA256_HEADER,
+    ] {
+        headers.remove(name);
+    }
+}
+
+/// Formats a distinguished name as described by RFC 4514.
+fn format_name(name: &X509Name) -> String {
+    let mut formatted = name
+        .iter()
+        .map(|rdn| {
+            rdn.iter()
+                .map(|attribute| {
+                    format!(
+                        "{}={}",
+                        attribute_type(attribute),
+                        attribute_value(attribute.attr_value())
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join("+")
+        })
+        .collect::<Vec<_>>();
+    // RFC 4514 names start with the most specific RDN.
+    formatted.reverse();
+    formatted.join(",")
+}
+
+fn attribute_type(attribute: &AttributeTypeAndValue) -> String {
+    let oid = attribute.attr_type();
+    let name = match oid.as_bytes() {
+        [0x55, 0x04, 0x03] => "CN",
+        [0x55, 0x04, 0x05] => "serialNumber",
+        [0x55, 0x04, 0x06] => "C",
+        [0x55, 0x04, 0x07] => "L",
+        [0x55, 0x04, 0x08] => "ST",
+        [0x55, 0x04, 0x09] => "STREET",
+        [0x55, 0x04, 0x0a] => "O",
+        [0x55, 0x04, 0x0b] => "OU",
+        [0x09, 0x92, 0x26, 0x89, 0x93, 0xf2, 0x2c, 0x64, 0x01, 0x19] => "DC",
+        [0x09, 0x92, 0x26, 0x89, 0x93, 0xf2, 0x2c, 0x64, 0x01, 0x01] => "UID",
+        _ => return oid.to_id_string(),
+    };
+    name.to_owned()
+}
+
+fn attribute_value(value: &Any) -> String {
+    let contents = value.as_bytes();
+    let text = match value.tag() {
+        Tag::Utf8String | Tag::PrintableString | Tag::T61String | Tag::Ia5String => {
+            String::from_utf8_lossy(contents).into_owned()
+        }
+        Tag::BmpString => {
+            let units = contents
+                .chunks(2)
+                .map(|pair| u16::from_be_bytes([pair[0], *pair.get(1).unwrap_or(&0)]));
+            char::decode_utf16(units)
+                .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+                .collect()
+        }
+        // Other values are written as the hex of their encoding.
+        _ => {
+            let encoded = value.to_der_vec().unwrap_or_default();
+            let hex: String = encoded.iter().map(|b| format!("{b:02x}")).collect();
+            return format!("#{hex}");
+        }
+    };
+
+    let last = text.chars().count().saturating_sub(1);
+    let mut escaped = String::with_capacity(text.len());
+    for (i, c) in text.chars().enumerate() {
+        let special = matches!(c, '"' | '+' | ',' | ';' | '<' | '>' | '\\')
+            || (i == 0 && matches!(c, '#' | ' '))
+            || (i == last && c == ' ');
+        if special {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Formats the names of a subject alternative name extension as OpenSSL does.
+fn format_general_names(names: &[GeneralName]) -> Vec<String> {
+    names
+        .iter()
+        .filter_map(|name| match name {
+            GeneralName::RFC822Name(email) => Some(format!("email:{email}")),
+            GeneralName::DNSName(dns) => Some(format!("DNS:{dns}")),
+            GeneralName::URI(uri) => Some(format!("URI:{uri}")),
+            GeneralName::IPAddress(ip) => {
+                let ip = match ip.len() {
+                    4 => <[u8; 4]>::try_from(*ip).map(IpAddr::from).ok(),
+                    16 => <[u8; 16]>::try_from(*ip).map(IpAddr::from).ok(),
+                    _ => None,
+                };
+                ip.map(|ip| format!("IP:{ip}"))
+            }
+            // Other kinds of names are rarely used to identify clients.
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io, path::Path};
+
+    use super::*;
+
+    const TESTDATA_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/testdata");
+
+    fn load_cert(name: &str) -> ClientCertificate {
+        let pem = fs::read(Path::new(TESTDATA_DIR).join(name)).unwrap();
+        let der = rustls_pemfile::certs(&mut io::Cursor::new(pem))
+            .next()
+            .unwrap()
+            .unwrap();
+        ClientCertificate::parse(&der).unwrap()
+    }
+
+    #[test]
+    fn certificates_are_summarized() {
+        let cert = load_cert("client-cert.pem");
+        assert_eq!(
+            cert.subject,
+            r"CN=billing,OU=Payments,O=Example\, Inc.,C=US"
+        );
+        assert_eq!(
+            cert.subject_alt_names,
+            [
+                "DNS:billing.internal",
+                "URI:spiffe://example.com/billing",
+                "IP:10.0.0.7",
+                "email:ops@example.com"
+            ]
+        );
+        assert_eq!(
+            cert.sha256,
+            "51dcb903bdc76fcaa5381b889602775e6ae231ce34f1da607446b1d5574555ac"
+        );
+
+        let cert = load_cert("valid-cert.pem");
+        assert_eq!(cert.subject, "CN=system:admin,O=system:masters");
+        assert!(cert.subject_alt_names.is_empty());
+    }
+
+    #[test]
+    fn client_cert_headers_come_only_from_the_connection() {
+        let mut req = Request::get("/")
+            .header(CLIENT_CERT_SUBJECT_HEADER, "CN=admin")
+            .body(())
+            .unwrap();
+        set_client_cert_headers(&mut req);
+        assert!(req.headers().get(CLIENT_CERT_SUBJECT_HEADER).is_none());
+
+        req.extensions_mut()
+            .insert(Arc::new(load_cert("valid-cert.pem")));
+        set_client_cert_headers(&mut req);
+        assert_eq!(
+            req.headers()[CLIENT_CERT_SUBJECT_HEADER],
+            "CN=system:admin,O=system:masters"
+        );
+        assert!(req.headers().get(CLIENT_CERT_SAN_HEADER).is_none());
+        assert_eq!(req.headers()[CLIENT_CERT_SHA256_HEADER].len(), 64);
+    }
+
+    #[test]
+    fn malformed_certificates_are_rejected() {
+        let pem = fs::read(Path::new(TESTDATA_DIR).join("client-cert.pem")).unwrap();
+        let der = rustls_pemfile::certs(&mut io::Cursor::new(pem))
+            .next()
+            .unwrap()
+            .unwrap();
+
+        // Truncated certificates are errors, not panics.
+        for len in 0..der.len() {
+            assert!(ClientCertificate::parse(&der[..len]).is_err(), "{len}");
+        }
+        let mut trailing = der.to_vec();
+        trailing.push(0);
+        assert!(ClientCertificate::parse(&trailing).is_err());
+
+        // Corrupting any byte either fails to parse or parses without
+        // panicking.
+        for index in 0..der.len() {
+            for flip in [0x01, 0x80, 0xff] {
+                let mut corrupt = der.to_vec();
+                corrupt[index] ^= flip;
+                let _ = ClientCertificate::parse(&corrupt);
+            }
+        }
+    }
+
+    #[test]
+    fn unknown_attribute_types_are_dotted_oids() {
+        let der = [
+            0x30, 0x14, 0x31, 0x12, 0x30, 0x10, 0x06, 0x09, 0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d,
+            0x01, 0x09, 0x01, 0x0c, 0x03, b'a', b',', b'b',
+        ];
+        let (_, name) = X509Name::from_der(&der).unwrap();
+        assert_eq!(r"1.2.840.113549.1.9.1=a\,b", format_name(&name));
+    }
+}
@@ -0,0 +1,335 @@
+use std::{
+    future::poll_fn,
+    io,
+    pin::Pin,
+    sync::{Arc, Mutex, Weak},
+    task::{ready, Context, Poll, Waker},
+};
+
+use http::{HeaderMap, Request, Version};
+use spin_factor_outbound_http::EarlyHintsSender;
+use spin_world::{async_trait, spin::http::early_hints::Error};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// The I/O of an HTTP/1.1 connection, through which `103 Early Hints`
+/// responses are written between the messages hyper writes.
+///
+/// hyper can't send informational responses itself, so hints are written
+/// whenever hyper isn't part way through writing a message: either straight
+/// away, or before hyper next writes.
+pub(crate) struct EarlyHintsIo {
+    shared: Arc<Mutex<Shared>>,
+}
+
+/// Starts the requests on a connection served through an [`EarlyHintsIo`].
+pub(crate) struct EarlyHintsConnection {
+    shared: Arc<Mutex<Shared>>,
+}
+
+/// Sends early hints for a request, until [`Self::finish`] is called as its
+/// response is returned.
+///
+/// It is added to requests as an extension, for the [`EarlyHintsSender`] of
+/// the instance handling them.
+#[derive(Clone)]
+pub(crate) struct EarlyHints {
+    shared: Weak<Mutex<Shared>>,
+    request: u64,
+}
+
+trait Io: AsyncRead + AsyncWrite + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Send> Io for T {}
+
+struct Shared {
+    io: Pin<Box<dyn Io>>,
+    /// Encoded hints waiting to be written.
+    hints: Vec<u8>,
+    /// Whether hyper has written part of a message without flushing it.
+    mid_message: bool,
+    /// Whether the connection has failed or closed.
+    closed: bool,
+    /// The senders waiting for their hints to be written.
+    waiters: Vec<Waker>,
+    /// Identifies the latest request started.
+    request: u64,
+    /// Whether the latest request's response is yet to be returned.
+    open: bool,
+}
+
+impl EarlyHintsIo {
+    pub(crate) fn new(
+        io: impl AsyncRead + AsyncWrite + Send + 'static,
+    ) -> (Self, EarlyHintsConnection) {
+        let shared = Arc::new(Mutex::new(Shared {
+            io: Box::pin(io),
+            hints: vec![],
+            mid_message: false,
+            closed: false,
+            waiters: vec![],
+            request: 0,
+            open: false,
+        }));
+        let connection = EarlyHintsConnection {
+            shared: shared.clone(),
+        };
+        (Self { shared }, connection)
+    }
+}
+
+impl EarlyHintsConnection {
+    /// Starts handling `request`, returning the sender of its hints if they
+    /// can be sent, which is only over HTTP/1.1.
+    pub(crate) fn start_request<B>(&self, request: &Request<B>) -> Option<EarlyHints> {
+        if request.version() != Version::HTTP_11 {
+            return None;
+        }
+        let mut shared = self.shared.lock().unwrap();
+        shared.request += 1;
+        shared.open = true;
+        Some(EarlyHints {
+            shared: Arc::downgrade(&self.shared),
+            request: shared.request,
+        })
+    }
+}
+
+impl EarlyHints {
+    /// Stops hints being sent, as the request's response is being returned.
+    ///
+    /// Hints which have been sent but not yet written are still written
+    /// ahead of the response.
+    pub(crate) fn finish(&self) {
+        let Some(shared) = self.shared.upgrade() else {
+            return;
+        };
+        let mut shared = shared.lock().unwrap();
+        if shared.request == self.request {
+            shared.open = false;
+        }
+    }
+}
+
+#[async_trait]
+impl EarlyHintsSender for EarlyHints {
+    async fn send(&self, headers: HeaderMap) -> Result<(), Error> {
+        let mut message = b"HTTP/1.1 103 Early Hints\r\n".to_vec();
+        for (name, value) in &headers {
+            message.extend_from_slice(name.as_str().as_bytes());
+            message.extend_from_slice(b": ");
+            message.extend_from_slice(value.as_bytes());
+            message.extend_from_slice(b"\r\n");
+        }
+        message.extend_from_slice(b"\r\n");
+
+        {
+            let shared = self.shared.upgrade().ok_or(Error::Unsupported)?;
+            let mut shared = shared.lock().unwrap();
+            if !shared.open || shared.request != self.request {
+                return Err(Error::Unsupported);
+            }
+            shared.hints.extend_from_slice(&message);
+        }
+
+        poll_fn(|cx| {
+            let Some(shared) = self.shared.upgrade() else {
+                return Poll::Ready(Err(closed()));
+            };
+            let mut shared = shared.lock().unwrap();
+            shared.poll_send_hints(cx)
+        })
+        .await
+        .map_err(|err| Error::Other(format!("failed to send early hints: {err}")))
+    }
+}
+
+impl Shared {
+    /// Writes and flushes any hints, or waits for hyper to write them if it is
+    /// part way through writing a message.
+    fn poll_send_hints(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        if self.closed {
+            return Poll::Ready(Err(closed()));
+        }
+        if self.hints.is_empty() {
+            return Poll::Ready(Ok(()));
+        }
+        if !self.mid_message {
+            if let Poll::Ready(result) = self.poll_write_hints(cx) {
+                result?;
+                return self.io.as_mut().poll_flush(cx);
+            }
+        }
+        // The I/O only wakes the task which polled it last, which may have
+        // been hyper's, so whichever writes the hints wakes the senders.
+        self.waiters.push(cx.waker().clone());
+        Poll::Pending
+    }
+
+    /// Writes any hints, which must only be done between hyper's messages.
+    fn poll_write_hints(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while !self.hints.is_empty() {
+            let result = match ready!(self.io.as_mut().poll_write(cx, &self.hints)) {
+                Ok(0) => Err(io::ErrorKind::WriteZero.into()),
+                result => result,
+            };
+            match result {
+                Ok(written) => {
+                    self.hints.drain(..written);
+                }
+                Err(err) => {
+                    self.close();
+                    return Poll::Ready(Err(err));
+                }
+            }
+        }
+        self.wake_waiters();
+        Poll::Ready(Ok(()))
+    }
+
+    fn close(&mut self) {
+        self.closed = true;
+        self.wake_waiters();
+    }
+
+    fn wake_waiters(&mut self) {
+        for waker in self.waiters.drain(..) {
+            waker.wake();
+        }
+    }
+}
+
+fn closed() -> io::Error {
+    io::Error::new(io::ErrorKind::NotConnected, "the connection has closed")
+}
+
+impl Drop for EarlyHintsIo {
+    fn drop(&mut self) {
+        self.shared.lock().unwrap().close();
+    }
+}
+
+impl AsyncRead for EarlyHintsIo {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        self.shared.lock().unwrap().io.as_mut().poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for EarlyHintsIo {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let mut shared = self.shared.lock().unwrap();
+        if !shared.mid_message {
+            ready!(shared.poll_write_hints(cx))?;
+        }
+        let written = ready!(shared.io.as_mut().poll_write(cx, buf))?;
+        if written > 0 {
+            shared.mid_message = true;
+        }
+        Poll::Ready(Ok(written))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let mut shared = self.shared.lock().unwrap();
+        ready!(shared.io.as_mut().poll_flush(cx))?;
+        // Hints sent while hyper was part way through a message are written
+        // now that it has finished.
+        shared.mid_message = false;
+        ready!(shared.poll_write_hints(cx))?;
+        shared.io.as_mut().poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.shared.lock().unwrap().io.as_mut().poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::*;
+
+    fn hints() -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert("link", "</style.css>; rel=preload".parse().unwrap());
+        headers
+    }
+
+    const HINTS_MESSAGE: &str =
+        "HTTP/1.1 103 Early Hints\r\nlink: </style.css>; rel=preload\r\n\r\n";
+
+    async fn read_string(client: &mut tokio::io::DuplexStream, len: usize) -> String {
+        let mut buf = vec![0; len];
+        client.read_exact(&mut buf).await.unwrap();
+        String::from_utf8(buf).unwrap()
+    }
+
+    #[tokio::test]
+    async fn hints_are_written_between_messages() {
+        let (mut client, server) = tokio::io::duplex(1024);
+        let (mut io, connection) = EarlyHintsIo::new(server);
+        let request = Request::get("/").body(()).unwrap();
+        let early_hints = connection.start_request(&request).unwrap();
+
+        early_hints.send(hints()).await.unwrap();
+        assert_eq!(
+            read_string(&mut client, HINTS_MESSAGE.len()).await,
+            HINTS_MESSAGE
+        );
+
+        // Hints sent while a message is part written follow that message.
+        io.write_all(b"HTTP/1.1 100 ").await.unwrap();
+        let sent = tokio::spawn({
+            let early_hints = early_hints.clone();
+            async move { early_hints.send(hints()).await }
+        });
+        tokio::task::yield_now().await;
+        assert!(!sent.is_finished());
+        io.write_all(b"Continue\r\n\r\n").await.unwrap();
+        io.flush().await.unwrap();
+        sent.await.unwrap().unwrap();
+        let expected = format!("HTTP/1.1 100 Continue\r\n\r\n{HINTS_MESSAGE}");
+        assert_eq!(read_string(&mut client, expected.len()).await, expected);
+
+        early_hints.finish();
+        assert!(matches!(
+            early_hints.send(hints()).await,
+            Err(Error::Unsupported)
+        ));
+    }
+
+    #[tokio::test]
+    async fn hints_are_only_sent_over_http_1_1() {
+        let (_client, server) = tokio::io::duplex(1024);
+        let (_io, connection) = EarlyHintsIo::new(server);
+        let request = Request::get("/")
+            .version(Version::HTTP_10)
+            .body(())
+            .unwrap();
+        assert!(connection.start_request(&request).is_none());
+    }
+
+    #[tokio::test]
+    async fn hints_for_earlier_requests_are_rejected() {
+        let (_client, server) = tokio::io::duplex(1024);
+        let (io, connection) = EarlyHintsIo::new(server);
+        let request = Request::get("/").body(()).unwrap();
+        let first = connection.start_request(&request).unwrap();
+        let second = connection.start_request(&request).unwrap();
+        assert!(matches!(first.send(hints()).await, Err(Error::Unsupported)));
+
+        drop(io);
+        drop(connection);
+        assert!(matches!(
+            second.send(hints()).await,
+            Err(Error::Unsupported)
+        ));
+    }
+}
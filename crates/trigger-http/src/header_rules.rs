@@ -0,0 +1,203 @@
+use anyhow::Context;
+use http::{HeaderMap, HeaderName, HeaderValue};
+use spin_expressions::Template;
+use spin_factor_variables::AppState as VariablesState;
+use spin_http::config::{HeaderRewriteConfig, HeaderRules};
+
+/// Applies a component's [`HeaderRewriteConfig`].
+pub(crate) struct HeaderRewriter {
+    request: CompiledRules,
+    response: CompiledRules,
+}
+
+/// [`HeaderRules`] with names and values parsed up front.
+#[derive(Default)]
+struct CompiledRules {
+    remove: Vec<HeaderName>,
+    set: Vec<(HeaderName, RuleValue)>,
+    append: Vec<(HeaderName, RuleValue)>,
+}
+
+enum RuleValue {
+    Literal(HeaderValue),
+    Template(String),
+}
+
+impl HeaderRewriter {
+    pub fn new(component_id: &str, config: &HeaderRewriteConfig) -> anyhow::Result<Self> {
+        Ok(Self {
+            request: CompiledRules::new(&config.request).with_context(|| {
+                format!("invalid request header rules for component {component_id:?}")
+            })?,
+            response: CompiledRules::new(&config.response).with_context(|| {
+                format!("invalid response header rules for component {component_id:?}")
+            })?,
+        })
+    }
+
+    /// Whether any rule value refers to application variables.
+    pub fn uses_variables(&self) -> bool {
+        self.request.uses_variables() || self.response.uses_variables()
+    }
+
+    /// Rewrites the headers of a request to the component.
+    pub async fn rewrite_request(
+        &self,
+        headers: &mut HeaderMap,
+        variables: Option<&VariablesState>,
+    ) -> anyhow::Result<()> {
+        self.request.apply(headers, variables).await
+    }
+
+    /// Rewrites the headers of a response from the component.
+    pub async fn rewrite_response(
+        &self,
+        headers: &mut HeaderMap,
+        variables: Option<&VariablesState>,
+    ) -> anyhow::Result<()> {
+        self.response.apply(headers, variables).await
+    }
+}
+
+impl CompiledRules {
+    fn new(rules: &HeaderRules) -> anyhow::Result<Self> {
+        let remove = rules
+            .remove
+            .iter()
+            .map(|name| parse_name(name))
+            .collect::<anyhow::Result<_>>()?;
+        let set = rules
+            .set
+            .iter()
+            .map(|(name, value)| Ok((parse_name(name)?, RuleValue::new(name, value)?)))
+            .collect::<anyhow::Result<_>>()?;
+        let append = rules
+            .append
+            .iter()
+            .map(|(name, value)| Ok((parse_name(name)?, RuleValue::new(name, value)?)))
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Self {
+            remove,
+            set,
+            append,
+        })
+    }
+
+    fn uses_variables(&self) -> bool {
+        self.set
+            .iter()
+            .chain(&self.append)
+            .any(|(_, value)| matches!(value, RuleValue::Template(_)))
+    }
+
+    async fn apply(
+        &self,
+        headers: &mut HeaderMap,
+        variables: Option<&VariablesState>,
+    ) -> anyhow::Result<()> {
+        for name in &self.remove {
+            headers.remove(name);
+        }
+        for (name, value) in &self.set {
+            headers.insert(name.clone(), value.resolve(name, variables).await?);
+        }
+        for (name, value) in &self.append {
+            headers.append(name.clone(), value.resolve(name, variables).await?);
+        }
+        Ok(())
+    }
+}
+
+impl RuleValue {
+    fn new(name: &str, value: &str) -> anyhow::Result<Self> {
+        let template = Template::new(value)
+            .with_context(|| format!("invalid value {value:?} for header {name:?}"))?;
+        if template.is_literal() {
+            let value = HeaderValue::from_str(value)
+                .with_context(|| format!("invalid value {value:?} for header {name:?}"))?;
+            Ok(Self::Literal(value))
+        } else {
+            Ok(Self::Template(value.to_owned()))
+        }
+    }
+
+    async fn resolve(
+        &self,
+        name: &HeaderName,
+        variables: Option<&VariablesState>,
+    ) -> anyhow::Result<HeaderValue> {
+        match self {
+            Self::Literal(value) => Ok(value.clone()),
+            Self::Template(template) => {
+                let variables = variables.context("header rules require variables support")?;
+                let value = variables
+                    .resolve_expression(template.as_str())
+                    .await
+                    .with_context(|| format!("failed to resolve value for header {name}"))?;
+                HeaderValue::from_str(&value)
+                    .with_context(|| format!("resolved value for header {name} is not valid"))
+            }
+        }
+    }
+}
+
+fn parse_name(name: &str) -> anyhow::Result<HeaderName> {
+    name.parse()
+        .with_context(|| format!("invalid header name {name:?}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rewriter(config: toml::Table) -> HeaderRewriter {
+        HeaderRewriter::new("test", &config.try_into().unwrap()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn rules_apply_in_order() {
+        let rewriter = rewriter(toml::toml! {
+            [request]
+            remove = ["cookie", "x-forwarded-by"]
+            set = { "x-api-version" = "2" }
+            append = { "x-forwarded-by" = "spin" }
+        });
+        let mut headers = HeaderMap::new();
+        headers.insert("cookie", HeaderValue::from_static("session=1"));
+        headers.insert("x-api-version", HeaderValue::from_static("1"));
+        headers.insert("x-forwarded-by", HeaderValue::from_static("client"));
+
+        rewriter.rewrite_request(&mut headers, None).await.unwrap();
+
+        assert!(!headers.contains_key("cookie"));
+        assert_eq!("2", headers["x-api-version"]);
+        assert_eq!(
+            vec!["spin"],
+            headers.get_all("x-forwarded-by").iter().collect::<Vec<_>>()
+        );
+        assert!(!rewriter.uses_variables());
+    }
+
+    #[tokio::test]
+    async fn templates_require_variables() {
+        let rewriter = rewriter(toml::toml! {
+            [response]
+            set = { authorization = "Bearer {{ api_key }}" }
+        });
+        assert!(rewriter.uses_variables());
+        let mut headers = HeaderMap::new();
+        rewriter
+            .rewrite_response(&mut headers, None)
+            .await
+            .unwrap_err();
+    }
+
+    #[test]
+    fn invalid_header_names_are_rejected() {
+        let config = toml::toml! {
+            [request]
+            remove = ["not a header"]
+        };
+        HeaderRewriter::new("test", &config.try_into().unwrap()).unwrap_err();
+    }
+}
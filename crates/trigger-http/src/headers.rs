@@ -6,7 +6,7 @@ use hyper::Request;
 use spin_factor_outbound_networking::is_service_chaining_host;
 use spin_http::routes::RouteMatch;
 
-use crate::Body;
+use crate::{Body, RequestId};
 
 // We need to make the following pieces of information available to both executors.
 // While the values we set are identical, the way they are passed to the
@@ -20,6 +20,7 @@ pub const COMPONENT_ROUTE: [&str; 2] = ["SPIN_COMPONENT_ROUTE", "X_COMPONENT_ROU
 pub const RAW_COMPONENT_ROUTE: [&str; 2] = ["SPIN_RAW_COMPONENT_ROUTE", "X_RAW_COMPONENT_ROUTE"];
 pub const BASE_PATH: [&str; 2] = ["SPIN_BASE_PATH", "X_BASE_PATH"];
 pub const CLIENT_ADDR: [&str; 2] = ["SPIN_CLIENT_ADDR", "X_CLIENT_ADDR"];
+pub const REQUEST_ID: [&str; 2] = ["SPIN_REQUEST_ID", "X_REQUEST_ID"];
 
 pub fn compute_default_headers(
     uri: &Uri,
@@ -112,6 +113,12 @@ pub fn prepare_request_headers(
     for (keys, val) in compute_default_headers(req.uri(), host, route_match, client_addr)? {
         res.push((prepare_header_key(&keys[0]), val));
     }
+    let request_id_key = prepare_header_key(REQUEST_ID[0]);
+    if let Some(request_id) = req.extensions().get::<RequestId>() {
+        // Replace any request ID header the client sent.
+        res.retain(|(name, _)| *name != request_id_key);
+        res.push((request_id_key, request_id.to_string()));
+    }
 
     Ok(res)
 }
@@ -0,0 +1,556 @@
+use std::{
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Context;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use futures::StreamExt;
+use http::{Method, Request, Response, StatusCode};
+use http_body_util::{BodyExt, BodyStream, StreamBody};
+use hyper::body::{Body as _, Bytes, Frame};
+use serde::{Deserialize, Serialize};
+use spin_factor_key_value::{AppState as KeyValueState, Store, LEASE_KEY_PREFIX};
+use spin_http::{body, config::IdempotencyConfig};
+
+use crate::Body;
+
+/// The request header carrying the client's idempotency key.
+const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+
+/// The response header marking a response as a replay.
+const REPLAYED_HEADER: &str = "idempotent-replayed";
+
+/// The longest idempotency key accepted.
+const MAX_KEY_LEN: usize = 255;
+
+/// The largest response body kept for replay. Larger responses are passed on
+/// but not kept.
+const MAX_BODY_BYTES: usize = 1024 * 1024;
+
+/// The largest request body accepted with an idempotency key, as the body is
+/// read up front to fingerprint the request.
+const MAX_REQUEST_BODY_BYTES: usize = 1024 * 1024;
+
+/// How long a request is marked as being handled, in case the process handling
+/// it exits before it is done.
+const IN_FLIGHT_TTL: Duration = Duration::from_secs(5 * 60);
+
+/// Enforces a component's [`IdempotencyConfig`].
+pub(crate) struct Idempotency {
+    component_id: String,
+    store: String,
+    ttl: Duration,
+}
+
+/// What to do with a request, as decided by [`Idempotency::admit`].
+pub(crate) enum Admission<'a> {
+    /// Handle the request, passing its response to the recorder if there is
+    /// one.
+    Handle(Request<Body>, Option<Recorder<'a>>),
+    /// Respond without invoking the component.
+    Respond(Response<Body>),
+}
+
+impl Idempotency {
+    pub fn new(component_id: &str, config: &IdempotencyConfig) -> anyhow::Result<Self> {
+        anyhow::ensure!(
+            config.ttl_secs > 0,
+            "idempotency TTL for component {component_id:?} must be at least 1 second"
+        );
+        Ok(Self {
+            component_id: component_id.to_owned(),
+            store: config.store.clone(),
+            ttl: Duration::from_secs(config.ttl_secs),
+        })
+    }
+
+    /// The label of the key-value store responses are kept in.
+    pub fn store(&self) -> &str {
+        &self.store
+    }
+
+    /// Decides whether a request is a retry whose response can be replayed.
+    ///
+    /// Only `POST` requests with an `Idempotency-Key` header are considered.
+    /// A key reused for a different request is rejected with a 422, and a
+    /// retry while the first request is still being handled, by any instance
+    /// of the app sharing the store, with a 409. If the store can't be reached
+    /// the request is handled as usual, so that a store outage doesn't take
+    /// the app down with it.
+    pub async fn admit(
+        &self,
+        req: Request<Body>,
+        key_value: Option<&KeyValueState>,
+    ) -> anyhow::Result<Admission<'_>> {
+        let store = match key_value {
+            Some(key_value) => key_value.get_store(&self.store).await,
+            None => None,
+        };
+        self.admit_with(req, store).await
+    }
+
+    async fn admit_with(
+        &self,
+        req: Request<Body>,
+        store: Option<Arc<dyn Store>>,
+    ) -> anyhow::Result<Admission<'_>> {
+        if req.method() != Method::POST {
+            return Ok(Admission::Handle(req, None));
+        }
+        let key = match req.headers().get(IDEMPOTENCY_KEY_HEADER) {
+            None => return Ok(Admission::Handle(req, None)),
+            Some(key) => match key.to_str() {
+                Ok(key) if !key.is_empty() && key.len() <= MAX_KEY_LEN => key.to_owned(),
+                _ => {
+                    return Ok(Admission::Respond(error_response(
+                        StatusCode::BAD_REQUEST,
+                        "invalid Idempotency-Key header",
+                    )?))
+                }
+            },
+        };
+
+        // The body is part of the fingerprint, so it has to be read up front.
+        let (parts, req_body) = req.into_parts();
+        let Ok(bytes) = read_limited(req_body, MAX_REQUEST_BODY_BYTES).await? else {
+            return Ok(Admission::Respond(error_response(
+                StatusCode::PAYLOAD_TOO_LARGE,
+                "request body too large for an Idempotency-Key",
+            )?));
+        };
+        let fingerprint = fingerprint(&parts.method, &parts.uri, &bytes);
+        let req = Request::from_parts(parts, body::full(bytes));
+
+        let Some(store) = store else {
+            self.log_store_error(&anyhow::anyhow!("no such store"));
+            return Ok(Admission::Handle(req, None));
+        };
+        let mut recorder = Recorder {
+            idempotency: self,
+            store,
+            key,
+            fingerprint,
+            lease: None,
+        };
+        // The request is marked as being handled before looking for its
+        // response, so that a retry can't slip in between.
+        let token = uuid::Uuid::new_v4().to_string();
+        match recorder
+            .store
+            .acquire_lease(&recorder.lease_key(), &token, IN_FLIGHT_TTL)
+            .await
+        {
+            Ok(true) => recorder.lease = Some(token),
+            Ok(false) => {
+                return Ok(Admission::Respond(error_response(
+                    StatusCode::CONFLICT,
+                    "a request with this Idempotency-Key is already being handled",
+                )?))
+            }
+            Err(err) => self.log_store_error(&err.into()),
+        }
+        match recorder.load().await {
+            Ok(Some(stored)) if stored.fingerprint != recorder.fingerprint => {
+                Ok(Admission::Respond(error_response(
+                    StatusCode::UNPROCESSABLE_ENTITY,
+                    "this Idempotency-Key was used for a different request",
+                )?))
+            }
+            Ok(Some(stored)) => Ok(Admission::Respond(stored.into_response()?)),
+            Ok(None) => Ok(Admission::Handle(req, Some(recorder))),
+            Err(err) => {
+                self.log_store_error(&err);
+                Ok(Admission::Handle(req, Some(recorder)))
+            }
+        }
+    }
+
+    fn log_store_error(&self, err: &anyhow::Error) {
+        tracing::warn!(
+            "Idempotency store {:?} failed for component {:?}: {err:#}",
+            self.store,
+            self.component_id
+        );
+    }
+}
+
+/// Keeps the response to a request with an idempotency key.
+///
+/// Until it is dropped, other requests with the same key are rejected.
+pub(crate) struct Recorder<'a> {
+    idempotency: &'a Idempotency,
+    store: Arc<dyn Store>,
+    key: String,
+    fingerprint: String,
+    /// The token of the lease marking the request as being handled, until it
+    /// is released.
+    lease: Option<String>,
+}
+
+impl Recorder<'_> {
+    /// Keeps the response for replay, returning it with its body buffered if
+    /// it is kept.
+    ///
+    /// Server errors aren't kept, so that retries can succeed.
+    pub async fn record(mut self, res: Response<Body>) -> anyhow::Result<Response<Body>> {
+        if res.status().is_server_error() {
+            self.release().await;
+            return Ok(res);
+        }
+        let (parts, res_body) = res.into_parts();
+        let bytes = match read_limited(res_body, MAX_BODY_BYTES).await? {
+            Ok(bytes) => bytes,
+            Err(res_body) => {
+                tracing::debug!(
+                    "Not keeping response of more than {MAX_BODY_BYTES} bytes for idempotency key {:?}",
+                    self.key
+                );
+                self.release().await;
+                return Ok(Response::from_parts(parts, res_body));
+            }
+        };
+        let res = Response::from_parts(parts, bytes);
+        let stored = StoredResponse::new(&res, self.fingerprint.clone(), self.expires_at());
+        if let Err(err) = self.save(&stored).await {
+            self.idempotency.log_store_error(&err);
+        }
+        self.release().await;
+        Ok(res.map(body::full))
+    }
+
+    async fn load(&self) -> anyhow::Result<Option<StoredResponse>> {
+        let Some(value) = self.store.get(&self.store_key()).await? else {
+            return Ok(None);
+        };
+        let stored: StoredResponse = match serde_json::from_slice(&value) {
+            Ok(stored) => stored,
+            Err(err) => {
+                tracing::debug!("Ignoring invalid stored response {:?}: {err}", self.key);
+                return Ok(None);
+            }
+        };
+        // Expired responses are left to be overwritten by the next response
+        // for the key.
+        Ok((stored.expires_at > now_secs()).then_some(stored))
+    }
+
+    async fn save(&self, stored: &StoredResponse) -> anyhow::Result<()> {
+        self.store
+            .set(&self.store_key(), &serde_json::to_vec(stored)?)
+            .await?;
+        Ok(())
+    }
+
+    /// Releases the lease marking the request as being handled, so that
+    /// retries are admitted.
+    async fn release(&mut self) {
+        if let Some(token) = self.lease.take() {
+            if let Err(err) = self.store.release_lease(&self.lease_key(), &token).await {
+                self.idempotency.log_store_error(&err.into());
+            }
+        }
+    }
+
+    fn store_key(&self) -> String {
+        format!(
+            "spin-idempotency/{}/{}",
+            self.idempotency.component_id, self.key
+        )
+    }
+
+    /// The key of the lease, which stores may hold alongside other keys.
+    fn lease_key(&self) -> String {
+        format!("{LEASE_KEY_PREFIX}{}", self.store_key())
+    }
+
+    fn expires_at(&self) -> u64 {
+        now_secs() + self.idempotency.ttl.as_secs()
+    }
+}
+
+impl Drop for Recorder<'_> {
+    fn drop(&mut self) {
+        // A recorder dropped without recording a response, such as when the
+        // component fails, releases its lease in the background.
+        let Some(token) = self.lease.take() else {
+            return;
+        };
+        let (store, lease_key) = (self.store.clone(), self.lease_key());
+        if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+            runtime.spawn(async move {
+                if let Err(err) = store.release_lease(&lease_key, &token).await {
+                    tracing::warn!("Failed to release idempotency lease {lease_key:?}: {err}");
+                }
+            });
+        }
+    }
+}
+
+/// A response kept for replay, with times in seconds since the Unix epoch so
+/// that responses kept in a store mean the same thing to every instance.
+#[derive(Debug, Serialize, Deserialize)]
+struct StoredResponse {
+    /// Identifies the request the response is for.
+    fingerprint: String,
+    expires_at: u64,
+    status: u16,
+    headers: Vec<(String, String)>,
+    /// The base64-encoded body.
+    body: String,
+}
+
+impl StoredResponse {
+    fn new(res: &Response<Bytes>, fingerprint: String, expires_at: u64) -> Self {
+        // Header values which aren't visible ASCII are rare in responses, and
+        // are left out rather than mangled.
+        let headers = res
+            .headers()
+            .iter()
+            .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_owned())))
+            .collect();
+        Self {
+            fingerprint,
+            expires_at,
+            status: res.status().as_u16(),
+            headers,
+            body: STANDARD.encode(res.body()),
+        }
+    }
+
+    fn into_response(self) -> anyhow::Result<Response<Body>> {
+        let mut builder = Response::builder().status(self.status);
+        for (name, value) in &self.headers {
+            builder = builder.header(name, value);
+        }
+        let body = STANDARD
+            .decode(&self.body)
+            .context("invalid stored response body")?;
+        Ok(builder
+            .header(REPLAYED_HEADER, "true")
+            .body(body::full(body.into()))?)
+    }
+}
+
+/// Identifies a request by its method, path, query and body, so that reuse
+/// of a key for a different request can be detected.
+fn fingerprint(method: &Method, uri: &http::Uri, body: &[u8]) -> String {
+    let path_and_query = uri.path_and_query().map(|p| p.as_str()).unwrap_or("/");
+    let mut digest = ring::digest::Context::new(&ring::digest::SHA256);
+    digest.update(method.as_str().as_bytes());
+    digest.update(b"\n");
+    digest.update(path_and_query.as_bytes());
+    digest.update(b"\n");
+    digest.update(body);
+    digest
+        .finish()
+        .as_ref()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+/// Reads a body of at most `limit` bytes. A larger body is returned in place
+/// of its bytes, with the bytes read so far put back.
+async fn read_limited(mut body: Body, limit: usize) -> anyhow::Result<Result<Bytes, Body>> {
+    if body.size_hint().lower() > limit as u64 {
+        return Ok(Err(body));
+    }
+    let mut bytes = Vec::new();
+    while let Some(frame) = body.frame().await {
+        // Trailers aren't kept.
+        let Ok(data) = frame?.into_data() else {
+            continue;
+        };
+        bytes.extend_from_slice(&data);
+        if bytes.len() > limit {
+            let read = futures::stream::once(std::future::ready(Ok(Frame::data(bytes.into()))));
+            let rest = BodyStream::new(body);
+            return Ok(Err(StreamBody::new(read.chain(rest)).boxed()));
+        }
+    }
+    Ok(Ok(bytes.into()))
+}
+
+fn error_response(status: StatusCode, message: &str) -> anyhow::Result<Response<Body>> {
+    Ok(Response::builder()
+        .status(status)
+        .body(body::full(Bytes::copy_from_slice(message.as_bytes())))?)
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use spin_factor_key_value::StoreManager;
+    use spin_key_value_spin::{DatabaseLocation, KeyValueSqlite};
+
+    use super::*;
+
+    async fn store() -> Arc<dyn Store> {
+        KeyValueSqlite::new(DatabaseLocation::InMemory)
+            .get("default")
+            .await
+            .unwrap()
+    }
+
+    fn idempotency() -> Idempotency {
+        Idempotency::new(
+            "api",
+            &IdempotencyConfig {
+                store: "default".into(),
+                ttl_secs: 60,
+            },
+        )
+        .unwrap()
+    }
+
+    fn post(key: Option<&str>, body: &'static str) -> Request<Body> {
+        let mut builder = Request::post("/orders");
+        if let Some(key) = key {
+            builder = builder.header(IDEMPOTENCY_KEY_HEADER, key);
+        }
+        builder
+            .body(body::full(Bytes::from_static(body.as_bytes())))
+            .unwrap()
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn only_posts_with_keys_are_recorded() -> anyhow::Result<()> {
+        let (idempotency, store) = (idempotency(), store().await);
+        let get = Request::get("/orders")
+            .header(IDEMPOTENCY_KEY_HEADER, "abc")
+            .body(body::empty())?;
+        assert!(matches!(
+            idempotency.admit_with(get, Some(store.clone())).await?,
+            Admission::Handle(_, None)
+        ));
+        assert!(matches!(
+            idempotency
+                .admit_with(post(None, "{}"), Some(store.clone()))
+                .await?,
+            Admission::Handle(_, None)
+        ));
+        assert!(matches!(
+            idempotency
+                .admit_with(post(Some("abc"), "{}"), Some(store))
+                .await?,
+            Admission::Handle(_, Some(_))
+        ));
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn concurrent_retries_are_rejected() -> anyhow::Result<()> {
+        let store = store().await;
+        let Admission::Handle(_, Some(recorder)) = idempotency()
+            .admit_with(post(Some("abc"), "{}"), Some(store.clone()))
+            .await?
+        else {
+            panic!("first request should be handled");
+        };
+        // Other instances sharing the store see the request being handled.
+        let other = idempotency();
+        let Admission::Respond(res) = other
+            .admit_with(post(Some("abc"), "{}"), Some(store.clone()))
+            .await?
+        else {
+            panic!("retry should be rejected");
+        };
+        assert_eq!(res.status(), StatusCode::CONFLICT);
+
+        let res = Response::builder()
+            .status(StatusCode::CREATED)
+            .body(body::full(Bytes::from_static(b"created")))?;
+        recorder.record(res).await?;
+        let Admission::Respond(res) = other
+            .admit_with(post(Some("abc"), "{}"), Some(store))
+            .await?
+        else {
+            panic!("retry should be replayed");
+        };
+        assert_eq!(res.status(), StatusCode::CREATED);
+        assert_eq!(res.headers()[REPLAYED_HEADER], "true");
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn large_bodies_are_bounded() -> anyhow::Result<()> {
+        let (idempotency, store) = (idempotency(), store().await);
+        let large = Bytes::from(vec![b'x'; MAX_REQUEST_BODY_BYTES + 1]);
+        let req = Request::post("/orders")
+            .header(IDEMPOTENCY_KEY_HEADER, "abc")
+            .body(body::full(large.clone()))?;
+        let Admission::Respond(res) = idempotency.admit_with(req, Some(store.clone())).await?
+        else {
+            panic!("large request should be rejected");
+        };
+        assert_eq!(res.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        // Large responses are passed on whole, but not kept.
+        let Admission::Handle(_, Some(recorder)) = idempotency
+            .admit_with(post(Some("abc"), "{}"), Some(store.clone()))
+            .await?
+        else {
+            panic!("request should be handled");
+        };
+        let res = Response::new(body::full(large.clone()));
+        let res = recorder.record(res).await?;
+        assert_eq!(res.into_body().collect().await?.to_bytes(), large);
+        assert!(matches!(
+            idempotency
+                .admit_with(post(Some("abc"), "{}"), Some(store))
+                .await?,
+            Admission::Handle(_, Some(_))
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn invalid_keys_are_rejected() -> anyhow::Result<()> {
+        let idempotency = idempotency();
+        let long_key = "k".repeat(MAX_KEY_LEN + 1);
+        for key in ["", long_key.as_str()] {
+            let Admission::Respond(res) =
+                idempotency.admit_with(post(Some(key), "{}"), None).await?
+            else {
+                panic!("key {key:?} should be rejected");
+            };
+            assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn fingerprints_distinguish_requests() {
+        let uri = "https://example.com/orders?a=1".parse().unwrap();
+        let other_uri = "https://example.com/orders?a=2".parse().unwrap();
+        let print = fingerprint(&Method::POST, &uri, b"{}");
+        assert_eq!(print, fingerprint(&Method::POST, &uri, b"{}"));
+        assert_ne!(print, fingerprint(&Method::POST, &uri, b"{\"a\":1}"));
+        assert_ne!(print, fingerprint(&Method::POST, &other_uri, b"{}"));
+    }
+
+    #[tokio::test]
+    async fn stored_responses_are_replayed() -> anyhow::Result<()> {
+        let res = Response::builder()
+            .status(StatusCode::CREATED)
+            .header("content-type", "application/json")
+            .body(Bytes::from_static(b"{\"id\":1}"))?;
+        let stored = StoredResponse::new(&res, "print".into(), now_secs() + 60);
+        let json = serde_json::to_vec(&stored)?;
+
+        let replayed = serde_json::from_slice::<StoredResponse>(&json)?.into_response()?;
+        assert_eq!(replayed.status(), StatusCode::CREATED);
+        assert_eq!(replayed.headers()["content-type"], "application/json");
+        assert_eq!(replayed.headers()[REPLAYED_HEADER], "true");
+        let body = replayed.into_body().collect().await?.to_bytes();
+        assert_eq!(body.as_ref(), b"{\"id\":1}");
+        Ok(())
+    }
+}
@@ -0,0 +1,224 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use spin_app::InstanceConfig;
+use spin_core::Instance;
+use spin_factors::RuntimeFactors;
+use spin_http::trigger::HandlerType;
+
+use crate::{session_affinity::SessionGuard, Store};
+
+/// An instance kept after handling a request, to handle later ones.
+pub(crate) struct KeptInstance<F: RuntimeFactors> {
+    pub instance: Instance,
+    pub store: Store<F>,
+    pub handler_type: HandlerType,
+    /// When the instance was created.
+    pub created: Instant,
+    /// How many requests the instance has handled.
+    pub requests: u64,
+}
+
+impl<F: RuntimeFactors> KeptInstance<F> {
+    /// An instance which has just handled its first request.
+    pub fn new(instance: Instance, store: Store<F>, handler_type: HandlerType) -> Self {
+        Self {
+            instance,
+            store,
+            handler_type,
+            created: Instant::now(),
+            requests: 1,
+        }
+    }
+}
+
+/// What a pool needs to know of the instances it keeps.
+pub(crate) trait Pooled {
+    /// When the instance was created.
+    fn created(&self) -> Instant;
+    /// How many requests the instance has handled.
+    fn requests(&self) -> u64;
+}
+
+impl<F: RuntimeFactors> Pooled for KeptInstance<F> {
+    fn created(&self) -> Instant {
+        self.created
+    }
+
+    fn requests(&self) -> u64 {
+        self.requests
+    }
+}
+
+/// Where an instance is kept between requests.
+pub(crate) enum Keeper<F: RuntimeFactors> {
+    /// The instance of a session.
+    Session(SessionGuard<KeptInstance<F>>),
+    /// The pool of a component with pooled instances.
+    Pool(Arc<InstancePool<KeptInstance<F>>>),
+}
+
+impl<F: RuntimeFactors> Keeper<F> {
+    /// Takes a kept instance to handle a request, if there is one.
+    ///
+    /// The instance holds no concurrency slots until it is given some with
+    /// `hold_concurrency_permits`.
+    pub fn take(&mut self) -> Option<KeptInstance<F>> {
+        match self {
+            Self::Session(session) => session.take(),
+            Self::Pool(pool) => pool.take(),
+        }
+    }
+
+    /// Keeps `instance`, which has handled a request, releasing its
+    /// concurrency slots while it is idle.
+    pub fn keep(self, mut instance: KeptInstance<F>) {
+        instance.store.data_mut().release_concurrency_permits();
+        match self {
+            Self::Session(session) => session.keep(instance),
+            Self::Pool(pool) => pool.put(instance),
+        }
+    }
+}
+
+/// The idle instances of a component with `lifetime = "pooled"`.
+///
+/// Idle instances don't count towards the component's concurrency limit; they
+/// wait for a slot like new instances when they are taken to handle a request.
+pub(crate) struct InstancePool<T> {
+    max_age: Option<Duration>,
+    max_requests: Option<u64>,
+    max_idle: usize,
+    idle: Mutex<Vec<T>>,
+}
+
+impl<T: Pooled> InstancePool<T> {
+    pub fn new(config: &InstanceConfig) -> Self {
+        Self {
+            max_age: config.max_age(),
+            max_requests: config.max_requests.map(Into::into),
+            max_idle: config.max_idle_instances.map_or(1, Into::into),
+            idle: Default::default(),
+        }
+    }
+
+    /// Takes the most recently used idle instance, if there is one.
+    pub fn take(&self) -> Option<T> {
+        let mut idle = self.idle.lock().unwrap();
+        while let Some(instance) = idle.pop() {
+            if !self.is_expired(&instance) {
+                return Some(instance);
+            }
+        }
+        None
+    }
+
+    /// Returns `instance` to the pool, unless it has reached its maximum age
+    /// or number of requests, or the pool is full.
+    pub fn put(&self, instance: T) {
+        if self.is_expired(&instance)
+            || self
+                .max_requests
+                .is_some_and(|max_requests| instance.requests() >= max_requests)
+        {
+            return;
+        }
+        let mut idle = self.idle.lock().unwrap();
+        if idle.len() < self.max_idle {
+            idle.push(instance);
+        }
+    }
+
+    fn is_expired(&self, instance: &T) -> bool {
+        self.max_age
+            .is_some_and(|max_age| instance.created().elapsed() >= max_age)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct TestInstance {
+        id: usize,
+        created: Instant,
+        requests: u64,
+    }
+
+    impl TestInstance {
+        fn new(id: usize, age: Duration, requests: u64) -> Self {
+            Self {
+                id,
+                created: Instant::now() - age,
+                requests,
+            }
+        }
+    }
+
+    impl Pooled for TestInstance {
+        fn created(&self) -> Instant {
+            self.created
+        }
+
+        fn requests(&self) -> u64 {
+            self.requests
+        }
+    }
+
+    fn test_pool(
+        max_age_secs: Option<u64>,
+        max_requests: Option<u64>,
+        max_idle_instances: Option<usize>,
+    ) -> InstancePool<TestInstance> {
+        InstancePool::new(&InstanceConfig {
+            lifetime: spin_app::InstanceLifetime::Pooled,
+            max_age_secs,
+            max_requests: max_requests.map(|n| n.try_into().unwrap()),
+            max_idle_instances: max_idle_instances.map(|n| n.try_into().unwrap()),
+        })
+    }
+
+    #[test]
+    fn instances_past_their_max_age_are_dropped() {
+        let pool = test_pool(Some(60), None, Some(2));
+        pool.put(TestInstance::new(1, Duration::from_secs(61), 1));
+        assert!(pool.take().is_none());
+
+        // Instances which expire while idle aren't taken.
+        pool.put(TestInstance::new(2, Duration::from_secs(59), 1));
+        pool.idle.lock().unwrap()[0].created -= Duration::from_secs(2);
+        assert!(pool.take().is_none());
+
+        pool.put(TestInstance::new(3, Duration::ZERO, 1));
+        assert_eq!(pool.take().map(|i| i.id), Some(3));
+    }
+
+    #[test]
+    fn instances_which_reach_max_requests_are_dropped() {
+        let pool = test_pool(None, Some(3), Some(2));
+        pool.put(TestInstance::new(1, Duration::ZERO, 2));
+        pool.put(TestInstance::new(2, Duration::ZERO, 3));
+        assert_eq!(pool.take().map(|i| i.id), Some(1));
+        assert!(pool.take().is_none());
+    }
+
+    #[test]
+    fn at_most_max_idle_instances_are_kept() {
+        let pool = test_pool(None, None, None);
+        pool.put(TestInstance::new(1, Duration::ZERO, 1));
+        pool.put(TestInstance::new(2, Duration::ZERO, 1));
+        assert_eq!(pool.take().map(|i| i.id), Some(1));
+        assert!(pool.take().is_none());
+
+        let pool = test_pool(None, None, Some(2));
+        for id in 1..=3 {
+            pool.put(TestInstance::new(id, Duration::ZERO, 1));
+        }
+        // The most recently used instance is taken first.
+        assert_eq!(pool.take().map(|i| i.id), Some(2));
+        assert_eq!(pool.take().map(|i| i.id), Some(1));
+        assert!(pool.take().is_none());
+    }
+}
@@ -23,6 +23,7 @@ macro_rules! http_span {
             "http.response.status_code" = ::tracing::field::Empty,
             "http.route" = ::tracing::field::Empty,
             "otel.name" = ::tracing::field::Empty,
+            "spin.request_id" = ::tracing::field::Empty,
         )
     };
 }
@@ -0,0 +1,487 @@
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use anyhow::Context;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use http::{header::AUTHORIZATION, Request};
+use ring::signature::{self, RsaPublicKeyComponents, UnparsedPublicKey, VerificationAlgorithm};
+use serde::{de::DeserializeOwned, Deserialize};
+use spin_http::config::JwtConfig;
+use tokio::sync::{Mutex, RwLock};
+
+/// The header a validated token's claims are passed to the component in, as
+/// the token's base64url-encoded JSON payload.
+pub(crate) const JWT_CLAIMS_HEADER: &str = "spin-jwt-claims";
+
+/// How long fetched keys are used before they are fetched again.
+const JWKS_TTL: Duration = Duration::from_secs(10 * 60);
+/// How often keys may be fetched early, for tokens signed with unknown keys.
+const MIN_JWKS_REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+/// How long each request made to fetch keys may take.
+const JWKS_FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+/// The clock skew allowed when checking `exp` and `nbf`, in seconds.
+const LEEWAY_SECS: f64 = 60.0;
+
+/// Why a request was not authenticated.
+#[derive(Debug)]
+pub(crate) enum AuthError {
+    /// The request has no bearer token.
+    Missing,
+    /// The request's bearer token is not valid.
+    Invalid(String),
+}
+
+/// Validates JWT bearer tokens according to a component's [`JwtConfig`].
+pub(crate) struct JwtValidator {
+    issuer: String,
+    audience: Option<String>,
+    jwks_url: Option<String>,
+    client: reqwest::Client,
+    keys: RwLock<KeyCache>,
+    /// Held while keys are fetched, so that one request fetches them while
+    /// others with known keys go on using them.
+    fetching: Mutex<()>,
+}
+
+#[derive(Default)]
+struct KeyCache {
+    keys: Vec<Jwk>,
+    fetched: Option<Instant>,
+}
+
+impl KeyCache {
+    fn find(&self, header: &JwtHeader) -> Option<Jwk> {
+        self.keys
+            .iter()
+            .find(|key| match &header.kid {
+                Some(kid) => key.kid.as_ref() == Some(kid),
+                None => key.alg.as_ref().map_or(true, |alg| *alg == header.alg),
+            })
+            .cloned()
+    }
+
+    fn is_stale(&self) -> bool {
+        self.fetched
+            .map_or(true, |fetched| fetched.elapsed() >= JWKS_TTL)
+    }
+
+    fn can_refresh_early(&self) -> bool {
+        self.fetched.map_or(true, |fetched| {
+            fetched.elapsed() >= MIN_JWKS_REFRESH_INTERVAL
+        })
+    }
+}
+
+impl JwtValidator {
+    pub fn new(config: &JwtConfig) -> Self {
+        Self {
+            issuer: config.issuer.clone(),
+            audience: config.audience.clone(),
+            jwks_url: config.jwks_url.clone(),
+            client: reqwest::Client::new(),
+            keys: Default::default(),
+            fetching: Default::default(),
+        }
+    }
+
+    /// Validates the request's bearer token, returning its claims as they
+    /// should be passed in the [`JWT_CLAIMS_HEADER`].
+    pub async fn authenticate<B>(&self, req: &Request<B>) -> Result<String, AuthError> {
+        let token = req
+            .headers()
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| {
+                let (scheme, token) = value.split_once(' ')?;
+                scheme.eq_ignore_ascii_case("bearer").then(|| token.trim())
+            })
+            .ok_or(AuthError::Missing)?;
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs_f64();
+        self.validate(token, now).await
+    }
+
+    async fn validate(&self, token: &str, now: f64) -> Result<String, AuthError> {
+        let invalid = |reason: &str| AuthError::Invalid(reason.to_owned());
+        let (signing_input, signature) = token
+            .rsplit_once('.')
+            .ok_or_else(|| invalid("malformed token"))?;
+        let (header, payload) = signing_input
+            .split_once('.')
+            .ok_or_else(|| invalid("malformed token"))?;
+        let header: JwtHeader = decode_json(header).ok_or_else(|| invalid("malformed header"))?;
+        let signature = URL_SAFE_NO_PAD
+            .decode(signature)
+            .map_err(|_| invalid("malformed signature"))?;
+
+        let key = self.key_for(&header).await?;
+        key.verify(&header.alg, signing_input.as_bytes(), &signature)
+            .map_err(|err| AuthError::Invalid(err.to_string()))?;
+
+        let claims: Claims = decode_json(payload).ok_or_else(|| invalid("malformed claims"))?;
+        if claims.iss.as_deref() != Some(self.issuer.as_str()) {
+            return Err(invalid("wrong issuer"));
+        }
+        if let Some(audience) = &self.audience {
+            if !claims.aud.contains(audience) {
+                return Err(invalid("wrong audience"));
+            }
+        }
+        match claims.exp {
+            Some(exp) if exp + LEEWAY_SECS > now => (),
+            Some(_) => return Err(invalid("token has expired")),
+            None => return Err(invalid("token has no expiry")),
+        }
+        if claims.nbf.is_some_and(|nbf| nbf - LEEWAY_SECS > now) {
+            return Err(invalid("token is not valid yet"));
+        }
+        Ok(payload.to_owned())
+    }
+
+    /// Finds the key for a token, fetching the issuer's keys when they are
+    /// stale or, at most every [`MIN_JWKS_REFRESH_INTERVAL`], when the token
+    /// was signed with an unknown key, e.g. after the issuer rotated its keys.
+    async fn key_for(&self, header: &JwtHeader) -> Result<Jwk, AuthError> {
+        {
+            let cache = self.keys.read().await;
+            if let (false, Some(key)) = (cache.is_stale(), cache.find(header)) {
+                return Ok(key);
+            }
+        }
+        let _fetching = self.fetching.lock().await;
+        {
+            let cache = self.keys.read().await;
+            let key = cache.find(header);
+            // Another request may have fetched the keys while this one waited.
+            let refresh = cache.is_stale() || (key.is_none() && cache.can_refresh_early());
+            if !refresh {
+                return key.ok_or_else(|| AuthError::Invalid("unknown signing key".into()));
+            }
+        }
+        // The keys are fetched without holding the cache's lock, so that
+        // requests with known keys aren't held up by a slow issuer.
+        let fetched = self.fetch_keys().await;
+        let mut cache = self.keys.write().await;
+        match fetched {
+            Ok(keys) => {
+                cache.keys = keys;
+                cache.fetched = Some(Instant::now());
+            }
+            // Keep using the keys we have until the issuer is reachable.
+            Err(err) => tracing::warn!(
+                "Failed to fetch JWT signing keys for {:?}: {err:#}",
+                self.issuer
+            ),
+        }
+        cache
+            .find(header)
+            .ok_or_else(|| AuthError::Invalid("unknown signing key".into()))
+    }
+
+    async fn fetch_keys(&self) -> anyhow::Result<Vec<Jwk>> {
+        #[derive(Deserialize)]
+        struct OpenIdConfiguration {
+            jwks_uri: String,
+        }
+        #[derive(Deserialize)]
+        struct JwkSet {
+            keys: Vec<Jwk>,
+        }
+
+        let jwks_url = match &self.jwks_url {
+            Some(url) => url.clone(),
+            None => {
+                let discovery_url = format!(
+                    "{}/.well-known/openid-configuration",
+                    self.issuer.trim_end_matches('/')
+                );
+                self.get_json::<OpenIdConfiguration>(&discovery_url)
+                    .await?
+                    .jwks_uri
+            }
+        };
+        Ok(self.get_json::<JwkSet>(&jwks_url).await?.keys)
+    }
+
+    async fn get_json<T: DeserializeOwned>(&self, url: &str) -> anyhow::Result<T> {
+        self.client
+            .get(url)
+            .timeout(JWKS_FETCH_TIMEOUT)
+            .send()
+            .await
+            .and_then(|resp| resp.error_for_status())
+            .with_context(|| format!("failed to fetch {url}"))?
+            .json()
+            .await
+            .with_context(|| format!("invalid response from {url}"))
+    }
+}
+
+fn decode_json<T: DeserializeOwned>(segment: &str) -> Option<T> {
+    let json = URL_SAFE_NO_PAD.decode(segment).ok()?;
+    serde_json::from_slice(&json).ok()
+}
+
+#[derive(Deserialize)]
+struct JwtHeader {
+    alg: String,
+    #[serde(default)]
+    kid: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Claims {
+    #[serde(default)]
+    iss: Option<String>,
+    #[serde(default)]
+    aud: Audience,
+    #[serde(default)]
+    exp: Option<f64>,
+    #[serde(default)]
+    nbf: Option<f64>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(untagged)]
+enum Audience {
+    #[default]
+    None,
+    One(String),
+    Many(Vec<String>),
+}
+
+impl Audience {
+    fn contains(&self, audience: &str) -> bool {
+        match self {
+            Audience::None => false,
+            Audience::One(aud) => aud == audience,
+            Audience::Many(auds) => auds.iter().any(|aud| aud == audience),
+        }
+    }
+}
+
+/// A JSON Web Key; only the fields needed to verify signatures.
+#[derive(Clone, Debug, Deserialize)]
+struct Jwk {
+    kty: String,
+    #[serde(default)]
+    kid: Option<String>,
+    #[serde(default)]
+    alg: Option<String>,
+    #[serde(default)]
+    crv: Option<String>,
+    #[serde(default)]
+    n: Option<String>,
+    #[serde(default)]
+    e: Option<String>,
+    #[serde(default)]
+    x: Option<String>,
+    #[serde(default)]
+    y: Option<String>,
+}
+
+impl Jwk {
+    fn verify(&self, alg: &str, message: &[u8], signature: &[u8]) -> anyhow::Result<()> {
+        let param = |value: &Option<String>, name: &str| -> anyhow::Result<Vec<u8>> {
+            let value = value
+                .as_deref()
+                .with_context(|| format!("signing key has no {name:?}"))?;
+            URL_SAFE_NO_PAD
+                .decode(value)
+                .with_context(|| format!("signing key has invalid {name:?}"))
+        };
+        let rsa = |algorithm: &signature::RsaParameters| -> anyhow::Result<()> {
+            anyhow::ensure!(self.kty == "RSA", "signing key is not an RSA key");
+            let n = param(&self.n, "n")?;
+            let e = param(&self.e, "e")?;
+            RsaPublicKeyComponents { n: &n, e: &e }
+                .verify(algorithm, message, signature)
+                .map_err(|_| anyhow::anyhow!("invalid signature"))
+        };
+        let ec =
+            |curve: &str, algorithm: &'static dyn VerificationAlgorithm| -> anyhow::Result<()> {
+                anyhow::ensure!(
+                    self.kty == "EC" && self.crv.as_deref() == Some(curve),
+                    "signing key is not a {curve} key"
+                );
+                let mut point = vec![0x04];
+                point.extend(param(&self.x, "x")?);
+                point.extend(param(&self.y, "y")?);
+                verify_with(algorithm, &point, message, signature)
+            };
+        match alg {
+            "RS256" => rsa(&signature::RSA_PKCS1_2048_8192_SHA256),
+            "RS384" => rsa(&signature::RSA_PKCS1_2048_8192_SHA384),
+            "RS512" => rsa(&signature::RSA_PKCS1_2048_8192_SHA512),
+            "PS256" => rsa(&signature::RSA_PSS_2048_8192_SHA256),
+            "PS384" => rsa(&signature::RSA_PSS_2048_8192_SHA384),
+            "PS512" => rsa(&signature::RSA_PSS_2048_8192_SHA512),
+            "ES256" => ec("P-256", &signature::ECDSA_P256_SHA256_FIXED),
+            "ES384" => ec("P-384", &signature::ECDSA_P384_SHA384_FIXED),
+            "EdDSA" => {
+                anyhow::ensure!(
+                    self.kty == "OKP" && self.crv.as_deref() == Some("Ed25519"),
+                    "signing key is not an Ed25519 key"
+                );
+                verify_with(
+                    &signature::ED25519,
+                    &param(&self.x, "x")?,
+                    message,
+                    signature,
+                )
+            }
+            // Notably `none` and the HMAC algorithms, which would need the
+            // host to share a secret with the issuer.
+            _ => anyhow::bail!("unsupported algorithm {alg:?}"),
+        }
+    }
+}
+
+fn verify_with(
+    algorithm: &'static dyn VerificationAlgorithm,
+    public_key: &[u8],
+    message: &[u8],
+    signature: &[u8],
+) -> anyhow::Result<()> {
+    UnparsedPublicKey::new(algorithm, public_key)
+        .verify(message, signature)
+        .map_err(|_| anyhow::anyhow!("invalid signature"))
+}
+
+#[cfg(test)]
+mod tests {
+    use ring::{
+        rand::SystemRandom,
+        signature::{Ed25519KeyPair, KeyPair},
+    };
+    use serde_json::json;
+
+    use super::*;
+
+    const NOW: f64 = 1_700_000_000.0;
+
+    struct Issuer(Ed25519KeyPair);
+
+    impl Issuer {
+        fn new() -> Self {
+            let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+            Self(Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap())
+        }
+
+        fn validator(&self) -> JwtValidator {
+            let validator = JwtValidator::new(&JwtConfig {
+                issuer: "https://issuer.example".into(),
+                audience: Some("my-app".into()),
+                jwks_url: None,
+            });
+            let jwk = Jwk {
+                kty: "OKP".into(),
+                kid: Some("key-1".into()),
+                alg: None,
+                crv: Some("Ed25519".into()),
+                n: None,
+                e: None,
+                x: Some(URL_SAFE_NO_PAD.encode(self.0.public_key())),
+                y: None,
+            };
+            *validator.keys.try_write().unwrap() = KeyCache {
+                keys: vec![jwk],
+                fetched: Some(Instant::now()),
+            };
+            validator
+        }
+
+        fn token(&self, header: serde_json::Value, claims: serde_json::Value) -> String {
+            let encode = |value: serde_json::Value| URL_SAFE_NO_PAD.encode(value.to_string());
+            let signing_input = format!("{}.{}", encode(header), encode(claims));
+            let signature = self.0.sign(signing_input.as_bytes());
+            format!(
+                "{signing_input}.{}",
+                URL_SAFE_NO_PAD.encode(signature.as_ref())
+            )
+        }
+
+        fn valid_token(&self, claims: serde_json::Value) -> String {
+            self.token(json!({ "alg": "EdDSA", "kid": "key-1" }), claims)
+        }
+    }
+
+    fn claims() -> serde_json::Value {
+        json!({
+            "iss": "https://issuer.example",
+            "aud": ["other-app", "my-app"],
+            "sub": "alice",
+            "exp": NOW + 300.0,
+        })
+    }
+
+    #[tokio::test]
+    async fn valid_tokens_pass_their_claims() {
+        let issuer = Issuer::new();
+        let token = issuer.valid_token(claims());
+        let payload = issuer.validator().validate(&token, NOW).await.unwrap();
+        let claims: serde_json::Value = decode_json(&payload).unwrap();
+        assert_eq!(claims["sub"], "alice");
+    }
+
+    #[tokio::test]
+    async fn known_keys_are_used_while_keys_are_fetched() {
+        let issuer = Issuer::new();
+        let validator = issuer.validator();
+        let _fetching = validator.fetching.lock().await;
+        let token = issuer.valid_token(claims());
+        let validated =
+            tokio::time::timeout(Duration::from_secs(5), validator.validate(&token, NOW)).await;
+        assert!(validated
+            .expect("validation shouldn't wait for a fetch")
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn invalid_tokens_are_rejected() {
+        let issuer = Issuer::new();
+        let validator = issuer.validator();
+
+        let mut wrong_issuer = claims();
+        wrong_issuer["iss"] = "https://attacker.example".into();
+        let mut wrong_audience = claims();
+        wrong_audience["aud"] = "other-app".into();
+        let mut expired = claims();
+        expired["exp"] = (NOW - 3600.0).into();
+
+        let forged = Issuer::new().valid_token(claims());
+        let unsigned = format!(
+            "{}.{}.",
+            URL_SAFE_NO_PAD.encode(json!({ "alg": "none" }).to_string()),
+            URL_SAFE_NO_PAD.encode(claims().to_string()),
+        );
+        let unknown_key = issuer.token(json!({ "alg": "EdDSA", "kid": "key-2" }), claims());
+
+        for token in [
+            issuer.valid_token(wrong_issuer),
+            issuer.valid_token(wrong_audience),
+            issuer.valid_token(expired),
+            forged,
+            unsigned,
+            // Fetching keys for an unknown key is rate limited, so this
+            // doesn't go to the network.
+            unknown_key,
+            "not-a-token".into(),
+        ] {
+            let result = validator.validate(&token, NOW).await;
+            assert!(matches!(result, Err(AuthError::Invalid(_))), "{token}");
+        }
+    }
+
+    #[tokio::test]
+    async fn requests_without_bearer_tokens_are_rejected() {
+        let validator = Issuer::new().validator();
+        let req = Request::get("/")
+            .header(AUTHORIZATION, "Basic dXNlcjpwYXNz")
+            .body(())
+            .unwrap();
+        let result = validator.authenticate(&req).await;
+        assert!(matches!(result, Err(AuthError::Missing)));
+    }
+}
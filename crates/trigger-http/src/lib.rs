@@ -1,32 +1,56 @@
 //! Implementation for the Spin HTTP engine.
 
+mod access_log;
+mod admin;
+mod canary;
+mod client_cert;
+mod early_hints;
+mod header_rules;
 mod headers;
+mod idempotency;
+mod instance_pool;
 mod instrument;
+mod jwt;
+mod mirror;
 mod outbound_http;
+mod probes;
+mod rate_limit;
+mod request_id;
 mod server;
+mod session_affinity;
 mod spin;
 mod tls;
 mod wagi;
 mod wasi;
 
 use std::{
+    collections::HashMap,
     error::Error,
     net::{Ipv4Addr, SocketAddr, ToSocketAddrs},
-    path::PathBuf,
+    path::{Path, PathBuf},
     sync::Arc,
 };
 
 use anyhow::{bail, Context};
 use clap::Args;
-use serde::Deserialize;
+use http::HeaderName;
+use serde::{de::IgnoredAny, Deserialize};
 use spin_app::App;
 use spin_factors::RuntimeFactors;
-use spin_trigger::Trigger;
+use spin_trigger::{saturation::SaturationTracker, Trigger};
 use wasmtime_wasi_http::bindings::http::types::ErrorCode;
 
+use access_log::AccessLog;
+use admin::AdminApi;
+use canary::CanaryConfig;
+use mirror::MirrorConfig;
+
+pub use access_log::AccessLogFormat;
+pub use probes::ProbePaths;
+pub use request_id::{RequestId, RequestIdConfig, DEFAULT_REQUEST_ID_HEADER};
 pub use server::HttpServer;
 
-pub use tls::TlsConfig;
+pub use tls::{ClientAuth, TlsConfig};
 
 pub(crate) use wasmtime_wasi_http::body::HyperIncomingBody as Body;
 
@@ -37,6 +61,22 @@ pub(crate) type TriggerApp<F> = spin_trigger::TriggerApp<HttpTrigger, F>;
 pub(crate) type TriggerInstanceBuilder<'a, F> =
     spin_trigger::TriggerInstanceBuilder<'a, HttpTrigger, F>;
 
+/// A [`spin_trigger::Store`] for the HTTP trigger.
+pub(crate) type Store<F> = spin_trigger::Store<HttpTrigger, F>;
+
+/// Details of a request for correlating core dumps of its handler with it.
+pub(crate) fn core_dump_event(
+    route_match: &spin_http::routes::RouteMatch,
+    method: &http::Method,
+    uri: &http::Uri,
+) -> [(&'static str, String); 3] {
+    [
+        ("route", route_match.raw_route().to_string()),
+        ("method", method.to_string()),
+        ("path", uri.path().to_string()),
+    ]
+}
+
 #[derive(Args)]
 pub struct CliArgs {
     /// IP address and port to listen on
@@ -50,14 +90,84 @@ pub struct CliArgs {
     /// The path to the certificate key to use for https, if this is not set, normal http will be used. The key should be in PKCS#8 format
     #[clap(long, env = "SPIN_TLS_KEY", requires = "tls-cert")]
     pub tls_key: Option<PathBuf>,
+
+    /// The header each request's ID is returned in on responses and sent in
+    /// on the outbound requests made while handling it.
+    #[clap(long, env = "SPIN_HTTP_REQUEST_ID_HEADER", default_value = DEFAULT_REQUEST_ID_HEADER)]
+    pub request_id_header: HeaderName,
+
+    /// Use the request ID in an incoming request's request ID header, if it
+    /// has one, instead of generating a new one. Only enable this behind a
+    /// proxy that sets the header.
+    #[clap(long, env = "SPIN_HTTP_TRUST_REQUEST_ID")]
+    pub trust_request_id: bool,
+
+    /// Serve an admin API for browsing, exporting and importing the app's
+    /// key-value stores and SQLite databases under /.well-known/spin/admin/,
+    /// to requests with this bearer token.
+    #[clap(long, env = "SPIN_HTTP_ADMIN_TOKEN", hide_env_values = true)]
+    pub admin_token: Option<String>,
+
+    /// Write a line for each request to this access log file, or to stdout
+    /// if "-".
+    #[clap(long, env = "SPIN_HTTP_ACCESS_LOG")]
+    pub access_log: Option<PathBuf>,
+
+    /// The format of access log lines.
+    #[clap(
+        long,
+        env = "SPIN_HTTP_ACCESS_LOG_FORMAT",
+        value_enum,
+        default_value = "common",
+        requires = "access-log"
+    )]
+    pub access_log_format: AccessLogFormat,
+
+    /// Serve a liveness probe at this path, ahead of the app's routes.
+    /// Defaults to /healthz if the flag is given without a path.
+    #[clap(
+        long,
+        env = "SPIN_HTTP_HEALTH_PATH",
+        num_args = 0..=1,
+        default_missing_value = "/healthz",
+        value_parser = parse_probe_path
+    )]
+    pub health_path: Option<String>,
+
+    /// Serve a readiness probe, reporting as JSON whether each component is
+    /// at its concurrency limit, at this path, ahead of the app's routes.
+    /// Defaults to /readyz if the flag is given without a path.
+    #[clap(
+        long,
+        env = "SPIN_HTTP_READY_PATH",
+        num_args = 0..=1,
+        default_missing_value = "/readyz",
+        value_parser = parse_probe_path
+    )]
+    pub ready_path: Option<String>,
 }
 
 impl CliArgs {
+    fn probe_paths(&self) -> ProbePaths {
+        ProbePaths {
+            health: self.health_path.clone(),
+            ready: self.ready_path.clone(),
+        }
+    }
+
+    fn request_id_config(&self) -> RequestIdConfig {
+        RequestIdConfig {
+            header: self.request_id_header.clone(),
+            trust_incoming: self.trust_request_id,
+        }
+    }
+
     fn into_tls_config(self) -> Option<TlsConfig> {
         match (self.tls_cert, self.tls_key) {
             (Some(cert_path), Some(key_path)) => Some(TlsConfig {
                 cert_path,
                 key_path,
+                client_auth: None,
             }),
             (None, None) => None,
             _ => unreachable!(),
@@ -73,6 +183,15 @@ pub struct HttpTrigger {
     /// If the port is set to 0, the actual address will be determined by the OS.
     listen_addr: SocketAddr,
     tls_config: Option<TlsConfig>,
+    request_ids: RequestIdConfig,
+    saturation: SaturationTracker,
+    admin_token: Option<String>,
+    access_log: Option<Arc<AccessLog>>,
+    probes: ProbePaths,
+    // Component ID -> canary
+    canaries: HashMap<String, CanaryConfig>,
+    // Component ID -> mirror
+    mirrors: HashMap<String, MirrorConfig>,
 }
 
 impl<F: RuntimeFactors> Trigger<F> for HttpTrigger {
@@ -82,7 +201,35 @@ impl<F: RuntimeFactors> Trigger<F> for HttpTrigger {
     type InstanceState = ();
 
     fn new(cli_args: Self::CliArgs, app: &spin_app::App) -> anyhow::Result<Self> {
-        Self::new(app, cli_args.address, cli_args.into_tls_config())
+        let request_ids = cli_args.request_id_config();
+        let admin_token = cli_args.admin_token.clone();
+        let access_log = cli_args.access_log.clone();
+        let access_log_format = cli_args.access_log_format;
+        let probes = cli_args.probe_paths();
+        let trigger = Self::new(app, cli_args.address, cli_args.into_tls_config())?
+            .with_request_id_config(request_ids)
+            .with_admin_token(admin_token)
+            .with_probes(probes);
+        match access_log {
+            Some(path) => trigger.with_access_log(&path, access_log_format),
+            None => Ok(trigger),
+        }
+    }
+
+    fn update_from_runtime_config(
+        &mut self,
+        runtime_config: &toml::Table,
+        runtime_config_dir: Option<&Path>,
+    ) -> anyhow::Result<()> {
+        let Some(http_server) = runtime_config.get("http_server") else {
+            return Ok(());
+        };
+        let mut http_server = tls::HttpServerRuntimeConfig::deserialize(http_server.clone())
+            .context("invalid [http_server] runtime config")?;
+        self.canaries = std::mem::take(&mut http_server.canaries);
+        self.mirrors = std::mem::take(&mut http_server.mirrors);
+        self.tls_config = http_server.apply(self.tls_config.take(), runtime_config_dir)?;
+        Ok(())
     }
 
     async fn run(self, trigger_app: TriggerApp<F>) -> anyhow::Result<()> {
@@ -93,6 +240,35 @@ impl<F: RuntimeFactors> Trigger<F> for HttpTrigger {
         Ok(())
     }
 
+    async fn run_reconfigurable(
+        self,
+        trigger_app: TriggerApp<F>,
+        mut reconfigured: tokio::sync::mpsc::UnboundedReceiver<TriggerApp<F>>,
+    ) -> anyhow::Result<()> {
+        let server = self.into_server(trigger_app)?;
+
+        let serve = server.clone().serve();
+        tokio::pin!(serve);
+        loop {
+            tokio::select! {
+                result = &mut serve => return result,
+                Some(trigger_app) = reconfigured.recv() => {
+                    match server.reconfigure(trigger_app) {
+                        Ok(()) => terminal::step!("Reconfigured", "HTTP routes"),
+                        Err(err) => {
+                            tracing::error!("Failed to reconfigure HTTP routes: {err:?}");
+                            terminal::error!("Couldn't apply changes to the app's HTTP triggers: {err:#}");
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn lifecycle_hook_instance_state() -> Option<Self::InstanceState> {
+        Some(())
+    }
+
     fn supported_host_requirements() -> Vec<&'static str> {
         vec![spin_app::locked::SERVICE_CHAINING_KEY]
     }
@@ -110,9 +286,47 @@ impl HttpTrigger {
         Ok(Self {
             listen_addr,
             tls_config,
+            request_ids: RequestIdConfig::default(),
+            saturation: SaturationTracker::new(),
+            admin_token: None,
+            access_log: None,
+            probes: ProbePaths::default(),
+            canaries: HashMap::new(),
+            mirrors: HashMap::new(),
         })
     }
 
+    /// Sets how request IDs are assigned and propagated.
+    pub fn with_request_id_config(mut self, request_ids: RequestIdConfig) -> Self {
+        self.request_ids = request_ids;
+        self
+    }
+
+    /// Serves the admin API to requests with the given bearer token, or
+    /// doesn't serve it if `None`.
+    pub fn with_admin_token(mut self, admin_token: Option<String>) -> Self {
+        self.admin_token = admin_token;
+        self
+    }
+
+    /// Writes a line for each request to the access log file at `path`, or to
+    /// stdout if `path` is `-`.
+    pub fn with_access_log(mut self, path: &Path, format: AccessLogFormat) -> anyhow::Result<Self> {
+        self.access_log = Some(Arc::new(AccessLog::open(path, format)?));
+        Ok(self)
+    }
+
+    /// Serves liveness and readiness probes at the given paths.
+    pub fn with_probes(mut self, probes: ProbePaths) -> Self {
+        self.probes = probes;
+        self
+    }
+
+    /// The [`SaturationTracker`] for requests handled by this trigger.
+    pub fn saturation(&self) -> &SaturationTracker {
+        &self.saturation
+    }
+
     /// Turn this [`HttpTrigger`] into an [`HttpServer`].
     pub fn into_server<F: RuntimeFactors>(
         self,
@@ -121,8 +335,30 @@ impl HttpTrigger {
         let Self {
             listen_addr,
             tls_config,
+            request_ids,
+            saturation,
+            admin_token,
+            access_log,
+            probes,
+            canaries,
+            mirrors,
         } = self;
-        let server = Arc::new(HttpServer::new(listen_addr, tls_config, trigger_app)?);
+        let mut server = HttpServer::new(listen_addr, tls_config, trigger_app)?;
+        server.request_ids = request_ids;
+        server.saturation = saturation;
+        server.admin = admin_token.map(AdminApi::new);
+        server.access_log = access_log;
+        server.set_probes(probes);
+        server.set_mirrors(mirrors)?;
+        for (component_id, canary) in canaries {
+            server
+                .traffic_splits
+                .set(&component_id, canary)
+                .with_context(|| {
+                    format!("invalid [http_server.canaries.{component_id}] runtime config")
+                })?;
+        }
+        let server = Arc::new(server);
         Ok(server)
     }
 
@@ -131,8 +367,14 @@ impl HttpTrigger {
         #[serde(deny_unknown_fields)]
         struct TriggerMetadata {
             base: Option<String>,
+            // Applied by the executor; see `App::trigger_concurrency_limit`.
+            #[serde(rename = "concurrency")]
+            _concurrency: Option<IgnoredAny>,
         }
-        if let Some(TriggerMetadata { base: Some(base) }) = app.get_trigger_metadata("http")? {
+        if let Some(TriggerMetadata {
+            base: Some(base), ..
+        }) = app.get_trigger_metadata("http")?
+        {
             if base == "/" {
                 tracing::warn!("This application has the deprecated trigger 'base' set to the default value '/'. This may be an error in the future!");
             } else {
@@ -156,6 +398,16 @@ fn parse_listen_addr(addr: &str) -> anyhow::Result<SocketAddr> {
     addrs.into_iter().next().context("couldn't resolve address")
 }
 
+fn parse_probe_path(path: &str) -> anyhow::Result<String> {
+    anyhow::ensure!(path.starts_with('/'), "probe path must start with '/'");
+    anyhow::ensure!(
+        !path.starts_with(spin_http::WELL_KNOWN_PREFIX),
+        "probe path must not be under {}",
+        spin_http::WELL_KNOWN_PREFIX
+    );
+    Ok(path.to_owned())
+}
+
 #[derive(Debug, PartialEq)]
 enum NotFoundRouteKind {
     Normal(String),
@@ -193,4 +445,11 @@ mod tests {
         assert_eq!(addr.ip(), Ipv4Addr::LOCALHOST);
         assert_eq!(addr.port(), 12345);
     }
+
+    #[test]
+    fn probe_paths_are_validated() {
+        assert_eq!(parse_probe_path("/healthz").unwrap(), "/healthz");
+        assert!(parse_probe_path("healthz").is_err());
+        assert!(parse_probe_path("/.well-known/spin/health").is_err());
+    }
 }
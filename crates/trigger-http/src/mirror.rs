@@ -0,0 +1,353 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::Context;
+use http::{header::HOST, Request};
+use http_body_util::BodyExt;
+use hyper::body::{Body as HttpBody, Bytes};
+use rand::Rng;
+use serde::Deserialize;
+use spin_http::body;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::Body;
+
+/// The largest request body mirrored by default.
+const DEFAULT_MAX_BODY_BYTES: u64 = 1024 * 1024;
+
+/// How long a request mirrored to a URL may take.
+const URL_MIRROR_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How many mirrored requests may be handled at once. Requests which would be
+/// mirrored while this many are being handled aren't mirrored.
+const MAX_MIRRORS_IN_FLIGHT: usize = 32;
+
+/// Mirrors a share of the requests routed to a component to another component,
+/// or to another server such as one running a new version of the app, as set
+/// by `[http_server.mirrors.<component>]` in the runtime config.
+///
+/// Mirrored requests are handled after, and independently of, the original
+/// request, and their responses are discarded, but their outcomes are logged
+/// and counted by the `spin.mirrored_request_count` metric. Mirrors don't take
+/// slots within components' concurrency limits, but at most
+/// [`MAX_MIRRORS_IN_FLIGHT`] mirrored requests are handled at once. A mirror's side
+/// effects, such as writes to key-value stores or outbound requests, are not
+/// isolated, so mirrors should use their own stores and hosts.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct MirrorConfig {
+    /// The component to mirror requests to, with the routed component's
+    /// trigger config.
+    #[serde(default)]
+    pub component: Option<String>,
+    /// The base URL of a server to mirror requests to. The request's path and
+    /// query are appended to it.
+    #[serde(default)]
+    pub url: Option<String>,
+    /// The percentage of requests to mirror, from 0 to 100.
+    pub percent: f64,
+    /// Requests with larger bodies, or bodies of unknown length, aren't
+    /// mirrored.
+    #[serde(default = "default_max_body_bytes")]
+    pub max_body_bytes: u64,
+}
+
+fn default_max_body_bytes() -> u64 {
+    DEFAULT_MAX_BODY_BYTES
+}
+
+/// The mirrors of the components routed by the HTTP trigger.
+pub(crate) struct Mirrors {
+    // Component ID -> mirror
+    mirrors: HashMap<String, Mirror>,
+    client: reqwest::Client,
+    in_flight: Arc<Semaphore>,
+}
+
+impl Default for Mirrors {
+    fn default() -> Self {
+        Self {
+            mirrors: Default::default(),
+            client: Default::default(),
+            in_flight: Arc::new(Semaphore::new(MAX_MIRRORS_IN_FLIGHT)),
+        }
+    }
+}
+
+struct Mirror {
+    target: MirrorTarget,
+    percent: f64,
+    max_body_bytes: u64,
+}
+
+/// Where a request is mirrored to.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum MirrorTarget {
+    Component(String),
+    Url(reqwest::Url),
+}
+
+impl std::fmt::Display for MirrorTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Component(component_id) => write!(f, "component {component_id:?}"),
+            Self::Url(url) => write!(f, "{url}"),
+        }
+    }
+}
+
+/// A copy of a request, to be handled by a mirror.
+pub(crate) struct MirroredRequest {
+    pub target: MirrorTarget,
+    pub req: Request<Bytes>,
+    /// Held until the mirrored request has been handled.
+    pub in_flight: OwnedSemaphorePermit,
+}
+
+impl Mirrors {
+    /// Validates the mirrors of `routed` components, which may mirror to any
+    /// of the app's `components`.
+    pub fn new(
+        configs: HashMap<String, MirrorConfig>,
+        routed: &HashSet<&str>,
+        components: &HashSet<&str>,
+    ) -> anyhow::Result<Self> {
+        let mirrors = configs
+            .into_iter()
+            .map(|(component_id, config)| {
+                let mirror =
+                    Mirror::new(&component_id, config, routed, components).with_context(|| {
+                        format!("invalid [http_server.mirrors.{component_id}] runtime config")
+                    })?;
+                tracing::info!(
+                    "Mirroring {}% of requests for component {component_id:?} to {}",
+                    mirror.percent,
+                    mirror.target
+                );
+                Ok((component_id, mirror))
+            })
+            .collect::<anyhow::Result<_>>()?;
+        let client = reqwest::Client::builder()
+            .timeout(URL_MIRROR_TIMEOUT)
+            .build()?;
+        Ok(Self {
+            mirrors,
+            client,
+            ..Default::default()
+        })
+    }
+
+    /// Chooses whether to mirror a request routed to `component_id`.
+    ///
+    /// A mirrored request's body is read up front, so that it can be copied.
+    /// Returns the request, to be handled as usual, and its copy if it is
+    /// mirrored.
+    pub async fn copy(
+        &self,
+        component_id: &str,
+        req: Request<Body>,
+    ) -> anyhow::Result<(Request<Body>, Option<MirroredRequest>)> {
+        let Some(mirror) = self.mirrors.get(component_id) else {
+            return Ok((req, None));
+        };
+        if rand::thread_rng().gen_range(0.0..100.0) >= mirror.percent {
+            return Ok((req, None));
+        }
+        let body_len = req.body().size_hint().upper();
+        if !body_len.is_some_and(|len| len <= mirror.max_body_bytes) {
+            tracing::debug!("Not mirroring request with a body of unknown or excessive length");
+            return Ok((req, None));
+        }
+        let Ok(in_flight) = self.in_flight.clone().try_acquire_owned() else {
+            tracing::debug!("Not mirroring request as too many mirrored requests are in flight");
+            return Ok((req, None));
+        };
+
+        let (parts, req_body) = req.into_parts();
+        let bytes = req_body.collect().await?.to_bytes();
+        let mut copy = Request::builder()
+            .method(parts.method.clone())
+            .uri(parts.uri.clone())
+            .version(parts.version)
+            .body(bytes.clone())?;
+        *copy.headers_mut() = parts.headers.clone();
+        let mirrored = MirroredRequest {
+            target: mirror.target.clone(),
+            req: copy,
+            in_flight,
+        };
+        Ok((
+            Request::from_parts(parts, body::full(bytes)),
+            Some(mirrored),
+        ))
+    }
+
+    /// Sends a request mirrored to a URL, returning the response status.
+    pub async fn send(&self, base: &reqwest::Url, req: Request<Bytes>) -> anyhow::Result<u16> {
+        let (parts, bytes) = req.into_parts();
+        let path_and_query = parts
+            .uri
+            .path_and_query()
+            .map(|path_and_query| path_and_query.as_str())
+            .unwrap_or("/");
+        let url = base.join(path_and_query.trim_start_matches('/'))?;
+        let mut headers = parts.headers;
+        headers.remove(HOST);
+        let res = self
+            .client
+            .request(parts.method, url)
+            .headers(headers)
+            .body(bytes)
+            .send()
+            .await?;
+        let status = res.status().as_u16();
+        // Read the response so that the connection can be reused.
+        res.bytes().await?;
+        Ok(status)
+    }
+}
+
+impl Mirror {
+    fn new(
+        component_id: &str,
+        config: MirrorConfig,
+        routed: &HashSet<&str>,
+        components: &HashSet<&str>,
+    ) -> anyhow::Result<Self> {
+        anyhow::ensure!(
+            routed.contains(component_id),
+            "component {component_id:?} has no HTTP route to mirror"
+        );
+        anyhow::ensure!(
+            (0.0..=100.0).contains(&config.percent),
+            "mirror percent must be from 0 to 100, not {}",
+            config.percent
+        );
+        let target = match (config.component, config.url) {
+            (Some(mirror_id), None) => {
+                anyhow::ensure!(
+                    components.contains(mirror_id.as_str()),
+                    "mirror component {mirror_id:?} is not in the app"
+                );
+                anyhow::ensure!(
+                    mirror_id != component_id,
+                    "component {component_id:?} can't be its own mirror"
+                );
+                MirrorTarget::Component(mirror_id)
+            }
+            (None, Some(url)) => {
+                let mut url = reqwest::Url::parse(&url)
+                    .with_context(|| format!("invalid mirror URL {url:?}"))?;
+                anyhow::ensure!(
+                    matches!(url.scheme(), "http" | "https"),
+                    "mirror URL {url} must be http or https"
+                );
+                // Treat the URL as a base, to which request paths are appended.
+                if !url.path().ends_with('/') {
+                    url.set_path(&format!("{}/", url.path()));
+                }
+                MirrorTarget::Url(url)
+            }
+            _ => anyhow::bail!("a mirror must set one of `component` or `url`"),
+        };
+        Ok(Self {
+            target,
+            percent: config.percent,
+            max_body_bytes: config.max_body_bytes,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mirrors(config: &str) -> anyhow::Result<Mirrors> {
+        let config: MirrorConfig = toml::from_str(config)?;
+        Mirrors::new(
+            [("api".to_string(), config)].into(),
+            &["api"].into(),
+            &["api", "api-v2"].into(),
+        )
+    }
+
+    fn request(body: &'static str) -> Request<Body> {
+        Request::post("http://localhost/api/items?x=1")
+            .header("content-type", "text/plain")
+            .body(body::full(Bytes::from_static(body.as_bytes())))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn requests_are_copied() {
+        let mirrors = mirrors("component = \"api-v2\"\npercent = 100").unwrap();
+        let (req, mirrored) = mirrors.copy("api", request("hello")).await.unwrap();
+        let mirrored = mirrored.expect("request should be mirrored");
+        assert_eq!(MirrorTarget::Component("api-v2".into()), mirrored.target);
+        assert_eq!(req.uri(), mirrored.req.uri());
+        assert_eq!("text/plain", mirrored.req.headers()["content-type"]);
+        assert_eq!("hello", mirrored.req.body());
+        let body = req.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!("hello", body);
+
+        let (_, mirrored) = mirrors.copy("api-v2", request("hello")).await.unwrap();
+        assert!(mirrored.is_none());
+    }
+
+    #[tokio::test]
+    async fn percent_and_body_size_limit_mirroring() {
+        let mirrors = mirrors("component = \"api-v2\"\npercent = 0").unwrap();
+        let (_, mirrored) = mirrors.copy("api", request("hello")).await.unwrap();
+        assert!(mirrored.is_none());
+
+        let mirrors = mirrors("component = \"api-v2\"\npercent = 100\nmax_body_bytes = 4").unwrap();
+        let (req, mirrored) = mirrors.copy("api", request("hello")).await.unwrap();
+        assert!(mirrored.is_none());
+        let body = req.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!("hello", body);
+    }
+
+    #[tokio::test]
+    async fn mirrors_in_flight_are_bounded() {
+        let mirrors = mirrors("component = \"api-v2\"\npercent = 100").unwrap();
+        let mut in_flight = vec![];
+        for _ in 0..MAX_MIRRORS_IN_FLIGHT {
+            let (_, mirrored) = mirrors.copy("api", request("hello")).await.unwrap();
+            in_flight.push(mirrored.expect("request should be mirrored"));
+        }
+        let (_, mirrored) = mirrors.copy("api", request("hello")).await.unwrap();
+        assert!(mirrored.is_none());
+
+        in_flight.pop();
+        let (_, mirrored) = mirrors.copy("api", request("hello")).await.unwrap();
+        assert!(mirrored.is_some());
+    }
+
+    #[test]
+    fn invalid_mirrors_are_rejected() {
+        assert!(mirrors("component = \"api-v3\"\npercent = 10").is_err());
+        assert!(mirrors("component = \"api\"\npercent = 10").is_err());
+        assert!(mirrors("component = \"api-v2\"\npercent = 101").is_err());
+        assert!(mirrors("percent = 10").is_err());
+        assert!(mirrors("url = \"ftp://example.com\"\npercent = 10").is_err());
+        assert!(
+            mirrors("component = \"api-v2\"\nurl = \"http://localhost:3001\"\npercent = 10")
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn url_mirrors_are_bases() {
+        let mirrors = mirrors("url = \"http://localhost:3001/v2\"\npercent = 10").unwrap();
+        let MirrorTarget::Url(url) = &mirrors.mirrors["api"].target else {
+            panic!("expected a URL mirror");
+        };
+        assert_eq!(
+            "http://localhost:3001/v2/api/items?x=1",
+            url.join("api/items?x=1").unwrap().as_str()
+        );
+    }
+}
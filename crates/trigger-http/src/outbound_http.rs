@@ -11,16 +11,18 @@ use spin_factors::RuntimeFactors;
 use spin_http::routes::RouteMatch;
 use wasmtime_wasi_http::{HttpError, HttpResult};
 
-use crate::HttpServer;
+use crate::{HttpServer, RequestId};
 
-/// An outbound HTTP interceptor that handles service chaining requests.
+/// An outbound HTTP interceptor that handles service chaining requests and
+/// propagates the ID of the request being handled.
 pub struct OutboundHttpInterceptor<F: RuntimeFactors> {
     server: Arc<HttpServer<F>>,
+    request_id: RequestId,
 }
 
 impl<F: RuntimeFactors> OutboundHttpInterceptor<F> {
-    pub fn new(server: Arc<HttpServer<F>>) -> Self {
-        Self { server }
+    pub fn new(server: Arc<HttpServer<F>>, request_id: RequestId) -> Self {
+        Self { server, request_id }
     }
 }
 
@@ -28,10 +30,11 @@ const CHAINED_CLIENT_ADDR: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new
 
 #[async_trait]
 impl<F: RuntimeFactors> intercept::OutboundHttpInterceptor for OutboundHttpInterceptor<F> {
-    async fn intercept(&self, request: InterceptRequest) -> HttpResult<InterceptOutcome> {
+    async fn intercept(&self, mut request: InterceptRequest) -> HttpResult<InterceptOutcome> {
         // Handle service chaining requests
         if let Some(component_id) = parse_service_chaining_target(request.uri()) {
-            let req = request.into_hyper_request();
+            let mut req = request.into_hyper_request();
+            req.extensions_mut().insert(self.request_id.clone());
             let route_match = RouteMatch::synthetic(&component_id, req.uri().path());
             let resp = self
                 .server
@@ -40,6 +43,11 @@ impl<F: RuntimeFactors> intercept::OutboundHttpInterceptor for OutboundHttpInter
                 .map_err(HttpError::trap)?;
             Ok(InterceptOutcome::Complete(resp))
         } else {
+            let header = &self.server.request_ids.header;
+            if !request.headers().contains_key(header) {
+                let value = self.request_id.to_header_value();
+                request.headers_mut().insert(header.clone(), value);
+            }
             Ok(InterceptOutcome::Continue(request))
         }
     }
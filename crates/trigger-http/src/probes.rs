@@ -0,0 +1,114 @@
+//! Liveness and readiness probes, for orchestrators such as Kubernetes.
+//!
+//! Probes are served at paths set on the command line, ahead of the app's
+//! own routes. The liveness probe responds whenever the server is running.
+//! The readiness probe reports whether each HTTP component can take another
+//! request without waiting, as JSON, with status 503 if any is running as
+//! many instances as its concurrency limit allows.
+//!
+//! The app's `on_startup` hook, SQLite migrations and eager compilation all
+//! finish before the server starts listening, so until then probes fail to
+//! connect.
+
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+use spin_factors::RuntimeFactors;
+
+use crate::TriggerApp;
+
+/// The paths at which probes are served, if any.
+#[derive(Clone, Debug, Default)]
+pub struct ProbePaths {
+    /// The path of the liveness probe, e.g. `/healthz`.
+    pub health: Option<String>,
+    /// The path of the readiness probe, e.g. `/readyz`.
+    pub ready: Option<String>,
+}
+
+impl ProbePaths {
+    /// All the paths at which probes are served.
+    pub(crate) fn paths(&self) -> impl Iterator<Item = &str> {
+        self.health.iter().chain(&self.ready).map(String::as_str)
+    }
+}
+
+/// The app's readiness, as reported by the readiness probe.
+#[derive(Debug, Serialize)]
+pub(crate) struct Readiness<'a> {
+    /// Whether every component is ready.
+    pub ready: bool,
+    /// The readiness of each HTTP component, by component ID.
+    components: BTreeMap<&'a str, ComponentReadiness>,
+}
+
+#[derive(Debug, Serialize)]
+struct ComponentReadiness {
+    ready: bool,
+    /// Whether the component, or the trigger as a whole, is running its
+    /// concurrency limit of instances, so that requests to it queue.
+    at_concurrency_limit: bool,
+}
+
+impl<'a> Readiness<'a> {
+    /// Checks the readiness of the given HTTP components of `trigger_app`.
+    pub fn check<F: RuntimeFactors>(
+        trigger_app: &TriggerApp<F>,
+        component_ids: impl IntoIterator<Item = &'a str>,
+    ) -> Self {
+        let components = component_ids
+            .into_iter()
+            .map(|component_id| {
+                let at_concurrency_limit = trigger_app.is_at_concurrency_limit(component_id);
+                let readiness = ComponentReadiness {
+                    ready: !at_concurrency_limit,
+                    at_concurrency_limit,
+                };
+                (component_id, readiness)
+            })
+            .collect::<BTreeMap<_, _>>();
+        Self {
+            ready: components.values().all(|component| component.ready),
+            components,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn readiness_is_reported_as_json() {
+        let readiness = Readiness {
+            ready: false,
+            components: [
+                (
+                    "api",
+                    ComponentReadiness {
+                        ready: false,
+                        at_concurrency_limit: true,
+                    },
+                ),
+                (
+                    "docs",
+                    ComponentReadiness {
+                        ready: true,
+                        at_concurrency_limit: false,
+                    },
+                ),
+            ]
+            .into(),
+        };
+        assert_eq!(
+            serde_json::to_value(&readiness).unwrap(),
+            serde_json::json!({
+                "ready": false,
+                "components": {
+                    "api": { "ready": false, "at_concurrency_limit": true },
+                    "docs": { "ready": true, "at_concurrency_limit": false },
+                },
+            })
+        );
+    }
+}
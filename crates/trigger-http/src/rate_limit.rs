@@ -0,0 +1,307 @@
+use std::{
+    net::SocketAddr,
+    num::NonZeroUsize,
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Context;
+use http::{HeaderName, Request};
+use lru::LruCache;
+use serde::{Deserialize, Serialize};
+use spin_factor_key_value::AppState as KeyValueState;
+use spin_http::config::{RateLimitConfig, RateLimitKey};
+
+/// The most buckets kept in memory. The least recently used bucket is
+/// discarded to make room for a new one, so its client starts afresh with a
+/// full bucket.
+const MAX_BUCKETS: usize = 10_000;
+
+/// Enforces a component's [`RateLimitConfig`].
+pub(crate) struct RateLimiter {
+    component_id: String,
+    requests_per_second: f64,
+    burst: f64,
+    key: BucketKey,
+    store: Option<String>,
+    buckets: Mutex<LruCache<String, Bucket>>,
+}
+
+enum BucketKey {
+    Global,
+    ClientIp,
+    Header(HeaderName),
+}
+
+impl RateLimiter {
+    pub fn new(component_id: &str, config: &RateLimitConfig) -> anyhow::Result<Self> {
+        let requests_per_second = config.requests_per_second;
+        anyhow::ensure!(
+            requests_per_second.is_finite() && requests_per_second > 0.0,
+            "rate limit for component {component_id:?} must allow a positive number of requests per second"
+        );
+        let burst = config
+            .burst
+            .unwrap_or_else(|| requests_per_second.ceil() as u32);
+        anyhow::ensure!(
+            burst > 0,
+            "rate limit burst for component {component_id:?} must be at least 1"
+        );
+        let key = match (config.by, &config.header) {
+            (RateLimitKey::Header, Some(header)) => {
+                BucketKey::Header(header.parse().with_context(|| {
+                    format!("invalid rate limit header {header:?} for component {component_id:?}")
+                })?)
+            }
+            (RateLimitKey::Header, None) => anyhow::bail!(
+                "rate limit for component {component_id:?} is by header but sets no `header`"
+            ),
+            (_, Some(_)) => anyhow::bail!(
+                "rate limit for component {component_id:?} sets a `header` but is not by header"
+            ),
+            (RateLimitKey::Global, None) => BucketKey::Global,
+            (RateLimitKey::ClientIp, None) => BucketKey::ClientIp,
+        };
+        Ok(Self {
+            component_id: component_id.to_owned(),
+            requests_per_second,
+            burst: burst.into(),
+            key,
+            store: config.store.clone(),
+            buckets: Mutex::new(LruCache::new(NonZeroUsize::new(MAX_BUCKETS).unwrap())),
+        })
+    }
+
+    /// The label of the key-value store buckets are kept in, if any.
+    pub fn store(&self) -> Option<&str> {
+        self.store.as_deref()
+    }
+
+    /// Takes a token for the given request, or returns how long the client
+    /// should wait before retrying.
+    ///
+    /// If the bucket store can't be reached the request is allowed, so that a
+    /// store outage doesn't take the app down with it.
+    pub async fn check<B>(
+        &self,
+        req: &Request<B>,
+        client_addr: SocketAddr,
+        key_value: Option<&KeyValueState>,
+    ) -> Result<(), Duration> {
+        let key = self.bucket_key(req, client_addr);
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs_f64();
+        let Some(label) = &self.store else {
+            return self.take_from_memory(key, now);
+        };
+        match self.take_from_store(label, &key, now, key_value).await {
+            Ok(taken) => taken,
+            Err(err) => {
+                tracing::warn!(
+                    "Rate limit store {label:?} failed for component {:?}: {err:#}",
+                    self.component_id
+                );
+                Ok(())
+            }
+        }
+    }
+
+    fn bucket_key<B>(&self, req: &Request<B>, client_addr: SocketAddr) -> String {
+        match &self.key {
+            BucketKey::Global => String::new(),
+            BucketKey::ClientIp => client_addr.ip().to_string(),
+            BucketKey::Header(name) => req
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .unwrap_or_default()
+                .to_owned(),
+        }
+    }
+
+    fn take_from_memory(&self, key: String, now: f64) -> Result<(), Duration> {
+        self.buckets
+            .lock()
+            .unwrap()
+            .get_or_insert_mut(key, || Bucket::full(self.burst, now))
+            .take(now, self.requests_per_second, self.burst)
+    }
+
+    // Buckets are read and written back without a transaction, so instances
+    // handling requests for the same bucket at the same moment may each take
+    // the last token.
+    async fn take_from_store(
+        &self,
+        label: &str,
+        key: &str,
+        now: f64,
+        key_value: Option<&KeyValueState>,
+    ) -> anyhow::Result<Result<(), Duration>> {
+        // Each store handle has its own read cache, so get a new one for
+        // every request to see the writes of other instances.
+        let store = key_value
+            .context("key-value stores are not available")?
+            .get_store(label)
+            .await
+            .context("no such store")?;
+        let store_key = format!("spin-rate-limit/{}/{key}", self.component_id);
+        let mut bucket = match store.get(&store_key).await? {
+            Some(value) => serde_json::from_slice(&value).unwrap_or_else(|err| {
+                tracing::debug!("Replacing invalid rate limit bucket {store_key:?}: {err}");
+                Bucket::full(self.burst, now)
+            }),
+            None => Bucket::full(self.burst, now),
+        };
+        let taken = bucket.take(now, self.requests_per_second, self.burst);
+        store.set(&store_key, &serde_json::to_vec(&bucket)?).await?;
+        Ok(taken)
+    }
+}
+
+/// A token bucket, with times in seconds since the Unix epoch so that buckets
+/// kept in a store mean the same thing to every instance.
+#[derive(Debug, Serialize, Deserialize)]
+struct Bucket {
+    tokens: f64,
+    updated: f64,
+}
+
+impl Bucket {
+    fn full(burst: f64, now: f64) -> Self {
+        Self {
+            tokens: burst,
+            updated: now,
+        }
+    }
+
+    /// Refills the bucket and takes a token, or returns how long until one
+    /// becomes available.
+    fn take(&mut self, now: f64, requests_per_second: f64, burst: f64) -> Result<(), Duration> {
+        let elapsed = (now - self.updated).max(0.0);
+        self.tokens = (self.tokens + elapsed * requests_per_second).min(burst);
+        self.updated = self.updated.max(now);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64(
+                (1.0 - self.tokens) / requests_per_second,
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(requests_per_second: f64) -> RateLimitConfig {
+        RateLimitConfig {
+            requests_per_second,
+            burst: None,
+            by: RateLimitKey::default(),
+            header: None,
+            store: None,
+        }
+    }
+
+    #[test]
+    fn buckets_allow_bursts_then_refill() -> anyhow::Result<()> {
+        let limiter = RateLimiter::new(
+            "api",
+            &RateLimitConfig {
+                burst: Some(3),
+                ..config(2.0)
+            },
+        )?;
+        for _ in 0..3 {
+            limiter.take_from_memory("a".into(), 100.0).unwrap();
+        }
+        let retry_after = limiter.take_from_memory("a".into(), 100.0).unwrap_err();
+        assert_eq!(retry_after, Duration::from_millis(500));
+
+        // Other clients have their own buckets.
+        limiter.take_from_memory("b".into(), 100.0).unwrap();
+
+        limiter.take_from_memory("a".into(), 100.5).unwrap();
+        limiter.take_from_memory("a".into(), 100.5).unwrap_err();
+        Ok(())
+    }
+
+    #[test]
+    fn buckets_in_memory_are_bounded() -> anyhow::Result<()> {
+        let limiter = RateLimiter::new(
+            "api",
+            &RateLimitConfig {
+                burst: Some(1),
+                ..config(1.0)
+            },
+        )?;
+        limiter.take_from_memory("first".into(), 100.0).unwrap();
+        limiter.take_from_memory("first".into(), 100.0).unwrap_err();
+        for i in 0..MAX_BUCKETS {
+            limiter.take_from_memory(i.to_string(), 100.0).unwrap();
+        }
+        assert_eq!(limiter.buckets.lock().unwrap().len(), MAX_BUCKETS);
+        // The least recently used bucket made room for the others.
+        limiter.take_from_memory("first".into(), 100.0).unwrap();
+        Ok(())
+    }
+
+    #[test]
+    fn requests_are_keyed_by_config() -> anyhow::Result<()> {
+        let req = Request::get("/")
+            .header("x-api-key", "secret")
+            .body(())
+            .unwrap();
+        let addr: SocketAddr = "10.0.0.1:1234".parse().unwrap();
+
+        let by_ip = RateLimiter::new("api", &config(1.0))?;
+        assert_eq!(by_ip.bucket_key(&req, addr), "10.0.0.1");
+
+        let by_header = RateLimiter::new(
+            "api",
+            &RateLimitConfig {
+                by: RateLimitKey::Header,
+                header: Some("x-api-key".into()),
+                ..config(1.0)
+            },
+        )?;
+        assert_eq!(by_header.bucket_key(&req, addr), "secret");
+        assert_eq!(by_header.bucket_key(&Request::new(()), addr), "");
+
+        let global = RateLimiter::new(
+            "api",
+            &RateLimitConfig {
+                by: RateLimitKey::Global,
+                ..config(1.0)
+            },
+        )?;
+        assert_eq!(global.bucket_key(&req, addr), "");
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_configs_are_rejected() {
+        let invalid = [
+            config(0.0),
+            RateLimitConfig {
+                by: RateLimitKey::Header,
+                ..config(1.0)
+            },
+            RateLimitConfig {
+                header: Some("x-api-key".into()),
+                ..config(1.0)
+            },
+            RateLimitConfig {
+                burst: Some(0),
+                ..config(1.0)
+            },
+        ];
+        for config in invalid {
+            assert!(RateLimiter::new("api", &config).is_err());
+        }
+    }
+}
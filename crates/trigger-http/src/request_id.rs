@@ -0,0 +1,131 @@
+use std::{fmt, sync::Arc};
+
+use http::{HeaderName, HeaderValue, Request};
+
+/// The default [`RequestIdConfig::header`].
+pub const DEFAULT_REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// The longest incoming request ID that will be honored.
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+/// Identifies an incoming request in logs, traces, error responses, and the
+/// outbound requests made while handling it.
+///
+/// The ID is available as a request extension while the request is handled.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RequestId(Arc<str>);
+
+impl RequestId {
+    /// Generates a new, unique request ID.
+    pub fn generate() -> Self {
+        Self(uuid::Uuid::new_v4().simple().to_string().into())
+    }
+
+    /// Parses a request ID from a header value.
+    ///
+    /// Only short, non-empty values of visible ASCII characters are accepted,
+    /// so that IDs can be safely logged and sent on in other headers.
+    fn from_header_value(value: &HeaderValue) -> Option<Self> {
+        let value = value.to_str().ok()?;
+        let valid = !value.is_empty()
+            && value.len() <= MAX_REQUEST_ID_LEN
+            && value.bytes().all(|b| b.is_ascii_graphic());
+        valid.then(|| Self(value.into()))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub(crate) fn to_header_value(&self) -> HeaderValue {
+        // Generated and parsed IDs are always visible ASCII.
+        HeaderValue::from_str(&self.0).expect("request ID should be a valid header value")
+    }
+}
+
+impl fmt::Display for RequestId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// How request IDs are assigned to incoming requests and propagated.
+#[derive(Clone, Debug)]
+pub struct RequestIdConfig {
+    /// The header the request ID is returned in on responses and sent in on
+    /// outbound requests.
+    pub header: HeaderName,
+    /// Whether to use the request ID from an incoming request's [`Self::header`]
+    /// instead of generating one. This should only be enabled behind a proxy
+    /// that sets or validates the header.
+    pub trust_incoming: bool,
+}
+
+impl Default for RequestIdConfig {
+    fn default() -> Self {
+        Self {
+            header: HeaderName::from_static(DEFAULT_REQUEST_ID_HEADER),
+            trust_incoming: false,
+        }
+    }
+}
+
+impl RequestIdConfig {
+    /// Returns the ID of the given incoming request.
+    pub(crate) fn request_id<B>(&self, req: &Request<B>) -> RequestId {
+        if self.trust_incoming {
+            if let Some(id) = req
+                .headers()
+                .get(&self.header)
+                .and_then(RequestId::from_header_value)
+            {
+                return id;
+            }
+        }
+        RequestId::generate()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn incoming_ids_are_only_honored_if_trusted() {
+        let req = Request::get("/")
+            .header(DEFAULT_REQUEST_ID_HEADER, "from-client")
+            .body(())
+            .unwrap();
+
+        let untrusted = RequestIdConfig::default().request_id(&req);
+        assert_ne!(untrusted.as_str(), "from-client");
+
+        let trusted = RequestIdConfig {
+            trust_incoming: true,
+            ..Default::default()
+        };
+        assert_eq!(trusted.request_id(&req).as_str(), "from-client");
+    }
+
+    #[test]
+    fn invalid_incoming_ids_are_replaced() {
+        let config = RequestIdConfig {
+            trust_incoming: true,
+            ..Default::default()
+        };
+        for invalid in ["", "has space", &"x".repeat(MAX_REQUEST_ID_LEN + 1)] {
+            let req = Request::get("/")
+                .header(DEFAULT_REQUEST_ID_HEADER, invalid)
+                .body(())
+                .unwrap();
+            let id = config.request_id(&req);
+            assert_ne!(id.as_str(), invalid);
+            assert!(!id.as_str().is_empty());
+        }
+    }
+
+    #[test]
+    fn generated_ids_are_unique() {
+        assert_ne!(RequestId::generate(), RequestId::generate());
+    }
+}
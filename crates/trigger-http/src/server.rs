@@ -1,9 +1,18 @@
-use std::{collections::HashMap, future::Future, io::IsTerminal, net::SocketAddr, sync::Arc};
+use std::{
+    collections::HashMap,
+    future::Future,
+    io::IsTerminal,
+    net::SocketAddr,
+    path::Path,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
 
 use anyhow::{bail, Context};
 use http::{
+    header::EXPECT,
     uri::{Authority, Scheme},
-    Request, Response, StatusCode, Uri,
+    HeaderValue, Request, Response, StatusCode, Uri,
 };
 use http_body_util::BodyExt;
 use hyper::{
@@ -12,9 +21,13 @@ use hyper::{
     service::service_fn,
 };
 use hyper_util::rt::TokioIo;
-use spin_app::{APP_DESCRIPTION_KEY, APP_NAME_KEY};
+use spin_app::{InstanceLifetime, APP_DESCRIPTION_KEY, APP_NAME_KEY};
+use spin_common::diagnostics::Diagnostic;
+use spin_factor_context::ContextFactor;
+use spin_factor_key_value::KeyValueFactor;
 use spin_factor_outbound_http::{OutboundHttpFactor, SelfRequestOrigin};
-use spin_factors::RuntimeFactors;
+use spin_factor_variables::VariablesFactor;
+use spin_factors::{RuntimeFactors, RuntimeFactorsInstanceState};
 use spin_http::{
     app_info::AppInfo,
     body,
@@ -22,6 +35,12 @@ use spin_http::{
     routes::{RouteMatch, Router},
     trigger::HandlerType,
 };
+use spin_trigger::{
+    cli::{enable_guest_profiling, DEFAULT_PROFILE_DIR},
+    correlation::with_correlation_id,
+    saturation::SaturationTracker,
+    ConcurrencyLimitExceeded, ConcurrencyPermits,
+};
 use tokio::{
     io::{AsyncRead, AsyncWrite},
     net::TcpListener,
@@ -31,29 +50,59 @@ use tracing::Instrument;
 use wasmtime_wasi_http::body::HyperOutgoingBody;
 
 use crate::{
+    access_log::{AccessLog, RoutedComponent},
+    admin::AdminApi,
+    canary::TrafficSplits,
+    client_cert::{set_client_cert_headers, ClientCertificate},
+    early_hints::{EarlyHints, EarlyHintsIo},
+    header_rules::HeaderRewriter,
     headers::strip_forbidden_headers,
+    idempotency::{Admission, Idempotency},
+    instance_pool::{InstancePool, Keeper, KeptInstance},
     instrument::{finalize_http_span, http_span, instrument_error, MatchedRoute},
+    jwt::{AuthError, JwtValidator, JWT_CLAIMS_HEADER},
+    mirror::{MirrorConfig, MirrorTarget, MirroredRequest, Mirrors},
     outbound_http::OutboundHttpInterceptor,
+    probes::{ProbePaths, Readiness},
+    rate_limit::RateLimiter,
+    request_id::{RequestId, RequestIdConfig},
+    session_affinity::SessionAffinity,
     spin::SpinHttpExecutor,
     wagi::WagiHttpExecutor,
     wasi::WasiHttpExecutor,
     Body, NotFoundRouteKind, TlsConfig, TriggerApp, TriggerInstanceBuilder,
 };
 
+/// In debug builds, requests with this header have their handler profiled,
+/// with profiles written to [`DEFAULT_PROFILE_DIR`].
+const PROFILE_HEADER: &str = "spin-profile";
+
 /// An HTTP server which runs Spin apps.
 pub struct HttpServer<F: RuntimeFactors> {
     /// The address the server is listening on.
     listen_addr: SocketAddr,
+    /// A listener passed in by systemd socket activation, used in place of
+    /// binding the listen address.
+    activated_listener: std::sync::Mutex<Option<std::net::TcpListener>>,
     /// The TLS configuration for the server.
     tls_config: Option<TlsConfig>,
-    /// Request router.
-    router: Router,
-    /// The app being triggered.
-    trigger_app: TriggerApp<F>,
-    // Component ID -> component trigger config
-    component_trigger_configs: HashMap<String, HttpTriggerConfig>,
-    // Component ID -> handler type
-    component_handler_types: HashMap<String, HandlerType>,
+    /// The app being triggered and its routes, replaced when the app is
+    /// reconfigured.
+    routes: RwLock<Arc<AppRoutes<F>>>,
+    /// Requests sent to canaries in place of the routed components.
+    pub(crate) traffic_splits: TrafficSplits,
+    /// How request IDs are assigned and propagated.
+    pub(crate) request_ids: RequestIdConfig,
+    /// Saturation of requests routed to components.
+    pub(crate) saturation: SaturationTracker,
+    /// The admin API, if it is enabled.
+    pub(crate) admin: Option<AdminApi>,
+    /// The access log, if it is enabled.
+    pub(crate) access_log: Option<Arc<AccessLog>>,
+    /// The paths of liveness and readiness probes.
+    probes: ProbePaths,
+    /// Requests mirrored to other components or servers.
+    mirrors: Mirrors,
 }
 
 impl<F: RuntimeFactors> HttpServer<F> {
@@ -63,78 +112,134 @@ impl<F: RuntimeFactors> HttpServer<F> {
         tls_config: Option<TlsConfig>,
         trigger_app: TriggerApp<F>,
     ) -> anyhow::Result<Self> {
-        // This needs to be a vec before building the router to handle duplicate routes
-        let component_trigger_configs = Vec::from_iter(
-            trigger_app
-                .app()
-                .trigger_configs::<HttpTriggerConfig>("http")?
-                .into_iter()
-                .map(|(_, config)| (config.component.clone(), config)),
-        );
+        let routes = AppRoutes::new(trigger_app)?;
 
-        // Build router
-        let component_routes = component_trigger_configs
-            .iter()
-            .map(|(component_id, config)| (component_id.as_str(), &config.route));
-        let (router, duplicate_routes) = Router::build("/", component_routes)?;
-        if !duplicate_routes.is_empty() {
-            tracing::error!(
-                "The following component routes are duplicates and will never be used:"
-            );
-            for dup in &duplicate_routes {
-                tracing::error!(
-                    "  {}: {} (duplicate of {})",
-                    dup.replaced_id,
-                    dup.route(),
-                    dup.effective_id,
-                );
-            }
-        }
-        tracing::trace!(
-            "Constructed router: {:?}",
-            router.routes().collect::<Vec<_>>()
+        let traffic_splits = TrafficSplits::new(
+            routes.component_trigger_configs.keys().cloned(),
+            routes
+                .trigger_app
+                .app()
+                .components()
+                .map(|component| component.id().to_owned()),
         );
 
-        // Now that router is built we can merge duplicate routes by component
-        let component_trigger_configs = HashMap::from_iter(component_trigger_configs);
+        let activated_listener = spin_trigger::systemd::take_listener("http")?;
+        let listen_addr = match &activated_listener {
+            Some(listener) => listener.local_addr()?,
+            None => listen_addr,
+        };
 
-        let component_handler_types = component_trigger_configs
-            .iter()
-            .map(|(component_id, trigger_config)| {
-                let handler_type = match &trigger_config.executor {
-                    None | Some(HttpExecutorType::Http) => {
-                        let component = trigger_app.get_component(component_id)?;
-                        HandlerType::from_component(trigger_app.engine().as_ref(), component)?
-                    }
-                    Some(HttpExecutorType::Wagi(wagi_config)) => {
-                        anyhow::ensure!(
-                            wagi_config.entrypoint == "_start",
-                            "Wagi component '{component_id}' cannot use deprecated 'entrypoint' field"
-                        );
-                        HandlerType::Wagi
-                    }
-                };
-                Ok((component_id.clone(), handler_type))
-            })
-            .collect::<anyhow::Result<_>>()?;
         Ok(Self {
             listen_addr,
+            activated_listener: std::sync::Mutex::new(activated_listener),
             tls_config,
-            router,
-            trigger_app,
-            component_trigger_configs,
-            component_handler_types,
+            routes: RwLock::new(Arc::new(routes)),
+            traffic_splits,
+            request_ids: RequestIdConfig::default(),
+            saturation: SaturationTracker::new(),
+            admin: None,
+            access_log: None,
+            probes: ProbePaths::default(),
+            mirrors: Mirrors::default(),
         })
     }
 
+    /// Switches to a reconfigured version of the app being served, such as
+    /// one with changed routes or variables. See
+    /// [`spin_factors_executor::FactorsExecutorApp::reconfigure`].
+    ///
+    /// Requests already being handled finish with the previous version.
+    pub fn reconfigure(&self, trigger_app: TriggerApp<F>) -> anyhow::Result<()> {
+        let routes = AppRoutes::new(trigger_app)?;
+        for (route, component_id) in routes.router.routes() {
+            tracing::info!("Reconfigured route {route} to component {component_id:?}");
+        }
+        self.traffic_splits.set_components(
+            routes.component_trigger_configs.keys().cloned(),
+            routes
+                .trigger_app
+                .app()
+                .components()
+                .map(|component| component.id().to_owned()),
+        );
+        *self.routes.write().unwrap() = Arc::new(routes);
+        Ok(())
+    }
+
+    /// Serves liveness and readiness probes at the given paths, ahead of any
+    /// routes to the same paths.
+    pub(crate) fn set_probes(&mut self, probes: ProbePaths) {
+        let routes = self.routes();
+        for path in probes.paths() {
+            if let Ok(route_match) = routes.router.route(path) {
+                routes.trigger_app.diagnostics().report(
+                    Diagnostic::new(
+                        "probe-shadows-route",
+                        format!("route to {path} is shadowed by the probe at that path"),
+                    )
+                    .component(route_match.component_id())
+                    .suggestion(
+                        "Serve the probe at another path with `--health-path` or `--ready-path`.",
+                    ),
+                );
+            }
+        }
+        self.probes = probes;
+    }
+
+    /// Mirrors a share of the requests routed to components, as set by
+    /// `[http_server.mirrors]` in the runtime config.
+    pub(crate) fn set_mirrors(
+        &mut self,
+        configs: HashMap<String, MirrorConfig>,
+    ) -> anyhow::Result<()> {
+        if configs.is_empty() {
+            return Ok(());
+        }
+        let routes = self.routes();
+        let routed = routes
+            .component_trigger_configs
+            .keys()
+            .map(String::as_str)
+            .collect();
+        let components = routes
+            .trigger_app
+            .app()
+            .components()
+            .map(|component| component.id())
+            .collect();
+        self.mirrors = Mirrors::new(configs, &routed, &components)?;
+        Ok(())
+    }
+
+    /// The app being served and its routes.
+    fn routes(&self) -> Arc<AppRoutes<F>> {
+        self.routes.read().unwrap().clone()
+    }
+
+    /// The [`SaturationTracker`] for requests routed to components.
+    pub fn saturation(&self) -> &SaturationTracker {
+        &self.saturation
+    }
+
     /// Serve incoming requests over the provided [`TcpListener`].
     pub async fn serve(self: Arc<Self>) -> anyhow::Result<()> {
-        let listener = TcpListener::bind(self.listen_addr).await.with_context(|| {
-            format!(
-                "Unable to listen on {listen_addr}",
-                listen_addr = self.listen_addr
-            )
-        })?;
+        let activated_listener = self.activated_listener.lock().unwrap().take();
+        let listener = match activated_listener {
+            Some(listener) => {
+                tracing::info!(
+                    "Using the socket passed by systemd for {}",
+                    self.listen_addr
+                );
+                TcpListener::from_std(listener)?
+            }
+            None => TcpListener::bind(self.listen_addr).await.with_context(|| {
+                format!(
+                    "Unable to listen on {listen_addr}",
+                    listen_addr = self.listen_addr
+                )
+            })?,
+        };
         if let Some(tls_config) = self.tls_config.clone() {
             self.serve_https(listener, tls_config).await?;
         } else {
@@ -145,6 +250,7 @@ impl<F: RuntimeFactors> HttpServer<F> {
 
     async fn serve_http(self: Arc<Self>, listener: TcpListener) -> anyhow::Result<()> {
         self.print_startup_msgs("http", &listener)?;
+        spin_trigger::systemd::notify_ready("Serving HTTP");
         loop {
             let (stream, client_addr) = listener.accept().await?;
             self.clone()
@@ -157,14 +263,28 @@ impl<F: RuntimeFactors> HttpServer<F> {
         listener: TcpListener,
         tls_config: TlsConfig,
     ) -> anyhow::Result<()> {
-        self.print_startup_msgs("https", &listener)?;
         let acceptor = tls_config.server_config()?;
+        self.print_startup_msgs("https", &listener)?;
+        spin_trigger::systemd::notify_ready("Serving HTTPS");
         loop {
             let (stream, client_addr) = listener.accept().await?;
             match acceptor.accept(stream).await {
-                Ok(stream) => self
-                    .clone()
-                    .serve_connection(stream, Scheme::HTTPS, client_addr),
+                Ok(stream) => {
+                    let client_cert = stream
+                        .get_ref()
+                        .1
+                        .peer_certificates()
+                        .and_then(|certs| certs.first())
+                        .and_then(|cert| match ClientCertificate::parse(cert) {
+                            Ok(cert) => Some(Arc::new(cert)),
+                            Err(err) => {
+                                tracing::warn!("Failed to read client certificate: {err:#}");
+                                None
+                            }
+                        });
+                    self.clone()
+                        .spawn_connection(stream, Scheme::HTTPS, client_addr, client_cert)
+                }
                 Err(err) => tracing::error!(?err, "Failed to start TLS session"),
             }
         }
@@ -181,12 +301,51 @@ impl<F: RuntimeFactors> HttpServer<F> {
         client_addr: SocketAddr,
     ) -> anyhow::Result<Response<Body>> {
         strip_forbidden_headers(&mut req);
+        set_client_cert_headers(&mut req);
+
+        // hyper meets `100-continue` expectations itself, sending the interim
+        // response when the body is first read: only once the request reaches
+        // its handler, and as the handler reads it. Other expectations can't be met.
+        if let Some(expect) = req.headers_mut().remove(EXPECT) {
+            if !expect.as_bytes().eq_ignore_ascii_case(b"100-continue") {
+                return Self::expectation_failed();
+            }
+        }
 
         spin_telemetry::extract_trace_context(&req);
 
+        let request_id = self.request_ids.request_id(&req);
+        tracing::Span::current().record("spin.request_id", request_id.as_str());
+        req.extensions_mut().insert(request_id.clone());
+
         let path = req.uri().path().to_string();
 
-        tracing::info!("Processing request on path '{path}'");
+        tracing::info!(%request_id, "Processing request on path '{path}'");
+
+        let mut response = self.route(req, path, server_scheme, client_addr).await?;
+        response.headers_mut().insert(
+            self.request_ids.header.clone(),
+            request_id.to_header_value(),
+        );
+        Ok(response)
+    }
+
+    async fn route(
+        self: &Arc<Self>,
+        req: Request<Body>,
+        path: String,
+        server_scheme: Scheme,
+        client_addr: SocketAddr,
+    ) -> anyhow::Result<Response<Body>> {
+        if self.probes.health.as_ref() == Some(&path) {
+            return Ok(MatchedRoute::with_response_extension(
+                Response::new(body::full(Bytes::from_static(b"OK"))),
+                path,
+            ));
+        }
+        if self.probes.ready.as_ref() == Some(&path) {
+            return self.readiness(path);
+        }
 
         // Handle well-known spin paths
         if let Some(well_known) = path.strip_prefix(spin_http::WELL_KNOWN_PREFIX) {
@@ -196,16 +355,41 @@ impl<F: RuntimeFactors> HttpServer<F> {
                     path,
                 )),
                 "info" => self.app_info(path),
-                _ => Self::not_found(NotFoundRouteKind::WellKnown),
+                _ => match (&self.admin, well_known.strip_prefix("admin/")) {
+                    (Some(admin), Some(admin_path)) => {
+                        let routes = self.routes();
+                        let response = admin
+                            .handle(
+                                req,
+                                admin_path,
+                                routes.trigger_app.configured_app(),
+                                &self.traffic_splits,
+                                &self.saturation,
+                            )
+                            .await?;
+                        Ok(MatchedRoute::with_response_extension(
+                            response,
+                            format!("{}admin/", spin_http::WELL_KNOWN_PREFIX),
+                        ))
+                    }
+                    _ => self.not_found(NotFoundRouteKind::WellKnown),
+                },
             };
         }
 
-        match self.router.route(&path) {
+        match self.routes().router.route(&path) {
             Ok(route_match) => {
-                self.handle_trigger_route(req, route_match, server_scheme, client_addr)
-                    .await
+                let component_id = route_match.component_id().to_owned();
+                let mut res = self
+                    .handle_trigger_route(req, route_match, server_scheme, client_addr)
+                    .await?;
+                // Requests handled by a canary are already marked with it.
+                if res.extensions().get::<RoutedComponent>().is_none() {
+                    res.extensions_mut().insert(RoutedComponent(component_id));
+                }
+                Ok(res)
             }
-            Err(_) => Self::not_found(NotFoundRouteKind::Normal(path.to_string())),
+            Err(_) => self.not_found(NotFoundRouteKind::Normal(path.to_string())),
         }
     }
 
@@ -218,7 +402,19 @@ impl<F: RuntimeFactors> HttpServer<F> {
         client_addr: SocketAddr,
     ) -> anyhow::Result<Response<Body>> {
         set_req_uri(&mut req, server_scheme.clone())?;
-        let app_id = self
+        // Service chaining requests carry the ID of the request that made them.
+        let request_id = match req.extensions().get::<RequestId>() {
+            Some(request_id) => request_id.clone(),
+            None => {
+                let request_id = self.request_ids.request_id(&req);
+                req.extensions_mut().insert(request_id.clone());
+                request_id
+            }
+        };
+        // The routes can be replaced while the request is handled, so it's
+        // handled with those it started with.
+        let routes = self.routes();
+        let app_id = routes
             .trigger_app
             .app()
             .get_metadata(APP_NAME_KEY)?
@@ -233,72 +429,364 @@ impl<F: RuntimeFactors> HttpServer<F> {
             component_id = component_id
         );
 
-        let mut instance_builder = self.trigger_app.prepare(component_id)?;
+        let key_value = routes
+            .trigger_app
+            .configured_app()
+            .app_state::<KeyValueFactor>()
+            .ok();
+        if let Some(limiter) = routes.rate_limiters.get(component_id) {
+            if let Err(retry_after) = limiter.check(&req, client_addr, key_value).await {
+                return Self::too_many_requests(retry_after, route_match.raw_route());
+            }
+        }
 
-        // Set up outbound HTTP request origin and service chaining
-        // The outbound HTTP factor is required since both inbound and outbound wasi HTTP
-        // implementations assume they use the same underlying wasmtime resource storage.
-        // Eventually, we may be able to factor this out to a separate factor.
-        let outbound_http = instance_builder
-            .factor_builder::<OutboundHttpFactor>()
-            .context(
-            "The wasi HTTP trigger was configured without the required wasi outbound http support",
-        )?;
-        let origin = SelfRequestOrigin::create(server_scheme, &self.listen_addr.to_string())?;
-        outbound_http.set_self_request_origin(origin);
-        outbound_http.set_request_interceptor(OutboundHttpInterceptor::new(self.clone()))?;
+        // Only the host may set the claims header.
+        req.headers_mut().remove(JWT_CLAIMS_HEADER);
+        if let Some(validator) = routes.jwt_validators.get(component_id) {
+            match validator.authenticate(&req).await {
+                Ok(claims) => {
+                    req.headers_mut()
+                        .insert(JWT_CLAIMS_HEADER, HeaderValue::from_str(&claims)?);
+                }
+                Err(err) => {
+                    tracing::info!("Rejecting unauthenticated request: {err:?}");
+                    return Self::unauthorized(&err, route_match.raw_route());
+                }
+            }
+        }
 
-        // Prepare HTTP executor
-        let trigger_config = self.component_trigger_configs.get(component_id).unwrap();
-        let handler_type = self.component_handler_types.get(component_id).unwrap();
-        let executor = trigger_config
-            .executor
-            .as_ref()
-            .unwrap_or(&HttpExecutorType::Http);
+        let header_rewriter = routes.header_rewriters.get(component_id);
+        if let Some(rewriter) = header_rewriter {
+            rewriter
+                .rewrite_request(req.headers_mut(), routes.variables())
+                .await?;
+        }
 
-        let res = match executor {
-            HttpExecutorType::Http => match handler_type {
-                HandlerType::Spin => {
-                    SpinHttpExecutor
-                        .execute(instance_builder, &route_match, req, client_addr)
-                        .await
+        let (req, recorder) = match routes.idempotency.get(component_id) {
+            Some(idempotency) => match idempotency.admit(req, key_value).await? {
+                Admission::Handle(req, recorder) => (req, recorder),
+                Admission::Respond(res) => {
+                    return Ok(MatchedRoute::with_response_extension(
+                        res,
+                        route_match.raw_route(),
+                    ))
                 }
-                HandlerType::Wasi0_2
-                | HandlerType::Wasi2023_11_10
-                | HandlerType::Wasi2023_10_18 => {
-                    WasiHttpExecutor {
-                        handler_type: *handler_type,
-                    }
-                    .execute(instance_builder, &route_match, req, client_addr)
-                    .await
-                }
-                HandlerType::Wagi => unreachable!(),
             },
-            HttpExecutorType::Wagi(wagi_config) => {
-                let executor = WagiHttpExecutor {
-                    wagi_config: wagi_config.clone(),
-                };
-                executor
-                    .execute(instance_builder, &route_match, req, client_addr)
-                    .await
+            None => (req, None),
+        };
+
+        // A canary handles the request in place of the routed component, with
+        // the routed component's trigger config.
+        let canary = self.traffic_splits.choose(component_id, &req);
+        let handler_id = canary.as_deref().unwrap_or(component_id);
+
+        // A copy of the request may be handled alongside it by a mirror.
+        let (req, mirrored) = self.mirrors.copy(component_id, req).await?;
+        if let Some(mirrored) = mirrored {
+            task::spawn(self.clone().mirror(
+                component_id.to_owned(),
+                mirrored,
+                server_scheme.clone(),
+                client_addr,
+                request_id.clone(),
+            ));
+        }
+
+        // Instances are kept to handle later requests for sessions, and for
+        // components with pooled instances. A request in a session waits for
+        // any other in the session to finish.
+        let mut keeper = match routes.session_affinity.get(component_id) {
+            Some(affinity) => affinity
+                .session(handler_id, &req)
+                .await
+                .map(Keeper::Session),
+            None => None,
+        }
+        .or_else(|| {
+            routes
+                .instance_pools
+                .get(handler_id)
+                .cloned()
+                .map(Keeper::Pool)
+        });
+        let early_hints = req.extensions().get::<EarlyHints>().cloned();
+
+        let queued = self.saturation.enqueue();
+        // Kept instances don't hold concurrency slots while idle, so requests
+        // wait for slots whether or not they reuse an instance.
+        let permits = match routes
+            .trigger_app
+            .acquire_concurrency_permits(handler_id)
+            .await
+        {
+            Ok(permits) => permits,
+            Err(err) if err.is::<ConcurrencyLimitExceeded>() => {
+                tracing::warn!("Rejecting request: {err}");
+                queued.reject();
+                return Self::service_unavailable(route_match.raw_route());
             }
+            Err(err) => return Err(err),
+        };
+        let _in_flight;
+        let res = if let Some(mut kept) = keeper.as_mut().and_then(Keeper::take) {
+            let keeper = keeper.expect("kept instances are taken from keepers");
+            kept.store.data_mut().hold_concurrency_permits(permits);
+            self.reuse_instance(&mut kept, server_scheme, &request_id, early_hints.as_ref())?;
+            let KeptInstance {
+                instance,
+                store,
+                handler_type,
+                created,
+                requests,
+            } = kept;
+            _in_flight = queued.start();
+            WasiHttpExecutor { handler_type }
+                .handle(
+                    instance,
+                    store,
+                    &route_match,
+                    req,
+                    client_addr,
+                    move |instance, store| {
+                        keeper.keep(KeptInstance {
+                            instance,
+                            store,
+                            handler_type,
+                            created,
+                            requests: requests + 1,
+                        })
+                    },
+                )
+                .await
+        } else {
+            let mut handler = self
+                .prepare_handler(
+                    &routes,
+                    component_id,
+                    handler_id,
+                    server_scheme,
+                    &request_id,
+                    permits,
+                )
+                .await?;
+
+            // In debug builds, any request may ask for its handler to be profiled.
+            if cfg!(debug_assertions) && req.headers().contains_key(PROFILE_HEADER) {
+                enable_guest_profiling(
+                    &mut handler.instance_builder,
+                    Path::new(DEFAULT_PROFILE_DIR),
+                )?;
+            }
+            if let Some(early_hints) = &early_hints {
+                outbound_http_builder(&mut handler.instance_builder)?
+                    .set_early_hints_sender(early_hints.clone());
+            }
+
+            _in_flight = queued.start();
+            handler
+                .execute(keeper, &route_match, req, client_addr)
+                .await
+        };
+        // Guests may run on after returning a response, but can't send hints
+        // once it has been.
+        if let Some(early_hints) = early_hints {
+            early_hints.finish();
+        }
+        let res = match (res, header_rewriter) {
+            (Ok(mut res), Some(rewriter)) => rewriter
+                .rewrite_response(res.headers_mut(), routes.variables())
+                .await
+                .map(|()| res),
+            (res, _) => res,
+        };
+        let res = match (res, recorder) {
+            (Ok(res), Some(recorder)) => recorder.record(res).await,
+            (res, _) => res,
         };
         match res {
-            Ok(res) => Ok(MatchedRoute::with_response_extension(
-                res,
-                route_match.raw_route(),
-            )),
+            Ok(res) => {
+                let mut res = MatchedRoute::with_response_extension(res, route_match.raw_route());
+                if let Some(canary) = canary {
+                    res.extensions_mut().insert(RoutedComponent(canary));
+                }
+                Ok(res)
+            }
             Err(err) => {
-                tracing::error!("Error processing request: {err:?}");
+                tracing::error!(%request_id, "Error processing request: {err:?}");
                 instrument_error(&err);
-                Self::internal_error(None, route_match.raw_route())
+                let body = format!("Internal Server Error\nRequest ID: {request_id}\n");
+                Self::internal_error(Some(&body), route_match.raw_route())
             }
         }
     }
 
+    /// Sets up a kept instance to handle another request, replacing the state
+    /// it was set up with for the request it last handled.
+    fn reuse_instance(
+        self: &Arc<Self>,
+        kept: &mut KeptInstance<F>,
+        server_scheme: Scheme,
+        request_id: &RequestId,
+        early_hints: Option<&EarlyHints>,
+    ) -> anyhow::Result<()> {
+        let state = kept.store.data_mut().factors_instance_state_mut();
+        // Component output is correlated with the request ID returned in the response.
+        if let Some(context) = state.get::<ContextFactor>() {
+            context.set_correlation_id(request_id.to_string());
+        }
+        let outbound_http = state.get::<OutboundHttpFactor>().context(
+            "The wasi HTTP trigger was configured without the required wasi outbound http support",
+        )?;
+        let origin = SelfRequestOrigin::create(server_scheme, &self.listen_addr.to_string())?;
+        outbound_http.set_self_request_origin(origin);
+        outbound_http.replace_request_interceptor(OutboundHttpInterceptor::new(
+            self.clone(),
+            request_id.clone(),
+        ));
+        match early_hints {
+            Some(early_hints) => outbound_http.set_early_hints_sender(early_hints.clone()),
+            None => outbound_http.clear_early_hints_sender(),
+        }
+        Ok(())
+    }
+
+    /// Handles a copy of a request routed to `component_id` with its mirror,
+    /// discarding the response.
+    async fn mirror(
+        self: Arc<Self>,
+        component_id: String,
+        mirrored: MirroredRequest,
+        server_scheme: Scheme,
+        client_addr: SocketAddr,
+        request_id: RequestId,
+    ) {
+        let MirroredRequest {
+            target,
+            req,
+            in_flight: _in_flight,
+        } = mirrored;
+        let result = match &target {
+            MirrorTarget::Component(mirror_id) => {
+                let req = req.map(body::full);
+                self.mirror_to_component(
+                    &component_id,
+                    mirror_id,
+                    req,
+                    server_scheme,
+                    client_addr,
+                    &request_id,
+                )
+                .await
+            }
+            MirrorTarget::Url(url) => self.mirrors.send(url, req).await,
+        };
+        let target = target.to_string();
+        let outcome = match &result {
+            Ok(status) => status.to_string(),
+            Err(err) => {
+                tracing::warn!(%request_id, "Mirroring request to {target} failed: {err:?}");
+                "error".to_owned()
+            }
+        };
+        spin_telemetry::metrics::monotonic_counter!(
+            spin.mirrored_request_count = 1,
+            component_id = component_id,
+            mirror = target,
+            outcome = outcome
+        );
+    }
+
+    /// Handles a mirrored request with the component `mirror_id`, using the
+    /// trigger config of `component_id`, returning the response status.
+    async fn mirror_to_component(
+        self: &Arc<Self>,
+        component_id: &str,
+        mirror_id: &str,
+        mut req: Request<Body>,
+        server_scheme: Scheme,
+        client_addr: SocketAddr,
+        request_id: &RequestId,
+    ) -> anyhow::Result<u16> {
+        let routes = self.routes();
+        let route_match = routes.router.route(req.uri().path())?;
+        anyhow::ensure!(
+            route_match.component_id() == component_id,
+            "the request is no longer routed to component {component_id:?}"
+        );
+        req.extensions_mut().insert(request_id.clone());
+
+        // Mirrors are bounded by their own limit, rather than taking slots
+        // from live requests.
+        let handler = self
+            .prepare_handler(
+                &routes,
+                component_id,
+                mirror_id,
+                server_scheme,
+                request_id,
+                ConcurrencyPermits::default(),
+            )
+            .await?;
+        let res = handler
+            .execute(None, &route_match, req, client_addr)
+            .await?;
+        let status = res.status().as_u16();
+        // Read the response, so that the guest runs to completion.
+        res.into_body().collect().await?;
+        Ok(status)
+    }
+
+    /// Prepares an instance of `handler_id` to handle a request routed to
+    /// `component_id`, with the routed component's trigger config, holding
+    /// the given concurrency permits.
+    async fn prepare_handler<'a>(
+        self: &Arc<Self>,
+        routes: &'a AppRoutes<F>,
+        component_id: &str,
+        handler_id: &'a str,
+        server_scheme: Scheme,
+        request_id: &RequestId,
+        permits: ConcurrencyPermits,
+    ) -> anyhow::Result<PreparedHandler<'a, F>> {
+        // Component output is correlated with the request ID returned in the response.
+        let mut instance_builder = with_correlation_id(
+            request_id.to_string(),
+            routes.trigger_app.prepare_with_permits(handler_id, permits),
+        )
+        .await?;
+
+        routes.set_context_trigger(&mut instance_builder, component_id);
+
+        // Set up outbound HTTP request origin and service chaining
+        let outbound_http = outbound_http_builder(&mut instance_builder)?;
+        let origin = SelfRequestOrigin::create(server_scheme, &self.listen_addr.to_string())?;
+        outbound_http.set_self_request_origin(origin);
+        outbound_http.set_request_interceptor(OutboundHttpInterceptor::new(
+            self.clone(),
+            request_id.clone(),
+        ))?;
+
+        // Prepare HTTP executor
+        let trigger_config = routes
+            .component_trigger_configs
+            .get(component_id)
+            .with_context(|| format!("component {component_id:?} has no HTTP trigger"))?;
+        let handler_type = routes.handler_type(handler_id, &instance_builder)?;
+        let executor = trigger_config
+            .executor
+            .as_ref()
+            .unwrap_or(&HttpExecutorType::Http);
+        Ok(PreparedHandler {
+            instance_builder,
+            handler_id,
+            handler_type,
+            executor,
+        })
+    }
+
     /// Returns spin status information.
     fn app_info(&self, route: String) -> anyhow::Result<Response<Body>> {
-        let info = AppInfo::new(self.trigger_app.app());
+        let info = AppInfo::new(self.routes().trigger_app.app());
         let body = serde_json::to_vec_pretty(&info)?;
         Ok(MatchedRoute::with_response_extension(
             Response::builder()
@@ -308,6 +796,29 @@ impl<F: RuntimeFactors> HttpServer<F> {
         ))
     }
 
+    /// Returns the readiness of the app's components as JSON, with status 503
+    /// if any is at its concurrency limit.
+    fn readiness(&self, route: String) -> anyhow::Result<Response<Body>> {
+        let routes = self.routes();
+        let readiness = Readiness::check(
+            &routes.trigger_app,
+            routes.component_trigger_configs.keys().map(String::as_str),
+        );
+        let status = if readiness.ready {
+            StatusCode::OK
+        } else {
+            StatusCode::SERVICE_UNAVAILABLE
+        };
+        let body = serde_json::to_vec_pretty(&readiness)?;
+        Ok(MatchedRoute::with_response_extension(
+            Response::builder()
+                .status(status)
+                .header("content-type", "application/json")
+                .body(body::full(body.into()))?,
+            route,
+        ))
+    }
+
     /// Creates an HTTP 500 response.
     fn internal_error(
         body: Option<&str>,
@@ -326,15 +837,70 @@ impl<F: RuntimeFactors> HttpServer<F> {
         ))
     }
 
+    /// Creates an HTTP 503 response.
+    fn service_unavailable(route: impl Into<String>) -> anyhow::Result<Response<Body>> {
+        Ok(MatchedRoute::with_response_extension(
+            Response::builder()
+                .status(StatusCode::SERVICE_UNAVAILABLE)
+                .body(body::empty())?,
+            route,
+        ))
+    }
+
+    /// Creates an HTTP 417 response, for requests with expectations other than
+    /// `100-continue`.
+    fn expectation_failed() -> anyhow::Result<Response<Body>> {
+        Ok(Response::builder()
+            .status(StatusCode::EXPECTATION_FAILED)
+            .body(body::empty())?)
+    }
+
+    /// Creates an HTTP 429 response, asking the client to retry after the
+    /// given delay, rounded up to whole seconds.
+    fn too_many_requests(
+        retry_after: Duration,
+        route: impl Into<String>,
+    ) -> anyhow::Result<Response<Body>> {
+        let retry_after_secs = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+        Ok(MatchedRoute::with_response_extension(
+            Response::builder()
+                .status(StatusCode::TOO_MANY_REQUESTS)
+                .header(http::header::RETRY_AFTER, retry_after_secs.max(1))
+                .body(body::empty())?,
+            route,
+        ))
+    }
+
+    /// Creates an HTTP 401 response challenging the client for a bearer token.
+    fn unauthorized(err: &AuthError, route: impl Into<String>) -> anyhow::Result<Response<Body>> {
+        let challenge = match err {
+            AuthError::Missing => "Bearer",
+            AuthError::Invalid(_) => "Bearer error=\"invalid_token\"",
+        };
+        Ok(MatchedRoute::with_response_extension(
+            Response::builder()
+                .status(StatusCode::UNAUTHORIZED)
+                .header(http::header::WWW_AUTHENTICATE, challenge)
+                .body(body::empty())?,
+            route,
+        ))
+    }
+
     /// Creates an HTTP 404 response.
-    fn not_found(kind: NotFoundRouteKind) -> anyhow::Result<Response<Body>> {
+    fn not_found(&self, kind: NotFoundRouteKind) -> anyhow::Result<Response<Body>> {
         use std::sync::atomic::{AtomicBool, Ordering};
         static SHOWN_GENERIC_404_WARNING: AtomicBool = AtomicBool::new(false);
         if let NotFoundRouteKind::Normal(route) = kind {
             if !SHOWN_GENERIC_404_WARNING.fetch_or(true, Ordering::Relaxed)
                 && std::io::stderr().is_terminal()
             {
-                terminal::warn!("Request to {route} matched no pattern, and received a generic 404 response. To serve a more informative 404 page, add a catch-all (/...) route.");
+                self.routes().trigger_app.diagnostics().report(
+                    Diagnostic::new(
+                        "unmatched-route",
+                        format!("Request to {route} matched no pattern, and received a generic 404 response."),
+                    )
+                    .suggestion("To serve a more informative 404 page, add a catch-all (/...) route."),
+                );
             }
         }
         Ok(Response::builder()
@@ -342,18 +908,41 @@ impl<F: RuntimeFactors> HttpServer<F> {
             .body(body::empty())?)
     }
 
-    fn serve_connection<S: AsyncRead + AsyncWrite + Unpin + Send + 'static>(
+    /// Serves HTTP requests on a single connection, on a new task.
+    ///
+    /// This is for embedders that accept connections themselves; [`Self::serve`]
+    /// binds and accepts on the server's listen address.
+    pub fn serve_connection<S: AsyncRead + AsyncWrite + Unpin + Send + 'static>(
+        self: Arc<Self>,
+        stream: S,
+        server_scheme: Scheme,
+        client_addr: SocketAddr,
+    ) {
+        self.spawn_connection(stream, server_scheme, client_addr, None)
+    }
+
+    /// Serves a connection, passing the client's verified certificate, if it
+    /// has one, to each request.
+    fn spawn_connection<S: AsyncRead + AsyncWrite + Unpin + Send + 'static>(
         self: Arc<Self>,
         stream: S,
         server_scheme: Scheme,
         client_addr: SocketAddr,
+        client_cert: Option<Arc<ClientCertificate>>,
     ) {
         task::spawn(async move {
+            let (stream, early_hints) = EarlyHintsIo::new(stream);
             if let Err(err) = http1::Builder::new()
                 .keep_alive(true)
                 .serve_connection(
                     TokioIo::new(stream),
-                    service_fn(move |request| {
+                    service_fn(move |mut request| {
+                        if let Some(client_cert) = &client_cert {
+                            request.extensions_mut().insert(client_cert.clone());
+                        }
+                        if let Some(early_hints) = early_hints.start_request(&request) {
+                            request.extensions_mut().insert(early_hints);
+                        }
                         self.clone().instrumented_service_fn(
                             server_scheme.clone(),
                             client_addr,
@@ -376,7 +965,11 @@ impl<F: RuntimeFactors> HttpServer<F> {
     ) -> anyhow::Result<Response<HyperOutgoingBody>> {
         let span = http_span!(request, client_addr);
         let method = request.method().to_string();
-        async {
+        let access_log_entry = self
+            .access_log
+            .as_ref()
+            .map(|log| log.start(&request, client_addr));
+        let result = async {
             let result = self
                 .handle(
                     request.map(|body: Incoming| {
@@ -390,7 +983,17 @@ impl<F: RuntimeFactors> HttpServer<F> {
             finalize_http_span(result, method)
         }
         .instrument(span)
-        .await
+        .await;
+        match (&self.access_log, access_log_entry, result) {
+            (Some(log), Some(entry), Ok(res)) => {
+                Ok(log.finish(entry, res, &self.request_ids.header))
+            }
+            (Some(log), Some(entry), Err(err)) => {
+                log.fail(entry);
+                Err(err)
+            }
+            (_, _, result) => result,
+        }
     }
 
     fn print_startup_msgs(&self, scheme: &str, listener: &TcpListener) -> anyhow::Result<()> {
@@ -400,9 +1003,10 @@ impl<F: RuntimeFactors> HttpServer<F> {
         tracing::info!("Serving {base_url}");
 
         println!("Available Routes:");
-        for (route, component_id) in self.router.routes() {
+        let routes = self.routes();
+        for (route, component_id) in routes.router.routes() {
             println!("  {}: {}{}", component_id, base_url, route);
-            if let Some(component) = self.trigger_app.app().get_component(component_id) {
+            if let Some(component) = routes.trigger_app.app().get_component(component_id) {
                 if let Some(description) = component.get_metadata(APP_DESCRIPTION_KEY)? {
                     println!("    {}", description);
                 }
@@ -412,6 +1016,368 @@ impl<F: RuntimeFactors> HttpServer<F> {
     }
 }
 
+/// The app served by an [`HttpServer`] and how requests are routed to its
+/// components.
+struct AppRoutes<F: RuntimeFactors> {
+    /// Request router.
+    router: Router,
+    /// The app being triggered.
+    trigger_app: TriggerApp<F>,
+    // Component ID -> component trigger config
+    component_trigger_configs: HashMap<String, HttpTriggerConfig>,
+    // Component ID -> ID of the component's trigger
+    component_trigger_ids: HashMap<String, String>,
+    // Component ID -> handler type
+    component_handler_types: HashMap<String, HandlerType>,
+    // Component ID -> handler type, for components that weren't compiled
+    // when the app was loaded, determined when they are first prepared
+    lazy_handler_types: std::sync::Mutex<HashMap<String, HandlerType>>,
+    // Component ID -> rate limiter
+    rate_limiters: HashMap<String, RateLimiter>,
+    // Component ID -> JWT validator
+    jwt_validators: HashMap<String, JwtValidator>,
+    // Component ID -> header rewriter
+    header_rewriters: HashMap<String, HeaderRewriter>,
+    // Component ID -> idempotent response replay
+    idempotency: HashMap<String, Idempotency>,
+    // Component ID -> session affinity
+    session_affinity: HashMap<String, SessionAffinity<KeptInstance<F>>>,
+    // Component ID -> idle instances, for components with pooled instances
+    instance_pools: HashMap<String, Arc<InstancePool<KeptInstance<F>>>>,
+}
+
+impl<F: RuntimeFactors> AppRoutes<F> {
+    fn new(trigger_app: TriggerApp<F>) -> anyhow::Result<Self> {
+        // This needs to be a vec before building the router to handle duplicate routes
+        let component_trigger_configs = Vec::from_iter(
+            trigger_app
+                .app()
+                .trigger_configs::<HttpTriggerConfig>("http")?
+                .into_iter()
+                .map(|(_, config)| (config.component.clone(), config)),
+        );
+
+        // Build router
+        let component_routes = component_trigger_configs
+            .iter()
+            .map(|(component_id, config)| (component_id.as_str(), &config.route));
+        let (router, duplicate_routes) = Router::build("/", component_routes)?;
+        if !duplicate_routes.is_empty() {
+            tracing::error!(
+                "The following component routes are duplicates and will never be used:"
+            );
+            for dup in &duplicate_routes {
+                tracing::error!(
+                    "  {}: {} (duplicate of {})",
+                    dup.replaced_id,
+                    dup.route(),
+                    dup.effective_id,
+                );
+            }
+        }
+        tracing::trace!(
+            "Constructed router: {:?}",
+            router.routes().collect::<Vec<_>>()
+        );
+
+        // Now that router is built we can merge duplicate routes by component
+        let component_trigger_configs = HashMap::from_iter(component_trigger_configs);
+        let component_trigger_ids = trigger_app
+            .app()
+            .triggers_with_type("http")
+            .map(|trigger| {
+                Ok((
+                    trigger.component()?.id().to_owned(),
+                    trigger.id().to_owned(),
+                ))
+            })
+            .collect::<anyhow::Result<HashMap<_, _>>>()?;
+
+        let component_handler_types = component_trigger_configs
+            .iter()
+            .map(|(component_id, trigger_config)| {
+                let handler_type = match &trigger_config.executor {
+                    None | Some(HttpExecutorType::Http) => {
+                        // Components that aren't compiled yet have their handler
+                        // type determined when they are first prepared.
+                        if !trigger_app.is_compiled(component_id) {
+                            return Ok(None);
+                        }
+                        let component = trigger_app.get_component(component_id)?;
+                        HandlerType::from_component(trigger_app.engine().as_ref(), component)?
+                    }
+                    Some(HttpExecutorType::Wagi(wagi_config)) => {
+                        anyhow::ensure!(
+                            wagi_config.entrypoint == "_start",
+                            "Wagi component '{component_id}' cannot use deprecated 'entrypoint' field"
+                        );
+                        HandlerType::Wagi
+                    }
+                };
+                Ok(Some((component_id.clone(), handler_type)))
+            })
+            .filter_map(Result::transpose)
+            .collect::<anyhow::Result<_>>()?;
+
+        let rate_limiters = component_trigger_configs
+            .iter()
+            .filter_map(|(component_id, trigger_config)| {
+                let config = trigger_config.rate_limit.as_ref()?;
+                Some(
+                    RateLimiter::new(component_id, config)
+                        .map(|limiter| (component_id.clone(), limiter)),
+                )
+            })
+            .collect::<anyhow::Result<HashMap<_, _>>>()?;
+        for (component_id, limiter) in &rate_limiters {
+            if let Some(label) = limiter.store() {
+                let key_value = trigger_app
+                    .configured_app()
+                    .app_state::<KeyValueFactor>()
+                    .context("rate limit stores require key-value support")?;
+                anyhow::ensure!(
+                    key_value.store_is_defined(label),
+                    "rate limit for component {component_id:?} uses undefined key-value store {label:?}"
+                );
+            }
+        }
+
+        let jwt_validators = component_trigger_configs
+            .iter()
+            .filter_map(|(component_id, trigger_config)| {
+                let config = trigger_config.jwt.as_ref()?;
+                Some((component_id.clone(), JwtValidator::new(config)))
+            })
+            .collect();
+
+        let header_rewriters = component_trigger_configs
+            .iter()
+            .filter_map(|(component_id, trigger_config)| {
+                let config = trigger_config.headers.as_ref()?;
+                Some(
+                    HeaderRewriter::new(component_id, config)
+                        .map(|rewriter| (component_id.clone(), rewriter)),
+                )
+            })
+            .collect::<anyhow::Result<HashMap<_, _>>>()?;
+        if header_rewriters
+            .values()
+            .any(HeaderRewriter::uses_variables)
+        {
+            trigger_app
+                .configured_app()
+                .app_state::<VariablesFactor>()
+                .context("header rules that use variables require variables support")?;
+        }
+
+        let idempotency = component_trigger_configs
+            .iter()
+            .filter_map(|(component_id, trigger_config)| {
+                let config = trigger_config.idempotency.as_ref()?;
+                Some(
+                    Idempotency::new(component_id, config)
+                        .map(|idempotency| (component_id.clone(), idempotency)),
+                )
+            })
+            .collect::<anyhow::Result<HashMap<_, _>>>()?;
+        for (component_id, idempotency) in &idempotency {
+            let label = idempotency.store();
+            let key_value = trigger_app
+                .configured_app()
+                .app_state::<KeyValueFactor>()
+                .context("idempotency stores require key-value support")?;
+            anyhow::ensure!(
+                key_value.store_is_defined(label),
+                "idempotency for component {component_id:?} uses undefined key-value store {label:?}"
+            );
+        }
+
+        let session_affinity = component_trigger_configs
+            .iter()
+            .filter_map(|(component_id, trigger_config)| {
+                let config = trigger_config.session_affinity.as_ref()?;
+                Some(
+                    SessionAffinity::new(component_id, config)
+                        .map(|affinity| (component_id.clone(), affinity)),
+                )
+            })
+            .collect::<anyhow::Result<HashMap<_, _>>>()?;
+        let mut instance_pools = HashMap::new();
+        for component in trigger_app.app().components() {
+            let config = component.instance_config()?;
+            if config.lifetime == InstanceLifetime::Pooled {
+                instance_pools.insert(
+                    component.id().to_owned(),
+                    Arc::new(InstancePool::new(&config)),
+                );
+            }
+        }
+        // Components that aren't compiled yet are checked as they are first
+        // prepared.
+        for component_id in session_affinity.keys().chain(instance_pools.keys()) {
+            anyhow::ensure!(
+                !matches!(
+                    component_handler_types.get(component_id),
+                    Some(HandlerType::Spin | HandlerType::Wagi)
+                ),
+                "component {component_id:?} uses session affinity or pooled instances, which require a wasi:http component"
+            );
+        }
+
+        Ok(Self {
+            router,
+            trigger_app,
+            component_trigger_configs,
+            component_trigger_ids,
+            component_handler_types,
+            lazy_handler_types: Default::default(),
+            rate_limiters,
+            jwt_validators,
+            header_rewriters,
+            idempotency,
+            session_affinity,
+            instance_pools,
+        })
+    }
+
+    /// The handler type of the given component, determining it from the
+    /// instance being prepared the first time a lazily compiled component
+    /// is used.
+    fn handler_type(
+        &self,
+        component_id: &str,
+        instance_builder: &TriggerInstanceBuilder<'_, F>,
+    ) -> anyhow::Result<HandlerType> {
+        if let Some(handler_type) = self.component_handler_types.get(component_id) {
+            return Ok(*handler_type);
+        }
+        if let Some(handler_type) = self.lazy_handler_types.lock().unwrap().get(component_id) {
+            return Ok(*handler_type);
+        }
+        let handler_type = HandlerType::from_component(
+            instance_builder.wasmtime_engine(),
+            instance_builder.component(),
+        )?;
+        self.lazy_handler_types
+            .lock()
+            .unwrap()
+            .insert(component_id.to_owned(), handler_type);
+        Ok(handler_type)
+    }
+
+    /// Tells an instance handling a request routed to the given component
+    /// which of the app's triggers it is executing for.
+    fn set_context_trigger(
+        &self,
+        instance_builder: &mut TriggerInstanceBuilder<'_, F>,
+        component_id: &str,
+    ) {
+        let Some(trigger_id) = self.component_trigger_ids.get(component_id) else {
+            return;
+        };
+        if let Some(context) = instance_builder.factor_builder::<ContextFactor>() {
+            context.set_trigger("http", trigger_id);
+        }
+    }
+
+    /// The app's variables, for resolving header rule values.
+    fn variables(&self) -> Option<&spin_factor_variables::AppState> {
+        self.trigger_app
+            .configured_app()
+            .app_state::<VariablesFactor>()
+            .ok()
+    }
+}
+
+/// An instance prepared to handle a request, and how to execute it.
+struct PreparedHandler<'a, F: RuntimeFactors> {
+    instance_builder: TriggerInstanceBuilder<'a, F>,
+    handler_id: &'a str,
+    handler_type: HandlerType,
+    executor: &'a HttpExecutorType,
+}
+
+impl<F: RuntimeFactors> PreparedHandler<'_, F> {
+    /// Handles a request, then keeps the instance with `keeper` if there is
+    /// one.
+    async fn execute(
+        self,
+        keeper: Option<Keeper<F>>,
+        route_match: &RouteMatch,
+        req: Request<Body>,
+        client_addr: SocketAddr,
+    ) -> anyhow::Result<Response<Body>> {
+        let Self {
+            instance_builder,
+            handler_id,
+            handler_type,
+            executor,
+        } = self;
+        match executor {
+            HttpExecutorType::Http => match (handler_type, keeper) {
+                (HandlerType::Spin, Some(_)) => Err(anyhow::anyhow!(
+                    "only wasi:http components' instances can be kept, but {handler_id:?} uses the Spin HTTP interface"
+                )),
+                (HandlerType::Spin, None) => {
+                    SpinHttpExecutor
+                        .execute(instance_builder, route_match, req, client_addr)
+                        .await
+                }
+                (
+                    HandlerType::Wasi0_2 | HandlerType::Wasi2023_11_10 | HandlerType::Wasi2023_10_18,
+                    Some(keeper),
+                ) => {
+                    let (instance, store) = instance_builder.instantiate(()).await?;
+                    WasiHttpExecutor { handler_type }
+                        .handle(
+                            instance,
+                            store,
+                            route_match,
+                            req,
+                            client_addr,
+                            move |instance, store| {
+                                keeper.keep(KeptInstance::new(instance, store, handler_type))
+                            },
+                        )
+                        .await
+                }
+                (
+                    HandlerType::Wasi0_2 | HandlerType::Wasi2023_11_10 | HandlerType::Wasi2023_10_18,
+                    None,
+                ) => {
+                    WasiHttpExecutor { handler_type }
+                        .execute(instance_builder, route_match, req, client_addr)
+                        .await
+                }
+                (HandlerType::Wagi, _) => unreachable!(),
+            },
+            HttpExecutorType::Wagi(wagi_config) => {
+                let executor = WagiHttpExecutor {
+                    wagi_config: wagi_config.clone(),
+                };
+                executor
+                    .execute(instance_builder, route_match, req, client_addr)
+                    .await
+            }
+        }
+    }
+}
+
+/// Returns an instance's outbound HTTP factor builder.
+///
+/// The outbound HTTP factor is required since both inbound and outbound wasi HTTP
+/// implementations assume they use the same underlying wasmtime resource storage.
+/// Eventually, we may be able to factor this out to a separate factor.
+fn outbound_http_builder<'b, F: RuntimeFactors>(
+    instance_builder: &'b mut TriggerInstanceBuilder<'_, F>,
+) -> anyhow::Result<&'b mut spin_factor_outbound_http::InstanceState> {
+    instance_builder
+        .factor_builder::<OutboundHttpFactor>()
+        .context(
+            "The wasi HTTP trigger was configured without the required wasi outbound http support",
+        )
+}
+
 /// The incoming request's scheme and authority
 ///
 /// The incoming request's URI is relative to the server, so we need to set the scheme and authority.
@@ -0,0 +1,283 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, Weak,
+    },
+    time::Duration,
+};
+
+use anyhow::Context;
+use http::{header::COOKIE, HeaderName, Request};
+use spin_http::config::SessionAffinityConfig;
+use tokio::sync::OwnedMutexGuard;
+
+use crate::Body;
+
+/// The longest session key accepted. Requests with longer keys are handled
+/// without session affinity.
+const MAX_KEY_LEN: usize = 256;
+
+/// Identifies a session: the ID of the component handling it, and its key.
+type SessionId = (String, String);
+
+type Sessions<T> = Mutex<HashMap<SessionId, Arc<Session<T>>>>;
+
+/// Enforces a component's [`SessionAffinityConfig`], keeping an instance of
+/// the component for each session.
+///
+/// `T` is the kept instance, a [`KeptInstance`](crate::instance_pool::KeptInstance).
+pub(crate) struct SessionAffinity<T> {
+    key: SessionKey,
+    idle_timeout: Duration,
+    max_sessions: usize,
+    sessions: Arc<Sessions<T>>,
+}
+
+/// Where a request's session key is taken from.
+#[derive(Debug)]
+enum SessionKey {
+    Cookie(String),
+    Header(HeaderName),
+}
+
+struct Session<T> {
+    /// The session's instance, locked by the request it is handling.
+    instance: Arc<tokio::sync::Mutex<Option<T>>>,
+    /// Counts the session's requests, so that an idle timer can tell whether
+    /// there have been any since it was started.
+    requests: AtomicU64,
+}
+
+/// A session whose instance is locked for a request.
+///
+/// Once it is dropped, the session is ended if it isn't used again before the
+/// idle timeout.
+pub(crate) struct SessionGuard<T: Send + 'static> {
+    id: SessionId,
+    instance: OwnedMutexGuard<Option<T>>,
+    session: Arc<Session<T>>,
+    sessions: Weak<Sessions<T>>,
+    idle_timeout: Duration,
+}
+
+impl<T: Send + 'static> SessionAffinity<T> {
+    pub fn new(component_id: &str, config: &SessionAffinityConfig) -> anyhow::Result<Self> {
+        let key = match (&config.cookie, &config.header) {
+            (Some(cookie), None) => SessionKey::Cookie(cookie.clone()),
+            (None, Some(header)) => SessionKey::Header(header.parse().with_context(|| {
+                format!("invalid session affinity header {header:?} for component {component_id:?}")
+            })?),
+            _ => anyhow::bail!(
+                "session affinity for component {component_id:?} must set one of `cookie` or `header`"
+            ),
+        };
+        anyhow::ensure!(
+            config.idle_timeout_secs > 0,
+            "session affinity idle timeout for component {component_id:?} must be at least 1 second"
+        );
+        Ok(Self {
+            key,
+            idle_timeout: Duration::from_secs(config.idle_timeout_secs),
+            max_sessions: config.max_sessions,
+            sessions: Default::default(),
+        })
+    }
+
+    /// Returns the session `req` is part of, once no other request is being
+    /// handled in it, or `None` if it isn't part of one.
+    ///
+    /// `handler_id` is the component which handles the request, which may be
+    /// a canary of the routed component.
+    pub async fn session(&self, handler_id: &str, req: &Request<Body>) -> Option<SessionGuard<T>> {
+        let id = (handler_id.to_owned(), self.key.get(req)?);
+        let session = {
+            let mut sessions = self.sessions.lock().unwrap();
+            if let Some(session) = sessions.get(&id) {
+                session.clone()
+            } else if sessions.len() < self.max_sessions {
+                let session = Arc::new(Session {
+                    instance: Default::default(),
+                    requests: AtomicU64::new(0),
+                });
+                sessions.insert(id.clone(), session.clone());
+                session
+            } else {
+                tracing::debug!(
+                    "Not starting a session as there are already {}",
+                    sessions.len()
+                );
+                return None;
+            }
+        };
+        session.requests.fetch_add(1, Ordering::SeqCst);
+        Some(SessionGuard {
+            id,
+            instance: session.instance.clone().lock_owned().await,
+            session,
+            sessions: Arc::downgrade(&self.sessions),
+            idle_timeout: self.idle_timeout,
+        })
+    }
+}
+
+impl SessionKey {
+    fn get<B>(&self, req: &Request<B>) -> Option<String> {
+        let key = match self {
+            Self::Header(name) => req.headers().get(name)?.to_str().ok()?,
+            Self::Cookie(name) => req
+                .headers()
+                .get_all(COOKIE)
+                .iter()
+                .filter_map(|value| value.to_str().ok())
+                .flat_map(|value| value.split(';'))
+                .find_map(|cookie| {
+                    let (cookie_name, value) = cookie.trim().split_once('=')?;
+                    (cookie_name == name).then_some(value)
+                })?,
+        };
+        (!key.is_empty() && key.len() <= MAX_KEY_LEN).then(|| key.to_owned())
+    }
+}
+
+impl<T: Send + 'static> SessionGuard<T> {
+    /// Takes the session's instance, if it has one.
+    ///
+    /// Once it has been taken the session has no instance until one is passed
+    /// to [`Self::keep`], so an instance which fails isn't used again.
+    pub fn take(&mut self) -> Option<T> {
+        self.instance.take()
+    }
+
+    /// Keeps `instance` for the session's later requests.
+    pub fn keep(mut self, instance: T) {
+        *self.instance = Some(instance);
+    }
+}
+
+impl<T: Send + 'static> Drop for SessionGuard<T> {
+    fn drop(&mut self) {
+        let requests = self.session.requests.load(Ordering::SeqCst);
+        let session = Arc::downgrade(&self.session);
+        let sessions = self.sessions.clone();
+        let id = std::mem::take(&mut self.id);
+        let idle_timeout = self.idle_timeout;
+        tokio::spawn(async move {
+            tokio::time::sleep(idle_timeout).await;
+            let (Some(sessions), Some(session)) = (sessions.upgrade(), session.upgrade()) else {
+                return;
+            };
+            if session.requests.load(Ordering::SeqCst) != requests {
+                return;
+            }
+            let mut sessions = sessions.lock().unwrap();
+            if sessions
+                .get(&id)
+                .is_some_and(|current| Arc::ptr_eq(current, &session))
+            {
+                tracing::debug!("Ending idle session of component {:?}", id.0);
+                sessions.remove(&id);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(headers: &[(&str, &str)]) -> Request<()> {
+        let mut builder = Request::get("/");
+        for (name, value) in headers {
+            builder = builder.header(*name, *value);
+        }
+        builder.body(()).unwrap()
+    }
+
+    #[test]
+    fn keys_are_taken_from_cookies() {
+        let key = SessionKey::Cookie("session".into());
+        let req = request(&[("cookie", "theme=dark"), ("cookie", "other=1; session=abc")]);
+        assert_eq!(key.get(&req).as_deref(), Some("abc"));
+        assert_eq!(key.get(&request(&[("cookie", "sessions=abc")])), None);
+        assert_eq!(key.get(&request(&[])), None);
+    }
+
+    #[test]
+    fn keys_are_taken_from_headers() {
+        let key = SessionKey::Header(HeaderName::from_static("x-session"));
+        assert_eq!(
+            key.get(&request(&[("x-session", "abc")])).as_deref(),
+            Some("abc")
+        );
+        assert_eq!(key.get(&request(&[("x-session", "")])), None);
+        let long = "a".repeat(MAX_KEY_LEN + 1);
+        assert_eq!(key.get(&request(&[("x-session", &long)])), None);
+    }
+
+    fn affinity(idle_timeout: Duration, max_sessions: usize) -> SessionAffinity<u32> {
+        SessionAffinity {
+            key: SessionKey::Header(HeaderName::from_static("x-session")),
+            idle_timeout,
+            max_sessions,
+            sessions: Default::default(),
+        }
+    }
+
+    fn session_request(key: &str) -> Request<Body> {
+        Request::get("/")
+            .header("x-session", key)
+            .body(spin_http::body::empty())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn sessions_keep_their_instance() {
+        let affinity = affinity(Duration::from_secs(60), 10);
+        let mut session = affinity.session("c", &session_request("a")).await.unwrap();
+        assert_eq!(session.take(), None);
+        session.keep(1);
+
+        let mut session = affinity.session("c", &session_request("a")).await.unwrap();
+        assert_eq!(session.take(), Some(1));
+        // A failed instance isn't kept.
+        drop(session);
+        let mut session = affinity.session("c", &session_request("a")).await.unwrap();
+        assert_eq!(session.take(), None);
+
+        let mut other = affinity.session("c", &session_request("b")).await.unwrap();
+        assert_eq!(other.take(), None);
+    }
+
+    #[tokio::test]
+    async fn requests_in_a_session_wait_for_each_other() {
+        let affinity = Arc::new(affinity(Duration::from_secs(60), 10));
+        let mut session = affinity.session("c", &session_request("a")).await.unwrap();
+        let waiting = tokio::spawn({
+            let affinity = affinity.clone();
+            async move {
+                let mut session = affinity.session("c", &session_request("a")).await.unwrap();
+                session.take()
+            }
+        });
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert!(!waiting.is_finished());
+        session.take();
+        session.keep(2);
+        assert_eq!(waiting.await.unwrap(), Some(2));
+    }
+
+    #[tokio::test]
+    async fn idle_sessions_end() {
+        let affinity = affinity(Duration::from_millis(10), 1);
+        let session = affinity.session("c", &session_request("a")).await.unwrap();
+        session.keep(1);
+        // There's no room for another session until the first has ended.
+        assert!(affinity.session("c", &session_request("b")).await.is_none());
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(affinity.sessions.lock().unwrap().is_empty());
+        let mut session = affinity.session("c", &session_request("b")).await.unwrap();
+        assert_eq!(session.take(), None);
+    }
+}
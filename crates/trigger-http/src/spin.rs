@@ -6,10 +6,12 @@ use hyper::{Request, Response};
 use spin_factors::RuntimeFactors;
 use spin_http::body;
 use spin_http::routes::RouteMatch;
+use spin_trigger::core_dump;
 use spin_world::v1::http_types;
 use tracing::{instrument, Level};
 
 use crate::{
+    core_dump_event,
     headers::{append_headers, prepare_request_headers},
     server::HttpExecutor,
     Body, TriggerInstanceBuilder,
@@ -50,6 +52,7 @@ impl HttpExecutor for SpinHttpExecutor {
 
         let (parts, body) = req.into_parts();
         let bytes = body.collect().await?.to_bytes().to_vec();
+        let event = core_dump_event(route_match, &parts.method, &parts.uri);
 
         let method = if let Some(method) = convert_method(&parts.method) {
             method
@@ -77,7 +80,10 @@ impl HttpExecutor for SpinHttpExecutor {
             body: Some(bytes),
         };
 
-        let (resp,) = func.call_async(&mut store, (req,)).await?;
+        let (resp,) = func
+            .call_async(&mut store, (req,))
+            .await
+            .map_err(|err| core_dump::capture(&mut store, err, "http", component_id, event))?;
 
         if resp.status < 100 || resp.status > 600 {
             tracing::error!("malformed HTTP status code");
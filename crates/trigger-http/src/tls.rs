@@ -1,10 +1,18 @@
+use anyhow::Context;
 use rustls_pemfile::private_key;
+use serde::Deserialize;
 use std::{
+    collections::HashMap,
     fs, io,
     path::{Path, PathBuf},
     sync::Arc,
 };
-use tokio_rustls::{rustls, TlsAcceptor};
+use tokio_rustls::{
+    rustls::{self, server::WebPkiClientVerifier},
+    TlsAcceptor,
+};
+
+use crate::{canary::CanaryConfig, mirror::MirrorConfig};
 
 // TODO: dedupe with spin-factor-outbound-networking (spin-tls crate?)
 
@@ -15,6 +23,18 @@ pub struct TlsConfig {
     pub cert_path: PathBuf,
     /// Path to TLS key.
     pub key_path: PathBuf,
+    /// How clients are authenticated with certificates, if they are.
+    pub client_auth: Option<ClientAuth>,
+}
+
+/// Client certificate verification for a TLS server.
+#[derive(Clone)]
+pub struct ClientAuth {
+    /// Path to the PEM bundle of the CAs client certificates must be issued by.
+    pub ca_path: PathBuf,
+    /// Whether clients must present a certificate. Otherwise clients are asked
+    /// for one, and connections without one are still accepted.
+    pub required: bool,
 }
 
 impl TlsConfig {
@@ -23,8 +43,12 @@ impl TlsConfig {
         let certs = load_certs(&self.cert_path)?;
         let private_key = load_key(&self.key_path)?;
 
-        let cfg = rustls::ServerConfig::builder()
-            .with_no_client_auth()
+        let builder = rustls::ServerConfig::builder();
+        let builder = match &self.client_auth {
+            Some(client_auth) => builder.with_client_cert_verifier(client_auth.verifier()?),
+            None => builder.with_no_client_auth(),
+        };
+        let cfg = builder
             .with_single_cert(certs, private_key)
             .map_err(|e| anyhow::anyhow!("{}", e))?;
 
@@ -32,6 +56,111 @@ impl TlsConfig {
     }
 }
 
+impl ClientAuth {
+    fn verifier(&self) -> anyhow::Result<Arc<dyn rustls::server::danger::ClientCertVerifier>> {
+        let mut roots = rustls::RootCertStore::empty();
+        for cert in load_certs(&self.ca_path)? {
+            roots
+                .add(cert)
+                .with_context(|| format!("invalid client CA certificate in {:?}", self.ca_path))?;
+        }
+        let builder = WebPkiClientVerifier::builder(Arc::new(roots));
+        let builder = if self.required {
+            builder
+        } else {
+            builder.allow_unauthenticated()
+        };
+        builder
+            .build()
+            .with_context(|| format!("invalid client CA bundle {:?}", self.ca_path))
+    }
+}
+
+/// The `[http_server]` section of the runtime config file.
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct HttpServerRuntimeConfig {
+    #[serde(default)]
+    tls: Option<TlsRuntimeConfig>,
+    /// Component ID -> canary
+    #[serde(default)]
+    pub canaries: HashMap<String, CanaryConfig>,
+    /// Component ID -> mirror
+    #[serde(default)]
+    pub mirrors: HashMap<String, MirrorConfig>,
+}
+
+/// The `[http_server.tls]` section of the runtime config file.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TlsRuntimeConfig {
+    /// Used if `--tls-cert` isn't given.
+    #[serde(default)]
+    cert_path: Option<PathBuf>,
+    /// Used if `--tls-key` isn't given.
+    #[serde(default)]
+    key_path: Option<PathBuf>,
+    #[serde(default)]
+    client_ca_path: Option<PathBuf>,
+    #[serde(default)]
+    client_auth: Option<ClientAuthMode>,
+}
+
+#[derive(Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum ClientAuthMode {
+    /// Refuse connections without a verified client certificate.
+    #[default]
+    Require,
+    /// Accept connections without a client certificate, but verify any that
+    /// are presented.
+    Request,
+}
+
+impl HttpServerRuntimeConfig {
+    /// Completes the TLS config given on the command line, if any, with the
+    /// runtime config's.
+    pub fn apply(
+        self,
+        tls_config: Option<TlsConfig>,
+        runtime_config_dir: Option<&Path>,
+    ) -> anyhow::Result<Option<TlsConfig>> {
+        let Some(tls) = self.tls else {
+            return Ok(tls_config);
+        };
+        let resolve = |path: PathBuf| match runtime_config_dir {
+            Some(dir) => dir.join(path),
+            None => path,
+        };
+        let (cert_path, key_path) = match (tls_config, tls.cert_path, tls.key_path) {
+            (Some(tls_config), _, _) => (tls_config.cert_path, tls_config.key_path),
+            (None, Some(cert_path), Some(key_path)) => (resolve(cert_path), resolve(key_path)),
+            (None, None, None) if tls.client_ca_path.is_none() => return Ok(None),
+            (None, None, None) => anyhow::bail!(
+                "[http_server.tls] verifies client certificates, but no server certificate is set: use `--tls-cert` and `--tls-key` or set `cert_path` and `key_path`"
+            ),
+            (None, _, _) => {
+                anyhow::bail!("[http_server.tls] must set both `cert_path` and `key_path`")
+            }
+        };
+        let client_auth = match (tls.client_ca_path, tls.client_auth) {
+            (Some(ca_path), mode) => Some(ClientAuth {
+                ca_path: resolve(ca_path),
+                required: matches!(mode.unwrap_or_default(), ClientAuthMode::Require),
+            }),
+            (None, Some(_)) => {
+                anyhow::bail!("[http_server.tls] sets `client_auth` but no `client_ca_path`")
+            }
+            (None, None) => None,
+        };
+        Ok(Some(TlsConfig {
+            cert_path,
+            key_path,
+            client_auth,
+        }))
+    }
+}
+
 // load_certs parse and return the certs from the provided file
 fn load_certs(
     path: impl AsRef<Path>,
@@ -128,4 +257,60 @@ mod tests {
         let keys = load_key(path);
         assert!(keys.is_ok());
     }
+
+    fn runtime_config(toml: &str) -> HttpServerRuntimeConfig {
+        toml::from_str(toml).unwrap()
+    }
+
+    #[test]
+    fn test_runtime_config_adds_client_auth() {
+        let cli_config = TlsConfig {
+            cert_path: "cli-cert.pem".into(),
+            key_path: "cli-key.pem".into(),
+            client_auth: None,
+        };
+        let config = runtime_config(
+            r#"
+            [tls]
+            cert_path = "cert.pem"
+            key_path = "key.pem"
+            client_ca_path = "ca.pem"
+            client_auth = "request"
+            "#,
+        )
+        .apply(Some(cli_config), Some(Path::new("/etc/spin")))
+        .unwrap()
+        .unwrap();
+        assert_eq!(config.cert_path, Path::new("cli-cert.pem"));
+        let client_auth = config.client_auth.unwrap();
+        assert_eq!(client_auth.ca_path, Path::new("/etc/spin/ca.pem"));
+        assert!(!client_auth.required);
+
+        let config = runtime_config(
+            r#"
+            [tls]
+            cert_path = "cert.pem"
+            key_path = "key.pem"
+            client_ca_path = "ca.pem"
+            "#,
+        )
+        .apply(None, Some(Path::new("/etc/spin")))
+        .unwrap()
+        .unwrap();
+        assert_eq!(config.key_path, Path::new("/etc/spin/key.pem"));
+        assert!(config.client_auth.unwrap().required);
+    }
+
+    #[test]
+    fn test_runtime_config_requires_server_cert() {
+        let invalid = [
+            "[tls]\nclient_ca_path = \"ca.pem\"",
+            "[tls]\ncert_path = \"cert.pem\"",
+            "[tls]\ncert_path = \"cert.pem\"\nkey_path = \"key.pem\"\nclient_auth = \"require\"",
+        ];
+        for toml in invalid {
+            assert!(runtime_config(toml).apply(None, None).is_err(), "{toml}");
+        }
+        assert!(runtime_config("").apply(None, None).unwrap().is_none());
+    }
 }
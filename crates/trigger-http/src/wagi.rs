@@ -1,16 +1,46 @@
-use std::{io::Cursor, net::SocketAddr};
+use std::{
+    future::Future,
+    io::Cursor,
+    net::SocketAddr,
+    pin::Pin,
+    task::{ready, Context as TaskContext, Poll},
+};
 
-use anyhow::{ensure, Context, Result};
-use http_body_util::BodyExt;
-use hyper::{Request, Response};
+use anyhow::{anyhow, ensure, Context, Result};
+use http_body_util::{combinators::BoxBody, BodyExt};
+use hyper::{
+    body::{Bytes, Frame},
+    Request, Response,
+};
 use spin_factor_wasi::WasiFactor;
 use spin_factors::RuntimeFactors;
 use spin_http::{config::WagiTriggerConfig, routes::RouteMatch, wagi};
-use tracing::{instrument, Level};
-use wasmtime_wasi::pipe::MemoryOutputPipe;
-use wasmtime_wasi_http::body::HyperIncomingBody as Body;
+use spin_trigger::core_dump;
+use spin_world::async_trait;
+use tokio::{
+    sync::mpsc::{self, error::TrySendError},
+    task::{self, JoinHandle},
+};
+use tracing::{instrument, Instrument, Level};
+use wasmtime_wasi::{HostOutputStream, StdoutStream, StreamError, StreamResult, Subscribe};
+use wasmtime_wasi_http::{bindings::http::types::ErrorCode, body::HyperIncomingBody as Body};
 
-use crate::{headers::compute_default_headers, server::HttpExecutor, TriggerInstanceBuilder};
+use crate::{
+    core_dump_event,
+    headers::{compute_default_headers, REQUEST_ID},
+    server::HttpExecutor,
+    RequestId, TriggerInstanceBuilder,
+};
+
+/// How many chunks of stdout may be waiting to be sent to the client before
+/// the component's writes wait.
+const STDOUT_CHUNKS: usize = 16;
+
+/// The most bytes the component may write to stdout at once.
+const MAX_STDOUT_CHUNK_LEN: usize = 64 * 1024;
+
+/// The most bytes the CGI headers written to stdout may take up.
+const MAX_HEADERS_LEN: usize = 64 * 1024;
 
 #[derive(Clone)]
 pub struct WagiHttpExecutor {
@@ -47,6 +77,7 @@ impl HttpExecutor for WagiHttpExecutor {
             .replace("${ARGS}", &args);
 
         let (parts, body) = req.into_parts();
+        let event = core_dump_event(route_match, &parts.method, &parts.uri);
 
         let body = body.collect().await?.to_bytes().to_vec();
         let len = body.len();
@@ -72,8 +103,11 @@ impl HttpExecutor for WagiHttpExecutor {
         for (keys, val) in compute_default_headers(&parts.uri, host, route_match, client_addr)? {
             headers.insert(keys[1].to_string(), val);
         }
+        if let Some(request_id) = parts.extensions.get::<RequestId>() {
+            headers.insert(REQUEST_ID[1].to_string(), request_id.to_string());
+        }
 
-        let stdout = MemoryOutputPipe::new(usize::MAX);
+        let (stdout, mut stdout_chunks) = mpsc::channel(STDOUT_CHUNKS);
 
         let wasi_builder = instance_builder
             .factor_builder::<WasiFactor>()
@@ -83,34 +117,160 @@ impl HttpExecutor for WagiHttpExecutor {
         wasi_builder.args(argv.split(' '));
         wasi_builder.env(headers);
         wasi_builder.stdin_pipe(Cursor::new(body));
-        wasi_builder.stdout(stdout.clone());
+        wasi_builder.stdout(StdoutSender(stdout));
 
         let (instance, mut store) = instance_builder.instantiate(()).await?;
 
         let command = wasmtime_wasi::bindings::Command::new(&mut store, &instance)?;
 
-        tracing::trace!("Calling Wasm entry point");
-        if let Err(()) = command
-            .wasi_cli_run()
-            .call_run(&mut store)
-            .await
-            .or_else(ignore_successful_proc_exit_trap)?
-        {
-            tracing::error!("Wagi main function returned unsuccessful result");
+        let component_id = component.to_string();
+        let run = task::spawn(
+            async move {
+                tracing::trace!("Calling Wasm entry point");
+                if let Err(()) = command
+                    .wasi_cli_run()
+                    .call_run(&mut store)
+                    .await
+                    .or_else(ignore_successful_proc_exit_trap)
+                    .map_err(|err| {
+                        core_dump::capture(&mut store, err, "http", &component_id, event)
+                    })?
+                {
+                    tracing::error!("Wagi main function returned unsuccessful result");
+                }
+                tracing::info!("Wagi execution complete");
+                // Dropping the store closes stdout.
+                Ok(())
+            }
+            .in_current_span(),
+        );
+
+        // Respond as soon as the CGI headers are complete, streaming the rest
+        // of stdout as the body.
+        let mut stdout = Vec::new();
+        let (headers, body_start) = loop {
+            if let Some(split) = wagi::split_headers(&stdout) {
+                break split;
+            }
+            ensure!(
+                stdout.len() <= MAX_HEADERS_LEN,
+                "The {component:?} component wrote more than {MAX_HEADERS_LEN} bytes \
+                 of CGI headers to stdout"
+            );
+            match stdout_chunks.recv().await {
+                Some(chunk) => stdout.extend_from_slice(&chunk),
+                None => {
+                    run.await.context("guest invocation panicked")??;
+                    ensure!(
+                        !stdout.is_empty(),
+                        "The {component:?} component is configured to use the WAGI executor \
+                         but did not write to stdout. Check the `executor` in spin.toml."
+                    );
+                    return wagi::compose_response(&stdout);
+                }
+            }
+        };
+
+        let body = StdoutBody {
+            first: Some(Bytes::from(stdout).slice(body_start..)),
+            chunks: stdout_chunks,
+            run: Some(run),
+        };
+        wagi::compose_response_with_body(headers, BoxBody::new(body))
+    }
+}
+
+/// The stdout of a WAGI component, which sends what the component writes to
+/// be streamed to the client.
+#[derive(Clone)]
+struct StdoutSender(mpsc::Sender<Bytes>);
+
+impl StdoutStream for StdoutSender {
+    fn stream(&self) -> Box<dyn HostOutputStream> {
+        Box::new(self.clone())
+    }
+
+    fn isatty(&self) -> bool {
+        false
+    }
+}
+
+impl HostOutputStream for StdoutSender {
+    fn write(&mut self, bytes: Bytes) -> StreamResult<()> {
+        if bytes.is_empty() {
+            return Ok(());
         }
-        tracing::info!("Wagi execution complete");
+        self.0.try_send(bytes).map_err(|err| match err {
+            TrySendError::Closed(_) => StreamError::Closed,
+            TrySendError::Full(_) => {
+                StreamError::Trap(anyhow!("stdout was written without checking for capacity"))
+            }
+        })
+    }
 
-        // Drop the store so we're left with a unique reference to `stdout`:
-        drop(store);
+    fn flush(&mut self) -> StreamResult<()> {
+        // Writes are sent straight away.
+        Ok(())
+    }
 
-        let stdout = stdout.try_into_inner().unwrap();
-        ensure!(
-            !stdout.is_empty(),
-            "The {component:?} component is configured to use the WAGI executor \
-             but did not write to stdout. Check the `executor` in spin.toml."
-        );
+    fn check_write(&mut self) -> StreamResult<usize> {
+        if self.0.is_closed() {
+            return Err(StreamError::Closed);
+        }
+        if self.0.capacity() == 0 {
+            return Ok(0);
+        }
+        Ok(MAX_STDOUT_CHUNK_LEN)
+    }
+}
+
+#[async_trait]
+impl Subscribe for StdoutSender {
+    async fn ready(&mut self) {
+        // Wait for the client to catch up, or to go away.
+        let _ = self.0.reserve().await;
+    }
+}
+
+/// The body of a WAGI response: the rest of stdout, once the CGI headers have
+/// been parsed.
+struct StdoutBody {
+    /// What was read of the body along with the headers.
+    first: Option<Bytes>,
+    chunks: mpsc::Receiver<Bytes>,
+    /// The component's run, which finishes once stdout has closed.
+    run: Option<JoinHandle<Result<()>>>,
+}
 
-        wagi::compose_response(&stdout)
+impl hyper::body::Body for StdoutBody {
+    type Data = Bytes;
+    type Error = ErrorCode;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, ErrorCode>>> {
+        let this = self.get_mut();
+        if let Some(first) = this.first.take().filter(|first| !first.is_empty()) {
+            return Poll::Ready(Some(Ok(Frame::data(first))));
+        }
+        if let Some(chunk) = ready!(this.chunks.poll_recv(cx)) {
+            return Poll::Ready(Some(Ok(Frame::data(chunk))));
+        }
+        let Some(run) = this.run.as_mut() else {
+            return Poll::Ready(None);
+        };
+        let result = ready!(Pin::new(run).poll(cx));
+        this.run = None;
+        match result.context("guest invocation panicked").and_then(|r| r) {
+            Ok(()) => Poll::Ready(None),
+            Err(err) => {
+                // The response has started, so the best that can be done is
+                // to cut its body short.
+                tracing::warn!("component error after response: {err:?}");
+                Poll::Ready(Some(Err(ErrorCode::InternalError(Some(err.to_string())))))
+            }
+        }
     }
 }
 
@@ -4,17 +4,22 @@ use anyhow::{anyhow, Context, Result};
 use futures::TryFutureExt;
 use http::{HeaderName, HeaderValue};
 use hyper::{Request, Response};
+use spin_core::Instance;
 use spin_factor_outbound_http::wasi_2023_10_18::exports::wasi::http::incoming_handler as incoming_handler2023_10_18;
 use spin_factor_outbound_http::wasi_2023_11_10::exports::wasi::http::incoming_handler as incoming_handler2023_11_10;
 use spin_factors::RuntimeFactors;
 use spin_http::routes::RouteMatch;
 use spin_http::trigger::HandlerType;
+use spin_trigger::core_dump;
 use tokio::{sync::oneshot, task};
 use tracing::{instrument, Instrument, Level};
 use wasmtime_wasi_http::bindings::http::types::Scheme;
 use wasmtime_wasi_http::{bindings::Proxy, body::HyperIncomingBody as Body, WasiHttpView};
 
-use crate::{headers::prepare_request_headers, server::HttpExecutor, TriggerInstanceBuilder};
+use crate::{
+    core_dump_event, headers::prepare_request_headers, server::HttpExecutor, Store,
+    TriggerInstanceBuilder,
+};
 
 /// An [`HttpExecutor`] that uses the `wasi:http/incoming-handler` interface.
 #[derive(Clone)]
@@ -35,8 +40,28 @@ impl HttpExecutor for WasiHttpExecutor {
 
         tracing::trace!("Executing request using the Wasi executor for component {component_id}");
 
-        let (instance, mut store) = instance_builder.instantiate(()).await?;
+        let (instance, store) = instance_builder.instantiate(()).await?;
+        self.handle(instance, store, route_match, req, client_addr, |_, _| {})
+            .await
+    }
+}
 
+impl WasiHttpExecutor {
+    /// Handles a request with an instance of the component.
+    ///
+    /// If the guest returns without failing, the instance and its store are
+    /// passed to `returned`, which may keep them to handle later requests.
+    pub(crate) async fn handle<F: RuntimeFactors>(
+        &self,
+        instance: Instance,
+        mut store: Store<F>,
+        route_match: &RouteMatch,
+        mut req: Request<Body>,
+        client_addr: SocketAddr,
+        returned: impl FnOnce(Instance, Store<F>) + Send + 'static,
+    ) -> Result<Response<Body>> {
+        let component_id = route_match.component_id();
+        let event = core_dump_event(route_match, req.method(), req.uri());
         let headers = prepare_request_headers(&req, route_match, client_addr)?;
         req.headers_mut().clear();
         req.headers_mut()
@@ -98,6 +123,7 @@ impl HttpExecutor for WasiHttpExecutor {
         };
 
         let span = tracing::debug_span!("execute_wasi");
+        let component_id = component_id.to_string();
         let handle = task::spawn(
             async move {
                 let result = match handler {
@@ -127,7 +153,19 @@ impl HttpExecutor for WasiHttpExecutor {
                     store.data().core_state().memory_consumed()
                 );
 
-                result
+                match result {
+                    Ok(()) => {
+                        returned(instance, store);
+                        Ok(())
+                    }
+                    Err(err) => Err(core_dump::capture(
+                        &mut store,
+                        err,
+                        "http",
+                        &component_id,
+                        event,
+                    )),
+                }
             }
             .in_current_span(),
         );
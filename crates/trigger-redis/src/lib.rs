@@ -1,22 +1,60 @@
-use std::{collections::HashMap, sync::Arc};
+mod quarantine;
+
+use std::{
+    collections::HashMap,
+    num::NonZeroUsize,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
 
 use anyhow::Context;
-use futures::{StreamExt, TryFutureExt};
+use futures::StreamExt;
 use redis::{Client, Msg};
-use serde::Deserialize;
+use serde::{de::IgnoredAny, Deserialize};
+use spin_core::Trap;
+use spin_factor_context::ContextFactor;
 use spin_factor_variables::VariablesFactor;
 use spin_factors::RuntimeFactors;
-use spin_trigger::{cli::NoCliArgs, App, Trigger, TriggerApp};
+use spin_trigger::{
+    cli::NoCliArgs, core_dump, saturation::SaturationTracker, App, ConcurrencyLimitExceeded,
+    Trigger, TriggerApp,
+};
 use spin_world::exports::fermyon::spin::inbound_redis;
+use tokio::sync::{
+    mpsc::{self, error::TrySendError},
+    Semaphore,
+};
 use tracing::{instrument, Level};
 
-pub struct RedisTrigger;
+use quarantine::{DeadLetterConfig, Quarantine, QuarantinePolicy};
+
+/// How many messages may wait for each handler before further messages for
+/// it are dropped. Redis pub/sub has no backpressure, so a handler which falls
+/// behind would otherwise queue messages without bound.
+const MAX_QUEUED_MESSAGES: usize = 1024;
+
+pub struct RedisTrigger {
+    saturation: SaturationTracker,
+}
+
+impl RedisTrigger {
+    /// The [`SaturationTracker`] for messages handled by this trigger.
+    pub fn saturation(&self) -> &SaturationTracker {
+        &self.saturation
+    }
+}
 
 /// Redis trigger metadata.
 #[derive(Clone, Debug, Default, Deserialize)]
 #[serde(deny_unknown_fields)]
 struct TriggerMetadata {
     address: String,
+    // Applied by the executor; see `App::trigger_concurrency_limit`.
+    #[serde(default, rename = "concurrency")]
+    _concurrency: Option<IgnoredAny>,
 }
 
 /// Redis trigger configuration.
@@ -26,7 +64,22 @@ struct TriggerConfig {
     /// Component ID to invoke
     component: String,
     /// Channel to subscribe to
-    channel: String,
+    channel: Option<String>,
+    /// Additional channels to subscribe to
+    #[serde(default)]
+    channels: Vec<String>,
+    /// Channel patterns to subscribe to, as for Redis `PSUBSCRIBE`
+    #[serde(default)]
+    patterns: Vec<String>,
+    /// The maximum number of messages from each channel or pattern that the
+    /// component handles at once. Defaults to 1, which preserves message order.
+    max_concurrency: Option<NonZeroUsize>,
+    /// How long the component may run for each message, in milliseconds,
+    /// before it is interrupted. If unset, there is no limit.
+    timeout_ms: Option<u64>,
+    /// Where to quarantine messages which repeatedly trap or time out the
+    /// component. If unset, such messages are never quarantined.
+    dead_letter: Option<DeadLetterConfig>,
     /// Optionally override address for trigger
     address: Option<String>,
 }
@@ -39,7 +92,9 @@ impl<F: RuntimeFactors> Trigger<F> for RedisTrigger {
     type InstanceState = ();
 
     fn new(_cli_args: Self::CliArgs, _app: &App) -> anyhow::Result<Self> {
-        Ok(Self)
+        Ok(Self {
+            saturation: SaturationTracker::new(),
+        })
     }
 
     async fn run(self, trigger_app: spin_trigger::TriggerApp<Self, F>) -> anyhow::Result<()> {
@@ -61,11 +116,11 @@ impl<F: RuntimeFactors> Trigger<F> for RedisTrigger {
                 format!("failed to resolve redis trigger default address {default_address_expr:?}")
             })?;
 
-        // Maps <server address> -> <channel> -> <component IDs>
-        let mut server_channel_components: HashMap<String, ChannelComponents> = HashMap::new();
+        // Maps <server address> -> <subscription> -> <handlers>
+        let mut server_subscriptions: HashMap<String, Subscriptions> = HashMap::new();
 
         // Resolve trigger configs before starting any subscribers
-        for (_, config) in app
+        for (trigger_id, config) in app
             .trigger_configs::<TriggerConfig>(trigger_type)?
             .into_iter()
             .collect::<Vec<_>>()
@@ -82,29 +137,69 @@ impl<F: RuntimeFactors> Trigger<F> for RedisTrigger {
                     )
                 })?;
 
-            let channel_expr = &config.channel;
-            let channel = app_variables
-                .resolve_expression(channel_expr.clone())
-                .await
-                .with_context(|| {
-                    format!(
-                        "failed to resolve redis trigger channel {channel_expr:?} for component {component_id}"
-                    )
-                })?;
-
-            server_channel_components
-                .entry(address)
-                .or_default()
-                .entry(channel)
-                .or_default()
-                .push(component_id);
+            let channels = config.channel.iter().chain(&config.channels);
+            let subscription_exprs = channels
+                .map(|expr| (expr, false))
+                .chain(config.patterns.iter().map(|expr| (expr, true)));
+            let max_concurrency = config.max_concurrency.map_or(1, NonZeroUsize::get);
+            let timeout = config.timeout_ms.map(Duration::from_millis);
+            let quarantine = match &config.dead_letter {
+                Some(dead_letter) => {
+                    Some(dead_letter.resolve(app_variables).await.with_context(|| {
+                        format!("invalid redis trigger `dead_letter` for component {component_id}")
+                    })?)
+                }
+                None => None,
+            };
+
+            let mut subscribed = false;
+            for (expr, is_pattern) in subscription_exprs {
+                let resolved = app_variables
+                    .resolve_expression(expr.clone())
+                    .await
+                    .with_context(|| {
+                        format!(
+                            "failed to resolve redis trigger channel {expr:?} for component {component_id}"
+                        )
+                    })?;
+                let subscription = if is_pattern {
+                    Subscription::Pattern(resolved)
+                } else {
+                    Subscription::Channel(resolved)
+                };
+                server_subscriptions
+                    .entry(address.clone())
+                    .or_default()
+                    .entry(subscription)
+                    .or_default()
+                    .push(Handler {
+                        trigger_id: trigger_id.to_owned(),
+                        component_id: component_id.clone(),
+                        max_concurrency,
+                        timeout,
+                        quarantine: quarantine.clone(),
+                    });
+                subscribed = true;
+            }
+            if !subscribed {
+                anyhow::bail!(
+                    "redis trigger for component {component_id} must set at least one of `channel`, `channels` or `patterns`"
+                );
+            }
         }
 
         // Start subscriber(s)
         let trigger_app = Arc::new(trigger_app);
+        let unsubscribed = Arc::new(AtomicUsize::new(server_subscriptions.len()));
         let mut subscriber_tasks = Vec::new();
-        for (address, channel_components) in server_channel_components {
-            let subscriber = Subscriber::new(address, trigger_app.clone(), channel_components)?;
+        for (address, subscriptions) in server_subscriptions {
+            let subscriber = Subscriber::new(
+                address,
+                trigger_app.clone(),
+                subscriptions,
+                self.saturation.clone(),
+                unsubscribed.clone(),
+            )?;
             let task = tokio::spawn(subscriber.run_listener());
             subscriber_tasks.push(task);
         }
@@ -113,34 +208,81 @@ impl<F: RuntimeFactors> Trigger<F> for RedisTrigger {
         let (res, _, _) = futures::future::select_all(subscriber_tasks).await;
         res?
     }
+
+    fn lifecycle_hook_instance_state() -> Option<Self::InstanceState> {
+        Some(())
+    }
+}
+
+/// A subscription to messages from a Redis server.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+enum Subscription {
+    /// Messages published to exactly this channel.
+    Channel(String),
+    /// Messages published to any channel matching this pattern.
+    Pattern(String),
+}
+
+impl std::fmt::Display for Subscription {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Channel(channel) => f.write_str(channel),
+            Self::Pattern(pattern) => write!(f, "{pattern} (pattern)"),
+        }
+    }
+}
+
+/// A component handling the messages of a [`Subscription`].
+#[derive(Clone, Debug)]
+struct Handler {
+    trigger_id: String,
+    component_id: String,
+    max_concurrency: usize,
+    timeout: Option<Duration>,
+    quarantine: Option<QuarantinePolicy>,
 }
 
-/// Maps <channel> -> <component IDs>
-type ChannelComponents = HashMap<String, Vec<String>>;
+/// Maps <subscription> -> <handlers>
+type Subscriptions = HashMap<Subscription, Vec<Handler>>;
+
+/// A message received from a Redis server.
+struct Message {
+    channel: String,
+    payload: Arc<[u8]>,
+    /// Trace context embedded in the payload by the publisher.
+    trace_context: Vec<(String, String)>,
+}
 
 /// Subscribes to channels from a single Redis server.
 struct Subscriber<F: RuntimeFactors> {
     client: Client,
     trigger_app: Arc<TriggerApp<RedisTrigger, F>>,
-    channel_components: ChannelComponents,
+    subscriptions: Subscriptions,
+    saturation: SaturationTracker,
+    /// The number of subscribers yet to finish subscribing.
+    unsubscribed: Arc<AtomicUsize>,
 }
 
 impl<F: RuntimeFactors> Subscriber<F> {
     fn new(
         address: String,
         trigger_app: Arc<TriggerApp<RedisTrigger, F>>,
-        channel_components: ChannelComponents,
+        subscriptions: Subscriptions,
+        saturation: SaturationTracker,
+        unsubscribed: Arc<AtomicUsize>,
     ) -> anyhow::Result<Self> {
         let client = Client::open(address)?;
         Ok(Self {
             client,
             trigger_app,
-            channel_components,
+            subscriptions,
+            saturation,
+            unsubscribed,
         })
     }
 
     async fn run_listener(self) -> anyhow::Result<()> {
-        let server_addr = &self.client.get_connection_info().addr;
+        let server_addr = self.client.get_connection_info().addr.to_string();
 
         tracing::info!("Connecting to Redis server at {server_addr}");
         let mut pubsub = self
@@ -151,73 +293,346 @@ impl<F: RuntimeFactors> Subscriber<F> {
 
         println!("Active Channels on {server_addr}:");
 
-        // Subscribe to channels
-        for (channel, components) in &self.channel_components {
-            tracing::info!("Subscribing to {channel:?} on {server_addr}");
-            pubsub.subscribe(channel).await.with_context(|| {
-                format!("Redis trigger failed to subscribe to channel {channel:?} on {server_addr}")
+        // Subscribe to channels and patterns, starting a dispatcher for each handler
+        let mut dispatchers: HashMap<Subscription, Vec<Queue>> = HashMap::new();
+        for (subscription, handlers) in &self.subscriptions {
+            tracing::info!("Subscribing to {subscription} on {server_addr}");
+            let result = match subscription {
+                Subscription::Channel(channel) => pubsub.subscribe(channel).await,
+                Subscription::Pattern(pattern) => pubsub.psubscribe(pattern).await,
+            };
+            result.with_context(|| {
+                format!("Redis trigger failed to subscribe to {subscription} on {server_addr}")
             })?;
-            println!("\t{server_addr}/{channel}: [{}]", components.join(","));
+            let component_ids = handlers.iter().map(|h| h.component_id.as_str());
+            println!(
+                "\t{server_addr}/{subscription}: [{}]",
+                component_ids.collect::<Vec<_>>().join(",")
+            );
+
+            for handler in handlers {
+                let (queue, rx) = Queue::new(&handler.component_id);
+                let dispatcher = Dispatcher {
+                    server_addr: server_addr.clone(),
+                    subscription: subscription.clone(),
+                    handler: handler.clone(),
+                    quarantine: handler
+                        .quarantine
+                        .clone()
+                        .map(|policy| Quarantine::new(self.client.clone(), policy)),
+                    trigger_app: self.trigger_app.clone(),
+                    saturation: self.saturation.clone(),
+                };
+                tokio::spawn(dispatcher.run(rx));
+                dispatchers
+                    .entry(subscription.clone())
+                    .or_default()
+                    .push(queue);
+            }
+        }
+
+        if self.unsubscribed.fetch_sub(1, Ordering::AcqRel) == 1 {
+            spin_trigger::systemd::notify_ready("Subscribed to Redis channels");
         }
 
         let mut message_stream = pubsub.on_message();
         while let Some(msg) = message_stream.next().await {
-            if let Err(err) = self.handle_message(msg).await {
+            if let Err(err) = route_message(&dispatchers, msg) {
                 tracing::error!("Error handling message from {server_addr}: {err}");
             }
         }
         Err(anyhow::anyhow!("disconnected from {server_addr}"))
     }
+}
+
+/// Queues the message with each handler of the subscription it was received for.
+fn route_message(dispatchers: &HashMap<Subscription, Vec<Queue>>, msg: Msg) -> anyhow::Result<()> {
+    let channel = msg.get_channel_name().to_string();
+    let subscription = if msg.from_pattern() {
+        Subscription::Pattern(msg.get_pattern()?)
+    } else {
+        Subscription::Channel(channel.clone())
+    };
+    let Some(queues) = dispatchers.get(&subscription) else {
+        anyhow::bail!("message from unexpected subscription {subscription}");
+    };
+    let (trace_context, payload) =
+        spin_telemetry::split_embedded_trace_context(msg.get_payload_bytes());
+    let message = Arc::new(Message {
+        channel,
+        payload: payload.into(),
+        trace_context,
+    });
+    for queue in queues {
+        queue.push(&subscription, message.clone());
+    }
+    Ok(())
+}
+
+/// The messages waiting for one handler's [`Dispatcher`].
+struct Queue {
+    component_id: String,
+    sender: mpsc::Sender<Arc<Message>>,
+}
+
+impl Queue {
+    fn new(component_id: &str) -> (Self, mpsc::Receiver<Arc<Message>>) {
+        let (sender, receiver) = mpsc::channel(MAX_QUEUED_MESSAGES);
+        let queue = Self {
+            component_id: component_id.to_owned(),
+            sender,
+        };
+        (queue, receiver)
+    }
+
+    /// Queues the message, or drops it if the queue is full.
+    fn push(&self, subscription: &Subscription, message: Arc<Message>) {
+        match self.sender.try_send(message) {
+            Ok(()) => (),
+            Err(TrySendError::Full(message)) => {
+                let component_id = self.component_id.as_str();
+                let subscription = subscription.to_string();
+                tracing::warn!(
+                    "Dropping a message from {} as component {component_id} has {MAX_QUEUED_MESSAGES} messages waiting",
+                    message.channel
+                );
+                spin_telemetry::metrics::monotonic_counter!(
+                    spin.redis.dropped_messages = 1,
+                    subscription = subscription,
+                    component_id = component_id
+                );
+            }
+            // Dispatchers only stop when the listener does.
+            Err(TrySendError::Closed(_)) => (),
+        }
+    }
+}
+
+/// Runs one component's handler for messages from one subscription, with
+/// that handler's concurrency limit.
+struct Dispatcher<F: RuntimeFactors> {
+    server_addr: String,
+    subscription: Subscription,
+    handler: Handler,
+    quarantine: Option<Quarantine>,
+    trigger_app: Arc<TriggerApp<RedisTrigger, F>>,
+    saturation: SaturationTracker,
+}
+
+impl<F: RuntimeFactors> Dispatcher<F> {
+    async fn run(self, mut messages: mpsc::Receiver<Arc<Message>>) {
+        let this = Arc::new(self);
+        let limit = Arc::new(Semaphore::new(this.handler.max_concurrency));
+        while let Some(message) = messages.recv().await {
+            // The number of permits never changes, so the semaphore is never closed.
+            let permit = limit.clone().acquire_owned().await.unwrap();
+            let this = this.clone();
+            tokio::spawn(async move {
+                this.dispatch(&message).await;
+                drop(permit);
+            });
+        }
+    }
+
+    /// Handles a message unless it has been quarantined, and quarantines it
+    /// if it traps or times out the component too many times.
+    async fn dispatch(&self, message: &Message) {
+        let component_id = self.handler.component_id.as_str();
+        let subscription = self.subscription.to_string();
+        if let Some(quarantine) = &self.quarantine {
+            if quarantine.is_quarantined(message) {
+                tracing::debug!(
+                    "Dropping quarantined message from {} for component {component_id}",
+                    message.channel
+                );
+                spin_telemetry::metrics::monotonic_counter!(
+                    spin.redis.dropped_messages = 1,
+                    subscription = subscription,
+                    component_id = component_id
+                );
+                return;
+            }
+        }
+
+        tracing::trace!("Executing Redis component {component_id}");
+        let err = match self.handle_message(message).await {
+            Ok(()) => {
+                if let Some(quarantine) = &self.quarantine {
+                    quarantine.record_success(message);
+                }
+                return;
+            }
+            Err(err) => err,
+        };
+        tracing::info!("Component {component_id} handler failed: {err}");
+
+        // Errors returned by the handler are its own business; only traps
+        // and timeouts suggest a poison message.
+        let reason = match err.downcast_ref::<Trap>() {
+            Some(Trap::Interrupt) => "timeout",
+            Some(_) => "trap",
+            None => return,
+        };
+        spin_telemetry::metrics::monotonic_counter!(
+            spin.redis.failed_messages = 1,
+            subscription = subscription,
+            component_id = component_id,
+            reason = reason
+        );
+        let Some(quarantine) = &self.quarantine else {
+            return;
+        };
+        match quarantine.record_failure(message, &err).await {
+            Ok(true) => {
+                tracing::warn!(
+                    "Quarantined a message from {} after it failed component {component_id} {} times",
+                    message.channel,
+                    quarantine.max_failures()
+                );
+                spin_telemetry::metrics::monotonic_counter!(
+                    spin.redis.quarantined_messages = 1,
+                    subscription = subscription,
+                    component_id = component_id
+                );
+            }
+            Ok(false) => (),
+            Err(err) => tracing::error!(
+                "Failed to quarantine a message from {} for component {component_id}: {err:#}",
+                message.channel
+            ),
+        }
+    }
 
     #[instrument(name = "spin_trigger_redis.handle_message", skip_all, err(level = Level::INFO), fields(
-        otel.name = format!("{} receive", msg.get_channel_name()),
+        otel.name = format!("{} receive", message.channel),
         otel.kind = "consumer",
         messaging.operation = "receive",
         messaging.system = "redis"
     ))]
-    async fn handle_message(&self, msg: Msg) -> anyhow::Result<()> {
-        let server_addr = &self.client.get_connection_info().addr;
-        let channel = msg.get_channel_name();
+    async fn handle_message(&self, message: &Message) -> anyhow::Result<()> {
+        let server_addr = &self.server_addr;
+        let channel = &message.channel;
+        let subscription = self.subscription.to_string();
+        let component_id = self.handler.component_id.as_str();
         tracing::trace!(%server_addr, %channel, "Received message");
+        spin_telemetry::extract_trace_context(&message.trace_context[..]);
 
-        let Some(component_ids) = self.channel_components.get(channel) else {
-            anyhow::bail!("message from unexpected channel {channel:?}");
-        };
-
-        let dispatch_futures = component_ids.iter().map(|component_id| {
-            tracing::trace!("Executing Redis component {component_id}");
-            self.dispatch_handler(&msg, component_id)
-                .inspect_err(move |err| {
-                    tracing::info!("Component {component_id} handler failed: {err}");
-                })
-        });
-        futures::future::join_all(dispatch_futures).await;
-
-        Ok(())
-    }
-
-    async fn dispatch_handler(&self, msg: &Msg, component_id: &str) -> anyhow::Result<()> {
         spin_telemetry::metrics::monotonic_counter!(
             spin.request_count = 1,
             trigger_type = "redis",
             app_id = self.trigger_app.app().id(),
             component_id = component_id
         );
+        spin_telemetry::metrics::monotonic_counter!(
+            spin.redis.message_count = 1,
+            subscription = subscription,
+            component_id = component_id
+        );
 
-        let (instance, mut store) = self
-            .trigger_app
-            .prepare(component_id)?
-            .instantiate(())
-            .await?;
+        let queued = self.saturation.enqueue();
+        let mut instance_builder = match self.trigger_app.prepare(component_id).await {
+            Ok(instance_builder) => instance_builder,
+            Err(err) => {
+                if err.is::<ConcurrencyLimitExceeded>() {
+                    queued.reject();
+                }
+                return Err(err);
+            }
+        };
+        if let Some(context) = instance_builder.factor_builder::<ContextFactor>() {
+            context.set_trigger("redis", &self.handler.trigger_id);
+        }
+        let (instance, mut store) = instance_builder.instantiate(()).await?;
+        if let Some(timeout) = self.handler.timeout {
+            store.set_deadline(Instant::now() + timeout);
+        }
 
         let guest_indices = inbound_redis::GuestIndices::new_instance(&mut store, &instance)?;
         let guest = guest_indices.load(&mut store, &instance)?;
+        let _in_flight = queued.start();
+        let _in_flight_metric = InFlightMetric::start(&subscription, component_id);
 
-        let payload = msg.get_payload_bytes().to_vec();
-
+        let event = [
+            ("channel", channel.clone()),
+            ("subscription", subscription.clone()),
+        ];
         guest
-            .call_handle_message(&mut store, &payload)
-            .await?
+            .call_handle_message(&mut store, &message.payload.to_vec())
+            .await
+            .map_err(|err| core_dump::capture(&mut store, err, "redis", component_id, event))?
             .context("Redis handler returned an error")
     }
 }
+
+/// Tracks the in-flight messages of a subscription, per component.
+struct InFlightMetric<'a> {
+    subscription: &'a str,
+    component_id: &'a str,
+}
+
+impl<'a> InFlightMetric<'a> {
+    fn start(subscription: &'a str, component_id: &'a str) -> Self {
+        spin_telemetry::metrics::counter!(
+            spin.redis.in_flight_messages = 1,
+            subscription = subscription,
+            component_id = component_id
+        );
+        Self {
+            subscription,
+            component_id,
+        }
+    }
+}
+
+impl Drop for InFlightMetric<'_> {
+    fn drop(&mut self) {
+        spin_telemetry::metrics::counter!(
+            spin.redis.in_flight_messages = -1,
+            subscription = self.subscription,
+            component_id = self.component_id
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(payload: &[u8]) -> Arc<Message> {
+        Arc::new(Message {
+            channel: "orders".into(),
+            payload: Arc::from(payload),
+            trace_context: vec![],
+        })
+    }
+
+    #[test]
+    fn messages_are_queued_in_order() {
+        let subscription = Subscription::Channel("orders".into());
+        let (queue, mut messages) = Queue::new("component");
+        queue.push(&subscription, message(b"first"));
+        queue.push(&subscription, message(b"second"));
+        assert_eq!(&*messages.try_recv().unwrap().payload, b"first");
+        assert_eq!(&*messages.try_recv().unwrap().payload, b"second");
+        assert!(messages.try_recv().is_err());
+    }
+
+    #[test]
+    fn messages_beyond_a_full_queue_are_dropped() {
+        let subscription = Subscription::Pattern("orders.*".into());
+        let (queue, mut messages) = Queue::new("component");
+        for _ in 0..MAX_QUEUED_MESSAGES {
+            queue.push(&subscription, message(b"queued"));
+        }
+        queue.push(&subscription, message(b"dropped"));
+
+        for _ in 0..MAX_QUEUED_MESSAGES {
+            assert_eq!(&*messages.try_recv().unwrap().payload, b"queued");
+        }
+        assert!(messages.try_recv().is_err());
+
+        // Once the dispatcher catches up, messages are queued again.
+        queue.push(&subscription, message(b"later"));
+        assert_eq!(&*messages.try_recv().unwrap().payload, b"later");
+    }
+}
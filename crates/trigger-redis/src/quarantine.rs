@@ -0,0 +1,299 @@
+//! Quarantining messages which repeatedly trap or time out their handler, so
+//! that a single bad payload can't keep failing, or tying up, a subscription.
+//!
+//! Messages are recognized by their channel and payload, so a message which is
+//! published again after failing counts towards the same limit. Once a message
+//! is quarantined it is written to the trigger's dead letter list or stream,
+//! and further copies of it are dropped without being handled.
+//!
+//! Identical payloads are often published for unrelated reasons, so neither
+//! failures nor quarantines are kept forever: a message's failures are
+//! forgotten once it hasn't failed for the quarantine period, and a quarantined
+//! message is handled again once the period has passed since it was
+//! quarantined.
+
+use std::{
+    hash::{DefaultHasher, Hash, Hasher},
+    num::{NonZeroU32, NonZeroU64, NonZeroUsize},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use lru::LruCache;
+use redis::{aio::MultiplexedConnection, AsyncCommands, Client};
+use serde::Deserialize;
+use spin_factor_variables::AppState as VariablesAppState;
+use tokio::sync::OnceCell;
+
+use crate::Message;
+
+/// How many times a message may fail before it is quarantined, by default.
+const DEFAULT_MAX_FAILURES: u32 = 3;
+/// How long a message stays quarantined, and its failures are remembered, by
+/// default.
+const DEFAULT_QUARANTINE_PERIOD: Duration = Duration::from_secs(10 * 60);
+/// How many failing messages each handler keeps track of.
+const TRACKED_MESSAGES: usize = 10_000;
+
+/// The `dead_letter` trigger config.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct DeadLetterConfig {
+    /// A list to push the payloads of quarantined messages onto.
+    key: Option<String>,
+    /// A stream to add quarantined messages to, with their channel and the
+    /// error they caused.
+    stream: Option<String>,
+    /// How many times a message may trap or time out before it is
+    /// quarantined. Defaults to 3.
+    max_failures: Option<NonZeroU32>,
+    /// How many seconds a message stays quarantined, and how long its
+    /// failures are remembered. Defaults to 600.
+    quarantine_secs: Option<NonZeroU64>,
+}
+
+impl DeadLetterConfig {
+    /// Resolves the variables in the config's key or stream.
+    pub async fn resolve(&self, variables: &VariablesAppState) -> anyhow::Result<QuarantinePolicy> {
+        let (expr, is_stream) = match (&self.key, &self.stream) {
+            (Some(key), None) => (key, false),
+            (None, Some(stream)) => (stream, true),
+            _ => anyhow::bail!("`dead_letter` must set exactly one of `key` or `stream`"),
+        };
+        let resolved = variables
+            .resolve_expression(expr.clone())
+            .await
+            .with_context(|| format!("failed to resolve dead letter {expr:?}"))?;
+        Ok(QuarantinePolicy {
+            dead_letter: if is_stream {
+                DeadLetter::Stream(resolved)
+            } else {
+                DeadLetter::List(resolved)
+            },
+            max_failures: self
+                .max_failures
+                .map_or(DEFAULT_MAX_FAILURES, NonZeroU32::get),
+            period: self
+                .quarantine_secs
+                .map_or(DEFAULT_QUARANTINE_PERIOD, |secs| {
+                    Duration::from_secs(secs.get())
+                }),
+        })
+    }
+}
+
+/// Where quarantined messages are written.
+#[derive(Clone, Debug)]
+pub(crate) enum DeadLetter {
+    /// A list, which payloads are pushed onto with `RPUSH`.
+    List(String),
+    /// A stream, which messages are added to with `XADD`.
+    Stream(String),
+}
+
+/// When a handler's messages are quarantined, and where to.
+#[derive(Clone, Debug)]
+pub(crate) struct QuarantinePolicy {
+    pub dead_letter: DeadLetter,
+    pub max_failures: u32,
+    /// How long a message stays quarantined, and its failures are remembered.
+    pub period: Duration,
+}
+
+/// The failures of a message.
+struct Failures {
+    count: u32,
+    /// When the message last failed, or was quarantined.
+    since: Instant,
+}
+
+/// Tracks the failures of the messages of one handler.
+pub(crate) struct Quarantine {
+    policy: QuarantinePolicy,
+    client: Client,
+    connection: OnceCell<MultiplexedConnection>,
+    /// Message hash -> how many times in a row it has failed
+    failures: Mutex<LruCache<u64, Failures>>,
+}
+
+impl Quarantine {
+    pub fn new(client: Client, policy: QuarantinePolicy) -> Self {
+        Self {
+            policy,
+            client,
+            connection: OnceCell::new(),
+            failures: Mutex::new(LruCache::new(NonZeroUsize::new(TRACKED_MESSAGES).unwrap())),
+        }
+    }
+
+    pub fn max_failures(&self) -> u32 {
+        self.policy.max_failures
+    }
+
+    /// Returns whether the message has been quarantined, and should be
+    /// dropped.
+    pub fn is_quarantined(&self, message: &Message) -> bool {
+        self.failures
+            .lock()
+            .unwrap()
+            .peek(&message_hash(message))
+            .is_some_and(|failures| {
+                failures.count >= self.policy.max_failures
+                    && failures.since.elapsed() < self.policy.period
+            })
+    }
+
+    /// Records that the message was handled, so that its earlier failures
+    /// are forgotten.
+    pub fn record_success(&self, message: &Message) {
+        self.failures.lock().unwrap().pop(&message_hash(message));
+    }
+
+    /// Records that the message trapped or timed out its handler, and
+    /// quarantines it if it has now failed too many times. Returns whether
+    /// it was quarantined.
+    pub async fn record_failure(
+        &self,
+        message: &Message,
+        error: &anyhow::Error,
+    ) -> anyhow::Result<bool> {
+        let failures = {
+            let mut tracked = self.failures.lock().unwrap();
+            let hash = message_hash(message);
+            let now = Instant::now();
+            match tracked.get_mut(&hash) {
+                Some(failures) if now - failures.since < self.policy.period => {
+                    failures.count += 1;
+                    failures.since = now;
+                    failures.count
+                }
+                _ => {
+                    tracked.put(
+                        hash,
+                        Failures {
+                            count: 1,
+                            since: now,
+                        },
+                    );
+                    1
+                }
+            }
+        };
+        // Copies of the message handled at the same time may fail together;
+        // only the one reaching the limit writes the dead letter.
+        if failures != self.policy.max_failures {
+            return Ok(false);
+        }
+        self.write_dead_letter(message, error).await?;
+        Ok(true)
+    }
+
+    async fn write_dead_letter(
+        &self,
+        message: &Message,
+        error: &anyhow::Error,
+    ) -> anyhow::Result<()> {
+        let mut connection = self
+            .connection
+            .get_or_try_init(|| self.client.get_multiplexed_async_connection())
+            .await
+            .context("failed to connect to Redis")?
+            .clone();
+        match &self.policy.dead_letter {
+            DeadLetter::List(key) => connection
+                .rpush::<_, _, ()>(key, &*message.payload)
+                .await
+                .with_context(|| format!("failed to push message onto dead letter list {key:?}")),
+            DeadLetter::Stream(stream) => {
+                let error = format!("{error:#}");
+                let fields: [(&str, &[u8]); 3] = [
+                    ("channel", message.channel.as_bytes()),
+                    ("payload", &message.payload[..]),
+                    ("error", error.as_bytes()),
+                ];
+                connection
+                    .xadd::<_, _, _, _, ()>(stream, "*", &fields)
+                    .await
+                    .with_context(|| {
+                        format!("failed to add message to dead letter stream {stream:?}")
+                    })
+            }
+        }
+    }
+}
+
+fn message_hash(message: &Message) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    message.channel.hash(&mut hasher);
+    message.payload.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    fn message(payload: &[u8]) -> Message {
+        Message {
+            channel: "orders".into(),
+            payload: Arc::from(payload),
+            trace_context: vec![],
+        }
+    }
+
+    // Nothing is written to Redis until a message is quarantined.
+    fn quarantine(period: Duration) -> anyhow::Result<Quarantine> {
+        Ok(Quarantine::new(
+            Client::open("redis://127.0.0.1:1")?,
+            QuarantinePolicy {
+                dead_letter: DeadLetter::List("dead".into()),
+                max_failures: 3,
+                period,
+            },
+        ))
+    }
+
+    #[tokio::test]
+    async fn failures_are_counted_per_message_until_success() -> anyhow::Result<()> {
+        let quarantine = quarantine(DEFAULT_QUARANTINE_PERIOD)?;
+        let error = anyhow::anyhow!("trapped");
+        let poison = message(b"poison");
+        let fine = message(b"fine");
+
+        assert!(!quarantine.record_failure(&poison, &error).await?);
+        assert!(!quarantine.record_failure(&fine, &error).await?);
+        quarantine.record_success(&fine);
+        assert!(!quarantine.record_failure(&poison, &error).await?);
+        assert!(!quarantine.is_quarantined(&poison));
+
+        // The dead letter can't be written, but the message is still counted.
+        assert!(quarantine.record_failure(&poison, &error).await.is_err());
+        assert!(quarantine.is_quarantined(&poison));
+        assert!(!quarantine.is_quarantined(&fine));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn quarantine_expires() -> anyhow::Result<()> {
+        let period = Duration::from_millis(100);
+        let quarantine = quarantine(period)?;
+        let error = anyhow::anyhow!("trapped");
+        let refresh = message(b"refresh");
+
+        for _ in 0..2 {
+            assert!(!quarantine.record_failure(&refresh, &error).await?);
+        }
+        assert!(quarantine.record_failure(&refresh, &error).await.is_err());
+        assert!(quarantine.is_quarantined(&refresh));
+
+        std::thread::sleep(period);
+        assert!(!quarantine.is_quarantined(&refresh));
+        // Its failures are counted afresh.
+        assert!(!quarantine.record_failure(&refresh, &error).await?);
+        assert!(!quarantine.is_quarantined(&refresh));
+        Ok(())
+    }
+}
@@ -0,0 +1,131 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use spin_core::{async_trait, wasmtime};
+use spin_factor_wasi::{HostMonotonicClock, HostWallClock, WasiFactor};
+use spin_factors::RuntimeFactors;
+use spin_factors_executor::{ExecutorHooks, FactorsInstanceBuilder};
+
+/// The wall clock time at which a [`FakeClock`] starts: 2024-01-01T00:00:00Z.
+pub const FAKE_CLOCK_START: Duration = Duration::from_secs(1_704_067_200);
+
+/// A manually advanced clock.
+///
+/// A `FakeClock` drives both the epoch ticks that enforce execution deadlines
+/// and the WASI monotonic and wall clocks seen by guests, so time only passes
+/// when a test calls [`FakeClock::advance`].
+#[derive(Clone)]
+pub struct FakeClock {
+    inner: Arc<Mutex<ClockState>>,
+    tick_interval: Duration,
+}
+
+struct ClockState {
+    elapsed: Duration,
+    ticks: u64,
+    engine: Option<wasmtime::Engine>,
+}
+
+impl FakeClock {
+    /// Creates a new clock which ticks the engine epoch once per `tick_interval`.
+    pub fn new(tick_interval: Duration) -> Self {
+        assert!(!tick_interval.is_zero(), "tick interval must be non-zero");
+        Self {
+            inner: Arc::new(Mutex::new(ClockState {
+                elapsed: Duration::ZERO,
+                ticks: 0,
+                engine: None,
+            })),
+            tick_interval,
+        }
+    }
+
+    /// The interval between epoch ticks.
+    pub fn tick_interval(&self) -> Duration {
+        self.tick_interval
+    }
+
+    /// The time elapsed since the clock was created.
+    pub fn elapsed(&self) -> Duration {
+        self.inner.lock().unwrap().elapsed
+    }
+
+    /// Advances the clock, incrementing the epoch of the attached engine once
+    /// for each tick interval crossed.
+    pub fn advance(&self, duration: Duration) {
+        let mut state = self.inner.lock().unwrap();
+        state.elapsed += duration;
+        let ticks = (state.elapsed.as_nanos() / self.tick_interval.as_nanos()) as u64;
+        if let Some(engine) = &state.engine {
+            for _ in state.ticks..ticks {
+                engine.increment_epoch();
+            }
+        }
+        state.ticks = ticks;
+    }
+
+    /// Attaches the engine whose epoch this clock drives.
+    pub(crate) fn attach(&self, engine: &wasmtime::Engine) {
+        self.inner.lock().unwrap().engine = Some(engine.clone());
+    }
+}
+
+impl Default for FakeClock {
+    fn default() -> Self {
+        Self::new(spin_core::DEFAULT_EPOCH_TICK_INTERVAL)
+    }
+}
+
+impl HostMonotonicClock for FakeClock {
+    fn resolution(&self) -> u64 {
+        1
+    }
+
+    fn now(&self) -> u64 {
+        self.elapsed().as_nanos() as u64
+    }
+}
+
+impl HostWallClock for FakeClock {
+    fn resolution(&self) -> Duration {
+        Duration::from_nanos(1)
+    }
+
+    fn now(&self) -> Duration {
+        FAKE_CLOCK_START + self.elapsed()
+    }
+}
+
+/// Gives every instance's WASI context the [`FakeClock`].
+pub(crate) struct FakeClockHooks(pub FakeClock);
+
+#[async_trait]
+impl<T: RuntimeFactors, U> ExecutorHooks<T, U> for FakeClockHooks {
+    fn prepare_instance(&self, builder: &mut FactorsInstanceBuilder<T, U>) -> anyhow::Result<()> {
+        if let Some(wasi) = builder.factor_builder::<WasiFactor>() {
+            wasi.monotonic_clock(self.0.clone());
+            wasi.wall_clock(self.0.clone());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn advance_accumulates_elapsed_time() {
+        let clock = FakeClock::new(Duration::from_millis(10));
+        clock.advance(Duration::from_millis(15));
+        clock.advance(Duration::from_millis(15));
+        assert_eq!(clock.elapsed(), Duration::from_millis(30));
+        assert_eq!(clock.inner.lock().unwrap().ticks, 3);
+        assert_eq!(
+            HostWallClock::now(&clock),
+            FAKE_CLOCK_START + Duration::from_millis(30)
+        );
+    }
+}
@@ -0,0 +1,110 @@
+use std::sync::Arc;
+
+use spin_core::async_trait;
+use spin_factor_key_value::{runtime_config::spin::MakeKeyValueStore, KeyValueFactor};
+use spin_factor_outbound_http::OutboundHttpFactor;
+use spin_factor_outbound_networking::OutboundNetworkingFactor;
+use spin_factor_sqlite::{Connection, SqliteFactor};
+use spin_factor_variables::VariablesFactor;
+use spin_factor_wasi::{spin::SpinFilesMounter, WasiFactor};
+use spin_factors::RuntimeFactors;
+use spin_key_value_spin::{SpinKeyValueRuntimeConfig, SpinKeyValueStore};
+use spin_sqlite_inproc::{InProcConnection, InProcDatabaseLocation};
+use spin_world::v2::sqlite;
+
+/// The factors needed by the built-in triggers, with all state kept in memory.
+#[derive(RuntimeFactors)]
+pub struct InMemoryFactors {
+    pub wasi: WasiFactor,
+    pub variables: VariablesFactor,
+    pub key_value: KeyValueFactor,
+    pub outbound_networking: OutboundNetworkingFactor,
+    pub outbound_http: OutboundHttpFactor,
+    pub sqlite: SqliteFactor,
+}
+
+impl InMemoryFactors {
+    pub fn new() -> Self {
+        Self {
+            // Files are mounted directly from their (absolute) source paths.
+            wasi: WasiFactor::new(SpinFilesMounter::new(".", false)),
+            variables: VariablesFactor::default(),
+            key_value: KeyValueFactor::new(),
+            outbound_networking: OutboundNetworkingFactor::new(),
+            outbound_http: OutboundHttpFactor::default(),
+            sqlite: SqliteFactor::new(),
+        }
+    }
+}
+
+impl Default for InMemoryFactors {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl InMemoryFactorsRuntimeConfig {
+    /// Runtime config with an in-memory "default" key-value store and SQLite
+    /// database, matching the defaults of `spin up`.
+    pub fn in_memory() -> anyhow::Result<Self> {
+        Self::default()
+            .with_key_value_store("default")?
+            .with_sqlite_database("default")
+    }
+
+    /// Adds an in-memory key-value store with the given label.
+    pub fn with_key_value_store(mut self, label: impl Into<String>) -> anyhow::Result<Self> {
+        let store =
+            SpinKeyValueStore::new(None).make_store(SpinKeyValueRuntimeConfig::new(None))?;
+        self.key_value
+            .get_or_insert_with(Default::default)
+            .add_store_manager(label.into(), Arc::new(store));
+        Ok(self)
+    }
+
+    /// Adds an in-memory SQLite database with the given label.
+    ///
+    /// All connections share one in-memory database, so data written by one
+    /// instance is visible to the next.
+    pub fn with_sqlite_database(mut self, label: impl Into<String>) -> anyhow::Result<Self> {
+        let connection = Arc::new(InProcConnection::new(InProcDatabaseLocation::InMemory)?);
+        let creator = move || -> anyhow::Result<Box<dyn Connection>> {
+            Ok(Box::new(SharedConnection(connection.clone())))
+        };
+        self.sqlite
+            .get_or_insert_with(Default::default)
+            .connection_creators
+            .insert(label.into(), Arc::new(creator));
+        Ok(self)
+    }
+}
+
+/// A handle to a connection shared by all instances.
+struct SharedConnection(Arc<InProcConnection>);
+
+#[async_trait]
+impl Connection for SharedConnection {
+    async fn query(
+        &self,
+        query: &str,
+        parameters: Vec<sqlite::Value>,
+    ) -> Result<sqlite::QueryResult, sqlite::Error> {
+        self.0.query(query, parameters).await
+    }
+
+    async fn execute_batch(&self, statements: &str) -> anyhow::Result<()> {
+        self.0.execute_batch(statements).await
+    }
+
+    async fn changes(&self) -> Result<u64, sqlite::Error> {
+        self.0.changes().await
+    }
+
+    async fn last_insert_rowid(&self) -> Result<i64, sqlite::Error> {
+        self.0.last_insert_rowid().await
+    }
+
+    fn summary(&self) -> Option<String> {
+        self.0.summary()
+    }
+}
@@ -0,0 +1,87 @@
+use std::{
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    sync::Arc,
+};
+
+use anyhow::Context as _;
+use http::{header::HOST, uri::Scheme, HeaderMap, HeaderValue, Request, Response, StatusCode};
+use http_body_util::BodyExt;
+use hyper::body::Bytes;
+use spin_factors::RuntimeFactors;
+use spin_http::{body, Body};
+use spin_trigger_http::{HttpServer, HttpTrigger};
+
+use crate::{TestApp, TestEnvironment};
+
+/// The server and client address of injected requests.
+const LOCALHOST: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0);
+
+/// An HTTP trigger server that requests are injected into directly, without
+/// binding a listener.
+pub struct HttpTestServer<F: RuntimeFactors> {
+    server: Arc<HttpServer<F>>,
+}
+
+impl<F: RuntimeFactors> TestEnvironment<F> {
+    /// Builds an app with the HTTP trigger.
+    pub async fn build_http(self) -> anyhow::Result<HttpTestServer<F>> {
+        self.build_with(|app| HttpTrigger::new(app, LOCALHOST, None))
+            .await?
+            .into_http_server()
+    }
+}
+
+impl<F: RuntimeFactors> TestApp<HttpTrigger, F> {
+    /// Turns this app into an [`HttpTestServer`].
+    pub fn into_http_server(self) -> anyhow::Result<HttpTestServer<F>> {
+        let server = self.trigger.into_server(self.trigger_app)?;
+        Ok(HttpTestServer { server })
+    }
+}
+
+impl<F: RuntimeFactors> HttpTestServer<F> {
+    /// Handles the request as if it had been received by the server.
+    pub async fn handle(&self, mut req: Request<Body>) -> anyhow::Result<Response<Body>> {
+        if req.uri().authority().is_none() && !req.headers().contains_key(HOST) {
+            req.headers_mut()
+                .insert(HOST, HeaderValue::from_static("localhost"));
+        }
+        self.server.handle(req, Scheme::HTTP, LOCALHOST).await
+    }
+
+    /// Sends the request and collects the response.
+    pub async fn send(&self, req: Request<impl Into<Bytes>>) -> anyhow::Result<TestResponse> {
+        let resp = self.handle(req.map(|b| body::full(b.into()))).await?;
+        let (parts, body) = resp.into_parts();
+        let body = body
+            .collect()
+            .await
+            .context("failed to read response body")?
+            .to_bytes();
+        Ok(TestResponse {
+            status: parts.status,
+            headers: parts.headers,
+            body,
+        })
+    }
+
+    /// Sends a `GET` request for the given path.
+    pub async fn get(&self, path: &str) -> anyhow::Result<TestResponse> {
+        self.send(Request::get(path).body(Bytes::new())?).await
+    }
+}
+
+/// A response with its body collected.
+#[derive(Debug)]
+pub struct TestResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: Bytes,
+}
+
+impl TestResponse {
+    /// The body as UTF-8 text.
+    pub fn text(&self) -> anyhow::Result<&str> {
+        std::str::from_utf8(&self.body).context("response body is not valid UTF-8")
+    }
+}
@@ -0,0 +1,213 @@
+//! A test harness for Spin triggers.
+//!
+//! Where `spin-factors-test` stops at building a factors instance state, this
+//! crate builds a complete [`TriggerApp`] for a manifest, backed by in-memory
+//! factors and driven by a [`FakeClock`], so trigger and factor authors can
+//! inject requests and exercise timeouts without a network or real time.
+//!
+//! [`MockFactors`] go further, backing key-value, outbound HTTP and LLM host
+//! interfaces with the scripted responses of a [`MockFixture`], so components
+//! can be tested against recorded interactions.
+
+mod clock;
+mod factors;
+mod http;
+mod mock;
+
+use std::{collections::HashMap, sync::Arc};
+
+use anyhow::Context;
+use spin_app::{App, AppComponent};
+use spin_core::{async_trait, wasmtime, Component};
+use spin_factors::RuntimeFactors;
+use spin_factors_executor::{ComponentLoader, FactorsExecutor};
+use spin_trigger::{Trigger, TriggerApp, TriggerInstanceBuilder};
+
+pub use clock::{FakeClock, FAKE_CLOCK_START};
+pub use factors::{InMemoryFactors, InMemoryFactorsRuntimeConfig};
+pub use http::{HttpTestServer, TestResponse};
+pub use mock::{
+    MockEmbeddings, MockFactors, MockFixture, MockHttpResponse, MockInference, MockLlm,
+};
+pub use spin_factors_test::toml;
+
+/// A test environment for building [`TriggerApp`]s.
+pub struct TestEnvironment<F: RuntimeFactors> {
+    /// The RuntimeFactors under test.
+    pub factors: F,
+    /// The `spin.toml` manifest.
+    pub manifest: toml::Table,
+    /// Runtime configuration for the factors.
+    pub runtime_config: F::RuntimeConfig,
+    clock: FakeClock,
+    component_sources: HashMap<String, Vec<u8>>,
+}
+
+impl<F: RuntimeFactors> TestEnvironment<F> {
+    /// Creates a new test environment for the given [`RuntimeFactors`].
+    pub fn new(factors: F) -> Self {
+        let manifest = toml! {
+            spin_manifest_version = 2
+
+            [application]
+            name = "test-app"
+
+            [[trigger.test-trigger]]
+        };
+        Self {
+            factors,
+            manifest,
+            runtime_config: Default::default(),
+            clock: FakeClock::default(),
+            component_sources: Default::default(),
+        }
+    }
+
+    /// Extends the manifest with the given TOML.
+    ///
+    /// The default manifest includes boilerplate like the
+    /// `spin_manifest_version` and `[application]` section, so you typically
+    /// need to pass only `[[trigger.<type>]]` and `[component.<id>]` sections.
+    /// Component sources and files should be absolute paths.
+    pub fn extend_manifest(mut self, manifest_merge: toml::Table) -> Self {
+        self.manifest.extend(manifest_merge);
+        self
+    }
+
+    /// Sets the runtime config.
+    pub fn runtime_config<C, E>(mut self, runtime_config: C) -> anyhow::Result<Self>
+    where
+        C: TryInto<F::RuntimeConfig, Error = E>,
+        E: Into<anyhow::Error>,
+    {
+        self.runtime_config = runtime_config
+            .try_into()
+            .map_err(Into::into)
+            .context("failed to build runtime config")?;
+        Ok(self)
+    }
+
+    /// Loads the given component from the given Wasm or WAT bytes rather than
+    /// from its manifest `source`.
+    pub fn component_source(
+        mut self,
+        component_id: impl Into<String>,
+        source: impl Into<Vec<u8>>,
+    ) -> Self {
+        self.component_sources
+            .insert(component_id.into(), source.into());
+        self
+    }
+
+    /// The clock that drives the app's epoch ticks and WASI clocks.
+    pub fn clock(&self) -> &FakeClock {
+        &self.clock
+    }
+
+    /// Builds the app with a trigger constructed from the given CLI args.
+    pub async fn build<T: Trigger<F>>(self, cli_args: T::CliArgs) -> anyhow::Result<TestApp<T, F>> {
+        self.build_with(|app| T::new(cli_args, app)).await
+    }
+
+    /// Builds the app with a trigger constructed by the given function.
+    pub async fn build_with<T: Trigger<F>>(
+        self,
+        new_trigger: impl FnOnce(&App) -> anyhow::Result<T>,
+    ) -> anyhow::Result<TestApp<T, F>> {
+        let locked_app = spin_factors_test::build_locked_app(&self.manifest)
+            .await
+            .context("failed to build locked app")?;
+        let app = App::new("test-app", locked_app);
+        let mut trigger = new_trigger(&app).context("failed to create trigger")?;
+
+        let mut engine_config = spin_core::Config::default();
+        trigger.update_core_config(&mut engine_config)?;
+        let mut core_engine_builder = spin_core::Engine::builder(&engine_config)?;
+        core_engine_builder.epoch_tick_interval(self.clock.tick_interval());
+        core_engine_builder.epoch_ticker_thread(false);
+        trigger.add_to_linker(core_engine_builder.linker())?;
+
+        let mut executor = FactorsExecutor::new(core_engine_builder, self.factors)?;
+        executor.add_hooks(clock::FakeClockHooks(self.clock.clone()));
+        if let Some(limit) = app.trigger_concurrency_limit(T::TYPE)? {
+            executor.limit_concurrency(limit);
+        }
+        self.clock.attach(executor.core_engine().as_ref());
+
+        let loader = TestComponentLoader {
+            sources: Arc::new(self.component_sources),
+            fallback: spin_trigger::loader::ComponentLoader::new(),
+        };
+        let trigger_app = Arc::new(executor)
+            .load_app(app, self.runtime_config, &loader)
+            .await?;
+
+        Ok(TestApp {
+            trigger,
+            trigger_app,
+            clock: self.clock,
+        })
+    }
+}
+
+impl TestEnvironment<InMemoryFactors> {
+    /// Creates a new test environment with [`InMemoryFactors`] and the
+    /// [`InMemoryFactorsRuntimeConfig::in_memory`] runtime config.
+    pub fn in_memory() -> Self {
+        let mut env = Self::new(InMemoryFactors::new());
+        env.runtime_config = InMemoryFactorsRuntimeConfig::in_memory()
+            .expect("in-memory runtime config failed to initialize");
+        env
+    }
+}
+
+/// A trigger and the [`TriggerApp`] it runs, built by a [`TestEnvironment`].
+pub struct TestApp<T: Trigger<F>, F: RuntimeFactors> {
+    pub trigger: T,
+    pub trigger_app: TriggerApp<T, F>,
+    clock: FakeClock,
+}
+
+impl<T: Trigger<F>, F: RuntimeFactors> TestApp<T, F> {
+    /// The clock that drives the app's epoch ticks and WASI clocks.
+    pub fn clock(&self) -> &FakeClock {
+        &self.clock
+    }
+
+    /// Prepares an instance of the given component, for triggers that
+    /// dispatch to components themselves.
+    pub async fn prepare(
+        &self,
+        component_id: &str,
+    ) -> anyhow::Result<TriggerInstanceBuilder<'_, T, F>> {
+        self.trigger_app.prepare(component_id).await
+    }
+
+    /// Runs the trigger until it exits.
+    pub async fn run(self) -> anyhow::Result<()> {
+        self.trigger.run(self.trigger_app).await
+    }
+}
+
+/// Loads components from in-memory sources, falling back to their manifest
+/// sources.
+#[derive(Clone)]
+struct TestComponentLoader {
+    sources: Arc<HashMap<String, Vec<u8>>>,
+    fallback: spin_trigger::loader::ComponentLoader,
+}
+
+#[async_trait]
+impl ComponentLoader for TestComponentLoader {
+    async fn load_component(
+        &self,
+        engine: &wasmtime::Engine,
+        component: &AppComponent,
+    ) -> anyhow::Result<Component> {
+        match self.sources.get(component.id()) {
+            Some(source) => Component::new(engine, source)
+                .with_context(|| format!("failed to compile component {:?}", component.id())),
+            None => self.fallback.load_component(engine, component).await,
+        }
+    }
+}
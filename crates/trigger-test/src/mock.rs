@@ -0,0 +1,380 @@
+use std::{collections::HashMap, path::Path, sync::Arc};
+
+use anyhow::Context as _;
+use http::{Method, Response, StatusCode};
+use http_body_util::{BodyExt, Full};
+use hyper::body::Bytes;
+use serde::Deserialize;
+use spin_core::async_trait;
+use spin_factor_key_value::{
+    runtime_config::spin::MakeKeyValueStore, KeyValueFactor, StoreManager,
+};
+use spin_factor_llm::{LlmEngine, LlmFactor};
+use spin_factor_outbound_http::{
+    intercept::{InterceptOutcome, InterceptRequest, OutboundHttpInterceptor},
+    HttpResult, HyperOutgoingBody, OutboundHttpFactor,
+};
+use spin_factor_outbound_networking::OutboundNetworkingFactor;
+use spin_factor_sqlite::SqliteFactor;
+use spin_factor_variables::VariablesFactor;
+use spin_factor_wasi::{spin::SpinFilesMounter, WasiFactor};
+use spin_factors::RuntimeFactors;
+use spin_key_value_spin::{SpinKeyValueRuntimeConfig, SpinKeyValueStore};
+use spin_world::{v1::llm as v1, v2::llm as v2};
+use tokio::sync::Mutex;
+use wasmtime_wasi_http::HttpError;
+
+use crate::{InMemoryFactorsRuntimeConfig, TestEnvironment};
+
+/// Scripted responses for Spin host interfaces, loaded from a TOML or JSON
+/// fixture.
+///
+/// ```toml
+/// [key_value.default]
+/// greeting = "hello"
+///
+/// [[outbound_http]]
+/// method = "GET"
+/// url = "https://example.com/users/1"
+/// headers = { content-type = "application/json" }
+/// body = '{"name": "Alice"}'
+///
+/// [[llm.inferencing]]
+/// model = "llama2-chat"
+/// prompt = "Say hello"
+/// text = "Hello!"
+/// ```
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MockFixture {
+    /// Initial key-value pairs by store label.
+    #[serde(default)]
+    pub key_value: HashMap<String, HashMap<String, String>>,
+    /// Responses to outbound HTTP requests, matched in order.
+    #[serde(default)]
+    pub outbound_http: Vec<MockHttpResponse>,
+    /// Responses to LLM requests, matched in order.
+    #[serde(default)]
+    pub llm: MockLlm,
+}
+
+impl MockFixture {
+    /// Loads a fixture from a `.toml` or `.json` file.
+    pub fn from_file(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read mock fixture {path:?}"))?;
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json") => Self::from_json(&contents),
+            _ => Self::from_toml(&contents),
+        }
+        .with_context(|| format!("invalid mock fixture {path:?}"))
+    }
+
+    /// Parses a fixture from TOML.
+    pub fn from_toml(fixture: &str) -> anyhow::Result<Self> {
+        Ok(toml::from_str(fixture)?)
+    }
+
+    /// Parses a fixture from JSON.
+    pub fn from_json(fixture: &str) -> anyhow::Result<Self> {
+        Ok(serde_json::from_str(fixture)?)
+    }
+}
+
+/// A scripted response to outbound HTTP requests.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MockHttpResponse {
+    /// The request method to match; any method matches if unset.
+    #[serde(default)]
+    pub method: Option<String>,
+    /// The full request URL to match.
+    pub url: String,
+    /// The response status.
+    #[serde(default = "default_status")]
+    pub status: u16,
+    /// The response headers.
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    /// The response body.
+    #[serde(default)]
+    pub body: String,
+}
+
+fn default_status() -> u16 {
+    200
+}
+
+impl MockHttpResponse {
+    fn matches(&self, method: &Method, url: &str) -> bool {
+        self.method
+            .as_deref()
+            .map_or(true, |m| m.eq_ignore_ascii_case(method.as_str()))
+            && self.url == url
+    }
+
+    fn to_response(&self) -> anyhow::Result<Response<HyperOutgoingBody>> {
+        let mut resp = Response::builder().status(StatusCode::from_u16(self.status)?);
+        for (name, value) in &self.headers {
+            resp = resp.header(name, value);
+        }
+        let body = Full::new(Bytes::from(self.body.clone()))
+            .map_err(|err| match err {})
+            .boxed();
+        Ok(resp.body(body)?)
+    }
+}
+
+/// Scripted responses to LLM requests.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MockLlm {
+    /// Responses to inferencing requests.
+    #[serde(default)]
+    pub inferencing: Vec<MockInference>,
+    /// Responses to embeddings requests.
+    #[serde(default)]
+    pub embeddings: Vec<MockEmbeddings>,
+}
+
+/// A scripted response to inferencing requests.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MockInference {
+    /// The model to match; any model matches if unset.
+    #[serde(default)]
+    pub model: Option<String>,
+    /// Text the prompt must contain; any prompt matches if unset.
+    #[serde(default)]
+    pub prompt: Option<String>,
+    /// The generated text.
+    pub text: String,
+}
+
+/// A scripted response to embeddings requests.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MockEmbeddings {
+    /// The model to match; any model matches if unset.
+    #[serde(default)]
+    pub model: Option<String>,
+    /// The generated embeddings.
+    pub embeddings: Vec<Vec<f32>>,
+}
+
+/// The factors needed by the built-in triggers, with host interfaces backed
+/// by a [`MockFixture`] rather than real networks and models.
+///
+/// Outbound HTTP requests that don't match the fixture fail rather than
+/// reaching the network.
+#[derive(RuntimeFactors)]
+pub struct MockFactors {
+    pub wasi: WasiFactor,
+    pub variables: VariablesFactor,
+    pub key_value: KeyValueFactor,
+    pub outbound_networking: OutboundNetworkingFactor,
+    pub outbound_http: OutboundHttpFactor,
+    pub sqlite: SqliteFactor,
+    pub llm: LlmFactor,
+}
+
+impl MockFactors {
+    pub fn new(fixture: &MockFixture) -> Self {
+        let mut outbound_http = OutboundHttpFactor::default();
+        outbound_http.set_request_interceptor(MockHttpInterceptor {
+            responses: fixture.outbound_http.clone(),
+        });
+        let llm_engine: Arc<Mutex<dyn LlmEngine>> =
+            Arc::new(Mutex::new(MockLlmEngine(fixture.llm.clone())));
+        Self {
+            // Files are mounted directly from their (absolute) source paths.
+            wasi: WasiFactor::new(SpinFilesMounter::new(".", false)),
+            variables: VariablesFactor::default(),
+            key_value: KeyValueFactor::new(),
+            outbound_networking: OutboundNetworkingFactor::new(),
+            outbound_http,
+            sqlite: SqliteFactor::new(),
+            llm: LlmFactor::new(move || llm_engine.clone()),
+        }
+    }
+}
+
+impl TestEnvironment<MockFactors> {
+    /// Creates a new test environment with [`MockFactors`] for the given
+    /// fixture.
+    ///
+    /// Key-value stores are in memory and seeded from the fixture; a
+    /// "default" store and SQLite database are always available.
+    pub async fn mocked(fixture: MockFixture) -> anyhow::Result<Self> {
+        let in_memory = InMemoryFactorsRuntimeConfig::in_memory()?;
+        let mut key_value = in_memory.key_value.unwrap_or_default();
+        for (label, pairs) in &fixture.key_value {
+            let store_manager =
+                SpinKeyValueStore::new(None).make_store(SpinKeyValueRuntimeConfig::new(None))?;
+            let store = store_manager
+                .get(label)
+                .await
+                .with_context(|| format!("failed to open mock key-value store {label:?}"))?;
+            for (key, value) in pairs {
+                store
+                    .set(key, value.as_bytes())
+                    .await
+                    .with_context(|| format!("failed to seed mock key-value store {label:?}"))?;
+            }
+            key_value.add_store_manager(label.clone(), Arc::new(store_manager));
+        }
+
+        let mut env = Self::new(MockFactors::new(&fixture));
+        env.runtime_config.key_value = Some(key_value);
+        env.runtime_config.sqlite = in_memory.sqlite;
+        Ok(env)
+    }
+}
+
+struct MockHttpInterceptor {
+    responses: Vec<MockHttpResponse>,
+}
+
+#[async_trait]
+impl OutboundHttpInterceptor for MockHttpInterceptor {
+    async fn intercept(&self, request: InterceptRequest) -> HttpResult<InterceptOutcome> {
+        let url = request.uri().to_string();
+        let mock = self
+            .responses
+            .iter()
+            .find(|mock| mock.matches(request.method(), &url))
+            .with_context(|| format!("no mock response for {} {url}", request.method()))
+            .map_err(HttpError::trap)?;
+        let resp = mock.to_response().map_err(HttpError::trap)?;
+        Ok(InterceptOutcome::Complete(resp))
+    }
+}
+
+struct MockLlmEngine(MockLlm);
+
+#[async_trait]
+impl LlmEngine for MockLlmEngine {
+    async fn infer(
+        &mut self,
+        model: v1::InferencingModel,
+        prompt: String,
+        _params: v2::InferencingParams,
+    ) -> Result<v2::InferencingResult, v2::Error> {
+        let mock = self
+            .0
+            .inferencing
+            .iter()
+            .find(|mock| {
+                mock.model.as_ref().map_or(true, |m| *m == model)
+                    && mock.prompt.as_ref().map_or(true, |p| prompt.contains(p))
+            })
+            .ok_or_else(|| {
+                v2::Error::RuntimeError(format!("no mock inferencing response for {model:?}"))
+            })?;
+        Ok(v2::InferencingResult {
+            text: mock.text.clone(),
+            usage: v2::InferencingUsage {
+                prompt_token_count: prompt.split_whitespace().count() as u32,
+                generated_token_count: mock.text.split_whitespace().count() as u32,
+            },
+        })
+    }
+
+    async fn generate_embeddings(
+        &mut self,
+        model: v2::EmbeddingModel,
+        data: Vec<String>,
+    ) -> Result<v2::EmbeddingsResult, v2::Error> {
+        let mock = self
+            .0
+            .embeddings
+            .iter()
+            .find(|mock| mock.model.as_ref().map_or(true, |m| *m == model))
+            .ok_or_else(|| {
+                v2::Error::RuntimeError(format!("no mock embeddings response for {model:?}"))
+            })?;
+        Ok(v2::EmbeddingsResult {
+            embeddings: mock.embeddings.clone(),
+            usage: v2::EmbeddingsUsage {
+                prompt_token_count: data
+                    .iter()
+                    .map(|d| d.split_whitespace().count())
+                    .sum::<usize>() as u32,
+            },
+        })
+    }
+
+    fn summary(&self) -> Option<String> {
+        Some("mock".into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use http::Request;
+
+    use super::*;
+
+    const FIXTURE: &str = r#"
+        [[outbound_http]]
+        method = "GET"
+        url = "https://example.com/users/1"
+        headers = { content-type = "application/json" }
+        body = '{"name": "Alice"}'
+
+        [[llm.inferencing]]
+        prompt = "hello"
+        text = "Hello yourself!"
+    "#;
+
+    #[tokio::test]
+    async fn outbound_http_returns_mock_responses() -> anyhow::Result<()> {
+        let fixture = MockFixture::from_toml(FIXTURE)?;
+        let interceptor = MockHttpInterceptor {
+            responses: fixture.outbound_http,
+        };
+
+        let req = Request::get("https://example.com/users/1").body(Vec::new())?;
+        let InterceptOutcome::Complete(resp) = interceptor.intercept(req.into()).await? else {
+            panic!("expected mock response");
+        };
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers()["content-type"], "application/json");
+        let body = resp.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, r#"{"name": "Alice"}"#);
+
+        let req = Request::post("https://example.com/users/1").body(Vec::new())?;
+        assert!(interceptor.intercept(req.into()).await.is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn llm_returns_mock_responses() -> anyhow::Result<()> {
+        let fixture = MockFixture::from_toml(FIXTURE)?;
+        let mut engine = MockLlmEngine(fixture.llm);
+
+        let result = engine
+            .infer("llama2-chat".into(), "say hello".into(), params())
+            .await?;
+        assert_eq!(result.text, "Hello yourself!");
+
+        let err = engine
+            .infer("llama2-chat".into(), "say goodbye".into(), params())
+            .await
+            .unwrap_err();
+        assert!(matches!(err, v2::Error::RuntimeError(_)));
+        Ok(())
+    }
+
+    fn params() -> v2::InferencingParams {
+        v2::InferencingParams {
+            max_tokens: 100,
+            repeat_penalty: 1.1,
+            repeat_penalty_last_n_token_count: 64,
+            temperature: 0.8,
+            top_k: 40,
+            top_p: 0.9,
+        }
+    }
+}
@@ -0,0 +1,280 @@
+use std::time::{Duration, Instant};
+
+use http::{header::AUTHORIZATION, Request, StatusCode};
+use hyper::body::Bytes;
+use spin_core::Trap;
+use spin_factor_key_value::KeyValueFactor;
+use spin_factor_sqlite::SqliteFactor;
+use spin_factors::RuntimeFactors;
+use spin_trigger::{cli::NoCliArgs, App, Trigger, TriggerApp};
+use spin_trigger_http::{HttpTrigger, DEFAULT_REQUEST_ID_HEADER};
+use spin_trigger_test::{
+    toml, HttpTestServer, InMemoryFactors, MockFixture, TestEnvironment, TestResponse,
+};
+use spin_world::v2::sqlite::Value;
+
+/// A trigger that leaves dispatch to the test.
+struct TestTrigger;
+
+impl<F: RuntimeFactors> Trigger<F> for TestTrigger {
+    const TYPE: &'static str = "test-trigger";
+    type CliArgs = NoCliArgs;
+    type InstanceState = ();
+
+    fn new(_cli_args: Self::CliArgs, _app: &App) -> anyhow::Result<Self> {
+        Ok(Self)
+    }
+
+    async fn run(self, _trigger_app: TriggerApp<Self, F>) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+const BUSY_LOOP_COMPONENT: &str = r#"
+(component
+  (core module $m
+    (func (export "run") (loop $forever (br $forever))))
+  (core instance $i (instantiate $m))
+  (func (export "run") (canon lift (core func $i "run"))))
+"#;
+
+#[tokio::test]
+async fn fake_clock_drives_execution_deadlines() -> anyhow::Result<()> {
+    let app = TestEnvironment::in_memory()
+        .extend_manifest(toml! {
+            [component.busy]
+            source = "busy.wasm"
+        })
+        .component_source("busy", BUSY_LOOP_COMPONENT)
+        .build::<TestTrigger>(NoCliArgs)
+        .await?;
+
+    let (instance, mut store) = app.prepare("busy").await?.instantiate(()).await?;
+    store.set_deadline(Instant::now() + Duration::from_millis(50));
+    app.clock().advance(Duration::from_millis(100));
+
+    let run = instance.get_typed_func::<(), ()>(&mut store, "run")?;
+    let err = run.call_async(&mut store, ()).await.unwrap_err();
+    assert_eq!(err.downcast_ref::<Trap>(), Some(&Trap::Interrupt));
+    Ok(())
+}
+
+#[tokio::test]
+async fn in_memory_sqlite_is_shared_between_connections() -> anyhow::Result<()> {
+    let app = TestEnvironment::in_memory()
+        .extend_manifest(toml! {
+            [component.empty]
+            source = "does-not-exist.wasm"
+            sqlite_databases = ["default"]
+            precompile = "lazy"
+        })
+        .build::<TestTrigger>(NoCliArgs)
+        .await?;
+    let sqlite = app
+        .trigger_app
+        .configured_app()
+        .app_state::<SqliteFactor>()?;
+
+    let writer = sqlite.get_connection("default").await.unwrap()?;
+    writer
+        .execute_batch("CREATE TABLE t (v INTEGER); INSERT INTO t VALUES (42);")
+        .await?;
+
+    let reader = sqlite.get_connection("default").await.unwrap()?;
+    let result = reader.query("SELECT v FROM t", vec![]).await?;
+    assert!(matches!(result.rows[0].values[..], [Value::Integer(42)]));
+    Ok(())
+}
+
+#[tokio::test]
+async fn http_requests_can_be_injected() -> anyhow::Result<()> {
+    let server = TestEnvironment::in_memory()
+        .extend_manifest(toml! {
+            [[trigger.http]]
+            route = "/..."
+            component = "empty"
+
+            [component.empty]
+            source = "empty.wasm"
+            precompile = "lazy"
+        })
+        .component_source("empty", "(component)")
+        .build_http()
+        .await?;
+
+    let health = server.get("/.well-known/spin/health").await?;
+    assert_eq!(health.status, StatusCode::OK);
+    assert_eq!(health.text()?, "OK");
+
+    // The component doesn't export an HTTP handler.
+    let resp = server.get("/hello").await?;
+    assert_eq!(resp.status, StatusCode::INTERNAL_SERVER_ERROR);
+    Ok(())
+}
+
+#[tokio::test]
+async fn http_error_responses_include_request_id() -> anyhow::Result<()> {
+    let server = TestEnvironment::in_memory()
+        .extend_manifest(toml! {
+            [[trigger.http]]
+            route = "/..."
+            component = "empty"
+
+            [component.empty]
+            source = "empty.wasm"
+            precompile = "lazy"
+        })
+        .component_source("empty", "(component)")
+        .build_http()
+        .await?;
+
+    let resp = server.get("/hello").await?;
+    assert_eq!(resp.status, StatusCode::INTERNAL_SERVER_ERROR);
+    let request_id = resp.headers[DEFAULT_REQUEST_ID_HEADER].to_str()?;
+    assert!(resp.text()?.contains(request_id));
+
+    let other = server.get("/hello").await?;
+    assert_ne!(other.headers[DEFAULT_REQUEST_ID_HEADER], request_id);
+    Ok(())
+}
+
+#[tokio::test]
+async fn mocked_key_value_stores_are_seeded_from_fixture() -> anyhow::Result<()> {
+    let fixture = MockFixture::from_toml(
+        r#"
+        [key_value.cache]
+        greeting = "hello"
+        "#,
+    )?;
+    let app = TestEnvironment::mocked(fixture)
+        .await?
+        .extend_manifest(toml! {
+            [component.empty]
+            source = "does-not-exist.wasm"
+            key_value_stores = ["default", "cache"]
+            precompile = "lazy"
+        })
+        .build::<TestTrigger>(NoCliArgs)
+        .await?;
+    let key_value = app
+        .trigger_app
+        .configured_app()
+        .app_state::<KeyValueFactor>()?;
+
+    let cache = key_value.get_store("cache").await.unwrap();
+    assert_eq!(cache.get("greeting").await?, Some(b"hello".to_vec()));
+    assert!(key_value.get_store("default").await.is_some());
+    Ok(())
+}
+
+#[tokio::test]
+async fn http_saturation_is_reported() -> anyhow::Result<()> {
+    let env = TestEnvironment::in_memory()
+        .extend_manifest(toml! {
+            [[trigger.http]]
+            route = "/..."
+            component = "empty"
+
+            [component.empty]
+            source = "empty.wasm"
+            precompile = "lazy"
+        })
+        .component_source("empty", "(component)");
+    let server = build_http_with_admin(env).await?;
+
+    // Saturation is only reported to the admin API.
+    let resp = server.get("/.well-known/spin/saturation").await?;
+    assert_eq!(resp.status, StatusCode::NOT_FOUND);
+
+    // The component can't handle requests, so they fail before executing.
+    server.get("/hello").await?;
+
+    let resp = get_saturation(&server).await?;
+    assert_eq!(resp.headers["content-type"], "application/json");
+    let saturation: serde_json::Value = serde_json::from_slice(&resp.body)?;
+    assert_eq!(saturation["queue_depth"], 0);
+    assert_eq!(saturation["in_flight"], 0);
+    assert_eq!(saturation["rejected_total"], 0);
+    assert_eq!(saturation["failed_total"], 1);
+    Ok(())
+}
+
+const ADMIN_TOKEN: &str = "s3cret";
+
+/// Builds the app with the HTTP trigger, serving the admin API.
+async fn build_http_with_admin(
+    env: TestEnvironment<InMemoryFactors>,
+) -> anyhow::Result<HttpTestServer<InMemoryFactors>> {
+    env.build_with(|app| {
+        let trigger = HttpTrigger::new(app, "127.0.0.1:0".parse()?, None)?;
+        Ok(trigger.with_admin_token(Some(ADMIN_TOKEN.into())))
+    })
+    .await?
+    .into_http_server()
+}
+
+async fn get_saturation(server: &HttpTestServer<InMemoryFactors>) -> anyhow::Result<TestResponse> {
+    let req = Request::get("/.well-known/spin/admin/saturation")
+        .header(AUTHORIZATION, format!("Bearer {ADMIN_TOKEN}"))
+        .body(Bytes::new())?;
+    server.send(req).await
+}
+
+#[tokio::test]
+async fn http_rate_limits_are_enforced_before_dispatch() -> anyhow::Result<()> {
+    let env = TestEnvironment::in_memory()
+        .extend_manifest(toml! {
+            [[trigger.http]]
+            route = "/..."
+            component = "empty"
+            rate_limit = { requests_per_second = 0.5, burst = 1, store = "default" }
+
+            [component.empty]
+            source = "empty.wasm"
+            precompile = "lazy"
+        })
+        .component_source("empty", "(component)");
+    let server = build_http_with_admin(env).await?;
+
+    let resp = server.get("/hello").await?;
+    assert_eq!(resp.status, StatusCode::INTERNAL_SERVER_ERROR);
+
+    let resp = server.get("/hello").await?;
+    assert_eq!(resp.status, StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(resp.headers["retry-after"], "2");
+
+    // Rate limited requests never reach the component.
+    let resp = get_saturation(&server).await?;
+    let saturation: serde_json::Value = serde_json::from_slice(&resp.body)?;
+    assert_eq!(saturation["rejected_total"], 0);
+    assert_eq!(saturation["failed_total"], 1);
+    Ok(())
+}
+
+#[tokio::test]
+async fn http_requests_without_a_jwt_are_rejected() -> anyhow::Result<()> {
+    let server = TestEnvironment::in_memory()
+        .extend_manifest(toml! {
+            [[trigger.http]]
+            route = "/..."
+            component = "empty"
+            jwt = { issuer = "https://auth.example.com", jwks_url = "https://auth.example.com/jwks.json" }
+
+            [component.empty]
+            source = "empty.wasm"
+            precompile = "lazy"
+        })
+        .component_source("empty", "(component)")
+        .build_http()
+        .await?;
+
+    let resp = server.get("/hello").await?;
+    assert_eq!(resp.status, StatusCode::UNAUTHORIZED);
+    assert_eq!(resp.headers["www-authenticate"], "Bearer");
+
+    // Unauthenticated requests never reach the component.
+    let resp = server.get("/.well-known/spin/saturation").await?;
+    let saturation: serde_json::Value = serde_json::from_slice(&resp.body)?;
+    assert_eq!(saturation["rejected_total"], 0);
+    Ok(())
+}
@@ -1,28 +1,54 @@
+mod audit;
+mod core_dump;
+mod debug;
+mod deterministic;
+mod host_calls;
 mod initial_kv_setter;
+mod kv_namespace_migration;
 mod launch_metadata;
+mod lifecycle;
+mod profiling;
+mod reload;
+mod sqlite_migrations;
 mod sqlite_statements;
 mod stdio;
 mod summary;
+mod tasks;
 
-use std::path::PathBuf;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
 use std::{future::Future, sync::Arc};
 
 use anyhow::{Context, Result};
 use clap::{Args, IntoApp, Parser};
 use spin_app::App;
+use spin_common::diagnostics::Diagnostics;
 use spin_common::sloth;
 use spin_common::ui::quoted_path;
 use spin_common::url::parse_file_url;
+use spin_factor_outbound_networking::OutboundNetworkingFactor;
 use spin_factors::RuntimeFactors;
 use spin_factors_executor::{ComponentLoader, FactorsExecutor};
 
 use crate::{loader::ComponentLoader as ComponentLoaderImpl, Trigger, TriggerApp};
+pub use audit::{AuditedCall, HostCallAudit, HostCallAuditHook};
+pub use core_dump::{CoreDumpHook, DEFAULT_CORE_DUMP_DIR_NAME};
+pub use debug::DEFAULT_DEBUG_ADDR;
+pub use deterministic::{
+    DeterministicExecutionHook, DEFAULT_VIRTUAL_CLOCK_START, DEFAULT_VIRTUAL_CLOCK_STEP,
+};
+pub use host_calls::{HostCall, HostCallRecording, HostCallRecordingHook};
 pub use initial_kv_setter::InitialKvSetterHook;
+pub use kv_namespace_migration::KeyValueNamespaceMigrationHook;
 pub use launch_metadata::LaunchMetadata;
+pub use lifecycle::{ensure_lifecycle_hooks_supported, run_lifecycle_hook, LifecycleStage};
+pub use profiling::{enable_guest_profiling, GuestProfilingHook, DEFAULT_PROFILE_DIR};
+pub use sqlite_migrations::SqliteMigrationsHook;
 pub use sqlite_statements::SqlStatementExecutorHook;
 use stdio::FollowComponents;
 pub use stdio::StdioLoggingExecutorHooks;
 pub use summary::{KeyValueDefaultStoreSummaryHook, SqliteDefaultStoreSummaryHook};
+pub use tasks::run_background_tasks;
 
 pub const APP_LOG_DIR: &str = "APP_LOG_DIR";
 pub const DISABLE_WASMTIME_CACHE: &str = "DISABLE_WASMTIME_CACHE";
@@ -34,6 +60,12 @@ pub const RUNTIME_CONFIG_FILE: &str = "RUNTIME_CONFIG_FILE";
 pub const SPIN_LOCKED_URL: &str = "SPIN_LOCKED_URL";
 pub const SPIN_LOCAL_APP_DIR: &str = "SPIN_LOCAL_APP_DIR";
 pub const SPIN_WORKING_DIR: &str = "SPIN_WORKING_DIR";
+/// Set to `false` for all but one of an app's trigger processes, so that the
+/// tasks and timers saved in a shared store are resumed by only one of them.
+pub const SPIN_RESUME_SAVED_TASKS: &str = "SPIN_RESUME_SAVED_TASKS";
+/// Set to `false` when the app's lifecycle hooks are run by `spin up`, once
+/// for the app, rather than by each of its trigger processes.
+pub const SPIN_RUN_LIFECYCLE_HOOKS: &str = "SPIN_RUN_LIFECYCLE_HOOKS";
 
 /// A command that runs a TriggerExecutor.
 #[derive(Parser, Debug)]
@@ -75,6 +107,17 @@ pub struct FactorsTriggerCommand<T: Trigger<B::Factors>, B: RuntimeFactorsBuilde
     #[clap(long = "disable-pooling")]
     pub disable_pooling: bool,
 
+    /// Compile components with debug info and serve the Debug Adapter
+    /// Protocol, so that editors such as VS Code can set breakpoints and step
+    /// through guest source code. Sessions are run by LLDB's debug adapter
+    /// (lldb-dap), which must be installed.
+    #[clap(long = "debug")]
+    pub debug: bool,
+
+    /// The address to serve the Debug Adapter Protocol on with --debug.
+    #[clap(long = "debug-addr", requires = "debug")]
+    pub debug_addr: Option<SocketAddr>,
+
     /// Print output to stdout/stderr only for given component(s)
     #[clap(
         name = FOLLOW_LOG_OPT,
@@ -100,6 +143,11 @@ pub struct FactorsTriggerCommand<T: Trigger<B::Factors>, B: RuntimeFactorsBuilde
     )]
     pub runtime_config_file: Option<PathBuf>,
 
+    /// Apply changes to the runtime config file while the app is running,
+    /// such as changes to a Kubernetes ConfigMap the file is mounted from.
+    #[clap(long = "watch-runtime-config", env = "SPIN_WATCH_RUNTIME_CONFIG")]
+    pub watch_runtime_config: bool,
+
     /// Set the application state directory path. This is used in the default
     /// locations for logs, key value stores, etc.
     ///
@@ -109,6 +157,29 @@ pub struct FactorsTriggerCommand<T: Trigger<B::Factors>, B: RuntimeFactorsBuilde
     #[clap(long)]
     pub state_dir: Option<String>,
 
+    /// Profile every instance of the given component(s), writing sampled guest
+    /// stacks to a file per instance in the profile directory.
+    #[clap(
+        long = "profile",
+        value_name = "COMPONENT_ID",
+        multiple_occurrences = true
+    )]
+    pub profile_components: Vec<String>,
+
+    /// The directory to write guest profiles to.
+    #[clap(long = "profile-dir", value_name = "DIR", default_value = DEFAULT_PROFILE_DIR)]
+    pub profile_dir: PathBuf,
+
+    /// Write a Wasm core dump whenever a component traps. Dumps are written
+    /// to the `core-dumps` directory in the application state directory
+    /// unless `--core-dump-dir` is given.
+    #[clap(long = "core-dumps")]
+    pub core_dumps: bool,
+
+    /// The directory to write core dumps to. Implies `--core-dumps`.
+    #[clap(long = "core-dump-dir", value_name = "DIR")]
+    pub core_dump_dir: Option<PathBuf>,
+
     #[clap(flatten)]
     pub trigger_args: T::CliArgs,
 
@@ -120,6 +191,11 @@ pub struct FactorsTriggerCommand<T: Trigger<B::Factors>, B: RuntimeFactorsBuilde
 
     #[clap(long = "launch-metadata-only", hide = true)]
     pub launch_metadata_only: bool,
+
+    /// Run the app's hook for the given lifecycle stage, then exit without
+    /// running the trigger.
+    #[clap(long = "lifecycle-hook-only", hide = true, value_enum)]
+    pub lifecycle_hook_only: Option<LifecycleStage>,
 }
 
 /// Configuration options that are common to all triggers.
@@ -129,6 +205,9 @@ pub struct FactorsConfig {
     pub working_dir: PathBuf,
     /// Path to the runtime config file.
     pub runtime_config_file: Option<PathBuf>,
+    /// Runtime config to use in place of reading `runtime_config_file`, which
+    /// is then only used to resolve relative paths.
+    pub runtime_config: Option<toml::Table>,
     /// Path to the state directory.
     pub state_dir: UserProvidedPath,
     /// Path to the local app directory.
@@ -137,6 +216,16 @@ pub struct FactorsConfig {
     pub follow_components: FollowComponents,
     /// Log directory for component stdout/stderr.
     pub log_dir: UserProvidedPath,
+    /// Which components should have their instances profiled.
+    pub profile_components: Vec<String>,
+    /// Directory for guest profiles.
+    pub profile_dir: PathBuf,
+    /// Whether to write core dumps of guest traps.
+    pub core_dumps: bool,
+    /// Directory for core dumps, if not the default within the state directory.
+    pub core_dump_dir: Option<PathBuf>,
+    /// Where diagnostics about the app are reported.
+    pub diagnostics: Diagnostics,
 }
 
 /// An empty implementation of clap::Args to be used as TriggerExecutor::RunConfig
@@ -171,19 +260,11 @@ impl<T: Trigger<B::Factors>, B: RuntimeFactorsBuilder> FactorsTriggerCommand<T,
 
         let follow_components = self.follow_components();
 
-        // Load App
-        let app = {
-            let path = parse_file_url(&locked_url)?;
-            let contents = std::fs::read(&path)
-                .with_context(|| format!("failed to read manifest at {}", quoted_path(&path)))?;
-            let locked =
-                serde_json::from_slice(&contents).context("failed to parse app lock file JSON")?;
-            App::new(locked_url, locked)
-        };
-
-        // Validate required host features
-        if let Err(unmet) = app.ensure_needs_only(&T::supported_host_requirements()) {
-            anyhow::bail!("This application requires the following features that are not available in this version of the '{}' trigger: {unmet}", T::TYPE);
+        let app = load_locked_app::<T, B::Factors>(&locked_url)?;
+        for component_id in &self.profile_components {
+            if app.get_component(component_id).is_none() {
+                anyhow::bail!("Cannot profile component {component_id:?}: the application has no such component");
+            }
         }
 
         let trigger = T::new(self.trigger_args, &app)?;
@@ -199,6 +280,13 @@ impl<T: Trigger<B::Factors>, B: RuntimeFactorsBuilder> FactorsTriggerCommand<T,
             config.disable_pooling();
         }
 
+        if self.debug {
+            config.enable_debug_info();
+            let addr =
+                debug::start_dap_server(self.debug_addr.unwrap_or(DEFAULT_DEBUG_ADDR)).await?;
+            print_debugger_instructions(addr);
+        }
+
         let state_dir = match &self.state_dir {
             // Make sure `--state-dir=""` unsets the state dir
             Some(s) if s.is_empty() => UserProvidedPath::Unset,
@@ -214,24 +302,73 @@ impl<T: Trigger<B::Factors>, B: RuntimeFactorsBuilder> FactorsTriggerCommand<T,
         let common_options = FactorsConfig {
             working_dir: PathBuf::from(working_dir),
             runtime_config_file: self.runtime_config_file.clone(),
+            runtime_config: None,
             state_dir,
             local_app_dir: local_app_dir.clone(),
             follow_components,
             log_dir,
+            profile_components: self.profile_components.clone(),
+            profile_dir: self.profile_dir.clone(),
+            core_dumps: self.core_dumps || self.core_dump_dir.is_some(),
+            core_dump_dir: self.core_dump_dir.clone(),
+            diagnostics: Diagnostics::new(),
         };
 
-        let run_fut = builder
-            .run(
+        let trigger_app = builder
+            .build(
                 app,
-                common_options,
-                self.builder_args,
+                &common_options,
+                &self.builder_args,
                 &ComponentLoaderImpl::new(),
             )
             .await?;
 
+        if let Some(stage) = self.lifecycle_hook_only {
+            let result = run_lifecycle_hook::<T, B::Factors>(&trigger_app, stage).await;
+            print_diagnostics(&common_options.diagnostics);
+            return result;
+        }
+        let run_lifecycle_hooks = std::env::var(SPIN_RUN_LIFECYCLE_HOOKS).as_deref() != Ok("false");
+        if run_lifecycle_hooks {
+            run_lifecycle_hook::<T, B::Factors>(&trigger_app, LifecycleStage::Startup).await?;
+        }
+        print_diagnostics(&common_options.diagnostics);
+
+        // Keep a handle to the app for the shutdown hook; the trigger consumes its own.
+        let shutdown_app = trigger_app.clone();
+        // Background tasks run on the latest version of the app, as reloaded.
+        let (apps_tx, apps_rx) = tokio::sync::watch::channel(trigger_app.clone());
+        let resume_saved_tasks = std::env::var(SPIN_RESUME_SAVED_TASKS).as_deref() != Ok("false");
+        let tasks_fut = run_background_tasks::<T, B::Factors>(apps_rx, resume_saved_tasks);
+        let (reconfigured_tx, reconfigured_rx) = tokio::sync::mpsc::unbounded_channel();
+        let watched_runtime_config = common_options
+            .runtime_config_file
+            .as_deref()
+            .filter(|_| self.watch_runtime_config);
+        let reload_fut = reload::reconfigure_on_change::<T, B>(
+            &locked_url,
+            apps_tx,
+            &common_options,
+            &self.builder_args,
+            watched_runtime_config,
+            reconfigured_tx,
+        );
+        let trigger_fut = builder
+            .trigger
+            .run_reconfigurable(trigger_app, reconfigured_rx);
+        // Background tasks and reloads run alongside the trigger and stop when it does.
+        let run_fut = async {
+            tokio::select! {
+                result = trigger_fut => result,
+                result = tasks_fut => result.context("background task runner failed"),
+                result = reload_fut => result.context("app reloader failed"),
+            }
+        };
+        crate::systemd::spawn_watchdog();
+
         let (abortable, abort_handle) = futures::future::abortable(run_fut);
         ctrlc::set_handler(move || abort_handle.abort())?;
-        match abortable.await {
+        let result = match abortable.await {
             Ok(Ok(())) => {
                 tracing::info!("Trigger executor shut down: exiting");
                 Ok(())
@@ -244,7 +381,16 @@ impl<T: Trigger<B::Factors>, B: RuntimeFactorsBuilder> FactorsTriggerCommand<T,
                 tracing::info!("User requested shutdown: exiting");
                 Ok(())
             }
-        }
+        };
+        crate::systemd::notify_stopping();
+
+        let shutdown_result = if run_lifecycle_hooks {
+            run_lifecycle_hook::<T, B::Factors>(&shutdown_app, LifecycleStage::Shutdown).await
+        } else {
+            Ok(())
+        };
+        print_learned_outbound_hosts(&shutdown_app);
+        result.and(shutdown_result)
     }
 
     fn follow_components(&self) -> FollowComponents {
@@ -259,6 +405,23 @@ impl<T: Trigger<B::Factors>, B: RuntimeFactorsBuilder> FactorsTriggerCommand<T,
     }
 }
 
+/// Loads the app locked at `locked_url`, checking that the trigger supports
+/// it.
+fn load_locked_app<T: Trigger<F>, F: RuntimeFactors>(locked_url: &str) -> Result<App> {
+    let path = parse_file_url(locked_url)?;
+    let contents = std::fs::read(&path)
+        .with_context(|| format!("failed to read manifest at {}", quoted_path(&path)))?;
+    let locked = serde_json::from_slice(&contents).context("failed to parse app lock file JSON")?;
+    let app = App::new(locked_url, locked);
+
+    // Validate required host features
+    if let Err(unmet) = app.ensure_needs_only(&T::supported_host_requirements()) {
+        anyhow::bail!("This application requires the following features that are not available in this version of the '{}' trigger: {unmet}", T::TYPE);
+    }
+    ensure_lifecycle_hooks_supported::<T, F>(&app)?;
+    Ok(app)
+}
+
 const SLOTH_WARNING_DELAY_MILLIS: u64 = 1250;
 
 fn warn_if_wasm_build_slothful() -> sloth::SlothGuard {
@@ -307,21 +470,43 @@ impl<T: Trigger<B::Factors>, B: RuntimeFactorsBuilder> TriggerAppBuilder<T, B> {
     pub async fn build(
         &mut self,
         app: App,
-        common_options: FactorsConfig,
-        options: B::CliArgs,
-        loader: &impl ComponentLoader,
+        common_options: &FactorsConfig,
+        options: &B::CliArgs,
+        loader: &(impl ComponentLoader + Clone + 'static),
     ) -> anyhow::Result<TriggerApp<T, B::Factors>> {
         let mut core_engine_builder = {
+            if common_options.core_dumps {
+                self.engine_config.enable_core_dumps();
+            }
             self.trigger.update_core_config(&mut self.engine_config)?;
 
             spin_core::Engine::builder(&self.engine_config)?
         };
         self.trigger.add_to_linker(core_engine_builder.linker())?;
 
-        let (factors, runtime_config) = B::build(&common_options, &options)?;
+        let (factors, runtime_config) = B::build(common_options, options)?;
+        if let Some(toml) = B::runtime_config_toml(&runtime_config) {
+            apply_telemetry_runtime_config(toml)?;
+            let runtime_config_dir = common_options
+                .runtime_config_file
+                .as_deref()
+                .and_then(Path::parent);
+            self.trigger
+                .update_from_runtime_config(toml, runtime_config_dir)?;
+        }
 
         let mut executor = FactorsExecutor::new(core_engine_builder, factors)?;
-        B::configure_app(&mut executor, &runtime_config, &common_options, &options)?;
+        B::configure_app(&mut executor, &runtime_config, common_options, options)?;
+        if !common_options.profile_components.is_empty() {
+            executor.add_hooks(GuestProfilingHook::new(
+                common_options.profile_components.clone(),
+                common_options.profile_dir.clone(),
+            ));
+        }
+        if let Some(limit) = app.trigger_concurrency_limit(self.trigger.trigger_type())? {
+            executor.limit_concurrency(limit);
+        }
+        executor.set_diagnostics(common_options.diagnostics.clone());
         let executor = Arc::new(executor);
 
         let configured_app = {
@@ -340,11 +525,89 @@ impl<T: Trigger<B::Factors>, B: RuntimeFactorsBuilder> TriggerAppBuilder<T, B> {
         app: App,
         common_options: FactorsConfig,
         options: B::CliArgs,
-        loader: &impl ComponentLoader,
+        loader: &(impl ComponentLoader + Clone + 'static),
     ) -> anyhow::Result<impl Future<Output = anyhow::Result<()>>> {
-        let configured_app = self.build(app, common_options, options, loader).await?;
+        let configured_app = self.build(app, &common_options, &options, loader).await?;
         Ok(self.trigger.run(configured_app))
     }
+
+    /// Reconfigures a [`TriggerApp`] built with the given options for a new
+    /// version of its app, re-reading the runtime config.
+    ///
+    /// See [`FactorsExecutorApp::reconfigure`](spin_factors_executor::FactorsExecutorApp::reconfigure).
+    pub async fn reconfigure(
+        trigger_app: &TriggerApp<T, B::Factors>,
+        app: App,
+        common_options: &FactorsConfig,
+        options: &B::CliArgs,
+    ) -> anyhow::Result<TriggerApp<T, B::Factors>> {
+        let (_, runtime_config) = B::build(common_options, options)?;
+        trigger_app.reconfigure(app, runtime_config.into()).await
+    }
+}
+
+/// Applies the `[telemetry]` section of the runtime config file.
+fn apply_telemetry_runtime_config(toml: &toml::Table) -> anyhow::Result<()> {
+    #[derive(serde::Deserialize)]
+    #[serde(deny_unknown_fields)]
+    struct TelemetryRuntimeConfig {
+        #[serde(default)]
+        propagation: spin_telemetry::PropagationFormat,
+        /// Embed trace context in the payloads of Redis messages, which have
+        /// no attributes to carry it.
+        #[serde(default)]
+        redis_payloads: bool,
+    }
+
+    let Some(telemetry) = toml.get("telemetry") else {
+        return Ok(());
+    };
+    let config: TelemetryRuntimeConfig = telemetry
+        .clone()
+        .try_into()
+        .context("invalid [telemetry] runtime config")?;
+    spin_telemetry::set_propagation_format(config.propagation);
+    spin_telemetry::set_payload_propagation(config.redis_payloads);
+    Ok(())
+}
+
+/// Tells the user how to connect a debugger to the DAP server.
+fn print_debugger_instructions(addr: SocketAddr) {
+    println!("Guest debugging enabled. Debug Adapter Protocol server listening on {addr}.");
+    println!("For example, add this configuration to VS Code's launch.json to start a session:");
+    println!(
+        r#"  {{ "name": "Debug Spin", "type": "lldb-dap", "request": "attach", "debugServer": {} }}"#,
+        addr.port()
+    );
+}
+
+/// Prints the diagnostics reported during startup, then prints each later one
+/// as it is reported.
+fn print_diagnostics(diagnostics: &Diagnostics) {
+    for diagnostic in diagnostics.take() {
+        terminal::warn!("{diagnostic}");
+    }
+    diagnostics.set_listener(|diagnostic| terminal::warn!("{diagnostic}"));
+}
+
+/// Prints the manifest entries needed to allow the outbound requests that
+/// were allowed only because outbound hosts were being learned.
+fn print_learned_outbound_hosts<T: Trigger<F>, F: RuntimeFactors>(app: &TriggerApp<T, F>) {
+    let Some(suggestion) = app
+        .configured_app()
+        .app_state::<OutboundNetworkingFactor>()
+        .ok()
+        .and_then(|networking| networking.suggested_allowed_outbound_hosts())
+    else {
+        return;
+    };
+    if suggestion.is_empty() {
+        eprintln!("No outbound requests needed to be allowed.");
+    } else {
+        eprintln!("To allow the outbound requests made, update these manifest component sections:");
+        eprintln!();
+        eprint!("{suggestion}");
+    }
 }
 
 /// A builder for runtime factors.
@@ -362,6 +625,13 @@ pub trait RuntimeFactorsBuilder {
         args: &Self::CliArgs,
     ) -> anyhow::Result<(Self::Factors, Self::RuntimeConfig)>;
 
+    /// The runtime config file's TOML, for triggers to read their own sections
+    /// from. See [`Trigger::update_from_runtime_config`].
+    fn runtime_config_toml(runtime_config: &Self::RuntimeConfig) -> Option<&toml::Table> {
+        let _ = runtime_config;
+        None
+    }
+
     /// Configure the factors in the executor.
     fn configure_app<U: Send + 'static>(
         executor: &mut FactorsExecutor<Self::Factors, U>,
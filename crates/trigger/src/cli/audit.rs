@@ -0,0 +1,653 @@
+use std::{
+    fs::File,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime},
+};
+
+use anyhow::Context as _;
+use http::{Request, Response};
+use serde::{Deserialize, Serialize};
+use spin_core::async_trait;
+use spin_factor_key_value::{Cas, Error as KeyValueError, KeyValueFactor, Store, StoreManager};
+use spin_factor_llm::{LlmEngine, LlmFactor};
+use spin_factor_outbound_http::{
+    intercept::{HyperBody, InterceptOutcome, InterceptRequest, OutboundHttpInterceptor},
+    HttpResult, OutboundHttpFactor,
+};
+use spin_factor_sqlite::{Connection, ConnectionCreator, SqliteFactor};
+use spin_factors::RuntimeFactors;
+use spin_factors_executor::ExecutorHooks;
+use spin_world::v1::llm::InferencingModel;
+use spin_world::v2::{llm, sqlite};
+
+/// A host interface call made by a component instance.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditedCall {
+    /// The host interface, e.g. `"spin:key-value/store"`.
+    pub interface: String,
+    /// The function called, e.g. `"get"`.
+    pub function: String,
+    /// What the call used, e.g. a key-value store label or the origin of an
+    /// outbound request.
+    pub target: String,
+    /// How long the call took, in microseconds.
+    pub duration_us: u64,
+    /// `"ok"`, an HTTP status such as `"200"`, or an error message. Outbound
+    /// requests whose response wasn't seen, e.g. because the request failed,
+    /// are `"unknown"`.
+    pub outcome: String,
+}
+
+/// The host interface calls made by one component instance, e.g. while
+/// handling a single request; one line of a [`HostCallAuditHook`] log.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct HostCallAudit {
+    /// The ID of the component that made the calls.
+    pub component_id: String,
+    /// When the instance was prepared, in milliseconds since the Unix epoch.
+    pub started_at_ms: u64,
+    /// The calls, in the order they returned.
+    pub calls: Vec<AuditedCall>,
+}
+
+/// An [`ExecutorHooks`] that appends the outbound HTTP, key-value, SQLite and
+/// LLM calls made by each instance to an audit log, one JSON [`HostCallAudit`]
+/// per line.
+///
+/// Unlike [`HostCallRecordingHook`](super::HostCallRecordingHook), no data is
+/// logged, only which capabilities were used and how, so the log can be used
+/// to find the minimal `allowed_outbound_hosts`, `key_value_stores`,
+/// `sqlite_databases` and `ai_models` grants a component needs, or to spot a
+/// third-party component doing something unexpected. Calls to other
+/// interfaces, such as outbound Redis or sockets, aren't audited.
+pub struct HostCallAuditHook {
+    path: PathBuf,
+    sink: Arc<Mutex<File>>,
+}
+
+impl HostCallAuditHook {
+    /// Appends audits to the file at `path`, creating it if necessary.
+    pub fn new(path: impl Into<PathBuf>) -> anyhow::Result<Self> {
+        let path = path.into();
+        let file = File::options()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .with_context(|| format!("failed to open host call audit log {path:?}"))?;
+        Ok(Self {
+            path,
+            sink: Arc::new(Mutex::new(file)),
+        })
+    }
+
+    /// The path of the audit log.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl<F: RuntimeFactors, U> ExecutorHooks<F, U> for HostCallAuditHook {
+    fn prepare_instance(
+        &self,
+        builder: &mut spin_factors_executor::FactorsInstanceBuilder<F, U>,
+    ) -> anyhow::Result<()> {
+        let log = Arc::new(AuditLog::new(
+            builder.app_component().id().to_string(),
+            self.sink.clone(),
+        ));
+        if let Some(outbound_http) = builder.factor_builder::<OutboundHttpFactor>() {
+            outbound_http.add_request_interceptor(AuditedHttp {
+                log: log.clone(),
+                pending: Default::default(),
+            });
+        }
+        if let Some(key_value) = builder.factor_builder::<KeyValueFactor>() {
+            let log = log.clone();
+            key_value.wrap_store_manager(|inner| Arc::new(AuditedStoreManager { inner, log }));
+        }
+        if let Some(sqlite) = builder.factor_builder::<SqliteFactor>() {
+            sqlite.wrap_connection_creators(|_, inner| {
+                Arc::new(AuditedConnectionCreator {
+                    inner,
+                    log: log.clone(),
+                })
+            });
+        }
+        if let Some(llm) = builder.factor_builder::<LlmFactor>() {
+            llm.wrap_engine(|inner| Arc::new(tokio::sync::Mutex::new(AuditedLlm { inner, log })));
+        }
+        Ok(())
+    }
+}
+
+/// The calls of one instance, written to the sink when the instance is dropped.
+struct AuditLog {
+    audit: Mutex<HostCallAudit>,
+    sink: Arc<Mutex<File>>,
+}
+
+impl AuditLog {
+    fn new(component_id: String, sink: Arc<Mutex<File>>) -> Self {
+        let started_at_ms = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        Self {
+            audit: Mutex::new(HostCallAudit {
+                component_id,
+                started_at_ms,
+                calls: vec![],
+            }),
+            sink,
+        }
+    }
+
+    fn push(
+        &self,
+        interface: &str,
+        function: &str,
+        target: &str,
+        started: Instant,
+        outcome: String,
+    ) {
+        self.audit.lock().unwrap().calls.push(AuditedCall {
+            interface: interface.into(),
+            function: function.into(),
+            target: target.into(),
+            duration_us: started.elapsed().as_micros() as u64,
+            outcome,
+        });
+    }
+
+    fn push_result<T, E: std::fmt::Display>(
+        &self,
+        interface: &str,
+        function: &str,
+        target: &str,
+        started: Instant,
+        result: &Result<T, E>,
+    ) {
+        let outcome = match result {
+            Ok(_) => "ok".into(),
+            Err(err) => err.to_string(),
+        };
+        self.push(interface, function, target, started, outcome);
+    }
+}
+
+impl Drop for AuditLog {
+    fn drop(&mut self) {
+        let audit = std::mem::take(self.audit.get_mut().unwrap());
+        if audit.calls.is_empty() {
+            return;
+        }
+        let result = serde_json::to_vec(&audit)
+            .map_err(anyhow::Error::from)
+            .and_then(|mut line| {
+                line.push(b'\n');
+                Ok(self.sink.lock().unwrap().write_all(&line)?)
+            });
+        if let Err(err) = result {
+            tracing::error!("Failed to write host call audit: {err:?}");
+        }
+    }
+}
+
+const HTTP_INTERFACE: &str = "wasi:http/outgoing-handler";
+
+struct AuditedHttp {
+    log: Arc<AuditLog>,
+    // Requests awaiting a response: (method, URL, start time)
+    pending: Mutex<Vec<(String, String, Instant)>>,
+}
+
+impl AuditedHttp {
+    fn origin(url: &str) -> String {
+        match url.parse::<http::Uri>() {
+            Ok(uri) => match (uri.scheme_str(), uri.authority()) {
+                (Some(scheme), Some(authority)) => format!("{scheme}://{authority}"),
+                _ => url.to_owned(),
+            },
+            Err(_) => url.to_owned(),
+        }
+    }
+}
+
+#[async_trait]
+impl OutboundHttpInterceptor for AuditedHttp {
+    async fn intercept(&self, request: InterceptRequest) -> HttpResult<InterceptOutcome> {
+        self.pending.lock().unwrap().push((
+            request.method().to_string(),
+            request.uri().to_string(),
+            Instant::now(),
+        ));
+        Ok(InterceptOutcome::Continue(request))
+    }
+
+    async fn intercept_response(
+        &self,
+        request: &Request<()>,
+        response: Response<HyperBody>,
+    ) -> HttpResult<Response<HyperBody>> {
+        let method = request.method().to_string();
+        let url = request.uri().to_string();
+        let started = {
+            let mut pending = self.pending.lock().unwrap();
+            // Later interceptors may have changed the request, in which case
+            // assume it is the oldest one.
+            let index = pending
+                .iter()
+                .position(|(m, u, _)| *m == method && *u == url)
+                .or((!pending.is_empty()).then_some(0));
+            index.map(|index| pending.remove(index).2)
+        };
+        self.log.push(
+            HTTP_INTERFACE,
+            "handle",
+            &Self::origin(&url),
+            started.unwrap_or_else(Instant::now),
+            response.status().as_u16().to_string(),
+        );
+        Ok(response)
+    }
+}
+
+impl Drop for AuditedHttp {
+    fn drop(&mut self) {
+        // The responses to requests that failed, e.g. because the host was not
+        // allowed, or that were handled by another interceptor, e.g. for
+        // service chaining, are never seen.
+        for (_, url, started) in self.pending.get_mut().unwrap().drain(..) {
+            self.log.push(
+                HTTP_INTERFACE,
+                "handle",
+                &Self::origin(&url),
+                started,
+                "unknown".into(),
+            );
+        }
+    }
+}
+
+const KEY_VALUE_INTERFACE: &str = "spin:key-value/store";
+
+struct AuditedStoreManager {
+    inner: Arc<dyn StoreManager>,
+    log: Arc<AuditLog>,
+}
+
+#[async_trait]
+impl StoreManager for AuditedStoreManager {
+    async fn get(&self, name: &str) -> Result<Arc<dyn Store>, KeyValueError> {
+        let started = Instant::now();
+        let result = self.inner.get(name).await;
+        self.log
+            .push_result(KEY_VALUE_INTERFACE, "open", name, started, &result);
+        Ok(Arc::new(AuditedStore {
+            name: name.to_string(),
+            inner: result?,
+            log: self.log.clone(),
+        }))
+    }
+
+    fn is_defined(&self, store_name: &str) -> bool {
+        self.inner.is_defined(store_name)
+    }
+
+    fn summary(&self, store_name: &str) -> Option<String> {
+        self.inner.summary(store_name)
+    }
+}
+
+struct AuditedStore {
+    name: String,
+    inner: Arc<dyn Store>,
+    log: Arc<AuditLog>,
+}
+
+impl AuditedStore {
+    fn audited<T>(
+        &self,
+        function: &str,
+        started: Instant,
+        result: Result<T, KeyValueError>,
+    ) -> Result<T, KeyValueError> {
+        self.log
+            .push_result(KEY_VALUE_INTERFACE, function, &self.name, started, &result);
+        result
+    }
+}
+
+#[async_trait]
+impl Store for AuditedStore {
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, KeyValueError> {
+        let started = Instant::now();
+        self.audited("get", started, self.inner.get(key).await)
+    }
+
+    async fn set(&self, key: &str, value: &[u8]) -> Result<(), KeyValueError> {
+        let started = Instant::now();
+        self.audited("set", started, self.inner.set(key, value).await)
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), KeyValueError> {
+        let started = Instant::now();
+        self.audited("delete", started, self.inner.delete(key).await)
+    }
+
+    async fn exists(&self, key: &str) -> Result<bool, KeyValueError> {
+        let started = Instant::now();
+        self.audited("exists", started, self.inner.exists(key).await)
+    }
+
+    async fn get_keys(&self) -> Result<Vec<String>, KeyValueError> {
+        let started = Instant::now();
+        self.audited("get-keys", started, self.inner.get_keys().await)
+    }
+
+    async fn get_many(
+        &self,
+        keys: Vec<String>,
+    ) -> Result<Vec<(String, Option<Vec<u8>>)>, KeyValueError> {
+        let started = Instant::now();
+        self.audited("get-many", started, self.inner.get_many(keys).await)
+    }
+
+    async fn set_many(&self, key_values: Vec<(String, Vec<u8>)>) -> Result<(), KeyValueError> {
+        let started = Instant::now();
+        self.audited("set-many", started, self.inner.set_many(key_values).await)
+    }
+
+    async fn delete_many(&self, keys: Vec<String>) -> Result<(), KeyValueError> {
+        let started = Instant::now();
+        self.audited("delete-many", started, self.inner.delete_many(keys).await)
+    }
+
+    async fn increment(&self, key: String, delta: i64) -> Result<i64, KeyValueError> {
+        let started = Instant::now();
+        self.audited("increment", started, self.inner.increment(key, delta).await)
+    }
+
+    async fn new_compare_and_swap(
+        &self,
+        bucket_rep: u32,
+        key: &str,
+    ) -> Result<Arc<dyn Cas>, KeyValueError> {
+        let started = Instant::now();
+        let result = self.inner.new_compare_and_swap(bucket_rep, key).await;
+        self.audited("compare-and-swap", started, result)
+    }
+
+    async fn acquire_lease(
+        &self,
+        key: &str,
+        token: &str,
+        ttl: Duration,
+    ) -> Result<bool, KeyValueError> {
+        let started = Instant::now();
+        let result = self.inner.acquire_lease(key, token, ttl).await;
+        self.audited("acquire-lease", started, result)
+    }
+
+    async fn renew_lease(
+        &self,
+        key: &str,
+        token: &str,
+        ttl: Duration,
+    ) -> Result<bool, KeyValueError> {
+        let started = Instant::now();
+        let result = self.inner.renew_lease(key, token, ttl).await;
+        self.audited("renew-lease", started, result)
+    }
+
+    async fn release_lease(&self, key: &str, token: &str) -> Result<bool, KeyValueError> {
+        let started = Instant::now();
+        let result = self.inner.release_lease(key, token).await;
+        self.audited("release-lease", started, result)
+    }
+}
+
+const SQLITE_INTERFACE: &str = "spin:sqlite/sqlite";
+
+struct AuditedConnectionCreator {
+    inner: Arc<dyn ConnectionCreator>,
+    log: Arc<AuditLog>,
+}
+
+#[async_trait]
+impl ConnectionCreator for AuditedConnectionCreator {
+    async fn create_connection(
+        &self,
+        label: &str,
+    ) -> Result<Box<dyn Connection + 'static>, sqlite::Error> {
+        let started = Instant::now();
+        let result = self.inner.create_connection(label).await;
+        self.log
+            .push_result(SQLITE_INTERFACE, "open", label, started, &result);
+        Ok(Box::new(AuditedConnection {
+            label: label.to_string(),
+            inner: result?,
+            log: self.log.clone(),
+        }))
+    }
+}
+
+struct AuditedConnection {
+    label: String,
+    inner: Box<dyn Connection>,
+    log: Arc<AuditLog>,
+}
+
+impl AuditedConnection {
+    fn audited<T, E: std::fmt::Display>(
+        &self,
+        function: &str,
+        started: Instant,
+        result: Result<T, E>,
+    ) -> Result<T, E> {
+        self.log
+            .push_result(SQLITE_INTERFACE, function, &self.label, started, &result);
+        result
+    }
+}
+
+#[async_trait]
+impl Connection for AuditedConnection {
+    async fn query(
+        &self,
+        query: &str,
+        parameters: Vec<sqlite::Value>,
+    ) -> Result<sqlite::QueryResult, sqlite::Error> {
+        let started = Instant::now();
+        let result = self.inner.query(query, parameters).await;
+        self.audited("execute", started, result)
+    }
+
+    async fn execute_batch(&self, statements: &str) -> anyhow::Result<()> {
+        let started = Instant::now();
+        let result = self.inner.execute_batch(statements).await;
+        self.audited("execute-batch", started, result)
+    }
+
+    async fn changes(&self) -> Result<u64, sqlite::Error> {
+        let started = Instant::now();
+        self.audited("changes", started, self.inner.changes().await)
+    }
+
+    async fn last_insert_rowid(&self) -> Result<i64, sqlite::Error> {
+        let started = Instant::now();
+        let result = self.inner.last_insert_rowid().await;
+        self.audited("last-insert-rowid", started, result)
+    }
+
+    fn summary(&self) -> Option<String> {
+        self.inner.summary()
+    }
+}
+
+const LLM_INTERFACE: &str = "fermyon:spin/llm";
+
+struct AuditedLlm {
+    inner: Arc<tokio::sync::Mutex<dyn LlmEngine>>,
+    log: Arc<AuditLog>,
+}
+
+#[async_trait]
+impl LlmEngine for AuditedLlm {
+    async fn infer(
+        &mut self,
+        model: InferencingModel,
+        prompt: String,
+        params: llm::InferencingParams,
+    ) -> Result<llm::InferencingResult, llm::Error> {
+        let started = Instant::now();
+        let result = self
+            .inner
+            .lock()
+            .await
+            .infer(model.clone(), prompt, params)
+            .await;
+        self.log
+            .push_result(LLM_INTERFACE, "infer", &model, started, &result);
+        result
+    }
+
+    async fn generate_embeddings(
+        &mut self,
+        model: llm::EmbeddingModel,
+        data: Vec<String>,
+    ) -> Result<llm::EmbeddingsResult, llm::Error> {
+        let started = Instant::now();
+        let result = self
+            .inner
+            .lock()
+            .await
+            .generate_embeddings(model.clone(), data)
+            .await;
+        self.log.push_result(
+            LLM_INTERFACE,
+            "generate-embeddings",
+            &model,
+            started,
+            &result,
+        );
+        result
+    }
+
+    fn summary(&self) -> Option<String> {
+        self.inner.try_lock().ok()?.summary()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn audits_are_appended_as_json_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let hook = HostCallAuditHook::new(dir.path().join("audit.jsonl")).unwrap();
+
+        for component_id in ["first", "second"] {
+            let log = AuditLog::new(component_id.into(), hook.sink.clone());
+            log.push_result::<_, KeyValueError>(
+                KEY_VALUE_INTERFACE,
+                "get",
+                "default",
+                Instant::now(),
+                &Ok(()),
+            );
+            log.push_result::<(), _>(
+                KEY_VALUE_INTERFACE,
+                "set",
+                "default",
+                Instant::now(),
+                &Err(KeyValueError::Other("store is read-only".into())),
+            );
+        }
+        // Instances that made no calls are not logged.
+        drop(AuditLog::new("idle".into(), hook.sink.clone()));
+
+        let contents = std::fs::read_to_string(hook.path()).unwrap();
+        let audits = contents
+            .lines()
+            .map(|line| serde_json::from_str::<HostCallAudit>(line).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(audits.len(), 2);
+        assert_eq!(audits[1].component_id, "second");
+        let outcomes = audits[0]
+            .calls
+            .iter()
+            .map(|call| call.outcome.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(outcomes[0], "ok");
+        assert!(outcomes[1].contains("store is read-only"));
+    }
+
+    #[tokio::test]
+    async fn sqlite_calls_are_audited() {
+        struct NoRows;
+
+        #[async_trait]
+        impl Connection for NoRows {
+            async fn query(
+                &self,
+                _query: &str,
+                _parameters: Vec<sqlite::Value>,
+            ) -> Result<sqlite::QueryResult, sqlite::Error> {
+                Ok(sqlite::QueryResult {
+                    columns: vec![],
+                    rows: vec![],
+                })
+            }
+
+            async fn execute_batch(&self, _statements: &str) -> anyhow::Result<()> {
+                Ok(())
+            }
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let hook = HostCallAuditHook::new(dir.path().join("audit.jsonl")).unwrap();
+        let log = Arc::new(AuditLog::new("db".into(), hook.sink.clone()));
+        let creator = AuditedConnectionCreator {
+            inner: Arc::new(|| -> anyhow::Result<Box<dyn Connection>> { Ok(Box::new(NoRows)) }),
+            log: log.clone(),
+        };
+        let connection = creator.create_connection("default").await.unwrap();
+        connection.query("SELECT 1", vec![]).await.unwrap();
+        connection.changes().await.unwrap_err();
+        drop((creator, connection, log));
+
+        let contents = std::fs::read_to_string(hook.path()).unwrap();
+        let audit = serde_json::from_str::<HostCallAudit>(contents.trim()).unwrap();
+        let calls = audit
+            .calls
+            .iter()
+            .map(|call| (call.function.as_str(), call.target.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            calls,
+            [
+                ("open", "default"),
+                ("execute", "default"),
+                ("changes", "default")
+            ]
+        );
+        assert!(audit
+            .calls
+            .iter()
+            .all(|call| call.interface == SQLITE_INTERFACE));
+        assert_ne!(audit.calls[2].outcome, "ok");
+    }
+
+    #[test]
+    fn http_targets_are_origins() {
+        assert_eq!(
+            AuditedHttp::origin("https://example.com:8443/path?q=1"),
+            "https://example.com:8443"
+        );
+        assert_eq!(AuditedHttp::origin("/relative"), "/relative");
+    }
+}
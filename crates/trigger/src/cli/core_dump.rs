@@ -0,0 +1,31 @@
+use std::path::PathBuf;
+
+use spin_factors::RuntimeFactors;
+use spin_factors_executor::{ExecutorHooks, FactorsInstanceBuilder};
+
+/// The name of the directory core dumps are written to within the state
+/// directory if not otherwise specified.
+pub const DEFAULT_CORE_DUMP_DIR_NAME: &str = "core-dumps";
+
+/// An [`ExecutorHooks`] that has guest traps of every instance written as core
+/// dumps to the given directory.
+///
+/// Core dumps must also be enabled in the engine with
+/// [`spin_core::Config::enable_core_dumps`], and triggers must pass guest
+/// errors to [`crate::core_dump::capture`].
+pub struct CoreDumpHook {
+    dir: PathBuf,
+}
+
+impl CoreDumpHook {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+}
+
+impl<F: RuntimeFactors, U> ExecutorHooks<F, U> for CoreDumpHook {
+    fn prepare_instance(&self, builder: &mut FactorsInstanceBuilder<F, U>) -> anyhow::Result<()> {
+        builder.store_builder().core_dump_dir(self.dir.clone());
+        Ok(())
+    }
+}
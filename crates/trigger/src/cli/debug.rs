@@ -0,0 +1,216 @@
+//! A Debug Adapter Protocol (DAP) server for debugging guests with `--debug`.
+//!
+//! Editors such as VS Code connect to the server as they would to any debug
+//! adapter. Each session is handed to LLDB's debug adapter (`lldb-dap`, or
+//! `lldb-vscode` in older LLVM releases), which reads the DWARF debug info of
+//! guests through Wasmtime's JIT debugging interface. Whatever the editor asks
+//! to launch or attach to, the session attaches to this process.
+
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+use std::process::Stdio;
+
+use anyhow::{bail, Context, Result};
+use serde_json::Value;
+use tokio::io::{
+    AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader,
+};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::process::{Child, Command};
+
+/// The address the DAP server listens on unless `--debug-addr` is given.
+pub const DEFAULT_DEBUG_ADDR: SocketAddr =
+    SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 4711));
+
+/// Env var naming the LLDB debug adapter to run, if it isn't `lldb-dap` or
+/// `lldb-vscode` on the `PATH`.
+const SPIN_LLDB_DAP: &str = "SPIN_LLDB_DAP";
+
+const LLDB_DAP_NAMES: &[&str] = &["lldb-dap", "lldb-vscode"];
+
+/// Starts a DAP server on the given address, returning the address it is
+/// listening on. Sessions run until the editor or the adapter disconnects.
+pub async fn start_dap_server(addr: SocketAddr) -> Result<SocketAddr> {
+    let listener = TcpListener::bind(addr)
+        .await
+        .with_context(|| format!("cannot start debug adapter server on {addr}"))?;
+    let addr = listener.local_addr()?;
+    tokio::spawn(async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, peer)) => {
+                    tracing::info!("Debug session started by {peer}");
+                    tokio::spawn(async move {
+                        if let Err(err) = run_session(stream).await {
+                            terminal::error!("Debug session failed: {err:#}");
+                        }
+                        tracing::info!("Debug session by {peer} ended");
+                    });
+                }
+                Err(err) => tracing::warn!("Cannot accept debug session: {err}"),
+            }
+        }
+    });
+    Ok(addr)
+}
+
+/// Relays a session between the editor and a new LLDB debug adapter.
+async fn run_session(stream: TcpStream) -> Result<()> {
+    let mut adapter = spawn_adapter()?;
+    let mut adapter_in = adapter.stdin.take().context("debug adapter has no stdin")?;
+    let mut adapter_out = adapter
+        .stdout
+        .take()
+        .context("debug adapter has no stdout")?;
+    let (client_read, mut client_write) = stream.into_split();
+
+    let to_adapter = async {
+        let mut client_read = BufReader::new(client_read);
+        while let Some(mut message) = read_message(&mut client_read).await? {
+            attach_to_self(&mut message);
+            write_message(&mut adapter_in, &message).await?;
+        }
+        anyhow::Ok(())
+    };
+    let to_client = async {
+        tokio::io::copy(&mut adapter_out, &mut client_write).await?;
+        anyhow::Ok(())
+    };
+    // The session is over when either side disconnects; the adapter is
+    // killed when dropped.
+    tokio::select! {
+        res = to_adapter => res,
+        res = to_client => res,
+    }
+}
+
+fn spawn_adapter() -> Result<Child> {
+    let names = match std::env::var(SPIN_LLDB_DAP) {
+        Ok(name) => vec![name],
+        Err(_) => LLDB_DAP_NAMES.iter().map(|name| name.to_string()).collect(),
+    };
+    for name in &names {
+        let spawned = Command::new(name)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn();
+        match spawned {
+            Ok(child) => return Ok(child),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(err) => return Err(err).with_context(|| format!("cannot run {name}")),
+        }
+    }
+    bail!(
+        "cannot find LLDB's debug adapter ({}); install LLDB, or set {SPIN_LLDB_DAP} to the adapter's path",
+        names.join(" or ")
+    )
+}
+
+/// Makes a `launch` or `attach` request attach to this process.
+fn attach_to_self(message: &mut Value) {
+    let is_start_request = message["type"] == "request"
+        && matches!(message["command"].as_str(), Some("launch" | "attach"));
+    if !is_start_request {
+        return;
+    }
+    message["command"] = "attach".into();
+    if !message["arguments"].is_object() {
+        message["arguments"] = Value::Object(Default::default());
+    }
+    let arguments = message["arguments"].as_object_mut().unwrap();
+    arguments.remove("program");
+    arguments.insert("pid".into(), std::process::id().into());
+}
+
+/// Reads a DAP message, or `None` at the end of the stream.
+async fn read_message(reader: &mut (impl AsyncBufRead + Unpin)) -> Result<Option<Value>> {
+    let mut content_length = None;
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line).await? == 0 {
+            if content_length.is_none() {
+                return Ok(None);
+            }
+            bail!("debug session ended within a message header");
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("Content-Length") {
+                content_length = Some(value.trim().parse::<usize>().with_context(|| {
+                    format!("invalid Content-Length {value:?} in debug session")
+                })?);
+            }
+        }
+    }
+    let content_length = content_length.context("debug message has no Content-Length")?;
+    let mut content = vec![0; content_length];
+    reader.read_exact(&mut content).await?;
+    Ok(Some(
+        serde_json::from_slice(&content).context("invalid debug message")?,
+    ))
+}
+
+async fn write_message(writer: &mut (impl AsyncWrite + Unpin), message: &Value) -> Result<()> {
+    let content = serde_json::to_vec(message)?;
+    let header = format!("Content-Length: {}\r\n\r\n", content.len());
+    writer.write_all(header.as_bytes()).await?;
+    writer.write_all(&content).await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn messages_round_trip() -> Result<()> {
+        let messages = [
+            json!({ "seq": 1, "type": "request", "command": "initialize" }),
+            json!({ "seq": 2, "type": "request", "command": "threads" }),
+        ];
+        let mut stream = vec![];
+        for message in &messages {
+            write_message(&mut stream, message).await?;
+        }
+        let mut reader = BufReader::new(stream.as_slice());
+        for message in &messages {
+            assert_eq!(Some(message), read_message(&mut reader).await?.as_ref());
+        }
+        assert_eq!(None, read_message(&mut reader).await?);
+        Ok(())
+    }
+
+    #[test]
+    fn launch_requests_attach_to_this_process() {
+        let mut launch = json!({
+            "seq": 3,
+            "type": "request",
+            "command": "launch",
+            "arguments": { "program": "spin", "sourceMap": [] },
+        });
+        attach_to_self(&mut launch);
+        assert_eq!(
+            json!({
+                "seq": 3,
+                "type": "request",
+                "command": "attach",
+                "arguments": { "pid": std::process::id(), "sourceMap": [] },
+            }),
+            launch
+        );
+
+        let mut other = json!({ "seq": 4, "type": "request", "command": "threads" });
+        attach_to_self(&mut other);
+        assert_eq!(
+            json!({ "seq": 4, "type": "request", "command": "threads" }),
+            other
+        );
+    }
+}
@@ -0,0 +1,167 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use spin_factor_wasi::{HostMonotonicClock, HostWallClock, WasiFactor};
+use spin_factors::RuntimeFactors;
+use spin_factors_executor::{ExecutorHooks, FactorsInstanceBuilder};
+
+/// The default wall clock time at which a virtual clock starts:
+/// 2024-01-01T00:00:00Z.
+pub const DEFAULT_VIRTUAL_CLOCK_START: Duration = Duration::from_secs(1_704_067_200);
+
+/// The default amount a virtual clock advances each time it is read.
+pub const DEFAULT_VIRTUAL_CLOCK_STEP: Duration = Duration::from_millis(1);
+
+/// An [`ExecutorHooks`] that makes the WASI random bytes and clocks seen by
+/// components deterministic.
+///
+/// Each instance gets random streams seeded from the hook's seed and its
+/// component ID, and a virtual clock that starts at a fixed time and advances
+/// by a fixed step each time it is read. Handling the same request with the
+/// same component therefore sees the same random bytes and times on every run.
+pub struct DeterministicExecutionHook {
+    seed: u64,
+    clock_start: Duration,
+    clock_step: Duration,
+}
+
+impl DeterministicExecutionHook {
+    /// Creates a hook seeding random streams from `seed`, with virtual clocks
+    /// starting at [`DEFAULT_VIRTUAL_CLOCK_START`] and advancing by
+    /// [`DEFAULT_VIRTUAL_CLOCK_STEP`].
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            clock_start: DEFAULT_VIRTUAL_CLOCK_START,
+            clock_step: DEFAULT_VIRTUAL_CLOCK_STEP,
+        }
+    }
+
+    /// Sets the wall clock time, since the Unix epoch, at which virtual clocks
+    /// start.
+    pub fn clock_start(mut self, start: Duration) -> Self {
+        self.clock_start = start;
+        self
+    }
+
+    /// Sets the amount virtual clocks advance each time they are read.
+    pub fn clock_step(mut self, step: Duration) -> Self {
+        self.clock_step = step;
+        self
+    }
+
+    fn rng(&self, component_id: &str, stream: u64) -> ChaCha8Rng {
+        let mut rng = ChaCha8Rng::seed_from_u64(self.seed ^ fnv1a(component_id));
+        rng.set_stream(stream);
+        rng
+    }
+}
+
+impl<F: RuntimeFactors, U> ExecutorHooks<F, U> for DeterministicExecutionHook {
+    fn prepare_instance(&self, builder: &mut FactorsInstanceBuilder<F, U>) -> anyhow::Result<()> {
+        let component_id = builder.app_component().id().to_string();
+        let secure_random = self.rng(&component_id, 0);
+        let insecure_random = self.rng(&component_id, 1);
+        let clock = VirtualClock {
+            start: self.clock_start,
+            step: self.clock_step,
+            elapsed_nanos: Default::default(),
+        };
+        if let Some(wasi) = builder.factor_builder::<WasiFactor>() {
+            wasi.secure_random(secure_random);
+            wasi.insecure_random(insecure_random);
+            wasi.wall_clock(clock.clone());
+            wasi.monotonic_clock(clock);
+        }
+        Ok(())
+    }
+}
+
+/// A clock shared by an instance's WASI wall and monotonic clocks, which
+/// advances only when read.
+#[derive(Clone)]
+struct VirtualClock {
+    start: Duration,
+    step: Duration,
+    elapsed_nanos: Arc<AtomicU64>,
+}
+
+impl VirtualClock {
+    /// Returns the elapsed time and advances the clock by a step.
+    fn tick(&self) -> u64 {
+        self.elapsed_nanos
+            .fetch_add(self.step.as_nanos() as u64, Ordering::Relaxed)
+    }
+}
+
+impl HostWallClock for VirtualClock {
+    fn resolution(&self) -> Duration {
+        Duration::from_nanos(1)
+    }
+
+    fn now(&self) -> Duration {
+        self.start + Duration::from_nanos(self.tick())
+    }
+}
+
+impl HostMonotonicClock for VirtualClock {
+    fn resolution(&self) -> u64 {
+        1
+    }
+
+    fn now(&self) -> u64 {
+        self.tick()
+    }
+}
+
+/// A hash of the component ID that, unlike [`std::hash::DefaultHasher`], is
+/// the same across Rust versions.
+fn fnv1a(s: &str) -> u64 {
+    s.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::RngCore;
+
+    use super::*;
+
+    #[test]
+    fn random_streams_depend_on_seed_and_component() {
+        let hook = DeterministicExecutionHook::new(42);
+        let first = hook.rng("hello", 0).next_u64();
+        assert_eq!(first, hook.rng("hello", 0).next_u64());
+        assert_ne!(first, hook.rng("hello", 1).next_u64());
+        assert_ne!(first, hook.rng("goodbye", 0).next_u64());
+        assert_ne!(
+            first,
+            DeterministicExecutionHook::new(43)
+                .rng("hello", 0)
+                .next_u64()
+        );
+    }
+
+    #[test]
+    fn virtual_clock_advances_when_read() {
+        let clock = VirtualClock {
+            start: DEFAULT_VIRTUAL_CLOCK_START,
+            step: Duration::from_millis(5),
+            elapsed_nanos: Default::default(),
+        };
+        assert_eq!(HostMonotonicClock::now(&clock), 0);
+        assert_eq!(
+            HostWallClock::now(&clock),
+            DEFAULT_VIRTUAL_CLOCK_START + Duration::from_millis(5)
+        );
+        assert_eq!(HostMonotonicClock::now(&clock), 10_000_000);
+    }
+}
@@ -0,0 +1,40 @@
+use anyhow::Context as _;
+use spin_core::async_trait;
+use spin_factor_key_value::KeyValueFactor;
+use spin_factors::RuntimeFactors;
+use spin_factors_executor::ExecutorHooks;
+
+/// An [`ExecutorHooks`] that moves existing keys into the key-value namespaces
+/// of the given components.
+pub struct KeyValueNamespaceMigrationHook {
+    component_ids: Vec<String>,
+}
+
+impl KeyValueNamespaceMigrationHook {
+    pub fn new(component_ids: Vec<String>) -> Self {
+        Self { component_ids }
+    }
+}
+
+#[async_trait]
+impl<F: RuntimeFactors, U> ExecutorHooks<F, U> for KeyValueNamespaceMigrationHook {
+    async fn configure_app(
+        &self,
+        configured_app: &spin_factors::ConfiguredApp<F>,
+    ) -> anyhow::Result<()> {
+        if self.component_ids.is_empty() {
+            return Ok(());
+        }
+        let kv = configured_app.app_state::<KeyValueFactor>().context(
+            "attempted to migrate key-value namespaces but the key-value factor was not configured",
+        )?;
+        for component_id in &self.component_ids {
+            let moved = kv.migrate_into_namespace(component_id).await?;
+            let namespace = kv.component_namespace(component_id).unwrap_or_default();
+            println!(
+                "Moved {moved} key-value key(s) into namespace {namespace:?} of component {component_id:?}."
+            );
+        }
+        Ok(())
+    }
+}
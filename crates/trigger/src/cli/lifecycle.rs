@@ -0,0 +1,133 @@
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Context, Result};
+use spin_app::{
+    App, HookFailurePolicy, LifecycleHook, MetadataKey, APP_ON_SHUTDOWN_KEY, APP_ON_STARTUP_KEY,
+};
+use spin_common::diagnostics::Diagnostic;
+use spin_factors::RuntimeFactors;
+
+use crate::{Trigger, TriggerApp};
+
+/// How long a lifecycle hook may run if the manifest doesn't say otherwise.
+const DEFAULT_HOOK_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The interface exported by lifecycle hook components.
+const WASI_CLI_RUN_INTERFACE: &str = "wasi:cli/run@0.2.0";
+
+/// A point in the app's lifecycle at which a hook component may run.
+#[derive(Clone, Copy, Debug, clap::ValueEnum)]
+pub enum LifecycleStage {
+    /// Once, before the trigger starts serving traffic.
+    Startup,
+    /// Once, after the trigger has stopped.
+    Shutdown,
+}
+
+impl LifecycleStage {
+    const ALL: [Self; 2] = [Self::Startup, Self::Shutdown];
+
+    fn metadata_key(self) -> MetadataKey<LifecycleHook> {
+        match self {
+            Self::Startup => APP_ON_STARTUP_KEY,
+            Self::Shutdown => APP_ON_SHUTDOWN_KEY,
+        }
+    }
+
+    fn manifest_field(self) -> &'static str {
+        match self {
+            Self::Startup => "on_startup",
+            Self::Shutdown => "on_shutdown",
+        }
+    }
+}
+
+/// Checks that the trigger `T` can run any lifecycle hooks the app declares.
+pub fn ensure_lifecycle_hooks_supported<T: Trigger<F>, F: RuntimeFactors>(app: &App) -> Result<()> {
+    for stage in LifecycleStage::ALL {
+        if app.get_metadata(stage.metadata_key())?.is_some()
+            && T::lifecycle_hook_instance_state().is_none()
+        {
+            anyhow::bail!(
+                "This application declares an `{}` hook, which is not supported by the '{}' trigger",
+                stage.manifest_field(),
+                T::TYPE
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Runs the app's hook component for the given stage, if it declares one.
+///
+/// Returns an error only if the hook fails and its failure policy is
+/// [`HookFailurePolicy::Fail`].
+pub async fn run_lifecycle_hook<T: Trigger<F>, F: RuntimeFactors>(
+    trigger_app: &TriggerApp<T, F>,
+    stage: LifecycleStage,
+) -> Result<()> {
+    let Some(hook) = trigger_app.app().get_metadata(stage.metadata_key())? else {
+        return Ok(());
+    };
+    let timeout = hook
+        .timeout_secs
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_HOOK_TIMEOUT);
+
+    tracing::info!(
+        "Running {} hook component {:?}",
+        stage.manifest_field(),
+        hook.component
+    );
+    let result = tokio::time::timeout(
+        timeout,
+        invoke_hook::<T, F>(trigger_app, &hook.component, timeout),
+    )
+    .await
+    .unwrap_or_else(|_elapsed| Err(anyhow!("timed out after {timeout:?}")))
+    .with_context(|| {
+        format!(
+            "`{}` hook component {:?} failed",
+            stage.manifest_field(),
+            hook.component
+        )
+    });
+
+    match (result, hook.on_failure) {
+        (Ok(()), _) => Ok(()),
+        (Err(err), HookFailurePolicy::Continue) => {
+            trigger_app.diagnostics().report(
+                Diagnostic::new("lifecycle-hook-failed", format!("{err:#}"))
+                    .component(&hook.component),
+            );
+            Ok(())
+        }
+        (Err(err), HookFailurePolicy::Fail) => Err(err),
+    }
+}
+
+async fn invoke_hook<T: Trigger<F>, F: RuntimeFactors>(
+    trigger_app: &TriggerApp<T, F>,
+    component_id: &str,
+    timeout: Duration,
+) -> Result<()> {
+    let instance_state = T::lifecycle_hook_instance_state()
+        .with_context(|| format!("the '{}' trigger does not support lifecycle hooks", T::TYPE))?;
+    let (instance, mut store) = trigger_app
+        .prepare(component_id)
+        .await?
+        .instantiate(instance_state)
+        .await?;
+    store.set_deadline(Instant::now() + timeout);
+
+    let run_interface = instance
+        .get_export(&mut store, None, WASI_CLI_RUN_INTERFACE)
+        .with_context(|| format!("component does not export {WASI_CLI_RUN_INTERFACE}"))?;
+    let run = instance
+        .get_export(&mut store, Some(&run_interface), "run")
+        .with_context(|| format!("component does not export {WASI_CLI_RUN_INTERFACE}#run"))?;
+    let run = instance.get_typed_func::<(), (Result<(), ()>,)>(&mut store, &run)?;
+
+    let (result,) = run.call_async(&mut store, ()).await?;
+    result.map_err(|()| anyhow!("component returned an error"))
+}
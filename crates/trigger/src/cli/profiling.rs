@@ -0,0 +1,59 @@
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use anyhow::Context as _;
+use spin_factors::RuntimeFactors;
+use spin_factors_executor::{ExecutorHooks, FactorsInstanceBuilder};
+
+/// The directory guest profiles are written to if not otherwise specified.
+pub const DEFAULT_PROFILE_DIR: &str = "spin-profiles";
+
+/// Distinguishes the profiles of instances of the same component.
+static NEXT_PROFILE_ID: AtomicU64 = AtomicU64::new(0);
+
+/// An [`ExecutorHooks`] that profiles every instance of the given components.
+pub struct GuestProfilingHook {
+    components: HashSet<String>,
+    output_dir: PathBuf,
+}
+
+impl GuestProfilingHook {
+    pub fn new(components: impl IntoIterator<Item = String>, output_dir: PathBuf) -> Self {
+        Self {
+            components: components.into_iter().collect(),
+            output_dir,
+        }
+    }
+}
+
+impl<F: RuntimeFactors, U> ExecutorHooks<F, U> for GuestProfilingHook {
+    fn prepare_instance(&self, builder: &mut FactorsInstanceBuilder<F, U>) -> anyhow::Result<()> {
+        if self.components.contains(builder.app_component().id()) {
+            enable_guest_profiling(builder, &self.output_dir)?;
+        }
+        Ok(())
+    }
+}
+
+/// Enables guest profiling of the instance being built.
+///
+/// The profile is written to a new file in `output_dir` when the instance's
+/// store is dropped.
+pub fn enable_guest_profiling<F: RuntimeFactors, U>(
+    builder: &mut FactorsInstanceBuilder<F, U>,
+    output_dir: &Path,
+) -> anyhow::Result<()> {
+    std::fs::create_dir_all(output_dir)
+        .with_context(|| format!("failed to create profile directory {output_dir:?}"))?;
+    let component_id = builder.app_component().id().to_string();
+    let id = NEXT_PROFILE_ID.fetch_add(1, Ordering::Relaxed);
+    let output = output_dir.join(format!("{component_id}-{}-{id}.json", std::process::id()));
+    let component = builder.component().clone();
+    builder
+        .store_builder()
+        .enable_guest_profiling(&component_id, component, output);
+    Ok(())
+}
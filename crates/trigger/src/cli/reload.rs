@@ -0,0 +1,165 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use spin_common::ui::quoted_path;
+use tokio::sync::{
+    mpsc::{UnboundedReceiver, UnboundedSender},
+    watch,
+};
+
+use super::{load_locked_app, FactorsConfig, RuntimeFactorsBuilder, TriggerAppBuilder};
+use crate::{Trigger, TriggerApp};
+
+/// How long to wait for a burst of file system events, such as a Kubernetes
+/// ConfigMap update swapping its files, to settle before reloading.
+const RUNTIME_CONFIG_SETTLE_TIME: Duration = Duration::from_millis(200);
+
+/// Why the app is being reloaded.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Change {
+    /// The process received `SIGHUP`.
+    Hangup,
+    /// Something changed in the directory of the runtime config file.
+    RuntimeConfig,
+}
+
+/// Reloads the app from its lock file each time the process receives
+/// `SIGHUP`, as `spin up` sends after re-locking a changed manifest, and, if
+/// `watched_runtime_config` is given, each time that runtime config file
+/// changes. The app reconfigured for the change is sent to the trigger, and
+/// then replaces the current app in `apps`, which starts with the app being
+/// run.
+///
+/// Only changes that don't need components to be recompiled can be applied;
+/// see [`TriggerAppBuilder::reconfigure`]. Other changes are reported, and
+/// need the app to be restarted. This never completes unless listening for
+/// signals or watching the runtime config file fails.
+pub(crate) async fn reconfigure_on_change<T: Trigger<B::Factors>, B: RuntimeFactorsBuilder>(
+    locked_url: &str,
+    apps: watch::Sender<TriggerApp<T, B::Factors>>,
+    common_options: &FactorsConfig,
+    options: &B::CliArgs,
+    watched_runtime_config: Option<&Path>,
+    reconfigured: UnboundedSender<TriggerApp<T, B::Factors>>,
+) -> Result<()> {
+    // The sender is kept for as long as this runs, so that receiving never
+    // ends even if there is nothing to send changes.
+    let (changes_tx, mut changes) = tokio::sync::mpsc::unbounded_channel();
+    forward_hangups(changes_tx.clone())?;
+    let _watcher = watched_runtime_config
+        .map(|path| watch_runtime_config(path, changes_tx.clone()))
+        .transpose()?;
+    let mut runtime_config = watched_runtime_config.and_then(|path| std::fs::read(path).ok());
+
+    while let Some(change) = changes.recv().await {
+        let mut hangup = change == Change::Hangup;
+        if let Some(path) = watched_runtime_config.filter(|_| !hangup) {
+            hangup |= settle(&mut changes).await;
+            let contents = std::fs::read(path).ok();
+            if contents == runtime_config && !hangup {
+                continue;
+            }
+            runtime_config = contents;
+            if !hangup {
+                terminal::step!("Reloading", "runtime config {}", quoted_path(path));
+            }
+        }
+
+        let current = apps.borrow().clone();
+        let next = match reconfigure::<T, B>(&current, locked_url, common_options, options).await {
+            Ok(next) => next,
+            Err(err) => {
+                tracing::warn!("Failed to reconfigure app: {err:?}");
+                eprintln!("Couldn't apply changes to the app without restarting it: {err:#}");
+                continue;
+            }
+        };
+        if reconfigured.send(next.clone()).is_err() {
+            eprintln!(
+                "The '{}' trigger can't apply changes to a running app: restart the app to apply them",
+                T::TYPE
+            );
+            continue;
+        }
+        apps.send_replace(next);
+    }
+    Ok(())
+}
+
+/// Waits for changes to stop arriving, discarding them. Returns whether any
+/// of them was a hangup.
+async fn settle(changes: &mut UnboundedReceiver<Change>) -> bool {
+    let mut hangup = false;
+    loop {
+        tokio::time::sleep(RUNTIME_CONFIG_SETTLE_TIME).await;
+        let mut any = false;
+        while let Ok(change) = changes.try_recv() {
+            any = true;
+            hangup |= change == Change::Hangup;
+        }
+        if !any {
+            return hangup;
+        }
+    }
+}
+
+#[cfg(unix)]
+fn forward_hangups(changes: UnboundedSender<Change>) -> Result<()> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangups = signal(SignalKind::hangup()).context("failed to listen for SIGHUP")?;
+    tokio::spawn(async move {
+        while hangups.recv().await.is_some() {
+            if changes.send(Change::Hangup).is_err() {
+                break;
+            }
+        }
+    });
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn forward_hangups(_changes: UnboundedSender<Change>) -> Result<()> {
+    Ok(())
+}
+
+/// Watches the directory of the runtime config file, rather than the file
+/// itself, as Kubernetes updates files mounted from a ConfigMap by swapping
+/// the symlink to the directory they are in.
+fn watch_runtime_config(
+    path: &Path,
+    changes: UnboundedSender<Change>,
+) -> Result<notify::RecommendedWatcher> {
+    use notify::Watcher;
+
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_owned(),
+        _ => PathBuf::from("."),
+    };
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        if event.is_ok_and(|event| !event.kind.is_access()) {
+            let _ = changes.send(Change::RuntimeConfig);
+        }
+    })
+    .context("failed to create runtime config file watcher")?;
+    watcher
+        .watch(&dir, notify::RecursiveMode::NonRecursive)
+        .with_context(|| {
+            format!(
+                "failed to watch runtime config directory {}",
+                quoted_path(&dir)
+            )
+        })?;
+    Ok(watcher)
+}
+
+async fn reconfigure<T: Trigger<B::Factors>, B: RuntimeFactorsBuilder>(
+    current: &TriggerApp<T, B::Factors>,
+    locked_url: &str,
+    common_options: &FactorsConfig,
+    options: &B::CliArgs,
+) -> Result<TriggerApp<T, B::Factors>> {
+    let app = load_locked_app::<T, B::Factors>(locked_url)?;
+    TriggerAppBuilder::<T, B>::reconfigure(current, app, common_options, options).await
+}
@@ -0,0 +1,27 @@
+use spin_core::async_trait;
+use spin_factor_sqlite::SqliteFactor;
+use spin_factors::RuntimeFactors;
+use spin_factors_executor::ExecutorHooks;
+
+/// An [`ExecutorHooks`] that applies the app's SQLite migrations before any
+/// component is instantiated.
+///
+/// The app does not need to use `SqliteFactor`; if it doesn't, the hook does
+/// nothing.
+pub struct SqliteMigrationsHook;
+
+#[async_trait]
+impl<F, U> ExecutorHooks<F, U> for SqliteMigrationsHook
+where
+    F: RuntimeFactors,
+{
+    async fn configure_app(
+        &self,
+        configured_app: &spin_factors::ConfiguredApp<F>,
+    ) -> anyhow::Result<()> {
+        let Some(sqlite) = configured_app.app_state::<SqliteFactor>().ok() else {
+            return Ok(());
+        };
+        sqlite.apply_migrations().await
+    }
+}
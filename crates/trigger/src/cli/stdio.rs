@@ -1,16 +1,17 @@
 use std::{
     collections::HashSet,
+    io::Write,
     path::{Path, PathBuf},
-    task::Poll,
 };
 
 use anyhow::{Context, Result};
 use spin_common::ui::quoted_path;
 use spin_core::async_trait;
+use spin_factor_context::{ContextFactor, CorrelationId};
 use spin_factor_wasi::WasiFactor;
 use spin_factors::RuntimeFactors;
 use spin_factors_executor::ExecutorHooks;
-use tokio::io::AsyncWrite;
+use spin_telemetry::logs::{AppLogSource, AppLogStream, AppLogger};
 
 /// Which components should have their logs followed on stdout/stderr.
 #[derive(Clone, Debug, Default)]
@@ -51,20 +52,28 @@ impl StdioLoggingExecutorHooks {
 
     fn component_stdio_writer(
         &self,
-        component_id: &str,
-        log_suffix: &str,
+        source: AppLogSource,
+        correlation_id: CorrelationId,
         log_dir: Option<&Path>,
     ) -> Result<ComponentStdioWriter> {
+        let component_id = source.component.as_str();
         let sanitized_component_id = sanitize_filename::sanitize(component_id);
+        let log_suffix = match source.stream {
+            AppLogStream::Stdout => "stdout",
+            AppLogStream::Stderr => "stderr",
+        };
         let log_path = log_dir
             .map(|log_dir| log_dir.join(format!("{sanitized_component_id}_{log_suffix}.txt",)));
         let log_path = log_path.as_deref();
 
         let follow = self.follow_components.should_follow(component_id);
+        let logger = AppLogger::new(source);
         match log_path {
-            Some(log_path) => ComponentStdioWriter::new_forward(log_path, follow)
-                .with_context(|| format!("Failed to open log file {}", quoted_path(log_path))),
-            None => ComponentStdioWriter::new_inherit(),
+            Some(log_path) => {
+                ComponentStdioWriter::new_forward(log_path, follow, correlation_id, logger)
+                    .with_context(|| format!("Failed to open log file {}", quoted_path(log_path)))
+            }
+            None => ComponentStdioWriter::new_inherit(correlation_id, logger),
         }
     }
 
@@ -109,209 +118,173 @@ impl<F: RuntimeFactors, U> ExecutorHooks<F, U> for StdioLoggingExecutorHooks {
         &self,
         builder: &mut spin_factors_executor::FactorsInstanceBuilder<F, U>,
     ) -> anyhow::Result<()> {
-        let component_id = builder.app_component().id().to_string();
+        let component = builder.app_component();
+        let component_id = component.id().to_string();
+        let app_name = component
+            .app
+            .get_metadata(spin_app::APP_NAME_KEY)?
+            .unwrap_or_default();
+        let trigger_type = component
+            .app
+            .triggers()
+            .find(|trigger| trigger.component().is_ok_and(|c| c.id() == component_id))
+            .map(|trigger| trigger.trigger_type().to_owned());
+        let id =
+            crate::correlation::current_correlation_id().unwrap_or_else(generate_correlation_id);
+        // Components see the same ID as prefixes their output, which follows
+        // it if the instance is reused for another execution.
+        let correlation_id = match builder.factor_builder::<ContextFactor>() {
+            Some(context) => {
+                context.set_correlation_id(id.clone());
+                context.correlation_id()
+            }
+            None => {
+                let correlation_id = CorrelationId::default();
+                correlation_id.set(id.clone());
+                correlation_id
+            }
+        };
+        let source = |stream| AppLogSource {
+            app: app_name.clone(),
+            component: component_id.clone(),
+            trigger: trigger_type.clone(),
+            correlation_id: Some(id.clone()),
+            stream,
+        };
+        let log_dir = self.log_dir.as_deref();
+        let stdout = self.component_stdio_writer(
+            source(AppLogStream::Stdout),
+            correlation_id.clone(),
+            log_dir,
+        )?;
+        let stderr =
+            self.component_stdio_writer(source(AppLogStream::Stderr), correlation_id, log_dir)?;
         let Some(wasi_builder) = builder.factor_builder::<WasiFactor>() else {
             return Ok(());
         };
-        wasi_builder.stdout_pipe(self.component_stdio_writer(
-            &component_id,
-            "stdout",
-            self.log_dir.as_deref(),
-        )?);
-        wasi_builder.stderr_pipe(self.component_stdio_writer(
-            &component_id,
-            "stderr",
-            self.log_dir.as_deref(),
-        )?);
+        wasi_builder.stdout_pipe(stdout);
+        wasi_builder.stderr_pipe(stderr);
         Ok(())
     }
 }
 
-/// ComponentStdioWriter forwards output to a log file, (optionally) stderr, and (optionally) to a
-/// tracing compatibility layer.
+/// ComponentStdioWriter forwards output to a log file, (optionally) stderr, and (optionally) to
+/// OTel and a tracing compatibility layer.
+///
+/// Each line written to the log file and stderr is prefixed with the correlation ID of the
+/// execution which wrote it.
 pub struct ComponentStdioWriter {
     inner: ComponentStdioWriterInner,
+    logger: AppLogger,
+    correlation_id: CorrelationId,
+    // The ID `line_prefix` was made from
+    prefixed_id: Option<String>,
+    line_prefix: Vec<u8>,
+    at_line_start: bool,
 }
 
 enum ComponentStdioWriterInner {
     /// Inherit stdout/stderr from the parent process.
     Inherit,
     /// Forward stdout/stderr to a file in addition to the inherited stdout/stderr.
-    Forward {
-        sync_file: std::fs::File,
-        async_file: tokio::fs::File,
-        state: ComponentStdioWriterState,
-        follow: bool,
-    },
-}
-
-#[derive(Debug)]
-enum ComponentStdioWriterState {
-    File,
-    Follow(std::ops::Range<usize>),
+    Forward { file: std::fs::File, follow: bool },
 }
 
 impl ComponentStdioWriter {
-    fn new_forward(log_path: &Path, follow: bool) -> anyhow::Result<Self> {
-        let sync_file = std::fs::File::options()
+    fn new_forward(
+        log_path: &Path,
+        follow: bool,
+        correlation_id: CorrelationId,
+        logger: AppLogger,
+    ) -> anyhow::Result<Self> {
+        let file = std::fs::File::options()
             .create(true)
             .append(true)
             .open(log_path)?;
-
-        let async_file = sync_file
-            .try_clone()
-            .context("could not get async file handle")?
-            .into();
-
-        Ok(Self {
-            inner: ComponentStdioWriterInner::Forward {
-                sync_file,
-                async_file,
-                state: ComponentStdioWriterState::File,
-                follow,
-            },
-        })
+        Ok(Self::new(
+            ComponentStdioWriterInner::Forward { file, follow },
+            correlation_id,
+            logger,
+        ))
     }
 
-    fn new_inherit() -> anyhow::Result<Self> {
-        Ok(Self {
-            inner: ComponentStdioWriterInner::Inherit,
-        })
+    fn new_inherit(correlation_id: CorrelationId, logger: AppLogger) -> anyhow::Result<Self> {
+        Ok(Self::new(
+            ComponentStdioWriterInner::Inherit,
+            correlation_id,
+            logger,
+        ))
     }
-}
 
-impl AsyncWrite for ComponentStdioWriter {
-    fn poll_write(
-        self: std::pin::Pin<&mut Self>,
-        cx: &mut std::task::Context<'_>,
-        buf: &[u8],
-    ) -> Poll<std::result::Result<usize, std::io::Error>> {
-        let this = self.get_mut();
-
-        loop {
-            match &mut this.inner {
-                ComponentStdioWriterInner::Inherit => {
-                    let written = futures::ready!(
-                        std::pin::Pin::new(&mut tokio::io::stderr()).poll_write(cx, buf)
-                    );
-                    let written = match written {
-                        Ok(w) => w,
-                        Err(e) => return Poll::Ready(Err(e)),
-                    };
-                    return Poll::Ready(Ok(written));
-                }
-                ComponentStdioWriterInner::Forward {
-                    async_file,
-                    state,
-                    follow,
-                    ..
-                } => match &state {
-                    ComponentStdioWriterState::File => {
-                        let written =
-                            futures::ready!(std::pin::Pin::new(async_file).poll_write(cx, buf));
-                        let written = match written {
-                            Ok(w) => w,
-                            Err(e) => return Poll::Ready(Err(e)),
-                        };
-                        if *follow {
-                            *state = ComponentStdioWriterState::Follow(0..written);
-                        } else {
-                            return Poll::Ready(Ok(written));
-                        }
-                    }
-                    ComponentStdioWriterState::Follow(range) => {
-                        let written = futures::ready!(std::pin::Pin::new(&mut tokio::io::stderr())
-                            .poll_write(cx, &buf[range.clone()]));
-                        let written = match written {
-                            Ok(w) => w,
-                            Err(e) => return Poll::Ready(Err(e)),
-                        };
-                        if range.start + written >= range.end {
-                            let end = range.end;
-                            *state = ComponentStdioWriterState::File;
-                            return Poll::Ready(Ok(end));
-                        } else {
-                            *state = ComponentStdioWriterState::Follow(
-                                (range.start + written)..range.end,
-                            );
-                        };
-                    }
-                },
-            }
+    fn new(
+        inner: ComponentStdioWriterInner,
+        correlation_id: CorrelationId,
+        logger: AppLogger,
+    ) -> Self {
+        Self {
+            inner,
+            logger,
+            correlation_id,
+            prefixed_id: None,
+            line_prefix: vec![],
+            at_line_start: true,
         }
     }
 
-    fn poll_flush(
-        self: std::pin::Pin<&mut Self>,
-        cx: &mut std::task::Context<'_>,
-    ) -> Poll<std::result::Result<(), std::io::Error>> {
-        let this = self.get_mut();
-
-        match &mut this.inner {
-            ComponentStdioWriterInner::Inherit => {
-                std::pin::Pin::new(&mut tokio::io::stderr()).poll_flush(cx)
+    /// Returns the given output with each line prefixed.
+    fn prefix_lines(&mut self, buf: &[u8]) -> Vec<u8> {
+        let mut prefixed = Vec::with_capacity(buf.len() + self.line_prefix.len() + 1);
+        // The instance may have been reused for another execution, whose
+        // output starts on a new line.
+        let id = self.correlation_id.get();
+        if id != self.prefixed_id {
+            if !self.at_line_start {
+                prefixed.push(b'\n');
+                self.at_line_start = true;
             }
-            ComponentStdioWriterInner::Forward {
-                async_file, state, ..
-            } => match state {
-                ComponentStdioWriterState::File => std::pin::Pin::new(async_file).poll_flush(cx),
-                ComponentStdioWriterState::Follow(_) => {
-                    std::pin::Pin::new(&mut tokio::io::stderr()).poll_flush(cx)
-                }
-            },
+            self.line_prefix = match &id {
+                Some(id) => format!("[{id}] ").into_bytes(),
+                None => vec![],
+            };
+            if self.prefixed_id.is_some() {
+                self.logger.set_correlation_id(id.clone());
+            }
+            self.prefixed_id = id;
         }
-    }
-
-    fn poll_shutdown(
-        self: std::pin::Pin<&mut Self>,
-        cx: &mut std::task::Context<'_>,
-    ) -> Poll<std::result::Result<(), std::io::Error>> {
-        let this = self.get_mut();
-
-        match &mut this.inner {
-            ComponentStdioWriterInner::Inherit => {
-                std::pin::Pin::new(&mut tokio::io::stderr()).poll_flush(cx)
+        for line in buf.split_inclusive(|&b| b == b'\n') {
+            if self.at_line_start {
+                prefixed.extend_from_slice(&self.line_prefix);
             }
-            ComponentStdioWriterInner::Forward {
-                async_file, state, ..
-            } => match state {
-                ComponentStdioWriterState::File => std::pin::Pin::new(async_file).poll_shutdown(cx),
-                ComponentStdioWriterState::Follow(_) => {
-                    std::pin::Pin::new(&mut tokio::io::stderr()).poll_flush(cx)
-                }
-            },
+            prefixed.extend_from_slice(line);
+            self.at_line_start = line.ends_with(b"\n");
         }
+        prefixed
     }
 }
 
-impl std::io::Write for ComponentStdioWriter {
+impl Write for ComponentStdioWriter {
     fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
-        spin_telemetry::logs::handle_app_log(buf);
-
+        let prefixed = self.prefix_lines(buf);
         match &mut self.inner {
             ComponentStdioWriterInner::Inherit => {
-                std::io::stderr().write_all(buf)?;
-                Ok(buf.len())
+                std::io::stderr().write_all(&prefixed)?;
             }
-            ComponentStdioWriterInner::Forward {
-                sync_file, follow, ..
-            } => {
-                let written = sync_file.write(buf)?;
+            ComponentStdioWriterInner::Forward { file, follow } => {
+                file.write_all(&prefixed)?;
                 if *follow {
-                    std::io::stderr().write_all(&buf[..written])?;
+                    std::io::stderr().write_all(&prefixed)?;
                 }
-                Ok(written)
             }
         }
+        self.logger.write(buf);
+        Ok(buf.len())
     }
 
     fn flush(&mut self) -> std::io::Result<()> {
         match &mut self.inner {
             ComponentStdioWriterInner::Inherit => std::io::stderr().flush(),
-            ComponentStdioWriterInner::Forward {
-                sync_file, follow, ..
-            } => {
-                sync_file.flush()?;
+            ComponentStdioWriterInner::Forward { file, follow } => {
+                file.flush()?;
                 if *follow {
                     std::io::stderr().flush()?;
                 }
@@ -321,6 +294,11 @@ impl std::io::Write for ComponentStdioWriter {
     }
 }
 
+/// Generates a correlation ID for an execution that wasn't given one by its trigger.
+fn generate_correlation_id() -> String {
+    format!("{:08x}", rand::random::<u32>())
+}
+
 fn bullet_list<S: std::fmt::Display>(items: impl IntoIterator<Item = S>) -> String {
     items
         .into_iter()
@@ -328,3 +306,33 @@ fn bullet_list<S: std::fmt::Display>(items: impl IntoIterator<Item = S>) -> Stri
         .collect::<Vec<_>>()
         .join("\n")
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines_are_prefixed_across_writes() {
+        let source = AppLogSource {
+            app: "app".into(),
+            component: "component".into(),
+            trigger: None,
+            correlation_id: None,
+            stream: AppLogStream::Stdout,
+        };
+        let correlation_id = CorrelationId::default();
+        correlation_id.set("abc");
+        let mut writer = ComponentStdioWriter::new(
+            ComponentStdioWriterInner::Inherit,
+            correlation_id.clone(),
+            AppLogger::new(source),
+        );
+        assert_eq!(writer.prefix_lines(b"one\ntw"), b"[abc] one\n[abc] tw");
+        assert_eq!(writer.prefix_lines(b"o\n"), b"o\n");
+        assert_eq!(writer.prefix_lines(b"\nthree"), b"[abc] \n[abc] three");
+
+        // A reused instance's output is prefixed with its new ID.
+        correlation_id.set("def");
+        assert_eq!(writer.prefix_lines(b"four\n"), b"\n[def] four\n");
+    }
+}
@@ -0,0 +1,195 @@
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Context, Result};
+use futures::{future::LocalBoxFuture, stream::FuturesUnordered, FutureExt, StreamExt};
+use spin_factor_tasks::{Task, TaskStore, TasksFactor, Timers};
+use spin_factors::RuntimeFactors;
+use tokio::sync::watch;
+
+use crate::{Trigger, TriggerApp};
+
+/// The interface exported by components that run background tasks.
+const TASK_HANDLER_INTERFACE: &str = "spin:task/handler@3.0.0";
+
+/// How long a background task may run.
+const TASK_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// How long to wait before first retrying a timer which failed to fire. The
+/// wait doubles with each further failure, up to [`MAX_TIMER_RETRY_DELAY`].
+const TIMER_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// The longest wait between retries of a timer which failed to fire.
+const MAX_TIMER_RETRY_DELAY: Duration = Duration::from_secs(3600);
+
+/// Runs the background tasks released by the app's instances, and the timers
+/// they schedule, as they become due, until an error prevents any more from
+/// running.
+///
+/// If `resume_saved` is set, tasks and timers saved by a previous run of the
+/// app are resumed first. Only one of the processes running an app's triggers
+/// should resume them, or they run once for each. If the app doesn't use
+/// `TasksFactor`, this never completes.
+///
+/// Each task runs on the latest version of the app received from `apps`, so
+/// that tasks released before a reload run with the reloaded configuration.
+pub async fn run_background_tasks<T: Trigger<F>, F: RuntimeFactors>(
+    apps: watch::Receiver<TriggerApp<T, F>>,
+    resume_saved: bool,
+) -> Result<()> {
+    let trigger_app = apps.borrow().clone();
+    let Ok(tasks) = trigger_app.configured_app().app_state::<TasksFactor>() else {
+        return std::future::pending().await;
+    };
+    let (Some(mut task_receiver), Some(mut timer_receiver)) = (
+        tasks.queue().take_receiver(),
+        tasks.timers().take_receiver(),
+    ) else {
+        return std::future::pending().await;
+    };
+    let task_store = tasks.task_store();
+    let timer_store = tasks.timer_store();
+    let timers = tasks.timers().as_ref();
+
+    let mut running = FuturesUnordered::<LocalBoxFuture<()>>::new();
+    if let Some(store) = task_store.filter(|_| resume_saved) {
+        let saved = store.load().await.context("failed to load saved tasks")?;
+        if !saved.is_empty() {
+            tracing::info!("Resuming {} saved background task(s)", saved.len());
+        }
+        for task in saved {
+            running.push(run_task_when_due(&apps, task_store, task).boxed_local());
+        }
+    }
+    if let Some(store) = timer_store.filter(|_| resume_saved) {
+        let saved = store.load().await.context("failed to load saved timers")?;
+        if !saved.is_empty() {
+            tracing::info!("Resuming {} saved timer(s)", saved.len());
+        }
+        for timer in saved {
+            timers.add(timer);
+        }
+    }
+    loop {
+        tokio::select! {
+            Some(task) = task_receiver.recv() => {
+                if let Some(store) = task_store {
+                    if let Err(err) = store.save(&task).await {
+                        tracing::warn!("Failed to save background task {}: {err:?}", task.id);
+                    }
+                }
+                running.push(run_task_when_due(&apps, task_store, task).boxed_local());
+            }
+            Some(timer) = timer_receiver.recv() => {
+                running.push(fire_timer_when_due(&apps, timers, timer_store, timer).boxed_local());
+            }
+            Some(()) = running.next() => {}
+            else => return std::future::pending().await,
+        }
+    }
+}
+
+async fn run_task_when_due<T: Trigger<F>, F: RuntimeFactors>(
+    apps: &watch::Receiver<TriggerApp<T, F>>,
+    store: Option<&TaskStore>,
+    task: Task,
+) {
+    tokio::time::sleep(task.time_until_due()).await;
+
+    tracing::info!(
+        "Running background task {} on component {:?}",
+        task.id,
+        task.component
+    );
+    if let Err(err) = invoke_task::<T, F>(apps, &task).await {
+        tracing::error!("Background task {} failed: {err:?}", task.id);
+        eprintln!(
+            "Background task {} on component {:?} failed: {err:#}",
+            task.id, task.component
+        );
+    }
+    // Failed tasks aren't retried.
+    if let Some(store) = store {
+        if let Err(err) = store.remove(&task.id).await {
+            tracing::warn!("Failed to remove background task {}: {err:?}", task.id);
+        }
+    }
+}
+
+/// Fires a timer when it is due, retrying until it succeeds or is cancelled.
+///
+/// The timer is removed from the store only once it has fired successfully,
+/// so it fires again after a restart if the app stopped before then.
+async fn fire_timer_when_due<T: Trigger<F>, F: RuntimeFactors>(
+    apps: &watch::Receiver<TriggerApp<T, F>>,
+    timers: &Timers,
+    store: Option<&TaskStore>,
+    mut timer: Task,
+) {
+    let mut retry_delay = TIMER_RETRY_DELAY;
+    loop {
+        tokio::time::sleep(timer.time_until_due()).await;
+        if !timers.begin_firing(&timer.id) {
+            // The timer was cancelled.
+            return;
+        }
+
+        tracing::info!(
+            "Firing timer {} on component {:?}",
+            timer.id,
+            timer.component
+        );
+        let Err(err) = invoke_task::<T, F>(apps, &timer).await else {
+            if let Some(store) = store {
+                if let Err(err) = store.remove(&timer.id).await {
+                    tracing::warn!("Failed to remove timer {}: {err:?}", timer.id);
+                }
+            }
+            timers.complete(&timer.id);
+            return;
+        };
+
+        tracing::error!("Timer {} failed: {err:?}", timer.id);
+        eprintln!(
+            "Timer {} on component {:?} failed, retrying in {retry_delay:?}: {err:#}",
+            timer.id, timer.component
+        );
+        timer.reschedule(retry_delay);
+        retry_delay = (retry_delay * 2).min(MAX_TIMER_RETRY_DELAY);
+        if let Some(store) = store {
+            if let Err(err) = store.save(&timer).await {
+                tracing::warn!("Failed to save timer {}: {err:?}", timer.id);
+            }
+        }
+        timers.retry(timer.clone());
+    }
+}
+
+async fn invoke_task<T: Trigger<F>, F: RuntimeFactors>(
+    apps: &watch::Receiver<TriggerApp<T, F>>,
+    task: &Task,
+) -> Result<()> {
+    let trigger_app = apps.borrow().clone();
+    let instance_state = T::lifecycle_hook_instance_state().with_context(|| {
+        format!(
+            "the '{}' trigger does not support background tasks",
+            T::TYPE
+        )
+    })?;
+    let (instance, mut store) = trigger_app
+        .prepare(&task.component)
+        .await?
+        .instantiate(instance_state)
+        .await?;
+    store.set_deadline(Instant::now() + TASK_TIMEOUT);
+
+    let handler_interface = instance
+        .get_export(&mut store, None, TASK_HANDLER_INTERFACE)
+        .with_context(|| format!("component does not export {TASK_HANDLER_INTERFACE}"))?;
+    let run = instance
+        .get_export(&mut store, Some(&handler_interface), "run")
+        .with_context(|| format!("component does not export {TASK_HANDLER_INTERFACE}#run"))?;
+    let run = instance.get_typed_func::<(Vec<u8>,), (Result<(), String>,)>(&mut store, &run)?;
+
+    let (result,) = run.call_async(&mut store, (task.payload.clone(),)).await?;
+    result.map_err(|err| anyhow!("component returned an error: {err}"))
+}
@@ -0,0 +1,233 @@
+//! Wasm core dumps of guest traps.
+//!
+//! When core dumps are enabled (see [`crate::cli::CoreDumpHook`]), triggers
+//! pass guest errors through [`capture`], which writes any core dump to the
+//! instance's core dump directory alongside a JSON file of
+//! [`CoreDumpMetadata`]. Tooling can find recent dumps with [`list`].
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Context as _;
+use serde::{Deserialize, Serialize};
+use spin_common::ui::quoted_path;
+
+/// The file extension of core dumps.
+pub const CORE_DUMP_EXTENSION: &str = "wasmcoredump";
+
+/// The file extension of core dump metadata files.
+const METADATA_EXTENSION: &str = "json";
+
+/// How many core dumps are kept in a directory; older dumps are removed as
+/// new ones are written.
+pub const MAX_CORE_DUMPS: usize = 20;
+
+/// Distinguishes core dumps written in the same millisecond.
+static NEXT_CORE_DUMP_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Describes the trap a core dump was captured for.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CoreDumpMetadata {
+    /// The type of the trigger that invoked the component.
+    pub trigger_type: String,
+    /// The ID of the component that trapped.
+    pub component_id: String,
+    /// When the trap occurred, in milliseconds since the Unix epoch.
+    pub timestamp_ms: u64,
+    /// The trap error, including the guest stack.
+    pub error: String,
+    /// Trigger-specific details of the event being handled, e.g. the HTTP
+    /// route or Redis channel, for correlating the dump with logs.
+    #[serde(default)]
+    pub event: BTreeMap<String, String>,
+}
+
+/// A core dump written by [`capture`].
+#[derive(Clone, Debug)]
+pub struct CoreDump {
+    /// The path of the core dump file.
+    pub path: PathBuf,
+    /// What the core dump was captured for.
+    pub metadata: CoreDumpMetadata,
+}
+
+impl CoreDump {
+    /// Reads the core dump, in the standard Wasm core dump format.
+    pub fn read(&self) -> anyhow::Result<Vec<u8>> {
+        std::fs::read(&self.path)
+            .with_context(|| format!("failed to read core dump {:?}", self.path))
+    }
+}
+
+/// Writes the core dump attached to a guest error, if any.
+///
+/// Nothing is written unless the store has a core dump directory and `err`
+/// is a trap carrying a core dump. Failure to write the dump is logged rather
+/// than returned, so this can be used as e.g. `.map_err(|err| capture(...))`;
+/// the error is returned unchanged.
+pub fn capture<T>(
+    store: &mut spin_core::Store<T>,
+    err: anyhow::Error,
+    trigger_type: &str,
+    component_id: &str,
+    event: impl IntoIterator<Item = (&'static str, String)>,
+) -> anyhow::Error {
+    let Some(dir) = store.core_dump_dir().map(Path::to_path_buf) else {
+        return err;
+    };
+    let Some(bytes) = store.serialize_core_dump(&err, component_id) else {
+        return err;
+    };
+    let metadata = CoreDumpMetadata {
+        trigger_type: trigger_type.into(),
+        component_id: component_id.into(),
+        timestamp_ms: now_ms(),
+        error: format!("{err:?}"),
+        event: event.into_iter().map(|(k, v)| (k.into(), v)).collect(),
+    };
+    match write(&dir, &metadata, &bytes) {
+        Ok(path) => {
+            tracing::info!("Wrote core dump of component {component_id} to {path:?}");
+            eprintln!(
+                "Component {component_id} trapped; core dump written to {}",
+                quoted_path(&path)
+            );
+        }
+        Err(e) => tracing::error!("Failed to write core dump of component {component_id}: {e:?}"),
+    }
+    err
+}
+
+/// Writes a core dump and its metadata to `dir`, removing the oldest dumps
+/// beyond [`MAX_CORE_DUMPS`]. Returns the path of the core dump file.
+pub fn write(dir: &Path, metadata: &CoreDumpMetadata, core_dump: &[u8]) -> anyhow::Result<PathBuf> {
+    std::fs::create_dir_all(dir)
+        .with_context(|| format!("failed to create core dump directory {dir:?}"))?;
+    let id = NEXT_CORE_DUMP_ID.fetch_add(1, Ordering::Relaxed);
+    let stem = format!(
+        "{}-{}-{id}",
+        sanitize_filename::sanitize(&metadata.component_id),
+        metadata.timestamp_ms
+    );
+    let path = dir.join(&stem).with_extension(CORE_DUMP_EXTENSION);
+    std::fs::write(&path, core_dump)
+        .with_context(|| format!("failed to write core dump {path:?}"))?;
+    let metadata_path = path.with_extension(METADATA_EXTENSION);
+    std::fs::write(&metadata_path, serde_json::to_vec_pretty(metadata)?)
+        .with_context(|| format!("failed to write core dump metadata {metadata_path:?}"))?;
+
+    for old in list(dir)?.into_iter().skip(MAX_CORE_DUMPS) {
+        if let Err(err) = remove(&old) {
+            tracing::warn!("Failed to remove old core dump {:?}: {err:?}", old.path);
+        }
+    }
+    Ok(path)
+}
+
+/// Lists the core dumps in `dir`, most recent first.
+///
+/// Core dumps without readable metadata are skipped. A missing directory has
+/// no core dumps.
+pub fn list(dir: &Path) -> anyhow::Result<Vec<CoreDump>> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(err) => {
+            return Err(err).with_context(|| format!("failed to read core dump directory {dir:?}"))
+        }
+    };
+    let mut core_dumps = vec![];
+    for entry in entries {
+        let path = entry?.path();
+        if path.extension().and_then(|ext| ext.to_str()) != Some(CORE_DUMP_EXTENSION) {
+            continue;
+        }
+        let metadata_path = path.with_extension(METADATA_EXTENSION);
+        let metadata = std::fs::read(&metadata_path)
+            .map_err(anyhow::Error::from)
+            .and_then(|json| Ok(serde_json::from_slice(&json)?));
+        match metadata {
+            Ok(metadata) => core_dumps.push(CoreDump { path, metadata }),
+            Err(err) => tracing::debug!("Skipping core dump {path:?}: {err:?}"),
+        }
+    }
+    core_dumps.sort_by(|a, b| {
+        (b.metadata.timestamp_ms, &b.path).cmp(&(a.metadata.timestamp_ms, &a.path))
+    });
+    Ok(core_dumps)
+}
+
+/// Removes a core dump and its metadata.
+pub fn remove(core_dump: &CoreDump) -> anyhow::Result<()> {
+    std::fs::remove_file(&core_dump.path)
+        .with_context(|| format!("failed to remove core dump {:?}", core_dump.path))?;
+    let metadata_path = core_dump.path.with_extension(METADATA_EXTENSION);
+    std::fs::remove_file(&metadata_path)
+        .with_context(|| format!("failed to remove core dump metadata {metadata_path:?}"))
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metadata(component_id: &str, timestamp_ms: u64) -> CoreDumpMetadata {
+        CoreDumpMetadata {
+            trigger_type: "http".into(),
+            component_id: component_id.into(),
+            timestamp_ms,
+            error: "wasm trap: unreachable".into(),
+            event: [("route".to_string(), "/...".to_string())].into(),
+        }
+    }
+
+    #[test]
+    fn written_core_dumps_are_listed_newest_first() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        write(dir.path(), &metadata("old", 1), b"old dump")?;
+        let path = write(dir.path(), &metadata("new", 2), b"new dump")?;
+
+        let core_dumps = list(dir.path())?;
+        assert_eq!(core_dumps.len(), 2);
+        assert_eq!(core_dumps[0].path, path);
+        assert_eq!(core_dumps[0].metadata, metadata("new", 2));
+        assert_eq!(core_dumps[0].read()?, b"new dump");
+        assert_eq!(core_dumps[1].metadata.component_id, "old");
+        Ok(())
+    }
+
+    #[test]
+    fn oldest_core_dumps_are_removed() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        for timestamp_ms in 0..(MAX_CORE_DUMPS as u64 + 2) {
+            write(dir.path(), &metadata("component", timestamp_ms), b"dump")?;
+        }
+
+        let core_dumps = list(dir.path())?;
+        assert_eq!(core_dumps.len(), MAX_CORE_DUMPS);
+        assert_eq!(
+            core_dumps.last().unwrap().metadata.timestamp_ms,
+            2,
+            "the two oldest dumps should have been removed"
+        );
+        assert_eq!(std::fs::read_dir(dir.path())?.count(), 2 * MAX_CORE_DUMPS);
+        Ok(())
+    }
+
+    #[test]
+    fn missing_directory_has_no_core_dumps() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        assert!(list(&dir.path().join("missing"))?.is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,24 @@
+//! Correlating the output of components with the executions that wrote it.
+
+use std::future::Future;
+
+tokio::task_local! {
+    static CORRELATION_ID: String;
+}
+
+/// Runs the given future with a correlation ID.
+///
+/// Instances prepared by the future prefix each line their component writes
+/// to stdout and stderr with the ID, in log files and `--follow` output.
+/// Triggers should use an ID they also give to the caller, such as the HTTP
+/// trigger's request ID, so that the output of a failed request can be found.
+/// Instances prepared without one are given a random ID.
+pub async fn with_correlation_id<R>(id: impl Into<String>, fut: impl Future<Output = R>) -> R {
+    CORRELATION_ID.scope(id.into(), fut).await
+}
+
+/// The correlation ID set by [`with_correlation_id`] for the current task, if
+/// any.
+pub fn current_correlation_id() -> Option<String> {
+    CORRELATION_ID.try_with(Clone::clone).ok()
+}
@@ -1,7 +1,12 @@
 pub mod cli;
+pub mod core_dump;
+pub mod correlation;
 pub mod loader;
+pub mod plugin;
+pub mod saturation;
+pub mod systemd;
 
-use std::future::Future;
+use std::{future::Future, path::Path};
 
 use clap::Args;
 use spin_core::Linker;
@@ -9,6 +14,7 @@ use spin_factors::RuntimeFactors;
 use spin_factors_executor::{FactorsExecutorApp, FactorsInstanceBuilder};
 
 pub use spin_app::App;
+pub use spin_factors_executor::{ConcurrencyLimitExceeded, ConcurrencyPermits};
 
 /// Type alias for a [`spin_factors_executor::FactorsExecutorApp`] specialized to a [`Trigger`].
 pub type TriggerApp<T, F> = FactorsExecutorApp<F, <T as Trigger<F>>::InstanceState>;
@@ -40,6 +46,14 @@ pub trait Trigger<F: RuntimeFactors>: Sized + Send {
     /// Constructs a new trigger.
     fn new(cli_args: Self::CliArgs, app: &App) -> anyhow::Result<Self>;
 
+    /// The type of the app's triggers that this trigger runs.
+    ///
+    /// This is [`Trigger::TYPE`] except for triggers that run other types of
+    /// triggers, such as [`plugin::PluginTrigger`].
+    fn trigger_type(&self) -> &str {
+        Self::TYPE
+    }
+
     /// Update the [`spin_core::Config`] for this trigger.
     ///
     /// !!!Warning!!! This is unsupported; many configurations are likely to
@@ -50,6 +64,19 @@ pub trait Trigger<F: RuntimeFactors>: Sized + Send {
         Ok(())
     }
 
+    /// Update this trigger from its own sections of the runtime config file,
+    /// e.g. `[http_server]` for the HTTP trigger.
+    ///
+    /// Relative paths in the runtime config are relative to `runtime_config_dir`.
+    fn update_from_runtime_config(
+        &mut self,
+        runtime_config: &toml::Table,
+        runtime_config_dir: Option<&Path>,
+    ) -> anyhow::Result<()> {
+        let _ = (runtime_config, runtime_config_dir);
+        Ok(())
+    }
+
     /// Update the [`Linker`] for this trigger.
     fn add_to_linker(
         &mut self,
@@ -65,6 +92,32 @@ pub trait Trigger<F: RuntimeFactors>: Sized + Send {
         trigger_app: TriggerApp<Self, F>,
     ) -> impl Future<Output = anyhow::Result<()>> + Send;
 
+    /// Run this trigger, switching to each reconfigured version of the app
+    /// received from `reconfigured`.
+    ///
+    /// Reconfigured apps have the same components as `trigger_app`, with
+    /// changes only to their configuration, such as their triggers or
+    /// variables; see [`FactorsExecutorApp::reconfigure`]. The default runs
+    /// the trigger with [`Trigger::run`] and drops `reconfigured`, so that
+    /// changes to the app need a restart.
+    fn run_reconfigurable(
+        self,
+        trigger_app: TriggerApp<Self, F>,
+        reconfigured: tokio::sync::mpsc::UnboundedReceiver<TriggerApp<Self, F>>,
+    ) -> impl Future<Output = anyhow::Result<()>> + Send {
+        drop(reconfigured);
+        self.run(trigger_app)
+    }
+
+    /// Returns the instance state for running the app's `on_startup` and
+    /// `on_shutdown` hook components.
+    ///
+    /// Returns `None` (the default) if this trigger doesn't support lifecycle
+    /// hooks, in which case apps that declare them fail to start.
+    fn lifecycle_hook_instance_state() -> Option<Self::InstanceState> {
+        None
+    }
+
     /// Returns a list of host requirements supported by this trigger specifically.
     ///
     /// See [`App::ensure_needs_only`].
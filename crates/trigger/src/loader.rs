@@ -3,7 +3,7 @@ use spin_common::{ui::quoted_path, url::parse_file_url};
 use spin_core::{async_trait, wasmtime, Component};
 use spin_factors::AppComponent;
 
-#[derive(Default)]
+#[derive(Clone, Default)]
 pub struct ComponentLoader {
     _private: (),
     #[cfg(feature = "unsafe-aot-compilation")]
@@ -83,19 +83,33 @@ impl spin_factors_executor::ComponentLoader for ComponentLoader {
 
         #[cfg(feature = "unsafe-aot-compilation")]
         if self.aot_compilation_enabled {
+            let middleware = component.middleware()?;
+            if !middleware.is_empty() {
+                let ids = middleware.iter().map(|m| m.id()).collect::<Vec<_>>();
+                tracing::warn!(
+                    "Component {:?} is precompiled, so its middleware {ids:?} is not applied; compose it into the component before precompiling",
+                    component.id()
+                );
+            }
             return self
                 .load_precompiled_component(engine, &path)
                 .with_context(|| format!("error deserializing component from {path:?}"));
         }
 
-        let composed = spin_compose::compose(&ComponentSourceLoader, component.locked)
-            .await
-            .with_context(|| {
-                format!(
-                    "failed to resolve dependencies for component {:?}",
-                    component.locked.id
-                )
-            })?;
+        let middleware = component.middleware()?;
+        let middleware = middleware.iter().map(|m| m.locked).collect::<Vec<_>>();
+        let composed = spin_compose::compose_with_middleware(
+            &ComponentSourceLoader,
+            component.locked,
+            &middleware,
+        )
+        .await
+        .with_context(|| {
+            format!(
+                "failed to resolve dependencies and middleware for component {:?}",
+                component.locked.id
+            )
+        })?;
 
         spin_core::Component::new(engine, composed)
             .with_context(|| format!("failed to compile component from {}", quoted_path(&path)))
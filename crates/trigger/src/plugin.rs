@@ -0,0 +1,357 @@
+//! Hosting trigger plugins.
+//!
+//! A trigger plugin is an external program that receives events for a trigger
+//! type Spin doesn't have built in, and asks Spin to run the app's components
+//! to handle them. Spin keeps loading the app, configuring its factors, and
+//! recording telemetry, so plugins don't need to embed the Spin runtime. The
+//! messages between them are described in [`protocol`].
+
+pub mod protocol;
+mod value;
+
+use std::{collections::HashMap, future::Future, path::PathBuf, process::Stdio, sync::Arc};
+
+use anyhow::{bail, ensure, Context};
+use clap::Args;
+use spin_core::wasmtime::component::Val;
+use spin_factor_context::ContextFactor;
+use spin_factors::RuntimeFactors;
+use tokio::{
+    io::{AsyncBufRead, AsyncBufReadExt, AsyncWriteExt, BufReader},
+    process::Command,
+    sync::mpsc,
+};
+use tracing::{instrument, Level};
+
+use crate::{App, Trigger, TriggerApp};
+use protocol::{HostMessage, InvokeResult, PluginMessage, TriggerInfo, PROTOCOL_VERSION};
+
+/// Runs an app's triggers of one type with a trigger plugin.
+pub struct PluginTrigger {
+    trigger_type: String,
+    plugin_path: PathBuf,
+}
+
+/// The trigger type and plugin of a [`PluginTrigger`]. These are set by
+/// `spin up` from the plugin's manifest.
+#[derive(Args)]
+pub struct PluginTriggerArgs {
+    /// The trigger type to run.
+    #[clap(long = "plugin-trigger-type", hide = true)]
+    pub trigger_type: String,
+
+    /// The trigger plugin executable.
+    #[clap(long = "plugin-path", hide = true)]
+    pub plugin_path: PathBuf,
+}
+
+impl<F: RuntimeFactors> Trigger<F> for PluginTrigger {
+    const TYPE: &'static str = "plugin";
+
+    type CliArgs = PluginTriggerArgs;
+
+    type InstanceState = ();
+
+    fn new(cli_args: Self::CliArgs, _app: &App) -> anyhow::Result<Self> {
+        Ok(Self {
+            trigger_type: cli_args.trigger_type,
+            plugin_path: cli_args.plugin_path,
+        })
+    }
+
+    fn trigger_type(&self) -> &str {
+        &self.trigger_type
+    }
+
+    async fn run(self, trigger_app: TriggerApp<Self, F>) -> anyhow::Result<()> {
+        let app = trigger_app.app();
+        let trigger_type = self.trigger_type.as_str();
+        let metadata = app
+            .get_trigger_metadata::<serde_json::Value>(trigger_type)?
+            .unwrap_or_default();
+        let mut triggers = Vec::new();
+        let mut components = HashMap::new();
+        for trigger in app.triggers_with_type(trigger_type) {
+            components
+                .entry(trigger.component()?.id().to_owned())
+                .or_insert_with(|| trigger.id().to_owned());
+            triggers.push(TriggerInfo {
+                id: trigger.id().to_owned(),
+                config: trigger.typed_config()?,
+            });
+        }
+
+        let mut plugin = Command::new(&self.plugin_path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .with_context(|| {
+                format!(
+                    "failed to start {trigger_type} trigger plugin {}",
+                    self.plugin_path.display()
+                )
+            })?;
+        let mut stdin = plugin.stdin.take().context("plugin stdin missing")?;
+        let plugin_output = BufReader::new(plugin.stdout.take().context("plugin stdout missing")?);
+
+        // Messages to the plugin are written by a single task so that
+        // invocations can reply as they finish.
+        let (sender, mut receiver) = mpsc::unbounded_channel::<HostMessage>();
+        tokio::spawn(async move {
+            while let Some(message) = receiver.recv().await {
+                let mut line = serde_json::to_vec(&message).expect("messages serialize");
+                line.push(b'\n');
+                if let Err(err) = stdin.write_all(&line).await {
+                    tracing::warn!("Failed to write to trigger plugin: {err}");
+                    break;
+                }
+            }
+        });
+        let _ = sender.send(HostMessage::Init {
+            protocol_version: PROTOCOL_VERSION,
+            trigger_type: trigger_type.to_owned(),
+            metadata,
+            triggers,
+        });
+
+        let invoker = Arc::new(Invoker {
+            trigger_type: self.trigger_type.clone(),
+            trigger_app,
+            components,
+        });
+        handle_plugin_messages(
+            trigger_type,
+            plugin_output,
+            sender,
+            move |component, export, args| {
+                let invoker = invoker.clone();
+                async move { invoker.invoke(&component, &export, &args).await }
+            },
+        )
+        .await?;
+
+        let status = plugin
+            .wait()
+            .await
+            .context("failed to wait for trigger plugin")?;
+        ensure!(
+            status.success(),
+            "{trigger_type} trigger plugin exited with {status}"
+        );
+        Ok(())
+    }
+
+    fn lifecycle_hook_instance_state() -> Option<Self::InstanceState> {
+        Some(())
+    }
+}
+
+/// Handles the messages a trigger plugin writes to `output` until it closes
+/// it, running each invocation with `invoke` and sending its result with
+/// `sender`.
+async fn handle_plugin_messages<Fut>(
+    trigger_type: &str,
+    output: impl AsyncBufRead + Unpin,
+    sender: mpsc::UnboundedSender<HostMessage>,
+    invoke: impl Fn(String, String, Vec<serde_json::Value>) -> Fut,
+) -> anyhow::Result<()>
+where
+    Fut: Future<Output = anyhow::Result<Vec<serde_json::Value>>> + Send + 'static,
+{
+    let mut lines = output.lines();
+    let mut ready = false;
+    while let Some(line) = lines
+        .next_line()
+        .await
+        .context("failed to read from trigger plugin")?
+    {
+        let message: PluginMessage = serde_json::from_str(&line)
+            .with_context(|| format!("invalid message from trigger plugin: {line}"))?;
+        match message {
+            PluginMessage::Ready { protocol_version } => {
+                ensure!(!ready, "trigger plugin sent more than one ready message");
+                ensure!(
+                    protocol_version == PROTOCOL_VERSION,
+                    "trigger plugin uses protocol version {protocol_version}, but Spin supports only version {PROTOCOL_VERSION}"
+                );
+                ready = true;
+                crate::systemd::notify_ready(&format!("{trigger_type} trigger plugin ready"));
+            }
+            PluginMessage::Invoke { .. } if !ready => {
+                bail!("trigger plugin invoked a component before it was ready")
+            }
+            PluginMessage::Invoke {
+                id,
+                component,
+                export,
+                args,
+            } => {
+                let invocation = invoke(component, export, args);
+                let sender = sender.clone();
+                tokio::spawn(async move {
+                    let result = match invocation.await {
+                        Ok(results) => InvokeResult::Ok(results),
+                        Err(err) => InvokeResult::Error(format!("{err:#}")),
+                    };
+                    let _ = sender.send(HostMessage::InvokeResult { id, result });
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Runs components on behalf of a trigger plugin.
+struct Invoker<F: RuntimeFactors> {
+    trigger_type: String,
+    trigger_app: TriggerApp<PluginTrigger, F>,
+    /// The components of the app's triggers of this type, and the ID of the
+    /// first trigger of each.
+    components: HashMap<String, String>,
+}
+
+impl<F: RuntimeFactors> Invoker<F> {
+    #[instrument(name = "spin_trigger.plugin.invoke", skip_all, err(level = Level::INFO), fields(
+        otel.name = format!("{} {export}", self.trigger_type),
+        trigger_type = %self.trigger_type,
+        component_id = %component_id,
+    ))]
+    async fn invoke(
+        &self,
+        component_id: &str,
+        export: &str,
+        args: &[serde_json::Value],
+    ) -> anyhow::Result<Vec<serde_json::Value>> {
+        let Some(trigger_id) = self.components.get(component_id) else {
+            bail!(
+                "component {component_id:?} is not the component of any {} trigger",
+                self.trigger_type
+            );
+        };
+        spin_telemetry::metrics::monotonic_counter!(
+            spin.request_count = 1,
+            trigger_type = self.trigger_type.as_str(),
+            app_id = self.trigger_app.app().id(),
+            component_id = component_id
+        );
+
+        let mut instance_builder = self.trigger_app.prepare(component_id).await?;
+        if let Some(context) = instance_builder.factor_builder::<ContextFactor>() {
+            context.set_trigger(&self.trigger_type, trigger_id);
+        }
+        let (instance, mut store) = instance_builder.instantiate(()).await?;
+        let func = match export.rsplit_once('#') {
+            Some((interface, name)) => instance
+                .get_export(&mut store, None, interface)
+                .and_then(|interface| instance.get_export(&mut store, Some(&interface), name))
+                .and_then(|index| instance.get_func(&mut store, &index)),
+            None => instance.get_func(&mut store, export),
+        }
+        .with_context(|| format!("component {component_id:?} does not export {export:?}"))?;
+
+        let param_types = func.params(&store);
+        ensure!(
+            args.len() == param_types.len(),
+            "{export:?} takes {} arguments but {} were given",
+            param_types.len(),
+            args.len()
+        );
+        let params = args
+            .iter()
+            .zip(param_types.iter())
+            .enumerate()
+            .map(|(index, (arg, ty))| {
+                value::from_json(arg, ty).with_context(|| format!("invalid argument {index}"))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let mut results = vec![Val::Bool(false); func.results(&store).len()];
+        func.call_async(&mut store, &params, &mut results).await?;
+        func.post_return_async(&mut store).await?;
+
+        results.iter().map(value::to_json).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    async fn handle(output: &str) -> (anyhow::Result<()>, Vec<HostMessage>) {
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let result = handle_plugin_messages(
+            "test",
+            output.as_bytes(),
+            sender,
+            |component, export, args| async move {
+                ensure!(component == "hello", "no component {component:?}");
+                Ok(vec![json!(format!("{export}: {}", args.len()))])
+            },
+        )
+        .await;
+        let mut replies = Vec::new();
+        while let Some(message) = receiver.recv().await {
+            replies.push(message);
+        }
+        replies.sort_by_key(|message| match message {
+            HostMessage::InvokeResult { id, .. } => *id,
+            HostMessage::Init { .. } => 0,
+        });
+        (result, replies)
+    }
+
+    #[tokio::test]
+    async fn invocations_are_answered() {
+        let (result, replies) = handle(concat!(
+            r#"{"type": "ready", "protocol_version": 1}"#,
+            "\n",
+            r#"{"type": "invoke", "id": 1, "component": "hello", "export": "run", "args": [1, 2]}"#,
+            "\n",
+            r#"{"type": "invoke", "id": 2, "component": "goodbye", "export": "run"}"#,
+            "\n",
+        ))
+        .await;
+        result.unwrap();
+        assert_eq!(
+            vec![
+                HostMessage::InvokeResult {
+                    id: 1,
+                    result: InvokeResult::Ok(vec![json!("run: 2")]),
+                },
+                HostMessage::InvokeResult {
+                    id: 2,
+                    result: InvokeResult::Error("no component \"goodbye\"".into()),
+                },
+            ],
+            replies
+        );
+    }
+
+    #[tokio::test]
+    async fn invocations_must_follow_ready() {
+        let (result, replies) = handle(concat!(
+            r#"{"type": "invoke", "id": 1, "component": "hello", "export": "run"}"#,
+            "\n",
+        ))
+        .await;
+        assert!(result.is_err());
+        assert!(replies.is_empty());
+    }
+
+    #[tokio::test]
+    async fn protocol_version_must_match() {
+        let (result, _) =
+            handle(concat!(r#"{"type": "ready", "protocol_version": 2}"#, "\n")).await;
+        let err = result.unwrap_err().to_string();
+        assert!(err.contains("protocol version 2"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn invalid_messages_are_errors() {
+        let (result, _) = handle("not json\n").await;
+        let err = result.unwrap_err().to_string();
+        assert!(err.contains("invalid message"), "{err}");
+    }
+}
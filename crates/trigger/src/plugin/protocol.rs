@@ -0,0 +1,138 @@
+//! Messages exchanged between Spin and a trigger plugin.
+//!
+//! Each message is a JSON object on a single line. Spin writes
+//! [`HostMessage`]s to the plugin's stdin and reads [`PluginMessage`]s from
+//! its stdout; the plugin's stderr is passed through to Spin's.
+//!
+//! A session goes:
+//!
+//! 1. Spin sends [`HostMessage::Init`] with the app's triggers of the
+//!    plugin's type.
+//! 2. The plugin replies [`PluginMessage::Ready`] once it is listening for
+//!    events, giving the protocol version it speaks.
+//! 3. For each event, the plugin sends [`PluginMessage::Invoke`], and Spin
+//!    runs the component and replies [`HostMessage::InvokeResult`] with the
+//!    same `id`. Invocations may overlap.
+//! 4. The plugin exits when it has no more events to handle. Spin treats a
+//!    nonzero exit status as a trigger failure.
+//!
+//! Function arguments and results are JSON encodings of component values:
+//! numbers, booleans and strings (including `char`) as themselves, lists and
+//! tuples as arrays, records as objects, options as `null` or the value,
+//! enums as the case name, flags as an array of the set flag names, and
+//! variants and results as either the case name (for cases without a value)
+//! or an object with the case name as its single key, e.g.
+//! `{"ok": [1, 2, 3]}`. Resources are not supported.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// The version of the protocol described by this module.
+///
+/// Plugin manifests declare the version they speak with `triggerProtocol`.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// A message from Spin to a trigger plugin.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum HostMessage {
+    /// Describes the app's triggers of the plugin's type. This is always the
+    /// first message.
+    Init {
+        protocol_version: u32,
+        /// The trigger type the plugin is run for.
+        trigger_type: String,
+        /// The `[application.trigger.<type>]` metadata, or `null`.
+        metadata: Value,
+        /// The app's triggers of this type.
+        triggers: Vec<TriggerInfo>,
+    },
+    /// The outcome of the [`PluginMessage::Invoke`] with the same `id`.
+    InvokeResult { id: u64, result: InvokeResult },
+}
+
+/// A trigger of the plugin's type.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TriggerInfo {
+    /// The app-unique ID of the trigger.
+    pub id: String,
+    /// The trigger's `[[trigger.<type>]]` table.
+    pub config: Value,
+}
+
+/// The outcome of an invocation.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InvokeResult {
+    /// The function's results.
+    Ok(Vec<Value>),
+    /// Why the component could not be run, or the trap that ended it.
+    Error(String),
+}
+
+/// A message from a trigger plugin to Spin.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PluginMessage {
+    /// The plugin is ready to handle events.
+    Ready { protocol_version: u32 },
+    /// Run an exported function of a new instance of a component.
+    Invoke {
+        id: u64,
+        /// The component to instantiate. This must be the component of one of
+        /// the plugin's triggers.
+        component: String,
+        /// The function to call, either a function exported by the component
+        /// itself or `<interface>#<function>`, e.g.
+        /// `fermyon:spin/inbound-redis#handle-message`.
+        export: String,
+        /// The function's arguments.
+        #[serde(default)]
+        args: Vec<Value>,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn host_messages_are_tagged() {
+        let message = HostMessage::InvokeResult {
+            id: 3,
+            result: InvokeResult::Ok(vec![json!("done")]),
+        };
+        assert_eq!(
+            json!({"type": "invoke_result", "id": 3, "result": {"ok": ["done"]}}),
+            serde_json::to_value(&message).unwrap()
+        );
+    }
+
+    #[test]
+    fn plugin_messages_parse() {
+        let message: PluginMessage = serde_json::from_str(
+            r#"{"type": "invoke", "id": 1, "component": "hello", "export": "run"}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            PluginMessage::Invoke {
+                id: 1,
+                component: "hello".into(),
+                export: "run".into(),
+                args: vec![],
+            },
+            message
+        );
+
+        let message: PluginMessage =
+            serde_json::from_str(r#"{"type": "ready", "protocol_version": 1}"#).unwrap();
+        assert_eq!(
+            PluginMessage::Ready {
+                protocol_version: PROTOCOL_VERSION
+            },
+            message
+        );
+    }
+}
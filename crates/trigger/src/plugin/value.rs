@@ -0,0 +1,245 @@
+//! Conversions between JSON and component values, as described in
+//! [`super::protocol`].
+
+use anyhow::{bail, ensure, Context, Result};
+use serde_json::{Map, Value};
+use spin_core::wasmtime::component::{types::Type, Val};
+
+/// Converts a JSON value to a component value of the given type.
+pub(crate) fn from_json(value: &Value, ty: &Type) -> Result<Val> {
+    Ok(match ty {
+        Type::Bool => Val::Bool(value.as_bool().context("expected a boolean")?),
+        Type::S8 => Val::S8(int(value)?),
+        Type::U8 => Val::U8(int(value)?),
+        Type::S16 => Val::S16(int(value)?),
+        Type::U16 => Val::U16(int(value)?),
+        Type::S32 => Val::S32(int(value)?),
+        Type::U32 => Val::U32(int(value)?),
+        Type::S64 => Val::S64(value.as_i64().context("expected an integer")?),
+        Type::U64 => Val::U64(value.as_u64().context("expected an unsigned integer")?),
+        Type::Float32 => Val::Float32(value.as_f64().context("expected a number")? as f32),
+        Type::Float64 => Val::Float64(value.as_f64().context("expected a number")?),
+        Type::Char => {
+            let s = string(value)?;
+            let mut chars = s.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => Val::Char(c),
+                _ => bail!("expected a single character"),
+            }
+        }
+        Type::String => Val::String(string(value)?.to_owned()),
+        Type::List(list) => {
+            let ty = list.ty();
+            let items = array(value)?
+                .iter()
+                .enumerate()
+                .map(|(index, item)| {
+                    from_json(item, &ty).with_context(|| format!("invalid list item {index}"))
+                })
+                .collect::<Result<_>>()?;
+            Val::List(items)
+        }
+        Type::Record(record) => {
+            let object = value.as_object().context("expected an object")?;
+            let fields = record
+                .fields()
+                .map(|field| {
+                    let value = object.get(field.name).unwrap_or(&Value::Null);
+                    let value = from_json(value, &field.ty)
+                        .with_context(|| format!("invalid field {:?}", field.name))?;
+                    Ok((field.name.to_owned(), value))
+                })
+                .collect::<Result<_>>()?;
+            Val::Record(fields)
+        }
+        Type::Tuple(tuple) => {
+            let items = array(value)?;
+            let types = tuple.types().collect::<Vec<_>>();
+            ensure!(
+                items.len() == types.len(),
+                "expected an array of {} values",
+                types.len()
+            );
+            let items = items
+                .iter()
+                .zip(&types)
+                .enumerate()
+                .map(|(index, (item, ty))| {
+                    from_json(item, ty).with_context(|| format!("invalid tuple item {index}"))
+                })
+                .collect::<Result<_>>()?;
+            Val::Tuple(items)
+        }
+        Type::Variant(variant) => {
+            let (name, payload) = case(value)?;
+            let case = variant
+                .cases()
+                .find(|case| case.name == name)
+                .with_context(|| format!("unknown case {name:?}"))?;
+            Val::Variant(
+                name.to_owned(),
+                payload_from_json(payload, case.ty.as_ref())?,
+            )
+        }
+        Type::Enum(enum_) => {
+            let name = string(value)?;
+            ensure!(
+                enum_.names().any(|case| case == name),
+                "unknown case {name:?}"
+            );
+            Val::Enum(name.to_owned())
+        }
+        Type::Option(option) => match value {
+            Value::Null => Val::Option(None),
+            value => Val::Option(Some(Box::new(from_json(value, &option.ty())?))),
+        },
+        Type::Result(result) => match case(value)? {
+            ("ok", payload) => Val::Result(Ok(payload_from_json(payload, result.ok().as_ref())?)),
+            ("err", payload) => {
+                Val::Result(Err(payload_from_json(payload, result.err().as_ref())?))
+            }
+            (name, _) => bail!("unknown case {name:?}; expected \"ok\" or \"err\""),
+        },
+        Type::Flags(flags) => {
+            let names = array(value)?
+                .iter()
+                .map(|name| string(name).map(ToOwned::to_owned))
+                .collect::<Result<Vec<_>>>()?;
+            for name in &names {
+                ensure!(
+                    flags.names().any(|flag| flag == name),
+                    "unknown flag {name:?}"
+                );
+            }
+            Val::Flags(names)
+        }
+        Type::Own(_) | Type::Borrow(_) => bail!("resources are not supported by trigger plugins"),
+    })
+}
+
+/// Converts a component value to JSON.
+pub(crate) fn to_json(value: &Val) -> Result<Value> {
+    Ok(match value {
+        Val::Bool(b) => Value::Bool(*b),
+        Val::S8(n) => (*n).into(),
+        Val::U8(n) => (*n).into(),
+        Val::S16(n) => (*n).into(),
+        Val::U16(n) => (*n).into(),
+        Val::S32(n) => (*n).into(),
+        Val::U32(n) => (*n).into(),
+        Val::S64(n) => (*n).into(),
+        Val::U64(n) => (*n).into(),
+        Val::Float32(n) => (*n).into(),
+        Val::Float64(n) => (*n).into(),
+        Val::Char(c) => Value::String(c.to_string()),
+        Val::String(s) => Value::String(s.clone()),
+        Val::List(items) | Val::Tuple(items) => {
+            Value::Array(items.iter().map(to_json).collect::<Result<_>>()?)
+        }
+        Val::Record(fields) => Value::Object(
+            fields
+                .iter()
+                .map(|(name, value)| Ok((name.clone(), to_json(value)?)))
+                .collect::<Result<_>>()?,
+        ),
+        Val::Variant(name, payload) => case_to_json(name, payload.as_deref())?,
+        Val::Enum(name) => Value::String(name.clone()),
+        Val::Option(None) => Value::Null,
+        Val::Option(Some(value)) => to_json(value)?,
+        Val::Result(Ok(payload)) => case_to_json("ok", payload.as_deref())?,
+        Val::Result(Err(payload)) => case_to_json("err", payload.as_deref())?,
+        Val::Flags(names) => Value::Array(names.iter().cloned().map(Value::String).collect()),
+        Val::Resource(_) => bail!("resources are not supported by trigger plugins"),
+    })
+}
+
+fn int<T: TryFrom<i64>>(value: &Value) -> Result<T> {
+    let n = value.as_i64().context("expected an integer")?;
+    T::try_from(n)
+        .ok()
+        .with_context(|| format!("integer {n} is out of range"))
+}
+
+fn string(value: &Value) -> Result<&str> {
+    value.as_str().context("expected a string")
+}
+
+fn array(value: &Value) -> Result<&Vec<Value>> {
+    value.as_array().context("expected an array")
+}
+
+/// Splits a variant or result into its case name and value.
+fn case(value: &Value) -> Result<(&str, Option<&Value>)> {
+    match value {
+        Value::String(name) => Ok((name, None)),
+        Value::Object(object) if object.len() == 1 => {
+            let (name, payload) = object.iter().next().unwrap();
+            Ok((name, Some(payload)))
+        }
+        _ => bail!("expected a case name or an object with a single case"),
+    }
+}
+
+fn payload_from_json(payload: Option<&Value>, ty: Option<&Type>) -> Result<Option<Box<Val>>> {
+    match (payload, ty) {
+        (None | Some(Value::Null), None) => Ok(None),
+        (Some(_), None) => bail!("case does not have a value"),
+        (Some(payload), Some(ty)) => Ok(Some(Box::new(from_json(payload, ty)?))),
+        (None, Some(_)) => bail!("case requires a value"),
+    }
+}
+
+fn case_to_json(name: &str, payload: Option<&Val>) -> Result<Value> {
+    Ok(match payload {
+        None => Value::String(name.to_owned()),
+        Some(payload) => {
+            let mut object = Map::new();
+            object.insert(name.to_owned(), to_json(payload)?);
+            Value::Object(object)
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn primitives_convert() {
+        assert_eq!(Val::U8(7), from_json(&json!(7), &Type::U8).unwrap());
+        assert_eq!(Val::Char('x'), from_json(&json!("x"), &Type::Char).unwrap());
+        assert_eq!(
+            Val::String("hi".into()),
+            from_json(&json!("hi"), &Type::String).unwrap()
+        );
+        from_json(&json!(256), &Type::U8).unwrap_err();
+        from_json(&json!("xy"), &Type::Char).unwrap_err();
+        from_json(&json!(1), &Type::Bool).unwrap_err();
+    }
+
+    #[test]
+    fn values_convert_to_json() {
+        let value = Val::Record(vec![
+            ("body".into(), Val::List(vec![Val::U8(104), Val::U8(105)])),
+            ("retry".into(), Val::Option(None)),
+            (
+                "outcome".into(),
+                Val::Result(Err(Some(Box::new(Val::Enum("timeout".into()))))),
+            ),
+            ("done".into(), Val::Variant("finished".into(), None)),
+            ("flags".into(), Val::Flags(vec!["urgent".into()])),
+        ]);
+        assert_eq!(
+            json!({
+                "body": [104, 105],
+                "retry": null,
+                "outcome": {"err": "timeout"},
+                "done": "finished",
+                "flags": ["urgent"],
+            }),
+            to_json(&value).unwrap()
+        );
+    }
+}
@@ -0,0 +1,176 @@
+//! Saturation metrics for autoscalers.
+//!
+//! Triggers record the work they accept with a [`SaturationTracker`], and
+//! embedders or autoscalers read it with [`SaturationTracker::current`] or
+//! follow it with [`SaturationTracker::subscribe`].
+
+use std::sync::Arc;
+
+use serde::Serialize;
+use tokio::sync::watch;
+
+/// A point-in-time view of a trigger's load.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct Saturation {
+    /// Events accepted by the trigger that are waiting for an instance.
+    pub queue_depth: u64,
+    /// Events currently executing in a component instance.
+    pub in_flight: u64,
+    /// Events turned away by the trigger, e.g. for lack of capacity, since
+    /// startup.
+    pub rejected_total: u64,
+    /// Events accepted by the trigger which failed before they executed, e.g.
+    /// because an instance couldn't be created, since startup.
+    pub failed_total: u64,
+    /// Events that finished executing, successfully or not, since startup.
+    pub completed_total: u64,
+}
+
+/// Tracks the [`Saturation`] of a trigger.
+///
+/// Cloning a tracker is cheap; clones share the same counts.
+#[derive(Clone, Debug)]
+pub struct SaturationTracker {
+    state: Arc<watch::Sender<Saturation>>,
+}
+
+impl SaturationTracker {
+    /// Creates a tracker with no recorded events.
+    pub fn new() -> Self {
+        let (state, _) = watch::channel(Saturation::default());
+        Self {
+            state: Arc::new(state),
+        }
+    }
+
+    /// Returns the current saturation.
+    pub fn current(&self) -> Saturation {
+        *self.state.borrow()
+    }
+
+    /// Returns a receiver that is notified whenever the saturation changes.
+    pub fn subscribe(&self) -> watch::Receiver<Saturation> {
+        self.state.subscribe()
+    }
+
+    /// Records that an event was accepted and is waiting for an instance.
+    ///
+    /// The event is counted as failed if the returned guard is dropped
+    /// without calling [`Queued::start`] or [`Queued::reject`].
+    pub fn enqueue(&self) -> Queued {
+        self.state.send_modify(|s| s.queue_depth += 1);
+        Queued {
+            tracker: Some(self.clone()),
+        }
+    }
+
+    /// Records that an event was rejected before it was queued.
+    pub fn reject(&self) {
+        self.state.send_modify(|s| s.rejected_total += 1);
+    }
+}
+
+impl Default for SaturationTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A queued event; see [`SaturationTracker::enqueue`].
+#[must_use]
+pub struct Queued {
+    tracker: Option<SaturationTracker>,
+}
+
+impl Queued {
+    /// Records that the event started executing.
+    ///
+    /// The event is counted as completed when the returned guard is dropped.
+    pub fn start(mut self) -> InFlight {
+        let tracker = self.tracker.take().unwrap();
+        tracker.state.send_modify(|s| {
+            s.queue_depth -= 1;
+            s.in_flight += 1;
+        });
+        InFlight { tracker }
+    }
+
+    /// Records that the event was turned away while it waited.
+    pub fn reject(mut self) {
+        let tracker = self.tracker.take().unwrap();
+        tracker.state.send_modify(|s| {
+            s.queue_depth -= 1;
+            s.rejected_total += 1;
+        });
+    }
+}
+
+impl Drop for Queued {
+    fn drop(&mut self) {
+        if let Some(tracker) = self.tracker.take() {
+            tracker.state.send_modify(|s| {
+                s.queue_depth -= 1;
+                s.failed_total += 1;
+            });
+        }
+    }
+}
+
+/// An executing event; see [`Queued::start`].
+#[must_use]
+pub struct InFlight {
+    tracker: SaturationTracker,
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.tracker.state.send_modify(|s| {
+            s.in_flight -= 1;
+            s.completed_total += 1;
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn guards_update_saturation() {
+        let tracker = SaturationTracker::new();
+        let changes = tracker.subscribe();
+
+        let queued = tracker.enqueue();
+        let rejected = tracker.enqueue();
+        let failed = tracker.enqueue();
+        assert_eq!(tracker.current().queue_depth, 3);
+        assert!(changes.has_changed().unwrap());
+
+        let in_flight = queued.start();
+        rejected.reject();
+        drop(failed);
+        assert_eq!(
+            tracker.current(),
+            Saturation {
+                queue_depth: 0,
+                in_flight: 1,
+                rejected_total: 1,
+                failed_total: 1,
+                completed_total: 0,
+            }
+        );
+
+        drop(in_flight);
+        tracker.reject();
+        assert_eq!(
+            tracker.current(),
+            Saturation {
+                queue_depth: 0,
+                in_flight: 0,
+                rejected_total: 2,
+                failed_total: 1,
+                completed_total: 1,
+            }
+        );
+    }
+}
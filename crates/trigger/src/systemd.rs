@@ -0,0 +1,280 @@
+//! Integration with systemd service management.
+//!
+//! When Spin runs as a systemd service, the service manager may pass in
+//! listening sockets by socket activation (`LISTEN_FDS`), which stay open
+//! across restarts, and may expect readiness and watchdog notifications on
+//! `NOTIFY_SOCKET` (`Type=notify` and `WatchdogSec=` units). Everything here
+//! does nothing when Spin isn't run by systemd.
+//!
+//! Triggers take any passed listeners with [`take_listener`] and call
+//! [`notify_ready`] once they are accepting work.
+//!
+//! `spin up` runs each trigger in a child process. systemd passes sockets to
+//! a single process, so `spin up` hands them on to its triggers explicitly
+//! with [`listener_env`]. Notifications come from the trigger processes, so
+//! `Type=notify` units need `NotifyAccess=all`: with systemd's default of
+//! `NotifyAccess=main`, notifications from anything but `spin up` itself are
+//! ignored.
+
+use std::{net::TcpListener, time::Duration};
+
+/// Takes a TCP listener passed in by socket activation, if there is one.
+///
+/// A socket whose `FileDescriptorName=` is `name` is preferred; otherwise the
+/// first passed socket not yet taken is returned. Each socket can be taken
+/// only once.
+pub fn take_listener(name: &str) -> anyhow::Result<Option<TcpListener>> {
+    #[cfg(unix)]
+    return activation::take_listener(name);
+    #[cfg(not(unix))]
+    {
+        let _ = name;
+        Ok(None)
+    }
+}
+
+/// The environment variables with which to pass the sockets systemd passed
+/// to this process on to a trigger process it starts, which takes them with
+/// [`take_listener`]. The sockets are inherited at the same descriptors.
+///
+/// systemd's own variables name the process they are for, so they are
+/// ignored in the child.
+pub fn listener_env() -> Vec<(&'static str, String)> {
+    #[cfg(unix)]
+    return activation::passed_fds()
+        .map(|(count, names)| {
+            vec![
+                (activation::SPIN_LISTEN_FDS, count.to_string()),
+                (activation::SPIN_LISTEN_FDNAMES, names),
+            ]
+        })
+        .unwrap_or_default();
+    #[cfg(not(unix))]
+    vec![]
+}
+
+/// Tells the service manager that startup has finished.
+pub fn notify_ready(status: &str) {
+    notify(&format!("READY=1\nSTATUS={status}"));
+}
+
+/// Tells the service manager that Spin is shutting down.
+pub fn notify_stopping() {
+    notify("STOPPING=1");
+}
+
+/// Sends keep-alive notifications for the lifetime of the async runtime if
+/// the service manager has a watchdog enabled for this process.
+///
+/// The notifications are sent from a runtime task, so a runtime that stops
+/// making progress gets restarted by the service manager.
+pub fn spawn_watchdog() {
+    let interval = watchdog_interval(
+        std::env::var("WATCHDOG_USEC").ok().as_deref(),
+        std::env::var("WATCHDOG_PID").ok().as_deref(),
+    );
+    let Some(interval) = interval else {
+        return;
+    };
+    tracing::debug!("Sending systemd watchdog notifications every {interval:?}");
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            notify("WATCHDOG=1");
+        }
+    });
+}
+
+/// The interval at which to notify a watchdog with the given `WATCHDOG_USEC`
+/// and `WATCHDOG_PID`, which is half its timeout as systemd recommends.
+fn watchdog_interval(usec: Option<&str>, pid: Option<&str>) -> Option<Duration> {
+    if let Some(pid) = pid {
+        if pid.parse::<u32>().ok()? != std::process::id() {
+            return None;
+        }
+    }
+    let usec = usec?.parse::<u64>().ok().filter(|usec| *usec > 0)?;
+    Some(Duration::from_micros(usec) / 2)
+}
+
+fn notify(state: &str) {
+    #[cfg(unix)]
+    if let Some(path) = std::env::var_os("NOTIFY_SOCKET") {
+        if let Err(err) = activation::send_notification(&path, state) {
+            tracing::debug!("Failed to notify systemd: {err}");
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = state;
+}
+
+#[cfg(unix)]
+mod activation {
+    use std::{
+        ffi::OsStr,
+        net::TcpListener,
+        os::{
+            fd::{FromRawFd, OwnedFd},
+            unix::{ffi::OsStrExt, net::UnixDatagram},
+        },
+        sync::{Mutex, OnceLock},
+    };
+
+    use anyhow::Context;
+
+    /// The first file descriptor passed by socket activation.
+    const SD_LISTEN_FDS_START: i32 = 3;
+
+    struct PassedSocket {
+        name: String,
+        fd: OwnedFd,
+    }
+
+    static PASSED_SOCKETS: OnceLock<Mutex<Vec<PassedSocket>>> = OnceLock::new();
+
+    pub fn take_listener(name: &str) -> anyhow::Result<Option<TcpListener>> {
+        let mut sockets = PASSED_SOCKETS
+            .get_or_init(|| Mutex::new(passed_sockets()))
+            .lock()
+            .unwrap();
+        if sockets.is_empty() {
+            return Ok(None);
+        }
+        let index = sockets
+            .iter()
+            .position(|socket| socket.name == name)
+            .unwrap_or(0);
+        let socket = sockets.remove(index);
+        let listener = TcpListener::from(socket.fd);
+        listener.local_addr().with_context(|| {
+            format!(
+                "socket {:?} passed by systemd is not a TCP listener",
+                socket.name
+            )
+        })?;
+        listener.set_nonblocking(true)?;
+        Ok(Some(listener))
+    }
+
+    /// Env vars set by a Spin process to pass the sockets systemd passed to it
+    /// on to a trigger process it starts. See [`super::listener_env`].
+    pub const SPIN_LISTEN_FDS: &str = "SPIN_LISTEN_FDS";
+    pub const SPIN_LISTEN_FDNAMES: &str = "SPIN_LISTEN_FDNAMES";
+
+    /// The number and names of the sockets passed to this process, either by
+    /// systemd or by the Spin process that started it.
+    pub fn passed_fds() -> Option<(i32, String)> {
+        if let Ok(count) = std::env::var(SPIN_LISTEN_FDS) {
+            let names = std::env::var(SPIN_LISTEN_FDNAMES).unwrap_or_default();
+            return Some((count.parse().ok()?, names));
+        }
+        let for_this_process = std::env::var("LISTEN_PID")
+            .ok()
+            .and_then(|pid| pid.parse::<u32>().ok())
+            == Some(std::process::id());
+        if !for_this_process {
+            return None;
+        }
+        let count = std::env::var("LISTEN_FDS").ok()?.parse().ok()?;
+        let names = std::env::var("LISTEN_FDNAMES").unwrap_or_default();
+        Some((count, names))
+    }
+
+    /// Takes ownership of the sockets passed to this process.
+    fn passed_sockets() -> Vec<PassedSocket> {
+        let passed = passed_fds();
+        // The variables describe this process only, so mustn't be inherited.
+        for var in [
+            "LISTEN_PID",
+            "LISTEN_FDS",
+            "LISTEN_FDNAMES",
+            SPIN_LISTEN_FDS,
+            SPIN_LISTEN_FDNAMES,
+        ] {
+            std::env::remove_var(var);
+        }
+        let Some((count, names)) = passed else {
+            return vec![];
+        };
+
+        let mut names = names.split(':');
+        (0..count)
+            .filter_map(|offset| {
+                let name = names.next().unwrap_or_default().to_owned();
+                // SAFETY: systemd, or the Spin process that started this one,
+                // hands the descriptors from SD_LISTEN_FDS_START on to this
+                // process, and nothing else in Spin claims them.
+                let passed = unsafe { OwnedFd::from_raw_fd(SD_LISTEN_FDS_START + offset) };
+                // Passed descriptors aren't close-on-exec; duplicating them
+                // makes sure they don't leak into child processes.
+                match passed.try_clone() {
+                    Ok(fd) => Some(PassedSocket { name, fd }),
+                    Err(err) => {
+                        tracing::warn!("Ignoring socket {name:?} passed by systemd: {err}");
+                        None
+                    }
+                }
+            })
+            .collect()
+    }
+
+    pub fn send_notification(path: &OsStr, state: &str) -> std::io::Result<()> {
+        let socket = UnixDatagram::unbound()?;
+        match path.as_bytes().strip_prefix(b"@") {
+            #[cfg(target_os = "linux")]
+            Some(name) => {
+                use std::os::{linux::net::SocketAddrExt, unix::net::SocketAddr};
+                let addr = SocketAddr::from_abstract_name(name)?;
+                socket.send_to_addr(state.as_bytes(), &addr)?;
+            }
+            #[cfg(not(target_os = "linux"))]
+            Some(_) => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::Unsupported,
+                    "abstract notification sockets are only supported on Linux",
+                ))
+            }
+            None => {
+                socket.send_to(state.as_bytes(), path)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn watchdog_interval_is_half_the_timeout() {
+        let own_pid = std::process::id().to_string();
+        assert_eq!(
+            watchdog_interval(Some("3000000"), None),
+            Some(Duration::from_millis(1500))
+        );
+        assert_eq!(
+            watchdog_interval(Some("3000000"), Some(&own_pid)),
+            Some(Duration::from_millis(1500))
+        );
+        assert_eq!(watchdog_interval(Some("3000000"), Some("1")), None);
+        assert_eq!(watchdog_interval(Some("0"), None), None);
+        assert_eq!(watchdog_interval(None, None), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn notifications_are_sent_to_the_socket() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("notify.sock");
+        let manager = std::os::unix::net::UnixDatagram::bind(&path)?;
+
+        activation::send_notification(path.as_os_str(), "READY=1\nSTATUS=Serving")?;
+
+        let mut buf = [0; 64];
+        let len = manager.recv(&mut buf)?;
+        assert_eq!(&buf[..len], b"READY=1\nSTATUS=Serving");
+        Ok(())
+    }
+}
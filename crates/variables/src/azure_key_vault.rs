@@ -114,32 +114,38 @@ impl AzureKeyVaultProvider {
         vault_url: impl Into<String>,
         auth_options: AzureKeyVaultAuthOptions,
     ) -> anyhow::Result<Self> {
-        let http_client = azure_core::new_http_client();
-        let token_credential = match auth_options {
-            AzureKeyVaultAuthOptions::RuntimeConfigValues {
-                client_id,
-                client_secret,
-                tenant_id,
-                authority_host,
-            } => {
-                let credential = azure_identity::ClientSecretCredential::new(
-                    http_client,
-                    authority_host.into(),
-                    tenant_id,
-                    client_id,
-                    client_secret,
-                );
-                Arc::new(credential) as Arc<dyn TokenCredential>
-            }
-            AzureKeyVaultAuthOptions::Environmental => azure_identity::create_default_credential()?,
-        };
-
+        let token_credential = token_credential(auth_options)?;
         Ok(Self {
             secret_client: SecretClient::new(&vault_url.into(), token_credential)?,
         })
     }
 }
 
+/// Creates the credential to authenticate to Azure Key Vault with.
+pub(crate) fn token_credential(
+    auth_options: AzureKeyVaultAuthOptions,
+) -> anyhow::Result<Arc<dyn TokenCredential>> {
+    let http_client = azure_core::new_http_client();
+    Ok(match auth_options {
+        AzureKeyVaultAuthOptions::RuntimeConfigValues {
+            client_id,
+            client_secret,
+            tenant_id,
+            authority_host,
+        } => {
+            let credential = azure_identity::ClientSecretCredential::new(
+                http_client,
+                authority_host.into(),
+                tenant_id,
+                client_id,
+                client_secret,
+            );
+            Arc::new(credential) as Arc<dyn TokenCredential>
+        }
+        AzureKeyVaultAuthOptions::Environmental => azure_identity::create_default_credential()?,
+    })
+}
+
 #[async_trait]
 impl Provider for AzureKeyVaultProvider {
     #[instrument(name = "spin_variables.get_from_azure_key_vault", level = Level::DEBUG, skip(self), err(level = Level::INFO), fields(otel.kind = "client"))]
@@ -0,0 +1,424 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::Context as _;
+use aws_config::{BehaviorVersion, Region, SdkConfig};
+use aws_credential_types::Credentials;
+use aws_sdk_kms::{config::SharedCredentialsProvider, primitives::Blob};
+use azure_core::auth::TokenCredential;
+use base64::{
+    engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD},
+    Engine,
+};
+use ring::aead::{self, Aad, LessSafeKey, Nonce, UnboundKey, NONCE_LEN};
+use serde::Deserialize;
+use spin_expressions::{async_trait::async_trait, Decryptor};
+use spin_factors::anyhow;
+use tokio::sync::OnceCell;
+use tracing::{instrument, Level};
+
+use crate::{AzureAuthorityHost, AzureKeyVaultAuthOptions};
+
+/// A runtime configuration for decrypting variable values with envelope
+/// encryption, for one key management service.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type")]
+pub enum VariableDecryptorConfiguration {
+    /// Data keys wrapped by AWS KMS.
+    AwsKms(AwsKmsDecryptorConfig),
+    /// Data keys wrapped by Google Cloud KMS.
+    GcpKms(GcpKmsDecryptorConfig),
+    /// Data keys wrapped by an Azure Key Vault key.
+    AzureKeyVault(AzureKeyVaultDecryptorConfig),
+}
+
+impl VariableDecryptorConfiguration {
+    /// Returns the decryptor for the configuration.
+    pub fn into_decryptor(self) -> anyhow::Result<Box<dyn Decryptor>> {
+        let decryptor = match self {
+            Self::AwsKms(config) => EnvelopeDecryptor::new("aws_kms", AwsKms::new(config)),
+            Self::GcpKms(config) => EnvelopeDecryptor::new("gcp_kms", GcpKms::new(config)),
+            Self::AzureKeyVault(config) => {
+                EnvelopeDecryptor::new("azure_key_vault", AzureKeyVault::new(config)?)
+            }
+        };
+        Ok(Box::new(decryptor))
+    }
+}
+
+/// Runtime config for unwrapping data keys with AWS KMS.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AwsKmsDecryptorConfig {
+    pub region: String,
+    pub access_key: Option<String>,
+    pub secret_key: Option<String>,
+    pub token: Option<String>,
+}
+
+/// Runtime config for unwrapping data keys with Google Cloud KMS.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GcpKmsDecryptorConfig {
+    /// The key's resource name, e.g.
+    /// `projects/p/locations/global/keyRings/r/cryptoKeys/k`.
+    pub key_name: String,
+    /// The OAuth access token to authenticate with. If unset, a token is got
+    /// from the metadata server of the Google Cloud instance Spin runs on.
+    pub access_token: Option<String>,
+}
+
+/// Runtime config for unwrapping data keys with an Azure Key Vault key.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AzureKeyVaultDecryptorConfig {
+    /// The key's identifier, including its version, e.g.
+    /// `https://my-vault.vault.azure.net/keys/my-key/<version>`.
+    pub key_id: String,
+    pub client_id: Option<String>,
+    pub client_secret: Option<String>,
+    pub tenant_id: Option<String>,
+    pub authority_host: Option<AzureAuthorityHost>,
+}
+
+/// Unwraps the data keys of envelope-encrypted values with a key management
+/// service.
+#[async_trait]
+trait UnwrapKey: Send + Sync {
+    async fn unwrap_key(&self, wrapped_key: &[u8]) -> anyhow::Result<Vec<u8>>;
+}
+
+/// Decrypts variable values with envelope encryption, of the form
+/// `enc:<scheme>:<wrapped key>:<ciphertext>`.
+///
+/// `<wrapped key>` is an AES-256 data key wrapped by a key management service,
+/// and `<ciphertext>` is the value sealed with the data key by AES-256-GCM,
+/// preceded by its nonce; both are base64-encoded. As values are resolved
+/// often, what has been decrypted is remembered.
+pub struct EnvelopeDecryptor {
+    scheme: &'static str,
+    unwrap: Box<dyn UnwrapKey>,
+    decrypted: Mutex<HashMap<String, Arc<str>>>,
+}
+
+impl EnvelopeDecryptor {
+    fn new(scheme: &'static str, unwrap: impl UnwrapKey + 'static) -> Self {
+        Self {
+            scheme,
+            unwrap: Box::new(unwrap),
+            decrypted: Default::default(),
+        }
+    }
+}
+
+impl fmt::Debug for EnvelopeDecryptor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EnvelopeDecryptor")
+            .field("scheme", &self.scheme)
+            .finish_non_exhaustive()
+    }
+}
+
+#[async_trait]
+impl Decryptor for EnvelopeDecryptor {
+    fn scheme(&self) -> &str {
+        self.scheme
+    }
+
+    #[instrument(name = "spin_variables.decrypt", level = Level::DEBUG, skip_all, fields(scheme = self.scheme), err(level = Level::INFO))]
+    async fn decrypt(&self, ciphertext: &str) -> anyhow::Result<String> {
+        if let Some(plaintext) = self.decrypted.lock().unwrap().get(ciphertext) {
+            return Ok(plaintext.to_string());
+        }
+        let (wrapped_key, sealed) = ciphertext
+            .split_once(':')
+            .context("expected `<wrapped key>:<ciphertext>`")?;
+        let wrapped_key = STANDARD
+            .decode(wrapped_key)
+            .context("wrapped key is not base64")?;
+        let sealed = STANDARD
+            .decode(sealed)
+            .context("ciphertext is not base64")?;
+        let data_key = self.unwrap.unwrap_key(&wrapped_key).await?;
+        let plaintext = open(&data_key, sealed)?;
+        self.decrypted
+            .lock()
+            .unwrap()
+            .insert(ciphertext.to_string(), plaintext.as_str().into());
+        Ok(plaintext)
+    }
+}
+
+/// Opens `sealed`, the nonce followed by the AES-256-GCM ciphertext, with
+/// `data_key`.
+fn open(data_key: &[u8], sealed: Vec<u8>) -> anyhow::Result<String> {
+    let key = UnboundKey::new(&aead::AES_256_GCM, data_key)
+        .map_err(|_| anyhow::anyhow!("data key is not an AES-256 key"))?;
+    anyhow::ensure!(sealed.len() >= NONCE_LEN, "ciphertext is too short");
+    let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+    let nonce = Nonce::try_assume_unique_for_key(nonce).expect("nonce has the right length");
+    let mut plaintext = ciphertext.to_vec();
+    let len = LessSafeKey::new(key)
+        .open_in_place(nonce, Aad::empty(), &mut plaintext)
+        .map_err(|_| anyhow::anyhow!("ciphertext was not sealed with its data key"))?
+        .len();
+    plaintext.truncate(len);
+    String::from_utf8(plaintext).context("decrypted value is not UTF-8")
+}
+
+struct AwsKms {
+    config: AwsKmsDecryptorConfig,
+    client: OnceCell<aws_sdk_kms::Client>,
+}
+
+impl AwsKms {
+    fn new(config: AwsKmsDecryptorConfig) -> Self {
+        Self {
+            config,
+            client: OnceCell::new(),
+        }
+    }
+
+    async fn client(&self) -> anyhow::Result<&aws_sdk_kms::Client> {
+        let region = Region::new(self.config.region.clone());
+        let credentials = match (&self.config.access_key, &self.config.secret_key) {
+            (Some(access_key), Some(secret_key)) => Some(Credentials::new(
+                access_key,
+                secret_key,
+                self.config.token.clone(),
+                None,
+                "spin_variables_runtime_config",
+            )),
+            (None, None) => None,
+            _ => anyhow::bail!("`access_key` and `secret_key` must be set together"),
+        };
+        Ok(self
+            .client
+            .get_or_init(|| async {
+                let sdk_config = match credentials {
+                    Some(credentials) => SdkConfig::builder()
+                        .credentials_provider(SharedCredentialsProvider::new(credentials))
+                        .region(region)
+                        .behavior_version(BehaviorVersion::latest())
+                        .build(),
+                    None => {
+                        aws_config::defaults(BehaviorVersion::latest())
+                            .region(region)
+                            .load()
+                            .await
+                    }
+                };
+                aws_sdk_kms::Client::new(&sdk_config)
+            })
+            .await)
+    }
+}
+
+#[async_trait]
+impl UnwrapKey for AwsKms {
+    async fn unwrap_key(&self, wrapped_key: &[u8]) -> anyhow::Result<Vec<u8>> {
+        let output = self
+            .client()
+            .await?
+            .decrypt()
+            .ciphertext_blob(Blob::new(wrapped_key))
+            .send()
+            .await
+            .context("failed to unwrap data key with AWS KMS")?;
+        output
+            .plaintext
+            .map(Blob::into_inner)
+            .context("AWS KMS returned no data key")
+    }
+}
+
+struct GcpKms {
+    config: GcpKmsDecryptorConfig,
+    http: reqwest::Client,
+}
+
+impl GcpKms {
+    fn new(config: GcpKmsDecryptorConfig) -> Self {
+        Self {
+            config,
+            http: reqwest::Client::new(),
+        }
+    }
+
+    async fn access_token(&self) -> anyhow::Result<String> {
+        if let Some(token) = &self.config.access_token {
+            return Ok(token.clone());
+        }
+        #[derive(Deserialize)]
+        struct Token {
+            access_token: String,
+        }
+        let token: Token = self
+            .http
+            .get("http://metadata.google.internal/computeMetadata/v1/instance/service-accounts/default/token")
+            .header("Metadata-Flavor", "Google")
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .context("failed to get a Google Cloud access token from the metadata server")?
+            .json()
+            .await?;
+        Ok(token.access_token)
+    }
+}
+
+#[async_trait]
+impl UnwrapKey for GcpKms {
+    async fn unwrap_key(&self, wrapped_key: &[u8]) -> anyhow::Result<Vec<u8>> {
+        #[derive(Deserialize)]
+        struct DecryptResponse {
+            plaintext: String,
+        }
+        let url = format!(
+            "https://cloudkms.googleapis.com/v1/{}:decrypt",
+            self.config.key_name
+        );
+        let response: DecryptResponse = self
+            .http
+            .post(url)
+            .bearer_auth(self.access_token().await?)
+            .json(&serde_json::json!({ "ciphertext": STANDARD.encode(wrapped_key) }))
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .context("failed to unwrap data key with Google Cloud KMS")?
+            .json()
+            .await?;
+        STANDARD
+            .decode(response.plaintext)
+            .context("Google Cloud KMS returned an invalid data key")
+    }
+}
+
+struct AzureKeyVault {
+    key_id: String,
+    credential: Arc<dyn TokenCredential>,
+    http: reqwest::Client,
+}
+
+impl AzureKeyVault {
+    fn new(config: AzureKeyVaultDecryptorConfig) -> anyhow::Result<Self> {
+        let auth_options = match (config.client_id, config.tenant_id, config.client_secret) {
+            (Some(client_id), Some(tenant_id), Some(client_secret)) => {
+                AzureKeyVaultAuthOptions::RuntimeConfigValues {
+                    client_id,
+                    client_secret,
+                    tenant_id,
+                    authority_host: config.authority_host.unwrap_or_default(),
+                }
+            }
+            (None, None, None) => AzureKeyVaultAuthOptions::Environmental,
+            _ => anyhow::bail!("`client_id`, `client_secret` and `tenant_id` must be set together"),
+        };
+        Ok(Self {
+            key_id: config.key_id.trim_end_matches('/').to_string(),
+            credential: crate::azure_key_vault::token_credential(auth_options)?,
+            http: reqwest::Client::new(),
+        })
+    }
+}
+
+#[async_trait]
+impl UnwrapKey for AzureKeyVault {
+    async fn unwrap_key(&self, wrapped_key: &[u8]) -> anyhow::Result<Vec<u8>> {
+        #[derive(Deserialize)]
+        struct UnwrapResponse {
+            value: String,
+        }
+        let token = self
+            .credential
+            .get_token(&["https://vault.azure.net/.default"])
+            .await
+            .context("failed to authenticate to Azure Key Vault")?;
+        let response: UnwrapResponse = self
+            .http
+            .post(format!("{}/unwrapkey?api-version=7.4", self.key_id))
+            .bearer_auth(token.token.secret())
+            .json(&serde_json::json!({
+                "alg": "RSA-OAEP-256",
+                "value": URL_SAFE_NO_PAD.encode(wrapped_key),
+            }))
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .context("failed to unwrap data key with Azure Key Vault")?
+            .json()
+            .await?;
+        URL_SAFE_NO_PAD
+            .decode(response.value)
+            .context("Azure Key Vault returned an invalid data key")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use ring::rand::{SecureRandom, SystemRandom};
+
+    use super::*;
+
+    /// "Wraps" data keys by reversing them, counting unwraps.
+    #[derive(Default)]
+    struct Reverse(Arc<AtomicUsize>);
+
+    #[async_trait]
+    impl UnwrapKey for Reverse {
+        async fn unwrap_key(&self, wrapped_key: &[u8]) -> anyhow::Result<Vec<u8>> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            Ok(wrapped_key.iter().rev().copied().collect())
+        }
+    }
+
+    fn seal(data_key: &[u8], plaintext: &str) -> String {
+        let mut nonce = [0; NONCE_LEN];
+        SystemRandom::new().fill(&mut nonce).unwrap();
+        let key = LessSafeKey::new(UnboundKey::new(&aead::AES_256_GCM, data_key).unwrap());
+        let mut sealed = plaintext.as_bytes().to_vec();
+        key.seal_in_place_append_tag(
+            Nonce::assume_unique_for_key(nonce),
+            Aad::empty(),
+            &mut sealed,
+        )
+        .unwrap();
+        let wrapped_key = data_key.iter().rev().copied().collect::<Vec<_>>();
+        format!(
+            "{}:{}",
+            STANDARD.encode(wrapped_key),
+            STANDARD.encode([&nonce[..], &sealed].concat())
+        )
+    }
+
+    #[tokio::test]
+    async fn values_are_decrypted_once() {
+        let unwraps = Arc::new(AtomicUsize::new(0));
+        let decryptor = EnvelopeDecryptor::new("test", Reverse(unwraps.clone()));
+        let data_key = (0..32).collect::<Vec<u8>>();
+        let ciphertext = seal(&data_key, "secret");
+        for _ in 0..2 {
+            assert_eq!(decryptor.decrypt(&ciphertext).await.unwrap(), "secret");
+        }
+        assert_eq!(unwraps.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn tampered_values_are_rejected() {
+        let decryptor = EnvelopeDecryptor::new("test", Reverse::default());
+        let data_key = (0..32).collect::<Vec<u8>>();
+        let other_key = (1..33).collect::<Vec<u8>>();
+        let ciphertext = seal(&data_key, "secret");
+        let (_, sealed) = ciphertext.split_once(':').unwrap();
+        let wrapped_other_key = other_key.iter().rev().copied().collect::<Vec<_>>();
+        let swapped = format!("{}:{sealed}", STANDARD.encode(wrapped_other_key));
+        assert!(decryptor.decrypt(&swapped).await.is_err());
+        assert!(decryptor.decrypt("not-an-envelope").await.is_err());
+    }
+}
@@ -2,11 +2,13 @@
 
 mod azure_key_vault;
 mod env;
+mod envelope;
 mod statik;
 mod vault;
 
 pub use azure_key_vault::*;
 pub use env::*;
+pub use envelope::*;
 pub use statik::*;
 pub use vault::*;
 
@@ -18,6 +20,18 @@ use spin_factor_variables::runtime_config::RuntimeConfig;
 
 /// Resolves a runtime configuration for the variables factor from a TOML table.
 pub fn runtime_config_from_toml(table: &impl GetTomlValue) -> anyhow::Result<RuntimeConfig> {
+    let decryptors = match table.get("variables_decryptor") {
+        Some(array) => {
+            let decryptor_configs: Vec<VariableDecryptorConfiguration> =
+                array.clone().try_into()?;
+            decryptor_configs
+                .into_iter()
+                .map(VariableDecryptorConfiguration::into_decryptor)
+                .collect::<anyhow::Result<Vec<_>>>()?
+        }
+        None => vec![],
+    };
+
     // Always include the environment variable provider.
     let var_provider = vec![Box::<EnvVariablesProvider>::default() as _];
     let value = table
@@ -26,6 +40,7 @@ pub fn runtime_config_from_toml(table: &impl GetTomlValue) -> anyhow::Result<Run
     let Some(array) = value else {
         return Ok(RuntimeConfig {
             providers: var_provider,
+            decryptors,
         });
     };
 
@@ -35,7 +50,10 @@ pub fn runtime_config_from_toml(table: &impl GetTomlValue) -> anyhow::Result<Run
         .map(VariableProviderConfiguration::into_provider)
         .collect::<anyhow::Result<Vec<_>>>()?;
     providers.extend(var_provider);
-    Ok(RuntimeConfig { providers })
+    Ok(RuntimeConfig {
+        providers,
+        decryptors,
+    })
 }
 
 /// A runtime configuration used in the Spin CLI for one type of variable provider.
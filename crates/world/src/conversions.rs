@@ -405,6 +405,63 @@ mod postgres {
     }
 }
 
+mod sqlite {
+    use super::*;
+
+    impl From<spin::sqlite::sqlite::Value> for v2::sqlite::Value {
+        fn from(value: spin::sqlite::sqlite::Value) -> Self {
+            match value {
+                spin::sqlite::sqlite::Value::Integer(i) => v2::sqlite::Value::Integer(i),
+                spin::sqlite::sqlite::Value::Real(r) => v2::sqlite::Value::Real(r),
+                spin::sqlite::sqlite::Value::Text(t) => v2::sqlite::Value::Text(t),
+                spin::sqlite::sqlite::Value::Blob(b) => v2::sqlite::Value::Blob(b),
+                spin::sqlite::sqlite::Value::Null => v2::sqlite::Value::Null,
+            }
+        }
+    }
+
+    impl From<v2::sqlite::Value> for spin::sqlite::sqlite::Value {
+        fn from(value: v2::sqlite::Value) -> Self {
+            match value {
+                v2::sqlite::Value::Integer(i) => spin::sqlite::sqlite::Value::Integer(i),
+                v2::sqlite::Value::Real(r) => spin::sqlite::sqlite::Value::Real(r),
+                v2::sqlite::Value::Text(t) => spin::sqlite::sqlite::Value::Text(t),
+                v2::sqlite::Value::Blob(b) => spin::sqlite::sqlite::Value::Blob(b),
+                v2::sqlite::Value::Null => spin::sqlite::sqlite::Value::Null,
+            }
+        }
+    }
+
+    impl From<v2::sqlite::QueryResult> for spin::sqlite::sqlite::QueryResult {
+        fn from(value: v2::sqlite::QueryResult) -> Self {
+            spin::sqlite::sqlite::QueryResult {
+                columns: value.columns,
+                rows: value
+                    .rows
+                    .into_iter()
+                    .map(|r| spin::sqlite::sqlite::RowResult {
+                        values: r.values.into_iter().map(Into::into).collect(),
+                    })
+                    .collect(),
+            }
+        }
+    }
+
+    impl From<v2::sqlite::Error> for spin::sqlite::sqlite::Error {
+        fn from(value: v2::sqlite::Error) -> Self {
+            match value {
+                v2::sqlite::Error::NoSuchDatabase => spin::sqlite::sqlite::Error::NoSuchDatabase,
+                v2::sqlite::Error::AccessDenied => spin::sqlite::sqlite::Error::AccessDenied,
+                v2::sqlite::Error::InvalidConnection => {
+                    spin::sqlite::sqlite::Error::InvalidConnection
+                }
+                v2::sqlite::Error::DatabaseFull => spin::sqlite::sqlite::Error::DatabaseFull,
+                v2::sqlite::Error::Io(s) => spin::sqlite::sqlite::Error::Io(s),
+            }
+        }
+    }
+}
+
 mod mysql {
     use super::*;
     impl From<v2::mysql::RowSet> for v1::mysql::RowSet {
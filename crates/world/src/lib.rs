@@ -30,12 +30,29 @@ wasmtime::component::bindgen!({
         "fermyon:spin/sqlite@2.0.0/error" => v2::sqlite::Error,
         "fermyon:spin/sqlite/error" => v1::sqlite::Error,
         "fermyon:spin/variables@2.0.0/error" => v2::variables::Error,
+        "spin:actor/actor/error" => spin::actor::actor::Error,
+        "spin:cache/cache/error" => spin::cache::cache::Error,
+        "spin:crypto/crypto/error" => spin::crypto::crypto::Error,
+        "spin:experiments/experiments/error" => spin::experiments::experiments::Error,
+        "spin:graphql/graphql/error" => spin::graphql::graphql::Error,
+        "spin:grpc/grpc/error" => spin::grpc::grpc::Error,
+        "spin:http/early-hints/error" => spin::http::early_hints::Error,
+        "spin:key-value/watch/error" => spin::key_value::watch::Error,
+        "spin:lock/lock/error" => spin::lock::lock::Error,
+        "spin:mail/send/error" => spin::mail::send::Error,
+        "spin:multipart/multipart/error" => spin::multipart::multipart::Error,
         "spin:postgres/postgres/error" => spin::postgres::postgres::Error,
+        "spin:sftp/sftp/error" => spin::sftp::sftp::Error,
+        "spin:sqlite/sqlite/error" => spin::sqlite::sqlite::Error,
+        "spin:task/tasks/error" => spin::task::tasks::Error,
         "wasi:config/store@0.2.0-draft-2024-09-27/error" => wasi::config::store::Error,
         "wasi:keyvalue/store/error" => wasi::keyvalue::store::Error,
         "wasi:keyvalue/atomics/cas-error" => wasi::keyvalue::atomics::CasError,
     },
     trappable_imports: true,
+    with: {
+        "wasi:io": wasmtime_wasi::bindings::io,
+    },
 });
 
 pub use fermyon::spin as v1;
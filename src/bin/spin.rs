@@ -4,12 +4,18 @@ use lazy_static::lazy_static;
 use spin_cli::commands::external::predefined_externals;
 use spin_cli::commands::{
     build::BuildCommand,
+    bundle::BundleCommands,
+    cache::CacheCommands,
+    ci::CiCommands,
     cloud::{DeployCommand, LoginCommand},
+    data::DataCommands,
+    debug::DebugCommands,
     doctor::DoctorCommand,
     external::execute_external_subcommand,
     new::{AddCommand, NewCommand},
     plugins::PluginCommands,
     registry::RegistryCommands,
+    runtime_config::RuntimeConfigCommands,
     templates::TemplateCommands,
     up::UpCommand,
     watch::WatchCommand,
@@ -18,6 +24,7 @@ use spin_cli::{build_info::*, subprocess::ExitStatusError};
 use spin_runtime_factors::FactorsBuilder;
 use spin_trigger::cli::help::HelpArgsOnlyTrigger;
 use spin_trigger::cli::FactorsTriggerCommand;
+use spin_trigger::plugin::PluginTrigger;
 use spin_trigger_http::HttpTrigger;
 use spin_trigger_redis::RedisTrigger;
 
@@ -136,12 +143,25 @@ enum SpinApp {
     #[clap(alias = "w")]
     Watch(WatchCommand),
     Doctor(DoctorCommand),
+    #[clap(subcommand)]
+    Cache(CacheCommands),
+    #[clap(subcommand)]
+    Ci(CiCommands),
+    #[clap(subcommand)]
+    Bundle(BundleCommands),
+    #[clap(subcommand)]
+    Data(DataCommands),
+    #[clap(subcommand)]
+    Debug(DebugCommands),
+    #[clap(subcommand)]
+    RuntimeConfig(RuntimeConfigCommands),
 }
 
 #[derive(Subcommand)]
 enum TriggerCommands {
     Http(FactorsTriggerCommand<HttpTrigger, FactorsBuilder>),
     Redis(FactorsTriggerCommand<RedisTrigger, FactorsBuilder>),
+    Plugin(FactorsTriggerCommand<PluginTrigger, FactorsBuilder>),
     #[clap(name = spin_cli::HELP_ARGS_ONLY_TRIGGER_TYPE, hide = true)]
     HelpArgsOnly(FactorsTriggerCommand<HelpArgsOnlyTrigger, FactorsBuilder>),
 }
@@ -160,11 +180,18 @@ impl SpinApp {
             Self::Build(cmd) => cmd.run().await,
             Self::Trigger(TriggerCommands::Http(cmd)) => cmd.run().await,
             Self::Trigger(TriggerCommands::Redis(cmd)) => cmd.run().await,
+            Self::Trigger(TriggerCommands::Plugin(cmd)) => cmd.run().await,
             Self::Trigger(TriggerCommands::HelpArgsOnly(cmd)) => cmd.run().await,
             Self::Plugins(cmd) => cmd.run().await,
             Self::External(cmd) => execute_external_subcommand(cmd, app).await,
             Self::Watch(cmd) => cmd.run().await,
             Self::Doctor(cmd) => cmd.run().await,
+            Self::Cache(cmd) => cmd.run().await,
+            Self::Ci(cmd) => cmd.run().await,
+            Self::Bundle(cmd) => cmd.run().await,
+            Self::Data(cmd) => cmd.run().await,
+            Self::Debug(cmd) => cmd.run().await,
+            Self::RuntimeConfig(cmd) => cmd.run().await,
         }
     }
 }
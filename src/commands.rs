@@ -2,8 +2,18 @@
 
 /// Commands for building Spin applications.
 pub mod build;
+/// Commands for exporting portable application bundles.
+pub mod bundle;
+/// Commands for managing Spin's cache.
+pub mod cache;
+/// Commands for generating CI pipelines.
+pub mod ci;
 /// Commands for publishing applications to the Fermyon Platform.
 pub mod cloud;
+/// Commands for moving data between key-value stores and SQLite databases.
+pub mod data;
+/// Commands for debugging Spin applications.
+pub mod debug;
 /// Command for running the Spin Doctor.
 pub mod doctor;
 /// Commands for external subcommands (i.e. plugins)
@@ -14,6 +24,8 @@ pub mod new;
 pub mod plugins;
 /// Commands for working with OCI registries.
 pub mod registry;
+/// Commands for working with runtime config files.
+pub mod runtime_config;
 /// Commands for working with templates.
 pub mod templates;
 /// Commands for starting the runtime.
@@ -29,6 +29,31 @@ pub struct BuildCommand {
     #[clap(short = 'c', long, multiple = true)]
     pub component_id: Vec<String>,
 
+    /// Build components even if their inputs haven't changed since they were
+    /// last built.
+    #[clap(long = "force")]
+    pub force: bool,
+
+    /// The most components to build at once. Defaults to 1. Components that
+    /// depend on others are built after them.
+    #[clap(short = 'j', long = "jobs")]
+    pub jobs: Option<usize>,
+
+    /// A cache of component builds to share with other machines. This may be
+    /// an http(s):// URL, or an oci:// registry repository.
+    #[clap(long = "cache", env = "SPIN_BUILD_CACHE")]
+    pub cache: Option<String>,
+
+    /// Fetch component builds from the cache, but don't upload to it.
+    #[clap(long = "cache-read-only", requires = "cache")]
+    pub cache_read_only: bool,
+
+    /// Patch the manifest with the overlay for the given environment, e.g.
+    /// spin.staging.toml next to spin.toml for `staging`, before building.
+    /// With --up, the app is also run in that environment.
+    #[clap(long = "environment", value_name = "NAME")]
+    pub environment: Option<String>,
+
     /// Run the application after building.
     #[clap(name = BUILD_UP_OPT, short = 'u', long = "up")]
     pub up: bool,
@@ -43,7 +68,14 @@ impl BuildCommand {
             spin_common::paths::find_manifest_file_path(self.app_source.as_ref())?;
         notify_if_nondefault_rel(&manifest_file, distance);
 
-        spin_build::build(&manifest_file, &self.component_id).await?;
+        let options = spin_build::BuildOptions {
+            force: self.force,
+            remote_cache: self.cache,
+            remote_cache_read_only: self.cache_read_only,
+            jobs: self.jobs,
+            environment: self.environment.clone(),
+        };
+        spin_build::build_with_options(&manifest_file, &self.component_id, &options).await?;
 
         if self.up {
             let mut cmd = UpCommand::parse_from(
@@ -54,6 +86,9 @@ impl BuildCommand {
                 .chain(self.up_args),
             );
             cmd.file_source = Some(manifest_file);
+            if self.environment.is_some() {
+                cmd.environment = self.environment;
+            }
             cmd.run().await
         } else {
             Ok(())
@@ -0,0 +1,97 @@
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use clap::{Parser, Subcommand};
+use spin_common::ui::quoted_path;
+use spin_loader::FilesMountStrategy;
+
+use crate::{directory_rels::notify_if_nondefault_rel, opts::*};
+
+/// Commands for working with portable application bundles.
+#[derive(Subcommand, Debug)]
+pub enum BundleCommands {
+    /// Export an application to a bundle that can be run with `spin up --from-bundle`,
+    /// for environments without access to a registry.
+    Export(Export),
+}
+
+impl BundleCommands {
+    pub async fn run(self) -> Result<()> {
+        match self {
+            BundleCommands::Export(cmd) => cmd.run().await,
+        }
+    }
+}
+
+#[derive(Parser, Debug)]
+pub struct Export {
+    /// The application to export. This may be a manifest (spin.toml) file, or a
+    /// directory containing a spin.toml file.
+    /// If omitted, it defaults to "spin.toml".
+    #[clap(
+        name = APP_MANIFEST_FILE_OPT,
+        short = 'f',
+        long = "from",
+        alias = "file",
+    )]
+    pub app_source: Option<PathBuf>,
+
+    /// The bundle file to write. Defaults to `<app name>.spinbundle` in the
+    /// current directory.
+    #[clap(short = 'o', long = "output")]
+    pub output: Option<PathBuf>,
+
+    /// A runtime config file to include in the bundle. It is used when the
+    /// bundle is run, unless another runtime config file is given.
+    #[clap(long = "runtime-config-file")]
+    pub runtime_config_file: Option<PathBuf>,
+
+    /// Specifies to perform `spin build` before exporting the application.
+    #[clap(long, takes_value = false, env = ALWAYS_BUILD_ENV)]
+    pub build: bool,
+
+    /// Cache directory for downloaded components and assets.
+    #[clap(long)]
+    pub cache_dir: Option<PathBuf>,
+}
+
+impl Export {
+    pub async fn run(self) -> Result<()> {
+        let (app_file, distance) =
+            spin_common::paths::find_manifest_file_path(self.app_source.as_ref())?;
+        notify_if_nondefault_rel(&app_file, distance);
+
+        if self.build {
+            spin_build::build(&app_file, &[]).await?;
+        }
+
+        let working_dir = tempfile::tempdir()?;
+        let locked_app = spin_loader::from_file(
+            &app_file,
+            FilesMountStrategy::Copy(working_dir.path().into()),
+            self.cache_dir.clone(),
+        )
+        .await
+        .with_context(|| format!("Failed to load manifest from {}", quoted_path(&app_file)))?;
+
+        let output = match self.output {
+            Some(output) => output,
+            None => {
+                let name = locked_app
+                    .get_metadata(spin_locked_app::APP_NAME_KEY)?
+                    .unwrap_or_else(|| "app".to_owned());
+                PathBuf::from(format!("{name}.spinbundle"))
+            }
+        };
+
+        let runtime_config_file = self.runtime_config_file.clone();
+        let bundle_path = output.clone();
+        tokio::task::spawn_blocking(move || {
+            spin_loader::bundle::export(&locked_app, runtime_config_file.as_deref(), &bundle_path)
+        })
+        .await??;
+
+        println!("Exported application to {}", quoted_path(&output));
+        Ok(())
+    }
+}
@@ -0,0 +1,105 @@
+use std::{path::PathBuf, time::Duration};
+
+use anyhow::{bail, Context, Result};
+use clap::{Parser, Subcommand};
+use indicatif::HumanBytes;
+use spin_loader::cache::{Cache, GcPolicy};
+
+/// Commands for managing Spin's cache.
+#[derive(Subcommand, Debug)]
+pub enum CacheCommands {
+    /// Remove applications pulled from registries, and the components and
+    /// files they use, from the cache. Applications which are running are
+    /// never removed.
+    Prune(PruneCommand),
+}
+
+impl CacheCommands {
+    pub async fn run(self) -> Result<()> {
+        match self {
+            CacheCommands::Prune(cmd) => cmd.run().await,
+        }
+    }
+}
+
+#[derive(Parser, Debug)]
+pub struct PruneCommand {
+    /// Remove the least recently used applications until the cache is no
+    /// bigger than this, e.g. `500M` or `2G`.
+    #[clap(long, value_parser = parse_size)]
+    pub max_size: Option<u64>,
+
+    /// Remove applications that haven't been used for this long, e.g. `30d`.
+    #[clap(long, value_parser = humantime::parse_duration)]
+    pub max_age: Option<Duration>,
+
+    /// Print what would be removed, without removing it.
+    #[clap(long)]
+    pub dry_run: bool,
+
+    /// Cache directory for downloaded components and assets.
+    #[clap(long)]
+    pub cache_dir: Option<PathBuf>,
+}
+
+impl PruneCommand {
+    pub async fn run(self) -> Result<()> {
+        let cache = Cache::new(self.cache_dir).await?;
+        let policy = GcPolicy {
+            max_size: self.max_size,
+            max_age: self.max_age,
+            dry_run: self.dry_run,
+        };
+        let report = cache.gc(&policy).await.context("failed to prune cache")?;
+
+        let verb = if self.dry_run {
+            "Would remove"
+        } else {
+            "Removed"
+        };
+        for app in &report.removed_apps {
+            println!("{verb} {app}");
+        }
+        for app in &report.in_use_apps {
+            println!("Kept {app}, which is in use");
+        }
+        println!(
+            "{verb} {} application(s) and {} file(s), freeing {}. The cache now uses {}.",
+            report.removed_apps.len(),
+            report.removed_files,
+            HumanBytes(report.freed_bytes),
+            HumanBytes(report.remaining_bytes),
+        );
+        Ok(())
+    }
+}
+
+/// Parses a size in bytes, optionally with a `K`, `M` or `G` binary suffix.
+fn parse_size(text: &str) -> Result<u64> {
+    let text = text.trim();
+    let (number, multiplier) = match text.char_indices().last() {
+        Some((i, 'k' | 'K')) => (&text[..i], 1 << 10),
+        Some((i, 'm' | 'M')) => (&text[..i], 1 << 20),
+        Some((i, 'g' | 'G')) => (&text[..i], 1 << 30),
+        _ => (text, 1),
+    };
+    let Ok(number) = number.trim().parse::<u64>() else {
+        bail!("invalid size {text:?}: expected a number of bytes, e.g. 500M");
+    };
+    number
+        .checked_mul(multiplier)
+        .with_context(|| format!("size {text:?} is too large"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sizes_are_parsed() {
+        assert_eq!(0, parse_size("0").unwrap());
+        assert_eq!(500 * 1024 * 1024, parse_size("500M").unwrap());
+        assert_eq!(2 << 30, parse_size("2g").unwrap());
+        assert!(parse_size("big").is_err());
+    }
+}
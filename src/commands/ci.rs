@@ -0,0 +1,290 @@
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+use clap::{Parser, Subcommand};
+use serde::Serialize;
+use spin_manifest::schema::v2::AppManifest;
+
+use crate::{directory_rels::notify_if_nondefault_rel, opts::APP_MANIFEST_FILE_OPT};
+
+/// The built-in GitHub Actions workflow template.
+const GITHUB_ACTIONS_TEMPLATE: &str = include_str!("ci/github-actions.yml");
+
+/// The default location of the generated workflow, relative to the application directory.
+const DEFAULT_WORKFLOW_PATH: &str = ".github/workflows/spin.yml";
+
+/// Commands for generating continuous integration pipelines.
+#[derive(Subcommand, Debug)]
+pub enum CiCommands {
+    /// Generate a CI pipeline for the application.
+    Init(InitCommand),
+}
+
+impl CiCommands {
+    pub async fn run(self) -> Result<()> {
+        match self {
+            CiCommands::Init(cmd) => cmd.run().await,
+        }
+    }
+}
+
+/// Generate a GitHub Actions workflow that builds, tests and publishes the application.
+#[derive(Parser, Debug)]
+pub struct InitCommand {
+    /// The application to generate a pipeline for. This may be a manifest (spin.toml) file, or a
+    /// directory containing a spin.toml file.
+    /// If omitted, it defaults to "spin.toml".
+    #[clap(
+        name = APP_MANIFEST_FILE_OPT,
+        short = 'f',
+        long = "from",
+        alias = "file",
+    )]
+    pub app_source: Option<PathBuf>,
+
+    /// The registry reference to push the application to, e.g. ghcr.io/my-org/my-app:latest.
+    /// If omitted, the pipeline does not publish the application.
+    #[clap(long = "registry")]
+    pub registry_reference: Option<String>,
+
+    /// Add a step that deploys the application after it has been built and tested.
+    #[clap(long = "deploy")]
+    pub deploy: bool,
+
+    /// A Liquid template to render instead of the built-in GitHub Actions workflow.
+    /// This allows platform teams to maintain their own pipeline conventions.
+    #[clap(long = "template")]
+    pub template: Option<PathBuf>,
+
+    /// The file to write the pipeline to. Relative paths are resolved against the
+    /// application directory.
+    #[clap(short = 'o', long = "output", default_value = DEFAULT_WORKFLOW_PATH)]
+    pub output: PathBuf,
+
+    /// Overwrite the output file if it already exists.
+    #[clap(long = "force")]
+    pub force: bool,
+}
+
+impl InitCommand {
+    pub async fn run(self) -> Result<()> {
+        let (manifest_file, distance) =
+            spin_common::paths::find_manifest_file_path(self.app_source.as_ref())?;
+        notify_if_nondefault_rel(&manifest_file, distance);
+
+        let manifest = spin_manifest::manifest_from_file(&manifest_file)
+            .with_context(|| format!("Failed to read manifest {}", manifest_file.display()))?;
+        let app_dir = spin_common::paths::parent_dir(&manifest_file)?;
+
+        let template_source = match &self.template {
+            Some(path) => tokio::fs::read_to_string(path)
+                .await
+                .with_context(|| format!("Failed to read template {}", path.display()))?,
+            None => GITHUB_ACTIONS_TEMPLATE.to_owned(),
+        };
+
+        let manifest_path = manifest_file
+            .strip_prefix(&app_dir)
+            .unwrap_or(&manifest_file)
+            .to_owned();
+        let context = PipelineContext::new(
+            &manifest,
+            &manifest_path,
+            self.registry_reference.as_deref(),
+            self.deploy,
+        );
+        let pipeline = render(&template_source, &context)?;
+
+        let output = app_dir.join(&self.output);
+        if output.exists() && !self.force {
+            bail!(
+                "{} already exists. Use --force to overwrite it.",
+                output.display()
+            );
+        }
+        if let Some(dir) = output.parent() {
+            tokio::fs::create_dir_all(dir)
+                .await
+                .with_context(|| format!("Failed to create directory {}", dir.display()))?;
+        }
+        tokio::fs::write(&output, pipeline)
+            .await
+            .with_context(|| format!("Failed to write {}", output.display()))?;
+
+        println!("Wrote CI pipeline to {}", output.display());
+        if context.components.is_empty() {
+            terminal::warn!("No components have a build command; the pipeline has no build job.");
+        }
+        Ok(())
+    }
+}
+
+/// The values available to pipeline templates.
+#[derive(Debug, Serialize)]
+struct PipelineContext {
+    app_name: String,
+    manifest_path: String,
+    /// The components that have a build command, with their detected language.
+    components: Vec<ComponentContext>,
+    /// The distinct languages across all components.
+    languages: Vec<String>,
+    has_tests: bool,
+    /// The registry host, e.g. `ghcr.io`. Absent if not publishing.
+    registry: Option<String>,
+    /// The full registry reference. Absent if not publishing.
+    registry_reference: Option<String>,
+    deploy: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct ComponentContext {
+    id: String,
+    language: String,
+}
+
+impl PipelineContext {
+    fn new(
+        manifest: &AppManifest,
+        manifest_path: &Path,
+        registry_reference: Option<&str>,
+        deploy: bool,
+    ) -> Self {
+        let components: Vec<_> = manifest
+            .components
+            .iter()
+            .filter_map(|(id, component)| {
+                let build = component.build.as_ref()?;
+                Some(ComponentContext {
+                    id: id.to_string(),
+                    language: detect_language(build.commands()).to_owned(),
+                })
+            })
+            .collect();
+        let languages = components
+            .iter()
+            .map(|c| c.language.clone())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect();
+        let has_tests = manifest.application.tool.contains_key("spin-test")
+            || manifest
+                .components
+                .values()
+                .any(|c| c.tool.contains_key("spin-test"));
+        let registry = registry_reference
+            .map(|reference| {
+                reference
+                    .split_once('/')
+                    .map_or(reference, |(host, _)| host)
+            })
+            .map(str::to_owned);
+
+        Self {
+            app_name: manifest.application.name.clone(),
+            manifest_path: manifest_path.to_string_lossy().replace('\\', "/"),
+            components,
+            languages,
+            has_tests,
+            registry,
+            registry_reference: registry_reference.map(str::to_owned),
+            deploy,
+        }
+    }
+}
+
+/// Guesses the language of a component from its build commands, so the pipeline
+/// can install the right toolchain.
+fn detect_language<'a>(commands: impl Iterator<Item = &'a String>) -> &'static str {
+    for command in commands {
+        let program = command.split_whitespace().next().unwrap_or_default();
+        let language = match program {
+            "cargo" => "rust",
+            "tinygo" | "go" => "go",
+            "npm" | "npx" | "yarn" | "pnpm" | "node" => "javascript",
+            "componentize-py" | "python" | "python3" | "pip" => "python",
+            "dotnet" => "dotnet",
+            _ => continue,
+        };
+        return language;
+    }
+    "other"
+}
+
+fn render(template: &str, context: &PipelineContext) -> Result<String> {
+    let parser = liquid::ParserBuilder::with_stdlib()
+        .build()
+        .context("Failed to create template parser")?;
+    let template = parser
+        .parse(template)
+        .context("Failed to parse pipeline template")?;
+    let globals = liquid::to_object(context).context("Failed to build template context")?;
+    template
+        .render(&globals)
+        .context("Failed to render pipeline template")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context_for(manifest: &str, registry: Option<&str>, deploy: bool) -> PipelineContext {
+        let manifest = spin_manifest::manifest_from_str(manifest).unwrap();
+        PipelineContext::new(&manifest, Path::new("spin.toml"), registry, deploy)
+    }
+
+    const MANIFEST: &str = r#"
+        spin_manifest_version = 2
+        [application]
+        name = "ci-app"
+        [[trigger.http]]
+        route = "/..."
+        component = "api"
+        [component.api]
+        source = "api.wasm"
+        build = { command = "cargo build --target wasm32-wasip1 --release" }
+        [component.api.tool.spin-test]
+        source = "tests.wasm"
+        [component.ui]
+        source = "ui.wasm"
+        build = { command = ["npm install", "npm run build"] }
+        [component.static]
+        source = "static.wasm"
+    "#;
+
+    #[test]
+    fn detects_component_languages() {
+        let context = context_for(MANIFEST, None, false);
+        let languages: Vec<_> = context
+            .components
+            .iter()
+            .map(|c| (c.id.as_str(), c.language.as_str()))
+            .collect();
+        assert_eq!(vec![("api", "rust"), ("ui", "javascript")], languages);
+        assert_eq!(vec!["javascript", "rust"], context.languages);
+        assert!(context.has_tests);
+    }
+
+    #[test]
+    fn built_in_template_includes_requested_jobs() {
+        let context = context_for(MANIFEST, Some("ghcr.io/fermyon/ci-app:latest"), false);
+        let pipeline = render(GITHUB_ACTIONS_TEMPLATE, &context).unwrap();
+        assert!(pipeline.contains("component: api"));
+        assert!(pipeline.contains("spin test -f spin.toml"));
+        assert!(pipeline.contains("spin registry login ghcr.io"));
+        assert!(pipeline.contains("--build ghcr.io/fermyon/ci-app:latest"));
+        assert!(pipeline.contains("--provenance provenance.json"));
+        assert!(pipeline.contains("${{ github.sha }}"));
+        assert!(!pipeline.contains("spin deploy"));
+    }
+
+    #[test]
+    fn built_in_template_omits_publish_without_registry() {
+        let context = context_for(MANIFEST, None, true);
+        let pipeline = render(GITHUB_ACTIONS_TEMPLATE, &context).unwrap();
+        assert!(!pipeline.contains("spin registry push"));
+        assert!(pipeline.contains("spin deploy -f spin.toml"));
+    }
+}
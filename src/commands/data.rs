@@ -0,0 +1,210 @@
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use clap::{Parser, Subcommand};
+use spin_common::ui::quoted_path;
+use spin_factor_key_value::Store;
+use spin_factor_sqlite::Connection;
+use spin_runtime_config::ResolvedRuntimeConfig;
+use spin_runtime_factors::TriggerFactorsRuntimeConfig;
+use spin_trigger::cli::UserProvidedPath;
+
+use super::debug::StateOptions;
+
+/// Commands for moving application data between key-value stores or SQLite
+/// databases, e.g. when changing the backend a store is configured with.
+#[derive(Subcommand, Debug)]
+pub enum DataCommands {
+    /// Dump a key-value store or SQLite database to a portable file: JSON
+    /// Lines for key-value stores, and a SQL script for databases.
+    Export(ExportData),
+    /// Load a file written by `spin data export` into a key-value store or
+    /// SQLite database, which may have a different backend from the one the
+    /// file was exported from.
+    Import(ImportData),
+}
+
+impl DataCommands {
+    pub async fn run(self) -> Result<()> {
+        match self {
+            DataCommands::Export(cmd) => cmd.run().await,
+            DataCommands::Import(cmd) => cmd.run().await,
+        }
+    }
+}
+
+/// The store or database to export or import, as configured by the
+/// application's runtime config.
+#[derive(Parser, Debug)]
+pub struct DataTarget {
+    /// The label of the key-value store to use.
+    #[clap(
+        name = "key-value",
+        long = "key-value",
+        conflicts_with = "sqlite",
+        required_unless_present = "sqlite"
+    )]
+    pub key_value: Option<String>,
+
+    /// The label of the SQLite database to use.
+    #[clap(name = "sqlite", long = "sqlite")]
+    pub sqlite: Option<String>,
+
+    #[clap(flatten)]
+    pub options: StateOptions,
+}
+
+enum Target {
+    KeyValue(String, std::sync::Arc<dyn Store>),
+    Sqlite(String, Box<dyn Connection>),
+}
+
+impl DataTarget {
+    async fn open(&self) -> Result<Target> {
+        let state = self.options.resolve()?;
+        let runtime_config = ResolvedRuntimeConfig::<TriggerFactorsRuntimeConfig>::from_file(
+            self.options.runtime_config_file.as_deref(),
+            state.manifest_file.parent().map(Path::to_path_buf),
+            UserProvidedPath::Provided(state.state_dir),
+            UserProvidedPath::Unset,
+        )?
+        .runtime_config;
+
+        if let Some(label) = &self.key_value {
+            let store = runtime_config
+                .key_value
+                .and_then(|config| config.get_store_manager(label))
+                .with_context(|| format!("no key-value store '{label}' is configured"))?
+                .get(label)
+                .await
+                .with_context(|| format!("failed to open key-value store '{label}'"))?;
+            return Ok(Target::KeyValue(label.clone(), store));
+        }
+
+        let label = self
+            .sqlite
+            .as_ref()
+            .context("either `--key-value` or `--sqlite` is required")?;
+        let connection = runtime_config
+            .sqlite
+            .and_then(|config| config.connection_creators.get(label).cloned())
+            .with_context(|| format!("no SQLite database '{label}' is configured"))?
+            .create_connection(label)
+            .await
+            .with_context(|| format!("failed to connect to SQLite database '{label}'"))?;
+        Ok(Target::Sqlite(label.clone(), connection))
+    }
+}
+
+#[derive(Parser, Debug)]
+pub struct ExportData {
+    #[clap(flatten)]
+    pub target: DataTarget,
+
+    /// The file to write. Defaults to standard output.
+    #[clap(short = 'o', long = "output")]
+    pub output: Option<PathBuf>,
+}
+
+impl ExportData {
+    pub async fn run(self) -> Result<()> {
+        let mut output: Box<dyn Write + Send> = match &self.output {
+            Some(path) => {
+                Box::new(BufWriter::new(File::create(path).with_context(|| {
+                    format!("failed to create {}", quoted_path(path))
+                })?))
+            }
+            None => Box::new(std::io::stdout()),
+        };
+
+        let summary = match self.target.open().await? {
+            Target::KeyValue(label, store) => {
+                let count = spin_factor_key_value::dump::dump(&*store, &mut output)
+                    .await
+                    .with_context(|| format!("failed to export key-value store '{label}'"))?;
+                format!("Exported {count} key(s) from key-value store '{label}'")
+            }
+            Target::Sqlite(label, connection) => {
+                spin_factor_sqlite::dump::dump(&*connection, &mut output)
+                    .await
+                    .with_context(|| format!("failed to export SQLite database '{label}'"))?;
+                format!("Exported SQLite database '{label}'")
+            }
+        };
+
+        // Keep standard output clean for the data.
+        if let Some(path) = &self.output {
+            println!("{summary} to {}", quoted_path(path));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Parser, Debug)]
+pub struct ImportData {
+    #[clap(flatten)]
+    pub target: DataTarget,
+
+    /// The file written by `spin data export` to import.
+    pub input: PathBuf,
+
+    /// Replace the values of keys which already exist in the key-value store.
+    /// By default they are kept.
+    #[clap(long, requires = "key-value")]
+    pub overwrite: bool,
+}
+
+impl ImportData {
+    pub async fn run(self) -> Result<()> {
+        let input = File::open(&self.input)
+            .with_context(|| format!("failed to open {}", quoted_path(&self.input)))?;
+
+        match self.target.open().await? {
+            Target::KeyValue(label, store) => {
+                let report = spin_factor_key_value::dump::load(
+                    &*store,
+                    BufReader::new(input),
+                    self.overwrite,
+                )
+                .await
+                .with_context(|| {
+                    format!(
+                        "failed to import {} into key-value store '{label}'",
+                        quoted_path(&self.input)
+                    )
+                })?;
+                println!(
+                    "Imported {} key(s) into key-value store '{label}'",
+                    report.loaded
+                );
+                if report.skipped > 0 {
+                    println!(
+                        "Kept the existing values of {} key(s); pass `--overwrite` to replace them",
+                        report.skipped
+                    );
+                }
+            }
+            Target::Sqlite(label, connection) => {
+                let script = std::io::read_to_string(input)
+                    .with_context(|| format!("failed to read {}", quoted_path(&self.input)))?;
+                spin_factor_sqlite::dump::load(&*connection, &script)
+                    .await
+                    .with_context(|| {
+                        format!(
+                            "failed to import {} into SQLite database '{label}'",
+                            quoted_path(&self.input)
+                        )
+                    })?;
+                println!(
+                    "Imported {} into SQLite database '{label}'",
+                    quoted_path(&self.input)
+                );
+            }
+        }
+        Ok(())
+    }
+}
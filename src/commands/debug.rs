@@ -0,0 +1,488 @@
+use std::{
+    collections::BTreeMap,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use clap::{Parser, Subcommand};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use serde::{Deserialize, Serialize};
+use spin_common::ui::quoted_path;
+use spin_expressions::{Key, ProviderResolver};
+use spin_runtime_config::{TomlResolver, DEFAULT_SPIN_STORE_FILENAME, DEFAULT_SQLITE_DB_FILENAME};
+use spin_trigger::cli::UserProvidedPath;
+
+use crate::{directory_rels::notify_if_nondefault_rel, opts::*};
+
+/// The path of the snapshot manifest within a snapshot archive.
+const SNAPSHOT_MANIFEST: &str = "snapshot.json";
+
+/// The snapshot archive format version written by this version of Spin.
+const SNAPSHOT_VERSION: u32 = 1;
+
+/// The component ID used to resolve application variables for a snapshot.
+const SNAPSHOT_COMPONENT: &str = "snapshot";
+
+/// The file the variables of an imported snapshot are written to, within the
+/// state directory.
+const IMPORTED_VARIABLES_FILE: &str = "snapshot-variables.toml";
+
+/// Commands for debugging Spin applications.
+#[derive(Subcommand, Debug)]
+pub enum DebugCommands {
+    /// Export or import a snapshot of an application's local state.
+    #[clap(subcommand)]
+    State(StateCommands),
+}
+
+impl DebugCommands {
+    pub async fn run(self) -> Result<()> {
+        match self {
+            DebugCommands::State(cmd) => cmd.run().await,
+        }
+    }
+}
+
+/// Commands for working with snapshots of an application's local state.
+#[derive(Subcommand, Debug)]
+pub enum StateCommands {
+    /// Bundle the application's default key-value store, default SQLite
+    /// database, and resolved variables into a snapshot archive. Secret
+    /// variable values are not included.
+    Export(ExportState),
+    /// Restore the stores and variables from a snapshot archive.
+    Import(ImportState),
+}
+
+impl StateCommands {
+    pub async fn run(self) -> Result<()> {
+        match self {
+            StateCommands::Export(cmd) => cmd.run().await,
+            StateCommands::Import(cmd) => cmd.run().await,
+        }
+    }
+}
+
+/// Options identifying an application's local state.
+#[derive(Parser, Debug)]
+pub struct StateOptions {
+    /// The application whose state to use. This may be a manifest (spin.toml)
+    /// file, or a directory containing a spin.toml file.
+    /// If omitted, it defaults to "spin.toml".
+    #[clap(
+        name = APP_MANIFEST_FILE_OPT,
+        short = 'f',
+        long = "from",
+        alias = "file",
+    )]
+    pub app_source: Option<PathBuf>,
+
+    /// The runtime config file the application is run with. It is used to
+    /// find the state directory and to resolve variables.
+    #[clap(long = "runtime-config-file")]
+    pub runtime_config_file: Option<PathBuf>,
+
+    /// The application state directory. Defaults to `.spin/` next to the
+    /// manifest, as for `spin up`.
+    #[clap(long = "state-dir")]
+    pub state_dir: Option<PathBuf>,
+}
+
+/// The application state that a [`StateOptions`] identifies.
+pub(crate) struct AppState {
+    pub manifest_file: PathBuf,
+    pub runtime_config: toml::Table,
+    pub state_dir: PathBuf,
+}
+
+impl StateOptions {
+    pub(crate) fn resolve(&self) -> Result<AppState> {
+        let (manifest_file, distance) =
+            spin_common::paths::find_manifest_file_path(self.app_source.as_ref())?;
+        notify_if_nondefault_rel(&manifest_file, distance);
+
+        let runtime_config = match &self.runtime_config_file {
+            Some(path) => spin_runtime_config::read_runtime_config_file(path)?,
+            None => toml::Table::new(),
+        };
+
+        let state_dir = match &self.state_dir {
+            Some(dir) => UserProvidedPath::Provided(dir.clone()),
+            None => UserProvidedPath::Default,
+        };
+        let app_dir = manifest_file.parent().map(Path::to_path_buf);
+        let state_dir = TomlResolver::new(
+            &runtime_config,
+            app_dir,
+            state_dir,
+            UserProvidedPath::Default,
+        )
+        .state_dir()?
+        .context("the application has no state directory")?;
+
+        Ok(AppState {
+            manifest_file,
+            runtime_config,
+            state_dir,
+        })
+    }
+}
+
+#[derive(Parser, Debug)]
+pub struct ExportState {
+    #[clap(flatten)]
+    pub options: StateOptions,
+
+    /// The snapshot file to write. Defaults to `<app name>.spinstate` in the
+    /// current directory.
+    #[clap(short = 'o', long = "output")]
+    pub output: Option<PathBuf>,
+}
+
+impl ExportState {
+    pub async fn run(self) -> Result<()> {
+        let state = self.options.resolve()?;
+        let manifest = spin_manifest::manifest_from_file(&state.manifest_file)?;
+
+        let mut stores = vec![];
+        for store in SnapshotStore::ALL {
+            let path = state.state_dir.join(store.file_name());
+            if path.exists() {
+                stores.push((store, path));
+            } else {
+                tracing::info!("Skipping {store:?}: {} does not exist", quoted_path(&path));
+            }
+        }
+
+        let variables = resolve_variables(&manifest, &state.runtime_config).await?;
+        let snapshot = SnapshotManifest {
+            snapshot_version: SNAPSHOT_VERSION,
+            app_name: manifest.application.name.clone(),
+            stores: stores.iter().map(|(store, _)| *store).collect(),
+            variables,
+        };
+
+        let output = self
+            .output
+            .unwrap_or_else(|| PathBuf::from(format!("{}.spinstate", snapshot.app_name)));
+        let output_path = output.clone();
+        tokio::task::spawn_blocking(move || write_snapshot(&output_path, &snapshot, &stores))
+            .await??;
+
+        println!("Exported application state to {}", quoted_path(&output));
+        Ok(())
+    }
+}
+
+#[derive(Parser, Debug)]
+pub struct ImportState {
+    #[clap(flatten)]
+    pub options: StateOptions,
+
+    /// The snapshot file to import.
+    pub snapshot: PathBuf,
+
+    /// Replace stores that already exist in the state directory.
+    #[clap(long)]
+    pub force: bool,
+}
+
+impl ImportState {
+    pub async fn run(self) -> Result<()> {
+        let state = self.options.resolve()?;
+        let snapshot_file = self.snapshot.clone();
+        let (snapshot, store_contents) =
+            tokio::task::spawn_blocking(move || read_snapshot(&snapshot_file)).await??;
+
+        std::fs::create_dir_all(&state.state_dir).with_context(|| {
+            format!(
+                "failed to create state directory {}",
+                quoted_path(&state.state_dir)
+            )
+        })?;
+        for (store, contents) in &store_contents {
+            let path = state.state_dir.join(store.file_name());
+            if path.exists() && !self.force {
+                anyhow::bail!(
+                    "{} already exists; pass `--force` to replace it",
+                    quoted_path(&path)
+                );
+            }
+            std::fs::write(&path, contents)
+                .with_context(|| format!("failed to write {}", quoted_path(&path)))?;
+            println!("Restored {} to {}", store.description(), quoted_path(&path));
+        }
+
+        let values: BTreeMap<_, _> = snapshot
+            .variables
+            .iter()
+            .filter_map(|(name, variable)| Some((name.clone(), variable.value.clone()?)))
+            .collect();
+        if !values.is_empty() {
+            let path = state.state_dir.join(IMPORTED_VARIABLES_FILE);
+            std::fs::write(&path, variables_runtime_config(values)?)
+                .with_context(|| format!("failed to write {}", quoted_path(&path)))?;
+            println!(
+                "Wrote the snapshot's variables to {}. Run the application with `--runtime-config-file {}` to use them.",
+                quoted_path(&path),
+                path.display()
+            );
+        }
+        let missing = snapshot
+            .variables
+            .iter()
+            .filter(|(_, variable)| variable.value.is_none())
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>();
+        if !missing.is_empty() {
+            println!(
+                "The snapshot does not include values for these variables, which must be provided separately: {}",
+                missing.join(", ")
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Describes the contents of a snapshot archive.
+#[derive(Debug, Serialize, Deserialize)]
+struct SnapshotManifest {
+    snapshot_version: u32,
+    app_name: String,
+    stores: Vec<SnapshotStore>,
+    variables: BTreeMap<String, SnapshotVariable>,
+}
+
+/// A store included in a snapshot.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum SnapshotStore {
+    /// The default key-value store.
+    DefaultKeyValue,
+    /// The default SQLite database.
+    DefaultSqlite,
+}
+
+impl SnapshotStore {
+    const ALL: [Self; 2] = [Self::DefaultKeyValue, Self::DefaultSqlite];
+
+    /// The store's file name in the state directory.
+    fn file_name(self) -> &'static str {
+        match self {
+            Self::DefaultKeyValue => DEFAULT_SPIN_STORE_FILENAME,
+            Self::DefaultSqlite => DEFAULT_SQLITE_DB_FILENAME,
+        }
+    }
+
+    /// The store's path within a snapshot archive.
+    fn archive_path(self) -> String {
+        format!("stores/{}", self.file_name())
+    }
+
+    fn description(self) -> &'static str {
+        match self {
+            Self::DefaultKeyValue => "the default key-value store",
+            Self::DefaultSqlite => "the default SQLite database",
+        }
+    }
+}
+
+/// An application variable as resolved when a snapshot was exported.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct SnapshotVariable {
+    /// The value, unless the variable is secret or could not be resolved.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    value: Option<String>,
+    #[serde(default)]
+    secret: bool,
+}
+
+/// Resolves the application's variables as `spin up` would, masking secrets.
+async fn resolve_variables(
+    manifest: &spin_manifest::schema::v2::AppManifest,
+    runtime_config: &toml::Table,
+) -> Result<BTreeMap<String, SnapshotVariable>> {
+    let variables = manifest.variables.iter().map(|(name, variable)| {
+        let locked = spin_locked_app::Variable {
+            default: variable.default.clone(),
+            secret: variable.secret,
+        };
+        (name.to_string(), locked)
+    });
+    let mut resolver = ProviderResolver::new(variables)?;
+    resolver.add_component_variables(
+        SNAPSHOT_COMPONENT,
+        manifest
+            .variables
+            .keys()
+            .map(|name| (name.to_string(), format!("{{{{ {name} }}}}"))),
+    )?;
+    for provider in spin_variables::runtime_config_from_toml(runtime_config)?.providers {
+        resolver.add_provider(provider);
+    }
+
+    let mut resolved = BTreeMap::new();
+    for (name, variable) in &manifest.variables {
+        let name = name.to_string();
+        let value = if variable.secret {
+            None
+        } else {
+            match resolver.resolve(SNAPSHOT_COMPONENT, Key::new(&name)?).await {
+                Ok(value) => Some(value),
+                Err(err) => {
+                    terminal::warn!("Could not resolve variable {name:?}: {err}");
+                    None
+                }
+            }
+        };
+        let secret = variable.secret;
+        resolved.insert(name, SnapshotVariable { value, secret });
+    }
+    Ok(resolved)
+}
+
+/// Returns a runtime config file providing the given variable values.
+fn variables_runtime_config(values: BTreeMap<String, String>) -> Result<String> {
+    let mut provider = toml::Table::new();
+    provider.insert("type".into(), "static".into());
+    provider.insert("values".into(), toml::Value::try_from(values)?);
+    let mut config = toml::Table::new();
+    config.insert(
+        "variables_provider".into(),
+        toml::Value::Array(vec![provider.into()]),
+    );
+    Ok(toml::to_string(&config)?)
+}
+
+fn write_snapshot(
+    output: &Path,
+    snapshot: &SnapshotManifest,
+    stores: &[(SnapshotStore, PathBuf)],
+) -> Result<()> {
+    let file = std::fs::File::create(output)
+        .with_context(|| format!("failed to create {}", quoted_path(output)))?;
+    let mut tar = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+
+    let manifest = serde_json::to_vec_pretty(snapshot)?;
+    let mut header = tar::Header::new_gnu();
+    header.set_size(manifest.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    tar.append_data(&mut header, SNAPSHOT_MANIFEST, manifest.as_slice())?;
+
+    for (store, path) in stores {
+        tar.append_path_with_name(path, store.archive_path())
+            .with_context(|| format!("failed to add {} to snapshot", quoted_path(path)))?;
+    }
+    tar.into_inner()?.finish()?;
+    Ok(())
+}
+
+type StoreContents = Vec<(SnapshotStore, Vec<u8>)>;
+
+fn read_snapshot(path: &Path) -> Result<(SnapshotManifest, StoreContents)> {
+    let file = std::fs::File::open(path)
+        .with_context(|| format!("failed to open snapshot {}", quoted_path(path)))?;
+    let mut archive = tar::Archive::new(GzDecoder::new(file));
+
+    let mut manifest = None;
+    let mut files = BTreeMap::new();
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let entry_path = entry.path()?.to_string_lossy().into_owned();
+        let mut contents = vec![];
+        entry.read_to_end(&mut contents)?;
+        if entry_path == SNAPSHOT_MANIFEST {
+            manifest = Some(serde_json::from_slice::<SnapshotManifest>(&contents)?);
+        } else {
+            files.insert(entry_path, contents);
+        }
+    }
+
+    let manifest = manifest.with_context(|| {
+        format!(
+            "{} is not a Spin state snapshot: it has no {SNAPSHOT_MANIFEST}",
+            quoted_path(path)
+        )
+    })?;
+    anyhow::ensure!(
+        manifest.snapshot_version == SNAPSHOT_VERSION,
+        "unsupported snapshot version {}; this version of Spin supports version {SNAPSHOT_VERSION}",
+        manifest.snapshot_version
+    );
+    let stores = manifest
+        .stores
+        .iter()
+        .map(|store| {
+            let contents = files
+                .remove(&store.archive_path())
+                .with_context(|| format!("snapshot is missing {}", store.description()))?;
+            Ok((*store, contents))
+        })
+        .collect::<Result<_>>()?;
+    Ok((manifest, stores))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snapshots_round_trip() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let kv_path = dir.path().join("kv.db");
+        std::fs::write(&kv_path, b"key-value contents")?;
+        let snapshot = SnapshotManifest {
+            snapshot_version: SNAPSHOT_VERSION,
+            app_name: "test-app".into(),
+            stores: vec![SnapshotStore::DefaultKeyValue],
+            variables: [
+                (
+                    "greeting".to_string(),
+                    SnapshotVariable {
+                        value: Some("hello".into()),
+                        secret: false,
+                    },
+                ),
+                (
+                    "token".to_string(),
+                    SnapshotVariable {
+                        value: None,
+                        secret: true,
+                    },
+                ),
+            ]
+            .into(),
+        };
+
+        let output = dir.path().join("test.spinstate");
+        write_snapshot(
+            &output,
+            &snapshot,
+            &[(SnapshotStore::DefaultKeyValue, kv_path)],
+        )?;
+        let (read, stores) = read_snapshot(&output)?;
+
+        assert_eq!(read.app_name, "test-app");
+        assert_eq!(read.variables, snapshot.variables);
+        assert_eq!(
+            stores,
+            vec![(
+                SnapshotStore::DefaultKeyValue,
+                b"key-value contents".to_vec()
+            )]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn imported_variables_are_a_static_provider() -> Result<()> {
+        let config = variables_runtime_config([("greeting".into(), "hello".into())].into())?;
+        let table: toml::Table = toml::from_str(&config)?;
+        let providers = table["variables_provider"].as_array().unwrap();
+        assert_eq!(providers[0]["type"].as_str(), Some("static"));
+        assert_eq!(providers[0]["values"]["greeting"].as_str(), Some("hello"));
+        Ok(())
+    }
+}
@@ -20,6 +20,12 @@ pub struct DoctorCommand {
         alias = "file"
     )]
     pub app_source: Option<PathBuf>,
+
+    /// The runtime config file the application is run with. If omitted, the
+    /// runtime-config.toml file next to the manifest is checked, if there is
+    /// one.
+    #[clap(long = "runtime-config-file")]
+    pub runtime_config_file: Option<PathBuf>,
 }
 
 impl DoctorCommand {
@@ -41,6 +47,9 @@ impl DoctorCommand {
         );
 
         let mut checkup = spin_doctor::Checkup::new(manifest_file)?;
+        if let Some(runtime_config_file) = self.runtime_config_file {
+            checkup.set_runtime_config_file(runtime_config_file);
+        }
         let mut has_problems = false;
         while let Some(PatientDiagnosis { diagnosis, patient }) = checkup.next_diagnosis().await? {
             show_diagnosis(&*diagnosis);
@@ -11,7 +11,7 @@ use itertools::Itertools;
 use path_absolutize::Absolutize;
 use tokio;
 
-use spin_templates::{RunOptions, Template, TemplateManager, TemplateVariantInfo};
+use spin_templates::{HookPolicy, RunOptions, Template, TemplateManager, TemplateVariantInfo};
 
 use crate::opts::{APP_MANIFEST_FILE_OPT, DEFAULT_MANIFEST_FILE};
 
@@ -76,6 +76,12 @@ pub struct TemplateNewCommandCore {
         takes_value = false
     )]
     pub allow_overwrite: bool,
+
+    /// Run the template's post-generation steps, such as installing
+    /// dependencies. These run commands on your machine, so only allow them
+    /// for templates you trust; otherwise they are listed but not run.
+    #[clap(long = "allow-hooks", takes_value = false)]
+    pub allow_hooks: bool,
 }
 
 /// Scaffold a new application based on a template.
@@ -195,6 +201,7 @@ impl TemplateNewCommandCore {
             accept_defaults: self.accept_defaults,
             no_vcs: self.no_vcs,
             allow_overwrite: self.allow_overwrite,
+            hooks: self.hook_policy(),
         };
 
         let run = template.run(options);
@@ -206,6 +213,14 @@ impl TemplateNewCommandCore {
         }
     }
 
+    fn hook_policy(&self) -> HookPolicy {
+        if self.allow_hooks {
+            HookPolicy::Run
+        } else {
+            HookPolicy::Skip
+        }
+    }
+
     // Try to guess if the user is using v1 or v2 syntax, and fix things up so
     // v1 syntax as used in existing content still works...!
     fn resolve_name_template_syntax(
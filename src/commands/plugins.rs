@@ -3,12 +3,14 @@
 
 use anyhow::{anyhow, Context, Result};
 use clap::{Parser, Subcommand, ValueEnum};
+use futures::StreamExt;
 use semver::Version;
 use spin_plugins::{
     error::Error,
     lookup::{fetch_plugins_repo, plugins_repo_url, PluginLookup},
     manager::{self, InstallAction, ManifestLocation, PluginManager},
     manifest::{PluginManifest, PluginPackage},
+    project::{self, PluginLockfile, PluginRequirements, REQUIREMENTS_FILE_NAME},
 };
 use std::path::{Path, PathBuf};
 use url::Url;
@@ -42,6 +44,14 @@ pub enum PluginCommands {
 
     /// Print information about a plugin.
     Show(Show),
+
+    /// Install the plugins a project requires, at the versions in its lockfile.
+    ///
+    /// Required plugins are listed, with version constraints, in
+    /// spin-plugins.toml. The versions they resolve to are recorded in
+    /// spin-plugins.lock, so that everyone working on the project installs
+    /// the same versions.
+    Sync(SyncPlugins),
 }
 
 impl PluginCommands {
@@ -54,6 +64,7 @@ impl PluginCommands {
             PluginCommands::Upgrade(cmd) => cmd.run().await,
             PluginCommands::Update => update().await,
             PluginCommands::Show(cmd) => cmd.run().await,
+            PluginCommands::Sync(cmd) => cmd.run().await,
         }
     }
 }
@@ -483,6 +494,98 @@ impl Show {
     }
 }
 
+/// Install the plugins a project requires, at the versions in its lockfile.
+#[derive(Parser, Debug)]
+pub struct SyncPlugins {
+    /// Path to the file listing the required plugins. The lockfile is
+    /// written next to it.
+    #[clap(short = 'f', long = "file", default_value = REQUIREMENTS_FILE_NAME)]
+    pub requirements_file: PathBuf,
+
+    /// Fail if the lockfile is missing or out of date, instead of updating
+    /// it. Use this in CI.
+    #[clap(long = "locked", takes_value = false)]
+    pub locked: bool,
+
+    /// The most plugins to install at once.
+    #[clap(short = 'j', long = "jobs", default_value = "4")]
+    pub jobs: usize,
+}
+
+impl SyncPlugins {
+    pub async fn run(self) -> Result<()> {
+        let requirements = PluginRequirements::from_file(&self.requirements_file)?;
+        let lockfile_path = PluginLockfile::path_for(&self.requirements_file);
+        let lockfile = PluginLockfile::from_file(&lockfile_path)?;
+        if self.locked && lockfile.is_none() {
+            anyhow::bail!(
+                "Plugin lockfile {} does not exist. Run `spin plugins sync` without `--locked` to create it.",
+                lockfile_path.display()
+            );
+        }
+
+        if update_silent().await.is_err() {
+            terminal::warn!("Couldn't update plugins registry cache - using most recent");
+        }
+
+        let manager = PluginManager::try_default()?;
+        let catalogue = manager.store().catalogue_manifests()?;
+        let (resolved, manifests) =
+            project::resolve(&requirements, lockfile.as_ref(), &catalogue, SPIN_VERSION)?;
+        let lockfile_changed = lockfile.as_ref() != Some(&resolved);
+        if self.locked && lockfile_changed {
+            anyhow::bail!(
+                "Plugin lockfile {} is out of date with {}. Run `spin plugins sync` without `--locked` to update it.",
+                lockfile_path.display(),
+                self.requirements_file.display()
+            );
+        }
+
+        let manager = &manager;
+        let failures = futures::stream::iter(&manifests)
+            .map(|manifest| async move { (manifest.name(), sync_plugin(manager, manifest).await) })
+            .buffer_unordered(self.jobs.max(1))
+            .filter_map(|(name, result)| async move { result.err().map(|e| (name, e)) })
+            .collect::<Vec<_>>()
+            .await;
+        if !failures.is_empty() {
+            for (name, e) in &failures {
+                terminal::error!("Failed to install plugin '{name}': {e:#}");
+            }
+            anyhow::bail!("Failed to install {} plugin(s)", failures.len());
+        }
+
+        if lockfile_changed {
+            resolved.write(&lockfile_path)?;
+            println!("Updated plugin lockfile {}", lockfile_path.display());
+        }
+        println!("All {} required plugins are installed", manifests.len());
+        Ok(())
+    }
+}
+
+// Installs the locked version of a plugin, unless it is already installed.
+async fn sync_plugin(manager: &PluginManager, manifest: &PluginManifest) -> Result<()> {
+    // Locked versions may be older than those installed.
+    let allow_downgrades = true;
+    let install_action = manager.check_manifest(manifest, SPIN_VERSION, false, allow_downgrades)?;
+    if let InstallAction::NoAction { .. } = install_action {
+        return Ok(());
+    }
+
+    let package = manager::get_package(manifest)?;
+    let source = ManifestLocation::PluginsRepository(PluginLookup::new(
+        &manifest.name(),
+        manifest.try_version().ok(),
+    ));
+    let installed = manager.install(manifest, package, &source, &None).await?;
+    println!(
+        "Plugin '{installed}' version {} was installed successfully!",
+        manifest.version()
+    );
+    Ok(())
+}
+
 fn is_potential_upgrade(current: &PluginManifest, candidate: &PluginManifest) -> bool {
     match (current.try_version(), candidate.try_version()) {
         (Ok(cur_ver), Ok(cand_ver)) => cand_ver > cur_ver,
@@ -3,8 +3,18 @@ use anyhow::{Context, Result};
 use clap::{Parser, Subcommand};
 use indicatif::{ProgressBar, ProgressStyle};
 use spin_common::arg_parser::parse_kv;
-use spin_oci::{client::InferPredefinedAnnotations, Client};
-use std::{io::Read, path::PathBuf, time::Duration};
+use spin_loader::ManifestLoadOptions;
+use spin_oci::{
+    client::{Attachment, AttachmentKind, InferPredefinedAnnotations},
+    mirrors::RegistryMirrors,
+    signing::{Signer, DEFAULT_FULCIO_URL, DEFAULT_REKOR_URL},
+    Client,
+};
+use std::{
+    io::Read,
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
 /// Commands for working with OCI registries to distribute applications.
 #[derive(Subcommand, Debug)]
@@ -53,6 +63,12 @@ pub struct Push {
     #[clap(long, takes_value = false, env = ALWAYS_BUILD_ENV)]
     pub build: bool,
 
+    /// Patch the manifest with the overlay for the given environment, e.g.
+    /// spin.staging.toml next to spin.toml for `staging`, and push the
+    /// patched application.
+    #[clap(long = "environment", value_name = "NAME")]
+    pub environment: Option<String>,
+
     /// Reference in the registry of the Spin application.
     /// This is a string whose format is defined by the registry standard, and generally consists of <registry>/<username>/<application-name>:<version>. E.g. ghcr.io/ogghead/spin-test-app:0.1.0
     #[clap()]
@@ -66,6 +82,55 @@ pub struct Push {
     /// Any existing value will be overwritten. Can be used multiple times.
     #[clap(long = "annotation", parse(try_from_str = parse_kv))]
     pub annotations: Vec<(String, String)>,
+
+    /// The URL of the source repository of the application. This is recorded
+    /// in the `org.opencontainers.image.source` annotation.
+    #[clap(long = "source-repo")]
+    pub source_repo: Option<String>,
+
+    /// The source revision (e.g. Git commit) of the application. This is
+    /// recorded in the `org.opencontainers.image.revision` annotation.
+    #[clap(long = "revision")]
+    pub revision: Option<String>,
+
+    /// Path to a software bill of materials (SPDX or CycloneDX JSON) to attach
+    /// to the pushed application.
+    #[clap(long = "sbom")]
+    pub sbom: Option<PathBuf>,
+
+    /// Path to a provenance attestation (in-toto JSON) to attach to the pushed
+    /// application.
+    #[clap(long = "provenance")]
+    pub provenance: Option<PathBuf>,
+
+    /// Sign the pushed application. By default this uses sigstore keyless
+    /// signing, which requires an OIDC identity token (see --identity-token).
+    #[clap(long = "sign", takes_value = false)]
+    pub sign: bool,
+
+    /// Sign with the given PEM-encoded PKCS#8 ECDSA P-256 private key instead
+    /// of keyless signing.
+    #[clap(long = "key", requires = "sign")]
+    pub key: Option<PathBuf>,
+
+    /// The OIDC identity token for keyless signing. If omitted, Spin uses the
+    /// ambient GitHub Actions token, if available.
+    #[clap(
+        long = "identity-token",
+        env = "SIGSTORE_ID_TOKEN",
+        hide_env_values = true,
+        requires = "sign",
+        conflicts_with = "key"
+    )]
+    pub identity_token: Option<String>,
+
+    /// The Fulcio certificate authority for keyless signing.
+    #[clap(long = "fulcio-url", default_value = DEFAULT_FULCIO_URL)]
+    pub fulcio_url: reqwest::Url,
+
+    /// The Rekor transparency log for keyless signing.
+    #[clap(long = "rekor-url", default_value = DEFAULT_REKOR_URL)]
+    pub rekor_url: reqwest::Url,
 }
 
 impl Push {
@@ -75,34 +140,132 @@ impl Push {
         notify_if_nondefault_rel(&app_file, distance);
 
         if self.build {
-            spin_build::build(&app_file, &[]).await?;
+            let options = spin_build::BuildOptions {
+                environment: self.environment.clone(),
+                ..Default::default()
+            };
+            spin_build::build_with_options(&app_file, &[], &options).await?;
         }
 
-        let annotations = if self.annotations.is_empty() {
+        let mut annotations = self.annotations.clone();
+        if let Some(source_repo) = &self.source_repo {
+            annotations.push((SOURCE_ANNOTATION.to_owned(), source_repo.clone()));
+        }
+        if let Some(revision) = &self.revision {
+            annotations.push((REVISION_ANNOTATION.to_owned(), revision.clone()));
+        }
+        let annotations = if annotations.is_empty() {
             None
         } else {
-            Some(self.annotations.iter().cloned().collect())
+            Some(annotations.into_iter().collect())
+        };
+
+        let attachments = [
+            (AttachmentKind::Sbom, self.sbom.as_ref()),
+            (AttachmentKind::Provenance, self.provenance.as_ref()),
+        ]
+        .into_iter()
+        .filter_map(|(kind, path)| path.map(|path| read_attachment(kind, path)))
+        .collect::<Result<Vec<_>>>()?;
+
+        let signer = if self.sign {
+            Some(self.signer().await?)
+        } else {
+            None
         };
 
         let mut client = spin_oci::Client::new(self.insecure, self.cache_dir.clone()).await?;
 
         let _spinner = create_dotted_spinner(2000, "Pushing app to the Registry".to_owned());
 
+        let load_options = ManifestLoadOptions {
+            environment: self.environment.clone(),
+            ..Default::default()
+        };
         let digest = client
-            .push(
+            .push_with_options(
                 &app_file,
+                load_options,
                 &self.reference,
                 annotations,
                 InferPredefinedAnnotations::All,
             )
             .await?;
-        match digest {
+        match &digest {
             Some(digest) => println!("Pushed with digest {digest}"),
             None => println!("Pushed; the registry did not return the digest"),
         };
 
+        if !attachments.is_empty() {
+            let digest = digest.as_ref().context(
+                "Cannot attach documents because the registry did not return the application digest",
+            )?;
+            for attachment in &attachments {
+                client
+                    .push_attachment(&self.reference, digest, attachment)
+                    .await?;
+                println!("Attached {:?} document", attachment.kind);
+            }
+        }
+
+        if let Some(signer) = signer {
+            let digest = digest.as_ref().context(
+                "Cannot sign the application because the registry did not return its digest",
+            )?;
+            let signature = signer.sign(&self.reference, digest).await?;
+            client
+                .push_attachment(&self.reference, digest, &signature)
+                .await?;
+            println!("Signed {digest}");
+        }
+
         Ok(())
     }
+
+    async fn signer(&self) -> Result<Signer> {
+        if let Some(key) = &self.key {
+            return Signer::from_key_file(key);
+        }
+        let identity_token = match &self.identity_token {
+            Some(token) => token.clone(),
+            None => spin_oci::signing::ambient_identity_token()
+                .await?
+                .context("Keyless signing requires an OIDC identity token. Pass --identity-token, or use --key to sign with a key")?,
+        };
+        Ok(Signer::Keyless {
+            identity_token,
+            fulcio_url: self.fulcio_url.clone(),
+            rekor_url: self.rekor_url.clone(),
+        })
+    }
+}
+
+const SOURCE_ANNOTATION: &str = "org.opencontainers.image.source";
+const REVISION_ANNOTATION: &str = "org.opencontainers.image.revision";
+
+fn read_attachment(kind: AttachmentKind, path: &Path) -> Result<Attachment> {
+    let data = std::fs::read(path)
+        .with_context(|| format!("Failed to read {kind:?} document {}", path.display()))?;
+    let media_type = match kind {
+        AttachmentKind::Sbom if is_cyclonedx(path) => "application/vnd.cyclonedx+json",
+        AttachmentKind::Sbom => "application/spdx+json",
+        AttachmentKind::Provenance => "application/vnd.in-toto+json",
+        AttachmentKind::Signature => spin_oci::signing::SIMPLE_SIGNING_MEDIA_TYPE,
+    };
+    Ok(Attachment {
+        kind,
+        media_type: media_type.to_owned(),
+        data,
+        annotations: Default::default(),
+    })
+}
+
+fn is_cyclonedx(path: &Path) -> bool {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    name.contains("cdx") || name.contains("cyclonedx")
 }
 
 #[derive(Parser, Debug)]
@@ -124,12 +287,22 @@ pub struct Pull {
     /// Cache directory for downloaded registry data.
     #[clap(long)]
     pub cache_dir: Option<PathBuf>,
+
+    /// Runtime config file whose `[registry.mirrors]` section configures
+    /// mirrors to pull the application through.
+    #[clap(long = "runtime-config-file")]
+    pub runtime_config_file: Option<PathBuf>,
 }
 
 impl Pull {
     /// Pull a Spin application from an OCI registry
     pub async fn run(self) -> Result<()> {
         let mut client = spin_oci::Client::new(self.insecure, self.cache_dir.clone()).await?;
+        if let Some(runtime_config_file) = &self.runtime_config_file {
+            if let Some(mirrors) = RegistryMirrors::from_runtime_config_file(runtime_config_file)? {
+                client = client.with_mirrors(mirrors)?;
+            }
+        }
 
         let _spinner = create_dotted_spinner(2000, "Pulling app from the Registry".to_owned());
 
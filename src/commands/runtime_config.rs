@@ -0,0 +1,96 @@
+use std::{collections::BTreeMap, path::PathBuf};
+
+use anyhow::{Context, Result};
+use clap::{Parser, Subcommand};
+use spin_common::ui::quoted_path;
+use spin_runtime_config::ResolvedRuntimeConfig;
+use spin_runtime_factors::TriggerFactorsRuntimeConfig;
+use spin_trigger::cli::UserProvidedPath;
+
+/// Commands for working with runtime config files.
+#[derive(Subcommand, Debug)]
+pub enum RuntimeConfigCommands {
+    /// Check a runtime config file for unknown keys, values of the wrong type,
+    /// and settings that Spin cannot resolve.
+    Validate(ValidateCommand),
+    /// Print a JSON schema for runtime config files, e.g. for editor
+    /// autocomplete.
+    Schema(SchemaCommand),
+}
+
+impl RuntimeConfigCommands {
+    pub async fn run(self) -> Result<()> {
+        match self {
+            RuntimeConfigCommands::Validate(cmd) => cmd.run().await,
+            RuntimeConfigCommands::Schema(cmd) => cmd.run().await,
+        }
+    }
+}
+
+#[derive(Parser, Debug)]
+pub struct ValidateCommand {
+    /// The runtime config file to check.
+    #[clap(default_value = "runtime-config.toml")]
+    pub file: PathBuf,
+}
+
+impl ValidateCommand {
+    pub async fn run(self) -> Result<()> {
+        let table = spin_runtime_config::read_runtime_config_file(&self.file)?;
+
+        let issues = spin_runtime_config::validate(&table);
+        if !issues.is_empty() {
+            let mut by_owner = BTreeMap::<_, Vec<_>>::new();
+            for issue in &issues {
+                by_owner.entry(issue.owner).or_default().push(issue);
+            }
+            for (owner, issues) in by_owner {
+                match owner {
+                    Some(owner) => println!("{owner}:"),
+                    None => println!("unused sections:"),
+                }
+                for issue in issues {
+                    println!("  {issue}");
+                }
+            }
+            anyhow::bail!(
+                "{} has {} problem(s)",
+                quoted_path(&self.file),
+                issues.len()
+            );
+        }
+
+        // The structure is valid, so check that the values can be resolved,
+        // e.g. that URLs parse and certificate files exist. State and log
+        // directories are left unset so that nothing is created.
+        ResolvedRuntimeConfig::<TriggerFactorsRuntimeConfig>::from_file(
+            Some(&self.file),
+            None,
+            UserProvidedPath::Unset,
+            UserProvidedPath::Unset,
+        )
+        .with_context(|| format!("{} is invalid", quoted_path(&self.file)))?;
+
+        println!("{} is valid", quoted_path(&self.file));
+        Ok(())
+    }
+}
+
+#[derive(Parser, Debug)]
+pub struct SchemaCommand {
+    /// The file to write the schema to. Defaults to stdout.
+    #[clap(short = 'o', long = "output")]
+    pub output: Option<PathBuf>,
+}
+
+impl SchemaCommand {
+    pub async fn run(self) -> Result<()> {
+        let schema = serde_json::to_string_pretty(&spin_runtime_config::json_schema())?;
+        match &self.output {
+            Some(path) => std::fs::write(path, schema + "\n")
+                .with_context(|| format!("failed to write schema to {}", quoted_path(path)))?,
+            None => println!("{schema}"),
+        }
+        Ok(())
+    }
+}
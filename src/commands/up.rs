@@ -11,12 +11,19 @@ use std::{
 use anyhow::{anyhow, bail, ensure, Context, Result};
 use clap::{CommandFactory, Parser};
 use reqwest::Url;
-use spin_app::locked::LockedApp;
-use spin_common::ui::quoted_path;
+use spin_app::{
+    locked::LockedApp, LifecycleHook, MetadataKey, APP_ON_SHUTDOWN_KEY, APP_ON_STARTUP_KEY,
+};
+use spin_common::{diagnostics::Diagnostics, ui::quoted_path};
 use spin_factor_outbound_networking::validate_service_chaining_for_components;
-use spin_loader::FilesMountStrategy;
+use spin_loader::{lockfile::LockfileMode, FilesMountStrategy, ManifestLoadOptions};
+use spin_oci::mirrors::RegistryMirrors;
+use spin_oci::signing::{VerificationPolicy, Verifier};
 use spin_oci::OciLoader;
-use spin_trigger::cli::{LaunchMetadata, SPIN_LOCAL_APP_DIR, SPIN_LOCKED_URL, SPIN_WORKING_DIR};
+use spin_trigger::cli::{
+    LaunchMetadata, RUNTIME_CONFIG_FILE, SPIN_LOCAL_APP_DIR, SPIN_LOCKED_URL,
+    SPIN_RESUME_SAVED_TASKS, SPIN_RUN_LIFECYCLE_HOOKS, SPIN_WORKING_DIR,
+};
 use tempfile::TempDir;
 
 use crate::{directory_rels::notify_if_nondefault_rel, opts::*};
@@ -79,6 +86,12 @@ pub struct UpCommand {
     )]
     pub registry_source: Option<String>,
 
+    /// The application to run, from a bundle created with `spin bundle export`.
+    /// If the bundle includes a runtime config file, it is used unless another
+    /// is given with `--runtime-config-file`.
+    #[clap(long = "from-bundle", group = "source")]
+    pub bundle_source: Option<PathBuf>,
+
     /// Ignore server certificate errors from a registry
     #[clap(
         name = INSECURE_OPT,
@@ -92,7 +105,8 @@ pub struct UpCommand {
     #[clap(short = 'e', long = "env", parse(try_from_str = parse_env_var))]
     pub env: Vec<(String, String)>,
 
-    /// Temporary directory for the static assets of the components.
+    /// Temporary directory for the static assets of the components. When the
+    /// same directory is used again, only the assets which changed are copied.
     #[clap(long = "temp", alias = "tmp")]
     pub tmp: Option<PathBuf>,
 
@@ -100,14 +114,47 @@ pub struct UpCommand {
     #[clap(long)]
     pub cache_dir: Option<PathBuf>,
 
-    /// For local apps with directory mounts and no excluded files, mount them directly instead of using a temporary
-    /// directory.
+    /// For local apps, mount directories directly instead of copying them to a temporary directory.
     ///
     /// This allows you to update the assets on the host filesystem such that the updates are visible to the guest
-    /// without a restart.  This cannot be used with registry apps or apps which use file patterns and/or exclusions.
+    /// without a restart.  This cannot be used with registry apps. Single files, file patterns, and directories
+    /// with exclusions are mounted through links to the files they include, so files added to them later
+    /// are not visible until the app is restarted.
     #[clap(long, takes_value = false)]
     pub direct_mounts: bool,
 
+    /// For local apps, the target to use the source of for components with a source for each
+    /// target, such as `source.local = "..."` and `source.staging = { ... }`. The default is
+    /// "local".
+    #[clap(long = "source-target", value_name = "TARGET")]
+    pub source_target: Option<String>,
+
+    /// For local apps, enable a component feature, applying the settings in the
+    /// `[component.<id>.features.<name>]` tables of the manifest. This can be
+    /// specified multiple times.
+    #[clap(long = "feature", value_name = "NAME")]
+    pub features: Vec<String>,
+
+    /// For local apps, patch the manifest with the overlay for the given
+    /// environment, e.g. spin.staging.toml next to spin.toml for `staging`.
+    /// The lockfile for the environment is then e.g. spin.staging.lock.
+    #[clap(long = "environment", value_name = "NAME")]
+    pub environment: Option<String>,
+
+    /// For local apps, write a lockfile (spin.lock) next to the manifest
+    /// recording the digests of all component sources, dependencies and files.
+    #[clap(
+        long = "write-lockfile",
+        takes_value = false,
+        conflicts_with = "locked"
+    )]
+    pub write_lockfile: bool,
+
+    /// For local apps, fail if the application does not match its lockfile
+    /// (spin.lock).
+    #[clap(long = "locked", takes_value = false)]
+    pub locked: bool,
+
     /// For local apps, specifies to perform `spin build` before running the application.
     ///
     /// This is ignored on remote applications, as they are already built.
@@ -193,25 +240,11 @@ impl UpCommand {
         if self.build {
             app_source.build().await?;
         }
-        let mut locked_app = self
-            .load_resolved_app_source(resolved_app_source, &working_dir)
-            .await
-            .context("Failed to load application")?;
-
-        if !self.components.is_empty() {
-            locked_app = spin_app::retain_components(
-                locked_app,
-                &self
-                    .components
-                    .iter()
-                    .map(|s| s.as_str())
-                    .collect::<Vec<&str>>(),
-                &[&validate_service_chaining_for_components],
-            )
-            .context(
-                "failed to resolve application with only components selected with --component",
-            )?;
-        }
+        let bundled_runtime_config_file = resolved_app_source
+            .bundled_runtime_config_file()
+            .filter(|_| self.runtime_config_file().is_none())
+            .map(ToOwned::to_owned);
+        let mut locked_app = self.lock_app(resolved_app_source, &working_dir).await?;
 
         let trigger_types: HashSet<&str> = locked_app
             .triggers
@@ -232,11 +265,33 @@ impl UpCommand {
 
         let run_opts = RunTriggerOpts {
             locked_url,
-            working_dir,
+            working_dir: working_dir.clone(),
             local_app_dir,
+            runtime_config_file: bundled_runtime_config_file,
+            resume_saved_tasks: true,
+        };
+
+        let trigger_args = self.trigger_args_by_command(&trigger_cmds).await?;
+        // Lifecycle hooks run once for the app, before any trigger starts and
+        // after they have all stopped, rather than in each trigger process.
+        let has_hook =
+            |key: MetadataKey<LifecycleHook>| locked_app.metadata.contains_key(key.as_ref());
+        let hook_cmd = match lifecycle_hook_command_index(&trigger_cmds) {
+            Some(index) => (&trigger_cmds[index], &trigger_args[index]),
+            None if has_hook(APP_ON_STARTUP_KEY) || has_hook(APP_ON_SHUTDOWN_KEY) => bail!(
+                "The app has lifecycle hooks, but none of its triggers can run them: plugins run as `spin trigger-<type>` subcommands don't support lifecycle hooks"
+            ),
+            // Unused without hooks.
+            None => (&trigger_cmds[0], &trigger_args[0]),
         };
+        if has_hook(APP_ON_STARTUP_KEY) {
+            self.run_lifecycle_hook(hook_cmd.0, hook_cmd.1, &run_opts, "startup")
+                .await?;
+        }
 
-        let trigger_processes = self.start_trigger_processes(trigger_cmds, run_opts).await?;
+        let trigger_processes = self
+            .start_trigger_processes(trigger_cmds.clone(), &trigger_args, run_opts.clone())
+            .await?;
         let pids = get_pids(&trigger_processes);
 
         set_kill_on_ctrl_c(&pids)?;
@@ -250,20 +305,41 @@ impl UpCommand {
             tokio::time::sleep(MULTI_TRIGGER_LET_ALL_START).await;
         }
 
-        let (first_to_finish, _index, _rest) = futures::future::select_all(trigger_tasks).await;
-
-        if let Ok(process_result) = first_to_finish {
-            let status = process_result?;
-            if !status.success() {
-                if is_multi {
-                    println!("A trigger exited unexpectedly. Terminating.");
+        let result = async {
+            let (first_to_finish, _index, rest) = tokio::select! {
+                finished = futures::future::select_all(trigger_tasks) => finished,
+                res = self.relock_on_hangup(&app_source, &working_dir, &pids) => {
                     kill_child_processes(&pids);
+                    return res;
+                }
+            };
+
+            let result = match first_to_finish {
+                Ok(Ok(status)) if !status.success() => {
+                    if is_multi {
+                        println!("A trigger exited unexpectedly. Terminating.");
+                    }
+                    Err(crate::subprocess::ExitStatusError::new(status).into())
                 }
-                return Err(crate::subprocess::ExitStatusError::new(status).into());
+                Ok(Err(err)) => Err(err.into()),
+                _ => Ok(()),
+            };
+            if is_multi {
+                kill_child_processes(&pids);
             }
+            // The shutdown hook runs once every trigger has stopped.
+            futures::future::join_all(rest).await;
+            result
         }
+        .await;
 
-        Ok(())
+        if has_hook(APP_ON_SHUTDOWN_KEY) {
+            let shutdown_result = self
+                .run_lifecycle_hook(hook_cmd.0, hook_cmd.1, &run_opts, "shutdown")
+                .await;
+            return result.and(shutdown_result);
+        }
+        result
     }
 
     fn get_canonical_working_dir(&self) -> Result<WorkingDirectory, anyhow::Error> {
@@ -301,16 +377,18 @@ impl UpCommand {
         Ok(metas)
     }
 
-    async fn start_trigger_processes(
-        self,
-        trigger_cmds: Vec<Vec<String>>,
-        run_opts: RunTriggerOpts,
-    ) -> anyhow::Result<Vec<tokio::process::Child>> {
+    /// Returns the trigger args to pass to each trigger command: all of them
+    /// if there is one trigger, or those each trigger accepts if there are
+    /// several.
+    async fn trigger_args_by_command(
+        &self,
+        trigger_cmds: &[Vec<String>],
+    ) -> anyhow::Result<Vec<Vec<&OsString>>> {
         let is_multi = trigger_cmds.len() > 1;
 
         let trigger_args = self.group_trigger_args();
         let trigger_metas = if is_multi {
-            match self.get_trigger_launch_metas(&trigger_cmds).await {
+            match self.get_trigger_launch_metas(trigger_cmds).await {
                 Ok(m) => Some(m),
                 Err(e) => {
                     tracing::warn!("Error getting trigger launch meta - allowing all. {e:?}");
@@ -330,16 +408,35 @@ impl UpCommand {
             }
         }
 
+        Ok(trigger_cmds
+            .iter()
+            .map(
+                |cmd| match trigger_metas.as_ref().and_then(|ms| ms.get(cmd)) {
+                    Some(m) => m.matches(&trigger_args),
+                    None => self.trigger_args.iter().collect(),
+                },
+            )
+            .collect())
+    }
+
+    async fn start_trigger_processes(
+        &self,
+        trigger_cmds: Vec<Vec<String>>,
+        trigger_args: &[Vec<&OsString>],
+        run_opts: RunTriggerOpts,
+    ) -> anyhow::Result<Vec<tokio::process::Child>> {
+        let is_multi = trigger_cmds.len() > 1;
+
         let mut trigger_processes = Vec::with_capacity(trigger_cmds.len());
 
-        for cmd in trigger_cmds {
-            let meta = trigger_metas.as_ref().and_then(|ms| ms.get(&cmd));
-            let trigger_args = match meta {
-                Some(m) => m.matches(&trigger_args),
-                None => self.trigger_args.iter().collect(),
+        for (index, (cmd, trigger_args)) in trigger_cmds.into_iter().zip(trigger_args).enumerate() {
+            // Saved tasks are shared by the app's triggers, so only the first resumes them.
+            let run_opts = RunTriggerOpts {
+                resume_saved_tasks: run_opts.resume_saved_tasks && index == 0,
+                ..run_opts.clone()
             };
             let child = self
-                .start_trigger(cmd.clone(), Some(run_opts.clone()), &trigger_args)
+                .start_trigger(cmd.clone(), Some(run_opts), trigger_args)
                 .await
                 .context("Failed to start trigger process")?;
             trigger_processes.push(child);
@@ -355,6 +452,30 @@ impl UpCommand {
         Ok(trigger_processes)
     }
 
+    /// Runs the app's hook component for a lifecycle stage in a process of
+    /// its own, so that it runs once for the app however many triggers it
+    /// has.
+    async fn run_lifecycle_hook(
+        &self,
+        trigger_cmd: &[String],
+        trigger_args: &[&OsString],
+        run_opts: &RunTriggerOpts,
+        stage: &str,
+    ) -> anyhow::Result<()> {
+        let mut cmd = trigger_cmd.to_vec();
+        cmd.extend(["--lifecycle-hook-only".to_owned(), stage.to_owned()]);
+        let status = self
+            .start_trigger(cmd, Some(run_opts.clone()), trigger_args)
+            .await
+            .context("Failed to start lifecycle hook process")?
+            .wait()
+            .await?;
+        if !status.success() {
+            return Err(crate::subprocess::ExitStatusError::new(status).into());
+        }
+        Ok(())
+    }
+
     async fn start_trigger(
         &self,
         trigger_cmd: Vec<String>,
@@ -370,16 +491,27 @@ impl UpCommand {
             locked_url,
             working_dir,
             local_app_dir,
+            runtime_config_file,
+            resume_saved_tasks,
         }) = opts
         {
             cmd.env(SPIN_LOCKED_URL, locked_url)
                 .env(SPIN_WORKING_DIR, &working_dir)
+                .env(SPIN_RESUME_SAVED_TASKS, resume_saved_tasks.to_string())
+                .env(SPIN_RUN_LIFECYCLE_HOOKS, "false")
                 .args(trigger_args);
 
             if let Some(local_app_dir) = local_app_dir {
                 cmd.env(SPIN_LOCAL_APP_DIR, local_app_dir);
             }
 
+            if let Some(runtime_config_file) = runtime_config_file {
+                cmd.env(RUNTIME_CONFIG_FILE, runtime_config_file);
+            }
+
+            // Hand on any sockets passed by systemd socket activation
+            cmd.envs(spin_trigger::systemd::listener_env());
+
             cmd.kill_on_drop(true);
         } else {
             cmd.env("SPIN_PLUGINS_SUPPRESS_COMPATIBILITY_WARNINGS", "1");
@@ -393,11 +525,17 @@ impl UpCommand {
     }
 
     fn app_source(&self) -> AppSource {
-        match (&self.app_source, &self.file_source, &self.registry_source) {
-            (None, None, None) => self.default_manifest_or_none(),
-            (Some(source), None, None) => AppSource::infer_source(source),
-            (None, Some(file), None) => AppSource::infer_file_source(file.to_owned()),
-            (None, None, Some(reference)) => AppSource::OciRegistry(reference.to_owned()),
+        match (
+            &self.app_source,
+            &self.file_source,
+            &self.registry_source,
+            &self.bundle_source,
+        ) {
+            (None, None, None, None) => self.default_manifest_or_none(),
+            (Some(source), None, None, None) => AppSource::infer_source(source),
+            (None, Some(file), None, None) => AppSource::infer_file_source(file.to_owned()),
+            (None, None, Some(reference), None) => AppSource::OciRegistry(reference.to_owned()),
+            (None, None, None, Some(bundle)) => AppSource::Bundle(bundle.to_owned()),
             _ => AppSource::unresolvable("More than one application source was specified"),
         }
     }
@@ -466,20 +604,83 @@ impl UpCommand {
                 let mut client = spin_oci::Client::new(self.insecure, self.cache_dir.clone())
                     .await
                     .context("cannot create registry client")?;
+                if let Some(mirrors) = self.registry_mirrors()? {
+                    client = client.with_mirrors(mirrors)?;
+                }
 
-                let locked_app = OciLoader::new(working_dir)
-                    .load_app(&mut client, reference)
-                    .await?;
+                let mut loader = OciLoader::new(working_dir);
+                if let Some(verifier) = self.signature_verifier()? {
+                    loader = loader.with_verifier(verifier);
+                }
+                let locked_app = loader.load_app(&mut client, reference).await?;
                 ResolvedAppSource::OciRegistry { locked_app }
             }
             AppSource::BareWasm(path) => ResolvedAppSource::BareWasm {
                 wasm_path: path.clone(),
             },
+            AppSource::Bundle(path) => {
+                let bundle_path = path.clone();
+                let dest_dir = working_dir.join("bundle");
+                let bundle = tokio::task::spawn_blocking(move || {
+                    spin_loader::bundle::unpack(&bundle_path, &dest_dir)
+                })
+                .await??;
+                ResolvedAppSource::Bundle {
+                    locked_app: bundle.locked_app,
+                    runtime_config_file: bundle.runtime_config_file,
+                }
+            }
             AppSource::Unresolvable(err) => bail!("{err}"),
             AppSource::None => bail!("Internal error - should have shown help"),
         })
     }
 
+    /// The signature verifier configured by `[registry.verification]` in the
+    /// runtime config file passed through to the trigger, if any.
+    fn signature_verifier(&self) -> anyhow::Result<Option<Verifier>> {
+        let Some(runtime_config_file) = self.runtime_config_file() else {
+            return Ok(None);
+        };
+        let Some(policy) = VerificationPolicy::from_runtime_config_file(&runtime_config_file)?
+        else {
+            return Ok(None);
+        };
+        let base_dir = runtime_config_file
+            .parent()
+            .map(ToOwned::to_owned)
+            .unwrap_or_default();
+        Verifier::new(policy, &base_dir)
+            .context("invalid [registry.verification] runtime config")
+            .map(Some)
+    }
+
+    /// The registry mirrors configured by `[registry.mirrors]` in the runtime
+    /// config file passed through to the trigger, if any.
+    fn registry_mirrors(&self) -> anyhow::Result<Option<RegistryMirrors>> {
+        match self.runtime_config_file() {
+            Some(runtime_config_file) => {
+                RegistryMirrors::from_runtime_config_file(&runtime_config_file)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn runtime_config_file(&self) -> Option<PathBuf> {
+        let mut args = self.trigger_args.iter();
+        while let Some(arg) = args.next() {
+            if arg == "--runtime-config-file" {
+                return args.next().map(PathBuf::from);
+            }
+            if let Some(path) = arg
+                .to_str()
+                .and_then(|arg| arg.strip_prefix("--runtime-config-file="))
+            {
+                return Some(PathBuf::from(path));
+            }
+        }
+        std::env::var_os(RUNTIME_CONFIG_FILE).map(PathBuf::from)
+    }
+
     // Finish preparing a ResolvedAppSource for execution.
     async fn load_resolved_app_source(
         &self,
@@ -489,20 +690,36 @@ impl UpCommand {
         match resolved {
             ResolvedAppSource::File { manifest_path, .. } => {
                 let files_mount_strategy = if self.direct_mounts {
-                    FilesMountStrategy::Direct
+                    FilesMountStrategy::Overlay(working_dir.join("overlays"))
                 } else {
                     FilesMountStrategy::Copy(working_dir.join("assets"))
                 };
-                spin_loader::from_file(&manifest_path, files_mount_strategy, self.cache_dir.clone())
-                    .await
-                    .with_context(|| {
-                        format!(
-                            "Failed to load manifest from {}",
-                            quoted_path(&manifest_path)
-                        )
-                    })
+                let diagnostics = Diagnostics::new();
+                let options = ManifestLoadOptions {
+                    source_target: self.source_target.clone(),
+                    features: self.features.clone(),
+                    lockfile_mode: self.lockfile_mode(),
+                    environment: self.environment.clone(),
+                    diagnostics: diagnostics.clone(),
+                };
+                let locked_app = spin_loader::from_file_with_options(
+                    &manifest_path,
+                    files_mount_strategy,
+                    self.cache_dir.clone(),
+                    options,
+                )
+                .await
+                .with_context(|| {
+                    format!(
+                        "Failed to load manifest from {}",
+                        quoted_path(&manifest_path)
+                    )
+                });
+                print_diagnostics(&diagnostics);
+                locked_app
             }
-            ResolvedAppSource::OciRegistry { locked_app } => Ok(locked_app),
+            ResolvedAppSource::OciRegistry { locked_app }
+            | ResolvedAppSource::Bundle { locked_app, .. } => Ok(locked_app),
             ResolvedAppSource::BareWasm { wasm_path } => spin_loader::from_wasm_file(&wasm_path)
                 .await
                 .with_context(|| {
@@ -511,6 +728,91 @@ impl UpCommand {
         }
     }
 
+    // Load a resolved app, keeping only the components selected with
+    // --component.
+    async fn lock_app(
+        &self,
+        resolved: ResolvedAppSource,
+        working_dir: &Path,
+    ) -> anyhow::Result<LockedApp> {
+        let locked_app = self
+            .load_resolved_app_source(resolved, working_dir)
+            .await
+            .context("Failed to load application")?;
+
+        if self.components.is_empty() {
+            return Ok(locked_app);
+        }
+        spin_app::retain_components(
+            locked_app,
+            &self
+                .components
+                .iter()
+                .map(|s| s.as_str())
+                .collect::<Vec<&str>>(),
+            &[&validate_service_chaining_for_components],
+        )
+        .context("failed to resolve application with only components selected with --component")
+    }
+
+    /// Each time `spin up` receives `SIGHUP`, re-locks an app loaded from a
+    /// manifest, rewriting the lock file the triggers were started from, and
+    /// sends `SIGHUP` on to the triggers so that they apply the changes
+    /// without restarting. This never completes unless listening for
+    /// signals fails.
+    #[cfg(not(windows))]
+    async fn relock_on_hangup(
+        &self,
+        app_source: &AppSource,
+        working_dir: &Path,
+        pids: &[nix::unistd::Pid],
+    ) -> Result<()> {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        if !matches!(app_source, AppSource::File(_)) {
+            return std::future::pending().await;
+        }
+        let mut hangups = signal(SignalKind::hangup()).context("failed to listen for SIGHUP")?;
+        while hangups.recv().await.is_some() {
+            let relocked = async {
+                let resolved = self.resolve_app_source(app_source, working_dir).await?;
+                let mut locked_app = self.lock_app(resolved, working_dir).await?;
+                self.update_locked_app(&mut locked_app);
+                self.write_locked_app(&locked_app, working_dir).await
+            };
+            if let Err(err) = relocked.await {
+                terminal::error!("Couldn't reload {app_source}: {err:#}");
+                continue;
+            }
+            for pid in pids {
+                if let Err(err) = nix::sys::signal::kill(*pid, nix::sys::signal::SIGHUP) {
+                    tracing::warn!("Failed to signal trigger handler process: {:?}", err)
+                }
+            }
+        }
+        Ok(())
+    }
+
+    #[cfg(windows)]
+    async fn relock_on_hangup(
+        &self,
+        _app_source: &AppSource,
+        _working_dir: &Path,
+        _pids: &[usize],
+    ) -> Result<()> {
+        std::future::pending().await
+    }
+
+    fn lockfile_mode(&self) -> LockfileMode {
+        if self.locked {
+            LockfileMode::Verify
+        } else if self.write_lockfile {
+            LockfileMode::Write
+        } else {
+            LockfileMode::Ignore
+        }
+    }
+
     fn update_locked_app(&self, locked_app: &mut LockedApp) {
         // Apply --env to component environments
         if !self.env.is_empty() {
@@ -550,6 +852,13 @@ impl UpCommand {
     }
 }
 
+/// Prints the diagnostics reported while loading the app.
+fn print_diagnostics(diagnostics: &Diagnostics) {
+    for diagnostic in diagnostics.take() {
+        terminal::warn!("{diagnostic}");
+    }
+}
+
 fn is_flag_arg(arg: &OsString) -> bool {
     if let Some(s) = arg.to_str() {
         s.starts_with('-')
@@ -605,6 +914,11 @@ struct RunTriggerOpts {
     locked_url: String,
     working_dir: PathBuf,
     local_app_dir: Option<PathBuf>,
+    /// A runtime config file to use if none was passed through to the trigger.
+    runtime_config_file: Option<PathBuf>,
+    /// Whether the trigger resumes the background tasks and timers saved by
+    /// a previous run of the app.
+    resume_saved_tasks: bool,
 }
 
 enum WorkingDirectory {
@@ -630,22 +944,39 @@ fn parse_env_var(s: &str) -> Result<(String, String)> {
     Ok((parts[0].to_owned(), parts[1].to_owned()))
 }
 
-fn resolve_trigger_plugin(trigger_type: &str) -> Result<String> {
+fn resolve_trigger_plugin(trigger_type: &str) -> Result<Vec<String>> {
     use crate::commands::plugins::PluginCompatibility;
     use spin_plugins::manager::PluginManager;
+    use spin_trigger::plugin::protocol::PROTOCOL_VERSION;
 
     let subcommand = format!("trigger-{trigger_type}");
     let plugin_manager = PluginManager::try_default()
         .with_context(|| format!("Failed to access plugins looking for '{subcommand}'"))?;
     let plugin_store = plugin_manager.store();
-    let is_installed = plugin_store
+    let installed = plugin_store
         .installed_manifests()
         .unwrap_or_default()
-        .iter()
-        .any(|m| m.name() == subcommand);
-
-    if is_installed {
-        return Ok(subcommand);
+        .into_iter()
+        .find(|m| m.name() == subcommand);
+
+    if let Some(installed) = installed {
+        // Plugins that speak the trigger plugin protocol are hosted by Spin;
+        // others are run as Spin subcommands that embed their own runtime.
+        return match installed.trigger_protocol() {
+            None => Ok(vec![subcommand]),
+            Some(PROTOCOL_VERSION) => {
+                let plugin_path = plugin_store.installed_binary_path(&subcommand);
+                Ok(vec![
+                    "trigger".to_owned(),
+                    "plugin".to_owned(),
+                    "--plugin-trigger-type".to_owned(),
+                    trigger_type.to_owned(),
+                    "--plugin-path".to_owned(),
+                    plugin_path.to_string_lossy().into_owned(),
+                ])
+            }
+            Some(version) => Err(anyhow!("Plugin '{subcommand}' uses trigger plugin protocol version {version}, but this version of Spin supports only version {PROTOCOL_VERSION}")),
+        };
     }
 
     if let Some(known) = plugin_store
@@ -667,15 +998,21 @@ fn trigger_command(trigger_type: &str) -> Vec<String> {
     vec!["trigger".to_owned(), trigger_type.to_owned()]
 }
 
+/// The index of a trigger command which can run the app's lifecycle hooks
+/// alone. Spin's own trigger commands, including those hosting trigger
+/// plugins, can; plugins run as subcommands of their own may not.
+fn lifecycle_hook_command_index(trigger_cmds: &[Vec<String>]) -> Option<usize> {
+    trigger_cmds
+        .iter()
+        .position(|cmd| cmd.first().is_some_and(|c| c == "trigger"))
+}
+
 fn trigger_commands_for_trigger_types(trigger_types: Vec<&str>) -> Result<Vec<Vec<String>>> {
     trigger_types
         .iter()
         .map(|&t| match t {
             "http" | "redis" => Ok(trigger_command(t)),
-            _ => {
-                let cmd = resolve_trigger_plugin(t)?;
-                Ok(vec![cmd])
-            }
+            _ => resolve_trigger_plugin(t),
         })
         .collect()
 }
@@ -692,6 +1029,30 @@ mod test {
         format!("{repo_base}/{path}")
     }
 
+    #[test]
+    fn lifecycle_hooks_run_through_spin_trigger_commands() {
+        let hosted_plugin = vec![
+            "trigger".to_owned(),
+            "plugin".to_owned(),
+            "--plugin-trigger-type".to_owned(),
+            "timer".to_owned(),
+        ];
+        let subcommand_plugin = vec!["trigger-timer".to_owned()];
+
+        assert_eq!(
+            None,
+            lifecycle_hook_command_index(&[subcommand_plugin.clone()])
+        );
+        assert_eq!(
+            Some(1),
+            lifecycle_hook_command_index(&[subcommand_plugin.clone(), trigger_command("http")])
+        );
+        assert_eq!(
+            Some(0),
+            lifecycle_hook_command_index(&[hosted_plugin, subcommand_plugin])
+        );
+    }
+
     #[test]
     fn can_infer_files() {
         let file = repo_path("examples/http-rust/spin.toml");
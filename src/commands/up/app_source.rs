@@ -13,6 +13,7 @@ pub enum AppSource {
     File(PathBuf),
     OciRegistry(String),
     BareWasm(PathBuf),
+    Bundle(PathBuf),
     Unresolvable(String),
     None,
 }
@@ -75,6 +76,7 @@ impl std::fmt::Display for AppSource {
             Self::File(path) => write!(f, "local app {}", quoted_path(path)),
             Self::OciRegistry(reference) => write!(f, "remote app {reference:?}"),
             Self::BareWasm(path) => write!(f, "Wasm file {}", quoted_path(path)),
+            Self::Bundle(path) => write!(f, "bundle {}", quoted_path(path)),
             Self::Unresolvable(s) => write!(f, "unknown app source: {s:?}"),
             Self::None => write!(f, "<no source>"),
         }
@@ -95,6 +97,10 @@ pub enum ResolvedAppSource {
     OciRegistry {
         locked_app: LockedApp,
     },
+    Bundle {
+        locked_app: LockedApp,
+        runtime_config_file: Option<PathBuf>,
+    },
 }
 
 impl ResolvedAppSource {
@@ -105,7 +111,8 @@ impl ResolvedAppSource {
                 .keys()
                 .map(|s| s.as_str())
                 .collect::<HashSet<_>>(),
-            ResolvedAppSource::OciRegistry { locked_app } => locked_app
+            ResolvedAppSource::OciRegistry { locked_app }
+            | ResolvedAppSource::Bundle { locked_app, .. } => locked_app
                 .triggers
                 .iter()
                 .map(|t| t.trigger_type.as_str())
@@ -115,4 +122,14 @@ impl ResolvedAppSource {
 
         types.into_iter().collect()
     }
+
+    pub fn bundled_runtime_config_file(&self) -> Option<&Path> {
+        match self {
+            ResolvedAppSource::Bundle {
+                runtime_config_file,
+                ..
+            } => runtime_config_file.as_deref(),
+            _ => None,
+        }
+    }
 }
@@ -74,15 +74,16 @@ impl WatchCommand {
     pub async fn run(self) -> Result<()> {
         // Strategy:
         // * The Uppificator runs `spin up`, and watches the manifest artifacts (component.source and component.files)
-        //   (and the manifest if build is not in play). When it detects a change, it restarts `spin up`.
+        //   and the manifest. When it detects an artifact change, it restarts `spin up`. When it detects a manifest
+        //   change that leaves the components' code alone, it signals `spin up` to reload the app instead.
         //   THAT'S ALL, THAT'S ALL IT DOES.
         //   * If `spin up` crashes, the Uppificator restarts it.  BUT APART FROM THAT THAT'S ALL IT DOES OKAY.
         // * The Buildifier, if in play, watches the manifest and component.build.watch collections. When it detects a
         //   change, it PAUSES the Uppificator, does the build, then unpauses the Uppificator.
         //   * It is on the Uppificator to recognise if any interesting files have changed when it unpauses.
         // * The Reconfiguriser watches the manifest *only*. When it detects a change, it reconfigures the `watchexec`
-        //   instances that underlie the Uppificator and Buildifier. There is no need to trigger a reload as
-        //   both of these will already be triggered by the manifest change.
+        //   instances that underlie the Uppificator and Buildifier. There is no need to trigger a rebuild or
+        //   reload as both of these will already be triggered by the manifest change.
         //   * Reconfiguration is supported by the ReconfigurableWatcher, which holds the watchexec instance,
         //     and the RuntimeConfigFactory, which holds the information needed to re-read the manifest
         //     and create a new configuration for the watchexec instances.
@@ -124,6 +125,7 @@ impl WatchCommand {
             up_args: self.up_args.clone(),
             clear_screen: self.clear,
             watched_changes: artifact_rx,
+            manifest_changes: manifest_rx.clone(),
             pause_feed: pause_rx,
             stopper: stop_rx,
         };
@@ -139,7 +141,6 @@ impl WatchCommand {
         let contains_direct_mounts = self.up_args.contains(&"--direct-mounts".to_owned());
 
         let artifact_filterer = Box::new(ArtifactFilterFactory {
-            skip_build: self.skip_build,
             skip_assets: contains_direct_mounts,
         });
         let (artifact_watcher, artifact_watcher_handle) = self
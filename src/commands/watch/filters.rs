@@ -20,7 +20,6 @@ pub(crate) trait FilterFactory: Send + Sync {
 }
 
 pub(crate) struct ArtifactFilterFactory {
-    pub skip_build: bool,
     pub skip_assets: bool,
 }
 
@@ -31,40 +30,31 @@ pub(crate) struct ManifestFilterFactory;
 impl FilterFactory for ArtifactFilterFactory {
     async fn build_filter(
         &self,
-        manifest_file: &Path,
+        _manifest_file: &Path,
         manifest_dir: &Path,
         manifest: &v2::AppManifest,
     ) -> anyhow::Result<Arc<dyn Filterer>> {
-        let manifest_glob = if self.skip_build {
-            vec![manifest_path_to_watch(manifest_file)?]
-        } else {
-            vec![] // In this case, manifest changes trigger a rebuild, which will poke the uppificator anyway
-        };
+        // Manifest changes reach the uppificator separately, so that it can
+        // reload rather than restart the app if they allow.
         let wasm_globs = manifest
             .components
             .values()
-            .filter_map(|c| match &c.source {
-                v2::ComponentSource::Local(path) => Some(path.clone()),
-                _ => None,
-            });
-        let asset_globs = match self.skip_assets {
-            true => {
-                tracing::debug!("Skipping asset globs from being watched");
-                vec![]
-            }
-            false => manifest
-                .components
-                .values()
-                .flat_map(|c| c.files.iter())
-                .filter_map(globbify)
-                .collect::<Vec<_>>(),
-        };
-
-        let artifact_globs = manifest_glob
-            .into_iter()
-            .chain(wasm_globs)
-            .chain(asset_globs)
+            .filter_map(|c| c.source.local_path().map(ToOwned::to_owned));
+        let asset_globs = manifest
+            .components
+            .values()
+            .flat_map(|c| {
+                c.files
+                    .iter()
+                    .filter(|f| !self.skip_assets || is_overlaid(manifest_dir, c, f))
+            })
+            .filter_map(globbify)
             .collect::<Vec<_>>();
+        if self.skip_assets {
+            tracing::debug!("Skipping directly mounted asset globs from being watched");
+        }
+
+        let artifact_globs = wasm_globs.chain(asset_globs).collect::<Vec<_>>();
 
         let filterer = globset_filter(manifest_dir, artifact_globs).await?;
 
@@ -72,6 +62,17 @@ impl FilterFactory for ArtifactFilterFactory {
     }
 }
 
+/// Whether a files mount is mounted through an overlay when mounting files
+/// directly, rather than directly, so that files added to it are only mounted
+/// on restart.
+fn is_overlaid(manifest_dir: &Path, component: &v2::Component, mount: &v2::WasiFilesMount) -> bool {
+    let source = match mount {
+        v2::WasiFilesMount::Placement { source, .. } => source,
+        v2::WasiFilesMount::Pattern(pattern) => pattern,
+    };
+    !component.exclude_files.is_empty() || !manifest_dir.join(source).is_dir()
+}
+
 fn globbify(files_mount: &v2::WasiFilesMount) -> Option<String> {
     match files_mount {
         v2::WasiFilesMount::Placement { source, .. } => {
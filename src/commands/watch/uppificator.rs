@@ -1,5 +1,6 @@
 use command_group::AsyncCommandGroup;
-use std::path::PathBuf;
+use spin_manifest::schema::v2::AppManifest;
+use std::{collections::HashMap, path::PathBuf};
 use uuid::Uuid;
 
 pub(crate) struct Uppificator {
@@ -8,6 +9,7 @@ pub(crate) struct Uppificator {
     pub manifest: PathBuf,
     pub clear_screen: bool,
     pub watched_changes: tokio::sync::watch::Receiver<Uuid>,
+    pub manifest_changes: tokio::sync::watch::Receiver<Uuid>,
     pub pause_feed: tokio::sync::mpsc::Receiver<Pause>,
     pub stopper: tokio::sync::watch::Receiver<Uuid>,
 }
@@ -20,6 +22,7 @@ pub(crate) enum Pause {
 
 enum UppificatorAction {
     Restart,
+    Reload,
     Resume,
     Stop,
     Wait,
@@ -50,11 +53,13 @@ impl Uppificator {
                 }
             };
 
+            let mut running_manifest = spin_manifest::manifest_from_file(&self.manifest).ok();
             let mut resuming_after_build = false;
 
             loop {
-                match self.next_event(&mut child).await {
+                match self.next_event(&mut child, &mut running_manifest).await {
                     UppificatorAction::Restart => break,
+                    UppificatorAction::Reload => continue,
                     UppificatorAction::Resume => {
                         resuming_after_build = true;
                         continue;
@@ -73,6 +78,7 @@ impl Uppificator {
     async fn next_event(
         &mut self,
         child: &mut command_group::AsyncGroupChild,
+        running_manifest: &mut Option<AppManifest>,
     ) -> UppificatorAction {
         tokio::select! {
             _ = child.wait() => {
@@ -82,6 +88,16 @@ impl Uppificator {
                 stop(child).await;
                 UppificatorAction::Restart
             },
+            _ = self.manifest_changes.changed() => {
+                let manifest = spin_manifest::manifest_from_file(&self.manifest).ok();
+                if can_reload(running_manifest.as_ref(), manifest.as_ref()) && reload(child) {
+                    *running_manifest = manifest;
+                    UppificatorAction::Reload
+                } else {
+                    stop(child).await;
+                    UppificatorAction::Restart
+                }
+            },
             p = self.pause_feed.recv() => {
                 if matches!(p, Some(Pause::Pause)) {
                     loop {
@@ -102,6 +118,48 @@ impl Uppificator {
     }
 }
 
+/// Whether `spin up` can apply the changes from the `old` manifest to the `new`
+/// one by reloading the app, rather than being restarted: that is, whether
+/// the app has only HTTP triggers, the only trigger which applies reloads,
+/// and has the same components with the same code.
+fn can_reload(old: Option<&AppManifest>, new: Option<&AppManifest>) -> bool {
+    let (Some(old), Some(new)) = (old, new) else {
+        return false;
+    };
+    let http_only = |manifest: &AppManifest| manifest.triggers.keys().all(|t| t == "http");
+    let code = |manifest: &AppManifest| {
+        manifest
+            .components
+            .iter()
+            .map(|(id, c)| {
+                let code = (&c.source, &c.dependencies, &c.middleware, &c.features);
+                (id.to_string(), serde_json::to_value(code).ok())
+            })
+            .collect::<HashMap<_, _>>()
+    };
+    http_only(old) && http_only(new) && code(old) == code(new)
+}
+
+/// Asks `spin up` to re-lock the manifest and reload the app, returning
+/// whether it could be asked.
+#[cfg(unix)]
+fn reload(child: &command_group::AsyncGroupChild) -> bool {
+    let Some(child_id) = child.id() else {
+        return false;
+    };
+    let pid = nix::unistd::Pid::from_raw(child_id as i32);
+    if let Err(e) = nix::sys::signal::kill(pid, Some(nix::sys::signal::Signal::SIGHUP)) {
+        tracing::warn!("Could not send hangup signal to child process: {e:#}");
+        return false;
+    }
+    true
+}
+
+#[cfg(not(unix))]
+fn reload(_child: &command_group::AsyncGroupChild) -> bool {
+    false
+}
+
 #[cfg(unix)]
 async fn stop(child: &mut command_group::AsyncGroupChild) {
     if let Some(child_id) = child.id() {
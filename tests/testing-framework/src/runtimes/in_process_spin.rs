@@ -109,8 +109,8 @@ async fn initialize_trigger(
     let trigger_app = builder
         .build(
             app,
-            spin_trigger::cli::FactorsConfig::default(),
-            TriggerAppArgs::default(),
+            &spin_trigger::cli::FactorsConfig::default(),
+            &TriggerAppArgs::default(),
             &ComponentLoader::new(),
         )
         .await?;